@@ -18,7 +18,7 @@ fn create_test_class_with_method(name: &str, method_name: &str) -> Class {
     let class = Class::new_root(name).unwrap();
 
     // Add a simple method to the class
-    extern "C" fn test_method_impl(
+    extern "C-unwind" fn test_method_impl(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -113,7 +113,7 @@ fn bench_inheritance_traversal(c: &mut Criterion) {
 
 /// Benchmark method swizzling overhead
 fn bench_method_swizzling(c: &mut Criterion) {
-    extern "C" fn original_method(
+    extern "C-unwind" fn original_method(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -121,7 +121,7 @@ fn bench_method_swizzling(c: &mut Criterion) {
     ) {
     }
 
-    extern "C" fn replacement_method(
+    extern "C-unwind" fn replacement_method(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -179,7 +179,7 @@ fn bench_cache_repopulation(c: &mut Criterion) {
     c.bench_function("cache_repopulation", |b| {
         b.iter(|| {
             // Invalidate cache by swizzling (even with same implementation)
-            extern "C" fn same_method(
+            extern "C-unwind" fn same_method(
                 _self: oxidec::runtime::object::ObjectPtr,
                 _cmd: oxidec::runtime::selector::SelectorHandle,
                 _args: *const *mut u8,
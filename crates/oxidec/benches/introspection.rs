@@ -18,7 +18,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 
 static BENCH_ID: AtomicUsize = AtomicUsize::new(0);
 
-unsafe extern "C" fn test_impl(
+unsafe extern "C-unwind" fn test_impl(
     _self: oxidec::runtime::object::ObjectPtr,
     _cmd: SelectorHandle,
     _args: *const *mut u8,
@@ -47,6 +47,20 @@ pub enum Error {
     /// Class name already exists in registry.
     ClassAlreadyExists,
 
+    /// Class cannot be removed from the registry.
+    ClassNotDisposable {
+        /// The class's name.
+        class: String,
+        /// Why the class must stay registered.
+        reason: String,
+    },
+
+    /// Class was disposed and cannot be instantiated.
+    ClassDisposed {
+        /// The class's name.
+        class: String,
+    },
+
     /// Inheritance cycle detected.
     InheritanceCycle,
 
@@ -110,6 +124,90 @@ pub enum Error {
         /// The forwarding depth when loop was detected.
         depth: u32,
     },
+
+    /// Instance variable name already declared by the class or a superclass.
+    IvarAlreadyExists,
+
+    /// Instance variable declared on a class whose layout live instances
+    /// use.
+    IvarLayoutInUse {
+        /// The class's name.
+        class: String,
+    },
+
+    /// Instance variable not declared by the class or a superclass.
+    IvarNotFound {
+        /// The instance variable's name.
+        name: String,
+    },
+
+    /// Instance variable accessed as the wrong kind of value.
+    IvarTypeMismatch {
+        /// The instance variable's name.
+        name: String,
+        /// The variable's type encoding.
+        types: String,
+    },
+
+    /// Property name already declared by the class or a superclass.
+    PropertyAlreadyExists,
+
+    /// Property declaration cannot be synthesized.
+    InvalidProperty {
+        /// The property's name.
+        property: String,
+        /// Human-readable reason the declaration is invalid.
+        reason: String,
+    },
+
+    /// Static metadata image is malformed.
+    InvalidImage {
+        /// What is wrong with the image.
+        reason: String,
+    },
+
+    /// Instance variable has a type no coder can encode.
+    NotCodable {
+        /// The instance variable's name.
+        field: String,
+    },
+
+    /// Shared library could not be loaded.
+    LibraryLoadFailed {
+        /// The library's path, or `<process>` for the running program.
+        library: String,
+        /// Why the dynamic loader refused it.
+        reason: String,
+    },
+
+    /// Symbol not found in a loaded library.
+    SymbolNotFound {
+        /// The symbol's name.
+        symbol: String,
+    },
+
+    /// Foreign function cannot be called with the given signature or
+    /// arguments.
+    InvalidForeignCall {
+        /// Human-readable reason for failure.
+        reason: String,
+    },
+
+    /// Component requires an ABI version or features the runtime lacks.
+    Incompatible {
+        /// The component, such as a plugin or an image.
+        component: String,
+        /// What the component requires that the runtime lacks.
+        reason: String,
+    },
+
+    /// Method implementation threw an exception or panicked.
+    Exception {
+        /// The exception's name.
+        name: String,
+        /// Why the exception was thrown.
+        reason: String,
+    },
 }
 
 impl fmt::Display for Error {
@@ -144,6 +242,12 @@ impl fmt::Display for Error {
             Error::ClassAlreadyExists => {
                 write!(f, "Class name already exists in registry")
             }
+            Error::ClassNotDisposable { class, reason } => {
+                write!(f, "Cannot dispose class '{class}': {reason}")
+            }
+            Error::ClassDisposed { class } => {
+                write!(f, "Class '{class}' was disposed")
+            }
             Error::InheritanceCycle => write!(f, "Inheritance cycle detected"),
             Error::InvalidEncoding => write!(f, "Invalid type encoding string"),
             Error::SelectorNotFound => {
@@ -195,6 +299,48 @@ impl fmt::Display for Error {
                     "Forwarding loop detected for selector '{selector}' at depth {depth}"
                 )
             }
+            Error::IvarAlreadyExists => {
+                write!(f, "Instance variable already declared by class")
+            }
+            Error::IvarLayoutInUse { class } => {
+                write!(
+                    f,
+                    "Instances of class '{class}' or a subclass are alive"
+                )
+            }
+            Error::IvarNotFound { name } => {
+                write!(f, "Instance variable '{name}' not declared by class")
+            }
+            Error::IvarTypeMismatch { name, types } => {
+                write!(f, "Instance variable '{name}' has type '{types}'")
+            }
+            Error::PropertyAlreadyExists => {
+                write!(f, "Property already declared by class")
+            }
+            Error::InvalidProperty { property, reason } => {
+                write!(f, "Invalid property '{property}': {reason}")
+            }
+            Error::InvalidImage { reason } => {
+                write!(f, "Invalid metadata image: {reason}")
+            }
+            Error::NotCodable { field } => {
+                write!(f, "Instance variable '{field}' cannot be encoded")
+            }
+            Error::LibraryLoadFailed { library, reason } => {
+                write!(f, "Cannot load library '{library}': {reason}")
+            }
+            Error::SymbolNotFound { symbol } => {
+                write!(f, "Symbol '{symbol}' not found")
+            }
+            Error::InvalidForeignCall { reason } => {
+                write!(f, "Invalid foreign call: {reason}")
+            }
+            Error::Incompatible { component, reason } => {
+                write!(f, "Incompatible {component}: {reason}")
+            }
+            Error::Exception { name, reason } => {
+                write!(f, "Uncaught exception {name}: {reason}")
+            }
         }
     }
 }
@@ -17,6 +17,15 @@
 //! - **Runtime Layer**: Unsafe internals with comprehensive safety documentation
 //! - **Memory Layer**: Arena allocators for high-performance allocation
 //!
+//! # Embedding
+//!
+//! Operations that can fail return [`Result`]; those that panic on failure,
+//! such as [`RuntimeString::new`] and [`Object::retain`], have `try_`
+//! variants that return an [`Error`] instead. The `no-abort` feature also
+//! removes the panics host applications cannot guard against: the runtime's
+//! locks are recovered after a panic in another thread, and reference
+//! counts saturate rather than overflow.
+//!
 //! # Example
 //!
 //! ```rust
@@ -0,0 +1,291 @@
+//! Runtime versioning and capability negotiation.
+//!
+//! Components built separately from the runtime, such as dynamically
+//! loaded plugins and serialized [`Image`]s, depend on its ABI and on
+//! optional features it may lack. [`capabilities`] describes what the
+//! running runtime provides, and such a component states what it requires
+//! as a [`Capabilities`] of its own, which [`Capabilities::check`]
+//! compares so that a mismatched component is refused with
+//! [`Error::Incompatible`] before any of its code runs.
+//!
+//! # ABI version
+//!
+//! [`ABI_VERSION`] is bumped whenever a change breaks code compiled
+//! against an earlier runtime: the [`Imp`] calling convention, the object
+//! layout or the layout of a `#[repr(C)]` type shared across the boundary.
+//! Components require an exact match.
+//!
+//! # Features
+//!
+//! Features are bits of [`Capabilities::features`]. A component requires
+//! a subset of them; the runtime must provide every one.
+//!
+//! # Plugins
+//!
+//! A plugin is a shared library exporting its requirements under
+//! [`PLUGIN_SYMBOL`]:
+//!
+//! ```rust
+//! use oxidec::runtime::capabilities::{Capabilities, FEATURE_FORWARDING};
+//!
+//! #[unsafe(no_mangle)]
+//! pub static OXIDEC_PLUGIN_CAPABILITIES: Capabilities =
+//!     Capabilities::requiring(FEATURE_FORWARDING);
+//! ```
+//!
+//! [`load_plugin`] opens the library and checks them before handing it
+//! out.
+//!
+//! [`Image`]: crate::runtime::Image
+//! [`Imp`]: crate::runtime::class::Imp
+
+use crate::error::{Error, Result};
+use crate::runtime::ffi::Library;
+use crate::runtime::image::IMAGE_VERSION;
+use std::fmt;
+
+/// Version of the runtime's binary interface.
+pub const ABI_VERSION: u32 = 1;
+
+/// Feature bit of tagged pointers, objects stored inline in their pointer.
+pub const FEATURE_TAGGED_POINTERS: u32 = 1 << 0;
+
+/// Feature bit of hooks for a tracing garbage collector.
+pub const FEATURE_GC_HOOKS: u32 = 1 << 1;
+
+/// Feature bit of the four-stage message forwarding pipeline.
+pub const FEATURE_FORWARDING: u32 = 1 << 2;
+
+/// Feature bit of exceptions caught at the message send boundary.
+pub const FEATURE_EXCEPTIONS: u32 = 1 << 3;
+
+/// Feature bit of recovering from poisoned locks instead of panicking.
+pub const FEATURE_NO_ABORT: u32 = 1 << 4;
+
+/// Feature bit of calling C functions through [`crate::runtime::ffi`].
+pub const FEATURE_FOREIGN_CALLS: u32 = 1 << 5;
+
+/// Every feature bit and its name.
+const FEATURES: [(u32, &str); 6] = [
+    (FEATURE_TAGGED_POINTERS, "tagged pointers"),
+    (FEATURE_GC_HOOKS, "GC hooks"),
+    (FEATURE_FORWARDING, "forwarding pipeline"),
+    (FEATURE_EXCEPTIONS, "exceptions"),
+    (FEATURE_NO_ABORT, "no-abort"),
+    (FEATURE_FOREIGN_CALLS, "foreign calls"),
+];
+
+/// Every feature bit this runtime knows.
+const KNOWN_FEATURES: u32 = FEATURE_TAGGED_POINTERS
+    | FEATURE_GC_HOOKS
+    | FEATURE_FORWARDING
+    | FEATURE_EXCEPTIONS
+    | FEATURE_NO_ABORT
+    | FEATURE_FOREIGN_CALLS;
+
+/// Symbol of a plugin's required [`Capabilities`].
+pub const PLUGIN_SYMBOL: &str = "OXIDEC_PLUGIN_CAPABILITIES";
+
+/// What a runtime provides, or what a component requires of one.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// [`ABI_VERSION`] of the runtime
+    pub abi_version: u32,
+    /// [`IMAGE_VERSION`] of the image layout the runtime reads
+    pub image_version: u32,
+    /// Feature bits
+    pub features: u32,
+}
+
+impl Capabilities {
+    /// The requirements of a component built against this runtime that
+    /// needs `features`.
+    #[must_use]
+    pub const fn requiring(features: u32) -> Self {
+        Self {
+            abi_version: ABI_VERSION,
+            image_version: IMAGE_VERSION,
+            features,
+        }
+    }
+
+    /// Returns whether every feature bit in `features` is set.
+    #[must_use]
+    pub const fn has(&self, features: u32) -> bool {
+        self.features & features == features
+    }
+
+    /// Returns the names of the features set, in bit order.
+    #[must_use]
+    pub fn feature_names(&self) -> Vec<&'static str> {
+        FEATURES
+            .iter()
+            .filter(|(bit, _)| self.has(*bit))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// Checks that these capabilities meet the requirements of
+    /// `component`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Incompatible`] naming `component` if `required`
+    /// targets another ABI version or needs a feature these capabilities
+    /// lack.
+    pub fn check(&self, component: &str, required: &Self) -> Result<()> {
+        let incompatible = |reason: String| Error::Incompatible {
+            component: component.to_string(),
+            reason,
+        };
+        if required.abi_version != self.abi_version {
+            return Err(incompatible(format!(
+                "built for ABI version {}, runtime has {}",
+                required.abi_version, self.abi_version
+            )));
+        }
+        let missing = Self {
+            features: required.features & !self.features,
+            ..*self
+        };
+        if missing.features != 0 {
+            let mut names = missing.feature_names();
+            if missing.features & !KNOWN_FEATURES != 0 {
+                names.push("unknown features");
+            }
+            return Err(incompatible(format!(
+                "requires {}, which the runtime lacks",
+                names.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ABI version {}, image version {}, features: ",
+            self.abi_version, self.image_version
+        )?;
+        let names = self.feature_names();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+/// Returns the capabilities of the running runtime.
+#[must_use]
+pub fn capabilities() -> Capabilities {
+    let mut features = FEATURE_FORWARDING | FEATURE_EXCEPTIONS;
+    if cfg!(feature = "no-abort") {
+        features |= FEATURE_NO_ABORT;
+    }
+    if cfg!(all(
+        unix,
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )) {
+        features |= FEATURE_FOREIGN_CALLS;
+    }
+    Capabilities::requiring(features)
+}
+
+/// Opens the plugin at `path`, checking its requirements against the
+/// runtime's capabilities.
+///
+/// # Safety
+///
+/// If the library exports [`PLUGIN_SYMBOL`], the symbol must be a
+/// [`Capabilities`].
+///
+/// # Errors
+///
+/// Returns `Err(Error::LibraryLoadFailed)` if the library cannot be loaded,
+/// and `Err(Error::Incompatible)` if it exports no requirements or the
+/// runtime does not meet them.
+pub unsafe fn load_plugin(path: &str) -> Result<Library> {
+    let library = Library::open(Some(path))?;
+    let component = format!("plugin '{path}'");
+    let Some(address) = library.address(PLUGIN_SYMBOL) else {
+        return Err(Error::Incompatible {
+            component,
+            reason: format!("exports no `{PLUGIN_SYMBOL}`"),
+        });
+    };
+    // SAFETY: guaranteed by the caller
+    let required = unsafe { address.cast::<Capabilities>().read() };
+    capabilities().check(&component, &required)?;
+    Ok(library)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let current = capabilities();
+        assert_eq!(current.abi_version, ABI_VERSION);
+        assert_eq!(current.image_version, IMAGE_VERSION);
+        assert!(current.has(FEATURE_FORWARDING | FEATURE_EXCEPTIONS));
+        assert!(!current.has(FEATURE_TAGGED_POINTERS));
+        assert_eq!(current.has(FEATURE_NO_ABORT), cfg!(feature = "no-abort"));
+        assert!(current.to_string().starts_with(
+            "ABI version 1, image version 1, features: forwarding pipeline"
+        ));
+    }
+
+    #[test]
+    fn test_check_requirements() {
+        let current = capabilities();
+        let component = "plugin 'test'";
+        assert_eq!(
+            current
+                .check(component, &Capabilities::requiring(FEATURE_FORWARDING)),
+            Ok(())
+        );
+
+        let newer = Capabilities {
+            abi_version: ABI_VERSION + 1,
+            ..current
+        };
+        assert_eq!(
+            current.check(component, &newer).unwrap_err().to_string(),
+            "Incompatible plugin 'test': built for ABI version 2, runtime \
+             has 1"
+        );
+
+        let required = Capabilities::requiring(
+            FEATURE_TAGGED_POINTERS | FEATURE_GC_HOOKS | 1 << 31,
+        );
+        assert_eq!(
+            current.check(component, &required).unwrap_err().to_string(),
+            "Incompatible plugin 'test': requires tagged pointers, GC \
+             hooks, unknown features, which the runtime lacks"
+        );
+    }
+
+    #[test]
+    fn test_load_plugin_errors() {
+        // SAFETY: the library does not exist
+        let result = unsafe { load_plugin("/nonexistent/libplugin.so") };
+        assert!(matches!(result, Err(Error::LibraryLoadFailed { .. })));
+
+        // The C library loads but is no plugin
+        #[cfg(target_os = "linux")]
+        {
+            // SAFETY: the C library does not export the symbol
+            let result = unsafe { load_plugin("libc.so.6") };
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "Incompatible plugin 'libc.so.6': exports no \
+                 `OXIDEC_PLUGIN_CAPABILITIES`"
+            );
+        }
+    }
+}
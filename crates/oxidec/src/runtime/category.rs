@@ -25,9 +25,11 @@
 //! multiple threads. Uses `RwLock` for method table protection.
 
 use crate::error::{Error, Result};
+use crate::runtime::class::{MethodTable, publish};
 use crate::runtime::{
     Class, Method, RuntimeString, Selector, get_global_arena,
 };
+use crate::runtime::sync::Recover;
 use std::collections::HashMap;
 use std::fmt;
 use std::ptr::NonNull;
@@ -42,7 +44,7 @@ pub(crate) struct CategoryInner {
     name: RuntimeString,
     /// Methods in this category: selector hash -> Method
     /// Protected by `RwLock` for thread-safe method addition
-    pub(crate) methods: RwLock<HashMap<u64, Method>>,
+    pub(crate) methods: RwLock<MethodTable>,
     /// Class this category extends
     associated_class: NonNull<crate::runtime::class::ClassInner>,
 }
@@ -92,7 +94,7 @@ pub(crate) struct CategoryInner {
 /// category.add_method(method).unwrap();
 ///
 /// // Method is now available on instances of MyClass
-/// # unsafe extern "C" fn my_method_impl(
+/// # unsafe extern "C-unwind" fn my_method_impl(
 /// #     _self: oxidec::runtime::object::ObjectPtr,
 /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
 /// #     _args: *const *mut u8,
@@ -149,15 +151,17 @@ impl Category {
         // SAFETY: class.inner points to valid ClassInner
         let class_inner = unsafe { &*class.inner.as_ptr() };
 
-        // Check for duplicate category names
-        {
-            let categories_lock = class_inner.categories.read().unwrap();
-            for cat_ptr in categories_lock.iter() {
+        let is_attached = |categories: &[NonNull<CategoryInner>]| {
+            categories.iter().any(|cat_ptr| {
+                // SAFETY: cat_ptr points to valid CategoryInner
                 let cat = unsafe { &*cat_ptr.as_ptr() };
-                if cat.name.as_str().ok() == Some(name) {
-                    return Err(Error::CategoryAlreadyExists);
-                }
-            }
+                cat.name.as_str().ok() == Some(name)
+            })
+        };
+
+        // Check for duplicate category names
+        if is_attached(&class_inner.categories.read().recover()) {
+            return Err(Error::CategoryAlreadyExists);
         }
 
         // Allocate CategoryInner in global arena
@@ -179,7 +183,13 @@ impl Category {
 
         // Register with class
         {
-            let mut categories_lock = class_inner.categories.write().unwrap();
+            let mut categories_lock = class_inner.categories.write().recover();
+
+            // Double-check: Another thread might have attached it while we
+            // allocated
+            if is_attached(&categories_lock) {
+                return Err(Error::CategoryAlreadyExists);
+            }
             categories_lock.push(inner);
         }
 
@@ -218,7 +228,7 @@ impl Category {
     /// };
     ///
     /// category.add_method(method).unwrap();
-    /// # unsafe extern "C" fn my_impl(
+    /// # unsafe extern "C-unwind" fn my_impl(
     /// #     _self: oxidec::runtime::object::ObjectPtr,
     /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
     /// #     _args: *const *mut u8,
@@ -234,16 +244,18 @@ impl Category {
     /// # Errors
     ///
     /// Returns `Err(Error::SelectorAlreadyExists)` if a method with this
-    /// selector already exists in the category.
+    /// selector already exists in the category, or `Err(Error::OutOfMemory)`
+    /// if the global arena cannot hold the method.
     pub fn add_method(&self, method: Method) -> Result<()> {
         // SAFETY: self.inner points to valid CategoryInner
         let inner = unsafe { &*self.inner.as_ptr() };
 
         let hash = method.selector.hash();
+        let method = publish(method)?;
 
         // Add method to category's method table
         {
-            let mut methods = inner.methods.write().unwrap();
+            let mut methods = inner.methods.write().recover();
             methods.insert(hash, method);
         }
 
@@ -286,19 +298,16 @@ impl Category {
     /// Returns `Some(&Method)` if found, `None` otherwise.
     #[must_use]
     #[allow(dead_code)]
-    pub(crate) fn lookup_method(&self, selector: &Selector) -> Option<&Method> {
+    pub(crate) fn lookup_method(
+        &self,
+        selector: &Selector,
+    ) -> Option<&'static Method> {
         // SAFETY: self.inner points to valid CategoryInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let methods = inner.methods.read().unwrap();
+        let methods = inner.methods.read().recover();
         let hash = selector.hash();
 
-        if let Some(method) = methods.get(&hash) {
-            // SAFETY: The method is in the arena and never deallocated
-            // We're creating a reference with extended lifetime
-            return unsafe { Some(&*std::ptr::from_ref::<Method>(method)) };
-        }
-
-        None
+        methods.get(&hash).copied()
     }
 }
 
@@ -306,7 +315,7 @@ impl fmt::Debug for Category {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // SAFETY: self.inner points to valid CategoryInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let methods = inner.methods.read().unwrap();
+        let methods = inner.methods.read().recover();
 
         f.debug_struct("Category")
             .field("name", &inner.name.as_str().unwrap_or("<invalid>"))
@@ -328,7 +337,7 @@ mod tests {
     use std::str::FromStr;
 
     // Test method implementation
-    unsafe extern "C" fn test_method_noop(
+    unsafe extern "C-unwind" fn test_method_noop(
         _self: crate::runtime::object::ObjectPtr,
         _cmd: crate::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
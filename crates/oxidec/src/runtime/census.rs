@@ -0,0 +1,420 @@
+//! Heap snapshots: a census of live objects by class.
+//!
+//! [`heap_snapshot`] counts the live instances of every registered class
+//! and their shallow size, the bytes each object takes itself without the
+//! objects it refers to, alongside the runtime's [`MemoryReport`]. Taking
+//! snapshots of a long-running program at intervals shows which classes
+//! its memory grows in.
+//!
+//! Every class counts its instances as they are created and freed, so a
+//! census reads a counter per class rather than walking the heap. The
+//! counts of different classes are read one after another, so a snapshot
+//! taken while other threads create objects may mix counts from before and
+//! after a creation.
+//!
+//! A snapshot is printed as a table or written as JSON
+//! ([`HeapSnapshot::to_json`]). On Unix, [`dump_on_signal`] makes a signal
+//! write one to a file, so a running program can be inspected from
+//! outside:
+//!
+//! ```text
+//! $ kill -USR1 <pid>
+//! $ cat heap.json
+//! {"instances":3,"shallow_bytes":72,"census":[{"class":"Point", ...
+//! ```
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::census::heap_snapshot;
+//! use oxidec::{Class, Object};
+//!
+//! let class = Class::new_root("CensusExample").unwrap();
+//! let first = Object::new(&class).unwrap();
+//! let second = Object::new(&class).unwrap();
+//!
+//! let snapshot = heap_snapshot();
+//! let census = snapshot.class("CensusExample").unwrap();
+//! assert_eq!(census.instances, 2);
+//! println!("{snapshot}");
+//! ```
+
+use crate::runtime::introspection::all_classes;
+use crate::runtime::object::RawObject;
+use crate::runtime::{Class, MemoryReport, memory_report};
+use std::fmt::{self, Write};
+
+/// The live instances of one class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassCensus {
+    /// The class's name
+    pub class: String,
+    /// Number of live instances, not counting instances of subclasses
+    pub instances: usize,
+    /// Bytes the instances take themselves, header and instance variables
+    pub shallow_bytes: usize,
+}
+
+/// Live objects by class, and the memory the runtime holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeapSnapshot {
+    /// Classes with live instances, largest shallow size first, then by
+    /// name
+    pub census: Vec<ClassCensus>,
+    /// The runtime's memory report when the snapshot was taken
+    pub memory: MemoryReport,
+}
+
+impl HeapSnapshot {
+    /// Returns the census of the class named `name`, if it has live
+    /// instances.
+    #[must_use]
+    pub fn class(&self, name: &str) -> Option<&ClassCensus> {
+        self.census.iter().find(|census| census.class == name)
+    }
+
+    /// Returns the number of live objects.
+    #[must_use]
+    pub fn instances(&self) -> usize {
+        self.census.iter().map(|census| census.instances).sum()
+    }
+
+    /// Returns the bytes live objects take themselves.
+    #[must_use]
+    pub fn shallow_bytes(&self) -> usize {
+        self.census.iter().map(|census| census.shallow_bytes).sum()
+    }
+
+    /// Returns the snapshot as a JSON object.
+    ///
+    /// The object holds the totals, the census as an array of objects with
+    /// the fields of [`ClassCensus`], and the figures of the memory report.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"instances\":{},\"shallow_bytes\":{},\"census\":[",
+            self.instances(),
+            self.shallow_bytes()
+        );
+        for (index, census) in self.census.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"class\":");
+            push_json_string(&mut json, &census.class);
+            let _ = write!(
+                json,
+                ",\"instances\":{},\"shallow_bytes\":{}}}",
+                census.instances, census.shallow_bytes
+            );
+        }
+        let memory = &self.memory;
+        let _ = write!(
+            json,
+            "],\"arena_bytes\":{},\"arena_capacity\":{},\
+             \"arena_chunks\":{},\"selectors\":{},\"classes\":{},\
+             \"pooled_invocations\":{}}}",
+            memory.arena.total_allocated,
+            memory.arena.total_capacity,
+            memory.arena.chunk_count,
+            memory.selectors,
+            memory.classes,
+            memory.pool.map_or(0, |pool| pool.pool_size)
+        );
+        json
+    }
+}
+
+/// Appends `text` to `json` as a JSON string.
+fn push_json_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+impl fmt::Display for HeapSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instances = self.instances();
+        let plural = if instances == 1 { "" } else { "s" };
+        write!(
+            f,
+            "heap census: {instances} live object{plural}, {} bytes",
+            self.shallow_bytes()
+        )?;
+        let width = self
+            .census
+            .iter()
+            .map(|census| census.class.len())
+            .max()
+            .unwrap_or(0);
+        for census in &self.census {
+            write!(
+                f,
+                "\n  {:<width$}  {:>8} {:>10} bytes",
+                census.class, census.instances, census.shallow_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the bytes an instance of `class` takes itself: the allocation
+/// [`Object::new`](crate::runtime::Object::new) makes for it, a header
+/// followed by its instance variables.
+fn shallow_size(class: &Class) -> usize {
+    RawObject::layout(class.instance_size()).map_or(0, |layout| layout.size())
+}
+
+/// Takes a census of the live objects of every registered class.
+#[must_use]
+pub fn heap_snapshot() -> HeapSnapshot {
+    let mut census: Vec<ClassCensus> = all_classes()
+        .iter()
+        .filter_map(|class| {
+            let instances = class.instance_count();
+            (instances > 0).then(|| ClassCensus {
+                class: class.name().to_string(),
+                instances,
+                shallow_bytes: instances * shallow_size(class),
+            })
+        })
+        .collect();
+    // Classes are enumerated by name, which the stable sort keeps for ties
+    census.sort_by_key(|census| std::cmp::Reverse(census.shallow_bytes));
+    HeapSnapshot {
+        census,
+        memory: memory_report(),
+    }
+}
+
+#[cfg(unix)]
+pub use signal::{DUMP_SIGNAL, dump_on_signal};
+
+#[cfg(unix)]
+mod signal {
+    use super::heap_snapshot;
+    use crate::runtime::sync::Recover;
+    use std::io;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    /// The signal hosts dump snapshots on by convention, `SIGUSR1`.
+    pub const DUMP_SIGNAL: i32 = libc::SIGUSR1;
+
+    /// Write end of the pipe through which the signal handler wakes the
+    /// dumping thread, or -1 before the thread is started
+    static WAKE: AtomicI32 = AtomicI32::new(-1);
+
+    /// File the dumping thread writes snapshots to
+    static DESTINATION: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+    /// Makes `signal` write a heap snapshot as JSON to the file at `path`,
+    /// replacing what it held.
+    ///
+    /// Signal handlers cannot take locks or allocate, so the handler only
+    /// wakes a thread the first call starts, which takes the snapshot and
+    /// writes it. Later calls change the file every signal writes to and
+    /// can handle further signals. A snapshot that cannot be written is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the operating system's error if the thread's pipe cannot be
+    /// created or the signal cannot be handled.
+    pub fn dump_on_signal(
+        signal: i32,
+        path: impl Into<PathBuf>,
+    ) -> io::Result<()> {
+        let mut destination = DESTINATION.lock().recover();
+        *destination = Some(path.into());
+        if WAKE.load(Ordering::Acquire) < 0 {
+            WAKE.store(start_dumping()?, Ordering::Release);
+        }
+        drop(destination);
+
+        // SAFETY: the handler only reads an atomic and writes to a pipe,
+        // both of which are async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = wake as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&raw mut action.sa_mask);
+            if libc::sigaction(signal, &raw const action, std::ptr::null_mut())
+                != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the thread writing snapshots, returning the write end of the
+    /// pipe that wakes it.
+    fn start_dumping() -> io::Result<i32> {
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for both ends of the pipe
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let [read, write] = fds;
+        // A full pipe already wakes the thread, so the handler never blocks
+        // SAFETY: `write` is an open descriptor
+        unsafe {
+            let flags = libc::fcntl(write, libc::F_GETFL);
+            libc::fcntl(write, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        std::thread::Builder::new()
+            .name("oxidec-heap-dump".to_string())
+            .spawn(move || {
+                let mut byte = 0u8;
+                loop {
+                    // SAFETY: `read` is an open descriptor and `byte` has
+                    // room for the one byte read
+                    let n = unsafe {
+                        libc::read(read, (&raw mut byte).cast(), 1)
+                    };
+                    if n < 0
+                        && io::Error::last_os_error().kind()
+                            == io::ErrorKind::Interrupted
+                    {
+                        continue;
+                    }
+                    if n <= 0 {
+                        return;
+                    }
+                    let path = DESTINATION.lock().recover().clone();
+                    if let Some(path) = path {
+                        let _ = std::fs::write(path, heap_snapshot().to_json());
+                    }
+                }
+            })?;
+        Ok(write)
+    }
+
+    /// Signal handler waking the dumping thread.
+    extern "C" fn wake(_signal: libc::c_int) {
+        let fd = WAKE.load(Ordering::Acquire);
+        if fd >= 0 {
+            let byte = 0u8;
+            // SAFETY: `write` is async-signal-safe and `byte` outlives it
+            unsafe { libc::write(fd, (&raw const byte).cast(), 1) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Ivar, Object, RuntimeString, get_global_arena};
+
+    #[test]
+    fn test_snapshot_counts_instances_by_class() {
+        let arena = get_global_arena();
+        let small = Class::new_root("CensusSmall").unwrap();
+        let large = Class::new("CensusLarge", &small).unwrap();
+        large
+            .add_ivar(Ivar::new(
+                RuntimeString::new("count", arena),
+                RuntimeString::new("q", arena),
+            ))
+            .unwrap();
+        let objects = [
+            Object::new(&small).unwrap(),
+            Object::new(&small).unwrap(),
+            Object::new(&large).unwrap(),
+        ];
+
+        // Instance variables are allocated with the object, after its
+        // header, so storing one takes no more memory
+        objects[2].set_ivar("count", 7).unwrap();
+        let snapshot = heap_snapshot();
+        let header = size_of::<RawObject>();
+        let census = snapshot.class("CensusSmall").unwrap();
+        assert_eq!((census.instances, census.shallow_bytes), (2, 2 * header));
+        let census = snapshot.class("CensusLarge").unwrap();
+        assert_eq!((census.instances, census.shallow_bytes), (1, header + 8));
+        assert!(snapshot.instances() >= 3);
+        assert!(
+            snapshot
+                .census
+                .windows(2)
+                .all(|pair| pair[0].shallow_bytes >= pair[1].shallow_bytes)
+        );
+
+        drop(objects);
+        let snapshot = heap_snapshot();
+        assert!(snapshot.class("CensusSmall").is_none());
+        assert!(snapshot.class("CensusLarge").is_none());
+    }
+
+    #[test]
+    fn test_json_and_display() {
+        let mut snapshot = heap_snapshot();
+        snapshot.census = vec![
+            ClassCensus {
+                class: "Point".to_string(),
+                instances: 2,
+                shallow_bytes: 48,
+            },
+            ClassCensus {
+                class: "Odd\"Name".to_string(),
+                instances: 1,
+                shallow_bytes: 16,
+            },
+        ];
+        let json = snapshot.to_json();
+        assert!(json.starts_with(
+            "{\"instances\":3,\"shallow_bytes\":64,\"census\":[\
+             {\"class\":\"Point\",\"instances\":2,\"shallow_bytes\":48},\
+             {\"class\":\"Odd\\\"Name\",\"instances\":1,\"shallow_bytes\":16}\
+             ],\"arena_bytes\":"
+        ));
+        assert!(json.ends_with('}'));
+
+        assert_eq!(
+            snapshot.to_string(),
+            "heap census: 3 live objects, 64 bytes\n  \
+             Point            2         48 bytes\n  \
+             Odd\"Name         1         16 bytes"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_signal_dumps_snapshot() {
+        let class = Class::new_root("CensusSignalled").unwrap();
+        let _object = Object::new(&class).unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("oxidec-heap-{}.json", std::process::id()));
+        dump_on_signal(libc::SIGUSR2, &path).unwrap();
+
+        // SAFETY: the signal has a handler
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR2) }, 0);
+        let mut json = String::new();
+        for _ in 0..500 {
+            json = std::fs::read_to_string(&path).unwrap_or_default();
+            if json.ends_with('}') {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(
+            json.contains(
+                "{\"class\":\"CensusSignalled\",\"instances\":1,"
+            ),
+            "{json}"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -9,8 +9,10 @@
 //! # Architecture
 //!
 //! `Class`es are **globally registered** and never deallocated:
-//! - Each class name maps to exactly one ``Class`` instance
+//! - Each registered class name maps to exactly one ``Class`` instance
 //! - `Class`es have `'static` lifetime (live for program duration)
+//! - Disposing a class (see [`dispose_class`]) frees its name for a new
+//!   class, but not its memory
 //! - Immutable after creation (except method addition)
 //! - Single inheritance chain to root class (Ox`Object`)
 //!
@@ -19,15 +21,25 @@
 //! The class registry is thread-safe and supports concurrent class creation
 //! from multiple threads. Uses `RwLock` for registry access and method table
 //! protection.
+//!
+//! [`dispose_class`]: crate::runtime::introspection::dispose_class
 
 use crate::error::{Error, Result};
+use crate::runtime::dispatch::MethodCache;
+use crate::runtime::encoding::size_of_type;
+use crate::runtime::introspection::subclasses;
 use crate::runtime::selector::SelectorHandle;
+use crate::runtime::object::FINALIZED_CLASSES;
+use crate::runtime::sync::Recover;
+use crate::runtime::property::Property;
 use crate::runtime::{Protocol, RuntimeString, Selector, get_global_arena};
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fmt;
 use std::ptr::NonNull;
 use std::sync::OnceLock;
 use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// `Method` implementation function pointer type.
 ///
@@ -43,7 +55,7 @@ use std::sync::RwLock;
 /// # Safety
 ///
 /// Function pointers of this type MUST:
-/// - Be `extern "C"` for proper calling convention
+/// - Be `extern "C-unwind"`, so a panic can unwind back to dispatch
 /// - Properly validate all pointer arguments before dereference
 /// - Only write to `return_value_ptr` if the return type is non-void
 /// - Handle argument marshalling based on method's type encoding
@@ -55,7 +67,7 @@ use std::sync::RwLock;
 /// the dispatch system or custom method registration.
 ///
 /// Uses `ObjectPtr` which is an opaque wrapper around the raw object data.
-pub type Imp = unsafe extern "C" fn(
+pub type Imp = unsafe extern "C-unwind" fn(
     _self: crate::runtime::object::ObjectPtr,
     _cmd: SelectorHandle,
     _args: *const *mut u8,
@@ -73,11 +85,16 @@ pub(crate) struct ClassInner {
     super_class: Option<NonNull<ClassInner>>,
     /// `Method` table: selector hash -> Method
     /// Protected by `RwLock` for thread-safe method addition
-    methods: RwLock<HashMap<u64, Method>>,
-    /// Method cache for fast dispatch: selector hash -> (`class_ptr`, imp)
-    /// Protected by `RwLock` for thread-safe cache access
-    /// Stores `class_ptr` to handle method swizzling (cache invalidation)
-    cache: RwLock<HashMap<u64, (NonNull<ClassInner>, Imp)>>,
+    methods: RwLock<MethodTable>,
+    /// Method cache for fast dispatch: selector hash -> imp
+    /// Invalidated whenever any class's methods change
+    cache: MethodCache,
+    /// Instance variables declared by this class, in declaration order
+    /// Protected by `RwLock` for thread-safe addition
+    ivars: RwLock<Vec<Ivar>>,
+    /// Properties declared by this class, in declaration order
+    /// Protected by `RwLock` for thread-safe addition
+    properties: RwLock<Vec<Property>>,
     /// `Class` flags (reserved for future use)
     flags: u32,
     /// Categories attached to this class
@@ -105,6 +122,78 @@ pub(crate) struct ClassInner {
     /// Protected by `RwLock` for thread-safe hook access
     pub(crate) does_not_recognize_hook:
         RwLock<Option<crate::runtime::forwarding::DoesNotRecognizeHook>>,
+    /// Finalizer run when an instance is deallocated (`-dealloc`)
+    /// Protected by `RwLock` for thread-safe hook access
+    pub(crate) finalizer: RwLock<Option<crate::runtime::object::Finalizer>>,
+    /// Number of live instances, counted as objects are created and freed
+    pub(crate) instances: AtomicUsize,
+    /// Set once the class is disposed, after which it cannot be instantiated
+    disposed: AtomicBool,
+}
+
+/// A method table: selector hash -> published `Method`.
+///
+/// Entries are never changed in place. Replacing a method publishes a new
+/// one and swaps the entry, so a reference handed out by a lookup stays
+/// valid and unchanged whatever happens to the table afterwards.
+pub(crate) type MethodTable = HashMap<u64, &'static Method>;
+
+/// Publishes `method` in the global arena, for a method table to hold.
+///
+/// # Errors
+///
+/// Returns `Err(Error::OutOfMemory)` if the arena cannot hold the method.
+pub(crate) fn publish(method: Method) -> Result<&'static Method> {
+    get_global_arena()
+        .try_alloc(method)
+        .map(|method| &*method)
+        .map_err(|_| Error::OutOfMemory)
+}
+
+/// Finds the method table holding `method`, searching every registered
+/// class and the categories attached to it.
+///
+/// Methods are matched by address, so a `Method` that has since been
+/// swizzled or replaced is not found.
+fn table_holding(method: &Method) -> Option<&'static RwLock<MethodTable>> {
+    let registry = REGISTRY.get()?;
+    let hash = method.selector.hash();
+    let holds = |table: &RwLock<MethodTable>| {
+        table
+            .read()
+            .recover()
+            .get(&hash)
+            .is_some_and(|&held| std::ptr::eq(held, method))
+    };
+
+    let classes = registry.classes.read().recover();
+    for class_ptr in classes.values() {
+        // SAFETY: registered classes are arena-allocated and never freed
+        let inner: &'static ClassInner = unsafe { &*class_ptr.as_ptr() };
+        if holds(&inner.methods) {
+            return Some(&inner.methods);
+        }
+
+        let categories = inner.categories.read().recover();
+        for cat_ptr in categories.iter() {
+            // SAFETY: attached categories are arena-allocated and never freed
+            let cat: &'static crate::runtime::category::CategoryInner =
+                unsafe { &*cat_ptr.as_ptr() };
+            if holds(&cat.methods) {
+                return Some(&cat.methods);
+            }
+        }
+    }
+
+    None
+}
+
+/// Flushes every cache that depends on method tables.
+fn invalidate_caches() {
+    crate::runtime::dispatch::flush_caches();
+
+    // Clear signature cache when methods change (forwarding may need new signatures)
+    crate::runtime::forwarding::clear_signature_cache();
 }
 
 /// Global class registry.
@@ -130,12 +219,13 @@ static REGISTRY: OnceLock<ClassRegistry> = OnceLock::new();
 ///
 /// # Memory Layout
 ///
-/// `Method`s are stored in class method tables and have `'static` lifetime
-/// (allocated in global arena).
+/// `Method`s are copied into the global arena when added to a class or
+/// category, and have `'static` lifetime from then on.
 ///
 /// # Thread Safety
 ///
-/// `Method`s are immutable after creation and safe to share between threads.
+/// `Method`s are immutable once added and safe to share between threads.
+/// Swizzling publishes a new `Method` rather than changing the old one.
 #[derive(Clone)]
 pub struct Method {
     /// `Method` selector
@@ -157,6 +247,193 @@ impl fmt::Debug for Method {
     }
 }
 
+impl Method {
+    /// Exchanges the implementations of two methods.
+    ///
+    /// This is Objective-C's `method_exchangeImplementations`: afterwards,
+    /// messages that called `a`'s implementation call `b`'s and vice versa.
+    /// The methods may belong to different classes or categories, and are
+    /// usually found with [`Class::lookup_method`].
+    ///
+    /// `a` and `b` themselves are left unchanged: both are replaced by newly
+    /// published `Method`s, so exchanging them back needs the methods found
+    /// by a fresh lookup.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Method, Selector, RuntimeString};
+    /// use oxidec::runtime::get_global_arena;
+    /// use std::str::FromStr;
+    ///
+    /// let class = Class::new_root("ExchangeExample").unwrap();
+    /// let arena = get_global_arena();
+    /// for name in ["original", "replacement"] {
+    ///     class.add_method(Method {
+    ///         selector: Selector::from_str(name).unwrap(),
+    ///         imp: noop,
+    ///         types: RuntimeString::new("v@:", arena),
+    ///     }).unwrap();
+    /// }
+    ///
+    /// let original = Selector::from_str("original").unwrap();
+    /// let replacement = Selector::from_str("replacement").unwrap();
+    /// Method::exchange_implementations(
+    ///     class.lookup_method(&original).unwrap(),
+    ///     class.lookup_method(&replacement).unwrap(),
+    /// ).unwrap();
+    /// #
+    /// # unsafe extern "C-unwind" fn noop(
+    /// #     _self: oxidec::runtime::object::ObjectPtr,
+    /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
+    /// #     _args: *const *mut u8,
+    /// #     _ret: *mut u8,
+    /// # ) {}
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::SelectorNotFound)` if either method is no longer
+    /// held by a class or category, for instance because it has been
+    /// swizzled since it was looked up, or `Err(Error::OutOfMemory)` if the
+    /// global arena cannot hold the exchanged methods. Neither method is
+    /// exchanged if an error is returned.
+    ///
+    /// # Thread Safety
+    ///
+    /// Both method tables are locked for the exchange, in address order so
+    /// that concurrent exchanges cannot deadlock, so no message sees one
+    /// method exchanged and the other not. A message sent concurrently
+    /// calls either the old or the new implementation, and one sent after
+    /// this returns calls the new one.
+    ///
+    /// # Safety
+    ///
+    /// As with [`Class::swizzle_method`], the caller MUST ensure that the
+    /// two implementations have the same signature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn exchange_implementations(a: &Method, b: &Method) -> Result<()> {
+        if std::ptr::eq(a, b) {
+            return Ok(());
+        }
+
+        let table_a = table_holding(a).ok_or(Error::SelectorNotFound)?;
+        let table_b = table_holding(b).ok_or(Error::SelectorNotFound)?;
+        // Publish both exchanged methods before touching either table, so
+        // that running out of memory leaves both methods as they were
+        let exchanged_a = publish(Method {
+            imp: b.imp,
+            ..a.clone()
+        })?;
+        let exchanged_b = publish(Method {
+            imp: a.imp,
+            ..b.clone()
+        })?;
+
+        // Either method may have been replaced since it was found, so check
+        // again under the lock before swapping anything
+        let holds = |table: &MethodTable, method: &Method| {
+            table
+                .get(&method.selector.hash())
+                .is_some_and(|&held| std::ptr::eq(held, method))
+        };
+
+        if std::ptr::eq(table_a, table_b) {
+            let mut table = table_a.write().recover();
+            if !holds(&table, a) || !holds(&table, b) {
+                return Err(Error::SelectorNotFound);
+            }
+            table.insert(a.selector.hash(), exchanged_a);
+            table.insert(b.selector.hash(), exchanged_b);
+        } else {
+            let a_first =
+                std::ptr::from_ref(table_a) < std::ptr::from_ref(table_b);
+            let (first, second) = if a_first {
+                (table_a, table_b)
+            } else {
+                (table_b, table_a)
+            };
+            let mut first = first.write().recover();
+            let mut second = second.write().recover();
+            let (table_a, table_b) = if a_first {
+                (&mut *first, &mut *second)
+            } else {
+                (&mut *second, &mut *first)
+            };
+            if !holds(table_a, a) || !holds(table_b, b) {
+                return Err(Error::SelectorNotFound);
+            }
+            table_a.insert(a.selector.hash(), exchanged_a);
+            table_b.insert(b.selector.hash(), exchanged_b);
+        }
+
+        invalidate_caches();
+
+        Ok(())
+    }
+}
+
+/// An instance variable declared by a class.
+///
+/// The runtime records instance variables so they can be listed by
+/// introspection, and stores their values in each object at the variable's
+/// offset in its class's instance layout (see [`Object::set_ivar`]).
+///
+/// As in Objective-C, a class's variables follow its superclass's, each
+/// aligned to its size. Offsets are computed from the superclass's layout
+/// whenever the variables are listed, so a superclass can declare more
+/// variables without breaking its subclasses, like Objective-C's
+/// non-fragile ivars, as long as neither has live instances yet.
+///
+/// [`Object::set_ivar`]: crate::runtime::Object::set_ivar
+#[derive(Clone, Debug)]
+pub struct Ivar {
+    /// Variable name
+    pub name: RuntimeString,
+    /// Type encoding of the variable (e.g., "q" for a 64-bit integer)
+    pub types: RuntimeString,
+    /// Offset of the variable in the instance layout, in bytes, set by
+    /// [`Class::ivars`]. The offset of a variable being declared is ignored.
+    pub offset: usize,
+}
+
+impl Ivar {
+    /// Creates an instance variable to declare, with offset 0 until its
+    /// class lays it out.
+    #[must_use]
+    pub fn new(name: RuntimeString, types: RuntimeString) -> Self {
+        Self {
+            name,
+            types,
+            offset: 0,
+        }
+    }
+
+    /// Returns the size of the variable in bytes, which is also its
+    /// alignment.
+    ///
+    /// Types without a fixed size, such as structures, take a pointer-sized
+    /// slot, which is what the runtime stores for any variable.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        let types = self.types.as_str().unwrap_or_default();
+        let mut chars = types.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ty), None) => size_of_type(ty)
+                .filter(|&size| size > 0)
+                .unwrap_or(POINTER_SIZE),
+            _ => POINTER_SIZE,
+        }
+    }
+}
+
+/// Size of an object or pointer variable, and the alignment of instances.
+const POINTER_SIZE: usize = 8;
+
 /// `Class` represents a runtime class definition with methods and inheritance.
 ///
 /// `Class`es are **globally registered** and never deallocated. They provide:
@@ -224,7 +501,7 @@ impl Class {
     /// # Errors
     ///
     /// Returns [`Error::ClassExists`] if a root class with this name already exists
-    /// in the runtime.
+    /// in the runtime, or [`Error::OutOfMemory`] if the class cannot be allocated.
     pub fn new_root(name: &str) -> Result<Self> {
         Self::create_class(name, None)
     }
@@ -261,7 +538,8 @@ impl Class {
     ///
     /// Returns [`Error::ClassExists`] if a class with this name already exists,
     /// or [`Error::InheritanceCycle`] if adding this class would create a cycle
-    /// in the inheritance hierarchy.
+    /// in the inheritance hierarchy, or [`Error::OutOfMemory`] if the class
+    /// cannot be allocated.
     pub fn new(name: &str, super_class: &Class) -> Result<Self> {
         // Check for inheritance cycles
         Self::check_inheritance_cycle(name, super_class)?;
@@ -282,11 +560,11 @@ impl Class {
 
         // Allocate class name in arena
         let arena = get_global_arena();
-        let name_str = RuntimeString::new(name, arena);
+        let name_str = RuntimeString::try_new(name, arena)?;
 
         // Check if class already exists
         {
-            let classes = registry.classes.read().unwrap();
+            let classes = registry.classes.read().recover();
             if classes.contains_key(&name_str) {
                 return Err(Error::ClassAlreadyExists);
             }
@@ -298,7 +576,9 @@ impl Class {
             name: name_str,
             super_class: super_ptr,
             methods: RwLock::new(HashMap::new()),
-            cache: RwLock::new(HashMap::new()),
+            cache: MethodCache::new(),
+            ivars: RwLock::new(Vec::new()),
+            properties: RwLock::new(Vec::new()),
             flags: 0,
             categories: RwLock::new(Vec::new()),
             protocols: RwLock::new(Vec::new()),
@@ -306,21 +586,24 @@ impl Class {
             signature_hook: RwLock::new(None),
             forward_invocation_hook: RwLock::new(None),
             does_not_recognize_hook: RwLock::new(None),
+            finalizer: RwLock::new(None),
+            instances: AtomicUsize::new(0),
+            disposed: AtomicBool::new(false),
         };
 
         // Allocate in global arena
-        let inner_ptr: *mut ClassInner = arena.alloc(class_inner);
-
-        // SAFETY: inner_ptr is not null and properly aligned (arena ensures)
-        let inner_nn =
-            NonNull::new(inner_ptr).expect("`Arena` allocation returned null");
+        let inner_nn = NonNull::from(
+            arena
+                .try_alloc(class_inner)
+                .map_err(|_| Error::OutOfMemory)?,
+        );
 
         // Register in global registry
         {
-            let mut classes = registry.classes.write().unwrap();
+            let mut classes = registry.classes.write().recover();
 
             // Double-check: Another thread might have created it while we waited
-            let name_check = RuntimeString::new(name, arena);
+            let name_check = RuntimeString::try_new(name, arena)?;
             if classes.contains_key(&name_check) {
                 return Err(Error::ClassAlreadyExists);
             }
@@ -349,8 +632,7 @@ impl Class {
             let inner = unsafe { &*ptr };
 
             // Check if we found the new class name in the superclass chain
-            // unwrap() is safe because `RuntimeString` in arena is always valid
-            if inner.name.as_str().unwrap() == new_class_name {
+            if inner.name.as_bytes() == new_class_name.as_bytes() {
                 return Err(Error::InheritanceCycle);
             }
 
@@ -375,16 +657,13 @@ impl Class {
     /// let class = Class::new_root("MyClass").unwrap();
     /// assert_eq!(class.name(), "MyClass");
     /// ```
-    ///
-    /// # Panics
-    ///
-    /// Panics if the class's name string in the arena is invalid UTF-8 (which
-    /// should never happen under normal circumstances).
     #[must_use]
     pub fn name(&self) -> &str {
         // SAFETY: self.inner points to valid `Class`Inner in arena
-        // unwrap() is safe because `RuntimeString` in arena is always valid
-        unsafe { &(*self.inner.as_ptr()).name }.as_str().unwrap()
+        // Names are created from `&str`, so they are always valid UTF-8
+        unsafe { &(*self.inner.as_ptr()).name }
+            .as_str()
+            .unwrap_or("<invalid>")
     }
 
     /// Returns the superclass (if any).
@@ -423,7 +702,9 @@ impl Class {
     /// # Thread Safety
     ///
     /// Multiple threads can add methods concurrently. `RwLock` ensures
-    /// synchronized access to the methods table.
+    /// synchronized access to the methods table. A message sent after this
+    /// returns finds the new method; see the dispatch module's memory
+    /// ordering guarantees for sends that race with it.
     ///
     /// # Example
     ///
@@ -443,8 +724,8 @@ impl Class {
     ///
     /// # Errors
     ///
-    /// This function currently always returns `Ok(())`. The `Result` type is
-    /// used for future extensibility.
+    /// Returns `Err(Error::OutOfMemory)` if the global arena cannot hold the
+    /// method.
     ///
     /// # Panics
     ///
@@ -454,34 +735,32 @@ impl Class {
         // SAFETY: self.inner points to valid `Class`Inner
         let inner = unsafe { &*self.inner.as_ptr() };
 
-        let mut methods = inner.methods.write().unwrap();
         let hash = method.selector.hash();
+        let method = publish(method)?;
 
+        let mut methods = inner.methods.write().recover();
         methods.insert(hash, method);
+        drop(methods);
 
-        // Clear signature cache when methods are added (forwarding may need new signatures)
-        crate::runtime::forwarding::clear_signature_cache();
+        // The method may override one this class or a subclass has cached
+        self.invalidate_cache();
 
         Ok(())
     }
 
     /// Invalidates the method cache for this class.
     ///
-    /// This is called internally when categories are added or methods are
-    /// swizzled to ensure the dispatch system uses the updated method list.
+    /// This is called internally when methods are added, categories are
+    /// attached or methods are swizzled to ensure the dispatch system uses
+    /// the updated method list. Subclasses cache the methods they inherit,
+    /// so this flushes the caches of every class.
     ///
     /// # Thread Safety
     ///
-    /// This method acquires the cache write lock and clears all cached entries.
-    /// Multiple threads can call this concurrently.
+    /// Must be called after the method table is updated and its lock is
+    /// released. Multiple threads can call this concurrently.
     pub(crate) fn invalidate_cache(&self) {
-        // SAFETY: self.inner points to valid `Class`Inner
-        let inner = unsafe { &*self.inner.as_ptr() };
-        let mut cache = inner.cache.write().unwrap();
-        cache.clear();
-
-        // Clear signature cache when methods are swizzled
-        crate::runtime::forwarding::clear_signature_cache();
+        invalidate_caches();
     }
 
     /// Looks up a method by selector (searches inheritance chain).
@@ -498,7 +777,10 @@ impl Class {
     /// # Note
     ///
     /// This walks the inheritance chain from the current class up to the root.
-    /// In Phase 2, method caching will be added for O(1) lookup.
+    /// Message dispatch uses [`Class::lookup_imp`], which caches the result.
+    ///
+    /// The returned method lives for the rest of the program and never
+    /// changes: swizzling or replacing it later publishes a new `Method`.
     ///
     /// # Example
     ///
@@ -523,7 +805,7 @@ impl Class {
     /// Panics if the internal lock is poisoned (indicates a concurrent
     /// access error or panic in another thread).
     #[must_use]
-    pub fn lookup_method(&self, selector: &Selector) -> Option<&Method> {
+    pub fn lookup_method(&self, selector: &Selector) -> Option<&'static Method> {
         let mut current_ptr = Some(self.inner.as_ptr());
 
         while let Some(ptr) = current_ptr {
@@ -531,29 +813,22 @@ impl Class {
             let inner = unsafe { &*ptr };
 
             // Try to find method in this class
-            let methods = inner.methods.read().unwrap();
+            let methods = inner.methods.read().recover();
             let hash = selector.hash();
 
-            if let Some(method) = methods.get(&hash) {
-                // Found! Return reference with extended lifetime
-                // SAFETY: The method is in the arena and never deallocated
-                // We're creating a reference with the same lifetime as &self
-                return unsafe { Some(&*std::ptr::from_ref::<Method>(method)) };
+            if let Some(&method) = methods.get(&hash) {
+                return Some(method);
             }
             drop(methods);
 
             // Check category methods (Phase 3.1)
-            let categories = inner.categories.read().unwrap();
+            let categories = inner.categories.read().recover();
             for cat_ptr in categories.iter() {
                 // SAFETY: cat_ptr points to valid CategoryInner
                 let cat = unsafe { &*cat_ptr.as_ptr() };
-                let cat_methods = cat.methods.read().unwrap();
-                if let Some(method) = cat_methods.get(&hash) {
-                    // Found in category!
-                    // SAFETY: The method is in the arena and never deallocated
-                    return unsafe {
-                        Some(&*std::ptr::from_ref::<Method>(method))
-                    };
+                let cat_methods = cat.methods.read().recover();
+                if let Some(&method) = cat_methods.get(&hash) {
+                    return Some(method);
                 }
             }
             drop(categories);
@@ -597,38 +872,20 @@ impl Class {
     #[must_use]
     pub fn lookup_imp(&self, selector: &Selector) -> Option<Imp> {
         let hash = selector.hash();
+        // SAFETY: self.inner points to valid `Class`Inner
+        let inner = unsafe { &*self.inner.as_ptr() };
 
         // Fast path: Check cache
-        {
-            // SAFETY: self.inner points to valid `Class`Inner
-            let inner = unsafe { &*self.inner.as_ptr() };
-            let cache = inner.cache.read().unwrap();
-
-            if let Some((cached_class, imp)) = cache.get(&hash) {
-                // Verify cache is still valid (handles method swizzling)
-                if std::ptr::eq(cached_class.as_ptr(), self.inner.as_ptr()) {
-                    return Some(*imp);
-                }
-                // Cache entry is stale, fall through to slow path
-            }
-        } // Release read lock
-
-        // Slow path: Walk inheritance chain
-        if let Some(method) = self.lookup_method(selector) {
-            let imp = method.imp;
-
-            // Update cache
-            {
-                // SAFETY: self.inner points to valid `Class`Inner
-                let inner = unsafe { &*self.inner.as_ptr() };
-                let mut cache = inner.cache.write().unwrap();
-                cache.insert(hash, (self.inner, imp));
-            }
-
-            Some(imp)
-        } else {
-            None
+        if let Some(imp) = inner.cache.get(hash) {
+            return Some(imp);
         }
+
+        // Slow path: Walk inheritance chain, noting the generation first so
+        // that a method changed during the walk is not cached
+        let epoch = crate::runtime::dispatch::cache_epoch();
+        let imp = self.lookup_method(selector)?.imp;
+        inner.cache.insert(epoch, hash, imp);
+        Some(imp)
     }
 
     /// Checks if this class inherits from the given class.
@@ -720,7 +977,7 @@ impl Class {
         let inner = unsafe { &*self.inner.as_ptr() };
 
         // Check for duplicate protocol adoption
-        let protocols = inner.protocols.read().unwrap();
+        let protocols = inner.protocols.read().recover();
         for proto_ptr in protocols.iter() {
             if proto_ptr.as_ptr() == protocol.inner.as_ptr() {
                 return Err(Error::ProtocolAlreadyAdopted);
@@ -730,7 +987,7 @@ impl Class {
 
         // Add protocol to class
         {
-            let mut protocols = inner.protocols.write().unwrap();
+            let mut protocols = inner.protocols.write().recover();
             protocols.push(protocol.inner);
         }
 
@@ -740,6 +997,237 @@ impl Class {
         Ok(())
     }
 
+    /// Declares an instance variable on this class.
+    ///
+    /// # Arguments
+    ///
+    /// * `ivar` - Instance variable to declare
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{Class, Ivar, RuntimeString, get_global_arena};
+    ///
+    /// let class = Class::new_root("IvarExample").unwrap();
+    /// let arena = get_global_arena();
+    /// class
+    ///     .add_ivar(Ivar::new(
+    ///         RuntimeString::new("count", arena),
+    ///         RuntimeString::new("q", arena),
+    ///     ))
+    ///     .unwrap();
+    /// assert_eq!(class.ivars()[0].name.as_str().unwrap(), "count");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::IvarAlreadyExists)` if this class or one of its
+    /// superclasses already declares a variable with the same name, or
+    /// `Err(Error::IvarLayoutInUse)` if this class or a subclass has live
+    /// instances, which are laid out without the variable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn add_ivar(&self, ivar: Ivar) -> Result<()> {
+        let in_use = self.instance_count() > 0
+            || subclasses(self).iter().any(|class| class.instance_count() > 0);
+        if in_use {
+            return Err(Error::IvarLayoutInUse {
+                class: self.name().to_string(),
+            });
+        }
+
+        let mut current = Some(self.clone());
+        while let Some(class) = current {
+            if class.ivars().iter().any(|declared| declared.name == ivar.name) {
+                return Err(Error::IvarAlreadyExists);
+            }
+            current = class.super_class();
+        }
+
+        // SAFETY: self.inner points to valid ClassInner
+        let inner = unsafe { &*self.inner.as_ptr() };
+        inner.ivars.write().recover().push(ivar);
+        Ok(())
+    }
+
+    /// Returns the instance variables declared by this class, with their
+    /// offsets.
+    ///
+    /// Variables declared by superclasses are not included;
+    /// [`instance_variables`] lists those too. The first variable starts
+    /// where the superclass's instances end (see [`Class::instance_size`]).
+    ///
+    /// [`instance_variables`]: crate::runtime::instance_variables
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    #[must_use]
+    pub fn ivars(&self) -> Vec<Ivar> {
+        let mut offset = self.superclass_size();
+
+        // SAFETY: self.inner points to valid ClassInner
+        let inner = unsafe { &*self.inner.as_ptr() };
+        let mut ivars = inner.ivars.read().recover().clone();
+        for ivar in &mut ivars {
+            let size = ivar.size();
+            ivar.offset = offset.next_multiple_of(size);
+            offset = ivar.offset + size;
+        }
+        ivars
+    }
+
+    /// Returns the size in bytes of the instance variables of this class's
+    /// instances, including those its superclasses declare, rounded up to
+    /// pointer alignment.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{Class, Ivar, RuntimeString, get_global_arena};
+    ///
+    /// let arena = get_global_arena();
+    /// let ivar = |name, types| {
+    ///     let name = RuntimeString::new(name, arena);
+    ///     Ivar::new(name, RuntimeString::new(types, arena))
+    /// };
+    /// let base = Class::new_root("LayoutBase").unwrap();
+    /// base.add_ivar(ivar("flag", "i")).unwrap();
+    /// let derived = Class::new("LayoutDerived", &base).unwrap();
+    /// derived.add_ivar(ivar("count", "q")).unwrap();
+    ///
+    /// assert_eq!(base.instance_size(), 8);
+    /// assert_eq!(derived.ivars()[0].offset, 8);
+    /// assert_eq!(derived.instance_size(), 16);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    #[must_use]
+    pub fn instance_size(&self) -> usize {
+        let end = self.ivars().last().map_or_else(
+            || self.superclass_size(),
+            |ivar| ivar.offset + ivar.size(),
+        );
+        end.next_multiple_of(POINTER_SIZE)
+    }
+
+    /// Returns the instance size of the superclass, where this class's
+    /// variables start, or 0 for a root class.
+    fn superclass_size(&self) -> usize {
+        self.super_class()
+            .map_or(0, |superclass| superclass.instance_size())
+    }
+
+    /// Declares a property on this class.
+    ///
+    /// Declares the instance variable backing the property and synthesizes
+    /// its accessors, keeping any this class already defines. See
+    /// [`property`](crate::runtime::property) for what the accessors do.
+    ///
+    /// # Arguments
+    ///
+    /// * `property` - Property to declare
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::property::{Property, PropertyAttributes};
+    /// use oxidec::runtime::{Class, RuntimeString, Selector, get_global_arena};
+    /// use std::str::FromStr;
+    ///
+    /// let class = Class::new_root("PropertyExample").unwrap();
+    /// let arena = get_global_arena();
+    /// class
+    ///     .add_property(Property {
+    ///         name: RuntimeString::new("identifier", arena),
+    ///         types: RuntimeString::new("q", arena),
+    ///         attributes: PropertyAttributes {
+    ///             readonly: true,
+    ///             ..PropertyAttributes::default()
+    ///         },
+    ///     })
+    ///     .unwrap();
+    ///
+    /// let property = &class.properties()[0];
+    /// assert_eq!(property.attribute_string(), "Tq,R,Videntifier");
+    ///
+    /// // Readonly, so only the getter is synthesized
+    /// let getter = Selector::from_str("identifier").unwrap();
+    /// let setter = Selector::from_str("setIdentifier:").unwrap();
+    /// assert!(class.lookup_method(&getter).is_some());
+    /// assert!(class.lookup_method(&setter).is_none());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::InvalidProperty)` if the property's accessors
+    /// cannot be synthesized, `Err(Error::PropertyAlreadyExists)` if this
+    /// class or one of its superclasses already declares a property with
+    /// the same name, `Err(Error::IvarAlreadyExists)` if one declares an
+    /// instance variable with that name, or `Err(Error::OutOfMemory)` if
+    /// the global arena cannot hold the accessors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn add_property(&self, property: Property) -> Result<()> {
+        property.validate()?;
+        let mut current = Some(self.clone());
+        while let Some(class) = current {
+            let declared = class.properties();
+            if declared.iter().any(|declared| declared.name == property.name) {
+                return Err(Error::PropertyAlreadyExists);
+            }
+            current = class.super_class();
+        }
+        let accessors = property.accessors()?;
+        self.add_ivar(property.ivar())?;
+
+        // SAFETY: self.inner points to valid ClassInner
+        let inner = unsafe { &*self.inner.as_ptr() };
+
+        // Record the property first: the accessors find it when called
+        inner.properties.write().recover().push(property);
+
+        let mut methods = inner.methods.write().recover();
+        for accessor in accessors {
+            if let Entry::Vacant(entry) = methods.entry(accessor.selector.hash())
+            {
+                entry.insert(publish(accessor)?);
+            }
+        }
+        drop(methods);
+
+        self.invalidate_cache();
+        Ok(())
+    }
+
+    /// Returns the properties declared by this class.
+    ///
+    /// Properties declared by superclasses are not included;
+    /// [`class_properties`] lists those too.
+    ///
+    /// [`class_properties`]: crate::runtime::class_properties
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    #[must_use]
+    pub fn properties(&self) -> Vec<Property> {
+        // SAFETY: self.inner points to valid ClassInner
+        let inner = unsafe { &*self.inner.as_ptr() };
+        inner.properties.read().recover().clone()
+    }
+
     /// Checks if this class conforms to a protocol.
     ///
     /// # Arguments
@@ -777,7 +1265,7 @@ impl Class {
         let inner = unsafe { &*self.inner.as_ptr() };
 
         // Check this class's protocols
-        let protocols = inner.protocols.read().unwrap();
+        let protocols = inner.protocols.read().recover();
         for proto_ptr in protocols.iter() {
             if proto_ptr.as_ptr() == protocol.inner.as_ptr() {
                 return true;
@@ -793,33 +1281,18 @@ impl Class {
         }
     }
 
-    /// Returns all protocols this class conforms to.
+    /// Returns all methods defined directly on this class.
     ///
-    /// Includes protocols from superclasses (conformance is transitive).
+    /// Does not include methods from categories or superclasses.
     ///
     /// # Returns
     ///
-    /// Vector of protocols this class conforms to.
+    /// Vector of methods, in selector name order, so that the same class
+    /// lists its methods in the same order in every run.
     ///
     /// # Thread Safety
     ///
-    /// Multiple threads can query protocols concurrently.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use oxidec::{Class, Protocol};
-    ///
-    /// let class = Class::new_root("MyClass").unwrap();
-    /// let protocol1 = Protocol::new("Protocol1", None).unwrap();
-    /// let protocol2 = Protocol::new("Protocol2", None).unwrap();
-    ///
-    /// class.add_protocol(&protocol1).unwrap();
-    /// class.add_protocol(&protocol2).unwrap();
-    ///
-    /// let protocols = class.protocols();
-    /// assert_eq!(protocols.len(), 2);
-    /// ```
+    /// Multiple threads can query methods concurrently.
     ///
     /// # Panics
     ///
@@ -832,11 +1305,13 @@ impl Class {
         // Add methods from this class
         // SAFETY: self.inner points to valid ClassInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let methods = inner.methods.read().unwrap();
-        for method in methods.values() {
+        let methods = inner.methods.read().recover();
+        for &method in methods.values() {
             result.push(method.clone());
         }
 
+        // The table is keyed by selector hash, which orders nothing useful
+        result.sort_by(|a, b| a.selector.name().cmp(b.selector.name()));
         result
     }
 
@@ -855,7 +1330,7 @@ impl Class {
         // Add protocols from this class
         // SAFETY: self.inner points to valid ClassInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let protocols = inner.protocols.read().unwrap();
+        let protocols = inner.protocols.read().recover();
         for &proto_ptr in protocols.iter() {
             result.push(Protocol { inner: proto_ptr });
         }
@@ -920,7 +1395,7 @@ impl Class {
     /// // Now validation passes
     /// class.validate_protocol_conformance(&protocol).unwrap();
     /// #
-    /// # unsafe extern "C" fn required_method_impl(
+    /// # unsafe extern "C-unwind" fn required_method_impl(
     /// #     _self: oxidec::runtime::object::ObjectPtr,
     /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
     /// #     _args: *const *mut u8,
@@ -1005,13 +1480,13 @@ impl Class {
     /// // Now calls to doSomething invoke replacement_imp
     /// // Can restore: class.swizzle_method(&sel, saved_original)?;
     /// #
-    /// # unsafe extern "C" fn my_original_impl(
+    /// # unsafe extern "C-unwind" fn my_original_impl(
     /// #     _self: oxidec::runtime::object::ObjectPtr,
     /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
     /// #     _args: *const *mut u8,
     /// #     _ret: *mut u8,
     /// # ) {}
-    /// # unsafe extern "C" fn my_replacement_impl(
+    /// # unsafe extern "C-unwind" fn my_replacement_impl(
     /// #     _self: oxidec::runtime::object::ObjectPtr,
     /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
     /// #     _args: *const *mut u8,
@@ -1022,7 +1497,15 @@ impl Class {
     /// # Errors
     ///
     /// Returns `Err(Error::SelectorNotFound)` if the selector doesn't exist
-    /// in this class's method table (excluding inherited methods).
+    /// in this class's method table (excluding inherited methods), or
+    /// `Err(Error::OutOfMemory)` if the global arena cannot hold the
+    /// swizzled method.
+    ///
+    /// # Thread Safety
+    ///
+    /// The swizzled method is published as a new `Method`, so a message sent
+    /// concurrently calls either the original or the new implementation,
+    /// and one sent after this returns calls the new one.
     ///
     /// # Safety
     ///
@@ -1049,15 +1532,19 @@ impl Class {
         let inner = unsafe { &*self.inner.as_ptr() };
 
         // Acquire write lock for thread-safe modification
-        let mut methods = inner.methods.write().unwrap();
+        let mut methods = inner.methods.write().recover();
 
         // Find method in this class's method table (does not search superclass)
         // Rationale: Swizzling should only affect this class, not parent
-        let method = methods.get_mut(&hash).ok_or(Error::SelectorNotFound)?;
+        let entry = methods.get_mut(&hash).ok_or(Error::SelectorNotFound)?;
 
-        // Swap implementation
-        let original_imp = method.imp;
-        method.imp = new_imp;
+        // Publish a copy with the new implementation rather than writing to
+        // the old method, which lookups may be reading without the lock
+        let original_imp = entry.imp;
+        *entry = publish(Method {
+            imp: new_imp,
+            ..(*entry).clone()
+        })?;
 
         // Drop write lock before invalidating cache (avoid deadlock)
         drop(methods);
@@ -1070,6 +1557,108 @@ impl Class {
         Ok(original_imp)
     }
 
+    /// Replaces the implementation this class uses for a selector.
+    ///
+    /// This is Objective-C's `class_replaceMethod`. Unlike
+    /// [`Class::swizzle_method`], the method does not have to be defined by
+    /// this class: if it is inherited or comes from a category, this class
+    /// gets its own method with the same type encoding and the new
+    /// implementation, overriding it for this class and its subclasses only.
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - The method selector to replace
+    /// * `imp` - The new implementation function pointer
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(previous_imp)` - the implementation instances of this class
+    /// used before, which can be passed back in to undo the replacement.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Method, Selector, RuntimeString};
+    /// use oxidec::runtime::get_global_arena;
+    /// use std::str::FromStr;
+    ///
+    /// let parent = Class::new_root("ReplaceParent").unwrap();
+    /// let child = Class::new("ReplaceChild", &parent).unwrap();
+    /// let sel = Selector::from_str("describe").unwrap();
+    /// parent.add_method(Method {
+    ///     selector: sel.clone(),
+    ///     imp: inherited,
+    ///     types: RuntimeString::new("v@:", get_global_arena()),
+    /// }).unwrap();
+    ///
+    /// // The child now overrides describe; the parent is unaffected
+    /// let previous = child.replace_method(&sel, replacement).unwrap();
+    /// assert_eq!(previous as *const (), inherited as *const ());
+    /// #
+    /// # unsafe extern "C-unwind" fn inherited(
+    /// #     _self: oxidec::runtime::object::ObjectPtr,
+    /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
+    /// #     _args: *const *mut u8,
+    /// #     _ret: *mut u8,
+    /// # ) {}
+    /// # unsafe extern "C-unwind" fn replacement(
+    /// #     _self: oxidec::runtime::object::ObjectPtr,
+    /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
+    /// #     _args: *const *mut u8,
+    /// #     _ret: *mut u8,
+    /// # ) {}
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::SelectorNotFound)` if neither this class nor its
+    /// categories or ancestors respond to the selector, or
+    /// `Err(Error::OutOfMemory)` if the global arena cannot hold the new
+    /// method.
+    ///
+    /// # Thread Safety
+    ///
+    /// As with [`Class::swizzle_method`], the new method is published under
+    /// the method table's write lock and caches are invalidated before this
+    /// returns, so a message sent after this returns calls `imp`.
+    ///
+    /// # Safety
+    ///
+    /// The caller MUST ensure that `imp` has the exact same function signature
+    /// as the method it replaces. Using a mismatched signature is **undefined
+    /// behavior**.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn replace_method(&self, selector: &Selector, imp: Imp) -> Result<Imp> {
+        let hash = selector.hash();
+        let found = self
+            .lookup_method(selector)
+            .ok_or(Error::SelectorNotFound)?;
+
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        let mut methods = inner.methods.write().recover();
+
+        // The class may have defined the method since the lookup
+        let current = methods.get(&hash).copied().unwrap_or(found);
+        let previous_imp = current.imp;
+        methods.insert(
+            hash,
+            publish(Method {
+                imp,
+                ..current.clone()
+            })?,
+        );
+        drop(methods);
+
+        self.invalidate_cache();
+
+        Ok(previous_imp)
+    }
+
     /// Returns the hash of this class's inner pointer.
     ///
     /// This is used by the forwarding cache to create a unique key for
@@ -1114,7 +1703,7 @@ impl Class {
     ) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.forwarding_hook.write().unwrap() = Some(hook);
+        *inner.forwarding_hook.write().recover() = Some(hook);
     }
 
     /// Clears this class's forwarding hook.
@@ -1126,7 +1715,178 @@ impl Class {
     pub fn clear_forwarding_hook(&self) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.forwarding_hook.write().unwrap() = None;
+        *inner.forwarding_hook.write().recover() = None;
+    }
+
+    /// Sets the finalizer run when an instance of this class, or of a
+    /// subclass, is deallocated.
+    ///
+    /// Finalizers run from the instance's class up to the root class, so a
+    /// subclass's finalizer runs before its superclass's. See
+    /// [`Finalizer`](crate::runtime::object::Finalizer).
+    ///
+    /// # Thread Safety
+    ///
+    /// This method is thread-safe. The last finalizer set wins.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Object};
+    ///
+    /// let class = Class::new_root("FinalizerExample").unwrap();
+    /// class.set_finalizer(|obj| {
+    ///     println!("deallocating a {}", obj.class().name());
+    /// });
+    ///
+    /// drop(Object::new(&class).unwrap());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn set_finalizer(&self, finalizer: crate::runtime::object::Finalizer) {
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        if inner.finalizer.write().recover().replace(finalizer).is_none() {
+            FINALIZED_CLASSES.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Returns the number of live instances of this class, not counting
+    /// instances of its subclasses.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Object};
+    ///
+    /// let class = Class::new_root("InstanceCountExample").unwrap();
+    /// let object = Object::new(&class).unwrap();
+    /// assert_eq!(class.instance_count(), 1);
+    ///
+    /// drop(object);
+    /// assert_eq!(class.instance_count(), 0);
+    /// ```
+    #[must_use]
+    pub fn instance_count(&self) -> usize {
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        inner.instances.load(Ordering::Acquire)
+    }
+
+    /// Counts a new instance of this class.
+    ///
+    /// The count is raised before the disposed flag is read, and
+    /// [`Class::unregister`] sets the flag before reading the count, so an
+    /// instance created while the class is disposed is either seen by the
+    /// disposal or refused here.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ClassDisposed`] if the class was disposed.
+    pub(crate) fn count_instance(&self) -> Result<()> {
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        inner.instances.fetch_add(1, Ordering::SeqCst);
+        if inner.disposed.load(Ordering::SeqCst) {
+            // A disposal refused for live instances sets the flag only
+            // while it holds the registry's lock, so wait for it to settle
+            let _settled = REGISTRY.get().map(|r| r.classes.read().recover());
+            if inner.disposed.load(Ordering::SeqCst) {
+                inner.instances.fetch_sub(1, Ordering::SeqCst);
+                return Err(Error::ClassDisposed {
+                    class: self.name().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes this class from the registry, freeing its name, and marks it
+    /// disposed so it can no longer be instantiated.
+    ///
+    /// The class's memory stays allocated, so handles to it remain valid.
+    /// Registered subclasses and live instances are checked and the class
+    /// removed under the registry's lock, so neither can appear in between.
+    /// Method caches are flushed once the class is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ClassNotDisposable`] if this class is not the one
+    /// registered under its name, or if it has registered subclasses or
+    /// live instances.
+    pub(crate) fn unregister(&self) -> Result<()> {
+        let not_disposable = |reason: String| Error::ClassNotDisposable {
+            class: self.name().to_string(),
+            reason,
+        };
+        let Some(registry) = REGISTRY.get() else {
+            return Err(not_disposable("it is not registered".to_string()));
+        };
+        let name = RuntimeString::try_new(self.name(), get_global_arena())?;
+        let mut classes = registry.classes.write().recover();
+        if classes.get(&name) != Some(&self.inner) {
+            return Err(not_disposable("it is not registered".to_string()));
+        }
+        let subclass = classes.values().find(|candidate| {
+            // SAFETY: registered classes are valid ClassInners in the arena
+            unsafe { (*candidate.as_ptr()).super_class == Some(self.inner) }
+        });
+        if let Some(&inner) = subclass {
+            return Err(not_disposable(format!(
+                "subclass '{}' is registered",
+                Class { inner }.name()
+            )));
+        }
+
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        inner.disposed.store(true, Ordering::SeqCst);
+        let instances = inner.instances.load(Ordering::SeqCst);
+        if instances > 0 {
+            inner.disposed.store(false, Ordering::SeqCst);
+            let plural = if instances == 1 { "" } else { "s" };
+            return Err(not_disposable(format!(
+                "it has {instances} live instance{plural}"
+            )));
+        }
+        classes.remove(&name);
+        drop(classes);
+
+        // Advance the cache generation, so no cache keeps the class's
+        // methods for its subclasses or a class reusing its name
+        invalidate_caches();
+        Ok(())
+    }
+
+    /// Clears this class's finalizer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub fn clear_finalizer(&self) {
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        if inner.finalizer.write().recover().take().is_some() {
+            FINALIZED_CLASSES.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Gets this class's own finalizer (if set), not inherited ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the internal lock is poisoned (indicates a concurrent
+    /// access error or panic in another thread).
+    pub(crate) fn finalizer(
+        &self,
+    ) -> Option<crate::runtime::object::Finalizer> {
+        // SAFETY: self.inner points to valid ClassInner allocated in arena
+        let inner = unsafe { &*self.inner.as_ptr() };
+        *inner.finalizer.read().recover()
     }
 
     /// Gets this class's forwarding hook (if set).
@@ -1142,7 +1902,7 @@ impl Class {
     ) -> Option<crate::runtime::forwarding::ClassForwardingHook> {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.forwarding_hook.read().unwrap()
+        *inner.forwarding_hook.read().recover()
     }
 
     /// Sets the method signature lookup hook for this class (Stage 2).
@@ -1193,7 +1953,7 @@ impl Class {
     pub fn set_signature_hook(&self, hook: crate::runtime::forwarding::MethodSignatureHook) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.signature_hook.write().unwrap() = Some(hook);
+        *inner.signature_hook.write().recover() = Some(hook);
     }
 
     /// Clears this class's method signature lookup hook.
@@ -1205,7 +1965,7 @@ impl Class {
     pub fn clear_signature_hook(&self) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.signature_hook.write().unwrap() = None;
+        *inner.signature_hook.write().recover() = None;
     }
 
     /// Sets the forward invocation hook for this class (Stage 3).
@@ -1256,7 +2016,7 @@ impl Class {
     ) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.forward_invocation_hook.write().unwrap() = Some(hook);
+        *inner.forward_invocation_hook.write().recover() = Some(hook);
     }
 
     /// Clears this class's forward invocation hook.
@@ -1268,7 +2028,7 @@ impl Class {
     pub fn clear_forward_invocation_hook(&self) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.forward_invocation_hook.write().unwrap() = None;
+        *inner.forward_invocation_hook.write().recover() = None;
     }
 
     /// Sets the does not recognize hook for this class (Stage 4).
@@ -1311,7 +2071,7 @@ impl Class {
     pub fn set_does_not_recognize_hook(&self, hook: crate::runtime::forwarding::DoesNotRecognizeHook) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.does_not_recognize_hook.write().unwrap() = Some(hook);
+        *inner.does_not_recognize_hook.write().recover() = Some(hook);
     }
 
     /// Clears this class's does not recognize hook.
@@ -1323,7 +2083,7 @@ impl Class {
     pub fn clear_does_not_recognize_hook(&self) {
         // SAFETY: self.inner points to valid ClassInner allocated in arena
         let inner = unsafe { &*self.inner.as_ptr() };
-        *inner.does_not_recognize_hook.write().unwrap() = None;
+        *inner.does_not_recognize_hook.write().recover() = None;
     }
 }
 
@@ -1371,7 +2131,7 @@ mod tests {
     use super::*;
 
     /// Test helper function that does nothing (no-op method implementation)
-    unsafe extern "C" fn test_method_noop(
+    unsafe extern "C-unwind" fn test_method_noop(
         _self: crate::runtime::object::ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
@@ -1584,4 +2344,107 @@ mod tests {
         let found_parent = parent.lookup_method(&sel);
         assert!(found_parent.is_some());
     }
+
+    /// Copies the receiver by making a new instance of its class
+    unsafe extern "C-unwind" fn test_method_copy(
+        this: crate::runtime::object::ObjectPtr,
+        _cmd: SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch passes a live receiver
+        let this =
+            unsafe { crate::runtime::Object::borrow_raw(this.as_raw_ptr()) };
+        let copy = crate::runtime::Object::new(&this.class()).unwrap();
+        let copy = std::mem::ManuallyDrop::new(copy);
+        let ptr = copy.as_raw().as_raw_ptr() as usize;
+        unsafe { ret.cast::<usize>().write_unaligned(ptr) };
+    }
+
+    #[test]
+    fn test_properties_synthesize_accessors() {
+        use crate::runtime::property::PropertyAttributes;
+        use crate::runtime::{MessageArgs, Object};
+
+        let arena = get_global_arena();
+        let property = |name, types, attributes| Property {
+            name: RuntimeString::new(name, arena),
+            types: RuntimeString::new(types, arena),
+            attributes,
+        };
+        let send = |obj: &Object, name: &str, args: &MessageArgs| {
+            let sel = Selector::from_str(name).unwrap();
+            Object::send_message(obj, &sel, args).unwrap()
+        };
+
+        let base = Class::new_root("PropertyBase").unwrap();
+        let readonly = PropertyAttributes {
+            readonly: true,
+            ..PropertyAttributes::default()
+        };
+        base.add_property(property("identifier", "q", readonly))
+            .unwrap();
+        let class = Class::new("PropertyDerived", &base).unwrap();
+
+        // A hand-written getter is kept
+        class
+            .add_method(Method {
+                selector: Selector::from_str("count").unwrap(),
+                imp: test_method_noop,
+                types: RuntimeString::new("v@:", arena),
+            })
+            .unwrap();
+        let read_write = PropertyAttributes::default();
+        class.add_property(property("count", "q", read_write)).unwrap();
+        let copy = PropertyAttributes {
+            copy: true,
+            ..PropertyAttributes::default()
+        };
+        class.add_property(property("label", "@", copy)).unwrap();
+
+        let obj = Object::new(&class).unwrap();
+        assert_eq!(send(&obj, "setCount:", &MessageArgs::one(5)), None);
+        assert_eq!(obj.ivar("count"), Ok(5));
+        assert_eq!(send(&obj, "count", &MessageArgs::None), None);
+
+        // Readonly properties only have a getter, inherited here
+        obj.set_ivar("identifier", 9).unwrap();
+        assert_eq!(send(&obj, "identifier", &MessageArgs::None), Some(9));
+        let setter = Selector::from_str("setIdentifier:").unwrap();
+        assert!(!obj.responds_to(&setter));
+
+        // Copied properties store the result of sending copy
+        let label_class = Class::new_root("PropertyLabel").unwrap();
+        label_class
+            .add_method(Method {
+                selector: Selector::from_str("copy").unwrap(),
+                imp: test_method_copy,
+                types: RuntimeString::new("@@:", arena),
+            })
+            .unwrap();
+        let label = Object::new(&label_class).unwrap();
+        let label_ptr = label.as_raw().as_raw_ptr() as usize;
+        send(&obj, "setLabel:", &MessageArgs::one(label_ptr));
+        let stored = obj.object_ivar("label").unwrap().unwrap();
+        assert_ne!(stored, label);
+        assert_eq!(stored.class(), label_class);
+        assert_eq!(stored.refcount(), 2);
+        assert_eq!(label.refcount(), 1);
+        let got = send(&obj, "label", &MessageArgs::None);
+        assert_eq!(got, Some(stored.as_raw().as_raw_ptr() as usize));
+
+        assert_eq!(
+            class.add_property(property("identifier", "q", readonly)),
+            Err(Error::PropertyAlreadyExists)
+        );
+        assert!(matches!(
+            class.add_property(property("flags", "q", copy)),
+            Err(Error::InvalidProperty { .. })
+        ));
+        let names: Vec<_> = crate::runtime::class_properties(&class)
+            .iter()
+            .map(|property| property.getter_name().to_string())
+            .collect();
+        assert_eq!(names, ["identifier", "count", "label"]);
+    }
 }
@@ -0,0 +1,254 @@
+//! Codable conformance for the ``OxideC`` runtime.
+//!
+//! `Encodable` and `Decodable` are the protocols of classes whose instances
+//! can be written to and read back from a serialization format. The runtime
+//! records a class's fields as instance variables with type encodings, so
+//! it can synthesize both conformances for any class whose fields all have
+//! a codable type:
+//!
+//! - Integers and booleans (`c`, `s`, `i`, `l`, `q`, their unsigned forms
+//!   and `B`)
+//! - Floats (`f`, `d`)
+//! - C strings (`*`)
+//! - Objects (`@`), which a coder encodes in turn
+//!
+//! [`synthesize_codable`] checks the fields, adopts both protocols and
+//! returns the fields in the order a coder writes them. Field values need
+//! not live in the runtime's instance variable storage, so coders ask the
+//! code that owns the object for each value by field name; the standard
+//! library's coders do so.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::codable::{FieldKind, encodable, synthesize_codable};
+//! use oxidec::runtime::{Class, Ivar, RuntimeString, get_global_arena};
+//!
+//! let arena = get_global_arena();
+//! let point = Class::new_root("CodablePoint").unwrap();
+//! for name in ["x", "y"] {
+//!     let types = RuntimeString::new("d", arena);
+//!     let name = RuntimeString::new(name, arena);
+//!     point.add_ivar(Ivar::new(name, types)).unwrap();
+//! }
+//!
+//! let fields = synthesize_codable(&point).unwrap();
+//! assert_eq!(fields[1].name.as_str().unwrap(), "y");
+//! assert_eq!(fields[1].kind, FieldKind::Float);
+//! assert!(point.conforms_to(&encodable().unwrap()));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::runtime::introspection::{all_protocols, instance_variables};
+use crate::runtime::{Class, Protocol, RuntimeString};
+use std::sync::OnceLock;
+
+/// Name of the protocol of classes whose instances can be encoded.
+pub const ENCODABLE: &str = "Encodable";
+
+/// Name of the protocol of classes whose instances can be decoded.
+pub const DECODABLE: &str = "Decodable";
+
+/// The `Encodable` protocol, once registered.
+static ENCODABLE_PROTOCOL: OnceLock<Protocol> = OnceLock::new();
+
+/// The `Decodable` protocol, once registered.
+static DECODABLE_PROTOCOL: OnceLock<Protocol> = OnceLock::new();
+
+/// What a codable field holds, as its type encoding says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// An integer of any width, or a boolean stored as one
+    Integer,
+    /// A boolean
+    Bool,
+    /// A floating-point number
+    Float,
+    /// A string
+    String,
+    /// An object, or a value the compiler boxes as one
+    Object,
+}
+
+impl FieldKind {
+    /// Returns the kind of field with type encoding `encoding`, or `None`
+    /// if no coder can encode it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::codable::FieldKind;
+    ///
+    /// assert_eq!(FieldKind::from_encoding("q"), Some(FieldKind::Integer));
+    /// assert_eq!(FieldKind::from_encoding("@"), Some(FieldKind::Object));
+    /// assert_eq!(FieldKind::from_encoding("^"), None);
+    /// ```
+    #[must_use]
+    pub fn from_encoding(encoding: &str) -> Option<Self> {
+        let kind = match encoding {
+            "c" | "C" | "s" | "S" | "i" | "I" | "l" | "L" | "q" | "Q" => {
+                Self::Integer
+            }
+            "B" => Self::Bool,
+            "f" | "d" => Self::Float,
+            "*" => Self::String,
+            "@" => Self::Object,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// A field of a codable class.
+#[derive(Debug, Clone)]
+pub struct CodableField {
+    /// Field name, the key a coder writes the value under
+    pub name: RuntimeString,
+    /// What the field holds
+    pub kind: FieldKind,
+}
+
+/// Returns the `Encodable` protocol, registering it on first use.
+///
+/// # Errors
+///
+/// Returns `Err(Error::OutOfMemory)` if the protocol cannot be allocated.
+pub fn encodable() -> Result<Protocol> {
+    protocol(&ENCODABLE_PROTOCOL, ENCODABLE)
+}
+
+/// Returns the `Decodable` protocol, registering it on first use.
+///
+/// # Errors
+///
+/// Returns `Err(Error::OutOfMemory)` if the protocol cannot be allocated.
+pub fn decodable() -> Result<Protocol> {
+    protocol(&DECODABLE_PROTOCOL, DECODABLE)
+}
+
+/// Returns the protocol named `name`, registering it if no protocol of
+/// that name exists yet.
+fn protocol(cell: &OnceLock<Protocol>, name: &str) -> Result<Protocol> {
+    if let Some(protocol) = cell.get() {
+        return Ok(protocol.clone());
+    }
+    let protocol = match Protocol::new(name, None) {
+        Ok(protocol) => protocol,
+        // Registered by another thread, or by a program declaring its own
+        Err(Error::ProtocolAlreadyExists) => all_protocols()
+            .into_iter()
+            .find(|protocol| protocol.name() == name)
+            .ok_or(Error::ProtocolAlreadyExists)?,
+        Err(err) => return Err(err),
+    };
+    Ok(cell.get_or_init(|| protocol).clone())
+}
+
+/// Synthesizes `Encodable` and `Decodable` conformance for `class` from
+/// its fields.
+///
+/// Returns the fields a coder reads and writes: the instance variables of
+/// the class and its superclasses, inherited ones first, each class's in
+/// declaration order. A class that already conforms keeps its
+/// conformance.
+///
+/// # Errors
+///
+/// Returns `Err(Error::NotCodable)` naming the first field whose type no
+/// coder can encode, in which case the class adopts neither protocol.
+///
+/// # Panics
+///
+/// Panics if the class's internal lock is poisoned, unless the `no-abort`
+/// feature is enabled.
+pub fn synthesize_codable(class: &Class) -> Result<Vec<CodableField>> {
+    let fields = instance_variables(class)
+        .into_iter()
+        .map(|ivar| {
+            let types = ivar.types.as_str().unwrap_or_default();
+            match FieldKind::from_encoding(types) {
+                Some(kind) => Ok(CodableField {
+                    name: ivar.name,
+                    kind,
+                }),
+                None => Err(Error::NotCodable {
+                    field: ivar.name.as_str().unwrap_or_default().to_string(),
+                }),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for protocol in [encodable()?, decodable()?] {
+        if class.conforms_to(&protocol) {
+            continue;
+        }
+        match class.add_protocol(&protocol) {
+            // Adopted concurrently by another thread
+            Ok(()) | Err(Error::ProtocolAlreadyAdopted) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Ivar, get_global_arena};
+
+    fn add_ivar(class: &Class, name: &str, types: &str) {
+        let arena = get_global_arena();
+        class
+            .add_ivar(Ivar::new(
+                RuntimeString::new(name, arena),
+                RuntimeString::new(types, arena),
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_synthesize_codable() {
+        let base = Class::new_root("CodableBase").unwrap();
+        add_ivar(&base, "id", "q");
+        let user = Class::new("CodableUser", &base).unwrap();
+        add_ivar(&user, "name", "@");
+        add_ivar(&user, "score", "d");
+
+        let fields = synthesize_codable(&user).unwrap();
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|field| (field.name.as_str().unwrap().to_string(), field.kind))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("id".to_string(), FieldKind::Integer),
+                ("name".to_string(), FieldKind::Object),
+                ("score".to_string(), FieldKind::Float),
+            ]
+        );
+        assert!(user.conforms_to(&encodable().unwrap()));
+        assert!(user.conforms_to(&decodable().unwrap()));
+        assert!(!base.conforms_to(&encodable().unwrap()));
+
+        // Synthesizing again keeps the conformance
+        assert_eq!(synthesize_codable(&user).unwrap().len(), 3);
+        assert_eq!(encodable().unwrap().name(), ENCODABLE);
+    }
+
+    #[test]
+    fn test_uncodable_field() {
+        let class = Class::new_root("CodableHandle").unwrap();
+        add_ivar(&class, "count", "i");
+        add_ivar(&class, "handle", "^");
+
+        let err = synthesize_codable(&class).unwrap_err();
+        assert_eq!(
+            err,
+            Error::NotCodable {
+                field: "handle".to_string()
+            }
+        );
+        assert!(!class.conforms_to(&encodable().unwrap()));
+    }
+}
@@ -20,6 +20,47 @@
 //! 5. Invoke the implementation
 //! 6. Return result or error
 //!
+//! # Method Cache
+//!
+//! Each class caches the implementation it resolved for each selector sent
+//! to its instances, so hot sends resolve in O(1) like `objc_msgSend`'s
+//! cache. Adding, swizzling or attaching methods to any class invalidates
+//! every cache, since subclasses cache the methods they inherit;
+//! [`flush_caches`] does the same on demand.
+//!
+//! # Memory Ordering
+//!
+//! Classes may be changed while other threads send messages to their
+//! instances. Adding a method, swizzling one and attaching a category or
+//! adding to it all follow the same protocol:
+//!
+//! 1. The `Method` is published in the global arena, where it never moves,
+//!    changes or is freed.
+//! 2. The method table entry is inserted or replaced under the table's
+//!    write lock, which is then released.
+//! 3. The cache generation is advanced (`AcqRel`), invalidating every
+//!    class's cache.
+//!
+//! A lookup reads the generation (`Acquire`) before walking the inheritance
+//! chain under each table's read lock, and only caches what it found if the
+//! generation has not moved since. This guarantees that:
+//!
+//! - A send that happens after a mutation returns (on the same thread, or
+//!   on one that synchronized with it through a join, a channel or a lock)
+//!   finds the new method, or one published later still.
+//! - A send racing with a mutation finds either the implementation from
+//!   before it or the one from after it, never a torn or freed `Method`.
+//! - No cache holds an implementation looked up before a mutation once
+//!   that mutation has returned.
+//! - References returned by [`Class::lookup_method`] stay valid and
+//!   unchanged for the rest of the program.
+//!
+//! Sends that race with each other and with several mutations are only
+//! ordered as far as those happens-before edges go: two threads may see
+//! concurrent mutations take effect in different orders.
+//!
+//! [`Class::lookup_method`]: crate::runtime::Class::lookup_method
+//!
 //! # Thread Safety
 //!
 //! All dispatch operations are thread-safe:
@@ -62,6 +103,12 @@ use crate::error::{Error, Result};
 use crate::runtime::MessageArgs;
 use crate::runtime::Object;
 use crate::runtime::Selector;
+use crate::runtime::class::Imp;
+use crate::runtime::exception;
+use crate::runtime::sync::Recover;
+use fxhash::FxHashMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 /// Sends a message to an object with the given arguments.
 ///
@@ -142,16 +189,21 @@ use crate::runtime::Selector;
 /// This extracts the common method calling logic to avoid duplication
 /// between normal dispatch and forwarded dispatch.
 ///
+/// The implementation is called behind a panic boundary, so an exception
+/// it throws, or a panic, is returned as [`Error::Exception`] instead of
+/// unwinding into the sender.
+///
 /// # Returns
 ///
-/// * `Some(value)` - Method returned a value
-/// * `None` - Method returned void
+/// * `Ok(Some(value))` - Method returned a value
+/// * `Ok(None)` - Method returned void
+/// * `Err(Error::Exception)` - Method threw an exception or panicked
 pub(crate) unsafe fn call_method_with_args(
     obj: &Object,
     imp: crate::runtime::class::Imp,
     selector: &Selector,
     args: &MessageArgs,
-) -> Option<usize> {
+) -> Result<Option<usize>> {
     // Pack arguments based on MessageArgs variant
     let arg_slice = args.as_slice();
     let args_ptr: *const *mut u8 = if arg_slice.is_empty() {
@@ -178,19 +230,22 @@ pub(crate) unsafe fn call_method_with_args(
     // - selector is valid (checked by lookup_imp)
     // - args_ptr points to valid arguments (if any)
     // - ret_ptr points to writable memory (16 bytes, stack-allocated)
-    unsafe {
+    // Imp is `extern "C-unwind"`, so what the implementation throws reaches
+    // this boundary instead of aborting
+    exception::try_catch(|| unsafe {
         imp(self_ptr, selector.as_handle(), args_ptr, ret_ptr);
-    }
+    })?;
 
     // Get method encoding for return value extraction
     let class = obj.class();
-    let method = class.lookup_method(selector).unwrap();
-    let encoding = method.types.as_str().unwrap();
-
-    // Extract return value based on method encoding
-    let return_type = encoding.chars().next().unwrap();
-    if return_type == 'v' {
-        None // Void return
+    let return_type = class.lookup_method(selector).and_then(|method| {
+        method.types.as_str().ok().and_then(|encoding| encoding.chars().next())
+    });
+
+    // Extract return value based on method encoding; a method without a
+    // readable encoding has no return value to read
+    if matches!(return_type, None | Some('v')) {
+        Ok(None) // Void return
     } else {
         // Non-void return: read the value written by the method implementation
         // SAFETY: ret_ptr points to valid memory where the IMP wrote the return value.
@@ -198,7 +253,7 @@ pub(crate) unsafe fn call_method_with_args(
         // The IMP function is responsible for writing the correct type.
         let value =
             unsafe { std::ptr::read_unaligned(ret_ptr as *const usize) };
-        Some(value)
+        Ok(Some(value))
     }
 }
 
@@ -222,19 +277,23 @@ pub(crate) unsafe fn call_method_with_args(
 /// * `Err(Error::ArgumentCountMismatch)` - Argument count doesn't match signature
 /// * `Err(Error::ForwardingFailed)` - Message forwarding failed
 /// * `Err(Error::ForwardingLoopDetected)` - Forwarding loop detected
+/// * `Err(Error::Exception)` - The method threw an exception or panicked
 ///
 /// # Panics
 ///
 /// Panics if the method lookup cache lock is poisoned (indicates a concurrent
-/// access error or panic in another thread).
+/// access error or panic in another thread), unless the `no-abort` feature is
+/// enabled.
 ///
 /// # Errors
 ///
 /// This function returns `Err` if:
 /// - The selector is not found in the class hierarchy
+/// - The method's type encoding is invalid
 /// - The argument count doesn't match the method signature
 /// - Message forwarding fails (target also doesn't recognize selector)
 /// - A forwarding loop is detected (exceeds max depth)
+/// - The method implementation throws an exception or panics
 ///
 /// # Safety
 ///
@@ -260,11 +319,16 @@ pub unsafe fn send_message(
             let target_class = cached_target.class();
             if let Some(imp) = target_class.lookup_imp(selector) {
                 // Validate arguments for cached target
-                let method = target_class.lookup_method(selector).unwrap();
-                let encoding = method.types.as_str().unwrap();
+                let method = target_class
+                    .lookup_method(selector)
+                    .ok_or(Error::SelectorNotFound)?;
+                let encoding = method.types.as_str()?;
                 let (_ret_type, arg_types) =
                     crate::runtime::encoding::parse_signature(encoding)?;
-                let expected_args = arg_types.len() - 2;
+                let expected_args = arg_types
+                    .len()
+                    .checked_sub(2)
+                    .ok_or(Error::InvalidEncoding)?;
                 let actual_args = args.count();
 
                 if actual_args != expected_args {
@@ -275,7 +339,7 @@ pub unsafe fn send_message(
                 }
 
                 // Call on cached target
-                return unsafe { Ok(call_method_with_args(&cached_target, imp, selector, args)) };
+                return unsafe { call_method_with_args(&cached_target, imp, selector, args) };
             }
             // Cache stale - fall through to four-stage pipeline
         }
@@ -289,13 +353,15 @@ pub unsafe fn send_message(
     };
 
     // Validate argument count
-    let method = class.lookup_method(selector).unwrap();
-    let encoding = method.types.as_str().unwrap();
+    let method =
+        class.lookup_method(selector).ok_or(Error::SelectorNotFound)?;
+    let encoding = method.types.as_str()?;
     let (_ret_type, arg_types) =
         crate::runtime::encoding::parse_signature(encoding)?;
 
     // arg_types includes self (@) and _cmd (:), so actual args = len - 2
-    let expected_args = arg_types.len() - 2;
+    let expected_args =
+        arg_types.len().checked_sub(2).ok_or(Error::InvalidEncoding)?;
     let actual_args = args.count();
 
     if actual_args != expected_args {
@@ -306,7 +372,89 @@ pub unsafe fn send_message(
     }
 
     // Call the method using the helper
-    unsafe { Ok(call_method_with_args(obj, imp, selector, args)) }
+    unsafe { call_method_with_args(obj, imp, selector, args) }
+}
+
+/// The cache generation, bumped whenever any class's methods change.
+///
+/// A class's cache also holds the implementations it inherits, so a change
+/// to a superclass, or to a category of one, makes the caches of all its
+/// subclasses stale. Rather than walking the subclasses, every cache
+/// remembers the generation it was filled in and is emptied on first use
+/// in a later one.
+static CACHE_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// A class's cache of the implementations its instances respond to each
+/// selector with, including inherited and category methods.
+///
+/// Keyed by selector hash, like the method tables, so a hit costs one hash
+/// lookup under a read lock instead of a walk of the inheritance chain.
+pub(crate) struct MethodCache {
+    /// The cached implementations, and the generation they are valid in
+    entries: RwLock<(u64, FxHashMap<u64, Imp>)>,
+}
+
+impl MethodCache {
+    /// Creates an empty cache.
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: RwLock::new((
+                CACHE_EPOCH.load(Ordering::Acquire),
+                FxHashMap::default(),
+            )),
+        }
+    }
+
+    /// Returns the cached implementation for the selector with `hash`, if
+    /// the cache holds one that is still valid.
+    #[inline]
+    pub(crate) fn get(&self, hash: u64) -> Option<Imp> {
+        let entries = self.entries.read().recover();
+        if entries.0 != CACHE_EPOCH.load(Ordering::Acquire) {
+            return None;
+        }
+        entries.1.get(&hash).copied()
+    }
+
+    /// Caches `imp` for the selector with `hash`, if no class changed since
+    /// `epoch`, the generation read before `imp` was looked up.
+    pub(crate) fn insert(&self, epoch: u64, hash: u64, imp: Imp) {
+        let mut entries = self.entries.write().recover();
+        if epoch != CACHE_EPOCH.load(Ordering::Acquire) {
+            // A class changed during the lookup, so `imp` may be stale
+            return;
+        }
+        if entries.0 != epoch {
+            entries.0 = epoch;
+            entries.1.clear();
+        }
+        entries.1.insert(hash, imp);
+    }
+}
+
+/// Returns the current cache generation, to pass to [`MethodCache::insert`].
+#[inline]
+pub(crate) fn cache_epoch() -> u64 {
+    CACHE_EPOCH.load(Ordering::Acquire)
+}
+
+/// Flushes the method cache of every class.
+///
+/// The runtime flushes the caches itself whenever methods are added,
+/// swizzled or attached through a category, so this is only needed by
+/// hosts that change method implementations behind its back, or that want
+/// to measure uncached dispatch. The next send of each selector walks the
+/// inheritance chain again.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::runtime::dispatch;
+///
+/// dispatch::flush_caches();
+/// ```
+pub fn flush_caches() {
+    CACHE_EPOCH.fetch_add(1, Ordering::AcqRel);
 }
 
 #[cfg(test)]
@@ -319,7 +467,7 @@ mod tests {
     use crate::runtime::selector::SelectorHandle;
 
     /// Test helper: no-op method implementation
-    unsafe extern "C" fn test_noop_impl(
+    unsafe extern "C-unwind" fn test_noop_impl(
         _self: crate::runtime::object::ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
@@ -329,7 +477,7 @@ mod tests {
     }
 
     /// Test helper: method that returns a value
-    unsafe extern "C" fn test_return_42_impl(
+    unsafe extern "C-unwind" fn test_return_42_impl(
         _self: crate::runtime::object::ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
@@ -343,6 +491,26 @@ mod tests {
         };
     }
 
+    /// Test helper: method that throws an exception
+    unsafe extern "C-unwind" fn test_throw_impl(
+        _self: crate::runtime::object::ObjectPtr,
+        _cmd: SelectorHandle,
+        _args: *const *mut u8,
+        _ret: *mut u8,
+    ) {
+        exception::throw(exception::Exception::new("TestException", "thrown"));
+    }
+
+    /// Test helper: method that panics
+    unsafe extern "C-unwind" fn test_panic_impl(
+        _self: crate::runtime::object::ObjectPtr,
+        _cmd: SelectorHandle,
+        _args: *const *mut u8,
+        _ret: *mut u8,
+    ) {
+        panic!("method panicked");
+    }
+
     #[test]
     fn test_send_message_0_basic() {
         let class = Class::new_root("DispatchTest0").unwrap();
@@ -523,6 +691,51 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_cache_follows_superclass_changes() {
+        let root = Class::new_root("DispatchCacheRoot").unwrap();
+        let mid = Class::new("DispatchCacheMid", &root).unwrap();
+        let leaf = Class::new("DispatchCacheLeaf", &mid).unwrap();
+        let sel = Selector::from_str("cacheFollowsSuper").unwrap();
+        let method = |imp| crate::runtime::class::Method {
+            selector: sel.clone(),
+            imp,
+            types: crate::runtime::RuntimeString::new("q@:", get_global_arena()),
+        };
+        root.add_method(method(test_noop_impl)).unwrap();
+
+        let obj = Object::new(&leaf).unwrap();
+        let send = || unsafe { send_message(&obj, &sel, &MessageArgs::None) };
+        assert_eq!(send(), Ok(Some(0)));
+
+        // Overriding in a superclass replaces the inherited, cached method
+        mid.add_method(method(test_return_42_impl)).unwrap();
+        assert_eq!(send(), Ok(Some(42)));
+
+        // As does swizzling it
+        mid.swizzle_method(&sel, test_noop_impl).unwrap();
+        assert_eq!(send(), Ok(Some(0)));
+    }
+
+    #[test]
+    fn test_flush_caches() {
+        let cache = MethodCache::new();
+        let epoch = cache_epoch();
+        cache.insert(epoch, 7, test_noop_impl);
+        // Other tests may flush concurrently, so only check the hit when the
+        // generation is unchanged
+        if cache_epoch() == epoch {
+            assert!(cache.get(7).is_some());
+        }
+
+        flush_caches();
+        assert!(cache.get(7).is_none());
+
+        // An implementation looked up before a flush is not cached
+        cache.insert(epoch, 7, test_noop_impl);
+        assert!(cache.get(7).is_none());
+    }
+
     #[test]
     fn test_send_message_many_args() {
         static ARGS: [usize; 9] = [1, 2, 3, 4, 5, 6, 7, 8, 9];
@@ -547,4 +760,48 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_send_message_catches_exceptions() {
+        let class = Class::new_root("DispatchThrows").unwrap();
+        let arena = get_global_arena();
+        let throws = Selector::from_str("throws").unwrap();
+        let panics = Selector::from_str("panics").unwrap();
+        for (selector, imp) in [
+            (&throws, test_throw_impl as Imp),
+            (&panics, test_panic_impl as Imp),
+        ] {
+            class
+                .add_method(crate::runtime::class::Method {
+                    selector: selector.clone(),
+                    imp,
+                    types: crate::runtime::RuntimeString::new("v@:", arena),
+                })
+                .unwrap();
+        }
+        let obj = Object::new(&class).unwrap();
+
+        let result =
+            unsafe { send_message(&obj, &throws, &MessageArgs::None) };
+        assert_eq!(
+            result,
+            Err(Error::Exception {
+                name: "TestException".to_string(),
+                reason: "thrown".to_string(),
+            })
+        );
+
+        let result =
+            unsafe { send_message(&obj, &panics, &MessageArgs::None) };
+        assert_eq!(
+            result,
+            Err(Error::Exception {
+                name: exception::PANIC_EXCEPTION.to_string(),
+                reason: "method panicked".to_string(),
+            })
+        );
+
+        // The receiver is still usable after an exception
+        assert_eq!(obj.refcount(), 1);
+    }
 }
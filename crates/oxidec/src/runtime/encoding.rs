@@ -102,20 +102,13 @@ pub mod types {
 /// Returns [`Error::InvalidEncoding`] if the encoding string is empty, contains
 /// invalid type characters, or doesn't include the required self and _cmd
 /// parameters.
-///
-/// # Panics
-///
-/// Panics if the encoding string is empty (should not happen as we check
-/// for this condition and return an error instead).
 pub fn validate_encoding(encoding: &str) -> Result<()> {
-    if encoding.is_empty() {
-        return Err(Error::InvalidEncoding);
-    }
-
     let mut chars = encoding.chars();
 
     // First character must be a valid return type
-    let return_type = chars.next().unwrap();
+    let Some(return_type) = chars.next() else {
+        return Err(Error::InvalidEncoding);
+    };
     if !is_valid_type_char(return_type) {
         return Err(Error::InvalidEncoding);
     }
@@ -216,16 +209,13 @@ const fn is_valid_type_char(ch: char) -> bool {
 ///
 /// Returns [`Error::InvalidEncoding`] if the encoding string is invalid
 /// (see [`validate_encoding`] for details).
-///
-/// # Panics
-///
-/// Panics if the encoding string is empty (should not happen as validation
-/// ensures it's non-empty).
 pub fn parse_signature(encoding: &str) -> Result<(char, Vec<char>)> {
     validate_encoding(encoding)?;
 
     let mut chars = encoding.chars();
-    let return_type = chars.next().unwrap();
+    let Some(return_type) = chars.next() else {
+        return Err(Error::InvalidEncoding);
+    };
     let arg_types: Vec<char> = chars.collect();
 
     Ok((return_type, arg_types))
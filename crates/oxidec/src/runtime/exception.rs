@@ -0,0 +1,168 @@
+//! Runtime exceptions.
+//!
+//! A method implementation reports a failure it cannot return through its
+//! IMP by [`throw`]ing an [`Exception`], as `@throw` does in Objective-C.
+//! The exception unwinds out of the implementation to the nearest
+//! [`try_catch`], or to the send that invoked it: dispatch calls every
+//! implementation behind a panic boundary and returns what unwinds out of
+//! it as [`Error::Exception`], so neither a thrown exception nor a panic
+//! crosses the C ABI of the caller.
+//!
+//! Implementations must therefore be `extern "C-unwind"`; unwinding out of
+//! an `extern "C"` function aborts the process before dispatch can catch
+//! it.
+//!
+//! A panic is caught like an exception named [`PANIC_EXCEPTION`], with the
+//! panic's message as its reason. The panic hook still runs for it, while
+//! [`throw`] unwinds without reporting anything.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::exception::{self, Exception};
+//!
+//! let caught = exception::try_catch(|| {
+//!     exception::throw(Exception::new("RangeException", "index 3 of 2"))
+//! })
+//! .unwrap_err();
+//!
+//! assert_eq!(caught.name(), "RangeException");
+//! assert_eq!(caught.reason(), "index 3 of 2");
+//! ```
+
+use crate::error::Error;
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Name of the exceptions that panics are caught as.
+pub const PANIC_EXCEPTION: &str = "PanicException";
+
+/// An exception thrown by a method implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exception {
+    /// What kind of failure this is, such as `RangeException`
+    name: String,
+    /// Human-readable description of this failure
+    reason: String,
+}
+
+impl Exception {
+    /// Creates an exception with a name and a reason.
+    #[must_use]
+    pub fn new(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Returns the exception's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the reason the exception was thrown.
+    #[must_use]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Converts what a caught unwind carried into an exception.
+    fn from_payload(payload: Box<dyn Any + Send>) -> Self {
+        let payload = match payload.downcast::<Self>() {
+            Ok(exception) => return *exception,
+            Err(payload) => payload,
+        };
+        let reason = if let Some(message) = payload.downcast_ref::<&str>() {
+            (*message).to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "panic with a non-string payload".to_string()
+        };
+        Self::new(PANIC_EXCEPTION, reason)
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.reason)
+    }
+}
+
+impl From<Exception> for Error {
+    fn from(exception: Exception) -> Self {
+        Error::Exception {
+            name: exception.name,
+            reason: exception.reason,
+        }
+    }
+}
+
+/// Throws an exception, unwinding to the nearest [`try_catch`] or message
+/// send.
+///
+/// Unlike a panic, throwing does not run the panic hook.
+pub fn throw(exception: Exception) -> ! {
+    panic::resume_unwind(Box::new(exception))
+}
+
+/// Calls `f`, catching any exception it throws and any panic.
+///
+/// # Errors
+///
+/// Returns the exception that unwound out of `f`, with panics converted to
+/// exceptions named [`PANIC_EXCEPTION`].
+pub fn try_catch<T>(f: impl FnOnce() -> T) -> Result<T, Exception> {
+    // Runtime state stays consistent across an unwind: every lock the
+    // runtime takes either completes its write or is recovered from
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(Exception::from_payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_catch_returns_value() {
+        assert_eq!(try_catch(|| 42), Ok(42));
+    }
+
+    #[test]
+    fn test_try_catch_thrown_exception() {
+        let caught = try_catch(|| -> usize {
+            throw(Exception::new("TestException", "thrown"))
+        });
+        assert_eq!(caught, Err(Exception::new("TestException", "thrown")));
+    }
+
+    #[test]
+    fn test_try_catch_panic() {
+        let caught = try_catch(|| panic!("index {} out of range", 3));
+        assert_eq!(
+            caught,
+            Err(Exception::new(PANIC_EXCEPTION, "index 3 out of range"))
+        );
+
+        let caught = try_catch(|| panic!("static message"));
+        assert_eq!(caught.unwrap_err().reason(), "static message");
+    }
+
+    #[test]
+    fn test_exception_into_error() {
+        let error = Error::from(Exception::new("TestException", "thrown"));
+        assert_eq!(
+            error,
+            Error::Exception {
+                name: "TestException".to_string(),
+                reason: "thrown".to_string(),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Uncaught exception TestException: thrown"
+        );
+    }
+}
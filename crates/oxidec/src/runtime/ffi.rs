@@ -0,0 +1,693 @@
+//! Calling C functions from the ``OxideC`` runtime.
+//!
+//! A [`Library`] is a shared library opened with `dlopen`, or the running
+//! process itself, whose symbols include the C library. Looking a symbol up
+//! with a type encoding gives a [`ForeignFunction`] that converts
+//! [`ForeignValue`]s to and from the C types the encoding names:
+//!
+//! - Signed integers (`c`, `s`, `i`, `q`) and unsigned ones (`C`, `S`,
+//!   `I`, `Q`)
+//! - Floats (`f`, `d`)
+//! - Booleans (`B`)
+//! - C strings (`*`), which may be null
+//! - `void` (`v`), as a return type only
+//!
+//! The first character of a signature encoding is the return type and the
+//! rest are the parameter types, so `strlen` is `Q*` and `cos` is `dd`.
+//!
+//! # Calling convention
+//!
+//! The runtime does not generate call stubs. On the supported targets
+//! (`x86_64` and `aarch64` Unix) a non-variadic C function receives its
+//! integer and pointer arguments in one bank of registers and its float
+//! arguments in another, each in order. A call therefore goes through a
+//! single fixed function type taking every integer register and every
+//! float register, with the arguments sorted into the two banks and the
+//! unused registers zeroed. This limits a function to
+//! [`MAX_INTEGER_ARGS`] integer and [`MAX_FLOAT_ARGS`] float arguments;
+//! variadic functions such as `printf` cannot be called.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::ffi::{ForeignValue, Library};
+//!
+//! let libc = Library::open(None).unwrap();
+//! let strlen = libc.function("strlen", "Q*").unwrap();
+//! let arg = ForeignValue::String("hello".to_string());
+//!
+//! // SAFETY: `strlen` takes a C string and returns a `size_t`
+//! let len = unsafe { strlen.call(&[arg]) };
+//! # #[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+//! assert_eq!(len.unwrap(), ForeignValue::Int(5));
+//! ```
+
+use crate::error::{Error, Result};
+use crate::runtime::sandbox::{self, Capability};
+use std::ffi::{CStr, CString, c_void};
+
+/// Largest number of integer, boolean and string arguments a foreign
+/// function can take.
+pub const MAX_INTEGER_ARGS: usize = 6;
+
+/// Largest number of float arguments a foreign function can take.
+pub const MAX_FLOAT_ARGS: usize = 8;
+
+/// A C type that values can be converted to and from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CType {
+    /// `void`, only valid as a return type
+    Void,
+    /// `int8_t` / `char`
+    Int8,
+    /// `int16_t` / `short`
+    Int16,
+    /// `int32_t` / `int`
+    Int32,
+    /// `int64_t` / `long long`
+    Int64,
+    /// `uint8_t` / `unsigned char`
+    UInt8,
+    /// `uint16_t` / `unsigned short`
+    UInt16,
+    /// `uint32_t` / `unsigned int`
+    UInt32,
+    /// `uint64_t` / `size_t`
+    UInt64,
+    /// `float`
+    Float32,
+    /// `double`
+    Float64,
+    /// `bool`
+    Bool,
+    /// `char *`, a NUL-terminated string
+    CString,
+}
+
+impl CType {
+    /// Returns the C type with type encoding `encoding`, or `None` if
+    /// foreign calls cannot pass it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::ffi::CType;
+    ///
+    /// assert_eq!(CType::from_encoding('i'), Some(CType::Int32));
+    /// assert_eq!(CType::from_encoding('@'), None);
+    /// ```
+    #[must_use]
+    pub fn from_encoding(encoding: char) -> Option<Self> {
+        let ty = match encoding {
+            'v' => Self::Void,
+            'c' => Self::Int8,
+            's' => Self::Int16,
+            'i' => Self::Int32,
+            'q' | 'l' => Self::Int64,
+            'C' => Self::UInt8,
+            'S' => Self::UInt16,
+            'I' => Self::UInt32,
+            'Q' | 'L' => Self::UInt64,
+            'f' => Self::Float32,
+            'd' => Self::Float64,
+            'B' => Self::Bool,
+            '*' => Self::CString,
+            _ => return None,
+        };
+        Some(ty)
+    }
+
+    /// Returns `true` if arguments of this type are passed in float
+    /// registers.
+    #[must_use]
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+}
+
+/// The parameter and return types of a foreign function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignSignature {
+    /// Return type
+    pub ret: CType,
+    /// Parameter types, in order
+    pub params: Vec<CType>,
+}
+
+impl ForeignSignature {
+    /// Parses a signature encoding: the return type followed by the
+    /// parameter types.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::InvalidForeignCall)` if the encoding is empty,
+    /// names a type foreign calls cannot pass, has a `void` parameter, or
+    /// has more arguments of a kind than there are registers for.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::ffi::{CType, ForeignSignature};
+    ///
+    /// let sig = ForeignSignature::parse("d*i").unwrap();
+    /// assert_eq!(sig.ret, CType::Float64);
+    /// assert_eq!(sig.params, [CType::CString, CType::Int32]);
+    /// ```
+    pub fn parse(encoding: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::InvalidForeignCall { reason };
+        let mut types = encoding.chars().map(|c| {
+            CType::from_encoding(c).ok_or_else(|| {
+                invalid(format!("type encoding '{c}' has no C counterpart"))
+            })
+        });
+        let ret = types
+            .next()
+            .ok_or_else(|| invalid("empty signature encoding".to_string()))??;
+        let params = types.collect::<Result<Vec<_>>>()?;
+
+        if params.contains(&CType::Void) {
+            return Err(invalid("void parameter".to_string()));
+        }
+        let floats = params.iter().filter(|ty| ty.is_float()).count();
+        let integers = params.len() - floats;
+        if integers > MAX_INTEGER_ARGS || floats > MAX_FLOAT_ARGS {
+            return Err(invalid(format!(
+                "signature '{encoding}' takes more than {MAX_INTEGER_ARGS} \
+                 integer or {MAX_FLOAT_ARGS} float arguments"
+            )));
+        }
+        Ok(Self { ret, params })
+    }
+}
+
+/// A value passed to or returned from a foreign function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForeignValue {
+    /// Result of a `void` function
+    Void,
+    /// A null C string
+    Nil,
+    /// An integer of any width
+    Int(i64),
+    /// A boolean
+    Bool(bool),
+    /// A float of either width
+    Float(f64),
+    /// A C string, copied in or out of the call
+    String(String),
+}
+
+/// A shared library, or the running process.
+///
+/// Libraries stay loaded for the life of the process, so the functions
+/// looked up in them stay callable.
+#[derive(Debug)]
+pub struct Library {
+    handle: *mut c_void,
+    name: String,
+}
+
+// SAFETY: A `dlopen` handle is a process-wide token; `dlsym` may be called
+// on it from any thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    /// Opens the shared library at `path`, or the running process and the
+    /// libraries it has loaded if `path` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::LibraryLoadFailed)` with the dynamic loader's
+    /// message if the library cannot be loaded, or if the
+    /// [sandbox](crate::runtime::sandbox) entered on this thread denies
+    /// calling native code.
+    pub fn open(path: Option<&str>) -> Result<Self> {
+        let name = path.unwrap_or("<process>").to_string();
+        if let Err(capability) = sandbox::current().check(Capability::Ffi) {
+            return Err(Error::LibraryLoadFailed {
+                library: name,
+                reason: format!("{capability} is denied by the sandbox"),
+            });
+        }
+        let path = path
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| Error::LibraryLoadFailed {
+                library: name.clone(),
+                reason: "path contains a NUL byte".to_string(),
+            })?;
+        let handle = dl::open(path.as_deref())
+            .map_err(|reason| Error::LibraryLoadFailed {
+                library: name.clone(),
+                reason,
+            })?;
+        Ok(Self { handle, name })
+    }
+
+    /// Returns the library's path, or `<process>` for the running process.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Looks up the C function `symbol` with signature encoding
+    /// `encoding`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::SymbolNotFound)` if the library has no such
+    /// symbol, or `Err(Error::InvalidForeignCall)` if the encoding is not
+    /// a valid signature (see [`ForeignSignature::parse`]).
+    pub fn function(
+        &self,
+        symbol: &str,
+        encoding: &str,
+    ) -> Result<ForeignFunction> {
+        let signature = ForeignSignature::parse(encoding)?;
+        let not_found = || Error::SymbolNotFound {
+            symbol: symbol.to_string(),
+        };
+        let address = self.address(symbol).ok_or_else(not_found)?;
+        Ok(ForeignFunction {
+            symbol: symbol.to_string(),
+            address,
+            signature,
+        })
+    }
+
+    /// Returns the address of `symbol`, if the library exports it.
+    pub(crate) fn address(&self, symbol: &str) -> Option<*const c_void> {
+        let name = CString::new(symbol).ok()?;
+        dl::symbol(self.handle, &name)
+    }
+}
+
+/// A C function and its signature.
+#[derive(Debug, Clone)]
+pub struct ForeignFunction {
+    symbol: String,
+    address: *const c_void,
+    signature: ForeignSignature,
+}
+
+// SAFETY: The address is code in a library that is never unloaded.
+unsafe impl Send for ForeignFunction {}
+unsafe impl Sync for ForeignFunction {}
+
+impl ForeignFunction {
+    /// Returns the function's symbol name.
+    #[must_use]
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Returns the function's signature.
+    #[must_use]
+    pub fn signature(&self) -> &ForeignSignature {
+        &self.signature
+    }
+
+    /// Calls the function with `args`, converted to its parameter types.
+    ///
+    /// Integers must fit their parameter's type; integers convert to float
+    /// parameters and `Nil` to a null string. String arguments are copied
+    /// for the duration of the call, and a string result is copied out,
+    /// with invalid UTF-8 replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::InvalidForeignCall)` if the number of arguments
+    /// is wrong, an argument does not convert to its parameter's type, or
+    /// the target does not support foreign calls.
+    ///
+    /// # Safety
+    ///
+    /// The signature must match the C function's declaration, and the
+    /// function must be safe to call with the given arguments; the
+    /// runtime cannot check either.
+    pub unsafe fn call(&self, args: &[ForeignValue]) -> Result<ForeignValue> {
+        let params = &self.signature.params;
+        if args.len() != params.len() {
+            return Err(Error::InvalidForeignCall {
+                reason: format!(
+                    "'{}' takes {} arguments but {} were given",
+                    self.symbol,
+                    params.len(),
+                    args.len()
+                ),
+            });
+        }
+
+        // Strings must outlive the call that borrows their pointers
+        let mut strings = Vec::new();
+        let mut integers = [0u64; MAX_INTEGER_ARGS];
+        let mut floats = [0f64; MAX_FLOAT_ARGS];
+        let (mut next_integer, mut next_float) = (0, 0);
+        for (index, (&ty, arg)) in params.iter().zip(args).enumerate() {
+            let word = self.marshal(index, ty, arg, &mut strings)?;
+            if ty.is_float() {
+                floats[next_float] = f64::from_bits(word);
+                next_float += 1;
+            } else {
+                integers[next_integer] = word;
+                next_integer += 1;
+            }
+        }
+
+        let ret = self.signature.ret;
+        // SAFETY: The caller guarantees the signature matches the function,
+        // so its arguments are in the registers it reads them from.
+        let word = unsafe {
+            if ret.is_float() {
+                call::float(self.address, &integers, &floats)?.to_bits()
+            } else {
+                call::integer(self.address, &integers, &floats)?
+            }
+        };
+
+        // Registers carry garbage above a narrow result
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let value = match ret {
+            CType::Void => ForeignValue::Void,
+            CType::Int8 => ForeignValue::Int(i64::from(word as i8)),
+            CType::Int16 => ForeignValue::Int(i64::from(word as i16)),
+            CType::Int32 => ForeignValue::Int(i64::from(word as i32)),
+            CType::Int64 => ForeignValue::Int(word as i64),
+            CType::UInt8 => ForeignValue::Int(i64::from(word as u8)),
+            CType::UInt16 => ForeignValue::Int(i64::from(word as u16)),
+            CType::UInt32 => ForeignValue::Int(i64::from(word as u32)),
+            CType::UInt64 => ForeignValue::Int(word as i64),
+            CType::Float32 => {
+                ForeignValue::Float(f64::from(f32::from_bits(word as u32)))
+            }
+            CType::Float64 => ForeignValue::Float(f64::from_bits(word)),
+            CType::Bool => ForeignValue::Bool(word as u8 != 0),
+            CType::CString if word == 0 => ForeignValue::Nil,
+            CType::CString => {
+                // SAFETY: The function returns a NUL-terminated string
+                let string = unsafe { CStr::from_ptr(word as *const _) };
+                ForeignValue::String(string.to_string_lossy().into_owned())
+            }
+        };
+        // A string result may point into an argument
+        drop(strings);
+        Ok(value)
+    }
+
+    /// Converts argument `index` to the register word of C type `ty`.
+    #[allow(clippy::cast_sign_loss)]
+    fn marshal(
+        &self,
+        index: usize,
+        ty: CType,
+        arg: &ForeignValue,
+        strings: &mut Vec<CString>,
+    ) -> Result<u64> {
+        let mismatch = || Error::InvalidForeignCall {
+            reason: format!(
+                "argument {} of '{}' cannot be passed as {ty:?}",
+                index + 1,
+                self.symbol
+            ),
+        };
+        let int = |value: i64, fits: bool| {
+            if fits { Ok(value as u64) } else { Err(mismatch()) }
+        };
+        match (ty, arg) {
+            (CType::Int8, &ForeignValue::Int(v)) => {
+                int(v, i8::try_from(v).is_ok())
+            }
+            (CType::Int16, &ForeignValue::Int(v)) => {
+                int(v, i16::try_from(v).is_ok())
+            }
+            (CType::Int32, &ForeignValue::Int(v)) => {
+                int(v, i32::try_from(v).is_ok())
+            }
+            (CType::Int64, &ForeignValue::Int(v)) => int(v, true),
+            (CType::UInt8, &ForeignValue::Int(v)) => {
+                int(v, u8::try_from(v).is_ok())
+            }
+            (CType::UInt16, &ForeignValue::Int(v)) => {
+                int(v, u16::try_from(v).is_ok())
+            }
+            (CType::UInt32, &ForeignValue::Int(v)) => {
+                int(v, u32::try_from(v).is_ok())
+            }
+            (CType::UInt64, &ForeignValue::Int(v)) => int(v, v >= 0),
+            (CType::Bool, &ForeignValue::Bool(v)) => Ok(u64::from(v)),
+            #[allow(clippy::cast_precision_loss)]
+            (CType::Float64, &ForeignValue::Int(v)) => {
+                Ok((v as f64).to_bits())
+            }
+            (CType::Float64, &ForeignValue::Float(v)) => Ok(v.to_bits()),
+            // A `float` argument occupies the low half of its register
+            #[allow(clippy::cast_precision_loss)]
+            (CType::Float32, &ForeignValue::Int(v)) => {
+                Ok(u64::from((v as f32).to_bits()))
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            (CType::Float32, &ForeignValue::Float(v)) => {
+                Ok(u64::from((v as f32).to_bits()))
+            }
+            (CType::CString, ForeignValue::Nil) => Ok(0),
+            (CType::CString, ForeignValue::String(v)) => {
+                let string = CString::new(v.as_str()).map_err(|_| {
+                    Error::InvalidForeignCall {
+                        reason: format!(
+                            "argument {} of '{}' contains a NUL byte",
+                            index + 1,
+                            self.symbol
+                        ),
+                    }
+                })?;
+                let word = string.as_ptr() as u64;
+                strings.push(string);
+                Ok(word)
+            }
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+/// The dynamic loader.
+#[cfg(unix)]
+mod dl {
+    use std::ffi::{CStr, c_void};
+
+    pub(super) fn open(path: Option<&CStr>) -> Result<*mut c_void, String> {
+        let path = path.map_or(std::ptr::null(), CStr::as_ptr);
+        // SAFETY: `path` is null or a NUL-terminated string
+        let handle = unsafe { libc::dlopen(path, libc::RTLD_NOW) };
+        if handle.is_null() {
+            Err(last_error())
+        } else {
+            Ok(handle)
+        }
+    }
+
+    pub(super) fn symbol(
+        handle: *mut c_void,
+        name: &CStr,
+    ) -> Option<*const c_void> {
+        // SAFETY: `handle` came from `dlopen` and is never closed
+        let address = unsafe { libc::dlsym(handle, name.as_ptr()) };
+        (!address.is_null()).then_some(address.cast_const())
+    }
+
+    fn last_error() -> String {
+        // SAFETY: `dlerror` returns null or a NUL-terminated message
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            "unknown error".to_string()
+        } else {
+            // SAFETY: Checked non-null above
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+/// The dynamic loader.
+#[cfg(not(unix))]
+mod dl {
+    use std::ffi::{CStr, c_void};
+
+    pub(super) fn open(_path: Option<&CStr>) -> Result<*mut c_void, String> {
+        Err("dynamic loading is not supported on this target".to_string())
+    }
+
+    pub(super) fn symbol(
+        _handle: *mut c_void,
+        _name: &CStr,
+    ) -> Option<*const c_void> {
+        None
+    }
+}
+
+/// Calls through the fixed register-bank function types.
+#[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod call {
+    use super::{MAX_FLOAT_ARGS, MAX_INTEGER_ARGS};
+    use crate::error::Result;
+    use std::ffi::c_void;
+
+    type Integers = [u64; MAX_INTEGER_ARGS];
+    type Floats = [f64; MAX_FLOAT_ARGS];
+
+    macro_rules! define_call {
+        ($name:ident -> $ret:ty) => {
+            pub(super) unsafe fn $name(
+                address: *const c_void,
+                i: &Integers,
+                f: &Floats,
+            ) -> Result<$ret> {
+                type Function = unsafe extern "C" fn(
+                    u64, u64, u64, u64, u64, u64,
+                    f64, f64, f64, f64, f64, f64, f64, f64,
+                ) -> $ret;
+                // SAFETY: `address` is a function taking its integer and
+                // float arguments from these registers in order
+                let function = unsafe {
+                    std::mem::transmute::<*const c_void, Function>(address)
+                };
+                // SAFETY: As above
+                Ok(unsafe {
+                    function(
+                        i[0], i[1], i[2], i[3], i[4], i[5], f[0], f[1], f[2],
+                        f[3], f[4], f[5], f[6], f[7],
+                    )
+                })
+            }
+        };
+    }
+
+    define_call!(integer -> u64);
+    define_call!(float -> f64);
+}
+
+/// Foreign calls on targets without a known register convention.
+#[cfg(not(all(unix, any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod call {
+    use super::{MAX_FLOAT_ARGS, MAX_INTEGER_ARGS};
+    use crate::error::{Error, Result};
+    use std::ffi::c_void;
+
+    fn unsupported<T>() -> Result<T> {
+        Err(Error::InvalidForeignCall {
+            reason: "foreign calls are not supported on this target"
+                .to_string(),
+        })
+    }
+
+    pub(super) unsafe fn integer(
+        _address: *const c_void,
+        _i: &[u64; MAX_INTEGER_ARGS],
+        _f: &[f64; MAX_FLOAT_ARGS],
+    ) -> Result<u64> {
+        unsupported()
+    }
+
+    pub(super) unsafe fn float(
+        _address: *const c_void,
+        _i: &[u64; MAX_INTEGER_ARGS],
+        _f: &[f64; MAX_FLOAT_ARGS],
+    ) -> Result<f64> {
+        unsupported()
+    }
+}
+
+#[cfg(all(test, unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests {
+    use super::*;
+    use crate::runtime::sandbox::Sandbox;
+
+    #[test]
+    fn test_call_libc() {
+        let libc = Library::open(None).unwrap();
+        let strlen = libc.function("strlen", "Q*").unwrap();
+        let abs = libc.function("abs", "ii").unwrap();
+        let strchr = libc.function("strchr", "**i").unwrap();
+
+        let hello = ForeignValue::String("hello".to_string());
+        unsafe {
+            assert_eq!(
+                strlen.call(std::slice::from_ref(&hello)).unwrap(),
+                ForeignValue::Int(5)
+            );
+            assert_eq!(
+                abs.call(&[ForeignValue::Int(-7)]).unwrap(),
+                ForeignValue::Int(7)
+            );
+            let found = strchr
+                .call(&[hello.clone(), ForeignValue::Int(i64::from(b'l'))])
+                .unwrap();
+            assert_eq!(found, ForeignValue::String("llo".to_string()));
+            let missing = strchr
+                .call(&[hello, ForeignValue::Int(i64::from(b'z'))])
+                .unwrap();
+            assert_eq!(missing, ForeignValue::Nil);
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_call_libm() {
+        let libm = Library::open(Some("libm.so.6")).unwrap();
+        let pow = libm.function("pow", "ddd").unwrap();
+        let ldexp = libm.function("ldexp", "ddi").unwrap();
+        let sqrtf = libm.function("sqrtf", "ff").unwrap();
+
+        unsafe {
+            let args = [ForeignValue::Float(2.0), ForeignValue::Int(10)];
+            assert_eq!(pow.call(&args).unwrap(), ForeignValue::Float(1024.0));
+            // Integer and float arguments interleave
+            assert_eq!(
+                ldexp.call(&[ForeignValue::Float(1.5), ForeignValue::Int(2)]),
+                Ok(ForeignValue::Float(6.0))
+            );
+            assert_eq!(
+                sqrtf.call(&[ForeignValue::Float(2.25)]),
+                Ok(ForeignValue::Float(1.5))
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_calls() {
+        let libc = Library::open(None).unwrap();
+        assert!(matches!(
+            libc.function("oxidec_no_such_symbol", "v"),
+            Err(Error::SymbolNotFound { .. })
+        ));
+        assert!(matches!(
+            Library::open(Some("liboxidec-missing.so")),
+            Err(Error::LibraryLoadFailed { .. })
+        ));
+        for encoding in ["", "i@", "iv", "iiiiiiii"] {
+            assert!(
+                ForeignSignature::parse(encoding).is_err(),
+                "{encoding:?}"
+            );
+        }
+
+        // Code sandboxed against native calls cannot load a library
+        let entered = Sandbox { deny_ffi: true, ..Sandbox::default() }.enter();
+        let Err(Error::LibraryLoadFailed { library, reason }) =
+            Library::open(None)
+        else {
+            panic!("expected the sandbox to refuse the library")
+        };
+        assert_eq!(library, "<process>");
+        assert_eq!(reason, "calling native code is denied by the sandbox");
+        drop(entered);
+
+        let abs = libc.function("abs", "ii").unwrap();
+        unsafe {
+            assert!(abs.call(&[]).is_err());
+            assert!(abs.call(&[ForeignValue::Int(i64::MAX)]).is_err());
+            assert!(abs.call(&[ForeignValue::Bool(true)]).is_err());
+        }
+    }
+}
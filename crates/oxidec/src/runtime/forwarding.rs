@@ -14,6 +14,7 @@ use crate::error::{Error, Result};
 use crate::runtime::invocation::Invocation;
 use crate::runtime::message::MessageArgs;
 use crate::runtime::pool::PooledInvocation;
+use crate::runtime::sync::Recover;
 use crate::runtime::{Object, Selector};
 use std::cell::Cell;
 use std::collections::HashMap;
@@ -170,7 +171,7 @@ static GLOBAL_DOES_NOT_RECOGNIZE_HOOK: RwLock<Option<DoesNotRecognizeHook>> =
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn set_global_forwarding_hook(hook: GlobalForwardingHook) {
-    let mut global_hook = GLOBAL_FORWARDING_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_FORWARDING_HOOK.write().recover();
     *global_hook = Some(hook);
 }
 
@@ -181,7 +182,7 @@ pub fn set_global_forwarding_hook(hook: GlobalForwardingHook) {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn get_global_forwarding_hook() -> Option<GlobalForwardingHook> {
-    let hook = GLOBAL_FORWARDING_HOOK.read().unwrap();
+    let hook = GLOBAL_FORWARDING_HOOK.read().recover();
     *hook
 }
 
@@ -192,7 +193,7 @@ pub fn get_global_forwarding_hook() -> Option<GlobalForwardingHook> {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_global_forwarding_hook() {
-    let mut global_hook = GLOBAL_FORWARDING_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_FORWARDING_HOOK.write().recover();
     *global_hook = None;
 }
 
@@ -211,7 +212,7 @@ pub fn clear_global_forwarding_hook() {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn set_global_signature_hook(hook: MethodSignatureHook) {
-    let mut global_hook = GLOBAL_SIGNATURE_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_SIGNATURE_HOOK.write().recover();
     *global_hook = Some(hook);
 }
 
@@ -222,7 +223,7 @@ pub fn set_global_signature_hook(hook: MethodSignatureHook) {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_global_signature_hook() {
-    let mut global_hook = GLOBAL_SIGNATURE_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_SIGNATURE_HOOK.write().recover();
     *global_hook = None;
 }
 
@@ -241,7 +242,7 @@ pub fn clear_global_signature_hook() {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn set_global_forward_invocation_hook(hook: ForwardInvocationHook) {
-    let mut global_hook = GLOBAL_FORWARD_INVOCATION_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_FORWARD_INVOCATION_HOOK.write().recover();
     *global_hook = Some(hook);
 }
 
@@ -252,7 +253,7 @@ pub fn set_global_forward_invocation_hook(hook: ForwardInvocationHook) {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_global_forward_invocation_hook() {
-    let mut global_hook = GLOBAL_FORWARD_INVOCATION_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_FORWARD_INVOCATION_HOOK.write().recover();
     *global_hook = None;
 }
 
@@ -271,7 +272,7 @@ pub fn clear_global_forward_invocation_hook() {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn set_global_does_not_recognize_hook(hook: DoesNotRecognizeHook) {
-    let mut global_hook = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.write().recover();
     *global_hook = Some(hook);
 }
 
@@ -282,7 +283,7 @@ pub fn set_global_does_not_recognize_hook(hook: DoesNotRecognizeHook) {
 /// Panics if the global hook lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_global_does_not_recognize_hook() {
-    let mut global_hook = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.write().unwrap();
+    let mut global_hook = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.write().recover();
     *global_hook = None;
 }
 
@@ -618,7 +619,7 @@ fn try_per_class_signature(obj: &Object, sel: &Selector) -> Option<String> {
     inner
         .signature_hook
         .read()
-        .unwrap()
+        .recover()
         .and_then(|hook| hook(obj, sel))
 }
 
@@ -626,7 +627,7 @@ fn try_per_class_signature(obj: &Object, sel: &Selector) -> Option<String> {
 fn try_global_signature(obj: &Object, sel: &Selector) -> Option<String> {
     GLOBAL_SIGNATURE_HOOK
         .read()
-        .unwrap()
+        .recover()
         .and_then(|hook| hook(obj, sel))
 }
 
@@ -645,7 +646,7 @@ fn try_per_class_forward_invocation(invocation: &mut Invocation) -> bool {
     inner
         .forward_invocation_hook
         .read()
-        .unwrap()
+        .recover()
         .is_some_and(|hook| {
             hook(invocation);
             true
@@ -656,7 +657,7 @@ fn try_per_class_forward_invocation(invocation: &mut Invocation) -> bool {
 fn try_global_forward_invocation(invocation: &mut Invocation) -> bool {
     GLOBAL_FORWARD_INVOCATION_HOOK
         .read()
-        .unwrap()
+        .recover()
         .is_some_and(|hook| {
             hook(invocation);
             true
@@ -673,14 +674,14 @@ fn try_per_class_does_not_recognize(obj: &Object, sel: &Selector) {
     let class = obj.class();
     // SAFETY: ClassInner is valid and allocated in arena
     let inner = unsafe { &*class.inner.as_ptr() };
-    if let Some(hook) = inner.does_not_recognize_hook.read().unwrap().as_ref() {
+    if let Some(hook) = inner.does_not_recognize_hook.read().recover().as_ref() {
         hook(obj, sel);
     }
 }
 
 /// Global does not recognize hook.
 fn try_global_does_not_recognize(obj: &Object, sel: &Selector) {
-    if let Some(hook) = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.read().unwrap().as_ref()
+    if let Some(hook) = GLOBAL_DOES_NOT_RECOGNIZE_HOOK.read().recover().as_ref()
     {
         hook(obj, sel);
     }
@@ -712,7 +713,7 @@ static FORWARDED_METHOD_CACHE: LazyLock<RwLock<HashMap<(u64, u64), Object>>> =
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn cache_forwarded_target(obj: &Object, sel: &Selector, target: &Object) {
-    let mut cache = FORWARDED_METHOD_CACHE.write().unwrap();
+    let mut cache = FORWARDED_METHOD_CACHE.write().recover();
     let key = (obj.class().inner_hash(), sel.hash());
     cache.insert(key, target.clone());
 }
@@ -734,7 +735,7 @@ pub fn cache_forwarded_target(obj: &Object, sel: &Selector, target: &Object) {
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn get_cached_target(obj: &Object, sel: &Selector) -> Option<Object> {
-    let cache = FORWARDED_METHOD_CACHE.read().unwrap();
+    let cache = FORWARDED_METHOD_CACHE.read().recover();
     let key = (obj.class().inner_hash(), sel.hash());
     cache.get(&key).cloned()
 }
@@ -749,7 +750,7 @@ pub fn get_cached_target(obj: &Object, sel: &Selector) -> Option<Object> {
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_forwarded_cache() {
-    let mut cache = FORWARDED_METHOD_CACHE.write().unwrap();
+    let mut cache = FORWARDED_METHOD_CACHE.write().recover();
     cache.clear();
 }
 
@@ -779,7 +780,7 @@ static SIGNATURE_CACHE: LazyLock<RwLock<HashMap<(u64, u64), String>>> =
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn cache_signature(obj: &Object, sel: &Selector, signature: &str) {
-    let mut cache = SIGNATURE_CACHE.write().unwrap();
+    let mut cache = SIGNATURE_CACHE.write().recover();
     let key = (obj.class().inner_hash(), sel.hash());
     cache.insert(key, signature.to_string());
 }
@@ -801,7 +802,7 @@ pub fn cache_signature(obj: &Object, sel: &Selector, signature: &str) {
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn get_cached_signature(obj: &Object, sel: &Selector) -> Option<String> {
-    let cache = SIGNATURE_CACHE.read().unwrap();
+    let cache = SIGNATURE_CACHE.read().recover();
     let key = (obj.class().inner_hash(), sel.hash());
     cache.get(&key).cloned()
 }
@@ -816,7 +817,7 @@ pub fn get_cached_signature(obj: &Object, sel: &Selector) -> Option<String> {
 /// Panics if the cache lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_signature_cache() {
-    let mut cache = SIGNATURE_CACHE.write().unwrap();
+    let mut cache = SIGNATURE_CACHE.write().recover();
     cache.clear();
 }
 
@@ -866,7 +867,7 @@ static FORWARDING_EVENT_CALLBACK: RwLock<Option<ForwardingEventCallback>> =
 /// Panics if the event callback lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn set_forwarding_event_callback(callback: ForwardingEventCallback) {
-    *FORWARDING_EVENT_CALLBACK.write().unwrap() = Some(callback);
+    *FORWARDING_EVENT_CALLBACK.write().recover() = Some(callback);
 }
 
 /// Clears the forwarding event callback.
@@ -876,7 +877,7 @@ pub fn set_forwarding_event_callback(callback: ForwardingEventCallback) {
 /// Panics if the event callback lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn clear_forwarding_event_callback() {
-    *FORWARDING_EVENT_CALLBACK.write().unwrap() = None;
+    *FORWARDING_EVENT_CALLBACK.write().recover() = None;
 }
 
 /// Emits a forwarding event if the event callback is set.
@@ -890,7 +891,7 @@ pub fn clear_forwarding_event_callback() {
 /// Panics if the event callback lock is poisoned (indicates a concurrent
 /// access error or panic in another thread).
 pub fn emit_forwarding_event(event: ForwardingEvent) {
-    if let Some(callback) = FORWARDING_EVENT_CALLBACK.read().unwrap().as_ref() {
+    if let Some(callback) = FORWARDING_EVENT_CALLBACK.read().recover().as_ref() {
         callback(event);
     }
 }
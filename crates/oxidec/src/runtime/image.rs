@@ -0,0 +1,606 @@
+//! Static metadata images.
+//!
+//! An [`Image`] describes the selectors, protocols and classes of a program
+//! as flat `#[repr(C)]` tables, so a compiler can lay it out as constant
+//! data in an object file and the program can register everything it
+//! declares with one call to [`register_image`], instead of running code
+//! that builds each class.
+//!
+//! # Layout
+//!
+//! Records refer to each other by `u32` index:
+//!
+//! - Names and type encodings are indices into [`Image::strings`].
+//! - Selectors are indices into [`Image::selectors`], whose entries are
+//!   string indices.
+//! - Protocols, classes, instance variables, methods and adopted protocol
+//!   names are stored in one table each; a protocol or class owns the
+//!   half-open range `start..end` of the member table.
+//!
+//! Classes appear after their superclass when both are in the image. A
+//! method without an implementation (a null `imp`) and static methods are
+//! not added to the runtime's method tables. Instance variables are
+//! recorded on their class for introspection.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::image::{
+//!     IMAGE_VERSION, Image, ImageClass, ImageString, SUPERCLASS_NONE,
+//!     register_image,
+//! };
+//!
+//! let name = "ImageDocExample";
+//! let strings = [ImageString { bytes: name.as_ptr(), len: name.len() }];
+//! let classes = [ImageClass {
+//!     name: 0,
+//!     superclass_kind: SUPERCLASS_NONE,
+//!     superclass: 0,
+//!     ivars_start: 0,
+//!     ivars_end: 0,
+//!     methods_start: 0,
+//!     methods_end: 0,
+//!     protocols_start: 0,
+//!     protocols_end: 0,
+//! }];
+//! let image = Image {
+//!     strings: strings.as_ptr(),
+//!     string_count: strings.len(),
+//!     classes: classes.as_ptr(),
+//!     class_count: classes.len(),
+//!     ..Image::empty()
+//! };
+//!
+//! // SAFETY: every table pointer is valid for its count
+//! let registered = unsafe { register_image(&image) }.unwrap();
+//! assert_eq!(registered[0].name(), "ImageDocExample");
+//! ```
+
+use crate::error::{Error, Result};
+use crate::runtime::class::Imp;
+use crate::runtime::{
+    Capabilities, Class, Ivar, Method, Protocol, RuntimeString, Selector,
+    all_protocols, capabilities, class_from_name, get_global_arena,
+};
+use std::str::FromStr;
+
+/// Version of the image layout this runtime reads.
+pub const IMAGE_VERSION: u32 = 1;
+
+/// [`ImageClass::superclass_kind`] of a root class.
+pub const SUPERCLASS_NONE: u32 = 0;
+
+/// [`ImageClass::superclass_kind`] of a class whose superclass is in the
+/// image, by class index.
+pub const SUPERCLASS_LOCAL: u32 = 1;
+
+/// [`ImageClass::superclass_kind`] of a class whose superclass is already
+/// registered with the runtime, by name.
+pub const SUPERCLASS_EXTERNAL: u32 = 2;
+
+/// [`ImageMethod::flags`] bit of a static method.
+pub const METHOD_STATIC: u32 = 1;
+
+/// A UTF-8 string, not necessarily NUL-terminated.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageString {
+    /// First byte
+    pub bytes: *const u8,
+    /// Length in bytes
+    pub len: usize,
+}
+
+/// A protocol and the range of its required methods.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageProtocol {
+    /// Name (string index)
+    pub name: u32,
+    /// First requirement
+    pub requirements_start: u32,
+    /// End of the requirements
+    pub requirements_end: u32,
+}
+
+/// A method a protocol requires.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRequirement {
+    /// Selector (selector index)
+    pub selector: u32,
+    /// Type encoding (string index)
+    pub types: u32,
+}
+
+/// A class and the ranges of its members.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageClass {
+    /// Name (string index)
+    pub name: u32,
+    /// How [`superclass`](Self::superclass) is interpreted
+    pub superclass_kind: u32,
+    /// Class index or string index of the superclass
+    pub superclass: u32,
+    /// First instance variable
+    pub ivars_start: u32,
+    /// End of the instance variables
+    pub ivars_end: u32,
+    /// First method
+    pub methods_start: u32,
+    /// End of the methods
+    pub methods_end: u32,
+    /// First adopted protocol name
+    pub protocols_start: u32,
+    /// End of the adopted protocol names
+    pub protocols_end: u32,
+}
+
+/// An instance variable declared by a class.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageIvar {
+    /// Name (string index)
+    pub name: u32,
+    /// Type encoding (string index)
+    pub types: u32,
+}
+
+/// A method of a class.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ImageMethod {
+    /// Selector (selector index)
+    pub selector: u32,
+    /// Type encoding (string index)
+    pub types: u32,
+    /// [`METHOD_STATIC`] for static methods
+    pub flags: u32,
+    /// Implementation, if there is one
+    pub imp: Option<Imp>,
+}
+
+/// The metadata of a program.
+///
+/// Each table is a pointer to its first record and a count, and the
+/// pointer may be null when the count is zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Image {
+    /// [`IMAGE_VERSION`] of the layout
+    pub version: u32,
+    /// Feature bits the image's code requires of the runtime (see
+    /// [`crate::runtime::capabilities`])
+    pub features: u32,
+    /// Strings
+    pub strings: *const ImageString,
+    /// Number of strings
+    pub string_count: usize,
+    /// Selector names (string indices)
+    pub selectors: *const u32,
+    /// Number of selectors
+    pub selector_count: usize,
+    /// Protocols
+    pub protocols: *const ImageProtocol,
+    /// Number of protocols
+    pub protocol_count: usize,
+    /// Required methods of all protocols
+    pub requirements: *const ImageRequirement,
+    /// Number of required methods
+    pub requirement_count: usize,
+    /// Classes, superclasses first
+    pub classes: *const ImageClass,
+    /// Number of classes
+    pub class_count: usize,
+    /// Instance variables of all classes
+    pub ivars: *const ImageIvar,
+    /// Number of instance variables
+    pub ivar_count: usize,
+    /// Methods of all classes
+    pub methods: *const ImageMethod,
+    /// Number of methods
+    pub method_count: usize,
+    /// Names of the protocols each class adopts (string indices)
+    pub adopted: *const u32,
+    /// Number of adopted protocol names
+    pub adopted_count: usize,
+}
+
+impl Image {
+    /// An image of the current version with every table empty.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self {
+            version: IMAGE_VERSION,
+            features: 0,
+            strings: std::ptr::null(),
+            string_count: 0,
+            selectors: std::ptr::null(),
+            selector_count: 0,
+            protocols: std::ptr::null(),
+            protocol_count: 0,
+            requirements: std::ptr::null(),
+            requirement_count: 0,
+            classes: std::ptr::null(),
+            class_count: 0,
+            ivars: std::ptr::null(),
+            ivar_count: 0,
+            methods: std::ptr::null(),
+            method_count: 0,
+            adopted: std::ptr::null(),
+            adopted_count: 0,
+        }
+    }
+}
+
+/// View a table as a slice.
+///
+/// # Safety
+///
+/// `records` must be valid for `count` records, or `count` must be zero.
+unsafe fn table<'a, T>(records: *const T, count: usize) -> &'a [T] {
+    if count == 0 {
+        &[]
+    } else {
+        // SAFETY: guaranteed by the caller
+        unsafe { std::slice::from_raw_parts(records, count) }
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidImage {
+        reason: reason.into(),
+    }
+}
+
+/// Look up a record by index.
+fn entry<'a, T>(records: &'a [T], index: u32, what: &str) -> Result<&'a T> {
+    records
+        .get(index as usize)
+        .ok_or_else(|| invalid(format!("{what} index {index} out of range")))
+}
+
+/// Look up the member range `start..end` of a record.
+fn range<'a, T>(
+    records: &'a [T],
+    start: u32,
+    end: u32,
+    what: &str,
+) -> Result<&'a [T]> {
+    records
+        .get(start as usize..end as usize)
+        .ok_or_else(|| invalid(format!("{what} range {start}..{end} invalid")))
+}
+
+/// Register the selectors, protocols and classes of an image.
+///
+/// Returns the registered classes in image order. Protocols a class adopts
+/// that the image does not describe are looked up among the registered
+/// protocols, and created empty if there is none.
+///
+/// # Safety
+///
+/// Every table pointer of the image must be valid for its count, every
+/// string must point to `len` readable bytes, and every `imp` must be a
+/// method implementation following the [`Imp`] contract.
+///
+/// # Errors
+///
+/// Returns [`Error::Incompatible`] if the image requires a feature the
+/// runtime lacks (see [`capabilities`]).
+/// Returns [`Error::InvalidImage`] if the image has another version, an
+/// index or range out of bounds, a string that is not UTF-8, or a class
+/// whose superclass is neither in the image before it nor registered.
+/// Returns the runtime's error if a class cannot be created, for example
+/// because a class of the same name exists.
+pub unsafe fn register_image(image: &Image) -> Result<Vec<Class>> {
+    if image.version != IMAGE_VERSION {
+        return Err(invalid(format!(
+            "version {} is not {IMAGE_VERSION}",
+            image.version
+        )));
+    }
+    capabilities().check("image", &Capabilities::requiring(image.features))?;
+    // SAFETY: the caller guarantees every table is valid for its count
+    let (strings, selectors, protocols, requirements) = unsafe {
+        (
+            table(image.strings, image.string_count),
+            table(image.selectors, image.selector_count),
+            table(image.protocols, image.protocol_count),
+            table(image.requirements, image.requirement_count),
+        )
+    };
+    // SAFETY: as above
+    let (classes, ivars, methods, adopted) = unsafe {
+        (
+            table(image.classes, image.class_count),
+            table(image.ivars, image.ivar_count),
+            table(image.methods, image.method_count),
+            table(image.adopted, image.adopted_count),
+        )
+    };
+
+    let strings: Vec<&str> = strings
+        .iter()
+        .map(|string| {
+            // SAFETY: the caller guarantees each string's bytes are readable
+            let bytes = unsafe { table(string.bytes, string.len) };
+            std::str::from_utf8(bytes)
+                .map_err(|_| invalid("string is not UTF-8"))
+        })
+        .collect::<Result<_>>()?;
+    let string = |index: u32| entry(&strings, index, "string").copied();
+    let selectors: Vec<Selector> = selectors
+        .iter()
+        .map(|&name| Selector::from_str(string(name)?))
+        .collect::<Result<_>>()?;
+    let selector = |index: u32| entry(&selectors, index, "selector").cloned();
+
+    let arena = get_global_arena();
+    let mut registered: Vec<Protocol> = Vec::with_capacity(protocols.len());
+    for protocol in protocols {
+        let created = Protocol::new(string(protocol.name)?, None)?;
+        for requirement in range(
+            requirements,
+            protocol.requirements_start,
+            protocol.requirements_end,
+            "requirement",
+        )? {
+            created.add_required(
+                selector(requirement.selector)?,
+                string(requirement.types)?,
+                arena,
+            )?;
+        }
+        registered.push(created);
+    }
+
+    let mut created: Vec<Class> = Vec::with_capacity(classes.len());
+    for class in classes {
+        let name = string(class.name)?;
+        let superclass = match class.superclass_kind {
+            SUPERCLASS_NONE => None,
+            SUPERCLASS_LOCAL => {
+                Some(created.get(class.superclass as usize).cloned().ok_or_else(
+                    || invalid(format!("superclass of '{name}' follows it")),
+                )?)
+            }
+            SUPERCLASS_EXTERNAL => {
+                let superclass = string(class.superclass)?;
+                Some(class_from_name(superclass).ok_or_else(|| {
+                    invalid(format!(
+                        "superclass '{superclass}' of '{name}' is unknown"
+                    ))
+                })?)
+            }
+            kind => {
+                return Err(invalid(format!(
+                    "superclass kind {kind} of '{name}' is unknown"
+                )));
+            }
+        };
+        let runtime_class = match &superclass {
+            Some(superclass) => Class::new(name, superclass)?,
+            None => Class::new_root(name)?,
+        };
+
+        for &protocol in range(
+            adopted,
+            class.protocols_start,
+            class.protocols_end,
+            "adopted protocol",
+        )? {
+            let protocol_name = string(protocol)?;
+            let protocol = match registered
+                .iter()
+                .chain(&all_protocols())
+                .find(|protocol| protocol.name() == protocol_name)
+            {
+                Some(protocol) => protocol.clone(),
+                None => {
+                    let protocol = Protocol::new(protocol_name, None)?;
+                    registered.push(protocol.clone());
+                    protocol
+                }
+            };
+            runtime_class.add_protocol(&protocol)?;
+        }
+
+        for ivar in range(
+            ivars,
+            class.ivars_start,
+            class.ivars_end,
+            "instance variable",
+        )? {
+            runtime_class.add_ivar(Ivar::new(
+                RuntimeString::new(string(ivar.name)?, arena),
+                RuntimeString::new(string(ivar.types)?, arena),
+            ))?;
+        }
+
+        for method in range(
+            methods,
+            class.methods_start,
+            class.methods_end,
+            "method",
+        )? {
+            let Some(imp) = method.imp else { continue };
+            if method.flags & METHOD_STATIC != 0 {
+                continue;
+            }
+            runtime_class.add_method(Method {
+                selector: selector(method.selector)?,
+                imp,
+                types: RuntimeString::new(string(method.types)?, arena),
+            })?;
+        }
+        created.push(runtime_class);
+    }
+
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::MessageArgs;
+    use crate::runtime::introspection::instance_variables;
+    use crate::runtime::object::{Object, ObjectPtr};
+    use crate::runtime::selector::SelectorHandle;
+
+    unsafe extern "C-unwind" fn answer(
+        _self: ObjectPtr,
+        _cmd: SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(42) };
+    }
+
+    fn strings(names: &[&'static str]) -> Vec<ImageString> {
+        names
+            .iter()
+            .map(|name| ImageString {
+                bytes: name.as_ptr(),
+                len: name.len(),
+            })
+            .collect()
+    }
+
+    fn class(
+        name: u32,
+        superclass_kind: u32,
+        methods: (u32, u32),
+    ) -> ImageClass {
+        ImageClass {
+            name,
+            superclass_kind,
+            superclass: 0,
+            ivars_start: 0,
+            ivars_end: 0,
+            methods_start: methods.0,
+            methods_end: methods.1,
+            protocols_start: 0,
+            protocols_end: u32::from(superclass_kind == SUPERCLASS_NONE),
+        }
+    }
+
+    #[test]
+    fn test_register_image() {
+        let strings = strings(&[
+            "ImageBase",
+            "ImageDerived",
+            "answer",
+            "q@:",
+            "ImageAnswering",
+            "value",
+            "q",
+        ]);
+        let selectors = [2];
+        let protocols = [ImageProtocol {
+            name: 4,
+            requirements_start: 0,
+            requirements_end: 1,
+        }];
+        let requirements = [ImageRequirement {
+            selector: 0,
+            types: 3,
+        }];
+        let mut classes = [
+            class(0, SUPERCLASS_NONE, (0, 1)),
+            class(1, SUPERCLASS_LOCAL, (1, 2)),
+        ];
+        classes[0].ivars_end = 1;
+        let ivars = [ImageIvar { name: 5, types: 6 }];
+        let method = |flags| ImageMethod {
+            selector: 0,
+            types: 3,
+            flags,
+            imp: Some(answer),
+        };
+        let methods = [method(0), method(METHOD_STATIC)];
+        let adopted = [4];
+        let image = Image {
+            strings: strings.as_ptr(),
+            string_count: strings.len(),
+            selectors: selectors.as_ptr(),
+            selector_count: selectors.len(),
+            protocols: protocols.as_ptr(),
+            protocol_count: protocols.len(),
+            requirements: requirements.as_ptr(),
+            requirement_count: requirements.len(),
+            classes: classes.as_ptr(),
+            class_count: classes.len(),
+            ivars: ivars.as_ptr(),
+            ivar_count: ivars.len(),
+            methods: methods.as_ptr(),
+            method_count: methods.len(),
+            adopted: adopted.as_ptr(),
+            adopted_count: adopted.len(),
+            ..Image::empty()
+        };
+
+        // SAFETY: every table outlives the call
+        let registered = unsafe { register_image(&image) }.unwrap();
+        assert_eq!(registered[1].super_class().unwrap().name(), "ImageBase");
+        let protocols = registered[1].protocols();
+        assert_eq!(protocols.len(), 1);
+        assert_eq!(protocols[0].name(), "ImageAnswering");
+        assert!(registered[1].conforms_to(&protocols[0]));
+        let ivars = instance_variables(&registered[1]);
+        assert_eq!(ivars.len(), 1);
+        assert_eq!(ivars[0].name.as_str().unwrap(), "value");
+        assert_eq!(ivars[0].types.as_str().unwrap(), "q");
+
+        // The derived class inherits the method, its static one aside
+        let derived = Object::new(&registered[1]).unwrap();
+        let selector = Selector::from_str("answer").unwrap();
+        let result = derived.send_message(&selector, &MessageArgs::None);
+        assert_eq!(result.unwrap(), Some(42));
+    }
+
+    #[test]
+    fn test_invalid_images_are_rejected() {
+        let old = Image {
+            version: 0,
+            ..Image::empty()
+        };
+        // SAFETY: the image has no tables
+        let result = unsafe { register_image(&old) };
+        assert!(matches!(result, Err(Error::InvalidImage { .. })));
+
+        let tagged = Image {
+            features: crate::runtime::capabilities::FEATURE_TAGGED_POINTERS,
+            ..Image::empty()
+        };
+        // SAFETY: the image has no tables
+        let result = unsafe { register_image(&tagged) };
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Incompatible image: requires tagged pointers, which the runtime \
+             lacks"
+        );
+
+        let strings = strings(&["ImageOrphan"]);
+        let classes = [ImageClass {
+            superclass: 7,
+            ..class(0, SUPERCLASS_LOCAL, (0, 0))
+        }];
+        let orphan = Image {
+            strings: strings.as_ptr(),
+            string_count: strings.len(),
+            classes: classes.as_ptr(),
+            class_count: classes.len(),
+            ..Image::empty()
+        };
+        // SAFETY: every table outlives the call
+        let result = unsafe { register_image(&orphan) };
+        let Err(Error::InvalidImage { reason }) = result else {
+            panic!("unexpected {result:?}")
+        };
+        assert_eq!(reason, "superclass of 'ImageOrphan' follows it");
+        assert!(class_from_name("ImageOrphan").is_none());
+    }
+}
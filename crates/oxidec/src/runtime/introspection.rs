@@ -5,8 +5,10 @@
 //!
 //! - **Class enumeration** - List all registered classes, find by name
 //! - **Method enumeration** - List methods for a class, find implementations
+//! - **Property enumeration** - List the properties a class declares
 //! - **Protocol inspection** - List protocols, check conformance
-//! - **Dynamic class creation** - Build classes at runtime
+//! - **Dynamic class creation** - Build classes at runtime, and dispose of
+//!   them so their names can be registered again
 //!
 //! # Example
 //!
@@ -28,9 +30,12 @@
 //! }
 //! ```
 
-use crate::error::Result;
-use crate::runtime::{Class, Method, Object, Protocol, Selector};
-use std::collections::HashMap;
+use crate::error::{Error, Result};
+use crate::runtime::sync::Recover;
+use crate::runtime::{
+    Class, Ivar, Method, Object, Property, Protocol, Selector,
+};
+use std::collections::BTreeMap;
 use std::sync::RwLock;
 
 use super::get_global_arena;
@@ -42,9 +47,10 @@ use super::get_global_arena;
 /// Global class registry for tracking all created classes.
 ///
 /// This registry maintains weak references to all classes to allow
-/// enumeration without preventing garbage collection.
+/// enumeration without preventing garbage collection. It is ordered by name,
+/// so enumeration is the same in every run.
 static CLASS_REGISTRY: std::sync::OnceLock<
-    std::sync::RwLock<HashMap<String, Class>>,
+    std::sync::RwLock<BTreeMap<String, Class>>,
 > = std::sync::OnceLock::new();
 
 /// Register a class in the global registry.
@@ -53,16 +59,16 @@ static CLASS_REGISTRY: std::sync::OnceLock<
 pub(crate) fn register_class(class: &Class) {
     let name = class.name().to_string();
     CLASS_REGISTRY
-        .get_or_init(|| RwLock::new(HashMap::new()))
+        .get_or_init(|| RwLock::new(BTreeMap::new()))
         .write()
-        .unwrap()
+        .recover()
         .insert(name, class.clone());
 }
 
 /// Enumerate all registered classes.
 ///
-/// Returns a vector of all classes currently registered in the runtime.
-/// Classes that have been dropped will not appear in the list.
+/// Returns a vector of all classes currently registered in the runtime, in
+/// name order. Classes that have been dropped will not appear in the list.
 ///
 /// # Returns
 ///
@@ -79,14 +85,23 @@ pub(crate) fn register_class(class: &Class) {
 #[must_use]
 pub fn all_classes() -> Vec<Class> {
     CLASS_REGISTRY
-        .get_or_init(|| RwLock::new(HashMap::new()))
+        .get_or_init(|| RwLock::new(BTreeMap::new()))
         .read()
-        .unwrap()
+        .recover()
         .values()
         .cloned()
         .collect()
 }
 
+/// Returns the number of registered classes, for memory reports.
+pub(crate) fn class_count() -> usize {
+    CLASS_REGISTRY
+        .get_or_init(|| RwLock::new(BTreeMap::new()))
+        .read()
+        .recover()
+        .len()
+}
+
 /// Get a class by name.
 ///
 /// Searches the global registry for a class with the given name.
@@ -111,9 +126,9 @@ pub fn all_classes() -> Vec<Class> {
 #[must_use]
 pub fn class_from_name(name: &str) -> Option<Class> {
     CLASS_REGISTRY
-        .get_or_init(|| RwLock::new(HashMap::new()))
+        .get_or_init(|| RwLock::new(BTreeMap::new()))
         .read()
-        .unwrap()
+        .recover()
         .get(name)
         .cloned()
 }
@@ -250,6 +265,99 @@ pub fn class_methods(_class: &Class) -> Vec<Method> {
     Vec::new()
 }
 
+/// Enumerate all instance variables of a class.
+///
+/// Collects the variables declared by the class and its superclasses, with
+/// superclass variables first, each class's in declaration order.
+///
+/// # Arguments
+///
+/// * `class` - The class to enumerate instance variables for
+///
+/// # Returns
+///
+/// A vector of instance variables.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::runtime::{
+///     Class, Ivar, RuntimeString, get_global_arena,
+///     introspection::instance_variables,
+/// };
+///
+/// let arena = get_global_arena();
+/// let ivar = |name| {
+///     let name = RuntimeString::new(name, arena);
+///     Ivar::new(name, RuntimeString::new("q", arena))
+/// };
+/// let point = Class::new_root("IvarPoint").unwrap();
+/// point.add_ivar(ivar("x")).unwrap();
+/// let point3 = Class::new("IvarPoint3", &point).unwrap();
+/// point3.add_ivar(ivar("z")).unwrap();
+///
+/// let names: Vec<_> = instance_variables(&point3)
+///     .iter()
+///     .map(|ivar| ivar.name.as_str().unwrap().to_string())
+///     .collect();
+/// assert_eq!(names, ["x", "z"]);
+/// ```
+#[must_use]
+pub fn instance_variables(class: &Class) -> Vec<Ivar> {
+    class_hierarchy(class)
+        .iter()
+        .rev()
+        .flat_map(Class::ivars)
+        .collect()
+}
+
+/// Enumerate all properties of a class.
+///
+/// Collects the properties declared by the class and its superclasses,
+/// with superclass properties first, each class's in declaration order.
+///
+/// # Arguments
+///
+/// * `class` - The class to enumerate properties for
+///
+/// # Returns
+///
+/// A vector of properties.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::runtime::{
+///     Class, Property, PropertyAttributes, RuntimeString, get_global_arena,
+///     introspection::class_properties,
+/// };
+///
+/// let arena = get_global_arena();
+/// let property = |name| Property {
+///     name: RuntimeString::new(name, arena),
+///     types: RuntimeString::new("d", arena),
+///     attributes: PropertyAttributes::default(),
+/// };
+/// let shape = Class::new_root("PropertyShape").unwrap();
+/// shape.add_property(property("area")).unwrap();
+/// let circle = Class::new("PropertyCircle", &shape).unwrap();
+/// circle.add_property(property("radius")).unwrap();
+///
+/// let names: Vec<_> = class_properties(&circle)
+///     .iter()
+///     .map(|property| property.getter_name().to_string())
+///     .collect();
+/// assert_eq!(names, ["area", "radius"]);
+/// ```
+#[must_use]
+pub fn class_properties(class: &Class) -> Vec<Property> {
+    class_hierarchy(class)
+        .iter()
+        .rev()
+        .flat_map(Class::properties)
+        .collect()
+}
+
 /// Check if a class responds to a selector.
 ///
 /// Searches the class hierarchy for a method matching the selector.
@@ -466,8 +574,8 @@ fn protocol_matches(a: &Protocol, b: &Protocol) -> bool {
 
 /// Builder for dynamically creating classes at runtime.
 ///
-/// Provides a fluent API for creating classes with methods, instance
-/// variables, and protocols.
+/// Provides a fluent API for creating classes with methods, properties,
+/// and protocols.
 ///
 /// # Example
 ///
@@ -482,6 +590,8 @@ pub struct ClassBuilder {
     name: String,
     superclass: Option<Class>,
     methods: Vec<(Selector, super::class::Imp)>,
+    ivars: Vec<Ivar>,
+    properties: Vec<Property>,
     protocols: Vec<Protocol>,
 }
 
@@ -509,6 +619,8 @@ impl ClassBuilder {
             name: name.to_string(),
             superclass: superclass.cloned(),
             methods: Vec::new(),
+            ivars: Vec::new(),
+            properties: Vec::new(),
             protocols: Vec::new(),
         }
     }
@@ -545,6 +657,82 @@ impl ClassBuilder {
         self
     }
 
+    /// Add an instance variable to the class.
+    ///
+    /// Variables are laid out in the order they are added, after the
+    /// superclass's (see [`Class::ivars`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `ivar` - The instance variable to declare
+    ///
+    /// # Returns
+    ///
+    /// `&mut self` for chaining.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{
+    ///     Ivar, RuntimeString, get_global_arena, introspection::ClassBuilder,
+    /// };
+    ///
+    /// let arena = get_global_arena();
+    /// let mut builder = ClassBuilder::new("BuiltWithIvars", None);
+    /// builder
+    ///     .add_ivar(Ivar::new(
+    ///         RuntimeString::new("flag", arena),
+    ///         RuntimeString::new("i", arena),
+    ///     ))
+    ///     .add_ivar(Ivar::new(
+    ///         RuntimeString::new("count", arena),
+    ///         RuntimeString::new("q", arena),
+    ///     ));
+    /// let class = builder.register().unwrap();
+    /// let ivars = class.ivars();
+    /// assert_eq!((ivars[0].offset, ivars[1].offset), (0, 8));
+    /// ```
+    pub fn add_ivar(&mut self, ivar: Ivar) -> &mut Self {
+        self.ivars.push(ivar);
+        self
+    }
+
+    /// Add a property to the class.
+    ///
+    /// The property's accessors are synthesized when the class is
+    /// registered, unless the builder adds methods with their selectors.
+    ///
+    /// # Arguments
+    ///
+    /// * `property` - The property to declare
+    ///
+    /// # Returns
+    ///
+    /// `&mut self` for chaining.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{
+    ///     Property, PropertyAttributes, RuntimeString, get_global_arena,
+    ///     introspection::ClassBuilder,
+    /// };
+    ///
+    /// let arena = get_global_arena();
+    /// let mut builder = ClassBuilder::new("BuiltWithProperty", None);
+    /// builder.add_property(Property {
+    ///     name: RuntimeString::new("name", arena),
+    ///     types: RuntimeString::new("@", arena),
+    ///     attributes: PropertyAttributes::default(),
+    /// });
+    /// let class = builder.register().unwrap();
+    /// assert_eq!(class.properties().len(), 1);
+    /// ```
+    pub fn add_property(&mut self, property: Property) -> &mut Self {
+        self.properties.push(property);
+        self
+    }
+
     /// Add a protocol to the class.
     ///
     /// # Arguments
@@ -572,7 +760,8 @@ impl ClassBuilder {
 
     /// Register the class with the runtime.
     ///
-    /// Creates the class and registers all methods and protocols.
+    /// Creates the class and registers all methods, instance variables,
+    /// properties and protocols.
     ///
     /// # Returns
     ///
@@ -581,7 +770,9 @@ impl ClassBuilder {
     /// # Errors
     ///
     /// Returns `Error::ClassAlreadyExists` if a class with this name
-    /// already exists.
+    /// already exists, `Error::IvarAlreadyExists` if an instance variable's
+    /// name is already declared, or the error [`Class::add_property`]
+    /// returns for a property that cannot be declared.
     ///
     /// # Example
     ///
@@ -612,6 +803,17 @@ impl ClassBuilder {
             class.add_method(method)?;
         }
 
+        // Add instance variables, before properties so a property's backing
+        // variable cannot take a declared variable's name
+        for ivar in self.ivars {
+            class.add_ivar(ivar)?;
+        }
+
+        // Add properties, after methods so hand-written accessors are kept
+        for property in self.properties {
+            class.add_property(property)?;
+        }
+
         // Add protocols
         for protocol in &self.protocols {
             class.add_protocol(protocol)?;
@@ -647,6 +849,63 @@ pub fn allocate_class(name: &str, superclass: Option<&Class>) -> ClassBuilder {
     ClassBuilder::new(name, superclass)
 }
 
+/// Dispose of a class, removing it from the registry.
+///
+/// Once disposed, the class is no longer found by name or enumerated, and
+/// a class of the same name can be registered again, as a REPL or a
+/// hot-reloading host does when a class is redefined. The cache generation
+/// is advanced so no dispatch keeps using the disposed class's methods.
+///
+/// The class's memory is never freed, so handles to it remain valid, but
+/// `Object::new` refuses to instantiate it, and it should not be
+/// subclassed again.
+///
+/// # Thread Safety
+///
+/// Subclasses and instances are checked and the class unregistered under
+/// the class table's lock. A subclass registered or an instance created
+/// concurrently either keeps the class registered or, once it is disposed,
+/// fails to be created.
+///
+/// # Errors
+///
+/// Returns `Error::ClassNotDisposable` if the class is not registered (it
+/// was disposed already), or if it has live instances or registered
+/// subclasses.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::runtime::{
+///     Class, Object,
+///     introspection::{class_from_name, dispose_class},
+/// };
+///
+/// let class = Class::new_root("DisposeExample").unwrap();
+/// let object = Object::new(&class).unwrap();
+/// assert!(dispose_class(&class).is_err());
+///
+/// drop(object);
+/// dispose_class(&class).unwrap();
+/// assert!(class_from_name("DisposeExample").is_none());
+/// assert!(Object::new(&class).is_err());
+///
+/// let redefined = Class::new_root("DisposeExample").unwrap();
+/// assert_ne!(redefined, class);
+/// ```
+pub fn dispose_class(class: &Class) -> Result<()> {
+    class.unregister()?;
+
+    let mut registry = CLASS_REGISTRY
+        .get_or_init(|| RwLock::new(BTreeMap::new()))
+        .write()
+        .recover();
+    if registry.get(class.name()) == Some(class) {
+        registry.remove(class.name());
+    }
+    Ok(())
+}
+
 // ============================================================================
 // Object Introspection
 // ============================================================================
@@ -740,6 +999,93 @@ pub fn object_responds_to(object: &Object, selector: &Selector) -> bool {
     has_method(&object.class(), selector)
 }
 
+/// Get the value of an instance variable of an object.
+///
+/// Like Objective-C's `object_getIvar`, takes the variable's descriptor,
+/// which must be one the object's class or a superclass lays out, with the
+/// offset of its current layout (see [`Class::ivars`]).
+///
+/// # Arguments
+///
+/// * `object` - The object to read
+/// * `ivar` - The instance variable to read
+///
+/// # Returns
+///
+/// The variable's value, type-erased as for [`Object::ivar`].
+///
+/// # Errors
+///
+/// Returns `Err(Error::IvarNotFound)` if the object's layout has no such
+/// variable at that offset, or `Err(Error::IvarTypeMismatch)` if it holds
+/// an object, which [`Object::object_ivar`] reads.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::runtime::{
+///     Class, Ivar, Object, RuntimeString, get_global_arena,
+///     introspection::{object_get_ivar, object_set_ivar},
+/// };
+///
+/// let arena = get_global_arena();
+/// let base = Class::new_root("IvarAccessBase").unwrap();
+/// base.add_ivar(Ivar::new(
+///     RuntimeString::new("width", arena),
+///     RuntimeString::new("q", arena),
+/// ))
+/// .unwrap();
+/// let derived = Class::new("IvarAccessDerived", &base).unwrap();
+///
+/// // Variables of a superclass are found in its subclasses' layouts
+/// let width = &base.ivars()[0];
+/// let object = Object::new(&derived).unwrap();
+/// object_set_ivar(&object, width, 12).unwrap();
+/// assert_eq!(object_get_ivar(&object, width).unwrap(), 12);
+/// ```
+pub fn object_get_ivar(object: &Object, ivar: &Ivar) -> Result<usize> {
+    object.ivar(laid_out_name(object, ivar)?)
+}
+
+/// Set the value of an instance variable of an object.
+///
+/// Like Objective-C's `object_setIvar`, takes the variable's descriptor,
+/// which must be one the object's class or a superclass lays out, with the
+/// offset of its current layout (see [`Class::ivars`]).
+///
+/// # Arguments
+///
+/// * `object` - The object to write
+/// * `ivar` - The instance variable to write
+/// * `value` - The value to store, type-erased as for [`Object::set_ivar`]
+///
+/// # Errors
+///
+/// Returns `Err(Error::IvarNotFound)` if the object's layout has no such
+/// variable at that offset, or `Err(Error::IvarTypeMismatch)` if it holds
+/// an object, which [`Object::set_object_ivar`] stores.
+pub fn object_set_ivar(
+    object: &Object,
+    ivar: &Ivar,
+    value: usize,
+) -> Result<()> {
+    object.set_ivar(laid_out_name(object, ivar)?, value)
+}
+
+/// Returns the name of `ivar` if the object's layout has it at its offset.
+fn laid_out_name<'a>(object: &Object, ivar: &'a Ivar) -> Result<&'a str> {
+    let name = ivar.name.as_str().unwrap_or_default();
+    instance_variables(&object.class())
+        .iter()
+        .any(|laid_out| {
+            laid_out.name == ivar.name && laid_out.offset == ivar.offset
+        })
+        .then_some(name)
+        .ok_or_else(|| Error::IvarNotFound {
+            name: name.to_string(),
+        })
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -747,6 +1093,7 @@ pub fn object_responds_to(object: &Object, selector: &Selector) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::runtime::RuntimeString;
     use std::str::FromStr;
     use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -880,6 +1227,71 @@ mod tests {
         assert_eq!(class.super_class().unwrap().name(), parent.name());
     }
 
+    #[test]
+    fn test_ivar_layout_follows_superclass() {
+        let arena = get_global_arena();
+        let ivar = |name: &str, types: &str| {
+            Ivar::new(
+                RuntimeString::new(name, arena),
+                RuntimeString::new(types, arena),
+            )
+        };
+        let base = setup_test_class();
+        base.add_ivar(ivar("flag", "i")).unwrap();
+
+        let id = TEST_ID.fetch_add(1, Ordering::SeqCst);
+        let mut builder =
+            ClassBuilder::new(&format!("BuilderTest_{id}"), Some(&base));
+        builder
+            .add_ivar(ivar("ratio", "f"))
+            .add_ivar(ivar("count", "q"));
+        let derived = builder.register().unwrap();
+
+        let offsets = |class: &Class| -> Vec<usize> {
+            instance_variables(class)
+                .iter()
+                .map(|ivar| ivar.offset)
+                .collect()
+        };
+        assert_eq!(offsets(&derived), [0, 8, 16]);
+        assert_eq!(derived.instance_size(), 24);
+
+        // A variable the superclass declares later moves the subclass's,
+        // which live instances of the subclass prevent
+        let ratio = derived.ivars()[0].clone();
+        let object = Object::new(&derived).unwrap();
+        object_set_ivar(&object, &ratio, 7).unwrap();
+        assert_eq!(object_get_ivar(&object, &ratio).unwrap(), 7);
+        assert!(matches!(
+            base.add_ivar(ivar("extra", "q")),
+            Err(Error::IvarLayoutInUse { .. })
+        ));
+        drop(object);
+        base.add_ivar(ivar("extra", "q")).unwrap();
+        assert_eq!(offsets(&derived), [0, 8, 16, 24]);
+        let object = Object::new(&derived).unwrap();
+        assert!(matches!(
+            object_get_ivar(&object, &ratio),
+            Err(Error::IvarNotFound { .. })
+        ));
+        let ratio = &derived.ivars()[0];
+        object_set_ivar(&object, ratio, 7).unwrap();
+        assert_eq!(object_get_ivar(&object, ratio).unwrap(), 7);
+
+        // Variables of unrelated classes are not the object's
+        let other = setup_test_class();
+        other.add_ivar(ivar("ratio", "f")).unwrap();
+        let other_object = Object::new(&other).unwrap();
+        assert!(object_get_ivar(&other_object, ratio).is_err());
+
+        // A subclass cannot declare a name its superclass already has
+        let id = TEST_ID.fetch_add(1, Ordering::SeqCst);
+        let mut builder =
+            ClassBuilder::new(&format!("BuilderTest_{id}"), Some(&base));
+        builder.add_ivar(ivar("flag", "q"));
+        assert!(matches!(builder.register(), Err(Error::IvarAlreadyExists)));
+    }
+
     #[test]
     fn test_object_get_class() {
         let class = setup_test_class();
@@ -917,4 +1329,118 @@ mod tests {
         assert!(class.is_ok());
         assert_eq!(class.unwrap().name(), name);
     }
+
+    unsafe extern "C-unwind" fn answer_one(
+        _this: crate::runtime::ObjectPtr,
+        _cmd: crate::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(1) };
+    }
+
+    unsafe extern "C-unwind" fn answer_two(
+        _this: crate::runtime::ObjectPtr,
+        _cmd: crate::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(2) };
+    }
+
+    #[test]
+    fn test_dispose_class_frees_its_name() {
+        use crate::runtime::MessageArgs;
+
+        let id = TEST_ID.fetch_add(1, Ordering::SeqCst);
+        let name = format!("Disposed_{id}");
+        let answer = Selector::from_str("answer").unwrap();
+        let method = |imp| Method {
+            selector: answer.clone(),
+            imp,
+            types: RuntimeString::new("q@:", get_global_arena()),
+        };
+        let class = Class::new_root(&name).unwrap();
+        class.add_method(method(answer_one)).unwrap();
+        let child = Class::new(&format!("DisposedChild_{id}"), &class).unwrap();
+        let object = Object::new(&class).unwrap();
+        assert_eq!(
+            object.send_message(&answer, &MessageArgs::None),
+            Ok(Some(1))
+        );
+
+        // Instances and subclasses keep the class registered
+        let err = dispose_class(&class).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Cannot dispose class '{name}': subclass 'DisposedChild_{id}' \
+                 is registered"
+            )
+        );
+        dispose_class(&child).unwrap();
+        let err = dispose_class(&class).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Cannot dispose class '{name}': it has 1 live instance")
+        );
+        assert_eq!(class.instance_count(), 1);
+
+        drop(object);
+        assert_eq!(class.instance_count(), 0);
+        dispose_class(&class).unwrap();
+        assert!(class_from_name(&name).is_none());
+        assert!(!all_classes().contains(&class));
+        assert!(matches!(
+            dispose_class(&class),
+            Err(Error::ClassNotDisposable { .. })
+        ));
+        // The disposed class can no longer be instantiated
+        let err = Object::new(&class).unwrap_err();
+        assert_eq!(err.to_string(), format!("Class '{name}' was disposed"));
+        assert_eq!(class.instance_count(), 0);
+
+        // The name can be registered again, with methods of its own
+        let redefined = Class::new_root(&name).unwrap();
+        redefined.add_method(method(answer_two)).unwrap();
+        assert_ne!(redefined, class);
+        assert_eq!(class_from_name(&name), Some(redefined.clone()));
+        let object = Object::new(&redefined).unwrap();
+        assert_eq!(
+            object.send_message(&answer, &MessageArgs::None),
+            Ok(Some(2))
+        );
+    }
+
+    #[test]
+    fn test_dispose_class_races_instantiation() {
+        let id = TEST_ID.fetch_add(1, Ordering::SeqCst);
+        let class = Class::new_root(&format!("DisposedRace_{id}")).unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let class = class.clone();
+                std::thread::spawn(move || {
+                    loop {
+                        match Object::new(&class) {
+                            Ok(object) => drop(object),
+                            Err(Error::ClassDisposed { .. }) => break,
+                            Err(err) => panic!("{err}"),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // Disposal succeeds only between instances, and none are created
+        // once it has
+        while dispose_class(&class).is_err() {
+            std::thread::yield_now();
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(class.instance_count(), 0);
+    }
 }
@@ -30,7 +30,7 @@
 //! use oxidec::runtime::get_global_arena;
 //! use std::str::FromStr;
 //!
-//! # unsafe extern "C" fn noop_impl(
+//! # unsafe extern "C-unwind" fn noop_impl(
 //! #     _self: oxidec::runtime::object::ObjectPtr,
 //! #     _cmd: oxidec::runtime::selector::SelectorHandle,
 //! #     _args: *const *mut u8,
@@ -98,7 +98,7 @@ const MAX_ARGS: usize = 16;
 /// use oxidec::runtime::get_global_arena;
 /// use std::str::FromStr;
 ///
-/// # unsafe extern "C" fn noop_impl(
+/// # unsafe extern "C-unwind" fn noop_impl(
 /// #     _self: oxidec::runtime::object::ObjectPtr,
 /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
 /// #     _args: *const *mut u8,
@@ -663,7 +663,7 @@ impl Invocation {
     /// use oxidec::runtime::get_global_arena;
     /// use std::str::FromStr;
     ///
-    /// # unsafe extern "C" fn noop_impl(
+    /// # unsafe extern "C-unwind" fn noop_impl(
     /// #     _self: oxidec::runtime::object::ObjectPtr,
     /// #     _cmd: oxidec::runtime::selector::SelectorHandle,
     /// #     _args: *const *mut u8,
@@ -731,7 +731,7 @@ impl Invocation {
                 self.selector(),
                 &args,
             )
-        };
+        }?;
 
         // Store return value if non-void
         self.flags.invoked = true;
@@ -0,0 +1,150 @@
+//! Runtime memory reports.
+//!
+//! [`memory_report`] gathers what the runtime holds in one snapshot: the
+//! global arena's chunks and the bytes allocated in them, the number of
+//! interned selectors and registered classes, the strings interned in the
+//! arena, and the invocation pool of the calling thread. Metadata is never
+//! freed, so the arena only grows over a program's life, even as classes
+//! are disposed; comparing two reports shows what a stretch of it
+//! allocated.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::{Class, memory_report};
+//!
+//! let before = memory_report();
+//! let _class = Class::new_root("MemoryReportExample").unwrap();
+//! let after = memory_report();
+//!
+//! assert!(after.classes > before.classes);
+//! assert!(after.arena.total_allocated > before.arena.total_allocated);
+//! println!("{after}");
+//! ```
+
+use crate::runtime::{PoolStats, PooledInvocation, get_global_arena};
+use oxidex_mem::ArenaStats;
+use std::fmt;
+
+/// The strings interned in the global arena.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StringStats {
+    /// Number of interned strings (short strings are stored inline and
+    /// never interned)
+    pub interned: usize,
+    /// Arena bytes their storage takes, headers included
+    pub bytes: usize,
+}
+
+/// A snapshot of the memory the runtime holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The global arena, where all runtime metadata lives
+    pub arena: ArenaStats,
+    /// Number of interned selectors
+    pub selectors: usize,
+    /// Number of registered classes
+    pub classes: usize,
+    /// The interned strings
+    pub strings: StringStats,
+    /// The calling thread's invocation pool, unless the thread is being
+    /// torn down
+    pub pool: Option<PoolStats>,
+}
+
+/// Takes a snapshot of the memory the runtime holds.
+///
+/// # Thread Safety
+///
+/// Each figure is read under its own lock, so a report taken while other
+/// threads allocate may mix figures from before and after an allocation.
+#[must_use]
+pub fn memory_report() -> MemoryReport {
+    let (interned, bytes) = crate::runtime::string::intern_stats();
+    MemoryReport {
+        arena: get_global_arena().stats(),
+        selectors: crate::runtime::selector::interned_count(),
+        classes: crate::runtime::introspection::class_count(),
+        strings: StringStats { interned, bytes },
+        pool: PooledInvocation::pool_stats(),
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ArenaStats {
+            total_allocated,
+            chunk_count,
+            total_capacity,
+        } = self.arena;
+        let plural = if chunk_count == 1 { "" } else { "s" };
+        writeln!(f, "runtime memory:")?;
+        writeln!(
+            f,
+            "  arena      {total_allocated} of {total_capacity} bytes in \
+             {chunk_count} chunk{plural}"
+        )?;
+        writeln!(f, "  selectors  {}", self.selectors)?;
+        writeln!(f, "  classes    {}", self.classes)?;
+        write!(
+            f,
+            "  strings    {} interned, {} bytes",
+            self.strings.interned, self.strings.bytes
+        )?;
+        if let Some(pool) = self.pool {
+            write!(
+                f,
+                "\n  pool       {} pooled, {} hits, {} misses",
+                pool.pool_size, pool.hits, pool.misses
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{RuntimeString, Selector};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_report_grows_with_runtime_metadata() {
+        let before = memory_report();
+        let _sel = Selector::from_str("memoryReportSelector:").unwrap();
+        let _class = crate::runtime::Class::new_root("MemoryReport").unwrap();
+        let _string = RuntimeString::intern("a string long enough to intern");
+        let after = memory_report();
+
+        // Other tests allocate concurrently, so only growth is certain
+        assert!(after.selectors > before.selectors);
+        assert!(after.classes > before.classes);
+        assert!(after.strings.interned > before.strings.interned);
+        assert!(after.strings.bytes > before.strings.bytes);
+        assert!(after.arena.total_allocated > before.arena.total_allocated);
+        assert!(after.arena.total_capacity >= after.arena.total_allocated);
+    }
+
+    #[test]
+    fn test_display() {
+        let report = MemoryReport {
+            arena: ArenaStats {
+                total_allocated: 100,
+                chunk_count: 1,
+                total_capacity: 4096,
+            },
+            selectors: 3,
+            classes: 2,
+            strings: StringStats {
+                interned: 1,
+                bytes: 64,
+            },
+            pool: None,
+        };
+        assert_eq!(
+            report.to_string(),
+            "runtime memory:\n  arena      100 of 4096 bytes in 1 chunk\n  \
+             selectors  3\n  classes    2\n  strings    1 interned, 64 bytes"
+        );
+    }
+}
@@ -15,6 +15,14 @@
 //! - [`selector`]: Selector interning and caching (✓ Implemented)
 //! - [`class`]: Class creation, inheritance, and method registry (✓ Implemented)
 //! - [`object`]: Object allocation and reference counting (✓ Implemented)
+//! - [`memory`]: Reports of the memory the runtime holds
+//! - [`census`]: Snapshots of the live objects of each class
+//! - [`codable`]: Synthesized `Encodable` and `Decodable` conformance
+//! - [`ffi`]: Loading C libraries and calling their functions
+//! - [`sandbox`]: Capabilities denied to untrusted code
+//! - [`capabilities`]: Versioning and feature checks for plugins and images
+//! - [`exception`]: Thrown exceptions and the panic boundary around sends
+//! - [`notify`]: Broadcasting notifications to observers
 //! - `dispatch`: Message dispatch system (Phase 2 - TODO)
 //! - `cache`: Method caching (Phase 2 - TODO)
 //! - `protocol`: Protocol conformance checking (Phase 3 - TODO)
@@ -36,29 +44,45 @@
 
 // Arena module removed - now using oxidex-mem
 // pub mod arena;
+pub mod capabilities;
 pub mod category;
+pub mod census;
 pub mod class;
+pub mod codable;
 pub mod dispatch;
 pub mod encoding;
+pub mod exception;
+pub mod ffi;
 pub mod forwarding;
+pub mod image;
 pub mod introspection;
 pub mod invocation;
+pub mod memory;
 pub mod message;
+pub mod notify;
 pub mod object;
 pub mod pool;
+pub mod property;
 pub mod protocol;
 pub mod proxy;
+pub mod sandbox;
 pub mod selector;
 pub mod string;
+mod sync;
 
 // Re-export arena types from oxidex-mem for backward compatibility
 pub use oxidex_mem::{GlobalArena as Arena, global_arena as get_global_arena};
+pub use capabilities::{Capabilities, capabilities};
 pub use category::Category;
-pub use class::{Class, Method};
+pub use image::{Image, register_image};
+pub use class::{Class, Ivar, Method};
 pub use invocation::Invocation;
+pub use memory::{MemoryReport, StringStats, memory_report};
 pub use message::MessageArgs;
-pub use object::{Object, ObjectPtr};
+pub use notify::{Notification, NotificationCenter};
+pub use object::{Object, ObjectPtr, WeakRef};
 pub use pool::{PoolStats, PooledInvocation};
+pub use property::{Property, PropertyAttributes};
 pub use protocol::Protocol;
 pub use proxy::{
     LoggingProxy, RemoteProxy, TransparentProxy, bypass_proxy, compose_proxies,
@@ -70,8 +94,10 @@ pub use string::RuntimeString;
 pub use introspection::{
     ClassBuilder, adopted_protocols, all_classes, all_protocols,
     allocate_class, class_from_name, class_hierarchy, class_methods,
-    conforms_to, has_method, instance_methods, is_subclass, method_provider,
-    object_get_class, object_is_instance, object_responds_to, subclasses,
+    class_properties, conforms_to, dispose_class, has_method,
+    instance_methods, instance_variables, is_subclass, method_provider,
+    object_get_class, object_get_ivar, object_is_instance,
+    object_responds_to, object_set_ivar, subclasses,
 };
 
 // Note: Global arena and get_global_arena are now provided by oxidex-mem
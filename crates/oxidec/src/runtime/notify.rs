@@ -0,0 +1,544 @@
+//! Notifications: broadcasting events to observers.
+//!
+//! A [`NotificationCenter`] delivers [`Notification`]s, as
+//! `NSNotificationCenter` does in Objective-C: an object posts one by name,
+//! naming the object it concerns and any extra information, and every
+//! observer registered for that name and object receives it. Posters and
+//! observers know nothing of each other, which is what lets delegates and
+//! observers decouple the parts of a program.
+//!
+//! An observer is either an object, sent a selector taking the
+//! notification as its one argument ([`Notification::from_args`] reads it
+//! in the implementation), or a closure. An observation can be limited to
+//! notifications of one name, from one object, or both.
+//!
+//! # Ownership
+//!
+//! The center holds observing objects and the objects observations are
+//! limited to weakly, so registering never keeps an object alive.
+//! Observations of deallocated objects are dropped.
+//!
+//! # Thread Safety
+//!
+//! Observers can be added, removed and notified from any thread. A post is
+//! delivered synchronously on the posting thread, to the observers
+//! registered when it started, in the order they were registered.
+//! Observers may post, and add or remove observers, while being notified.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::notify::{NotificationCenter, UserInfo};
+//! use std::sync::Arc;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! let center = NotificationCenter::new();
+//! let received = Arc::new(AtomicUsize::new(0));
+//! let counter = Arc::clone(&received);
+//! let token = center.add_observer_fn(Some("DidSave"), None, move |note| {
+//!     assert_eq!(note.name(), "DidSave");
+//!     counter.fetch_add(1, Ordering::Relaxed);
+//! });
+//!
+//! center.post("DidSave", None, UserInfo::new()).unwrap();
+//! center.post("DidLoad", None, UserInfo::new()).unwrap();
+//! assert_eq!(received.load(Ordering::Relaxed), 1);
+//!
+//! center.remove(token);
+//! center.post("DidSave", None, UserInfo::new()).unwrap();
+//! assert_eq!(received.load(Ordering::Relaxed), 1);
+//! ```
+
+use crate::error::{Error, Result};
+use crate::runtime::sync::Recover;
+use crate::runtime::{MessageArgs, Object, Selector, WeakRef};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Extra information a notification carries, by key.
+pub type UserInfo = BTreeMap<String, Object>;
+
+/// An event broadcast through a [`NotificationCenter`].
+#[derive(Debug, Clone)]
+pub struct Notification {
+    /// What happened, such as `DidSave`
+    name: String,
+    /// The object the notification concerns, usually its poster
+    object: Option<Object>,
+    /// Extra information about the event
+    user_info: UserInfo,
+}
+
+impl Notification {
+    /// Creates a notification.
+    #[must_use]
+    pub fn new(
+        name: impl Into<String>,
+        object: Option<&Object>,
+        user_info: UserInfo,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            object: object.cloned(),
+            user_info,
+        }
+    }
+
+    /// Returns the notification's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the object the notification concerns.
+    #[must_use]
+    pub fn object(&self) -> Option<&Object> {
+        self.object.as_ref()
+    }
+
+    /// Returns the extra information the notification carries.
+    #[must_use]
+    pub fn user_info(&self) -> &UserInfo {
+        &self.user_info
+    }
+
+    /// Returns the notification an observing object was sent, from the
+    /// arguments of the implementation of its selector.
+    ///
+    /// # Safety
+    ///
+    /// `args` must be the arguments of a send a [`NotificationCenter`]
+    /// made to deliver a notification, and the notification must not be
+    /// used after the implementation returns.
+    #[must_use]
+    pub unsafe fn from_args<'a>(args: *const *mut u8) -> &'a Self {
+        // SAFETY: the center passes a pointer to the notification as the
+        // one argument, valid for the duration of the send
+        unsafe { &*args.read().cast::<Self>() }
+    }
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(object) = &self.object {
+            write!(f, " from a {}", object.class().name())?;
+        }
+        if !self.user_info.is_empty() {
+            let keys: Vec<&str> =
+                self.user_info.keys().map(String::as_str).collect();
+            write!(f, " with {}", keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies an observation, to remove it with
+/// [`NotificationCenter::remove`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverToken(u64);
+
+/// A closure notified of posts.
+type Callback = Arc<dyn Fn(&Notification) + Send + Sync>;
+
+/// What an observation notifies.
+#[derive(Clone)]
+enum Target {
+    /// An object, sent a selector taking the notification
+    Selector { observer: WeakRef, selector: Selector },
+    /// A closure, called with the notification
+    Closure(Callback),
+}
+
+/// A registration for notifications.
+#[derive(Clone)]
+struct Observation {
+    /// Identifies the observation, to remove it
+    token: ObserverToken,
+    /// Name of the notifications observed, or `None` for every name
+    name: Option<String>,
+    /// Object the observed notifications concern, or `None` for any
+    sender: Option<WeakRef>,
+    /// What is notified
+    target: Target,
+}
+
+impl Observation {
+    /// Returns whether the observation's objects are still alive.
+    fn is_alive(&self) -> bool {
+        let observer = match &self.target {
+            Target::Selector { observer, .. } => observer.is_alive(),
+            Target::Closure(_) => true,
+        };
+        observer && self.sender.as_ref().is_none_or(WeakRef::is_alive)
+    }
+
+    /// Returns whether `notification` is one the observation is for.
+    fn matches(&self, notification: &Notification) -> bool {
+        if self.name.as_ref().is_some_and(|name| *name != notification.name) {
+            return false;
+        }
+        match (&self.sender, &notification.object) {
+            (None, _) => true,
+            (Some(sender), Some(object)) => {
+                sender.upgrade().is_some_and(|sender| sender == *object)
+            }
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Delivers notifications to the observers registered for them.
+pub struct NotificationCenter {
+    /// Observations in registration order
+    observations: RwLock<Vec<Observation>>,
+    /// Number of the next observation's token
+    next_token: AtomicU64,
+}
+
+impl NotificationCenter {
+    /// Creates a center with no observers.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            observations: RwLock::new(Vec::new()),
+            next_token: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the process's shared center.
+    #[must_use]
+    pub fn default_center() -> &'static Self {
+        static DEFAULT: OnceLock<NotificationCenter> = OnceLock::new();
+        DEFAULT.get_or_init(Self::new)
+    }
+
+    /// Registers `observer` to be sent `selector` for notifications named
+    /// `name` concerning `object`, or of every name or object if `None`.
+    ///
+    /// The selector's implementation receives the notification as its one
+    /// argument, read with [`Notification::from_args`]; its type encoding
+    /// is `v@:^`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ArgumentCountMismatch`] if the selector does not
+    /// take exactly one argument.
+    pub fn add_observer(
+        &self,
+        observer: &Object,
+        selector: &Selector,
+        name: Option<&str>,
+        object: Option<&Object>,
+    ) -> Result<ObserverToken> {
+        let arguments = selector.name().matches(':').count();
+        if arguments != 1 {
+            return Err(Error::ArgumentCountMismatch {
+                expected: 1,
+                got: arguments,
+            });
+        }
+        let target = Target::Selector {
+            observer: observer.downgrade(),
+            selector: selector.clone(),
+        };
+        Ok(self.register(name, object, target))
+    }
+
+    /// Registers `callback` to be called for notifications named `name`
+    /// concerning `object`, or of every name or object if `None`.
+    pub fn add_observer_fn(
+        &self,
+        name: Option<&str>,
+        object: Option<&Object>,
+        callback: impl Fn(&Notification) + Send + Sync + 'static,
+    ) -> ObserverToken {
+        self.register(name, object, Target::Closure(Arc::new(callback)))
+    }
+
+    /// Adds an observation, dropping those of deallocated objects.
+    fn register(
+        &self,
+        name: Option<&str>,
+        object: Option<&Object>,
+        target: Target,
+    ) -> ObserverToken {
+        let token =
+            ObserverToken(self.next_token.fetch_add(1, Ordering::Relaxed));
+        let observation = Observation {
+            token,
+            name: name.map(str::to_string),
+            sender: object.map(Object::downgrade),
+            target,
+        };
+        let mut observations = self.observations.write().recover();
+        observations.retain(Observation::is_alive);
+        observations.push(observation);
+        token
+    }
+
+    /// Removes the observation `token` identifies. Removing it again does
+    /// nothing.
+    pub fn remove(&self, token: ObserverToken) {
+        self.observations
+            .write()
+            .recover()
+            .retain(|observation| observation.token != token);
+    }
+
+    /// Removes every observation sending to `observer`.
+    pub fn remove_observer(&self, observer: &Object) {
+        self.observations.write().recover().retain(|observation| {
+            match &observation.target {
+                Target::Selector { observer: weak, .. } => {
+                    weak.upgrade().is_none_or(|weak| weak != *observer)
+                }
+                Target::Closure(_) => true,
+            }
+        });
+    }
+
+    /// Returns the number of observations of live objects and closures.
+    #[must_use]
+    pub fn observer_count(&self) -> usize {
+        self.observations
+            .read()
+            .recover()
+            .iter()
+            .filter(|observation| observation.is_alive())
+            .count()
+    }
+
+    /// Posts a notification named `name` concerning `object`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first observer whose selector could not be
+    /// sent or threw, after which later observers are not notified.
+    pub fn post(
+        &self,
+        name: &str,
+        object: Option<&Object>,
+        user_info: UserInfo,
+    ) -> Result<()> {
+        self.post_notification(&Notification::new(name, object, user_info))
+    }
+
+    /// Delivers `notification` to every observer registered for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first observer whose selector could not be
+    /// sent or threw, after which later observers are not notified.
+    pub fn post_notification(&self, notification: &Notification) -> Result<()> {
+        // Observers are notified without the lock held, so they can
+        // register and post themselves
+        let targets: Vec<Target> = self
+            .observations
+            .read()
+            .recover()
+            .iter()
+            .filter(|observation| observation.matches(notification))
+            .map(|observation| observation.target.clone())
+            .collect();
+        let argument = std::ptr::from_ref(notification) as usize;
+        for target in targets {
+            match target {
+                Target::Selector { observer, selector } => {
+                    // An observer deallocated since the post began is
+                    // skipped
+                    if let Some(observer) = observer.upgrade() {
+                        observer.send_message(
+                            &selector,
+                            &MessageArgs::one(argument),
+                        )?;
+                    }
+                }
+                Target::Closure(callback) => callback(notification),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for NotificationCenter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for NotificationCenter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotificationCenter")
+            .field("observers", &self.observer_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::class::Imp;
+    use crate::runtime::exception::{self, Exception};
+    use crate::runtime::object::ObjectPtr;
+    use crate::runtime::selector::SelectorHandle;
+    use crate::runtime::{Class, Method, RuntimeString, get_global_arena};
+    use std::str::FromStr;
+    use std::sync::Mutex;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Names of the notifications `Recorder` instances received, with the
+    /// key of their user info if any
+    static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    unsafe extern "C-unwind" fn record(
+        _this: ObjectPtr,
+        _cmd: SelectorHandle,
+        args: *const *mut u8,
+        _ret: *mut u8,
+    ) {
+        // SAFETY: the center sends the selector to deliver a notification
+        let notification = unsafe { Notification::from_args(args) };
+        RECORDED.lock().unwrap().push(notification.to_string());
+    }
+
+    unsafe extern "C-unwind" fn refuse(
+        _this: ObjectPtr,
+        _cmd: SelectorHandle,
+        _args: *const *mut u8,
+        _ret: *mut u8,
+    ) {
+        exception::throw(Exception::new("RefusedException", "not now"))
+    }
+
+    fn observer_class(name: &str) -> Class {
+        let class = Class::new_root(name).unwrap();
+        let imps: [(&str, Imp); 2] = [("record:", record), ("refuse:", refuse)];
+        for (selector, imp) in imps {
+            class
+                .add_method(Method {
+                    selector: Selector::from_str(selector).unwrap(),
+                    imp,
+                    types: RuntimeString::new("v@:^", get_global_arena()),
+                })
+                .unwrap();
+        }
+        class
+    }
+
+    #[test]
+    fn test_selector_observers_receive_matching_posts() {
+        let class = observer_class("NotifyRecorder");
+        let center = NotificationCenter::new();
+        let record = Selector::from_str("record:").unwrap();
+        let observer = Object::new(&class).unwrap();
+        let sender = Object::new(&class).unwrap();
+        let other = Object::new(&class).unwrap();
+        center
+            .add_observer(&observer, &record, Some("DidSave"), Some(&sender))
+            .unwrap();
+
+        let user_info = UserInfo::from([("path".to_string(), other.clone())]);
+        center.post("DidSave", Some(&sender), user_info).unwrap();
+        center.post("DidSave", Some(&other), UserInfo::new()).unwrap();
+        center.post("DidSave", None, UserInfo::new()).unwrap();
+        center.post("DidLoad", Some(&sender), UserInfo::new()).unwrap();
+        assert_eq!(
+            *RECORDED.lock().unwrap(),
+            ["DidSave from a NotifyRecorder with path"]
+        );
+
+        // Observations go with the objects they name
+        assert_eq!(center.observer_count(), 1);
+        drop(sender);
+        assert_eq!(center.observer_count(), 0);
+        center.add_observer(&observer, &record, None, None).unwrap();
+        center.remove_observer(&observer);
+        assert_eq!(center.observer_count(), 0);
+
+        let two = Selector::from_str("record:with:").unwrap();
+        assert_eq!(
+            center.add_observer(&observer, &two, None, None),
+            Err(Error::ArgumentCountMismatch {
+                expected: 1,
+                got: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_closure_observers_and_failures() {
+        let class = observer_class("NotifyRefuser");
+        let center = NotificationCenter::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let first = center.add_observer_fn(None, None, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let refuser = Object::new(&class).unwrap();
+        let refuse = Selector::from_str("refuse:").unwrap();
+        center
+            .add_observer(&refuser, &refuse, Some("Refused"), None)
+            .unwrap();
+        let counter = Arc::clone(&calls);
+        center.add_observer_fn(Some("Refused"), None, move |_| {
+            counter.fetch_add(10, Ordering::Relaxed);
+        });
+
+        // A throwing observer stops delivery to those after it
+        assert_eq!(
+            center.post("Refused", None, UserInfo::new()),
+            Err(Error::Exception {
+                name: "RefusedException".to_string(),
+                reason: "not now".to_string(),
+            })
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        center.remove_observer(&refuser);
+        center.post("Refused", None, UserInfo::new()).unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 12);
+
+        center.remove(first);
+        center.remove(first);
+        center.post("Other", None, UserInfo::new()).unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 12);
+    }
+
+    #[test]
+    fn test_posts_from_many_threads() {
+        let center = Arc::new(NotificationCenter::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let inner = Arc::clone(&center);
+        // Observers may post themselves while being notified
+        center.add_observer_fn(Some("Outer"), None, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            inner.post("Inner", None, UserInfo::new()).unwrap();
+        });
+        let counter = Arc::clone(&calls);
+        center.add_observer_fn(Some("Inner"), None, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let center = Arc::clone(&center);
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        center.post("Outer", None, UserInfo::new()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 2 * 4 * 25);
+        assert!(std::ptr::eq(
+            NotificationCenter::default_center(),
+            NotificationCenter::default_center()
+        ));
+    }
+}
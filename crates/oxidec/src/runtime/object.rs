@@ -20,14 +20,62 @@
 //! - Multiple threads can hold references to same object
 //! - retain/release are thread-safe (atomic operations)
 //! - `Object` data access requires external synchronization (Phase 2)
+//!
+//! # Weak References
+//!
+//! [`Object::downgrade`] returns a [`WeakRef`], which refers to an object
+//! without keeping it alive, as delegates and back-pointers need to avoid
+//! retain cycles. The weak references to each object share a slot in a
+//! global side table; when the object's refcount reaches 0 the slot is
+//! nilled before the object is freed, and [`WeakRef::upgrade`] returns
+//! `None` from then on. Objects that were never downgraded skip the side
+//! table entirely.
+//!
+//! # Instance Variables
+//!
+//! Instance variables live in the object itself, after its header, at the
+//! offsets [`Class::ivars`] lays them out at: an object is allocated with
+//! its class's [`Class::instance_size`] bytes of variables, zeroed. A class
+//! cannot declare variables while it or a subclass has live instances,
+//! whose layout would no longer match. [`Object::set_ivar`] and
+//! [`Object::set_object_ivar`] store values and the synthesized accessors of
+//! declared properties use the same storage. Object variables (`@`) hold
+//! strong references, released when the object is deallocated.
+//!
+//! # Deallocation
+//!
+//! When the last reference to an object is released, it is torn down in
+//! this order:
+//!
+//! 1. The callbacks registered with [`Object::on_dealloc`] run, in
+//!    registration order.
+//! 2. The [`Finalizer`] of its class runs, then that of each superclass in
+//!    turn, like `-dealloc` calling `[super dealloc]`. The object and its
+//!    instance variables are still intact.
+//! 3. Its weak references are nilled and the objects its instance
+//!    variables hold are released.
+//! 4. The [`DeallocObserver`], if one is set, is told the class of the
+//!    deallocated object, and its memory is freed.
+//!
+//! Callbacks and finalizers may retain and release the object while they
+//! run, but must not keep a reference to it. An object they resurrect that
+//! way is not freed; its finalizers run again when the last of those
+//! references is released.
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::runtime::Class;
 use crate::runtime::MessageArgs;
 use crate::runtime::Selector;
+use crate::runtime::introspection::instance_variables;
+use crate::runtime::sync::Recover;
+use crate::runtime::Ivar;
+use std::alloc::{self, Layout};
+use std::collections::HashMap;
 use std::fmt;
+use std::mem::ManuallyDrop;
 use std::ptr::NonNull;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
 // Raw pointer to ClassInner (defined in class.rs)
 // We use raw pointer to avoid circular dependency
@@ -57,7 +105,7 @@ type ClassInnerPtr = *const ();
 /// use oxidec::runtime::ObjectPtr;
 ///
 /// // In method implementations, ObjectPtr is used as the self parameter
-/// unsafe extern "C" fn my_method(
+/// unsafe extern "C-unwind" fn my_method(
 ///     _self: ObjectPtr,
 ///     _cmd: oxidec::runtime::Selector,
 ///     _args: *const *mut u8,
@@ -102,16 +150,34 @@ pub(crate) struct RawObject {
     /// Points to `ClassInner` in arena (never deallocated)
     /// Stored as opaque pointer to avoid circular dependency
     class_ptr: ClassInnerPtr,
-    /// Object flags (reserved for future use: tagged pointers, etc.)
-    flags: u32,
+    /// Object flags (`WEAKLY_REFERENCED`, `HAS_IVARS`, `HAS_DEALLOC_HOOKS`,
+    /// `DEALLOCATING`; the rest are reserved for future use: tagged
+    /// pointers, etc.)
+    /// Atomic because weak references may be taken from any thread
+    flags: AtomicU32,
     /// Reference count (starts at 1, deallocated when reaches 0)
     /// Atomic for thread-safe retain/release
     refcount: AtomicU32,
+    /// Size of the payload in bytes: the instance size of the class when
+    /// the object was allocated
+    payload_size: usize,
     /// Payload data (flexible array member pattern)
-    /// For Phase 1, this is empty. In Phase 2+, it will hold instance variables.
+    /// Instance variables, at the offsets their class lays them out at
     payload: [u8; 0],
 }
 
+impl RawObject {
+    /// Returns the layout of an object with `payload_size` bytes of
+    /// instance variables.
+    pub(crate) fn layout(payload_size: usize) -> Result<Layout> {
+        Layout::from_size_align(
+            size_of::<RawObject>() + payload_size,
+            align_of::<RawObject>(),
+        )
+        .map_err(|_| Error::OutOfMemory)
+    }
+}
+
 /// `Object` represents a runtime instance with dynamic dispatch.
 ///
 /// `Object`s are reference-counted and support:
@@ -183,31 +249,39 @@ impl Object {
     ///
     /// # Errors
     ///
-    /// Returns [`Error::OutOfMemory`] if object allocation fails.
+    /// Returns [`Error::OutOfMemory`] if object allocation fails, or
+    /// [`Error::ClassDisposed`] if the class was disposed.
     pub fn new(class: &Class) -> Result<Self> {
         // Get class pointer for isa
         // Store as opaque pointer to avoid circular dependency
         let class_ptr = class.inner.as_ptr() as ClassInnerPtr;
+        let payload_size = class.instance_size();
+
+        // Allocate on heap (not arena) for individual lifecycle, with room
+        // for the instance variables, which start zeroed
+        let layout = RawObject::layout(payload_size)?;
+        // SAFETY: the layout has a nonzero size, that of the header
+        let ptr = unsafe { alloc::alloc_zeroed(layout) }.cast::<RawObject>();
+        let ptr = NonNull::new(ptr).ok_or(Error::OutOfMemory)?;
+        if let Err(err) = class.count_instance() {
+            // SAFETY: ptr was allocated above with this layout
+            unsafe { alloc::dealloc(ptr.as_ptr().cast(), layout) };
+            return Err(err);
+        }
 
         // Create RawObject with initial refcount = 1
-        let raw_obj = RawObject {
-            class_ptr,
-            flags: 0,
-            refcount: AtomicU32::new(1),
-            payload: [],
-        };
-
-        // Allocate on heap (not arena) for individual lifecycle
-        // Box allocation ensures proper alignment and validity
-        let boxed = Box::new(raw_obj);
-
-        // Convert to raw pointer (ownership transferred to Object)
-        let ptr = Box::into_raw(boxed);
+        // SAFETY: ptr is a fresh allocation large and aligned enough
+        unsafe {
+            ptr.as_ptr().write(RawObject {
+                class_ptr,
+                flags: AtomicU32::new(0),
+                refcount: AtomicU32::new(1),
+                payload_size,
+                payload: [],
+            });
+        }
 
-        // SAFETY: ptr is not null (Box::new always succeeds)
-        Ok(Object {
-            ptr: unsafe { NonNull::new_unchecked(ptr) },
-        })
+        Ok(Object { ptr })
     }
 
     /// Increments the reference count (retain).
@@ -219,7 +293,10 @@ impl Object {
     ///
     /// # Panics
     ///
-    /// Panics if refcount overflows (`u32::MAX`).
+    /// Panics if refcount overflows (`u32::MAX`). With the `no-abort`
+    /// feature the count saturates instead, and the object is never
+    /// deallocated. See [`Object::try_retain`] for a variant that returns an
+    /// error instead.
     ///
     /// # Example
     ///
@@ -235,17 +312,42 @@ impl Object {
     /// assert_eq!(obj.refcount(), 2);
     /// ```
     pub fn retain(&self) {
+        if self.try_retain().is_err() {
+            #[cfg(not(feature = "no-abort"))]
+            panic!("Reference count overflow in Object::retain");
+        }
+    }
+
+    /// Increments the reference count, or returns an error if it would
+    /// overflow.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::RefCountOverflow` if the refcount is already
+    /// `u32::MAX`; the refcount is left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Object};
+    ///
+    /// let class = Class::new_root("MyClass").unwrap();
+    /// let obj = Object::new(&class).unwrap();
+    ///
+    /// obj.try_retain().unwrap();
+    /// assert_eq!(obj.refcount(), 2);
+    /// ```
+    pub fn try_retain(&self) -> Result<()> {
         // SAFETY: self.ptr points to valid RawObject
         let obj = unsafe { &*self.ptr.as_ptr() };
 
-        // Atomic increment with AcqRel ordering
-        let old = obj.refcount.fetch_add(1, Ordering::AcqRel);
-
-        // Check for overflow
-        assert!(
-            old != u32::MAX,
-            "Reference count overflow in Object::retain"
-        );
+        // Atomic increment with AcqRel ordering, unless it would overflow
+        obj.refcount
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_add(1)
+            })
+            .map(drop)
+            .map_err(|_| Error::RefCountOverflow)
     }
 
     /// Decrements the reference count (release).
@@ -279,17 +381,459 @@ impl Object {
         // SAFETY: self.ptr points to valid RawObject
         let obj = unsafe { &*self.ptr.as_ptr() };
 
+        // A saturated refcount no longer counts references, so the object
+        // must live forever
+        #[cfg(feature = "no-abort")]
+        if obj.refcount.load(Ordering::Acquire) == u32::MAX {
+            return;
+        }
+
         // Atomic decrement with AcqRel ordering
         let old = obj.refcount.fetch_sub(1, Ordering::AcqRel);
 
-        if old == 1 {
-            // Refcount reached 0, deallocate
-            // SAFETY: ptr was created with Box::into_raw
-            // Reclaim ownership with Box::from_raw and drop
-            unsafe {
-                drop(Box::from_raw(self.ptr.as_ptr()));
+        // A release while the object is being torn down only undoes a
+        // retain its callbacks or finalizers made
+        if old == 1 && obj.flags.load(Ordering::Acquire) & DEALLOCATING == 0 {
+            self.dealloc();
+        }
+    }
+
+    /// Tears down an object whose last reference was just released (see
+    /// the module documentation), freeing it unless a callback or
+    /// finalizer resurrected it.
+    fn dealloc(&self) {
+        // SAFETY: self.ptr points to valid RawObject
+        let obj = unsafe { &*self.ptr.as_ptr() };
+        let addr = self.ptr.as_ptr() as usize;
+        let class = self.class();
+
+        obj.flags.fetch_or(DEALLOCATING, Ordering::AcqRel);
+        // A callback may register more callbacks, which run too
+        while obj.flags.fetch_and(!HAS_DEALLOC_HOOKS, Ordering::AcqRel)
+            & HAS_DEALLOC_HOOKS
+            != 0
+        {
+            let callbacks = dealloc_table().lock().recover().remove(&addr);
+            for callback in callbacks.into_iter().flatten() {
+                callback();
+            }
+        }
+        if FINALIZED_CLASSES.load(Ordering::Acquire) != 0 {
+            let mut current = Some(class.clone());
+            while let Some(finalized) = current {
+                if let Some(finalizer) = finalized.finalizer() {
+                    finalizer(self);
+                }
+                current = finalized.super_class();
             }
         }
+        let resurrected = obj.refcount.load(Ordering::Acquire) != 0;
+        obj.flags.fetch_and(!DEALLOCATING, Ordering::AcqRel);
+        if resurrected {
+            return;
+        }
+
+        // Nil the weak references, then deallocate
+        if obj.flags.load(Ordering::Acquire) & WEAKLY_REFERENCED != 0 {
+            let slot = weak_table().lock().recover().remove(&addr);
+            if let Some(slot) = slot {
+                // Waits for any upgrade in progress, which will have
+                // seen the refcount at 0 and failed
+                *slot.object.lock().recover() = std::ptr::null_mut();
+            }
+        }
+
+        // Release the objects its instance variables hold
+        if obj.flags.load(Ordering::Acquire) & HAS_IVARS != 0 {
+            for ivar in instance_variables(&class) {
+                if let Some(slot) = self.ivar_slot(&ivar)
+                    && holds_object(&ivar)
+                {
+                    // SAFETY: object variables are pointer-sized and aligned
+                    let slot = unsafe { AtomicUsize::from_ptr(slot.cast()) };
+                    let value = slot.swap(0, Ordering::AcqRel);
+                    if value != 0 {
+                        // SAFETY: the variable held a reference to the object
+                        drop(unsafe { Object::owned_raw(value as *mut _) });
+                    }
+                }
+            }
+        }
+
+        let layout = RawObject::layout(obj.payload_size)
+            .expect("the object was allocated with this layout");
+        // SAFETY: ptr was allocated by Object::new with this layout
+        unsafe {
+            alloc::dealloc(self.ptr.as_ptr().cast(), layout);
+        }
+        // SAFETY: class.inner points to valid ClassInner allocated in arena
+        let class_inner = unsafe { &*class.inner.as_ptr() };
+        class_inner.instances.fetch_sub(1, Ordering::AcqRel);
+
+        let observer = *DEALLOC_OBSERVER.read().recover();
+        if let Some(observer) = observer {
+            observer(&class);
+        }
+    }
+
+    /// Registers a callback to run when the object is deallocated.
+    ///
+    /// Callbacks run in registration order once the last reference to the
+    /// object is released, before the finalizers of its class. They must
+    /// not keep a reference to the object.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Object};
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let class = Class::new_root("OnDeallocExample").unwrap();
+    /// let obj = Object::new(&class).unwrap();
+    /// let deallocated = Arc::new(AtomicBool::new(false));
+    ///
+    /// let flag = Arc::clone(&deallocated);
+    /// obj.on_dealloc(move || flag.store(true, Ordering::SeqCst));
+    /// assert!(!deallocated.load(Ordering::SeqCst));
+    ///
+    /// drop(obj);
+    /// assert!(deallocated.load(Ordering::SeqCst));
+    /// ```
+    pub fn on_dealloc(&self, callback: impl FnOnce() + Send + 'static) {
+        // SAFETY: self.ptr points to valid RawObject
+        let obj = unsafe { &*self.ptr.as_ptr() };
+        let addr = self.ptr.as_ptr() as usize;
+        dealloc_table()
+            .lock()
+            .recover()
+            .entry(addr)
+            .or_default()
+            .push(Box::new(callback));
+        obj.flags.fetch_or(HAS_DEALLOC_HOOKS, Ordering::AcqRel);
+    }
+
+    /// Sets the observer told about every deallocated object.
+    ///
+    /// Meant for tests checking that objects are deallocated, and when.
+    /// The last observer set wins.
+    pub fn set_dealloc_observer(observer: DeallocObserver) {
+        *DEALLOC_OBSERVER.write().recover() = Some(observer);
+    }
+
+    /// Clears the deallocation observer.
+    pub fn clear_dealloc_observer() {
+        *DEALLOC_OBSERVER.write().recover() = None;
+    }
+
+    /// Creates a weak reference to this object.
+    ///
+    /// The weak reference does not change the refcount. Once the last
+    /// strong reference is released, [`WeakRef::upgrade`] returns `None`.
+    ///
+    /// # Thread Safety
+    ///
+    /// Multiple threads can downgrade the same object concurrently; all its
+    /// weak references share one side table slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the side table's lock is poisoned, unless the `no-abort`
+    /// feature is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::{Class, Object};
+    ///
+    /// let class = Class::new_root("MyClass").unwrap();
+    /// let obj = Object::new(&class).unwrap();
+    /// let weak = obj.downgrade();
+    ///
+    /// assert_eq!(obj.refcount(), 1);
+    /// assert_eq!(weak.upgrade(), Some(obj.clone()));
+    ///
+    /// drop(obj);
+    /// assert!(weak.upgrade().is_none());
+    /// ```
+    #[must_use]
+    pub fn downgrade(&self) -> WeakRef {
+        // SAFETY: self.ptr points to valid RawObject
+        let obj = unsafe { &*self.ptr.as_ptr() };
+
+        let mut table = weak_table().lock().recover();
+        obj.flags.fetch_or(WEAKLY_REFERENCED, Ordering::Release);
+        let slot = table.entry(self.ptr.as_ptr() as usize).or_insert_with(|| {
+            Arc::new(WeakSlot {
+                object: Mutex::new(self.ptr.as_ptr()),
+            })
+        });
+        WeakRef {
+            slot: Arc::clone(slot),
+        }
+    }
+
+    /// Returns the value of an instance variable, as stored by
+    /// [`Object::set_ivar`].
+    ///
+    /// A variable that has never been stored reads as zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::IvarNotFound)` if neither the object's class nor a
+    /// superclass declares the variable, or `Err(Error::IvarTypeMismatch)`
+    /// if it holds an object, which [`Object::object_ivar`] reads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a lock of the object's class is poisoned, unless the
+    /// `no-abort` feature is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{Class, Ivar, Object, RuntimeString};
+    /// use oxidec::get_global_arena;
+    ///
+    /// let arena = get_global_arena();
+    /// let class = Class::new_root("IvarCounter").unwrap();
+    /// class
+    ///     .add_ivar(Ivar::new(
+    ///         RuntimeString::new("count", arena),
+    ///         RuntimeString::new("q", arena),
+    ///     ))
+    ///     .unwrap();
+    ///
+    /// let counter = Object::new(&class).unwrap();
+    /// assert_eq!(counter.ivar("count").unwrap(), 0);
+    /// counter.set_ivar("count", 3).unwrap();
+    /// assert_eq!(counter.ivar("count").unwrap(), 3);
+    /// ```
+    pub fn ivar(&self, name: &str) -> Result<usize> {
+        let ivar = self.declared_ivar(name, false)?;
+        Ok(self.load_ivar(&ivar))
+    }
+
+    /// Stores the value of an instance variable.
+    ///
+    /// Values are type-erased as `usize`, as message arguments are: floats
+    /// are stored as their bits.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::IvarNotFound)` if neither the object's class nor a
+    /// superclass declares the variable, or `Err(Error::IvarTypeMismatch)`
+    /// if it holds an object, which [`Object::set_object_ivar`] stores.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a lock of the object's class is poisoned, unless the
+    /// `no-abort` feature is enabled.
+    pub fn set_ivar(&self, name: &str, value: usize) -> Result<()> {
+        let ivar = self.declared_ivar(name, false)?;
+        // SAFETY: the variable does not hold an object
+        unsafe { self.store_ivar(&ivar, value) };
+        Ok(())
+    }
+
+    /// Returns the object an object instance variable (`@`) holds, or
+    /// `None` if it is nil.
+    ///
+    /// The returned reference is retained while the variable is read, so it
+    /// stays valid even if another thread stores a new value at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::IvarNotFound)` if neither the object's class nor a
+    /// superclass declares the variable, or `Err(Error::IvarTypeMismatch)`
+    /// if it does not hold an object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a lock of the object's class or the lock guarding object
+    /// variables is poisoned, unless the `no-abort` feature is enabled.
+    pub fn object_ivar(&self, name: &str) -> Result<Option<Object>> {
+        let ivar = self.declared_ivar(name, true)?;
+
+        let lock = self.ivar_lock().lock().recover();
+        let value = self.load_ivar(&ivar);
+        // Retain under the lock: the variable's own reference keeps the
+        // object alive until a store, which needs the lock to replace it
+        let object = NonNull::new(value as *mut RawObject).map(|ptr| {
+            // SAFETY: stored objects are retained by the variable
+            let object = unsafe { Object::borrow_raw(ptr.as_ptr()) };
+            Object::clone(&object)
+        });
+        drop(lock);
+        Ok(object)
+    }
+
+    /// Stores an object in an object instance variable (`@`), retaining it
+    /// and releasing the object the variable held before.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::IvarNotFound)` if neither the object's class nor a
+    /// superclass declares the variable, or `Err(Error::IvarTypeMismatch)`
+    /// if it does not hold an object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a lock of the object's class or the lock guarding object
+    /// variables is poisoned, unless the `no-abort` feature is enabled.
+    pub fn set_object_ivar(
+        &self,
+        name: &str,
+        value: Option<&Object>,
+    ) -> Result<()> {
+        let ivar = self.declared_ivar(name, true)?;
+        let value = value.map_or(0, |object| object.ptr.as_ptr() as usize);
+        // SAFETY: value is nil or a live object
+        unsafe { self.store_ivar(&ivar, value) };
+        Ok(())
+    }
+
+    /// Finds the declaration of the instance variable `name` in the
+    /// object's class or a superclass, checking whether it holds an object.
+    /// Variables laid out past the end of the object, declared while it was
+    /// being allocated, are not found.
+    fn declared_ivar(&self, name: &str, object: bool) -> Result<Ivar> {
+        let ivar = instance_variables(&self.class())
+            .into_iter()
+            .find(|ivar| ivar.name.as_str().is_ok_and(|found| found == name))
+            .filter(|ivar| self.ivar_slot(ivar).is_some())
+            .ok_or_else(|| Error::IvarNotFound {
+                name: name.to_string(),
+            })?;
+        if holds_object(&ivar) != object {
+            return Err(Error::IvarTypeMismatch {
+                name: name.to_string(),
+                types: ivar.types.as_str().unwrap_or_default().to_string(),
+            });
+        }
+        Ok(ivar)
+    }
+
+    /// Returns the raw value of `ivar`, which must be declared by the
+    /// object's class or a superclass. An object variable's value is the
+    /// object's address, not retained.
+    ///
+    /// A 4-byte `i` is sign-extended, and other 4-byte values are
+    /// zero-extended.
+    pub(crate) fn load_ivar(&self, ivar: &Ivar) -> usize {
+        let Some(slot) = self.ivar_slot(ivar) else {
+            return 0;
+        };
+        if ivar.size() == 4 {
+            // SAFETY: the slot is in the payload, aligned to its size
+            let bits = unsafe { AtomicU32::from_ptr(slot.cast()) }
+                .load(Ordering::Acquire);
+            if ivar.types.as_str().is_ok_and(|types| types == "i") {
+                bits.cast_signed() as isize as usize
+            } else {
+                bits as usize
+            }
+        } else {
+            // SAFETY: as above
+            unsafe { AtomicUsize::from_ptr(slot.cast()) }
+                .load(Ordering::Acquire)
+        }
+    }
+
+    /// Stores the raw value of `ivar`, which must be declared by the
+    /// object's class or a superclass. An object variable retains the new
+    /// object and releases the old one.
+    ///
+    /// # Safety
+    ///
+    /// If `ivar` holds an object, `value` must be 0 or the address of a
+    /// live object.
+    pub(crate) unsafe fn store_ivar(&self, ivar: &Ivar, value: usize) {
+        let Some(slot) = self.ivar_slot(ivar) else {
+            return;
+        };
+        if !holds_object(ivar) {
+            if ivar.size() == 4 {
+                // SAFETY: the slot is in the payload, aligned to its size;
+                // the variable keeps the value's low bits
+                unsafe { AtomicU32::from_ptr(slot.cast()) }
+                    .store(value as u32, Ordering::Release);
+            } else {
+                // SAFETY: as above
+                unsafe { AtomicUsize::from_ptr(slot.cast()) }
+                    .store(value, Ordering::Release);
+            }
+            return;
+        }
+
+        // SAFETY: self.ptr points to valid RawObject
+        let obj = unsafe { &*self.ptr.as_ptr() };
+        if value != 0 {
+            // SAFETY: the caller guarantees value is a live object
+            unsafe { Object::borrow_raw(value as *mut RawObject) }.retain();
+        }
+
+        let lock = self.ivar_lock().lock().recover();
+        obj.flags.fetch_or(HAS_IVARS, Ordering::Release);
+        // SAFETY: object variables are pointer-sized and aligned
+        let old = unsafe { AtomicUsize::from_ptr(slot.cast()) }
+            .swap(value, Ordering::AcqRel);
+        drop(lock);
+
+        // Release outside the lock: deallocating the old object may need it
+        if old != 0 {
+            // SAFETY: the variable held a reference to the old object
+            drop(unsafe { Object::owned_raw(old as *mut RawObject) });
+        }
+    }
+
+    /// Returns the address of `ivar` in the object's payload, or `None` if
+    /// it is laid out past the end of the object.
+    fn ivar_slot(&self, ivar: &Ivar) -> Option<*mut u8> {
+        // SAFETY: self.ptr points to valid RawObject
+        let obj = unsafe { &*self.ptr.as_ptr() };
+        if ivar.offset + ivar.size() > obj.payload_size {
+            return None;
+        }
+        // SAFETY: the variable is within the payload allocated after the
+        // header
+        let payload = unsafe { &raw mut (*self.ptr.as_ptr()).payload };
+        Some(unsafe { payload.cast::<u8>().add(ivar.offset) })
+    }
+
+    /// Returns the lock guarding the object's object variables, so that a
+    /// read can retain the object a variable holds before a store releases
+    /// it. Objects share a fixed set of locks, like Objective-C's locks
+    /// for atomic properties.
+    fn ivar_lock(&self) -> &'static Mutex<()> {
+        let addr = self.ptr.as_ptr() as usize;
+        &IVAR_LOCKS[(addr / align_of::<RawObject>()) % IVAR_LOCKS.len()]
+    }
+
+    /// Borrows the object `ptr` points to without retaining it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a live object, which must outlive the borrow.
+    pub(crate) unsafe fn borrow_raw(ptr: *mut RawObject) -> ManuallyDrop<Self> {
+        ManuallyDrop::new(Object {
+            // SAFETY: the caller guarantees ptr is a live object
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        })
+    }
+
+    /// Takes ownership of a reference to the object `ptr` points to, which
+    /// is released when the returned `Object` is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a live object, and the caller must own the
+    /// reference it gives up.
+    pub(crate) unsafe fn owned_raw(ptr: *mut RawObject) -> Self {
+        Object {
+            // SAFETY: the caller guarantees ptr is a live object
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        }
     }
 
     /// Returns the object's class (isa pointer).
@@ -613,6 +1157,180 @@ impl fmt::Debug for Object {
     }
 }
 
+/// Flag set on an object once it has been downgraded, so that only such
+/// objects look up the weak side table when deallocated.
+const WEAKLY_REFERENCED: u32 = 1;
+
+/// The object the weak references to it share, nilled on deallocation.
+struct WeakSlot {
+    /// The object, or null once it is deallocated
+    /// Held while upgrading, so the object cannot be freed mid-upgrade
+    object: Mutex<*mut RawObject>,
+}
+
+// SAFETY: WeakSlot is Send + Sync because:
+// - The pointer is only dereferenced under the mutex
+// - Deallocation nils it under the same mutex before freeing the object
+unsafe impl Send for WeakSlot {}
+unsafe impl Sync for WeakSlot {}
+
+/// Weak side table: object address -> the slot its weak references share
+/// Holds only downgraded objects that are still alive, so a new object at
+/// a freed object's address never inherits its weak references
+static WEAK_TABLE: OnceLock<Mutex<HashMap<usize, Arc<WeakSlot>>>> =
+    OnceLock::new();
+
+fn weak_table() -> &'static Mutex<HashMap<usize, Arc<WeakSlot>>> {
+    WEAK_TABLE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Flag set on an object once one of its object variables is stored, so
+/// that only such objects look for objects to release in their variables
+/// when deallocated.
+const HAS_IVARS: u32 = 2;
+
+/// Flag set on an object once a dealloc callback is registered for it, so
+/// that only such objects look up the callback side table when
+/// deallocated.
+const HAS_DEALLOC_HOOKS: u32 = 4;
+
+/// Flag set on an object while its callbacks and finalizers run.
+const DEALLOCATING: u32 = 8;
+
+/// A callback run when an object is deallocated.
+type DeallocCallback = Box<dyn FnOnce() + Send>;
+
+/// Dealloc callback side table: object address -> its callbacks
+/// Like the weak table, holds only objects that are still alive
+static DEALLOC_TABLE: OnceLock<Mutex<HashMap<usize, Vec<DeallocCallback>>>> =
+    OnceLock::new();
+
+fn dealloc_table() -> &'static Mutex<HashMap<usize, Vec<DeallocCallback>>> {
+    DEALLOC_TABLE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// A class's finalizer, run when one of its instances, or of its
+/// subclasses, is deallocated.
+///
+/// Like `-dealloc`, it cleans up what the object owns outside the runtime.
+/// It runs before its superclass's finalizer, while the object's instance
+/// variables are still set, and must not keep a reference to the object.
+pub type Finalizer = fn(obj: &Object);
+
+/// An observer told the class of every object deallocated, after the
+/// object is freed.
+pub type DeallocObserver = fn(class: &Class);
+
+/// Number of classes with a finalizer, so deallocation only looks for
+/// finalizers once there are any.
+pub(crate) static FINALIZED_CLASSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Deallocation observer (optional).
+static DEALLOC_OBSERVER: RwLock<Option<DeallocObserver>> = RwLock::new(None);
+
+/// Locks guarding object variables, shared by objects by address
+static IVAR_LOCKS: [Mutex<()>; 16] = [const { Mutex::new(()) }; 16];
+
+/// Returns `true` if `ivar` holds an object, which it keeps a reference to.
+fn holds_object(ivar: &Ivar) -> bool {
+    ivar.types.as_str().is_ok_and(|types| types == "@")
+}
+
+/// `WeakRef` is a reference to an [`Object`] that does not keep it alive.
+///
+/// Created by [`Object::downgrade`]. Use it where a strong reference would
+/// form a cycle, such as an object's delegate or parent.
+///
+/// # Thread Safety
+///
+/// `WeakRef`s are `Send + Sync`. An upgrade racing with the release of the
+/// last strong reference either returns the object, which it then keeps
+/// alive, or returns `None`; it never returns a deallocated object.
+///
+/// # Example
+///
+/// ```rust
+/// use oxidec::{Class, Object};
+///
+/// let class = Class::new_root("Delegate").unwrap();
+/// let delegate = Object::new(&class).unwrap();
+/// let weak = delegate.downgrade();
+///
+/// if let Some(delegate) = weak.upgrade() {
+///     assert_eq!(delegate.refcount(), 2);
+/// }
+/// drop(delegate);
+/// assert!(!weak.is_alive());
+/// ```
+#[derive(Clone)]
+pub struct WeakRef {
+    /// Slot shared by every weak reference to the object
+    slot: Arc<WeakSlot>,
+}
+
+impl WeakRef {
+    /// Returns a strong reference to the object, or `None` if it has been
+    /// deallocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object's refcount would overflow, as
+    /// [`Object::retain`] does. With the `no-abort` feature the count stays
+    /// saturated and the object is returned.
+    #[must_use]
+    pub fn upgrade(&self) -> Option<Object> {
+        let object = self.slot.object.lock().recover();
+        let ptr = NonNull::new(*object)?;
+
+        // SAFETY: ptr points to valid RawObject: deallocation nils the slot
+        // under the lock we hold before freeing it
+        let obj = unsafe { &*ptr.as_ptr() };
+
+        // Only revive a reference while another keeps the object alive; at
+        // 0 the object is being deallocated
+        let retained =
+            obj.refcount
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                    match count {
+                        0 | u32::MAX => None,
+                        _ => Some(count + 1),
+                    }
+                });
+        match retained {
+            Ok(_) => {}
+            Err(0) => return None,
+            Err(_) => {
+                #[cfg(not(feature = "no-abort"))]
+                panic!("Reference count overflow in WeakRef::upgrade");
+            }
+        }
+        Some(Object { ptr })
+    }
+
+    /// Returns `true` if the object has not been deallocated.
+    ///
+    /// Another thread may release the object right after this returns, so
+    /// only [`WeakRef::upgrade`] can tell whether it is still usable.
+    #[must_use]
+    pub fn is_alive(&self) -> bool {
+        !self.slot.object.lock().recover().is_null()
+    }
+
+    /// Returns `true` if both weak references refer to the same object.
+    #[must_use]
+    pub fn ptr_eq(&self, other: &WeakRef) -> bool {
+        Arc::ptr_eq(&self.slot, &other.slot)
+    }
+}
+
+impl fmt::Debug for WeakRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakRef")
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -622,7 +1340,7 @@ mod tests {
     use crate::runtime::get_global_arena;
     use crate::runtime::selector::SelectorHandle;
 
-    unsafe extern "C" fn test_impl(
+    unsafe extern "C-unwind" fn test_impl(
         _self: ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
@@ -724,6 +1442,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "no-abort"))]
     #[should_panic(expected = "Reference count overflow")]
     fn test_refcount_overflow() {
         let class = create_test_class("ObjOverflowTest");
@@ -740,6 +1459,36 @@ mod tests {
         obj.retain();
     }
 
+    #[test]
+    fn test_try_retain_overflow() {
+        let class = create_test_class("ObjTryOverflowTest");
+        let obj = Object::new(&class).unwrap();
+        obj.try_retain().unwrap();
+        assert_eq!(obj.refcount(), 2);
+
+        // SAFETY: Direct manipulation for testing
+        unsafe {
+            let raw = &*obj.ptr.as_ptr();
+            raw.refcount.store(u32::MAX, Ordering::Release);
+        }
+        assert_eq!(obj.try_retain(), Err(Error::RefCountOverflow));
+        assert_eq!(obj.refcount(), u32::MAX);
+
+        // With `no-abort` the count saturates and the object is immortal
+        #[cfg(feature = "no-abort")]
+        {
+            obj.retain();
+            obj.release();
+            assert_eq!(obj.refcount(), u32::MAX);
+        }
+
+        // Restore a count that lets the object be freed
+        unsafe {
+            let raw = &*obj.ptr.as_ptr();
+            raw.refcount.store(1, Ordering::Release);
+        }
+    }
+
     #[test]
     fn test_send_message_basic() {
         let class = create_test_class("SendMsgTest");
@@ -853,4 +1602,225 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_weak_ref_upgrade_and_nil() {
+        let class = create_test_class("ObjWeakRefTest");
+        let obj = Object::new(&class).unwrap();
+        let weak = obj.downgrade();
+        let other = obj.downgrade();
+
+        // Weak references do not retain
+        assert_eq!(obj.refcount(), 1);
+        assert!(weak.ptr_eq(&other) && weak.is_alive());
+
+        let strong = weak.upgrade().unwrap();
+        assert_eq!(strong, obj);
+        assert_eq!(obj.refcount(), 2);
+        drop(strong);
+
+        drop(obj);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+        assert!(other.upgrade().is_none());
+
+        // A new object, even at the same address, starts without weak
+        // references
+        let fresh = Object::new(&class).unwrap();
+        assert!(!fresh.downgrade().ptr_eq(&weak));
+    }
+
+    #[test]
+    fn test_weak_ref_upgrade_races_release() {
+        let class = create_test_class("ObjWeakRaceTest");
+        for _ in 0..100 {
+            let obj = Object::new(&class).unwrap();
+            let weak = obj.downgrade();
+            let upgrader = std::thread::spawn(move || {
+                // Either the object, kept alive, or nothing
+                if let Some(obj) = weak.upgrade() {
+                    assert!(obj.refcount() >= 1);
+                    assert_eq!(obj.class().name(), "ObjWeakRaceTest");
+                }
+                weak
+            });
+            drop(obj);
+            let weak = upgrader.join().unwrap();
+            assert!(weak.upgrade().is_none());
+        }
+    }
+
+    fn declare_ivar(class: &Class, name: &str, types: &str) {
+        let arena = get_global_arena();
+        class
+            .add_ivar(Ivar::new(
+                RuntimeString::new(name, arena),
+                RuntimeString::new(types, arena),
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_ivars_store_values() {
+        let base = create_test_class("ObjIvarBase");
+        declare_ivar(&base, "count", "q");
+        declare_ivar(&base, "flag", "i");
+        let class = Class::new("ObjIvarDerived", &base).unwrap();
+        declare_ivar(&class, "ratio", "d");
+
+        let obj = Object::new(&class).unwrap();
+        let other = Object::new(&class).unwrap();
+        assert_eq!(obj.ivar("count"), Ok(0));
+
+        obj.set_ivar("count", 3).unwrap();
+        obj.set_ivar("ratio", 0.5f64.to_bits() as usize).unwrap();
+        assert_eq!(obj.ivar("count"), Ok(3));
+        assert_eq!(obj.ivar("ratio"), Ok(0.5f64.to_bits() as usize));
+        assert_eq!(other.ivar("count"), Ok(0));
+
+        // Values live in the object, at their variables' offsets
+        let ratio = obj.declared_ivar("ratio", false).unwrap();
+        let slot = obj.ivar_slot(&ratio).unwrap();
+        let header = size_of::<RawObject>();
+        assert_eq!(slot as usize - obj.ptr.as_ptr() as usize, header + 16);
+        // SAFETY: the slot holds the variable's 8 bytes
+        assert_eq!(unsafe { slot.cast::<u64>().read() }, 0.5f64.to_bits());
+        obj.set_ivar("flag", -2isize as usize).unwrap();
+        assert_eq!(obj.ivar("flag"), Ok(-2isize as usize));
+        assert_eq!(obj.ivar("count"), Ok(3));
+
+        // The layout is fixed while instances are alive
+        assert!(matches!(
+            base.add_ivar(Ivar::new(ratio.name.clone(), ratio.types.clone())),
+            Err(Error::IvarLayoutInUse { .. })
+        ));
+
+        assert!(matches!(
+            obj.ivar("missing"),
+            Err(Error::IvarNotFound { .. })
+        ));
+        assert!(matches!(
+            obj.object_ivar("count"),
+            Err(Error::IvarTypeMismatch { .. })
+        ));
+
+        // A new object, even at the same address, starts without values
+        drop(obj);
+        let fresh = Object::new(&class).unwrap();
+        assert_eq!(fresh.ivar("count"), Ok(0));
+    }
+
+    #[test]
+    fn test_object_ivars_hold_strong_references() {
+        let class = create_test_class("ObjIvarOwner");
+        declare_ivar(&class, "child", "@");
+        let child_class = create_test_class("ObjIvarChild");
+
+        let owner = Object::new(&class).unwrap();
+        let child = Object::new(&child_class).unwrap();
+        let weak = child.downgrade();
+        assert!(owner.object_ivar("child").unwrap().is_none());
+        assert!(owner.set_ivar("child", 1).is_err());
+
+        owner.set_object_ivar("child", Some(&child)).unwrap();
+        assert_eq!(child.refcount(), 2);
+        assert_eq!(owner.object_ivar("child").unwrap(), Some(child.clone()));
+        drop(child);
+        assert!(weak.is_alive());
+
+        // Replacing the value releases the old object
+        owner.set_object_ivar("child", None).unwrap();
+        assert!(!weak.is_alive());
+
+        // Deallocating the owner releases the objects it holds
+        let child = Object::new(&child_class).unwrap();
+        let weak = child.downgrade();
+        owner.set_object_ivar("child", Some(&child)).unwrap();
+        drop(child);
+        drop(owner);
+        assert!(!weak.is_alive());
+    }
+
+    /// Teardown events of the dealloc tests, in order
+    static TEARDOWN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    fn record(event: &str) {
+        TEARDOWN.lock().unwrap().push(event.to_string());
+    }
+
+    fn teardown(prefix: &str) -> Vec<String> {
+        let events = TEARDOWN.lock().unwrap();
+        events
+            .iter()
+            .filter_map(|event| event.strip_prefix(prefix))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_dealloc_runs_callbacks_then_finalizers() {
+        let base = create_test_class("DeallocOrderBase");
+        let derived = Class::new("DeallocOrderDerived", &base).unwrap();
+        declare_ivar(&derived, "count", "q");
+        base.set_finalizer(|_| record("order:base"));
+        derived.set_finalizer(|obj| {
+            // The object is still intact, and may be retained in passing
+            let count = obj.ivar("count").unwrap();
+            drop(obj.clone());
+            record(&format!("order:derived {count}"));
+        });
+
+        let obj = Object::new(&derived).unwrap();
+        obj.set_ivar("count", 3).unwrap();
+        obj.on_dealloc(|| record("order:first callback"));
+        obj.on_dealloc(|| record("order:second callback"));
+        let weak = obj.downgrade();
+        let copy = obj.clone();
+        drop(obj);
+        assert!(teardown("order:").is_empty());
+
+        drop(copy);
+        assert_eq!(
+            teardown("order:"),
+            [
+                "first callback",
+                "second callback",
+                "derived 3",
+                "base"
+            ]
+        );
+        assert!(!weak.is_alive());
+
+        // Only the instances of the class and its subclasses are finalized
+        base.clear_finalizer();
+        drop(Object::new(&base).unwrap());
+        drop(Object::new(&create_test_class("DeallocOrderOther")).unwrap());
+        assert_eq!(teardown("order:").len(), 4);
+    }
+
+    #[test]
+    fn test_dealloc_resurrection() {
+        static KEPT: Mutex<Option<Object>> = Mutex::new(None);
+        static RESURRECTED: AtomicU32 = AtomicU32::new(0);
+        let class = create_test_class("DeallocResurrected");
+        class.set_finalizer(|obj| {
+            record("resurrect:finalized");
+            if RESURRECTED.fetch_add(1, Ordering::SeqCst) == 0 {
+                *KEPT.lock().unwrap() = Some(obj.clone());
+            }
+        });
+
+        let obj = Object::new(&class).unwrap();
+        let weak = obj.downgrade();
+        drop(obj);
+
+        // Kept alive by the finalizer, and finalized again once released
+        assert!(weak.is_alive());
+        let kept = KEPT.lock().unwrap().clone().unwrap();
+        assert_eq!(kept.refcount(), 2);
+        drop(kept);
+        drop(KEPT.lock().unwrap().take());
+        assert!(!weak.is_alive());
+        assert_eq!(teardown("resurrect:"), ["finalized", "finalized"]);
+    }
 }
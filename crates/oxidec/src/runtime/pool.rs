@@ -125,17 +125,20 @@ impl InvocationPool {
     /// # Returns
     ///
     /// A reusable invocation from the pool, or a new allocation if pool is empty.
-    fn acquire(&mut self, target: &Object, selector: &Selector) -> Invocation {
+    fn acquire(
+        &mut self,
+        target: &Object,
+        selector: &Selector,
+    ) -> Result<Invocation> {
         if let Some(mut invocation) = self.pool.pop() {
             // Pool hit - reset invocation for reuse
             self.hits.fetch_add(1, Ordering::Relaxed);
             invocation.reset(target, selector);
-            invocation
+            Ok(invocation)
         } else {
             // Pool miss - allocate new
             self.misses.fetch_add(1, Ordering::Relaxed);
-            // SAFETY: We know target and selector are valid from the caller
-            Invocation::new(target, selector).unwrap()
+            Invocation::new(target, selector)
         }
     }
 
@@ -220,7 +223,7 @@ impl Default for InvocationPool {
 /// Invocation pool statistics.
 ///
 /// Provides visibility into pool performance and efficiency.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PoolStats {
     /// Current number of invocations in the pool.
     pub pool_size: usize,
@@ -300,7 +303,7 @@ impl PooledInvocation {
     pub fn new(target: &Object, selector: &Selector) -> Result<Self> {
         LOCAL_POOL.try_with(|pool| {
             let mut pool = pool.borrow_mut();
-            let invocation = pool.acquire(target, selector);
+            let invocation = pool.acquire(target, selector)?;
             Ok(Self {
                 invocation: Some(invocation),
             })
@@ -0,0 +1,377 @@
+//! Declared properties for the ``OxideC`` runtime.
+//!
+//! A [`Property`] is a named, typed value of a class's instances, exposed
+//! through accessor methods like Objective-C's `@property`. Declaring one
+//! with [`Class::add_property`] does three things:
+//!
+//! - Declares a backing instance variable with the property's name and type
+//!   encoding, stored by the runtime (see [`Object::set_ivar`])
+//! - Synthesizes a getter named after the property and, unless it is
+//!   `readonly`, a setter named `setName:`. Accessors the class already
+//!   defines are kept, as `@synthesize` keeps hand-written ones.
+//! - Records the property, so it can be listed by introspection
+//!
+//! # Attributes
+//!
+//! - `readonly`: no setter is synthesized
+//! - `copy`: the setter stores a copy of the new object, made by sending it
+//!   `copy`, rather than the object itself. Only object properties (`@`)
+//!   can be copied.
+//! - `atomic`: accessors never observe a half-written value. The runtime's
+//!   instance variable storage is locked for every access, so synthesized
+//!   accessors are atomic either way; the attribute records what the class
+//!   declared, as Objective-C's attribute strings do.
+//!
+//! Object properties are strong: the setter retains the new object and
+//! releases the old one. The getter returns the object without retaining
+//! it.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::property::{Property, PropertyAttributes};
+//! use oxidec::runtime::{Class, MessageArgs, Object, RuntimeString, Selector};
+//! use oxidec::get_global_arena;
+//! use std::str::FromStr;
+//!
+//! let arena = get_global_arena();
+//! let point = Class::new_root("PropertyPoint").unwrap();
+//! point
+//!     .add_property(Property {
+//!         name: RuntimeString::new("x", arena),
+//!         types: RuntimeString::new("q", arena),
+//!         attributes: PropertyAttributes::default(),
+//!     })
+//!     .unwrap();
+//!
+//! let p = Object::new(&point).unwrap();
+//! let set_x = Selector::from_str("setX:").unwrap();
+//! let x = Selector::from_str("x").unwrap();
+//! Object::send_message(&p, &set_x, &MessageArgs::one(7)).unwrap();
+//! let value = Object::send_message(&p, &x, &MessageArgs::None).unwrap();
+//! assert_eq!(value, Some(7));
+//! ```
+//!
+//! [`Class::add_property`]: crate::runtime::Class::add_property
+
+use crate::error::{Error, Result};
+use crate::runtime::encoding::size_of_type;
+use crate::runtime::introspection::instance_variables;
+use crate::runtime::object::ObjectPtr;
+use crate::runtime::selector::SelectorHandle;
+use crate::runtime::{
+    Class, Ivar, MessageArgs, Method, Object, RuntimeString, Selector,
+    get_global_arena,
+};
+use std::str::FromStr;
+
+/// The attributes a property is declared with.
+///
+/// The default is a read-write, atomic property that stores the value
+/// itself, as in Objective-C.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PropertyAttributes {
+    /// Synthesize a getter only
+    pub readonly: bool,
+    /// The setter stores a copy of the new value
+    pub copy: bool,
+    /// Accessors never observe a half-written value
+    pub atomic: bool,
+}
+
+impl Default for PropertyAttributes {
+    fn default() -> Self {
+        Self {
+            readonly: false,
+            copy: false,
+            atomic: true,
+        }
+    }
+}
+
+/// A property declared by a class.
+#[derive(Clone, Debug)]
+pub struct Property {
+    /// Property name, also the name of its getter and backing instance
+    /// variable
+    pub name: RuntimeString,
+    /// Type encoding of the value (e.g., "@" for an object)
+    pub types: RuntimeString,
+    /// Declared attributes
+    pub attributes: PropertyAttributes,
+}
+
+impl Property {
+    /// Returns the name of the property's getter, the property's name.
+    #[must_use]
+    pub fn getter_name(&self) -> &str {
+        self.name.as_str().unwrap_or_default()
+    }
+
+    /// Returns the name of the property's setter, `setName:` for a property
+    /// named `name`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::property::{Property, PropertyAttributes};
+    /// use oxidec::runtime::RuntimeString;
+    /// use oxidec::get_global_arena;
+    ///
+    /// let arena = get_global_arena();
+    /// let title = Property {
+    ///     name: RuntimeString::new("title", arena),
+    ///     types: RuntimeString::new("@", arena),
+    ///     attributes: PropertyAttributes::default(),
+    /// };
+    /// assert_eq!(title.setter_name(), "setTitle:");
+    /// ```
+    #[must_use]
+    pub fn setter_name(&self) -> String {
+        let mut chars = self.getter_name().chars();
+        let first = chars.next().map(|c| c.to_ascii_uppercase());
+        let first = first.map(String::from).unwrap_or_default();
+        format!("set{first}{}:", chars.as_str())
+    }
+
+    /// Returns the property's Objective-C style attribute string, such as
+    /// `T@,R,C,N,Vtitle` for a readonly, copied, nonatomic object property
+    /// named `title`.
+    #[must_use]
+    pub fn attribute_string(&self) -> String {
+        let mut attributes =
+            format!("T{}", self.types.as_str().unwrap_or_default());
+        if self.attributes.readonly {
+            attributes.push_str(",R");
+        }
+        if self.attributes.copy {
+            attributes.push_str(",C");
+        }
+        if !self.attributes.atomic {
+            attributes.push_str(",N");
+        }
+        attributes.push_str(",V");
+        attributes.push_str(self.getter_name());
+        attributes
+    }
+
+    /// Returns the instance variable backing the property, to declare.
+    pub(crate) fn ivar(&self) -> Ivar {
+        Ivar::new(self.name.clone(), self.types.clone())
+    }
+
+    /// Checks that the property's accessors can be synthesized.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::InvalidProperty)` if the name is empty, the type
+    /// encoding is not a single type of at most 8 bytes, or a non-object
+    /// property is `copy`.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let invalid = |reason: &str| Error::InvalidProperty {
+            property: self.getter_name().to_string(),
+            reason: reason.to_string(),
+        };
+        if self.getter_name().is_empty() {
+            return Err(invalid("name is empty"));
+        }
+
+        let types = self.types.as_str().unwrap_or_default();
+        let mut chars = types.chars();
+        let storable = match (chars.next(), chars.next()) {
+            (Some(ty), None) => size_of_type(ty).is_some_and(|size| size > 0),
+            _ => false,
+        };
+        if !storable {
+            return Err(invalid(&format!("type '{types}' cannot be stored")));
+        }
+        if self.attributes.copy && types != "@" {
+            return Err(invalid("only object properties can be copied"));
+        }
+        Ok(())
+    }
+
+    /// Returns the accessor methods to synthesize for the property: the
+    /// getter, then the setter unless the property is readonly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::OutOfMemory)` if the arena cannot hold the
+    /// selectors or type encodings.
+    pub(crate) fn accessors(&self) -> Result<Vec<Method>> {
+        let arena = get_global_arena();
+        let types = self.types.as_str().unwrap_or_default();
+
+        let mut accessors = vec![Method {
+            selector: Selector::from_str(self.getter_name())?,
+            imp: synthesized_getter,
+            types: RuntimeString::try_new(&format!("{types}@:"), arena)?,
+        }];
+        if !self.attributes.readonly {
+            accessors.push(Method {
+                selector: Selector::from_str(&self.setter_name())?,
+                imp: synthesized_setter,
+                types: RuntimeString::try_new(&format!("v@:{types}"), arena)?,
+            });
+        }
+        Ok(accessors)
+    }
+}
+
+/// Finds the property of `class` or a superclass whose accessor `matches`.
+fn find_property(
+    class: &Class,
+    matches: impl Fn(&Property) -> bool,
+) -> Option<Property> {
+    let mut current = Some(class.clone());
+    while let Some(class) = current {
+        if let Some(property) =
+            class.properties().into_iter().find(|property| matches(property))
+        {
+            return Some(property);
+        }
+        current = class.super_class();
+    }
+    None
+}
+
+/// Finds the instance variable backing `property` as the class of `object`
+/// lays it out.
+fn backing_ivar(object: &Object, property: &Property) -> Option<Ivar> {
+    instance_variables(&object.class())
+        .into_iter()
+        .find(|ivar| ivar.name == property.name)
+}
+
+/// The getter synthesized for every property, which finds the property by
+/// the selector it was sent.
+unsafe extern "C-unwind" fn synthesized_getter(
+    this: ObjectPtr,
+    cmd: SelectorHandle,
+    _args: *const *mut u8,
+    ret: *mut u8,
+) {
+    // SAFETY: dispatch passes a live receiver and the selector it sent
+    let object = unsafe { Object::borrow_raw(this.as_raw_ptr()) };
+    let selector = unsafe { Selector::from_handle(cmd) };
+
+    let Some(property) = find_property(&object.class(), |property| {
+        property.getter_name() == selector.name()
+    }) else {
+        return;
+    };
+    let Some(ivar) = backing_ivar(&object, &property) else {
+        return;
+    };
+    let value = object.load_ivar(&ivar);
+
+    // SAFETY: ret points to the 16 bytes dispatch reserves for the result
+    unsafe { ret.cast::<usize>().write_unaligned(value) };
+}
+
+/// The setter synthesized for every read-write property, which finds the
+/// property by the selector it was sent.
+unsafe extern "C-unwind" fn synthesized_setter(
+    this: ObjectPtr,
+    cmd: SelectorHandle,
+    args: *const *mut u8,
+    _ret: *mut u8,
+) {
+    // SAFETY: dispatch passes a live receiver and the selector it sent
+    let object = unsafe { Object::borrow_raw(this.as_raw_ptr()) };
+    let selector = unsafe { Selector::from_handle(cmd) };
+
+    let Some(property) = find_property(&object.class(), |property| {
+        property.setter_name() == selector.name()
+    }) else {
+        return;
+    };
+    let Some(ivar) = backing_ivar(&object, &property) else {
+        return;
+    };
+    // SAFETY: the setter's encoding takes one argument, which dispatch
+    // checked before calling
+    let value = unsafe { args.read() } as usize;
+
+    if property.attributes.copy && value != 0 {
+        // SAFETY: object arguments are live objects
+        let original = unsafe { Object::borrow_raw(value as *mut _) };
+        let copied = Selector::from_str("copy")
+            .ok()
+            .filter(|copy| original.responds_to(copy))
+            .and_then(|copy| {
+                Object::send_message(&original, &copy, &MessageArgs::None).ok()
+            })
+            .flatten()
+            .filter(|&copied| copied != 0);
+        if let Some(copied) = copied {
+            // SAFETY: copy returns a new object its caller owns, as in
+            // Objective-C, which the variable retains before it is released
+            let copied = unsafe { Object::owned_raw(copied as *mut _) };
+            let copied = copied.as_raw().as_raw_ptr() as usize;
+            // SAFETY: the copy is alive until the end of this block
+            unsafe { object.store_ivar(&ivar, copied) };
+            return;
+        }
+    }
+
+    // SAFETY: object arguments are nil or live objects
+    unsafe { object.store_ivar(&ivar, value) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(
+        name: &str,
+        types: &str,
+        attributes: PropertyAttributes,
+    ) -> Property {
+        let arena = get_global_arena();
+        Property {
+            name: RuntimeString::new(name, arena),
+            types: RuntimeString::new(types, arena),
+            attributes,
+        }
+    }
+
+    #[test]
+    fn test_property_names_and_attribute_string() {
+        let title = property(
+            "title",
+            "@",
+            PropertyAttributes {
+                readonly: true,
+                copy: true,
+                atomic: false,
+            },
+        );
+        assert_eq!(title.getter_name(), "title");
+        assert_eq!(title.setter_name(), "setTitle:");
+        assert_eq!(title.attribute_string(), "T@,R,C,N,Vtitle");
+
+        let count = property("count", "q", PropertyAttributes::default());
+        assert_eq!(count.attribute_string(), "Tq,Vcount");
+        assert_eq!(count.accessors().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_property_validation() {
+        let valid = PropertyAttributes::default();
+        assert!(property("x", "d", valid).validate().is_ok());
+        assert!(property("", "d", valid).validate().is_err());
+        assert!(property("x", "v", valid).validate().is_err());
+        assert!(property("x", "qq", valid).validate().is_err());
+
+        let copy = PropertyAttributes {
+            copy: true,
+            ..valid
+        };
+        assert!(property("name", "@", copy).validate().is_ok());
+        assert!(matches!(
+            property("count", "q", copy).validate(),
+            Err(Error::InvalidProperty { .. })
+        ));
+    }
+}
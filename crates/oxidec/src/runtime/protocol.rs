@@ -41,6 +41,7 @@
 //! Uses `RwLock` for method tables and adopted classes tracking.
 
 use crate::error::{Error, Result};
+use crate::runtime::sync::Recover;
 use crate::runtime::{RuntimeString, Selector, get_global_arena};
 use std::collections::HashMap;
 use std::fmt;
@@ -233,7 +234,7 @@ impl Protocol {
         };
 
         // Add to required methods
-        let mut required = inner.required_methods.write().unwrap();
+        let mut required = inner.required_methods.write().recover();
         if required.contains_key(&hash) {
             return Err(Error::ProtocolMethodAlreadyRegistered);
         }
@@ -294,7 +295,7 @@ impl Protocol {
         };
 
         // Add to optional methods
-        let mut optional = inner.optional_methods.write().unwrap();
+        let mut optional = inner.optional_methods.write().recover();
         if optional.contains_key(&hash) {
             return Err(Error::ProtocolMethodAlreadyRegistered);
         }
@@ -322,7 +323,8 @@ impl Protocol {
 
     /// Returns all required methods declared in this protocol.
     ///
-    /// Does not include methods from base protocols.
+    /// Does not include methods from base protocols. The selectors are in
+    /// name order, the same in every run.
     ///
     /// # Example
     ///
@@ -348,13 +350,14 @@ impl Protocol {
     pub fn required(&self) -> Vec<Selector> {
         // SAFETY: self.inner points to valid ProtocolInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let required = inner.required_methods.read().unwrap();
-        required.values().map(|m| m.selector.clone()).collect()
+        let required = inner.required_methods.read().recover();
+        by_name(&required).map(|(_, selector)| selector).collect()
     }
 
     /// Returns all optional methods declared in this protocol.
     ///
-    /// Does not include methods from base protocols.
+    /// Does not include methods from base protocols. The selectors are in
+    /// name order, the same in every run.
     ///
     /// # Example
     ///
@@ -380,8 +383,8 @@ impl Protocol {
     pub fn optional(&self) -> Vec<Selector> {
         // SAFETY: self.inner points to valid ProtocolInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let optional = inner.optional_methods.read().unwrap();
-        optional.values().map(|m| m.selector.clone()).collect()
+        let optional = inner.optional_methods.read().recover();
+        by_name(&optional).map(|(_, selector)| selector).collect()
     }
 
     /// Returns the base protocol if this protocol inherits from another.
@@ -425,7 +428,7 @@ impl Protocol {
     pub fn adopted_protocols(&self) -> Vec<Protocol> {
         // SAFETY: self.inner points to valid ProtocolInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let adopted = inner.adopted_protocols.read().unwrap();
+        let adopted = inner.adopted_protocols.read().recover();
         adopted.iter().map(|&inner| Protocol { inner }).collect()
     }
 
@@ -447,15 +450,26 @@ impl Protocol {
 
         // Add from this protocol (overriding base if needed)
         let inner = unsafe { &*self.inner.as_ptr() };
-        let required = inner.required_methods.read().unwrap();
-        for (hash, method) in required.iter() {
-            methods.push((*hash, method.selector.clone()));
-        }
+        let required = inner.required_methods.read().recover();
+        methods.extend(by_name(&required));
 
         methods
     }
 }
 
+/// The methods of a protocol's table, with their hashes, in selector name
+/// order rather than the table's, which varies from run to run.
+fn by_name(
+    methods: &HashMap<u64, ProtocolMethod>,
+) -> impl Iterator<Item = (u64, Selector)> {
+    let mut methods: Vec<(u64, Selector)> = methods
+        .iter()
+        .map(|(&hash, method)| (hash, method.selector.clone()))
+        .collect();
+    methods.sort_by(|(_, a), (_, b)| a.name().cmp(b.name()));
+    methods.into_iter()
+}
+
 impl fmt::Debug for Protocol {
     /// Formats the `Protocol` for debugging.
     ///
@@ -466,8 +480,8 @@ impl fmt::Debug for Protocol {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // SAFETY: self.inner points to valid ProtocolInner
         let inner = unsafe { &*self.inner.as_ptr() };
-        let required = inner.required_methods.read().unwrap();
-        let optional = inner.optional_methods.read().unwrap();
+        let required = inner.required_methods.read().recover();
+        let optional = inner.optional_methods.read().recover();
 
         f.debug_struct("Protocol")
             .field("name", &inner.name.as_str().unwrap_or("<invalid>"))
@@ -541,6 +555,35 @@ mod tests {
         assert_eq!(optional[0].name(), "optionalMethod");
     }
 
+    #[test]
+    fn test_methods_are_listed_in_name_order() {
+        let base = Protocol::new("OrderedBase", None).unwrap();
+        let derived = Protocol::new("OrderedDerived", Some(&base)).unwrap();
+        for name in ["zeta", "alpha", "mu:", "beta:with:"] {
+            let sel = Selector::from_str(name).unwrap();
+            derived
+                .add_required(sel.clone(), "v@:", get_global_arena())
+                .unwrap();
+            derived.add_optional(sel, "v@:", get_global_arena()).unwrap();
+        }
+        let sel = Selector::from_str("omega").unwrap();
+        base.add_required(sel, "v@:", get_global_arena()).unwrap();
+
+        let names = |selectors: Vec<Selector>| {
+            selectors.iter().map(|s| s.name().to_string()).collect::<Vec<_>>()
+        };
+        let sorted = ["alpha", "beta:with:", "mu:", "zeta"];
+        assert_eq!(names(derived.required()), sorted);
+        assert_eq!(names(derived.optional()), sorted);
+
+        // Base protocol requirements come first, each protocol's sorted
+        let all = derived.all_required().into_iter().map(|(_, sel)| sel);
+        assert_eq!(
+            names(all.collect()),
+            ["omega", "alpha", "beta:with:", "mu:", "zeta"]
+        );
+    }
+
     #[test]
     fn test_protocol_inheritance_methods() {
         let base = Protocol::new("BaseProtocol", None).unwrap();
@@ -839,7 +882,7 @@ mod tests {
     }
 
     // Test method implementation
-    unsafe extern "C" fn test_method_impl(
+    unsafe extern "C-unwind" fn test_method_impl(
         _self: crate::runtime::object::ObjectPtr,
         _cmd: crate::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
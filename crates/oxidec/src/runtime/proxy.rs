@@ -266,8 +266,27 @@ impl RemoteProxy {
     ///
     /// This is a placeholder implementation for the RPC foundation.
     /// In production, this would create a real proxy with serialization hooks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if neither the proxy class nor its fallback can be created,
+    /// e.g. because the host already registered classes with both names. See
+    /// [`RemoteProxy::try_new`] for a variant that returns an error instead.
     #[must_use]
     pub fn new(connection_id: u64, object_id: u64) -> Self {
+        Self::try_new(connection_id, object_id)
+            .expect("Failed to create remote proxy class")
+    }
+
+    /// Creates a new remote proxy, or returns an error if its class cannot
+    /// be created.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ClassAlreadyExists` if classes named like the proxy
+    /// class and its fallback are already registered, or
+    /// `Error::OutOfMemory` if the class cannot be allocated.
+    pub fn try_new(connection_id: u64, object_id: u64) -> Result<Self> {
         // Create a placeholder proxy class
         let id = PROXY_ID.fetch_add(1, Ordering::SeqCst);
         let class_name = format!("RemoteProxy_{}_{}", connection_id, id);
@@ -275,17 +294,18 @@ impl RemoteProxy {
         // Create the proxy object
         let proxy_object = create_proxy_class(&class_name)
             .and_then(|class| Object::new(&class))
-            .ok();
-
-        Self {
-            proxy_object: proxy_object.unwrap_or_else(|| {
+            .or_else(|_| {
                 // Create a minimal fallback for placeholder implementation
-                let fallback_class = Class::new_root(&format!("RemoteFallback_{}", id)).unwrap();
-                Object::new(&fallback_class).unwrap()
-            }),
+                let fallback_class =
+                    Class::new_root(&format!("RemoteFallback_{}", id))?;
+                Object::new(&fallback_class)
+            })?;
+
+        Ok(Self {
+            proxy_object,
             connection_id,
             object_id,
-        }
+        })
     }
 
     /// Returns the connection ID.
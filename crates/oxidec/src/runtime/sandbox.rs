@@ -0,0 +1,186 @@
+//! Sandboxes for untrusted code.
+//!
+//! A [`Sandbox`] denies the code an interpreter or VM runs the
+//! [`Capability`]s that reach outside it, so that an embedder can run code
+//! it does not trust. The interpreter and the VM check a sandbox before
+//! each operation needing a capability; the runtime enforces the parts it
+//! owns itself. While a sandbox is [entered](Sandbox::enter) on a thread:
+//!
+//! - [`Library::open`](crate::runtime::ffi::Library::open) refuses to load
+//!   libraries if the sandbox denies [`Capability::Ffi`].
+//!
+//! Entering a sandbox inside another denies what either denies, so code
+//! cannot loosen the sandbox it runs in.
+//!
+//! # Example
+//!
+//! ```rust
+//! use oxidec::runtime::ffi::Library;
+//! use oxidec::runtime::sandbox::Sandbox;
+//!
+//! let entered = Sandbox::strict().enter();
+//! assert!(Library::open(None).is_err());
+//! drop(entered);
+//! # #[cfg(unix)]
+//! assert!(Library::open(None).is_ok());
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Something outside the interpreter or VM that code can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Reading and writing files
+    FileIo,
+    /// Calling native code
+    Ffi,
+    /// Loading code at run time
+    DynamicLoading,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileIo => write!(f, "file I/O"),
+            Self::Ffi => write!(f, "calling native code"),
+            Self::DynamicLoading => write!(f, "loading code at run time"),
+        }
+    }
+}
+
+/// Capabilities denied to the code an interpreter or VM runs. Nothing is
+/// denied by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Deny reading and writing files
+    pub deny_file_io: bool,
+    /// Deny calling native code
+    pub deny_ffi: bool,
+    /// Deny loading code at run time
+    pub deny_dynamic_loading: bool,
+}
+
+thread_local! {
+    // The sandbox entered on this thread
+    static CURRENT: Cell<Sandbox> = const {
+        Cell::new(Sandbox {
+            deny_file_io: false,
+            deny_ffi: false,
+            deny_dynamic_loading: false,
+        })
+    };
+}
+
+impl Sandbox {
+    /// A sandbox denying every capability, for untrusted code.
+    #[must_use]
+    pub const fn strict() -> Self {
+        Self {
+            deny_file_io: true,
+            deny_ffi: true,
+            deny_dynamic_loading: true,
+        }
+    }
+
+    /// Whether code may use `capability`.
+    #[must_use]
+    pub const fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::FileIo => !self.deny_file_io,
+            Capability::Ffi => !self.deny_ffi,
+            Capability::DynamicLoading => !self.deny_dynamic_loading,
+        }
+    }
+
+    /// Check that code may use `capability`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the capability if the sandbox denies it.
+    pub const fn check(&self, capability: Capability) -> Result<(), Capability> {
+        if self.allows(capability) { Ok(()) } else { Err(capability) }
+    }
+
+    /// Enforce the sandbox in the runtime on this thread until the
+    /// returned guard is dropped.
+    #[must_use = "the sandbox is left when the guard is dropped"]
+    pub fn enter(self) -> Entered {
+        let previous = current();
+        CURRENT.with(|current| {
+            current.set(Self {
+                deny_file_io: previous.deny_file_io || self.deny_file_io,
+                deny_ffi: previous.deny_ffi || self.deny_ffi,
+                deny_dynamic_loading: previous.deny_dynamic_loading
+                    || self.deny_dynamic_loading,
+            });
+        });
+        Entered { previous, _thread: PhantomData }
+    }
+}
+
+/// The sandbox entered on this thread, which denies nothing outside any.
+#[must_use]
+pub fn current() -> Sandbox {
+    CURRENT.with(Cell::get)
+}
+
+/// A sandbox entered on a thread, which is left when this is dropped.
+#[derive(Debug)]
+pub struct Entered {
+    /// The sandbox entered before
+    previous: Sandbox,
+    /// Keeps the guard on the thread that entered the sandbox
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_allows() {
+        let all =
+            [Capability::FileIo, Capability::Ffi, Capability::DynamicLoading];
+        assert!(all.iter().all(|&c| Sandbox::default().allows(c)));
+        assert!(all.iter().all(|&c| !Sandbox::strict().allows(c)));
+
+        let sandbox = Sandbox { deny_ffi: true, ..Sandbox::default() };
+        assert_eq!(sandbox.check(Capability::FileIo), Ok(()));
+        assert_eq!(sandbox.check(Capability::Ffi), Err(Capability::Ffi));
+        assert_eq!(Capability::Ffi.to_string(), "calling native code");
+    }
+
+    #[test]
+    fn test_entered_sandboxes_nest() {
+        let ffi = Sandbox { deny_ffi: true, ..Sandbox::default() };
+        let files = Sandbox { deny_file_io: true, ..Sandbox::default() };
+        assert_eq!(current(), Sandbox::default());
+        {
+            let _outer = ffi.enter();
+            assert_eq!(current(), ffi);
+            {
+                // An inner sandbox adds its denials to the outer one's
+                let _inner = files.enter();
+                let both = Sandbox { deny_file_io: true, ..ffi };
+                assert_eq!(current(), both);
+                let _open = Sandbox::default().enter();
+                assert_eq!(current(), both);
+            }
+            assert_eq!(current(), ffi);
+        }
+        assert_eq!(current(), Sandbox::default());
+
+        // Other threads are not sandboxed
+        let _entered = Sandbox::strict().enter();
+        let other = std::thread::spawn(current).join().unwrap();
+        assert_eq!(other, Sandbox::default());
+    }
+}
@@ -52,6 +52,7 @@
 
 use crate::Error;
 use crate::error::Result;
+use crate::runtime::sync::Recover;
 use crate::runtime::{RuntimeString, get_global_arena};
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -59,6 +60,7 @@ use std::ptr::NonNull;
 use std::str::FromStr;
 use std::sync::OnceLock;
 use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// Number of shards in the selector registry (power of 2 for fast bit masking).
 /// Sharding reduces lock contention by allowing concurrent access to different shards.
@@ -126,8 +128,8 @@ const BUCKET_MASK: usize = BUCKETS_PER_SHARD - 1;
 /// // Convert to handle for FFI
 /// let handle = selector.as_handle();
 ///
-/// // In FFI implementation (extern "C" fn)
-/// unsafe extern "C" fn my_method_impl(
+/// // In FFI implementation (extern "C-unwind" fn)
+/// unsafe extern "C-unwind" fn my_method_impl(
 ///     _self: ObjectPtr,
 ///     _cmd: SelectorHandle,  // Received as FFI handle
 ///     _args: *const *mut u8,
@@ -209,6 +211,14 @@ unsafe impl Sync for SelectorShard {}
 /// Global selector registry instance.
 static REGISTRY: OnceLock<SelectorRegistry> = OnceLock::new();
 
+/// Number of selectors interned in the registry.
+static INTERNED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of selectors interned, for memory reports.
+pub(crate) fn interned_count() -> usize {
+    INTERNED.load(Ordering::Relaxed)
+}
+
 /// `Selector` represents a unique method name in the runtime.
 ///
 /// `Selector`s are **globally interned** - each unique name has exactly one
@@ -306,7 +316,7 @@ impl FromStr for Selector {
 
         // Fast path: Acquire read lock on ONE shard, search buckets
         {
-            let buckets = shard.buckets.read().unwrap();
+            let buckets = shard.buckets.read().recover();
             let mut current = buckets[bucket_idx];
 
             while !current.is_null() {
@@ -325,8 +335,7 @@ impl FromStr for Selector {
                     }
                     // Hash and length match, verify name equality
                     // SAFETY: interned.name is valid `RuntimeString`
-                    // unwrap() is safe because `RuntimeString` in arena is always valid
-                    if interned.name.as_str().unwrap() == name {
+                    if interned.name.as_bytes() == name.as_bytes() {
                         // Found existing selector
                         // SAFETY: current is not null (checked above)
                         return Ok(Selector {
@@ -344,14 +353,14 @@ impl FromStr for Selector {
         } // Release read lock
 
         // Slow path: Acquire write lock on ONE shard, allocate and insert
-        let mut buckets = shard.buckets.write().unwrap();
+        let mut buckets = shard.buckets.write().recover();
 
         // Double-check: Another thread might have inserted while we waited for write lock
         let mut current = buckets[bucket_idx];
         while !current.is_null() {
             let interned = unsafe { &*current };
-            // unwrap() is safe because `RuntimeString` in arena is always valid
-            if interned.hash == hash && interned.name.as_str().unwrap() == name
+            if interned.hash == hash
+                && interned.name.as_bytes() == name.as_bytes()
             {
                 // Another thread inserted it, return existing
                 return Ok(Selector {
@@ -367,7 +376,7 @@ impl FromStr for Selector {
         let arena = get_global_arena();
 
         // Allocate `RuntimeString` for the name
-        let name_str = RuntimeString::new(name, arena);
+        let name_str = RuntimeString::try_new(name, arena)?;
         let name_len = name.len();  // Precompute length for fast comparison
 
         // Create Interned`Selector` struct
@@ -381,10 +390,13 @@ impl FromStr for Selector {
         // Allocate Interned`Selector` struct in arena
         // SAFETY: We're allocating in the global arena, which lives for 'static
         // The struct will never be deallocated
-        let interned_ptr: *mut InternedSelector = arena.alloc(interned);
+        let interned_ptr: *mut InternedSelector = arena
+            .try_alloc(interned)
+            .map_err(|_| Error::OutOfMemory)?;
 
         // Insert at head of bucket
         buckets[bucket_idx] = interned_ptr as *const InternedSelector;
+        INTERNED.fetch_add(1, Ordering::Relaxed);
 
         // SAFETY: interned_ptr is not null and properly aligned
         Ok(Selector {
@@ -432,19 +444,16 @@ impl Selector {
     /// let sel = Selector::from_str("initWithObjects:").unwrap();
     /// assert_eq!(sel.name(), "initWithObjects:");
     /// ```
-    ///
-    /// # Panics
-    ///
-    /// Panics if the selector's name string in the arena is invalid UTF-8
-    /// (which should never happen under normal circumstances).
     #[must_use]
     pub fn name(&self) -> &str {
         // SAFETY: self.ptr points to valid Interned`Selector` in global arena
         // - `Arena` is never deallocated
         // - Pointer is properly aligned
         // - Interned`Selector`.name is valid `RuntimeString`
-        // unwrap() is safe because `RuntimeString` in arena is always valid
-        unsafe { &(*self.ptr.as_ptr()).name }.as_str().unwrap()
+        // Names are created from `&str`, so they are always valid UTF-8
+        unsafe { &(*self.ptr.as_ptr()).name }
+            .as_str()
+            .unwrap_or("<invalid>")
     }
 
     /// Returns the precomputed hash of the selector name.
@@ -36,7 +36,8 @@
 //! assert!(!long.is_inline());
 //! ```
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::runtime::sync::Recover;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -149,10 +150,14 @@ impl Clone for RuntimeString {
                 // Check for overflow
                 // Panic on overflow - this is a programming error
                 // (creating more than 4 billion references to the same string)
-                assert!(
-                    old_count != u32::MAX,
-                    "Reference count overflow in `RuntimeString`::clone"
-                );
+                // With `no-abort` the count saturates instead; it is only
+                // used for copy-on-write, as the arena owns the memory
+                if old_count == u32::MAX {
+                    #[cfg(feature = "no-abort")]
+                    (*heap_ptr).refcount.store(u32::MAX, Ordering::Release);
+                    #[cfg(not(feature = "no-abort"))]
+                    panic!("Reference count overflow in `RuntimeString`::clone");
+                }
             }
 
             // Return copy with same tagged pointer
@@ -279,7 +284,7 @@ impl StringInternCache {
 
         // Fast path: Read lock (non-blocking for multiple readers)
         {
-            let cache = self.cache.read().unwrap();
+            let cache = self.cache.read().recover();
             if let Some(entry) = cache.get(&hash) {
                 // Search bucket for matching string
                 for &ptr in entry {
@@ -297,7 +302,7 @@ impl StringInternCache {
 
         // Only cache heap-allocated strings
         if let Ok(heap_ptr) = rs.heap_ptr() {
-            let mut cache = self.cache.write().unwrap();
+            let mut cache = self.cache.write().recover();
             cache.entry(hash).or_default().push(heap_ptr);
         }
 
@@ -370,7 +375,34 @@ impl RuntimeString {
     /// assert!(rs.is_inline());
     /// assert_eq!(rs.len(), 5);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the string cannot be allocated. See
+    /// [`RuntimeString::try_new`] for a variant that returns an error
+    /// instead.
     pub fn new(s: &str, arena: &crate::runtime::Arena) -> Self {
+        Self::try_new(s, arena).expect("Failed to allocate `RuntimeString`")
+    }
+
+    /// Creates a new `RuntimeString` from a string slice, or returns an
+    /// error if it cannot be allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::OutOfMemory` if the string is longer than a
+    /// `RuntimeString` can hold (4 GiB), or the arena cannot allocate it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use oxidec::runtime::{RuntimeString, get_global_arena};
+    ///
+    /// let arena = get_global_arena();
+    /// let rs = RuntimeString::try_new("a long string on the heap", arena);
+    /// assert_eq!(rs.unwrap().len(), 25);
+    /// ```
+    pub fn try_new(s: &str, arena: &crate::runtime::Arena) -> Result<Self> {
         let bytes = s.as_bytes();
         let len = bytes.len();
 
@@ -380,7 +412,7 @@ impl RuntimeString {
         // Choose SSO or heap based on length
         if len <= SSO_THRESHOLD {
             // Fast path: Inline SSO
-            Self::new_inline(s, is_latin1)
+            Ok(Self::new_inline(s, is_latin1))
         } else {
             // Slow path: Heap allocation
             Self::new_heap(s, is_latin1, arena)
@@ -431,16 +463,20 @@ impl RuntimeString {
         s: &str,
         is_latin1: bool,
         arena: &crate::runtime::Arena,
-    ) -> Self {
+    ) -> Result<Self> {
         let bytes = s.as_bytes();
         let len = bytes.len();
 
+        // Calculate capacity (power of 2, at least len + 1 for NUL terminator),
+        // which must fit the header's `u32`
+        let capacity = (len + 1)
+            .checked_next_power_of_two()
+            .filter(|&capacity| u32::try_from(capacity).is_ok())
+            .ok_or(Error::OutOfMemory)?;
+
         // Compute hash
         let hash = Self::compute_hash(bytes);
 
-        // Calculate capacity (power of 2, at least len + 1 for NUL terminator)
-        let capacity = (len + 1).next_power_of_two();
-
         // Create `HeapString` header
         let heap_str = HeapString {
             length: AtomicU32::new(len as u32),
@@ -456,7 +492,9 @@ impl RuntimeString {
         };
 
         // Allocate in arena
-        let ptr: *mut HeapString = arena.alloc_string(heap_str, capacity);
+        let ptr: *mut HeapString = arena
+            .try_alloc_string(heap_str, capacity)
+            .map_err(|_| Error::OutOfMemory)?;
 
         // Copy string data
         // SAFETY: ptr is valid and points to allocated memory in arena
@@ -495,7 +533,7 @@ impl RuntimeString {
             // We write zeros to bytes 8-15 (the padding beyond the 8-byte pointer)
             // This ensures is_inline() always sees a valid value in byte 15
             data.inline[8..16].fill(0);
-            RuntimeString { data }
+            Ok(RuntimeString { data })
         }
     }
 
@@ -837,6 +875,18 @@ impl Hash for RuntimeString {
     }
 }
 
+/// Returns the number of strings interned, and the arena bytes their
+/// storage takes, headers included, for memory reports.
+pub(crate) fn intern_stats() -> (usize, usize) {
+    let cache = get_intern_cache().cache.read().recover();
+    cache.values().flatten().fold((0, 0), |(count, bytes), &ptr| {
+        // SAFETY: ptr points to a valid `HeapString` in arena; `capacity` is
+        // never written after allocation
+        let capacity = unsafe { (*ptr).capacity } as usize;
+        (count + 1, bytes + std::mem::size_of::<HeapString>() + capacity)
+    })
+}
+
 /// Returns the global string intern cache.
 ///
 /// This function lazily initializes the cache on first call and returns
@@ -0,0 +1,74 @@
+//! Lock acquisition for the runtime's shared state.
+//!
+//! The runtime keeps its registries, method tables and hooks behind
+//! `RwLock`s and `Mutex`es. A lock is poisoned when a thread panics while
+//! holding it, typically inside a user-provided method or hook.
+//!
+//! By default acquiring a poisoned lock panics, as `unwrap` would. With the
+//! `no-abort` feature the guard is recovered instead: every write the
+//! runtime makes under a lock leaves the data consistent, so a panic in
+//! one caller of a host application never takes the runtime down with it.
+
+use std::sync::LockResult;
+
+/// Acquires the guard of a lock, whether or not it is poisoned.
+pub(crate) trait Recover<G> {
+    /// Returns the guard, panicking on a poisoned lock unless the
+    /// `no-abort` feature is enabled.
+    fn recover(self) -> G;
+}
+
+impl<G> Recover<G> for LockResult<G> {
+    #[inline]
+    #[track_caller]
+    fn recover(self) -> G {
+        #[cfg(feature = "no-abort")]
+        {
+            self.unwrap_or_else(std::sync::PoisonError::into_inner)
+        }
+        #[cfg(not(feature = "no-abort"))]
+        {
+            self.expect("runtime lock poisoned")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+
+    /// A lock poisoned by a thread that panicked holding it.
+    fn poisoned() -> Arc<RwLock<u32>> {
+        let lock = Arc::new(RwLock::new(1));
+        let poisoner = Arc::clone(&lock);
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(lock.is_poisoned());
+        lock
+    }
+
+    #[test]
+    fn test_recover_unpoisoned() {
+        let lock = RwLock::new(1);
+        *lock.write().recover() += 1;
+        assert_eq!(*lock.read().recover(), 2);
+    }
+
+    #[cfg(feature = "no-abort")]
+    #[test]
+    fn test_recover_poisoned() {
+        assert_eq!(*poisoned().read().recover(), 1);
+    }
+
+    #[cfg(not(feature = "no-abort"))]
+    #[test]
+    #[should_panic(expected = "runtime lock poisoned")]
+    fn test_poisoned_lock_panics() {
+        let lock = poisoned();
+        let _guard = lock.read().recover();
+    }
+}
@@ -69,7 +69,7 @@ pub fn create_test_method_returning_int(
 ///
 /// This function is a valid method implementation and does not dereference
 /// any raw pointers.
-pub unsafe extern "C" fn void_method_impl(
+pub unsafe extern "C-unwind" fn void_method_impl(
     _self: oxidec::runtime::object::ObjectPtr,
     _cmd: oxidec::runtime::selector::SelectorHandle,
     _args: *const *mut u8,
@@ -84,7 +84,7 @@ pub unsafe extern "C" fn void_method_impl(
 ///
 /// This function is a valid method implementation and does not dereference
 /// any raw pointers.
-pub unsafe extern "C" fn counter_method_impl(
+pub unsafe extern "C-unwind" fn counter_method_impl(
     _self: oxidec::runtime::object::ObjectPtr,
     _cmd: oxidec::runtime::selector::SelectorHandle,
     _args: *const *mut u8,
@@ -99,7 +99,7 @@ pub unsafe extern "C" fn counter_method_impl(
 ///
 /// This function writes to the return pointer, which is guaranteed to be
 /// valid and properly aligned for the return type.
-pub unsafe extern "C" fn return_42_impl(
+pub unsafe extern "C-unwind" fn return_42_impl(
     _self: oxidec::runtime::object::ObjectPtr,
     _cmd: oxidec::runtime::selector::SelectorHandle,
     _args: *const *mut u8,
@@ -119,7 +119,7 @@ pub unsafe extern "C" fn return_42_impl(
 ///
 /// This function writes to the return pointer, which is guaranteed to be
 /// valid and properly aligned for the return type.
-pub unsafe extern "C" fn return_100_impl(
+pub unsafe extern "C-unwind" fn return_100_impl(
     _self: oxidec::runtime::object::ObjectPtr,
     _cmd: oxidec::runtime::selector::SelectorHandle,
     _args: *const *mut u8,
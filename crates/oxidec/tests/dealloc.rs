@@ -0,0 +1,73 @@
+//! Integration tests for deallocation hooks and finalizers.
+//!
+//! The deallocation observer is global, so these tests only look at the
+//! classes they create.
+
+use oxidec::runtime::{Class, Ivar, Object, RuntimeString, get_global_arena};
+use std::sync::Mutex;
+
+/// Names of the classes of deallocated objects, in order
+static DEALLOCATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn observe(class: &Class) {
+    DEALLOCATED.lock().unwrap().push(class.name().to_string());
+}
+
+fn deallocated(name: &str) -> usize {
+    DEALLOCATED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|deallocated| *deallocated == name)
+        .count()
+}
+
+#[test]
+fn test_observer_sees_deallocation() {
+    Object::set_dealloc_observer(observe);
+    let class = Class::new_root("DeallocObserved").unwrap();
+
+    let obj = Object::new(&class).unwrap();
+    let copy = obj.clone();
+    drop(obj);
+    assert_eq!(deallocated("DeallocObserved"), 0);
+    drop(copy);
+    assert_eq!(deallocated("DeallocObserved"), 1);
+
+    let objects: Vec<Object> =
+        (0..10).map(|_| Object::new(&class).unwrap()).collect();
+    drop(objects);
+    assert_eq!(deallocated("DeallocObserved"), 11);
+}
+
+#[test]
+fn test_owned_objects_are_deallocated_with_their_owner() {
+    Object::set_dealloc_observer(observe);
+    let owner_class = Class::new_root("DeallocOwner").unwrap();
+    owner_class.set_finalizer(|obj| {
+        // The owned object is still held while the owner is finalized
+        let child = obj.object_ivar("child").unwrap().unwrap();
+        assert_eq!(child.class().name(), "DeallocOwned");
+        assert_eq!(deallocated("DeallocOwned"), 0);
+    });
+    let arena = get_global_arena();
+    owner_class
+        .add_ivar(Ivar::new(
+            RuntimeString::new("child", arena),
+            RuntimeString::new("@", arena),
+        ))
+        .unwrap();
+    let child_class = Class::new_root("DeallocOwned").unwrap();
+
+    let owner = Object::new(&owner_class).unwrap();
+    owner
+        .set_object_ivar("child", Some(&Object::new(&child_class).unwrap()))
+        .unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    owner.on_dealloc(move || tx.send("owner").unwrap());
+
+    drop(owner);
+    assert_eq!(rx.try_recv(), Ok("owner"));
+    assert_eq!(deallocated("DeallocOwner"), 1);
+    assert_eq!(deallocated("DeallocOwned"), 1);
+}
@@ -169,7 +169,7 @@ fn test_method_provider() {
     use oxidec::runtime::selector::SelectorHandle;
 
     // Dummy function pointer for testing (never called)
-    unsafe extern "C" fn dummy_method(
+    unsafe extern "C-unwind" fn dummy_method(
         _self: ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
@@ -293,7 +293,7 @@ fn test_object_responds_to() {
     use oxidec::runtime::selector::SelectorHandle;
 
     // Dummy function pointer for testing (never called)
-    unsafe extern "C" fn dummy_method(
+    unsafe extern "C-unwind" fn dummy_method(
         _self: ObjectPtr,
         _cmd: SelectorHandle,
         _args: *const *mut u8,
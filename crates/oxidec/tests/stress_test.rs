@@ -5,16 +5,21 @@
 //! - Heavy proxy usage
 //! - Pool exhaustion
 //! - Concurrent access patterns
+//! - Dispatch during class mutation
 //!
 //! Run with: `cargo test --test stress_test -- --test-threads=1 --nocapture`
 
+use oxidec::runtime::class::Imp;
+use oxidec::runtime::object::ObjectPtr;
+use oxidec::runtime::selector::SelectorHandle;
 use oxidec::runtime::{
-    Class, LoggingProxy, MessageArgs, Object, PooledInvocation, Selector,
-    TransparentProxy, compose_proxies,
+    Category, Class, LoggingProxy, MessageArgs, Method, Object,
+    PooledInvocation, RuntimeString, Selector, TransparentProxy,
+    compose_proxies, get_global_arena,
 };
 use std::str::FromStr;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread;
 use std::time::Instant;
 
@@ -348,6 +353,192 @@ fn test_concurrent_pool_exhaustion() {
     );
 }
 
+// ============================================================================
+// Concurrent Dispatch Tests
+// ============================================================================
+
+unsafe extern "C-unwind" fn return_old_impl(
+    _self: ObjectPtr,
+    _cmd: SelectorHandle,
+    _args: *const *mut u8,
+    ret: *mut u8,
+) {
+    // SAFETY: ret points to a word-sized return slot
+    unsafe { ret.cast::<i64>().write_unaligned(1) };
+}
+
+unsafe extern "C-unwind" fn return_new_impl(
+    _self: ObjectPtr,
+    _cmd: SelectorHandle,
+    _args: *const *mut u8,
+    ret: *mut u8,
+) {
+    // SAFETY: ret points to a word-sized return slot
+    unsafe { ret.cast::<i64>().write_unaligned(2) };
+}
+
+fn word_method(selector: &Selector, imp: Imp) -> Method {
+    Method {
+        selector: selector.clone(),
+        imp,
+        types: RuntimeString::new("q@:", get_global_arena()),
+    }
+}
+
+/// Sends `selector` to `object` from `threads` threads until `mutate`
+/// returns, asserting every send answers with the old or the new value,
+/// then asserts that sends from fresh threads all see the new one.
+fn dispatch_during(
+    object: Object,
+    selector: &Selector,
+    threads: usize,
+    mutate: impl FnOnce(),
+) {
+    let object = Arc::new(object);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..threads)
+        .map(|_| {
+            let object = Arc::clone(&object);
+            let selector = selector.clone();
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut sends = 0;
+                while !done.load(Ordering::Acquire) || sends == 0 {
+                    let result =
+                        object.send_message(&selector, &MessageArgs::None);
+                    let value = result.unwrap().unwrap();
+                    assert!(value == 1 || value == 2, "torn send: {value}");
+                    sends += 1;
+                }
+                sends
+            })
+        })
+        .collect();
+
+    mutate();
+    done.store(true, Ordering::Release);
+    for reader in readers {
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    // Joining the readers orders the mutation before every send below
+    let after: Vec<_> = (0..threads)
+        .map(|_| {
+            let object = Arc::clone(&object);
+            let selector = selector.clone();
+            thread::spawn(move || {
+                object.send_message(&selector, &MessageArgs::None).unwrap()
+            })
+        })
+        .collect();
+    for reader in after {
+        assert_eq!(reader.join().unwrap(), Some(2));
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_dispatch_during_swizzle() {
+    let (class, object) = setup_stress_class();
+    let selector = Selector::from_str("swizzledValue").unwrap();
+    class
+        .add_method(word_method(&selector, return_old_impl))
+        .unwrap();
+
+    dispatch_during(object, &selector, 8, || {
+        for i in 0..1000 {
+            let imp: Imp = if i % 2 == 0 {
+                return_new_impl
+            } else {
+                return_old_impl
+            };
+            class.swizzle_method(&selector, imp).unwrap();
+        }
+        class.swizzle_method(&selector, return_new_impl).unwrap();
+    });
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_dispatch_during_method_addition() {
+    let (parent, _) = setup_stress_class();
+    let selector = Selector::from_str("inheritedValue").unwrap();
+    parent
+        .add_method(word_method(&selector, return_old_impl))
+        .unwrap();
+    let child_name = format!("{}Child", parent.name());
+    let child = Class::new(&child_name, &parent).unwrap();
+    let object = Object::new(&child).unwrap();
+
+    // Methods found before the child's table grows must stay valid
+    let inherited = child.lookup_method(&selector).unwrap();
+
+    dispatch_during(object, &selector, 8, || {
+        // Enough methods to make the child's table reallocate
+        for i in 0..500 {
+            let filler = Selector::from_str(&format!("filler{i}")).unwrap();
+            child
+                .add_method(word_method(&filler, return_old_impl))
+                .unwrap();
+        }
+        child
+            .add_method(word_method(&selector, return_new_impl))
+            .unwrap();
+    });
+
+    assert_eq!(inherited.selector.name(), "inheritedValue");
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_dispatch_during_category_load() {
+    let (parent, _) = setup_stress_class();
+    let selector = Selector::from_str("categoryValue").unwrap();
+    parent
+        .add_method(word_method(&selector, return_old_impl))
+        .unwrap();
+    // A class's own methods win over its categories, so the category
+    // overrides an inherited method
+    let class_name = format!("{}Extended", parent.name());
+    let class = Class::new(&class_name, &parent).unwrap();
+    let object = Object::new(&class).unwrap();
+
+    dispatch_during(object, &selector, 8, || {
+        for i in 0..100 {
+            let category =
+                Category::new(&format!("Filler{i}"), &class).unwrap();
+            let filler = Selector::from_str(&format!("loaded{i}")).unwrap();
+            category
+                .add_method(word_method(&filler, return_old_impl))
+                .unwrap();
+        }
+        let category = Category::new("Override", &class).unwrap();
+        category
+            .add_method(word_method(&selector, return_new_impl))
+            .unwrap();
+    });
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_concurrent_category_names() {
+    let (class, _object) = setup_stress_class();
+
+    let attached: usize = (0..8)
+        .map(|_| {
+            let class = class.clone();
+            thread::spawn(move || {
+                usize::from(Category::new("Contended", &class).is_ok())
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .sum();
+    assert_eq!(attached, 1);
+}
+
 // ============================================================================
 // Performance Stress Tests
 // ============================================================================
@@ -6,7 +6,7 @@
 mod common;
 
 use oxidec::runtime::MessageArgs;
-use oxidec::runtime::{Class, Object, Selector};
+use oxidec::runtime::{Class, Method, Object, Selector};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicI32, Ordering};
 use std::thread;
@@ -199,7 +199,7 @@ fn test_swizzle_thread_safety() {
 #[allow(clippy::cast_possible_wrap)]
 fn test_swizzle_runtime_patching() {
     // Buggy implementation returns -1 (error)
-    unsafe extern "C" fn buggy_impl(
+    unsafe extern "C-unwind" fn buggy_impl(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -211,7 +211,7 @@ fn test_swizzle_runtime_patching() {
     }
 
     // Fixed implementation returns 0 (success)
-    unsafe extern "C" fn fixed_impl(
+    unsafe extern "C-unwind" fn fixed_impl(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -252,7 +252,7 @@ fn test_swizzle_runtime_patching() {
 #[allow(clippy::cast_possible_wrap)]
 fn test_swizzle_debugging_injection() {
     // Original implementation
-    unsafe extern "C" fn original_impl(
+    unsafe extern "C-unwind" fn original_impl(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -266,7 +266,7 @@ fn test_swizzle_debugging_injection() {
     static DEBUG_CALL_COUNT: AtomicI32 = AtomicI32::new(0);
 
     // Debug wrapper that logs calls
-    unsafe extern "C" fn debug_wrapper(
+    unsafe extern "C-unwind" fn debug_wrapper(
         _self: oxidec::runtime::object::ObjectPtr,
         _cmd: oxidec::runtime::selector::SelectorHandle,
         _args: *const *mut u8,
@@ -310,3 +310,157 @@ fn test_swizzle_debugging_injection() {
 
     DEBUG_CALL_COUNT.store(0, Ordering::SeqCst);
 }
+
+/// Test replacing an inherited method overrides it in the subclass only
+#[test]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_possible_wrap)]
+fn test_replace_method_overrides_inherited() {
+    let parent = Class::new_root("ReplaceInheritedParent").unwrap();
+    let child = Class::new("ReplaceInheritedChild", &parent).unwrap();
+    let sel = Selector::from_str("replacedMethod").unwrap();
+    let method = common::create_test_method_returning_int(
+        sel.clone(),
+        common::return_42_impl,
+    );
+    parent.add_method(method).unwrap();
+
+    let parent_obj = Object::new(&parent).unwrap();
+    let child_obj = Object::new(&child).unwrap();
+
+    // Populate the child's cache with the inherited method
+    let result =
+        Object::send_message(&child_obj, &sel, &MessageArgs::None).unwrap();
+    assert_eq!(result.unwrap() as i32, 42);
+
+    // swizzle_method only looks at the class's own table
+    assert!(child.swizzle_method(&sel, common::return_100_impl).is_err());
+
+    let previous = child.replace_method(&sel, common::return_100_impl).unwrap();
+    assert_eq!(previous as *const (), common::return_42_impl as *const ());
+
+    let result =
+        Object::send_message(&child_obj, &sel, &MessageArgs::None).unwrap();
+    assert_eq!(result.unwrap() as i32, 100, "Child should use replacement");
+    let result =
+        Object::send_message(&parent_obj, &sel, &MessageArgs::None).unwrap();
+    assert_eq!(result.unwrap() as i32, 42, "Parent should be unaffected");
+
+    // Replacing again swaps the child's own method and keeps its encoding
+    let previous = child.replace_method(&sel, common::return_42_impl).unwrap();
+    assert_eq!(previous as *const (), common::return_100_impl as *const ());
+    let own = child.lookup_method(&sel).unwrap();
+    assert_eq!(own.types.as_str().unwrap(), "i@:");
+
+    let unknown = Selector::from_str("neverDefined").unwrap();
+    assert!(child.replace_method(&unknown, common::return_42_impl).is_err());
+}
+
+/// Test exchanging the implementations of two methods
+#[test]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_possible_wrap)]
+fn test_exchange_implementations() {
+    let parent = Class::new_root("ExchangeParent").unwrap();
+    let child = Class::new("ExchangeChild", &parent).unwrap();
+    let original = Selector::from_str("exchangeOriginal").unwrap();
+    let replacement = Selector::from_str("exchangeReplacement").unwrap();
+    parent
+        .add_method(common::create_test_method_returning_int(
+            original.clone(),
+            common::return_42_impl,
+        ))
+        .unwrap();
+    child
+        .add_method(common::create_test_method_returning_int(
+            replacement.clone(),
+            common::return_100_impl,
+        ))
+        .unwrap();
+
+    let obj = Object::new(&child).unwrap();
+    let send = |sel: &Selector| {
+        Object::send_message(&obj, sel, &MessageArgs::None)
+            .unwrap()
+            .unwrap() as i32
+    };
+
+    // Populate the cache before exchanging
+    assert_eq!(send(&original), 42);
+    assert_eq!(send(&replacement), 100);
+
+    let a = child.lookup_method(&original).unwrap();
+    let b = child.lookup_method(&replacement).unwrap();
+    Method::exchange_implementations(a, b).unwrap();
+
+    assert_eq!(send(&original), 100, "Cache should be invalidated");
+    assert_eq!(send(&replacement), 42, "Cache should be invalidated");
+
+    // The exchanged methods are new, so the old ones are stale
+    assert!(Method::exchange_implementations(a, b).is_err());
+
+    // Exchanging the fresh methods restores the originals
+    Method::exchange_implementations(
+        child.lookup_method(&original).unwrap(),
+        child.lookup_method(&replacement).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(send(&original), 42);
+    assert_eq!(send(&replacement), 100);
+}
+
+/// Test concurrent exchanges in opposite orders neither deadlock nor lose
+/// an implementation
+#[test]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_possible_wrap)]
+fn test_exchange_implementations_thread_safety() {
+    let first = Class::new_root("ExchangeThreadsFirst").unwrap();
+    let second = Class::new_root("ExchangeThreadsSecond").unwrap();
+    let sel = Selector::from_str("exchangedConcurrently").unwrap();
+    first
+        .add_method(common::create_test_method_returning_int(
+            sel.clone(),
+            common::return_42_impl,
+        ))
+        .unwrap();
+    second
+        .add_method(common::create_test_method_returning_int(
+            sel.clone(),
+            common::return_100_impl,
+        ))
+        .unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let (first, second, sel) =
+                (first.clone(), second.clone(), sel.clone());
+            thread::spawn(move || {
+                let mut exchanged = 0;
+                while exchanged < 50 {
+                    let a = first.lookup_method(&sel).unwrap();
+                    let b = second.lookup_method(&sel).unwrap();
+                    let (a, b) = if i % 2 == 0 { (a, b) } else { (b, a) };
+                    // Another thread may have exchanged them since the lookup
+                    if Method::exchange_implementations(a, b).is_ok() {
+                        exchanged += 1;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // 200 exchanges in total, so both implementations are back in place
+    let send = |class: &Class| {
+        let obj = Object::new(class).unwrap();
+        Object::send_message(&obj, &sel, &MessageArgs::None)
+            .unwrap()
+            .unwrap() as i32
+    };
+    assert_eq!(send(&first), 42);
+    assert_eq!(send(&second), 100);
+}
@@ -0,0 +1,363 @@
+//! Whole-program analysis.
+//!
+//! An AOT build sees every module of the program at once, so it knows
+//! every class and every function that can run. Analysis builds:
+//!
+//! - a [`ClassHierarchy`] of all modules' classes, answering which methods
+//!   a message to an instance of a class can reach, and which classes and
+//!   methods no subclass anywhere overrides;
+//! - a [`CallGraph`] of all modules' functions, where a direct call is an
+//!   edge to its callee, a send is an edge to every method the hierarchy
+//!   says it can reach, and loading a function as a value is an edge to it,
+//!   since the value may be called;
+//! - the functions reachable from the program's roots, which are the only
+//!   ones the program needs. Functions a module exports to C are roots as
+//!   well, since the host may call them.
+//!
+//! A send to a receiver whose class is not known statically can reach the
+//! instance method of that selector in any class. The facts the hierarchy
+//! establishes hold for the whole program, which a single module cannot
+//! know, and feed [devirtualization](oxidex_codegen::optimize::devirt)
+//! through [`ClassHierarchy::sealing`].
+
+use oxidex_codegen::ir::{Inst, Module, method_symbol};
+use oxidex_codegen::lowering::{LoweredModule, TypeKind};
+use oxidex_codegen::optimize::DevirtConfig;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// A class as the hierarchy knows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClassInfo {
+    /// Kind of type it was declared as
+    kind: TypeKind,
+    /// Name of its superclass
+    superclass: Option<String>,
+    /// Selectors of the instance methods it defines
+    methods: BTreeSet<String>,
+}
+
+/// The classes of a whole program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassHierarchy {
+    /// Classes by name
+    classes: BTreeMap<String, ClassInfo>,
+}
+
+impl ClassHierarchy {
+    /// Create an empty hierarchy.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the hierarchy of the classes of every module.
+    #[must_use]
+    pub fn from_modules(modules: &[&LoweredModule<'_>]) -> Self {
+        let mut hierarchy = Self::new();
+        for class in modules.iter().flat_map(|module| &module.classes) {
+            let selectors = class.methods.iter().filter(|method| !method.is_static()).map(|method| &method.selector);
+            hierarchy.add_class(&class.name, class.kind, class.superclass.as_deref(), selectors);
+        }
+        hierarchy
+    }
+
+    /// Add a class with the selectors of its instance methods, replacing a
+    /// class of the same name.
+    pub fn add_class<S: AsRef<str>>(
+        &mut self,
+        name: &str,
+        kind: TypeKind,
+        superclass: Option<&str>,
+        methods: impl IntoIterator<Item = S>,
+    ) {
+        let methods = methods.into_iter().map(|selector| selector.as_ref().to_string()).collect();
+        self.classes.insert(name.to_string(), ClassInfo { kind, superclass: superclass.map(str::to_string), methods });
+    }
+
+    /// Whether a class is known.
+    #[must_use]
+    pub fn contains(&self, class: &str) -> bool {
+        self.classes.contains_key(class)
+    }
+
+    /// Names of the classes inheriting from a class, directly or not.
+    #[must_use]
+    pub fn subclasses(&self, class: &str) -> BTreeSet<&str> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![class];
+        while let Some(parent) = pending.pop() {
+            for (name, info) in &self.classes {
+                if info.superclass.as_deref() == Some(parent) && found.insert(name.as_str()) {
+                    pending.push(name);
+                }
+            }
+        }
+        found.remove(class);
+        found
+    }
+
+    /// Name of the class whose instance method a class inherits for a
+    /// selector: the class itself or its nearest superclass defining it.
+    #[must_use]
+    pub fn defining_class(&self, class: &str, selector: &str) -> Option<&str> {
+        let mut visited = HashSet::new();
+        let mut current = self.classes.get_key_value(class);
+        while let Some((name, info)) = current {
+            if !visited.insert(name.as_str()) {
+                break;
+            }
+            if info.methods.contains(selector) {
+                return Some(name);
+            }
+            current = info.superclass.as_deref().and_then(|name| self.classes.get_key_value(name));
+        }
+        None
+    }
+
+    /// Functions a message to an instance of `class`, or of any of its
+    /// subclasses, can run.
+    #[must_use]
+    pub fn implementations(&self, class: &str, selector: &str) -> BTreeSet<String> {
+        let mut found: BTreeSet<String> = self
+            .defining_class(class, selector)
+            .map(|owner| method_symbol(owner, selector))
+            .into_iter()
+            .collect();
+        for subclass in self.subclasses(class) {
+            if self.classes[subclass].methods.contains(selector) {
+                found.insert(method_symbol(subclass, selector));
+            }
+        }
+        found
+    }
+
+    /// Functions a message to a receiver of any class can run.
+    #[must_use]
+    pub fn all_implementations(&self, selector: &str) -> BTreeSet<String> {
+        self.classes
+            .iter()
+            .filter(|(_, info)| info.methods.contains(selector))
+            .map(|(name, _)| method_symbol(name, selector))
+            .collect()
+    }
+
+    /// Whether no class anywhere inherits from a class.
+    #[must_use]
+    pub fn is_leaf(&self, class: &str) -> bool {
+        self.contains(class) && self.subclasses(class).is_empty()
+    }
+
+    /// The facts devirtualization may rely on in this program: every leaf
+    /// class is sealed, and every method no subclass overrides is final.
+    #[must_use]
+    pub fn sealing(&self) -> DevirtConfig {
+        let mut config = DevirtConfig::new();
+        for (name, info) in &self.classes {
+            if info.kind == TypeKind::Class && self.is_leaf(name) {
+                config = config.seal(name.as_str());
+            }
+            let subclasses = self.subclasses(name);
+            for selector in &info.methods {
+                if !subclasses.iter().any(|subclass| self.classes[*subclass].methods.contains(selector)) {
+                    config = config.finalize(name.as_str(), selector.as_str());
+                }
+            }
+        }
+        config
+    }
+}
+
+/// Which functions each function of a program may run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    /// Functions each function may run, by name
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    /// Build the call graph of every module's functions, resolving sends
+    /// through `hierarchy`.
+    #[must_use]
+    pub fn build(modules: &[&Module], hierarchy: &ClassHierarchy) -> Self {
+        let defined: HashSet<&str> =
+            modules.iter().flat_map(|module| &module.functions).map(|function| function.name.as_str()).collect();
+        let mut edges = BTreeMap::new();
+        for function in modules.iter().flat_map(|module| &module.functions) {
+            let mut callees = BTreeSet::new();
+            for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+                match &inst.inst {
+                    Inst::Call { callee, .. } | Inst::Global(callee) => {
+                        callees.insert(callee.clone());
+                    }
+                    Inst::Send { receiver, selector, .. } => {
+                        callees.extend(match function.value_type(*receiver).class_name() {
+                            Some(class) if hierarchy.contains(class) => hierarchy.implementations(class, selector),
+                            _ => hierarchy.all_implementations(selector),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            // Globals that are not functions, such as constants, are not
+            // part of the graph
+            callees.retain(|callee| defined.contains(callee.as_str()));
+            edges.insert(function.name.clone(), callees);
+        }
+        Self { edges }
+    }
+
+    /// Functions a function may run directly.
+    pub fn callees(&self, function: &str) -> impl Iterator<Item = &str> {
+        self.edges.get(function).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Names of every function in the graph.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.edges.keys().map(String::as_str)
+    }
+
+    /// Functions that can run once any of `roots` runs, roots included.
+    #[must_use]
+    pub fn reachable<'g>(&'g self, roots: &[&str]) -> BTreeSet<&'g str> {
+        let mut reached = BTreeSet::new();
+        let mut pending: Vec<&str> =
+            roots.iter().filter_map(|root| self.edges.get_key_value(*root)).map(|(name, _)| name.as_str()).collect();
+        while let Some(function) = pending.pop() {
+            if reached.insert(function) {
+                pending.extend(self.callees(function));
+            }
+        }
+        reached
+    }
+}
+
+/// The result of analysing a whole program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// The program's classes
+    pub hierarchy: ClassHierarchy,
+    /// The program's functions and what they may run
+    pub calls: CallGraph,
+    /// Functions reachable from the roots
+    pub reachable: BTreeSet<String>,
+}
+
+impl Analysis {
+    /// Functions no root can reach, which the program does not need.
+    #[must_use]
+    pub fn dead(&self) -> Vec<&str> {
+        self.calls.functions().filter(|function| !self.reachable.contains(*function)).collect()
+    }
+}
+
+/// Analyse a program of several modules, each lowered and built to IR,
+/// whose execution starts at `roots`.
+#[must_use]
+pub fn analyze(lowered: &[&LoweredModule<'_>], modules: &[&Module], roots: &[&str]) -> Analysis {
+    let hierarchy = ClassHierarchy::from_modules(lowered);
+    analyze_with(hierarchy, modules, roots)
+}
+
+/// Analyse a program's functions against an already built hierarchy.
+#[must_use]
+pub fn analyze_with(hierarchy: ClassHierarchy, modules: &[&Module], roots: &[&str]) -> Analysis {
+    let calls = CallGraph::build(modules, &hierarchy);
+    let exports = modules.iter().flat_map(|module| &module.exports).map(|export| export.function.as_str());
+    let roots: Vec<&str> = roots.iter().copied().chain(exports).collect();
+    let reachable = calls.reachable(&roots).into_iter().map(str::to_string).collect();
+    Analysis { hierarchy, calls, reachable }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_codegen::ir::{Export, parse_module};
+
+    fn hierarchy() -> ClassHierarchy {
+        let mut hierarchy = ClassHierarchy::new();
+        hierarchy.add_class("Shape", TypeKind::Class, None, ["area", "name"]);
+        hierarchy.add_class("Square", TypeKind::Class, Some("Shape"), ["area"]);
+        hierarchy.add_class("Circle", TypeKind::Class, Some("Shape"), ["area"]);
+        hierarchy.add_class("Point", TypeKind::Struct, None, ["norm"]);
+        hierarchy
+    }
+
+    #[test]
+    fn test_class_hierarchy_facts() {
+        let hierarchy = hierarchy();
+        assert_eq!(hierarchy.subclasses("Shape"), BTreeSet::from(["Circle", "Square"]));
+        assert_eq!(hierarchy.defining_class("Square", "name"), Some("Shape"));
+        let symbols = |names: &[&str]| names.iter().map(|name| (*name).to_string()).collect::<BTreeSet<_>>();
+        assert_eq!(hierarchy.implementations("Shape", "area"), symbols(&["Circle.area", "Shape.area", "Square.area"]));
+        assert_eq!(hierarchy.implementations("Square", "name"), symbols(&["Shape.name"]));
+
+        let sealing = hierarchy.sealing();
+        assert!(sealing.is_sealed("Square") && !sealing.is_sealed("Shape") && !sealing.is_sealed("Point"));
+        assert!(sealing.is_final("Shape", "name") && !sealing.is_final("Shape", "area"));
+        assert!(sealing.is_final("Square", "area"));
+    }
+
+    #[test]
+    fn test_reachability_follows_calls_sends_and_function_values() {
+        let module = parse_module(
+            r#"
+fn "main"(%0: object("Square"), %1: object) -> unit {
+bb0:
+    %2: int = send %0, "area"()
+    %3: int = send %1, "norm"()
+    %4: object = global "helper"
+    %5: int = call_indirect %4()
+    return
+}
+
+fn "helper"() -> int {
+bb0:
+    %0: int = call "leaf"()
+    return %0
+}
+
+fn "leaf"() -> int {
+bb0:
+    %0: int = const int 1
+    return %0
+}
+
+fn "unused"() -> int {
+bb0:
+    %0: int = call "leaf"()
+    return %0
+}
+
+fn "Square.area"(%0: object("Square")) -> int {
+bb0:
+    %1: int = call "unused"()
+    return %1
+}
+
+fn "Circle.area"(%0: object("Circle")) -> int {
+bb0:
+    %1: int = const int 3
+    return %1
+}
+
+fn "Point.norm"(%0: object("Point")) -> int {
+bb0:
+    %1: int = const int 0
+    return %1
+}
+"#,
+        )
+        .unwrap();
+
+        let analysis = analyze_with(hierarchy(), &[&module], &["main"]);
+        assert_eq!(analysis.calls.callees("main").collect::<Vec<_>>(), ["Point.norm", "Square.area", "helper"]);
+        // Squares have no subclasses, so circles' areas are never asked for
+        assert_eq!(analysis.dead(), ["Circle.area"]);
+        assert!(analysis.reachable.contains("unused"));
+
+        // A host can call an exported function
+        let mut module = module;
+        module.exports.push(Export { function: "Circle.area".to_string(), symbol: "circle_area".to_string() });
+        assert!(analyze_with(hierarchy(), &[&module], &["main"]).dead().is_empty());
+    }
+}
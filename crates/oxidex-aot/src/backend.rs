@@ -0,0 +1,755 @@
+//! Native code generation.
+//!
+//! The backend translates a module of IR to machine code and writes it as a
+//! relocatable object file, which links against the runtime's support
+//! library into an executable. It is a small emitter of its own rather than
+//! a general code generator: every value lives in a stack slot, and each
+//! instruction becomes a fixed sequence of machine instructions.
+//!
+//! Integers, booleans and floats are unboxed words and are computed inline,
+//! as are direct and indirect calls, branches, switches and the arithmetic
+//! [intrinsics](oxidex_codegen::intrinsics). Everything that involves
+//! objects (messages, fields, enum values, collections, strings and globals
+//! that are not functions) calls an entry point of the runtime listed in
+//! [`runtime`]. Those entry points take and return object words, so scalars
+//! passed to them are boxed first and scalar results unboxed.
+//!
+//! Functions call each other in the System V convention, every argument and
+//! result a word in a general register, floats included. Each function of
+//! the module is defined under its [symbol name](symbol_name). Given an
+//! [entry](Backend::entry), the object also defines `main`, which boots the
+//! runtime with the process's arguments, calls the entry function, and
+//! returns the status the runtime makes of its result. Given the program's
+//! [metadata](Backend::metadata), the object also holds its classes,
+//! protocols and selectors as a static image, which `main` registers before
+//! running any code:
+//!
+//! ```text
+//! main(argc, argv):
+//!     oxidex_rt_init(argc, argv)
+//!     oxidex_rt_register_image(&_OXimage) ; with metadata only
+//!     result = _OXmain()
+//!     return oxidex_rt_exit(result)       ; 0 unless main returns an int
+//! ```
+//!
+//! A function the module [exports](oxidex_codegen::ir::Export) is also
+//! defined under its C symbol, as an entry point taking and returning its
+//! values as C does, so a host application can link against the object and
+//! call it; [`c_header`] declares those entry points. The host boots the
+//! runtime with `oxidex_rt_init`, and registers `_OXimage` if the object
+//! holds one, before calling any of them.
+//!
+//! Only x86-64 ELF objects are written so far; AArch64 and Mach-O targets
+//! are rejected.
+
+mod image;
+mod object;
+mod x86_64;
+
+pub use object::{Object, Relocation, RelocationKind, RelocationTarget, SectionKind, Symbol, SymbolKind};
+
+use image::Metadata;
+use oxidex_codegen::emit::MetadataTables;
+use oxidex_codegen::ir::{Function, Inst, IrType, Module};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Names and signatures of the runtime's entry points.
+///
+/// Words are 64 bits. `object` is an object word, `str` a pointer to a
+/// NUL-terminated UTF-8 string, and `objects` a pointer to object words.
+pub mod runtime {
+    /// `oxidex_rt_init(argc: int, argv: *const str)`, booting the runtime
+    pub const INIT: &str = "oxidex_rt_init";
+    /// `oxidex_rt_exit(result: word) -> int`, tearing the runtime down and
+    /// returning the process's exit status
+    pub const EXIT: &str = "oxidex_rt_exit";
+    /// `oxidex_rt_register_image(image: *const Image)`, registering the
+    /// program's metadata as `oxidec::runtime::image::register_image` does
+    pub const REGISTER_IMAGE: &str = "oxidex_rt_register_image";
+    /// `oxidex_rt_box(kind: ScalarKind, word) -> object`
+    pub const BOX: &str = "oxidex_rt_box";
+    /// `oxidex_rt_unbox(kind: ScalarKind, object) -> word`
+    pub const UNBOX: &str = "oxidex_rt_unbox";
+    /// `oxidex_rt_string(bytes: *const u8, length: int) -> object`
+    pub const STRING: &str = "oxidex_rt_string";
+    /// `oxidex_rt_global(name: str) -> object`
+    pub const GLOBAL: &str = "oxidex_rt_global";
+    /// `oxidex_rt_binary(op: int, lhs: object, rhs: object) -> object`,
+    /// with the operator's position in `BinaryOp`
+    pub const BINARY: &str = "oxidex_rt_binary";
+    /// `oxidex_rt_unary(op: int, operand: object) -> object`, with the
+    /// operator's position in `UnaryOp`
+    pub const UNARY: &str = "oxidex_rt_unary";
+    /// `oxidex_rt_send(receiver: object, selector: str, args: objects, count: int) -> object`
+    pub const SEND: &str = "oxidex_rt_send";
+    /// `oxidex_rt_alloc(class: str) -> object`
+    pub const ALLOC: &str = "oxidex_rt_alloc";
+    /// `oxidex_rt_get_field(object, field: str) -> object`
+    pub const GET_FIELD: &str = "oxidex_rt_get_field";
+    /// `oxidex_rt_set_field(object, field: str, value: object)`
+    pub const SET_FIELD: &str = "oxidex_rt_set_field";
+    /// `oxidex_rt_variant(enum: str, variant: str, payload: objects, has_payload: bool) -> object`
+    pub const VARIANT: &str = "oxidex_rt_variant";
+    /// `oxidex_rt_tuple(elements: objects, count: int) -> object`
+    pub const TUPLE: &str = "oxidex_rt_tuple";
+    /// `oxidex_rt_array(elements: objects, count: int) -> object`
+    pub const ARRAY: &str = "oxidex_rt_array";
+    /// `oxidex_rt_dict(entries: objects, count: int) -> object`, keys and
+    /// values alternating
+    pub const DICT: &str = "oxidex_rt_dict";
+    /// `oxidex_rt_concat(parts: objects, count: int) -> object`
+    pub const CONCAT: &str = "oxidex_rt_concat";
+    /// `oxidex_rt_get_index(collection: object, index: object) -> object`
+    pub const GET_INDEX: &str = "oxidex_rt_get_index";
+    /// `oxidex_rt_set_index(collection: object, index: object, value: object)`
+    pub const SET_INDEX: &str = "oxidex_rt_set_index";
+    /// `oxidex_rt_tag(value: object) -> object`
+    pub const TAG: &str = "oxidex_rt_tag";
+    /// `oxidex_rt_payload(value: object) -> object`
+    pub const PAYLOAD: &str = "oxidex_rt_payload";
+    /// `oxidex_rt_length(collection: object) -> object`
+    pub const LENGTH: &str = "oxidex_rt_length";
+    /// `oxidex_rt_slice(collection: object, start: int) -> object`
+    pub const SLICE: &str = "oxidex_rt_slice";
+    /// `oxidex_rt_divide_by_zero() -> !`
+    pub const DIVIDE_BY_ZERO: &str = "oxidex_rt_divide_by_zero";
+    /// `oxidex_rt_unreachable() -> !`
+    pub const UNREACHABLE: &str = "oxidex_rt_unreachable";
+}
+
+/// How a scalar is represented in a word, as the runtime boxes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ScalarKind {
+    /// The unit value, always 0
+    Unit = 0,
+    /// 0 or 1
+    Bool = 1,
+    /// A two's complement integer
+    Int = 2,
+    /// The bits of a double precision float
+    Float = 3,
+}
+
+impl ScalarKind {
+    /// How values of an IR type are represented, or `None` for objects.
+    #[must_use]
+    pub fn of(ty: &IrType) -> Option<Self> {
+        match ty {
+            IrType::Unit => Some(Self::Unit),
+            IrType::Bool => Some(Self::Bool),
+            IrType::Int => Some(Self::Int),
+            IrType::Float => Some(Self::Float),
+            IrType::String | IrType::Object(_) => None,
+        }
+    }
+}
+
+/// Name of the symbol defining the code of a function.
+#[must_use]
+pub fn symbol_name(function: &str) -> String {
+    format!("_OX{function}")
+}
+
+/// Instruction set of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// x86-64
+    X86_64,
+    /// 64-bit ARM
+    Aarch64,
+}
+
+/// Object file format of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// ELF, as on Linux and the BSDs
+    Elf,
+    /// Mach-O, as on macOS
+    MachO,
+}
+
+/// What the backend generates code for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    /// Instruction set
+    pub arch: Arch,
+    /// Object file format
+    pub format: Format,
+}
+
+impl Target {
+    /// The host, if its instruction set and object format are known.
+    pub const HOST: Option<Self> = {
+        let arch = if cfg!(target_arch = "x86_64") {
+            Some(Arch::X86_64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Arch::Aarch64)
+        } else {
+            None
+        };
+        let format = if cfg!(target_vendor = "apple") { Format::MachO } else { Format::Elf };
+        match arch {
+            Some(arch) => Some(Self { arch, format }),
+            None => None,
+        }
+    };
+
+    /// ELF machine number of the instruction set.
+    fn elf_machine(self) -> u16 {
+        match self.arch {
+            Arch::X86_64 => 62,
+            Arch::Aarch64 => 183,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arch = match self.arch {
+            Arch::X86_64 => "x86-64",
+            Arch::Aarch64 => "aarch64",
+        };
+        let format = match self.format {
+            Format::Elf => "ELF",
+            Format::MachO => "Mach-O",
+        };
+        write!(f, "{arch} {format}")
+    }
+}
+
+/// Errors generating native code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    /// The backend cannot generate code for the target.
+    UnsupportedTarget(Target),
+
+    /// The entry function is not in the module.
+    MissingEntry(String),
+
+    /// The entry function takes parameters, which `main` has none to pass.
+    EntryTakesParameters(String),
+
+    /// An exported function is not in the module.
+    MissingExport(String),
+
+    /// The module declares an extern C function, which native code cannot
+    /// call with its own calling convention yet.
+    ExternFunction(String),
+
+    /// A function creates a closure or box, which native code has no
+    /// runtime support for yet.
+    Closure(String),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedTarget(target) => write!(f, "cannot generate code for {target}"),
+            Self::MissingEntry(name) => write!(f, "entry function '{name}' is not defined"),
+            Self::EntryTakesParameters(name) => write!(f, "entry function '{name}' must not take parameters"),
+            Self::MissingExport(name) => write!(f, "exported function '{name}' is not defined"),
+            Self::ExternFunction(name) => write!(f, "extern function '{name}' cannot be compiled ahead of time"),
+            Self::Closure(name) => write!(f, "function '{name}' uses closures, which cannot be compiled ahead of time"),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+/// Generates object files for a target.
+#[derive(Debug, Clone)]
+pub struct Backend {
+    /// What code is generated for
+    target: Target,
+    /// Function `main` calls, if the object defines `main`
+    entry: Option<String>,
+    /// Metadata image of the program, if the object holds one
+    metadata: Option<Metadata>,
+}
+
+impl Backend {
+    /// Generate code for a target.
+    #[must_use]
+    pub fn new(target: Target) -> Self {
+        Self { target, entry: None, metadata: None }
+    }
+
+    /// Also define a `main` that boots the runtime and calls `function`.
+    #[must_use]
+    pub fn entry(mut self, function: impl Into<String>) -> Self {
+        self.entry = Some(function.into());
+        self
+    }
+
+    /// Also define the metadata image of the program's tables, with an
+    /// `Imp` adapter for each method the module compiles, and register it
+    /// from `main`.
+    #[must_use]
+    pub fn metadata(mut self, tables: &MetadataTables) -> Self {
+        self.metadata = Some(Metadata::new(tables));
+        self
+    }
+
+    /// Translate a module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target is not supported, the module declares
+    /// an extern or uses closures, the entry function is missing or takes
+    /// parameters, or an exported function is missing.
+    pub fn compile(&self, module: &Module) -> Result<Object, BackendError> {
+        if self.target != (Target { arch: Arch::X86_64, format: Format::Elf }) {
+            return Err(BackendError::UnsupportedTarget(self.target));
+        }
+        if let Some(external) = module.externs.first() {
+            return Err(BackendError::ExternFunction(external.name.clone()));
+        }
+        let closure = |inst: &Inst| {
+            matches!(inst, Inst::Closure { .. } | Inst::Box(_) | Inst::Load(_) | Inst::Store { .. })
+        };
+        let uses_closures = |function: &&Function| {
+            function.blocks.iter().flat_map(|block| &block.insts).any(|inst| closure(&inst.inst))
+        };
+        if let Some(function) = module.functions.iter().find(uses_closures) {
+            return Err(BackendError::Closure(function.name.clone()));
+        }
+        let main = match &self.entry {
+            Some(entry) => {
+                let function = module.function(entry).ok_or_else(|| BackendError::MissingEntry(entry.clone()))?;
+                if !function.params.is_empty() {
+                    return Err(BackendError::EntryTakesParameters(entry.clone()));
+                }
+                Some(x86_64::main(entry, function.return_type == IrType::Int, self.metadata.is_some()))
+            }
+            None => None,
+        };
+
+        let mut object = Object::new();
+        let functions: HashSet<&str> = module.functions.iter().map(|function| function.name.as_str()).collect();
+        for function in &module.functions {
+            let code = x86_64::compile(function, x86_64::Context { functions: &functions, object: &mut object });
+            let name = symbol_name(&function.name);
+            object.define(&name, SymbolKind::Function, SectionKind::Text, 16, &code.bytes, code.relocations);
+        }
+        for export in &module.exports {
+            let function =
+                module.function(&export.function).ok_or_else(|| BackendError::MissingExport(export.function.clone()))?;
+            let code = x86_64::export(function);
+            object.define(&export.symbol, SymbolKind::Function, SectionKind::Text, 16, &code.bytes, code.relocations);
+        }
+        if let Some(metadata) = &self.metadata {
+            metadata.define(module, &mut object);
+        }
+        if let Some(code) = main {
+            object.define("main", SymbolKind::Function, SectionKind::Text, 16, &code.bytes, code.relocations);
+        }
+        Ok(object)
+    }
+
+    /// Translate a module and write it as an object file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module cannot be translated.
+    pub fn emit(&self, module: &Module) -> Result<Vec<u8>, BackendError> {
+        Ok(self.compile(module)?.write_elf(self.target.elf_machine()))
+    }
+}
+
+/// A C header declaring the functions a module exports.
+#[must_use]
+pub fn c_header(module: &Module) -> String {
+    let c_type = |ty: &IrType| match ty {
+        IrType::Bool => "bool",
+        IrType::Int | IrType::Unit => "int64_t",
+        IrType::Float => "double",
+        IrType::String | IrType::Object(_) => "void *",
+    };
+    let mut header = String::from("#include <stdbool.h>\n#include <stdint.h>\n");
+    for export in &module.exports {
+        let Some(function) = module.function(&export.function) else {
+            continue;
+        };
+        let params: Vec<&str> = function.params.iter().map(|&param| c_type(function.value_type(param))).collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let result = if function.return_type == IrType::Unit { "void" } else { c_type(&function.return_type) };
+        header.push_str(&format!("\n/* {} */\n{result} {}({params});\n", export.function, export.symbol));
+    }
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_codegen::ir::{Extern, parse_module};
+    use std::path::Path;
+    use std::process::Command;
+
+    const X86_64_ELF: Target = Target { arch: Arch::X86_64, format: Format::Elf };
+
+    fn module() -> Module {
+        parse_module(
+            r#"
+fn "fib"(%0: int) -> int {
+bb0:
+    %1: int = const int 2
+    %2: bool = binary lt %0, %1
+    branch %2, bb1, bb2
+bb1:
+    return %0
+bb2:
+    %3: int = const int 1
+    %4: int = binary sub %0, %3
+    %5: int = call "fib"(%4)
+    %6: int = binary sub %4, %3
+    %7: int = call "fib"(%6)
+    %8: int = binary add %5, %7
+    return %8
+}
+
+fn "sum"(%0: int, %1: int, %2: int, %3: int, %4: int, %5: int, %6: int, %7: int) -> int {
+bb0:
+    %8: int = binary add %0, %7
+    %9: int = binary sub %8, %6
+    return %9
+}
+
+fn "count"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %2: int = const int 1
+    jump bb1
+bb1:
+    %3: int = phi [bb0: %0, bb2: %5]
+    %4: int = phi [bb0: %1, bb2: %6]
+    %7: bool = binary gt %3, %1
+    branch %7, bb2, bb3
+bb2:
+    %5: int = binary sub %3, %2
+    %6: int = binary add %4, %2
+    jump bb1
+bb3:
+    return %4
+}
+
+fn "main"() -> int {
+bb0:
+    %0: int = const int 10
+    %1: object = global "fib"
+    %2: int = call_indirect %1(%0)
+    %3: string = const string "hello"
+    %4: int = length %3
+    %5: int = binary add %2, %4
+    %6: int = call "sum"(%5, %5, %5, %5, %5, %5, %5, %0)
+    %7: float = const float 1.5
+    %8: float = binary mul %7, %7
+    %9: bool = binary gt %8, %7
+    %10: int = call "count"(%0)
+    switch %10 [10: bb1], default bb2
+bb1:
+    branch %9, bb3, bb2
+bb2:
+    %11: int = const int 0
+    return %11
+bb3:
+    return %6
+}
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_object_defines_functions_and_main() {
+        let object = Backend::new(X86_64_ELF).entry("main").compile(&module()).unwrap();
+        let names: Vec<&str> = object.symbols().iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["_OXfib", "_OXsum", "_OXcount", "_OXmain", "main"]);
+        let runtime = [runtime::EXIT, runtime::INIT, runtime::LENGTH, runtime::STRING, runtime::UNBOX];
+        assert_eq!(object.undefined(), runtime);
+        assert_eq!(&object.section(SectionKind::Rodata)[..6], b"hello\0");
+        assert!(object.symbols().iter().all(|symbol| symbol.offset % 16 == 0));
+
+        let unsupported = Target { arch: Arch::Aarch64, format: Format::Elf };
+        let aarch64 = Backend::new(unsupported).compile(&module());
+        assert_eq!(aarch64.unwrap_err(), BackendError::UnsupportedTarget(unsupported));
+        let missing = Backend::new(X86_64_ELF).entry("start").compile(&module());
+        assert_eq!(missing.unwrap_err(), BackendError::MissingEntry("start".to_string()));
+        let parameters = Backend::new(X86_64_ELF).entry("fib").compile(&module());
+        assert_eq!(parameters.unwrap_err(), BackendError::EntryTakesParameters("fib".to_string()));
+    }
+
+    #[test]
+    fn test_emitted_object_is_reproducible() {
+        let emit = || {
+            let tables = image::tests::tables();
+            Backend::new(X86_64_ELF).entry("main").metadata(&tables).emit(&module()).unwrap()
+        };
+        let first = emit();
+        for _ in 0..8 {
+            assert_eq!(emit(), first);
+        }
+    }
+
+    /// A runtime whose strings are their length and whose boxes are the
+    /// words themselves.
+    const RUNTIME: &str = "
+        long oxidex_rt_init_called;
+        void oxidex_rt_init(int argc, char **argv) { oxidex_rt_init_called = argc > 0 && argv[0] != 0; }
+        int oxidex_rt_exit(long result) { return oxidex_rt_init_called ? (int)result : 1; }
+        long oxidex_rt_string(const char *bytes, long length) { return length; }
+        long oxidex_rt_length(long string) { return string; }
+        long oxidex_rt_unbox(long kind, long object) { return kind == 2 ? object : -1; }
+    ";
+
+    #[test]
+    fn test_linked_program_runs() {
+        if Target::HOST != Some(X86_64_ELF) || Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("oxidex-aot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("program.o");
+        std::fs::write(&object, Backend::new(X86_64_ELF).entry("main").emit(&module()).unwrap()).unwrap();
+        std::fs::write(dir.join("runtime.c"), RUNTIME).unwrap();
+        let program = dir.join("program");
+        let link = |inputs: [&Path; 2]| Command::new("cc").args(inputs).arg("-o").arg(&program).status().unwrap();
+        assert!(link([&object, &dir.join("runtime.c")]).success());
+
+        // The first argument to sum, fib(10) + "hello".length, plus the
+        // eighth less the seventh
+        let status = Command::new(&program).status().unwrap();
+        assert_eq!(status.code(), Some((55 + 5) + 10 - (55 + 5)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_linked_intrinsics_run() {
+        if Target::HOST != Some(X86_64_ELF) || Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let module = parse_module(
+            r#"
+fn "main"() -> int {
+bb0:
+    %0: int = const int -7
+    %1: int = intrinsic abs(%0)
+    %2: int = const int 3
+    %3: int = intrinsic max(%1, %2)
+    %4: int = intrinsic min(%1, %2)
+    %5: float = const float 16.0
+    %6: float = intrinsic sqrt(%5)
+    %7: float = const float -2.5
+    %8: float = intrinsic abs(%7)
+    %9: float = intrinsic max(%6, %8)
+    %10: float = intrinsic min(%6, %8)
+    %11: float = binary sub %9, %10
+    %12: float = const float 1.5
+    %13: bool = binary eq %11, %12
+    %14: string = const string "hello"
+    %15: int = intrinsic length(%14)
+    %16: int = const int 10
+    %17: int = binary mul %4, %16
+    %18: int = binary add %3, %17
+    %19: int = binary mul %15, %16
+    %20: int = binary add %18, %19
+    branch %13, bb1, bb2
+bb1:
+    return %20
+bb2:
+    %21: int = const int 0
+    return %21
+}
+"#,
+        )
+        .unwrap();
+        let object = Backend::new(X86_64_ELF).entry("main").compile(&module).unwrap();
+        let runtime = [runtime::EXIT, runtime::INIT, runtime::LENGTH, runtime::STRING, runtime::UNBOX];
+        assert_eq!(object.undefined(), runtime);
+
+        let dir = std::env::temp_dir().join(format!("oxidex-aot-intrinsics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("program.o");
+        std::fs::write(&object, Backend::new(X86_64_ELF).entry("main").emit(&module).unwrap()).unwrap();
+        std::fs::write(dir.join("runtime.c"), RUNTIME).unwrap();
+        let program = dir.join("program");
+        let status = Command::new("cc").arg(&object).arg(dir.join("runtime.c")).arg("-o").arg(&program).status();
+        assert!(status.unwrap().success());
+
+        // max(7, 3) + 10 * min(7, 3) + 10 * "hello".length, the float
+        // intrinsics having held
+        let status = Command::new(&program).status().unwrap();
+        assert_eq!(status.code(), Some(7 + 30 + 50));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// A runtime whose image registration checks the first class and sends
+    /// its methods through their adapters, making the status their results.
+    const IMAGE_RUNTIME: &str = "
+        #include <string.h>
+        struct str { const char *bytes; long len; };
+        struct class { unsigned name, kind, superclass, ivars[2], methods[2], protocols[2]; };
+        typedef void imp(void *self, void *cmd, long *args, long *ret);
+        struct method { unsigned selector, types, flags; imp *imp; };
+        struct table { const void *records; long count; };
+        struct image { unsigned version; struct table tables[8]; };
+        long status = 1;
+        void oxidex_rt_init(int argc, char **argv) {}
+        int oxidex_rt_exit(long result) { return (int)(result + status); }
+        void oxidex_rt_register_image(const struct image *image) {
+            const struct str *strings = image->tables[0].records;
+            const unsigned *selectors = image->tables[1].records;
+            const struct class *adder = image->tables[4].records;
+            const struct method *methods = image->tables[6].records;
+            const struct str *selector = &strings[selectors[methods[1].selector]];
+            long one[] = {41}, six[] = {1, 2, 3, 4, 5, 6}, sum = 0;
+            if (image->version != 1 || strings[adder->name].len != 5 || memcmp(strings[adder->name].bytes, \"Adder\", 5)
+                || selector->len != 14 || memcmp(selector->bytes, \"sum:b:c:d:e:f:\", 14) || methods[2].imp) {
+                return;
+            }
+            methods[0].imp(0, 0, one, &status);
+            methods[1].imp(0, 0, six, &sum);
+            methods[1].imp(0, 0, six, 0);
+            status += sum;
+        }
+    ";
+
+    #[test]
+    fn test_linked_program_registers_image() {
+        if Target::HOST != Some(X86_64_ELF) || Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let module = parse_module(
+            r#"
+fn "Adder.add:"(%0: object, %1: int) -> int {
+bb0:
+    %2: int = const int 1
+    %3: int = binary add %1, %2
+    return %3
+}
+
+fn "Adder.sum:b:c:d:e:f:"(%0: object, %1: int, %2: int, %3: int, %4: int, %5: int, %6: int) -> int {
+bb0:
+    %7: int = binary sub %6, %1
+    %8: int = binary add %7, %5
+    return %8
+}
+
+fn "main"() -> int {
+bb0:
+    %0: int = const int 0
+    return %0
+}
+"#,
+        )
+        .unwrap();
+        let backend = Backend::new(X86_64_ELF).entry("main").metadata(&image::tests::tables());
+        let dir = std::env::temp_dir().join(format!("oxidex-aot-image-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("program.o");
+        std::fs::write(&object, backend.emit(&module).unwrap()).unwrap();
+        std::fs::write(dir.join("runtime.c"), IMAGE_RUNTIME).unwrap();
+        let program = dir.join("program");
+        let link = Command::new("cc").arg(&object).arg(dir.join("runtime.c")).arg("-o").arg(&program).status();
+        assert!(link.unwrap().success());
+
+        // add:(41), then sum's sixth less its first plus its fifth
+        let status = Command::new(&program).status().unwrap();
+        assert_eq!(status.code(), Some(42 + (6 - 1 + 5)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// A host calling the module's exports through the generated header.
+    const EXPORT_HOST: &str = "
+        #include \"module.h\"
+        int main(void) {
+            if (ox_scale(1.5, true, 4.0) != 6.0 || ox_scale(1.5, false, 4.0) != 1.5) {
+                return 1;
+            }
+            return (int)ox_weigh(1, 2, 3, 4, 5, 6, 2.5, 50, true) + (int)ox_weigh(1, 2, 3, 4, 5, 6, 2.5, 50, false);
+        }
+    ";
+
+    #[test]
+    fn test_exports_are_callable_from_c() {
+        let module = parse_module(
+            r#"
+export "scale" as "ox_scale"
+export "weigh" as "ox_weigh"
+
+fn "scale"(%0: float, %1: bool, %2: float) -> float {
+bb0:
+    branch %1, bb1, bb2
+bb1:
+    %3: float = binary mul %0, %2
+    return %3
+bb2:
+    return %0
+}
+
+fn "weigh"(%0: int, %1: int, %2: int, %3: int, %4: int, %5: int, %6: float, %7: int, %8: bool) -> int {
+bb0:
+    branch %8, bb1, bb2
+bb1:
+    %9: int = binary sub %7, %0
+    return %9
+bb2:
+    %10: int = const int 0
+    return %10
+}
+"#,
+        )
+        .unwrap();
+        let header = c_header(&module);
+        assert!(header.contains("double ox_scale(double, bool, double);"));
+        let ints = ["int64_t"; 6].join(", ");
+        assert!(header.contains(&format!("int64_t ox_weigh({ints}, double, int64_t, bool);")));
+        let object = Backend::new(X86_64_ELF).compile(&module).unwrap();
+        assert!(object.symbol("ox_scale").is_some_and(|symbol| symbol.kind == SymbolKind::Function));
+        let mut missing = module.clone();
+        missing.exports[0].function = "absent".to_string();
+        let err = Backend::new(X86_64_ELF).compile(&missing).unwrap_err();
+        assert_eq!(err, BackendError::MissingExport("absent".to_string()));
+        let mut foreign = module.clone();
+        foreign.externs.push(Extern { name: "cos".to_string(), library: None, encoding: "dd".to_string() });
+        let err = Backend::new(X86_64_ELF).compile(&foreign).unwrap_err();
+        assert_eq!(err, BackendError::ExternFunction("cos".to_string()));
+        let closure = parse_module(
+            r#"
+fn "add"(%0: int, %1: int) -> int {
+bb0:
+    %2: int = binary add %0, %1
+    return %2
+}
+
+fn "adder"(%0: int) -> object {
+bb0:
+    %1: object = closure "add"(%0)
+    return %1
+}
+"#,
+        )
+        .unwrap();
+        let err = Backend::new(X86_64_ELF).compile(&closure).unwrap_err();
+        assert_eq!(err, BackendError::Closure("adder".to_string()));
+
+        if Target::HOST != Some(X86_64_ELF) || Command::new("cc").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("oxidex-aot-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let object = dir.join("module.o");
+        std::fs::write(&object, Backend::new(X86_64_ELF).emit(&module).unwrap()).unwrap();
+        std::fs::write(dir.join("module.h"), header).unwrap();
+        std::fs::write(dir.join("host.c"), EXPORT_HOST).unwrap();
+        let program = dir.join("host");
+        let link = Command::new("cc").arg(dir.join("host.c")).arg(&object).arg("-o").arg(&program).status();
+        assert!(link.unwrap().success());
+
+        // The eighth argument less the first, when the ninth is true
+        let status = Command::new(&program).status().unwrap();
+        assert_eq!(status.code(), Some(50 - 1));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,302 @@
+//! Runtime metadata images.
+//!
+//! A program's classes, protocols and selectors are written as constant
+//! tables in the layout of `oxidec::runtime::image::Image`, under the symbol
+//! [`SYMBOL`], so `main` registers them with one runtime call instead of
+//! running code that builds each class. Strings point into the object's
+//! constant data, and every table pointer is relocated against the image
+//! itself, so the image needs no fixing up beyond what the linker does.
+//!
+//! The runtime calls a method through an `Imp`, which receives the message
+//! arguments as an array of words. Each method with compiled code gets an
+//! adapter named `<symbol>$imp` that spreads the array into the System V
+//! registers the compiled function expects, and stores the result.
+
+use super::object::{Object, Relocation, RelocationKind, RelocationTarget, SectionKind, SymbolKind};
+use super::{symbol_name, x86_64};
+use oxidec::runtime::image::{
+    IMAGE_VERSION, METHOD_STATIC, SUPERCLASS_EXTERNAL, SUPERCLASS_LOCAL, SUPERCLASS_NONE,
+};
+use oxidex_codegen::emit::{MetadataTables, SuperclassRef};
+use oxidex_codegen::ir::Module;
+use std::collections::HashMap;
+
+/// Symbol of the image.
+pub const SYMBOL: &str = "_OXimage";
+
+/// Size of the image header, a version word, a word of required runtime
+/// features (none) and eight tables.
+const HEADER: usize = 8 + 8 * 16;
+
+/// A method record and the function implementing it.
+#[derive(Debug, Clone)]
+struct MethodRecord {
+    /// Selector, type encoding and flags
+    words: [u32; 3],
+    /// IR function of the method
+    function: String,
+}
+
+/// The records of an image, with strings by index.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Metadata {
+    strings: Vec<String>,
+    /// String index of each selector's name
+    selectors: Vec<u32>,
+    /// Name and requirement range of each protocol
+    protocols: Vec<[u32; 3]>,
+    /// Selector and type encoding of each requirement
+    requirements: Vec<[u32; 2]>,
+    /// Name, superclass kind and reference, and member ranges of each class
+    classes: Vec<[u32; 9]>,
+    /// Name and type encoding of each instance variable
+    ivars: Vec<[u32; 2]>,
+    methods: Vec<MethodRecord>,
+    /// String index of each adopted protocol's name
+    adopted: Vec<u32>,
+}
+
+impl Metadata {
+    /// Collect the records of a program's metadata tables.
+    pub(super) fn new(tables: &MetadataTables) -> Self {
+        let mut strings: Vec<String> = tables
+            .strings
+            .iter()
+            .map(|(_, string)| String::from_utf8_lossy(string.as_bytes()).into_owned())
+            .collect();
+        let mut lookup: HashMap<String, u32> =
+            strings.iter().enumerate().map(|(i, string)| (string.clone(), i as u32)).collect();
+        let selectors = tables
+            .selectors
+            .iter()
+            .map(|(_, selector)| {
+                *lookup.entry(selector.name().to_string()).or_insert_with(|| {
+                    strings.push(selector.name().to_string());
+                    (strings.len() - 1) as u32
+                })
+            })
+            .collect();
+
+        let protocols = tables
+            .protocols
+            .iter()
+            .map(|protocol| [protocol.name.0, protocol.requirements.start, protocol.requirements.end])
+            .collect();
+        let requirements =
+            tables.requirements.iter().map(|requirement| [requirement.selector.0, requirement.types.0]).collect();
+        let classes = tables
+            .classes
+            .iter()
+            .map(|class| {
+                let (kind, superclass) = match class.superclass {
+                    None => (SUPERCLASS_NONE, 0),
+                    Some(SuperclassRef::Local(id)) => (SUPERCLASS_LOCAL, id.0),
+                    Some(SuperclassRef::External(name)) => (SUPERCLASS_EXTERNAL, name.0),
+                };
+                [
+                    class.name.0,
+                    kind,
+                    superclass,
+                    class.ivars.start,
+                    class.ivars.end,
+                    class.methods.start,
+                    class.methods.end,
+                    class.protocols.start,
+                    class.protocols.end,
+                ]
+            })
+            .collect();
+        let ivars = tables.ivars.iter().map(|ivar| [ivar.name.0, ivar.types.0]).collect();
+        let methods = tables
+            .methods
+            .iter()
+            .map(|method| MethodRecord {
+                words: [method.selector.0, method.types.0, if method.is_static { METHOD_STATIC } else { 0 }],
+                function: strings[method.function.0 as usize].clone(),
+            })
+            .collect();
+        let adopted = tables.adopted.iter().map(|name| name.0).collect();
+
+        Self { strings, selectors, protocols, requirements, classes, ivars, methods, adopted }
+    }
+
+    /// Define the image and the adapters of the methods `module` compiles.
+    pub(super) fn define(&self, module: &Module, object: &mut Object) {
+        let mut image = Image { bytes: vec![0; HEADER], relocations: Vec::new() };
+        image.bytes[..4].copy_from_slice(&IMAGE_VERSION.to_le_bytes());
+
+        image.table(0, self.strings.len(), |image, index| {
+            let string = &self.strings[index];
+            let offset = object.cstring(string);
+            image.pointer(RelocationTarget::Section(SectionKind::Rodata), offset as i64);
+            image.word(string.len() as u64);
+        });
+        image.table(1, self.selectors.len(), |image, index| image.u32s(&[self.selectors[index]]));
+        image.table(2, self.protocols.len(), |image, index| image.u32s(&self.protocols[index]));
+        image.table(3, self.requirements.len(), |image, index| image.u32s(&self.requirements[index]));
+        image.table(4, self.classes.len(), |image, index| image.u32s(&self.classes[index]));
+        image.table(5, self.ivars.len(), |image, index| image.u32s(&self.ivars[index]));
+        image.table(6, self.methods.len(), |image, index| {
+            let method = &self.methods[index];
+            image.u32s(&method.words);
+            image.u32s(&[0]);
+            let function = module.function(&method.function).filter(|_| method.words[2] & METHOD_STATIC == 0);
+            match function {
+                Some(function) => {
+                    let adapter = format!("{}$imp", symbol_name(&function.name));
+                    if object.symbol(&adapter).is_none() {
+                        let code = x86_64::method(&function.name, function.params.len());
+                        let relocations = code.relocations;
+                        object.define(&adapter, SymbolKind::Function, SectionKind::Text, 16, &code.bytes, relocations);
+                    }
+                    image.pointer(RelocationTarget::Symbol(adapter), 0);
+                }
+                None => image.word(0),
+            }
+        });
+        image.table(7, self.adopted.len(), |image, index| image.u32s(&[self.adopted[index]]));
+
+        object.define(SYMBOL, SymbolKind::Data, SectionKind::Data, 8, &image.bytes, image.relocations);
+    }
+}
+
+/// An image being laid out.
+struct Image {
+    bytes: Vec<u8>,
+    /// Fields to relocate, at offsets within the image
+    relocations: Vec<Relocation>,
+}
+
+impl Image {
+    fn word(&mut self, word: u64) {
+        self.bytes.extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn u32s(&mut self, words: &[u32]) {
+        for word in words {
+            self.bytes.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    /// Append the address of `target` plus `addend`.
+    fn pointer(&mut self, target: RelocationTarget, addend: i64) {
+        self.relocate(self.bytes.len(), target, addend);
+        self.word(0);
+    }
+
+    fn relocate(&mut self, offset: usize, target: RelocationTarget, addend: i64) {
+        let kind = RelocationKind::Absolute64;
+        self.relocations.push(Relocation { section: SectionKind::Data, offset, target, kind, addend });
+    }
+
+    /// Append the `count` records of the table the header lists at
+    /// `position`, and point the header at them. An empty table is null.
+    fn table(&mut self, position: usize, count: usize, mut record: impl FnMut(&mut Self, usize)) {
+        let field = 8 + 16 * position;
+        self.bytes[field + 8..field + 16].copy_from_slice(&(count as u64).to_le_bytes());
+        if count == 0 {
+            return;
+        }
+        self.bytes.resize(self.bytes.len().next_multiple_of(8), 0);
+        self.relocate(field, RelocationTarget::Symbol(SYMBOL.to_string()), self.bytes.len() as i64);
+        for index in 0..count {
+            record(self, index);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use oxidec::get_global_arena;
+    use oxidex_codegen::emit::{ClassDescriptor, ClassId, MethodEntry};
+    use oxidex_codegen::ir::method_symbol;
+
+    /// Tables of a root class `Adder` with methods `add:` and
+    /// `sum:b:c:d:e:f:`, and a subclass `Tally` with a static method,
+    /// adopting `Summing`.
+    pub(in super::super) fn tables() -> MetadataTables {
+        let mut tables = MetadataTables::default();
+        let arena = get_global_arena();
+        let adder = tables.strings.intern("Adder");
+        let add = MethodEntry {
+            selector: tables.selectors.intern("add:").unwrap(),
+            types: tables.strings.intern("q@:q"),
+            function: tables.strings.intern(&method_symbol("Adder", "add:")),
+            is_static: false,
+        };
+        let sum = MethodEntry {
+            selector: tables.selectors.intern("sum:b:c:d:e:f:").unwrap(),
+            types: tables.strings.intern("q@:qqqqqq"),
+            function: tables.strings.intern(&method_symbol("Adder", "sum:b:c:d:e:f:")),
+            is_static: false,
+        };
+        let make = MethodEntry {
+            selector: tables.selectors.intern("make").unwrap(),
+            types: tables.strings.intern("@@:"),
+            function: tables.strings.intern(&method_symbol("Tally", "make")),
+            is_static: true,
+        };
+        tables.methods = vec![add, sum, make];
+        let tally = tables.strings.intern("Tally");
+        tables.adopted.push(tables.strings.intern("Summing"));
+        let classes = [
+            ClassDescriptor { name: adder, superclass: None, ivars: 0..0, methods: 0..2, protocols: 0..0 },
+            ClassDescriptor {
+                name: tally,
+                superclass: Some(SuperclassRef::Local(ClassId(0))),
+                ivars: 0..0,
+                methods: 2..3,
+                protocols: 0..1,
+            },
+        ];
+        tables.classes = classes.into_iter().map(|class| &*arena.alloc(class)).collect();
+        tables
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_image_layout() {
+        let module = oxidex_codegen::ir::parse_module(
+            r#"
+fn "Adder.add:"(%0: object, %1: int) -> int {
+bb0:
+    return %1
+}
+"#,
+        )
+        .unwrap();
+        let mut object = Object::new();
+        Metadata::new(&tables()).define(&module, &mut object);
+        assert_eq!(size_of::<oxidec::runtime::Image>(), HEADER);
+
+        let image = object.symbol(SYMBOL).unwrap();
+        assert_eq!((image.kind, image.section), (SymbolKind::Data, SectionKind::Data));
+        let bytes = &object.section(SectionKind::Data)[image.offset..image.offset + image.size];
+        assert_eq!(&bytes[..4], &IMAGE_VERSION.to_le_bytes());
+        // Strings (the selectors' names join the pool), selectors,
+        // protocols, requirements, classes, ivars, methods, adopted names
+        let counts: Vec<u64> = (0..8).map(|table| u64_at(bytes, 16 + 16 * table)).collect();
+        assert_eq!(counts, [12, 3, 0, 0, 2, 0, 3, 1]);
+
+        // Every non-empty table is relocated against the image, and only
+        // the compiled instance method has an adapter
+        let relocations = object.relocations();
+        let into_image = relocations.iter().filter(|r| r.target == RelocationTarget::Symbol(SYMBOL.to_string()));
+        assert_eq!(into_image.count(), 5);
+        let imps: Vec<_> = relocations
+            .iter()
+            .filter(|r| r.section == SectionKind::Data)
+            .filter_map(|r| match &r.target {
+                RelocationTarget::Symbol(name) if name != SYMBOL => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(imps, ["_OXAdder.add:$imp"]);
+        assert!(object.symbol("_OXAdder.add:$imp").is_some());
+        assert_eq!(object.undefined(), ["_OXAdder.add:"]);
+    }
+}
@@ -0,0 +1,539 @@
+//! Relocatable object files.
+//!
+//! An [`Object`] holds the contents of the sections a program needs, the
+//! symbols defined in them, and the relocations the linker applies to them.
+//! Symbols are referred to by name, so code may refer to a function defined
+//! later in the object or in another object: a name the object does not
+//! define is written as an undefined symbol for the linker to resolve.
+//!
+//! [`Object::write_elf`] writes an ELF64 relocatable file. Defined symbols
+//! are global, and every object marks its stack non-executable.
+
+use std::collections::HashMap;
+
+/// A section of an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SectionKind {
+    /// Executable code
+    Text,
+    /// Constant data
+    Rodata,
+    /// Writable data
+    Data,
+}
+
+impl SectionKind {
+    /// Every kind of section, in the order they are written.
+    pub const ALL: [Self; 3] = [Self::Text, Self::Rodata, Self::Data];
+
+    /// Name of the section.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Text => ".text",
+            Self::Rodata => ".rodata",
+            Self::Data => ".data",
+        }
+    }
+}
+
+/// What a symbol names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Code
+    Function,
+    /// Data
+    Data,
+}
+
+/// A symbol the object defines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Name the linker knows it by
+    pub name: String,
+    /// What it names
+    pub kind: SymbolKind,
+    /// Section it is defined in
+    pub section: SectionKind,
+    /// Offset of its start in the section
+    pub offset: usize,
+    /// Length of what it names
+    pub size: usize,
+}
+
+/// What a relocation refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelocationTarget {
+    /// A symbol, defined in this object or not
+    Symbol(String),
+    /// The start of a section of this object
+    Section(SectionKind),
+}
+
+/// How a relocation is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// 64-bit address of the target
+    Absolute64,
+    /// 32-bit displacement of the target from the relocated field
+    Relative32,
+    /// 32-bit displacement of a call target, through the PLT if the target
+    /// is in a shared library
+    Call32,
+}
+
+/// A field the linker fills in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Section holding the field
+    pub section: SectionKind,
+    /// Offset of the field in the section
+    pub offset: usize,
+    /// What the field refers to
+    pub target: RelocationTarget,
+    /// How the field is computed
+    pub kind: RelocationKind,
+    /// Value added to the target's address
+    pub addend: i64,
+}
+
+/// A relocatable object.
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    /// Contents of each section
+    sections: HashMap<SectionKind, Vec<u8>>,
+    /// Symbols defined, in definition order
+    symbols: Vec<Symbol>,
+    /// Fields to relocate
+    relocations: Vec<Relocation>,
+    /// Offsets of the NUL-terminated strings in the constant data
+    strings: HashMap<String, usize>,
+}
+
+impl Object {
+    /// Create an empty object.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contents of a section.
+    #[must_use]
+    pub fn section(&self, section: SectionKind) -> &[u8] {
+        self.sections.get(&section).map_or(&[], Vec::as_slice)
+    }
+
+    /// Symbols the object defines.
+    #[must_use]
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Look up a symbol the object defines.
+    #[must_use]
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Fields the linker fills in.
+    #[must_use]
+    pub fn relocations(&self) -> &[Relocation] {
+        &self.relocations
+    }
+
+    /// Names the object refers to without defining them.
+    #[must_use]
+    pub fn undefined(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .relocations
+            .iter()
+            .filter_map(|relocation| match &relocation.target {
+                RelocationTarget::Symbol(name) if self.symbol(name).is_none() => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Append `bytes` to a section at an offset aligned to `align`,
+    /// defining a symbol for them and relocating their fields, whose
+    /// offsets are relative to the start of `bytes`.
+    pub fn define(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        section: SectionKind,
+        align: usize,
+        bytes: &[u8],
+        relocations: impl IntoIterator<Item = Relocation>,
+    ) -> usize {
+        let offset = self.append(section, align, bytes);
+        self.symbols.push(Symbol { name: name.to_string(), kind, section, offset, size: bytes.len() });
+        for relocation in relocations {
+            self.relocations.push(Relocation { section, offset: offset + relocation.offset, ..relocation });
+        }
+        offset
+    }
+
+    /// Offset in the constant data of `string` followed by a NUL byte,
+    /// adding it if the object has none.
+    pub fn cstring(&mut self, string: &str) -> usize {
+        if let Some(&offset) = self.strings.get(string) {
+            return offset;
+        }
+        let offset = self.append(SectionKind::Rodata, 1, &[string.as_bytes(), b"\0"].concat());
+        self.strings.insert(string.to_string(), offset);
+        offset
+    }
+
+    fn append(&mut self, section: SectionKind, align: usize, bytes: &[u8]) -> usize {
+        let contents = self.sections.entry(section).or_default();
+        let fill = if section == SectionKind::Text { 0xcc } else { 0 };
+        contents.resize(contents.len().next_multiple_of(align.max(1)), fill);
+        let offset = contents.len();
+        contents.extend_from_slice(bytes);
+        offset
+    }
+
+    /// Write the object as an ELF64 relocatable file for `machine`.
+    #[must_use]
+    pub fn write_elf(&self, machine: u16) -> Vec<u8> {
+        Elf::new(self).write(machine)
+    }
+}
+
+/// Section header types and flags.
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+/// x86-64 relocation types.
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+
+/// Sizes of ELF64 structures.
+const HEADER: usize = 64;
+const SECTION_HEADER: usize = 64;
+const SYMBOL: usize = 24;
+const RELA: usize = 24;
+
+/// A section header.
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+/// An object laid out as ELF.
+struct Elf<'a> {
+    object: &'a Object,
+    /// Section kinds with contents, and their section indices
+    sections: Vec<(SectionKind, u16)>,
+    /// Symbol table and its string table
+    symtab: Vec<u8>,
+    strtab: Vec<u8>,
+    /// Index of the first global symbol
+    first_global: u32,
+    /// Symbol table index of each name
+    indices: HashMap<&'a str, u32>,
+}
+
+impl<'a> Elf<'a> {
+    fn new(object: &'a Object) -> Self {
+        let sections: Vec<(SectionKind, u16)> = SectionKind::ALL
+            .into_iter()
+            .filter(|section| !object.section(*section).is_empty())
+            .zip(1..)
+            .collect();
+        let (symtab, strtab) = (vec![0; SYMBOL], vec![0]);
+        let mut elf = Self { object, sections, symtab, strtab, first_global: 0, indices: HashMap::new() };
+        // Locals first: a symbol for each section, for references to it
+        for index in 0..elf.sections.len() {
+            let shndx = elf.sections[index].1;
+            elf.symbol(0, 0x03, shndx, 0, 0);
+        }
+        elf.first_global = elf.count();
+        for symbol in &object.symbols {
+            let name = elf.name(&symbol.name);
+            let info = match symbol.kind {
+                SymbolKind::Function => 0x12,
+                SymbolKind::Data => 0x11,
+            };
+            let shndx = elf.index_of(symbol.section);
+            elf.indices.insert(&symbol.name, elf.count());
+            elf.symbol(name, info, shndx, symbol.offset as u64, symbol.size as u64);
+        }
+        for undefined in object.undefined() {
+            let name = elf.name(undefined);
+            elf.indices.insert(undefined, elf.count());
+            elf.symbol(name, 0x10, 0, 0, 0);
+        }
+        elf
+    }
+
+    fn count(&self) -> u32 {
+        (self.symtab.len() / SYMBOL) as u32
+    }
+
+    fn index_of(&self, section: SectionKind) -> u16 {
+        self.sections.iter().find(|(kind, _)| *kind == section).map_or(0, |(_, index)| *index)
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        let offset = self.strtab.len() as u32;
+        self.strtab.extend_from_slice(name.as_bytes());
+        self.strtab.push(0);
+        offset
+    }
+
+    fn symbol(&mut self, name: u32, info: u8, shndx: u16, value: u64, size: u64) {
+        self.symtab.extend_from_slice(&name.to_le_bytes());
+        self.symtab.push(info);
+        self.symtab.push(0); // st_other: default visibility
+        self.symtab.extend_from_slice(&shndx.to_le_bytes());
+        self.symtab.extend_from_slice(&value.to_le_bytes());
+        self.symtab.extend_from_slice(&size.to_le_bytes());
+    }
+
+    /// Relocation entries for a section.
+    fn relocations(&self, section: SectionKind) -> Vec<u8> {
+        let mut rela = Vec::new();
+        for relocation in self.object.relocations.iter().filter(|relocation| relocation.section == section) {
+            let symbol = match &relocation.target {
+                RelocationTarget::Symbol(name) => self.indices[name.as_str()],
+                RelocationTarget::Section(target) => {
+                    self.sections.iter().position(|(kind, _)| kind == target).map_or(0, |index| index as u32 + 1)
+                }
+            };
+            let kind = match relocation.kind {
+                RelocationKind::Absolute64 => R_X86_64_64,
+                RelocationKind::Relative32 => R_X86_64_PC32,
+                RelocationKind::Call32 => R_X86_64_PLT32,
+            };
+            rela.extend_from_slice(&(relocation.offset as u64).to_le_bytes());
+            rela.extend_from_slice(&((u64::from(symbol) << 32) | u64::from(kind)).to_le_bytes());
+            rela.extend_from_slice(&relocation.addend.to_le_bytes());
+        }
+        rela
+    }
+
+    fn write(self, machine: u16) -> Vec<u8> {
+        let mut shstrtab = vec![0];
+        let mut section_name = |name: &str| {
+            let offset = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+            offset
+        };
+
+        // Contents, each followed by its relocations, then the tables
+        let mut body: Vec<u8> = Vec::new();
+        let mut place = |bytes: &[u8], align: usize| {
+            let offset = (HEADER + body.len()).next_multiple_of(align);
+            body.resize(offset - HEADER, 0);
+            body.extend_from_slice(bytes);
+            offset
+        };
+        let mut headers = vec![SectionHeader {
+            name: 0,
+            kind: 0,
+            flags: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 0,
+            entry_size: 0,
+        }];
+        let symtab_index = (1 + 2 * self.sections.len()) as u32;
+        for &(section, _) in &self.sections {
+            let contents = self.object.section(section);
+            let flags = match section {
+                SectionKind::Text => SHF_ALLOC | SHF_EXECINSTR,
+                SectionKind::Rodata => SHF_ALLOC,
+                SectionKind::Data => SHF_ALLOC | SHF_WRITE,
+            };
+            let align = if section == SectionKind::Text { 16 } else { 8 };
+            headers.push(SectionHeader {
+                name: section_name(section.name()),
+                kind: SHT_PROGBITS,
+                flags,
+                offset: place(contents, align as usize),
+                size: contents.len(),
+                link: 0,
+                info: 0,
+                align,
+                entry_size: 0,
+            });
+        }
+        // Relocations follow all contents so section indices stay in order
+        for (index, &(section, _)) in self.sections.iter().enumerate() {
+            let rela = self.relocations(section);
+            headers.push(SectionHeader {
+                name: section_name(&format!(".rela{}", section.name())),
+                kind: SHT_RELA,
+                flags: SHF_INFO_LINK,
+                offset: place(&rela, 8),
+                size: rela.len(),
+                link: symtab_index,
+                info: index as u32 + 1,
+                align: 8,
+                entry_size: RELA as u64,
+            });
+        }
+        headers.push(SectionHeader {
+            name: section_name(".symtab"),
+            kind: SHT_SYMTAB,
+            flags: 0,
+            offset: place(&self.symtab, 8),
+            size: self.symtab.len(),
+            link: symtab_index + 1,
+            info: self.first_global,
+            align: 8,
+            entry_size: SYMBOL as u64,
+        });
+        headers.push(SectionHeader {
+            name: section_name(".strtab"),
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: place(&self.strtab, 1),
+            size: self.strtab.len(),
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        // An empty .note.GNU-stack marks the stack non-executable
+        headers.push(SectionHeader {
+            name: section_name(".note.GNU-stack"),
+            kind: SHT_PROGBITS,
+            flags: 0,
+            offset: place(&[], 1),
+            size: 0,
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        let shstrndx = headers.len() as u16;
+        let name = section_name(".shstrtab");
+        headers.push(SectionHeader {
+            name,
+            kind: SHT_STRTAB,
+            flags: 0,
+            offset: place(&shstrtab, 1),
+            size: shstrtab.len(),
+            link: 0,
+            info: 0,
+            align: 1,
+            entry_size: 0,
+        });
+        let sections_at = (HEADER + body.len()).next_multiple_of(8);
+
+        let mut elf = Vec::with_capacity(sections_at + headers.len() * SECTION_HEADER);
+        elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]); // 64-bit, little endian, version 1
+        elf.resize(16, 0);
+        elf.extend_from_slice(&1_u16.to_le_bytes()); // e_type: relocatable
+        elf.extend_from_slice(&machine.to_le_bytes());
+        elf.extend_from_slice(&1_u32.to_le_bytes()); // e_version
+        elf.extend_from_slice(&0_u64.to_le_bytes()); // e_entry
+        elf.extend_from_slice(&0_u64.to_le_bytes()); // e_phoff
+        elf.extend_from_slice(&(sections_at as u64).to_le_bytes()); // e_shoff
+        elf.extend_from_slice(&0_u32.to_le_bytes()); // e_flags
+        for half in [HEADER as u16, 0, 0, SECTION_HEADER as u16, headers.len() as u16, shstrndx] {
+            // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+            elf.extend_from_slice(&half.to_le_bytes());
+        }
+        elf.extend_from_slice(&body);
+        elf.resize(sections_at, 0);
+        for header in headers {
+            elf.extend_from_slice(&header.name.to_le_bytes());
+            elf.extend_from_slice(&header.kind.to_le_bytes());
+            elf.extend_from_slice(&header.flags.to_le_bytes());
+            elf.extend_from_slice(&0_u64.to_le_bytes()); // sh_addr
+            elf.extend_from_slice(&(header.offset as u64).to_le_bytes());
+            elf.extend_from_slice(&(header.size as u64).to_le_bytes());
+            elf.extend_from_slice(&header.link.to_le_bytes());
+            elf.extend_from_slice(&header.info.to_le_bytes());
+            elf.extend_from_slice(&header.align.to_le_bytes());
+            elf.extend_from_slice(&header.entry_size.to_le_bytes());
+        }
+        elf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> usize {
+        usize::try_from(u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_elf_sections_symbols_and_relocations() {
+        let mut object = Object::new();
+        let greeting = object.cstring("hi");
+        assert_eq!(object.cstring("hi"), greeting);
+        let call = Relocation {
+            section: SectionKind::Text,
+            offset: 1,
+            target: RelocationTarget::Symbol("puts".to_string()),
+            kind: RelocationKind::Call32,
+            addend: -4,
+        };
+        object.define("f", SymbolKind::Function, SectionKind::Text, 16, &[0xc3], []);
+        let g = object.define("g", SymbolKind::Function, SectionKind::Text, 16, &[0xe8, 0, 0, 0, 0, 0xc3], [call]);
+        assert_eq!(g, 16);
+        assert_eq!(object.relocations()[0].offset, 17);
+        assert_eq!(object.undefined(), ["puts"]);
+
+        let elf = object.write_elf(62);
+        assert_eq!(elf[..4], [0x7f, b'E', b'L', b'F']);
+        let (sections, count, names) = (u64_at(&elf, 40), usize::from(u16_at(&elf, 60)), u16_at(&elf, 62));
+        // null, .text, .rodata, their relocations, .symtab, .strtab, .note.GNU-stack and .shstrtab
+        assert_eq!((count, names), (9, 8));
+        assert_eq!(elf.len(), sections + count * SECTION_HEADER);
+        let header = |index: usize| sections + index * SECTION_HEADER;
+        let shstrtab = u64_at(&elf, header(8) + 24);
+        let name = |index: usize| {
+            let start = shstrtab + u32_at(&elf, header(index)) as usize;
+            let end = start + elf[start..].iter().position(|&byte| byte == 0).unwrap();
+            std::str::from_utf8(&elf[start..end]).unwrap().to_string()
+        };
+        let names: Vec<String> = (1..count).map(name).collect();
+        let expected = [".text", ".rodata", ".rela.text", ".rela.rodata", ".symtab", ".strtab", ".note.GNU-stack"];
+        assert_eq!(names[..7], expected);
+        assert_eq!(names[7], ".shstrtab");
+
+        // The call names the undefined symbol, after null, the section symbols, f and g
+        let rela = u64_at(&elf, header(3) + 24);
+        assert_eq!((u64_at(&elf, rela), u32_at(&elf, rela + 8), u32_at(&elf, rela + 12)), (17, 4, 5));
+        // The symbol table's locals are the two section symbols
+        assert_eq!(u32_at(&elf, header(5) + 44), 3);
+        let strtab = u64_at(&elf, header(6) + 24);
+        assert_eq!(&elf[strtab..strtab + 9], b"\0f\0g\0puts");
+    }
+}
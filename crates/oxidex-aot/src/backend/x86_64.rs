@@ -0,0 +1,783 @@
+//! Code generation for x86-64, in the System V calling convention.
+//!
+//! Every value of a function lives in a stack slot of its own, below the
+//! saved frame pointer, and each instruction loads its operands into
+//! registers, computes, and stores its result. Below the slots is a
+//! scratch area where operands passed to the runtime are gathered, boxed
+//! if they are scalars, and from where arrays of them are passed by
+//! address. Phis are assigned on the edge leaving each predecessor, by
+//! pushing every incoming value before popping them into the phis' slots,
+//! so phis reading each other see the values from before the edge.
+
+use super::object::{Object, Relocation, RelocationKind, RelocationTarget, SectionKind};
+use super::{ScalarKind, runtime, symbol_name};
+use oxidex_codegen::intrinsics::Intrinsic;
+use oxidex_codegen::ir::{BlockId, Constant, Function, Inst, IrType, Terminator, ValueId};
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use std::collections::HashSet;
+
+/// Registers, by encoding.
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RSI: u8 = 6;
+const RDI: u8 = 7;
+const R8: u8 = 8;
+const R9: u8 = 9;
+
+/// Registers arguments are passed in, in order.
+const ARGUMENTS: [u8; 6] = [RDI, RSI, RDX, RCX, R8, R9];
+
+/// Condition codes, as the low nibble of `setcc` and `jcc`.
+const ABOVE_OR_EQUAL: u8 = 0x3;
+const EQUAL: u8 = 0x4;
+const NOT_EQUAL: u8 = 0x5;
+const ABOVE: u8 = 0x7;
+const LESS: u8 = 0xc;
+const GREATER_OR_EQUAL: u8 = 0xd;
+const LESS_OR_EQUAL: u8 = 0xe;
+const GREATER: u8 = 0xf;
+const PARITY: u8 = 0xa;
+const NO_PARITY: u8 = 0xb;
+
+/// Where a function's code goes, and what else the program defines.
+pub(super) struct Context<'a> {
+    /// Functions defined by the program, whose address a global load takes
+    pub functions: &'a HashSet<&'a str>,
+    /// The object receiving the strings the code refers to
+    pub object: &'a mut Object,
+}
+
+/// An operand of a runtime call.
+enum Operand<'a> {
+    /// A word
+    Immediate(u64),
+    /// Address of a NUL-terminated string in the constant data
+    String(&'a str),
+    /// A scratch word
+    Scratch(usize),
+    /// Address of a run of scratch words
+    ScratchAddress(usize),
+}
+
+/// Machine code for one function, with the fields the linker fills in.
+pub(super) struct Code {
+    /// The code
+    pub bytes: Vec<u8>,
+    /// Fields to relocate, at offsets within the code
+    pub relocations: Vec<Relocation>,
+}
+
+/// Compile a function.
+pub(super) fn compile(function: &Function, context: Context<'_>) -> Code {
+    let scratch = function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.insts)
+        .map(|inst| match &inst.inst {
+            Inst::Dict(entries) => 2 * entries.len(),
+            other => other.operands().len(),
+        })
+        .max()
+        .unwrap_or(0);
+    let mut assembler = Assembler {
+        function,
+        context,
+        code: Code { bytes: Vec::new(), relocations: Vec::new() },
+        scratch_base: -8 * (function.values.len() + scratch) as i32,
+        labels: vec![None; function.blocks.len()],
+        fixups: Vec::new(),
+    };
+    assembler.function();
+    assembler.code
+}
+
+/// The generated `main(argc, argv)`: boot the runtime, register the
+/// metadata image if there is one, run `entry`, and return the status the
+/// runtime's exit routine makes of its result.
+pub(super) fn main(entry: &str, returns_int: bool, image: bool) -> Code {
+    let mut code = Code { bytes: Vec::new(), relocations: Vec::new() };
+    let bytes = &mut code.bytes;
+    bytes.extend_from_slice(&[0x55, 0x48, 0x89, 0xe5]); // push rbp; mov rbp, rsp
+    bytes.push(0xe8); // call init, with argc and argv still in rdi and rsi
+    code.relocations.push(call(bytes.len(), runtime::INIT.to_string()));
+    bytes.extend_from_slice(&[0; 4]);
+    if image {
+        bytes.extend_from_slice(&[0x48, 0x8d, 0x3d]); // lea rdi, [rip + image]
+        code.relocations.push(Relocation {
+            section: SectionKind::Text,
+            offset: bytes.len(),
+            target: RelocationTarget::Symbol(super::image::SYMBOL.to_string()),
+            kind: RelocationKind::Relative32,
+            addend: -4,
+        });
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.push(0xe8); // call register_image
+        code.relocations.push(call(bytes.len(), runtime::REGISTER_IMAGE.to_string()));
+        bytes.extend_from_slice(&[0; 4]);
+    }
+    bytes.push(0xe8); // call entry
+    code.relocations.push(call(bytes.len(), symbol_name(entry)));
+    bytes.extend_from_slice(&[0; 4]);
+    if returns_int {
+        bytes.extend_from_slice(&[0x48, 0x89, 0xc7]); // mov rdi, rax
+    } else {
+        bytes.extend_from_slice(&[0x31, 0xff]); // xor edi, edi
+    }
+    bytes.push(0xe8); // call exit
+    code.relocations.push(call(bytes.len(), runtime::EXIT.to_string()));
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&[0x5d, 0xc3]); // pop rbp; ret
+    code
+}
+
+/// The `Imp` adapter of a compiled method taking `params` words, the
+/// receiver first: load the message's argument words into the registers and
+/// stack slots after the receiver's, call the method, and store its result
+/// in the return buffer if there is one.
+pub(super) fn method(function: &str, params: usize) -> Code {
+    let mut code = Code { bytes: Vec::new(), relocations: Vec::new() };
+    let bytes = &mut code.bytes;
+    bytes.extend_from_slice(&[0x55, 0x48, 0x89, 0xe5]); // push rbp; mov rbp, rsp
+    bytes.extend_from_slice(&[0x51, 0x52]); // push rcx (result); push rdx (arguments)
+    bytes.extend_from_slice(&[0x48, 0x89, 0xd0]); // mov rax, rdx
+    let stack = stack_words(params);
+    if stack % 2 == 1 {
+        bytes.extend_from_slice(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8
+    }
+    // Parameter i is argument word i - 1, the receiver staying in rdi
+    for param in (ARGUMENTS.len()..params).rev() {
+        bytes.extend_from_slice(&[0xff, 0xb0]); // push [rax + disp]
+        bytes.extend_from_slice(&(8 * (param as i32 - 1)).to_le_bytes());
+    }
+    for (param, &reg) in ARGUMENTS.iter().enumerate().take(params).skip(1) {
+        // mov reg, [rax + disp]
+        bytes.extend_from_slice(&[0x48 | ((reg >> 3) << 2), 0x8b, 0x80 | ((reg & 7) << 3)]);
+        bytes.extend_from_slice(&(8 * (param as i32 - 1)).to_le_bytes());
+    }
+    bytes.push(0xe8); // call function
+    code.relocations.push(call(bytes.len(), symbol_name(function)));
+    bytes.extend_from_slice(&[0; 4]);
+    if stack > 0 {
+        bytes.extend_from_slice(&[0x48, 0x81, 0xc4]); // add rsp, size
+        bytes.extend_from_slice(&((8 * stack.next_multiple_of(2)) as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(&[0x48, 0x8b, 0x4d, 0xf8]); // mov rcx, [rbp - 8]
+    bytes.extend_from_slice(&[0x48, 0x85, 0xc9, 0x74, 0x03]); // test rcx, rcx; jz done
+    bytes.extend_from_slice(&[0x48, 0x89, 0x01]); // mov [rcx], rax
+    bytes.extend_from_slice(&[0xc9, 0xc3]); // done: leave; ret
+    code
+}
+
+/// The C entry point of an exported function. C passes floats in the
+/// vector registers and everything else in the general ones, each class
+/// spilling to the stack on its own, where the compiled function takes
+/// every parameter as a word in the general registers. The entry spills
+/// both kinds of register, then loads each parameter from wherever C put
+/// it: `bool`s by their low byte, as C leaves the rest undefined.
+pub(super) fn export(function: &Function) -> Code {
+    /// Frame offset of the spilled general registers, then vector ones
+    const SPILL: i32 = -8;
+    const VECTOR_SPILL: i32 = SPILL - 8 * ARGUMENTS.len() as i32;
+
+    let mut code = Code { bytes: Vec::new(), relocations: Vec::new() };
+    let bytes = &mut code.bytes;
+    bytes.extend_from_slice(&[0x55, 0x48, 0x89, 0xe5]); // push rbp; mov rbp, rsp
+    bytes.extend_from_slice(&[0x48, 0x83, 0xec, 0x70]); // sub rsp, 112
+    for (index, &reg) in ARGUMENTS.iter().enumerate() {
+        // mov [rbp + disp], reg
+        bytes.extend_from_slice(&[0x48 | ((reg >> 3) << 2), 0x89, 0x85 | ((reg & 7) << 3)]);
+        bytes.extend_from_slice(&(SPILL - 8 * index as i32).to_le_bytes());
+    }
+    for xmm in 0..8u8 {
+        bytes.extend_from_slice(&[0x66, 0x0f, 0xd6, 0x85 | (xmm << 3)]); // movq [rbp + disp], xmm
+        bytes.extend_from_slice(&(VECTOR_SPILL - 8 * i32::from(xmm)).to_le_bytes());
+    }
+
+    // Where C put each parameter, as a frame offset
+    let (mut general, mut vector, mut memory) = (0, 0, 0);
+    let sources: Vec<(i32, bool)> = function
+        .params
+        .iter()
+        .map(|&param| {
+            let ty = function.value_type(param);
+            let disp = if *ty == IrType::Float && vector < 8 {
+                vector += 1;
+                VECTOR_SPILL - 8 * (vector - 1)
+            } else if *ty != IrType::Float && general < ARGUMENTS.len() {
+                general += 1;
+                SPILL - 8 * (general as i32 - 1)
+            } else {
+                memory += 1;
+                16 + 8 * (memory - 1)
+            };
+            (disp, *ty == IrType::Bool)
+        })
+        .collect();
+    // Load a parameter into a register, zero-extending a bool
+    let load = |bytes: &mut Vec<u8>, reg: u8, (disp, is_bool): (i32, bool)| {
+        if is_bool {
+            bytes.extend_from_slice(&[0x40 | ((reg >> 3) << 2), 0x0f, 0xb6, 0x85 | ((reg & 7) << 3)]);
+        } else {
+            bytes.extend_from_slice(&[0x48 | ((reg >> 3) << 2), 0x8b, 0x85 | ((reg & 7) << 3)]);
+        }
+        bytes.extend_from_slice(&disp.to_le_bytes());
+    };
+
+    let stack = stack_words(sources.len());
+    if stack % 2 == 1 {
+        bytes.extend_from_slice(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8
+    }
+    for &source in sources.iter().skip(ARGUMENTS.len()).rev() {
+        load(bytes, RAX, source);
+        bytes.push(0x50); // push rax
+    }
+    for (&reg, &source) in ARGUMENTS.iter().zip(&sources) {
+        load(bytes, reg, source);
+    }
+    bytes.push(0xe8); // call function
+    code.relocations.push(call(bytes.len(), symbol_name(&function.name)));
+    bytes.extend_from_slice(&[0; 4]);
+    if function.return_type == IrType::Float {
+        bytes.extend_from_slice(&[0x66, 0x48, 0x0f, 0x6e, 0xc0]); // movq xmm0, rax
+    }
+    bytes.extend_from_slice(&[0xc9, 0xc3]); // leave; ret
+    code
+}
+
+fn call(offset: usize, callee: String) -> Relocation {
+    Relocation {
+        section: SectionKind::Text,
+        offset,
+        target: RelocationTarget::Symbol(callee),
+        kind: RelocationKind::Call32,
+        addend: -4,
+    }
+}
+
+struct Assembler<'a> {
+    function: &'a Function,
+    context: Context<'a>,
+    code: Code,
+    /// Frame offset of the first scratch word
+    scratch_base: i32,
+    /// Offset of each block's code
+    labels: Vec<Option<usize>>,
+    /// Jump displacements to patch with a block's offset
+    fixups: Vec<(usize, BlockId)>,
+}
+
+impl Assembler<'_> {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.bytes.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, word: u32) {
+        self.emit(&word.to_le_bytes());
+    }
+
+    /// Frame offset of a value's slot.
+    fn slot(value: ValueId) -> i32 {
+        -8 * (value.0 as i32 + 1)
+    }
+
+    /// Frame offset of a scratch word.
+    fn scratch(&self, index: usize) -> i32 {
+        self.scratch_base + 8 * index as i32
+    }
+
+    fn kind(&self, value: ValueId) -> Option<ScalarKind> {
+        ScalarKind::of(self.function.value_type(value))
+    }
+
+    /// Emit `op reg, [rbp + disp]` for a 64-bit operation.
+    fn frame(&mut self, opcode: u8, reg: u8, disp: i32) {
+        self.emit(&[0x48 | ((reg >> 3) << 2), opcode, 0x85 | ((reg & 7) << 3)]);
+        self.emit(&disp.to_le_bytes());
+    }
+
+    fn load(&mut self, reg: u8, value: ValueId) {
+        self.frame(0x8b, reg, Self::slot(value)); // mov reg, [rbp + slot]
+    }
+
+    fn store(&mut self, value: ValueId, reg: u8) {
+        self.frame(0x89, reg, Self::slot(value)); // mov [rbp + slot], reg
+    }
+
+    fn immediate(&mut self, reg: u8, word: u64) {
+        self.emit(&[0x48 | (reg >> 3), 0xb8 + (reg & 7)]); // mov reg, word
+        self.emit(&word.to_le_bytes());
+    }
+
+    /// Emit `lea reg, [rip + target + addend]`.
+    fn address(&mut self, reg: u8, target: RelocationTarget, addend: i64) {
+        self.emit(&[0x48 | ((reg >> 3) << 2), 0x8d, 0x05 | ((reg & 7) << 3)]);
+        let offset = self.code.bytes.len();
+        self.code.relocations.push(Relocation {
+            section: SectionKind::Text,
+            offset,
+            target,
+            kind: RelocationKind::Relative32,
+            addend: addend - 4,
+        });
+        self.emit_u32(0);
+    }
+
+    fn string(&mut self, reg: u8, string: &str) {
+        let offset = self.context.object.cstring(string);
+        self.address(reg, RelocationTarget::Section(SectionKind::Rodata), offset as i64);
+    }
+
+    fn call(&mut self, callee: String) {
+        self.emit(&[0xe8]);
+        self.code.relocations.push(call(self.code.bytes.len(), callee));
+        self.emit_u32(0);
+    }
+
+    /// Emit a jump to a block.
+    fn jump(&mut self, target: BlockId) {
+        self.emit(&[0xe9]);
+        self.fixups.push((self.code.bytes.len(), target));
+        self.emit_u32(0);
+    }
+
+    /// Emit a forward `jcc` over code emitted later, returning the field to
+    /// patch once it is.
+    fn skip(&mut self, condition: u8) -> usize {
+        self.emit(&[0x0f, 0x80 | condition]);
+        let field = self.code.bytes.len();
+        self.emit_u32(0);
+        field
+    }
+
+    fn land(&mut self, field: usize) {
+        let rel = (self.code.bytes.len() - field - 4) as u32;
+        self.code.bytes[field..field + 4].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    fn function(&mut self) {
+        let frame = (-self.scratch_base + 15) & !15;
+        self.emit(&[0x55, 0x48, 0x89, 0xe5]); // push rbp; mov rbp, rsp
+        self.emit(&[0x48, 0x81, 0xec]); // sub rsp, frame
+        self.emit_u32(frame as u32);
+        for (index, &param) in self.function.params.iter().enumerate() {
+            match ARGUMENTS.get(index) {
+                Some(&reg) => self.store(param, reg),
+                None => {
+                    // Above the return address, in order
+                    self.frame(0x8b, RAX, 16 + 8 * (index - ARGUMENTS.len()) as i32);
+                    self.store(param, RAX);
+                }
+            }
+        }
+        for block in &self.function.blocks {
+            self.labels[block.id.0 as usize] = Some(self.code.bytes.len());
+            for inst in &block.insts {
+                self.instruction(inst.result, &inst.inst);
+            }
+            self.terminator(block.id, &block.terminator);
+        }
+        for (field, target) in std::mem::take(&mut self.fixups) {
+            let label = self.labels[target.0 as usize].expect("jump to a block of the function");
+            let rel = label as i64 - (field + 4) as i64;
+            self.code.bytes[field..field + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+    }
+
+    fn instruction(&mut self, result: ValueId, inst: &Inst) {
+        match inst {
+            Inst::Const(constant) => match constant {
+                Constant::Unit | Constant::Nil => self.constant(result, 0),
+                Constant::Bool(value) => self.constant(result, u64::from(*value)),
+                Constant::Int(value) => self.constant(result, *value as u64),
+                Constant::Float(value) => self.constant(result, value.to_bits()),
+                Constant::String(value) => {
+                    self.string(RDI, value);
+                    self.immediate(RSI, value.len() as u64);
+                    self.call(runtime::STRING.to_string());
+                    self.store(result, RAX);
+                }
+            },
+            Inst::Global(name) if self.context.functions.contains(name.as_str()) => {
+                self.address(RAX, RelocationTarget::Symbol(symbol_name(name)), 0);
+                self.store(result, RAX);
+            }
+            Inst::Global(name) => self.runtime(result, runtime::GLOBAL, &[], &[Operand::String(name)]),
+            Inst::Binary { op, lhs, rhs } => self.binary(result, *op, *lhs, *rhs),
+            Inst::Unary { op, operand } => self.unary(result, *op, *operand),
+            Inst::Call { callee, args } => {
+                self.arguments(args);
+                self.call(symbol_name(callee));
+                self.pop_arguments(args.len());
+                self.store(result, RAX);
+            }
+            Inst::CallIndirect { callee, args } => {
+                self.arguments(args);
+                self.load(RAX, *callee);
+                self.emit(&[0xff, 0xd0]); // call rax
+                self.pop_arguments(args.len());
+                self.store(result, RAX);
+            }
+            Inst::Send { receiver, selector, args } => {
+                let operands = [
+                    Operand::Scratch(0),
+                    Operand::String(selector),
+                    Operand::ScratchAddress(1),
+                    Operand::Immediate(args.len() as u64),
+                ];
+                let values: Vec<ValueId> = std::iter::once(*receiver).chain(args.iter().copied()).collect();
+                self.runtime(result, runtime::SEND, &values, &operands);
+            }
+            Inst::Alloc { class } => self.runtime(result, runtime::ALLOC, &[], &[Operand::String(class)]),
+            Inst::GetField { object, field } => {
+                self.runtime(result, runtime::GET_FIELD, &[*object], &[Operand::Scratch(0), Operand::String(field)]);
+            }
+            Inst::SetField { object, field, value } => {
+                let operands = [Operand::Scratch(0), Operand::String(field), Operand::Scratch(1)];
+                self.runtime(result, runtime::SET_FIELD, &[*object, *value], &operands);
+            }
+            Inst::Variant { enum_name, variant, payload, .. } => {
+                let operands = [
+                    Operand::String(enum_name),
+                    Operand::String(variant),
+                    Operand::ScratchAddress(0),
+                    Operand::Immediate(u64::from(payload.is_some())),
+                ];
+                let payload: Vec<ValueId> = payload.iter().copied().collect();
+                self.runtime(result, runtime::VARIANT, &payload, &operands);
+            }
+            Inst::Tuple(values) | Inst::Array(values) | Inst::Concat(values) => {
+                let routine = match inst {
+                    Inst::Tuple(_) => runtime::TUPLE,
+                    Inst::Array(_) => runtime::ARRAY,
+                    _ => runtime::CONCAT,
+                };
+                let operands = [Operand::ScratchAddress(0), Operand::Immediate(values.len() as u64)];
+                self.runtime(result, routine, values, &operands);
+            }
+            Inst::Dict(entries) => {
+                let values: Vec<ValueId> = entries.iter().flat_map(|&(key, value)| [key, value]).collect();
+                let operands = [Operand::ScratchAddress(0), Operand::Immediate(entries.len() as u64)];
+                self.runtime(result, runtime::DICT, &values, &operands);
+            }
+            Inst::GetIndex { collection, index } => {
+                let operands = [Operand::Scratch(0), Operand::Scratch(1)];
+                self.runtime(result, runtime::GET_INDEX, &[*collection, *index], &operands);
+            }
+            Inst::SetIndex { collection, index, value } => {
+                let operands = [Operand::Scratch(0), Operand::Scratch(1), Operand::Scratch(2)];
+                self.runtime(result, runtime::SET_INDEX, &[*collection, *index, *value], &operands);
+            }
+            Inst::Tag(value) => self.runtime(result, runtime::TAG, &[*value], &[Operand::Scratch(0)]),
+            Inst::Payload(value) => self.runtime(result, runtime::PAYLOAD, &[*value], &[Operand::Scratch(0)]),
+            Inst::Length(value) => self.runtime(result, runtime::LENGTH, &[*value], &[Operand::Scratch(0)]),
+            Inst::Intrinsic { intrinsic, args } => self.intrinsic(result, *intrinsic, args),
+            Inst::Slice { collection, start } => {
+                let operands = [Operand::Scratch(0), Operand::Immediate(*start as u64)];
+                self.runtime(result, runtime::SLICE, &[*collection], &operands);
+            }
+            Inst::Closure { .. } | Inst::Box(_) | Inst::Load(_) | Inst::Store { .. } => {
+                unreachable!("modules using closures are rejected before code generation")
+            }
+        }
+    }
+
+    fn constant(&mut self, result: ValueId, word: u64) {
+        self.immediate(RAX, word);
+        self.store(result, RAX);
+    }
+
+    /// Call a runtime routine with `values` boxed into the scratch words,
+    /// and unbox its result.
+    fn runtime(&mut self, result: ValueId, routine: &str, values: &[ValueId], operands: &[Operand<'_>]) {
+        for (index, &value) in values.iter().enumerate() {
+            self.boxed(RAX, value);
+            let disp = self.scratch(index);
+            self.frame(0x89, RAX, disp);
+        }
+        for (operand, &reg) in operands.iter().zip(&ARGUMENTS) {
+            match *operand {
+                Operand::Immediate(word) => self.immediate(reg, word),
+                Operand::String(string) => self.string(reg, string),
+                Operand::Scratch(index) => {
+                    let disp = self.scratch(index);
+                    self.frame(0x8b, reg, disp);
+                }
+                Operand::ScratchAddress(index) => {
+                    let disp = self.scratch(index);
+                    self.frame(0x8d, reg, disp);
+                }
+            }
+        }
+        self.call(routine.to_string());
+        self.unboxed(result);
+    }
+
+    /// Load a value as an object word into `reg`, boxing scalars.
+    fn boxed(&mut self, reg: u8, value: ValueId) {
+        match self.kind(value) {
+            Some(kind) => {
+                self.immediate(RDI, kind as u64);
+                self.load(RSI, value);
+                self.call(runtime::BOX.to_string());
+                if reg != RAX {
+                    self.emit(&[0x48 | (reg >> 3), 0x89, 0xc0 | (reg & 7)]); // mov reg, rax
+                }
+            }
+            None => self.load(reg, value),
+        }
+    }
+
+    /// Store the object word in `rax` as `result`, unboxing scalars.
+    fn unboxed(&mut self, result: ValueId) {
+        match self.kind(result) {
+            Some(ScalarKind::Unit) => self.constant(result, 0),
+            Some(kind) => {
+                self.immediate(RDI, kind as u64);
+                self.emit(&[0x48, 0x89, 0xc6]); // mov rsi, rax
+                self.call(runtime::UNBOX.to_string());
+                self.store(result, RAX);
+            }
+            None => self.store(result, RAX),
+        }
+    }
+
+    /// Pass the arguments of a direct call: the first in registers, the
+    /// rest pushed in reverse, below padding keeping the stack aligned.
+    fn arguments(&mut self, args: &[ValueId]) {
+        let stack = stack_words(args.len());
+        if stack % 2 == 1 {
+            self.emit(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8
+        }
+        for &arg in args.iter().skip(ARGUMENTS.len()).rev() {
+            self.emit(&[0xff, 0xb5]); // push [rbp + slot]
+            self.emit(&Self::slot(arg).to_le_bytes());
+        }
+        for (&arg, &reg) in args.iter().zip(&ARGUMENTS) {
+            self.load(reg, arg);
+        }
+    }
+
+    fn pop_arguments(&mut self, count: usize) {
+        let stack = stack_words(count);
+        if stack > 0 {
+            self.emit(&[0x48, 0x81, 0xc4]); // add rsp, size
+            self.emit_u32((8 * stack.next_multiple_of(2)) as u32);
+        }
+    }
+
+    fn binary(&mut self, result: ValueId, op: BinaryOp, lhs: ValueId, rhs: ValueId) {
+        let operands = (self.function.value_type(lhs), self.function.value_type(rhs));
+        match operands {
+            (IrType::Int | IrType::Bool, IrType::Int | IrType::Bool) if !matches!(op, BinaryOp::And | BinaryOp::Or) => {
+                self.load(RAX, lhs);
+                self.load(RCX, rhs);
+                match op {
+                    BinaryOp::Add => self.emit(&[0x48, 0x01, 0xc8]), // add rax, rcx
+                    BinaryOp::Sub => self.emit(&[0x48, 0x29, 0xc8]), // sub rax, rcx
+                    BinaryOp::Mul => self.emit(&[0x48, 0x0f, 0xaf, 0xc1]), // imul rax, rcx
+                    BinaryOp::Div | BinaryOp::Mod => {
+                        self.emit(&[0x48, 0x85, 0xc9]); // test rcx, rcx
+                        let nonzero = self.skip(NOT_EQUAL);
+                        self.call(runtime::DIVIDE_BY_ZERO.to_string());
+                        self.land(nonzero);
+                        self.emit(&[0x48, 0x99]); // cqo
+                        self.emit(&[0x48, 0xf7, 0xf9]); // idiv rcx
+                        if op == BinaryOp::Mod {
+                            self.emit(&[0x48, 0x89, 0xd0]); // mov rax, rdx
+                        }
+                    }
+                    _ => {
+                        let condition = match op {
+                            BinaryOp::Eq => EQUAL,
+                            BinaryOp::Neq => NOT_EQUAL,
+                            BinaryOp::Lt => LESS,
+                            BinaryOp::Gt => GREATER,
+                            BinaryOp::Lte => LESS_OR_EQUAL,
+                            _ => GREATER_OR_EQUAL,
+                        };
+                        self.emit(&[0x48, 0x39, 0xc8]); // cmp rax, rcx
+                        self.set(condition);
+                    }
+                }
+                self.store(result, RAX);
+            }
+            (IrType::Float, IrType::Float) if op != BinaryOp::Mod => {
+                // Comparisons swap operands where needed so that unordered
+                // operands compare false
+                let swapped = matches!(op, BinaryOp::Lt | BinaryOp::Lte);
+                let (first, second) = if swapped { (rhs, lhs) } else { (lhs, rhs) };
+                self.load(RAX, first);
+                self.load(RCX, second);
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc0]); // movq xmm0, rax
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc9]); // movq xmm1, rcx
+                let arithmetic = match op {
+                    BinaryOp::Add => Some(0x58),
+                    BinaryOp::Sub => Some(0x5c),
+                    BinaryOp::Mul => Some(0x59),
+                    BinaryOp::Div => Some(0x5e),
+                    _ => None,
+                };
+                if let Some(opcode) = arithmetic {
+                    self.emit(&[0xf2, 0x0f, opcode, 0xc1]); // op xmm0, xmm1
+                    self.emit(&[0x66, 0x48, 0x0f, 0x7e, 0xc0]); // movq rax, xmm0
+                } else {
+                    self.emit(&[0x66, 0x0f, 0x2e, 0xc1]); // ucomisd xmm0, xmm1
+                    match op {
+                        BinaryOp::Eq | BinaryOp::Neq => {
+                            let (equal, ordered, combine) =
+                                if op == BinaryOp::Eq { (EQUAL, NO_PARITY, 0x21) } else { (NOT_EQUAL, PARITY, 0x09) };
+                            self.emit(&[0x0f, 0x90 | equal, 0xc0]); // setcc al
+                            self.emit(&[0x0f, 0x90 | ordered, 0xc1]); // setcc cl
+                            self.emit(&[combine, 0xc8]); // and/or eax, ecx
+                            self.emit(&[0x0f, 0xb6, 0xc0]); // movzx eax, al
+                        }
+                        // Above and above or equal, with the operands swapped
+                        // for less than and less or equal
+                        BinaryOp::Gt | BinaryOp::Lt => self.set(ABOVE),
+                        _ => self.set(ABOVE_OR_EQUAL),
+                    }
+                }
+                self.store(result, RAX);
+            }
+            _ => {
+                let operands = [Operand::Immediate(op as u64), Operand::Scratch(0), Operand::Scratch(1)];
+                self.runtime(result, runtime::BINARY, &[lhs, rhs], &operands);
+            }
+        }
+    }
+
+    /// Set `rax` to 1 if a condition holds, else to 0.
+    fn set(&mut self, condition: u8) {
+        self.emit(&[0x0f, 0x90 | condition, 0xc0]); // setcc al
+        self.emit(&[0x0f, 0xb6, 0xc0]); // movzx eax, al
+    }
+
+    fn unary(&mut self, result: ValueId, op: UnaryOp, operand: ValueId) {
+        match (op, self.function.value_type(operand)) {
+            (UnaryOp::Minus, IrType::Int) => {
+                self.load(RAX, operand);
+                self.emit(&[0x48, 0xf7, 0xd8]); // neg rax
+            }
+            (UnaryOp::Minus, IrType::Float) => {
+                self.load(RAX, operand);
+                self.immediate(RCX, 1 << 63);
+                self.emit(&[0x48, 0x31, 0xc8]); // xor rax, rcx
+            }
+            (UnaryOp::Negate, IrType::Bool) => {
+                self.load(RAX, operand);
+                self.emit(&[0x48, 0x83, 0xf0, 0x01]); // xor rax, 1
+            }
+            _ => {
+                let operands = [Operand::Immediate(op as u64), Operand::Scratch(0)];
+                return self.runtime(result, runtime::UNARY, &[operand], &operands);
+            }
+        }
+        self.store(result, RAX);
+    }
+
+    /// Compute an intrinsic inline. Lengths count through the runtime.
+    fn intrinsic(&mut self, result: ValueId, intrinsic: Intrinsic, args: &[ValueId]) {
+        let float = *self.function.value_type(args[0]) == IrType::Float;
+        match intrinsic {
+            Intrinsic::Length => return self.runtime(result, runtime::LENGTH, args, &[Operand::Scratch(0)]),
+            Intrinsic::Abs if float => {
+                self.load(RAX, args[0]);
+                self.immediate(RCX, !(1 << 63));
+                self.emit(&[0x48, 0x21, 0xc8]); // and rax, rcx
+            }
+            Intrinsic::Abs => {
+                self.load(RAX, args[0]);
+                self.emit(&[0x48, 0x89, 0xc1]); // mov rcx, rax
+                self.emit(&[0x48, 0xf7, 0xd8]); // neg rax
+                self.emit(&[0x48, 0x0f, 0x48, 0xc1]); // cmovs rax, rcx
+            }
+            Intrinsic::Min | Intrinsic::Max if float => {
+                self.load(RAX, args[0]);
+                self.load(RCX, args[1]);
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc0]); // movq xmm0, rax
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc9]); // movq xmm1, rcx
+                let opcode = if intrinsic == Intrinsic::Min { 0x5d } else { 0x5f };
+                self.emit(&[0xf2, 0x0f, opcode, 0xc1]); // minsd/maxsd xmm0, xmm1
+                self.emit(&[0x66, 0x48, 0x0f, 0x7e, 0xc0]); // movq rax, xmm0
+            }
+            Intrinsic::Min | Intrinsic::Max => {
+                self.load(RAX, args[0]);
+                self.load(RCX, args[1]);
+                self.emit(&[0x48, 0x39, 0xc8]); // cmp rax, rcx
+                let condition = if intrinsic == Intrinsic::Min { GREATER } else { LESS };
+                self.emit(&[0x48, 0x0f, 0x40 | condition, 0xc1]); // cmovcc rax, rcx
+            }
+            Intrinsic::Sqrt => {
+                self.load(RAX, args[0]);
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc0]); // movq xmm0, rax
+                self.emit(&[0xf2, 0x0f, 0x51, 0xc0]); // sqrtsd xmm0, xmm0
+                self.emit(&[0x66, 0x48, 0x0f, 0x7e, 0xc0]); // movq rax, xmm0
+            }
+        }
+        self.store(result, RAX);
+    }
+
+    fn terminator(&mut self, block: BlockId, terminator: &Terminator) {
+        match terminator {
+            Terminator::Return(value) => {
+                match value {
+                    Some(value) => self.load(RAX, *value),
+                    None => self.emit(&[0x31, 0xc0]), // xor eax, eax
+                }
+                self.emit(&[0xc9, 0xc3]); // leave; ret
+            }
+            Terminator::Jump(target) => self.edge(block, *target),
+            Terminator::Branch { cond, then_block, else_block } => {
+                self.load(RAX, *cond);
+                self.emit(&[0x48, 0x85, 0xc0]); // test rax, rax
+                let otherwise = self.skip(EQUAL);
+                self.edge(block, *then_block);
+                self.land(otherwise);
+                self.edge(block, *else_block);
+            }
+            Terminator::Switch { value, cases, default } => {
+                self.load(RAX, *value);
+                for &(case, target) in cases {
+                    self.immediate(RCX, case as u64);
+                    self.emit(&[0x48, 0x39, 0xc8]); // cmp rax, rcx
+                    let next = self.skip(NOT_EQUAL);
+                    self.edge(block, target);
+                    self.land(next);
+                }
+                self.edge(block, *default);
+            }
+            // Errors abort native code, so nothing enters a cleanup's handler
+            Terminator::Unreachable | Terminator::Resume => {
+                self.call(runtime::UNREACHABLE.to_string());
+                self.emit(&[0x0f, 0x0b]); // ud2
+            }
+        }
+    }
+
+    /// Jump from one block to another, assigning the target's phis.
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        let phis = &self.function.block(to).phis;
+        let moves: Vec<(ValueId, ValueId)> = phis
+            .iter()
+            .filter_map(|phi| {
+                let (_, value) = phi.incoming.iter().find(|(pred, _)| *pred == from)?;
+                Some((phi.result, *value))
+            })
+            .collect();
+        for &(_, value) in &moves {
+            self.emit(&[0xff, 0xb5]); // push [rbp + slot]
+            self.emit(&Self::slot(value).to_le_bytes());
+        }
+        for &(phi, _) in moves.iter().rev() {
+            self.emit(&[0x8f, 0x85]); // pop [rbp + slot]
+            self.emit(&Self::slot(phi).to_le_bytes());
+        }
+        self.jump(to);
+    }
+}
+
+/// Number of arguments of a call passed on the stack.
+fn stack_words(count: usize) -> usize {
+    count.saturating_sub(ARGUMENTS.len())
+}
@@ -6,12 +6,28 @@
 //! - Native code generation
 //! - Linker integration
 //!
-//! **Phase:** 10 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 10 - AOT
+//! **Status:** In Progress
 
 #![warn(missing_docs)]
 
+// Call graph, class hierarchy and reachability of a whole program
+pub mod analyze;
+
+// Machine code and object files
+pub mod backend;
+
+// Profile-guided ordering, inlining and cache layout
+pub mod pgo;
+
+// Removal of code and metadata a whole program does not need
+pub mod strip;
+
 // Module declarations will be added during Phase 10 implementation:
-// pub mod analyze;
-// pub mod backend;
 // pub mod link;
+
+// Re-exports for convenience
+pub use analyze::{Analysis, CallGraph, ClassHierarchy, analyze};
+pub use backend::{Backend, BackendError, Object, Target, c_header};
+pub use pgo::{ProfileData, ProfileError};
+pub use strip::{Size, StripReport, strip};
@@ -0,0 +1,245 @@
+//! Profile-guided optimization.
+//!
+//! A profile the JIT's [profiler](oxidex_jit::profile) printed while the
+//! program ran in the VM tells an AOT build where the program spends its
+//! time. Functions are matched by name, so the profile must come from the
+//! same program. A [`ProfileData`] read from it:
+//!
+//! - [orders](ProfileData::order) a module's functions hottest first, so
+//!   the code that runs most is packed together;
+//! - [biases inlining](ProfileData::inline_config) toward the functions it
+//!   finds hot;
+//! - lays out the inline cache of each send a function made, the classes
+//!   of its receivers most frequent first, so a cache can be
+//!   [seeded](ProfileData::cache_layouts) before the program runs.
+
+use oxidex_codegen::ir::Module;
+use oxidex_codegen::optimize::InlineConfig;
+use oxidex_jit::profile::{Hotness, PROFILE_HEADER};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Most receiver classes a seeded cache holds, as many as a polymorphic
+/// inline cache remembers.
+pub const CACHE_ENTRIES: usize = 4;
+
+/// Why a profile could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileError {
+    /// The text does not start with the profile header.
+    MissingHeader,
+    /// A line is not a function or send record.
+    Malformed {
+        /// Line number, from 1
+        line: usize,
+        /// The line
+        text: String,
+    },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "profile does not start with '{PROFILE_HEADER}'"),
+            Self::Malformed { line, text } => write!(f, "malformed profile line {line}: '{text}'"),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// The counts of one profiled function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCounts {
+    /// How hot the profiler found it
+    pub hotness: Hotness,
+    /// Calls to it
+    pub calls: u64,
+    /// Iterations of its loops
+    pub iterations: u64,
+    /// Receiver classes of each selector it sent, most messages first
+    pub sends: BTreeMap<String, Vec<(String, u64)>>,
+}
+
+impl FunctionCounts {
+    /// How often the function ran code, for ordering.
+    #[must_use]
+    pub fn weight(&self) -> u64 {
+        self.calls.saturating_add(self.iterations)
+    }
+}
+
+/// The starting layout of the inline cache of a send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheLayout {
+    /// Function sending the message
+    pub function: String,
+    /// Selector of the message
+    pub selector: String,
+    /// Receiver classes, most frequent first
+    pub classes: Vec<String>,
+}
+
+/// A profile read back for an AOT build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileData {
+    /// Counts of each profiled function, by name
+    functions: BTreeMap<String, FunctionCounts>,
+}
+
+impl ProfileData {
+    /// Read a printed profile snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is missing or a line is malformed.
+    pub fn parse(text: &str) -> Result<Self, ProfileError> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        if lines.next().map(|(_, line)| line.trim()) != Some(PROFILE_HEADER) {
+            return Err(ProfileError::MissingHeader);
+        }
+        let mut profile = Self::default();
+        for (index, line) in lines {
+            let malformed = || ProfileError::Malformed { line: index + 1, text: line.to_string() };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                ["fn", name, hotness, calls, iterations] => {
+                    let hotness = match hotness {
+                        "cold" => Hotness::Cold,
+                        "warm" => Hotness::Warm,
+                        "hot" => Hotness::Hot,
+                        _ => return Err(malformed()),
+                    };
+                    let counts = FunctionCounts {
+                        hotness,
+                        calls: calls.parse().map_err(|_| malformed())?,
+                        iterations: iterations.parse().map_err(|_| malformed())?,
+                        sends: BTreeMap::new(),
+                    };
+                    profile.functions.insert(name.to_string(), counts);
+                }
+                ["send", function, selector, class, count] => {
+                    let count = count.parse().map_err(|_| malformed())?;
+                    let counts = profile.functions.get_mut(function).ok_or_else(malformed)?;
+                    let receivers = counts.sends.entry(selector.to_string()).or_default();
+                    receivers.push((class.to_string(), count));
+                    receivers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(profile)
+    }
+
+    /// The counts of a function, if it was profiled.
+    #[must_use]
+    pub fn function(&self, name: &str) -> Option<&FunctionCounts> {
+        self.functions.get(name)
+    }
+
+    /// Order a module's functions hotter first, then by how often they ran,
+    /// keeping unprofiled functions last in their original order.
+    pub fn order(&self, module: &mut Module) {
+        module.functions.sort_by_key(|function| {
+            let counts = self.functions.get(&function.name);
+            std::cmp::Reverse(counts.map(|counts| (counts.hotness, counts.weight())))
+        });
+    }
+
+    /// `base` with the functions the profile finds hot added to those
+    /// inlined at a larger size.
+    #[must_use]
+    pub fn inline_config(&self, base: InlineConfig) -> InlineConfig {
+        let mut config = base;
+        let hot = self.functions.iter().filter(|(_, counts)| counts.hotness == Hotness::Hot);
+        config.hot.extend(hot.map(|(name, _)| name.clone()));
+        config
+    }
+
+    /// The layout of the inline cache of each send, by function and
+    /// selector, holding at most [`CACHE_ENTRIES`] classes.
+    #[must_use]
+    pub fn cache_layouts(&self) -> Vec<CacheLayout> {
+        self.functions
+            .iter()
+            .flat_map(|(function, counts)| {
+                counts.sends.iter().map(move |(selector, receivers)| CacheLayout {
+                    function: function.clone(),
+                    selector: selector.clone(),
+                    classes: receivers.iter().take(CACHE_ENTRIES).map(|(class, _)| class.clone()).collect(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for ProfileData {
+    type Err = ProfileError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_codegen::ir::parse_module;
+
+    const PROFILE: &str = "oxidex-profile 1
+fn main warm 1 2000
+send main area Square 1500
+send main area Circle 500
+fn Square.area hot 1500 0
+fn Circle.area cold 500 0
+";
+
+    #[test]
+    fn test_profile_orders_functions_and_biases_inlining() {
+        let profile: ProfileData = PROFILE.parse().unwrap();
+        let mut module = parse_module(
+            r#"
+fn "helper"() -> unit {
+bb0:
+    return
+}
+
+fn "Circle.area"() -> unit {
+bb0:
+    return
+}
+
+fn "main"() -> unit {
+bb0:
+    return
+}
+
+fn "Square.area"() -> unit {
+bb0:
+    return
+}
+"#,
+        )
+        .unwrap();
+        profile.order(&mut module);
+        let names: Vec<&str> = module.functions.iter().map(|function| function.name.as_str()).collect();
+        assert_eq!(names, ["Square.area", "main", "Circle.area", "helper"]);
+
+        let config = profile.inline_config(InlineConfig::default());
+        assert_eq!(config.hot.iter().collect::<Vec<_>>(), ["Square.area"]);
+        let layouts = profile.cache_layouts();
+        assert_eq!(layouts.len(), 1);
+        assert_eq!((layouts[0].function.as_str(), layouts[0].selector.as_str()), ("main", "area"));
+        assert_eq!(layouts[0].classes, ["Square", "Circle"]);
+    }
+
+    #[test]
+    fn test_malformed_profiles_are_rejected() {
+        assert_eq!(ProfileData::parse("fn main hot 1 0"), Err(ProfileError::MissingHeader));
+        let unknown = format!("{PROFILE_HEADER}\nsend main area Square 1\n");
+        let err = ProfileData::parse(&unknown).unwrap_err();
+        assert_eq!(err, ProfileError::Malformed { line: 2, text: "send main area Square 1".to_string() });
+        assert!(ProfileData::parse(&format!("{PROFILE_HEADER}\nfn main tepid 1 0")).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Link-time stripping.
+//!
+//! Once [analysis](crate::analyze) knows which functions a program can run,
+//! whatever only the others need can go before code is generated:
+//!
+//! - functions no root reaches, and the method entries they implement;
+//! - selectors neither a remaining method, a protocol requirement, nor a
+//!   remaining send refers to;
+//! - strings no remaining metadata or code refers to.
+//!
+//! Classes, their instance variables and the protocols they adopt are kept,
+//! since the runtime can look any of them up by name. The stripped tables
+//! are rebuilt in the order [`emit_tables`](oxidex_codegen::emit_tables)
+//! assigns indices, so stripping an already stripped program changes
+//! nothing. A [`StripReport`] compares the program's size before and after.
+
+use crate::analyze::Analysis;
+use oxidex_codegen::MetadataTables;
+use oxidex_codegen::emit::{
+    ClassDescriptor, IvarEntry, MethodEntry, ProtocolDescriptor, RequirementEntry, StringId, SuperclassRef,
+};
+use oxidex_codegen::ir::{Constant, Inst, Module};
+use oxidec::get_global_arena;
+use std::collections::HashSet;
+use std::fmt;
+
+/// How much code and metadata a program has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Size {
+    /// Functions and methods with code
+    pub functions: usize,
+    /// Instructions of all functions
+    pub instructions: usize,
+    /// Method entries of all classes
+    pub methods: usize,
+    /// Selectors
+    pub selectors: usize,
+    /// Pooled strings
+    pub strings: usize,
+    /// Bytes of the pooled strings
+    pub string_bytes: usize,
+}
+
+impl Size {
+    /// Measure a program.
+    #[must_use]
+    pub fn measure(module: &Module, tables: &MetadataTables) -> Self {
+        Self {
+            functions: module.functions.len(),
+            instructions: module.functions.iter().flat_map(|function| &function.blocks).map(|b| b.insts.len()).sum(),
+            methods: tables.methods.len(),
+            selectors: tables.selectors.len(),
+            strings: tables.strings.len(),
+            string_bytes: tables.strings.iter().map(|(_, string)| string.len()).sum(),
+        }
+    }
+
+    fn rows(self) -> [(&'static str, usize); 6] {
+        [
+            ("functions", self.functions),
+            ("instructions", self.instructions),
+            ("methods", self.methods),
+            ("selectors", self.selectors),
+            ("strings", self.strings),
+            ("string bytes", self.string_bytes),
+        ]
+    }
+}
+
+/// The size of a program before and after stripping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripReport {
+    /// Size as built
+    pub before: Size,
+    /// Size once stripped
+    pub after: Size,
+}
+
+impl fmt::Display for StripReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<14}{:>10}{:>10}{:>10}", "", "before", "after", "removed")?;
+        for ((name, before), (_, after)) in self.before.rows().into_iter().zip(self.after.rows()) {
+            writeln!(f, "{name:<14}{before:>10}{after:>10}{:>10}", before.saturating_sub(after))?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove what no reachable function needs from a program and its
+/// metadata tables.
+///
+/// # Panics
+///
+/// Panics if a selector of the tables no longer interns with the runtime,
+/// which a selector it interned before cannot fail to do.
+pub fn strip(module: &mut Module, tables: &mut MetadataTables, analysis: &Analysis) -> StripReport {
+    let before = Size::measure(module, tables);
+    // Functions the analysis did not see, such as those of other modules,
+    // are not known to be dead
+    let dead: HashSet<&str> = analysis.dead().into_iter().collect();
+    module.functions.retain(|function| !dead.contains(function.name.as_str()));
+
+    let old = std::mem::take(tables);
+    let arena = get_global_arena();
+    let string = |tables: &mut MetadataTables, id: StringId| {
+        let bytes = old.strings.resolve(id).as_bytes();
+        tables.strings.intern(&String::from_utf8_lossy(bytes))
+    };
+    let selector = |tables: &mut MetadataTables, id| {
+        let name = old.selectors.resolve(id).name();
+        tables.selectors.intern(name).expect("selector was interned before")
+    };
+
+    for protocol in &old.protocols {
+        let name = string(tables, protocol.name);
+        let start = tables.requirements.len() as u32;
+        for requirement in &old.requirements[protocol.requirements.start as usize..protocol.requirements.end as usize] {
+            let entry = RequirementEntry {
+                selector: selector(tables, requirement.selector),
+                types: string(tables, requirement.types),
+            };
+            tables.requirements.push(entry);
+        }
+        let descriptor = ProtocolDescriptor { name, requirements: start..tables.requirements.len() as u32 };
+        tables.protocols.push(arena.alloc(descriptor));
+    }
+
+    for class in &old.classes {
+        let name = string(tables, class.name);
+        let superclass = class.superclass.map(|superclass| match superclass {
+            SuperclassRef::Local(id) => SuperclassRef::Local(id),
+            SuperclassRef::External(name) => SuperclassRef::External(string(tables, name)),
+        });
+
+        let ivars_start = tables.ivars.len() as u32;
+        for ivar in &old.ivars[class.ivars.start as usize..class.ivars.end as usize] {
+            let entry = IvarEntry { name: string(tables, ivar.name), types: string(tables, ivar.types) };
+            tables.ivars.push(entry);
+        }
+
+        let methods_start = tables.methods.len() as u32;
+        for method in &old.methods[class.methods.start as usize..class.methods.end as usize] {
+            let function = String::from_utf8_lossy(old.strings.resolve(method.function).as_bytes()).into_owned();
+            if dead.contains(function.as_str()) {
+                continue;
+            }
+            let entry = MethodEntry {
+                selector: selector(tables, method.selector),
+                types: string(tables, method.types),
+                function: tables.strings.intern(&function),
+                is_static: method.is_static,
+            };
+            tables.methods.push(entry);
+        }
+
+        let protocols_start = tables.adopted.len() as u32;
+        for &protocol in &old.adopted[class.protocols.start as usize..class.protocols.end as usize] {
+            let protocol = string(tables, protocol);
+            tables.adopted.push(protocol);
+        }
+
+        let descriptor = ClassDescriptor {
+            name,
+            superclass,
+            ivars: ivars_start..tables.ivars.len() as u32,
+            methods: methods_start..tables.methods.len() as u32,
+            protocols: protocols_start..tables.adopted.len() as u32,
+        };
+        tables.classes.push(arena.alloc(descriptor));
+    }
+
+    for function in &module.functions {
+        for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+            match &inst.inst {
+                Inst::Const(Constant::String(s)) => {
+                    tables.strings.intern(s);
+                }
+                Inst::Send { selector, .. } => {
+                    tables.selectors.intern(selector).expect("selector was interned before");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    StripReport { before, after: Size::measure(module, tables) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze::{ClassHierarchy, analyze_with};
+    use oxidex_codegen::emit::ClassId;
+    use oxidex_codegen::ir::parse_module;
+    use oxidex_codegen::lowering::TypeKind;
+
+    fn program() -> (Module, MetadataTables) {
+        let module = parse_module(
+            r#"
+fn "main"(%0: object("Shape")) -> int {
+bb0:
+    %1: string = const string "kept"
+    %2: int = send %0, "area"()
+    return %2
+}
+
+fn "unused"() -> int {
+bb0:
+    %0: string = const string "dropped"
+    %1: int = length %0
+    return %1
+}
+
+fn "Shape.area"(%0: object("Shape")) -> int {
+bb0:
+    %1: int = const int 1
+    return %1
+}
+
+fn "Shape.describe"(%0: object("Shape")) -> int {
+bb0:
+    %1: int = call "unused"()
+    return %1
+}
+"#,
+        )
+        .unwrap();
+
+        let mut tables = MetadataTables::default();
+        let arena = get_global_arena();
+        let name = tables.strings.intern("Shape");
+        for selector in ["area", "describe"] {
+            let entry = MethodEntry {
+                selector: tables.selectors.intern(selector).unwrap(),
+                types: tables.strings.intern("q@:"),
+                function: tables.strings.intern(&format!("Shape.{selector}")),
+                is_static: false,
+            };
+            tables.methods.push(entry);
+        }
+        let requirement = RequirementEntry {
+            selector: tables.selectors.intern("size").unwrap(),
+            types: tables.strings.intern("q@:"),
+        };
+        tables.requirements.push(requirement);
+        let protocol = ProtocolDescriptor { name: tables.strings.intern("Sized"), requirements: 0..1 };
+        tables.protocols.push(arena.alloc(protocol));
+        let class = ClassDescriptor { name, superclass: None, ivars: 0..0, methods: 0..2, protocols: 0..0 };
+        tables.classes.push(arena.alloc(class));
+        tables.strings.intern("kept");
+        tables.strings.intern("dropped");
+        (module, tables)
+    }
+
+    #[test]
+    fn test_strip_removes_unreachable_code_and_metadata() {
+        let (mut module, mut tables) = program();
+        let mut hierarchy = ClassHierarchy::new();
+        hierarchy.add_class("Shape", TypeKind::Class, None, ["area", "describe"]);
+        let analysis = analyze_with(hierarchy, &[&module], &["main"]);
+
+        let report = strip(&mut module, &mut tables, &analysis);
+        let names: Vec<&str> = module.functions.iter().map(|function| function.name.as_str()).collect();
+        assert_eq!(names, ["main", "Shape.area"]);
+        let methods = tables.class_methods(ClassId(0));
+        assert_eq!(methods.len(), 1);
+        assert_eq!(tables.selectors.resolve(methods[0].selector).name(), "area");
+        // The protocol's requirement keeps its selector
+        assert!(tables.selectors.get("size").is_some() && tables.selectors.get("describe").is_none());
+        assert!(tables.strings.get("kept").is_some());
+        assert!(tables.strings.get("dropped").is_none() && tables.strings.get("Shape.describe").is_none());
+
+        assert_eq!((report.before.functions, report.after.functions), (4, 2));
+        assert_eq!((report.before.selectors, report.after.selectors), (3, 2));
+        assert_eq!((report.before.strings, report.after.strings), (7, 5));
+        assert!(report.to_string().lines().any(|line| line.split_whitespace().eq(["methods", "2", "1", "1"])));
+
+        // Nothing else is unreachable once stripped
+        let analysis = analyze_with(analysis.hierarchy, &[&module], &["main"]);
+        assert_eq!(strip(&mut module, &mut tables, &analysis).before, report.after);
+    }
+}
@@ -0,0 +1,118 @@
+//! VM dispatch benchmarks.
+//!
+//! Measures instruction throughput of the bytecode VM on:
+//! - A counting loop (locals, arithmetic and jumps)
+//! - Recursive calls (call frames and returns)
+//!
+//! each in the stack encoding and lowered to the register encoding.
+//!
+//! Compare the match-based loop with threaded dispatch by running
+//! `cargo bench -p oxidex-bytecode` with and without
+//! `--features threaded-dispatch`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use oxidex_bytecode::value::Closure;
+use oxidex_bytecode::{register, Chunk, Constant, Function, OpCode, Value, Vm};
+use oxidex_syntax::Span;
+use std::rc::Rc;
+
+fn span() -> Span {
+    Span::new(0, 0, 1, 1, 1, 1)
+}
+
+fn local(chunk: &mut Chunk, op: OpCode, slot: u8) {
+    chunk.write_op(op, span());
+    chunk.write(slot, span());
+}
+
+/// `var sum = 0; var i = 0; while i < n { i = i + 1; sum = sum + i }; sum`,
+/// which runs 17 instructions per iteration.
+fn counting_loop(n: i64) -> Function {
+    let span = span();
+    let mut chunk = Chunk::new();
+    chunk.write_constant(OpCode::Constant, Constant::Int(0), span).unwrap();
+    chunk.write_constant(OpCode::Constant, Constant::Int(0), span).unwrap();
+    let start = chunk.len();
+    local(&mut chunk, OpCode::GetLocal, 1);
+    chunk.write_constant(OpCode::Constant, Constant::Int(n), span).unwrap();
+    chunk.write_op(OpCode::Less, span);
+    let exit = chunk.write_jump(OpCode::JumpIfFalse, span);
+    local(&mut chunk, OpCode::GetLocal, 1);
+    chunk.write_constant(OpCode::Constant, Constant::Int(1), span).unwrap();
+    chunk.write_op(OpCode::Add, span);
+    local(&mut chunk, OpCode::SetLocal, 1);
+    chunk.write_op(OpCode::Pop, span);
+    local(&mut chunk, OpCode::GetLocal, 0);
+    local(&mut chunk, OpCode::GetLocal, 1);
+    chunk.write_op(OpCode::Add, span);
+    local(&mut chunk, OpCode::SetLocal, 0);
+    chunk.write_op(OpCode::Pop, span);
+    chunk.write_loop(start, span).unwrap();
+    chunk.patch_jump(exit).unwrap();
+    local(&mut chunk, OpCode::GetLocal, 0);
+    chunk.write_op(OpCode::Return, span);
+    Function { name: "loop".to_string(), arity: 0, captures: vec![], chunk }
+}
+
+/// `fn fib(n) { if n < 2 { return n }; fib(n - 1) + fib(n - 2) }`, through
+/// the global `fib`.
+fn fib() -> Function {
+    let span = span();
+    let mut chunk = Chunk::new();
+    local(&mut chunk, OpCode::GetLocal, 0);
+    chunk.write_constant(OpCode::Constant, Constant::Int(2), span).unwrap();
+    chunk.write_op(OpCode::Less, span);
+    let recurse = chunk.write_jump(OpCode::JumpIfFalse, span);
+    local(&mut chunk, OpCode::GetLocal, 0);
+    chunk.write_op(OpCode::Return, span);
+    chunk.patch_jump(recurse).unwrap();
+    for step in [1, 2] {
+        chunk.write_constant(OpCode::GetGlobal, Constant::String(Rc::from("fib")), span).unwrap();
+        local(&mut chunk, OpCode::GetLocal, 0);
+        chunk.write_constant(OpCode::Constant, Constant::Int(step), span).unwrap();
+        chunk.write_op(OpCode::Subtract, span);
+        local(&mut chunk, OpCode::Call, 1);
+    }
+    chunk.write_op(OpCode::Add, span);
+    chunk.write_op(OpCode::Return, span);
+    Function { name: "fib".to_string(), arity: 1, captures: vec![], chunk }
+}
+
+fn bench_loop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm_loop");
+
+    for n in [1_000, 100_000].iter() {
+        let stack = Rc::new(counting_loop(*n));
+        let lowered = Rc::new(register::lower(&stack));
+        // Throughput counts the stack instructions the loop takes
+        group.throughput(Throughput::Elements(*n as u64 * 17));
+        for (encoding, script) in [("stack", stack), ("register", lowered)] {
+            group.bench_with_input(BenchmarkId::new(encoding, n), &script, |b, script| {
+                let mut vm = Vm::new();
+                b.iter(|| black_box(vm.run(Rc::clone(script)).unwrap()));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_calls(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm_calls");
+
+    for (encoding, function) in [("stack", fib()), ("register", register::lower(&fib()))] {
+        let mut vm = Vm::new();
+        let fib = Value::Closure(Rc::new(Closure { function: Rc::new(function), upvalues: vec![] }));
+        vm.define_global("fib", fib.clone());
+        for n in [10, 20].iter() {
+            group.bench_with_input(BenchmarkId::new(format!("fib_{encoding}"), n), n, |b, &n| {
+                b.iter(|| black_box(vm.call(fib.clone(), vec![Value::Int(n)]).unwrap()));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_loop, bench_calls);
+criterion_main!(benches);
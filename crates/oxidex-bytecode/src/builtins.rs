@@ -0,0 +1,127 @@
+//! The standard builtins of bytecode programs.
+//!
+//! Programs compiled to bytecode call the standard builtins the interpreter
+//! gives them, with the same signatures (see
+//! [`oxidex_typecheck::builtins`]). [`declare`] binds those signatures, and
+//! the intrinsic functions', for the type checker; [`install`] defines the
+//! builtins as globals of a VM that runs the program:
+//!
+//! - `print(value)` writes a value's description and a newline to
+//!   standard output
+//! - `assert(condition)` fails unless the condition holds
+//! - `clock()` returns the seconds elapsed since the Unix epoch
+//! - `throw(value)` raises an error carrying the value
+//! - `unwrap(optional)` returns the optional's value, failing if it is
+//!   `nil`
+//! - `read_file(path)` returns the text of a file, and
+//!   `write_file(path, text)` replaces a file's text; both need
+//!   [`Capability::FileIo`], and throw the error of a failed access
+//!
+//! `len`, `abs`, `min`, `max` and `sqrt` are intrinsics, which compile to
+//! an instruction rather than a call. `range` and `catch` are only provided
+//! by the interpreter.
+
+use crate::error::VmErrorKind;
+use crate::value::Value;
+use crate::vm::Vm;
+use oxidec::runtime::sandbox::Capability;
+use oxidex_codegen::intrinsics;
+use oxidex_typecheck::InferContext as Context;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Names of the standard builtins [`install`] defines.
+pub const BUILTINS: [&str; 7] = ["print", "assert", "clock", "throw", "unwrap", "read_file", "write_file"];
+
+/// Bind the signature of every builtin and intrinsic function the program
+/// mentions in the type checker's environment, so calls to them type
+/// check. The program's own declarations of the same names replace them.
+pub fn declare(ctx: &mut Context<'_>) {
+    oxidex_typecheck::builtins::declare(ctx, BUILTINS);
+    intrinsics::declare(ctx);
+}
+
+/// Define the standard builtins as globals of `vm`.
+pub fn install(vm: &mut Vm) {
+    vm.define_native("print", 1, |vm, args| {
+        println!("{}", vm.describe(&args[0])?);
+        Ok(Value::Nil)
+    });
+    vm.define_native("assert", 1, |_, args| match args[0] {
+        Value::Bool(true) => Ok(Value::Nil),
+        Value::Bool(false) => Err(VmErrorKind::AssertionFailed),
+        ref other => Err(VmErrorKind::TypeMismatch { expected: "a boolean", found: other.kind() }),
+    });
+    vm.define_native("clock", 0, |_, _| {
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(Value::Float(elapsed.as_secs_f64()))
+    });
+    vm.define_native("throw", 1, |_, args| Err(VmErrorKind::Thrown(args[0].clone())));
+    vm.define_native("unwrap", 1, |_, args| match &args[0] {
+        Value::Nil => Err(VmErrorKind::NilUnwrap),
+        value => Ok(value.clone()),
+    });
+    vm.define_native_requiring("read_file", Capability::FileIo, 1, |_, args| {
+        let text = std::fs::read_to_string(string(&args[0])?).map_err(|err| io_error(&err))?;
+        Ok(Value::string(&text))
+    });
+    vm.define_native_requiring("write_file", Capability::FileIo, 2, |_, args| {
+        std::fs::write(string(&args[0])?, string(&args[1])?).map_err(|err| io_error(&err))?;
+        Ok(Value::Nil)
+    });
+}
+
+/// The text of a string argument.
+fn string(value: &Value) -> Result<&str, VmErrorKind> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(VmErrorKind::TypeMismatch { expected: "a string", found: other.kind() }),
+    }
+}
+
+/// A failed file access, thrown so that programs can catch it.
+fn io_error(err: &std::io::Error) -> VmErrorKind {
+    VmErrorKind::Thrown(Value::string(&err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::runtime::sandbox::Sandbox;
+
+    #[test]
+    fn test_install_defines_builtins() {
+        let mut vm = Vm::new();
+        install(&mut vm);
+        for name in BUILTINS {
+            assert!(matches!(vm.global(name), Some(Value::Native(_))), "{name}");
+            assert!(oxidex_typecheck::builtins::signature(name).is_some(), "{name}");
+        }
+        let mut call = |name: &str, args: Vec<Value>| {
+            let function = vm.global(name).unwrap();
+            vm.call(function, args).map_err(|err| err.kind)
+        };
+
+        assert!(matches!(call("assert", vec![Value::Bool(true)]), Ok(Value::Nil)));
+        assert!(matches!(call("assert", vec![Value::Bool(false)]), Err(VmErrorKind::AssertionFailed)));
+        assert!(matches!(call("assert", vec![Value::Int(1)]), Err(VmErrorKind::TypeMismatch { .. })));
+        assert!(matches!(call("clock", Vec::new()), Ok(Value::Float(seconds)) if seconds > 0.0));
+        assert!(matches!(call("throw", vec![Value::Int(1)]), Err(VmErrorKind::Thrown(Value::Int(1)))));
+        assert!(matches!(call("unwrap", vec![Value::Int(1)]), Ok(Value::Int(1))));
+        assert!(matches!(call("unwrap", vec![Value::Nil]), Err(VmErrorKind::NilUnwrap)));
+
+        let file = std::env::temp_dir().join(format!("oxidex-bytecode-builtins-{}.txt", std::process::id()));
+        let path = Value::string(&file.to_string_lossy());
+        assert!(matches!(call("write_file", vec![path.clone(), Value::string("saved")]), Ok(Value::Nil)));
+        assert!(matches!(call("read_file", vec![path.clone()]), Ok(Value::String(text)) if &*text == "saved"));
+        std::fs::remove_file(&file).unwrap();
+        assert!(matches!(call("read_file", vec![path.clone()]), Err(VmErrorKind::Thrown(Value::String(_)))));
+
+        // The file builtins need file I/O
+        vm.set_sandbox(Sandbox { deny_file_io: true, ..Sandbox::default() });
+        let read_file = vm.global("read_file").unwrap();
+        let err = vm.call(read_file, vec![path]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Sandboxed(Capability::FileIo)), "{err}");
+        let print = vm.global("print").unwrap();
+        assert!(vm.call(print, vec![Value::Nil]).is_ok());
+    }
+}
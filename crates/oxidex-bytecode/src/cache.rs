@@ -0,0 +1,131 @@
+//! Inline caches.
+//!
+//! Each `SEND`, `GET_FIELD` and `SET_FIELD` instruction remembers what it
+//! found for the classes of the receivers it has seen: the selector and
+//! return type of the method a message resolves to, or the index of a
+//! field. A site starts empty, becomes monomorphic on its first receiver
+//! and polymorphic on its second, and stops caching once it has seen more
+//! than [`POLYMORPHIC_LIMIT`] classes.
+//!
+//! Caches live beside the bytecode, in a table the VM keeps for each
+//! function, by instruction offset. Messages are still sent through the
+//! runtime, so replaced implementations are called; like the runtime's own
+//! method cache, an entry is not revisited when a class gains a method.
+
+use crate::chunk::Function;
+use oxidec::{Class, Selector};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Most classes a polymorphic site remembers.
+pub const POLYMORPHIC_LIMIT: usize = 4;
+
+/// What a site has learned about the classes of its receivers.
+#[derive(Debug, Clone, Default)]
+pub enum InlineCache<T> {
+    /// No receiver seen yet
+    #[default]
+    Empty,
+    /// Only receivers of one class seen
+    Monomorphic(Class, T),
+    /// Receivers of a few classes seen, oldest first
+    Polymorphic(Vec<(Class, T)>),
+    /// Too many classes seen to be worth caching
+    Megamorphic,
+}
+
+impl<T> InlineCache<T> {
+    /// Look up what was recorded for a class.
+    #[must_use]
+    pub fn get(&self, class: &Class) -> Option<&T> {
+        match self {
+            Self::Monomorphic(cached, target) if cached == class => Some(target),
+            Self::Polymorphic(entries) => entries.iter().find(|(cached, _)| cached == class).map(|(_, target)| target),
+            _ => None,
+        }
+    }
+
+    /// Record what was found for a class, replacing an earlier entry for it.
+    pub fn record(&mut self, class: Class, target: T) {
+        *self = match std::mem::take(self) {
+            Self::Empty => Self::Monomorphic(class, target),
+            Self::Monomorphic(cached, _) if cached == class => Self::Monomorphic(class, target),
+            Self::Monomorphic(cached, old) => Self::Polymorphic(vec![(cached, old), (class, target)]),
+            Self::Polymorphic(mut entries) => match entries.iter().position(|(cached, _)| *cached == class) {
+                Some(index) => {
+                    entries[index].1 = target;
+                    Self::Polymorphic(entries)
+                }
+                None if entries.len() < POLYMORPHIC_LIMIT => {
+                    entries.push((class, target));
+                    Self::Polymorphic(entries)
+                }
+                None => Self::Megamorphic,
+            },
+            Self::Megamorphic => Self::Megamorphic,
+        };
+    }
+}
+
+/// The method a message resolves to for a class.
+#[derive(Debug, Clone)]
+pub struct SendTarget {
+    /// Registered selector of the message
+    pub selector: Selector,
+    /// Return type from the method's type encoding
+    pub return_type: char,
+}
+
+/// The inline caches of one function's instructions.
+#[derive(Debug)]
+pub(crate) struct FunctionCaches {
+    /// The function, kept allocated so its address is not reused
+    function: Weak<Function>,
+    /// Caches of `SEND` instructions
+    pub(crate) sends: HashMap<usize, InlineCache<SendTarget>>,
+    /// Caches of `GET_FIELD` and `SET_FIELD` instructions
+    pub(crate) fields: HashMap<usize, InlineCache<usize>>,
+}
+
+impl FunctionCaches {
+    pub(crate) fn new(function: &Rc<Function>) -> Self {
+        Self { function: Rc::downgrade(function), sends: HashMap::new(), fields: HashMap::new() }
+    }
+
+    /// Whether the function can still run.
+    pub(crate) fn is_live(&self) -> bool {
+        self.function.strong_count() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_cache_states() {
+        let classes: Vec<_> = (0..=POLYMORPHIC_LIMIT)
+            .map(|index| Class::new_root(&format!("InlineCacheState{index}")).unwrap())
+            .collect();
+        let mut cache = InlineCache::default();
+        assert!(cache.get(&classes[0]).is_none());
+
+        cache.record(classes[0].clone(), 0);
+        cache.record(classes[0].clone(), 1);
+        assert!(matches!(cache, InlineCache::Monomorphic(_, 1)));
+        assert!(cache.get(&classes[1]).is_none());
+
+        for (index, class) in classes[1..POLYMORPHIC_LIMIT].iter().enumerate() {
+            cache.record(class.clone(), index + 2);
+        }
+        assert!(matches!(&cache, InlineCache::Polymorphic(entries) if entries.len() == POLYMORPHIC_LIMIT));
+        assert_eq!(cache.get(&classes[0]), Some(&1));
+        assert_eq!(cache.get(&classes[POLYMORPHIC_LIMIT - 1]), Some(&POLYMORPHIC_LIMIT));
+
+        cache.record(classes[POLYMORPHIC_LIMIT].clone(), 0);
+        assert!(matches!(cache, InlineCache::Megamorphic));
+        assert!(cache.get(&classes[0]).is_none());
+        cache.record(classes[0].clone(), 0);
+        assert!(matches!(cache, InlineCache::Megamorphic));
+    }
+}
@@ -0,0 +1,466 @@
+//! Chunks of bytecode.
+//!
+//! A [`Chunk`] is the compiled body of one function or script: its
+//! instruction bytes, the constants they refer to, a line table mapping
+//! each instruction back to the source it was compiled from, and a table of
+//! the [`Handler`]s that run when an error is raised. For debugging, a
+//! chunk also names its local variables and the instructions each is in
+//! scope for. Functions nested in a
+//! chunk are [`Constant::Function`]s in its constant pool. Instructions are
+//! [`OpCode`]s unless the chunk's [`Encoding`] says they are register
+//! instructions.
+
+use crate::error::{BytecodeError, Result};
+use crate::opcodes::OpCode;
+use oxidex_codegen::ir::Extern;
+use oxidex_syntax::Span;
+use std::fmt;
+use std::rc::Rc;
+
+/// A value in a chunk's constant pool.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// Integer literal
+    Int(i64),
+    /// Floating point literal
+    Float(f64),
+    /// String literal, or the name of a global, field or selector
+    String(Rc<str>),
+    /// Function prototype, made into a closure by [`OpCode::Closure`]
+    Function(Rc<Function>),
+    /// C function declared `extern`, loaded on its first call
+    Extern(Rc<Extern>),
+}
+
+impl Constant {
+    /// Whether two constants can share a pool entry. Floats are compared by
+    /// their bits, so `0.0` and `-0.0` stay apart, and functions are never
+    /// shared.
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::String(a), Self::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Constants display as literals, and functions by name.
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Float(value) => write!(f, "{value:?}"),
+            Self::String(text) => write!(f, "{text:?}"),
+            Self::Function(function) => write!(f, "<fn {}>", function.name),
+            Self::Extern(external) => write!(f, "<extern fn {}>", external.name),
+        }
+    }
+}
+
+/// A variable a closure captures when it is made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    /// A local of the enclosing function, by slot
+    Local(u8),
+    /// A variable the enclosing closure captured, by index
+    Upvalue(u8),
+}
+
+/// A compiled function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Name of the function, for stack traces
+    pub name: String,
+    /// Number of parameters, which occupy the first local slots
+    pub arity: u8,
+    /// Variables captured by closures over the function
+    pub captures: Vec<Capture>,
+    /// The function's body
+    pub chunk: Chunk,
+}
+
+/// What a handler does with an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandlerKind {
+    /// Handle the error: its code runs with the error pushed, as the
+    /// thrown value or, for errors the VM raised, a
+    /// [`Value::Error`](crate::Value::Error)
+    Catch,
+    /// Clean up and pass the error on: its code runs with the error pushed,
+    /// and ends with [`OpCode::Rethrow`]. Deferred code is compiled both
+    /// inline and as a cleanup, so it runs however its block exits
+    Cleanup,
+}
+
+/// Code that runs when an error is raised in a range of instructions.
+///
+/// When an instruction raises an error, the first handler covering it
+/// runs; handlers of nested ranges must therefore come first, as they do
+/// when each is added as its range is closed. If no handler covers it, the
+/// frame is popped and the call instruction in its caller is tried, and so
+/// on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handler {
+    /// What the handler does
+    pub kind: HandlerKind,
+    /// Offset of the first instruction covered
+    pub start: usize,
+    /// Offset after the last instruction covered
+    pub end: usize,
+    /// Offset of the handler's code
+    pub target: usize,
+    /// Number of the frame's stack slots, from its base, live at the
+    /// handler; slots above are popped before it runs
+    pub depth: usize,
+}
+
+/// A named local variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Local {
+    /// Name in the source
+    pub name: Rc<str>,
+    /// Local slot, or register, holding the variable
+    pub slot: u8,
+    /// Offset of the first instruction the variable is in scope for
+    pub start: usize,
+    /// Offset after the last instruction the variable is in scope for
+    pub end: usize,
+}
+
+/// How a chunk's instructions are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Stack instructions, [`OpCode`]s
+    #[default]
+    Stack,
+    /// Register instructions, [`RegOp`](crate::register::RegOp)s
+    Register {
+        /// Number of registers in a frame, counting the parameters
+        registers: u8,
+    },
+}
+
+/// A run of instruction bytes compiled from the same source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRun {
+    /// Offset of the first byte of the run
+    start: usize,
+    /// Source the bytes were compiled from
+    span: Span,
+}
+
+/// Instructions, constants and line table of a function or script.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    /// Instruction bytes
+    code: Vec<u8>,
+    /// Constant pool
+    constants: Vec<Constant>,
+    /// Line table, run-length encoded by span and sorted by offset
+    lines: Vec<LineRun>,
+    /// Error handlers, innermost first
+    handlers: Vec<Handler>,
+    /// Instruction set of the code
+    encoding: Encoding,
+    /// Named local variables, in declaration order
+    locals: Vec<Local>,
+}
+
+impl Chunk {
+    /// Create an empty chunk.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instruction bytes.
+    #[must_use]
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Constant pool.
+    #[must_use]
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// Constant at `index`, if there is one.
+    #[must_use]
+    pub fn constant(&self, index: u16) -> Option<&Constant> {
+        self.constants.get(usize::from(index))
+    }
+
+    /// Number of instruction bytes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Whether the chunk has no instructions.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// Append a byte compiled from `span`.
+    pub fn write(&mut self, byte: u8, span: Span) {
+        if self.lines.last().is_none_or(|run| run.span != span) {
+            self.lines.push(LineRun { start: self.code.len(), span });
+        }
+        self.code.push(byte);
+    }
+
+    /// Append an opcode, returning its offset.
+    pub fn write_op(&mut self, op: OpCode, span: Span) -> usize {
+        let offset = self.code.len();
+        self.write(op.into(), span);
+        offset
+    }
+
+    /// Append a little-endian `u16` operand.
+    pub fn write_u16(&mut self, value: u16, span: Span) {
+        for byte in value.to_le_bytes() {
+            self.write(byte, span);
+        }
+    }
+
+    /// Add a constant to the pool, returning its index. Equal literals and
+    /// names share an entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BytecodeError::TooManyConstants`] if the pool is full.
+    pub fn add_constant(&mut self, constant: Constant) -> Result<u16> {
+        if let Some(index) = self.constants.iter().position(|existing| existing.same(&constant)) {
+            return Ok(index as u16);
+        }
+        let index = u16::try_from(self.constants.len()).map_err(|_| BytecodeError::TooManyConstants)?;
+        self.constants.push(constant);
+        Ok(index)
+    }
+
+    /// Append an instruction with a constant operand, adding the constant to
+    /// the pool.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BytecodeError::TooManyConstants`] if the pool is full.
+    pub fn write_constant(&mut self, op: OpCode, constant: Constant, span: Span) -> Result<()> {
+        let index = self.add_constant(constant)?;
+        self.write_op(op, span);
+        self.write_u16(index, span);
+        Ok(())
+    }
+
+    /// Append a forward jump whose distance is not known yet, returning its
+    /// offset for [`Chunk::patch_jump`].
+    pub fn write_jump(&mut self, op: OpCode, span: Span) -> usize {
+        let offset = self.write_op(op, span);
+        self.write_u16(u16::MAX, span);
+        offset
+    }
+
+    /// Point the forward jump at `offset` to the end of the chunk.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BytecodeError::JumpTooFar`] if the distance does not fit.
+    pub fn patch_jump(&mut self, offset: usize) -> Result<()> {
+        let distance = self.code.len() - (offset + 3);
+        let distance = u16::try_from(distance).map_err(|_| BytecodeError::JumpTooFar { offset })?;
+        self.code[offset + 1..offset + 3].copy_from_slice(&distance.to_le_bytes());
+        Ok(())
+    }
+
+    /// Append a backward jump to `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BytecodeError::JumpTooFar`] if the distance does not fit.
+    pub fn write_loop(&mut self, target: usize, span: Span) -> Result<()> {
+        let offset = self.write_op(OpCode::Loop, span);
+        let distance = u16::try_from(offset + 3 - target).map_err(|_| BytecodeError::JumpTooFar { offset })?;
+        self.write_u16(distance, span);
+        Ok(())
+    }
+
+    /// Instruction set of the code.
+    #[must_use]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Add an error handler. Handlers of nested ranges must be added first.
+    pub fn add_handler(&mut self, handler: Handler) {
+        self.handlers.push(handler);
+    }
+
+    /// Error handlers, innermost first.
+    #[must_use]
+    pub fn handlers(&self) -> &[Handler] {
+        &self.handlers
+    }
+
+    /// The handler that runs for an error raised by the instruction at
+    /// `offset`, if any.
+    #[must_use]
+    pub fn handler(&self, offset: usize) -> Option<&Handler> {
+        self.handlers.iter().find(|handler| (handler.start..handler.end).contains(&offset))
+    }
+
+    /// Name a local variable for the instructions it is in scope for.
+    pub fn add_local(&mut self, local: Local) {
+        self.locals.push(local);
+    }
+
+    /// Named local variables, in declaration order.
+    #[must_use]
+    pub fn locals(&self) -> &[Local] {
+        &self.locals
+    }
+
+    /// The local variables in scope at `offset`, innermost last.
+    pub fn locals_at(&self, offset: usize) -> impl Iterator<Item = &Local> {
+        self.locals.iter().filter(move |local| (local.start..local.end).contains(&offset))
+    }
+
+    /// Name of the variable in a local slot at `offset`, if it is in scope.
+    #[must_use]
+    pub fn local_name(&self, slot: u8, offset: usize) -> Option<&str> {
+        self.locals_at(offset).filter(|local| local.slot == slot).last().map(|local| &*local.name)
+    }
+
+    /// Read the byte at `offset`.
+    #[must_use]
+    pub fn read_u8(&self, offset: usize) -> Option<u8> {
+        self.code.get(offset).copied()
+    }
+
+    /// Read the little-endian `u16` at `offset`.
+    #[must_use]
+    pub fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.code.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Source the byte at `offset` was compiled from.
+    #[must_use]
+    pub fn span(&self, offset: usize) -> Option<Span> {
+        if offset >= self.code.len() {
+            return None;
+        }
+        let run = self.lines.partition_point(|run| run.start <= offset);
+        Some(self.lines[run - 1].span)
+    }
+
+    /// Line the byte at `offset` was compiled from.
+    #[must_use]
+    pub fn line(&self, offset: usize) -> Option<usize> {
+        self.span(offset).map(|span| span.start_line)
+    }
+
+    /// Offset of the first byte compiled from source starting on `line`,
+    /// where a breakpoint on the line stops.
+    #[must_use]
+    pub fn offset_of_line(&self, line: usize) -> Option<usize> {
+        self.lines.iter().find(|run| run.span.start_line == line).map(|run| run.start)
+    }
+
+    /// Line table entries: the offset starting each run of bytes compiled
+    /// from the same source, and that source.
+    pub(crate) fn line_runs(&self) -> impl Iterator<Item = (usize, Span)> + '_ {
+        self.lines.iter().map(|run| (run.start, run.span))
+    }
+
+    /// Assemble a chunk from its parts. Returns `None` unless the line table
+    /// starts at offset 0 and its runs are in order within the code, and
+    /// the ranges of the handlers and locals and the handlers' code are
+    /// within the code.
+    pub(crate) fn from_parts(
+        code: Vec<u8>,
+        constants: Vec<Constant>,
+        lines: Vec<(usize, Span)>,
+        handlers: Vec<Handler>,
+        encoding: Encoding,
+        locals: Vec<Local>,
+    ) -> Option<Self> {
+        let starts_at_zero = lines.first().map_or(code.is_empty(), |(start, _)| *start == 0);
+        let ordered = lines.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        let within = lines.last().is_none_or(|(start, _)| *start < code.len());
+        let handled = handlers
+            .iter()
+            .all(|handler| handler.start <= handler.end && handler.end <= code.len() && handler.target < code.len());
+        let scoped = locals.iter().all(|local| local.start <= local.end && local.end <= code.len());
+        if !(starts_at_zero && ordered && within && handled && scoped) {
+            return None;
+        }
+        let lines = lines.into_iter().map(|(start, span)| LineRun { start, span }).collect();
+        Some(Self { code, constants, lines, handlers, encoding, locals })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    #[test]
+    fn test_constants_are_shared() {
+        let mut chunk = Chunk::new();
+        assert_eq!(chunk.add_constant(Constant::Int(1)), Ok(0));
+        assert_eq!(chunk.add_constant(Constant::String(Rc::from("x"))), Ok(1));
+        assert_eq!(chunk.add_constant(Constant::Int(1)), Ok(0));
+        assert_eq!(chunk.add_constant(Constant::Float(0.0)), Ok(2));
+        assert_eq!(chunk.add_constant(Constant::Float(-0.0)), Ok(3));
+        assert_eq!(chunk.add_constant(Constant::String(Rc::from("x"))), Ok(1));
+        assert_eq!(chunk.constant(3), Some(&Constant::Float(-0.0)));
+    }
+
+    #[test]
+    fn test_jumps_and_line_table() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::True, line(1));
+        let jump = chunk.write_jump(OpCode::JumpIfFalse, line(1));
+        chunk.write_constant(OpCode::Constant, Constant::Int(7), line(2)).unwrap();
+        chunk.write_op(OpCode::Pop, line(2));
+        chunk.patch_jump(jump).unwrap();
+        chunk.write_loop(0, line(3)).unwrap();
+
+        assert_eq!(chunk.len(), 11);
+        assert_eq!(OpCode::try_from(chunk.code()[1]), Ok(OpCode::JumpIfFalse));
+        // Skips the constant and the pop
+        assert_eq!(chunk.read_u16(2), Some(4));
+        assert_eq!(chunk.read_u16(9), Some(11));
+        assert_eq!(chunk.line(0), Some(1));
+        assert_eq!(chunk.line(3), Some(1));
+        assert_eq!(chunk.line(4), Some(2));
+        assert_eq!(chunk.line(10), Some(3));
+        assert_eq!(chunk.line(11), None);
+        assert_eq!(chunk.offset_of_line(2), Some(4));
+        assert_eq!(chunk.offset_of_line(4), None);
+    }
+
+    #[test]
+    fn test_local_scopes() {
+        let local = |name: &str, slot, start, end| Local { name: Rc::from(name), slot, start, end };
+        let mut chunk = Chunk::new();
+        chunk.add_local(local("x", 0, 0, 10));
+        chunk.add_local(local("y", 1, 4, 8));
+        chunk.add_local(local("z", 1, 8, 10));
+        chunk.add_local(local("x", 2, 6, 8));
+
+        assert_eq!(chunk.local_name(0, 0), Some("x"));
+        assert_eq!(chunk.local_name(1, 2), None);
+        assert_eq!(chunk.local_name(1, 5), Some("y"));
+        assert_eq!(chunk.local_name(1, 8), Some("z"));
+        assert_eq!(chunk.local_name(0, 10), None);
+        let names: Vec<_> = chunk.locals_at(7).map(|local| (&*local.name, local.slot)).collect();
+        assert_eq!(names, [("x", 0), ("y", 1), ("x", 2)]);
+    }
+}
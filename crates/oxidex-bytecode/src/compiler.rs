@@ -0,0 +1,993 @@
+//! Compilation of the IR to stack bytecode.
+//!
+//! [`compile`] turns a checked program's [IR module](oxidex_codegen::ir)
+//! into a script: a function that defines every function of the module as
+//! a global closure, and every extern as a global C function, by name, and
+//! returns `nil`. Running the script and then calling the global `main`
+//! runs the program.
+//!
+//! Each SSA value lives in a local slot of its function's frame, after the
+//! parameters, so an instruction reads its operands with
+//! [`OpCode::GetLocal`] and stores its result with [`OpCode::SetLocal`]:
+//!
+//! ```text
+//! %2: int = binary add %0, %1      GET_LOCAL 0, GET_LOCAL 1, ADD,
+//!                                  SET_LOCAL 2, POP
+//! ```
+//!
+//! Intrinsics push their operands and compute the result with a single
+//! [`OpCode::Intrinsic`] naming the intrinsic by ID.
+//!
+//! The slots of the values a function defines are set to `nil` on entry.
+//! Blocks are laid out in order, and a jump to a block before the jumping
+//! one is a [`OpCode::Loop`]. Phis are resolved on the edges into their
+//! block: the incoming values are all pushed, then stored, so phis reading
+//! each other see the values from before the edge.
+//!
+//! Each block a [cleanup](ir::Cleanup) covers gets a
+//! [`HandlerKind::Cleanup`] handler entering the cleanup's handler block.
+//! The error lands in a slot past the values', which `resume` pushes and
+//! rethrows.
+//!
+//! A closure is a VM closure over the values it captures, which calls the
+//! lifted function with them before its arguments. A box is a VM closure
+//! over the boxed value, reading it when called with `(nil, false)` and
+//! assigning it when called with `(value, true)`. Both capture the values
+//! from temporary slots above the frame's, which are closed at once.
+//!
+//! Instances, enums and collections are objects the VM makes itself: an
+//! allocation sends `alloc` to the name of the class, enum values and collections are built with
+//! [`OpCode::SetField`], enum tags and payloads read with
+//! [`OpCode::GetField`], and indexing, counting and slicing are messages
+//! the collections answer:
+//!
+//! ```text
+//! %3: object = array(%1, %2)       CONSTANT "Array", SEND alloc 0,
+//!                                  DUP, GET_LOCAL 1, SET_FIELD "0", POP,
+//!                                  DUP, GET_LOCAL 2, SET_FIELD "1", POP
+//! %4: int = get_index %3, %1       GET_LOCAL 3, GET_LOCAL 1, SEND at: 1
+//! ```
+//!
+//! Code is located at the source of the instruction it was compiled from,
+//! or of the last instruction before it with a location. A named variable
+//! is a [`Local`] over the slot of each value it holds, in scope from the
+//! value's definition until the next value the name holds is defined, in
+//! layout order.
+
+use crate::chunk::{Capture, Chunk, Constant, Function, Handler, HandlerKind, Local};
+use crate::error::{BytecodeError, Result};
+use crate::opcodes::OpCode;
+use crate::vm::objects;
+use oxidex_codegen::ir::{self, BlockId, Inst, IrType, Terminator, ValueId};
+use oxidex_syntax::Span;
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Name of the script function a module compiles to.
+pub const SCRIPT: &str = "<script>";
+
+/// Location of code compiled from no source: the script, and the code of
+/// a function before its first instruction with a location.
+const NO_SOURCE: Span = Span::new(0, 0, 0, 0, 0, 0);
+
+/// Compile a module to a script defining its functions as globals.
+///
+/// # Errors
+///
+/// Returns an error if a function uses an operation the instruction set
+/// cannot express, or outgrows the limits of a chunk or frame.
+pub fn compile(module: &ir::Module) -> Result<Function> {
+    let mut chunk = Chunk::new();
+    for external in &module.externs {
+        chunk.write_constant(OpCode::Constant, Constant::Extern(Rc::new(external.clone())), NO_SOURCE)?;
+        chunk.write_constant(OpCode::DefineGlobal, name(&external.name), NO_SOURCE)?;
+    }
+    for function in &module.functions {
+        let compiled = compile_function(module, function)?;
+        chunk.write_constant(OpCode::Closure, Constant::Function(Rc::new(compiled)), NO_SOURCE)?;
+        chunk.write_constant(OpCode::DefineGlobal, name(&function.name), NO_SOURCE)?;
+    }
+    chunk.write_op(OpCode::Nil, NO_SOURCE);
+    chunk.write_op(OpCode::Return, NO_SOURCE);
+    Ok(Function { name: SCRIPT.to_string(), arity: 0, captures: Vec::new(), chunk })
+}
+
+/// Compile one function of `module`.
+///
+/// # Errors
+///
+/// Returns an error if the function uses an operation the instruction set
+/// cannot express, or outgrows the limits of a chunk or frame.
+pub fn compile_function(module: &ir::Module, function: &ir::Function) -> Result<Function> {
+    FunctionCompiler::new(module, function)?.compile()
+}
+
+/// A string constant naming a global, field or selector.
+fn name(name: &str) -> Constant {
+    Constant::String(Rc::from(name))
+}
+
+/// The stack opcode of a binary operator.
+fn binary_op(op: BinaryOp) -> Option<OpCode> {
+    Some(match op {
+        BinaryOp::Add => OpCode::Add,
+        BinaryOp::Sub => OpCode::Subtract,
+        BinaryOp::Mul => OpCode::Multiply,
+        BinaryOp::Div => OpCode::Divide,
+        BinaryOp::Mod => OpCode::Remainder,
+        BinaryOp::Eq => OpCode::Equal,
+        BinaryOp::Neq => OpCode::NotEqual,
+        BinaryOp::Lt => OpCode::Less,
+        BinaryOp::Lte => OpCode::LessEqual,
+        BinaryOp::Gt => OpCode::Greater,
+        BinaryOp::Gte => OpCode::GreaterEqual,
+        // Lowered to control flow and variable writes by the IR builder
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Assign => return None,
+    })
+}
+
+/// Compiles the blocks of one function into a chunk.
+struct FunctionCompiler<'f> {
+    /// The module of the function, for the functions its closures lift
+    module: &'f ir::Module,
+    /// The function being compiled
+    function: &'f ir::Function,
+    /// Local slot of each value defined in the function
+    slots: HashMap<ValueId, u8>,
+    /// Slot the error entering a cleanup's handler lands in, if the
+    /// function has cleanups
+    error_slot: Option<u8>,
+    /// Offset of each block compiled so far
+    offsets: HashMap<BlockId, usize>,
+    /// Offset after the code of each block compiled so far
+    ends: HashMap<BlockId, usize>,
+    /// Forward jumps waiting for their target block to be compiled
+    pending: Vec<(usize, BlockId)>,
+    /// Names of the variables holding each value
+    names: HashMap<ValueId, Vec<&'f str>>,
+    /// Named variables, and the position among them of the one each name
+    /// refers to since its last assignment
+    locals: Vec<Local>,
+    in_scope: HashMap<&'f str, usize>,
+    /// Location of the instruction being compiled, or of the last one with
+    /// a location
+    span: Span,
+    /// The code being written
+    chunk: Chunk,
+}
+
+impl<'f> FunctionCompiler<'f> {
+    /// Assign every parameter and defined value a slot, parameters first.
+    fn new(module: &'f ir::Module, function: &'f ir::Function) -> Result<Self> {
+        let params = function.params.iter().copied();
+        let defined = function.blocks.iter().flat_map(|block| {
+            let phis = block.phis.iter().map(|phi| phi.result);
+            phis.chain(block.insts.iter().map(|inst| inst.result))
+        });
+        let mut slots = HashMap::new();
+        let too_many = |_| BytecodeError::TooManyLocals { function: function.name.clone() };
+        for (slot, value) in params.chain(defined).enumerate() {
+            slots.insert(value, u8::try_from(slot).map_err(too_many)?);
+        }
+        let mut names: HashMap<ValueId, Vec<&str>> = HashMap::new();
+        for (value, name) in &function.variables {
+            names.entry(*value).or_default().push(name);
+        }
+        let span = function.blocks.iter().flat_map(|block| &block.insts).find_map(|inst| inst.span);
+        let span = span.unwrap_or(NO_SOURCE);
+        let error_slot = if function.cleanups.is_empty() {
+            None
+        } else {
+            Some(u8::try_from(slots.len()).map_err(too_many)?)
+        };
+        Ok(Self {
+            module,
+            function,
+            slots,
+            error_slot,
+            offsets: HashMap::new(),
+            ends: HashMap::new(),
+            pending: Vec::new(),
+            names,
+            locals: Vec::new(),
+            in_scope: HashMap::new(),
+            span,
+            chunk: Chunk::new(),
+        })
+    }
+
+    /// Number of slots of the frame: those of the values, and the error
+    /// slot if there is one.
+    fn frame_size(&self) -> usize {
+        self.slots.len() + usize::from(self.error_slot.is_some())
+    }
+
+    fn compile(mut self) -> Result<Function> {
+        let arity = self.function.params.len();
+        let arity = u8::try_from(arity).map_err(|_| self.unsupported("more than 255 parameters"))?;
+        for &param in &self.function.params {
+            self.define(param);
+        }
+        for _ in usize::from(arity)..self.frame_size() {
+            self.chunk.write_op(OpCode::Nil, self.span);
+        }
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            self.start_block(block.id)?;
+            for phi in &block.phis {
+                self.define(phi.result);
+            }
+            for inst in &block.insts {
+                self.span = inst.span.unwrap_or(self.span);
+                self.instruction(&inst.inst)?;
+                self.store(inst.result);
+                self.define(inst.result);
+            }
+            let next = self.function.blocks.get(index + 1).map(|next| next.id);
+            self.terminator(block.id, &block.terminator, next)?;
+            self.ends.insert(block.id, self.chunk.len());
+        }
+
+        // Cleanups come innermost first, as the handlers must
+        for cleanup in &self.function.cleanups {
+            let (Some(&target), Some(depth)) = (self.offsets.get(&cleanup.handler), self.error_slot) else {
+                return Err(self.unsupported("a cleanup of an undefined block"));
+            };
+            for block in &cleanup.blocks {
+                let (Some(&start), Some(&end)) = (self.offsets.get(block), self.ends.get(block)) else {
+                    return Err(self.unsupported("a cleanup of an undefined block"));
+                };
+                let depth = usize::from(depth);
+                self.chunk.add_handler(Handler { kind: HandlerKind::Cleanup, start, end, target, depth });
+            }
+        }
+        for mut local in std::mem::take(&mut self.locals) {
+            local.end = local.end.min(self.chunk.len());
+            self.chunk.add_local(local);
+        }
+        let name = self.function.name.clone();
+        Ok(Function { name, arity, captures: Vec::new(), chunk: self.chunk })
+    }
+
+    /// Bring the variables holding `value` into scope from here on, each
+    /// replacing the variable of the same name in scope before.
+    fn define(&mut self, value: ValueId) {
+        let (Some(names), Some(&slot)) = (self.names.get(&value), self.slots.get(&value)) else {
+            return;
+        };
+        let offset = self.chunk.len();
+        for &name in names {
+            if let Some(previous) = self.in_scope.insert(name, self.locals.len()) {
+                self.locals[previous].end = offset;
+            }
+            self.locals.push(Local { name: Rc::from(name), slot, start: offset, end: usize::MAX });
+        }
+    }
+
+    fn unsupported(&self, construct: &'static str) -> BytecodeError {
+        BytecodeError::Unsupported { function: self.function.name.clone(), construct }
+    }
+
+    /// Record where a block starts, and point the jumps waiting for it
+    /// there.
+    fn start_block(&mut self, block: BlockId) -> Result<()> {
+        self.offsets.insert(block, self.chunk.len());
+        let (ready, waiting) = std::mem::take(&mut self.pending).into_iter().partition(|&(_, target)| target == block);
+        self.pending = waiting;
+        for (jump, _) in ready {
+            self.chunk.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    fn slot(&self, value: ValueId) -> Result<u8> {
+        self.slots.get(&value).copied().ok_or_else(|| self.unsupported("a value with no definition"))
+    }
+
+    fn local(&mut self, op: OpCode, slot: u8) {
+        self.chunk.write_op(op, self.span);
+        self.chunk.write(slot, self.span);
+    }
+
+    /// Push the values of `values`.
+    fn push(&mut self, values: &[ValueId]) -> Result<()> {
+        for &value in values {
+            let slot = self.slot(value)?;
+            self.local(OpCode::GetLocal, slot);
+        }
+        Ok(())
+    }
+
+    /// Pop the value on top of the stack into the slot of `value`.
+    fn store(&mut self, value: ValueId) {
+        let slot = self.slots[&value];
+        self.local(OpCode::SetLocal, slot);
+        self.chunk.write_op(OpCode::Pop, self.span);
+    }
+
+    /// The `u8` count of arguments of a call or send.
+    fn argc(&self, args: &[ValueId]) -> Result<u8> {
+        u8::try_from(args.len()).map_err(|_| self.unsupported("a call with more than 255 arguments"))
+    }
+
+    /// Write the code of an instruction, leaving its result pushed.
+    fn instruction(&mut self, inst: &Inst) -> Result<()> {
+        match inst {
+            Inst::Const(constant) => match constant {
+                ir::Constant::Unit | ir::Constant::Nil => {
+                    self.chunk.write_op(OpCode::Nil, self.span);
+                }
+                ir::Constant::Bool(value) => {
+                    self.chunk.write_op(if *value { OpCode::True } else { OpCode::False }, self.span);
+                }
+                ir::Constant::Int(value) => {
+                    self.chunk.write_constant(OpCode::Constant, Constant::Int(*value), self.span)?;
+                }
+                ir::Constant::Float(value) => {
+                    self.chunk.write_constant(OpCode::Constant, Constant::Float(*value), self.span)?;
+                }
+                ir::Constant::String(text) => self.chunk.write_constant(OpCode::Constant, name(text), self.span)?,
+            },
+            Inst::Global(global) => self.chunk.write_constant(OpCode::GetGlobal, name(global), self.span)?,
+            Inst::Binary { op, lhs, rhs } => {
+                let op = binary_op(*op).ok_or_else(|| self.unsupported("a logical or assignment operator"))?;
+                self.push(&[*lhs, *rhs])?;
+                self.chunk.write_op(op, self.span);
+            }
+            Inst::Unary { op, operand } => {
+                self.push(&[*operand])?;
+                let op = match op {
+                    UnaryOp::Negate => OpCode::Not,
+                    UnaryOp::Minus => OpCode::Negate,
+                };
+                self.chunk.write_op(op, self.span);
+            }
+            Inst::Call { callee, args } => {
+                let argc = self.argc(args)?;
+                self.chunk.write_constant(OpCode::GetGlobal, name(callee), self.span)?;
+                self.push(args)?;
+                self.chunk.write_op(OpCode::Call, self.span);
+                self.chunk.write(argc, self.span);
+            }
+            Inst::CallIndirect { callee, args } => {
+                let argc = self.argc(args)?;
+                self.push(&[*callee])?;
+                self.push(args)?;
+                self.chunk.write_op(OpCode::Call, self.span);
+                self.chunk.write(argc, self.span);
+            }
+            Inst::Send { receiver, selector, args } => {
+                let argc = self.argc(args)?;
+                self.push(&[*receiver])?;
+                self.push(args)?;
+                self.send(selector, argc)?;
+            }
+            Inst::GetField { object, field } => {
+                self.push(&[*object])?;
+                self.chunk.write_constant(OpCode::GetField, name(field), self.span)?;
+            }
+            Inst::SetField { object, field, value } => {
+                self.push(&[*object, *value])?;
+                self.chunk.write_constant(OpCode::SetField, name(field), self.span)?;
+            }
+            // `ADD` joins strings; anything else is sent `description` first
+            Inst::Concat(parts) => {
+                self.chunk.write_constant(OpCode::Constant, name(""), self.span)?;
+                for part in parts {
+                    self.push(&[*part])?;
+                    if *self.function.value_type(*part) != IrType::String {
+                        self.send(objects::DESCRIPTION, 0)?;
+                    }
+                    self.chunk.write_op(OpCode::Add, self.span);
+                }
+            }
+            Inst::Intrinsic { intrinsic, args } => {
+                self.push(args)?;
+                self.chunk.write_op(OpCode::Intrinsic, self.span);
+                self.chunk.write(intrinsic.id(), self.span);
+            }
+            Inst::Closure { function, captures } if captures.is_empty() => {
+                self.chunk.write_constant(OpCode::GetGlobal, name(function), self.span)?;
+            }
+            Inst::Closure { function, captures } => {
+                let lifted = self.module.function(function);
+                let lifted = lifted.ok_or_else(|| self.unsupported("a closure of an undefined function"))?;
+                let params = u8::try_from(lifted.params.len());
+                let params = params.map_err(|_| self.unsupported("more than 255 parameters"))?;
+                let captured = u8::try_from(captures.len()).ok().filter(|&captured| captured <= params);
+                let captured = captured.ok_or_else(|| self.unsupported("a closure capturing more than it takes"))?;
+                self.close_over(captures, partial(function, captured, params - captured, self.span)?)?;
+            }
+            Inst::Box(value) => self.close_over(&[*value], cell(self.span))?,
+            Inst::Load(cell) => {
+                self.push(&[*cell])?;
+                self.chunk.write_op(OpCode::Nil, self.span);
+                self.chunk.write_op(OpCode::False, self.span);
+                self.chunk.write_op(OpCode::Call, self.span);
+                self.chunk.write(2, self.span);
+            }
+            Inst::Store { cell, value } => {
+                self.push(&[*cell, *value])?;
+                self.chunk.write_op(OpCode::True, self.span);
+                self.chunk.write_op(OpCode::Call, self.span);
+                self.chunk.write(2, self.span);
+                self.chunk.write_op(OpCode::Pop, self.span);
+                self.chunk.write_op(OpCode::Nil, self.span);
+            }
+            Inst::Alloc { class } => self.allocate(class)?,
+            Inst::Variant { enum_name, variant, tag, payload } => {
+                self.allocate(enum_name)?;
+                let tag = i64::try_from(*tag).map_err(|_| self.unsupported("an enum with too many variants"))?;
+                for (field, value) in [(objects::TAG, Constant::Int(tag)), (objects::VARIANT, name(variant))] {
+                    self.chunk.write_op(OpCode::Dup, self.span);
+                    self.chunk.write_constant(OpCode::Constant, value, self.span)?;
+                    self.chunk.write_constant(OpCode::SetField, name(field), self.span)?;
+                    self.chunk.write_op(OpCode::Pop, self.span);
+                }
+                if let Some(payload) = payload {
+                    self.set_field(objects::PAYLOAD, *payload)?;
+                }
+            }
+            Inst::Tag(value) => {
+                self.push(&[*value])?;
+                self.chunk.write_constant(OpCode::GetField, name(objects::TAG), self.span)?;
+            }
+            Inst::Payload(value) => {
+                self.push(&[*value])?;
+                self.chunk.write_constant(OpCode::GetField, name(objects::PAYLOAD), self.span)?;
+            }
+            Inst::Tuple(elements) | Inst::Array(elements) => {
+                let class = if matches!(inst, Inst::Tuple(_)) { objects::TUPLE } else { objects::ARRAY };
+                self.allocate(class)?;
+                for (index, element) in elements.iter().enumerate() {
+                    self.set_field(&index.to_string(), *element)?;
+                }
+            }
+            Inst::Dict(entries) => {
+                self.allocate(objects::DICT)?;
+                for &(key, value) in entries {
+                    self.chunk.write_op(OpCode::Dup, self.span);
+                    self.push(&[key, value])?;
+                    self.send(objects::AT_PUT, 2)?;
+                    self.chunk.write_op(OpCode::Pop, self.span);
+                }
+            }
+            Inst::GetIndex { collection, index } => {
+                self.push(&[*collection, *index])?;
+                self.send(objects::AT, 1)?;
+            }
+            Inst::SetIndex { collection, index, value } => {
+                self.push(&[*collection, *index, *value])?;
+                self.send(objects::AT_PUT, 2)?;
+            }
+            Inst::Length(collection) => {
+                self.push(&[*collection])?;
+                self.send(objects::COUNT, 0)?;
+            }
+            Inst::Slice { collection, start } => {
+                let start = i64::try_from(*start).map_err(|_| self.unsupported("a slice of a huge array"))?;
+                self.push(&[*collection])?;
+                self.chunk.write_constant(OpCode::Constant, Constant::Int(start), self.span)?;
+                self.send(objects::SUFFIX_FROM, 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send `selector` to the receiver below the top `argc` values.
+    fn send(&mut self, selector: &str, argc: u8) -> Result<()> {
+        self.chunk.write_constant(OpCode::Send, name(selector), self.span)?;
+        self.chunk.write(argc, self.span);
+        Ok(())
+    }
+
+    /// Push a new instance of the class named `class`.
+    fn allocate(&mut self, class: &str) -> Result<()> {
+        self.chunk.write_constant(OpCode::Constant, name(class), self.span)?;
+        self.send(objects::ALLOC, 0)
+    }
+
+    /// Set a field of the instance on top of the stack, leaving it there.
+    fn set_field(&mut self, field: &str, value: ValueId) -> Result<()> {
+        self.chunk.write_op(OpCode::Dup, self.span);
+        self.push(&[value])?;
+        self.chunk.write_constant(OpCode::SetField, name(field), self.span)?;
+        self.chunk.write_op(OpCode::Pop, self.span);
+        Ok(())
+    }
+
+    /// Push a closure of `function` over `values`, capturing each from a
+    /// temporary slot above the frame's and closing it straight after.
+    fn close_over(&mut self, values: &[ValueId], function: Function) -> Result<()> {
+        // The closure goes in the slot below the captured ones
+        let result = self.frame_size();
+        let too_many = |_| BytecodeError::TooManyLocals { function: self.function.name.clone() };
+        let slot = u8::try_from(result).map_err(too_many)?;
+        let mut captures = Vec::with_capacity(values.len());
+        for index in 1..=values.len() {
+            captures.push(Capture::Local(u8::try_from(result + index).map_err(too_many)?));
+        }
+        self.chunk.write_op(OpCode::Nil, self.span);
+        self.push(values)?;
+        let function = Function { captures, ..function };
+        self.chunk.write_constant(OpCode::Closure, Constant::Function(Rc::new(function)), self.span)?;
+        self.local(OpCode::SetLocal, slot);
+        self.chunk.write_op(OpCode::Pop, self.span);
+        for _ in values {
+            self.chunk.write_op(OpCode::CloseUpvalue, self.span);
+        }
+        Ok(())
+    }
+
+    /// Write the code leaving `from`, whose successor in the layout is
+    /// `next`.
+    fn terminator(&mut self, from: BlockId, terminator: &Terminator, next: Option<BlockId>) -> Result<()> {
+        match terminator {
+            Terminator::Return(value) => {
+                match value {
+                    Some(value) => self.push(&[*value])?,
+                    None => {
+                        self.chunk.write_op(OpCode::Nil, self.span);
+                    }
+                }
+                self.chunk.write_op(OpCode::Return, self.span);
+            }
+            Terminator::Jump(target) => self.edge(from, *target, next)?,
+            Terminator::Branch { cond, then_block, else_block } => {
+                self.push(&[*cond])?;
+                let otherwise = self.chunk.write_jump(OpCode::JumpIfFalse, self.span);
+                self.edge(from, *then_block, None)?;
+                self.chunk.patch_jump(otherwise)?;
+                self.edge(from, *else_block, next)?;
+            }
+            Terminator::Switch { value, cases, default } => {
+                for &(case, target) in cases {
+                    self.push(&[*value])?;
+                    self.chunk.write_constant(OpCode::Constant, Constant::Int(case), self.span)?;
+                    self.chunk.write_op(OpCode::Equal, self.span);
+                    let otherwise = self.chunk.write_jump(OpCode::JumpIfFalse, self.span);
+                    self.edge(from, target, None)?;
+                    self.chunk.patch_jump(otherwise)?;
+                }
+                self.edge(from, *default, next)?;
+            }
+            Terminator::Unreachable => {
+                self.chunk.write_constant(OpCode::Constant, name("unreachable code was reached"), self.span)?;
+                self.chunk.write_op(OpCode::Throw, self.span);
+            }
+            Terminator::Resume => {
+                let slot = self.error_slot.ok_or_else(|| self.unsupported("a resume outside of a cleanup"))?;
+                self.local(OpCode::GetLocal, slot);
+                self.chunk.write_op(OpCode::Rethrow, self.span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the code of the edge from `from` to `to`: the moves into the
+    /// phis of `to`, then a jump, unless `to` is `next`, laid out right
+    /// after.
+    fn edge(&mut self, from: BlockId, to: BlockId, next: Option<BlockId>) -> Result<()> {
+        let phis = &self.function.block(to).phis;
+        let mut moves = Vec::with_capacity(phis.len());
+        for phi in phis {
+            let incoming = phi.incoming.iter().find(|&&(pred, _)| pred == from);
+            let &(_, value) = incoming.ok_or_else(|| self.unsupported("a phi missing an incoming value"))?;
+            moves.push((value, phi.result));
+        }
+        for &(value, _) in &moves {
+            self.push(&[value])?;
+        }
+        for &(_, result) in moves.iter().rev() {
+            self.store(result);
+        }
+
+        if next == Some(to) {
+            return Ok(());
+        }
+        match self.offsets.get(&to) {
+            Some(&target) => self.chunk.write_loop(target, self.span)?,
+            None => {
+                let jump = self.chunk.write_jump(OpCode::Jump, self.span);
+                self.pending.push((jump, to));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function taking `arity` arguments, which calls the global `lifted`
+/// with its `captured` upvalues and then its arguments, compiled from
+/// `span`.
+fn partial(lifted: &str, captured: u8, arity: u8, span: Span) -> Result<Function> {
+    let mut chunk = Chunk::new();
+    chunk.write_constant(OpCode::GetGlobal, name(lifted), span)?;
+    for (op, count) in [(OpCode::GetUpvalue, captured), (OpCode::GetLocal, arity)] {
+        for index in 0..count {
+            chunk.write_op(op, span);
+            chunk.write(index, span);
+        }
+    }
+    chunk.write_op(OpCode::Call, span);
+    chunk.write(captured + arity, span);
+    chunk.write_op(OpCode::Return, span);
+    Ok(Function { name: lifted.to_string(), arity, captures: Vec::new(), chunk })
+}
+
+/// A function over one upvalue, the boxed value, taking a value and
+/// whether to assign it: it returns the value boxed, after the assignment.
+/// Its code is compiled from `span`.
+fn cell(span: Span) -> Function {
+    let mut chunk = Chunk::new();
+    chunk.write_op(OpCode::GetLocal, span);
+    chunk.write(1, span);
+    let read = chunk.write_jump(OpCode::JumpIfFalse, span);
+    chunk.write_op(OpCode::GetLocal, span);
+    chunk.write(0, span);
+    chunk.write_op(OpCode::SetUpvalue, span);
+    chunk.write(0, span);
+    chunk.write_op(OpCode::Return, span);
+    chunk.patch_jump(read).expect("the jump is short");
+    chunk.write_op(OpCode::GetUpvalue, span);
+    chunk.write(0, span);
+    chunk.write_op(OpCode::Return, span);
+    Function { name: "<box>".to_string(), arity: 2, captures: Vec::new(), chunk }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::VmErrorKind;
+    use crate::value::Value;
+    use crate::vm::Vm;
+    use oxidec::runtime::sandbox::{Capability, Sandbox};
+    use oxidex_codegen::ir::parse_module;
+
+    /// Compile a module written as IR text, run its script and call `main`.
+    fn run(text: &str, args: Vec<Value>) -> Value {
+        let script = compile(&parse_module(text).unwrap()).unwrap();
+        let mut vm = Vm::new();
+        vm.run(Rc::new(script)).unwrap();
+        let main = vm.global("main").unwrap();
+        vm.call(main, args).unwrap()
+    }
+
+    #[test]
+    fn test_compile_calls_branches_and_loops() {
+        let program = r#"
+fn "fact"(%0: int) -> int {
+bb0:
+    %1: int = const int 1
+    %2: bool = binary le %0, %1
+    branch %2, bb1, bb2
+bb1:
+    return %1
+bb2:
+    %3: int = binary sub %0, %1
+    %4: int = call "fact"(%3)
+    %5: int = binary mul %0, %4
+    return %5
+}
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %7: int = const int 1
+    jump bb1
+bb1:
+    %2: int = phi [bb0: %1, bb2: %5]
+    %3: int = phi [bb0: %1, bb2: %6]
+    %4: bool = binary lt %2, %0
+    branch %4, bb2, bb3
+bb2:
+    %5: int = binary add %2, %7
+    %6: int = call "fact"(%5)
+    jump bb1
+bb3:
+    %8: int = binary add %3, %2
+    return %8
+}
+"#;
+        // The last factorial below 4, plus the count
+        assert_eq!(run(program, vec![Value::Int(4)]), Value::Int(24 + 4));
+        assert_eq!(run(program, vec![Value::Int(0)]), Value::Int(0));
+    }
+
+    #[test]
+    fn test_compile_swaps_phis_on_the_same_edge() {
+        let program = r#"
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = const int 1
+    %2: int = const int 2
+    jump bb1
+bb1:
+    %3: int = phi [bb0: %1, bb2: %4]
+    %4: int = phi [bb0: %2, bb2: %3]
+    %5: int = phi [bb0: %0, bb2: %7]
+    %6: bool = binary gt %5, %1
+    branch %6, bb2, bb3
+bb2:
+    %7: int = binary sub %5, %1
+    jump bb1
+bb3:
+    %8: int = const int 10
+    %9: int = binary mul %3, %8
+    %10: int = binary add %9, %4
+    return %10
+}
+"#;
+        assert_eq!(run(program, vec![Value::Int(1)]), Value::Int(12));
+        assert_eq!(run(program, vec![Value::Int(2)]), Value::Int(21));
+        assert_eq!(run(program, vec![Value::Int(3)]), Value::Int(12));
+    }
+
+    #[test]
+    fn test_compile_intrinsics() {
+        let program = r#"
+fn "main"(%0: int, %1: string) -> int {
+bb0:
+    %2: int = intrinsic abs(%0)
+    %3: int = intrinsic length(%1)
+    %4: int = intrinsic max(%2, %3)
+    %5: float = const float 16.0
+    %6: float = intrinsic sqrt(%5)
+    return %4
+}
+"#;
+        let text = Value::string("four");
+        assert_eq!(run(program, vec![Value::Int(-7), text.clone()]), Value::Int(7));
+        assert_eq!(run(program, vec![Value::Int(2), text]), Value::Int(4));
+    }
+
+    #[test]
+    fn test_compile_runs_cleanups_as_errors_unwind() {
+        let program = r#"
+fn "deferred"() -> unit {
+bb0:
+    %0: object = call "record"()
+    return
+}
+
+fn "main"(%0: bool) -> int {
+    cleanup bb4 for [bb1, bb2]
+bb0:
+    %1: object = closure "deferred"()
+    jump bb1
+bb1:
+    branch %0, bb2, bb3
+bb2:
+    %2: object = call "fail"()
+    jump bb3
+bb3:
+    %3: object = call_indirect %1()
+    %4: int = const int 7
+    return %4
+bb4:
+    %5: object = call_indirect %1()
+    resume
+}
+"#;
+        let script = compile(&parse_module(program).unwrap()).unwrap();
+        let mut vm = Vm::new();
+        let records = Rc::new(std::cell::Cell::new(0));
+        let counter = Rc::clone(&records);
+        vm.define_native("record", 0, move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Nil)
+        });
+        vm.define_native("fail", 0, |_, _| Err(VmErrorKind::Thrown(Value::string("boom"))));
+        vm.run(Rc::new(script)).unwrap();
+        let main = vm.global("main").unwrap();
+
+        // The deferred code runs once however the block is left
+        assert_eq!(vm.call(main.clone(), vec![Value::Bool(false)]).unwrap(), Value::Int(7));
+        assert_eq!(records.get(), 1);
+        let err = vm.call(main, vec![Value::Bool(true)]).unwrap_err();
+        assert!(matches!(&err.kind, VmErrorKind::Thrown(value) if *value == Value::string("boom")), "{err:?}");
+        assert_eq!(records.get(), 2);
+    }
+
+    #[test]
+    fn test_compile_instances_enums_and_collections() {
+        let program = r#"
+fn "CompiledPoint.sum"(%0: object) -> int {
+bb0:
+    %1: int = get_field %0, "x"
+    %2: int = get_field %0, "y"
+    %3: int = binary add %1, %2
+    return %3
+}
+
+fn "area"(%0: object) -> int {
+bb0:
+    %1: int = tag %0
+    switch %1 [0: bb1], default bb2
+bb1:
+    %2: int = payload %0
+    %3: int = binary mul %2, %2
+    return %3
+bb2:
+    %4: int = const int 0
+    return %4
+}
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: object = alloc "CompiledPoint"
+    %2: unit = set_field %1, "x", %0
+    %3: int = const int 2
+    %4: unit = set_field %1, "y", %3
+    %5: int = send %1, "sum"()
+    %6: object = variant "CompiledShape", 0, "circle"(%3)
+    %7: int = call "area"(%6)
+    %8: object = variant "CompiledShape", 1, "empty"
+    %9: int = call "area"(%8)
+    %10: object = array(%0, %3, %5)
+    %11: unit = set_index %10, %3, %7
+    %12: object = slice %10, 1
+    %13: int = length %12
+    %14: int = const int 1
+    %15: int = get_index %12, %14
+    %16: object = dict(%3: %5)
+    %17: unit = set_index %16, %0, %3
+    %18: int = get_index %16, %0
+    %19: int = length %16
+    %20: int = binary add %5, %7
+    %21: int = binary add %20, %9
+    %22: int = binary add %21, %13
+    %23: int = binary add %22, %15
+    %24: int = binary add %23, %18
+    %25: int = binary add %24, %19
+    return %25
+}
+
+fn "past"(%0: int) -> int {
+bb0:
+    %1: object = array(%0)
+    %2: int = get_index %1, %0
+    return %2
+}
+"#;
+        // The method's 5 + 2, the circle's 2 * 2 and the empty shape's 0;
+        // the slice's two elements, the last replaced by the area; the
+        // entry added under 5 and the dictionary's two entries
+        assert_eq!(run(program, vec![Value::Int(5)]), Value::Int(7 + 4 + 2 + 4 + 2 + 2));
+
+        let script = compile(&parse_module(program).unwrap()).unwrap();
+        let mut vm = Vm::new();
+        vm.run(Rc::new(script)).unwrap();
+        let past = vm.global("past").unwrap();
+        let err = vm.call(past, vec![Value::Int(1)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::IndexOutOfBounds { index: 1, len: 1 }), "{err:?}");
+    }
+
+    #[test]
+    fn test_compile_describes_interpolated_values() {
+        let program = r#"
+fn "CompiledLabel.description"(%0: object) -> string {
+bb0:
+    %1: string = const string "label"
+    return %1
+}
+
+fn "main"(%0: int) -> string {
+bb0:
+    %1: object = alloc "CompiledLabel"
+    %2: object = alloc "CompiledPair"
+    %3: unit = set_field %2, "x", %0
+    %4: string = const string "s"
+    %5: unit = set_field %2, "name", %4
+    %6: object = variant "CompiledOption", 0, "some"(%4)
+    %7: object = array(%0, %4, %1)
+    %8: float = const float 1.5
+    %9: string = const string " "
+    %10: string = concat(%0, %4, %9, %1, %9, %2, %9, %6, %9, %7, %9, %8)
+    return %10
+}
+
+fn "label"() -> object {
+bb0:
+    %0: object = alloc "CompiledLabel"
+    return %0
+}
+"#;
+        // Parts other than strings are described as the interpreter
+        // describes them, by their `description()` if they have one
+        assert_eq!(
+            run(program, vec![Value::Int(5)]),
+            Value::string(r#"5s label CompiledPair(x: 5, name: "s") CompiledOption::some("s") [5, "s", label] 1.5"#)
+        );
+
+        // `print` describes values the same way
+        let script = compile(&parse_module(program).unwrap()).unwrap();
+        let mut vm = Vm::new();
+        vm.run(Rc::new(script)).unwrap();
+        let label = vm.call(vm.global("label").unwrap(), Vec::new()).unwrap();
+        assert_eq!(vm.describe(&label).unwrap(), "label");
+        assert_eq!(label.to_string(), "CompiledLabel()");
+    }
+
+    #[test]
+    fn test_compile_records_spans_and_named_locals() {
+        let program = r#"
+fn "main"(%0: int) -> int {
+    var %0 "by"
+    var %2 "scaled"
+bb0:
+    %1: int = const int 10
+    %2: int = binary mul %1, %1 span(20, 26, 2:18, 2:23)
+    %3: int = binary div %2, %0 span(31, 42, 3:5, 3:15)
+    return %3
+}
+"#;
+        let script = compile(&parse_module(program).unwrap()).unwrap();
+        let mut vm = Vm::new();
+        vm.run(Rc::new(script)).unwrap();
+        let main = vm.global("main").unwrap();
+        let err = vm.call(main, vec![Value::Int(0)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::DivisionByZero), "{err:?}");
+        let frame = &err.trace[0];
+        assert_eq!(frame.span, Some(Span::new(31, 42, 3, 5, 3, 15)));
+        let locals: Vec<(&str, &Value)> = frame.locals.iter().map(|(name, value)| (name.as_str(), value)).collect();
+        assert_eq!(locals, [("by", &Value::Int(0)), ("scaled", &Value::Int(100))]);
+    }
+
+    #[test]
+    fn test_compile_rejects_what_has_no_instructions() {
+        let module = parse_module(
+            r#"
+fn "both"(%0: bool) -> bool {
+bb0:
+    %1: bool = binary and %0, %0
+    return %1
+}
+"#,
+        )
+        .unwrap();
+        let err = compile(&module).unwrap_err();
+        let construct = "a logical or assignment operator";
+        assert_eq!(err, BytecodeError::Unsupported { function: "both".to_string(), construct });
+        assert_eq!(err.to_string(), "`both` uses a logical or assignment operator, which bytecode cannot express yet");
+    }
+
+    #[test]
+    #[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn test_compile_calls_extern_functions() {
+        let program = r#"
+extern "abs" "ii"
+extern "strlen" "Q*"
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = call "abs"(%0)
+    %2: string = const string "four"
+    %3: int = call "strlen"(%2)
+    %4: int = binary add %1, %3
+    return %4
+}
+"#;
+        assert_eq!(run(program, vec![Value::Int(-3)]), Value::Int(3 + 4));
+
+        // The function is looked up on its first call
+        let missing = "extern \"oxidex_no_such_function\" \"v\"\n";
+        let mut vm = Vm::new();
+        vm.run(Rc::new(compile(&parse_module(missing).unwrap()).unwrap())).unwrap();
+        let function = vm.global("oxidex_no_such_function").unwrap();
+        let err = vm.call(function, vec![]).unwrap_err();
+        let VmErrorKind::Runtime(oxidec::Error::SymbolNotFound { symbol }) = err.kind else { panic!("{err:?}") };
+        assert_eq!(symbol, "oxidex_no_such_function");
+
+        // A sandbox denying native code refuses before loading anything
+        vm.set_sandbox(Sandbox { deny_ffi: true, ..Sandbox::default() });
+        let function = vm.global("oxidex_no_such_function").unwrap();
+        let err = vm.call(function, vec![]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Sandboxed(Capability::Ffi)), "{err:?}");
+        let script = compile(&parse_module(program).unwrap()).unwrap();
+        vm.run(Rc::new(script)).unwrap();
+        let main = vm.global("main").unwrap();
+        let err = vm.call(main, vec![Value::Int(-3)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Sandboxed(Capability::Ffi)), "{err:?}");
+        assert_eq!(err.trace[0].function, "main");
+    }
+}
@@ -0,0 +1,245 @@
+//! Disassembly.
+//!
+//! Chunks are printed one instruction per line, as
+//!
+//! ```text
+//! 0000    1 CONSTANT            0 7
+//! 0003    | JUMP_IF_FALSE       3 -> 13
+//! ```
+//!
+//! with the offset, the source line (or `|` when it is the same as the
+//! previous instruction's), the mnemonic and the operands. Constant operands
+//! show the constant they refer to, and jumps show their target. Functions in
+//! the constant pool are printed after the chunk that contains them, and
+//! each chunk's error handlers and named locals after its instructions.
+//! Register operands are printed as `r0`, `r1` and so on, and register
+//! chunks say how many registers their frames have:
+//!
+//! ```text
+//! == count (3 registers) ==
+//! 0000    1 ADD              r1   r1 r2
+//! ```
+
+use crate::chunk::{Capture, Chunk, Constant, Encoding, HandlerKind};
+use crate::opcodes::OpCode;
+use crate::register::RegOp;
+use oxidex_codegen::intrinsics::Intrinsic;
+use std::fmt::Write;
+
+/// Disassemble a chunk and the functions in its constant pool.
+#[must_use]
+pub fn disassemble(chunk: &Chunk, name: &str) -> String {
+    let mut out = String::new();
+    write_chunk(&mut out, chunk, name);
+    out
+}
+
+fn write_chunk(out: &mut String, chunk: &Chunk, name: &str) {
+    let _ = match chunk.encoding() {
+        Encoding::Stack => writeln!(out, "== {name} =="),
+        Encoding::Register { registers } => writeln!(out, "== {name} ({registers} registers) =="),
+    };
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let (line, next) = instruction(chunk, offset);
+        let _ = writeln!(out, "{line}");
+        offset = next;
+    }
+    for handler in chunk.handlers() {
+        let kind = match handler.kind {
+            HandlerKind::Catch => "catch",
+            HandlerKind::Cleanup => "cleanup",
+        };
+        let _ = writeln!(
+            out,
+            "handler {:04}..{:04} -> {:04} {kind} depth {}",
+            handler.start, handler.end, handler.target, handler.depth
+        );
+    }
+    for local in chunk.locals() {
+        let _ = writeln!(out, "local {} slot {} {:04}..{:04}", local.name, local.slot, local.start, local.end);
+    }
+    for constant in chunk.constants() {
+        if let Constant::Function(function) = constant {
+            out.push('\n');
+            write_chunk(out, &function.chunk, &function.name);
+        }
+    }
+}
+
+/// Disassemble the instruction at `offset`, returning its line and the
+/// offset of the next instruction.
+#[must_use]
+pub fn instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let mut out = format!("{offset:04} ");
+    let line = chunk.line(offset);
+    match line {
+        Some(line) if offset > 0 && chunk.line(offset - 1) == Some(line) => out.push_str("   | "),
+        Some(line) => {
+            let _ = write!(out, "{line:4} ");
+        }
+        None => out.push_str("   ? "),
+    }
+
+    if let Encoding::Register { .. } = chunk.encoding() {
+        return register_instruction(chunk, offset, out);
+    }
+    let byte = chunk.code()[offset];
+    let Ok(op) = OpCode::try_from(byte) else {
+        let _ = write!(out, "<invalid {byte:#04x}>");
+        return (out, offset + 1);
+    };
+    let next = offset + 1 + op.operand_width();
+    if next > chunk.len() {
+        let _ = write!(out, "{op} <truncated>");
+        return (out, chunk.len());
+    }
+
+    let u8_operand = || chunk.code()[offset + 1];
+    let u16_operand = || chunk.read_u16(offset + 1).unwrap_or_default();
+    let constant = |index: u16| chunk.constant(index).map_or_else(|| "<invalid>".to_string(), Constant::to_string);
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetField
+        | OpCode::SetField => {
+            let index = u16_operand();
+            let _ = write!(out, "{op:<16} {index:4} {}", constant(index));
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+            let _ = write!(out, "{op:<16} {:4}", u8_operand());
+        }
+        OpCode::Intrinsic => {
+            let id = u8_operand();
+            let name = Intrinsic::from_id(id).map_or("<invalid>", Intrinsic::name);
+            let _ = write!(out, "{op:<16} {id:4} {name}");
+        }
+        OpCode::Jump | OpCode::JumpIfFalse => {
+            let _ = write!(out, "{op:<16} {offset:4} -> {}", next + usize::from(u16_operand()));
+        }
+        OpCode::Loop => {
+            let target = next.checked_sub(usize::from(u16_operand()));
+            let _ = write!(out, "{op:<16} {offset:4} -> {}", target.map_or_else(|| "?".to_string(), |t| t.to_string()));
+        }
+        OpCode::Send => {
+            let index = u16_operand();
+            let argc = chunk.code()[offset + 3];
+            let _ = write!(out, "{op:<16} {index:4} {} ({argc} args)", constant(index));
+        }
+        OpCode::Closure => {
+            let index = u16_operand();
+            let _ = write!(out, "{op:<16} {index:4} {}", constant(index));
+            if let Some(Constant::Function(function)) = chunk.constant(index) {
+                for capture in &function.captures {
+                    let _ = match capture {
+                        Capture::Local(slot) => write!(out, "\n{:<28}local {slot}", "     |"),
+                        Capture::Upvalue(index) => write!(out, "\n{:<28}upvalue {index}", "     |"),
+                    };
+                }
+            }
+        }
+        _ => out.push_str(op.name()),
+    }
+    (out, next)
+}
+
+/// Disassemble the register instruction at `offset` after `out`, the
+/// instruction's offset and line.
+fn register_instruction(chunk: &Chunk, offset: usize, mut out: String) -> (String, usize) {
+    let byte = chunk.code()[offset];
+    let Ok(op) = RegOp::try_from(byte) else {
+        let _ = write!(out, "<invalid {byte:#04x}>");
+        return (out, offset + 1);
+    };
+    let next = offset + 1 + op.operand_width();
+    if next > chunk.len() {
+        let _ = write!(out, "{op} <truncated>");
+        return (out, chunk.len());
+    }
+
+    let register = |at: usize| format!("r{}", chunk.code()[offset + at]);
+    let u16_operand = |at: usize| chunk.read_u16(offset + at).unwrap_or_default();
+    let constant = |index: u16| chunk.constant(index).map_or_else(|| "<invalid>".to_string(), Constant::to_string);
+    match op {
+        RegOp::LoadConstant | RegOp::GetGlobal | RegOp::DefineGlobal | RegOp::SetGlobal => {
+            let index = u16_operand(2);
+            let _ = write!(out, "{op:<16} {:<4} {index:4} {}", register(1), constant(index));
+        }
+        RegOp::Jump => {
+            let _ = write!(out, "{op:<16} {offset:4} -> {}", next + usize::from(u16_operand(1)));
+        }
+        RegOp::JumpIfFalse => {
+            let _ = write!(out, "{op:<16} {:<4} {offset:4} -> {}", register(1), next + usize::from(u16_operand(2)));
+        }
+        RegOp::Loop => {
+            let target = next.checked_sub(usize::from(u16_operand(1)));
+            let _ = write!(out, "{op:<16} {offset:4} -> {}", target.map_or_else(|| "?".to_string(), |t| t.to_string()));
+        }
+        RegOp::Call => {
+            let _ = write!(out, "{op:<16} {:<4} ({} args)", register(1), chunk.code()[offset + 2]);
+        }
+        _ if op.operand_width() == 1 => {
+            let _ = write!(out, "{op:<16} {}", register(1));
+        }
+        _ => {
+            let _ = write!(out, "{op:<16} {:<4}", register(1));
+            for at in 2..=op.operand_width() {
+                let _ = write!(out, " {}", register(at));
+            }
+        }
+    }
+    (out, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Function;
+    use oxidex_syntax::Span;
+    use std::rc::Rc;
+
+    fn line(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    #[test]
+    fn test_disassemble_chunk() {
+        let mut inner = Chunk::new();
+        inner.write_op(OpCode::GetUpvalue, line(3));
+        inner.write(0, line(3));
+        inner.write_op(OpCode::Return, line(3));
+        let inner = Function { name: "inner".to_string(), arity: 0, captures: vec![Capture::Local(0)], chunk: inner };
+
+        let mut chunk = Chunk::new();
+        chunk.write_constant(OpCode::Constant, Constant::Int(7), line(1)).unwrap();
+        let jump = chunk.write_jump(OpCode::JumpIfFalse, line(1));
+        chunk.write_constant(OpCode::Closure, Constant::Function(Rc::new(inner)), line(2)).unwrap();
+        chunk.write_constant(OpCode::Send, Constant::String(Rc::from("add:")), line(2)).unwrap();
+        chunk.write(1, line(2));
+        chunk.patch_jump(jump).unwrap();
+        chunk.write_loop(0, line(2)).unwrap();
+        chunk.write(0xff, line(4));
+        chunk.add_handler(crate::chunk::Handler { kind: HandlerKind::Catch, start: 3, end: 13, target: 16, depth: 0 });
+        chunk.add_local(crate::chunk::Local { name: Rc::from("n"), slot: 0, start: 3, end: 16 });
+
+        let expected = "\
+== script ==
+0000    1 CONSTANT            0 7
+0003    | JUMP_IF_FALSE       3 -> 13
+0006    2 CLOSURE             1 <fn inner>
+     |                      local 0
+0009    | SEND                2 \"add:\" (1 args)
+0013    | LOOP               13 -> 0
+0016    4 <invalid 0xff>
+handler 0003..0013 -> 0016 catch depth 0
+local n slot 0 0003..0016
+
+== inner ==
+0000    3 GET_UPVALUE         0
+0002    | RETURN
+";
+        assert_eq!(disassemble(&chunk, "script"), expected);
+    }
+}
@@ -0,0 +1,418 @@
+//! Bytecode errors.
+//!
+//! [`BytecodeError`]s are raised while building chunks, [`LinkError`]s while
+//! linking compiled modules into one program, and [`LoadError`]s while
+//! reading them back from bytecode files. [`VmError`]s are raised
+//! while running them, and record where each active call was, so they can be
+//! reported against the source.
+
+use crate::value::Value;
+use oxidec::runtime::sandbox::Capability;
+use oxidex_syntax::Span;
+use std::fmt;
+use std::rc::Rc;
+
+/// Errors building a chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BytecodeError {
+    /// The constant pool is full.
+    TooManyConstants,
+
+    /// A jump is farther than its `u16` operand can encode.
+    JumpTooFar {
+        /// Offset of the jump instruction
+        offset: usize,
+    },
+
+    /// A function defines more values than a frame has local slots.
+    TooManyLocals {
+        /// Name of the function
+        function: String,
+    },
+
+    /// A function uses an operation the instruction set cannot express.
+    Unsupported {
+        /// Name of the function
+        function: String,
+        /// Description of the operation
+        construct: &'static str,
+    },
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyConstants => write!(f, "a chunk cannot hold more than {} constants", u16::MAX as usize + 1),
+            Self::JumpTooFar { offset } => write!(f, "jump at offset {offset} is too far"),
+            Self::TooManyLocals { function } => write!(f, "`{function}` needs more than 256 local slots"),
+            Self::Unsupported { function, construct } => {
+                write!(f, "`{function}` uses {construct}, which bytecode cannot express yet")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError {}
+
+/// Errors linking compiled modules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// Two modules, or one module twice, define the same global.
+    DuplicateSymbol {
+        /// Name of the global
+        symbol: String,
+        /// Module defining it first
+        first: String,
+        /// Module defining it again
+        second: String,
+    },
+
+    /// A module refers to a global that no module defines and the host
+    /// does not provide.
+    UndefinedSymbol {
+        /// Name of the global
+        symbol: String,
+        /// Module referring to it
+        module: String,
+    },
+
+    /// A module is not a script [`compile`](crate::compile) could have
+    /// produced.
+    Malformed {
+        /// Name of the module
+        module: String,
+        /// Description of the problem
+        problem: &'static str,
+    },
+
+    /// The linked program outgrows the limits of a chunk.
+    Bytecode(BytecodeError),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateSymbol { symbol, first, second } if first == second => {
+                write!(f, "`{symbol}` is defined twice in `{first}`")
+            }
+            Self::DuplicateSymbol { symbol, first, second } => {
+                write!(f, "`{symbol}` is defined in both `{first}` and `{second}`")
+            }
+            Self::UndefinedSymbol { symbol, module } => {
+                write!(f, "`{symbol}`, used in `{module}`, is not defined in any module")
+            }
+            Self::Malformed { module, problem } => write!(f, "module `{module}` is malformed: {problem}"),
+            Self::Bytecode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bytecode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<BytecodeError> for LinkError {
+    fn from(err: BytecodeError) -> Self {
+        Self::Bytecode(err)
+    }
+}
+
+/// Errors reading a bytecode file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file cannot be read.
+    Io(std::io::Error),
+
+    /// The file does not start with the bytecode magic.
+    NotBytecode,
+
+    /// The file was written by another version of the format.
+    UnsupportedVersion(u16),
+
+    /// The file does not match its checksum.
+    ChecksumMismatch {
+        /// Checksum stored in the file
+        expected: u32,
+        /// Checksum of its contents
+        found: u32,
+    },
+
+    /// The file ends in the middle of a section.
+    Truncated,
+
+    /// A section is inconsistent.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "cannot read bytecode: {err}"),
+            Self::NotBytecode => write!(f, "not a bytecode file"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported bytecode version {version}"),
+            Self::ChecksumMismatch { expected, found } => {
+                write!(f, "bytecode is damaged: checksum {found:#010x} does not match {expected:#010x}")
+            }
+            Self::Truncated => write!(f, "bytecode is truncated"),
+            Self::Malformed(problem) => write!(f, "bytecode is malformed: {problem}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// What went wrong while running bytecode.
+#[derive(Debug, Clone)]
+pub enum VmErrorKind {
+    /// A byte that is not an opcode was executed.
+    InvalidOpcode(u8),
+
+    /// An instruction ended before its operands, or control left the chunk.
+    Truncated,
+
+    /// An instruction referred to a constant the chunk does not have, or of
+    /// the wrong kind.
+    BadConstant(u16),
+
+    /// An instruction referred to a variable the closure did not capture.
+    BadUpvalue(u8),
+
+    /// An instruction referred to a register outside the frame.
+    BadRegister(u8),
+
+    /// An instruction named an intrinsic the VM does not have.
+    BadIntrinsic(u8),
+
+    /// An instruction popped more values than the frame has.
+    StackUnderflow,
+
+    /// Calls nested deeper than the VM allows.
+    StackOverflow,
+
+    /// A global was read or assigned before it was defined.
+    UndefinedGlobal(String),
+
+    /// An operation received a value of the wrong kind.
+    TypeMismatch {
+        /// Kind of value the operation requires
+        expected: &'static str,
+        /// Kind of value it received
+        found: &'static str,
+    },
+
+    /// An integer was divided by zero.
+    DivisionByZero,
+
+    /// Integer arithmetic overflowed.
+    IntegerOverflow,
+
+    /// A value that is not a function was called.
+    NotCallable(&'static str),
+
+    /// A function was called with the wrong number of arguments.
+    ArityMismatch {
+        /// Name of the function
+        function: String,
+        /// Number of parameters
+        expected: u8,
+        /// Number of arguments
+        found: usize,
+    },
+
+    /// An object has no field of a name.
+    NoSuchField(String),
+
+    /// An index was past the end of a tuple or an array.
+    IndexOutOfBounds {
+        /// The index
+        index: i64,
+        /// Number of elements
+        len: usize,
+    },
+
+    /// A dictionary has no entry for a key.
+    MissingKey(String),
+
+    /// A value does not respond to a message.
+    DoesNotRespond {
+        /// Name of the selector
+        selector: String,
+        /// Kind of the receiver
+        receiver: &'static str,
+    },
+
+    /// A value cannot be passed to or returned from native code.
+    NotNative(&'static str),
+
+    /// The runtime rejected a message.
+    Runtime(oxidec::Error),
+
+    /// A value was thrown.
+    Thrown(Value),
+
+    /// An assertion did not hold.
+    AssertionFailed,
+
+    /// An expectation of a test did not hold, with why.
+    ExpectationFailed(String),
+
+    /// `nil` was unwrapped.
+    NilUnwrap,
+
+    /// A task was suspended outside its own code: by a host function the
+    /// host called back into, or outside any task.
+    CannotSuspend,
+
+    /// Every task left waits for a value or a task that will never come.
+    Deadlock {
+        /// Number of tasks waiting
+        blocked: usize,
+    },
+
+    /// A task that was joined raised an error it did not catch.
+    TaskFailed(Rc<VmError>),
+
+    /// Bytecode used a capability the VM's sandbox denies.
+    Sandboxed(Capability),
+}
+
+impl VmErrorKind {
+    /// Whether handlers may catch the error. Errors showing that the
+    /// bytecode itself is broken cannot be caught, nor can sandbox
+    /// violations, so that bytecode cannot probe what it is denied.
+    #[must_use]
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            Self::InvalidOpcode(_)
+                | Self::Truncated
+                | Self::BadConstant(_)
+                | Self::BadUpvalue(_)
+                | Self::BadRegister(_)
+                | Self::BadIntrinsic(_)
+                | Self::StackUnderflow
+                | Self::Sandboxed(_)
+        )
+    }
+}
+
+impl fmt::Display for VmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOpcode(byte) => write!(f, "invalid opcode {byte:#04x}"),
+            Self::Truncated => write!(f, "instruction runs past the end of its chunk"),
+            Self::BadConstant(index) => write!(f, "invalid constant {index}"),
+            Self::BadUpvalue(index) => write!(f, "invalid upvalue {index}"),
+            Self::BadRegister(index) => write!(f, "invalid register r{index}"),
+            Self::BadIntrinsic(id) => write!(f, "invalid intrinsic {id}"),
+            Self::StackUnderflow => write!(f, "stack underflow"),
+            Self::StackOverflow => write!(f, "stack overflow"),
+            Self::UndefinedGlobal(name) => write!(f, "undefined global `{name}`"),
+            Self::TypeMismatch { expected, found } => write!(f, "expected {expected}, found {found}"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::IntegerOverflow => write!(f, "integer overflow"),
+            Self::NotCallable(kind) => write!(f, "{kind} is not callable"),
+            Self::ArityMismatch { function, expected, found } => {
+                write!(f, "`{function}` takes {expected} arguments, but {found} were given")
+            }
+            Self::NoSuchField(name) => write!(f, "no field `{name}`"),
+            Self::IndexOutOfBounds { index, len } => write!(f, "index {index} is out of bounds for an array of length {len}"),
+            Self::MissingKey(key) => write!(f, "no entry for key {key}"),
+            Self::DoesNotRespond { selector, receiver } => write!(f, "{receiver} does not respond to `{selector}`"),
+            Self::NotNative(kind) => write!(f, "{kind} cannot cross into native code"),
+            Self::Runtime(err) => write!(f, "runtime error: {err}"),
+            Self::Thrown(value) => write!(f, "uncaught error: {value}"),
+            Self::AssertionFailed => write!(f, "assertion failed"),
+            Self::ExpectationFailed(message) => write!(f, "{message}"),
+            Self::NilUnwrap => write!(f, "unwrapped a nil value"),
+            Self::CannotSuspend => write!(f, "only a task's own code can suspend it"),
+            Self::Deadlock { blocked: 1 } => write!(f, "deadlock: a task waits for something that never comes"),
+            Self::Deadlock { blocked } => write!(f, "deadlock: {blocked} tasks wait for something that never comes"),
+            Self::TaskFailed(error) => write!(f, "joined task failed: {error}"),
+            Self::Sandboxed(capability) => write!(f, "{capability} is denied by the sandbox"),
+        }
+    }
+}
+
+/// Where a call was when an error was raised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// Name of the function
+    pub function: String,
+    /// Offset of the instruction being executed
+    pub offset: usize,
+    /// Source the instruction was compiled from
+    pub span: Option<Span>,
+    /// Named local variables in scope, with their values
+    pub locals: Vec<(String, Value)>,
+}
+
+/// An error raised while running bytecode.
+#[derive(Debug, Clone)]
+pub struct VmError {
+    /// What went wrong
+    pub kind: VmErrorKind,
+    /// Calls active when it went wrong, innermost first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl VmError {
+    /// Source of the instruction that raised the error, if known.
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        self.trace.first().and_then(|frame| frame.span)
+    }
+
+    /// Offset of the instruction that raised the error.
+    #[must_use]
+    pub fn offset(&self) -> Option<usize> {
+        self.trace.first().map(|frame| frame.offset)
+    }
+
+    /// Describe the error and every call in its trace, with the local
+    /// variables each had in scope.
+    #[must_use]
+    pub fn backtrace(&self) -> String {
+        let mut out = self.kind.to_string();
+        for frame in &self.trace {
+            out.push_str(&format!("\n  in `{}` at offset {}", frame.function, frame.offset));
+            if let Some(span) = frame.span {
+                out.push_str(&format!(" (line {})", span.start_line));
+            }
+            for (name, value) in &frame.locals {
+                out.push_str(&format!("\n      {name} = {value}"));
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(frame) = self.trace.first() {
+            write!(f, " in `{}` at offset {}", frame.function, frame.offset)?;
+            if let Some(span) = frame.span {
+                write!(f, " (line {})", span.start_line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// Result type for building bytecode.
+pub type Result<T> = std::result::Result<T, BytecodeError>;
@@ -0,0 +1,352 @@
+//! Garbage collection.
+//!
+//! Closures, the variables they capture and instances are reference
+//! counted, so they are freed when the last reference to them goes, unless
+//! they refer to each other in a cycle: a closure stored in a variable it
+//! captured, or instances whose fields refer to each other. The
+//! [`Collector`] frees cycles by marking every value reachable from the
+//! VM's roots, then emptying the captured variables and instances that were
+//! not marked. Emptying a container breaks the cycles through it, and
+//! reference counting frees the rest.
+//!
+//! The roots are the stack, the globals, the running closures and the
+//! instances whose runtime object is retained outside the VM, since native
+//! code may still hand those back.
+//!
+//! Containers are tracked when they are made. With the nursery on, most
+//! collections are minor: they only sweep the containers made since the
+//! last collection, and only mark through older ones that were changed
+//! since then. Containers that survive a collection become old, and every
+//! [`MINOR_PER_MAJOR`]th collection is a major one that sweeps them too.
+
+use crate::error::VmErrorKind;
+use crate::value::{Instance, Upvalue, Value};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+
+/// Minor collections between two major ones, with the nursery on.
+pub const MINOR_PER_MAJOR: usize = 8;
+
+/// A container that may be part of a cycle.
+#[derive(Debug, Clone)]
+enum Candidate {
+    Upvalue(Weak<RefCell<Upvalue>>),
+    Object(Weak<Instance>),
+}
+
+impl Candidate {
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Upvalue(upvalue) => upvalue.strong_count() > 0,
+            Self::Object(instance) => instance.strong_count() > 0,
+        }
+    }
+}
+
+/// Finds and frees unreachable cycles of VM values.
+#[derive(Debug, Default)]
+pub struct Collector {
+    /// Containers made since the last collection, by address
+    young: Vec<(usize, Candidate)>,
+    /// Containers that survived a collection, by address
+    old: Vec<(usize, Candidate)>,
+    /// Addresses of the old containers
+    old_addresses: HashSet<usize>,
+    /// Old containers changed since the last collection, by address
+    remembered: Vec<(usize, Candidate)>,
+    /// Whether collections are minor until a major one is due
+    nursery: bool,
+    /// Minor collections since the last major one
+    minors: usize,
+    /// Containers made since the last collection
+    made: usize,
+    /// Young containers that make a collection due
+    threshold: Option<usize>,
+}
+
+impl Collector {
+    /// Create a collector that never becomes due.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make a collection due once `threshold` containers have been made
+    /// since the last one, or never if `None`.
+    pub fn set_threshold(&mut self, threshold: Option<usize>) {
+        self.threshold = threshold;
+    }
+
+    /// Turn minor collections of the containers made since the last
+    /// collection on or off.
+    pub fn set_nursery(&mut self, nursery: bool) {
+        self.nursery = nursery;
+        self.minors = 0;
+    }
+
+    /// Number of tracked containers that are still alive.
+    #[must_use]
+    pub fn tracked(&self) -> usize {
+        self.young.iter().chain(&self.old).filter(|(_, candidate)| candidate.is_alive()).count()
+    }
+
+    /// Whether enough containers were made to make a collection due.
+    #[must_use]
+    pub fn is_due(&self) -> bool {
+        self.threshold.is_some_and(|threshold| self.made >= threshold)
+    }
+
+    /// Track a captured variable that was just made.
+    pub(crate) fn track_upvalue(&mut self, upvalue: &Rc<RefCell<Upvalue>>) {
+        self.young.push((address(upvalue), Candidate::Upvalue(Rc::downgrade(upvalue))));
+        self.made += 1;
+    }
+
+    /// Track an instance that was just made.
+    pub(crate) fn track_instance(&mut self, instance: &Rc<Instance>) {
+        self.young.push((address(instance), Candidate::Object(Rc::downgrade(instance))));
+        self.made += 1;
+    }
+
+    /// Note that a captured variable was assigned.
+    pub(crate) fn write_upvalue(&mut self, upvalue: &Rc<RefCell<Upvalue>>) {
+        self.remember(address(upvalue), || Candidate::Upvalue(Rc::downgrade(upvalue)));
+    }
+
+    /// Note that a field of an instance was assigned.
+    pub(crate) fn write_instance(&mut self, instance: &Rc<Instance>) {
+        self.remember(address(instance), || Candidate::Object(Rc::downgrade(instance)));
+    }
+
+    /// Remember an old container that changed, since it may now refer to
+    /// young ones.
+    fn remember(&mut self, address: usize, candidate: impl FnOnce() -> Candidate) {
+        if self.old_addresses.contains(&address) && !self.remembered.iter().any(|(old, _)| *old == address) {
+            self.remembered.push((address, candidate()));
+        }
+    }
+
+    /// Collect, minor or major as is due, returning how many containers
+    /// were emptied.
+    pub(crate) fn collect_due(&mut self, roots: impl IntoIterator<Item = Value>) -> usize {
+        if self.nursery && self.minors < MINOR_PER_MAJOR {
+            self.minors += 1;
+            self.collect(roots, false)
+        } else {
+            self.minors = 0;
+            self.collect(roots, true)
+        }
+    }
+
+    /// Empty the tracked containers not reachable from `roots`, returning
+    /// how many were emptied. A minor collection only sweeps the young
+    /// containers.
+    ///
+    /// Every value still in use must be reachable from a root.
+    pub(crate) fn collect(&mut self, roots: impl IntoIterator<Item = Value>, major: bool) -> usize {
+        let mut old = std::mem::take(&mut self.old);
+        let remembered = std::mem::take(&mut self.remembered);
+        let mut roots: Vec<Value> = roots.into_iter().collect();
+        // Instances native code holds may come back, with their fields
+        for (_, candidate) in self.young.iter().chain(&old) {
+            if let Candidate::Object(instance) = candidate
+                && let Some(instance) = instance.upgrade()
+                && instance.object.refcount() > 1
+            {
+                roots.push(Value::Object(instance));
+            }
+        }
+        let marked = if major {
+            mark(roots, &HashSet::new())
+        } else {
+            // Old containers are assumed reachable, so only the changed ones
+            // can lead to young containers
+            for (_, candidate) in &remembered {
+                roots.extend(contents(candidate));
+            }
+            mark(roots, &self.old_addresses)
+        };
+
+        let mut freed = 0;
+        let mut swept = std::mem::take(&mut self.young);
+        if major {
+            swept.append(&mut old);
+        }
+        for (address, candidate) in swept {
+            match sweep(&candidate, marked.contains(&address)) {
+                Sweep::Freed => freed += 1,
+                Sweep::Kept => old.push((address, candidate)),
+                Sweep::Dead => {}
+            }
+        }
+        old.retain(|(_, candidate)| candidate.is_alive());
+        self.old_addresses = old.iter().map(|(address, _)| *address).collect();
+        self.old = old;
+        self.made = 0;
+        freed
+    }
+}
+
+/// What sweeping did to a container.
+enum Sweep {
+    /// It was unreachable, and was emptied
+    Freed,
+    /// It is still in use
+    Kept,
+    /// It was already freed
+    Dead,
+}
+
+/// Empty a container if it was not marked. Captured variables still on the
+/// stack are left alone, since they hold no values.
+fn sweep(candidate: &Candidate, marked: bool) -> Sweep {
+    // Contents are dropped after the borrow ends, since dropping them may
+    // reach this container again
+    match candidate {
+        Candidate::Upvalue(upvalue) => match upvalue.upgrade() {
+            Some(upvalue) if !marked && matches!(*upvalue.borrow(), Upvalue::Closed(_)) => {
+                drop(upvalue.replace(Upvalue::Closed(Value::Nil)));
+                Sweep::Freed
+            }
+            Some(_) => Sweep::Kept,
+            None => Sweep::Dead,
+        },
+        Candidate::Object(instance) => match instance.upgrade() {
+            Some(instance) if !marked => {
+                drop(std::mem::take(&mut *instance.fields.borrow_mut()));
+                Sweep::Freed
+            }
+            Some(_) => Sweep::Kept,
+            None => Sweep::Dead,
+        },
+    }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc).cast::<()>() as usize
+}
+
+/// The values a container refers to.
+fn contents(candidate: &Candidate) -> Vec<Value> {
+    match candidate {
+        Candidate::Upvalue(upvalue) => match upvalue.upgrade().map(|upvalue| upvalue.borrow().clone()) {
+            Some(Upvalue::Closed(value)) => vec![value],
+            _ => Vec::new(),
+        },
+        Candidate::Object(instance) => instance
+            .upgrade()
+            .map(|instance| instance.fields.borrow().iter().map(|(_, value)| value.clone()).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Find the addresses of the containers reachable from `roots`, without
+/// marking through the containers in `skip`.
+fn mark(roots: Vec<Value>, skip: &HashSet<usize>) -> HashSet<usize> {
+    let mut marked = HashSet::new();
+    let mut stack = roots;
+    while let Some(value) = stack.pop() {
+        match &value {
+            Value::Closure(closure) if marked.insert(address(closure)) => {
+                for upvalue in &closure.upvalues {
+                    let address = address(upvalue);
+                    if !skip.contains(&address)
+                        && marked.insert(address)
+                        && let Upvalue::Closed(value) = &*upvalue.borrow()
+                    {
+                        stack.push(value.clone());
+                    }
+                }
+            }
+            Value::Object(instance) if !skip.contains(&address(instance)) && marked.insert(address(instance)) => {
+                stack.extend(instance.fields.borrow().iter().map(|(_, value)| value.clone()));
+            }
+            Value::Error(error) if marked.insert(address(error)) => {
+                stack.extend(error.trace.iter().flat_map(|frame| frame.locals.iter().map(|(_, value)| value.clone())));
+                if let VmErrorKind::Thrown(value) = &error.kind {
+                    stack.push(value.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    marked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::{Class, Object};
+
+    fn instance(collector: &mut Collector, class: &Class) -> Rc<Instance> {
+        let instance = Rc::new(Instance::new(Object::new(class).unwrap()));
+        collector.track_instance(&instance);
+        instance
+    }
+
+    #[test]
+    fn test_collect_frees_unreachable_cycles() {
+        let class = Class::new_root("GcCycle").unwrap();
+        let mut collector = Collector::new();
+        collector.set_threshold(Some(3));
+
+        // Two instances referring to each other, and one referring to itself
+        // that a root reaches
+        let (a, b) = (instance(&mut collector, &class), instance(&mut collector, &class));
+        a.set_field("next", Value::Object(Rc::clone(&b)));
+        b.set_field("next", Value::Object(Rc::clone(&a)));
+        let weak = Rc::downgrade(&a);
+        drop((a, b));
+        assert!(!collector.is_due());
+
+        let root = instance(&mut collector, &class);
+        root.set_field("me", Value::Object(Rc::clone(&root)));
+        assert!(collector.is_due());
+
+        // Emptying one instance of the pair frees the other
+        assert_eq!(collector.collect([Value::Object(Rc::clone(&root))], true), 1);
+        assert_eq!(weak.strong_count(), 0);
+        assert!(root.field("me").is_some());
+        assert_eq!(collector.tracked(), 1);
+        assert!(!collector.is_due());
+
+        // Instances retained by native code are roots
+        let held = instance(&mut collector, &class);
+        held.set_field("me", Value::Object(Rc::clone(&held)));
+        let object = held.object.clone();
+        assert_eq!(collector.collect([Value::Object(Rc::clone(&root))], true), 0);
+        assert!(held.field("me").is_some());
+        drop(object);
+        assert_eq!(collector.collect([Value::Object(Rc::clone(&root))], true), 1);
+        assert!(held.field("me").is_none());
+    }
+
+    #[test]
+    fn test_minor_collections_sweep_the_nursery() {
+        let class = Class::new_root("GcNursery").unwrap();
+        let mut collector = Collector::new();
+        collector.set_nursery(true);
+
+        let old = instance(&mut collector, &class);
+        old.set_field("me", Value::Object(Rc::clone(&old)));
+        assert_eq!(collector.collect_due([Value::Object(Rc::clone(&old))]), 0);
+
+        // An old container that became unreachable waits for a major
+        // collection, while a young one reached only through a changed old
+        // container survives
+        let young = instance(&mut collector, &class);
+        young.set_field("me", Value::Object(Rc::clone(&young)));
+        old.set_field("young", Value::Object(Rc::clone(&young)));
+        collector.write_instance(&old);
+        let weak = Rc::downgrade(&young);
+        drop((old, young));
+        for _ in 0..MINOR_PER_MAJOR - 1 {
+            assert_eq!(collector.collect_due([]), 0);
+        }
+        assert_eq!(collector.tracked(), 2);
+        assert_eq!(collector.collect_due([]), 2);
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(collector.tracked(), 0);
+    }
+}
@@ -2,17 +2,68 @@
 //!
 //! This crate provides bytecode compilation and execution, including:
 //! - Instruction set definition
-//! - AST to bytecode compilation
+//! - IR to bytecode compilation
 //! - Bytecode virtual machine
 //! - Debug information and disassembly
 //!
-//! **Phase:** 8 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 8 - Bytecode
+//! **Status:** In Progress
 
 #![warn(missing_docs)]
 
-// Module declarations will be added during Phase 8 implementation:
-// pub mod opcodes;
-// pub mod compiler;
-// pub mod vm;
-// pub mod chunk;
+// Bytecode errors
+pub mod error;
+
+// Stack-based instruction set
+pub mod opcodes;
+
+// Instruction bytes, constant pools and line tables
+pub mod chunk;
+
+// Values on the VM stack
+pub mod value;
+
+// Virtual machine
+pub mod vm;
+
+// Register-based instruction set
+pub mod register;
+
+// Inline caches for sends and field accesses
+pub mod cache;
+
+// Garbage collection of cycles among VM values
+pub mod gc;
+
+// Disassembly of chunks
+pub mod disasm;
+
+// The `.oxb` bytecode file format
+pub mod oxb;
+
+// Compilation of the IR to stack bytecode
+pub mod compiler;
+
+// Linking of compiled modules into one program
+pub mod link;
+
+// The standard builtins of bytecode programs
+pub mod builtins;
+
+// Re-exports for convenience
+pub use cache::InlineCache;
+pub use compiler::compile;
+pub use chunk::{Capture, Chunk, Constant, Encoding, Function, Handler, HandlerKind, Local};
+pub use disasm::disassemble;
+pub use error::{BytecodeError, LinkError, LoadError, Result, VmError, VmErrorKind};
+pub use gc::Collector;
+pub use link::link;
+pub use opcodes::OpCode;
+pub use oxidec::runtime::sandbox::{Capability, Sandbox};
+pub use oxidex_codegen::intrinsics::Intrinsic;
+pub use register::RegOp;
+pub use value::{Native, NativeFn, Value};
+pub use vm::{
+    ChannelId, Compiler, NativeCode, NativeHelper, NativeStatus, Profiler, SendHelper, SendSite, TaskId, Tracer, Vm,
+    native_helper,
+};
@@ -0,0 +1,385 @@
+//! Linking of compiled modules into one program.
+//!
+//! [`compile`](crate::compile) turns each module of a program into a script
+//! defining the module's functions and externs as globals. [`link`] merges
+//! the scripts into one, which runs the definitions of every module in
+//! order and can be saved as a single [`.oxb`](crate::oxb) file:
+//!
+//! - The instructions of the scripts are concatenated, and the constants
+//!   they refer to moved into the one pool of the linked script, where
+//!   equal literals and names share an entry.
+//! - Strings are interned across modules, so every function of the program
+//!   naming the same global, field or selector shares one string.
+//! - Every global a module reads or assigns must be defined by a module,
+//!   or provided by the host, like the natives it defines on the VM.
+//!   References are resolved by name when they run, as in a single module.
+//! - A global defined by two modules, or twice by one, is an error, unless
+//!   every definition is the same `extern` declaration.
+
+use crate::chunk::{Chunk, Constant, Encoding, Function, Handler, Local};
+use crate::compiler::SCRIPT;
+use crate::error::LinkError;
+use crate::opcodes::OpCode;
+use oxidex_codegen::ir::Extern;
+use oxidex_syntax::Span;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Location of the instructions the linker adds.
+const NO_SPAN: Span = Span::new(0, 0, 0, 0, 0, 0);
+
+/// Link the scripts of named modules into one script defining the globals
+/// of them all, checking every global they use is defined by a module or
+/// named in `host`.
+///
+/// # Errors
+///
+/// Returns a [`LinkError`] if a global is defined twice or not at all, a
+/// script is not one [`compile`](crate::compile) could have produced, or
+/// the linked script outgrows the limits of a chunk.
+pub fn link(modules: &[(&str, &Function)], host: &[&str]) -> Result<Function, LinkError> {
+    let mut linker = Linker::default();
+    for &(module, script) in modules {
+        linker.script(module, script)?;
+    }
+    for (symbol, module) in &linker.references {
+        if !linker.definitions.contains_key(symbol) && !host.contains(&&**symbol) {
+            return Err(LinkError::UndefinedSymbol { symbol: symbol.to_string(), module: module.to_string() });
+        }
+    }
+    linker.chunk.write_op(OpCode::Nil, NO_SPAN);
+    linker.chunk.write_op(OpCode::Return, NO_SPAN);
+    Ok(Function { name: SCRIPT.to_string(), arity: 0, captures: Vec::new(), chunk: linker.chunk })
+}
+
+/// A global a module defines.
+struct Definition<'m> {
+    /// Name of the module
+    module: &'m str,
+    /// The declaration, if the global is an extern
+    external: Option<Rc<Extern>>,
+}
+
+/// Merges scripts into one.
+#[derive(Default)]
+struct Linker<'m> {
+    /// The linked script
+    chunk: Chunk,
+    /// Strings interned so far
+    strings: HashSet<Rc<str>>,
+    /// Globals defined so far, by name
+    definitions: HashMap<Rc<str>, Definition<'m>>,
+    /// Globals read or assigned, and the module doing so, in order
+    references: Vec<(Rc<str>, &'m str)>,
+}
+
+impl<'m> Linker<'m> {
+    /// Append the instructions of a module's script, except its final
+    /// `NIL, RETURN`.
+    fn script(&mut self, module: &'m str, script: &Function) -> Result<(), LinkError> {
+        let malformed = |problem| LinkError::Malformed { module: module.to_string(), problem };
+        let chunk = &script.chunk;
+        if chunk.encoding() != Encoding::Stack {
+            return Err(malformed("its script is not stack bytecode"));
+        }
+        let end = chunk.len().checked_sub(2).ok_or_else(|| malformed("its script does not return"))?;
+        if chunk.code()[end..] != [OpCode::Nil.into(), OpCode::Return.into()] {
+            return Err(malformed("its script does not end by returning `nil`"));
+        }
+
+        let base = self.chunk.len();
+        // The extern the previous instruction pushed, which a definition
+        // right after it defines
+        let mut external = None;
+        let mut offset = 0;
+        while offset < end {
+            let op = decode(module, chunk, offset, end)?;
+            let width = 1 + op.operand_width();
+            let span = chunk.span(offset).unwrap_or(NO_SPAN);
+            if op == OpCode::Return {
+                return Err(malformed("its script returns before its end"));
+            }
+            if !has_constant(op) {
+                for &byte in &chunk.code()[offset..offset + width] {
+                    self.chunk.write(byte, span);
+                }
+                external = None;
+                offset += width;
+                continue;
+            }
+
+            let constant = self.constant(module, chunk, offset)?;
+            match op {
+                OpCode::DefineGlobal => self.define(module, global(module, &constant)?, external.take())?,
+                OpCode::GetGlobal | OpCode::SetGlobal => self.references.push((global(module, &constant)?, module)),
+                _ => {}
+            }
+            external = match (op, &constant) {
+                (OpCode::Constant, Constant::Extern(declaration)) => Some(Rc::clone(declaration)),
+                _ => None,
+            };
+            let index = self.chunk.add_constant(constant)?;
+            self.chunk.write_op(op, span);
+            self.chunk.write_u16(index, span);
+            for &byte in &chunk.code()[offset + 3..offset + width] {
+                self.chunk.write(byte, span);
+            }
+            offset += width;
+        }
+
+        for handler in chunk.handlers() {
+            self.chunk.add_handler(Handler {
+                start: base + handler.start.min(end),
+                end: base + handler.end.min(end),
+                target: base + handler.target,
+                ..*handler
+            });
+        }
+        for local in chunk.locals() {
+            let name = self.intern(&local.name);
+            self.chunk.add_local(Local {
+                name,
+                start: base + local.start.min(end),
+                end: base + local.end.min(end),
+                ..local.clone()
+            });
+        }
+        Ok(())
+    }
+
+    /// The constant the instruction at `offset` refers to, relinked.
+    fn constant(&mut self, module: &'m str, chunk: &Chunk, offset: usize) -> Result<Constant, LinkError> {
+        let constant = chunk
+            .read_u16(offset + 1)
+            .and_then(|index| chunk.constant(index))
+            .ok_or_else(|| LinkError::Malformed { module: module.to_string(), problem: "invalid constant" })?;
+        self.relink(module, constant)
+    }
+
+    /// A constant with its strings interned, and the globals its functions
+    /// use recorded.
+    fn relink(&mut self, module: &'m str, constant: &Constant) -> Result<Constant, LinkError> {
+        Ok(match constant {
+            Constant::String(text) => Constant::String(self.intern(text)),
+            Constant::Function(function) => Constant::Function(Rc::new(self.function(module, function)?)),
+            Constant::Int(_) | Constant::Float(_) | Constant::Extern(_) => constant.clone(),
+        })
+    }
+
+    /// A function nested in a script, with its strings interned and the
+    /// globals it uses recorded. Its constants keep their indices, so its
+    /// code is unchanged.
+    fn function(&mut self, module: &'m str, function: &Function) -> Result<Function, LinkError> {
+        let malformed = |problem| LinkError::Malformed { module: module.to_string(), problem };
+        let chunk = &function.chunk;
+        if chunk.encoding() != Encoding::Stack {
+            return Err(malformed("a function is not stack bytecode"));
+        }
+        let mut offset = 0;
+        while offset < chunk.len() {
+            let op = decode(module, chunk, offset, chunk.len())?;
+            if matches!(op, OpCode::GetGlobal | OpCode::SetGlobal) {
+                let constant = self.constant(module, chunk, offset)?;
+                self.references.push((global(module, &constant)?, module));
+            }
+            offset += 1 + op.operand_width();
+        }
+
+        let constants =
+            chunk.constants().iter().map(|constant| self.relink(module, constant)).collect::<Result<_, _>>()?;
+        let locals =
+            chunk.locals().iter().map(|local| Local { name: self.intern(&local.name), ..local.clone() }).collect();
+        let chunk = Chunk::from_parts(
+            chunk.code().to_vec(),
+            constants,
+            chunk.line_runs().collect(),
+            chunk.handlers().to_vec(),
+            chunk.encoding(),
+            locals,
+        )
+        .ok_or_else(|| malformed("a function's tables are inconsistent"))?;
+        Ok(Function { name: function.name.clone(), arity: function.arity, captures: function.captures.clone(), chunk })
+    }
+
+    /// Record the definition of a global, unless it repeats an identical
+    /// extern declaration.
+    fn define(&mut self, module: &'m str, symbol: Rc<str>, external: Option<Rc<Extern>>) -> Result<(), LinkError> {
+        if let Some(first) = self.definitions.get(&symbol) {
+            if first.external.is_some() && first.external == external {
+                return Ok(());
+            }
+            return Err(LinkError::DuplicateSymbol {
+                symbol: symbol.to_string(),
+                first: first.module.to_string(),
+                second: module.to_string(),
+            });
+        }
+        self.definitions.insert(symbol, Definition { module, external });
+        Ok(())
+    }
+
+    /// The program's copy of a string.
+    fn intern(&mut self, text: &Rc<str>) -> Rc<str> {
+        if let Some(interned) = self.strings.get(text) {
+            return Rc::clone(interned);
+        }
+        self.strings.insert(Rc::clone(text));
+        Rc::clone(text)
+    }
+}
+
+/// Decode the opcode at `offset`, checking its operands end by `end`.
+fn decode(module: &str, chunk: &Chunk, offset: usize, end: usize) -> Result<OpCode, LinkError> {
+    let malformed = |problem| LinkError::Malformed { module: module.to_string(), problem };
+    let op = OpCode::try_from(chunk.code()[offset]).map_err(|_| malformed("invalid opcode"))?;
+    if offset + 1 + op.operand_width() > end {
+        return Err(malformed("instruction runs past the end of its chunk"));
+    }
+    Ok(op)
+}
+
+/// Whether the operand of an opcode starts with a constant index.
+fn has_constant(op: OpCode) -> bool {
+    op.operand_width() >= 2 && !op.is_jump()
+}
+
+/// The name of the global a constant names.
+fn global(module: &str, constant: &Constant) -> Result<Rc<str>, LinkError> {
+    match constant {
+        Constant::String(name) => Ok(Rc::clone(name)),
+        _ => Err(LinkError::Malformed { module: module.to_string(), problem: "a global is named by a non-string" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+    use crate::value::Value;
+    use crate::vm::Vm;
+    use oxidex_codegen::ir::parse_module;
+
+    fn module(text: &str) -> Function {
+        compile(&parse_module(text).unwrap()).unwrap()
+    }
+
+    const SQUARE: &str = r#"
+extern "abs" "ii"
+
+fn "square"(%0: int) -> int {
+bb0:
+    %1: int = binary mul %0, %0
+    return %1
+}
+"#;
+
+    const MAIN: &str = r#"
+extern "abs" "ii"
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = call "square"(%0)
+    %2: int = call "cube"(%0)
+    %3: int = binary add %1, %2
+    return %3
+}
+"#;
+
+    const CUBE: &str = r#"
+fn "cube"(%0: int) -> int {
+bb0:
+    %1: int = call "square"(%0)
+    %2: int = binary mul %1, %0
+    return %2
+}
+"#;
+
+    #[test]
+    fn test_link_modules() {
+        let (square, main, cube) = (module(SQUARE), module(MAIN), module(CUBE));
+        let program = link(&[("square", &square), ("main", &main), ("cube", &cube)], &[]).unwrap();
+
+        let mut vm = Vm::new();
+        vm.run(Rc::new(program.clone())).unwrap();
+        let entry = vm.global("main").unwrap();
+        assert_eq!(vm.call(entry, vec![Value::Int(3)]).unwrap(), Value::Int(9 + 27));
+
+        // Every function naming `square` shares the string defining it
+        let definition = program.chunk.constants().iter().find_map(|constant| match constant {
+            Constant::String(name) if &**name == "square" => Some(Rc::clone(name)),
+            _ => None,
+        });
+        let functions: Vec<_> = program
+            .chunk
+            .constants()
+            .iter()
+            .filter_map(|constant| match constant {
+                Constant::Function(function) => Some(Rc::clone(function)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(functions.len(), 3);
+        for function in &functions[1..] {
+            let used = function.chunk.constants().iter().find_map(|constant| match constant {
+                Constant::String(name) if &**name == "square" => Some(Rc::clone(name)),
+                _ => None,
+            });
+            assert!(Rc::ptr_eq(used.as_ref().unwrap(), definition.as_ref().unwrap()));
+        }
+
+        // The linked program saves and loads as one file
+        let loaded = crate::oxb::load(&crate::oxb::save(&program)).unwrap();
+        assert_eq!(*loaded, program);
+    }
+
+    #[test]
+    fn test_link_rejects_duplicate_symbols() {
+        let (square, cube) = (module(SQUARE), module(CUBE));
+        let err = link(&[("square", &square), ("cube", &cube), ("again", &square)], &[]).unwrap_err();
+        assert_eq!(
+            err,
+            LinkError::DuplicateSymbol {
+                symbol: "square".to_string(),
+                first: "square".to_string(),
+                second: "again".to_string(),
+            }
+        );
+        assert_eq!(err.to_string(), "`square` is defined in both `square` and `again`");
+
+        // Declaring the same extern in several modules is not a duplicate
+        let other = module("extern \"abs\" \"ii\"\n");
+        assert!(link(&[("square", &square), ("other", &other)], &[]).is_ok());
+        let conflicting = module("extern \"abs\" \"qq\"\n");
+        let err = link(&[("square", &square), ("conflicting", &conflicting)], &[]).unwrap_err();
+        assert!(matches!(err, LinkError::DuplicateSymbol { symbol, .. } if symbol == "abs"));
+    }
+
+    #[test]
+    fn test_link_rejects_undefined_symbols() {
+        let (square, main) = (module(SQUARE), module(MAIN));
+        let err = link(&[("square", &square), ("main", &main)], &[]).unwrap_err();
+        assert_eq!(err, LinkError::UndefinedSymbol { symbol: "cube".to_string(), module: "main".to_string() });
+        assert_eq!(err.to_string(), "`cube`, used in `main`, is not defined in any module");
+
+        // The host may provide it
+        let program = link(&[("square", &square), ("main", &main)], &["cube"]).unwrap();
+        let mut vm = Vm::new();
+        vm.define_native("cube", 1, |_, args| match args {
+            [Value::Int(n)] => Ok(Value::Int(n * n * n)),
+            _ => unreachable!(),
+        });
+        vm.run(Rc::new(program)).unwrap();
+        let entry = vm.global("main").unwrap();
+        assert_eq!(vm.call(entry, vec![Value::Int(2)]).unwrap(), Value::Int(4 + 8));
+    }
+
+    #[test]
+    fn test_link_rejects_malformed_scripts() {
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::True, NO_SPAN);
+        chunk.write_op(OpCode::Return, NO_SPAN);
+        let script = Function { name: SCRIPT.to_string(), arity: 0, captures: Vec::new(), chunk };
+        let err = link(&[("odd", &script)], &[]).unwrap_err();
+        assert_eq!(err.to_string(), "module `odd` is malformed: its script does not end by returning `nil`");
+    }
+}
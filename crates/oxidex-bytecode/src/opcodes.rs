@@ -0,0 +1,286 @@
+//! The instruction set.
+//!
+//! The VM is a stack machine: instructions pop their inputs off the value
+//! stack and push their result. Each instruction is one opcode byte followed
+//! by its operands, which are little-endian and of a fixed width per opcode
+//! (see [`OpCode::operand_width`]):
+//!
+//! - Constant, global, field, selector and function operands are `u16`
+//!   indices into the chunk's constant pool.
+//! - Local operands are `u8` slots relative to the frame pointer, so a
+//!   function has at most 256 locals, including its parameters.
+//! - Jump operands are `u16` distances from the end of the jump
+//!   instruction; [`OpCode::Loop`] jumps backwards, the others forwards.
+//! - Argument counts are `u8` and do not count the receiver or callee.
+//! - Intrinsic operands are `u8` [IDs](oxidex_codegen::intrinsics::Intrinsic::id).
+//!
+//! Errors are handled through the chunk's handler table rather than
+//! instructions (see [`Handler`](crate::chunk::Handler)); only raising them
+//! takes an instruction.
+
+use std::fmt;
+
+/// An instruction opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum OpCode {
+    // Literals
+    /// Push the constant at a `u16` index
+    Constant,
+    /// Push `nil`
+    Nil,
+    /// Push `true`
+    True,
+    /// Push `false`
+    False,
+
+    // Stack
+    /// Discard the top value
+    Pop,
+    /// Push a copy of the top value
+    Dup,
+
+    // Variables
+    /// Push the local at a `u8` slot
+    GetLocal,
+    /// Store the top value in the local at a `u8` slot, leaving it pushed
+    SetLocal,
+    /// Push the global named by the constant at a `u16` index
+    GetGlobal,
+    /// Pop a value into a new global named by the constant at a `u16` index
+    DefineGlobal,
+    /// Store the top value in an existing global, leaving it pushed
+    SetGlobal,
+    /// Push the captured variable at a `u8` index of the running closure
+    GetUpvalue,
+    /// Store the top value in a captured variable, leaving it pushed
+    SetUpvalue,
+
+    // Arithmetic and comparison
+    /// Pop two values and push their sum
+    Add,
+    /// Pop two values and push their difference
+    Subtract,
+    /// Pop two values and push their product
+    Multiply,
+    /// Pop two values and push their quotient
+    Divide,
+    /// Pop two values and push the remainder of their division
+    Remainder,
+    /// Negate the top value
+    Negate,
+    /// Replace the top value with its logical negation
+    Not,
+    /// Pop two values and push whether they are equal
+    Equal,
+    /// Pop two values and push whether they differ
+    NotEqual,
+    /// Pop two values and push whether the first is less
+    Less,
+    /// Pop two values and push whether the first is less or equal
+    LessEqual,
+    /// Pop two values and push whether the first is greater
+    Greater,
+    /// Pop two values and push whether the first is greater or equal
+    GreaterEqual,
+
+    // Messages and fields
+    /// Send the selector at a `u16` index to a receiver below a `u8` count
+    /// of arguments, popping them and pushing the result
+    Send,
+    /// Replace an object with its field named by the constant at a `u16`
+    /// index
+    GetField,
+    /// Pop a value and an object below it, store the value in the field
+    /// named by the constant at a `u16` index, and push the value
+    SetField,
+
+    // Control flow
+    /// Jump forwards by a `u16` distance
+    Jump,
+    /// Pop the condition, and jump forwards by a `u16` distance if it is
+    /// false
+    JumpIfFalse,
+    /// Jump backwards by a `u16` distance
+    Loop,
+
+    // Functions and closures
+    /// Push a closure over the function constant at a `u16` index,
+    /// capturing the variables its prototype lists
+    Closure,
+    /// Move the local on top of the stack to the heap, where the closures
+    /// capturing it share it, and pop it
+    CloseUpvalue,
+    /// Call a callee below a `u8` count of arguments, popping them and
+    /// pushing the result
+    Call,
+    /// Return the top value from the running function
+    Return,
+
+    // Errors
+    /// Pop a value and raise it as an error
+    Throw,
+    /// Pop an error a handler received and raise it again, keeping its
+    /// trace
+    Rethrow,
+
+    // Intrinsics
+    /// Pop the operands of the intrinsic with a `u8` ID and push its
+    /// result
+    Intrinsic,
+}
+
+impl OpCode {
+    /// Every opcode, in encoding order.
+    pub const ALL: [Self; 39] = [
+        Self::Constant,
+        Self::Nil,
+        Self::True,
+        Self::False,
+        Self::Pop,
+        Self::Dup,
+        Self::GetLocal,
+        Self::SetLocal,
+        Self::GetGlobal,
+        Self::DefineGlobal,
+        Self::SetGlobal,
+        Self::GetUpvalue,
+        Self::SetUpvalue,
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Remainder,
+        Self::Negate,
+        Self::Not,
+        Self::Equal,
+        Self::NotEqual,
+        Self::Less,
+        Self::LessEqual,
+        Self::Greater,
+        Self::GreaterEqual,
+        Self::Send,
+        Self::GetField,
+        Self::SetField,
+        Self::Jump,
+        Self::JumpIfFalse,
+        Self::Loop,
+        Self::Closure,
+        Self::CloseUpvalue,
+        Self::Call,
+        Self::Return,
+        Self::Throw,
+        Self::Rethrow,
+        Self::Intrinsic,
+    ];
+
+    /// Number of operand bytes following the opcode.
+    #[must_use]
+    pub const fn operand_width(self) -> usize {
+        match self {
+            Self::GetLocal | Self::SetLocal | Self::GetUpvalue | Self::SetUpvalue | Self::Call | Self::Intrinsic => 1,
+            Self::Constant
+            | Self::GetGlobal
+            | Self::DefineGlobal
+            | Self::SetGlobal
+            | Self::GetField
+            | Self::SetField
+            | Self::Jump
+            | Self::JumpIfFalse
+            | Self::Loop
+            | Self::Closure => 2,
+            Self::Send => 3,
+            _ => 0,
+        }
+    }
+
+    /// Whether the opcode's operand is a jump distance.
+    #[must_use]
+    pub const fn is_jump(self) -> bool {
+        matches!(self, Self::Jump | Self::JumpIfFalse | Self::Loop)
+    }
+
+    /// Mnemonic used by disassembly.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Constant => "CONSTANT",
+            Self::Nil => "NIL",
+            Self::True => "TRUE",
+            Self::False => "FALSE",
+            Self::Pop => "POP",
+            Self::Dup => "DUP",
+            Self::GetLocal => "GET_LOCAL",
+            Self::SetLocal => "SET_LOCAL",
+            Self::GetGlobal => "GET_GLOBAL",
+            Self::DefineGlobal => "DEFINE_GLOBAL",
+            Self::SetGlobal => "SET_GLOBAL",
+            Self::GetUpvalue => "GET_UPVALUE",
+            Self::SetUpvalue => "SET_UPVALUE",
+            Self::Add => "ADD",
+            Self::Subtract => "SUBTRACT",
+            Self::Multiply => "MULTIPLY",
+            Self::Divide => "DIVIDE",
+            Self::Remainder => "REMAINDER",
+            Self::Negate => "NEGATE",
+            Self::Not => "NOT",
+            Self::Equal => "EQUAL",
+            Self::NotEqual => "NOT_EQUAL",
+            Self::Less => "LESS",
+            Self::LessEqual => "LESS_EQUAL",
+            Self::Greater => "GREATER",
+            Self::GreaterEqual => "GREATER_EQUAL",
+            Self::Send => "SEND",
+            Self::GetField => "GET_FIELD",
+            Self::SetField => "SET_FIELD",
+            Self::Jump => "JUMP",
+            Self::JumpIfFalse => "JUMP_IF_FALSE",
+            Self::Loop => "LOOP",
+            Self::Closure => "CLOSURE",
+            Self::CloseUpvalue => "CLOSE_UPVALUE",
+            Self::Call => "CALL",
+            Self::Return => "RETURN",
+            Self::Throw => "THROW",
+            Self::Rethrow => "RETHROW",
+            Self::Intrinsic => "INTRINSIC",
+        }
+    }
+}
+
+impl From<OpCode> for u8 {
+    fn from(op: OpCode) -> Self {
+        op as Self
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    /// Decode an opcode byte, returning the byte back if it is not one.
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        Self::ALL.get(usize::from(byte)).copied().ok_or(byte)
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcodes_round_trip_through_bytes() {
+        for (byte, op) in OpCode::ALL.into_iter().enumerate() {
+            assert_eq!(usize::from(u8::from(op)), byte);
+            assert_eq!(OpCode::try_from(u8::from(op)), Ok(op));
+        }
+        assert_eq!(OpCode::try_from(OpCode::ALL.len() as u8), Err(39));
+        assert_eq!(OpCode::Send.operand_width(), 3);
+        assert_eq!(OpCode::Add.operand_width(), 0);
+        assert!(OpCode::Loop.is_jump());
+    }
+}
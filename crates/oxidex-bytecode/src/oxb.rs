@@ -0,0 +1,500 @@
+//! The `.oxb` bytecode file format.
+//!
+//! A compiled script is saved with its nested functions, so it can be run
+//! later without parsing or compiling its source again. All integers are
+//! little-endian:
+//!
+//! ```text
+//! magic      "OXB\0"
+//! version    u16                      FORMAT_VERSION
+//! flags      u16                      reserved, 0
+//! strings    u32 count, then each:    u32 length, UTF-8 bytes
+//! functions  u32 count, then each:
+//!     name       u32                  string index
+//!     arity      u8
+//!     encoding   u8 (0 stack, 1 register), u8 registers (0 for stack)
+//!     captures   u32 count, then each: u8 kind (0 local, 1 upvalue), u8 index
+//!     code       u32 length, bytes
+//!     constants  u32 count, then each: u8 tag and its payload
+//!                    0 int            i64
+//!                    1 float          u64 bits
+//!                    2 string         u32 string index
+//!                    3 function       u32 index of an earlier function
+//!                    4 extern         u32 name and encoding string
+//!                                     indices, u8 1 and a u32 library
+//!                                     string index, or u8 0
+//!     lines      u32 count, then each: u32 start offset, span as u64
+//!                                      start and end, then u32 start
+//!                                      line, column, end line and column
+//!     handlers   u32 count, then each: u8 kind (0 catch, 1 cleanup),
+//!                                      u32 start, end, target and depth
+//!     locals     u32 count, then each: u32 name string index, u8 slot,
+//!                                      u32 start and end
+//! checksum   u32                      CRC-32 of everything before it
+//! ```
+//!
+//! Every string, whether a literal, a name or a selector, is stored once in
+//! the string table. Functions are stored after the functions they contain,
+//! and the last one is the script.
+
+use crate::chunk::{Capture, Chunk, Constant, Encoding, Function, Handler, HandlerKind, Local};
+use oxidex_codegen::ir::Extern;
+use crate::error::LoadError;
+use oxidex_syntax::Span;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+/// File extension of bytecode files.
+pub const EXTENSION: &str = "oxb";
+
+/// Bytes every bytecode file starts with.
+pub const MAGIC: [u8; 4] = *b"OXB\0";
+
+/// Version of the format this crate reads and writes.
+pub const FORMAT_VERSION: u16 = 6;
+
+/// Serialize a script and the functions it contains.
+#[must_use]
+pub fn save(script: &Function) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.function(script);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    put_u32(&mut out, writer.strings.len());
+    for string in &writer.strings {
+        put_u32(&mut out, string.len());
+        out.extend_from_slice(string.as_bytes());
+    }
+    put_u32(&mut out, writer.count);
+    out.extend_from_slice(&writer.functions);
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// Deserialize a script saved by [`save`].
+///
+/// # Errors
+///
+/// Returns a [`LoadError`] if `bytes` are not a bytecode file of this
+/// version, or are damaged.
+pub fn load(bytes: &[u8]) -> Result<Rc<Function>, LoadError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::NotBytecode);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len().max(8) - 4);
+    let mut reader = Reader { bytes: body, position: MAGIC.len() };
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let expected = u32::from_le_bytes(checksum.try_into().map_err(|_| LoadError::Truncated)?);
+    let found = crc32(body);
+    if expected != found {
+        return Err(LoadError::ChecksumMismatch { expected, found });
+    }
+    reader.u16()?;
+
+    let strings = (0..reader.u32()?)
+        .map(|_| {
+            let len = reader.u32()? as usize;
+            let text = std::str::from_utf8(reader.bytes(len)?).map_err(|_| LoadError::Malformed("invalid UTF-8"))?;
+            Ok(Rc::from(text))
+        })
+        .collect::<Result<Vec<Rc<str>>, LoadError>>()?;
+    let mut functions: Vec<Rc<Function>> = Vec::new();
+    for _ in 0..reader.u32()? {
+        let function = reader.function(&strings, &functions)?;
+        functions.push(Rc::new(function));
+    }
+    if reader.position != body.len() {
+        return Err(LoadError::Malformed("trailing bytes"));
+    }
+    functions.pop().ok_or(LoadError::Malformed("no script"))
+}
+
+/// Save a script to a file.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+pub fn save_file(path: impl AsRef<Path>, script: &Function) -> std::io::Result<()> {
+    std::fs::write(path, save(script))
+}
+
+/// Load a script from a file.
+///
+/// # Errors
+///
+/// Returns a [`LoadError`] if the file cannot be read or is not valid
+/// bytecode.
+pub fn load_file(path: impl AsRef<Path>) -> Result<Rc<Function>, LoadError> {
+    load(&std::fs::read(path).map_err(LoadError::Io)?)
+}
+
+/// Serializes functions, collecting the strings they use.
+#[derive(Default)]
+struct Writer {
+    /// String table
+    strings: Vec<Rc<str>>,
+    /// Index of each string in the table
+    string_indices: HashMap<Rc<str>, usize>,
+    /// Serialized functions
+    functions: Vec<u8>,
+    /// Number of functions serialized
+    count: usize,
+    /// Index of each function serialized, by address
+    function_indices: HashMap<*const Function, usize>,
+}
+
+impl Writer {
+    /// Serialize a function after the functions it contains, returning its
+    /// index.
+    fn function(&mut self, function: &Function) -> usize {
+        let chunk = &function.chunk;
+        let nested: Vec<usize> = chunk
+            .constants()
+            .iter()
+            .filter_map(|constant| match constant {
+                Constant::Function(nested) => Some(self.nested(nested)),
+                _ => None,
+            })
+            .collect();
+        let mut nested = nested.into_iter();
+
+        let name = self.string(&function.name);
+        let mut out = std::mem::take(&mut self.functions);
+        put_u32(&mut out, name);
+        out.push(function.arity);
+        out.extend_from_slice(&match chunk.encoding() {
+            Encoding::Stack => [0, 0],
+            Encoding::Register { registers } => [1, registers],
+        });
+        put_u32(&mut out, function.captures.len());
+        for capture in &function.captures {
+            out.extend_from_slice(&match *capture {
+                Capture::Local(slot) => [0, slot],
+                Capture::Upvalue(index) => [1, index],
+            });
+        }
+        put_u32(&mut out, chunk.len());
+        out.extend_from_slice(chunk.code());
+        put_u32(&mut out, chunk.constants().len());
+        for constant in chunk.constants() {
+            match constant {
+                Constant::Int(value) => {
+                    out.push(0);
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+                Constant::Float(value) => {
+                    out.push(1);
+                    out.extend_from_slice(&value.to_bits().to_le_bytes());
+                }
+                Constant::String(text) => {
+                    out.push(2);
+                    put_u32(&mut out, self.string(text));
+                }
+                Constant::Function(_) => {
+                    out.push(3);
+                    put_u32(&mut out, nested.next().expect("nested functions were serialized"));
+                }
+                Constant::Extern(external) => {
+                    out.push(4);
+                    put_u32(&mut out, self.string(&external.name));
+                    put_u32(&mut out, self.string(&external.encoding));
+                    match &external.library {
+                        Some(library) => {
+                            out.push(1);
+                            put_u32(&mut out, self.string(library));
+                        }
+                        None => out.push(0),
+                    }
+                }
+            }
+        }
+        let runs: Vec<_> = chunk.line_runs().collect();
+        put_u32(&mut out, runs.len());
+        for (start, span) in runs {
+            put_u32(&mut out, start);
+            // Byte offsets carry the file's place among the program's
+            for field in [span.start, span.end] {
+                out.extend_from_slice(&(field as u64).to_le_bytes());
+            }
+            for field in [span.start_line, span.start_col, span.end_line, span.end_col] {
+                put_u32(&mut out, field);
+            }
+        }
+        put_u32(&mut out, chunk.handlers().len());
+        for handler in chunk.handlers() {
+            out.push(match handler.kind {
+                HandlerKind::Catch => 0,
+                HandlerKind::Cleanup => 1,
+            });
+            for field in [handler.start, handler.end, handler.target, handler.depth] {
+                put_u32(&mut out, field);
+            }
+        }
+        put_u32(&mut out, chunk.locals().len());
+        for local in chunk.locals() {
+            put_u32(&mut out, self.string(&local.name));
+            out.push(local.slot);
+            put_u32(&mut out, local.start);
+            put_u32(&mut out, local.end);
+        }
+        self.functions = out;
+        self.count += 1;
+        self.count - 1
+    }
+
+    /// Serialize a nested function once, however many chunks refer to it.
+    fn nested(&mut self, function: &Rc<Function>) -> usize {
+        if let Some(index) = self.function_indices.get(&Rc::as_ptr(function)) {
+            return *index;
+        }
+        let index = self.function(function);
+        self.function_indices.insert(Rc::as_ptr(function), index);
+        index
+    }
+
+    fn string(&mut self, text: &str) -> usize {
+        if let Some(index) = self.string_indices.get(text) {
+            return *index;
+        }
+        let text: Rc<str> = Rc::from(text);
+        self.strings.push(Rc::clone(&text));
+        self.string_indices.insert(text, self.strings.len() - 1);
+        self.strings.len() - 1
+    }
+}
+
+/// Append a length or index as a `u32`.
+fn put_u32(out: &mut Vec<u8>, value: usize) {
+    let value = u32::try_from(value).expect("bytecode sections are smaller than 4 GiB");
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Reads the sections of a bytecode file.
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn bytes(&mut self, len: usize) -> Result<&'b [u8], LoadError> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len()).ok_or(LoadError::Truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        Ok(self.bytes(N)?.try_into().expect("N bytes were read"))
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// Read an index into `items`.
+    fn index<'t, T>(&mut self, items: &'t [T], what: &'static str) -> Result<&'t T, LoadError> {
+        let index = self.u32()? as usize;
+        items.get(index).ok_or(LoadError::Malformed(what))
+    }
+
+    fn function(&mut self, strings: &[Rc<str>], functions: &[Rc<Function>]) -> Result<Function, LoadError> {
+        let name = self.index(strings, "invalid string index")?.to_string();
+        let arity = self.u8()?;
+        let encoding = match self.array::<2>()? {
+            [0, 0] => Encoding::Stack,
+            [1, registers] => Encoding::Register { registers },
+            _ => return Err(LoadError::Malformed("invalid encoding")),
+        };
+        let captures = (0..self.u32()?)
+            .map(|_| match self.array::<2>()? {
+                [0, slot] => Ok(Capture::Local(slot)),
+                [1, index] => Ok(Capture::Upvalue(index)),
+                _ => Err(LoadError::Malformed("invalid capture")),
+            })
+            .collect::<Result<_, _>>()?;
+        let len = self.u32()? as usize;
+        let code = self.bytes(len)?.to_vec();
+        let constants = (0..self.u32()?)
+            .map(|_| {
+                Ok(match self.u8()? {
+                    0 => Constant::Int(self.u64()? as i64),
+                    1 => Constant::Float(f64::from_bits(self.u64()?)),
+                    2 => Constant::String(Rc::clone(self.index(strings, "invalid string index")?)),
+                    3 => Constant::Function(Rc::clone(self.index(functions, "invalid function index")?)),
+                    4 => {
+                        let name = self.index(strings, "invalid string index")?.to_string();
+                        let encoding = self.index(strings, "invalid string index")?.to_string();
+                        let library = match self.u8()? {
+                            0 => None,
+                            1 => Some(self.index(strings, "invalid string index")?.to_string()),
+                            _ => return Err(LoadError::Malformed("invalid extern library")),
+                        };
+                        Constant::Extern(Rc::new(Extern { name, library, encoding }))
+                    }
+                    _ => return Err(LoadError::Malformed("invalid constant")),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let lines = (0..self.u32()?)
+            .map(|_| {
+                let start = self.u32()? as usize;
+                let (from, to) = (self.u64()? as usize, self.u64()? as usize);
+                let mut fields = [0; 4];
+                for field in &mut fields {
+                    *field = self.u32()? as usize;
+                }
+                let [start_line, start_col, end_line, end_col] = fields;
+                Ok((start, Span::new(from, to, start_line, start_col, end_line, end_col)))
+            })
+            .collect::<Result<_, _>>()?;
+        let handlers = (0..self.u32()?)
+            .map(|_| {
+                let kind = match self.u8()? {
+                    0 => HandlerKind::Catch,
+                    1 => HandlerKind::Cleanup,
+                    _ => return Err(LoadError::Malformed("invalid handler")),
+                };
+                let fields = [self.u32()?, self.u32()?, self.u32()?, self.u32()?];
+                let [start, end, target, depth] = fields.map(|field| field as usize);
+                Ok(Handler { kind, start, end, target, depth })
+            })
+            .collect::<Result<_, _>>()?;
+        let locals = (0..self.u32()?)
+            .map(|_| {
+                let name = Rc::clone(self.index(strings, "invalid string index")?);
+                let slot = self.u8()?;
+                let [start, end] = [self.u32()?, self.u32()?].map(|field| field as usize);
+                Ok(Local { name, slot, start, end })
+            })
+            .collect::<Result<_, _>>()?;
+        let chunk = Chunk::from_parts(code, constants, lines, handlers, encoding, locals);
+        let chunk = chunk.ok_or(LoadError::Malformed("invalid line, handler or local table"))?;
+        Ok(Function { name, arity, captures, chunk })
+    }
+}
+
+/// CRC-32 (IEEE) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::OpCode;
+    use crate::value::Value;
+    use crate::vm::Vm;
+    use oxidex_syntax::session::FILE_SPACE;
+
+    fn line(line: usize) -> Span {
+        Span::new(line * 10, line * 10 + 5, line, 1, line, 6)
+    }
+
+    /// A span on `line` of the second file of a program.
+    fn imported(line: usize) -> Span {
+        let start = FILE_SPACE + line * 10;
+        Span::new(start, start + 5, line, 1, line, 6)
+    }
+
+    /// A script calling a closure over one of its locals.
+    fn script() -> Function {
+        let mut inner = Chunk::new();
+        inner.write_op(OpCode::GetUpvalue, imported(2));
+        inner.write(0, imported(2));
+        inner.write_constant(OpCode::Constant, Constant::Float(0.5), imported(2)).unwrap();
+        inner.write_op(OpCode::Add, line(3));
+        inner.write_op(OpCode::Return, line(3));
+        let captures = vec![Capture::Local(0)];
+        let inner = Rc::new(Function { name: "inner".to_string(), arity: 0, captures, chunk: inner });
+
+        let mut chunk = Chunk::new();
+        chunk.write_constant(OpCode::Constant, Constant::Float(1.0), line(1)).unwrap();
+        chunk.write_constant(OpCode::Closure, Constant::Function(inner), line(1)).unwrap();
+        chunk.write_op(OpCode::Dup, line(1));
+        chunk.write_constant(OpCode::DefineGlobal, Constant::String(Rc::from("inner")), line(4)).unwrap();
+        chunk.write_op(OpCode::Call, line(5));
+        chunk.write(0, line(5));
+        chunk.write_op(OpCode::Return, line(5));
+        chunk.add_local(Local { name: Rc::from("x"), slot: 0, start: 2, end: chunk.len() });
+        Function { name: "script".to_string(), arity: 0, captures: vec![], chunk }
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let script = script();
+        let bytes = save(&script);
+        assert_eq!(bytes[..4], MAGIC);
+        let loaded = load(&bytes).unwrap();
+        assert_eq!(*loaded, script);
+        // The function name and the global name share a string
+        assert_eq!(bytes.windows(5).filter(|window| window == b"inner").count(), 1);
+
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(loaded).unwrap(), Value::Float(1.5));
+
+        // Externs keep their library, or lack of one
+        let mut chunk = Chunk::new();
+        for library in [None, Some("libm.so.6".to_string())] {
+            let external = Extern { name: "cos".to_string(), library, encoding: "dd".to_string() };
+            chunk.write_constant(OpCode::Constant, Constant::Extern(Rc::new(external)), line(1)).unwrap();
+        }
+        let script = Function { name: "externs".to_string(), arity: 0, captures: vec![], chunk };
+        assert_eq!(*load(&save(&script)).unwrap(), script);
+    }
+
+    #[test]
+    fn test_load_rejects_damaged_files() {
+        let bytes = save(&script());
+        assert!(matches!(load(b"#!/usr/bin/env ox"), Err(LoadError::NotBytecode)));
+
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert!(matches!(load(&newer), Err(LoadError::UnsupportedVersion(9))));
+
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        assert!(matches!(load(&flipped), Err(LoadError::ChecksumMismatch { .. })));
+
+        let mut truncated = bytes[..bytes.len() - 12].to_vec();
+        let checksum = crc32(&truncated);
+        truncated.extend_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(load(&truncated), Err(LoadError::Truncated)));
+    }
+
+    #[test]
+    fn test_save_file_and_load_file() {
+        let path = std::env::temp_dir().join(format!("oxidex-test-{}.{EXTENSION}", std::process::id()));
+        save_file(&path, &script()).unwrap();
+        let loaded = load_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*loaded, script());
+        assert!(matches!(load_file(&path), Err(LoadError::Io(_))));
+    }
+}
@@ -0,0 +1,991 @@
+//! The register instruction set.
+//!
+//! Stack code spends many of its instructions moving values: reading a
+//! local pushes a copy of it, and assigning one copies the top of the stack
+//! back and pops it. Register instructions name their inputs and output
+//! instead, as `u8` registers of the frame, so `i = i + 1` is
+//!
+//! ```text
+//! LOAD_CONSTANT    r2      1 1
+//! ADD              r1   r1 r2
+//! ```
+//!
+//! rather than five stack instructions. Registers are the frame's stack
+//! slots: register 0 is the first parameter, and a chunk's
+//! [`Encoding`] says how many registers its frames need. Constant and jump
+//! operands are as in the stack instruction set.
+//!
+//! There is no compiler targeting registers directly; [`lower`] translates
+//! stack functions that only compute with locals, globals and calls. The
+//! stack instruction set stays the portable default, and a program can mix
+//! functions of both.
+
+use crate::chunk::{Chunk, Constant, Encoding, Function, Local};
+use crate::opcodes::OpCode;
+use oxidex_syntax::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A register instruction opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum RegOp {
+    // Loads
+    /// Copy a register into a register: `dst src`
+    Move,
+    /// Load the constant at a `u16` index: `dst index`
+    LoadConstant,
+    /// Load `nil`: `dst`
+    LoadNil,
+    /// Load `true`: `dst`
+    LoadTrue,
+    /// Load `false`: `dst`
+    LoadFalse,
+
+    // Globals
+    /// Load the global named by the constant at a `u16` index: `dst index`
+    GetGlobal,
+    /// Define a global named by the constant at a `u16` index: `src index`
+    DefineGlobal,
+    /// Store in an existing global named by the constant at a `u16` index:
+    /// `src index`
+    SetGlobal,
+
+    // Arithmetic and comparison, as their stack counterparts: `dst a b`
+    /// Sum
+    Add,
+    /// Difference
+    Subtract,
+    /// Product
+    Multiply,
+    /// Quotient
+    Divide,
+    /// Remainder of the division
+    Remainder,
+    /// Whether the operands are equal
+    Equal,
+    /// Whether the operands differ
+    NotEqual,
+    /// Whether the first operand is less
+    Less,
+    /// Whether the first operand is less or equal
+    LessEqual,
+    /// Whether the first operand is greater
+    Greater,
+    /// Whether the first operand is greater or equal
+    GreaterEqual,
+    /// Negation: `dst src`
+    Negate,
+    /// Logical negation: `dst src`
+    Not,
+
+    // Control flow
+    /// Jump forwards by a `u16` distance
+    Jump,
+    /// Jump forwards by a `u16` distance if a register is false: `src distance`
+    JumpIfFalse,
+    /// Jump backwards by a `u16` distance
+    Loop,
+
+    // Functions
+    /// Call the callee in a register with the `u8` count of arguments in
+    /// the registers after it, storing the result over the callee:
+    /// `callee argc`
+    Call,
+    /// Return a register from the running function: `src`
+    Return,
+}
+
+impl RegOp {
+    /// Every opcode, in encoding order.
+    pub const ALL: [Self; 26] = [
+        Self::Move,
+        Self::LoadConstant,
+        Self::LoadNil,
+        Self::LoadTrue,
+        Self::LoadFalse,
+        Self::GetGlobal,
+        Self::DefineGlobal,
+        Self::SetGlobal,
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Remainder,
+        Self::Equal,
+        Self::NotEqual,
+        Self::Less,
+        Self::LessEqual,
+        Self::Greater,
+        Self::GreaterEqual,
+        Self::Negate,
+        Self::Not,
+        Self::Jump,
+        Self::JumpIfFalse,
+        Self::Loop,
+        Self::Call,
+        Self::Return,
+    ];
+
+    /// Number of operand bytes following the opcode.
+    #[must_use]
+    pub const fn operand_width(self) -> usize {
+        match self {
+            Self::LoadNil | Self::LoadTrue | Self::LoadFalse | Self::Return => 1,
+            Self::Move | Self::Negate | Self::Not | Self::Jump | Self::Loop | Self::Call => 2,
+            _ => 3,
+        }
+    }
+
+    /// The stack opcode computing the same operator, for arithmetic,
+    /// comparison and negation.
+    #[must_use]
+    pub const fn operator(self) -> Option<OpCode> {
+        Some(match self {
+            Self::Add => OpCode::Add,
+            Self::Subtract => OpCode::Subtract,
+            Self::Multiply => OpCode::Multiply,
+            Self::Divide => OpCode::Divide,
+            Self::Remainder => OpCode::Remainder,
+            Self::Equal => OpCode::Equal,
+            Self::NotEqual => OpCode::NotEqual,
+            Self::Less => OpCode::Less,
+            Self::LessEqual => OpCode::LessEqual,
+            Self::Greater => OpCode::Greater,
+            Self::GreaterEqual => OpCode::GreaterEqual,
+            Self::Negate => OpCode::Negate,
+            Self::Not => OpCode::Not,
+            _ => return None,
+        })
+    }
+
+    /// Mnemonic used by disassembly.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Move => "MOVE",
+            Self::LoadConstant => "LOAD_CONSTANT",
+            Self::LoadNil => "LOAD_NIL",
+            Self::LoadTrue => "LOAD_TRUE",
+            Self::LoadFalse => "LOAD_FALSE",
+            Self::GetGlobal => "GET_GLOBAL",
+            Self::DefineGlobal => "DEFINE_GLOBAL",
+            Self::SetGlobal => "SET_GLOBAL",
+            Self::Add => "ADD",
+            Self::Subtract => "SUBTRACT",
+            Self::Multiply => "MULTIPLY",
+            Self::Divide => "DIVIDE",
+            Self::Remainder => "REMAINDER",
+            Self::Equal => "EQUAL",
+            Self::NotEqual => "NOT_EQUAL",
+            Self::Less => "LESS",
+            Self::LessEqual => "LESS_EQUAL",
+            Self::Greater => "GREATER",
+            Self::GreaterEqual => "GREATER_EQUAL",
+            Self::Negate => "NEGATE",
+            Self::Not => "NOT",
+            Self::Jump => "JUMP",
+            Self::JumpIfFalse => "JUMP_IF_FALSE",
+            Self::Loop => "LOOP",
+            Self::Call => "CALL",
+            Self::Return => "RETURN",
+        }
+    }
+
+    /// The register opcode for a stack arithmetic, comparison or negation
+    /// opcode.
+    const fn from_operator(op: OpCode) -> Option<Self> {
+        Some(match op {
+            OpCode::Add => Self::Add,
+            OpCode::Subtract => Self::Subtract,
+            OpCode::Multiply => Self::Multiply,
+            OpCode::Divide => Self::Divide,
+            OpCode::Remainder => Self::Remainder,
+            OpCode::Equal => Self::Equal,
+            OpCode::NotEqual => Self::NotEqual,
+            OpCode::Less => Self::Less,
+            OpCode::LessEqual => Self::LessEqual,
+            OpCode::Greater => Self::Greater,
+            OpCode::GreaterEqual => Self::GreaterEqual,
+            OpCode::Negate => Self::Negate,
+            OpCode::Not => Self::Not,
+            _ => return None,
+        })
+    }
+}
+
+impl From<RegOp> for u8 {
+    fn from(op: RegOp) -> Self {
+        op as Self
+    }
+}
+
+impl TryFrom<u8> for RegOp {
+    type Error = u8;
+
+    /// Decode an opcode byte, returning the byte back if it is not one.
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        Self::ALL.get(usize::from(byte)).copied().ok_or(byte)
+    }
+}
+
+impl fmt::Display for RegOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// Translate a function, and the functions it contains, to register
+/// instructions where they can be. Functions that use upvalues, messages,
+/// fields, closures or error handlers keep their stack instructions.
+#[must_use]
+pub fn lower(function: &Function) -> Function {
+    lower_function(function, &mut HashMap::new())
+}
+
+/// Lower a function, sharing the functions already lowered by address.
+fn lower_function(function: &Function, lowered: &mut HashMap<*const Function, Rc<Function>>) -> Function {
+    let chunk = &function.chunk;
+    let constants: Vec<_> = chunk
+        .constants()
+        .iter()
+        .map(|constant| match constant {
+            Constant::Function(nested) => {
+                let nested = match lowered.get(&Rc::as_ptr(nested)) {
+                    Some(nested) => Rc::clone(nested),
+                    None => {
+                        let nested_lowered = Rc::new(lower_function(nested, lowered));
+                        lowered.insert(Rc::as_ptr(nested), Rc::clone(&nested_lowered));
+                        nested_lowered
+                    }
+                };
+                Constant::Function(nested)
+            }
+            constant => constant.clone(),
+        })
+        .collect();
+    let chunk = match Translator::translate(chunk, function.arity) {
+        Some((code, lines, locals, registers)) => {
+            Chunk::from_parts(code, constants, lines, Vec::new(), Encoding::Register { registers }, locals)
+        }
+        None => {
+            let lines = chunk.line_runs().collect();
+            let (code, handlers, locals) = (chunk.code().to_vec(), chunk.handlers().to_vec(), chunk.locals().to_vec());
+            Chunk::from_parts(code, constants, lines, handlers, chunk.encoding(), locals)
+        }
+    };
+    let chunk = chunk.expect("lowering keeps the line, handler and local tables valid");
+    Function { name: function.name.clone(), arity: function.arity, captures: function.captures.clone(), chunk }
+}
+
+/// Where a value on the translated stack is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// In its own register, numbered by its stack position
+    Home,
+    /// Equal to a lower register that is home to its value, and not copied
+    Alias(u8),
+    /// The constant at an index, not loaded yet
+    Constant(u16),
+    /// `nil`, not loaded yet
+    Nil,
+    /// A boolean, not loaded yet
+    Bool(bool),
+}
+
+/// Register code, its line and local tables and its number of registers.
+type Translation = (Vec<u8>, Vec<(usize, Span)>, Vec<Local>, u8);
+
+/// Translates stack code to register code.
+///
+/// Each stack position has a register. Values are only copied into their
+/// position's register when an instruction needs them there; otherwise
+/// instructions read locals and literals where they already are. At every
+/// jump and jump target, all values are copied home, so the code either
+/// side of the jump agrees on where they are.
+struct Translator<'c> {
+    /// Stack code being translated
+    source: &'c Chunk,
+    /// Register code
+    code: Vec<u8>,
+    /// Line table of the register code
+    lines: Vec<(usize, Span)>,
+    /// Register code offset of each stack code offset, for the local table
+    offsets: Vec<usize>,
+    /// Source of the instruction being translated
+    span: Span,
+    /// The stack at the instruction being translated
+    stack: Vec<Slot>,
+    /// Most stack positions used
+    registers: usize,
+    /// Register code offset of each jump target translated, by stack code
+    /// offset
+    labels: HashMap<usize, usize>,
+    /// Stack depth at each jump target, by stack code offset
+    depths: HashMap<usize, usize>,
+    /// Forward jumps to patch: the stack code target and the offset of the
+    /// distance operand
+    patches: Vec<(usize, usize)>,
+    /// Offset of the destination operand of the last instruction, if it
+    /// wrote the top of the stack and nothing has been emitted since
+    last_write: Option<usize>,
+}
+
+impl<'c> Translator<'c> {
+    /// Translate a chunk, returning its register code, line and local
+    /// tables and number of registers, or `None` if it uses instructions
+    /// without a register form.
+    fn translate(source: &'c Chunk, arity: u8) -> Option<Translation> {
+        if source.encoding() != Encoding::Stack || !source.handlers().is_empty() {
+            return None;
+        }
+        let targets = jump_targets(source)?;
+        let mut translator = Self {
+            source,
+            code: Vec::new(),
+            lines: Vec::new(),
+            offsets: Vec::with_capacity(source.len() + 1),
+            span: source.span(0)?,
+            stack: vec![Slot::Home; usize::from(arity)],
+            registers: usize::from(arity),
+            labels: HashMap::new(),
+            depths: HashMap::new(),
+            patches: Vec::new(),
+            last_write: None,
+        };
+        let mut offset = 0;
+        let mut reachable = true;
+        while offset < source.len() {
+            let op = OpCode::try_from(source.code()[offset]).ok()?;
+            let next = offset + 1 + op.operand_width();
+            // Values copied home before a jump target belong to the code
+            // before it
+            if targets.contains(&offset) {
+                translator.label(offset, reachable)?;
+                reachable = true;
+            }
+            translator.span = source.span(offset)?;
+            translator.offsets.resize(next, translator.code.len());
+            if reachable {
+                reachable = translator.instruction(op, offset, next)?;
+            }
+            offset = next;
+        }
+        for (target, operand) in std::mem::take(&mut translator.patches) {
+            let distance = translator.labels.get(&target)?.checked_sub(operand + 2)?;
+            let distance = u16::try_from(distance).ok()?;
+            translator.code[operand..operand + 2].copy_from_slice(&distance.to_le_bytes());
+        }
+        translator.offsets.push(translator.code.len());
+        let offsets = &translator.offsets;
+        let locals = source
+            .locals()
+            .iter()
+            .map(|local| Local { start: offsets[local.start], end: offsets[local.end], ..local.clone() })
+            .collect();
+        let registers = u8::try_from(translator.registers).ok()?;
+        Some((translator.code, translator.lines, locals, registers))
+    }
+
+    /// Start the code at a jump target.
+    fn label(&mut self, offset: usize, reachable: bool) -> Option<()> {
+        if reachable {
+            self.flush()?;
+            self.record_depth(offset, self.stack.len())?;
+        } else {
+            self.stack = vec![Slot::Home; *self.depths.get(&offset)?];
+        }
+        self.labels.insert(offset, self.code.len());
+        self.last_write = None;
+        Some(())
+    }
+
+    /// Translate the stack instruction at `offset`, returning whether the
+    /// next one is reached from it.
+    fn instruction(&mut self, op: OpCode, offset: usize, next: usize) -> Option<bool> {
+        let u8_operand = || self.source.read_u8(offset + 1);
+        let u16_operand = || self.source.read_u16(offset + 1);
+        match op {
+            OpCode::Constant => self.push(Slot::Constant(u16_operand()?)),
+            OpCode::Nil => self.push(Slot::Nil),
+            OpCode::True => self.push(Slot::Bool(true)),
+            OpCode::False => self.push(Slot::Bool(false)),
+            OpCode::Pop => {
+                self.stack.pop()?;
+            }
+            OpCode::Dup => {
+                let top = self.stack.len().checked_sub(1)?;
+                self.push(self.read(top));
+            }
+            OpCode::GetLocal => {
+                let slot = usize::from(u8_operand()?);
+                if slot >= self.stack.len() {
+                    return None;
+                }
+                self.push(self.read(slot));
+            }
+            OpCode::SetLocal => self.set_local(usize::from(u8_operand()?))?,
+            OpCode::GetGlobal => {
+                let dst = self.stack.len();
+                self.push(Slot::Home);
+                self.emit_write(RegOp::GetGlobal, dst)?;
+                self.emit_u16(u16_operand()?);
+            }
+            OpCode::DefineGlobal | OpCode::SetGlobal => {
+                let top = self.stack.len().checked_sub(1)?;
+                let src = self.register(top)?;
+                if op == OpCode::DefineGlobal {
+                    self.stack.pop();
+                }
+                let op = if op == OpCode::DefineGlobal { RegOp::DefineGlobal } else { RegOp::SetGlobal };
+                self.emit(&[op.into(), src]);
+                self.emit_u16(u16_operand()?);
+            }
+            OpCode::Negate | OpCode::Not => {
+                let top = self.stack.len().checked_sub(1)?;
+                let src = self.register(top)?;
+                self.stack[top] = Slot::Home;
+                self.emit_write(RegOp::from_operator(op)?, top)?;
+                self.emit(&[src]);
+            }
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Remainder
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual => {
+                let right = self.stack.len().checked_sub(1)?;
+                let left = right.checked_sub(1)?;
+                let (a, b) = (self.register(left)?, self.register(right)?);
+                self.stack.truncate(left);
+                self.push(Slot::Home);
+                self.emit_write(RegOp::from_operator(op)?, left)?;
+                self.emit(&[a, b]);
+            }
+            OpCode::Jump => {
+                self.flush()?;
+                self.jump(RegOp::Jump, &[], next + usize::from(u16_operand()?))?;
+                return Some(false);
+            }
+            OpCode::JumpIfFalse => {
+                let top = self.stack.len().checked_sub(1)?;
+                let condition = self.register(top)?;
+                self.stack.pop();
+                self.flush()?;
+                self.jump(RegOp::JumpIfFalse, &[condition], next + usize::from(u16_operand()?))?;
+            }
+            OpCode::Loop => {
+                self.flush()?;
+                let target = next.checked_sub(usize::from(u16_operand()?))?;
+                if self.depths.get(&target) != Some(&self.stack.len()) {
+                    return None;
+                }
+                let end = self.emit(&[RegOp::Loop.into()]) + 3;
+                let distance = u16::try_from(end - self.labels.get(&target)?).ok()?;
+                self.emit_u16(distance);
+                return Some(false);
+            }
+            OpCode::Call => {
+                let argc = u8_operand()?;
+                let callee = self.stack.len().checked_sub(usize::from(argc) + 1)?;
+                for position in callee..self.stack.len() {
+                    self.materialize(position)?;
+                }
+                self.stack.truncate(callee + 1);
+                self.emit(&[RegOp::Call.into(), u8::try_from(callee).ok()?, argc]);
+                self.last_write = None;
+            }
+            OpCode::Return => {
+                let top = self.stack.len().checked_sub(1)?;
+                let src = self.register(top)?;
+                self.emit(&[RegOp::Return.into(), src]);
+                return Some(false);
+            }
+            _ => return None,
+        }
+        Some(true)
+    }
+
+    /// Translate `SET_LOCAL slot`.
+    fn set_local(&mut self, slot: usize) -> Option<()> {
+        let top = self.stack.len().checked_sub(1)?;
+        if slot > top {
+            return None;
+        }
+        let dst = u8::try_from(slot).ok()?;
+        let value = self.stack[top];
+        if slot == top || value == Slot::Alias(dst) {
+            return Some(());
+        }
+        // Values still reading the local's old value need a copy of it
+        let stale = (slot + 1..top).filter(|position| self.stack[*position] == Slot::Alias(dst)).collect::<Vec<_>>();
+        for position in stale {
+            self.materialize(position)?;
+        }
+        match value {
+            Slot::Home => match self.last_write {
+                // The instruction that computed the value can write it to
+                // the local directly
+                Some(operand) if usize::from(self.code[operand]) == top => self.code[operand] = dst,
+                _ => {
+                    self.emit(&[RegOp::Move.into(), dst, u8::try_from(top).ok()?]);
+                }
+            },
+            Slot::Alias(src) => {
+                self.emit(&[RegOp::Move.into(), dst, src]);
+            }
+            _ => self.load(dst, value),
+        }
+        self.stack[slot] = Slot::Home;
+        if value == Slot::Home || matches!(value, Slot::Alias(_)) {
+            self.stack[top] = Slot::Alias(dst);
+        }
+        self.last_write = None;
+        Some(())
+    }
+
+    /// Emit a jump to a stack code offset, recording the stack depth there.
+    fn jump(&mut self, op: RegOp, operands: &[u8], target: usize) -> Option<()> {
+        self.record_depth(target, self.stack.len())?;
+        self.emit(&[op.into()]);
+        self.emit(operands);
+        let operand = self.code.len();
+        self.emit_u16(u16::MAX);
+        self.patches.push((target, operand));
+        Some(())
+    }
+
+    /// Record the stack depth at a jump target, which every way of reaching
+    /// it must agree on.
+    fn record_depth(&mut self, target: usize, depth: usize) -> Option<()> {
+        match self.depths.insert(target, depth) {
+            Some(recorded) if recorded != depth => None,
+            _ => Some(()),
+        }
+    }
+
+    /// Push a value.
+    fn push(&mut self, slot: Slot) {
+        self.stack.push(slot);
+        self.registers = self.registers.max(self.stack.len());
+    }
+
+    /// Describe where the value at a position is, for a copy of it.
+    fn read(&self, position: usize) -> Slot {
+        match self.stack[position] {
+            Slot::Home => Slot::Alias(position as u8),
+            slot => slot,
+        }
+    }
+
+    /// Get a register holding the value at a position, loading it there if
+    /// it is a literal.
+    fn register(&mut self, position: usize) -> Option<u8> {
+        match self.stack[position] {
+            Slot::Alias(src) => Some(src),
+            _ => {
+                self.materialize(position)?;
+                u8::try_from(position).ok()
+            }
+        }
+    }
+
+    /// Copy the value at a position into its register.
+    fn materialize(&mut self, position: usize) -> Option<()> {
+        let dst = u8::try_from(position).ok()?;
+        match self.stack[position] {
+            Slot::Home => return Some(()),
+            Slot::Alias(src) => {
+                self.emit(&[RegOp::Move.into(), dst, src]);
+            }
+            slot => self.load(dst, slot),
+        }
+        self.stack[position] = Slot::Home;
+        self.last_write = None;
+        Some(())
+    }
+
+    /// Copy every value into its register.
+    fn flush(&mut self) -> Option<()> {
+        for position in 0..self.stack.len() {
+            self.materialize(position)?;
+        }
+        Some(())
+    }
+
+    /// Load a literal into a register.
+    fn load(&mut self, dst: u8, literal: Slot) {
+        match literal {
+            Slot::Constant(index) => {
+                self.emit(&[RegOp::LoadConstant.into(), dst]);
+                self.emit_u16(index);
+            }
+            Slot::Nil => {
+                self.emit(&[RegOp::LoadNil.into(), dst]);
+            }
+            Slot::Bool(value) => {
+                self.emit(&[if value { RegOp::LoadTrue } else { RegOp::LoadFalse }.into(), dst]);
+            }
+            Slot::Home | Slot::Alias(_) => unreachable!("only literals are loaded"),
+        }
+        self.last_write = None;
+    }
+
+    /// Emit an opcode writing the register of a position, and the register.
+    fn emit_write(&mut self, op: RegOp, position: usize) -> Option<()> {
+        let dst = u8::try_from(position).ok()?;
+        let offset = self.emit(&[op.into(), dst]);
+        self.last_write = Some(offset + 1);
+        Some(())
+    }
+
+    /// Emit bytes, returning the offset of the first.
+    fn emit(&mut self, bytes: &[u8]) -> usize {
+        let offset = self.code.len();
+        if !bytes.is_empty() && self.lines.last().is_none_or(|(_, span)| *span != self.span) {
+            self.lines.push((offset, self.span));
+        }
+        self.code.extend_from_slice(bytes);
+        offset
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        self.emit(&value.to_le_bytes());
+    }
+}
+
+/// Offsets of the instructions jumps land on, or `None` if the code does
+/// not decode.
+fn jump_targets(chunk: &Chunk) -> Option<Vec<usize>> {
+    let mut targets = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let op = OpCode::try_from(chunk.code()[offset]).ok()?;
+        let next = offset + 1 + op.operand_width();
+        if op.is_jump() {
+            let distance = usize::from(chunk.read_u16(offset + 1)?);
+            targets.push(if op == OpCode::Loop { next.checked_sub(distance)? } else { next + distance });
+        }
+        offset = next;
+    }
+    Some(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Handler, HandlerKind};
+    use crate::disasm::disassemble;
+    use crate::oxb;
+    use crate::value::{Closure, Native, Value};
+    use crate::vm::Vm;
+    use std::collections::BTreeSet;
+
+    fn line(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    /// `fn count(n) { var sum = 0; var i = 0; while i < n { i = i + 1; sum = sum + i }; sum }`
+    fn count() -> Function {
+        let mut chunk = Chunk::new();
+        let local = |chunk: &mut Chunk, op: OpCode, slot: u8, span: Span| {
+            chunk.write_op(op, span);
+            chunk.write(slot, span);
+        };
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), line(1)).unwrap();
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), line(1)).unwrap();
+        let start = chunk.len();
+        local(&mut chunk, OpCode::GetLocal, 2, line(2));
+        local(&mut chunk, OpCode::GetLocal, 0, line(2));
+        chunk.write_op(OpCode::Less, line(2));
+        let exit = chunk.write_jump(OpCode::JumpIfFalse, line(2));
+        local(&mut chunk, OpCode::GetLocal, 2, line(3));
+        chunk.write_constant(OpCode::Constant, Constant::Int(1), line(3)).unwrap();
+        chunk.write_op(OpCode::Add, line(3));
+        local(&mut chunk, OpCode::SetLocal, 2, line(3));
+        chunk.write_op(OpCode::Pop, line(3));
+        local(&mut chunk, OpCode::GetLocal, 1, line(4));
+        local(&mut chunk, OpCode::GetLocal, 2, line(4));
+        chunk.write_op(OpCode::Add, line(4));
+        local(&mut chunk, OpCode::SetLocal, 1, line(4));
+        chunk.write_op(OpCode::Pop, line(4));
+        chunk.write_loop(start, line(4)).unwrap();
+        chunk.patch_jump(exit).unwrap();
+        local(&mut chunk, OpCode::GetLocal, 1, line(5));
+        chunk.write_op(OpCode::Return, line(5));
+        for (name, slot, start) in [("n", 0, 0), ("total", 1, 3), ("i", 2, start)] {
+            chunk.add_local(Local { name: Rc::from(name), slot, start, end: chunk.len() });
+        }
+        Function { name: "count".to_string(), arity: 1, captures: vec![], chunk }
+    }
+
+    fn instructions(chunk: &Chunk) -> usize {
+        let mut offset = 0;
+        let mut count = 0;
+        while offset < chunk.len() {
+            let width = match chunk.encoding() {
+                Encoding::Stack => OpCode::try_from(chunk.code()[offset]).unwrap().operand_width(),
+                Encoding::Register { .. } => RegOp::try_from(chunk.code()[offset]).unwrap().operand_width(),
+            };
+            offset += 1 + width;
+            count += 1;
+        }
+        count
+    }
+
+    fn closure(function: Function) -> Value {
+        Value::Closure(Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() }))
+    }
+
+    /// A function on one line, written by `write`.
+    fn function(name: &str, arity: u8, write: impl FnOnce(&mut Chunk)) -> Function {
+        let mut chunk = Chunk::new();
+        write(&mut chunk);
+        Function { name: name.to_string(), arity, captures: vec![], chunk }
+    }
+
+    fn get_local(chunk: &mut Chunk, slot: u8) {
+        chunk.write_op(OpCode::GetLocal, line(1));
+        chunk.write(slot, line(1));
+    }
+
+    /// Run a function and its lowering in fresh VMs on each set of
+    /// arguments, asserting they agree on the result or on the error and
+    /// where it was raised, and return the register opcodes it lowered to.
+    fn parity(stack: Function, inputs: &[Vec<Value>]) -> BTreeSet<u8> {
+        let lowered = lower(&stack);
+        assert!(matches!(lowered.chunk.encoding(), Encoding::Register { .. }), "{} stays on the stack", stack.name);
+        let run = |function: &Function, args: &[Value]| {
+            Vm::new()
+                .call(closure(function.clone()), args.to_vec())
+                .map_err(|err| (err.kind.to_string(), err.span().map(|span| span.start_line)))
+        };
+        for args in inputs {
+            assert_eq!(run(&lowered, args), run(&stack, args), "{} on {args:?}", stack.name);
+        }
+        let mut ops = BTreeSet::new();
+        let mut offset = 0;
+        while offset < lowered.chunk.len() {
+            let op = RegOp::try_from(lowered.chunk.code()[offset]).unwrap();
+            ops.insert(u8::from(op));
+            offset += 1 + op.operand_width();
+        }
+        ops
+    }
+
+    #[test]
+    fn test_lower_arithmetic_loop() {
+        let stack = count();
+        let lowered = lower(&stack);
+        assert_eq!(lowered.chunk.encoding(), Encoding::Register { registers: 5 });
+        assert!(instructions(&lowered.chunk) * 2 < instructions(&stack.chunk));
+
+        let expected = "\
+== count (5 registers) ==
+0000    1 LOAD_CONSTANT    r1      0 0
+0004    | LOAD_CONSTANT    r2      0 0
+0008    2 LESS             r3   r2 r0
+0012    | JUMP_IF_FALSE    r3     12 -> 31
+0016    3 LOAD_CONSTANT    r4      1 1
+0020    | ADD              r2   r2 r4
+0024    4 ADD              r1   r1 r2
+0028    | LOOP               28 -> 8
+0031    5 RETURN           r1
+local n slot 0 0000..0033
+local total slot 1 0000..0033
+local i slot 2 0008..0033
+";
+        assert_eq!(disassemble(&lowered.chunk, "count"), expected);
+
+        let mut vm = Vm::new();
+        for n in [0, 1, 10] {
+            let stack = vm.call(closure(count()), vec![Value::Int(n)]).unwrap();
+            assert_eq!(vm.call(closure(lower(&count())), vec![Value::Int(n)]).unwrap(), stack);
+        }
+        let err = vm.call(closure(lowered.clone()), vec![Value::string("ten")]).unwrap_err();
+        assert!(matches!(err.kind, crate::VmErrorKind::TypeMismatch { .. }));
+        assert_eq!(err.span().map(|span| span.start_line), Some(2));
+        assert!(vm.call(closure(lowered.clone()), vec![Value::Int(3)]).is_ok());
+
+        let loaded = oxb::load(&oxb::save(&lowered)).unwrap();
+        assert_eq!(*loaded, lowered);
+    }
+
+    #[test]
+    fn test_lower_calls_and_mixed_encodings() {
+        // fn fib(n) { if n < 2 { return n }; fib(n - 1) + fib(n - 2) }
+        let mut chunk = Chunk::new();
+        let span = line(1);
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(0, span);
+        chunk.write_constant(OpCode::Constant, Constant::Int(2), span).unwrap();
+        chunk.write_op(OpCode::Less, span);
+        let recurse = chunk.write_jump(OpCode::JumpIfFalse, span);
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(0, span);
+        chunk.write_op(OpCode::Return, span);
+        chunk.patch_jump(recurse).unwrap();
+        for step in [1, 2] {
+            chunk.write_constant(OpCode::GetGlobal, Constant::String(Rc::from("fib")), span).unwrap();
+            chunk.write_op(OpCode::GetLocal, span);
+            chunk.write(0, span);
+            chunk.write_constant(OpCode::Constant, Constant::Int(step), span).unwrap();
+            chunk.write_op(OpCode::Subtract, span);
+            chunk.write_op(OpCode::Call, span);
+            chunk.write(1, span);
+        }
+        chunk.write_op(OpCode::Add, span);
+        chunk.write_op(OpCode::Return, span);
+        let fib = Function { name: "fib".to_string(), arity: 1, captures: vec![], chunk };
+
+        // The script makes a closure, so stays on the stack, while the
+        // function it contains is lowered
+        let mut script = Chunk::new();
+        script.write_constant(OpCode::Closure, Constant::Function(Rc::new(fib)), span).unwrap();
+        script.write_constant(OpCode::DefineGlobal, Constant::String(Rc::from("fib")), span).unwrap();
+        script.write_constant(OpCode::GetGlobal, Constant::String(Rc::from("fib")), span).unwrap();
+        script.write_constant(OpCode::Constant, Constant::Int(20), span).unwrap();
+        script.write_op(OpCode::Call, span);
+        script.write(1, span);
+        script.write_op(OpCode::Return, span);
+        let script = lower(&Function { name: "script".to_string(), arity: 0, captures: vec![], chunk: script });
+        assert_eq!(script.chunk.encoding(), Encoding::Stack);
+        let Some(Constant::Function(fib)) = script.chunk.constant(0) else { panic!("expected fib") };
+        assert_eq!(fib.chunk.encoding(), Encoding::Register { registers: 5 });
+
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(6765));
+        assert_eq!(vm.call(vm.global("fib").unwrap(), vec![Value::Int(10)]).unwrap(), Value::Int(55));
+
+        let mut handled = count();
+        handled.chunk.add_handler(Handler { kind: HandlerKind::Catch, start: 0, end: 4, target: 4, depth: 0 });
+        assert_eq!(lower(&handled).chunk.encoding(), Encoding::Stack);
+    }
+
+    #[test]
+    fn test_register_forms_match_stack_encoding() {
+        let mut forms = BTreeSet::new();
+        let values = [
+            Value::Int(7),
+            Value::Int(-2),
+            Value::Int(0),
+            Value::Int(i64::MAX),
+            Value::Int(i64::MIN),
+            Value::Float(2.5),
+            Value::Bool(true),
+            Value::Nil,
+            Value::string("ox"),
+        ];
+        let pairs: Vec<_> = values.iter().flat_map(|a| values.iter().map(|b| vec![a.clone(), b.clone()])).collect();
+        let singles: Vec<_> = values.iter().map(|value| vec![value.clone()]).collect();
+
+        // fn op(a, b) { a <op> b }
+        for op in RegOp::ALL.into_iter().filter_map(RegOp::operator) {
+            let binary = !matches!(op, OpCode::Negate | OpCode::Not);
+            let operator = function(op.name(), if binary { 2 } else { 1 }, |chunk| {
+                get_local(chunk, 0);
+                if binary {
+                    get_local(chunk, 1);
+                }
+                chunk.write_op(op, line(1));
+                chunk.write_op(OpCode::Return, line(1));
+            });
+            forms.extend(parity(operator, if binary { &pairs } else { &singles }));
+        }
+
+        // fn literal() { <literal> }
+        for literal in [OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False] {
+            let load = function(literal.name(), 0, |chunk| {
+                match literal {
+                    OpCode::Constant => chunk.write_constant(literal, Constant::Float(1.5), line(1)).unwrap(),
+                    _ => {
+                        chunk.write_op(literal, line(1));
+                    }
+                }
+                chunk.write_op(OpCode::Return, line(1));
+            });
+            forms.extend(parity(load, &[vec![]]));
+        }
+
+        // fn assign(a, b) { a = b; a }
+        let assign = function("assign", 2, |chunk| {
+            get_local(chunk, 1);
+            chunk.write_op(OpCode::SetLocal, line(1));
+            chunk.write(0, line(1));
+            chunk.write_op(OpCode::Pop, line(1));
+            get_local(chunk, 0);
+            chunk.write_op(OpCode::Return, line(1));
+        });
+        forms.extend(parity(assign, &pairs));
+
+        // fn globals(a) { var g = a; g = g * 2; g }, and the same assigning
+        // and reading a global that is not defined
+        for (defined, name) in [(true, "g"), (false, "missing")] {
+            let name = || Constant::String(Rc::from(name));
+            let globals = function("globals", 1, |chunk| {
+                if defined {
+                    get_local(chunk, 0);
+                    chunk.write_constant(OpCode::DefineGlobal, name(), line(1)).unwrap();
+                }
+                chunk.write_constant(OpCode::GetGlobal, name(), line(2)).unwrap();
+                chunk.write_constant(OpCode::Constant, Constant::Int(2), line(2)).unwrap();
+                chunk.write_op(OpCode::Multiply, line(2));
+                chunk.write_constant(OpCode::SetGlobal, name(), line(2)).unwrap();
+                chunk.write_op(OpCode::Pop, line(2));
+                chunk.write_constant(OpCode::GetGlobal, name(), line(3)).unwrap();
+                chunk.write_op(OpCode::Return, line(3));
+            });
+            forms.extend(parity(globals, &singles));
+        }
+        let missing = function("missing", 1, |chunk| {
+            get_local(chunk, 0);
+            chunk.write_constant(OpCode::SetGlobal, Constant::String(Rc::from("missing")), line(1)).unwrap();
+            chunk.write_op(OpCode::Return, line(1));
+        });
+        forms.extend(parity(missing, &singles));
+
+        // fn choose(c) { if c { 1 } else { 2 } }
+        let choose = function("choose", 1, |chunk| {
+            get_local(chunk, 0);
+            let otherwise = chunk.write_jump(OpCode::JumpIfFalse, line(1));
+            chunk.write_constant(OpCode::Constant, Constant::Int(1), line(2)).unwrap();
+            let end = chunk.write_jump(OpCode::Jump, line(2));
+            chunk.patch_jump(otherwise).unwrap();
+            chunk.write_constant(OpCode::Constant, Constant::Int(2), line(3)).unwrap();
+            chunk.patch_jump(end).unwrap();
+            chunk.write_op(OpCode::Return, line(4));
+        });
+        forms.extend(parity(choose, &singles));
+        // count(n) loops n times
+        let counts: Vec<_> = singles.iter().filter(|args| args[0] != Value::Int(i64::MAX)).cloned().collect();
+        forms.extend(parity(count(), &counts));
+
+        // fn apply(f, x) { f(x) }
+        let apply = function("apply", 2, |chunk| {
+            get_local(chunk, 0);
+            get_local(chunk, 1);
+            chunk.write_op(OpCode::Call, line(1));
+            chunk.write(1, line(1));
+            chunk.write_op(OpCode::Return, line(1));
+        });
+        let double = Value::Native(Rc::new(Native::new("double", 1, |_, args| match &args[0] {
+            Value::Int(n) => Ok(Value::Int(n.wrapping_mul(2))),
+            other => Err(crate::VmErrorKind::TypeMismatch { expected: "an integer", found: other.kind() }),
+        })));
+        let pair = Value::Native(Rc::new(Native::new("pair", 2, |_, _| Ok(Value::Nil))));
+        let callees = [closure(count()), closure(lower(&count())), double, pair, Value::Int(1)];
+        let calls: Vec<_> = callees
+            .iter()
+            .flat_map(|callee| counts.iter().map(|arg| vec![callee.clone(), arg[0].clone()]))
+            .collect();
+        forms.extend(parity(apply, &calls));
+
+        assert_eq!(forms, RegOp::ALL.into_iter().map(u8::from).collect());
+    }
+}
@@ -0,0 +1,373 @@
+//! Values on the VM stack.
+//!
+//! Scalars are stored inline; strings, closures and instances are shared by
+//! reference. Instances are backed by a runtime object, which messages are
+//! dispatched on, and keep their fields beside it, as the runtime does not
+//! store instance variables yet. Tasks and channels are numbered by the VM
+//! that made them (see [`Vm::spawn`]).
+
+use crate::chunk::{Constant, Function};
+use crate::error::{VmError, VmErrorKind};
+use crate::vm::{ChannelId, TaskId, Vm, objects};
+use oxidec::Object;
+use oxidec::runtime::ffi::{ForeignFunction, Library};
+use oxidec::runtime::sandbox::Capability;
+use oxidex_codegen::ir::Extern;
+use std::cell::{OnceCell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+/// A value the VM computes with.
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// `nil`
+    Nil,
+    /// Boolean
+    Bool(bool),
+    /// Integer of any width
+    Int(i64),
+    /// Floating point number of any width
+    Float(f64),
+    /// String
+    String(Rc<str>),
+    /// Function with the variables it captured
+    Closure(Rc<Closure>),
+    /// C function declared `extern`
+    Foreign(Rc<Foreign>),
+    /// Function the host implements
+    Native(Rc<Native>),
+    /// Instance of a runtime class
+    Object(Rc<Instance>),
+    /// Error raised by the VM, as a handler receives it
+    Error(Rc<VmError>),
+    /// Task the VM runs alongside others
+    Task(TaskId),
+    /// Queue of values tasks send each other
+    Channel(ChannelId),
+}
+
+/// A variable captured by a closure.
+///
+/// While the variable's function is running, the upvalue refers to its
+/// stack slot; when the slot goes away, the value moves into the upvalue.
+#[derive(Debug, Clone)]
+pub enum Upvalue {
+    /// Still on the stack, at an absolute index
+    Open(usize),
+    /// Moved off the stack
+    Closed(Value),
+}
+
+/// A function and the variables it captured.
+#[derive(Debug)]
+pub struct Closure {
+    /// The compiled function
+    pub function: Rc<Function>,
+    /// Captured variables, in the order of the function's captures
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A C function declared `extern`. It is looked up on its first call,
+/// and the lookup is kept for the calls after.
+#[derive(Debug)]
+pub struct Foreign {
+    /// The declaration
+    pub declaration: Rc<Extern>,
+    /// The function, once looked up
+    function: OnceCell<Rc<ForeignFunction>>,
+}
+
+impl Foreign {
+    /// Wrap a declaration whose function is not looked up yet.
+    #[must_use]
+    pub fn new(declaration: Rc<Extern>) -> Self {
+        Self { declaration, function: OnceCell::new() }
+    }
+
+    /// Get the function, loading its library and looking it up on first
+    /// use.
+    pub(crate) fn function(&self) -> oxidec::Result<Rc<ForeignFunction>> {
+        if let Some(function) = self.function.get() {
+            return Ok(Rc::clone(function));
+        }
+        let declaration = &self.declaration;
+        let library = Library::open(declaration.library.as_deref())?;
+        let function = Rc::new(library.function(&declaration.name, &declaration.encoding)?);
+        Ok(Rc::clone(self.function.get_or_init(|| function)))
+    }
+}
+
+/// Implementation of a host function: receives the VM and the arguments,
+/// which are as many as the function's arity.
+pub type NativeFn = Rc<dyn Fn(&mut Vm, &[Value]) -> Result<Value, VmErrorKind>>;
+
+/// A function the host implements, which bytecode calls like any other.
+///
+/// The VM's garbage collector does not see the values a host function
+/// captured, so those must be kept alive elsewhere, such as in a global.
+pub struct Native {
+    /// Name, for error messages
+    pub name: String,
+    /// Number of arguments
+    pub arity: u8,
+    /// Implementation
+    pub function: NativeFn,
+    /// Capability the function needs, if it reaches outside the VM
+    pub capability: Option<Capability>,
+}
+
+impl Native {
+    /// Wrap a host function.
+    pub fn new(
+        name: impl Into<String>,
+        arity: u8,
+        function: impl Fn(&mut Vm, &[Value]) -> Result<Value, VmErrorKind> + 'static,
+    ) -> Self {
+        Self { name: name.into(), arity, function: Rc::new(function), capability: None }
+    }
+
+    /// Wrap a host function that needs `capability`, which the VM's
+    /// sandbox must allow for bytecode to call it.
+    pub fn requiring(
+        name: impl Into<String>,
+        capability: Capability,
+        arity: u8,
+        function: impl Fn(&mut Vm, &[Value]) -> Result<Value, VmErrorKind> + 'static,
+    ) -> Self {
+        Self { capability: Some(capability), ..Self::new(name, arity, function) }
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("capability", &self.capability)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An instance of a runtime class.
+#[derive(Debug)]
+pub struct Instance {
+    /// Runtime object giving the instance its identity
+    pub object: Object,
+    /// Fields set so far, in assignment order
+    pub fields: RefCell<Vec<(Rc<str>, Value)>>,
+}
+
+impl Instance {
+    /// Wrap a runtime object with no fields set.
+    #[must_use]
+    pub fn new(object: Object) -> Self {
+        Self { object, fields: RefCell::new(Vec::new()) }
+    }
+
+    /// Get the value of a field.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().iter().find(|(field, _)| &**field == name).map(|(_, value)| value.clone())
+    }
+
+    /// Set the value of a field.
+    pub fn set_field(&self, name: &str, value: Value) {
+        let mut fields = self.fields.borrow_mut();
+        match fields.iter_mut().find(|(field, _)| &**field == name) {
+            Some((_, slot)) => *slot = value,
+            None => fields.push((Rc::from(name), value)),
+        }
+    }
+
+    /// Find the index of a field, trying `hint` first.
+    pub(crate) fn field_index(&self, name: &str, hint: Option<usize>) -> Option<usize> {
+        let fields = self.fields.borrow();
+        let is_named = |index: &usize| fields.get(*index).is_some_and(|(field, _)| &**field == name);
+        hint.filter(is_named).or_else(|| fields.iter().position(|(field, _)| &**field == name))
+    }
+
+    /// Set the value of a field, trying the index `hint` first, and return
+    /// the field's index.
+    pub(crate) fn store_field(&self, name: &Rc<str>, value: Value, hint: Option<usize>) -> usize {
+        let index = self.field_index(name, hint);
+        let mut fields = self.fields.borrow_mut();
+        match index {
+            Some(index) => {
+                fields[index].1 = value;
+                index
+            }
+            None => {
+                fields.push((Rc::clone(name), value));
+                fields.len() - 1
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Create a string value.
+    #[must_use]
+    pub fn string(text: &str) -> Self {
+        Self::String(Rc::from(text))
+    }
+
+    /// Describe the kind of the value, for error messages.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Bool(_) => "a boolean",
+            Self::Int(_) => "an integer",
+            Self::Float(_) => "a float",
+            Self::String(_) => "a string",
+            Self::Closure(_) | Self::Foreign(_) | Self::Native(_) => "a function",
+            Self::Object(_) => "an object",
+            Self::Error(_) => "an error",
+            Self::Task(_) => "a task",
+            Self::Channel(_) => "a channel",
+        }
+    }
+}
+
+impl From<&Constant> for Value {
+    /// Load a literal constant. Function prototypes become closures that
+    /// capture nothing; [`OpCode::Closure`](crate::OpCode::Closure) is what
+    /// captures their variables.
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Int(value) => Self::Int(*value),
+            Constant::Float(value) => Self::Float(*value),
+            Constant::String(text) => Self::String(Rc::clone(text)),
+            Constant::Function(function) => {
+                Self::Closure(Rc::new(Closure { function: Rc::clone(function), upvalues: Vec::new() }))
+            }
+            Constant::Extern(declaration) => Self::Foreign(Rc::new(Foreign::new(Rc::clone(declaration)))),
+        }
+    }
+}
+
+/// Scalars and strings compare by value; functions, instances, errors,
+/// tasks and channels by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Closure(a), Self::Closure(b)) => Rc::ptr_eq(a, b),
+            (Self::Foreign(a), Self::Foreign(b)) => Rc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => Rc::ptr_eq(a, b),
+            (Self::Object(a), Self::Object(b)) => Rc::ptr_eq(a, b),
+            (Self::Error(a), Self::Error(b)) => Rc::ptr_eq(a, b),
+            (Self::Task(a), Self::Task(b)) => a == b,
+            (Self::Channel(a), Self::Channel(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Values display as the interpreter's do: strings without quotes at the
+/// top level and quoted inside collections and instances, which show their
+/// elements and fields. The alternate form (`{:#}`) quotes top-level strings
+/// too. `print` and string interpolation show an instance by the
+/// `description()` its class defines instead, if it has one.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(text) if !f.alternate() => write!(f, "{text}"),
+            value => write_nested(f, value),
+        }
+    }
+}
+
+fn write_nested(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::Nil => write!(f, "nil"),
+        Value::Bool(value) => write!(f, "{value}"),
+        Value::Int(value) => write!(f, "{value}"),
+        Value::Float(value) => write!(f, "{value:?}"),
+        Value::String(text) => write!(f, "{text:?}"),
+        Value::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+        Value::Foreign(foreign) => write!(f, "<extern fn {}>", foreign.declaration.name),
+        Value::Native(native) => write!(f, "<native fn {}>", native.name),
+        Value::Object(instance) => write_instance(f, instance),
+        Value::Error(error) => write!(f, "{}", error.kind),
+        Value::Task(task) => write!(f, "<{task}>"),
+        Value::Channel(channel) => write!(f, "<{channel}>"),
+    }
+}
+
+/// Write an instance: a collection by its elements, an enum value by its
+/// variant and payload, and anything else by its class and fields.
+fn write_instance(f: &mut fmt::Formatter<'_>, instance: &Instance) -> fmt::Result {
+    let list = |f: &mut fmt::Formatter<'_>, values: &[(Rc<str>, Value)]| -> fmt::Result {
+        for (index, (_, value)) in values.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write_nested(f, value)?;
+        }
+        Ok(())
+    };
+
+    let class = instance.object.class();
+    let fields = instance.fields.borrow();
+    match class.name() {
+        objects::TUPLE => {
+            write!(f, "(")?;
+            list(f, &fields)?;
+            write!(f, ")")
+        }
+        objects::ARRAY => {
+            write!(f, "[")?;
+            list(f, &fields)?;
+            write!(f, "]")
+        }
+        objects::DICT if fields.is_empty() => write!(f, "[:]"),
+        objects::DICT => {
+            write!(f, "[")?;
+            for (index, (_, entry)) in fields.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                let Value::Object(entry) = entry else {
+                    return write_nested(f, entry);
+                };
+                let (key, value) = (entry.field("0").unwrap_or(Value::Nil), entry.field("1").unwrap_or(Value::Nil));
+                write_nested(f, &key)?;
+                write!(f, ": ")?;
+                write_nested(f, &value)?;
+            }
+            write!(f, "]")
+        }
+        name => match instance.field(objects::VARIANT) {
+            Some(Value::String(variant)) => {
+                write!(f, "{name}::{variant}")?;
+                match instance.field(objects::PAYLOAD) {
+                    Some(Value::Object(payload)) if payload.object.class().name() == objects::TUPLE => {
+                        write_instance(f, &payload)
+                    }
+                    Some(payload) => {
+                        write!(f, "(")?;
+                        write_nested(f, &payload)?;
+                        write!(f, ")")
+                    }
+                    None => Ok(()),
+                }
+            }
+            _ => {
+                write!(f, "{name}(")?;
+                for (index, (field, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{field}: ")?;
+                    write_nested(f, value)?;
+                }
+                write!(f, ")")
+            }
+        },
+    }
+}
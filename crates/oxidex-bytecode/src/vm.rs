@@ -0,0 +1,1957 @@
+//! The virtual machine.
+//!
+//! The [`Vm`] runs [`Function`]s on a value stack. Each call pushes a frame
+//! whose base is the stack index of its first argument: the callee sits just
+//! below it, and local slots count from it, so a function's parameters are
+//! its first locals. Returning pops the callee, arguments and locals, and
+//! pushes the result in their place. Functions in the
+//! [register encoding](crate::register) keep their registers on the stack
+//! too, from the same base: while one runs, the stack holds exactly its
+//! registers above its base.
+//!
+//! A message to an instance whose class, or a superclass, has a method the
+//! program defines, a global function named by [`method_symbol`], calls it
+//! with the receiver first. The VM answers the messages of the objects it
+//! makes itself; other messages to instances are dispatched through the
+//! runtime, crossing into native code as machine words the way the
+//! method's type encoding describes them. Instances returned from native code are found again by
+//! their object address, so they must have been made by [`Vm::instance`].
+//! Functions declared `extern` are C functions, which the runtime loads on
+//! their first call and converts arguments for.
+//! Message sends and field accesses keep [inline caches](crate::cache) of
+//! what they resolved to for the classes of their receivers.
+//!
+//! Instructions are decoded with a `match` on the opcode. With the
+//! `threaded-dispatch` feature, the opcode byte instead indexes a table of
+//! handlers, one per opcode, which is faster but compiles a copy of the
+//! instruction code per opcode.
+//!
+//! A [`Tracer`] set with [`Vm::set_tracer`] receives a line for each
+//! instruction before it runs: the function, the instruction's disassembly
+//! and the running frame's part of the stack. A [`Profiler`] set with
+//! [`Vm::set_profiler`] is told of every call, loop back edge and message
+//! send.
+//!
+//! A [`Compiler`] set with [`Vm::set_compiler`] is asked for machine code
+//! for each function called, and for a function looping without any. A
+//! frame with machine code runs it instead of interpreting, leaving it at
+//! calls, returns and errors, which the VM handles as it would for an
+//! interpreted instruction; returning to the frame enters the code again.
+//!
+//! An error raised by an instruction records, for each frame, the offset of
+//! the instruction it was executing, the source that instruction was
+//! compiled from and the values of the named locals in scope. The VM then unwinds to the innermost [`Handler`] covering
+//! an active instruction, popping the frames and stack slots above it and
+//! closing their captured variables. Errors no handler catches stop the run,
+//! which unwinds every frame it pushed.
+//!
+//! Closures and instances the VM makes are tracked by a
+//! [garbage collector](crate::gc), which frees the cycles among them at
+//! safepoints once enough were made.
+//!
+//! Functions the host implements are [`Native`] values, which bytecode calls
+//! like any other function. The VM also runs calls as cooperative tasks,
+//! each with a stack and frames of its own, which host functions suspend
+//! to wait for a time, a value on a channel or another task (see
+//! [`Vm::spawn`]).
+//!
+//! A [`Sandbox`] set with [`Vm::set_sandbox`] denies bytecode capabilities:
+//! calling a host function [requiring](Vm::define_native_requiring) one it
+//! denies raises [`VmErrorKind::Sandboxed`], which handlers cannot catch, as
+//! do calling an `extern` function and sending a message to a runtime
+//! object while it denies [`Capability::Ffi`]. The VM enters the sandbox
+//! while it runs, so the runtime enforces it on what host functions do too.
+
+use crate::cache::{FunctionCaches, InlineCache, SendTarget};
+use crate::chunk::{Capture, Constant, Encoding, Function, Handler, HandlerKind};
+use crate::disasm;
+use crate::error::{TraceFrame, VmError, VmErrorKind};
+use crate::gc::Collector;
+use crate::opcodes::OpCode;
+use crate::register::RegOp;
+use crate::value::{Closure, Foreign, Instance, Native, Upvalue, Value};
+use oxidec::runtime::encoding::parse_signature;
+use oxidec::runtime::ffi::ForeignValue;
+use oxidec::runtime::sandbox::{Capability, Sandbox};
+use oxidec::runtime::{MessageArgs, ObjectPtr};
+use oxidec::{Class, Object, Selector};
+use oxidex_codegen::intrinsics::Intrinsic;
+use oxidex_codegen::ir::method_symbol;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::rc::{Rc, Weak};
+use std::str::FromStr;
+
+mod native;
+pub(crate) mod objects;
+mod task;
+#[cfg(feature = "threaded-dispatch")]
+mod threaded;
+
+pub use native::{NativeHelper, NativeStatus, SendHelper, SendSite, native_helper};
+pub use task::{ChannelId, TaskId};
+
+/// Result of executing part of an instruction.
+type Step<T> = std::result::Result<T, VmErrorKind>;
+
+/// An error on its way to a handler.
+enum Raise {
+    /// Raised by the running instruction
+    Kind(VmErrorKind),
+    /// Raised again by a handler, with the trace it was first raised with
+    Error(VmError),
+}
+
+impl From<VmErrorKind> for Raise {
+    fn from(kind: VmErrorKind) -> Self {
+        Self::Kind(kind)
+    }
+}
+
+/// Most calls that may be active at once.
+pub const MAX_FRAMES: usize = 1024;
+
+/// Receives a line for each instruction the VM executes.
+pub trait Tracer {
+    /// Record an instruction that is about to run.
+    fn trace(&mut self, line: &str);
+}
+
+impl<F: FnMut(&str)> Tracer for F {
+    fn trace(&mut self, line: &str) {
+        self(line);
+    }
+}
+
+/// Receives the calls and loop iterations the VM executes.
+pub trait Profiler {
+    /// Record a call to a function.
+    fn call(&mut self, function: &Rc<Function>);
+
+    /// Record a jump back to the loop header at offset `header` of a
+    /// function.
+    fn backedge(&mut self, function: &Rc<Function>, header: usize);
+
+    /// Record a message a function sent to an instance of `class`.
+    fn send(&mut self, function: &Rc<Function>, selector: &str, class: &Class) {
+        let _ = (function, selector, class);
+    }
+
+    /// Record the arguments of a call to a function.
+    fn arguments(&mut self, function: &Rc<Function>, args: &[Value]) {
+        let _ = (function, args);
+    }
+}
+
+/// Machine code compiled from a function.
+///
+/// The code runs the function's instructions from an instruction offset by
+/// calling the [helper](native_helper) of each instruction's opcode, and
+/// stops at the first helper that returns [`NativeStatus::Exit`].
+///
+/// Code entered at offset 0 may instead compute the whole call from the
+/// frame's [arguments](Vm::native_arguments) and
+/// [return](Vm::native_return) it, if the function has no effect but its
+/// result.
+pub trait NativeCode: fmt::Debug {
+    /// Run the code from the instruction at `offset` of the running frame,
+    /// passing `floor` to the helpers, returning `false` without running
+    /// anything if the code has no entry there.
+    fn run(&self, vm: &mut Vm, floor: usize, offset: usize) -> bool;
+}
+
+/// Compiles functions to machine code as the VM runs them.
+pub trait Compiler {
+    /// Get the machine code for a stack-encoded function about to be called
+    /// or looping, compiling it if it is due, or `None` to interpret it.
+    fn compile(&mut self, function: &Rc<Function>) -> Option<Rc<dyn NativeCode>>;
+}
+
+/// A running call.
+#[derive(Debug)]
+struct CallFrame {
+    /// The function being run
+    closure: Rc<Closure>,
+    /// Offset of the next byte to execute
+    ip: usize,
+    /// Offset of the instruction being executed
+    current: usize,
+    /// Stack index of local slot 0
+    base: usize,
+    /// Number of registers, if the function is register encoded
+    registers: Option<usize>,
+    /// Machine code compiled from the function
+    native: Option<Rc<dyn NativeCode>>,
+}
+
+/// Runs bytecode.
+#[derive(Default)]
+pub struct Vm {
+    /// Value stack
+    stack: Vec<Value>,
+    /// Active calls, innermost last
+    frames: Vec<CallFrame>,
+    /// Global variables
+    globals: HashMap<Rc<str>, Value>,
+    /// Captured variables still on the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    /// Instances by object address, so native code can return them
+    objects: HashMap<usize, Weak<Instance>>,
+    /// Inline caches by function address
+    caches: HashMap<usize, FunctionCaches>,
+    /// Frees cycles of closures and instances
+    collector: Collector,
+    /// Receiver of executed instructions, in trace mode
+    tracer: Option<Box<dyn Tracer>>,
+    /// Receiver of calls and loop iterations
+    profiler: Option<Box<dyn Profiler>>,
+    /// Compiler of functions to machine code
+    compiler: Option<Box<dyn Compiler>>,
+    /// How the last run of machine code ended, if it returned from the run
+    /// or raised an error
+    native_outcome: Option<Result<Option<Value>, Raise>>,
+    /// Tasks and channels
+    tasks: task::Scheduler,
+    /// Capabilities denied to bytecode
+    sandbox: Sandbox,
+}
+
+impl fmt::Debug for Vm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vm")
+            .field("stack", &self.stack)
+            .field("frames", &self.frames)
+            .field("globals", &self.globals)
+            .field("tracing", &self.tracer.is_some())
+            .field("profiling", &self.profiler.is_some())
+            .field("compiling", &self.compiler.is_some())
+            .field("tasks", &self.tasks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Vm {
+    /// Create a VM with no globals.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define a global variable, replacing any previous definition.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(Rc::from(name), value);
+    }
+
+    /// Define a global host function taking `arity` arguments, replacing
+    /// any previous definition.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: u8,
+        function: impl Fn(&mut Vm, &[Value]) -> Result<Value, VmErrorKind> + 'static,
+    ) {
+        self.define_global(name, Value::Native(Rc::new(Native::new(name, arity, function))));
+    }
+
+    /// Define a global host function taking `arity` arguments that needs
+    /// `capability`, replacing any previous definition. Calls to it raise
+    /// [`VmErrorKind::Sandboxed`] if the sandbox denies the capability.
+    pub fn define_native_requiring(
+        &mut self,
+        name: &str,
+        capability: Capability,
+        arity: u8,
+        function: impl Fn(&mut Vm, &[Value]) -> Result<Value, VmErrorKind> + 'static,
+    ) {
+        let native = Native::requiring(name, capability, arity, function);
+        self.define_global(name, Value::Native(Rc::new(native)));
+    }
+
+    /// Get the value of a global variable.
+    #[must_use]
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
+    }
+
+    /// Wrap a runtime object as an instance the VM can send messages to and
+    /// receive back from native code.
+    pub fn instance(&mut self, object: Object) -> Value {
+        let instance = Rc::new(Instance::new(object));
+        self.collector.track_instance(&instance);
+        self.objects.retain(|_, instance| instance.strong_count() > 0);
+        self.objects.insert(object_address(instance.object.as_raw()), Rc::downgrade(&instance));
+        Value::Object(instance)
+    }
+
+    /// Free unreachable cycles of closures and instances, returning how
+    /// many captured variables and instances were emptied to break them.
+    ///
+    /// Only the VM's stack and globals, and instances whose runtime object
+    /// is retained elsewhere, keep values alive here: a value returned to
+    /// the host that is part of a cycle is emptied unless one of those also
+    /// reaches it.
+    pub fn collect_garbage(&mut self) -> usize {
+        let roots = self.roots();
+        self.collector.collect(roots, true)
+    }
+
+    /// Collect garbage at safepoints, when a closure is made and at loop
+    /// back edges, once `threshold` captured variables and instances have
+    /// been made since the last collection, or never if `None`, the
+    /// default. See [`Vm::collect_garbage`] for the values a collection
+    /// keeps.
+    pub fn set_gc_threshold(&mut self, threshold: Option<usize>) {
+        self.collector.set_threshold(threshold);
+    }
+
+    /// Make most collections due at safepoints minor ones, which only
+    /// sweep what was made since the last collection.
+    pub fn set_gc_nursery(&mut self, nursery: bool) {
+        self.collector.set_nursery(nursery);
+    }
+
+    /// Turn on trace mode, sending each executed instruction to `tracer`.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Turn off trace mode, returning the tracer.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    /// Send each call and loop iteration to `profiler`.
+    pub fn set_profiler(&mut self, profiler: impl Profiler + 'static) {
+        self.profiler = Some(Box::new(profiler));
+    }
+
+    /// Stop profiling, returning the profiler.
+    pub fn take_profiler(&mut self) -> Option<Box<dyn Profiler>> {
+        self.profiler.take()
+    }
+
+    /// Run functions through machine code from `compiler` once it compiles
+    /// them. Trace mode interprets every instruction regardless.
+    pub fn set_compiler(&mut self, compiler: impl Compiler + 'static) {
+        self.compiler = Some(Box::new(compiler));
+    }
+
+    /// Stop compiling, returning the compiler. Frames already running
+    /// machine code keep running it.
+    pub fn take_compiler(&mut self) -> Option<Box<dyn Compiler>> {
+        self.compiler.take()
+    }
+
+    /// Deny bytecode the capabilities `sandbox` denies.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = sandbox;
+    }
+
+    /// The capabilities denied to bytecode.
+    #[must_use]
+    pub fn sandbox(&self) -> Sandbox {
+        self.sandbox
+    }
+
+    /// Run a script: a function taking no arguments.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`VmError`] if the script raises one.
+    pub fn run(&mut self, script: Rc<Function>) -> Result<Value, VmError> {
+        self.caches.retain(|_, caches| caches.is_live());
+        let closure = Closure { function: script, upvalues: Vec::new() };
+        self.call(Value::Closure(Rc::new(closure)), Vec::new())
+    }
+
+    /// Call a function with arguments.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`VmError`] if `callee` is not a function taking `args`, or
+    /// if it raises one.
+    pub fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, VmError> {
+        let _sandbox = self.sandbox.enter();
+        if let Value::Foreign(foreign) = &callee {
+            return call_foreign(self.sandbox, foreign, &args).map_err(|kind| VmError { kind, trace: Vec::new() });
+        }
+        if let Value::Native(native) = &callee {
+            self.tasks.calls += 1;
+            let result = call_native(self, native, &args);
+            self.tasks.calls -= 1;
+            return result.map_err(|kind| VmError { kind, trace: Vec::new() });
+        }
+        let (height, floor) = (self.stack.len(), self.frames.len());
+        let argc = args.len();
+        self.stack.push(callee);
+        self.stack.extend(args);
+        if let Err(kind) = self.call_value(argc) {
+            self.stack.truncate(height);
+            return Err(VmError { kind, trace: Vec::new() });
+        }
+        // Host functions this call reaches cannot suspend the running task
+        self.tasks.calls += 1;
+        let result = self.execute(height, floor);
+        self.tasks.calls -= 1;
+        result
+    }
+
+    /// Execute until the frame above `floor` returns, or a host function
+    /// suspends the running task, returning `nil`.
+    fn execute(&mut self, height: usize, floor: usize) -> Result<Value, VmError> {
+        loop {
+            let native = match self.frames.last() {
+                Some(frame) if self.tracer.is_none() => frame.native.clone(),
+                _ => None,
+            };
+            let result = match native {
+                Some(native) => self.run_native(&*native, floor),
+                None => {
+                    if self.tracer.is_some() {
+                        self.trace();
+                    }
+                    self.step(floor)
+                }
+            };
+            match result {
+                Ok(None) if self.tasks.is_suspending() => return Ok(Value::Nil),
+                Ok(None) => {}
+                Ok(Some(result)) => return Ok(result),
+                Err(raise) => self.raise(raise, height, floor)?,
+            }
+        }
+    }
+
+    /// Unwind an error to the innermost handler above `floor` covering an
+    /// active instruction, or, if none does, pop every frame above `floor`
+    /// and the stack down to `height`, and return the error.
+    fn raise(&mut self, raise: Raise, height: usize, floor: usize) -> Result<(), VmError> {
+        let error = match raise {
+            Raise::Kind(kind) => {
+                let trace = self.frames[floor..].iter().rev().map(|frame| trace_frame(frame, &self.stack));
+                VmError { kind, trace: trace.collect() }
+            }
+            Raise::Error(error) => error,
+        };
+        if error.kind.is_catchable() && self.unwind(&error, floor) {
+            return Ok(());
+        }
+        self.close_upvalues(height);
+        self.frames.truncate(floor);
+        self.stack.truncate(height);
+        Err(error)
+    }
+
+    /// Run the running frame's machine code from its next instruction,
+    /// or execute the instruction if the code has no entry there.
+    fn run_native(&mut self, native: &dyn NativeCode, floor: usize) -> Result<Option<Value>, Raise> {
+        let ip = self.frame().ip;
+        if !native.run(self, floor, ip) {
+            return self.step(floor);
+        }
+        self.native_outcome.take().unwrap_or(Ok(None))
+    }
+
+    /// Execute one instruction, returning the result if it returned from
+    /// the frame above `floor`.
+    fn step(&mut self, floor: usize) -> Result<Option<Value>, Raise> {
+        let frame = self.frame_mut();
+        frame.current = frame.ip;
+        let registers = frame.registers.is_some();
+        let byte = self.read_u8()?;
+        if registers {
+            return self.execute_register(byte, floor);
+        }
+        self.dispatch(byte, floor)
+    }
+
+    /// Execute the instruction with opcode `byte`, whose operands follow.
+    #[cfg(not(feature = "threaded-dispatch"))]
+    #[inline(always)]
+    fn dispatch(&mut self, byte: u8, floor: usize) -> Result<Option<Value>, Raise> {
+        self.match_dispatch(byte, floor)
+    }
+
+    /// Execute the instruction with opcode `byte` by matching on the
+    /// opcode. Tests of threaded dispatch compare it with this.
+    #[cfg(any(test, not(feature = "threaded-dispatch")))]
+    #[inline(always)]
+    fn match_dispatch(&mut self, byte: u8, floor: usize) -> Result<Option<Value>, Raise> {
+        let op = OpCode::try_from(byte).map_err(VmErrorKind::InvalidOpcode)?;
+        self.execute_op(op, floor)
+    }
+
+    /// Execute an instruction whose opcode has been read.
+    #[inline(always)]
+    fn execute_op(&mut self, op: OpCode, floor: usize) -> Result<Option<Value>, Raise> {
+        match op {
+            OpCode::Constant => {
+                let index = self.read_u16()?;
+                let value = Value::from(&self.constant(index)?);
+                self.stack.push(value);
+            }
+            OpCode::Nil => self.stack.push(Value::Nil),
+            OpCode::True => self.stack.push(Value::Bool(true)),
+            OpCode::False => self.stack.push(Value::Bool(false)),
+
+            OpCode::Pop => {
+                self.pop()?;
+            }
+            OpCode::Dup => {
+                let value = self.peek(0)?.clone();
+                self.stack.push(value);
+            }
+
+            OpCode::GetLocal => {
+                let slot = self.local_slot()?;
+                self.stack.push(self.stack[slot].clone());
+            }
+            OpCode::SetLocal => {
+                let slot = self.local_slot()?;
+                self.stack[slot] = self.peek(0)?.clone();
+            }
+            OpCode::GetGlobal => {
+                let name = self.read_name()?;
+                let value = self.globals.get(&name).cloned();
+                self.stack.push(value.ok_or_else(|| VmErrorKind::UndefinedGlobal(name.to_string()))?);
+            }
+            OpCode::DefineGlobal => {
+                let name = self.read_name()?;
+                let value = self.pop()?;
+                self.globals.insert(name, value);
+            }
+            OpCode::SetGlobal => {
+                let name = self.read_name()?;
+                let value = self.peek(0)?.clone();
+                match self.globals.get_mut(&name) {
+                    Some(global) => *global = value,
+                    None => return Err(VmErrorKind::UndefinedGlobal(name.to_string()).into()),
+                }
+            }
+            OpCode::GetUpvalue => {
+                let upvalue = self.read_upvalue()?;
+                let value = match &*upvalue.borrow() {
+                    Upvalue::Open(index) => self.stack[*index].clone(),
+                    Upvalue::Closed(value) => value.clone(),
+                };
+                self.stack.push(value);
+            }
+            OpCode::SetUpvalue => {
+                let upvalue = self.read_upvalue()?;
+                let value = self.peek(0)?.clone();
+                match &mut *upvalue.borrow_mut() {
+                    Upvalue::Open(index) => self.stack[*index] = value,
+                    Upvalue::Closed(closed) => *closed = value,
+                }
+                self.collector.write_upvalue(&upvalue);
+            }
+
+            OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Remainder
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual => {
+                let right = self.pop()?;
+                let left = self.pop()?;
+                self.stack.push(binary(op, left, right)?);
+            }
+            OpCode::Equal | OpCode::NotEqual => {
+                let right = self.pop()?;
+                let left = self.pop()?;
+                self.stack.push(Value::Bool((left == right) == (op == OpCode::Equal)));
+            }
+            OpCode::Negate => {
+                let value = negate(self.pop()?)?;
+                self.stack.push(value);
+            }
+            OpCode::Not => {
+                let value = truth(&self.pop()?)?;
+                self.stack.push(Value::Bool(!value));
+            }
+
+            OpCode::Send => {
+                let name = self.read_name()?;
+                let argc = usize::from(self.read_u8()?);
+                self.send_message(&name, argc, None)?;
+            }
+            OpCode::GetField => {
+                let name = self.read_name()?;
+                let instance = self.pop_instance()?;
+                let (site, class) = (self.frame().current, instance.object.class());
+                let cache = self.caches().fields.entry(site).or_default();
+                let index = instance.field_index(&name, cache.get(&class).copied());
+                let index = index.ok_or_else(|| VmErrorKind::NoSuchField(name.to_string()))?;
+                cache.record(class, index);
+                let value = instance.fields.borrow()[index].1.clone();
+                self.stack.push(value);
+            }
+            OpCode::SetField => {
+                let name = self.read_name()?;
+                let value = self.pop()?;
+                let instance = self.pop_instance()?;
+                let (site, class) = (self.frame().current, instance.object.class());
+                let cache = self.caches().fields.entry(site).or_default();
+                let index = instance.store_field(&name, value.clone(), cache.get(&class).copied());
+                cache.record(class, index);
+                self.collector.write_instance(&instance);
+                self.stack.push(value);
+            }
+
+            OpCode::Jump => {
+                let distance = self.read_u16()?;
+                self.frame_mut().ip += usize::from(distance);
+            }
+            OpCode::JumpIfFalse => {
+                let distance = self.read_u16()?;
+                if !truth(&self.pop()?)? {
+                    self.frame_mut().ip += usize::from(distance);
+                }
+            }
+            OpCode::Loop => {
+                let distance = self.read_u16()?;
+                let frame = self.frame_mut();
+                frame.ip = frame.ip.checked_sub(usize::from(distance)).ok_or(VmErrorKind::Truncated)?;
+                if let Some(profiler) = &mut self.profiler {
+                    let frame = self.frames.last().expect("a frame is running");
+                    profiler.backedge(&frame.closure.function, frame.ip);
+                }
+                // A function looping long enough may be compiled mid-call
+                if let Some(compiler) = &mut self.compiler {
+                    let frame = self.frames.last_mut().expect("a frame is running");
+                    if frame.native.is_none() {
+                        frame.native = compiler.compile(&frame.closure.function);
+                    }
+                }
+                self.safepoint();
+            }
+
+            OpCode::Closure => {
+                let index = self.read_u16()?;
+                let Constant::Function(function) = self.constant(index)? else {
+                    return Err(VmErrorKind::BadConstant(index).into());
+                };
+                let frame = self.frame();
+                let (base, enclosing) = (frame.base, Rc::clone(&frame.closure));
+                let mut upvalues = Vec::with_capacity(function.captures.len());
+                for capture in &function.captures {
+                    upvalues.push(match *capture {
+                        Capture::Local(slot) => self.capture(base + usize::from(slot))?,
+                        Capture::Upvalue(index) => {
+                            let upvalue = enclosing.upvalues.get(usize::from(index));
+                            Rc::clone(upvalue.ok_or(VmErrorKind::BadUpvalue(index))?)
+                        }
+                    });
+                }
+                self.stack.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                self.safepoint();
+            }
+            OpCode::CloseUpvalue => {
+                self.peek(0)?;
+                self.close_upvalues(self.stack.len() - 1);
+                self.stack.pop();
+            }
+            OpCode::Call => {
+                let argc = usize::from(self.read_u8()?);
+                self.call_value(argc)?;
+            }
+            OpCode::Return => {
+                // The result may be a captured local, so it is closed over first
+                self.peek(0)?;
+                self.close_upvalues(self.frame().base);
+                let result = self.stack.pop().expect("the stack was checked");
+                return Ok(self.return_value(result, floor));
+            }
+
+            OpCode::Throw => {
+                let value = self.pop()?;
+                return Err(VmErrorKind::Thrown(value).into());
+            }
+            OpCode::Rethrow => {
+                return Err(match self.pop()? {
+                    Value::Error(error) => Raise::Error(Rc::unwrap_or_clone(error)),
+                    value => VmErrorKind::Thrown(value).into(),
+                });
+            }
+
+            OpCode::Intrinsic => {
+                let id = self.read_u8()?;
+                let intrinsic = Intrinsic::from_id(id).ok_or(VmErrorKind::BadIntrinsic(id))?;
+                self.peek(intrinsic.arity() - 1)?;
+                let args = self.stack.split_off(self.stack.len() - intrinsic.arity());
+                self.stack.push(intrinsic_value(intrinsic, args)?);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Execute a register instruction with opcode `byte`, whose operands
+    /// follow.
+    fn execute_register(&mut self, byte: u8, floor: usize) -> Result<Option<Value>, Raise> {
+        let op = RegOp::try_from(byte).map_err(VmErrorKind::InvalidOpcode)?;
+        match op {
+            RegOp::Move => {
+                let dst = self.read_register()?;
+                let src = self.read_register()?;
+                self.stack[dst] = self.stack[src].clone();
+            }
+            RegOp::LoadConstant => {
+                let dst = self.read_register()?;
+                let index = self.read_u16()?;
+                self.stack[dst] = Value::from(&self.constant(index)?);
+            }
+            RegOp::LoadNil | RegOp::LoadTrue | RegOp::LoadFalse => {
+                let dst = self.read_register()?;
+                self.stack[dst] = match op {
+                    RegOp::LoadNil => Value::Nil,
+                    _ => Value::Bool(op == RegOp::LoadTrue),
+                };
+            }
+
+            RegOp::GetGlobal => {
+                let dst = self.read_register()?;
+                let name = self.read_name()?;
+                let value = self.globals.get(&name).cloned();
+                self.stack[dst] = value.ok_or_else(|| VmErrorKind::UndefinedGlobal(name.to_string()))?;
+            }
+            RegOp::DefineGlobal => {
+                let src = self.read_register()?;
+                let name = self.read_name()?;
+                self.globals.insert(name, self.stack[src].clone());
+            }
+            RegOp::SetGlobal => {
+                let src = self.read_register()?;
+                let name = self.read_name()?;
+                match self.globals.get_mut(&name) {
+                    Some(global) => *global = self.stack[src].clone(),
+                    None => return Err(VmErrorKind::UndefinedGlobal(name.to_string()).into()),
+                }
+            }
+
+            RegOp::Add
+            | RegOp::Subtract
+            | RegOp::Multiply
+            | RegOp::Divide
+            | RegOp::Remainder
+            | RegOp::Less
+            | RegOp::LessEqual
+            | RegOp::Greater
+            | RegOp::GreaterEqual => {
+                let dst = self.read_register()?;
+                let left = self.read_register()?;
+                let right = self.read_register()?;
+                let operator = op.operator().expect("arithmetic and comparisons have stack operators");
+                self.stack[dst] = binary(operator, self.stack[left].clone(), self.stack[right].clone())?;
+            }
+            RegOp::Equal | RegOp::NotEqual => {
+                let dst = self.read_register()?;
+                let left = self.read_register()?;
+                let right = self.read_register()?;
+                self.stack[dst] = Value::Bool((self.stack[left] == self.stack[right]) == (op == RegOp::Equal));
+            }
+            RegOp::Negate => {
+                let dst = self.read_register()?;
+                let src = self.read_register()?;
+                self.stack[dst] = negate(self.stack[src].clone())?;
+            }
+            RegOp::Not => {
+                let dst = self.read_register()?;
+                let src = self.read_register()?;
+                self.stack[dst] = Value::Bool(!truth(&self.stack[src])?);
+            }
+
+            RegOp::Jump => {
+                let distance = self.read_u16()?;
+                self.frame_mut().ip += usize::from(distance);
+            }
+            RegOp::JumpIfFalse => {
+                let src = self.read_register()?;
+                let distance = self.read_u16()?;
+                if !truth(&self.stack[src])? {
+                    self.frame_mut().ip += usize::from(distance);
+                }
+            }
+            RegOp::Loop => {
+                let distance = self.read_u16()?;
+                let frame = self.frame_mut();
+                frame.ip = frame.ip.checked_sub(usize::from(distance)).ok_or(VmErrorKind::Truncated)?;
+                if let Some(profiler) = &mut self.profiler {
+                    let frame = self.frames.last().expect("a frame is running");
+                    profiler.backedge(&frame.closure.function, frame.ip);
+                }
+                self.safepoint();
+            }
+
+            RegOp::Call => {
+                let callee = self.read_register()?;
+                let argc = usize::from(self.read_u8()?);
+                if callee + argc >= self.stack.len() {
+                    return Err(VmErrorKind::StackUnderflow.into());
+                }
+                self.stack.truncate(callee + argc + 1);
+                self.call_value(argc)?;
+            }
+            RegOp::Return => {
+                let src = self.read_register()?;
+                let result = self.stack[src].clone();
+                self.close_upvalues(self.frame().base);
+                return Ok(self.return_value(result, floor));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pop the running frame, whose upvalues are closed, and return `result`
+    /// to its caller, or from the run if it was the frame above `floor`.
+    fn return_value(&mut self, result: Value, floor: usize) -> Option<Value> {
+        let frame = self.frames.pop().expect("a frame is running");
+        self.stack.truncate(frame.base - 1);
+        if self.frames.len() == floor {
+            return Some(result);
+        }
+        self.stack.push(result);
+        let caller = self.frame();
+        if let Some(registers) = caller.registers {
+            self.stack.resize(caller.base + registers, Value::Nil);
+        }
+        None
+    }
+
+    /// Unwind to the innermost handler for `error` in a frame above `floor`
+    /// and jump to it, returning whether there was one.
+    fn unwind(&mut self, error: &VmError, floor: usize) -> bool {
+        while self.frames.len() > floor {
+            let frame = self.frame();
+            let handler = frame.closure.function.chunk.handler(frame.current).copied();
+            let base = frame.base;
+            let Some(Handler { kind, target, depth, .. }) = handler else {
+                self.close_upvalues(base);
+                self.stack.truncate(base - 1);
+                self.frames.pop();
+                continue;
+            };
+            self.close_upvalues(base + depth);
+            self.stack.truncate(base + depth);
+            self.stack.push(match (kind, &error.kind) {
+                (HandlerKind::Catch, VmErrorKind::Thrown(value)) => value.clone(),
+                _ => Value::Error(Rc::new(error.clone())),
+            });
+            self.frame_mut().ip = target;
+            return true;
+        }
+        false
+    }
+
+    /// Send the instruction about to run to the tracer.
+    fn trace(&mut self) {
+        let frame = self.frame();
+        let function = &frame.closure.function;
+        if frame.ip >= function.chunk.len() {
+            return;
+        }
+        let (instruction, _) = disasm::instruction(&function.chunk, frame.ip);
+        let instruction = instruction.lines().next().unwrap_or_default();
+        let mut line = format!("{:<12} {instruction:<48}", function.name);
+        for value in &self.stack[frame.base - 1..] {
+            let _ = write!(line, "[ {value} ]");
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace(line.trim_end());
+        }
+    }
+
+    /// Call the value below the top `argc` values.
+    fn call_value(&mut self, argc: usize) -> Step<()> {
+        let callee = self.stack.len().checked_sub(argc + 1).ok_or(VmErrorKind::StackUnderflow)?;
+        if let Value::Foreign(_) | Value::Native(_) = &self.stack[callee] {
+            // C and host functions return at once, in the callee's place
+            let result = match &self.stack[callee] {
+                Value::Foreign(foreign) => call_foreign(self.sandbox, foreign, &self.stack[callee + 1..])?,
+                Value::Native(native) => {
+                    let (native, args) = (Rc::clone(native), self.stack[callee + 1..].to_vec());
+                    let result = call_native(self, &native, &args);
+                    // A host function that fails does not suspend
+                    self.tasks.suspended_at(callee, result.is_ok());
+                    result?
+                }
+                _ => unreachable!("the callee is a C or host function"),
+            };
+            self.stack.truncate(callee);
+            self.stack.push(result);
+            // A task may call one as its whole body, with no frame
+            if let Some(frame) = self.frames.last()
+                && let Some(registers) = frame.registers
+            {
+                self.stack.resize(frame.base + registers, Value::Nil);
+            }
+            return Ok(());
+        }
+        let Value::Closure(closure) = &self.stack[callee] else {
+            return Err(VmErrorKind::NotCallable(self.stack[callee].kind()));
+        };
+        let function = &closure.function;
+        if usize::from(function.arity) != argc {
+            let function = function.name.clone();
+            return Err(VmErrorKind::ArityMismatch { function, expected: closure.function.arity, found: argc });
+        }
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(VmErrorKind::StackOverflow);
+        }
+        let registers = match function.chunk.encoding() {
+            Encoding::Stack => None,
+            Encoding::Register { registers } => Some(usize::from(registers)),
+        };
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(function);
+            profiler.arguments(function, &self.stack[callee + 1..]);
+        }
+        let native = match (&mut self.compiler, registers) {
+            (Some(compiler), None) => compiler.compile(function),
+            _ => None,
+        };
+        let closure = Rc::clone(closure);
+        self.frames.push(CallFrame { closure, ip: 0, current: 0, base: callee + 1, registers, native });
+        if let Some(registers) = registers {
+            self.stack.resize(callee + 1 + registers, Value::Nil);
+        }
+        Ok(())
+    }
+
+    /// Send a message to the receiver below the top `argc` values.
+    ///
+    /// A method the program defines is called, pushing its frame. Anything
+    /// else pops the receiver and arguments and pushes the result: the VM
+    /// answers the messages of the objects it makes itself, and looks the
+    /// methods of runtime objects up through `cache`, or the running
+    /// function's caches if there is none.
+    fn send_message(&mut self, name: &str, argc: usize, cache: Option<&mut InlineCache<SendTarget>>) -> Step<()> {
+        if self.call_method(name, argc)? {
+            return Ok(());
+        }
+        let args = self.stack.split_off(self.stack.len() - argc);
+        let receiver = self.pop()?;
+        let result = match self.answer(&receiver, name, &args) {
+            Some(result) => result?,
+            None => {
+                let (instance, target) = match cache {
+                    Some(cache) => lookup(cache, &receiver, name)?,
+                    None => {
+                        let site = self.frame().current;
+                        lookup(self.caches().sends.entry(site).or_default(), &receiver, name)?
+                    }
+                };
+                self.profile_send(name, instance);
+                self.invoke(instance, &target, &args)?
+            }
+        };
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Call the method the program defines for a message to the receiver
+    /// below the top `argc` values, if its class or a superclass defines
+    /// one, returning whether it did. A method is the global function
+    /// named by [`method_symbol`], which takes the receiver first.
+    fn call_method(&mut self, name: &str, argc: usize) -> Step<bool> {
+        let Value::Object(instance) = self.peek(argc)? else {
+            return Ok(false);
+        };
+        let Some(method) = self.method(instance, name) else {
+            return Ok(false);
+        };
+        let receiver = self.stack.len() - argc - 1;
+        self.stack.insert(receiver, method);
+        self.call_value(argc + 1)?;
+        Ok(true)
+    }
+
+    /// The method the program defines for a message to an instance, if its
+    /// class or a superclass defines one.
+    fn method(&self, instance: &Instance, name: &str) -> Option<Value> {
+        let mut class = Some(instance.object.class());
+        while let Some(current) = class {
+            if let Some(method @ Value::Closure(_)) = self.globals.get(method_symbol(current.name(), name).as_str()) {
+                return Some(method.clone());
+            }
+            class = current.super_class();
+        }
+        None
+    }
+
+    /// Tell the profiler of a message the running function sends.
+    fn profile_send(&mut self, name: &str, instance: &Instance) {
+        if self.profiler.is_some() {
+            let function = Rc::clone(&self.frame().closure.function);
+            if let Some(profiler) = &mut self.profiler {
+                profiler.send(&function, name, &instance.object.class());
+            }
+        }
+    }
+
+    /// Call the method a message resolved to.
+    fn invoke(&self, instance: &Instance, target: &SendTarget, args: &[Value]) -> Step<Value> {
+        // Methods of runtime objects are native code
+        self.sandbox.check(Capability::Ffi).map_err(VmErrorKind::Sandboxed)?;
+        let words = args.iter().map(encode).collect::<Step<Vec<_>>>()?;
+        let args = match words[..] {
+            [] => MessageArgs::None,
+            [a] => MessageArgs::One(a),
+            [a, b] => MessageArgs::Two([a, b]),
+            [a, b, c] => MessageArgs::Three([a, b, c]),
+            [a, b, c, d] => MessageArgs::Four([a, b, c, d]),
+            [a, b, c, d, e] => MessageArgs::Five([a, b, c, d, e]),
+            [a, b, c, d, e, f] => MessageArgs::Six([a, b, c, d, e, f]),
+            [a, b, c, d, e, f, g] => MessageArgs::Seven([a, b, c, d, e, f, g]),
+            [a, b, c, d, e, f, g, h] => MessageArgs::Eight([a, b, c, d, e, f, g, h]),
+            _ => return Err(VmErrorKind::NotNative("a message with more than eight arguments")),
+        };
+        let word = instance.object.send_message(&target.selector, &args).map_err(VmErrorKind::Runtime)?;
+        self.decode_return(target.return_type, word.unwrap_or_default())
+    }
+
+    /// Decode the return word of a native method from its return type.
+    fn decode_return(&self, return_type: char, word: usize) -> Step<Value> {
+        Ok(match return_type {
+            'v' => Value::Nil,
+            'c' | 's' | 'i' | 'l' => Value::Int(i64::from(word as i32)),
+            'C' | 'S' | 'I' | 'L' => Value::Int(i64::from(word as u32)),
+            'q' | 'Q' => Value::Int(word as i64),
+            'B' => Value::Bool(word != 0),
+            'f' => Value::Float(f64::from(f32::from_bits(word as u32))),
+            'd' => Value::Float(f64::from_bits(word as u64)),
+            '@' if word == 0 => Value::Nil,
+            '@' => match self.objects.get(&word).and_then(Weak::upgrade) {
+                Some(instance) => Value::Object(instance),
+                None => return Err(VmErrorKind::NotNative("an object the VM did not make")),
+            },
+            _ => return Err(VmErrorKind::NotNative("a native return value of this type")),
+        })
+    }
+
+    /// Get the upvalue for the stack slot at `index`, sharing an open one if
+    /// another closure already captured the slot.
+    fn capture(&mut self, index: usize) -> Step<Rc<RefCell<Upvalue>>> {
+        if index >= self.stack.len() {
+            return Err(VmErrorKind::StackUnderflow);
+        }
+        let is_slot = |upvalue: &&Rc<RefCell<Upvalue>>| matches!(*upvalue.borrow(), Upvalue::Open(i) if i == index);
+        if let Some(upvalue) = self.open_upvalues.iter().find(is_slot) {
+            return Ok(Rc::clone(upvalue));
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(index)));
+        self.collector.track_upvalue(&upvalue);
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        Ok(upvalue)
+    }
+
+    /// Move the captured stack slots from `from` upwards off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        let (stack, collector) = (&self.stack, &mut self.collector);
+        self.open_upvalues.retain(|upvalue| {
+            let index = match *upvalue.borrow() {
+                Upvalue::Open(index) if index >= from => index,
+                _ => return true,
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[index].clone());
+            collector.write_upvalue(upvalue);
+            false
+        });
+    }
+
+    /// Collect garbage if a collection is due.
+    fn safepoint(&mut self) {
+        if self.collector.is_due() {
+            let roots = self.roots();
+            self.collector.collect_due(roots);
+        }
+    }
+
+    /// The values that are in use wherever the VM is: the stack, the globals
+    /// and the running closures.
+    fn roots(&self) -> Vec<Value> {
+        let closures = self.frames.iter().map(|frame| Value::Closure(Rc::clone(&frame.closure)));
+        let roots = self.stack.iter().chain(self.globals.values()).cloned().chain(closures);
+        roots.chain(self.tasks.roots()).collect()
+    }
+
+    /// Get the inline caches of the running function.
+    fn caches(&mut self) -> &mut FunctionCaches {
+        let function = &self.frames.last().expect("a frame is running").closure.function;
+        let address = Rc::as_ptr(function) as usize;
+        self.caches.entry(address).or_insert_with(|| FunctionCaches::new(function))
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a frame is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("a frame is running")
+    }
+
+    fn read_u8(&mut self) -> Step<u8> {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.read_u8(frame.ip).ok_or(VmErrorKind::Truncated)?;
+        frame.ip += 1;
+        Ok(byte)
+    }
+
+    fn read_u16(&mut self) -> Step<u16> {
+        let frame = self.frame_mut();
+        let value = frame.closure.function.chunk.read_u16(frame.ip).ok_or(VmErrorKind::Truncated)?;
+        frame.ip += 2;
+        Ok(value)
+    }
+
+    fn constant(&self, index: u16) -> Step<Constant> {
+        self.frame().closure.function.chunk.constant(index).cloned().ok_or(VmErrorKind::BadConstant(index))
+    }
+
+    /// Read a constant operand naming a global, field or selector.
+    fn read_name(&mut self) -> Step<Rc<str>> {
+        let index = self.read_u16()?;
+        match self.constant(index)? {
+            Constant::String(name) => Ok(name),
+            _ => Err(VmErrorKind::BadConstant(index)),
+        }
+    }
+
+    /// Read a register operand, as a stack index.
+    fn read_register(&mut self) -> Step<usize> {
+        let register = self.read_u8()?;
+        let index = self.frame().base + usize::from(register);
+        if index < self.stack.len() { Ok(index) } else { Err(VmErrorKind::BadRegister(register)) }
+    }
+
+    /// Read a local slot operand, as a stack index.
+    fn local_slot(&mut self) -> Step<usize> {
+        let slot = self.frame().base + usize::from(self.read_u8()?);
+        if slot < self.stack.len() { Ok(slot) } else { Err(VmErrorKind::StackUnderflow) }
+    }
+
+    fn read_upvalue(&mut self) -> Step<Rc<RefCell<Upvalue>>> {
+        let index = self.read_u8()?;
+        let upvalue = self.frame().closure.upvalues.get(usize::from(index));
+        upvalue.cloned().ok_or(VmErrorKind::BadUpvalue(index))
+    }
+
+    /// Get the value `distance` below the top of the running frame's stack.
+    fn peek(&self, distance: usize) -> Step<&Value> {
+        let index = self.stack.len().checked_sub(distance + 1).filter(|index| *index >= self.frame().base);
+        index.map(|index| &self.stack[index]).ok_or(VmErrorKind::StackUnderflow)
+    }
+
+    fn pop(&mut self) -> Step<Value> {
+        self.peek(0)?;
+        Ok(self.stack.pop().expect("the stack was checked"))
+    }
+
+    fn pop_instance(&mut self) -> Step<Rc<Instance>> {
+        match self.pop()? {
+            Value::Object(instance) => Ok(instance),
+            value => Err(VmErrorKind::TypeMismatch { expected: "an object", found: value.kind() }),
+        }
+    }
+}
+
+/// Find the method a message to a value resolves to, through the inline
+/// cache of the sending instruction.
+fn lookup<'a>(
+    cache: &mut InlineCache<SendTarget>,
+    receiver: &'a Value,
+    name: &str,
+) -> Step<(&'a Instance, SendTarget)> {
+    let does_not_respond = || VmErrorKind::DoesNotRespond { selector: name.to_string(), receiver: receiver.kind() };
+    let Value::Object(instance) = receiver else {
+        return Err(does_not_respond());
+    };
+    let class = instance.object.class();
+    let target = match cache.get(&class) {
+        Some(target) => target.clone(),
+        None => {
+            let target = resolve(&class, name)?.ok_or_else(does_not_respond)?;
+            cache.record(class, target.clone());
+            target
+        }
+    };
+    Ok((instance, target))
+}
+
+/// Find the method a message resolves to for a class, if it responds.
+fn resolve(class: &Class, name: &str) -> Step<Option<SendTarget>> {
+    let selector = Selector::from_str(name).map_err(VmErrorKind::Runtime)?;
+    let Some(encoding) = class.lookup_method(&selector).and_then(|method| method.types.as_str().ok()) else {
+        return Ok(None);
+    };
+    let (return_type, _) = parse_signature(encoding).map_err(VmErrorKind::Runtime)?;
+    Ok(Some(SendTarget { selector, return_type }))
+}
+
+/// Record where a call is, and the values of its named locals.
+fn trace_frame(frame: &CallFrame, stack: &[Value]) -> TraceFrame {
+    let function = &frame.closure.function;
+    let chunk = &function.chunk;
+    let locals = chunk
+        .locals_at(frame.current)
+        .filter_map(|local| {
+            let value = stack.get(frame.base + usize::from(local.slot))?;
+            Some((local.name.to_string(), value.clone()))
+        })
+        .collect();
+    TraceFrame { function: function.name.clone(), offset: frame.current, span: chunk.span(frame.current), locals }
+}
+
+/// Negate a number.
+fn negate(value: Value) -> Step<Value> {
+    match value {
+        Value::Int(value) => Ok(Value::Int(value.checked_neg().ok_or(VmErrorKind::IntegerOverflow)?)),
+        Value::Float(value) => Ok(Value::Float(-value)),
+        value => Err(VmErrorKind::TypeMismatch { expected: "a number", found: value.kind() }),
+    }
+}
+
+/// Compute an intrinsic over its operands.
+fn intrinsic_value(intrinsic: Intrinsic, args: Vec<Value>) -> Step<Value> {
+    match (intrinsic, args.as_slice()) {
+        (Intrinsic::Length, [Value::String(text)]) => {
+            let length = text.chars().count();
+            Ok(Value::Int(i64::try_from(length).map_err(|_| VmErrorKind::IntegerOverflow)?))
+        }
+        (Intrinsic::Length, [value]) => Err(VmErrorKind::TypeMismatch { expected: "a string", found: value.kind() }),
+        (Intrinsic::Abs, [Value::Int(value)]) => {
+            value.checked_abs().map(Value::Int).ok_or(VmErrorKind::IntegerOverflow)
+        }
+        (Intrinsic::Abs, [Value::Float(value)]) => Ok(Value::Float(value.abs())),
+        (Intrinsic::Min, [Value::Int(a), Value::Int(b)]) => Ok(Value::Int(*a.min(b))),
+        (Intrinsic::Max, [Value::Int(a), Value::Int(b)]) => Ok(Value::Int(*a.max(b))),
+        (Intrinsic::Min, [Value::Float(a), Value::Float(b)]) => Ok(Value::Float(a.min(*b))),
+        (Intrinsic::Max, [Value::Float(a), Value::Float(b)]) => Ok(Value::Float(a.max(*b))),
+        (Intrinsic::Min | Intrinsic::Max, [a @ (Value::Int(_) | Value::Float(_)), b]) => {
+            Err(VmErrorKind::TypeMismatch { expected: a.kind(), found: b.kind() })
+        }
+        (Intrinsic::Sqrt, [Value::Float(value)]) => Ok(Value::Float(value.sqrt())),
+        (Intrinsic::Sqrt, [value]) => Err(VmErrorKind::TypeMismatch { expected: "a float", found: value.kind() }),
+        (_, [value, ..]) => Err(VmErrorKind::TypeMismatch { expected: "a number", found: value.kind() }),
+        (_, []) => Err(VmErrorKind::StackUnderflow),
+    }
+}
+
+/// Whether a condition holds. Conditions must be booleans.
+fn truth(value: &Value) -> Step<bool> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        value => Err(VmErrorKind::TypeMismatch { expected: "a boolean", found: value.kind() }),
+    }
+}
+
+/// Apply an arithmetic or ordering operator. Both operands must be of the
+/// same kind; strings can be added and ordered, numbers can also be
+/// combined arithmetically.
+fn binary(op: OpCode, left: Value, right: Value) -> Step<Value> {
+    let ordering = match (&left, &right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let value = match op {
+                OpCode::Add => a.checked_add(*b),
+                OpCode::Subtract => a.checked_sub(*b),
+                OpCode::Multiply => a.checked_mul(*b),
+                OpCode::Divide | OpCode::Remainder if *b == 0 => return Err(VmErrorKind::DivisionByZero),
+                OpCode::Divide => a.checked_div(*b),
+                OpCode::Remainder => a.checked_rem(*b),
+                _ => return Ok(Value::Bool(compare(op, a.partial_cmp(b)))),
+            };
+            return value.map(Value::Int).ok_or(VmErrorKind::IntegerOverflow);
+        }
+        (Value::Float(a), Value::Float(b)) => {
+            return Ok(match op {
+                OpCode::Add => Value::Float(a + b),
+                OpCode::Subtract => Value::Float(a - b),
+                OpCode::Multiply => Value::Float(a * b),
+                OpCode::Divide => Value::Float(a / b),
+                OpCode::Remainder => Value::Float(a % b),
+                _ => Value::Bool(compare(op, a.partial_cmp(b))),
+            });
+        }
+        (Value::String(a), Value::String(b)) => match op {
+            OpCode::Add => return Ok(Value::String(Rc::from(format!("{a}{b}")))),
+            _ if is_arithmetic(op) => None,
+            _ => Some(a.cmp(b)),
+        },
+        _ => None,
+    };
+    if ordering.is_some() {
+        return Ok(Value::Bool(compare(op, ordering)));
+    }
+    let (expected, found) = match left {
+        Value::Int(_) | Value::Float(_) => (left.kind(), right.kind()),
+        Value::String(_) if !is_arithmetic(op) => ("a string", right.kind()),
+        _ => ("a number", left.kind()),
+    };
+    Err(VmErrorKind::TypeMismatch { expected, found })
+}
+
+fn is_arithmetic(op: OpCode) -> bool {
+    matches!(op, OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Remainder)
+}
+
+/// Whether an ordering satisfies a comparison operator. Unordered values
+/// satisfy none.
+fn compare(op: OpCode, ordering: Option<Ordering>) -> bool {
+    let Some(ordering) = ordering else { return false };
+    match op {
+        OpCode::Less => ordering.is_lt(),
+        OpCode::LessEqual => ordering.is_le(),
+        OpCode::Greater => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+/// Call a host function after checking its arity, and that the sandbox
+/// allows what it needs.
+fn call_native(vm: &mut Vm, native: &Native, args: &[Value]) -> Step<Value> {
+    if args.len() != usize::from(native.arity) {
+        let function = native.name.clone();
+        return Err(VmErrorKind::ArityMismatch { function, expected: native.arity, found: args.len() });
+    }
+    if let Some(capability) = native.capability {
+        vm.sandbox.check(capability).map_err(VmErrorKind::Sandboxed)?;
+    }
+    (native.function)(vm, args)
+}
+
+/// Call a C function declared `extern`, loading it on its first call, if
+/// the sandbox allows calling native code.
+fn call_foreign(sandbox: Sandbox, foreign: &Foreign, args: &[Value]) -> Step<Value> {
+    sandbox.check(Capability::Ffi).map_err(VmErrorKind::Sandboxed)?;
+    let function = foreign.function().map_err(VmErrorKind::Runtime)?;
+    let args = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Nil => Ok(ForeignValue::Nil),
+            Value::Bool(value) => Ok(ForeignValue::Bool(*value)),
+            Value::Int(value) => Ok(ForeignValue::Int(*value)),
+            Value::Float(value) => Ok(ForeignValue::Float(*value)),
+            Value::String(text) => Ok(ForeignValue::String(text.to_string())),
+            _ => Err(VmErrorKind::NotNative(arg.kind())),
+        })
+        .collect::<Step<Vec<_>>>()?;
+    // SAFETY: the program declared the function's signature, and the
+    // checker kept it to types C has
+    let result = unsafe { function.call(&args) }.map_err(VmErrorKind::Runtime)?;
+    Ok(match result {
+        ForeignValue::Void | ForeignValue::Nil => Value::Nil,
+        ForeignValue::Int(value) => Value::Int(value),
+        ForeignValue::Bool(value) => Value::Bool(value),
+        ForeignValue::Float(value) => Value::Float(value),
+        ForeignValue::String(text) => Value::String(Rc::from(text)),
+    })
+}
+
+/// Encode a value as an argument word.
+fn encode(value: &Value) -> Step<usize> {
+    match value {
+        Value::Nil => Ok(0),
+        Value::Bool(value) => Ok(usize::from(*value)),
+        Value::Int(value) => Ok(*value as usize),
+        Value::Float(value) => Ok(value.to_bits() as usize),
+        Value::Object(instance) => Ok(object_address(instance.object.as_raw())),
+        Value::String(_)
+        | Value::Closure(_)
+        | Value::Foreign(_)
+        | Value::Native(_)
+        | Value::Error(_)
+        | Value::Task(_)
+        | Value::Channel(_) => Err(VmErrorKind::NotNative(value.kind())),
+    }
+}
+
+/// Get the address of an object, which identifies it across the boundary.
+fn object_address(object: ObjectPtr) -> usize {
+    // SAFETY: `ObjectPtr` is a transparent wrapper around a raw pointer
+    unsafe { std::mem::transmute::<ObjectPtr, *mut u8>(object) as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Chunk, Local};
+    use oxidex_syntax::Span;
+
+    fn line(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    /// Build a function from instructions, each on its own line.
+    fn function(name: &str, arity: u8, captures: Vec<Capture>, code: &[(OpCode, Option<Constant>, &[u8])]) -> Function {
+        let mut chunk = Chunk::new();
+        for (index, (op, constant, operands)) in code.iter().enumerate() {
+            let span = line(index + 1);
+            match constant {
+                Some(constant) => chunk.write_constant(*op, constant.clone(), span).unwrap(),
+                None => {
+                    chunk.write_op(*op, span);
+                }
+            }
+            for byte in *operands {
+                chunk.write(*byte, span);
+            }
+        }
+        Function { name: name.to_string(), arity, captures, chunk }
+    }
+
+    fn int(value: i64) -> Option<Constant> {
+        Some(Constant::Int(value))
+    }
+
+    fn name(name: &str) -> Option<Constant> {
+        Some(Constant::String(Rc::from(name)))
+    }
+
+    fn closure(function: Function) -> Value {
+        Value::Closure(Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() }))
+    }
+
+    #[test]
+    fn test_loops_locals_and_calls() {
+        // var sum = 0; var i = 0; while i < 10 { i = i + 1; sum = sum + i }; sum
+        let span = line(1);
+        let local = |chunk: &mut Chunk, op: OpCode, slot: u8| {
+            chunk.write_op(op, span);
+            chunk.write(slot, span);
+        };
+        let mut chunk = Chunk::new();
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), span).unwrap();
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), span).unwrap();
+        let start = chunk.len();
+        local(&mut chunk, OpCode::GetLocal, 1);
+        chunk.write_constant(OpCode::Constant, Constant::Int(10), span).unwrap();
+        chunk.write_op(OpCode::Less, span);
+        let exit = chunk.write_jump(OpCode::JumpIfFalse, span);
+        local(&mut chunk, OpCode::GetLocal, 1);
+        chunk.write_constant(OpCode::Constant, Constant::Int(1), span).unwrap();
+        chunk.write_op(OpCode::Add, span);
+        local(&mut chunk, OpCode::SetLocal, 1);
+        chunk.write_op(OpCode::Pop, span);
+        local(&mut chunk, OpCode::GetLocal, 0);
+        local(&mut chunk, OpCode::GetLocal, 1);
+        chunk.write_op(OpCode::Add, span);
+        local(&mut chunk, OpCode::SetLocal, 0);
+        chunk.write_op(OpCode::Pop, span);
+        chunk.write_loop(start, span).unwrap();
+        chunk.patch_jump(exit).unwrap();
+        local(&mut chunk, OpCode::GetLocal, 0);
+        chunk.write_op(OpCode::Return, span);
+        let script = Function { name: "script".to_string(), arity: 0, captures: vec![], chunk };
+
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(55));
+
+        // add(2, 3), through a global
+        let add = function(
+            "add",
+            2,
+            vec![],
+            &[
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::GetLocal, None, &[1]),
+                (OpCode::Add, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        vm.define_global("add", closure(add));
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("add"), &[]),
+                (OpCode::Constant, int(2), &[]),
+                (OpCode::Constant, int(3), &[]),
+                (OpCode::Call, None, &[2]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(5));
+        let greeting = vm.call(vm.global("add").unwrap(), vec![Value::string("a"), Value::string("b")]).unwrap();
+        assert_eq!(greeting, Value::string("ab"));
+    }
+
+    #[test]
+    fn test_trace_mode_logs_instructions_and_stack() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut vm = Vm::new();
+        let sink = Rc::clone(&lines);
+        vm.set_tracer(move |line: &str| sink.borrow_mut().push(line.to_string()));
+
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, int(1), &[]),
+                (OpCode::Constant, int(2), &[]),
+                (OpCode::Add, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(3));
+        let expected = [
+            "script       0000    1 CONSTANT            0 1               [ <fn script> ]",
+            "script       0003    2 CONSTANT            1 2               [ <fn script> ][ 1 ]",
+            "script       0006    3 ADD                                   [ <fn script> ][ 1 ][ 2 ]",
+            "script       0007    4 RETURN                                [ <fn script> ][ 3 ]",
+        ];
+        assert_eq!(*lines.borrow(), expected);
+
+        assert!(vm.take_tracer().is_some());
+        vm.run(Rc::new(function("script", 0, vec![], &[(OpCode::Nil, None, &[]), (OpCode::Return, None, &[])])))
+            .unwrap();
+        assert_eq!(lines.borrow().len(), 4);
+    }
+
+    #[test]
+    fn test_handlers_catch_and_clean_up_errors() {
+        let catch = |start, end, target, depth| Handler { kind: HandlerKind::Catch, start, end, target, depth };
+        let mut vm = Vm::new();
+        let throw = [(OpCode::Constant, name("oops"), &[][..]), (OpCode::Throw, None, &[])];
+        let thrower = function("thrower", 0, vec![], &throw);
+        vm.define_global("thrower", closure(thrower));
+
+        // try { 1 / 0 } catch e { e }: errors the VM raises arrive as errors
+        let mut script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, int(1), &[]),
+                (OpCode::Constant, int(0), &[]),
+                (OpCode::Divide, None, &[]),
+                (OpCode::Return, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        script.chunk.add_handler(catch(0, 7, 8, 0));
+        let Value::Error(error) = vm.run(Rc::new(script)).unwrap() else { panic!("expected an error") };
+        assert!(matches!(error.kind, VmErrorKind::DivisionByZero));
+        assert_eq!(error.offset(), Some(6));
+
+        // var x = 5; try { thrower() } catch e { e }: thrown values arrive as
+        // they were, above the live locals, after the callee's frame is popped
+        let mut script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, int(5), &[]),
+                (OpCode::GetGlobal, name("thrower"), &[]),
+                (OpCode::Call, None, &[0]),
+                (OpCode::Return, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        script.chunk.add_handler(catch(3, 8, 9, 1));
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::string("oops"));
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+
+        // defer { cleaned = true }; thrower(): the cleanup runs, and the error
+        // leaves with the trace it was raised with
+        let mut script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("thrower"), &[]),
+                (OpCode::Call, None, &[0]),
+                (OpCode::Return, None, &[]),
+                (OpCode::True, None, &[]),
+                (OpCode::DefineGlobal, name("cleaned"), &[]),
+                (OpCode::Rethrow, None, &[]),
+            ],
+        );
+        script.chunk.add_handler(Handler { kind: HandlerKind::Cleanup, start: 0, end: 5, target: 6, depth: 0 });
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(&err.kind, VmErrorKind::Thrown(value) if *value == Value::string("oops")));
+        assert_eq!(err.trace[0].function, "thrower");
+        assert_eq!(vm.global("cleaned"), Some(Value::Bool(true)));
+        assert!(vm.stack.is_empty() && vm.open_upvalues.is_empty());
+
+        // Broken bytecode is not caught
+        let mut chunk = Chunk::new();
+        chunk.write(0xff, line(1));
+        chunk.write_op(OpCode::Return, line(1));
+        chunk.add_handler(catch(0, 1, 1, 0));
+        let script = Function { name: "script".to_string(), arity: 0, captures: vec![], chunk };
+        assert!(matches!(vm.run(Rc::new(script)).unwrap_err().kind, VmErrorKind::InvalidOpcode(0xff)));
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        // fn makeCounter() { var count = 0; return { count = count + 1; count } }
+        let increment = function(
+            "increment",
+            0,
+            vec![Capture::Local(0)],
+            &[
+                (OpCode::GetUpvalue, None, &[0]),
+                (OpCode::Constant, int(1), &[]),
+                (OpCode::Add, None, &[]),
+                (OpCode::SetUpvalue, None, &[0]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let increment = Some(Constant::Function(Rc::new(increment)));
+        let make_counter = function(
+            "makeCounter",
+            0,
+            vec![],
+            &[(OpCode::Constant, int(0), &[]), (OpCode::Closure, increment, &[]), (OpCode::Return, None, &[])],
+        );
+        let make_counter = closure(make_counter);
+
+        let mut vm = Vm::new();
+        let first = vm.call(make_counter.clone(), vec![]).unwrap();
+        let second = vm.call(make_counter, vec![]).unwrap();
+        assert_eq!(vm.call(first.clone(), vec![]).unwrap(), Value::Int(1));
+        assert_eq!(vm.call(first.clone(), vec![]).unwrap(), Value::Int(2));
+        assert_eq!(vm.call(second, vec![]).unwrap(), Value::Int(1));
+        assert_eq!(vm.call(first, vec![]).unwrap(), Value::Int(3));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_garbage_collection_frees_closure_cycles() {
+        // var f = nil; f = { f }, optionally keeping f in a global
+        let inner = [(OpCode::GetUpvalue, None, &[0][..]), (OpCode::Return, None, &[])];
+        let inner = function("f", 0, vec![Capture::Local(0)], &inner);
+        let inner = Some(Constant::Function(Rc::new(inner)));
+        let script = |keep: bool| {
+            let mut ops: Vec<(OpCode, Option<Constant>, &[u8])> = vec![
+                (OpCode::Nil, None, &[]),
+                (OpCode::Closure, inner.clone(), &[]),
+                (OpCode::SetLocal, None, &[0]),
+                (OpCode::Pop, None, &[]),
+            ];
+            if keep {
+                ops.extend([(OpCode::GetLocal, None, &[0][..]), (OpCode::DefineGlobal, name("f"), &[])]);
+            }
+            ops.extend([(OpCode::Nil, None, &[][..]), (OpCode::Return, None, &[])]);
+            Rc::new(function("script", 0, vec![], &ops))
+        };
+
+        let mut vm = Vm::new();
+        vm.run(script(false)).unwrap();
+        assert_eq!(vm.collect_garbage(), 1);
+        assert_eq!(vm.collect_garbage(), 0);
+
+        vm.run(script(true)).unwrap();
+        assert_eq!(vm.collect_garbage(), 0);
+        let f = vm.global("f").unwrap();
+        assert_eq!(vm.call(f.clone(), vec![]).unwrap(), f);
+
+        // Making the second closure collects the first one's cycle
+        vm.set_gc_threshold(Some(1));
+        vm.run(script(false)).unwrap();
+        vm.run(script(false)).unwrap();
+        assert_eq!(vm.collect_garbage(), 1);
+
+        // Captured variables outlive the minor collection made while they
+        // are on the stack, so their cycles wait for a major one
+        vm.set_gc_nursery(true);
+        for _ in 0..3 {
+            vm.run(script(false)).unwrap();
+        }
+        assert_eq!(vm.collect_garbage(), 3);
+        assert_eq!(vm.call(f.clone(), vec![]).unwrap(), f);
+    }
+
+    #[test]
+    fn test_errors_map_offsets_to_spans() {
+        let mut divide = function(
+            "divide",
+            2,
+            vec![],
+            &[
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::GetLocal, None, &[1]),
+                (OpCode::Divide, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let end = divide.chunk.len();
+        divide.chunk.add_local(Local { name: Rc::from("a"), slot: 0, start: 0, end });
+        divide.chunk.add_local(Local { name: Rc::from("b"), slot: 1, start: 0, end });
+        let mut vm = Vm::new();
+        vm.define_global("divide", closure(divide));
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("divide"), &[]),
+                (OpCode::Constant, int(1), &[]),
+                (OpCode::Constant, int(0), &[]),
+                (OpCode::Call, None, &[2]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::DivisionByZero));
+        let trace: Vec<_> = err.trace.iter().map(|frame| (frame.function.as_str(), frame.offset)).collect();
+        assert_eq!(trace, [("divide", 4), ("script", 9)]);
+        assert_eq!(err.span().map(|span| span.start_line), Some(3));
+        assert_eq!(err.trace[1].span.map(|span| span.start_line), Some(4));
+        assert_eq!(err.to_string(), "division by zero in `divide` at offset 4 (line 3)");
+        assert_eq!(err.trace[0].locals, [("a".to_string(), Value::Int(1)), ("b".to_string(), Value::Int(0))]);
+        assert!(err.trace[1].locals.is_empty());
+        let expected = "division by zero\n  in `divide` at offset 4 (line 3)\n      a = 1\n      b = 0\n  \
+                        in `script` at offset 9 (line 4)";
+        assert_eq!(err.backtrace(), expected);
+
+        // The VM is left ready for the next run
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+        let divide = vm.global("divide").unwrap();
+        assert_eq!(vm.call(divide.clone(), vec![Value::Int(6), Value::Int(3)]).unwrap(), Value::Int(2));
+        let err = vm.call(divide, vec![Value::Int(6)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::ArityMismatch { expected: 2, found: 1, .. }));
+
+        let script = function("script", 0, vec![], &[(OpCode::Pop, None, &[])]);
+        assert!(matches!(vm.run(Rc::new(script)).unwrap_err().kind, VmErrorKind::StackUnderflow));
+        let script = function("script", 0, vec![], &[(OpCode::Nil, None, &[])]);
+        assert!(matches!(vm.run(Rc::new(script)).unwrap_err().kind, VmErrorKind::Truncated));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_intrinsics_compute_inline() {
+        let length = Intrinsic::Length.id();
+        let max = Intrinsic::Max.id();
+        let abs = Intrinsic::Abs.id();
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, name("héllo"), &[]),
+                (OpCode::Intrinsic, None, &[length]),
+                (OpCode::Constant, int(-9), &[]),
+                (OpCode::Intrinsic, None, &[abs]),
+                (OpCode::Intrinsic, None, &[max]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(9));
+
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, Some(Constant::Float(2.0)), &[]),
+                (OpCode::Intrinsic, None, &[Intrinsic::Sqrt.id()]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Float(2f64.sqrt()));
+
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, int(1), &[]),
+                (OpCode::Constant, name("one"), &[]),
+                (OpCode::Intrinsic, None, &[max]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::TypeMismatch { expected: "an integer", found: "a string" }));
+        let script = function("script", 0, vec![], &[(OpCode::Intrinsic, None, &[abs])]);
+        assert!(matches!(vm.run(Rc::new(script)).unwrap_err().kind, VmErrorKind::StackUnderflow));
+        let script = function("script", 0, vec![], &[(OpCode::Intrinsic, None, &[200])]);
+        assert!(matches!(vm.run(Rc::new(script)).unwrap_err().kind, VmErrorKind::BadIntrinsic(200)));
+    }
+
+    unsafe extern "C-unwind" fn native_answer(
+        _receiver: ObjectPtr,
+        _cmd: oxidec::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(42) };
+    }
+
+    #[test]
+    fn test_messages_dispatch_through_the_runtime() {
+        let class = oxidec::Class::new_root("VmAnswerer").unwrap();
+        class
+            .add_method(oxidec::Method {
+                selector: Selector::from_str("answer").unwrap(),
+                imp: native_answer,
+                types: oxidec::RuntimeString::new("q@:", oxidec::get_global_arena()),
+            })
+            .unwrap();
+        let mut vm = Vm::new();
+        let answerer = vm.instance(Object::new(&class).unwrap());
+        vm.define_global("answerer", answerer.clone());
+
+        // answerer.count = answerer.answer(); answerer.count
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("answerer"), &[]),
+                (OpCode::Dup, None, &[]),
+                (OpCode::Send, name("answer"), &[0]),
+                (OpCode::SetField, name("count"), &[]),
+                (OpCode::Pop, None, &[]),
+                (OpCode::GetGlobal, name("answerer"), &[]),
+                (OpCode::GetField, name("count"), &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        assert_eq!(vm.run(Rc::new(script)).unwrap(), Value::Int(42));
+        let Value::Object(instance) = &answerer else { unreachable!() };
+        assert_eq!(instance.field("count"), Some(Value::Int(42)));
+
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[(OpCode::GetGlobal, name("answerer"), &[]), (OpCode::Send, name("question"), &[0])],
+        );
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::DoesNotRespond { .. }));
+        assert_eq!(err.offset(), Some(3));
+    }
+
+    #[test]
+    fn test_sandbox_denies_capabilities() {
+        use oxidec::runtime::sandbox;
+
+        let class = oxidec::Class::new_root("VmSandboxed").unwrap();
+        class
+            .add_method(oxidec::Method {
+                selector: Selector::from_str("answer").unwrap(),
+                imp: native_answer,
+                types: oxidec::RuntimeString::new("q@:", oxidec::get_global_arena()),
+            })
+            .unwrap();
+        let mut vm = Vm::new();
+        vm.set_sandbox(Sandbox { deny_file_io: true, deny_ffi: true, ..Sandbox::default() });
+        vm.define_native_requiring("secret", Capability::FileIo, 0, |_, _| Ok(Value::string("hunter2")));
+        // Host functions run inside the sandbox
+        vm.define_native("entered", 0, |_, _| Ok(Value::Bool(!sandbox::current().allows(Capability::Ffi))));
+        let answerer = vm.instance(Object::new(&class).unwrap());
+        vm.define_global("answerer", answerer);
+
+        // try { secret() } catch e { e }: the error is not caught
+        let mut script = function(
+            "script",
+            0,
+            vec![],
+            &[(OpCode::GetGlobal, name("secret"), &[]), (OpCode::Call, None, &[0]), (OpCode::Return, None, &[])],
+        );
+        let handler = Handler { kind: HandlerKind::Catch, start: 0, end: 5, target: 5, depth: 0 };
+        script.chunk.add_handler(handler);
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Sandboxed(Capability::FileIo)), "{err}");
+        assert_eq!(err.offset(), Some(3));
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+
+        let script = function(
+            "script",
+            0,
+            vec![],
+            &[(OpCode::GetGlobal, name("answerer"), &[]), (OpCode::Send, name("answer"), &[0])],
+        );
+        let err = vm.run(Rc::new(script)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Sandboxed(Capability::Ffi)), "{err}");
+
+        let entered = vm.global("entered").unwrap();
+        assert_eq!(vm.call(entered.clone(), Vec::new()).unwrap(), Value::Bool(true));
+        assert!(sandbox::current().allows(Capability::Ffi));
+        vm.set_sandbox(Sandbox::default());
+        assert_eq!(vm.call(entered, Vec::new()).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_inline_caches_follow_receiver_classes() {
+        use crate::cache::{InlineCache, POLYMORPHIC_LIMIT};
+
+        let base = oxidec::Class::new_root("VmCached").unwrap();
+        base.add_method(oxidec::Method {
+            selector: Selector::from_str("answer").unwrap(),
+            imp: native_answer,
+            types: oxidec::RuntimeString::new("q@:", oxidec::get_global_arena()),
+        })
+        .unwrap();
+        let mut vm = Vm::new();
+        let instances: Vec<_> = (0..=POLYMORPHIC_LIMIT)
+            .map(|index| {
+                let class = oxidec::Class::new(&format!("VmCached{index}"), &base).unwrap();
+                vm.instance(Object::new(&class).unwrap())
+            })
+            .collect();
+
+        // fn ask(receiver) { receiver.answer() }
+        let ask = closure(function(
+            "ask",
+            1,
+            vec![],
+            &[(OpCode::GetLocal, None, &[0]), (OpCode::Send, name("answer"), &[0]), (OpCode::Return, None, &[])],
+        ));
+        let site = |vm: &Vm| vm.caches.values().next().unwrap().sends[&2].clone();
+        for _ in 0..2 {
+            assert_eq!(vm.call(ask.clone(), vec![instances[0].clone()]).unwrap(), Value::Int(42));
+        }
+        assert!(matches!(site(&vm), InlineCache::Monomorphic(..)));
+        for instance in &instances[1..POLYMORPHIC_LIMIT] {
+            assert_eq!(vm.call(ask.clone(), vec![instance.clone()]).unwrap(), Value::Int(42));
+        }
+        assert!(matches!(site(&vm), InlineCache::Polymorphic(entries) if entries.len() == POLYMORPHIC_LIMIT));
+        assert_eq!(vm.call(ask.clone(), vec![instances[POLYMORPHIC_LIMIT].clone()]).unwrap(), Value::Int(42));
+        assert!(matches!(site(&vm), InlineCache::Megamorphic));
+        assert_eq!(vm.call(ask.clone(), vec![instances[0].clone()]).unwrap(), Value::Int(42));
+
+        // fn y(receiver) { receiver.y }, for instances whose fields were set
+        // in different orders
+        let Value::Object(first) = &instances[0] else { unreachable!() };
+        let Value::Object(second) = &instances[1] else { unreachable!() };
+        first.set_field("x", Value::Int(1));
+        first.set_field("y", Value::Int(2));
+        second.set_field("y", Value::Int(3));
+        let y = closure(function(
+            "y",
+            1,
+            vec![],
+            &[(OpCode::GetLocal, None, &[0]), (OpCode::GetField, name("y"), &[]), (OpCode::Return, None, &[])],
+        ));
+        let other = oxidec::Class::new("VmCachedOther", &base).unwrap();
+        let third = vm.instance(Object::new(&other).unwrap());
+        let Value::Object(instance) = &third else { unreachable!() };
+        instance.set_field("x", Value::Int(4));
+        instance.set_field("y", Value::Int(5));
+        for (instance, expected) in [(&instances[0], 2), (&instances[1], 3), (&third, 5), (&instances[0], 2)] {
+            assert_eq!(vm.call(y.clone(), vec![instance.clone()]).unwrap(), Value::Int(expected));
+        }
+        second.set_field("x", Value::Int(6));
+        first.fields.borrow_mut().remove(0);
+        assert_eq!(vm.call(y.clone(), vec![instances[0].clone()]).unwrap(), Value::Int(2));
+        assert!(matches!(vm.call(y, vec![Value::Nil]).unwrap_err().kind, VmErrorKind::TypeMismatch { .. }));
+
+        // Caches go with their functions
+        drop(ask);
+        vm.run(Rc::new(function("script", 0, vec![], &[(OpCode::Nil, None, &[]), (OpCode::Return, None, &[])])))
+            .unwrap();
+        assert!(vm.caches.values().all(|caches| caches.sends.is_empty()));
+    }
+}
@@ -0,0 +1,199 @@
+//! Helpers for machine code.
+//!
+//! Compiled code runs a function by calling, for each instruction, the
+//! helper of its opcode with the VM, the frame floor and the instruction's
+//! offset. A helper is the VM's instruction code specialised to one opcode,
+//! as with threaded dispatch, and tells the code what to do next: go on to
+//! the next instruction, take the instruction's jump, or return to the VM.
+//! Helpers leave to the VM whatever changes the running frame, such as
+//! calls, returns and errors.
+//!
+//! A `SEND` can instead be compiled to a call through a [`SendSite`], an
+//! inline cache of the code's own, which skips finding the running
+//! function's caches and looks only at the site's. The code loads the
+//! helper to call from the site, so the site relinks itself as it learns:
+//! its helper guards on the classes the site has seen, taking the method
+//! found for a receiver's class without resolving the message again, until
+//! the site sees too many classes and links a helper that always resolves
+//! it.
+
+use super::{Raise, Step, Vm};
+use crate::cache::{InlineCache, SendTarget};
+use crate::opcodes::OpCode;
+use crate::value::Value;
+use std::cell::{Cell, RefCell};
+
+/// Executes the instruction at an offset of the running frame, given the
+/// VM, the frame floor and the offset, returning a [`NativeStatus`].
+///
+/// # Safety
+///
+/// The VM pointer must be the VM running the compiled code, and the offset
+/// that of an instruction with the helper's opcode.
+pub type NativeHelper = unsafe extern "C" fn(*mut Vm, usize, usize) -> u32;
+
+/// Executes the `SEND` at an offset of the running frame through a send
+/// site, given the VM, the frame floor, the offset and the site, returning a
+/// [`NativeStatus`].
+///
+/// # Safety
+///
+/// As for a [`NativeHelper`], and the site must be alive.
+pub type SendHelper = unsafe extern "C" fn(*mut Vm, usize, usize, *const SendSite) -> u32;
+
+/// What compiled code does after a helper returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum NativeStatus {
+    /// Go on to the next instruction
+    Next = 0,
+    /// Take the instruction's jump
+    Branch = 1,
+    /// Return to the VM
+    Exit = 2,
+}
+
+macro_rules! helpers {
+    ($($op:ident),* $(,)?) => {{
+        const _: () = assert!([$(OpCode::$op),*].len() == OpCode::ALL.len(), "every opcode needs a helper");
+        let mut table: [NativeHelper; OpCode::ALL.len()] = [helper::<0>; OpCode::ALL.len()];
+        $(table[OpCode::$op as usize] = helper::<{ OpCode::$op as u8 }>;)*
+        table
+    }};
+}
+
+/// Helpers by opcode.
+static HELPERS: [NativeHelper; OpCode::ALL.len()] = helpers![
+    Constant, Nil, True, False, Pop, Dup, GetLocal, SetLocal, GetGlobal, DefineGlobal, SetGlobal, GetUpvalue,
+    SetUpvalue, Add, Subtract, Multiply, Divide, Remainder, Negate, Not, Equal, NotEqual, Less, LessEqual, Greater,
+    GreaterEqual, Send, GetField, SetField, Jump, JumpIfFalse, Loop, Closure, CloseUpvalue, Call, Return, Throw,
+    Rethrow, Intrinsic,
+];
+
+/// The helper compiled code calls to execute an instruction with opcode
+/// `op`.
+#[must_use]
+pub fn native_helper(op: OpCode) -> NativeHelper {
+    HELPERS[op as usize]
+}
+
+unsafe extern "C" fn helper<const OP: u8>(vm: *mut Vm, floor: usize, offset: usize) -> u32 {
+    // SAFETY: compiled code passes the VM that entered it, which is not
+    // otherwise borrowed while the code runs
+    let vm = unsafe { &mut *vm };
+    let op = OpCode::ALL[OP as usize];
+    let depth = vm.frames.len();
+    let frame = vm.frame_mut();
+    frame.current = offset;
+    frame.ip = offset + 1;
+    let status = match vm.execute_op(op, floor) {
+        Ok(None) if vm.frames.len() != depth || vm.tasks.is_suspending() => NativeStatus::Exit,
+        Ok(None) if vm.frame().ip == offset + 1 + op.operand_width() => NativeStatus::Next,
+        Ok(None) => NativeStatus::Branch,
+        Ok(Some(result)) => vm.exit(Ok(Some(result))),
+        Err(raise) => vm.exit(Err(raise)),
+    };
+    status as u32
+}
+
+/// The inline cache of a `SEND` in compiled code.
+///
+/// Code calls the helper at the start of the site, which is the one for
+/// what the site has learned, with the site's address.
+#[derive(Debug)]
+#[repr(C)]
+pub struct SendSite {
+    /// Helper the code calls
+    helper: Cell<SendHelper>,
+    /// Methods found for the classes of the receivers seen
+    cache: RefCell<InlineCache<SendTarget>>,
+}
+
+impl Default for SendSite {
+    fn default() -> Self {
+        Self { helper: Cell::new(send_cached), cache: RefCell::default() }
+    }
+}
+
+impl SendSite {
+    /// Create a site that has seen no receiver.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What the site has learned so far.
+    #[must_use]
+    pub fn cache(&self) -> InlineCache<SendTarget> {
+        self.cache.borrow().clone()
+    }
+}
+
+unsafe extern "C" fn send_cached(vm: *mut Vm, _floor: usize, offset: usize, site: *const SendSite) -> u32 {
+    // SAFETY: as for the helpers, and compiled code passes a site it keeps
+    // alive
+    let (vm, site) = unsafe { (&mut *vm, &*site) };
+    let status = vm.send_at(offset, &mut site.cache.borrow_mut());
+    if matches!(*site.cache.borrow(), InlineCache::Megamorphic) {
+        site.helper.set(send_megamorphic);
+    }
+    status as u32
+}
+
+unsafe extern "C" fn send_megamorphic(vm: *mut Vm, _floor: usize, offset: usize, _site: *const SendSite) -> u32 {
+    // SAFETY: as for the helpers
+    let vm = unsafe { &mut *vm };
+    vm.send_at(offset, &mut InlineCache::Megamorphic) as u32
+}
+
+impl Vm {
+    /// The arguments of the running frame, which code that computes a whole
+    /// call reads instead of running the frame's instructions.
+    #[must_use]
+    pub fn native_arguments(&self) -> &[Value] {
+        let frame = self.frame();
+        &self.stack[frame.base..frame.base + usize::from(frame.closure.function.arity)]
+    }
+
+    /// Number of frames running, which calls made by compiled code count
+    /// against [`MAX_FRAMES`](super::MAX_FRAMES).
+    #[must_use]
+    pub fn native_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Return `result` from the running frame as its `RETURN` would, for
+    /// code that computed the call without running its instructions.
+    pub fn native_return(&mut self, result: Value, floor: usize) {
+        self.close_upvalues(self.frame().base);
+        if let Some(result) = self.return_value(result, floor) {
+            self.exit(Ok(Some(result)));
+        }
+    }
+
+    /// Execute the `SEND` at `offset` through `cache`.
+    fn send_at(&mut self, offset: usize, cache: &mut InlineCache<SendTarget>) -> NativeStatus {
+        let frame = self.frame_mut();
+        frame.current = offset;
+        frame.ip = offset + 1;
+        let depth = self.frames.len();
+        match self.send_through(cache) {
+            // A method the program defines runs in a frame of its own
+            Ok(()) if self.frames.len() != depth => NativeStatus::Exit,
+            Ok(()) => NativeStatus::Next,
+            Err(kind) => self.exit(Err(Raise::from(kind))),
+        }
+    }
+
+    fn send_through(&mut self, cache: &mut InlineCache<SendTarget>) -> Step<()> {
+        let name = self.read_name()?;
+        let argc = usize::from(self.read_u8()?);
+        self.send_message(&name, argc, Some(cache))
+    }
+
+    /// Leave compiled code with the outcome of its last instruction.
+    fn exit(&mut self, outcome: Result<Option<Value>, Raise>) -> NativeStatus {
+        self.native_outcome = Some(outcome);
+        NativeStatus::Exit
+    }
+}
@@ -0,0 +1,296 @@
+//! Objects the VM makes itself.
+//!
+//! Bytecode allocates an instance by sending [`ALLOC`] to the name of its
+//! class, which the runtime creates as a root class the first time the VM
+//! allocates one it does not have. Enum values are instances of their enum
+//! with a [`TAG`] field, the position of their variant in the declaration,
+//! and a [`PAYLOAD`] field if the variant carries one.
+//!
+//! Tuples, arrays and dictionaries are instances of [`TUPLE`], [`ARRAY`]
+//! and [`DICT`] whose fields are their elements in order, named by
+//! position; a dictionary's elements are `(key, value)` tuples, in
+//! insertion order. They answer:
+//!
+//! - [`AT`] with the element at an index, or the value of a key
+//! - [`AT_PUT`] by replacing the element at an index, or the value of a
+//!   key, returning `nil`
+//! - [`COUNT`] with their number of elements
+//! - [`SUFFIX_FROM`] with an array of their elements from an index on
+//!
+//! Every value answers [`DESCRIPTION`] as the interpreter describes it,
+//! unless its class defines a `description()` of its own (see
+//! [`Vm::describe`]); an enum value also has a [`VARIANT`] field naming its
+//! variant, so that it can be described.
+//!
+//! Arrays, dictionaries and strings conform to the iteration protocol as
+//! they do in the interpreter: [`MAKE_ITERATOR`] returns an iterator over
+//! their elements as they are when it is made, dictionaries yielding their
+//! entries and strings one-character strings, and the iterator answers
+//! [`NEXT`] with each in turn, then `nil`.
+
+use super::{Step, Vm};
+use crate::error::VmErrorKind;
+use crate::value::{Instance, Value};
+use oxidec::runtime::class_from_name;
+use oxidec::{Class, Object};
+use std::rc::Rc;
+
+/// Selector allocating an instance of the class a string names.
+pub(crate) const ALLOC: &str = "alloc";
+/// Field holding the position of an enum value's variant.
+pub(crate) const TAG: &str = "tag";
+/// Field holding an enum value's payload.
+pub(crate) const PAYLOAD: &str = "payload";
+/// Field holding the name of an enum value's variant.
+pub(crate) const VARIANT: &str = "variant";
+
+/// Class of tuples.
+pub(crate) const TUPLE: &str = "Tuple";
+/// Class of arrays.
+pub(crate) const ARRAY: &str = "Array";
+/// Class of dictionaries.
+pub(crate) const DICT: &str = "Dict";
+/// Class of iterators over built-in sequences.
+const ITERATOR: &str = "IndexingIterator";
+
+/// Selector reading an element of a collection.
+pub(crate) const AT: &str = "at:";
+/// Selector replacing an element of a collection.
+pub(crate) const AT_PUT: &str = "at:put:";
+/// Selector counting the elements of a collection.
+pub(crate) const COUNT: &str = "count";
+/// Selector copying the elements of an array from an index on.
+pub(crate) const SUFFIX_FROM: &str = "suffixFrom:";
+/// Selector making an iterator over a sequence.
+pub(crate) const MAKE_ITERATOR: &str = "makeIterator";
+/// Selector advancing an iterator, which answers `nil` when it is done.
+pub(crate) const NEXT: &str = "next";
+/// Selector describing a value, for `print` and string interpolation.
+pub(crate) const DESCRIPTION: &str = "description";
+/// Selector describing a value for debugging, inside a description.
+const DEBUG_DESCRIPTION: &str = "debugDescription";
+
+/// Fields of an iterator: the array of what it yields, and the position of
+/// the next element.
+const ITEMS: &str = "items";
+const POSITION: &str = "position";
+
+impl Vm {
+    /// Allocate an instance of the class named `name`, creating a root
+    /// class of that name if the runtime has none.
+    fn allocate(&mut self, name: &str) -> Step<Value> {
+        let class = match class_from_name(name) {
+            Some(class) => class,
+            None => match Class::new_root(name) {
+                Ok(class) => class,
+                // Another thread created it in the meantime
+                Err(oxidec::Error::ClassAlreadyExists) => {
+                    class_from_name(name).ok_or(VmErrorKind::Runtime(oxidec::Error::ClassAlreadyExists))?
+                }
+                Err(err) => return Err(VmErrorKind::Runtime(err)),
+            },
+        };
+        let object = Object::new(&class).map_err(VmErrorKind::Runtime)?;
+        Ok(self.instance(object))
+    }
+
+    /// Answer a message the VM implements itself, or return `None` if it
+    /// is not one.
+    pub(super) fn answer(&mut self, receiver: &Value, selector: &str, args: &[Value]) -> Option<Step<Value>> {
+        let instance = match (receiver, selector) {
+            (Value::String(class), ALLOC) if args.is_empty() => return Some(self.allocate(class)),
+            (Value::String(text), MAKE_ITERATOR) if args.is_empty() => {
+                let chars = text.chars().map(|ch| Value::string(ch.encode_utf8(&mut [0; 4]))).collect();
+                return Some(self.iterator(chars));
+            }
+            (_, DESCRIPTION) if args.is_empty() => return Some(self.describe(receiver).map(|text| Value::string(&text))),
+            (Value::Object(instance), _) => instance,
+            _ => return None,
+        };
+        let class = instance.object.class();
+        Some(match (class.name(), selector, args) {
+            (TUPLE | ARRAY, AT, [index]) => element(instance, index),
+            (DICT, AT, [key]) => match entry(instance, key) {
+                Some((_, entry)) => field(&entry, 1),
+                None => Err(VmErrorKind::MissingKey(key.to_string())),
+            },
+            (ARRAY, AT_PUT, [index, value]) => self.replace(instance, index, value.clone()),
+            (DICT, AT_PUT, [key, value]) => self.insert(instance, key, value.clone()),
+            (TUPLE | ARRAY | DICT, COUNT, []) => count(instance),
+            (ARRAY, SUFFIX_FROM, [Value::Int(start)]) => {
+                let elements = elements(instance);
+                let start = usize::try_from(*start).unwrap_or(usize::MAX).min(elements.len());
+                self.collection(ARRAY, elements[start..].to_vec())
+            }
+            (ARRAY | DICT, MAKE_ITERATOR, []) => self.iterator(elements(instance)),
+            (ITERATOR, NEXT, []) => self.advance(instance),
+            _ => return None,
+        })
+    }
+
+    /// Describe a value as `print` and string interpolation show it: by the
+    /// `description()` its class defines, if it has one, and otherwise as
+    /// the interpreter does.
+    pub(crate) fn describe(&mut self, value: &Value) -> Step<String> {
+        if let Some(description) = self.described_by(value, &[DESCRIPTION])? {
+            return Ok(description);
+        }
+        match value {
+            Value::String(text) => Ok(text.to_string()),
+            value => self.default_description(value),
+        }
+    }
+
+    /// Describe a value for debugging, preferring its `debugDescription()`
+    /// to its `description()`.
+    fn debug_describe(&mut self, value: &Value) -> Step<String> {
+        match self.described_by(value, &[DEBUG_DESCRIPTION, DESCRIPTION])? {
+            Some(description) => Ok(description),
+            None => self.default_description(value),
+        }
+    }
+
+    /// Call the first of `methods` the class of an instance defines, if it
+    /// defines any, for its description.
+    fn described_by(&mut self, value: &Value, methods: &[&str]) -> Step<Option<String>> {
+        let Value::Object(instance) = value else {
+            return Ok(None);
+        };
+        let Some(method) = methods.iter().find_map(|name| self.method(instance, name)) else {
+            return Ok(None);
+        };
+        let description = self.call(method, vec![value.clone()]).map_err(|err| err.kind)?;
+        Ok(Some(description.to_string()))
+    }
+
+    /// Describe a value whose class has no description of its own: an
+    /// instance by its class and its fields, arrays and tuples by their
+    /// elements, each described for debugging, and anything else as it
+    /// displays.
+    fn default_description(&mut self, value: &Value) -> Step<String> {
+        let Value::Object(instance) = value else {
+            return Ok(format!("{value:#}"));
+        };
+        let class = instance.object.class();
+        let fields = instance.fields.borrow().clone();
+        let mut described = Vec::with_capacity(fields.len());
+        match class.name() {
+            TUPLE | ARRAY => {
+                for (_, element) in &fields {
+                    described.push(self.debug_describe(element)?);
+                }
+                let elements = described.join(", ");
+                Ok(if class.name() == TUPLE { format!("({elements})") } else { format!("[{elements}]") })
+            }
+            DICT => Ok(format!("{value:#}")),
+            _ if instance.field(VARIANT).is_some() => Ok(format!("{value:#}")),
+            name => {
+                for (field, value) in &fields {
+                    described.push(format!("{field}: {}", self.debug_describe(value)?));
+                }
+                Ok(format!("{name}({})", described.join(", ")))
+            }
+        }
+    }
+
+    /// Make a collection of `class` holding `elements`.
+    fn collection(&mut self, class: &str, elements: Vec<Value>) -> Step<Value> {
+        let collection = self.allocate(class)?;
+        if let Value::Object(instance) = &collection {
+            let fields = elements.into_iter().enumerate().map(|(index, value)| (Rc::from(index.to_string()), value));
+            instance.fields.borrow_mut().extend(fields);
+        }
+        Ok(collection)
+    }
+
+    /// Make an iterator yielding `items`.
+    fn iterator(&mut self, items: Vec<Value>) -> Step<Value> {
+        let items = self.collection(ARRAY, items)?;
+        let iterator = self.allocate(ITERATOR)?;
+        if let Value::Object(instance) = &iterator {
+            instance.set_field(ITEMS, items);
+            instance.set_field(POSITION, Value::Int(0));
+        }
+        Ok(iterator)
+    }
+
+    /// Yield the next item of an iterator, or `nil` once there are none.
+    fn advance(&mut self, iterator: &Rc<Instance>) -> Step<Value> {
+        let (Some(Value::Object(items)), Some(Value::Int(position))) = (iterator.field(ITEMS), iterator.field(POSITION))
+        else {
+            return Err(VmErrorKind::NoSuchField(ITEMS.to_string()));
+        };
+        let Some(item) = usize::try_from(position).ok().and_then(|index| items.fields.borrow().get(index).cloned())
+        else {
+            return Ok(Value::Nil);
+        };
+        iterator.set_field(POSITION, Value::Int(position + 1));
+        Ok(item.1)
+    }
+
+    /// Replace the element at `index` of an array.
+    fn replace(&mut self, array: &Rc<Instance>, index: &Value, value: Value) -> Step<Value> {
+        let index = position(array, index)?;
+        array.fields.borrow_mut()[index].1 = value;
+        self.collector.write_instance(array);
+        Ok(Value::Nil)
+    }
+
+    /// Set the value of `key` in a dictionary, adding an entry at the end
+    /// if it has none.
+    fn insert(&mut self, dict: &Rc<Instance>, key: &Value, value: Value) -> Step<Value> {
+        let pair = self.collection(TUPLE, vec![key.clone(), value])?;
+        match entry(dict, key) {
+            Some((index, _)) => dict.fields.borrow_mut()[index].1 = pair,
+            None => {
+                let index = dict.fields.borrow().len();
+                dict.fields.borrow_mut().push((Rc::from(index.to_string()), pair));
+            }
+        }
+        self.collector.write_instance(dict);
+        Ok(Value::Nil)
+    }
+}
+
+/// The elements of a collection, in order.
+fn elements(collection: &Instance) -> Vec<Value> {
+    collection.fields.borrow().iter().map(|(_, value)| value.clone()).collect()
+}
+
+/// The number of elements of a collection.
+fn count(collection: &Instance) -> Step<Value> {
+    let count = collection.fields.borrow().len();
+    Ok(Value::Int(i64::try_from(count).map_err(|_| VmErrorKind::IntegerOverflow)?))
+}
+
+/// Check an index against the elements of a tuple or an array.
+fn position(collection: &Instance, index: &Value) -> Step<usize> {
+    let len = collection.fields.borrow().len();
+    match *index {
+        Value::Int(index) => {
+            usize::try_from(index).ok().filter(|&i| i < len).ok_or(VmErrorKind::IndexOutOfBounds { index, len })
+        }
+        ref other => Err(VmErrorKind::TypeMismatch { expected: "an integer", found: other.kind() }),
+    }
+}
+
+/// The element at `index` of a tuple or an array.
+fn element(collection: &Instance, index: &Value) -> Step<Value> {
+    let index = position(collection, index)?;
+    Ok(collection.fields.borrow()[index].1.clone())
+}
+
+/// The field at `index` of an instance.
+fn field(instance: &Instance, index: usize) -> Step<Value> {
+    let fields = instance.fields.borrow();
+    fields.get(index).map(|(_, value)| value.clone()).ok_or_else(|| VmErrorKind::NoSuchField(index.to_string()))
+}
+
+/// The position and `(key, value)` tuple of the entry for `key` in a
+/// dictionary.
+fn entry(dict: &Instance, key: &Value) -> Option<(usize, Rc<Instance>)> {
+    dict.fields.borrow().iter().enumerate().find_map(|(index, (_, pair))| match pair {
+        Value::Object(pair) if field(pair, 0).is_ok_and(|candidate| candidate == *key) => Some((index, Rc::clone(pair))),
+        _ => None,
+    })
+}
@@ -0,0 +1,702 @@
+//! Cooperative tasks.
+//!
+//! A task is a call the VM runs alongside others, with a stack and frames
+//! of its own. Tasks are green threads: they take turns on the thread
+//! running the VM, and a task runs until it returns, raises an error it
+//! does not catch, or suspends. It suspends by calling a host function that
+//! waits: for its next turn ([`Vm::yield_now`]), a time
+//! ([`Vm::sleep_until`]), a value on a channel ([`Vm::channel_receive`]) or
+//! another task ([`Vm::join`]). Its frames stay as they were, so it resumes
+//! after the call once what it waited for comes.
+//!
+//! [`Vm::spawn`] adds a task, and [`Vm::run_task`] and [`Vm::run_tasks`]
+//! run the tasks that are ready in turn, in the order they became ready.
+//! When none is ready but some sleep, the thread sleeps until the first
+//! wakes; when the rest wait for something no task can send, they are
+//! deadlocked.
+//!
+//! Switching tasks swaps the VM's stack, frames and open captured variables
+//! for the task's. While a task is parked, the variables closures captured
+//! from its stack are closed over the values of their slots, and they are
+//! reopened when it resumes, so a closure shared between tasks sees the
+//! same variable in each.
+//!
+//! Channels are unbounded queues: sending never waits, and receiving waits
+//! until there is a value. Values are received in the order they were sent,
+//! by the tasks waiting in the order they began to.
+//!
+//! Only a host function the task's own code calls can suspend it: the Rust
+//! frames of a host function that called back into the VM through
+//! [`Vm::call`] cannot be suspended, so functions that call reaches cannot
+//! suspend the task.
+
+use super::{CallFrame, Vm};
+use crate::chunk::{Chunk, Function};
+use crate::error::{VmError, VmErrorKind};
+use crate::value::{Closure, Upvalue, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Identifies a task of a VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(usize);
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}", self.0)
+    }
+}
+
+/// Identifies a channel of a VM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChannelId(usize);
+
+impl fmt::Display for ChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "channel {}", self.0)
+    }
+}
+
+/// What a suspended task waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wait {
+    /// Its next turn, after the tasks already ready
+    Turn,
+    /// A time
+    Until(Instant),
+    /// A value on a channel
+    Receive(ChannelId),
+    /// A task to finish
+    Join(TaskId),
+}
+
+/// A call that is not running.
+#[derive(Debug, Default)]
+struct Parked {
+    /// Its stack
+    stack: Vec<Value>,
+    /// Its frames
+    frames: Vec<CallFrame>,
+    /// Variables captured from its stack, closed while it is parked, with
+    /// their slots
+    upvalues: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+}
+
+impl Parked {
+    /// Add the values the call keeps alive to `roots`.
+    fn roots(&self, roots: &mut Vec<Value>) {
+        roots.extend(self.stack.iter().cloned());
+        roots.extend(self.frames.iter().map(|frame| Value::Closure(Rc::clone(&frame.closure))));
+        // The collector empties closed variables it does not reach, so the
+        // variables are reached through a closure over them
+        if !self.upvalues.is_empty() {
+            let name = "<parked>".to_string();
+            let function = Function { name, arity: 0, captures: Vec::new(), chunk: Chunk::new() };
+            let upvalues = self.upvalues.iter().map(|(_, upvalue)| Rc::clone(upvalue)).collect();
+            roots.push(Value::Closure(Rc::new(Closure { function: Rc::new(function), upvalues })));
+        }
+    }
+}
+
+/// A task that is not running.
+#[derive(Debug)]
+struct Task {
+    /// Its state
+    parked: Parked,
+    /// What it waits for, or `None` if it is ready
+    wait: Option<Wait>,
+    /// Stack index of the result of the host function that suspended it,
+    /// or `None` if it has not started, in which case its stack holds its
+    /// callee and arguments
+    slot: Option<usize>,
+    /// What it waited for: the result of the host function, or an error
+    /// to raise when it resumes
+    delivery: Option<Result<Value, VmErrorKind>>,
+}
+
+/// A channel's values and the tasks waiting for them.
+#[derive(Debug, Default)]
+struct Channel {
+    /// Values sent and not received yet
+    values: VecDeque<Value>,
+    /// Tasks waiting to receive, in the order they began to
+    receivers: VecDeque<TaskId>,
+}
+
+/// The tasks and channels of a VM.
+#[derive(Debug, Default)]
+pub(super) struct Scheduler {
+    /// Tasks that have not finished
+    tasks: BTreeMap<TaskId, Task>,
+    /// Tasks ready to run, in the order they became ready
+    ready: VecDeque<TaskId>,
+    /// Results of the tasks that finished
+    finished: HashMap<TaskId, Result<Value, Rc<VmError>>>,
+    /// Channels, by id
+    channels: Vec<Channel>,
+    /// Number of tasks spawned
+    spawned: usize,
+    /// The running task
+    current: Option<TaskId>,
+    /// State of the host's call while tasks run
+    host: Option<Parked>,
+    /// Calls the host made into the VM that are running
+    pub(super) calls: usize,
+    /// Host calls that were running when the running task resumed
+    level: usize,
+    /// What the running task waits for, once a host function suspended it
+    suspending: Option<Wait>,
+    /// Stack index of the result of the host function that suspended the
+    /// running task
+    slot: Option<usize>,
+}
+
+impl Scheduler {
+    /// Whether a host function suspended the running task.
+    pub(super) fn is_suspending(&self) -> bool {
+        self.suspending.is_some()
+    }
+
+    /// Note that the host function whose result goes to stack index `slot`
+    /// returned, or failed if not `returned`, in which case it does not
+    /// suspend the task.
+    pub(super) fn suspended_at(&mut self, slot: usize, returned: bool) {
+        if self.suspending.is_some() {
+            if returned {
+                self.slot = Some(slot);
+            } else {
+                self.suspending = None;
+            }
+        }
+    }
+
+    /// The values parked calls, channels and finished tasks keep alive.
+    pub(super) fn roots(&self) -> Vec<Value> {
+        let mut roots = Vec::new();
+        for parked in self.tasks.values().map(|task| &task.parked).chain(&self.host) {
+            parked.roots(&mut roots);
+        }
+        for task in self.tasks.values() {
+            if let Some(Ok(value)) = &task.delivery {
+                roots.push(value.clone());
+            }
+        }
+        roots.extend(self.channels.iter().flat_map(|channel| channel.values.iter().cloned()));
+        roots.extend(self.finished.values().map(|result| match result {
+            Ok(value) => value.clone(),
+            Err(error) => Value::Error(Rc::clone(error)),
+        }));
+        roots
+    }
+
+    /// A channel of this VM.
+    fn channel(&mut self, channel: ChannelId) -> Result<&mut Channel, VmErrorKind> {
+        let unknown = VmErrorKind::TypeMismatch { expected: "a channel of this VM", found: "a channel" };
+        self.channels.get_mut(channel.0).ok_or(unknown)
+    }
+
+    /// Make a waiting task ready, with what it waited for.
+    fn wake(&mut self, id: TaskId, delivery: Option<Result<Value, VmErrorKind>>) {
+        if let Some(task) = self.tasks.get_mut(&id) {
+            task.wait = None;
+            task.delivery = delivery;
+            self.ready.push_back(id);
+        }
+    }
+
+    /// Make the tasks whose sleep is over ready, and take the first ready
+    /// task.
+    fn next_ready(&mut self) -> Option<TaskId> {
+        let now = Instant::now();
+        let woken: Vec<TaskId> = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| matches!(task.wait, Some(Wait::Until(time)) if time <= now))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in woken {
+            self.wake(id, None);
+        }
+        self.ready.pop_front()
+    }
+
+    /// The time the first sleeping task wakes, if any sleeps.
+    fn next_wake(&self) -> Option<Instant> {
+        self.tasks
+            .values()
+            .filter_map(|task| match task.wait {
+                Some(Wait::Until(time)) => Some(time),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Record the result of a task, waking the tasks joining it.
+    fn finish(&mut self, id: TaskId, result: Result<Value, VmError>) {
+        let result = result.map_err(Rc::new);
+        let joiners: Vec<TaskId> =
+            self.tasks.iter().filter(|(_, task)| task.wait == Some(Wait::Join(id))).map(|(&id, _)| id).collect();
+        for joiner in joiners {
+            let delivery = match &result {
+                Ok(value) => Ok(value.clone()),
+                Err(error) => Err(VmErrorKind::TaskFailed(Rc::clone(error))),
+            };
+            self.wake(joiner, Some(delivery));
+        }
+        self.finished.insert(id, result);
+    }
+}
+
+impl Vm {
+    /// Add a task calling `callee` with `args`, returning its id.
+    ///
+    /// The task runs when tasks are run (see [`Vm::run_tasks`]); one
+    /// spawned by a running task runs once that task suspends.
+    pub fn spawn(&mut self, callee: Value, args: Vec<Value>) -> TaskId {
+        let id = TaskId(self.tasks.spawned);
+        self.tasks.spawned += 1;
+        let mut stack = vec![callee];
+        stack.extend(args);
+        let parked = Parked { stack, ..Parked::default() };
+        self.tasks.tasks.insert(id, Task { parked, wait: None, slot: None, delivery: None });
+        self.tasks.ready.push_back(id);
+        id
+    }
+
+    /// Run tasks until `task` finishes, returning its result. The other
+    /// tasks run in turn with it, and those that have not finished when it
+    /// does stay for later runs.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the task raised, or a [`VmErrorKind::Deadlock`] if
+    /// it waits for something no task can send. Returns
+    /// [`VmErrorKind::CannotSuspend`] if called from a task.
+    pub fn run_task(&mut self, task: TaskId) -> Result<Value, VmError> {
+        if !self.tasks.tasks.contains_key(&task) && !self.tasks.finished.contains_key(&task) {
+            let kind = VmErrorKind::TypeMismatch { expected: "a task of this VM", found: "a task" };
+            return Err(VmError { kind, trace: Vec::new() });
+        }
+        self.schedule(Some(task))?;
+        match &self.tasks.finished[&task] {
+            Ok(value) => Ok(value.clone()),
+            Err(error) => Err(VmError::clone(error)),
+        }
+    }
+
+    /// Run tasks until every task has finished. Errors the tasks raise are
+    /// their results, for the tasks joining them.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`VmErrorKind::Deadlock`] if the tasks left wait for
+    /// something no task can send, or [`VmErrorKind::CannotSuspend`] if
+    /// called from a task.
+    pub fn run_tasks(&mut self) -> Result<(), VmError> {
+        self.schedule(None)
+    }
+
+    /// Make a channel, returning its id.
+    pub fn channel(&mut self) -> ChannelId {
+        self.tasks.channels.push(Channel::default());
+        ChannelId(self.tasks.channels.len() - 1)
+    }
+
+    /// Send a value on a channel, to the first task waiting for one, if
+    /// any. Sending never waits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel is not one of this VM's.
+    pub fn channel_send(&mut self, channel: ChannelId, value: Value) -> Result<(), VmErrorKind> {
+        let channel = self.tasks.channel(channel)?;
+        match channel.receivers.pop_front() {
+            Some(receiver) => self.tasks.wake(receiver, Some(Ok(value))),
+            None => channel.values.push_back(value),
+        }
+        Ok(())
+    }
+
+    /// Receive the next value on a channel, from a host function. If there
+    /// is none, the running task is suspended until one is sent, and the
+    /// function's result is replaced by the value when it resumes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel is not one of this VM's, or if there
+    /// is no value and the task cannot be suspended (see [`Vm::yield_now`]).
+    pub fn channel_receive(&mut self, channel: ChannelId) -> Result<Value, VmErrorKind> {
+        match self.tasks.channel(channel)?.values.pop_front() {
+            Some(value) => Ok(value),
+            None => self.suspend(Wait::Receive(channel)).map(|()| Value::Nil),
+        }
+    }
+
+    /// Get the result of a task, from a host function. If the task has not
+    /// finished, the running task is suspended until it does, and the
+    /// function's result is replaced by the task's when it resumes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VmErrorKind::TaskFailed`], raised when the running task
+    /// resumes if it was suspended, if the task raised an error. Returns an
+    /// error if the task is not one of this VM's, or if it has not finished
+    /// and the running task cannot be suspended (see [`Vm::yield_now`]).
+    pub fn join(&mut self, task: TaskId) -> Result<Value, VmErrorKind> {
+        match self.tasks.finished.get(&task) {
+            Some(Ok(value)) => Ok(value.clone()),
+            Some(Err(error)) => Err(VmErrorKind::TaskFailed(Rc::clone(error))),
+            None if self.tasks.tasks.contains_key(&task) => self.suspend(Wait::Join(task)).map(|()| Value::Nil),
+            None => Err(VmErrorKind::TypeMismatch { expected: "a task of this VM", found: "a task" }),
+        }
+    }
+
+    /// Suspend the running task until its next turn, from a host function,
+    /// letting the other ready tasks run first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`VmErrorKind::CannotSuspend`] if no task is running, or the
+    /// host function was not called by the task's own code.
+    pub fn yield_now(&mut self) -> Result<(), VmErrorKind> {
+        self.suspend(Wait::Turn)
+    }
+
+    /// Suspend the running task until `time`, from a host function.
+    ///
+    /// # Errors
+    ///
+    /// As for [`Vm::yield_now`].
+    pub fn sleep_until(&mut self, time: Instant) -> Result<(), VmErrorKind> {
+        self.suspend(Wait::Until(time))
+    }
+
+    /// Suspend the running task once the host function calling this
+    /// returns.
+    fn suspend(&mut self, wait: Wait) -> Result<(), VmErrorKind> {
+        if self.tasks.current.is_none() || self.tasks.calls != self.tasks.level {
+            return Err(VmErrorKind::CannotSuspend);
+        }
+        self.tasks.suspending = Some(wait);
+        Ok(())
+    }
+
+    /// Run tasks until `until` finishes, or every task if `None`.
+    fn schedule(&mut self, until: Option<TaskId>) -> Result<(), VmError> {
+        let error = |kind| VmError { kind, trace: Vec::new() };
+        if self.tasks.current.is_some() {
+            return Err(error(VmErrorKind::CannotSuspend));
+        }
+        let _sandbox = self.sandbox.enter();
+        let host = self.park();
+        self.tasks.host = Some(host);
+        let result = loop {
+            let done = match until {
+                Some(task) => !self.tasks.tasks.contains_key(&task),
+                None => self.tasks.tasks.is_empty(),
+            };
+            if done {
+                break Ok(());
+            }
+            if let Some(task) = self.tasks.next_ready() {
+                self.resume(task);
+                continue;
+            }
+            match self.tasks.next_wake() {
+                Some(time) => std::thread::sleep(time.saturating_duration_since(Instant::now())),
+                None => break Err(error(VmErrorKind::Deadlock { blocked: self.tasks.tasks.len() })),
+            }
+        };
+        let host = self.tasks.host.take().expect("the host's call was parked");
+        self.unpark(host);
+        result
+    }
+
+    /// Run a ready task until it finishes or suspends.
+    fn resume(&mut self, id: TaskId) {
+        let Some(mut task) = self.tasks.tasks.remove(&id) else {
+            return;
+        };
+        let parked = std::mem::take(&mut task.parked);
+        self.unpark(parked);
+        self.tasks.current = Some(id);
+        self.tasks.level = self.tasks.calls;
+        let result = match (task.slot, task.delivery.take()) {
+            (None, _) => self.start(),
+            (Some(_), Some(Err(kind))) => self.raise(kind.into(), 0, 0).and_then(|()| self.proceed()),
+            (Some(slot), Some(Ok(value))) => {
+                self.stack[slot] = value;
+                self.proceed()
+            }
+            (Some(_), None) => self.proceed(),
+        };
+        self.tasks.current = None;
+
+        let (Some(wait), Ok(_)) = (self.tasks.suspending.take(), &result) else {
+            self.tasks.slot = None;
+            self.tasks.finish(id, result);
+            return;
+        };
+        task.parked = self.park();
+        task.slot = self.tasks.slot.take();
+        match wait {
+            Wait::Turn => self.tasks.ready.push_back(id),
+            Wait::Receive(channel) => {
+                if let Ok(channel) = self.tasks.channel(channel) {
+                    channel.receivers.push_back(id);
+                }
+                task.wait = Some(wait);
+            }
+            Wait::Until(_) | Wait::Join(_) => task.wait = Some(wait),
+        }
+        self.tasks.tasks.insert(id, task);
+    }
+
+    /// Call the callee on the stack of a task that has not started with
+    /// the arguments above it.
+    fn start(&mut self) -> Result<Value, VmError> {
+        let argc = self.stack.len() - 1;
+        if let Err(kind) = self.call_value(argc) {
+            self.stack.clear();
+            return Err(VmError { kind, trace: Vec::new() });
+        }
+        if self.tasks.is_suspending() {
+            return Ok(Value::Nil);
+        }
+        self.proceed()
+    }
+
+    /// Run the running task's frames, or, if a host function was called as
+    /// the task and returned, take its result.
+    fn proceed(&mut self) -> Result<Value, VmError> {
+        if self.frames.is_empty() {
+            return Ok(self.stack.pop().unwrap_or(Value::Nil));
+        }
+        self.execute(0, 0)
+    }
+
+    /// Take the running call off the VM, closing the variables captured
+    /// from its stack.
+    fn park(&mut self) -> Parked {
+        let stack = std::mem::take(&mut self.stack);
+        let frames = std::mem::take(&mut self.frames);
+        let collector = &mut self.collector;
+        let upvalues = std::mem::take(&mut self.open_upvalues)
+            .into_iter()
+            .filter_map(|upvalue| {
+                let Upvalue::Open(index) = *upvalue.borrow() else {
+                    return None;
+                };
+                *upvalue.borrow_mut() = Upvalue::Closed(stack[index].clone());
+                collector.write_upvalue(&upvalue);
+                Some((index, upvalue))
+            })
+            .collect();
+        Parked { stack, frames, upvalues }
+    }
+
+    /// Put a parked call back on the VM, reopening the variables captured
+    /// from its stack with the values other tasks gave them.
+    fn unpark(&mut self, parked: Parked) {
+        self.stack = parked.stack;
+        self.frames = parked.frames;
+        for (index, upvalue) in parked.upvalues {
+            if let Upvalue::Closed(value) = upvalue.replace(Upvalue::Open(index)) {
+                self.stack[index] = value;
+            }
+            self.open_upvalues.push(upvalue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Capture, Constant};
+    use crate::opcodes::OpCode;
+    use oxidex_syntax::Span;
+
+    /// Build a closure over `captures` from instructions.
+    fn closure(name: &str, arity: u8, captures: Vec<Capture>, code: &[(OpCode, Option<Constant>, &[u8])]) -> Value {
+        let span = Span::new(0, 0, 1, 1, 1, 1);
+        let mut chunk = Chunk::new();
+        for (op, constant, operands) in code {
+            match constant {
+                Some(constant) => chunk.write_constant(*op, constant.clone(), span).unwrap(),
+                None => {
+                    chunk.write_op(*op, span);
+                }
+            }
+            for byte in *operands {
+                chunk.write(*byte, span);
+            }
+        }
+        let function = Function { name: name.to_string(), arity, captures, chunk };
+        Value::Closure(Rc::new(Closure { function: Rc::new(function), upvalues: Vec::new() }))
+    }
+
+    fn name(name: &str) -> Option<Constant> {
+        Some(Constant::String(Rc::from(name)))
+    }
+
+    /// A VM whose `log(value)` appends to the returned log, and whose
+    /// `yield_now`, `receive(channel)` and `join(task)` suspend.
+    fn vm() -> (Vm, Rc<RefCell<Vec<Value>>>) {
+        let mut vm = Vm::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let logged = Rc::clone(&log);
+        vm.define_native("log", 1, move |_, args| {
+            logged.borrow_mut().push(args[0].clone());
+            Ok(Value::Nil)
+        });
+        vm.define_native("yield_now", 0, |vm, _| vm.yield_now().map(|()| Value::Nil));
+        vm.define_native("receive", 1, |vm, args| match args[0] {
+            Value::Channel(channel) => vm.channel_receive(channel),
+            ref other => Err(VmErrorKind::TypeMismatch { expected: "a channel", found: other.kind() }),
+        });
+        vm.define_native("join", 1, |vm, args| match args[0] {
+            Value::Task(task) => vm.join(task),
+            ref other => Err(VmErrorKind::TypeMismatch { expected: "a task", found: other.kind() }),
+        });
+        (vm, log)
+    }
+
+    /// `fn(x) { log(x); yield_now(); log(x) }`
+    fn log_twice() -> Value {
+        closure(
+            "log_twice",
+            1,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("log"), &[]),
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Pop, None, &[]),
+                (OpCode::GetGlobal, name("yield_now"), &[]),
+                (OpCode::Call, None, &[0]),
+                (OpCode::Pop, None, &[]),
+                (OpCode::GetGlobal, name("log"), &[]),
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Return, None, &[]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_tasks_take_turns() {
+        let (mut vm, log) = vm();
+        let a = vm.spawn(log_twice(), vec![Value::Int(1)]);
+        let b = vm.spawn(log_twice(), vec![Value::Int(2)]);
+        vm.run_tasks().unwrap();
+        assert_eq!(*log.borrow(), [Value::Int(1), Value::Int(2), Value::Int(1), Value::Int(2)]);
+        assert_eq!(vm.run_task(a).unwrap(), Value::Nil);
+        assert_eq!(vm.run_task(b).unwrap(), Value::Nil);
+
+        // Outside a task, nothing can be suspended
+        let err = vm.call(vm.global("yield_now").unwrap(), vec![]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::CannotSuspend));
+    }
+
+    #[test]
+    fn test_channels_and_joins() {
+        let (mut vm, log) = vm();
+        let channel = vm.channel();
+        // receive(channel) + 1, waiting for the value
+        let receiver = closure(
+            "receiver",
+            1,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("receive"), &[]),
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Constant, Some(Constant::Int(1)), &[]),
+                (OpCode::Add, None, &[]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let receiving = vm.spawn(receiver, vec![Value::Channel(channel)]);
+        // log(join(task))
+        let joiner = closure(
+            "joiner",
+            1,
+            vec![],
+            &[
+                (OpCode::GetGlobal, name("log"), &[]),
+                (OpCode::GetGlobal, name("join"), &[]),
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let joining = vm.spawn(joiner, vec![Value::Task(receiving)]);
+
+        let err = vm.run_tasks().unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Deadlock { blocked: 2 }), "{err}");
+        vm.channel_send(channel, Value::Int(41)).unwrap();
+        assert_eq!(vm.run_task(joining).unwrap(), Value::Nil);
+        assert_eq!(*log.borrow(), [Value::Int(42)]);
+        assert_eq!(vm.run_task(receiving).unwrap(), Value::Int(42));
+
+        // Values sent before anyone waits are queued
+        vm.channel_send(channel, Value::Int(1)).unwrap();
+        let receive = vm.global("receive").unwrap();
+        let task = vm.spawn(receive, vec![Value::Channel(channel)]);
+        assert_eq!(vm.run_task(task).unwrap(), Value::Int(1));
+
+        // Joining a task that failed raises its error
+        let failing = vm.spawn(Value::Int(3), vec![]);
+        let join = vm.global("join").unwrap();
+        let task = vm.spawn(join, vec![Value::Task(failing)]);
+        let err = vm.run_task(task).unwrap_err();
+        assert_eq!(err.kind.to_string(), "joined task failed: an integer is not callable");
+    }
+
+    #[test]
+    fn test_captured_variables_are_shared_between_tasks() {
+        let (mut vm, log) = vm();
+        // var n = 0; spawn(|| { n = n + 1 }); yield_now(); log(n)
+        let increment = closure(
+            "increment",
+            0,
+            vec![Capture::Local(0)],
+            &[
+                (OpCode::GetUpvalue, None, &[0]),
+                (OpCode::Constant, Some(Constant::Int(1)), &[]),
+                (OpCode::Add, None, &[]),
+                (OpCode::SetUpvalue, None, &[0]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        let Value::Closure(increment) = increment else { unreachable!() };
+        let main = closure(
+            "main",
+            0,
+            vec![],
+            &[
+                (OpCode::Constant, Some(Constant::Int(0)), &[]),
+                (OpCode::GetGlobal, name("spawn"), &[]),
+                (OpCode::Closure, Some(Constant::Function(Rc::clone(&increment.function))), &[]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Pop, None, &[]),
+                (OpCode::GetGlobal, name("yield_now"), &[]),
+                (OpCode::Call, None, &[0]),
+                (OpCode::Pop, None, &[]),
+                (OpCode::GetGlobal, name("log"), &[]),
+                (OpCode::GetLocal, None, &[0]),
+                (OpCode::Call, None, &[1]),
+                (OpCode::Return, None, &[]),
+            ],
+        );
+        vm.define_native("spawn", 1, |vm, args| Ok(Value::Task(vm.spawn(args[0].clone(), Vec::new()))));
+        let task = vm.spawn(main, vec![]);
+        vm.set_gc_threshold(Some(1));
+        vm.run_task(task).unwrap();
+        assert_eq!(*log.borrow(), [Value::Int(1)]);
+    }
+}
@@ -0,0 +1,211 @@
+//! Threaded dispatch.
+//!
+//! Instead of decoding each opcode and matching on it, the VM indexes a
+//! table of handlers by the opcode byte and calls the handler. Each handler
+//! is the VM's instruction code specialised to one opcode, so the call lands
+//! directly on that instruction's code, and bytes that are not opcodes land
+//! on a handler that raises the error.
+
+use super::{Raise, Vm};
+use crate::error::VmErrorKind;
+use crate::opcodes::OpCode;
+use crate::value::Value;
+
+/// Executes one instruction, given the frame floor of the run.
+type Handler = fn(&mut Vm, usize) -> Result<Option<Value>, Raise>;
+
+macro_rules! handlers {
+    ($($op:ident),* $(,)?) => {{
+        const _: () = assert!([$(OpCode::$op),*].len() == OpCode::ALL.len(), "every opcode needs a handler");
+        let mut table: [Handler; 256] = [invalid; 256];
+        $(table[OpCode::$op as usize] = handler::<{ OpCode::$op as u8 }>;)*
+        table
+    }};
+}
+
+/// Handlers by opcode byte.
+static HANDLERS: [Handler; 256] = handlers![
+    Constant, Nil, True, False, Pop, Dup, GetLocal, SetLocal, GetGlobal, DefineGlobal, SetGlobal, GetUpvalue,
+    SetUpvalue, Add, Subtract, Multiply, Divide, Remainder, Negate, Not, Equal, NotEqual, Less, LessEqual, Greater,
+    GreaterEqual, Send, GetField, SetField, Jump, JumpIfFalse, Loop, Closure, CloseUpvalue, Call, Return, Throw,
+    Rethrow, Intrinsic,
+];
+
+fn handler<const OP: u8>(vm: &mut Vm, floor: usize) -> Result<Option<Value>, Raise> {
+    vm.execute_op(OpCode::ALL[OP as usize], floor)
+}
+
+fn invalid(vm: &mut Vm, _floor: usize) -> Result<Option<Value>, Raise> {
+    let frame = vm.frame();
+    Err(VmErrorKind::InvalidOpcode(frame.closure.function.chunk.code()[frame.current]).into())
+}
+
+impl Vm {
+    /// Execute the instruction with opcode `byte`, whose operands follow.
+    pub(super) fn dispatch(&mut self, byte: u8, floor: usize) -> Result<Option<Value>, Raise> {
+        #[cfg(test)]
+        if tests::MATCH_DISPATCH.get() {
+            return self.match_dispatch(byte, floor);
+        }
+        HANDLERS[usize::from(byte)](self, floor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{Chunk, Function, Handler, HandlerKind};
+    use crate::compiler::compile;
+    use crate::error::VmError;
+    use crate::opcodes::OpCode;
+    use crate::value::Value;
+    use crate::vm::Vm;
+    use oxidex_codegen::ir::parse_module;
+    use oxidex_syntax::Span;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    thread_local! {
+        /// Whether the VM matches on opcodes instead of indexing the table
+        pub(super) static MATCH_DISPATCH: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// What a run ends with: its value, with a caught error described, or
+    /// its error's message, offset and the functions of its trace
+    type Outcome = Result<Value, (String, Option<usize>, Vec<String>)>;
+
+    /// Run `script`, then `main` with `args` if there is one, by threaded
+    /// dispatch or by matching on opcodes.
+    fn run(script: &Function, args: &[Value], matching: bool) -> Outcome {
+        MATCH_DISPATCH.set(matching);
+        let mut vm = Vm::new();
+        let result = vm.run(Rc::new(script.clone())).and_then(|value| match vm.global("main") {
+            Some(main) => vm.call(main, args.to_vec()),
+            None => Ok(value),
+        });
+        MATCH_DISPATCH.set(false);
+        // Caught errors are values equal only to themselves
+        let result = result.map(|value| match value {
+            Value::Error(error) => Value::string(&format!("caught {} at {:?}", error.kind, error.offset())),
+            value => value,
+        });
+        result.map_err(|err: VmError| {
+            let trace = err.trace.iter().map(|frame| frame.function.clone()).collect();
+            (err.kind.to_string(), err.offset(), trace)
+        })
+    }
+
+    /// Run `script` both ways, checking that they end the same.
+    fn parity(script: &Function, args: &[Value]) -> Outcome {
+        let threaded = run(script, args, false);
+        assert_eq!(threaded, run(script, args, true), "{}", script.name);
+        threaded
+    }
+
+    fn ir(text: &str) -> Function {
+        compile(&parse_module(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_threaded_dispatch_matches_matching_on_opcodes() {
+        let program = ir(r#"
+fn "fact"(%0: int) -> int {
+bb0:
+    %1: int = const int 1
+    %2: bool = binary le %0, %1
+    branch %2, bb1, bb2
+bb1:
+    return %1
+bb2:
+    %3: int = binary sub %0, %1
+    %4: int = call "fact"(%3)
+    %5: int = binary mul %0, %4
+    return %5
+}
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %7: int = const int 1
+    jump bb1
+bb1:
+    %2: int = phi [bb0: %1, bb2: %5]
+    %3: int = phi [bb0: %1, bb2: %6]
+    %4: bool = binary lt %2, %0
+    branch %4, bb2, bb3
+bb2:
+    %5: int = binary add %2, %7
+    %6: int = call "fact"(%5)
+    jump bb1
+bb3:
+    %8: int = binary add %3, %2
+    return %8
+}
+"#);
+        assert_eq!(parity(&program, &[Value::Int(5)]), Ok(Value::Int(120 + 5)));
+        assert_eq!(parity(&program, &[Value::Int(0)]), Ok(Value::Int(0)));
+        // fact(21) overflows multiplying fact(20) by 21
+        let Err((message, _, trace)) = parity(&program, &[Value::Int(21)]) else { panic!("expected an error") };
+        assert_eq!(message, "integer overflow");
+        assert_eq!(trace, ["fact", "main"]);
+
+        let program = ir(r#"
+fn "main"(%0: int, %1: string) -> int {
+bb0:
+    %2: int = intrinsic abs(%0)
+    %3: int = intrinsic length(%1)
+    %4: int = intrinsic max(%2, %3)
+    %5: bool = binary ne %2, %3
+    branch %5, bb1, bb2
+bb1:
+    return %4
+bb2:
+    %6: int = unary neg %4
+    return %6
+}
+"#);
+        let text = Value::string("sixteen!");
+        assert_eq!(parity(&program, &[Value::Int(-16), text.clone()]), Ok(Value::Int(16)));
+        assert_eq!(parity(&program, &[Value::Int(8), text]), Ok(Value::Int(-8)));
+
+        // Errors are raised at the same instruction, and caught by the same
+        // handlers
+        let program = ir(r#"
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %2: int = binary div %0, %1
+    return %2
+}
+"#);
+        let Err((message, offset, trace)) = parity(&program, &[Value::Int(1)]) else { panic!("expected an error") };
+        assert_eq!(message, "division by zero");
+        assert_eq!(trace, ["main"]);
+        assert!(offset.is_some());
+
+        let span = Span::new(0, 1, 1, 1, 1, 2);
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::Constant, span);
+        let index = chunk.add_constant(crate::chunk::Constant::Int(1)).unwrap();
+        chunk.write_u16(index, span);
+        // 1 / (1 - 1)
+        chunk.write_op(OpCode::Constant, span);
+        chunk.write_u16(index, span);
+        chunk.write_op(OpCode::Dup, span);
+        chunk.write_op(OpCode::Subtract, span);
+        chunk.write_op(OpCode::Divide, span);
+        chunk.write_op(OpCode::Return, span);
+        chunk.write_op(OpCode::Return, span);
+        chunk.add_handler(Handler { kind: HandlerKind::Catch, start: 0, end: 9, target: 10, depth: 0 });
+        let script = Function { name: "caught".to_string(), arity: 0, captures: Vec::new(), chunk };
+        assert_eq!(parity(&script, &[]), Ok(Value::string("caught division by zero at Some(8)")));
+
+        // Bytes that are not opcodes fail the same way, uncaught
+        let mut chunk = Chunk::new();
+        chunk.write(0xff, span);
+        chunk.write_op(OpCode::Return, span);
+        chunk.add_handler(Handler { kind: HandlerKind::Catch, start: 0, end: 1, target: 1, depth: 0 });
+        let script = Function { name: "invalid".to_string(), arity: 0, captures: Vec::new(), chunk };
+        let Err((message, offset, _)) = parity(&script, &[]) else { panic!("expected an error") };
+        assert_eq!((message.as_str(), offset), ("invalid opcode 0xff", Some(0)));
+    }
+}
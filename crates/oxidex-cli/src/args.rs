@@ -0,0 +1,240 @@
+//! Command-line tokenizing.
+//!
+//! [`Args`] splits the raw arguments into flags and values the way most
+//! Unix tools read them: `--name`, `--name=value` and `--name value` for long
+//! flags; `-n`, `-nvalue` and `-n value` for short ones, which may be grouped
+//! as in `-vv`; and `--` to end the flags, so everything after it is a value.
+//! A lone `-` is a value, conventionally standard input.
+//!
+//! Whether a flag takes a value is up to the caller, which asks for one with
+//! [`Args::value`] after seeing the flag.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+/// A malformed command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError {
+    message: String,
+}
+
+impl UsageError {
+    /// An error reporting `message`.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// One argument, as the tokenizer reads it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// A short flag, such as `-v`
+    Short(char),
+    /// A long flag without its dashes, such as `verbose` for `--verbose`
+    Long(String),
+    /// A positional argument, or any argument after `--`
+    Value(String),
+}
+
+impl Arg {
+    /// Whether this is the flag `--long`, or `-short` if it has one.
+    pub fn is(&self, short: Option<char>, long: &str) -> bool {
+        match self {
+            Self::Short(c) => Some(*c) == short,
+            Self::Long(name) => name == long,
+            Self::Value(_) => false,
+        }
+    }
+
+    /// The argument as it was written, for messages.
+    pub fn display(&self) -> String {
+        match self {
+            Self::Short(c) => format!("-{c}"),
+            Self::Long(name) => format!("--{name}"),
+            Self::Value(value) => value.clone(),
+        }
+    }
+
+    /// The error for an argument the command does not accept.
+    pub fn unexpected(&self) -> UsageError {
+        match self {
+            Self::Value(value) => UsageError::new(format!("unexpected argument `{value}`")),
+            flag => UsageError::new(format!("unknown flag `{}`", flag.display())),
+        }
+    }
+}
+
+/// What is left of the argument being read.
+#[derive(Debug)]
+enum Pending {
+    /// Short flags grouped after the first, as `v` in `-vv`
+    Shorts(String),
+    /// The value after `=` in `--name=value`
+    Value(String),
+}
+
+/// A cursor over the command line.
+#[derive(Debug)]
+pub struct Args {
+    raw: VecDeque<String>,
+    pending: Option<Pending>,
+    /// The last flag read, for messages
+    last: Option<Arg>,
+    /// Whether `--` has been read
+    finished: bool,
+}
+
+impl Args {
+    /// A cursor over `raw`, which excludes the program name.
+    pub fn new(raw: impl IntoIterator<Item = String>) -> Self {
+        Self { raw: raw.into_iter().collect(), pending: None, last: None, finished: false }
+    }
+
+    /// The next argument, or `None` at the end.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the previous flag was given a value with `=` that
+    /// was not asked for.
+    pub fn next(&mut self) -> Result<Option<Arg>, UsageError> {
+        match self.pending.take() {
+            Some(Pending::Value(_)) => {
+                let flag = self.last.as_ref().map(Arg::display).unwrap_or_default();
+                return Err(UsageError::new(format!("flag `{flag}` does not take a value")));
+            }
+            Some(Pending::Shorts(shorts)) => {
+                let mut chars = shorts.chars();
+                let short = chars.next().unwrap_or_default();
+                let rest = chars.as_str();
+                if !rest.is_empty() {
+                    self.pending = Some(Pending::Shorts(rest.to_string()));
+                }
+                return Ok(Some(self.flag(Arg::Short(short))));
+            }
+            None => {}
+        }
+
+        let Some(raw) = self.raw.pop_front() else {
+            return Ok(None);
+        };
+        if self.finished {
+            return Ok(Some(Arg::Value(raw)));
+        }
+        if raw == "--" {
+            self.finished = true;
+            return self.next();
+        }
+        if let Some(long) = raw.strip_prefix("--") {
+            let arg = match long.split_once('=') {
+                Some((name, value)) => {
+                    self.pending = Some(Pending::Value(value.to_string()));
+                    Arg::Long(name.to_string())
+                }
+                None => Arg::Long(long.to_string()),
+            };
+            return Ok(Some(self.flag(arg)));
+        }
+        if let Some(shorts) = raw.strip_prefix('-')
+            && !shorts.is_empty()
+        {
+            self.pending = Some(Pending::Shorts(shorts.to_string()));
+            return self.next();
+        }
+        Ok(Some(Arg::Value(raw)))
+    }
+
+    fn flag(&mut self, arg: Arg) -> Arg {
+        self.last = Some(arg.clone());
+        arg
+    }
+
+    /// The value of the flag just read: what follows `=` or the short flag,
+    /// or else the next argument, even one starting with `-`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no value left.
+    pub fn value(&mut self) -> Result<String, UsageError> {
+        match self.pending.take() {
+            Some(Pending::Value(value)) => return Ok(value),
+            Some(Pending::Shorts(shorts)) => {
+                return Ok(shorts.strip_prefix('=').map_or_else(|| shorts.clone(), str::to_string));
+            }
+            None => {}
+        }
+        self.raw.pop_front().ok_or_else(|| {
+            let flag = self.last.as_ref().map(Arg::display).unwrap_or_default();
+            UsageError::new(format!("flag `{flag}` needs a value"))
+        })
+    }
+
+    /// Every argument not yet read, as written, such as the arguments an
+    /// `ox run` passes on to the program.
+    pub fn rest(&mut self) -> Vec<String> {
+        self.pending = None;
+        self.raw.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(raw: &[&str]) -> Args {
+        Args::new(raw.iter().map(ToString::to_string))
+    }
+
+    fn read_all(args: &mut Args) -> Vec<Arg> {
+        std::iter::from_fn(|| args.next().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_flags_values_and_separator() {
+        let mut parsed = args(&["run", "-vv", "--color", "-", "--", "--not-a-flag", "-x"]);
+        assert_eq!(
+            read_all(&mut parsed),
+            [
+                Arg::Value("run".into()),
+                Arg::Short('v'),
+                Arg::Short('v'),
+                Arg::Long("color".into()),
+                Arg::Value("-".into()),
+                Arg::Value("--not-a-flag".into()),
+                Arg::Value("-x".into()),
+            ]
+        );
+        assert!(Arg::Short('v').is(Some('v'), "verbose") && Arg::Long("verbose".into()).is(None, "verbose"));
+        assert!(!Arg::Value("verbose".into()).is(Some('v'), "verbose"));
+    }
+
+    #[test]
+    fn test_flag_values() {
+        let mut parsed = args(&["--log-format=json", "-ofile.oxb", "-o", "-dashed", "--color"]);
+        assert_eq!(parsed.next(), Ok(Some(Arg::Long("log-format".into()))));
+        assert_eq!(parsed.value().as_deref(), Ok("json"));
+        assert_eq!(parsed.next(), Ok(Some(Arg::Short('o'))));
+        assert_eq!(parsed.value().as_deref(), Ok("file.oxb"));
+        assert_eq!(parsed.next(), Ok(Some(Arg::Short('o'))));
+        assert_eq!(parsed.value().as_deref(), Ok("-dashed"));
+        assert_eq!(parsed.next(), Ok(Some(Arg::Long("color".into()))));
+        assert_eq!(parsed.value().unwrap_err().to_string(), "flag `--color` needs a value");
+
+        let mut parsed = args(&["--check=yes"]);
+        parsed.next().unwrap();
+        assert_eq!(parsed.next().unwrap_err().to_string(), "flag `--check` does not take a value");
+
+        let mut parsed = args(&["main.ox", "--verbose", "x"]);
+        parsed.next().unwrap();
+        assert_eq!(parsed.rest(), ["--verbose", "x"]);
+        assert_eq!(parsed.next(), Ok(None));
+        assert_eq!(Arg::Long("frob".into()).unexpected().to_string(), "unknown flag `--frob`");
+    }
+}
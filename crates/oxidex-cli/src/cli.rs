@@ -0,0 +1,476 @@
+//! The command line, global options, logging and exit codes.
+//!
+//! [`parse`] reads `ox [options] <command> [args...]` into an [`Invocation`].
+//! The global flags are accepted before or after the subcommand, up to a
+//! `--`: `-v`/`--verbose` (repeatable), `--color=auto|always|never`,
+//! `--log-format=text|json`, `--max-errors=<n>` and
+//! `--warnings=warn|deny|allow`, and `--define NAME=VALUE` to set a constant of
+//! the program, long only since `ox lint` takes `-D` for `--deny`. `ox` itself
+//! writes everything but a command's output to standard error through
+//! [`GlobalOptions::log`], as plain lines or as one JSON object per line for
+//! tools that read them.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::commands::{COMMANDS, Command, CommandInfo, find};
+use oxidex_syntax::sink::{SinkOptions, WarningPolicy};
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+/// The command succeeded.
+pub const EXIT_SUCCESS: u8 = 0;
+
+/// The command failed: the program did not compile or run, or a file could
+/// not be read or written.
+pub const EXIT_FAILURE: u8 = 1;
+
+/// The command line was malformed.
+pub const EXIT_USAGE: u8 = 2;
+
+/// Help for the flags every command takes.
+pub const GLOBAL_HELP: &str = "\
+Global options:
+  -v, --verbose              Print more about what ox is doing; repeat for more
+      --color <when>         Color diagnostics: auto, always or never [default: auto]
+      --log-format <format>  Format of ox's own messages: text or json [default: text]
+      --max-errors <n>       Print at most n errors of a program, 0 for all [default: 0]
+      --warnings <policy>    Report warnings: warn, deny (as errors) or allow (not at all) [default: warn]
+      --define <name=value>  Set the constant `name` of the program to `value`, or to true without one
+  -h, --help                 Print help";
+
+/// What a command line asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invocation {
+    /// Print this help text
+    Help(String),
+    /// Print the version
+    Version,
+    /// Run the command
+    Command(Command),
+}
+
+/// Read the command line `raw`, without the program name, taking the global
+/// flags into `global`.
+///
+/// # Errors
+///
+/// Returns an error, with a pointer to the relevant help, if the command
+/// line is malformed.
+pub fn parse(raw: impl IntoIterator<Item = String>, global: &mut GlobalOptions) -> Result<Invocation, UsageError> {
+    let mut args = Args::new(raw);
+    let see_help = |err: UsageError| UsageError::new(format!("{err}; see `ox help`"));
+    while let Some(arg) = args.next().map_err(see_help)? {
+        match arg {
+            Arg::Value(name) if name == "help" => {
+                return match args.next().map_err(see_help)? {
+                    None => Ok(Invocation::Help(help(None))),
+                    Some(Arg::Value(name)) => match find(&name) {
+                        Some(info) => Ok(Invocation::Help(help(Some(info)))),
+                        None => Err(see_help(UsageError::new(format!("unknown command `{name}`")))),
+                    },
+                    Some(arg) => Err(see_help(arg.unexpected())),
+                };
+            }
+            Arg::Value(name) => {
+                let info = find(&name).ok_or_else(|| see_help(UsageError::new(format!("unknown command `{name}`"))))?;
+                let command = Command::parse(&name, &mut args, global)
+                    .map_err(|err| UsageError::new(format!("{err}; see `ox help {name}`")))?;
+                return Ok(if global.help { Invocation::Help(help(Some(info))) } else { Invocation::Command(command) });
+            }
+            _ if arg.is(Some('V'), "version") => return Ok(Invocation::Version),
+            _ => global.accept(&arg, &mut args).map_err(see_help)?,
+        }
+    }
+    if global.help {
+        Ok(Invocation::Help(help(None)))
+    } else {
+        Err(see_help(UsageError::new("no command given")))
+    }
+}
+
+/// The help for `command`, or for `ox` itself.
+pub fn help(command: Option<&CommandInfo>) -> String {
+    if let Some(info) = command {
+        return format!("{}\n\n{GLOBAL_HELP}", info.help);
+    }
+    let width = COMMANDS.iter().map(|info| info.name.len()).max().unwrap_or_default();
+    let mut text = String::from("The OxideX toolchain\n\nUsage: ox [options] <command> [args...]\n\nCommands:\n");
+    for info in COMMANDS {
+        text.push_str(&format!("  {:width$}  {}\n", info.name, info.summary));
+    }
+    text.push_str(&format!("  {:width$}  Print help for a command\n\n{GLOBAL_HELP}\n", "help"));
+    text.push_str("  -V, --version              Print the version");
+    text
+}
+
+/// When to color output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Color {
+    /// When standard error is a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl Color {
+    /// Whether to color what is written to standard error.
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// The format of `ox`'s own messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `ox: level: message` lines
+    #[default]
+    Text,
+    /// One JSON object per line, with `level` and `message` fields
+    Json,
+}
+
+/// How much a message matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Printed with `-vv`
+    Debug,
+    /// Printed with `-v`
+    Info,
+    /// Always printed
+    Error,
+}
+
+impl Level {
+    /// The level's name, as logged.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The flags every command takes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GlobalOptions {
+    /// How many times `-v` was given
+    pub verbose: u8,
+    /// When to color output
+    pub color: Color,
+    /// The format of `ox`'s own messages
+    pub log_format: LogFormat,
+    /// The most errors of a program to print, if there is a limit
+    pub max_errors: Option<usize>,
+    /// What to do with warnings
+    pub warnings: WarningPolicy,
+    /// The constants set with `--define`, by name, with their values as written
+    pub defines: Vec<(String, String)>,
+    /// Whether help was asked for
+    pub help: bool,
+}
+
+impl GlobalOptions {
+    /// Take `arg` as a global flag, reading its value from `args`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `arg` is not a global flag, or its value is
+    /// missing or not one it accepts.
+    pub fn accept(&mut self, arg: &Arg, args: &mut Args) -> Result<(), UsageError> {
+        if arg.is(Some('v'), "verbose") {
+            self.verbose = self.verbose.saturating_add(1);
+        } else if arg.is(Some('h'), "help") {
+            self.help = true;
+        } else if arg.is(None, "color") {
+            self.color = match args.value()?.as_str() {
+                "auto" => Color::Auto,
+                "always" => Color::Always,
+                "never" => Color::Never,
+                other => return Err(invalid_value(arg, other, "auto, always or never")),
+            };
+        } else if arg.is(None, "log-format") {
+            self.log_format = match args.value()?.as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                other => return Err(invalid_value(arg, other, "text or json")),
+            };
+        } else if arg.is(None, "max-errors") {
+            let value = args.value()?;
+            let max = value.parse().map_err(|_| invalid_value(arg, &value, "a number"))?;
+            self.max_errors = (max > 0).then_some(max);
+        } else if arg.is(None, "warnings") {
+            self.warnings = match args.value()?.as_str() {
+                "warn" => WarningPolicy::Warn,
+                "deny" => WarningPolicy::Deny,
+                "allow" => WarningPolicy::Allow,
+                other => return Err(invalid_value(arg, other, "warn, deny or allow")),
+            };
+        } else if arg.is(None, "define") {
+            let define = args.value()?;
+            let (name, value) = define.split_once('=').unwrap_or((define.as_str(), "true"));
+            if name.is_empty() {
+                return Err(invalid_value(arg, &define, "name=value"));
+            }
+            self.defines.push((name.to_string(), value.to_string()));
+        } else {
+            return Err(arg.unexpected());
+        }
+        Ok(())
+    }
+
+    /// How the diagnostics of a program are limited and escalated.
+    pub fn sink(&self) -> SinkOptions {
+        SinkOptions { max_errors: self.max_errors, warnings: self.warnings }
+    }
+
+    /// Whether a message at `level` is printed.
+    pub fn logs(&self, level: Level) -> bool {
+        match level {
+            Level::Debug => self.verbose >= 2,
+            Level::Info => self.verbose >= 1,
+            Level::Error => true,
+        }
+    }
+
+    /// Print `message` to standard error if `level` is printed.
+    pub fn log(&self, level: Level, message: impl fmt::Display) {
+        if self.logs(level) {
+            eprintln!("{}", self.format_log(level, &message.to_string()));
+        }
+    }
+
+    /// The line [`log`](Self::log) prints.
+    pub fn format_log(&self, level: Level, message: &str) -> String {
+        match self.log_format {
+            LogFormat::Text if self.color.enabled() => {
+                let style = match level {
+                    Level::Debug => "2",
+                    Level::Info => "1;36",
+                    Level::Error => "1;31",
+                };
+                format!("ox: \x1b[{style}m{}\x1b[0m: {message}", level.name())
+            }
+            LogFormat::Text => format!("ox: {}: {message}", level.name()),
+            LogFormat::Json => format!(r#"{{"level":"{}","message":{}}}"#, level.name(), json_string(message)),
+        }
+    }
+}
+
+fn invalid_value(flag: &Arg, value: &str, expected: &str) -> UsageError {
+    UsageError::new(format!("invalid value `{value}` for `{}`; expected {expected}", flag.display()))
+}
+
+/// `text` as a JSON string literal.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A command that failed after its command line was read.
+#[derive(Debug)]
+pub enum CliError {
+    /// The command has no implementation yet
+    Unimplemented(&'static str),
+    /// A file could not be read or written
+    Io {
+        /// The file
+        path: PathBuf,
+        /// What went wrong
+        source: std::io::Error,
+    },
+    /// A file did not compile; its diagnostics were already reported
+    Compile {
+        /// Name of the file
+        file: String,
+        /// How many errors were reported
+        errors: usize,
+    },
+    /// A file to run has no `main` function
+    NoMain {
+        /// Name of the file
+        file: String,
+    },
+    /// A file `ox` reads, such as a project manifest, is malformed
+    Malformed {
+        /// The file
+        path: PathBuf,
+        /// The line of the problem, or zero for the file as a whole
+        line: usize,
+        /// What is wrong
+        message: String,
+    },
+    /// A build profile that does not exist
+    UnknownProfile {
+        /// Its name
+        name: String,
+        /// The profiles that do
+        known: Vec<String>,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unimplemented(command) => write!(f, "`ox {command}` is not implemented yet"),
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Compile { file, errors: 1 } => write!(f, "could not compile `{file}` due to an error"),
+            Self::Compile { file, errors } => write!(f, "could not compile `{file}` due to {errors} errors"),
+            Self::NoMain { file } => write!(f, "`{file}` has no `main` function to run"),
+            Self::Malformed { path, line: 0, message } => write!(f, "{}: {message}", path.display()),
+            Self::Malformed { path, line, message } => write!(f, "{}:{line}: {message}", path.display()),
+            Self::UnknownProfile { name, known } => {
+                write!(f, "unknown profile `{name}`; expected one of {}", known.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Unimplemented(_)
+            | Self::Compile { .. }
+            | Self::NoMain { .. }
+            | Self::Malformed { .. }
+            | Self::UnknownProfile { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::LintLevel;
+
+    fn accept_all(raw: &[&str]) -> Result<GlobalOptions, UsageError> {
+        let mut args = Args::new(raw.iter().map(ToString::to_string));
+        let mut global = GlobalOptions::default();
+        while let Some(arg) = args.next()? {
+            global.accept(&arg, &mut args)?;
+        }
+        Ok(global)
+    }
+
+    #[test]
+    fn test_global_flags() {
+        let global = accept_all(&["-vv", "--color", "never", "--log-format=json", "--help"]).unwrap();
+        let expected =
+            GlobalOptions { verbose: 2, color: Color::Never, log_format: LogFormat::Json, help: true, ..Default::default() };
+        assert_eq!(global, expected);
+        assert!(global.logs(Level::Debug) && !GlobalOptions::default().logs(Level::Info));
+        assert!(!Color::Never.enabled() && Color::Always.enabled());
+
+        let sink = accept_all(&["--max-errors=3", "--warnings", "deny"]).unwrap().sink();
+        assert_eq!(sink, SinkOptions { max_errors: Some(3), warnings: WarningPolicy::Deny });
+        assert_eq!(accept_all(&["--max-errors", "0"]).unwrap().sink(), SinkOptions::default());
+
+        let err = accept_all(&["--color=sometimes"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid value `sometimes` for `--color`; expected auto, always or never");
+        let err = accept_all(&["--max-errors=many"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid value `many` for `--max-errors`; expected a number");
+        assert_eq!(accept_all(&["main.ox"]).unwrap_err().to_string(), "unexpected argument `main.ox`");
+
+        let global = accept_all(&["--define=LEVEL=3", "--define", "DEBUG", "--define", "NAME=a=b"]).unwrap();
+        let defines = [("LEVEL", "3"), ("DEBUG", "true"), ("NAME", "a=b")].map(|(n, v)| (n.to_string(), v.to_string()));
+        assert_eq!(global.defines, defines);
+        let err = accept_all(&["--define", "=1"]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid value `=1` for `--define`; expected name=value");
+        assert_eq!(accept_all(&["-D", "DEBUG"]).unwrap_err().to_string(), "unknown flag `-D`");
+    }
+
+    fn parse_line(line: &str) -> (Result<Invocation, UsageError>, GlobalOptions) {
+        let mut global = GlobalOptions::default();
+        (parse(line.split_whitespace().map(ToString::to_string), &mut global), global)
+    }
+
+    #[test]
+    fn test_subcommand_dispatch() {
+        let (invocation, global) = parse_line("-v run --color never main.ox --verbose 1");
+        let Ok(Invocation::Command(Command::Run(options))) = invocation else { panic!("{invocation:?}") };
+        assert_eq!((options.file.to_str(), options.args), (Some("main.ox"), vec!["--verbose".into(), "1".into()]));
+        assert_eq!((global.verbose, global.color), (1, Color::Never));
+        let (invocation, _) = parse_line("run --watch --memory-stats main.ox --watch");
+        let Ok(Invocation::Command(Command::Run(options))) = invocation else { panic!("{invocation:?}") };
+        assert_eq!((options.watch, options.memory_stats, options.args), (true, true, vec!["--watch".into()]));
+        let (invocation, _) = parse_line("run --heap-dump=heap.json main.ox");
+        let Ok(Invocation::Command(Command::Run(options))) = invocation else { panic!("{invocation:?}") };
+        assert_eq!(options.heap_dump, Some(PathBuf::from("heap.json")));
+
+        let (invocation, _) = parse_line("build a.ox b.ox -o out.oxb");
+        let Ok(Invocation::Command(Command::Build(options))) = invocation else { panic!("{invocation:?}") };
+        assert_eq!((options.files.len(), options.output.unwrap().to_str()), (2, Some("out.oxb")));
+
+        for (line, name) in [("compile a.ox", "compile"), ("jit --stats a.ox", "jit"), ("fmt --check", "fmt")] {
+            assert!(matches!(parse_line(line).0, Ok(Invocation::Command(command)) if command.name() == name));
+        }
+        for (line, name) in [("lint a.ox", "lint"), ("doc a.ox", "doc"), ("repl", "repl"), ("test -f x", "test")] {
+            assert!(matches!(parse_line(line).0, Ok(Invocation::Command(command)) if command.name() == name));
+        }
+        assert!(matches!(parse_line("bench -n 5").0, Ok(Invocation::Command(command)) if command.name() == "bench"));
+
+        // `-D` after `lint` denies a rule; constants are defined long only
+        let (invocation, global) = parse_line("--define DEBUG lint -D all a.ox --define LEVEL=2");
+        let Ok(Invocation::Command(Command::Lint(options))) = invocation else { panic!("{invocation:?}") };
+        assert_eq!(options.levels, [("all".to_string(), LintLevel::Deny)]);
+        let defines = [("DEBUG", "true"), ("LEVEL", "2")].map(|(n, v)| (n.to_string(), v.to_string()));
+        assert_eq!(global.defines, defines);
+        assert_eq!(parse_line("-D DEBUG run a.ox").0.unwrap_err().to_string(), "unknown flag `-D`; see `ox help`");
+        assert_eq!(parse_line("--version").0, Ok(Invocation::Version));
+    }
+
+    #[test]
+    fn test_help_and_usage_errors() {
+        let run_help = Invocation::Help(help(find("run")));
+        assert_eq!(parse_line("help run").0, Ok(run_help.clone()));
+        assert_eq!(parse_line("run --help").0, Ok(run_help.clone()));
+        assert_eq!(parse_line("--help run").0, Ok(run_help));
+        assert_eq!(parse_line("--help").0, Ok(Invocation::Help(help(None))));
+        assert!(help(None).contains("  repl     Start an interactive session"));
+        assert!(help(find("fmt")).ends_with(GLOBAL_HELP));
+
+        let error = |line| parse_line(line).0.unwrap_err().to_string();
+        assert_eq!(error(""), "no command given; see `ox help`");
+        assert_eq!(error("frobnicate"), "unknown command `frobnicate`; see `ox help`");
+        assert_eq!(error("help frobnicate"), "unknown command `frobnicate`; see `ox help`");
+        assert_eq!(error("run"), "missing the source file to run; see `ox help run`");
+        assert_eq!(error("compile a.ox b.ox"), "unexpected argument `b.ox`; see `ox help compile`");
+        assert_eq!(error("build --debug a.ox"), "unknown flag `--debug`; see `ox help build`");
+        let invalid = "invalid value `yaml` for `--log-format`; expected text or json; see `ox help`";
+        assert_eq!(error("--log-format yaml run"), invalid);
+    }
+
+    #[test]
+    fn test_log_formats() {
+        let text = GlobalOptions { color: Color::Never, ..GlobalOptions::default() };
+        assert_eq!(text.format_log(Level::Error, "no such file"), "ox: error: no such file");
+        let colored = GlobalOptions { color: Color::Always, ..GlobalOptions::default() };
+        assert_eq!(colored.format_log(Level::Info, "done"), "ox: \x1b[1;36minfo\x1b[0m: done");
+        let json = GlobalOptions { log_format: LogFormat::Json, ..GlobalOptions::default() };
+        assert_eq!(
+            json.format_log(Level::Error, "bad \"quote\"\n\u{1}"),
+            r#"{"level":"error","message":"bad \"quote\"\n\u0001"}"#
+        );
+    }
+}
@@ -0,0 +1,562 @@
+//! `ox bench`: run the benchmarks of source files.
+//!
+//! A benchmark is a function marked `@bench` that takes no parameters.
+//! Files are found as `ox test` finds them, in the `bench` directory by
+//! default. Each file is compiled to bytecode once, with the files it
+//! imports, then each of its benchmarks runs in a VM of its own, with the
+//! JIT if asked for: a few calls to warm up, then timed calls, summarized
+//! by the mean, median and standard deviation of their times and the heap
+//! allocations a call makes. The program has the `assert_eq` and `expect`
+//! assertions of `ox test`, so tests beside the benchmarks check as well.
+//!
+//! Results can be saved to a baseline file, and a later run compared with
+//! one: a change in a benchmark's mean counts when it is beyond both the
+//! noise of either run and a few percent.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::heap;
+use crate::pipeline::{self, Parsed};
+use oxidex_bytecode::{Function, Vm, builtins, compile};
+use oxidex_codegen::ir;
+use oxidex_jit::{Jit, Profile};
+use oxidex_std::runtime::builtins as reflection;
+use oxidex_std::testing::builtins as assertions;
+use oxidex_syntax::{Decl, Span};
+use oxidex_typecheck::infer::Context;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Help for `ox bench`.
+pub const HELP: &str = "\
+Run the benchmarks of OxideX source files
+
+Usage: ox bench [options] [paths...]
+
+Arguments:
+  [paths...]  Source files, or directories to search for them [default: bench]
+
+Options:
+  -f, --filter <text>         Only run the benchmarks whose names contain the
+                              text
+  -n, --iterations <n>        How many timed calls to make [default: 10]
+      --warmup <n>            How many calls to make before timing [default: 3]
+      --jit                   Compile hot functions to machine code
+      --save-baseline <file>  Save the results to the file
+      --baseline <file>       Compare the results with those saved in the file
+
+A benchmark is a function marked `@bench` that takes no parameters. It runs
+under the bytecode VM, and its calls are timed and their heap allocations
+counted.";
+
+/// Timed calls made unless `--iterations` says otherwise.
+const ITERATIONS: usize = 10;
+
+/// Calls made before timing unless `--warmup` says otherwise.
+const WARMUP: usize = 3;
+
+/// The smallest change in a mean, in percent, that counts as one.
+const THRESHOLD: f64 = 2.0;
+
+/// Options of `ox bench`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// The source files and directories
+    pub paths: Vec<PathBuf>,
+    /// Text the names of the benchmarks to run contain
+    pub filter: Option<String>,
+    /// Timed calls of each benchmark
+    pub iterations: usize,
+    /// Calls of each benchmark before timing
+    pub warmup: usize,
+    /// Whether to run with the JIT
+    pub jit: bool,
+    /// Where to save the results
+    pub save_baseline: Option<PathBuf>,
+    /// Results to compare with
+    pub baseline: Option<PathBuf>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            filter: None,
+            iterations: ITERATIONS,
+            warmup: WARMUP,
+            jit: false,
+            save_baseline: None,
+            baseline: None,
+        }
+    }
+}
+
+impl BenchOptions {
+    /// Read the options from the arguments after `bench`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a flag is unknown or a count is not a number, or
+    /// there would be no timed calls.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) => options.paths.push(PathBuf::from(path)),
+                _ if arg.is(Some('f'), "filter") => options.filter = Some(args.value()?),
+                _ if arg.is(Some('n'), "iterations") => options.iterations = count(args, "--iterations")?,
+                _ if arg.is(None, "warmup") => options.warmup = count(args, "--warmup")?,
+                _ if arg.is(None, "jit") => options.jit = true,
+                _ if arg.is(None, "save-baseline") => options.save_baseline = Some(PathBuf::from(args.value()?)),
+                _ if arg.is(None, "baseline") => options.baseline = Some(PathBuf::from(args.value()?)),
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        if options.iterations == 0 {
+            return Err(UsageError::new("`--iterations` must be at least 1"));
+        }
+        if options.paths.is_empty() {
+            options.paths.push(PathBuf::from("bench"));
+        }
+        Ok(options)
+    }
+
+    /// Whether the benchmark named `name` is run.
+    fn selects(&self, name: &str) -> bool {
+        self.filter.as_ref().is_none_or(|filter| name.contains(filter.as_str()))
+    }
+}
+
+/// The value of `flag`, a count.
+fn count(args: &mut Args, flag: &str) -> Result<usize, UsageError> {
+    let value = args.value()?;
+    value.parse().map_err(|_| UsageError::new(format!("invalid count `{value}` for `{flag}`")))
+}
+
+/// What the timed calls of a benchmark took, in nanoseconds, and allocated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Measurement {
+    /// The mean time of a call
+    mean: f64,
+    /// The median time of a call
+    median: f64,
+    /// The sample standard deviation of the times
+    stddev: f64,
+    /// The mean number of heap allocations a call made
+    allocations: f64,
+}
+
+impl Measurement {
+    /// Summarize the times and allocation counts of at least one call.
+    fn new(times: &[f64], allocations: &[u64]) -> Self {
+        let n = times.len() as f64;
+        let mean = times.iter().sum::<f64>() / n;
+        let mut sorted = times.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            f64::midpoint(sorted[middle - 1], sorted[middle])
+        } else {
+            sorted[middle]
+        };
+        let variance = if times.len() > 1 {
+            times.iter().map(|time| (time - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else {
+            0.0
+        };
+        let allocations = allocations.iter().sum::<u64>() as f64 / n;
+        Self { mean, median, stddev: variance.sqrt(), allocations }
+    }
+
+    /// How this compares with `before`: the change in the mean and whether
+    /// it counts.
+    fn compare(&self, before: &Self) -> String {
+        let change = (self.mean - before.mean) / before.mean * 100.0;
+        let noise = 2.0 * self.stddev.max(before.stddev);
+        let verdict = if (self.mean - before.mean).abs() <= noise || change.abs() < THRESHOLD {
+            "no change"
+        } else if change > 0.0 {
+            "regressed"
+        } else {
+            "improved"
+        };
+        format!("{change:+.1}%, {verdict}")
+    }
+}
+
+/// Results saved by `--save-baseline`, by benchmark name.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Baseline {
+    /// Each benchmark's results, in the order they ran
+    results: Vec<(String, Measurement)>,
+}
+
+impl Baseline {
+    /// The first line of a baseline file.
+    const HEADER: &str = "# ox bench baseline: name, mean, median and standard deviation in nanoseconds, allocations";
+
+    /// The results of the benchmark named `name`.
+    fn get(&self, name: &str) -> Option<&Measurement> {
+        self.results.iter().find(|(saved, _)| saved == name).map(|(_, measurement)| measurement)
+    }
+
+    /// The baseline as a file: after the header, one line per benchmark of
+    /// tab-separated fields.
+    fn render(&self) -> String {
+        let mut text = format!("{}\n", Self::HEADER);
+        for (name, m) in &self.results {
+            let _ = writeln!(text, "{name}\t{}\t{}\t{}\t{}", m.mean, m.median, m.stddev, m.allocations);
+        }
+        text
+    }
+
+    /// Read a baseline from its file's text.
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut results = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let numbers: Vec<f64> = fields[1..].iter().filter_map(|field| field.parse().ok()).collect();
+            let [mean, median, stddev, allocations] = numbers[..] else {
+                return Err((index + 1, "expected a name and four numbers, separated by tabs".to_string()));
+            };
+            results.push((fields[0].to_string(), Measurement { mean, median, stddev, allocations }));
+        }
+        Ok(Self { results })
+    }
+
+    /// Read the baseline file at `path`.
+    fn load(path: &Path) -> Result<Self, CliError> {
+        let text = std::fs::read_to_string(path).map_err(|source| CliError::Io { path: path.to_path_buf(), source })?;
+        Self::parse(&text).map_err(|(line, message)| CliError::Malformed { path: path.to_path_buf(), line, message })
+    }
+}
+
+/// How the benchmarks went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Summary {
+    /// Benchmarks that ran to the end
+    measured: usize,
+    /// Benchmarks that failed
+    failed: usize,
+    /// Benchmarks the filter left out
+    filtered: usize,
+    /// Files that did not compile, whose benchmarks did not run
+    broken: usize,
+}
+
+/// Run `ox bench`, returning failure if a benchmark failed or a file did
+/// not compile.
+///
+/// # Errors
+///
+/// Returns an error if a path does not exist, a directory cannot be read,
+/// or a baseline cannot be read or written.
+pub fn execute(options: &BenchOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    run(options, global, &mut std::io::stdout().lock())
+}
+
+/// Run the benchmarks, writing their results to `out`.
+fn run(options: &BenchOptions, global: &GlobalOptions, out: &mut impl Write) -> Result<u8, CliError> {
+    let before = options.baseline.as_deref().map(Baseline::load).transpose()?;
+    let mut summary = Summary::default();
+    let mut results = Baseline::default();
+    for file in pipeline::expand(&options.paths)? {
+        let mut bench = Bench { options, global, before: before.as_ref(), summary: &mut summary, out };
+        if let Err(err) = bench.file(&file, &mut results) {
+            global.log(Level::Error, err);
+            summary.broken += 1;
+        }
+    }
+
+    if let Some(path) = &options.save_baseline {
+        std::fs::write(path, results.render()).map_err(|source| CliError::Io { path: path.clone(), source })?;
+        global.log(Level::Info, format_args!("saved the baseline to {}", path.display()));
+    }
+    let result = if summary.failed == 0 && summary.broken == 0 { "ok" } else { "FAILED" };
+    let mut line = format!(
+        "bench result: {result}. {} measured; {} failed; {} filtered out",
+        summary.measured, summary.failed, summary.filtered
+    );
+    match summary.broken {
+        0 => {}
+        1 => line.push_str("; 1 file did not compile"),
+        broken => line.push_str(&format!("; {broken} files did not compile")),
+    }
+    print(out, &line);
+    Ok(if result == "ok" { EXIT_SUCCESS } else { EXIT_FAILURE })
+}
+
+/// What running the benchmarks of a file needs.
+struct Bench<'a, W> {
+    options: &'a BenchOptions,
+    global: &'a GlobalOptions,
+    /// The baseline to compare with
+    before: Option<&'a Baseline>,
+    summary: &'a mut Summary,
+    out: &'a mut W,
+}
+
+impl<W: Write> Bench<'_, W> {
+    /// Run the benchmarks `file` declares that the filter selects, adding
+    /// their results to `results`.
+    fn file(&mut self, file: &Path, results: &mut Baseline) -> Result<(), CliError> {
+        let global = self.global;
+        let sources = pipeline::load(file, global)?;
+        let parsed = pipeline::parse(&sources, global)?;
+        let all = benches(&parsed);
+        let selected: Vec<&Benchmark> = all.iter().filter(|bench| self.options.selects(&bench.name)).collect();
+        self.summary.filtered += all.len() - selected.len();
+        // Files without benchmarks are only imported by others
+        if selected.is_empty() {
+            return Ok(());
+        }
+
+        let mut ctx = Context::with_session(parsed.session());
+        builtins::declare(&mut ctx);
+        reflection::declare(&mut ctx);
+        assertions::declare(&mut ctx);
+        pipeline::check(&parsed, &mut ctx, global)?;
+        let lowered = pipeline::lower_program(&parsed, &mut ctx, global)?;
+        let module = pipeline::build_ir(&parsed, &mut ctx, &lowered, global)?;
+        let script = Rc::new(compile(&module).map_err(|err| parsed.fail(err.to_string(), None))?);
+
+        let source = parsed.root();
+        let count = match selected.len() {
+            1 => "1 benchmark".to_string(),
+            count => format!("{count} benchmarks"),
+        };
+        print(self.out, &format!("running {count} from {}", source.name()));
+        for bench in selected {
+            let measured = if bench.takes_params {
+                Err(format!("benchmark `{}` cannot take parameters", bench.name))
+            } else {
+                self.measure(&script, &module, &bench.name)
+            };
+            match measured {
+                Ok(measurement) => {
+                    let mut line = format!("bench {} ... {}", bench.name, describe(&measurement));
+                    match self.before.map(|before| before.get(&bench.name)) {
+                        Some(Some(before)) => line.push_str(&format!(" [{}]", measurement.compare(before))),
+                        Some(None) => line.push_str(" [new]"),
+                        None => {}
+                    }
+                    print(self.out, &line);
+                    results.results.push((bench.name.clone(), measurement));
+                    self.summary.measured += 1;
+                }
+                Err(message) => {
+                    print(self.out, &format!("bench {} ... FAILED", bench.name));
+                    parsed.report(&[pipeline::error(message, bench.span)]);
+                    self.summary.failed += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Warm up the benchmark named `name`, then time its calls, in a VM of
+    /// its own.
+    #[cfg_attr(not(feature = "cranelift"), allow(unused_variables))]
+    fn measure(&self, script: &Rc<Function>, module: &ir::Module, name: &str) -> Result<Measurement, String> {
+        let mut vm = Vm::new();
+        builtins::install(&mut vm);
+        reflection::install(&mut vm);
+        assertions::install(&mut vm);
+        if self.options.jit {
+            let jit = Jit::new(Profile::new());
+            // Hot numeric functions are optimized from the program's IR
+            #[cfg(feature = "cranelift")]
+            let jit = jit.with_module(module.clone());
+            jit.install(&mut vm);
+        }
+        vm.run(Rc::clone(script)).map_err(|err| err.to_string())?;
+        let Some(function) = vm.global(name) else {
+            return Err(format!("benchmark `{name}` was not compiled"));
+        };
+        for _ in 0..self.options.warmup {
+            vm.call(function.clone(), Vec::new()).map_err(|err| err.to_string())?;
+        }
+
+        let mut times = Vec::with_capacity(self.options.iterations);
+        let mut allocations = Vec::with_capacity(self.options.iterations);
+        for _ in 0..self.options.iterations {
+            let allocated = heap::allocations();
+            let start = Instant::now();
+            let result = vm.call(function.clone(), Vec::new());
+            let elapsed = start.elapsed();
+            allocations.push(heap::allocations() - allocated);
+            result.map_err(|err| err.to_string())?;
+            times.push(elapsed.as_secs_f64() * 1e9);
+        }
+        Ok(Measurement::new(&times, &allocations))
+    }
+}
+
+/// A benchmark function.
+struct Benchmark {
+    /// Its name
+    name: String,
+    /// Whether it wrongly takes parameters
+    takes_params: bool,
+    /// Where it is declared
+    span: Span,
+}
+
+/// The benchmarks the root file declares, in order.
+fn benches(parsed: &Parsed<'_>) -> Vec<Benchmark> {
+    let interner = parsed.interner();
+    parsed
+        .marked("bench")
+        .into_iter()
+        .filter_map(|decl| match decl {
+            Decl::Fn { name, params, span, .. } => {
+                let name = interner.resolve(*name).unwrap_or_default().to_string();
+                Some(Benchmark { name, takes_params: !params.is_empty(), span: *span })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// A measurement as `ox bench` prints it.
+fn describe(measurement: &Measurement) -> String {
+    let allocations = match measurement.allocations.round() {
+        1.0 => "1 allocation".to_string(),
+        allocations => format!("{allocations} allocations"),
+    };
+    format!(
+        "{} ± {} (median {}, {allocations})",
+        duration(measurement.mean),
+        duration(measurement.stddev),
+        duration(measurement.median)
+    )
+}
+
+/// `nanos` nanoseconds, in the largest unit that keeps it at least one.
+fn duration(nanos: f64) -> String {
+    match nanos {
+        _ if nanos < 1e3 => format!("{nanos:.1} ns"),
+        _ if nanos < 1e6 => format!("{:.2} µs", nanos / 1e3),
+        _ if nanos < 1e9 => format!("{:.2} ms", nanos / 1e6),
+        _ => format!("{:.2} s", nanos / 1e9),
+    }
+}
+
+fn print(out: &mut impl Write, text: &str) {
+    // A closed pipe, as in `ox bench | head`, is not an error
+    let _ = writeln!(out, "{text}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<BenchOptions, UsageError> {
+        let mut global = GlobalOptions::default();
+        BenchOptions::parse(&mut Args::new(args.iter().map(ToString::to_string)), &mut global)
+    }
+
+    #[test]
+    fn test_bench_options() {
+        let defaults = options(&[]).unwrap();
+        assert_eq!((defaults.paths, defaults.iterations, defaults.warmup), (vec![PathBuf::from("bench")], 10, 3));
+        let set = options(&["a.ox", "-n", "50", "--warmup=0", "--jit", "--baseline", "base.tsv"]).unwrap();
+        assert_eq!((set.iterations, set.warmup, set.jit), (50, 0, true));
+        assert_eq!(set.baseline, Some(PathBuf::from("base.tsv")));
+        assert_eq!(options(&["-n", "many"]).unwrap_err().to_string(), "invalid count `many` for `--iterations`");
+        assert_eq!(options(&["-n", "0"]).unwrap_err().to_string(), "`--iterations` must be at least 1");
+    }
+
+    #[test]
+    fn test_measurement_statistics() {
+        let measurement = Measurement::new(&[4.0, 1.0, 3.0, 2.0], &[2, 2, 3, 3]);
+        assert_eq!((measurement.mean, measurement.median, measurement.allocations), (2.5, 2.5, 2.5));
+        assert!((measurement.stddev - 1.290_994).abs() < 1e-6);
+        assert_eq!(Measurement::new(&[5.0], &[0]).stddev, 0.0);
+        assert_eq!(describe(&Measurement::new(&[1500.0], &[1])), "1.50 µs ± 0.0 ns (median 1.50 µs, 1 allocation)");
+
+        let steady = Measurement { mean: 100.0, median: 100.0, stddev: 1.0, allocations: 0.0 };
+        let slower = Measurement { mean: 120.0, ..steady };
+        let noisy = Measurement { stddev: 15.0, ..slower };
+        let faster = Measurement { mean: 90.0, ..steady };
+        assert_eq!(slower.compare(&steady), "+20.0%, regressed");
+        assert_eq!(noisy.compare(&steady), "+20.0%, no change");
+        assert_eq!(faster.compare(&steady), "-10.0%, improved");
+        assert_eq!(Measurement { mean: 101.5, stddev: 0.1, ..steady }.compare(&steady), "+1.5%, no change");
+
+        let baseline = Baseline { results: vec![("fib".to_string(), noisy), ("sum".to_string(), faster)] };
+        assert_eq!(Baseline::parse(&baseline.render()), Ok(baseline));
+        let err = Baseline::parse("# header\nfib\t1\t2\n").unwrap_err();
+        assert_eq!(err, (2, "expected a name and four numbers, separated by tabs".to_string()));
+    }
+
+    #[test]
+    fn test_bench_measures_and_compares_with_a_baseline() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let benches = "fn sum(n: Int) -> Int {\
+             mut total = 0; mut i = 0; while (i < n) { total = total + i; i = i + 1; }; total }\n\
+            @bench fn bench_sum() -> Int { sum(1000) }\n\
+            @bench fn bench_fails() -> Int { 1 / 0 }\n\
+            @bench fn bench_params(n: Int) -> Int { n }";
+        std::fs::write(dir.join("sum.ox"), benches).unwrap();
+
+        let outcome = |options: &BenchOptions| {
+            let mut out = Vec::new();
+            let code = run(options, &global, &mut out);
+            code.map(|code| (code, String::from_utf8(out).unwrap()))
+        };
+        let baseline = dir.join("baseline.tsv");
+        let saving = BenchOptions {
+            paths: vec![dir.clone()],
+            iterations: 3,
+            warmup: 1,
+            save_baseline: Some(baseline.clone()),
+            ..BenchOptions::default()
+        };
+        let (code, out) = outcome(&saving).unwrap();
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(out.starts_with("running 3 benchmarks from "), "{out}");
+        assert!(out.contains("bench bench_sum ... ") && out.contains(" allocations)\n"), "{out}");
+        assert!(out.contains("bench bench_fails ... FAILED\nbench bench_params ... FAILED\n"), "{out}");
+        assert!(out.ends_with("bench result: FAILED. 1 measured; 2 failed; 0 filtered out\n"), "{out}");
+        let saved = Baseline::load(&baseline).unwrap();
+        assert_eq!(saved.results.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["bench_sum"]);
+
+        let comparing = BenchOptions {
+            filter: Some("sum".to_string()),
+            jit: true,
+            save_baseline: None,
+            baseline: Some(baseline.clone()),
+            ..saving
+        };
+        let (code, out) = outcome(&comparing).unwrap();
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(out.contains("%, "), "{out}");
+        assert!(out.ends_with("bench result: ok. 1 measured; 0 failed; 2 filtered out\n"), "{out}");
+
+        // Benchmarks and the tests beside them call the builtins tests can
+        let checked = "@test fn test_positive() { assert_eq(1 + 1, 2); }\n\
+            @bench fn bench_checked() -> Int { expect(clock() > 0.0, \"time passes\"); assert_eq(2, 2); 2 }";
+        std::fs::write(dir.join("checked.ox"), checked).unwrap();
+        let checking = BenchOptions { filter: Some("checked".to_string()), baseline: None, ..comparing.clone() };
+        let (code, out) = outcome(&checking).unwrap();
+        assert!(out.contains("bench bench_checked ... "), "{out}");
+        assert!(out.ends_with("bench result: ok. 1 measured; 0 failed; 3 filtered out\n"), "{out}");
+        assert_eq!(code, EXIT_SUCCESS);
+        std::fs::remove_file(dir.join("checked.ox")).unwrap();
+
+        std::fs::write(&baseline, "bench_sum\tfast\n").unwrap();
+        let err = outcome(&comparing).unwrap_err();
+        let expected = format!("{}:1: expected a name and four numbers, separated by tabs", baseline.display());
+        assert_eq!(err.to_string(), expected);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
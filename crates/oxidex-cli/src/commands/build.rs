@@ -0,0 +1,392 @@
+//! `ox build`: compile source files and projects to bytecode.
+//!
+//! Each file is compiled together with the files it imports, transitively,
+//! into one self-contained `.oxb` file: a script that defines every
+//! function of the program as a global. The bytecode is written beside the
+//! source unless `-o` says where. A directory stands for the project whose
+//! `ox.toml` is in it: its main file is compiled, with imports found in the
+//! project's source roots and dependencies, into `target/<profile>/` under
+//! the project. With `--emit`, the chosen forms of the program are printed
+//! instead, for debugging the compiler.
+//!
+//! The profile, `dev` unless `--release` or `--profile` choose another,
+//! decides whether the IR is optimized before it is compiled. Projects may
+//! define profiles of their own; files have `dev` and `release`.
+//!
+//! With `--watch`, each file is built again whenever one of the files it
+//! was built from changes, until `ox` is interrupted.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::pipeline::{self, Cache, Parsed};
+use crate::project::{MANIFEST, Profile, Project};
+use crate::watch::Session;
+use oxidex_bytecode::{Function, builtins, compile, disassemble, oxb};
+use oxidex_codegen::optimize::{DevirtConfig, InlineConfig, devirtualize, gvn, inline, sink_allocations};
+use oxidex_interpreter::Resolver;
+use oxidex_syntax::pretty::PrettyPrinter;
+use oxidex_typecheck::infer::Context;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Help for `ox build`.
+pub const HELP: &str = "\
+Compile OxideX source files and projects to bytecode
+
+Usage: ox build [options] <files...>
+
+Arguments:
+  <files...>  The source files to compile, each with the files it imports, or
+              directories of projects' ox.toml to compile their main files
+
+Options:
+  -o, --output <path>     Where to write the bytecode: a file for one source,
+                          a directory for several
+      --release           Build with the `release` profile, which optimizes
+      --profile <name>    Build with the named profile; `dev` by default
+      --emit <form>       Print the program as `ast`, `ir` or `bytecode`
+                          instead of writing it; may be repeated
+      --watch             Build each file again whenever one of the files it
+                          is built from changes
+
+Projects' bytecode goes in target/<profile>/ under the project by default.";
+
+/// A form of the program `--emit` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The parsed declarations, printed as source
+    Ast,
+    /// The SSA intermediate representation
+    Ir,
+    /// The disassembled bytecode
+    Bytecode,
+}
+
+impl Emit {
+    fn parse(form: &str) -> Result<Self, UsageError> {
+        match form {
+            "ast" => Ok(Self::Ast),
+            "ir" => Ok(Self::Ir),
+            "bytecode" => Ok(Self::Bytecode),
+            _ => Err(UsageError::new(format!("unknown form `{form}` for `--emit`; expected ast, ir or bytecode"))),
+        }
+    }
+}
+
+/// Options of `ox build`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildOptions {
+    /// The source files
+    pub files: Vec<PathBuf>,
+    /// Where to write the bytecode, if not beside the sources
+    pub output: Option<PathBuf>,
+    /// Forms of the program to print instead of writing bytecode
+    pub emit: Vec<Emit>,
+    /// The profile to build with, if not the default
+    pub profile: Option<String>,
+    /// Whether to build again when files change
+    pub watch: bool,
+}
+
+impl BuildOptions {
+    /// Read the options from the arguments after `build`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no files or a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) => options.files.push(PathBuf::from(path)),
+                _ if arg.is(Some('o'), "output") => options.output = Some(PathBuf::from(args.value()?)),
+                _ if arg.is(None, "release") => options.profile = Some("release".to_string()),
+                _ if arg.is(None, "profile") => options.profile = Some(args.value()?),
+                _ if arg.is(None, "watch") => options.watch = true,
+                _ if arg.is(None, "emit") => {
+                    let emit = Emit::parse(&args.value()?)?;
+                    if !options.emit.contains(&emit) {
+                        options.emit.push(emit);
+                    }
+                }
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        if options.files.is_empty() && !global.help {
+            return Err(UsageError::new("missing the source files to compile"));
+        }
+        Ok(options)
+    }
+
+    /// The name of the profile to build with.
+    fn profile(&self) -> &str {
+        self.profile.as_deref().unwrap_or(Profile::DEFAULT)
+    }
+
+    /// Where the bytecode of `file` goes, or of `project`'s main file if
+    /// `file` is its directory.
+    fn artifact(&self, file: &Path, project: Option<&Project>) -> PathBuf {
+        let name = match project {
+            Some(project) => Path::new(&project.manifest.name).with_extension(oxb::EXTENSION),
+            None => Path::new(file.file_name().unwrap_or_default()).with_extension(oxb::EXTENSION),
+        };
+        match (&self.output, project) {
+            (Some(output), _) if self.files.len() == 1 => output.clone(),
+            (Some(output), _) => output.join(name),
+            (None, Some(project)) => project.artifact(self.profile()),
+            (None, None) => file.with_extension(oxb::EXTENSION),
+        }
+    }
+}
+
+/// What to compile for one of the files `ox build` was given.
+struct Target {
+    /// The file whose imports are followed
+    file: PathBuf,
+    /// The project the file is the main file of, if it was given as one
+    project: Option<Project>,
+    /// Finds the files imports name
+    resolver: Resolver,
+    /// The profile to build with
+    profile: Profile,
+}
+
+impl Target {
+    /// The target for `path`, built with the profile named `profile`,
+    /// adding the manifests it reads to `read`.
+    fn new(path: &Path, profile: &str, read: &mut Vec<PathBuf>) -> Result<Self, CliError> {
+        let (file, resolver, project, profiles) = if path.is_dir() {
+            read.push(path.join(MANIFEST));
+            let project = Project::load(path)?;
+            read.extend(project.manifests().into_iter().skip(1));
+            let profiles = project.manifest.profiles.clone();
+            (project.main(), project.resolver(), Some(project), profiles)
+        } else {
+            (path.to_path_buf(), Resolver::new(), None, Profile::standard())
+        };
+        let Some(profile) = profiles.iter().find(|known| known.name == profile).cloned() else {
+            let known = profiles.into_iter().map(|known| known.name).collect();
+            return Err(CliError::UnknownProfile { name: profile.to_string(), known });
+        };
+        Ok(Self { file, project, resolver, profile })
+    }
+}
+
+/// Run `ox build`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written, or does not
+/// compile; the files after it are not built.
+pub fn execute(options: &BuildOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    if let Some(output) = &options.output
+        && options.files.len() > 1
+        && options.emit.is_empty()
+    {
+        std::fs::create_dir_all(output).map_err(|source| CliError::Io { path: output.clone(), source })?;
+    }
+    if options.watch {
+        let session = Session::new(options.files.iter().map(|file| (file.clone(), file.display().to_string())));
+        let mut build = |file: &PathBuf, cache: &mut Cache, read: &mut Vec<PathBuf>| {
+            build_file(file, options, cache, read, global)
+        };
+        return session.run(&mut build, global);
+    }
+    for file in &options.files {
+        build_file(file, options, &mut Cache::new(), &mut Vec::new(), global)?;
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+/// Build `file`, a source file or a project's directory, with the builds
+/// before it in `cache`, and write its bytecode unless `--emit` printed
+/// forms instead. Adds the files it reads to `read`.
+fn build_file(
+    file: &Path,
+    options: &BuildOptions,
+    cache: &mut Cache,
+    read: &mut Vec<PathBuf>,
+    global: &GlobalOptions,
+) -> Result<u8, CliError> {
+    let target = Target::new(file, options.profile(), read)?;
+    let script = build(&target, options, cache, read, global)?;
+    if let Some(script) = script {
+        let artifact = options.artifact(file, target.project.as_ref());
+        if let Some(dir) = artifact.parent()
+            && target.project.is_some()
+        {
+            std::fs::create_dir_all(dir).map_err(|source| CliError::Io { path: dir.to_path_buf(), source })?;
+        }
+        oxb::save_file(&artifact, &script).map_err(|source| CliError::Io { path: artifact.clone(), source })?;
+        global.log(Level::Info, format_args!("wrote {}", artifact.display()));
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+/// Compile the target's file and its imports, printing the forms `--emit`
+/// asks for. Returns the script to write, unless forms were printed instead.
+fn build(
+    target: &Target,
+    options: &BuildOptions,
+    cache: &mut Cache,
+    read: &mut Vec<PathBuf>,
+    global: &GlobalOptions,
+) -> Result<Option<Function>, CliError> {
+    read.push(target.file.clone());
+    let sources = pipeline::load_with(&target.file, &target.resolver, global)?;
+    read.extend(sources.iter().map(|source| source.path.clone()).filter(|source| *source != target.file));
+    let parsed = pipeline::parse_cached(&sources, cache, global)?;
+    if options.emit.contains(&Emit::Ast) {
+        print(&ast(&parsed));
+    }
+
+    let mut ctx = Context::with_session(parsed.session());
+    builtins::declare(&mut ctx);
+    pipeline::check_cached(&parsed, &mut ctx, cache, global)?;
+    let lowered = pipeline::lower_program(&parsed, &mut ctx, global)?;
+    let mut module = pipeline::build_ir(&parsed, &mut ctx, &lowered, global)?;
+    if target.profile.optimize {
+        global.log(Level::Debug, format_args!("optimizing {} for `{}`", parsed.root().name(), target.profile.name));
+        devirtualize(&mut module, &lowered, &DevirtConfig::new());
+        inline(&mut module, &InlineConfig::default());
+        sink_allocations(&mut module);
+        gvn(&mut module);
+    }
+    if options.emit.contains(&Emit::Ir) {
+        print(&module.to_string());
+    }
+
+    global.log(Level::Debug, format_args!("compiling {}", parsed.root().name()));
+    let script = compile(&module).map_err(|err| parsed.fail(err.to_string(), None))?;
+    if options.emit.contains(&Emit::Bytecode) {
+        print(&disassemble(&script.chunk, &script.name));
+    }
+    Ok(options.emit.is_empty().then_some(script))
+}
+
+/// The declarations of every file of a program, printed as source.
+fn ast(parsed: &Parsed<'_>) -> String {
+    let mut printer = PrettyPrinter::new(parsed.clone_interner());
+    let decls: Vec<String> = parsed.decls.iter().map(|decl| printer.print_decl(decl)).collect();
+    decls.join("\n\n")
+}
+
+fn print(text: &str) {
+    // A closed pipe, as in `ox build --emit ir | head`, is not an error
+    let _ = writeln!(std::io::stdout(), "{}", text.trim_end());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_bytecode::{Value, Vm, VmErrorKind};
+    use std::rc::Rc;
+
+    fn options(args: &[&str]) -> Result<BuildOptions, UsageError> {
+        let mut global = GlobalOptions::default();
+        BuildOptions::parse(&mut Args::new(args.iter().map(ToString::to_string)), &mut global)
+    }
+
+    #[test]
+    fn test_build_options() {
+        let built = options(&["a.ox", "--emit=ir", "--emit", "ast", "--emit=ir"]).unwrap();
+        assert_eq!(built.emit, [Emit::Ir, Emit::Ast]);
+        assert_eq!(built.artifact(Path::new("src/a.ox"), None), PathBuf::from("src/a.oxb"));
+        let err = options(&["a.ox", "--emit=llvm"]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown form `llvm` for `--emit`; expected ast, ir or bytecode");
+
+        let single = options(&["src/a.ox", "-o", "out.oxb"]).unwrap();
+        assert_eq!(single.artifact(Path::new("src/a.ox"), None), PathBuf::from("out.oxb"));
+        let several = options(&["src/a.ox", "b.ox", "-o", "out"]).unwrap();
+        assert_eq!(several.artifact(Path::new("src/a.ox"), None), PathBuf::from("out/a.oxb"));
+
+        assert_eq!(options(&["a.ox"]).unwrap().profile(), "dev");
+        assert_eq!(options(&["a.ox", "--release"]).unwrap().profile(), "release");
+        assert_eq!(options(&["--release", "--profile=bench", "a.ox"]).unwrap().profile(), "bench");
+        assert!(options(&["a.ox", "--watch"]).unwrap().watch && !options(&["a.ox"]).unwrap().watch);
+    }
+
+    #[test]
+    fn test_build_writes_runnable_bytecode_with_imports() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-build-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("math.ox"), "pub fn square(x: Int) -> Int { x * x }").unwrap();
+        let main = "import \"./math\";\n\
+            fn main(n: Int) -> Int {\
+             mut total = 0; mut i = 1; while (i <= n) { total = total + square(i); i = i + 1; }; total }";
+        std::fs::write(dir.join("main.ox"), main).unwrap();
+
+        let options = BuildOptions { files: vec![dir.join("main.ox")], ..BuildOptions::default() };
+        assert_eq!(execute(&options, &global).unwrap(), EXIT_SUCCESS);
+        let script = oxb::load_file(dir.join("main.oxb")).unwrap();
+        let mut vm = Vm::new();
+        vm.run(Rc::clone(&script)).unwrap();
+        let main = vm.global("main").unwrap();
+        assert_eq!(vm.call(main, vec![Value::Int(3)]).unwrap(), Value::Int(14));
+
+        std::fs::write(dir.join("pair.ox"), "fn pair(x: Int) -> (Int, Int) { (x, x) }").unwrap();
+        let options = BuildOptions { files: vec![dir.join("pair.ox")], ..BuildOptions::default() };
+        assert!(matches!(execute(&options, &global), Err(CliError::Compile { errors: 1, .. })));
+        assert!(!dir.join("pair.oxb").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_calls_standard_builtins() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-build-builtins-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = "fn main(n: Int) -> Int { assert(n > 0); print(\"checked\"); n * 2 }";
+        std::fs::write(dir.join("main.ox"), main).unwrap();
+
+        let options = BuildOptions { files: vec![dir.join("main.ox")], ..BuildOptions::default() };
+        assert_eq!(execute(&options, &global).unwrap(), EXIT_SUCCESS);
+        let script = oxb::load_file(dir.join("main.oxb")).unwrap();
+        let mut vm = Vm::new();
+        builtins::install(&mut vm);
+        vm.run(Rc::clone(&script)).unwrap();
+        let main = vm.global("main").unwrap();
+        assert_eq!(vm.call(main.clone(), vec![Value::Int(4)]).unwrap(), Value::Int(8));
+        let err = vm.call(main, vec![Value::Int(0)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::AssertionFailed));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_project_with_profiles() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-build-project-{}", std::process::id()));
+        for (path, text) in [
+            ("app/ox.toml", "[package]\nname = \"app\"\n[dependencies]\nmath = { path = \"../math\" }"),
+            ("app/src/main.ox", "import \"math\";\nfn main(n: Int) -> Int { square(n) + square(n + 1) }"),
+            ("math/ox.toml", "[package]\nname = \"math\"\n[profile.fast]\noptimize = true"),
+            ("math/src/lib.ox", "pub fn square(x: Int) -> Int { x * x }"),
+        ] {
+            std::fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(path), text).unwrap();
+        }
+
+        let app = dir.join("app");
+        for profile in ["dev", "release"] {
+            let profile_name = Some(profile.to_string());
+            let options = BuildOptions { files: vec![app.clone()], profile: profile_name, ..BuildOptions::default() };
+            assert_eq!(execute(&options, &global).unwrap(), EXIT_SUCCESS);
+            let script = oxb::load_file(app.join("target").join(profile).join("app.oxb")).unwrap();
+            let mut vm = Vm::new();
+            vm.run(Rc::clone(&script)).unwrap();
+            let main = vm.global("main").unwrap();
+            assert_eq!(vm.call(main, vec![Value::Int(3)]).unwrap(), Value::Int(25));
+        }
+
+        // Profiles are the project's own: `fast` is its dependency's
+        let fast = BuildOptions { files: vec![app], profile: Some("fast".to_string()), ..BuildOptions::default() };
+        let err = execute(&fast, &global).unwrap_err();
+        assert_eq!(err.to_string(), "unknown profile `fast`; expected one of dev, release");
+        std::fs::write(dir.join("math/src/main.ox"), "fn main() -> Int { 0 }").unwrap();
+        let fast = BuildOptions { files: vec![dir.join("math")], ..fast };
+        assert_eq!(execute(&fast, &global).unwrap(), EXIT_SUCCESS);
+        assert!(dir.join("math/target/fast/math.oxb").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! `ox compile`: compile a program ahead of time to a native object.
+
+use super::required;
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, GlobalOptions};
+use std::path::PathBuf;
+
+/// Help for `ox compile`.
+pub const HELP: &str = "\
+Compile an OxideX program ahead of time to a native object file
+
+Usage: ox compile [options] <file>
+
+Arguments:
+  <file>  The source file of the program
+
+Options:
+  -o, --output <path>  Where to write the object file";
+
+/// Options of `ox compile`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// The source file
+    pub file: PathBuf,
+    /// Where to write the object file, if not beside the source
+    pub output: Option<PathBuf>,
+}
+
+impl CompileOptions {
+    /// Read the options from the arguments after `compile`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is missing, there is more than one, or a
+    /// flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let (mut file, mut output) = (None, None);
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) if file.is_none() => file = Some(PathBuf::from(path)),
+                _ if arg.is(Some('o'), "output") => output = Some(PathBuf::from(args.value()?)),
+                Arg::Value(_) => return Err(arg.unexpected()),
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        let file = required(file, "the source file to compile", global)?;
+        Ok(Self { file, output })
+    }
+}
+
+/// Run `ox compile`.
+///
+/// # Errors
+///
+/// Returns an error until native compilation is wired up.
+pub fn execute(_options: &CompileOptions, _global: &GlobalOptions) -> Result<u8, CliError> {
+    Err(CliError::Unimplemented("compile"))
+}
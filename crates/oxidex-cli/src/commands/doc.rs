@@ -0,0 +1,57 @@
+//! `ox doc`: generate documentation from source files.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, GlobalOptions};
+use std::path::PathBuf;
+
+/// Help for `ox doc`.
+pub const HELP: &str = "\
+Generate documentation from the doc comments of OxideX source files
+
+Usage: ox doc [options] <files...>
+
+Arguments:
+  <files...>  The source files to document
+
+Options:
+  -o, --output <dir>  Where to write the documentation";
+
+/// Options of `ox doc`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocOptions {
+    /// The source files
+    pub files: Vec<PathBuf>,
+    /// Where to write the documentation, if not the default directory
+    pub output: Option<PathBuf>,
+}
+
+impl DocOptions {
+    /// Read the options from the arguments after `doc`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no files or a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) => options.files.push(PathBuf::from(path)),
+                _ if arg.is(Some('o'), "output") => options.output = Some(PathBuf::from(args.value()?)),
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        if options.files.is_empty() && !global.help {
+            return Err(UsageError::new("missing the source files to document"));
+        }
+        Ok(options)
+    }
+}
+
+/// Run `ox doc`.
+///
+/// # Errors
+///
+/// Returns an error until documentation generation is wired up.
+pub fn execute(_options: &DocOptions, _global: &GlobalOptions) -> Result<u8, CliError> {
+    Err(CliError::Unimplemented("doc"))
+}
@@ -0,0 +1,299 @@
+//! `ox fmt`: format source files.
+//!
+//! Files are formatted in place with the formatter of the syntax crate,
+//! which keeps every comment; directories stand for the `.ox` files under
+//! them. With no files, or `-`, the source is read from standard input and
+//! the formatted source written to standard output. With `--check`, nothing
+//! is written; each file that is not formatted is shown as a diff against
+//! its formatted self instead, and the command fails.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::pipeline::{self, Source};
+use oxidex_syntax::Spanned;
+use oxidex_syntax::format::format_source;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Help for `ox fmt`.
+pub const HELP: &str = "\
+Format OxideX source files in place
+
+Usage: ox fmt [options] [files...]
+
+Arguments:
+  [files...]  The source files, or directories of them, to format; standard
+              input if none or `-`
+
+Options:
+      --check  Change nothing; show what formatting would change, and fail if
+               any file is not formatted";
+
+/// Lines of context around each change in a diff.
+const CONTEXT: usize = 3;
+
+/// Options of `ox fmt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FmtOptions {
+    /// The source files, standard input if empty
+    pub files: Vec<PathBuf>,
+    /// Whether to only check the formatting
+    pub check: bool,
+}
+
+impl FmtOptions {
+    /// Read the options from the arguments after `fmt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) => options.files.push(PathBuf::from(path)),
+                _ if arg.is(None, "check") => options.check = true,
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Run `ox fmt`, failing if a file does not lex or, with `--check`, is not
+/// formatted.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written.
+pub fn execute(options: &FmtOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    run(options, global, std::io::stdin().lock(), &mut std::io::stdout().lock())
+}
+
+/// Run `ox fmt` with `input` as standard input and `out` as standard
+/// output.
+fn run(
+    options: &FmtOptions,
+    global: &GlobalOptions,
+    mut input: impl Read,
+    out: &mut impl Write,
+) -> Result<u8, CliError> {
+    let stdin = Path::new("-");
+    let mut files = Vec::new();
+    for path in &options.files {
+        if path == stdin {
+            files.push(path.clone());
+        } else {
+            files.extend(pipeline::expand(std::slice::from_ref(path))?);
+        }
+    }
+    if files.is_empty() {
+        files.push(stdin.to_path_buf());
+    }
+
+    let mut status = EXIT_SUCCESS;
+    for file in files {
+        let source = if file == stdin {
+            let mut text = String::new();
+            let path = PathBuf::from("<stdin>");
+            input.read_to_string(&mut text).map_err(|source| CliError::Io { path: path.clone(), source })?;
+            Source { path, text }
+        } else {
+            Source::read(&file)?
+        };
+        let formatted = match format_source(&source.text) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                source.report(&[pipeline::error(err.to_string(), err.span())], global);
+                global.log(Level::Error, format_args!("could not format `{}`", source.name()));
+                status = EXIT_FAILURE;
+                continue;
+            }
+        };
+
+        if options.check {
+            if formatted != source.text {
+                let _ = write!(out, "{}", diff(&source.name(), &source.text, &formatted));
+                status = EXIT_FAILURE;
+            }
+        } else if file == stdin {
+            let _ = write!(out, "{formatted}");
+        } else if formatted != source.text {
+            std::fs::write(&file, formatted).map_err(|source| CliError::Io { path: file.clone(), source })?;
+            global.log(Level::Info, format_args!("formatted {}", source.name()));
+        }
+    }
+    Ok(status)
+}
+
+/// A line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    /// In both texts
+    Same(&'a str),
+    /// Only in the old text
+    Removed(&'a str),
+    /// Only in the new text
+    Added(&'a str),
+}
+
+/// The unified diff from `old` to `new`, the formatted text of the file
+/// `name`, with [`CONTEXT`] lines around each change.
+fn diff(name: &str, old: &str, new: &str) -> String {
+    let lines = diff_lines(&old.lines().collect::<Vec<_>>(), &new.lines().collect::<Vec<_>>());
+    let mut text = format!("--- {name}\n+++ {name} (formatted)\n");
+
+    // Where each line is in the old and new texts, counting from one
+    let mut at = Vec::with_capacity(lines.len());
+    let (mut old_line, mut new_line) = (1, 1);
+    for line in &lines {
+        at.push((old_line, new_line));
+        match line {
+            Line::Same(_) => (old_line, new_line) = (old_line + 1, new_line + 1),
+            Line::Removed(_) => old_line += 1,
+            Line::Added(_) => new_line += 1,
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len()).filter(|&i| !matches!(lines[i], Line::Same(_))).collect();
+    let mut next = 0;
+    while next < changed.len() {
+        // A hunk runs until the context of one change no longer reaches the next
+        let start = changed[next].saturating_sub(CONTEXT);
+        let mut last = changed[next];
+        next += 1;
+        while next < changed.len() && changed[next] <= last + 2 * CONTEXT + 1 {
+            last = changed[next];
+            next += 1;
+        }
+        let end = (last + CONTEXT + 1).min(lines.len());
+
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let new_len = hunk.iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+        let (old_start, new_start) = at[start];
+        text.push_str(&format!("@@ -{old_start},{old_len} +{new_start},{new_len} @@\n"));
+        for line in hunk {
+            let (sign, line) = match line {
+                Line::Same(line) => (' ', line),
+                Line::Removed(line) => ('-', line),
+                Line::Added(line) => ('+', line),
+            };
+            text.push_str(&format!("{sign}{line}\n"));
+        }
+    }
+    text
+}
+
+/// The lines of a diff from `old` to `new`, keeping a longest common
+/// subsequence of lines. Very long changed regions, past the size the table
+/// of common lengths is kept to, are all removed and then all added.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    const MAX_TABLE: usize = 1 << 22;
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix =
+        old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut lines: Vec<Line<'a>> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    if a.len().saturating_mul(b.len()) > MAX_TABLE {
+        lines.extend(a.iter().map(|line| Line::Removed(line)));
+        lines.extend(b.iter().map(|line| Line::Added(line)));
+    } else {
+        // common[i][j] is the length of a longest common subsequence of
+        // a[i..] and b[j..]
+        let width = b.len() + 1;
+        let mut common = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i * width + j] = if a[i] == b[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push(Line::Same(a[i]));
+                (i, j) = (i + 1, j + 1);
+            } else if j == b.len() || (i < a.len() && common[(i + 1) * width + j] >= common[i * width + j + 1]) {
+                lines.push(Line::Removed(a[i]));
+                i += 1;
+            } else {
+                lines.push(Line::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| Line::Same(line)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(options: &FmtOptions, input: &str) -> (Result<u8, CliError>, String) {
+        let mut out = Vec::new();
+        let result = run(options, &GlobalOptions::default(), input.as_bytes(), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_fmt_standard_input() {
+        let (result, out) = fmt(&FmtOptions::default(), "fn main( ) {\n// hi\nprint( 1 )\n}");
+        assert_eq!(result.unwrap(), EXIT_SUCCESS);
+        assert_eq!(out, "fn main() {\n    // hi\n    print(1)\n}\n");
+
+        let check = FmtOptions { files: vec![PathBuf::from("-")], check: true };
+        let (result, out) = fmt(&check, "let x = 1\n");
+        assert_eq!((result.unwrap(), out.as_str()), (EXIT_SUCCESS, ""));
+        let (result, out) = fmt(&check, "let x=1\n");
+        assert_eq!(result.unwrap(), EXIT_FAILURE);
+        assert_eq!(out, "--- <stdin>\n+++ <stdin> (formatted)\n@@ -1,1 +1,1 @@\n-let x=1\n+let x = 1\n");
+        assert_eq!(fmt(&FmtOptions::default(), "let x = 1 ^ 2").0.unwrap(), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn test_fmt_files_in_place_and_checked() {
+        let dir = std::env::temp_dir().join(format!("ox-fmt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.ox");
+        let lines: Vec<String> = (0..12).map(|i| format!("let x{i} = {i}")).collect();
+        let mut source = lines.join("\n") + "\n";
+        source = source.replace("x1 = 1", "x1=1").replace("x10 = 10", "x10=10");
+        std::fs::write(&file, &source).unwrap();
+
+        let check = FmtOptions { files: vec![dir.clone()], check: true };
+        let (result, out) = fmt(&check, "");
+        assert_eq!(result.unwrap(), EXIT_FAILURE);
+        assert!(out.contains("@@ -1,5 +1,5 @@\n let x0 = 0\n-let x1=1\n+let x1 = 1\n let x2 = 2\n"), "{out}");
+        assert!(out.contains("@@ -8,5 +8,5 @@\n let x7 = 7\n"), "{out}");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), source);
+
+        let in_place = FmtOptions { files: vec![file.clone()], check: false };
+        assert_eq!(fmt(&in_place, "").0.unwrap(), EXIT_SUCCESS);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), lines.join("\n") + "\n");
+        let (result, out) = fmt(&check, "");
+        assert_eq!((result.unwrap(), out.as_str()), (EXIT_SUCCESS, ""));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = diff_lines(&["a", "b", "c", "d"], &["a", "x", "c", "e", "d"]);
+        let expected = [
+            Line::Same("a"),
+            Line::Removed("b"),
+            Line::Added("x"),
+            Line::Same("c"),
+            Line::Added("e"),
+            Line::Same("d"),
+        ];
+        assert_eq!(lines, expected);
+        assert_eq!(diff("f", "a\n", "b\n"), "--- f\n+++ f (formatted)\n@@ -1,1 +1,1 @@\n-a\n+b\n");
+    }
+}
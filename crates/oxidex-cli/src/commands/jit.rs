@@ -0,0 +1,238 @@
+//! `ox jit`: run a source file with JIT compilation.
+//!
+//! The file is compiled to bytecode with the files it imports, as `ox
+//! bench` compiles it, and its `main` function runs in the VM with the JIT
+//! installed: warm functions are compiled by the baseline tier, and with
+//! the `cranelift` feature, hot numeric functions are optimized from the
+//! program's IR. The exit status is what `main` returns, as with `ox run`.
+//! With `--stats`, what the JIT did is printed to stderr once the program
+//! ends: each function's tier, deoptimizations and tier transitions.
+
+use super::required;
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::pipeline;
+use oxidex_bytecode::{Value, Vm, builtins, compile};
+use oxidex_jit::{Jit, JitStats, Profile};
+use oxidex_std::runtime::builtins as reflection;
+use oxidex_syntax::Decl;
+use oxidex_typecheck::infer::Context;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Help for `ox jit`.
+pub const HELP: &str = "\
+Run an OxideX source file, compiling hot functions to machine code
+
+Usage: ox jit [options] <file> [args...]
+
+Arguments:
+  <file>     The source file to run
+  [args...]  Arguments passed on to the program, flags included
+
+Options:
+      --stats  Report what was compiled, at which tier and how long it took
+
+The file's `main` function is run under the bytecode VM, so it cannot take the
+program's arguments. The exit status is what `main` returns, if it returns an
+`Int`.";
+
+/// Options of `ox jit`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JitOptions {
+    /// The source file
+    pub file: PathBuf,
+    /// Arguments for the program
+    pub args: Vec<String>,
+    /// Whether to report compilation statistics
+    pub stats: bool,
+}
+
+impl JitOptions {
+    /// Read the options from the arguments after `jit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is missing or a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let (mut file, mut stats) = (None, false);
+        while let Some(arg) = args.next()? {
+            match arg {
+                // Everything after the file belongs to the program
+                Arg::Value(path) => {
+                    file = Some(PathBuf::from(path));
+                    break;
+                }
+                _ if arg.is(None, "stats") => stats = true,
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        let file = required(file, "the source file to run", global)?;
+        Ok(Self { file, args: args.rest(), stats })
+    }
+}
+
+/// Run `ox jit`, returning the program's exit status.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, the program does not
+/// compile to bytecode or it has no `main` function it can run.
+pub fn execute(options: &JitOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    let (status, stats) = run(options, global)?;
+    if options.stats {
+        eprint!("{stats}");
+    }
+    Ok(status)
+}
+
+/// Run the program, returning its exit status and what the JIT did.
+fn run(options: &JitOptions, global: &GlobalOptions) -> Result<(u8, JitStats), CliError> {
+    let sources = pipeline::load(&options.file, global)?;
+    let parsed = pipeline::parse(&sources, global)?;
+    let source = parsed.root();
+    let Some(Decl::Fn { params, span, .. }) = parsed.function("main") else {
+        return Err(CliError::NoMain { file: source.name() });
+    };
+    if !params.is_empty() {
+        let message = "`main` cannot take the program's arguments under the VM".to_string();
+        return Err(parsed.fail(message, Some(*span)));
+    }
+
+    let mut ctx = Context::with_session(parsed.session());
+    builtins::declare(&mut ctx);
+    reflection::declare(&mut ctx);
+    pipeline::check(&parsed, &mut ctx, global)?;
+    let lowered = pipeline::lower_program(&parsed, &mut ctx, global)?;
+    let module = pipeline::build_ir(&parsed, &mut ctx, &lowered, global)?;
+    let script = Rc::new(compile(&module).map_err(|err| parsed.fail(err.to_string(), None))?);
+
+    global.log(Level::Info, format_args!("running {} with the JIT", source.name()));
+    let mut vm = Vm::new();
+    builtins::install(&mut vm);
+    reflection::install(&mut vm);
+    let jit = Jit::new(Profile::new());
+    // Hot numeric functions are optimized from the program's IR
+    #[cfg(feature = "cranelift")]
+    let jit = jit.with_module(module);
+    jit.install(&mut vm);
+    let result = vm.run(script).and_then(|_| match vm.global("main") {
+        Some(main) => vm.call(main, Vec::new()),
+        None => Ok(Value::Nil),
+    });
+    // Only the functions still alive are reported
+    let stats = jit.stats();
+    let status = match result {
+        Ok(value) => exit_status(&value),
+        Err(err) => {
+            parsed.report(&[pipeline::error(err.to_string(), err.span().unwrap_or(*span))]);
+            EXIT_FAILURE
+        }
+    };
+    Ok((status, stats))
+}
+
+/// The exit status for what `main` returned.
+fn exit_status(value: &Value) -> u8 {
+    match value {
+        Value::Int(code) => u8::try_from(code.rem_euclid(256)).unwrap_or(EXIT_FAILURE),
+        _ => EXIT_SUCCESS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_jit::Tier;
+    use std::path::Path;
+
+    /// Write `text` to a fresh source file named after `name`.
+    fn script(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ox-jit-{}-{name}.ox", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn run_file(file: &Path) -> Result<(u8, JitStats), CliError> {
+        let options = JitOptions { file: file.to_path_buf(), stats: true, ..JitOptions::default() };
+        run(&options, &GlobalOptions::default())
+    }
+
+    #[test]
+    fn test_jit_runs_main_and_reports_tiers() {
+        let fib = script(
+            "fib",
+            "fn fib(n: Int) -> Int { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }\n\
+             fn main() -> Int { fib(25) % 256 }",
+        );
+        let (status, stats) = run_file(&fib).unwrap();
+        assert_eq!(status, (75_025 % 256) as u8);
+        let fib_stats = stats.functions.iter().find(|function| function.name == "fib").unwrap();
+        assert!(fib_stats.calls > 10_000);
+        let expected = if cfg!(feature = "cranelift") && oxidex_jit::Arch::HOST.is_some() {
+            vec![Tier::Interpreted, Tier::Baseline, Tier::Optimized]
+        } else if oxidex_jit::Arch::HOST.is_some() {
+            vec![Tier::Interpreted, Tier::Baseline]
+        } else {
+            vec![Tier::Interpreted]
+        };
+        assert_eq!(fib_stats.tiers, expected);
+        assert!(stats.to_string().contains("transitions:"));
+
+        let failing = script("failing", "fn main() -> Int { 1 / 0 }");
+        assert_eq!(run_file(&failing).unwrap().0, EXIT_FAILURE);
+        let takes_args = script("takes-args", "fn main(args: [String]) -> Int { len(args) }");
+        assert!(matches!(run_file(&takes_args), Err(CliError::Compile { errors: 1, .. })));
+        let no_main = script("no-main", "fn helper() -> Int { 1 }");
+        assert!(matches!(run_file(&no_main), Err(CliError::NoMain { .. })));
+        for path in [fib, failing, takes_args, no_main] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_jit_runs_structs_enums_and_collections() {
+        let shapes = script(
+            "shapes",
+            "struct Tally { total: Int }\n\
+             impl Tally { mut fn bump(by: Int) { total = total + by; } }\n\
+             enum Shape { case circle(Int), case empty }\n\
+             fn area(s: Shape) -> Int { match s { Shape::circle(r) => r * r, Shape::empty => 0, } }\n\
+             fn main() -> Int {\n\
+                 mut t = Tally { total: area(Shape::circle(3)) + area(Shape::empty) };\n\
+                 let xs = [1, 2, 3];\n\
+                 for x in xs { t.bump(x); };\n\
+                 let names = [1: \"a\", 2: \"b\"];\n\
+                 t.total + xs[2] + len(names[2])\n\
+             }",
+        );
+        assert_eq!(run_file(&shapes).unwrap().0, 9 + 6 + 3 + 1);
+        std::fs::remove_file(shapes).unwrap();
+    }
+
+    #[test]
+    fn test_jit_interpolates_as_run_does() {
+        // Each backend fails the assertion unless it describes the values
+        // as the other does. The interpreter runs first, as it registers
+        // the program's classes with the runtime the VM then finds them in
+        let describing = script(
+            "describing",
+            "struct Spot { x: Int, y: Int }\n\
+             impl Spot { fn description() -> String { \"(\\(x), \\(y))\" } }\n\
+             struct Plain { a: Int, s: String }\n\
+             enum Figure { case circle(Int), case empty }\n\
+             fn main() -> Int {\n\
+                 let p = Spot { x: 1, y: 2 };\n\
+                 let n = 4;\n\
+                 let text = \"p=\\(p) n=\\(n + 1) b=\\(Plain { s: \"q\", a: 3 }) \\([p]) \\(Figure::circle(n))\";\n\
+                 assert(text == \"p=(1, 2) n=5 b=Plain(a: 3, s: \\\"q\\\") [(1, 2)] Figure::circle(4)\");\n\
+                 assert(\"\\(1.5 * 2.0) \\([0.5])\" == \"3.0 [0.5]\");\n\
+                 0\n\
+             }",
+        );
+        let options = crate::commands::run::RunOptions { file: describing.clone(), ..Default::default() };
+        assert_eq!(crate::commands::run::execute(&options, &GlobalOptions::default()).unwrap(), EXIT_SUCCESS);
+        assert_eq!(run_file(&describing).unwrap().0, EXIT_SUCCESS);
+        std::fs::remove_file(describing).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! `ox lint`: check source files for likely mistakes.
+//!
+//! Each file is parsed on its own, without the files it imports, and the
+//! rules of the [`Linter`] run over its declarations. Their findings are
+//! warnings, except for the rules denied on the command line, whose
+//! findings are errors that fail the command; allowed rules do not run.
+//! With `--warnings deny`, every finding is an error.
+//! Directories stand for the `.ox` files under them.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::lint::{LintLevel, Linter};
+use crate::pipeline;
+use std::path::{Path, PathBuf};
+
+/// Help for `ox lint`.
+pub const HELP: &str = "\
+Check OxideX source files for likely mistakes
+
+Usage: ox lint [options] <files...>
+
+Arguments:
+  <files...>  The source files, or directories of them, to check
+
+Options:
+  -A, --allow <rule>  Do not run the rule; may be repeated
+  -W, --warn <rule>   Report what the rule finds as warnings; may be repeated
+  -D, --deny <rule>   Report what the rule finds as errors, failing the
+                      command; may be repeated
+
+A rule may be `all`, for every rule; later flags override earlier ones.
+
+Rules:
+  unused-variable    Local variables that are never read
+  shadowing          Local variables that hide another variable of the same
+                     name
+  unreachable-code   Code after a `return` or `throw` that can never run
+  naming-convention  Names that are not snake_case, UpperCamelCase or
+                     SCREAMING_SNAKE_CASE as their kind calls for";
+
+/// Options of `ox lint`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintOptions {
+    /// The source files and directories
+    pub files: Vec<PathBuf>,
+    /// The levels set for rules, in order
+    pub levels: Vec<(String, LintLevel)>,
+}
+
+impl LintOptions {
+    /// Read the options from the arguments after `lint`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no files, a flag is unknown or a rule
+    /// does not exist.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let linter = Linter::standard();
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            let level = match arg {
+                Arg::Value(path) => {
+                    options.files.push(PathBuf::from(path));
+                    continue;
+                }
+                _ if arg.is(Some('A'), "allow") => LintLevel::Allow,
+                _ if arg.is(Some('W'), "warn") => LintLevel::Warn,
+                _ if arg.is(Some('D'), "deny") => LintLevel::Deny,
+                _ => {
+                    global.accept(&arg, args)?;
+                    continue;
+                }
+            };
+            let rule = args.value()?;
+            if !linter.knows(&rule) {
+                return Err(UsageError::new(format!("unknown lint rule `{rule}`")));
+            }
+            options.levels.push((rule, level));
+        }
+        if options.files.is_empty() && !global.help {
+            return Err(UsageError::new("missing the source files to check"));
+        }
+        Ok(options)
+    }
+
+    /// The standard linter, with the levels set.
+    fn linter(&self) -> Linter {
+        let mut linter = Linter::standard();
+        for (rule, level) in &self.levels {
+            linter.set_level(rule, *level);
+        }
+        linter
+    }
+}
+
+/// Run `ox lint`, failing if a file does not parse or a denied rule found
+/// something.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read.
+pub fn execute(options: &LintOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    let linter = options.linter();
+    let mut status = EXIT_SUCCESS;
+    for file in pipeline::expand(&options.files)? {
+        let denied = match lint_file(&file, &linter, global) {
+            Ok(denied) => denied,
+            Err(CliError::Compile { file, .. }) => {
+                global.log(Level::Error, format_args!("could not lint `{file}`"));
+                true
+            }
+            Err(err) => return Err(err),
+        };
+        if denied {
+            status = EXIT_FAILURE;
+        }
+    }
+    Ok(status)
+}
+
+/// Lint `file`, reporting what the rules find. Returns whether a denied
+/// rule found anything.
+fn lint_file(file: &Path, linter: &Linter, global: &GlobalOptions) -> Result<bool, CliError> {
+    let source = pipeline::Source::read(file)?;
+    let parsed = pipeline::parse(std::slice::from_ref(&source), global)?;
+    global.log(Level::Debug, format_args!("linting {}", source.name()));
+    let diagnostics = linter.lint(parsed.root_decls(), parsed.interner(), &source.text);
+    Ok(parsed.report(&diagnostics) > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<LintOptions, UsageError> {
+        let mut global = GlobalOptions::default();
+        LintOptions::parse(&mut Args::new(args.iter().map(ToString::to_string)), &mut global)
+    }
+
+    #[test]
+    fn test_lint_options() {
+        let linted = options(&["a.ox", "-D", "all", "--allow=shadowing", "-W", "unused-variable"]).unwrap();
+        assert_eq!(linted.files, [PathBuf::from("a.ox")]);
+        let levels = [("all", LintLevel::Deny), ("shadowing", LintLevel::Allow), ("unused-variable", LintLevel::Warn)];
+        assert_eq!(linted.levels, levels.map(|(rule, level)| (rule.to_string(), level)));
+
+        let err = options(&["a.ox", "--deny", "unused"]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown lint rule `unused`");
+        assert_eq!(options(&["-D", "all"]).unwrap_err().to_string(), "missing the source files to check");
+    }
+
+    #[test]
+    fn test_lint_fails_only_on_denied_findings() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.ox"), "fn main() {\n    let unused = 1;\n}").unwrap();
+        std::fs::write(dir.join("clean.ox"), "fn square(x: Int) -> Int { x * x }").unwrap();
+
+        let warned = LintOptions { files: vec![dir.clone()], levels: Vec::new() };
+        assert_eq!(execute(&warned, &global).unwrap(), EXIT_SUCCESS);
+        let levels = vec![("unused-variable".to_string(), LintLevel::Deny)];
+        let denied = LintOptions { files: vec![dir.clone()], levels };
+        assert_eq!(execute(&denied, &global).unwrap(), EXIT_FAILURE);
+        let clean = LintOptions { files: vec![dir.join("clean.ox")], ..denied.clone() };
+        assert_eq!(execute(&clean, &global).unwrap(), EXIT_SUCCESS);
+
+        std::fs::write(dir.join("broken.ox"), "fn broken( {").unwrap();
+        assert_eq!(execute(&warned, &global).unwrap(), EXIT_FAILURE);
+        let missing = LintOptions { files: vec![dir.join("gone.ox")], ..warned };
+        assert!(matches!(execute(&missing, &global), Err(CliError::Io { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
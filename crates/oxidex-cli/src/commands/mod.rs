@@ -1,11 +1,166 @@
 //! CLI command implementations
 //!
-//! **Coming in Phase 12**
+//! Each command module has a `HELP` text, an options struct read from the
+//! arguments after the command's name, and an `execute` function that runs
+//! the command and returns its exit code. [`COMMANDS`] lists them for
+//! dispatch and for `ox help`.
 
+// Interpret a source file
 pub mod run;
+
+// Compile to bytecode
 pub mod build;
+
+// Compile ahead of time to a native object
 pub mod compile;
+
+// Run with JIT compilation
 pub mod jit;
+
+// Run the tests of source files
+pub mod test;
+
+// Run the benchmarks of source files
+pub mod bench;
+
+// Format source code
 pub mod fmt;
+
+// Lint source code
 pub mod lint;
+
+// Generate documentation
 pub mod doc;
+
+// Read, evaluate and print interactively
+pub mod repl;
+
+use crate::args::{Args, UsageError};
+use crate::cli::{CliError, GlobalOptions};
+
+/// A command, for dispatch and help.
+#[derive(Debug)]
+pub struct CommandInfo {
+    /// The name it is invoked by
+    pub name: &'static str,
+    /// One line on what it does
+    pub summary: &'static str,
+    /// Its help text, without the global options
+    pub help: &'static str,
+}
+
+/// Every command, in the order `ox help` lists them.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo { name: "run", summary: "Interpret a source file", help: run::HELP },
+    CommandInfo { name: "build", summary: "Compile to bytecode", help: build::HELP },
+    CommandInfo { name: "compile", summary: "Compile ahead of time to a native object", help: compile::HELP },
+    CommandInfo { name: "jit", summary: "Run with JIT compilation", help: jit::HELP },
+    CommandInfo { name: "test", summary: "Run the tests of source files", help: test::HELP },
+    CommandInfo { name: "bench", summary: "Run the benchmarks of source files", help: bench::HELP },
+    CommandInfo { name: "fmt", summary: "Format source code", help: fmt::HELP },
+    CommandInfo { name: "lint", summary: "Lint source code", help: lint::HELP },
+    CommandInfo { name: "doc", summary: "Generate documentation", help: doc::HELP },
+    CommandInfo { name: "repl", summary: "Start an interactive session", help: repl::HELP },
+];
+
+/// The command named `name`.
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|info| info.name == name)
+}
+
+/// A command with its options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `ox run`
+    Run(run::RunOptions),
+    /// `ox build`
+    Build(build::BuildOptions),
+    /// `ox compile`
+    Compile(compile::CompileOptions),
+    /// `ox jit`
+    Jit(jit::JitOptions),
+    /// `ox test`
+    Test(test::TestOptions),
+    /// `ox bench`
+    Bench(bench::BenchOptions),
+    /// `ox fmt`
+    Fmt(fmt::FmtOptions),
+    /// `ox lint`
+    Lint(lint::LintOptions),
+    /// `ox doc`
+    Doc(doc::DocOptions),
+    /// `ox repl`
+    Repl(repl::ReplOptions),
+}
+
+impl Command {
+    /// Read the options of the command named `name` from `args`, taking
+    /// global flags among them into `global`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such command or its arguments are
+    /// malformed.
+    pub fn parse(name: &str, args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        Ok(match name {
+            "run" => Self::Run(run::RunOptions::parse(args, global)?),
+            "build" => Self::Build(build::BuildOptions::parse(args, global)?),
+            "compile" => Self::Compile(compile::CompileOptions::parse(args, global)?),
+            "jit" => Self::Jit(jit::JitOptions::parse(args, global)?),
+            "test" => Self::Test(test::TestOptions::parse(args, global)?),
+            "bench" => Self::Bench(bench::BenchOptions::parse(args, global)?),
+            "fmt" => Self::Fmt(fmt::FmtOptions::parse(args, global)?),
+            "lint" => Self::Lint(lint::LintOptions::parse(args, global)?),
+            "doc" => Self::Doc(doc::DocOptions::parse(args, global)?),
+            "repl" => Self::Repl(repl::ReplOptions::parse(args, global)?),
+            _ => return Err(UsageError::new(format!("unknown command `{name}`"))),
+        })
+    }
+
+    /// The name the command is invoked by.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Run(_) => "run",
+            Self::Build(_) => "build",
+            Self::Compile(_) => "compile",
+            Self::Jit(_) => "jit",
+            Self::Test(_) => "test",
+            Self::Bench(_) => "bench",
+            Self::Fmt(_) => "fmt",
+            Self::Lint(_) => "lint",
+            Self::Doc(_) => "doc",
+            Self::Repl(_) => "repl",
+        }
+    }
+
+    /// Run the command, returning its exit code.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails in a way it does not report
+    /// itself.
+    pub fn execute(&self, global: &GlobalOptions) -> Result<u8, CliError> {
+        match self {
+            Self::Run(options) => run::execute(options, global),
+            Self::Build(options) => build::execute(options, global),
+            Self::Compile(options) => compile::execute(options, global),
+            Self::Jit(options) => jit::execute(options, global),
+            Self::Test(options) => test::execute(options, global),
+            Self::Bench(options) => bench::execute(options, global),
+            Self::Fmt(options) => fmt::execute(options, global),
+            Self::Lint(options) => lint::execute(options, global),
+            Self::Doc(options) => doc::execute(options, global),
+            Self::Repl(options) => repl::execute(options, global),
+        }
+    }
+}
+
+/// `value`, or an error that `what` is missing. Nothing is required when
+/// help was asked for, so `Default::default()` stands in.
+fn required<T: Default>(value: Option<T>, what: &str, global: &GlobalOptions) -> Result<T, UsageError> {
+    match value {
+        Some(value) => Ok(value),
+        None if global.help => Ok(T::default()),
+        None => Err(UsageError::new(format!("missing {what}"))),
+    }
+}
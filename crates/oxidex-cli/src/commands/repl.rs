@@ -0,0 +1,37 @@
+//! `ox repl`: read, evaluate and print interactively.
+
+use crate::args::{Args, UsageError};
+use crate::cli::{CliError, GlobalOptions};
+
+/// Help for `ox repl`.
+pub const HELP: &str = "\
+Start an interactive OxideX session
+
+Usage: ox repl [options]";
+
+/// Options of `ox repl`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplOptions {}
+
+impl ReplOptions {
+    /// Read the options from the arguments after `repl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is any argument but a global flag.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        while let Some(arg) = args.next()? {
+            global.accept(&arg, args)?;
+        }
+        Ok(Self {})
+    }
+}
+
+/// Run `ox repl`.
+///
+/// # Errors
+///
+/// Returns an error until the interactive session is wired up.
+pub fn execute(_options: &ReplOptions, _global: &GlobalOptions) -> Result<u8, CliError> {
+    Err(CliError::Unimplemented("repl"))
+}
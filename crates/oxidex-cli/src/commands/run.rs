@@ -0,0 +1,307 @@
+//! `ox run`: interpret a source file or project.
+//!
+//! A directory stands for the project whose `ox.toml` is in it, and its
+//! main file is run, with imports found in the project's source roots and
+//! dependencies. The file is parsed, type checked and lowered, then its `main` function
+//! runs under the interpreter. If `main` takes a parameter, it receives the
+//! program's arguments as an array of strings. The program's exit status is
+//! what `main` returns, if it returns an `Int`, truncated to its low eight
+//! bits as on Unix; otherwise it is zero, or one if the program failed with
+//! a runtime error. Besides the standard builtins, programs have the
+//! reflection and memory builtins of [`oxidex_std::runtime::builtins`].
+//!
+//! With `--watch`, the program runs again whenever one of its files
+//! changes, until `ox` is interrupted. With `--memory-stats`, the `OxideC`
+//! runtime's memory report is printed to stderr after each run. With
+//! `--heap-dump <file>`, sending `ox` `SIGUSR1` while the program runs
+//! writes a census of its live objects to the file as JSON.
+
+use super::required;
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::pipeline::{self, Cache};
+use crate::project::{MANIFEST, Project};
+use crate::watch::Session;
+use oxidex_interpreter::{Builtins, Resolver, Value};
+use oxidex_std::runtime::builtins as reflection;
+use oxidex_syntax::Decl;
+use oxidex_typecheck::infer::Context;
+use std::path::{Path, PathBuf};
+
+/// Help for `ox run`.
+pub const HELP: &str = "\
+Interpret an OxideX source file or project
+
+Usage: ox run [options] <file> [args...]
+
+Arguments:
+  <file>     The source file to run, or the directory of a project's ox.toml
+             to run its main file
+  [args...]  Arguments passed on to the program, flags included
+
+Options:
+      --watch         Run the program again whenever one of its files changes
+      --memory-stats  Print the memory the runtime holds after the program ends
+      --heap-dump <file>
+                      Write the live objects by class to <file> as JSON whenever
+                      ox receives SIGUSR1
+
+The file's `main` function is run, with the arguments if it takes an array of
+strings. The exit status is what `main` returns, if it returns an `Int`.";
+
+/// Options of `ox run`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// The source file, or a project's directory
+    pub file: PathBuf,
+    /// Arguments for the program
+    pub args: Vec<String>,
+    /// Whether to run again when the program's files change
+    pub watch: bool,
+    /// Whether to print the runtime's memory report after each run
+    pub memory_stats: bool,
+    /// File to write a heap snapshot to on `SIGUSR1`
+    pub heap_dump: Option<PathBuf>,
+}
+
+impl RunOptions {
+    /// Read the options from the arguments after `run`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is missing or a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut file = None;
+        let mut watch = false;
+        let mut memory_stats = false;
+        let mut heap_dump = None;
+        while let Some(arg) = args.next()? {
+            match arg {
+                // Everything after the file belongs to the program
+                Arg::Value(path) => {
+                    file = Some(PathBuf::from(path));
+                    break;
+                }
+                _ if arg.is(None, "watch") => watch = true,
+                _ if arg.is(None, "memory-stats") => memory_stats = true,
+                _ if arg.is(None, "heap-dump") => heap_dump = Some(PathBuf::from(args.value()?)),
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        let file = required(file, "the source file to run", global)?;
+        Ok(Self { file, args: args.rest(), watch, memory_stats, heap_dump })
+    }
+}
+
+/// Run `ox run`, returning the program's exit status.
+///
+/// # Errors
+///
+/// Returns an error if the file or the project's manifests cannot be read,
+/// the program does not compile or it has no `main` function.
+pub fn execute(options: &RunOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    if let Some(path) = &options.heap_dump {
+        dump_heap_on_signal(path, global);
+    }
+    if options.watch {
+        let session = Session::new([(options.file.clone(), options.file.display().to_string())]);
+        let mut run = |path: &PathBuf, cache: &mut Cache, read: &mut Vec<PathBuf>| {
+            run_file(path, options, cache, read, global)
+        };
+        return session.run(&mut run, global);
+    }
+    run_file(&options.file, options, &mut Cache::new(), &mut Vec::new(), global)
+}
+
+/// Run the program at `path`, a file or a project's directory, as
+/// `options` say, with the builds before it in `cache`, adding the files it
+/// reads to `read`.
+fn run_file(
+    path: &Path,
+    options: &RunOptions,
+    cache: &mut Cache,
+    read: &mut Vec<PathBuf>,
+    global: &GlobalOptions,
+) -> Result<u8, CliError> {
+    let (file, resolver) = if path.is_dir() {
+        read.push(path.join(MANIFEST));
+        let project = Project::load(path)?;
+        read.extend(project.manifests().into_iter().skip(1));
+        (project.main(), project.resolver())
+    } else {
+        (path.to_path_buf(), Resolver::new())
+    };
+    read.push(file.clone());
+    let sources = pipeline::load_with(&file, &resolver, global)?;
+    read.extend(sources.iter().map(|source| source.path.clone()).filter(|source| *source != file));
+    let parsed = pipeline::parse_cached(&sources, cache, global)?;
+    let source = parsed.root();
+    let Some(Decl::Fn { params, .. }) = parsed.function("main") else {
+        return Err(CliError::NoMain { file: source.name() });
+    };
+    let args = if params.is_empty() {
+        Vec::new()
+    } else {
+        vec![Value::array(options.args.iter().map(Value::string).collect())]
+    };
+
+    let mut builtins = Builtins::standard();
+    reflection::register(&mut builtins);
+    let mut ctx = Context::with_session(parsed.session());
+    builtins.declare(&mut ctx);
+    pipeline::check_cached(&parsed, &mut ctx, cache, global)?;
+    let lowered = pipeline::lower_program(&parsed, &mut ctx, global)?;
+
+    global.log(Level::Info, format_args!("running {}", source.name()));
+    let mut interp = pipeline::interpreter(&parsed, &ctx, &lowered, builtins);
+    interp.set_resolver(resolver);
+    let result = match interp.load(parsed.root_decls()) {
+        Ok(()) => interp.call("main", args),
+        Err(err) => Err(err),
+    };
+    if options.memory_stats {
+        eprintln!("{}", oxidec::runtime::memory_report());
+    }
+    match result {
+        Ok(value) => Ok(exit_status(&value)),
+        Err(err) => {
+            parsed.report(&[err.diagnostic()]);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+/// Write a heap snapshot to `path` whenever `ox` receives `SIGUSR1`.
+fn dump_heap_on_signal(path: &Path, global: &GlobalOptions) {
+    #[cfg(unix)]
+    match oxidec::runtime::census::dump_on_signal(oxidec::runtime::census::DUMP_SIGNAL, path) {
+        Ok(()) => global.log(Level::Info, format_args!("SIGUSR1 writes a heap snapshot to {}", path.display())),
+        Err(err) => global.log(Level::Error, format_args!("cannot dump the heap on a signal: {err}")),
+    }
+    #[cfg(not(unix))]
+    global.log(Level::Error, format_args!("heap dumps on a signal need Unix; not writing {}", path.display()));
+}
+
+/// The exit status for what `main` returned.
+fn exit_status(value: &Value) -> u8 {
+    match value {
+        Value::Int(code) => u8::try_from(code.rem_euclid(256)).unwrap_or(EXIT_FAILURE),
+        _ => EXIT_SUCCESS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `text` to a fresh source file named after `name`.
+    fn script(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ox-run-{}-{name}.ox", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn run(file: &Path, args: &[&str]) -> Result<u8, CliError> {
+        let args = args.iter().map(ToString::to_string).collect();
+        execute(&RunOptions { file: file.to_path_buf(), args, ..RunOptions::default() }, &GlobalOptions::default())
+    }
+
+    #[test]
+    fn test_run_forwards_arguments_and_exit_status() {
+        let counts = script("counts", "fn main(args: [String]) -> Int { len(args) + 254 }");
+        assert_eq!(run(&counts, &["a", "--b"]).unwrap(), 0);
+        assert_eq!(run(&counts, &[]).unwrap(), 254);
+
+        let unit = script("unit", "fn main() { let x = 1; }");
+        assert_eq!(run(&unit, &["ignored"]).unwrap(), EXIT_SUCCESS);
+        let failing = script("failing", "fn main() -> Int { 1 / 0 }");
+        assert_eq!(run(&failing, &[]).unwrap(), EXIT_FAILURE);
+        assert_eq!(exit_status(&Value::Int(-1)), 255);
+        // The memory report goes to stderr and leaves the exit status alone
+        let options = RunOptions { file: counts.clone(), memory_stats: true, ..RunOptions::default() };
+        assert_eq!(execute(&options, &GlobalOptions::default()).unwrap(), 254);
+        for path in [counts, unit, failing] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_reflects_the_programs_objects() {
+        let reflecting = script(
+            "reflecting",
+            "struct Counter { total: Int, step: Int }\n\
+             impl Counter {\n\
+               fn get() -> Int { total }\n\
+               fn add(by: Int) -> Int { total + by * step }\n\
+             }\n\
+             fn main() -> Int {\n\
+               let c = Counter { total: 40, step: 2 };\n\
+               let named = class_name(c) == \"Counter\" && field_names(c) == [\"total\", \"step\"];\n\
+               let methods = method_names(c) == [\"addBy:\", \"get\"] && responds_to(c, \"remove\") == false;\n\
+               let total: Int = perform(c, \"get\");\n\
+               let sum: Int = perform_with(c, \"addBy:\", 1);\n\
+               if named && methods { total + sum } else { 1 }\n\
+             }",
+        );
+        // The sends run the methods' bodies: 40 + (40 + 1 * 2)
+        assert_eq!(run(&reflecting, &[]).unwrap(), 82);
+        let unknown = script("unknown", "struct Empty {}\nfn main() -> Int { perform(Empty {}, \"missing\") }");
+        assert_eq!(run(&unknown, &[]).unwrap(), EXIT_FAILURE);
+        for path in [reflecting, unknown] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_reads_the_memory_report() {
+        let reading = script(
+            "memory",
+            "struct Probe { id: Int }\n\
+             fn main() -> Int {\n\
+               let probe = Probe { id: 3 };\n\
+               let stats = memory_stats();\n\
+               let read = len(stats) == 8 && memory_stat(\"classes\") > 0;\n\
+               if read && len(heap_snapshot()) > 0 { probe.id } else { 1 }\n\
+             }",
+        );
+        assert_eq!(run(&reading, &[]).unwrap(), 3);
+        let unknown = script("unknown-stat", "fn main() -> Int { memory_stat(\"objects\") }");
+        assert_eq!(run(&unknown, &[]).unwrap(), EXIT_FAILURE);
+        for path in [reading, unknown] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_reports_files_that_cannot_run() {
+        let no_main = script("no-main", "fn helper() -> Int { 1 }");
+        let err = run(&no_main, &[]).unwrap_err();
+        assert_eq!(err.to_string(), format!("`{}` has no `main` function to run", no_main.display()));
+
+        let mistyped = script("mistyped", "fn main() -> Int { missing }");
+        assert!(matches!(run(&mistyped, &[]), Err(CliError::Compile { errors: 1, .. })));
+        let missing = std::env::temp_dir().join("ox-run-missing.ox");
+        assert!(matches!(run(&missing, &[]), Err(CliError::Io { .. })));
+        for path in [no_main, mistyped] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_project_with_path_dependency() {
+        let dir = std::env::temp_dir().join(format!("ox-run-project-{}", std::process::id()));
+        for (path, text) in [
+            ("app/ox.toml", "[package]\nname = \"app\"\n[dependencies]\nmath = { path = \"../math\" }"),
+            ("app/src/main.ox", "import \"math\";\nimport \"util\";\nfn main() -> Int { square(offset()) }"),
+            ("app/src/util.ox", "pub fn offset() -> Int { 7 }"),
+            ("math/ox.toml", "[package]\nname = \"math\""),
+            ("math/src/lib.ox", "pub fn square(x: Int) -> Int { x * x }"),
+        ] {
+            std::fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(path), text).unwrap();
+        }
+        assert_eq!(run(&dir.join("app"), &[]).unwrap(), 49);
+        assert!(matches!(run(&dir.join("math/src"), &[]), Err(CliError::Io { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+//! `ox test`: run the tests of source files.
+//!
+//! A test is a function marked `@test`. The files given are searched for
+//! tests, with every `.ox` file under the directories given; with no paths,
+//! the `test` directory is. Each file is checked and lowered once, with the
+//! files it imports, then each of its tests runs in an interpreter of its
+//! own, so that no test sees what another left behind. A test passes if it
+//! returns, and fails if an `assert` fails, it throws, or it fails at run
+//! time, with a diagnostic pointing at where.
+//!
+//! Tests also have the assertions of [`oxidex_std::testing`]: `assert_eq`
+//! reports a diff of the values, `expect` a message of the test's own, and
+//! `assert_snapshot` compares a value with a snapshot file, kept in
+//! `snapshots/<file stem>/` beside the test file. Missing snapshots are
+//! written, and `--update-snapshots` rewrites those that differ.
+
+use crate::args::{Arg, Args, UsageError};
+use crate::cli::{CliError, EXIT_FAILURE, EXIT_SUCCESS, GlobalOptions, Level};
+use crate::pipeline::{self, Parsed};
+use oxidex_interpreter::{Builtins, Capability, Value};
+use oxidex_std::runtime::builtins as reflection;
+use oxidex_std::testing::builtins::{self as assertions, failure};
+use oxidex_std::testing::{SnapshotError, SnapshotOutcome, Snapshots};
+use oxidex_syntax::{Decl, Span};
+use oxidex_typecheck::infer::Context;
+use oxidex_typecheck::{PrimTy, Ty};
+use std::cell::Cell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Help for `ox test`.
+pub const HELP: &str = "\
+Run the tests of OxideX source files
+
+Usage: ox test [options] [paths...]
+
+Arguments:
+  [paths...]  Source files, or directories to search for them [default: test]
+
+Options:
+  -f, --filter <text>     Only run the tests whose names contain the text
+  -u, --update-snapshots  Rewrite the snapshots that differ from the output
+
+A test is a function marked `@test`. It passes if it returns, and fails if an
+`assert` fails, it throws or it fails at run time.
+
+Tests can also call:
+  assert_eq(actual, expected)  Fail with a diff unless the values are equal
+  expect(condition, message)   Fail with the message unless the condition holds
+  assert_snapshot(name, value) Fail unless the value matches the snapshot
+                               `snapshots/<file stem>/<name>.snap`, writing it
+                               if there is none";
+
+/// Options of `ox test`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestOptions {
+    /// The source files and directories
+    pub paths: Vec<PathBuf>,
+    /// Text the names of the tests to run contain
+    pub filter: Option<String>,
+    /// Rewrite the snapshots that differ from the output
+    pub update_snapshots: bool,
+}
+
+impl TestOptions {
+    /// Read the options from the arguments after `test`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a flag is unknown.
+    pub fn parse(args: &mut Args, global: &mut GlobalOptions) -> Result<Self, UsageError> {
+        let mut options = Self::default();
+        while let Some(arg) = args.next()? {
+            match arg {
+                Arg::Value(path) => options.paths.push(PathBuf::from(path)),
+                _ if arg.is(Some('f'), "filter") => options.filter = Some(args.value()?),
+                _ if arg.is(Some('u'), "update-snapshots") => options.update_snapshots = true,
+                _ => global.accept(&arg, args)?,
+            }
+        }
+        if options.paths.is_empty() {
+            options.paths.push(PathBuf::from("test"));
+        }
+        Ok(options)
+    }
+
+    /// Whether the test named `name` is run.
+    fn selects(&self, name: &str) -> bool {
+        self.filter.as_ref().is_none_or(|filter| name.contains(filter.as_str()))
+    }
+}
+
+/// How the tests went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Summary {
+    /// Tests that passed
+    passed: usize,
+    /// Tests that failed
+    failed: usize,
+    /// Tests the filter left out
+    filtered: usize,
+    /// Files that did not compile, whose tests did not run
+    broken: usize,
+    /// Snapshots written, because they were missing or updated
+    snapshots: usize,
+}
+
+/// Run `ox test`, returning failure if a test failed or a file did not
+/// compile.
+///
+/// # Errors
+///
+/// Returns an error if a path does not exist or a directory cannot be
+/// read.
+pub fn execute(options: &TestOptions, global: &GlobalOptions) -> Result<u8, CliError> {
+    run(options, global, &mut std::io::stdout().lock())
+}
+
+/// Run the tests, writing their results to `out`.
+fn run(options: &TestOptions, global: &GlobalOptions, out: &mut impl Write) -> Result<u8, CliError> {
+    let mut summary = Summary::default();
+    for file in pipeline::expand(&options.paths)? {
+        if let Err(err) = test_file(&file, options, global, &mut summary, out) {
+            global.log(Level::Error, err);
+            summary.broken += 1;
+        }
+    }
+
+    let result = if summary.failed == 0 && summary.broken == 0 { "ok" } else { "FAILED" };
+    let mut line = format!(
+        "test result: {result}. {} passed; {} failed; {} filtered out",
+        summary.passed, summary.failed, summary.filtered
+    );
+    match summary.broken {
+        0 => {}
+        1 => line.push_str("; 1 file did not compile"),
+        broken => line.push_str(&format!("; {broken} files did not compile")),
+    }
+    match summary.snapshots {
+        0 => {}
+        1 => line.push_str("; 1 snapshot written"),
+        snapshots => line.push_str(&format!("; {snapshots} snapshots written")),
+    }
+    print(out, &line);
+    Ok(if result == "ok" { EXIT_SUCCESS } else { EXIT_FAILURE })
+}
+
+/// Run the tests `file` declares that the filter selects.
+fn test_file(
+    file: &Path,
+    options: &TestOptions,
+    global: &GlobalOptions,
+    summary: &mut Summary,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let sources = pipeline::load(file, global)?;
+    let parsed = pipeline::parse(&sources, global)?;
+    let all = tests(&parsed);
+    let selected: Vec<&Test> = all.iter().filter(|test| options.selects(&test.name)).collect();
+    summary.filtered += all.len() - selected.len();
+    // Files without tests are only imported by others, which check them
+    if all.is_empty() {
+        return Ok(());
+    }
+
+    let written = Rc::new(Cell::new(0));
+    let builtins = builtins(file, options, &written);
+    let mut ctx = Context::with_session(parsed.session());
+    builtins.declare(&mut ctx);
+    pipeline::check(&parsed, &mut ctx, global)?;
+    let lowered = pipeline::lower_program(&parsed, &mut ctx, global)?;
+
+    if selected.is_empty() {
+        return Ok(());
+    }
+    let source = parsed.root();
+    let count = if selected.len() == 1 { "1 test".to_string() } else { format!("{} tests", selected.len()) };
+    print(out, &format!("running {count} from {}", source.name()));
+    for test in selected {
+        let diagnostics = if test.takes_params {
+            vec![pipeline::error(format!("test `{}` cannot take parameters", test.name), test.span)]
+        } else {
+            // A fresh interpreter per test, so globals start over
+            let mut interp = pipeline::interpreter(&parsed, &ctx, &lowered, builtins.clone());
+            let result = match interp.load(parsed.root_decls()) {
+                Ok(()) => interp.call(&test.name, Vec::new()),
+                Err(err) => Err(err),
+            };
+            result.err().map(|err| vec![err.diagnostic()]).unwrap_or_default()
+        };
+        if diagnostics.is_empty() {
+            print(out, &format!("test {} ... ok", test.name));
+            summary.passed += 1;
+        } else {
+            print(out, &format!("test {} ... FAILED", test.name));
+            parsed.report(&diagnostics);
+            summary.failed += 1;
+        }
+    }
+    summary.snapshots += written.get();
+    Ok(())
+}
+
+/// The standard, reflection and memory builtins, with the assertions tests
+/// of `file` can call.
+/// `written` counts the snapshots `assert_snapshot` writes.
+// Builtins return the interpreter's errors, which are large
+#[allow(clippy::result_large_err)]
+fn builtins(file: &Path, options: &TestOptions, written: &Rc<Cell<usize>>) -> Builtins {
+    let mut builtins = Builtins::standard();
+    reflection::register(&mut builtins);
+    assertions::register(&mut builtins);
+
+    let stem = file.file_stem().unwrap_or_default();
+    let dir = file.parent().unwrap_or(Path::new("")).join("snapshots").join(stem);
+    let snapshots = Snapshots::new(dir).updating(options.update_snapshots);
+    let written = Rc::clone(written);
+    let snapshot = assertions::unit_signature(vec![Ty::Primitive(PrimTy::String), Ty::TypeVar(0)]);
+    builtins.register_requiring("assert_snapshot", Capability::FileIo, snapshot, move |args, span| {
+        let output = match &args[1] {
+            Value::String(_) => args[1].to_string(),
+            value => value.pretty(),
+        };
+        match snapshots.check(&args[0].to_string(), &output) {
+            Ok(SnapshotOutcome::Matched) => Ok(Value::Unit),
+            Ok(SnapshotOutcome::Created | SnapshotOutcome::Updated) => {
+                written.set(written.get() + 1);
+                Ok(Value::Unit)
+            }
+            Err(SnapshotError::Mismatch { path, mismatch }) => {
+                let message = format!("`assert_snapshot` failed: output differs from {}", path.display());
+                let detail = format!("- snapshot, + output:\n{}", mismatch.diff());
+                Err(failure(message, Some(detail), span))
+            }
+            Err(err) => Err(failure(format!("`assert_snapshot` failed: {err}"), None, span)),
+        }
+    });
+    builtins
+}
+
+/// A test function.
+struct Test {
+    /// Its name
+    name: String,
+    /// Whether it wrongly takes parameters
+    takes_params: bool,
+    /// Where it is declared
+    span: Span,
+}
+
+/// The tests the root file declares, in order.
+fn tests(parsed: &Parsed<'_>) -> Vec<Test> {
+    let interner = parsed.interner();
+    parsed
+        .marked("test")
+        .into_iter()
+        .filter_map(|decl| match decl {
+            Decl::Fn { name, params, span, .. } => {
+                let name = interner.resolve(*name).unwrap_or_default().to_string();
+                Some(Test { name, takes_params: !params.is_empty(), span: *span })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn print(out: &mut impl Write, text: &str) {
+    // A closed pipe, as in `ox test | head`, is not an error
+    let _ = writeln!(out, "{text}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_interpreter::RuntimeError;
+
+    fn options(args: &[&str]) -> TestOptions {
+        let mut global = GlobalOptions::default();
+        TestOptions::parse(&mut Args::new(args.iter().map(ToString::to_string)), &mut global).unwrap()
+    }
+
+    #[test]
+    fn test_test_options() {
+        assert_eq!(options(&[]).paths, [PathBuf::from("test")]);
+        let filtered = options(&["a.ox", "lib", "-f", "parse"]);
+        assert_eq!(filtered.paths, [PathBuf::from("a.ox"), PathBuf::from("lib")]);
+        assert!(filtered.selects("test_parse_ints") && !filtered.selects("test_print"));
+        assert!(options(&["--filter=x"]).selects("x"));
+        assert!(options(&["-u"]).update_snapshots && !options(&[]).update_snapshots);
+    }
+
+    #[test]
+    fn test_tests_run_in_isolation_and_report_failures() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("math.ox"), "pub fn square(x: Int) -> Int { x * x }").unwrap();
+        let tests = "import \"../math\";\n\
+            @test fn test_square() { assert(square(3) == 9); }\n\
+            @test fn test_wrong() { assert(square(2) == 5); }\n\
+            @test fn test_params(x: Int) { }\n\
+            @test fn test_throws() { throw(\"no\"); }\n\
+            fn helper() { assert(false); }";
+        std::fs::write(dir.join("nested/math_test.ox"), tests).unwrap();
+
+        let outcome = |options: &TestOptions| {
+            let mut out = Vec::new();
+            let code = run(options, &global, &mut out);
+            code.map(|code| (code, String::from_utf8(out).unwrap()))
+        };
+        let all = TestOptions { paths: vec![dir.join("nested")], ..TestOptions::default() };
+        let (code, out) = outcome(&all).unwrap();
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(out.contains("test test_square ... ok\ntest test_wrong ... FAILED\ntest test_params ... FAILED\n"));
+        assert!(out.ends_with("test result: FAILED. 1 passed; 3 failed; 0 filtered out\n"));
+
+        let square = TestOptions { filter: Some("square".to_string()), ..all };
+        let (code, out) = outcome(&square).unwrap();
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(out.starts_with("running 1 test from "), "{out}");
+        assert!(out.ends_with("test result: ok. 1 passed; 0 failed; 3 filtered out\n"));
+
+        std::fs::write(dir.join("nested/broken.ox"), "@test fn test_broken() { missing }").unwrap();
+        let (code, out) = outcome(&square).unwrap();
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(out.ends_with("; 1 file did not compile\n"));
+        let missing = TestOptions { paths: vec![dir.join("gone")], ..TestOptions::default() };
+        assert!(matches!(outcome(&missing), Err(CliError::Io { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_assertions_and_snapshots() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-test-snapshots-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("values_test.ox");
+        let write = |value: &str| {
+            let tests = format!(
+                "@test fn test_eq() {{ assert_eq([1, 2], [1, 2]); expect(true, \"unused\"); }}\n\
+                 @test fn test_snapshot() {{ assert_snapshot(\"values\", {value}); }}"
+            );
+            std::fs::write(&file, tests).unwrap();
+        };
+        let outcome = |options: &TestOptions| {
+            let mut out = Vec::new();
+            let code = run(options, &global, &mut out).unwrap();
+            (code, String::from_utf8(out).unwrap())
+        };
+
+        write("[1, 2]");
+        let check = TestOptions { paths: vec![file.clone()], ..TestOptions::default() };
+        let (code, out) = outcome(&check);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(out.ends_with("test result: ok. 2 passed; 0 failed; 0 filtered out; 1 snapshot written\n"), "{out}");
+        let snapshot = dir.join("snapshots/values_test/values.snap");
+        assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "[\n    1,\n    2,\n]\n");
+        assert!(outcome(&check).1.ends_with("test result: ok. 2 passed; 0 failed; 0 filtered out\n"));
+
+        write("[1, 3]");
+        let (code, out) = outcome(&check);
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(out.contains("test test_snapshot ... FAILED"), "{out}");
+        let update = TestOptions { update_snapshots: true, ..check.clone() };
+        assert!(outcome(&update).1.ends_with("1 snapshot written\n"));
+        assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "[\n    1,\n    3,\n]\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_assertion_failures() {
+        let builtins = builtins(Path::new("values_test.ox"), &TestOptions::default(), &Rc::new(Cell::new(0)));
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let fail = |name: &str, args: &[Value]| (builtins.get(name).unwrap().function)(args, span).err();
+
+        let err = fail("assert_eq", &[Value::Int(4), Value::Int(5)]).unwrap();
+        assert_eq!(err.to_string(), "`assert_eq` failed: expected 5, found 4");
+        let (actual, expected) = (Value::array(vec![Value::Int(1)]), Value::array(vec![Value::Int(2)]));
+        let Some(RuntimeError::ExpectationFailed { detail, .. }) = fail("assert_eq", &[actual, expected]) else {
+            panic!("different arrays should not be equal");
+        };
+        assert_eq!(detail.as_deref(), Some("- expected, + found:\n  [\n-     2,\n+     1,\n  ]"));
+
+        let err = fail("expect", &[Value::Bool(false), Value::string("too slow")]).unwrap();
+        assert_eq!(err.to_string(), "too slow");
+        assert!(fail("expect", &[Value::Bool(true), Value::string("unused")]).is_none());
+    }
+}
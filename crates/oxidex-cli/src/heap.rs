@@ -0,0 +1,81 @@
+//! Counting heap allocations, for `ox bench`.
+//!
+//! `ox` allocates through [`Counting`], which passes every request on to the
+//! system allocator and counts the allocations each thread makes. The count
+//! is per thread so that a benchmark sees its own allocations, not those of
+//! tests running beside it.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// Allocations the thread made; constant-initialized, so reading it
+    /// never allocates
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The system allocator, counting allocations.
+pub struct Counting;
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn count() {
+    // A thread being torn down has no count left to add to
+    let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+}
+
+// SAFETY: every request is passed on to the system allocator unchanged
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        // SAFETY: the caller upholds `GlobalAlloc::alloc`'s contract
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        // SAFETY: the caller upholds `GlobalAlloc::alloc_zeroed`'s contract
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        // SAFETY: the caller upholds `GlobalAlloc::realloc`'s contract
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the caller upholds `GlobalAlloc::dealloc`'s contract
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// The number of allocations, and reallocations, the current thread has
+/// made.
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocations_are_counted_per_thread() {
+        let before = allocations();
+        let boxed = std::hint::black_box(Box::new([0u8; 64]));
+        assert_eq!(allocations() - before, 1);
+        drop(boxed);
+
+        // A new thread starts from its own count
+        let counted = std::thread::spawn(|| {
+            let before = allocations();
+            std::hint::black_box(vec![1, 2, 3]);
+            (before, allocations() - before)
+        });
+        let (before, counted) = counted.join().unwrap();
+        assert!(before < 100, "{before}");
+        assert_eq!(counted, 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! The lint engine behind `ox lint`.
+//!
+//! A [`Rule`] looks at the declarations of one file and returns what it
+//! finds suspicious, usually by walking them with a
+//! [`Visitor`](oxidex_syntax::visit::Visitor). A [`Linter`] holds the rules
+//! and the [`LintLevel`] each runs at, and turns their findings into
+//! diagnostics: warnings, or errors for the rules that are denied. Each
+//! diagnostic carries the name of the rule that raised it as its code, so
+//! that the rule can be allowed or denied by name.
+
+// The standard rules
+mod rules;
+
+use oxidex_mem::StringInterner;
+use oxidex_syntax::diagnostic::{Diagnostic, DiagnosticBuilder, DiagnosticLevel};
+use oxidex_syntax::{Decl, Span};
+use std::collections::HashMap;
+
+/// How seriously a rule's findings are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    /// The rule does not run
+    Allow,
+    /// Findings are warnings
+    Warn,
+    /// Findings are errors, and fail `ox lint`
+    Deny,
+}
+
+/// Something a rule found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// What is wrong
+    pub message: String,
+    /// Where it is
+    pub span: Span,
+    /// How to fix it
+    pub help: Option<String>,
+    /// A related place, with what it has to do with the finding
+    pub note: Option<(String, Span)>,
+}
+
+impl Finding {
+    /// A finding with neither help nor a note.
+    pub fn new(message: String, span: Span) -> Self {
+        Self { message, span, help: None, note: None }
+    }
+
+    /// The finding, with how to fix it.
+    #[must_use]
+    pub fn help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    /// The finding, with a note on a related place.
+    #[must_use]
+    pub fn note(mut self, message: String, span: Span) -> Self {
+        self.note = Some((message, span));
+        self
+    }
+}
+
+/// A lint rule.
+pub trait Rule {
+    /// The name it is allowed and denied by, in kebab case.
+    fn name(&self) -> &'static str;
+
+    /// The level it runs at unless configured otherwise.
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    /// What it finds in the declarations of a file, whose text is `source`.
+    fn check(&self, decls: &[Decl<'_>], interner: &StringInterner, source: &str) -> Vec<Finding>;
+}
+
+/// Rules and the levels they run at.
+pub struct Linter {
+    /// The rules, in the order they run
+    rules: Vec<Box<dyn Rule>>,
+    /// The level of each rule, by name
+    levels: HashMap<&'static str, LintLevel>,
+}
+
+impl Linter {
+    /// A linter without rules.
+    pub fn new() -> Self {
+        Self { rules: Vec::new(), levels: HashMap::new() }
+    }
+
+    /// A linter with the standard rules, at their default levels.
+    pub fn standard() -> Self {
+        let mut linter = Self::new();
+        linter.register(Box::new(rules::UnusedVariable));
+        linter.register(Box::new(rules::Shadowing));
+        linter.register(Box::new(rules::UnreachableCode));
+        linter.register(Box::new(rules::NamingConvention));
+        linter
+    }
+
+    /// Add `rule`, at its default level.
+    pub fn register(&mut self, rule: Box<dyn Rule>) {
+        self.levels.insert(rule.name(), rule.default_level());
+        self.rules.push(rule);
+    }
+
+    /// Whether `name` is a rule or `all`, which [`set_level`](Self::set_level)
+    /// accepts.
+    pub fn knows(&self, name: &str) -> bool {
+        name == "all" || self.levels.contains_key(name)
+    }
+
+    /// Run the rule named `name`, or every rule for `all`, at `level`.
+    /// Unknown names are ignored.
+    pub fn set_level(&mut self, name: &str, level: LintLevel) {
+        for (rule, current) in &mut self.levels {
+            if name == "all" || name == *rule {
+                *current = level;
+            }
+        }
+    }
+
+    /// The diagnostics of every rule that is not allowed, in the order of
+    /// where they are in the file whose text is `source`.
+    pub fn lint(&self, decls: &[Decl<'_>], interner: &StringInterner, source: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for rule in &self.rules {
+            let level = match self.levels[rule.name()] {
+                LintLevel::Allow => continue,
+                LintLevel::Warn => DiagnosticLevel::Warning,
+                LintLevel::Deny => DiagnosticLevel::Error,
+            };
+            for finding in rule.check(decls, interner, source) {
+                let mut builder =
+                    DiagnosticBuilder::new(level, finding.message, finding.span).code(rule.name().to_string());
+                if let Some(help) = finding.help {
+                    builder = builder.suggest(help);
+                }
+                if let Some((message, span)) = finding.note {
+                    builder = builder.note(message, span);
+                }
+                diagnostics.push(builder.build());
+            }
+        }
+        // Stable, so findings at one place stay in the order of the rules
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        diagnostics
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::LocalArena;
+    use oxidex_syntax::Lexer;
+    use oxidex_syntax::parser::Parser;
+
+    #[test]
+    fn test_lint_reports_at_the_configured_level() {
+        let source = "fn f() -> Int {\n    let x = 1;\n    let x = 2;\n    return x;\n    x\n}";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty());
+
+        let mut linter = Linter::standard();
+        linter.set_level("unused-variable", LintLevel::Deny);
+        let diagnostics = linter.lint(&decls, parser.interner(), source);
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.level, diagnostic.code.as_deref().unwrap(), diagnostic.span.start_line))
+            .collect();
+        let expected = [
+            (DiagnosticLevel::Error, "unused-variable", 2),
+            (DiagnosticLevel::Warning, "shadowing", 3),
+            (DiagnosticLevel::Warning, "unreachable-code", 6),
+        ];
+        assert_eq!(found, expected);
+        assert_eq!(diagnostics[1].notes[0].span.start_line, 2);
+
+        assert!(linter.knows("shadowing") && linter.knows("all") && !linter.knows("shadow"));
+        linter.set_level("all", LintLevel::Allow);
+        linter.set_level("shadowing", LintLevel::Deny);
+        let diagnostics = linter.lint(&decls, parser.interner(), source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, DiagnosticLevel::Error);
+    }
+}
@@ -0,0 +1,622 @@
+//! The standard lint rules.
+//!
+//! `unused-variable` and `shadowing` share [`Scopes`], a visitor that
+//! follows the local bindings of each function through its blocks;
+//! `unreachable-code` and `naming-convention` walk the AST on their own.
+
+use super::{Finding, Rule};
+use oxidex_mem::{StringInterner, Symbol};
+use oxidex_syntax::ast::decl::FnDecl;
+use oxidex_syntax::ast::expr::{BinaryOp, MatchArm};
+use oxidex_syntax::visit::{self, Visitor};
+use oxidex_syntax::ast::FnParam;
+use oxidex_syntax::{Decl, Expr, Pattern, Span, Spanned, Stmt};
+
+/// Local variables that are never read. Names starting with an underscore
+/// are left alone.
+pub struct UnusedVariable;
+
+impl Rule for UnusedVariable {
+    fn name(&self) -> &'static str {
+        "unused-variable"
+    }
+
+    fn check(&self, decls: &[Decl<'_>], interner: &StringInterner, _source: &str) -> Vec<Finding> {
+        let mut scopes = Scopes::default();
+        for decl in decls {
+            scopes.visit_decl(decl);
+        }
+        let mut findings: Vec<Finding> = scopes
+            .unused
+            .into_iter()
+            .filter_map(|binding| {
+                let name = resolve(interner, binding.name);
+                let finding = Finding::new(format!("unused variable `{name}`"), binding.span)
+                    .help(format!("if this is intentional, prefix it with an underscore: `_{name}`"));
+                (!name.starts_with('_')).then_some(finding)
+            })
+            .collect();
+        findings.sort_by_key(|finding| finding.span.start);
+        findings
+    }
+}
+
+/// Local variables that hide another of the same name.
+pub struct Shadowing;
+
+impl Rule for Shadowing {
+    fn name(&self) -> &'static str {
+        "shadowing"
+    }
+
+    fn check(&self, decls: &[Decl<'_>], interner: &StringInterner, _source: &str) -> Vec<Finding> {
+        let mut scopes = Scopes::default();
+        for decl in decls {
+            scopes.visit_decl(decl);
+        }
+        scopes
+            .shadowed
+            .into_iter()
+            .filter_map(|(binding, previous)| {
+                let name = resolve(interner, binding.name);
+                let finding = Finding::new(format!("`{name}` shadows an earlier binding"), binding.span)
+                    .note(format!("`{name}` is first bound here"), previous);
+                (!name.starts_with('_')).then_some(finding)
+            })
+            .collect()
+    }
+}
+
+/// A local variable.
+#[derive(Debug, Clone, Copy)]
+struct Binding {
+    /// Its name
+    name: Symbol,
+    /// Where it is bound
+    span: Span,
+    /// Whether it is a parameter, which need not be read
+    param: bool,
+    /// Whether it is read
+    used: bool,
+}
+
+/// Follows the local variables of functions through their scopes,
+/// recording those never read and those that hide another.
+#[derive(Default)]
+struct Scopes {
+    /// The variables of each enclosing scope of the current function,
+    /// innermost last
+    scopes: Vec<Vec<Binding>>,
+    /// Variables that went out of scope unread
+    unused: Vec<Binding>,
+    /// Variables that hide another, with where that one is bound
+    shadowed: Vec<(Binding, Span)>,
+}
+
+impl Scopes {
+    /// Run `f` in a new scope, then close it.
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        f(self);
+        let scope = self.scopes.pop().unwrap_or_default();
+        self.unused.extend(scope.into_iter().filter(|binding| !binding.used && !binding.param));
+    }
+
+    /// Bind `name` in the innermost scope.
+    fn bind(&mut self, name: Symbol, span: Span, param: bool) {
+        let binding = Binding { name, span, param, used: false };
+        if let Some(previous) = self.scopes.iter().flatten().rev().find(|binding| binding.name == name) {
+            self.shadowed.push((binding, previous.span));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(binding);
+        }
+    }
+
+    /// Mark the variable `name` refers to as read.
+    fn read(&mut self, name: Symbol) {
+        if let Some(binding) = self.scopes.iter_mut().flatten().rev().find(|binding| binding.name == name) {
+            binding.used = true;
+        }
+    }
+}
+
+impl Visitor for Scopes {
+    fn visit_fn(&mut self, params: &[FnParam], body: &Expr<'_>) {
+        // Functions see no locals but their own
+        let outer = std::mem::take(&mut self.scopes);
+        self.scoped(|scopes| visit::walk_fn(scopes, params, body));
+        self.scopes = outer;
+    }
+
+    fn visit_param(&mut self, param: &FnParam) {
+        self.bind(param.name, param.span, true);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt<'_>) {
+        match stmt {
+            Stmt::Let { name, init, span, .. } | Stmt::Mut { name, init, span, .. } => {
+                if let Some(init) = init {
+                    self.visit_expr(init);
+                }
+                self.bind(*name, *span, false);
+            }
+            Stmt::ForLoop { pattern, iter, body, .. } => {
+                self.visit_expr(iter);
+                self.scoped(|scopes| {
+                    scopes.visit_pattern(pattern);
+                    scopes.visit_expr(body);
+                });
+            }
+            // Assigning to a variable is not reading it
+            Stmt::Assign { target: Expr::Identifier(_), value, .. } => self.visit_expr(value),
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr<'_>) {
+        match expr {
+            Expr::Identifier(name) => self.read(*name),
+            // Assigning to a variable is not reading it
+            Expr::Binary { op: BinaryOp::Assign, left: Expr::Identifier(_), right, .. } => self.visit_expr(right),
+            Expr::Block { .. } => self.scoped(|scopes| visit::walk_expr(scopes, expr)),
+            // Closures see the locals of the function around them
+            Expr::Closure { params, body, .. } => self.scoped(|scopes| visit::walk_fn(scopes, params, body)),
+            Expr::ForLoop { pattern, iter, body, .. } => {
+                self.visit_expr(iter);
+                self.scoped(|scopes| {
+                    scopes.visit_pattern(pattern);
+                    scopes.visit_expr(body);
+                });
+            }
+            Expr::Struct { fields, .. } => {
+                for field in fields {
+                    match field.value {
+                        Some(value) => self.visit_expr(value),
+                        // `Point { x }` reads `x`
+                        None => self.read(field.name),
+                    }
+                }
+            }
+            _ => visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &MatchArm<'_>) {
+        self.scoped(|scopes| visit::walk_arm(scopes, arm));
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variable { name, span, .. } => self.bind(*name, *span, false),
+            Pattern::Struct { fields, .. } => {
+                for field in fields {
+                    match &field.pattern {
+                        Some(pattern) => self.visit_pattern(pattern),
+                        // `Point { x }` binds `x`
+                        None => self.bind(field.name, field.span, false),
+                    }
+                }
+            }
+            // Both sides bind the same variables
+            Pattern::Or { left, .. } => self.visit_pattern(left),
+            _ => visit::walk_pattern(self, pattern),
+        }
+    }
+}
+
+/// Code after a `return` or `throw` that can never run.
+pub struct UnreachableCode;
+
+impl Rule for UnreachableCode {
+    fn name(&self) -> &'static str {
+        "unreachable-code"
+    }
+
+    fn check(&self, decls: &[Decl<'_>], interner: &StringInterner, _source: &str) -> Vec<Finding> {
+        let mut unreachable = Unreachable { throw: interner.get_symbol("throw"), findings: Vec::new() };
+        for decl in decls {
+            unreachable.visit_decl(decl);
+        }
+        unreachable.findings
+    }
+}
+
+/// Finds the first unreachable statement or expression of each block.
+struct Unreachable {
+    /// The `throw` builtin, if the file names it
+    throw: Option<Symbol>,
+    /// What was found
+    findings: Vec<Finding>,
+}
+
+impl Unreachable {
+    /// Whether control never continues past `stmt`.
+    fn stmt_diverges(&self, stmt: &Stmt<'_>) -> bool {
+        match stmt {
+            Stmt::Return { .. } => true,
+            Stmt::If { then_branch, else_branch: Some(else_branch), .. } => {
+                self.diverges(then_branch) && self.diverges(else_branch)
+            }
+            Stmt::Match { arms, .. } => !arms.is_empty() && arms.iter().all(|arm| self.diverges(arm.body)),
+            Stmt::Expr { expr, .. } => self.diverges(expr),
+            _ => false,
+        }
+    }
+
+    /// Whether control never continues past `expr`.
+    fn diverges(&self, expr: &Expr<'_>) -> bool {
+        match expr {
+            Expr::Call { callee: Expr::Identifier(name), .. } => Some(*name) == self.throw,
+            Expr::Block { stmts, expr, .. } => {
+                stmts.iter().any(|stmt| self.stmt_diverges(stmt)) || expr.is_some_and(|expr| self.diverges(expr))
+            }
+            Expr::If { then_branch, else_branch: Some(else_branch), .. } => {
+                self.diverges(then_branch) && self.diverges(else_branch)
+            }
+            Expr::Match { arms, .. } => !arms.is_empty() && arms.iter().all(|arm| self.diverges(arm.body)),
+            Expr::Paren { expr, .. } => self.diverges(expr),
+            _ => false,
+        }
+    }
+}
+
+impl Visitor for Unreachable {
+    fn visit_expr(&mut self, expr: &Expr<'_>) {
+        if let Expr::Block { stmts, expr: tail, span } = expr
+            && let Some(at) = stmts.iter().position(|stmt| self.stmt_diverges(stmt))
+        {
+            // Identifiers have no span of their own, so the closing brace
+            // of the block stands in
+            let end = Span::point(span.end.saturating_sub(1), span.end_line, span.end_col.saturating_sub(1));
+            let next = match (stmts.get(at + 1), tail) {
+                (Some(stmt), _) => Some(("statement", stmt.span())),
+                (None, Some(tail)) if tail.span().start_line == 0 => Some(("expression", end)),
+                (None, Some(tail)) => Some(("expression", tail.span())),
+                (None, None) => None,
+            };
+            if let Some((what, next)) = next {
+                let finding = Finding::new(format!("unreachable {what}"), next)
+                    .note("any code following this is unreachable".to_string(), stmts[at].span());
+                self.findings.push(finding);
+            }
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Names that do not follow the naming conventions.
+pub struct NamingConvention;
+
+impl Rule for NamingConvention {
+    fn name(&self) -> &'static str {
+        "naming-convention"
+    }
+
+    fn check(&self, decls: &[Decl<'_>], interner: &StringInterner, source: &str) -> Vec<Finding> {
+        let mut naming = Naming { interner, source, findings: Vec::new() };
+        for decl in decls {
+            naming.visit_decl(decl);
+        }
+        naming.findings
+    }
+}
+
+/// A naming convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    /// `snake_case`, for functions and variables
+    Snake,
+    /// `UpperCamelCase`, for types
+    UpperCamel,
+    /// `SCREAMING_SNAKE_CASE`, for constants and statics
+    ScreamingSnake,
+}
+
+impl Case {
+    /// Whether `name` follows the convention. Leading underscores are
+    /// ignored.
+    fn fits(self, name: &str) -> bool {
+        let name = name.trim_start_matches('_');
+        match self {
+            Self::Snake => !name.chars().any(char::is_uppercase),
+            Self::UpperCamel => name.chars().next().is_none_or(char::is_uppercase) && !name.contains('_'),
+            Self::ScreamingSnake => !name.chars().any(char::is_lowercase),
+        }
+    }
+
+    /// `name` in the convention, keeping its leading underscores.
+    fn convert(self, name: &str) -> String {
+        let trimmed = name.trim_start_matches('_');
+        let words = words(trimmed);
+        let converted = match self {
+            Self::Snake => words.join("_"),
+            Self::ScreamingSnake => words.join("_").to_uppercase(),
+            Self::UpperCamel => words
+                .iter()
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+                })
+                .collect::<String>(),
+        };
+        format!("{}{converted}", &name[..name.len() - trimmed.len()])
+    }
+
+    /// How the convention is written.
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Snake => "a snake case",
+            Self::UpperCamel => "an upper camel case",
+            Self::ScreamingSnake => "an upper case",
+        }
+    }
+}
+
+/// The words of `name`, in lower case: split at underscores and where
+/// case changes, keeping acronyms together, so `parseHTTPRequest` is
+/// `parse`, `http`, `request`.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            let boundary = i > 0
+                && c.is_uppercase()
+                && (!chars[i - 1].is_uppercase() || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+/// Checks the names declared against their conventions.
+struct Naming<'i> {
+    /// Resolves the names
+    interner: &'i StringInterner,
+    /// The text of the file, to find the names in
+    source: &'i str,
+    /// What was found
+    findings: Vec<Finding>,
+}
+
+impl Naming<'_> {
+    /// Check the name of a `kind` declared at `span`, reporting it where the
+    /// name is written. Returns where that is, for the generic parameters
+    /// after it.
+    fn check(&mut self, kind: &str, name: Symbol, case: Case, span: Span) -> Span {
+        let name = resolve(self.interner, name);
+        let at = self.locate(name, span);
+        if !case.fits(name) {
+            let finding = Finding::new(format!("{kind} `{name}` should have {} name", case.describe()), at)
+                .help(format!("rename it to `{}`", case.convert(name)));
+            self.findings.push(finding);
+        }
+        at
+    }
+
+    /// Check the names of the generic parameters of a declaration at
+    /// `span`, written after its name at `name`.
+    fn check_generics(&mut self, generics: &[Symbol], span: Span, name: Span) {
+        let mut after = Span { start: name.end, ..span };
+        for generic in generics {
+            let at = self.check("type parameter", *generic, Case::UpperCamel, after);
+            after.start = at.end.max(after.start);
+        }
+    }
+
+    /// Where `name` is first written as a word in `span`, other than as an
+    /// attribute, or `span` itself if it is not.
+    fn locate(&self, name: &str, span: Span) -> Span {
+        let Some(text) = self.source.get(span.start..span.end) else {
+            return span;
+        };
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let found = text.match_indices(name).map(|(offset, _)| offset).find(|&offset| {
+            let before = text[..offset].chars().next_back();
+            let after = text[offset + name.len()..].chars().next();
+            !before.is_some_and(|c| is_word(c) || c == '@') && !after.is_some_and(is_word)
+        });
+        let Some(offset) = found else {
+            return span;
+        };
+        let skipped = &text[..offset];
+        let (line, col) = match skipped.rfind('\n') {
+            Some(newline) => (span.start_line + skipped.matches('\n').count(), offset - newline),
+            None => (span.start_line, span.start_col + offset),
+        };
+        let start = span.start + offset;
+        Span::new(start, start + name.len(), line, col, line, col + name.len())
+    }
+}
+
+impl Visitor for Naming<'_> {
+    fn visit_decl(&mut self, decl: &Decl<'_>) {
+        match decl {
+            Decl::Fn { name, generics, span, .. } => {
+                let at = self.check("function", *name, Case::Snake, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::Struct { name, generics, span, .. } => {
+                let at = self.check("struct", *name, Case::UpperCamel, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::Class { name, generics, span, .. } => {
+                let at = self.check("class", *name, Case::UpperCamel, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::Enum { name, generics, span, .. } => {
+                let at = self.check("enum", *name, Case::UpperCamel, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::Protocol { name, generics, span, .. } => {
+                let at = self.check("protocol", *name, Case::UpperCamel, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::TypeAlias { name, generics, span, .. } => {
+                let at = self.check("type alias", *name, Case::UpperCamel, *span);
+                self.check_generics(generics, *span, at);
+            }
+            Decl::Const { name, span, .. } => {
+                self.check("constant", *name, Case::ScreamingSnake, *span);
+            }
+            Decl::Static { name, span, .. } => {
+                self.check("static", *name, Case::ScreamingSnake, *span);
+            }
+            // Extern functions are named by the C library declaring them
+            Decl::Impl { .. } | Decl::Import { .. } | Decl::Extern { .. } => {}
+        }
+        visit::walk_decl(self, decl);
+    }
+
+    fn visit_method(&mut self, method: &FnDecl<'_>) {
+        let at = match method.name {
+            Some(name) => self.check("method", name, Case::Snake, method.span),
+            None => Span { end: method.span.start, ..method.span },
+        };
+        self.check_generics(&method.generics, method.span, at);
+        visit::walk_method(self, method);
+    }
+
+    fn visit_param(&mut self, param: &FnParam) {
+        self.check("parameter", param.name, Case::Snake, param.span);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt<'_>) {
+        if let Stmt::Let { name, span, .. } | Stmt::Mut { name, span, .. } = stmt {
+            self.check("variable", *name, Case::Snake, *span);
+        }
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        if let Pattern::Variable { name, span, .. } = pattern {
+            self.check("variable", *name, Case::Snake, *span);
+        }
+        visit::walk_pattern(self, pattern);
+    }
+}
+
+/// The text of `symbol`.
+fn resolve(interner: &StringInterner, symbol: Symbol) -> &str {
+    interner.resolve(symbol).unwrap_or("?")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::LocalArena;
+    use oxidex_syntax::Lexer;
+    use oxidex_syntax::parser::Parser;
+
+    /// The messages and lines of what `rule` finds in `source`.
+    fn findings(rule: &dyn Rule, source: &str) -> Vec<(String, usize)> {
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let found = rule.check(&decls, parser.interner(), source);
+        found.into_iter().map(|finding| (finding.message, finding.span.start_line)).collect()
+    }
+
+    #[test]
+    fn test_unused_variable() {
+        let source = "fn f(unused_param: Int) -> Int {\n\
+            let a = 1;\n\
+            let _b = 2;\n\
+            mut c = 0;\n\
+            c = 3;\n\
+            let d = 4;\n\
+            let e = Point { d };\n\
+            for i in (xs) { print(e); };\n\
+            match (a) { Some(x) => 1, Point { y } => y }\n\
+            }";
+        let expected = [
+            ("unused variable `c`".to_string(), 4),
+            ("unused variable `i`".to_string(), 8),
+            ("unused variable `x`".to_string(), 9),
+        ];
+        assert_eq!(findings(&UnusedVariable, source), expected);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let source = "fn f(a: Int) {\n\
+            let a = a + 1;\n\
+            let b = a;\n\
+            if (b) { let b = 2; b };\n\
+            print(b);\n\
+            }\n\
+            fn g() { let a = 1; print(a) }";
+        let found = findings(&Shadowing, source);
+        let expected = [
+            ("`a` shadows an earlier binding".to_string(), 2),
+            ("`b` shadows an earlier binding".to_string(), 4),
+        ];
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let source = "fn f(x: Int) -> Int {\n\
+            if (x) { return 1; } else { throw(\"no\"); };\n\
+            print(x);\n\
+            }\n\
+            fn g(x: Int) -> Int {\n\
+            if (x) { return 1; };\n\
+            while (x) { return 2;\n print(x); };\n\
+            { throw(\"no\") };\n\
+            x\n\
+            }";
+        let expected = [
+            ("unreachable statement".to_string(), 3),
+            ("unreachable expression".to_string(), 11),
+            ("unreachable statement".to_string(), 8),
+        ];
+        assert_eq!(findings(&UnreachableCode, source), expected);
+    }
+
+    #[test]
+    fn test_naming_convention() {
+        let source = "struct my_point { x: Int }\n\
+            const maxSize: Int = 1;\n\
+            fn parseHTTPRequest<t>(InputText: Int) { let Total = 1; print(Total) }\n\
+            fn good_name<T>(_Ok: Int) { let snake_case = 1; print(snake_case) }";
+        let found: Vec<String> = findings(&NamingConvention, source).into_iter().map(|(message, _)| message).collect();
+        let expected = [
+            "struct `my_point` should have an upper camel case name",
+            "constant `maxSize` should have an upper case name",
+            "function `parseHTTPRequest` should have a snake case name",
+            "type parameter `t` should have an upper camel case name",
+            "parameter `InputText` should have a snake case name",
+            "variable `Total` should have a snake case name",
+            "parameter `_Ok` should have a snake case name",
+        ];
+        assert_eq!(found, expected);
+
+        // Findings point at the names, not the declarations
+        let source = "@test fn test() {}\nstruct Pair<first, Second> {\n  a: Int }\nimpl Pair { fn makeOne<t>() {} }";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        let found = NamingConvention.check(&decls, parser.interner(), source);
+        let spans: Vec<_> =
+            found.iter().map(|finding| (&source[finding.span.start..finding.span.end], finding.span.start_line)).collect();
+        assert_eq!(spans, [("first", 2), ("makeOne", 4), ("t", 4)]);
+        assert_eq!((found[1].span.start_col, found[1].span.end_col), (16, 23));
+    }
+
+    #[test]
+    fn test_case_conversion() {
+        assert_eq!(Case::Snake.convert("parseHTTPRequest"), "parse_http_request");
+        assert_eq!(Case::Snake.convert("_Total"), "_total");
+        assert_eq!(Case::UpperCamel.convert("my_point"), "MyPoint");
+        assert_eq!(Case::ScreamingSnake.convert("maxSize"), "MAX_SIZE");
+        assert!(Case::UpperCamel.fits("T") && Case::Snake.fits("x2") && Case::ScreamingSnake.fits("PI_2"));
+    }
+}
@@ -5,20 +5,71 @@
 //! - `ox build` - Compile to bytecode
 //! - `ox compile` - AOT compile to native
 //! - `ox jit` - Run with JIT compilation
+//! - `ox test` - Run `@test` functions
+//! - `ox bench` - Run `@bench` functions
 //! - `ox fmt` - Format source code
 //! - `ox lint` - Lint source code
 //! - `ox doc` - Generate documentation
+//! - `ox repl` - Interactive session
 //!
-//! **Phase:** 12 - Planned
-//! **Status:** Placeholder - Implementation TBD
-
-fn main() {
-    println!("`OxideX` CLI (ox) - Coming in Phase 12");
-    println!("This is a placeholder binary.");
-    println!();
-    println!("Future commands:");
-    println!("  ox run <file>     - Interpret `OxideX` source");
-    println!("  ox build <file>   - Compile to bytecode");
-    println!("  ox compile <file> - AOT compile to native");
-    println!("  ox jit <file>     - Run with JIT compilation");
+//! `ox` exits with 0 on success, 1 when a command fails and 2 when the
+//! command line is malformed; `ox run` exits with the program's own status.
+//!
+//! **Phase:** 12 - CLI
+//! **Status:** In Progress
+
+// Tokenizing of the command line
+mod args;
+
+// The command line, global options, logging and exit codes
+mod cli;
+
+// One module per subcommand
+mod commands;
+
+// Counting heap allocations
+mod heap;
+
+// Lint rules and the engine that runs them
+mod lint;
+
+// Reading, parsing, checking and lowering source files
+mod pipeline;
+
+// `ox.toml` manifests and the projects they describe
+mod project;
+
+// Rebuilding programs as their files change
+mod watch;
+
+use cli::{EXIT_FAILURE, EXIT_SUCCESS, EXIT_USAGE, GlobalOptions, Invocation, Level};
+use std::io::Write;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut global = GlobalOptions::default();
+    let code = match cli::parse(std::env::args().skip(1), &mut global) {
+        Ok(Invocation::Help(text)) => {
+            // A closed pipe, as in `ox help | head`, is not an error
+            let _ = writeln!(std::io::stdout(), "{text}");
+            EXIT_SUCCESS
+        }
+        Ok(Invocation::Version) => {
+            let _ = writeln!(std::io::stdout(), "ox {}", env!("CARGO_PKG_VERSION"));
+            EXIT_SUCCESS
+        }
+        Ok(Invocation::Command(command)) => {
+            global.log(Level::Info, format_args!("running `ox {}`", command.name()));
+            global.log(Level::Debug, format_args!("{command:?}"));
+            command.execute(&global).unwrap_or_else(|err| {
+                global.log(Level::Error, err);
+                EXIT_FAILURE
+            })
+        }
+        Err(err) => {
+            global.log(Level::Error, err);
+            EXIT_USAGE
+        }
+    };
+    ExitCode::from(code)
 }
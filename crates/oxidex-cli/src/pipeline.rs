@@ -0,0 +1,734 @@
+//! Reading, parsing, checking and lowering source files.
+//!
+//! A program is a root file and the files its imports name, transitively.
+//! [`load`] reads them in dependency order, each file after the files it
+//! imports, and [`parse`] parses them with one interner, so that the
+//! declarations of every file can be checked and lowered as one program.
+//!
+//! A program is parsed in one [`Session`], which every phase after reports
+//! to and whose interner they resolve symbols through. Each phase reports
+//! every problem it finds as diagnostics, which the session renders to
+//! standard error against the file each points into, and the pipeline
+//! stops after the first phase that found any: there is no point type
+//! checking a file that did not parse.
+//!
+//! A program built over and over, as in watch mode, keeps a [`Cache`] and
+//! goes through [`parse_cached`] and [`check_cached`], which redo only what
+//! its edits invalidated.
+
+use crate::cli::{CliError, GlobalOptions, Level};
+use oxidex_codegen::ir::{self, build_module};
+use oxidex_codegen::{LoweredModule, lower};
+use oxidex_interpreter::{Builtins, Interpreter, Resolver};
+use oxidex_mem::{StringInterner, Symbol};
+use oxidex_syntax::diagnostic::{Diagnostic, DiagnosticBuilder, DiagnosticLevel};
+use oxidex_syntax::lexer::string_value;
+use oxidex_syntax::parser::Parser;
+use oxidex_syntax::session::{FileId, Session, SessionOptions};
+use oxidex_syntax::{Decl, Lexer, Span, TokenKind};
+use oxidex_typecheck::check::ConstValue;
+use oxidex_typecheck::infer::{Context, solve_constraints};
+use oxidex_typecheck::query::CacheStats;
+use oxidex_typecheck::{QueryCache, error::TypeError};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// A source file read into memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// Where it was read from
+    pub path: PathBuf,
+    /// Its contents
+    pub text: String,
+}
+
+impl Source {
+    /// Read the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not UTF-8.
+    pub fn read(path: &Path) -> Result<Self, CliError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Self { path: path.to_path_buf(), text }),
+            Err(source) => Err(CliError::Io { path: path.to_path_buf(), source }),
+        }
+    }
+
+    /// The file's name as diagnostics show it.
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// Print `diagnostics` against the file to standard error, and return
+    /// how many were errors.
+    pub fn report(&self, diagnostics: &[Diagnostic], global: &GlobalOptions) -> usize {
+        let mut session = session(global);
+        session.add_file(self.name(), &self.text);
+        for diagnostic in diagnostics {
+            session.report(diagnostic.clone());
+        }
+        emit(&session)
+    }
+
+    /// Report `diagnostics` and the error that the file did not compile.
+    fn fail(&self, diagnostics: &[Diagnostic], global: &GlobalOptions) -> CliError {
+        let errors = self.report(diagnostics, global);
+        CliError::Compile { file: self.name(), errors }
+    }
+
+    /// The paths the file's imports name, as written, with where each is.
+    ///
+    /// A file that does not lex has no imports here; parsing it reports why.
+    fn imports(&self) -> Vec<(String, Span)> {
+        let Ok((tokens, interner)) = Lexer::new(&self.text).lex_with_interner() else {
+            return Vec::new();
+        };
+        tokens
+            .windows(2)
+            .filter_map(|pair| match (&pair[0].kind, &pair[1].kind) {
+                (TokenKind::Import, TokenKind::StringLiteral(path)) => {
+                    let path = string_value(interner.resolve(*path).unwrap_or_default());
+                    Some((path, Span::merge(pair[0].span, pair[1].span)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Read `root` and the files its imports name, transitively.
+///
+/// Every file comes after the files it imports, so `root` is last. Imports
+/// are found as the interpreter finds them: paths starting with `./` or
+/// `../` are relative to the importing file, and `.ox` may be left out.
+///
+/// # Errors
+///
+/// Returns an error, after reporting it, if a file cannot be read, an
+/// import names no file, or files import each other in a cycle.
+pub fn load(root: &Path, global: &GlobalOptions) -> Result<Vec<Source>, CliError> {
+    load_with(root, &Resolver::new(), global)
+}
+
+/// Read `root` and the files its imports name, transitively, finding the
+/// imports with `resolver`: the source roots and dependencies of a project,
+/// say, as [`Project::resolver`](crate::project::Project::resolver) sets up.
+///
+/// # Errors
+///
+/// Returns an error, after reporting it, if a file cannot be read, an
+/// import names no file, or files import each other in a cycle.
+pub fn load_with(root: &Path, resolver: &Resolver, global: &GlobalOptions) -> Result<Vec<Source>, CliError> {
+    let mut loader = Loader { resolver, sources: Vec::new(), loaded: HashSet::new(), stack: Vec::new() };
+    loader.visit(Source::read(root)?, global)?;
+    Ok(loader.sources)
+}
+
+/// Walks the imports of a program, depth first.
+struct Loader<'r> {
+    /// Finds the files imports name
+    resolver: &'r Resolver,
+    /// Files read so far, in dependency order
+    sources: Vec<Source>,
+    /// Canonical paths of the files in `sources`
+    loaded: HashSet<PathBuf>,
+    /// Canonical paths of the files whose imports are being read, importers
+    /// first
+    stack: Vec<PathBuf>,
+}
+
+impl Loader<'_> {
+    fn visit(&mut self, source: Source, global: &GlobalOptions) -> Result<(), CliError> {
+        let canonical = source.path.canonicalize().unwrap_or_else(|_| source.path.clone());
+        self.stack.push(canonical.clone());
+        for (path, span) in source.imports() {
+            let Some(file) = self.resolver.resolve(&path, Some(&source.path)) else {
+                return Err(source.fail(&[error(format!("cannot find module `{path}`"), span)], global));
+            };
+            if let Some(start) = self.stack.iter().position(|active| *active == file) {
+                let cycle: Vec<String> =
+                    self.stack[start..].iter().chain([&file]).map(|path| path.display().to_string()).collect();
+                let message = format!("import cycle: {}", cycle.join(" -> "));
+                return Err(source.fail(&[error(message, span)], global));
+            }
+            if !self.loaded.contains(&file) {
+                global.log(Level::Debug, format_args!("importing {}", file.display()));
+                self.visit(Source::read(&file)?, global)?;
+            }
+        }
+        self.stack.pop();
+        self.loaded.insert(canonical);
+        self.sources.push(source);
+        Ok(())
+    }
+}
+
+/// The session a compilation with `global` reports through.
+fn session<'src>(global: &GlobalOptions) -> Session<'src> {
+    Session::new(SessionOptions { color: global.color.enabled(), sink: global.sink() })
+}
+
+/// Print the diagnostics reported to `session` to standard error, and
+/// return how many were errors, counting those over the error limit.
+fn emit(session: &Session<'_>) -> usize {
+    let (errors, omitted) = (session.error_count(), session.omitted_errors());
+    for diagnostic in &session.take_diagnostics() {
+        eprint!("{}", session.render(diagnostic));
+    }
+    if omitted > 0 {
+        let level = DiagnosticLevel::Error.format_colored(session.options.color);
+        let plural = if omitted == 1 { "" } else { "s" };
+        eprintln!("{level}: {omitted} more error{plural} not shown; see `--max-errors`");
+    }
+    errors
+}
+
+/// A parsed program.
+///
+/// The session holds the interner the declarations' symbols resolve
+/// through, and the parsers the arenas the declarations live in, so they
+/// are kept alongside them.
+pub struct Parsed<'src> {
+    session: Session<'src>,
+    parsers: Vec<Parser<'src, 'src>>,
+    /// The declarations of every file, each file's after those of the files
+    /// it imports
+    pub decls: Vec<Decl<'src>>,
+    /// Each file, with its place in the session and the range of `decls`
+    /// it declares
+    files: Vec<(&'src Source, FileId, Range<usize>)>,
+}
+
+impl<'src> Parsed<'src> {
+    /// The session the program was parsed in.
+    pub fn session(&self) -> &Session<'src> {
+        &self.session
+    }
+
+    /// The interner the declarations' symbols resolve through.
+    pub fn interner(&self) -> &StringInterner {
+        self.session.interner()
+    }
+
+    /// A copy of [`Parsed::interner`], for consumers that take ownership.
+    pub fn clone_interner(&self) -> StringInterner {
+        let interner = self.interner();
+        let mut copy = StringInterner::new();
+        for id in 0..interner.len() {
+            copy.intern(interner.resolve(Symbol::new(id as u32)).unwrap_or_default());
+        }
+        copy
+    }
+
+    /// The root file.
+    pub fn root(&self) -> &'src Source {
+        self.files.last().expect("a program has a root file").0
+    }
+
+    /// The root file's place in the session.
+    fn root_file(&self) -> FileId {
+        self.files.last().expect("a program has a root file").1
+    }
+
+    /// The declarations of the root file.
+    pub fn root_decls(&self) -> &[Decl<'src>] {
+        let (_, _, range) = self.files.last().expect("a program has a root file");
+        &self.decls[range.clone()]
+    }
+
+    /// The files the root imports, with their declarations.
+    pub fn imports(&self) -> impl Iterator<Item = (&'src Source, &[Decl<'src>])> {
+        let imports = &self.files[..self.files.len() - 1];
+        imports.iter().map(|(source, _, range)| (*source, &self.decls[range.clone()]))
+    }
+
+    /// The function named `name`, if the root file declares one.
+    pub fn function(&self, name: &str) -> Option<&Decl<'src>> {
+        let sym = self.interner().get_symbol(name)?;
+        self.root_decls().iter().find(|decl| matches!(decl, Decl::Fn { name, .. } if *name == sym))
+    }
+
+    /// The functions the root file declares with the attribute `@name`, in
+    /// order.
+    pub fn marked(&self, attribute: &str) -> Vec<&Decl<'src>> {
+        let Some(attribute) = self.interner().get_symbol(attribute) else {
+            return Vec::new();
+        };
+        let marked = |decl: &&Decl<'src>| {
+            matches!(decl, Decl::Fn { attributes, .. } if attributes.iter().any(|marked| marked.name == attribute))
+        };
+        self.root_decls().iter().filter(marked).collect()
+    }
+
+    /// The file declaring `decls[index]`.
+    fn file_of(&self, index: usize) -> FileId {
+        let file = self.files.iter().find(|(_, _, range)| range.contains(&index));
+        file.map_or(self.root_file(), |(_, file, _)| *file)
+    }
+
+    /// Report `diagnostic` to the session, pointing at `file` as a whole if
+    /// it has no location of its own.
+    fn report_in(&self, mut diagnostic: Diagnostic, file: FileId) {
+        if diagnostic.span.start_line == 0 {
+            diagnostic.span = self.session.sources().file(file).span();
+        }
+        self.session.report(diagnostic);
+    }
+
+    /// Print `diagnostics` about the program to standard error, each
+    /// against the file it points into, or the root file if it has no
+    /// location, and return how many were errors once `--warnings` was
+    /// applied.
+    pub fn report(&self, diagnostics: &[Diagnostic]) -> usize {
+        for diagnostic in diagnostics {
+            self.report_in(diagnostic.clone(), self.root_file());
+        }
+        emit(&self.session)
+    }
+
+    /// Report an error in the program as a whole, against the root file,
+    /// and the error that the root file did not compile.
+    pub fn fail(&self, message: String, span: Option<Span>) -> CliError {
+        let span = span.unwrap_or_else(|| self.session.sources().file(self.root_file()).span());
+        self.session.error(message, span);
+        self.abort()
+    }
+
+    /// Print the diagnostics reported to the session, and return the error
+    /// that the root file did not compile.
+    fn abort(&self) -> CliError {
+        abort(&self.session)
+    }
+}
+
+/// Print the diagnostics reported to `session`, and return the error that
+/// its last file, the root, did not compile.
+fn abort(session: &Session<'_>) -> CliError {
+    let root = session.sources().files().last().map(|(_, file)| file.name().to_string());
+    CliError::Compile { file: root.unwrap_or_default(), errors: emit(session) }
+}
+
+/// What the builds of one program share, so that building it again after
+/// an edit redoes as little as it can.
+#[derive(Default)]
+pub struct Cache {
+    /// The names the last build that parsed interned, in the order of
+    /// their symbols, which the next interns first, so that names keep
+    /// their symbols and unchanged declarations the keys their check
+    /// results are cached under
+    names: Vec<String>,
+    /// Check results of declarations
+    checks: QueryCache,
+}
+
+impl Cache {
+    /// An empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many declarations the last check reused and checked.
+    pub fn stats(&self) -> CacheStats {
+        self.checks.stats()
+    }
+}
+
+/// Lex and parse `sources`, which [`load`] read.
+///
+/// # Errors
+///
+/// Returns an error, after reporting them, if there are syntax errors.
+pub fn parse<'src>(sources: &'src [Source], global: &GlobalOptions) -> Result<Parsed<'src>, CliError> {
+    parse_from(sources, None, global)
+}
+
+/// Lex and parse `sources` as [`parse`] does, with the symbols of the
+/// cache's last build.
+///
+/// # Errors
+///
+/// Returns an error, after reporting them, if there are syntax errors.
+pub fn parse_cached<'src>(
+    sources: &'src [Source],
+    cache: &mut Cache,
+    global: &GlobalOptions,
+) -> Result<Parsed<'src>, CliError> {
+    let names: Vec<&str> = cache.names.iter().map(String::as_str).collect();
+    let interner = (!names.is_empty()).then(|| StringInterner::with_pre_interned(&names));
+    let parsed = parse_from(sources, interner, global)?;
+    let interner = parsed.interner();
+    let symbols = (0..interner.len()).map(|id| Symbol::new(id as u32));
+    cache.names = symbols.filter_map(|symbol| interner.resolve(symbol)).map(String::from).collect();
+    Ok(parsed)
+}
+
+/// Lex and parse `sources` in a new session, interning into `interner`
+/// if there is one.
+fn parse_from<'src>(
+    sources: &'src [Source],
+    interner: Option<StringInterner>,
+    global: &GlobalOptions,
+) -> Result<Parsed<'src>, CliError> {
+    let session = match interner {
+        Some(interner) => Session::with_interner(interner, session(global).options),
+        None => session(global),
+    };
+    let mut parsed = Parsed { session, parsers: Vec::new(), decls: Vec::new(), files: Vec::new() };
+    for source in sources {
+        global.log(Level::Debug, format_args!("parsing {}", source.name()));
+        let file = parsed.session.add_file(source.name(), &source.text);
+        // A file that does not lex is only reported
+        let Some((parser, decls)) = parsed.session.parse(file) else {
+            continue;
+        };
+        let start = parsed.decls.len();
+        parsed.decls.extend(decls);
+        parsed.files.push((source, file, start..parsed.decls.len()));
+        parsed.parsers.push(parser);
+    }
+    if parsed.session.error_count() == 0 {
+        return Ok(parsed);
+    }
+    Err(parsed.abort())
+}
+
+/// Type check the program in `ctx`.
+///
+/// # Errors
+///
+/// Returns an error, after reporting them, if there are type errors.
+pub fn check<'ctx>(parsed: &'ctx Parsed<'_>, ctx: &mut Context<'ctx>, global: &GlobalOptions) -> Result<(), CliError> {
+    check_with(parsed, ctx, &mut QueryCache::new(), global)
+}
+
+/// Type check the program in `ctx` as [`check`] does, reusing the results
+/// of the declarations that did not change since the cache's last build.
+///
+/// # Errors
+///
+/// Returns an error, after reporting them, if there are type errors.
+pub fn check_cached<'ctx>(
+    parsed: &'ctx Parsed<'_>,
+    ctx: &mut Context<'ctx>,
+    cache: &mut Cache,
+    global: &GlobalOptions,
+) -> Result<(), CliError> {
+    let checked = check_with(parsed, ctx, &mut cache.checks, global);
+    let stats = cache.stats();
+    global.log(Level::Debug, format_args!("checked {} declarations, reused {}", stats.misses, stats.hits));
+    checked
+}
+
+/// Type check the program in `ctx`, with `checks` holding the results of
+/// declarations checked before.
+fn check_with<'ctx>(
+    parsed: &'ctx Parsed<'_>,
+    ctx: &mut Context<'ctx>,
+    checks: &mut QueryCache,
+    global: &GlobalOptions,
+) -> Result<(), CliError> {
+    global.log(Level::Debug, format_args!("checking {}", parsed.root().name()));
+    for (name, value) in &global.defines {
+        let constant = parsed.decls.iter().find_map(|decl| match decl {
+            Decl::Const { name: sym, .. } if ctx.interner.resolve(*sym) == Some(name) => Some(*sym),
+            _ => None,
+        });
+        let Some(constant) = constant else {
+            return Err(parsed.fail(format!("`--define {name}` names no constant of the program"), None));
+        };
+        ctx.defines.insert(constant, ConstValue::parse(value));
+    }
+    let root = parsed.root_file();
+    // One error per declaration, rather than stopping at the first
+    let mut errors: Vec<(FileId, TypeError)> = match checks.check_program(ctx, &parsed.decls) {
+        Ok(results) => results
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, result)| result.err().map(|err| (parsed.file_of(index), err)))
+            .collect(),
+        Err(err) => vec![(root, err)],
+    };
+    if errors.is_empty() {
+        // Calls to functions declared later are only checked against their
+        // bounds now, and holes once everything else passed
+        match solve_constraints(ctx, true) {
+            Ok(()) => errors = ctx.hole_diagnostics().into_iter().map(|err| (root, err)).collect(),
+            Err(err) => errors.push((root, err)),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    for (file, err) in errors {
+        parsed.report_in(err.diagnostic(), file);
+    }
+    Err(parsed.abort())
+}
+
+/// Lower the checked program.
+///
+/// # Errors
+///
+/// Returns an error, after reporting it, if the program uses a construct
+/// that cannot be lowered.
+pub fn lower_program<'a>(
+    parsed: &'a Parsed<'a>,
+    ctx: &mut Context<'_>,
+    global: &GlobalOptions,
+) -> Result<LoweredModule<'a>, CliError> {
+    global.log(Level::Debug, format_args!("lowering {}", parsed.root().name()));
+    lower(ctx, &parsed.decls).map_err(|err| parsed.fail(err.to_string(), err.span()))
+}
+
+/// Build the IR of the checked and lowered program.
+///
+/// # Errors
+///
+/// Returns an error, after reporting it, if a body uses a construct that
+/// has no IR yet.
+pub fn build_ir(
+    parsed: &Parsed<'_>,
+    ctx: &mut Context<'_>,
+    lowered: &LoweredModule<'_>,
+    global: &GlobalOptions,
+) -> Result<ir::Module, CliError> {
+    global.log(Level::Debug, format_args!("building the IR of {}", parsed.root().name()));
+    build_module(ctx, lowered, &parsed.decls).map_err(|err| parsed.fail(err.to_string(), err.span()))
+}
+
+/// An interpreter for the checked and lowered program, with `builtins`,
+/// whose imports load from the parsed files rather than from disk.
+///
+/// Call [`Interpreter::load`] with [`Parsed::root_decls`] to run it.
+pub fn interpreter<'a, 'ctx>(
+    parsed: &'a Parsed<'a>,
+    ctx: &'a Context<'ctx>,
+    lowered: &'a LoweredModule<'a>,
+    builtins: Builtins,
+) -> Interpreter<'a, 'ctx> {
+    let mut interp = Interpreter::with_builtins(ctx, lowered, builtins);
+    interp.set_file(parsed.root().name());
+    // Imports were loaded by their canonical paths, as the resolver finds them
+    let modules: HashMap<PathBuf, &[Decl<'_>]> =
+        parsed.imports().map(|(import, decls)| (import.path.clone(), decls)).collect();
+    interp.set_module_source(modules);
+    interp
+}
+
+/// The `.ox` files among `paths`, and under those that are directories,
+/// recursively, each directory's in name order.
+///
+/// # Errors
+///
+/// Returns an error if a path does not exist or a directory cannot be read.
+pub fn expand(paths: &[PathBuf]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            let source = std::io::Error::from(std::io::ErrorKind::NotFound);
+            return Err(CliError::Io { path: path.clone(), source });
+        }
+    }
+    Ok(files)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), CliError> {
+    let io = |source| CliError::Io { path: dir.to_path_buf(), source };
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io)? {
+        entries.push(entry.map_err(io)?.path());
+    }
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "ox") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// An error diagnostic at `span`.
+pub fn error(message: String, span: Span) -> Diagnostic {
+    DiagnosticBuilder::new(DiagnosticLevel::Error, message, span).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_interpreter::Value;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn source(text: &str) -> Source {
+        Source { path: PathBuf::from("main.ox"), text: text.to_string() }
+    }
+
+    #[test]
+    fn test_phases_report_every_error() {
+        let global = GlobalOptions::default();
+        let good = [source("fn twice(x: Int) -> Int { x * 2 }\nfn main() -> Int { twice(21) }")];
+        let parsed = parse(&good, &global).unwrap();
+        assert!(parsed.function("main").is_some() && parsed.function("x").is_none());
+        let mut ctx = Context::with_session(parsed.session());
+        check(&parsed, &mut ctx, &global).unwrap();
+        let lowered = lower_program(&parsed, &mut ctx, &global).unwrap();
+        assert_eq!(build_ir(&parsed, &mut ctx, &lowered, &global).unwrap().functions.len(), 2);
+
+        let broken = [source("fn a( {}\nfn b() {}\nfn c( {}")];
+        let Err(CliError::Compile { errors, .. }) = parse(&broken, &global) else { panic!("parsed") };
+        assert_eq!(errors, 2);
+
+        let mistyped = [source("fn a() -> Int { missing }\nfn b() -> Int { gone }")];
+        let parsed = parse(&mistyped, &global).unwrap();
+        let mut ctx = Context::with_session(parsed.session());
+        let err = check(&parsed, &mut ctx, &global).unwrap_err();
+        assert_eq!(err.to_string(), "could not compile `main.ox` due to 2 errors");
+    }
+
+    #[test]
+    fn test_runtime_errors_trace_their_calls() {
+        let text = "struct Divider { by: Int }\n\
+                    impl Divider { fn divide(n: Int) -> Int { n / self.by } }\n\
+                    fn halve(n: Int) -> Int { let d = Divider { by: 0 }; d.divide(n) + 1 }\n\
+                    fn main() -> Int { halve(3) }";
+        let program = [source(text)];
+        let global = GlobalOptions::default();
+        let parsed = parse(&program, &global).unwrap();
+        let mut ctx = Context::with_session(parsed.session());
+        check(&parsed, &mut ctx, &global).unwrap();
+        let lowered = lower_program(&parsed, &mut ctx, &global).unwrap();
+        let mut interp = interpreter(&parsed, &ctx, &lowered, Builtins::default());
+        interp.load(parsed.root_decls()).unwrap();
+
+        // The active calls, innermost first, each at its call site
+        let err = interp.call("main", Vec::new()).unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!((&text[span.start..span.end], span.start_line), ("n / self.by", 2));
+        let frames: Vec<_> = err
+            .trace()
+            .iter()
+            .map(|frame| (frame.function.as_str(), &text[frame.call_site.start..frame.call_site.end]))
+            .collect();
+        assert_eq!(frames[..2], [("Divider.divide", "d.divide(n)"), ("halve", "halve(3)")]);
+        assert_eq!(err.trace()[0].call_site.start_line, 3);
+        assert_eq!(err.trace()[1].call_site.start_line, 4);
+        assert_eq!(frames[2].0, "main");
+        assert_eq!(frames.len(), 3);
+
+        // One diagnostic, its notes in the same order
+        let diagnostic = err.diagnostic();
+        assert_eq!(diagnostic.message, "division by zero");
+        let notes: Vec<_> = diagnostic.notes.iter().map(|note| note.message.as_str()).collect();
+        assert_eq!(notes, [
+            "in `Divider.divide`, called here",
+            "in `halve`, called here",
+            "in `main`, called here",
+        ]);
+    }
+
+    #[test]
+    fn test_defines_set_constants() {
+        let program = [source(
+            "const DEBUG: Bool = false;\nconst LEVEL: Int = 1;\n\
+             fn main() -> Int { comptime if DEBUG { LEVEL * 10 } else { missing() } }",
+        )];
+        let defines = vec![("DEBUG".into(), "true".into()), ("LEVEL".into(), "4".into())];
+        let mut global = GlobalOptions { defines, ..Default::default() };
+        let parsed = parse(&program, &global).unwrap();
+        let mut ctx = Context::with_session(parsed.session());
+        // The branch DEBUG rules out is never checked
+        check(&parsed, &mut ctx, &global).unwrap();
+        let lowered = lower_program(&parsed, &mut ctx, &global).unwrap();
+        let mut interp = interpreter(&parsed, &ctx, &lowered, Builtins::default());
+        interp.load(parsed.root_decls()).unwrap();
+        assert_eq!(interp.call("main", Vec::new()).unwrap().to_string(), "40");
+
+        global.defines[1].1 = "high".into();
+        let mut ctx = Context::with_session(parsed.session());
+        let err = check(&parsed, &mut ctx, &global).unwrap_err();
+        assert_eq!(err.to_string(), "could not compile `main.ox` due to an error");
+        global.defines = vec![("VERBOSE".into(), "true".into())];
+        let mut ctx = Context::with_session(parsed.session());
+        assert!(check(&parsed, &mut ctx, &global).is_err());
+    }
+
+    #[test]
+    // Builtins return the interpreter's errors, which are large
+    #[allow(clippy::result_large_err)]
+    fn test_values_are_described() {
+        let program = [source(
+            "struct Point { y: Int, x: Int }\nstruct Label { text: String }\n\
+             impl CustomStringConvertible for Label { fn description() -> String { self.text } }\n\
+             impl CustomDebugStringConvertible for Label { fn debugDescription() -> String { \"label\" } }\n\
+             fn main() -> Label {\n\
+               let label = Label { text: \"hi\" }; print(label); print(Point { x: 1, y: 2 }); label\n\
+             }",
+        )];
+        let global = GlobalOptions::default();
+        let parsed = parse(&program, &global).unwrap();
+        let mut builtins = Builtins::standard();
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&printed);
+        let signature = builtins.get("print").unwrap().signature.clone();
+        builtins.register("print", signature, move |args, _| {
+            sink.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        });
+        let mut ctx = Context::with_session(parsed.session());
+        builtins.declare(&mut ctx);
+        check(&parsed, &mut ctx, &global).unwrap();
+        let lowered = lower_program(&parsed, &mut ctx, &global).unwrap();
+        let mut interp = interpreter(&parsed, &ctx, &lowered, builtins);
+        interp.load(parsed.root_decls()).unwrap();
+
+        // Fields are described in declaration order
+        let label = interp.call("main", Vec::new()).unwrap();
+        assert_eq!(*printed.borrow(), ["hi", "Point(y: 2, x: 1)"]);
+        assert_eq!(interp.description(label.clone()).unwrap(), "hi");
+        let values = Value::array(vec![label, Value::string("hi")]);
+        assert_eq!(interp.debug_description(values).unwrap(), r#"[label, "hi"]"#);
+
+        // A type claiming a description must implement it as the protocol requires
+        let program = [source(
+            "struct Label {}\n\
+             impl CustomStringConvertible for Label { fn description(short: Bool) -> String { \"\" } }",
+        )];
+        let parsed = parse(&program, &global).unwrap();
+        let mut ctx = Context::with_session(parsed.session());
+        assert!(check(&parsed, &mut ctx, &global).is_err());
+    }
+
+    #[test]
+    fn test_load_orders_imports_and_finds_cycles() {
+        let global = GlobalOptions::default();
+        let dir = std::env::temp_dir().join(format!("ox-load-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+        write("math.ox", "pub fn double(x: Int) -> Int { x * 2 }");
+        write("util.ox", "import \"./math\";\npub fn quadruple(x: Int) -> Int { double(double(x)) }");
+        write("main.ox", "import \"./util\";\nimport \"./math.ox\";\nfn main() -> Int { quadruple(double(1)) }");
+
+        let sources = load(&dir.join("main.ox"), &global).unwrap();
+        let names: Vec<_> = sources.iter().map(|source| source.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, ["math.ox", "util.ox", "main.ox"]);
+        let parsed = parse(&sources, &global).unwrap();
+        assert_eq!(parsed.imports().count(), 2);
+        assert_eq!(parsed.root_decls().len(), 3);
+        let mut ctx = Context::with_session(parsed.session());
+        check(&parsed, &mut ctx, &global).unwrap();
+
+        write("math.ox", "import \"./util\";\npub fn double(x: Int) -> Int { x * 2 }");
+        assert!(matches!(load(&dir.join("main.ox"), &global), Err(CliError::Compile { errors: 1, .. })));
+        write("main.ox", "import \"./missing\";");
+        assert!(matches!(load(&dir.join("main.ox"), &global), Err(CliError::Compile { errors: 1, .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,549 @@
+//! Projects: directories of source files described by an `ox.toml`.
+//!
+//! The manifest names the package, where its sources are, the packages it
+//! depends on and the build profiles it defines:
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! version = "0.1.0"
+//! sources = ["src"]        # the default
+//! main = "src/main.ox"     # the default: main.ox in the first source root
+//!
+//! [dependencies]
+//! math = { path = "../math" }
+//!
+//! [profile.release]
+//! optimize = true
+//! ```
+//!
+//! Source roots are searched by imports that are not relative, and a
+//! dependency is imported by its name, as `import "math";` for the `lib.ox`
+//! in its first source root or `import "math/vec";` for a file under it.
+//! Dependencies are other projects, found by path, and may have their own.
+//!
+//! The manifest is read as the subset of TOML it needs: tables, and keys
+//! with strings, integers, booleans, and arrays and inline tables of them,
+//! each on one line.
+
+use crate::cli::CliError;
+use oxidex_interpreter::Resolver;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest file.
+pub const MANIFEST: &str = "ox.toml";
+
+/// Directory under a project's root that builds go in.
+pub const TARGET: &str = "target";
+
+/// How a build is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Its name, which `--profile` selects it by
+    pub name: String,
+    /// Whether the IR is optimized
+    pub optimize: bool,
+}
+
+impl Profile {
+    /// The profile builds use unless told otherwise.
+    pub const DEFAULT: &str = "dev";
+
+    /// The profiles every project has, unless its manifest changes them:
+    /// `dev`, which does not optimize, and `release`, which does.
+    pub fn standard() -> Vec<Self> {
+        vec![Self { name: "dev".to_string(), optimize: false }, Self { name: "release".to_string(), optimize: true }]
+    }
+}
+
+/// A dependency on another project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// The name it is imported by
+    pub name: String,
+    /// Its directory, relative to the manifest's
+    pub path: PathBuf,
+    /// The line of the manifest it is declared on
+    line: usize,
+}
+
+/// The contents of an `ox.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// The name of the package
+    pub name: String,
+    /// Its version, if it has one
+    pub version: Option<String>,
+    /// Directories of source files, relative to the manifest's
+    pub sources: Vec<PathBuf>,
+    /// The file `ox run` runs and `ox build` builds, relative to the
+    /// manifest's directory
+    pub main: PathBuf,
+    /// The packages it depends on
+    pub dependencies: Vec<Dependency>,
+    /// The build profiles, the standard ones first
+    pub profiles: Vec<Profile>,
+}
+
+/// A problem with a manifest, at a line counting from one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestError {
+    /// The line, or zero for the manifest as a whole
+    pub line: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl ManifestError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self { line, message: message.into() }
+    }
+}
+
+impl Manifest {
+    /// Read a manifest from its text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not TOML this reads, a key is
+    /// unknown or has a value of the wrong kind, or the package has no name.
+    pub fn parse(text: &str) -> Result<Self, ManifestError> {
+        let mut name = None;
+        let mut version = None;
+        let mut sources = None;
+        let mut main = None;
+        let mut dependencies: Vec<Dependency> = Vec::new();
+        let mut profiles = Profile::standard();
+
+        let mut table = String::new();
+        let mut seen: HashSet<(String, String)> = HashSet::new();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let mut cursor = Cursor { text: line, at: 0, line: number };
+            cursor.skip_space();
+            if cursor.done() {
+                continue;
+            }
+            if cursor.eat('[') {
+                table = cursor.key()?;
+                while cursor.eat('.') {
+                    table.push('.');
+                    table.push_str(&cursor.key()?);
+                }
+                cursor.expect(']')?;
+                cursor.end()?;
+                if let Some(profile) = table.strip_prefix("profile.") {
+                    if !profiles.iter().any(|existing| existing.name == profile) {
+                        profiles.push(Profile { name: profile.to_string(), optimize: false });
+                    }
+                } else if !matches!(table.as_str(), "package" | "dependencies") {
+                    return Err(ManifestError::new(number, format!("unknown table `[{table}]`")));
+                }
+                continue;
+            }
+
+            let key = cursor.key()?;
+            cursor.expect('=')?;
+            let value = cursor.value()?;
+            cursor.end()?;
+            if !seen.insert((table.clone(), key.clone())) {
+                return Err(ManifestError::new(number, format!("`{key}` is set twice")));
+            }
+            match (table.as_str(), key.as_str()) {
+                ("package", "name") => name = Some(value.string(number, &key)?),
+                ("package", "version") => version = Some(value.string(number, &key)?),
+                ("package", "main") => main = Some(PathBuf::from(value.string(number, &key)?)),
+                ("package", "sources") => {
+                    let Value::Array(roots) = value else {
+                        return Err(ManifestError::new(number, "`sources` must be an array of strings"));
+                    };
+                    let roots: Result<Vec<PathBuf>, _> =
+                        roots.into_iter().map(|root| root.string(number, &key).map(PathBuf::from)).collect();
+                    sources = Some(roots?);
+                }
+                ("dependencies", _) => {
+                    let path = match value {
+                        Value::Table(fields) => match fields.as_slice() {
+                            [(field, path)] if field == "path" => path.clone().string(number, "path")?,
+                            _ => return Err(ManifestError::new(number, format!("`{key}` must only set a `path`"))),
+                        },
+                        _ => {
+                            let example = format!("{key} = {{ path = \"...\" }}");
+                            let message = format!("`{key}` must be a path dependency, as `{example}`");
+                            return Err(ManifestError::new(number, message));
+                        }
+                    };
+                    dependencies.push(Dependency { name: key, path: PathBuf::from(path), line: number });
+                }
+                (profile, "optimize") if profile.starts_with("profile.") => {
+                    let Value::Bool(optimize) = value else {
+                        return Err(ManifestError::new(number, "`optimize` must be a boolean"));
+                    };
+                    let name = &profile["profile.".len()..];
+                    if let Some(profile) = profiles.iter_mut().find(|profile| profile.name == name) {
+                        profile.optimize = optimize;
+                    }
+                }
+                ("", _) => return Err(ManifestError::new(number, format!("`{key}` must be in a table"))),
+                _ => return Err(ManifestError::new(number, format!("unknown key `{key}` in `[{table}]`"))),
+            }
+        }
+
+        let name = name.ok_or_else(|| ManifestError::new(0, "missing the package's `name` in `[package]`"))?;
+        let sources = sources.unwrap_or_else(|| vec![PathBuf::from("src")]);
+        let Some(first) = sources.first() else {
+            return Err(ManifestError::new(0, "`sources` must name at least one directory"));
+        };
+        let main = main.unwrap_or_else(|| first.join("main.ox"));
+        Ok(Self { name, version, sources, main, dependencies, profiles })
+    }
+}
+
+/// A value in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The string this is, as the value of `key` on `line`.
+    fn string(self, line: usize, key: &str) -> Result<String, ManifestError> {
+        match self {
+            Self::String(text) => Ok(text),
+            _ => Err(ManifestError::new(line, format!("`{key}` must be a string"))),
+        }
+    }
+}
+
+/// Reads one line of a manifest.
+struct Cursor<'a> {
+    /// The line
+    text: &'a str,
+    /// Where in it reading is, in bytes
+    at: usize,
+    /// Its number, counting from one
+    line: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.at..].chars().next()
+    }
+
+    fn error(&self, message: impl Into<String>) -> ManifestError {
+        ManifestError::new(self.line, message)
+    }
+
+    /// Skip spaces and a comment.
+    fn skip_space(&mut self) {
+        let rest = &self.text[self.at..];
+        self.at += rest.len() - rest.trim_start().len();
+        if self.peek() == Some('#') {
+            self.at = self.text.len();
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.at == self.text.len()
+    }
+
+    /// Take `expected`, and the spaces after it, if it is next.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() != Some(expected) {
+            return false;
+        }
+        self.at += expected.len_utf8();
+        self.skip_space();
+        true
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ManifestError> {
+        if self.eat(expected) { Ok(()) } else { Err(self.error(format!("expected `{expected}`"))) }
+    }
+
+    /// Require the line to be over.
+    fn end(&mut self) -> Result<(), ManifestError> {
+        if self.done() { Ok(()) } else { Err(self.error(format!("unexpected `{}`", &self.text[self.at..]))) }
+    }
+
+    /// A bare or quoted key.
+    fn key(&mut self) -> Result<String, ManifestError> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let rest = &self.text[self.at..];
+        let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a key"));
+        }
+        self.at += len;
+        self.skip_space();
+        Ok(rest[..len].to_string())
+    }
+
+    /// A quoted string, with its escapes.
+    fn string(&mut self) -> Result<String, ManifestError> {
+        self.at += 1;
+        let mut text = String::new();
+        let mut chars = self.text[self.at..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.at += offset + 1;
+                    self.skip_space();
+                    return Ok(text);
+                }
+                '\\' => match chars.next().map(|(_, escaped)| escaped) {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(other) => return Err(self.error(format!("unknown escape `\\{other}`"))),
+                    None => break,
+                },
+                _ => text.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn value(&mut self) -> Result<Value, ManifestError> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.eat('[');
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value()?);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.eat('{');
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let key = self.key()?;
+                    self.expect('=')?;
+                    fields.push((key, self.value()?));
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                Ok(Value::Table(fields))
+            }
+            _ => {
+                let rest = &self.text[self.at..];
+                let len = rest.find(|c: char| c.is_whitespace() || ",]}#".contains(c)).unwrap_or(rest.len());
+                let word = &rest[..len];
+                let value = match word {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => match word.replace('_', "").parse() {
+                        Ok(number) => Value::Integer(number),
+                        Err(_) => return Err(self.error(format!("expected a value, found `{word}`"))),
+                    },
+                };
+                self.at += len;
+                self.skip_space();
+                Ok(value)
+            }
+        }
+    }
+}
+
+/// A project and, transitively, the projects it depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// The directory of its manifest
+    pub root: PathBuf,
+    /// Its manifest
+    pub manifest: Manifest,
+    /// The projects it depends on, in the order the manifest lists them
+    pub dependencies: Vec<Project>,
+}
+
+impl Project {
+    /// Load the project whose manifest is in `dir`, and its dependencies.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a manifest cannot be read or is malformed, or
+    /// projects depend on each other in a cycle.
+    pub fn load(dir: &Path) -> Result<Self, CliError> {
+        Self::load_from(dir, &mut Vec::new())
+    }
+
+    /// Load the project in `dir`, which the projects in `stack` depend on,
+    /// each on the next.
+    fn load_from(dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Self, CliError> {
+        let path = dir.join(MANIFEST);
+        let text = std::fs::read_to_string(&path).map_err(|source| CliError::Io { path: path.clone(), source })?;
+        let manifest = Manifest::parse(&text)
+            .map_err(|err| CliError::Malformed { path: path.clone(), line: err.line, message: err.message })?;
+
+        let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        stack.push(canonical);
+        let mut dependencies = Vec::new();
+        for dependency in &manifest.dependencies {
+            let dep_dir = dir.join(&dependency.path);
+            let dep_canonical = dep_dir.canonicalize().unwrap_or_else(|_| dep_dir.clone());
+            if let Some(start) = stack.iter().position(|active| *active == dep_canonical) {
+                let mut message = String::from("dependency cycle: ");
+                for active in &stack[start..] {
+                    let _ = write!(message, "{} -> ", active.display());
+                }
+                message.push_str(&dep_canonical.display().to_string());
+                return Err(CliError::Malformed { path, line: dependency.line, message });
+            }
+            dependencies.push(Self::load_from(&dep_dir, stack)?);
+        }
+        stack.pop();
+        Ok(Self { root: dir.to_path_buf(), manifest, dependencies })
+    }
+
+    /// The file `ox run` runs and `ox build` builds.
+    pub fn main(&self) -> PathBuf {
+        self.root.join(&self.manifest.main)
+    }
+
+    /// The manifests of the project and, transitively, its dependencies,
+    /// the project's first.
+    pub fn manifests(&self) -> Vec<PathBuf> {
+        let mut manifests = vec![self.root.join(MANIFEST)];
+        for dependency in &self.dependencies {
+            manifests.extend(dependency.manifests());
+        }
+        manifests
+    }
+
+    /// How the project's imports find files: its source roots, in order,
+    /// and each of its dependencies, transitively, by name. Where two
+    /// dependencies share a name, the nearer one wins.
+    pub fn resolver(&self) -> Resolver {
+        let mut resolver = Resolver::new();
+        for root in &self.manifest.sources {
+            resolver.add_search_path(self.root.join(root));
+        }
+        let mut level: Vec<&Self> = vec![self];
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for project in level {
+                for (dependency, decl) in project.dependencies.iter().zip(&project.manifest.dependencies) {
+                    resolver.add_package(&decl.name, dependency.root.join(&dependency.manifest.sources[0]));
+                    next.push(dependency);
+                }
+            }
+            level = next;
+        }
+        resolver
+    }
+
+    /// Where a build with `profile` is written, unless told otherwise.
+    pub fn artifact(&self, profile: &str) -> PathBuf {
+        let file = Path::new(&self.manifest.name).with_extension(oxidex_bytecode::oxb::EXTENSION);
+        self.root.join(TARGET).join(profile).join(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let text = r#"
+            # The application
+            [package]
+            name = "app"
+            version = "0.1.0"  # comments may follow values
+            sources = ["src", "gen",]
+
+            [dependencies]
+            math = { path = "../math" }
+            "quoted-name" = {path="vendor/q"}
+
+            [profile.release]
+            optimize = false
+
+            [profile.bench]
+            optimize = true
+        "#;
+        let manifest = Manifest::parse(text).unwrap();
+        assert_eq!((manifest.name.as_str(), manifest.version.as_deref()), ("app", Some("0.1.0")));
+        assert_eq!(manifest.sources, [PathBuf::from("src"), PathBuf::from("gen")]);
+        assert_eq!(manifest.main, PathBuf::from("src/main.ox"));
+        let dependencies: Vec<_> = manifest.dependencies.iter().map(|dep| (dep.name.as_str(), dep.line)).collect();
+        assert_eq!(dependencies, [("math", 9), ("quoted-name", 10)]);
+        assert_eq!(manifest.dependencies[1].path, PathBuf::from("vendor/q"));
+        let profiles: Vec<_> =
+            manifest.profiles.iter().map(|profile| (profile.name.as_str(), profile.optimize)).collect();
+        assert_eq!(profiles, [("dev", false), ("release", false), ("bench", true)]);
+
+        let minimal = Manifest::parse("[package]\nname = \"tool\"\nmain = \"tool.ox\"").unwrap();
+        assert_eq!((minimal.main, minimal.profiles), (PathBuf::from("tool.ox"), Profile::standard()));
+    }
+
+    #[test]
+    fn test_manifest_errors() {
+        let error = |text: &str| {
+            let err = Manifest::parse(text).unwrap_err();
+            (err.line, err.message)
+        };
+        let unknown = error("[package]\nname = \"a\"\nedition = 2024");
+        assert_eq!(unknown, (3, "unknown key `edition` in `[package]`".to_string()));
+        assert_eq!(error("[package]\nversion = \"1\"").1, "missing the package's `name` in `[package]`");
+        assert_eq!(error("[package]\nname = 1").1, "`name` must be a string");
+        assert_eq!(error("[package]\nname = \"a\"\nname = \"b\""), (3, "`name` is set twice".to_string()));
+        assert_eq!(error("[package]\nname = \"a").1, "unterminated string");
+        assert_eq!(error("[workspace]").1, "unknown table `[workspace]`");
+        let registry = error("[dependencies]\nmath = \"1.0\"").1;
+        assert_eq!(registry, "`math` must be a path dependency, as `math = { path = \"...\" }`");
+        assert_eq!(error("[profile.dev]\noptimize = yes").1, "expected a value, found `yes`");
+        assert_eq!(error("[package] x").1, "unexpected `x`");
+    }
+
+    #[test]
+    fn test_load_project_with_dependencies() {
+        let dir = std::env::temp_dir().join(format!("ox-project-{}", std::process::id()));
+        let depends = |name: &str, extra: &str, dependency: &str| {
+            let package = format!("[package]\nname = \"{name}\"\n{extra}");
+            format!("{package}[dependencies]\n{dependency} = {{ path = \"../{dependency}\" }}")
+        };
+        for (path, text) in [
+            ("app/ox.toml", depends("app", "", "math")),
+            ("math/ox.toml", depends("math", "sources = [\"lib\"]\n", "bits")),
+            ("bits/ox.toml", "[package]\nname = \"bits\"".to_string()),
+        ] {
+            std::fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(path), text).unwrap();
+        }
+        let project = Project::load(&dir.join("app")).unwrap();
+        assert_eq!(project.main(), dir.join("app/src/main.ox"));
+        assert_eq!(project.artifact("dev"), dir.join("app/target/dev/app.oxb"));
+        assert_eq!(project.dependencies[0].dependencies[0].manifest.name, "bits");
+
+        std::fs::create_dir_all(dir.join("math/lib")).unwrap();
+        std::fs::write(dir.join("math/lib/lib.ox"), "").unwrap();
+        let lib = dir.join("math/lib/lib.ox").canonicalize().unwrap();
+        assert_eq!(project.resolver().resolve("math", None), Some(lib));
+
+        std::fs::write(dir.join("bits/ox.toml"), depends("bits", "", "app")).unwrap();
+        let Err(CliError::Malformed { line, message, .. }) = Project::load(&dir.join("app")) else {
+            panic!("expected a dependency cycle");
+        };
+        assert_eq!(line, 4);
+        assert!(message.starts_with("dependency cycle: "), "{message}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
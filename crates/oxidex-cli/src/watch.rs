@@ -0,0 +1,223 @@
+//! Watch mode: build programs again whenever their files change.
+//!
+//! `ox run --watch` and `ox build --watch` hand their targets to a
+//! [`Session`], which builds each once and then polls the files each build
+//! read, every [`INTERVAL`], for changes to their modification time or
+//! size. Only the targets that read a changed file are built again, each
+//! with the [`Cache`] of its previous builds, so that a rebuild re-checks
+//! only the declarations the edit affected.
+//!
+//! Builds report their diagnostics as usual. After each, the session
+//! prints one line saying how it went and what changed since the target's
+//! last build, so that a fix shows up as errors going away.
+
+use crate::cli::{CliError, EXIT_SUCCESS, GlobalOptions};
+use crate::pipeline::Cache;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often files are checked for changes.
+pub const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Builds a target, with its cache, adding the files it reads to the list,
+/// and returns the exit status of the build or what it ran.
+pub type Build<'a, T> = dyn FnMut(&T, &mut Cache, &mut Vec<PathBuf>) -> Result<u8, CliError> + 'a;
+
+/// A file's modification time and size, or `None` if it cannot be read.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// A target being watched.
+struct Job<T> {
+    /// What to build
+    target: T,
+    /// Its name, as the session's lines show it
+    name: String,
+    /// What its builds share
+    cache: Cache,
+    /// The files its last build read
+    files: Vec<PathBuf>,
+    /// The number of errors its last build failed with
+    errors: usize,
+}
+
+/// Targets being watched, and the stamps of the files they read.
+pub struct Session<T> {
+    /// The targets, in the order they build
+    jobs: Vec<Job<T>>,
+    /// Each watched file's stamp when it was last read
+    stamps: HashMap<PathBuf, Stamp>,
+    /// Whether every target has been built once
+    started: bool,
+}
+
+impl<T> Session<T> {
+    /// A session watching `targets`, each with its name.
+    pub fn new(targets: impl IntoIterator<Item = (T, String)>) -> Self {
+        let jobs = targets
+            .into_iter()
+            .map(|(target, name)| Job { target, name, cache: Cache::new(), files: Vec::new(), errors: 0 })
+            .collect();
+        Self { jobs, stamps: HashMap::new(), started: false }
+    }
+
+    /// The watched files that changed since they were last read.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> =
+            self.stamps.iter().filter(|(path, old)| stamp(path) != **old).map(|(path, _)| path.clone()).collect();
+        changed.sort();
+        changed
+    }
+
+    /// Build every target the first time, and afterwards the targets that
+    /// read one of `changed`, writing a line on each build to `out`.
+    /// Returns how many targets were built.
+    pub fn step(&mut self, changed: &[PathBuf], build: &mut Build<'_, T>, out: &mut dyn Write) -> usize {
+        let changed: HashSet<&PathBuf> = changed.iter().collect();
+        let mut built = 0;
+        for job in &mut self.jobs {
+            if self.started && !job.files.iter().any(|file| changed.contains(file)) {
+                continue;
+            }
+            let mut files = Vec::new();
+            let result = build(&job.target, &mut job.cache, &mut files);
+            for file in &job.files {
+                self.stamps.remove(file);
+            }
+            for file in &files {
+                self.stamps.insert(file.clone(), stamp(file));
+            }
+            job.files = files;
+            let _ = writeln!(out, "{}", summary(job, &result));
+            job.errors = match result {
+                Err(CliError::Compile { errors, .. }) => errors,
+                _ => 0,
+            };
+            built += 1;
+        }
+        // Files only targets that were not rebuilt read
+        for job in &self.jobs {
+            for file in &job.files {
+                self.stamps.entry(file.clone()).or_insert_with(|| stamp(file));
+            }
+        }
+        self.started = true;
+        built
+    }
+
+    /// Build every target, then build them again as their files change,
+    /// until the process is interrupted; the session never returns on its
+    /// own.
+    pub fn run(mut self, build: &mut Build<'_, T>, global: &GlobalOptions) -> Result<u8, CliError> {
+        let mut stderr = std::io::stderr();
+        self.step(&[], build, &mut stderr);
+        let files = if self.stamps.len() == 1 { "file" } else { "files" };
+        let watching = format!("watching {} {files}; press Ctrl-C to stop", self.stamps.len());
+        let _ = writeln!(stderr, "{}", dim(global, &watching));
+        loop {
+            std::thread::sleep(INTERVAL);
+            let changed = self.changed();
+            if changed.is_empty() {
+                continue;
+            }
+            let names: Vec<String> = changed.iter().map(|path| path.display().to_string()).collect();
+            let _ = writeln!(stderr, "{}", dim(global, &format!("changed: {}", names.join(", "))));
+            self.step(&changed, build, &mut stderr);
+        }
+    }
+}
+
+/// The line printed after `job` was built with `result`.
+fn summary<T>(job: &Job<T>, result: &Result<u8, CliError>) -> String {
+    // Builds that check nothing, or fail before checking, have no stats
+    let stats = job.cache.stats();
+    let checked = match result {
+        Ok(_) if stats.hits + stats.misses > 0 => {
+            format!(" (checked {} of {} declarations)", stats.misses, stats.hits + stats.misses)
+        }
+        _ => String::new(),
+    };
+    let fixed = match job.errors {
+        0 => String::new(),
+        1 => ", fixing the error".to_string(),
+        errors => format!(", fixing {errors} errors"),
+    };
+    match result {
+        Ok(EXIT_SUCCESS) => format!("ok: {}{fixed}{checked}", job.name),
+        Ok(status) => format!("exited with {status}: {}{fixed}{checked}", job.name),
+        Err(CliError::Compile { errors, .. }) => {
+            let was = match job.errors {
+                0 => String::new(),
+                before if before == *errors => ", as before".to_string(),
+                before => format!(", {before} before"),
+            };
+            let plural = if *errors == 1 { "" } else { "s" };
+            format!("failed: {} with {errors} error{plural}{was}", job.name)
+        }
+        Err(err) => format!("failed: {err}"),
+    }
+}
+
+/// `text`, dimmed if the output is colored.
+fn dim(global: &GlobalOptions, text: &str) -> String {
+    if global.color.enabled() { format!("\x1b[2m{text}\x1b[0m") } else { text.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline;
+    use oxidex_typecheck::infer::Context;
+
+    /// Load, parse and check `file`, as the commands build it.
+    fn check(file: &PathBuf, cache: &mut Cache, read: &mut Vec<PathBuf>) -> Result<u8, CliError> {
+        let global = GlobalOptions::default();
+        read.push(file.clone());
+        let sources = pipeline::load(file, &global)?;
+        read.extend(sources.iter().map(|source| source.path.clone()).filter(|source| source != file));
+        let parsed = pipeline::parse_cached(&sources, cache, &global)?;
+        pipeline::check_cached(&parsed, &mut Context::with_session(parsed.session()), cache, &global)?;
+        Ok(EXIT_SUCCESS)
+    }
+
+    #[test]
+    fn test_session_rebuilds_what_changed() {
+        let dir = std::env::temp_dir().join(format!("ox-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+        write("util.ox", "pub fn one() -> Int { 1 }");
+        write("a.ox", "import \"./util\";\nfn main() -> Int { one() + two() }\nfn two() -> Int { 2 }");
+        write("b.ox", "fn main() -> Int { 0 }");
+
+        let targets = ["a.ox", "b.ox"].map(|name| (dir.join(name), name.to_string()));
+        let mut session = Session::new(targets);
+        let step = |session: &mut Session<PathBuf>| {
+            let changed = session.changed();
+            let mut out = Vec::new();
+            let built = session.step(&changed, &mut check, &mut out);
+            (built, String::from_utf8(out).unwrap())
+        };
+        let first = "ok: a.ox (checked 3 of 3 declarations)\nok: b.ox (checked 1 of 1 declarations)\n";
+        assert_eq!(step(&mut session), (2, first.to_string()));
+        assert_eq!(step(&mut session), (0, String::new()));
+
+        // Only `one` changed, so only it is checked again
+        write("util.ox", "pub fn one() -> Int { 10 }");
+        assert_eq!(step(&mut session), (1, "ok: a.ox (checked 1 of 3 declarations)\n".to_string()));
+        write("util.ox", "pub fn one() -> Int { missing }");
+        assert_eq!(step(&mut session), (1, "failed: a.ox with 1 error\n".to_string()));
+        write("util.ox", "pub fn one() -> Int { 100 }");
+        let fixed = "ok: a.ox, fixing the error (checked 1 of 3 declarations)\n";
+        assert_eq!(step(&mut session), (1, fixed.to_string()));
+
+        write("b.ox", "fn main() -> Int { zero }");
+        assert_eq!(step(&mut session), (1, "failed: b.ox with 1 error\n".to_string()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
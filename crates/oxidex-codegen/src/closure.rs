@@ -0,0 +1,67 @@
+//! Closure conversion.
+//!
+//! A closure expression becomes a function of its own, lifted out of the
+//! function containing it and named after it (see [`lifted_name`]). The
+//! lifted function takes the variables the closure captures as its first
+//! parameters, followed by the closure's own. Where the closure is
+//! created, an [`Inst::Closure`](crate::ir::Inst::Closure) pairs the lifted
+//! function with the captured values, which calls pass before their
+//! arguments.
+//!
+//! The checker's capture analysis decides how each variable is captured
+//! (see [`CaptureMode`](oxidex_typecheck::infer::CaptureMode)):
+//!
+//! - Variables that cannot change are copied into the closure when it is
+//!   created.
+//! - Mutable variables live in a box from their declaration on
+//!   ([`Inst::Box`](crate::ir::Inst::Box)). The function declaring the
+//!   variable and every closure capturing it share the box, reading it with
+//!   [`Inst::Load`](crate::ir::Inst::Load) and assigning it with
+//!   [`Inst::Store`](crate::ir::Inst::Store), so an assignment on either
+//!   side is seen by the other.
+//! - A closure using the receiver or its fields captures `self`, and
+//!   reaches the fields through it as methods do.
+//!
+//! The [builder](crate::ir::builder) lowers closure expressions with the
+//! conversion [`convert`] describes.
+
+use crate::error::{CodegenError, Result};
+use oxidex_syntax::Span;
+use oxidex_typecheck::Ty;
+use oxidex_typecheck::infer::{Capture, Context};
+
+/// How one closure expression is converted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    /// Name of the lifted function
+    pub function: String,
+    /// Variables the closure captures, in the order the lifted function
+    /// takes them
+    pub captures: Vec<Capture>,
+    /// Type the closure returns
+    pub return_type: Ty,
+}
+
+/// Name of the function lifted out of the closure numbered `index`, in
+/// source order, among those `function` contains directly.
+#[must_use]
+pub fn lifted_name(function: &str, index: usize) -> String {
+    format!("{function}$closure{index}")
+}
+
+/// Describe the conversion of the closure at `span`, the closure numbered
+/// `index` in `function`, from what the checker found.
+///
+/// # Errors
+///
+/// Returns an error if the checker has not seen the closure.
+pub fn convert(ctx: &mut Context<'_>, function: &str, index: usize, span: Span) -> Result<Conversion> {
+    let Some(info) = ctx.closures.get(&span).cloned() else {
+        return Err(CodegenError::Unsupported { construct: "a closure that was not type checked", span });
+    };
+    let return_type = match ctx.subst().apply_ty(&info.ty) {
+        Ty::Function { return_type, .. } => *return_type,
+        _ => Ty::Never,
+    };
+    Ok(Conversion { function: lifted_name(function, index), captures: info.captures, return_type })
+}
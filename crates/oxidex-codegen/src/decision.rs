@@ -0,0 +1,743 @@
+//! Decision trees for pattern matching.
+//!
+//! A `match` is compiled once into a [`Decision`] tree, and every backend
+//! executes the tree instead of re-deriving pattern semantics:
+//!
+//! - [`Decision::Switch`] inspects one part of the scrutinee, named by a
+//!   [`Path`], and picks a branch by variant tag, literal value or array
+//!   length.
+//! - [`Decision::Leaf`] selects an arm and lists the [`Binding`]s its
+//!   pattern introduces.
+//! - [`Decision::Guard`] does the same for an arm with a guard, and says
+//!   where to continue when the guard is false.
+//! - [`Decision::Fail`] is reached only by values no arm matches.
+//!
+//! Compilation follows Maranget's scheme: arms form the rows of a pattern
+//! matrix, irrefutable patterns (wildcards, variables, tuples and structs)
+//! are expanded away, and the first refutable column of the first row is
+//! tested next. Each part of the scrutinee is therefore inspected at most
+//! once on any path through the tree.
+
+use crate::error::{CodegenError, Result};
+use crate::ir::Constant;
+use oxidex_mem::Symbol;
+use oxidex_syntax::Span;
+use oxidex_syntax::ast::expr::{Expr, MatchArm};
+use oxidex_syntax::ast::pat::{FieldPat, Pattern};
+use oxidex_syntax::span::Spanned;
+use oxidex_syntax::token::TokenKind;
+use oxidex_typecheck::check::{ConstValue, eval_const};
+use oxidex_typecheck::infer::Context;
+use oxidex_typecheck::Ty;
+
+/// One step from a value to a part of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Projection {
+    /// The payload of an enum value; a tuple for multi-field variants
+    Payload,
+    /// A named field of a struct or class instance
+    Field(String),
+    /// An element of a tuple or array
+    Element(usize),
+    /// The elements of an array from an index on
+    Rest(usize),
+}
+
+/// The location of a part of the scrutinee, as projections from the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Path(pub Vec<Projection>);
+
+impl Path {
+    /// The scrutinee itself.
+    #[must_use]
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Extend the path by one projection.
+    #[must_use]
+    pub fn then(&self, projection: Projection) -> Self {
+        let mut path = self.clone();
+        path.0.push(projection);
+        path
+    }
+
+    /// Split the path into its parent and last projection.
+    #[must_use]
+    pub fn split_last(&self) -> Option<(Self, &Projection)> {
+        let (last, parent) = self.0.split_last()?;
+        Some((Self(parent.to_vec()), last))
+    }
+}
+
+/// A branch of a [`Decision::Switch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Case {
+    /// The value is the given enum variant
+    Variant {
+        /// Enum name (`Option` and `Result` for the built-in ones)
+        enum_name: String,
+        /// Variant name
+        variant: String,
+        /// Tag of the variant: its position in the declaration
+        tag: usize,
+    },
+    /// The value equals a literal
+    Literal(Constant),
+    /// The array has `len` elements, or at least `len` with `rest`
+    Length {
+        /// Number of elements
+        len: usize,
+        /// Whether longer arrays match too
+        rest: bool,
+    },
+}
+
+/// A variable bound by a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// Variable name
+    pub name: Symbol,
+    /// Whether the binding is mutable
+    pub mutable: bool,
+    /// Part of the scrutinee bound to the variable
+    pub path: Path,
+    /// Checked type of that part, where known
+    pub ty: Option<Ty>,
+}
+
+/// A node of a decision tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// No arm matches
+    Fail,
+    /// Run an arm
+    Leaf {
+        /// Index of the arm
+        arm: usize,
+        /// Variables the arm's pattern binds
+        bindings: Vec<Binding>,
+    },
+    /// Run an arm if its guard holds
+    Guard {
+        /// Index of the arm
+        arm: usize,
+        /// Variables the arm's pattern binds, visible to the guard
+        bindings: Vec<Binding>,
+        /// Where to continue when the guard is false
+        otherwise: Box<Decision>,
+    },
+    /// Branch on a part of the scrutinee
+    ///
+    /// Cases are tried in order and the first one that holds is taken. The
+    /// default is absent when the cases cover every possible value.
+    Switch {
+        /// Part of the scrutinee being tested
+        path: Path,
+        /// Tests and their subtrees
+        cases: Vec<(Case, Decision)>,
+        /// Subtree for values no case covers
+        default: Option<Box<Decision>>,
+    },
+}
+
+impl Decision {
+    /// Indices of the arms some path through the tree reaches, sorted.
+    #[must_use]
+    pub fn reachable_arms(&self) -> Vec<usize> {
+        fn walk(decision: &Decision, arms: &mut Vec<usize>) {
+            match decision {
+                Decision::Fail => {}
+                Decision::Leaf { arm, .. } => arms.push(*arm),
+                Decision::Guard { arm, otherwise, .. } => {
+                    arms.push(*arm);
+                    walk(otherwise, arms);
+                }
+                Decision::Switch { cases, default, .. } => {
+                    for (_, subtree) in cases {
+                        walk(subtree, arms);
+                    }
+                    if let Some(default) = default {
+                        walk(default, arms);
+                    }
+                }
+            }
+        }
+
+        let mut arms = Vec::new();
+        walk(self, &mut arms);
+        arms.sort_unstable();
+        arms.dedup();
+        arms
+    }
+}
+
+/// Compile the arms of a `match` into a decision tree.
+///
+/// `scrutinee` is the checked type of the matched value, if known; it
+/// resolves variants written without their enum, such as `Some(x)`.
+///
+/// # Errors
+///
+/// Returns an error if a pattern names an unknown enum or variant, or uses
+/// a literal that is not a constant.
+pub fn compile_match(ctx: &Context<'_>, scrutinee: Option<&Ty>, arms: &[MatchArm<'_>]) -> Result<Decision> {
+    let compiler = Compiler { ctx };
+
+    let mut rows = Vec::new();
+    for (arm, source) in arms.iter().enumerate() {
+        let row = Row {
+            columns: vec![Column { path: Path::root(), ty: scrutinee.cloned(), pattern: &source.pattern }],
+            bindings: Vec::new(),
+            arm,
+            guarded: source.guard.is_some(),
+        };
+        rows.extend(compiler.expand(row)?);
+    }
+
+    compiler.compile(rows)
+}
+
+/// A pattern yet to be matched against a part of the scrutinee.
+#[derive(Debug, Clone)]
+struct Column<'p> {
+    path: Path,
+    /// Checked type of the part of the scrutinee, where known
+    ty: Option<Ty>,
+    pattern: &'p Pattern,
+}
+
+/// A row of the pattern matrix. Only refutable patterns remain in columns;
+/// a path without a column matches anything.
+#[derive(Debug, Clone)]
+struct Row<'p> {
+    columns: Vec<Column<'p>>,
+    bindings: Vec<Binding>,
+    arm: usize,
+    guarded: bool,
+}
+
+impl Row<'_> {
+    fn column(&self, path: &Path) -> Option<usize> {
+        self.columns.iter().position(|c| c.path == *path)
+    }
+}
+
+/// A resolved enum variant.
+struct VariantInfo {
+    case: Case,
+    payload: Option<Ty>,
+    fields: Vec<Symbol>,
+    /// Number of variants of the enum
+    count: usize,
+}
+
+struct Compiler<'c, 'ctx> {
+    ctx: &'c Context<'ctx>,
+}
+
+impl Compiler<'_, '_> {
+    fn compile(&self, rows: Vec<Row<'_>>) -> Result<Decision> {
+        let Some(first) = rows.first() else {
+            return Ok(Decision::Fail);
+        };
+
+        let Some(column) = first.columns.first() else {
+            let (arm, bindings) = (first.arm, first.bindings.clone());
+            if !first.guarded {
+                return Ok(Decision::Leaf { arm, bindings });
+            }
+            let otherwise = self.compile(rows[1..].to_vec())?;
+            return Ok(Decision::Guard { arm, bindings, otherwise: Box::new(otherwise) });
+        };
+        let path = column.path.clone();
+
+        // Every distinct test applied to this path, in order of appearance
+        let mut cases = Vec::new();
+        let mut variant_count = None;
+        for row in &rows {
+            let Some(index) = row.column(&path) else { continue };
+            let (case, count) = self.case(&row.columns[index])?;
+            variant_count = variant_count.or(count);
+            if !cases.contains(&case) {
+                cases.push(case);
+            }
+        }
+        sort_lengths(&mut cases);
+
+        let complete = match cases.first() {
+            Some(Case::Variant { .. }) => variant_count.is_some_and(|count| cases.len() >= count),
+            Some(Case::Literal(Constant::Bool(_))) => cases.len() == 2,
+            Some(Case::Length { .. }) => cases.contains(&Case::Length { len: 0, rest: true }),
+            _ => false,
+        };
+
+        let mut branches = Vec::with_capacity(cases.len());
+        for case in cases {
+            let mut specialized = Vec::new();
+            for row in &rows {
+                specialized.extend(self.specialize(row, &path, &case)?);
+            }
+            let subtree = self.compile(specialized)?;
+            branches.push((case, subtree));
+        }
+
+        let default = if complete {
+            None
+        } else {
+            let rows = rows.iter().filter(|row| row.column(&path).is_none()).cloned().collect();
+            Some(Box::new(self.compile(rows)?))
+        };
+
+        Ok(Decision::Switch { path, cases: branches, default })
+    }
+
+    /// Get the test a refutable pattern performs, with the number of
+    /// variants for enum patterns.
+    fn case(&self, column: &Column<'_>) -> Result<(Case, Option<usize>)> {
+        match column.pattern {
+            Pattern::Literal { value, span } => Ok((Case::Literal(self.literal(value, *span)?), None)),
+            Pattern::Array { elements, rest, .. } => {
+                Ok((Case::Length { len: elements.len(), rest: rest.is_some() }, None))
+            }
+            Pattern::Enum { .. } | Pattern::Struct { .. } => {
+                let info = self.variant_for(column)?;
+                Ok((info.case, Some(info.count)))
+            }
+            _ => unreachable!("irrefutable patterns are expanded away"),
+        }
+    }
+
+    /// Keep a row for the branch taking `case` at `path`, expanding the
+    /// tested pattern into its subpatterns.
+    fn specialize<'p>(&self, row: &Row<'p>, path: &Path, case: &Case) -> Result<Vec<Row<'p>>> {
+        let Some(index) = row.column(path) else {
+            return Ok(vec![row.clone()]);
+        };
+        let mut row = row.clone();
+        let column = row.columns.remove(index);
+
+        match (column.pattern, case) {
+            (Pattern::Literal { value, span }, Case::Literal(expected)) => {
+                if self.literal(value, *span)? != *expected {
+                    return Ok(vec![]);
+                }
+            }
+
+            (Pattern::Array { elements, rest, .. }, Case::Length { len, rest: case_rest }) => {
+                let matches = match (rest.is_some(), case_rest) {
+                    (false, false) => elements.len() == *len,
+                    (true, _) => elements.len() <= *len,
+                    (false, true) => false,
+                };
+                if !matches {
+                    return Ok(vec![]);
+                }
+                let element_ty = match &column.ty {
+                    Some(Ty::Array(element)) => Some((**element).clone()),
+                    _ => None,
+                };
+                for (i, element) in elements.iter().enumerate() {
+                    let sub = path.then(Projection::Element(i));
+                    row.columns.push(Column { path: sub, ty: element_ty.clone(), pattern: element });
+                }
+                if let Some(rest) = rest {
+                    let sub = path.then(Projection::Rest(elements.len()));
+                    row.columns.push(Column { path: sub, ty: column.ty.clone(), pattern: rest });
+                }
+            }
+
+            (Pattern::Enum { payload, .. }, Case::Variant { variant, .. }) => {
+                let info = self.variant_for(&column)?;
+                if !matches!(&info.case, Case::Variant { variant: v, .. } if v == variant) {
+                    return Ok(vec![]);
+                }
+                if let Some(payload) = payload {
+                    let sub = path.then(Projection::Payload);
+                    row.columns.push(Column { path: sub, ty: info.payload, pattern: payload });
+                }
+            }
+
+            (Pattern::Struct { fields, span, .. }, Case::Variant { variant, .. }) => {
+                let info = self.variant_for(&column)?;
+                if !matches!(&info.case, Case::Variant { variant: v, .. } if v == variant) {
+                    return Ok(vec![]);
+                }
+                let payload = path.then(Projection::Payload);
+                let types = match &info.payload {
+                    Some(Ty::Tuple(types)) => types.clone(),
+                    _ => vec![],
+                };
+                for field in fields {
+                    let Some(i) = info.fields.iter().position(|&f| f == field.name) else {
+                        let construct = "a pattern on an unknown field";
+                        return Err(CodegenError::Unsupported { construct, span: *span });
+                    };
+                    let sub = payload.then(Projection::Element(i));
+                    push_field(&mut row, sub, types.get(i).cloned(), field);
+                }
+            }
+
+            _ => return Ok(vec![]),
+        }
+
+        self.expand(row)
+    }
+
+    /// Expand the irrefutable patterns of a row until only refutable ones
+    /// remain. Or-patterns split the row in two.
+    fn expand<'p>(&self, mut row: Row<'p>) -> Result<Vec<Row<'p>>> {
+        let mut i = 0;
+        while i < row.columns.len() {
+            let Column { path, ty, pattern } = row.columns[i].clone();
+            match pattern {
+                Pattern::Wildcard { .. } => {
+                    row.columns.remove(i);
+                }
+                Pattern::Variable { name, mutable, .. } => {
+                    row.columns.remove(i);
+                    row.bindings.push(Binding { name: *name, mutable: *mutable, path, ty });
+                }
+                Pattern::Tuple { elements, .. } => {
+                    row.columns.remove(i);
+                    let types = match ty {
+                        Some(Ty::Tuple(types)) => types,
+                        _ => vec![],
+                    };
+                    for (index, element) in elements.iter().enumerate() {
+                        let sub = path.then(Projection::Element(index));
+                        row.columns.push(Column { path: sub, ty: types.get(index).cloned(), pattern: element });
+                    }
+                }
+                Pattern::Struct { type_path, fields, .. } if !self.is_variant_path(type_path) => {
+                    row.columns.remove(i);
+                    let field_types = self.field_types(type_path);
+                    for field in fields {
+                        let sub = path.then(Projection::Field(self.name(field.name)));
+                        let ty = field_types.iter().find(|(name, _)| *name == field.name).map(|(_, ty)| ty.clone());
+                        push_field(&mut row, sub, ty, field);
+                    }
+                }
+                Pattern::Or { left, right, .. } => {
+                    let mut other = row.clone();
+                    row.columns[i].pattern = left;
+                    other.columns[i].pattern = right;
+                    let mut rows = self.expand(row)?;
+                    rows.extend(self.expand(other)?);
+                    return Ok(rows);
+                }
+                _ => i += 1,
+            }
+        }
+        Ok(vec![row])
+    }
+
+    fn name(&self, sym: Symbol) -> String {
+        self.ctx.interner.resolve(sym).unwrap_or("").to_string()
+    }
+
+    /// Whether a struct pattern's path names an enum variant, as in
+    /// `Shape::Rect { w, h }`.
+    fn is_variant_path(&self, type_path: &[Symbol]) -> bool {
+        matches!(type_path, [.., owner, _] if self.ctx.types.has_enum(self.ctx.resolve_self_name(*owner)))
+    }
+
+    /// Declared field names and types of a struct or class.
+    fn field_types(&self, type_path: &[Symbol]) -> Vec<(Symbol, Ty)> {
+        let Some(&name) = type_path.last() else { return vec![] };
+        let name = self.ctx.resolve_self_name(name);
+        let fields = match (self.ctx.types.lookup_struct(name), self.ctx.types.lookup_class(name)) {
+            (Some(info), _) => &info.fields,
+            (None, Some(info)) => &info.fields,
+            (None, None) => return vec![],
+        };
+        fields.iter().map(|f| (f.name, f.ty.clone())).collect()
+    }
+
+    fn variant_for(&self, column: &Column<'_>) -> Result<VariantInfo> {
+        match column.pattern {
+            Pattern::Enum { type_path, variant, span, .. } => {
+                self.variant(column.ty.as_ref(), type_path, *variant, *span)
+            }
+            Pattern::Struct { type_path, span, .. } => {
+                let (variant, owner) = type_path.split_last().expect("variant paths have two segments");
+                self.variant(column.ty.as_ref(), owner, *variant, *span)
+            }
+            _ => unreachable!("only enum and struct patterns name variants"),
+        }
+    }
+
+    /// Resolve a variant, taking the enum from the pattern if written and
+    /// from the type of the matched value otherwise.
+    fn variant(
+        &self,
+        scrutinee: Option<&Ty>,
+        type_path: &[Symbol],
+        variant: Symbol,
+        span: Span,
+    ) -> Result<VariantInfo> {
+        let variant_name = self.name(variant);
+
+        let written = type_path.last().map(|&owner| self.ctx.resolve_self_name(owner));
+        let owner = match (written, scrutinee) {
+            (Some(owner), _) if self.ctx.types.has_enum(owner) => Some(owner),
+            (_, Some(Ty::Enum { name, .. } | Ty::Struct { name, .. })) if self.ctx.types.has_enum(*name) => {
+                Some(*name)
+            }
+            _ => None,
+        };
+
+        if let Some(owner) = owner {
+            let info = self.ctx.types.lookup_enum(owner).expect("checked above");
+            let Some(tag) = info.variants.iter().position(|v| v.name == variant) else {
+                return Err(CodegenError::UnknownType { name: variant_name, span });
+            };
+            let found = &info.variants[tag];
+            return Ok(VariantInfo {
+                case: Case::Variant { enum_name: self.name(owner), variant: variant_name, tag },
+                payload: found.payload.clone(),
+                fields: found.fields.clone(),
+                count: info.variants.len(),
+            });
+        }
+
+        // Built-in optionals and results
+        let written = written.map(|owner| self.name(owner));
+        let builtin = match (written.as_deref(), scrutinee) {
+            (Some("Option" | "Optional"), inner) | (None, inner @ Some(Ty::Optional(_))) => {
+                let inner = match inner {
+                    Some(Ty::Optional(inner)) => Some((**inner).clone()),
+                    _ => None,
+                };
+                match variant_name.as_str() {
+                    "None" => Some(("Option", 0, None)),
+                    "Some" => Some(("Option", 1, inner)),
+                    _ => None,
+                }
+            }
+            (Some("Result"), ty) | (None, ty @ Some(Ty::Result { .. })) => {
+                let (ok, error) = match ty {
+                    Some(Ty::Result { ok, error }) => (Some((**ok).clone()), Some((**error).clone())),
+                    _ => (None, None),
+                };
+                match variant_name.as_str() {
+                    "Ok" => Some(("Result", 0, ok)),
+                    "Err" => Some(("Result", 1, error)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match builtin {
+            Some((enum_name, tag, payload)) => Ok(VariantInfo {
+                case: Case::Variant { enum_name: enum_name.to_string(), variant: variant_name, tag },
+                payload,
+                fields: vec![],
+                count: 2,
+            }),
+            None => Err(CodegenError::UnknownType { name: written.unwrap_or(variant_name), span }),
+        }
+    }
+
+    /// Evaluate a literal pattern.
+    fn literal(&self, token: &TokenKind, span: Span) -> Result<Constant> {
+        let expr = match token {
+            TokenKind::IntegerLiteral(value, type_suffix) => {
+                Expr::IntegerLiteral { value: *value, type_suffix: *type_suffix, span }
+            }
+            TokenKind::FloatLiteral(value, type_suffix) => {
+                Expr::FloatLiteral { value: *value, type_suffix: *type_suffix, span }
+            }
+            TokenKind::StringLiteral(value) => Expr::StringLiteral { value: *value, span },
+            TokenKind::BoolLiteral(value) => return Ok(Constant::Bool(*value)),
+            _ => return Err(CodegenError::Unsupported { construct: "this literal pattern", span }),
+        };
+        Ok(match eval_const(self.ctx, &expr)? {
+            ConstValue::Int(value) => Constant::Int(i64::try_from(value).map_err(|_| {
+                CodegenError::Unsupported { construct: "an integer wider than 64 bits", span: expr.span() }
+            })?),
+            ConstValue::Float(value) => Constant::Float(value),
+            ConstValue::Bool(value) => Constant::Bool(value),
+            ConstValue::String(value) => Constant::String(value),
+        })
+    }
+}
+
+/// Add a field pattern to a row; `Point { x }` binds `x` to the field.
+fn push_field<'p>(row: &mut Row<'p>, path: Path, ty: Option<Ty>, field: &'p FieldPat) {
+    match &field.pattern {
+        Some(pattern) => row.columns.push(Column { path, ty, pattern }),
+        None => row.bindings.push(Binding { name: field.name, mutable: false, path, ty }),
+    }
+}
+
+/// Order length tests so that first-match semantics are exact: fixed
+/// lengths first, then open-ended ones from the longest minimum down.
+fn sort_lengths(cases: &mut [Case]) {
+    if !matches!(cases.first(), Some(Case::Length { .. })) {
+        return;
+    }
+    cases.sort_by_key(|case| match case {
+        Case::Length { len, rest: false } => (false, *len),
+        Case::Length { len, rest: true } => (true, usize::MAX - len),
+        _ => unreachable!("a path is tested one way throughout"),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::ast::decl::{Decl, EnumVariant, StructField, Visibility};
+    use oxidex_syntax::ast::ty::Type;
+    use oxidex_typecheck::check::check_decl;
+
+    #[test]
+    fn test_enum_arms_compile_to_tag_switches() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Shape", "Circle", "Rect", "Empty", "w", "h", "r", "Int", "0", "1"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [shape, circle, rect, empty, w, h, r, int_sym, zero, one] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let field = |name| StructField { name, type_annotation: int.clone(), span };
+        let decl = Decl::Enum {
+            name: shape,
+            generics: vec![],
+            variants: vec![
+                EnumVariant::Tuple { name: circle, fields: vec![int.clone()], span },
+                EnumVariant::Struct { name: rect, fields: vec![field(w), field(h)], span },
+                EnumVariant::Unit { name: empty, span },
+            ],
+            methods: vec![],
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span,
+        };
+        check_decl(&mut ctx, &decl).unwrap();
+
+        let body = Expr::BoolLiteral { value: true, span };
+        let literal = |value| Pattern::Literal { value: TokenKind::IntegerLiteral(value, None), span };
+        let circle_pat = |payload| Pattern::Enum {
+            type_path: vec![shape],
+            variant: circle,
+            payload: Some(Box::new(payload)),
+            span,
+        };
+        let arm = |pattern, guard| MatchArm { pattern, guard, body: &body, span };
+        // match s {
+        //     Shape::Circle(0) => ..,
+        //     Shape::Circle(r) if .. => ..,
+        //     Shape::Rect { w, h: 1 } => ..,
+        //     _ => ..,
+        // }
+        let arms = [
+            arm(circle_pat(literal(zero)), None),
+            arm(circle_pat(Pattern::Variable { name: r, mutable: false, span }), Some(&body)),
+            arm(
+                Pattern::Struct {
+                    type_path: vec![shape, rect],
+                    fields: vec![
+                        FieldPat { name: w, pattern: None, span },
+                        FieldPat { name: h, pattern: Some(Box::new(literal(one))), span },
+                    ],
+                    span,
+                },
+                None,
+            ),
+            arm(Pattern::Wildcard { span }, None),
+        ];
+
+        let scrutinee = Ty::Enum { name: shape, type_args: vec![] };
+        let tree = compile_match(&ctx, Some(&scrutinee), &arms).unwrap();
+        let int = Some(Ty::Primitive(oxidex_typecheck::PrimTy::Int64));
+
+        let payload = Path::root().then(Projection::Payload);
+        let fallback = || Box::new(Decision::Leaf { arm: 3, bindings: vec![] });
+        let variant = |variant: &str, tag| Case::Variant { enum_name: "Shape".into(), variant: variant.into(), tag };
+        let circle_tree = Decision::Switch {
+            path: payload.clone(),
+            cases: vec![(Case::Literal(Constant::Int(0)), Decision::Leaf { arm: 0, bindings: vec![] })],
+            default: Some(Box::new(Decision::Guard {
+                arm: 1,
+                bindings: vec![Binding { name: r, mutable: false, path: payload.clone(), ty: int.clone() }],
+                otherwise: fallback(),
+            })),
+        };
+        let rect_tree = Decision::Switch {
+            path: payload.then(Projection::Element(1)),
+            cases: vec![(
+                Case::Literal(Constant::Int(1)),
+                Decision::Leaf {
+                    arm: 2,
+                    bindings: vec![Binding {
+                        name: w,
+                        mutable: false,
+                        path: payload.then(Projection::Element(0)),
+                        ty: int,
+                    }],
+                },
+            )],
+            default: Some(fallback()),
+        };
+        let expected = Decision::Switch {
+            path: Path::root(),
+            cases: vec![(variant("Circle", 0), circle_tree), (variant("Rect", 1), rect_tree)],
+            // `Empty` is left to the wildcard
+            default: Some(fallback()),
+        };
+        assert_eq!(tree, expected);
+        assert_eq!(tree.reachable_arms(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_or_patterns_and_array_lengths() {
+        let mut interner = StringInterner::new();
+        let [x, rest] = ["x", "rest"].map(|n| interner.intern(n));
+        let ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let body = Expr::BoolLiteral { value: true, span };
+        let var = |name| Pattern::Variable { name, mutable: false, span };
+        let wild = || Pattern::Wildcard { span };
+        let bool_pat = |value| Pattern::Literal { value: TokenKind::BoolLiteral(value), span };
+        let arm = |pattern| MatchArm { pattern, guard: None, body: &body, span };
+
+        // match xs { [] | [_] => .., [x, ..rest] => .. }
+        let arms = [
+            arm(Pattern::Or {
+                left: Box::new(Pattern::Array { elements: vec![], rest: None, span }),
+                right: Box::new(Pattern::Array { elements: vec![wild()], rest: None, span }),
+                span,
+            }),
+            arm(Pattern::Array { elements: vec![var(x)], rest: Some(Box::new(var(rest))), span }),
+        ];
+        let tree = compile_match(&ctx, None, &arms).unwrap();
+        let Decision::Switch { cases, default, .. } = &tree else { panic!() };
+        let lengths: Vec<&Case> = cases.iter().map(|(case, _)| case).collect();
+        // Exact lengths are tested before the open-ended one
+        assert_eq!(
+            lengths,
+            [&Case::Length { len: 0, rest: false }, &Case::Length { len: 1, rest: false }, &Case::Length {
+                len: 1,
+                rest: true
+            }]
+        );
+        assert_eq!(cases[1].1, Decision::Leaf { arm: 0, bindings: vec![] });
+        let Decision::Leaf { arm: 1, bindings } = &cases[2].1 else { panic!() };
+        assert_eq!(bindings[1].path, Path::root().then(Projection::Rest(1)));
+        assert_eq!(**default.as_ref().unwrap(), Decision::Fail);
+
+        // Both booleans cover a `Bool` completely
+        let arms = [arm(bool_pat(true)), arm(bool_pat(false))];
+        let tree = compile_match(&ctx, None, &arms).unwrap();
+        assert!(matches!(tree, Decision::Switch { default: None, .. }));
+    }
+}
@@ -0,0 +1,488 @@
+//! Selector and metadata emission.
+//!
+//! Backends refer to names, selectors and types by index rather than by
+//! string. This module assigns those indices once per program, so the VM
+//! chunk format and AOT images agree on them:
+//!
+//! - [`StringPool`]: every string the program needs at run time (literals,
+//!   class, field and protocol names, type encodings), deduplicated.
+//! - [`SelectorTable`]: every selector the program defines or sends,
+//!   deduplicated and interned with the runtime.
+//! - [`ClassDescriptor`] and [`ProtocolDescriptor`]: the shape of each type,
+//!   expressed as indices into the pools and into flat member tables.
+//!
+//! Indices are assigned in first-use order, metadata before code, so the
+//! same program always produces the same tables. Strings, selectors and
+//! descriptors live in the global arena and stay valid for the life of the
+//! program.
+
+use crate::error::Result;
+use crate::ir::{Constant, Inst, Module, method_symbol};
+use crate::lowering::{LoweredModule, encode_signature};
+use oxidec::runtime::encoding::types;
+use oxidec::{RuntimeString, Selector, get_global_arena};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Index of a string in a [`StringPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StringId(pub u32);
+
+/// Index of a selector in a [`SelectorTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SelectorId(pub u32);
+
+/// Index of a class in [`MetadataTables::classes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClassId(pub u32);
+
+/// Index of a protocol in [`MetadataTables::protocols`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProtocolId(pub u32);
+
+/// Convert a table length to a 32-bit index.
+fn index(len: usize) -> u32 {
+    u32::try_from(len).expect("metadata table exceeds u32::MAX entries")
+}
+
+/// Deduplicated pool of run-time strings.
+#[derive(Debug, Default)]
+pub struct StringPool {
+    entries: Vec<RuntimeString>,
+    lookup: BTreeMap<String, StringId>,
+}
+
+impl StringPool {
+    /// Add a string, returning the index of the existing entry if present.
+    pub fn intern(&mut self, s: &str) -> StringId {
+        if let Some(&id) = self.lookup.get(s) {
+            return id;
+        }
+        let id = StringId(index(self.entries.len()));
+        self.entries.push(RuntimeString::new(s, get_global_arena()));
+        self.lookup.insert(s.to_string(), id);
+        id
+    }
+
+    /// Look up the index of a string.
+    #[must_use]
+    pub fn get(&self, s: &str) -> Option<StringId> {
+        self.lookup.get(s).copied()
+    }
+
+    /// Get a string by index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index does not belong to this pool.
+    #[must_use]
+    pub fn resolve(&self, id: StringId) -> &RuntimeString {
+        &self.entries[id.0 as usize]
+    }
+
+    /// Number of strings in the pool.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the pool is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the strings in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (StringId, &RuntimeString)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (StringId(index(i)), s))
+    }
+}
+
+/// Deduplicated table of selectors.
+#[derive(Debug, Default)]
+pub struct SelectorTable {
+    entries: Vec<Selector>,
+    lookup: BTreeMap<String, SelectorId>,
+}
+
+impl SelectorTable {
+    /// Add a selector, interning it with the runtime on first use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime fails to intern the selector.
+    pub fn intern(&mut self, name: &str) -> Result<SelectorId> {
+        if let Some(&id) = self.lookup.get(name) {
+            return Ok(id);
+        }
+        let id = SelectorId(index(self.entries.len()));
+        self.entries.push(Selector::from_str(name)?);
+        self.lookup.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    /// Look up the index of a selector.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<SelectorId> {
+        self.lookup.get(name).copied()
+    }
+
+    /// Get a selector by index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index does not belong to this table.
+    #[must_use]
+    pub fn resolve(&self, id: SelectorId) -> &Selector {
+        &self.entries[id.0 as usize]
+    }
+
+    /// Number of selectors in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the table is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the selectors in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (SelectorId, &Selector)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (SelectorId(index(i)), s))
+    }
+}
+
+/// The superclass of a described class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuperclassRef {
+    /// A class described in the same tables
+    Local(ClassId),
+    /// A class already registered with the runtime, by name
+    External(StringId),
+}
+
+/// An instance variable of a described class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IvarEntry {
+    /// Field name
+    pub name: StringId,
+    /// Type encoding of the field
+    pub types: StringId,
+}
+
+/// A method of a described class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodEntry {
+    /// Selector the method is registered under
+    pub selector: SelectorId,
+    /// Type encoding of the signature
+    pub types: StringId,
+    /// Name of the IR function implementing the method
+    pub function: StringId,
+    /// Whether the method is static
+    pub is_static: bool,
+}
+
+/// A method a protocol requires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequirementEntry {
+    /// Selector of the required method
+    pub selector: SelectorId,
+    /// Type encoding of the signature
+    pub types: StringId,
+}
+
+/// Shape of a class, as indices into [`MetadataTables`].
+///
+/// Member lists are ranges into the flat member tables, so a descriptor is
+/// a fixed-size record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassDescriptor {
+    /// Class name
+    pub name: StringId,
+    /// Superclass, for classes that inherit
+    pub superclass: Option<SuperclassRef>,
+    /// Range of [`MetadataTables::ivars`] declared by the class itself
+    pub ivars: Range<u32>,
+    /// Range of [`MetadataTables::methods`]
+    pub methods: Range<u32>,
+    /// Range of [`MetadataTables::adopted`]
+    pub protocols: Range<u32>,
+}
+
+/// Shape of a protocol, as indices into [`MetadataTables`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolDescriptor {
+    /// Protocol name
+    pub name: StringId,
+    /// Range of [`MetadataTables::requirements`]
+    pub requirements: Range<u32>,
+}
+
+/// Metadata of a program, shared by the VM and AOT backends.
+#[derive(Debug, Default)]
+pub struct MetadataTables {
+    /// Strings
+    pub strings: StringPool,
+    /// Selectors
+    pub selectors: SelectorTable,
+    /// Class descriptors; superclasses precede their subclasses
+    pub classes: Vec<&'static ClassDescriptor>,
+    /// Protocol descriptors
+    pub protocols: Vec<&'static ProtocolDescriptor>,
+    /// Instance variables of all classes
+    pub ivars: Vec<IvarEntry>,
+    /// Methods of all classes
+    pub methods: Vec<MethodEntry>,
+    /// Names of the protocols each class adopts
+    pub adopted: Vec<StringId>,
+    /// Required methods of all protocols
+    pub requirements: Vec<RequirementEntry>,
+}
+
+impl MetadataTables {
+    /// Look up a class by name.
+    #[must_use]
+    pub fn class_id(&self, name: &str) -> Option<ClassId> {
+        let name = self.strings.get(name)?;
+        self.classes
+            .iter()
+            .position(|class| class.name == name)
+            .map(|i| ClassId(index(i)))
+    }
+
+    /// Get a class descriptor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index does not belong to these tables.
+    #[must_use]
+    pub fn class(&self, id: ClassId) -> &ClassDescriptor {
+        self.classes[id.0 as usize]
+    }
+
+    /// Get the methods of a class.
+    #[must_use]
+    pub fn class_methods(&self, id: ClassId) -> &[MethodEntry] {
+        let range = &self.class(id).methods;
+        &self.methods[range.start as usize..range.end as usize]
+    }
+
+    /// Get the instance variables a class declares.
+    #[must_use]
+    pub fn class_ivars(&self, id: ClassId) -> &[IvarEntry] {
+        let range = &self.class(id).ivars;
+        &self.ivars[range.start as usize..range.end as usize]
+    }
+
+    /// Get the required methods of a protocol.
+    #[must_use]
+    pub fn protocol_requirements(&self, id: ProtocolId) -> &[RequirementEntry] {
+        let range = &self.protocols[id.0 as usize].requirements;
+        &self.requirements[range.start as usize..range.end as usize]
+    }
+}
+
+/// Build the metadata tables of a program.
+///
+/// Metadata is emitted first, in the order of the lowered module, followed
+/// by the string literals and selectors used by the IR, in function order.
+///
+/// # Errors
+///
+/// Returns an error if the runtime fails to intern a selector.
+pub fn emit_tables(lowered: &LoweredModule<'_>, module: &Module) -> Result<MetadataTables> {
+    let arena = get_global_arena();
+    let mut tables = MetadataTables::default();
+
+    for protocol in &lowered.protocols {
+        let name = tables.strings.intern(&protocol.name);
+        let start = index(tables.requirements.len());
+        for (selector, encoding) in &protocol.requirements {
+            let entry = RequirementEntry {
+                selector: tables.selectors.intern(selector)?,
+                types: tables.strings.intern(encoding),
+            };
+            tables.requirements.push(entry);
+        }
+        let descriptor = ProtocolDescriptor {
+            name,
+            requirements: start..index(tables.requirements.len()),
+        };
+        tables.protocols.push(arena.alloc(descriptor));
+    }
+
+    for class in &lowered.classes {
+        let name = tables.strings.intern(&class.name);
+        let superclass = class.superclass.as_deref().map(|superclass| {
+            match lowered.classes.iter().position(|c| c.name == superclass) {
+                Some(i) => SuperclassRef::Local(ClassId(index(i))),
+                None => SuperclassRef::External(tables.strings.intern(superclass)),
+            }
+        });
+
+        let ivars_start = index(tables.ivars.len());
+        for ivar in &class.ivars {
+            // A field is encoded like the return value of its getter
+            let encoding = encode_signature(&ivar.ty, &[]);
+            let types = encoding.strip_suffix(&format!("{}{}", types::OBJECT, types::SELECTOR));
+            let entry = IvarEntry {
+                name: tables.strings.intern(&ivar.name),
+                types: tables.strings.intern(types.unwrap_or(&encoding)),
+            };
+            tables.ivars.push(entry);
+        }
+
+        let methods_start = index(tables.methods.len());
+        for method in &class.methods {
+            let entry = MethodEntry {
+                selector: tables.selectors.intern(&method.selector)?,
+                types: tables.strings.intern(&method.encoding),
+                function: tables.strings.intern(&method_symbol(&class.name, &method.selector)),
+                is_static: method.is_static(),
+            };
+            tables.methods.push(entry);
+        }
+
+        let protocols_start = index(tables.adopted.len());
+        for protocol in &class.protocols {
+            let protocol = tables.strings.intern(protocol);
+            tables.adopted.push(protocol);
+        }
+
+        let descriptor = ClassDescriptor {
+            name,
+            superclass,
+            ivars: ivars_start..index(tables.ivars.len()),
+            methods: methods_start..index(tables.methods.len()),
+            protocols: protocols_start..index(tables.adopted.len()),
+        };
+        tables.classes.push(arena.alloc(descriptor));
+    }
+
+    for function in &module.functions {
+        for inst in function.blocks.iter().flat_map(|b| &b.insts) {
+            match &inst.inst {
+                Inst::Const(Constant::String(s)) => {
+                    tables.strings.intern(s);
+                }
+                Inst::Send { selector, .. } => {
+                    tables.selectors.intern(selector)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::build_module;
+    use crate::lowering::lower;
+    use oxidex_mem::{StringInterner, Symbol};
+    use oxidex_syntax::Span;
+    use oxidex_syntax::ast::decl::{Decl, FnDecl, StructField, Visibility};
+    use oxidex_syntax::ast::expr::{CallArg, Expr};
+    use oxidex_syntax::ast::ty::Type;
+    use oxidex_typecheck::infer::Context;
+
+    #[test]
+    fn test_tables_deduplicate_and_index_metadata() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Shape", "Square", "name", "describe", "String", "self", "NSObject"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [shape, square, name, describe, string, self_sym, external] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let string_ty = Type::Simple { name: string, span };
+        let literal = Expr::StringLiteral { value: shape, span };
+        let self_expr = Expr::Identifier(self_sym);
+        let send = Expr::MethodCall { receiver: &self_expr, method: describe, args: Vec::<CallArg<'_>>::new(), span };
+        let method = |body| FnDecl {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: Some(describe),
+            generics: vec![],
+            params: vec![],
+            return_type: Some(string_ty.clone()),
+            body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let class = |name, methods| Decl::Impl { type_path: vec![name], protocol: None, methods, span };
+        let decls = vec![
+            Decl::Class {
+                name: shape,
+                generics: vec![],
+                superclass: Some(vec![external]),
+                fields: vec![StructField { name, type_annotation: string_ty.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Class {
+                name: square,
+                generics: vec![],
+                superclass: Some(vec![shape]),
+                fields: vec![],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            class(shape, vec![method(&literal)]),
+            class(square, vec![method(&send)]),
+        ];
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        let tables = emit_tables(&lowered, &module).unwrap();
+
+        // Both classes share one `describe` selector and one encoding string
+        assert_eq!(tables.selectors.len(), 1);
+        let [shape_method] = tables.class_methods(ClassId(0)) else { panic!() };
+        let [square_method] = tables.class_methods(ClassId(1)) else { panic!() };
+        assert_eq!(shape_method.selector, square_method.selector);
+        assert_eq!(shape_method.types, square_method.types);
+        assert_ne!(shape_method.function, square_method.function);
+
+        // Superclasses resolve locally when possible, by name otherwise
+        let square_id = tables.class_id("Square").unwrap();
+        assert_eq!(tables.class(square_id).superclass, Some(SuperclassRef::Local(ClassId(0))));
+        let Some(SuperclassRef::External(parent)) = tables.class(ClassId(0)).superclass else { panic!() };
+        assert_eq!(tables.strings.resolve(parent).as_str().unwrap(), "NSObject");
+
+        // Fields carry their type encoding
+        let [ivar] = tables.class_ivars(ClassId(0)) else { panic!() };
+        assert_eq!(tables.strings.resolve(ivar.types).as_str().unwrap(), "@");
+
+        // The `"Shape"` literal shares the pool entry of the class name
+        let shape_name = tables.strings.get("Shape").unwrap();
+        assert_eq!(tables.class(ClassId(0)).name, shape_name);
+        assert_eq!(tables.strings.iter().filter(|(_, s)| s.to_string() == "Shape").count(), 1);
+
+        // Emission is deterministic
+        let again = emit_tables(&lowered, &module).unwrap();
+        let strings = |t: &MetadataTables| t.strings.iter().map(|(_, s)| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(strings(&tables), strings(&again));
+        assert_eq!(format!("{tables:?}"), format!("{again:?}"));
+    }
+}
@@ -0,0 +1,104 @@
+//! Code generation errors.
+
+use oxidex_syntax::Span;
+use oxidex_typecheck::error::TypeError;
+use std::fmt;
+
+/// Code generation errors.
+#[derive(Debug, Clone)]
+pub enum CodegenError {
+    /// A declaration refers to a type the module does not define.
+    UnknownType {
+        /// Name of the type
+        name: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// A type is defined more than once in the module.
+    DuplicateType {
+        /// Name of the type
+        name: String,
+        /// Source location of the second definition
+        span: Span,
+    },
+
+    /// A class inherits from a class that is neither in the module nor
+    /// already registered with the runtime.
+    UnknownSuperclass {
+        /// Name of the class
+        class: String,
+        /// Name of the missing superclass
+        superclass: String,
+    },
+
+    /// A construct the current backend pipeline cannot lower yet.
+    Unsupported {
+        /// Description of the construct
+        construct: &'static str,
+        /// Source location
+        span: Span,
+    },
+
+    /// An attribute is unknown or misused.
+    InvalidAttribute {
+        /// What is wrong with it
+        reason: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// A type annotation could not be resolved.
+    Type(TypeError),
+
+    /// The runtime rejected a registration.
+    Runtime(oxidec::Error),
+}
+
+impl CodegenError {
+    /// Get the source location of the error, if it has one.
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UnknownType { span, .. }
+            | Self::DuplicateType { span, .. }
+            | Self::Unsupported { span, .. }
+            | Self::InvalidAttribute { span, .. } => Some(*span),
+            Self::Type(err) => Some(err.span()),
+            Self::UnknownSuperclass { .. } | Self::Runtime(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownType { name, .. } => write!(f, "unknown type `{name}`"),
+            Self::DuplicateType { name, .. } => write!(f, "type `{name}` is defined more than once"),
+            Self::UnknownSuperclass { class, superclass } => {
+                write!(f, "superclass `{superclass}` of `{class}` is not defined")
+            }
+            Self::Unsupported { construct, .. } => write!(f, "{construct} cannot be lowered yet"),
+            Self::InvalidAttribute { reason, .. } => write!(f, "invalid attribute: {reason}"),
+            Self::Type(err) => write!(f, "{err}"),
+            Self::Runtime(err) => write!(f, "runtime error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+impl From<TypeError> for CodegenError {
+    fn from(err: TypeError) -> Self {
+        Self::Type(err)
+    }
+}
+
+impl From<oxidec::Error> for CodegenError {
+    fn from(err: oxidec::Error) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// Result type for code generation.
+pub type Result<T> = std::result::Result<T, CodegenError>;
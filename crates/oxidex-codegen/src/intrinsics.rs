@@ -0,0 +1,215 @@
+//! Intrinsics: standard library operations the backends compute inline.
+//!
+//! Some std functions do less work than calling them: the length of a
+//! string or array, and arithmetic such as `abs` and `min`. The IR builder
+//! lowers calls and sends to them to an [`Inst::Intrinsic`] naming an
+//! [`Intrinsic`], which the bytecode compiler turns into a single
+//! `INTRINSIC` instruction and the AOT backend into machine instructions,
+//! rather than a generic call or message send.
+//!
+//! The registry maps std function names ([`function`]) and selectors
+//! ([`selector`]) to intrinsics. A call is only lowered when the program
+//! declares no function or extern of the same name, and a send only when
+//! its receiver is a string or a number, so programs and user classes
+//! keep their own definitions. Operands of the wrong types leave the call
+//! or send as it is.
+//!
+//! Programs compiled without the interpreter's builtins see the intrinsic
+//! functions through [`declare`].
+//!
+//! [`Inst::Intrinsic`]: crate::ir::Inst::Intrinsic
+
+use crate::ir::IrType;
+use oxidex_typecheck::infer::Context;
+use oxidex_typecheck::{PrimTy, Scheme, Ty};
+use std::fmt;
+
+/// A std operation the backends compute inline.
+///
+/// The discriminant is the intrinsic's ID, which bytecode encodes as the
+/// operand of `INTRINSIC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Intrinsic {
+    /// Number of characters of a string or elements of an array
+    Length,
+    /// Absolute value of a number
+    Abs,
+    /// Lesser of two numbers of the same type
+    Min,
+    /// Greater of two numbers of the same type
+    Max,
+    /// Square root of a float
+    Sqrt,
+}
+
+/// Std functions computed by an intrinsic, by name.
+const FUNCTIONS: [(&str, Intrinsic); 5] = [
+    ("len", Intrinsic::Length),
+    ("abs", Intrinsic::Abs),
+    ("min", Intrinsic::Min),
+    ("max", Intrinsic::Max),
+    ("sqrt", Intrinsic::Sqrt),
+];
+
+/// Std selectors computed by an intrinsic, sent to a string or number.
+const SELECTORS: [(&str, Intrinsic); 6] = [
+    ("length", Intrinsic::Length),
+    ("count", Intrinsic::Length),
+    ("abs", Intrinsic::Abs),
+    ("min:", Intrinsic::Min),
+    ("max:", Intrinsic::Max),
+    ("sqrt", Intrinsic::Sqrt),
+];
+
+impl Intrinsic {
+    /// Every intrinsic, in ID order.
+    pub const ALL: [Self; 5] = [Self::Length, Self::Abs, Self::Min, Self::Max, Self::Sqrt];
+
+    /// The intrinsic's ID.
+    #[must_use]
+    pub const fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// The intrinsic with an ID.
+    #[must_use]
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(id)).copied()
+    }
+
+    /// Name used by the IR text and disassembly.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Length => "length",
+            Self::Abs => "abs",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Sqrt => "sqrt",
+        }
+    }
+
+    /// The intrinsic with a name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|intrinsic| intrinsic.name() == name)
+    }
+
+    /// Number of operands, counting the receiver of a send.
+    #[must_use]
+    pub const fn arity(self) -> usize {
+        match self {
+            Self::Length | Self::Abs | Self::Sqrt => 1,
+            Self::Min | Self::Max => 2,
+        }
+    }
+
+    /// Whether the result depends on nothing but the operands' values. The
+    /// length of an array changes as elements are added.
+    #[must_use]
+    pub const fn is_pure(self) -> bool {
+        !matches!(self, Self::Length)
+    }
+
+    /// Type of the result for operands of `operands` types, or `None` if
+    /// the intrinsic does not take them.
+    #[must_use]
+    pub fn result_type(self, operands: &[&IrType]) -> Option<IrType> {
+        match (self, operands) {
+            (Self::Length, [IrType::String | IrType::Object(None)]) => Some(IrType::Int),
+            (Self::Abs, [ty @ (IrType::Int | IrType::Float)]) => Some((*ty).clone()),
+            (Self::Min | Self::Max, [ty @ (IrType::Int | IrType::Float), other]) if ty == other => {
+                Some((*ty).clone())
+            }
+            (Self::Sqrt, [IrType::Float]) => Some(IrType::Float),
+            _ => None,
+        }
+    }
+
+    /// Signature of the std function computed by the intrinsic.
+    #[must_use]
+    pub fn signature(self) -> Scheme {
+        let var = Ty::TypeVar(0);
+        let (params, return_type) = match self {
+            Self::Length => (vec![var], Ty::Primitive(PrimTy::Int64)),
+            Self::Abs => (vec![var.clone()], var),
+            Self::Min | Self::Max => (vec![var.clone(), var.clone()], var),
+            Self::Sqrt => {
+                let float = Ty::Primitive(PrimTy::Float64);
+                return Scheme::mono(Ty::Function {
+                    params: vec![float.clone()],
+                    return_type: Box::new(float),
+                    labels: vec![None],
+                });
+            }
+        };
+        let labels = vec![None; params.len()];
+        Scheme::poly(vec![0], Ty::Function { params, return_type: Box::new(return_type), labels })
+    }
+}
+
+impl fmt::Display for Intrinsic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// The intrinsic computing the std function `name`.
+#[must_use]
+pub fn function(name: &str) -> Option<Intrinsic> {
+    FUNCTIONS.iter().find(|(candidate, _)| *candidate == name).map(|&(_, intrinsic)| intrinsic)
+}
+
+/// The intrinsic computing the std method `selector`.
+#[must_use]
+pub fn selector(selector: &str) -> Option<Intrinsic> {
+    SELECTORS.iter().find(|(candidate, _)| *candidate == selector).map(|&(_, intrinsic)| intrinsic)
+}
+
+/// Bind the signature of every intrinsic function the program mentions in
+/// the type checker's environment, so calls to them type check. The
+/// program's own declarations of the same names replace them.
+pub fn declare(ctx: &mut Context<'_>) {
+    for (name, intrinsic) in FUNCTIONS {
+        if let Some(sym) = ctx.interner.get_symbol(name) {
+            ctx.env.bind(sym, intrinsic.signature());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_maps_std_names_to_intrinsics() {
+        for (id, intrinsic) in Intrinsic::ALL.into_iter().enumerate() {
+            assert_eq!(usize::from(intrinsic.id()), id);
+            assert_eq!(Intrinsic::from_id(intrinsic.id()), Some(intrinsic));
+            assert_eq!(Intrinsic::from_name(intrinsic.name()), Some(intrinsic));
+        }
+        assert_eq!(Intrinsic::from_id(5), None);
+
+        assert_eq!(function("len"), Some(Intrinsic::Length));
+        assert_eq!(function("sqrt"), Some(Intrinsic::Sqrt));
+        assert_eq!(function("print"), None);
+        assert_eq!(selector("count"), Some(Intrinsic::Length));
+        assert_eq!(selector("max:"), Some(Intrinsic::Max));
+        assert_eq!(selector("max"), None);
+    }
+
+    #[test]
+    fn test_result_types() {
+        let (int, float, string) = (IrType::Int, IrType::Float, IrType::String);
+        let point = IrType::Object(Some("Point".to_string()));
+        assert_eq!(Intrinsic::Length.result_type(&[&string]), Some(IrType::Int));
+        assert_eq!(Intrinsic::Length.result_type(&[&IrType::Object(None)]), Some(IrType::Int));
+        assert_eq!(Intrinsic::Length.result_type(&[&point]), None);
+        assert_eq!(Intrinsic::Abs.result_type(&[&float]), Some(IrType::Float));
+        assert_eq!(Intrinsic::Min.result_type(&[&int, &int]), Some(IrType::Int));
+        assert_eq!(Intrinsic::Max.result_type(&[&int, &float]), None);
+        assert_eq!(Intrinsic::Sqrt.result_type(&[&int]), None);
+        assert_eq!(Intrinsic::Abs.result_type(&[&int, &int]), None);
+    }
+}
@@ -0,0 +1,2455 @@
+//! Construction of the IR from the AST.
+//!
+//! Functions are built directly in SSA form with the algorithm of Braun et
+//! al. ("Simple and Efficient Construction of Static Single Assignment
+//! Form"): local variables are never stored in memory, and a read looks
+//! the variable's current definition up through the predecessors of the
+//! block, inserting phis where definitions meet. Blocks whose predecessors
+//! are not all known yet (loop headers) are left unsealed and receive their
+//! phi operands once sealed; phis that turn out to select a single value
+//! are removed.
+//!
+//! The program must have been checked, and its types lowered with
+//! [`crate::lowering::lower`]: method calls are resolved to selectors
+//! through the lowered classes. Calls to std functions the program does not
+//! declare itself, and std methods of strings and numbers, become
+//! [intrinsics](crate::intrinsics) when their operands have the types the
+//! intrinsic takes.
+//!
+//! `for` loops follow the iteration protocol: the sequence is asked for an
+//! iterator with `makeIterator()`, whose `next()` is sent until it returns
+//! `nil`. A user type with `next()` but no `makeIterator()` is its own
+//! iterator.
+//!
+//! The body of a `defer` becomes a closure, called where its block ends
+//! and before each `return` it encloses. The blocks between the `defer`
+//! and the end of its block are covered by a [`Cleanup`] whose handler
+//! calls the closure too, so the deferred code also runs when an error
+//! unwinds out of them.
+
+use super::{
+    Block, BlockId, Cleanup, Constant, Export, Extern, Function, Inst, Instruction, IrType, Module, Phi, Terminator,
+    ValueId, method_symbol,
+};
+use crate::closure::{self, Conversion};
+use crate::error::{CodegenError, Result};
+use crate::decision::{Binding, Case, Decision, Path, Projection, compile_match};
+use crate::intrinsics::{self, Intrinsic};
+use crate::lowering::{LoweredModule, TypeKind, selector_name};
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::{Attribute, Decl, ExternFn, FnParam};
+use oxidex_syntax::ast::expr::{BinaryOp, CallArg, Expr, InterpolationPart, MatchArm, UnaryOp};
+use oxidex_syntax::ast::pat::Pattern;
+use oxidex_syntax::ast::stmt::Stmt;
+use oxidex_syntax::ast::ty::Type;
+use oxidex_syntax::span::{Span, Spanned};
+use oxidex_typecheck::check::{ConstValue, ast_to_ty, binary_bound, comptime_branch, eval_const, unary_bound};
+use oxidex_typecheck::infer::Context;
+use oxidex_typecheck::{PrimTy, Ty};
+use std::collections::{HashMap, HashSet};
+
+/// Build the IR of every function and method of a program.
+///
+/// # Errors
+///
+/// Returns an error if a body uses a construct that has no IR lowering yet
+/// (typed holes, or a `for` loop pattern other than a variable or tuple),
+/// if an annotation fails to resolve, or if an attribute is unknown or
+/// misused.
+pub fn build_module(ctx: &mut Context<'_>, lowered: &LoweredModule<'_>, decls: &[Decl<'_>]) -> Result<Module> {
+    let mut module = Module::default();
+    let defined = declared_functions(ctx, decls);
+
+    for decl in decls {
+        if let Decl::Fn { name, generics, params, return_type, body, attributes, .. } = decl {
+            let name = ctx.interner.resolve(*name).unwrap_or("").to_string();
+            for attribute in attributes {
+                let Some(export) = build_export(ctx, &name, generics, attribute)? else {
+                    continue;
+                };
+                if module.exports.iter().any(|other| other.symbol == export.symbol) {
+                    let reason = format!("symbol `{}` is exported more than once", export.symbol);
+                    return Err(CodegenError::InvalidAttribute { reason, span: attribute.span });
+                }
+                module.exports.push(export);
+            }
+            let source = FnSource {
+                name,
+                receiver: None,
+                generics,
+                params,
+                return_type: return_type.as_ref(),
+                is_init: false,
+                body,
+            };
+            module.functions.extend(build_function(ctx, lowered, &defined, &source)?);
+        } else if let Decl::Extern { library, functions, .. } = decl {
+            let library = library.map(|library| ctx.interner.resolve(library).unwrap_or("").to_string());
+            for function in functions {
+                module.externs.push(build_extern(ctx, library.clone(), function)?);
+            }
+        }
+    }
+
+    for class in &lowered.classes {
+        for method in &class.methods {
+            let decl = method.decl;
+            let source = FnSource {
+                name: method_symbol(&class.name, &method.selector),
+                receiver: (!decl.is_static).then_some(class.name.as_str()),
+                generics: &decl.generics,
+                params: &decl.params,
+                return_type: decl.return_type.as_ref(),
+                is_init: decl.is_init,
+                body: decl.body,
+            };
+            module.functions.extend(build_function(ctx, lowered, &defined, &source)?);
+        }
+    }
+
+    Ok(module)
+}
+
+/// Names of the functions and externs the program declares.
+fn declared_functions(ctx: &Context<'_>, decls: &[Decl<'_>]) -> HashSet<String> {
+    let mut names = Vec::new();
+    for decl in decls {
+        match decl {
+            Decl::Fn { name, .. } => names.push(*name),
+            Decl::Extern { functions, .. } => names.extend(functions.iter().map(|function| function.name)),
+            _ => {}
+        }
+    }
+    names.into_iter().map(|name| ctx.interner.resolve(name).unwrap_or("").to_string()).collect()
+}
+
+/// The extern a function of an `extern` block declares, with its signature
+/// as a type encoding. The checker has made sure every type has one.
+fn build_extern(ctx: &mut Context<'_>, library: Option<String>, function: &ExternFn) -> Result<Extern> {
+    let mut encoding = String::new();
+    let params = function.params.iter().map(|param| Some(&param.type_annotation));
+    for ty in std::iter::once(function.return_type.as_ref()).chain(params) {
+        let c = match ty {
+            Some(ty) => match ast_to_ty(ctx, ty)? {
+                Ty::Primitive(prim) => prim.c_encoding(),
+                _ => None,
+            },
+            None => PrimTy::Unit.c_encoding(),
+        };
+        let Some(c) = c else {
+            let construct = "extern signature type without a C counterpart";
+            return Err(CodegenError::Unsupported { construct, span: function.span });
+        };
+        encoding.push(c);
+    }
+    let name = ctx.interner.resolve(function.name).unwrap_or("").to_string();
+    Ok(Extern { name, library, encoding })
+}
+
+/// The export an `@export("symbol")` attribute of a function asks for.
+/// The symbol must be a C identifier, and the function not generic, since
+/// C sees a single signature. `@test` and `@bench` mark a test for
+/// `ox test` and a benchmark for `ox bench`, and ask for nothing.
+fn build_export(
+    ctx: &Context<'_>,
+    function: &str,
+    generics: &[Symbol],
+    attribute: &Attribute,
+) -> Result<Option<Export>> {
+    let invalid = |reason: String| Err(CodegenError::InvalidAttribute { reason, span: attribute.span });
+    let name = ctx.interner.resolve(attribute.name).unwrap_or("");
+    match name {
+        "export" => {}
+        "test" | "bench" if attribute.args.is_empty() => return Ok(None),
+        "test" | "bench" => return invalid(format!("`@{name}` takes no arguments")),
+        _ => return invalid(format!("unknown attribute `@{name}`")),
+    }
+    let [symbol] = attribute.args[..] else {
+        return invalid("`@export` takes the symbol name as its only argument".to_string());
+    };
+    let symbol = ctx.interner.resolve(symbol).unwrap_or("");
+    let mut chars = symbol.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return invalid(format!("`{symbol}` is not a C identifier"));
+    }
+    if !generics.is_empty() {
+        return invalid(format!("generic function `{function}` cannot be exported"));
+    }
+    Ok(Some(Export { function: function.to_string(), symbol: symbol.to_string() }))
+}
+
+/// The parts of a function or method declaration the builder needs.
+struct FnSource<'s, 'a> {
+    name: String,
+    receiver: Option<&'s str>,
+    generics: &'s [Symbol],
+    params: &'s [FnParam],
+    return_type: Option<&'s Type>,
+    is_init: bool,
+    body: &'s Expr<'a>,
+}
+
+/// Build a single function, followed by the functions lifted out of its
+/// closures.
+fn build_function(
+    ctx: &mut Context<'_>,
+    lowered: &LoweredModule<'_>,
+    defined: &HashSet<String>,
+    source: &FnSource<'_, '_>,
+) -> Result<Vec<Function>> {
+    ctx.push_generic_params(source.generics);
+    let result = FnBuilder::new(ctx, lowered, defined, source.receiver).build(source);
+    ctx.pop_generic_params(source.generics);
+    result
+}
+
+/// Arms of the `match` being lowered.
+struct MatchState<'m, 'a> {
+    arms: &'m [MatchArm<'a>],
+    /// Block holding each arm's body
+    blocks: Vec<BlockId>,
+    /// Variables bound by each arm's pattern
+    vars: Vec<HashMap<Symbol, Var>>,
+}
+
+/// A local variable. Shadowing declarations get distinct variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Var(u32);
+
+/// Code a `defer` left to run when its block ends.
+struct Deferred {
+    /// Closure running the deferred code
+    closure: ValueId,
+    /// Blocks lowered since the `defer`, which an error leaves through its
+    /// cleanup
+    blocks: Vec<BlockId>,
+}
+
+/// A variable a closure captures, as the lifted function receives it.
+struct Captured {
+    name: Symbol,
+    ty: IrType,
+    /// Type of the value in the box, for variables captured by box
+    boxed: Option<IrType>,
+}
+
+/// State for building one function.
+struct FnBuilder<'b, 'ctx, 'a> {
+    ctx: &'b mut Context<'ctx>,
+    lowered: &'b LoweredModule<'a>,
+    /// Functions and externs the program declares, which calls to by name
+    /// reach rather than an intrinsic
+    defined: &'b HashSet<String>,
+    func: Function,
+    current: BlockId,
+    /// Predecessors of each block, as edges are added
+    preds: Vec<Vec<BlockId>>,
+    sealed: HashSet<BlockId>,
+    /// Current definition of each variable at the end of each block
+    defs: HashMap<(Var, BlockId), ValueId>,
+    /// Phis of unsealed blocks still waiting for their operands
+    incomplete: HashMap<BlockId, Vec<(Var, ValueId)>>,
+    /// Block each phi lives in
+    phi_blocks: HashMap<ValueId, BlockId>,
+    var_types: Vec<IrType>,
+    /// Source name of each named variable
+    var_names: HashMap<Var, String>,
+    scopes: Vec<HashMap<Symbol, Var>>,
+    /// Class of the receiver, in instance methods
+    receiver: Option<String>,
+    /// Variable holding `self`, in instance methods
+    self_var: Option<Var>,
+    /// Variables living in a box, with the type of the value inside
+    boxed: HashMap<Var, IrType>,
+    /// Closures lowered so far, which number the next one
+    closures: usize,
+    /// Functions lifted out of closures, including nested ones
+    lifted: Vec<Function>,
+    /// Type each closure created in this function returns
+    closure_returns: HashMap<ValueId, IrType>,
+    /// Code deferred by the blocks being lowered, innermost last
+    defers: Vec<Deferred>,
+    /// Location of the expression or statement being lowered
+    span: Option<Span>,
+}
+
+impl<'b, 'ctx, 'a> FnBuilder<'b, 'ctx, 'a> {
+    fn new(
+        ctx: &'b mut Context<'ctx>,
+        lowered: &'b LoweredModule<'a>,
+        defined: &'b HashSet<String>,
+        receiver: Option<&str>,
+    ) -> Self {
+        let entry = Block {
+            id: BlockId(0),
+            phis: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        };
+        Self {
+            ctx,
+            lowered,
+            defined,
+            func: Function {
+                name: String::new(),
+                params: Vec::new(),
+                return_type: IrType::Unit,
+                blocks: vec![entry],
+                values: Vec::new(),
+                cleanups: Vec::new(),
+                variables: Vec::new(),
+            },
+            current: BlockId(0),
+            preds: vec![Vec::new()],
+            sealed: HashSet::from([BlockId(0)]),
+            defs: HashMap::new(),
+            incomplete: HashMap::new(),
+            phi_blocks: HashMap::new(),
+            var_types: Vec::new(),
+            var_names: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            receiver: receiver.map(str::to_string),
+            self_var: None,
+            boxed: HashMap::new(),
+            closures: 0,
+            lifted: Vec::new(),
+            closure_returns: HashMap::new(),
+            defers: Vec::new(),
+            span: None,
+        }
+    }
+
+    fn build(mut self, source: &FnSource<'_, '_>) -> Result<Vec<Function>> {
+        self.func.name.clone_from(&source.name);
+
+        if let Some(class) = self.receiver.clone() {
+            let ty = IrType::Object(Some(class));
+            let value = self.new_value(ty.clone());
+            self.func.params.push(value);
+            let var = self.new_var(ty);
+            self.var_names.insert(var, "self".to_string());
+            self.write_var(var, self.current, value);
+            if let Some(sym) = self.ctx.interner.get_symbol("self") {
+                self.scopes[0].insert(sym, var);
+            }
+            self.self_var = Some(var);
+        }
+        self.params(source.params)?;
+        self.func.return_type = match (source.return_type, &self.receiver) {
+            (Some(ty), _) => self.lower_type(ty)?,
+            (None, Some(class)) if source.is_init => IrType::Object(Some(class.clone())),
+            (None, _) => IrType::Unit,
+        };
+
+        let value = self.lower_expr(source.body)?;
+        let returned = if source.is_init {
+            // Initializers return the initialized receiver
+            self.func.params.first().copied()
+        } else if self.func.return_type == IrType::Unit {
+            None
+        } else {
+            Some(value)
+        };
+        self.terminate(Terminator::Return(returned));
+
+        Ok(self.finish_with_closures())
+    }
+
+    /// Build the function lifted out of a closure: the captured variables
+    /// are its first parameters (see [`crate::closure`]).
+    fn build_closure(
+        mut self,
+        conversion: &Conversion,
+        captured: Vec<Captured>,
+        params: &[FnParam],
+        body: &Expr<'_>,
+    ) -> Result<Vec<Function>> {
+        self.func.name.clone_from(&conversion.function);
+
+        let self_sym = self.ctx.interner.get_symbol("self");
+        for capture in captured {
+            let value = self.new_value(capture.ty.clone());
+            self.func.params.push(value);
+            let var = self.new_var(capture.ty);
+            self.name_var(var, capture.name);
+            self.scopes[0].insert(capture.name, var);
+            self.write_var(var, self.current, value);
+            if let Some(ty) = capture.boxed {
+                self.boxed.insert(var, ty);
+            }
+            if Some(capture.name) == self_sym && self.receiver.is_some() {
+                self.self_var = Some(var);
+            }
+        }
+        self.params(params)?;
+        self.func.return_type = self.ir_type(&conversion.return_type);
+
+        let value = self.lower_expr(body)?;
+        let returned = (self.func.return_type != IrType::Unit).then_some(value);
+        self.terminate(Terminator::Return(returned));
+
+        Ok(self.finish_with_closures())
+    }
+
+    /// Declare the parameters of the function.
+    fn params(&mut self, params: &[FnParam]) -> Result<()> {
+        for param in params {
+            let ty = self.lower_type(&param.type_annotation)?;
+            let value = self.new_value(ty.clone());
+            self.func.params.push(value);
+            self.declare_local(param.name, ty, value, param.span);
+        }
+        Ok(())
+    }
+
+    // ===== Values and blocks =====
+
+    fn new_value(&mut self, ty: IrType) -> ValueId {
+        let id = ValueId(u32::try_from(self.func.values.len()).expect("too many values"));
+        self.func.values.push(ty);
+        id
+    }
+
+    fn emit(&mut self, inst: Inst, ty: IrType) -> ValueId {
+        self.emit_in(self.current, inst, ty)
+    }
+
+    fn emit_in(&mut self, block: BlockId, inst: Inst, ty: IrType) -> ValueId {
+        let result = self.new_value(ty);
+        self.func.blocks[block.0 as usize].insts.push(Instruction { result, inst, span: self.span });
+        result
+    }
+
+    fn constant(&mut self, constant: Constant) -> ValueId {
+        let ty = match &constant {
+            Constant::Unit => IrType::Unit,
+            Constant::Nil => IrType::Object(None),
+            Constant::Bool(_) => IrType::Bool,
+            Constant::Int(_) => IrType::Int,
+            Constant::Float(_) => IrType::Float,
+            Constant::String(_) => IrType::String,
+        };
+        self.emit(Inst::Const(constant), ty)
+    }
+
+    fn unit(&mut self) -> ValueId {
+        self.constant(Constant::Unit)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        let id = BlockId(u32::try_from(self.func.blocks.len()).expect("too many blocks"));
+        self.func.blocks.push(Block {
+            id,
+            phis: Vec::new(),
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        self.preds.push(Vec::new());
+        for deferred in &mut self.defers {
+            deferred.blocks.push(id);
+        }
+        id
+    }
+
+    /// Whether control can reach the block.
+    ///
+    /// Code following a `return` is lowered into a block without
+    /// predecessors; nothing it does can flow into live code. Handlers of
+    /// cleanups have no predecessors either, but errors enter them.
+    fn is_dead(&self, block: BlockId) -> bool {
+        block != BlockId(0)
+            && self.sealed.contains(&block)
+            && self.preds[block.0 as usize].is_empty()
+            && !self.func.cleanups.iter().any(|cleanup| cleanup.handler == block)
+    }
+
+    /// End the current block. Dead blocks keep their `Unreachable`
+    /// terminator and add no edges.
+    fn terminate(&mut self, terminator: Terminator) {
+        if self.is_dead(self.current) {
+            return;
+        }
+        for succ in terminator.successors() {
+            self.preds[succ.0 as usize].push(self.current);
+        }
+        self.func.blocks[self.current.0 as usize].terminator = terminator;
+    }
+
+    /// Continue in a fresh block that nothing jumps to.
+    fn start_dead_block(&mut self) {
+        let dead = self.new_block();
+        self.seal(dead);
+        self.current = dead;
+    }
+
+    // ===== SSA construction =====
+
+    fn new_var(&mut self, ty: IrType) -> Var {
+        let var = Var(u32::try_from(self.var_types.len()).expect("too many variables"));
+        self.var_types.push(ty);
+        var
+    }
+
+    fn declare(&mut self, name: Symbol, ty: IrType, value: ValueId) -> Var {
+        let var = self.new_var(ty);
+        self.name_var(var, name);
+        self.scopes.last_mut().expect("no scope").insert(name, var);
+        self.write_var(var, self.current, value);
+        var
+    }
+
+    /// Declare a variable declared at `span`, in a box if a closure
+    /// captures it by box.
+    fn declare_local(&mut self, name: Symbol, ty: IrType, value: ValueId, span: Span) -> Var {
+        if !self.ctx.boxed.contains(&span) {
+            return self.declare(name, ty, value);
+        }
+        let cell = self.emit(Inst::Box(value), IrType::Object(None));
+        let var = self.declare(name, IrType::Object(None), cell);
+        self.boxed.insert(var, ty);
+        var
+    }
+
+    fn lookup_var(&self, name: Symbol) -> Option<Var> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name).copied())
+    }
+
+    /// Give a variable the name it has in the source.
+    fn name_var(&mut self, var: Var, name: Symbol) {
+        if let Some(name) = self.ctx.interner.resolve(name) {
+            self.var_names.insert(var, name.to_string());
+        }
+    }
+
+    fn write_var(&mut self, var: Var, block: BlockId, value: ValueId) {
+        self.defs.insert((var, block), value);
+        if let Some(name) = self.var_names.get(&var)
+            && !self.func.variables.iter().any(|(held, known)| *held == value && known == name)
+        {
+            self.func.variables.push((value, name.clone()));
+        }
+    }
+
+    fn read_var(&mut self, var: Var, block: BlockId) -> ValueId {
+        match self.defs.get(&(var, block)) {
+            Some(&value) => value,
+            None => self.read_var_recursive(var, block),
+        }
+    }
+
+    fn read_var_recursive(&mut self, var: Var, block: BlockId) -> ValueId {
+        let ty = self.var_types[var.0 as usize].clone();
+        let value = if !self.sealed.contains(&block) {
+            let phi = self.new_phi(block, ty);
+            self.incomplete.entry(block).or_default().push((var, phi));
+            phi
+        } else {
+            match self.preds[block.0 as usize].as_slice() {
+                // Only reachable from dead code; the value is never observed
+                [] => self.emit_in(block, Inst::Const(Constant::Unit), ty),
+                [pred] => {
+                    let pred = *pred;
+                    self.read_var(var, pred)
+                }
+                _ => {
+                    let phi = self.new_phi(block, ty);
+                    self.write_var(var, block, phi);
+                    self.add_phi_operands(var, phi, block)
+                }
+            }
+        };
+        self.write_var(var, block, value);
+        value
+    }
+
+    fn new_phi(&mut self, block: BlockId, ty: IrType) -> ValueId {
+        let result = self.new_value(ty);
+        self.func.blocks[block.0 as usize].phis.push(Phi { result, incoming: Vec::new() });
+        self.phi_blocks.insert(result, block);
+        result
+    }
+
+    fn phi_mut(&mut self, phi: ValueId) -> Option<&mut Phi> {
+        let block = *self.phi_blocks.get(&phi)?;
+        self.func.blocks[block.0 as usize].phis.iter_mut().find(|p| p.result == phi)
+    }
+
+    fn add_phi_operands(&mut self, var: Var, phi: ValueId, block: BlockId) -> ValueId {
+        for pred in self.preds[block.0 as usize].clone() {
+            let value = self.read_var(var, pred);
+            if let Some(node) = self.phi_mut(phi) {
+                node.incoming.push((pred, value));
+            }
+        }
+        self.remove_trivial_phi(phi)
+    }
+
+    /// Replace a phi whose operands are all the same value (or the phi
+    /// itself) by that value.
+    fn remove_trivial_phi(&mut self, phi: ValueId) -> ValueId {
+        let Some(block) = self.phi_blocks.get(&phi).copied() else {
+            return phi;
+        };
+        let Some(node) = self.func.blocks[block.0 as usize].phis.iter().find(|p| p.result == phi) else {
+            return phi;
+        };
+
+        let mut same = None;
+        for &(_, value) in &node.incoming {
+            if Some(value) == same || value == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(value);
+        }
+        let same = match same {
+            Some(value) => value,
+            // Only reachable from dead code
+            None => self.emit_in(block, Inst::Const(Constant::Unit), self.func.value_type(phi).clone()),
+        };
+
+        // Phis that used this one may become trivial in turn
+        let users: Vec<ValueId> = self
+            .func
+            .blocks
+            .iter()
+            .flat_map(|b| &b.phis)
+            .filter(|p| p.result != phi && p.incoming.iter().any(|&(_, v)| v == phi))
+            .map(|p| p.result)
+            .collect();
+
+        self.func.blocks[block.0 as usize].phis.retain(|p| p.result != phi);
+        self.phi_blocks.remove(&phi);
+        self.func.replace_uses(phi, same);
+        for def in self.defs.values_mut() {
+            if *def == phi {
+                *def = same;
+            }
+        }
+        for pending in self.incomplete.values_mut() {
+            for (_, value) in pending.iter_mut() {
+                if *value == phi {
+                    *value = same;
+                }
+            }
+        }
+
+        for user in users {
+            if !self.is_incomplete(user) {
+                self.remove_trivial_phi(user);
+            }
+        }
+        same
+    }
+
+    fn is_incomplete(&self, phi: ValueId) -> bool {
+        self.incomplete.values().flatten().any(|&(_, value)| value == phi)
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        if let Some(pending) = self.incomplete.remove(&block) {
+            for (var, phi) in pending {
+                self.add_phi_operands(var, phi, block);
+            }
+        }
+        self.sealed.insert(block);
+    }
+
+    /// Merge values flowing into `block` from its predecessors.
+    fn merge(&mut self, block: BlockId, incoming: Vec<(BlockId, ValueId)>) -> ValueId {
+        let mut types = incoming.iter().map(|&(_, v)| self.func.value_type(v));
+        let ty = match types.next() {
+            Some(first) if types.all(|ty| ty == first) => first.clone(),
+            Some(_) => IrType::Object(None),
+            None => IrType::Unit,
+        };
+        let phi = self.new_phi(block, ty);
+        if let Some(node) = self.phi_mut(phi) {
+            node.incoming = incoming;
+        }
+        self.remove_trivial_phi(phi)
+    }
+
+    /// Finish the function, followed by the functions lifted out of its
+    /// closures.
+    fn finish_with_closures(mut self) -> Vec<Function> {
+        let lifted = std::mem::take(&mut self.lifted);
+        std::iter::once(self.finish()).chain(lifted).collect()
+    }
+
+    /// Drop dead blocks and renumber the rest. The handler of a cleanup
+    /// lives as long as one of the blocks it covers.
+    fn finish(mut self) -> Function {
+        let mut live = vec![false; self.func.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(block) = stack.pop() {
+            if std::mem::replace(&mut live[block.0 as usize], true) {
+                continue;
+            }
+            stack.extend(self.func.block(block).terminator.successors());
+            stack.extend(self.func.handler(block));
+        }
+
+        let mut renumber = HashMap::new();
+        for (index, _) in live.iter().enumerate().filter(|&(_, &live)| live) {
+            let id = BlockId(u32::try_from(renumber.len()).expect("too many blocks"));
+            renumber.insert(BlockId(u32::try_from(index).expect("too many blocks")), id);
+        }
+        let blocks = std::mem::take(&mut self.func.blocks);
+        for mut block in blocks.into_iter().filter(|b| live[b.id.0 as usize]) {
+            block.id = renumber[&block.id];
+            for phi in &mut block.phis {
+                phi.incoming.retain(|(pred, _)| renumber.contains_key(pred));
+                for (pred, _) in &mut phi.incoming {
+                    *pred = renumber[pred];
+                }
+            }
+            block.terminator = match block.terminator {
+                Terminator::Jump(target) => Terminator::Jump(renumber[&target]),
+                Terminator::Branch { cond, then_block, else_block } => Terminator::Branch {
+                    cond,
+                    then_block: renumber[&then_block],
+                    else_block: renumber[&else_block],
+                },
+                Terminator::Switch { value, cases, default } => Terminator::Switch {
+                    value,
+                    cases: cases.into_iter().map(|(case, target)| (case, renumber[&target])).collect(),
+                    default: renumber[&default],
+                },
+                other => other,
+            };
+            self.func.blocks.push(block);
+        }
+        let cleanups = std::mem::take(&mut self.func.cleanups);
+        for mut cleanup in cleanups.into_iter().filter(|cleanup| live[cleanup.handler.0 as usize]) {
+            cleanup.handler = renumber[&cleanup.handler];
+            cleanup.blocks = cleanup.blocks.iter().filter_map(|block| renumber.get(block).copied()).collect();
+            self.func.cleanups.push(cleanup);
+        }
+
+        // Removing trivial phis can make a variable hold a value twice, and
+        // dead blocks take the values they defined with them
+        let mut defined: HashSet<ValueId> = self.func.params.iter().copied().collect();
+        for block in &self.func.blocks {
+            defined.extend(block.phis.iter().map(|phi| phi.result).chain(block.insts.iter().map(|inst| inst.result)));
+        }
+        let mut seen = HashSet::new();
+        self.func.variables.retain(|variable| defined.contains(&variable.0) && seen.insert(variable.clone()));
+        self.func
+    }
+
+    // ===== Names and types =====
+
+    fn name(&self, sym: Symbol) -> String {
+        self.ctx.interner.resolve(sym).unwrap_or("").to_string()
+    }
+
+    /// Resolve a type name, mapping `Self` to the receiver's class.
+    fn type_name(&self, path: &[Symbol]) -> String {
+        let name = path.last().map(|&sym| self.name(sym)).unwrap_or_default();
+        match (&self.receiver, name.as_str()) {
+            (Some(class), "Self") => class.clone(),
+            _ => name,
+        }
+    }
+
+    fn lower_type(&mut self, ty: &Type) -> Result<IrType> {
+        let ty = ast_to_ty(self.ctx, ty)?;
+        Ok(self.ir_type(&ty))
+    }
+
+    fn ir_type(&self, ty: &Ty) -> IrType {
+        match ty {
+            Ty::SelfType => IrType::Object(self.receiver.clone()),
+            ty => IrType::from_ty(ty, self.ctx.interner),
+        }
+    }
+
+    fn is_type(&self, name: &str) -> bool {
+        self.lowered.class(name).is_some()
+    }
+
+    fn labels(&self, args: &[CallArg<'_>]) -> Vec<Option<String>> {
+        args.iter().map(|arg| arg.label.map(|l| self.name(l))).collect()
+    }
+
+    /// Emit `intrinsic` on `args`, unless there is no intrinsic or it does
+    /// not take operands of their types.
+    fn intrinsic(&mut self, intrinsic: Option<Intrinsic>, args: &[ValueId]) -> Option<ValueId> {
+        let intrinsic = intrinsic?;
+        let operands: Vec<&IrType> = args.iter().map(|&arg| self.func.value_type(arg)).collect();
+        let ty = intrinsic.result_type(&operands)?;
+        Some(self.emit(Inst::Intrinsic { intrinsic, args: args.to_vec() }, ty))
+    }
+
+    /// Resolve a method to its selector and result type.
+    ///
+    /// Calls that do not resolve (the receiver's type is unknown and the
+    /// name is ambiguous) fall back to a selector built from the call-site
+    /// labels, and are left for dispatch to resolve.
+    fn resolve_method(
+        &self,
+        class: Option<&str>,
+        name: &str,
+        args: &[CallArg<'_>],
+        is_static: bool,
+    ) -> (String, IrType) {
+        let labels = self.labels(args);
+        match self.lowered.resolve_method(class, name, &labels, is_static) {
+            Some(method) => {
+                let ty = match &method.return_type {
+                    Ty::SelfType => IrType::Object(class.map(str::to_string)),
+                    ty => self.ir_type(ty),
+                };
+                (method.selector.clone(), ty)
+            }
+            None => {
+                let labels: Vec<String> = labels.into_iter().map(Option::unwrap_or_default).collect();
+                (selector_name(name, &labels), IrType::Object(None))
+            }
+        }
+    }
+
+    // ===== Expressions =====
+
+    /// Lower an expression, locating the instructions it emits at it.
+    fn lower_expr(&mut self, expr: &Expr<'_>) -> Result<ValueId> {
+        let outer = self.span;
+        // Identifiers have no location of their own
+        if !matches!(expr, Expr::Identifier(_)) {
+            self.span = Some(expr.span());
+        }
+        let value = self.lower_expr_kind(expr);
+        self.span = outer;
+        value
+    }
+
+    fn lower_expr_kind(&mut self, expr: &Expr<'_>) -> Result<ValueId> {
+        match expr {
+            // Only the branch a `comptime if` selects is compiled
+            Expr::Comptime { expr: inner @ Expr::If { .. }, .. } => match comptime_branch(self.ctx, inner)? {
+                Some(branch) => self.lower_expr(branch),
+                None => Ok(self.unit()),
+            },
+
+            Expr::IntegerLiteral { .. }
+            | Expr::FloatLiteral { .. }
+            | Expr::StringLiteral { .. }
+            | Expr::Comptime { .. } => {
+                let constant = match eval_const(self.ctx, expr)? {
+                    ConstValue::Int(value) => Constant::Int(i64::try_from(value).map_err(|_| {
+                        CodegenError::Unsupported { construct: "an integer wider than 64 bits", span: expr.span() }
+                    })?),
+                    ConstValue::Float(value) => Constant::Float(value),
+                    ConstValue::Bool(value) => Constant::Bool(value),
+                    ConstValue::String(value) => Constant::String(value),
+                };
+                Ok(self.constant(constant))
+            }
+
+            Expr::BoolLiteral { value, .. } => Ok(self.constant(Constant::Bool(*value))),
+            Expr::Nil { .. } => Ok(self.constant(Constant::Nil)),
+            Expr::Hole { span } => Err(CodegenError::Unsupported { construct: "a typed hole", span: *span }),
+
+            Expr::Identifier(sym) => self.lower_identifier(*sym),
+
+            Expr::Path { segments, .. } => {
+                let (type_path, member) = segments.split_at(segments.len().saturating_sub(1));
+                let class = self.type_name(type_path);
+                let member = member.first().map(|&sym| self.name(sym)).unwrap_or_default();
+                if let Some(tag) = self.variant_tag(&class, &member) {
+                    let ty = IrType::Object(Some(class.clone()));
+                    let variant = Inst::Variant { enum_name: class, variant: member, tag, payload: None };
+                    return Ok(self.emit(variant, ty));
+                }
+                let name = match self.lowered.resolve_method(Some(&class), &member, &[], true) {
+                    Some(method) => method_symbol(&class, &method.selector),
+                    None => format!("{class}.{member}"),
+                };
+                Ok(self.emit(Inst::Global(name), IrType::Object(None)))
+            }
+
+            Expr::Unary { op, operand, .. } => {
+                let value = self.lower_expr(operand)?;
+                let ty = self.func.value_type(value).clone();
+                if let Some(class) = ty.class_name().map(str::to_string)
+                    && let Some((method, _)) = unary_bound(op).as_ref().and_then(|b| b.method())
+                {
+                    let (selector, ty) = self.resolve_method(Some(&class), method, &[], false);
+                    return Ok(self.emit(Inst::Send { receiver: value, selector, args: vec![] }, ty));
+                }
+                let ty = if matches!(op, UnaryOp::Negate) { IrType::Bool } else { ty };
+                Ok(self.emit(Inst::Unary { op: *op, operand: value }, ty))
+            }
+
+            Expr::Binary { left, op, right, span } => match op {
+                BinaryOp::And | BinaryOp::Or => self.lower_logical(left, *op, right),
+                BinaryOp::Assign => {
+                    self.lower_assign(left, right, *span)?;
+                    Ok(self.unit())
+                }
+                _ => {
+                    let lhs = self.lower_expr(left)?;
+                    let rhs = self.lower_expr(right)?;
+                    let lhs_ty = self.func.value_type(lhs).clone();
+
+                    // Arithmetic on user types sends the operator's method
+                    if let Some(class) = lhs_ty.class_name().map(str::to_string)
+                        && let Some((method, _)) = binary_bound(op).as_ref().and_then(|b| b.method())
+                    {
+                        let arg = CallArg { label: None, value: right, span: *span };
+                        let (selector, ty) =
+                            self.resolve_method(Some(&class), method, std::slice::from_ref(&arg), false);
+                        return Ok(self.emit(Inst::Send { receiver: lhs, selector, args: vec![rhs] }, ty));
+                    }
+
+                    let ty = match op {
+                        BinaryOp::Eq
+                        | BinaryOp::Neq
+                        | BinaryOp::Lt
+                        | BinaryOp::Lte
+                        | BinaryOp::Gt
+                        | BinaryOp::Gte => IrType::Bool,
+                        _ => lhs_ty,
+                    };
+                    Ok(self.emit(Inst::Binary { op: *op, lhs, rhs }, ty))
+                }
+            },
+
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.lower_if(condition, then_branch, *else_branch)
+            }
+
+            Expr::Match { scrutinee, arms, .. } => self.lower_match(scrutinee, arms),
+            Expr::ForLoop { pattern, iter, body, .. } => {
+                self.lower_for(pattern, iter, body)?;
+                Ok(self.unit())
+            }
+
+            Expr::Block { stmts, expr, .. } => {
+                self.scopes.push(HashMap::new());
+                let result = self.lower_block(stmts, *expr);
+                self.scopes.pop();
+                result
+            }
+
+            Expr::WhileLoop { condition, body, .. } => {
+                self.lower_while(condition, body)?;
+                Ok(self.unit())
+            }
+
+            Expr::Call { callee, args, .. } => self.lower_call(callee, args),
+
+            Expr::Closure { params, body, span, .. } => self.lower_closure(params, body, *span),
+
+            Expr::MethodCall { receiver, method, args, .. } => {
+                let receiver = self.lower_expr(receiver)?;
+                let class = self.func.value_type(receiver).class_name().map(str::to_string);
+                let method = self.name(*method);
+                let (selector, ty) = self.resolve_method(class.as_deref(), &method, args, false);
+                let args = self.lower_args(args)?;
+                if matches!(self.func.value_type(receiver), IrType::String | IrType::Int | IrType::Float) {
+                    let operands: Vec<ValueId> = std::iter::once(receiver).chain(args.iter().copied()).collect();
+                    if let Some(value) = self.intrinsic(intrinsics::selector(&selector), &operands) {
+                        return Ok(value);
+                    }
+                }
+                Ok(self.emit(Inst::Send { receiver, selector, args }, ty))
+            }
+
+            Expr::Struct { type_path, fields, .. } => {
+                let class = self.type_name(type_path);
+                let object = self.emit(Inst::Alloc { class: class.clone() }, IrType::Object(Some(class.clone())));
+                let mut values = Vec::with_capacity(fields.len());
+                for field in fields {
+                    let value = match field.value {
+                        Some(value) => self.lower_expr(value)?,
+                        None => self.lower_identifier(field.name)?,
+                    };
+                    values.push((self.name(field.name), value));
+                }
+                // Fields are set in the order they are declared in, which
+                // describes the instance, as the interpreter orders them
+                let ivars = self.lowered.class(&class).map(|class| &class.ivars);
+                let position = |field: &str| ivars.and_then(|ivars| ivars.iter().position(|ivar| ivar.name == field));
+                values.sort_by_key(|(field, _)| position(field).unwrap_or(usize::MAX));
+                for (field, value) in values {
+                    self.emit(Inst::SetField { object, field, value }, IrType::Unit);
+                }
+                Ok(object)
+            }
+
+            Expr::Enum { type_path, variant, payload, .. } => {
+                let enum_name = self.type_name(type_path);
+                let payload = payload.map(|p| self.lower_expr(p)).transpose()?;
+                let variant = self.name(*variant);
+                let tag = self.variant_tag(&enum_name, &variant).unwrap_or_default();
+                let ty = IrType::Object(Some(enum_name.clone()));
+                Ok(self.emit(Inst::Variant { enum_name, variant, tag, payload }, ty))
+            }
+
+            Expr::Array { elements, .. } => {
+                let elements = elements.iter().map(|e| self.lower_expr(e)).collect::<Result<Vec<_>>>()?;
+                Ok(self.emit(Inst::Array(elements), IrType::Object(None)))
+            }
+
+            Expr::Dict { entries, .. } => {
+                let mut pairs = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    pairs.push((self.lower_expr(entry.key)?, self.lower_expr(entry.value)?));
+                }
+                Ok(self.emit(Inst::Dict(pairs), IrType::Object(None)))
+            }
+
+            Expr::Field { object, field, .. } => {
+                let object = self.lower_expr(object)?;
+                let field = self.name(*field);
+                let ty = self.field_type(self.func.value_type(object).class_name(), &field);
+                Ok(self.emit(Inst::GetField { object, field }, ty))
+            }
+
+            Expr::Index { collection, index, .. } => {
+                let collection = self.lower_expr(collection)?;
+                let index = self.lower_expr(index)?;
+                Ok(self.emit(Inst::GetIndex { collection, index }, IrType::Object(None)))
+            }
+
+            Expr::Paren { expr, .. } => self.lower_expr(expr),
+
+            Expr::Interpolation { parts, .. } => {
+                let mut values = Vec::with_capacity(parts.len());
+                for part in parts {
+                    values.push(match part {
+                        InterpolationPart::Text(text) => {
+                            let text = self.name(*text);
+                            self.constant(Constant::String(text))
+                        }
+                        InterpolationPart::Expr(expr) => {
+                            let value = self.lower_expr(expr)?;
+                            self.description(value)
+                        }
+                    });
+                }
+                Ok(self.emit(Inst::Concat(values), IrType::String))
+            }
+        }
+    }
+
+    /// The `description()` of an instance whose class implements one, for
+    /// interpolation; anything else is described by `Concat` itself.
+    fn description(&mut self, value: ValueId) -> ValueId {
+        let class = self.func.value_type(value).class_name().map(str::to_string);
+        match class.and_then(|class| self.lowered.resolve_method(Some(&class), "description", &[], false)) {
+            Some(method) => {
+                let selector = method.selector.clone();
+                self.emit(Inst::Send { receiver: value, selector, args: vec![] }, IrType::String)
+            }
+            None => value,
+        }
+    }
+
+    fn lower_args(&mut self, args: &[CallArg<'_>]) -> Result<Vec<ValueId>> {
+        args.iter().map(|arg| self.lower_expr(arg.value)).collect()
+    }
+
+    fn lower_identifier(&mut self, sym: Symbol) -> Result<ValueId> {
+        if let Some(var) = self.lookup_var(sym) {
+            let value = self.read_var(var, self.current);
+            return Ok(match self.boxed.get(&var).cloned() {
+                Some(ty) => self.emit(Inst::Load(value), ty),
+                None => value,
+            });
+        }
+
+        let name = self.name(sym);
+
+        // Fields are in scope inside methods
+        if let Some(class) = self.receiver.clone()
+            && self.lowered.ivar(&class, &name).is_some()
+        {
+            let this = self.read_var(self.self_var.expect("instance method without self"), self.current);
+            let ty = self.field_type(Some(&class), &name);
+            return Ok(self.emit(Inst::GetField { object: this, field: name }, ty));
+        }
+
+        // Constants fold to their value
+        if let Some(value) = self.ctx.consts.get(&sym).cloned() {
+            let constant = match value {
+                ConstValue::Int(value) => i64::try_from(value).map_or(Constant::Nil, Constant::Int),
+                ConstValue::Float(value) => Constant::Float(value),
+                ConstValue::Bool(value) => Constant::Bool(value),
+                ConstValue::String(value) => Constant::String(value),
+            };
+            return Ok(self.constant(constant));
+        }
+
+        let ty = match self.ctx.env.lookup(sym) {
+            Some(scheme) => self.ir_type(&scheme.ty),
+            None => IrType::Object(None),
+        };
+        Ok(self.emit(Inst::Global(name), ty))
+    }
+
+    fn field_type(&self, class: Option<&str>, field: &str) -> IrType {
+        class
+            .and_then(|class| self.lowered.ivar(class, field))
+            .map_or(IrType::Object(None), |ivar| self.ir_type(&ivar.ty))
+    }
+
+    /// The position of a variant in its enum's declaration.
+    fn variant_tag(&self, enum_name: &str, variant: &str) -> Option<usize> {
+        let info = self.ctx.types.lookup_enum(self.ctx.interner.get_symbol(enum_name)?)?;
+        info.variants.iter().position(|v| self.ctx.interner.resolve(v.name) == Some(variant))
+    }
+
+    fn lower_call(&mut self, callee: &Expr<'_>, args: &[CallArg<'_>]) -> Result<ValueId> {
+        match callee {
+            Expr::Identifier(sym) if self.lookup_var(*sym).is_none() => {
+                let name = self.type_name(&[*sym]);
+
+                // `Type(...)` allocates an instance and runs its initializer
+                if self.is_type(&name) {
+                    let object = self.emit(Inst::Alloc { class: name.clone() }, IrType::Object(Some(name.clone())));
+                    let labels = self.labels(args);
+                    if let Some(init) = self.lowered.resolve_method(Some(&name), "init", &labels, false) {
+                        let selector = init.selector.clone();
+                        let args = self.lower_args(args)?;
+                        self.emit(Inst::Send { receiver: object, selector, args }, IrType::Object(Some(name)));
+                    }
+                    return Ok(object);
+                }
+
+                let is_field = self.receiver.as_ref().is_some_and(|class| self.lowered.ivar(class, &name).is_some());
+                if !is_field {
+                    let ty = match self.ctx.env.lookup(*sym).map(|scheme| &scheme.ty) {
+                        Some(Ty::Function { return_type, .. }) => self.ir_type(return_type),
+                        _ => IrType::Object(None),
+                    };
+                    let args = self.lower_args(args)?;
+                    if !self.defined.contains(&name)
+                        && let Some(value) = self.intrinsic(intrinsics::function(&name), &args)
+                    {
+                        return Ok(value);
+                    }
+                    return Ok(self.emit(Inst::Call { callee: name, args }, ty));
+                }
+            }
+
+            Expr::Path { segments, .. } if segments.len() >= 2 => {
+                let (type_path, member) = segments.split_at(segments.len() - 1);
+                let class = self.type_name(type_path);
+                let member = self.name(member[0]);
+
+                if let Some(tag) = self.variant_tag(&class, &member) {
+                    let mut values = self.lower_args(args)?;
+                    let payload = match values.len() {
+                        0 => None,
+                        1 => values.pop(),
+                        _ => Some(self.emit(Inst::Tuple(values), IrType::Object(None))),
+                    };
+                    let ty = IrType::Object(Some(class.clone()));
+                    return Ok(self.emit(Inst::Variant { enum_name: class, variant: member, tag, payload }, ty));
+                }
+
+                let (selector, ty) = self.resolve_method(Some(&class), &member, args, true);
+                let args = self.lower_args(args)?;
+                return Ok(self.emit(Inst::Call { callee: method_symbol(&class, &selector), args }, ty));
+            }
+
+            _ => {}
+        }
+
+        let callee = self.lower_expr(callee)?;
+        let args = self.lower_args(args)?;
+        let ty = self.closure_returns.get(&callee).cloned().unwrap_or(IrType::Object(None));
+        Ok(self.emit(Inst::CallIndirect { callee, args }, ty))
+    }
+
+    /// Lower a closure expression to a function lifted out of this one,
+    /// and create the closure from the values it captures.
+    fn lower_closure(&mut self, params: &[FnParam], body: &Expr<'_>, span: Span) -> Result<ValueId> {
+        let conversion = closure::convert(self.ctx, &self.func.name, self.closures, span)?;
+        self.closures += 1;
+
+        // Anything else the closure names is a global to it as well
+        let mut captured = Vec::new();
+        let mut captures = Vec::new();
+        for capture in &conversion.captures {
+            let Some(var) = self.lookup_var(capture.name) else {
+                continue;
+            };
+            captures.push(self.read_var(var, self.current));
+            let ty = self.var_types[var.0 as usize].clone();
+            captured.push(Captured { name: capture.name, ty, boxed: self.boxed.get(&var).cloned() });
+        }
+
+        // A closure using the receiver reaches its fields as methods do
+        let self_sym = self.ctx.interner.get_symbol("self");
+        let receiver = self.receiver.clone().filter(|_| captured.iter().any(|c| Some(c.name) == self_sym));
+        let builder = FnBuilder::new(self.ctx, self.lowered, self.defined, receiver.as_deref());
+        let functions = builder.build_closure(&conversion, captured, params, body)?;
+        self.lifted.extend(functions);
+
+        let return_type = self.ir_type(&conversion.return_type);
+        let closure = self.emit(Inst::Closure { function: conversion.function, captures }, IrType::Object(None));
+        self.closure_returns.insert(closure, return_type);
+        Ok(closure)
+    }
+
+    fn lower_assign(&mut self, target: &Expr<'_>, value: &Expr<'_>, span: oxidex_syntax::Span) -> Result<()> {
+        match target {
+            Expr::Identifier(sym) => {
+                let value = self.lower_expr(value)?;
+                if let Some(var) = self.lookup_var(*sym) {
+                    if self.boxed.contains_key(&var) {
+                        let cell = self.read_var(var, self.current);
+                        self.emit(Inst::Store { cell, value }, IrType::Unit);
+                    } else {
+                        self.write_var(var, self.current, value);
+                    }
+                    return Ok(());
+                }
+                let field = self.name(*sym);
+                if let Some(class) = self.receiver.clone()
+                    && self.lowered.ivar(&class, &field).is_some()
+                {
+                    let object = self.read_var(self.self_var.expect("instance method without self"), self.current);
+                    self.emit(Inst::SetField { object, field, value }, IrType::Unit);
+                    return Ok(());
+                }
+                Err(CodegenError::Unsupported { construct: "an assignment to a global", span })
+            }
+            Expr::Field { object, field, .. } => {
+                let object = self.lower_expr(object)?;
+                let value = self.lower_expr(value)?;
+                let field = self.name(*field);
+                self.emit(Inst::SetField { object, field, value }, IrType::Unit);
+                Ok(())
+            }
+            Expr::Index { collection, index, .. } => {
+                let collection = self.lower_expr(collection)?;
+                let index = self.lower_expr(index)?;
+                let value = self.lower_expr(value)?;
+                self.emit(Inst::SetIndex { collection, index, value }, IrType::Unit);
+                Ok(())
+            }
+            Expr::Paren { expr, .. } => self.lower_assign(expr, value, span),
+            _ => Err(CodegenError::Unsupported { construct: "an assignment to this expression", span }),
+        }
+    }
+
+    fn lower_logical(&mut self, left: &Expr<'_>, op: BinaryOp, right: &Expr<'_>) -> Result<ValueId> {
+        let lhs = self.lower_expr(left)?;
+        let lhs_end = self.current;
+        let rhs_block = self.new_block();
+        let merge = self.new_block();
+        let (then_block, else_block) = match op {
+            BinaryOp::And => (rhs_block, merge),
+            _ => (merge, rhs_block),
+        };
+        self.terminate(Terminator::Branch { cond: lhs, then_block, else_block });
+        self.seal(rhs_block);
+
+        self.current = rhs_block;
+        let rhs = self.lower_expr(right)?;
+        let rhs_end = self.current;
+        self.terminate(Terminator::Jump(merge));
+
+        self.seal(merge);
+        self.current = merge;
+        let incoming = self.live_incoming(merge, [(lhs_end, lhs), (rhs_end, rhs)]);
+        Ok(self.merge(merge, incoming))
+    }
+
+    fn lower_if(
+        &mut self,
+        condition: &Expr<'_>,
+        then_branch: &Expr<'_>,
+        else_branch: Option<&Expr<'_>>,
+    ) -> Result<ValueId> {
+        let cond = self.lower_expr(condition)?;
+        let then_block = self.new_block();
+        let else_block = self.new_block();
+        let merge = self.new_block();
+        self.terminate(Terminator::Branch { cond, then_block, else_block });
+        self.seal(then_block);
+        self.seal(else_block);
+
+        self.current = then_block;
+        let then_value = self.lower_expr(then_branch)?;
+        let then_end = self.current;
+        self.terminate(Terminator::Jump(merge));
+
+        self.current = else_block;
+        let else_value = match else_branch {
+            Some(branch) => Some(self.lower_expr(branch)?),
+            None => None,
+        };
+        let else_end = self.current;
+        self.terminate(Terminator::Jump(merge));
+
+        self.seal(merge);
+        self.current = merge;
+        match else_value {
+            Some(else_value) => {
+                let incoming = self.live_incoming(merge, [(then_end, then_value), (else_end, else_value)]);
+                Ok(self.merge(merge, incoming))
+            }
+            None => Ok(self.unit()),
+        }
+    }
+
+    /// Keep the incoming values whose block actually jumps to `block`.
+    fn live_incoming(&self, block: BlockId, incoming: [(BlockId, ValueId); 2]) -> Vec<(BlockId, ValueId)> {
+        let preds = &self.preds[block.0 as usize];
+        incoming.into_iter().filter(|(pred, _)| preds.contains(pred)).collect()
+    }
+
+    fn lower_while(&mut self, condition: &Expr<'_>, body: &Expr<'_>) -> Result<()> {
+        let header = self.new_block();
+        let body_block = self.new_block();
+        let exit = self.new_block();
+        self.terminate(Terminator::Jump(header));
+
+        // The header stays unsealed until the back edge is known
+        self.current = header;
+        let cond = self.lower_expr(condition)?;
+        self.terminate(Terminator::Branch { cond, then_block: body_block, else_block: exit });
+        self.seal(body_block);
+
+        self.current = body_block;
+        self.lower_expr(body)?;
+        self.terminate(Terminator::Jump(header));
+        self.seal(header);
+
+        self.seal(exit);
+        self.current = exit;
+        Ok(())
+    }
+
+    /// Lower a `for` loop through the iteration protocol.
+    fn lower_for(&mut self, pattern: &Pattern, iter: &Expr<'_>, body: &Expr<'_>) -> Result<()> {
+        let sequence = self.lower_expr(iter)?;
+        let class = self.func.value_type(sequence).class_name().map(str::to_string);
+        let is_iterator = class.as_deref().is_some_and(|class| {
+            self.lowered.resolve_method(Some(class), "makeIterator", &[], false).is_none()
+                && self.lowered.resolve_method(Some(class), "next", &[], false).is_some()
+        });
+        let iterator = if is_iterator {
+            sequence
+        } else {
+            let (selector, ty) = self.resolve_method(class.as_deref(), "makeIterator", &[], false);
+            self.emit(Inst::Send { receiver: sequence, selector, args: vec![] }, ty)
+        };
+
+        let header = self.new_block();
+        let body_block = self.new_block();
+        let exit = self.new_block();
+        self.terminate(Terminator::Jump(header));
+
+        // The header stays unsealed until the back edge is known
+        self.current = header;
+        let class = self.func.value_type(iterator).class_name().map(str::to_string);
+        let (selector, ty) = self.resolve_method(class.as_deref(), "next", &[], false);
+        let element = self.emit(Inst::Send { receiver: iterator, selector, args: vec![] }, ty);
+        let nil = self.constant(Constant::Nil);
+        let done = self.emit(Inst::Binary { op: BinaryOp::Eq, lhs: element, rhs: nil }, IrType::Bool);
+        self.terminate(Terminator::Branch { cond: done, then_block: exit, else_block: body_block });
+        self.seal(body_block);
+
+        self.current = body_block;
+        self.scopes.push(HashMap::new());
+        let result = self.bind_element(pattern, element).and_then(|()| self.lower_expr(body));
+        self.scopes.pop();
+        result?;
+        self.terminate(Terminator::Jump(header));
+        self.seal(header);
+
+        self.seal(exit);
+        self.current = exit;
+        Ok(())
+    }
+
+    /// Bind the variables of a `for` loop's pattern to an element.
+    fn bind_element(&mut self, pattern: &Pattern, element: ValueId) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(()),
+            Pattern::Variable { name, span, .. } => {
+                let ty = self.func.value_type(element).clone();
+                self.declare_local(*name, ty, element, *span);
+                Ok(())
+            }
+            Pattern::Tuple { elements, .. } => {
+                for (index, pattern) in elements.iter().enumerate() {
+                    let index = self.constant(Constant::Int(i64::try_from(index).expect("tuple too long")));
+                    let part = self.emit(Inst::GetIndex { collection: element, index }, IrType::Object(None));
+                    self.bind_element(pattern, part)?;
+                }
+                Ok(())
+            }
+            _ => Err(CodegenError::Unsupported { construct: "this pattern in a for loop", span: pattern.span() }),
+        }
+    }
+
+    // ===== Pattern matching =====
+
+    /// Lower a `match` through its decision tree. Each arm's body is lowered
+    /// once; the leaves reaching it bind its variables and jump to it.
+    fn lower_match(&mut self, scrutinee: &Expr<'_>, arms: &[MatchArm<'_>]) -> Result<ValueId> {
+        let value = self.lower_expr(scrutinee)?;
+        let ty = self.checked_type(self.func.value_type(value));
+        let tree = compile_match(self.ctx, ty.as_ref(), arms)?;
+
+        let mut state = MatchState {
+            arms,
+            blocks: arms.iter().map(|_| self.new_block()).collect(),
+            vars: vec![HashMap::new(); arms.len()],
+        };
+        let paths = HashMap::from([(Path::root(), value)]);
+        self.lower_decision(&mut state, &tree, paths)?;
+
+        let merge = self.new_block();
+        let mut incoming = Vec::new();
+        for (index, arm) in arms.iter().enumerate() {
+            let block = state.blocks[index];
+            self.seal(block);
+            if self.is_dead(block) {
+                continue;
+            }
+            self.current = block;
+            self.scopes.push(state.vars[index].clone());
+            let result = self.lower_expr(arm.body);
+            self.scopes.pop();
+            incoming.push((self.current, result?));
+            self.terminate(Terminator::Jump(merge));
+        }
+
+        self.seal(merge);
+        self.current = merge;
+        let preds = &self.preds[merge.0 as usize];
+        let incoming = incoming.into_iter().filter(|(pred, _)| preds.contains(pred)).collect();
+        Ok(self.merge(merge, incoming))
+    }
+
+    fn lower_decision(
+        &mut self,
+        state: &mut MatchState<'_, '_>,
+        decision: &Decision,
+        mut paths: HashMap<Path, ValueId>,
+    ) -> Result<()> {
+        match decision {
+            Decision::Fail => {
+                self.terminate(Terminator::Unreachable);
+                Ok(())
+            }
+
+            Decision::Leaf { arm, bindings } => {
+                self.bind(state, *arm, bindings, &mut paths);
+                self.terminate(Terminator::Jump(state.blocks[*arm]));
+                Ok(())
+            }
+
+            Decision::Guard { arm, bindings, otherwise } => {
+                self.bind(state, *arm, bindings, &mut paths);
+                let guard = state.arms[*arm].guard.expect("guard leaves come from guarded arms");
+                self.scopes.push(state.vars[*arm].clone());
+                let cond = self.lower_expr(guard);
+                self.scopes.pop();
+
+                let next = self.new_block();
+                self.terminate(Terminator::Branch { cond: cond?, then_block: state.blocks[*arm], else_block: next });
+                self.seal(next);
+                self.current = next;
+                self.lower_decision(state, otherwise, paths)
+            }
+
+            Decision::Switch { path, cases, default } => {
+                let value = self.project(path, None, &mut paths);
+                let default = default.as_deref().unwrap_or(&Decision::Fail);
+
+                // Tags and integers dispatch at once; other tests are chained
+                let switch_on = match cases.first() {
+                    Some((Case::Variant { .. }, _)) => Some(self.emit(Inst::Tag(value), IrType::Int)),
+                    Some((Case::Literal(Constant::Int(_)), _)) => Some(value),
+                    _ => None,
+                };
+                if let Some(switch_on) = switch_on {
+                    let blocks: Vec<BlockId> = cases.iter().map(|_| self.new_block()).collect();
+                    let default_block = self.new_block();
+                    let keys = cases.iter().map(|(case, _)| match case {
+                        Case::Variant { tag, .. } => i64::try_from(*tag).expect("too many variants"),
+                        Case::Literal(Constant::Int(value)) => *value,
+                        _ => unreachable!("a path is tested one way throughout"),
+                    });
+                    let targets = keys.zip(blocks.iter().copied()).collect();
+                    self.terminate(Terminator::Switch { value: switch_on, cases: targets, default: default_block });
+
+                    for ((_, subtree), block) in cases.iter().zip(blocks) {
+                        self.seal(block);
+                        self.current = block;
+                        self.lower_decision(state, subtree, paths.clone())?;
+                    }
+                    self.seal(default_block);
+                    self.current = default_block;
+                    return self.lower_decision(state, default, paths);
+                }
+
+                let length = match cases.first() {
+                    Some((Case::Length { .. }, _)) => Some(self.emit(Inst::Length(value), IrType::Int)),
+                    _ => None,
+                };
+                for (case, subtree) in cases {
+                    let cond = match (case, length) {
+                        (Case::Length { len, rest }, Some(length)) => {
+                            let len = self.constant(Constant::Int(i64::try_from(*len).expect("array too long")));
+                            let op = if *rest { BinaryOp::Gte } else { BinaryOp::Eq };
+                            self.emit(Inst::Binary { op, lhs: length, rhs: len }, IrType::Bool)
+                        }
+                        (Case::Literal(constant), _) => {
+                            let constant = self.constant(constant.clone());
+                            self.emit(Inst::Binary { op: BinaryOp::Eq, lhs: value, rhs: constant }, IrType::Bool)
+                        }
+                        _ => unreachable!("a path is tested one way throughout"),
+                    };
+                    let then_block = self.new_block();
+                    let else_block = self.new_block();
+                    self.terminate(Terminator::Branch { cond, then_block, else_block });
+                    self.seal(then_block);
+                    self.seal(else_block);
+
+                    self.current = then_block;
+                    self.lower_decision(state, subtree, paths.clone())?;
+                    self.current = else_block;
+                }
+                self.lower_decision(state, default, paths)
+            }
+        }
+    }
+
+    /// Bind the variables of an arm in the current block.
+    fn bind(
+        &mut self,
+        state: &mut MatchState<'_, '_>,
+        arm: usize,
+        bindings: &[Binding],
+        paths: &mut HashMap<Path, ValueId>,
+    ) {
+        for binding in bindings {
+            let value = self.project(&binding.path, binding.ty.as_ref(), paths);
+            let var = match state.vars[arm].get(&binding.name) {
+                Some(&var) => var,
+                None => {
+                    let ty = match &binding.ty {
+                        Some(ty) => self.ir_type(ty),
+                        None => self.func.value_type(value).clone(),
+                    };
+                    let var = self.new_var(ty);
+                    self.name_var(var, binding.name);
+                    state.vars[arm].insert(binding.name, var);
+                    var
+                }
+            };
+            self.write_var(var, self.current, value);
+        }
+    }
+
+    /// Get the value of a part of the scrutinee, reading it in the current
+    /// block if no dominating block has. `ty` is its checked type, if known.
+    fn project(&mut self, path: &Path, ty: Option<&Ty>, paths: &mut HashMap<Path, ValueId>) -> ValueId {
+        if let Some(&value) = paths.get(path) {
+            return value;
+        }
+        let (parent, projection) = path.split_last().expect("the root is always known");
+        let parent = self.project(&parent, None, paths);
+        let ty = ty.map_or(IrType::Object(None), |ty| self.ir_type(ty));
+
+        let value = match projection {
+            Projection::Payload => self.emit(Inst::Payload(parent), ty),
+            Projection::Field(field) => {
+                let ty = match ty {
+                    IrType::Object(None) => self.field_type(self.func.value_type(parent).class_name(), field),
+                    ty => ty,
+                };
+                self.emit(Inst::GetField { object: parent, field: field.clone() }, ty)
+            }
+            Projection::Element(index) => {
+                let index = self.constant(Constant::Int(i64::try_from(*index).expect("tuple too long")));
+                self.emit(Inst::GetIndex { collection: parent, index }, ty)
+            }
+            Projection::Rest(start) => self.emit(Inst::Slice { collection: parent, start: *start }, ty),
+        };
+        paths.insert(path.clone(), value);
+        value
+    }
+
+    /// Recover the checked type of a value of a user type.
+    fn checked_type(&self, ty: &IrType) -> Option<Ty> {
+        let name = ty.class_name()?;
+        let class = self.lowered.class(name)?;
+        let name = self.ctx.interner.get_symbol(name)?;
+        Some(match class.kind {
+            TypeKind::Class => Ty::Class { name, type_args: vec![] },
+            TypeKind::Struct => Ty::Struct { name, type_args: vec![] },
+            TypeKind::Enum => Ty::Enum { name, type_args: vec![] },
+        })
+    }
+
+    // ===== Statements =====
+
+    /// Lower the statements of a block and its value, then run what the
+    /// block deferred, innermost first.
+    fn lower_block(&mut self, stmts: &[Stmt<'_>], expr: Option<&Expr<'_>>) -> Result<ValueId> {
+        let outer = self.defers.len();
+        for stmt in stmts {
+            self.lower_stmt(stmt)?;
+        }
+        let value = match expr {
+            Some(expr) => self.lower_expr(expr)?,
+            None => self.unit(),
+        };
+        while self.defers.len() > outer {
+            let deferred = self.defers.pop().expect("deferred code");
+            self.run_deferred(deferred.closure);
+
+            // An error raised before the end of the block runs it on the way out
+            let handler = self.new_block();
+            self.seal(handler);
+            self.func.cleanups.push(Cleanup { blocks: deferred.blocks, handler });
+            let current = std::mem::replace(&mut self.current, handler);
+            self.call_deferred(deferred.closure);
+            self.terminate(Terminator::Resume);
+            self.current = current;
+        }
+        Ok(value)
+    }
+
+    /// Call deferred code in a block of its own, past the blocks an error
+    /// leaves through its cleanup.
+    fn run_deferred(&mut self, closure: ValueId) {
+        let block = self.new_block();
+        self.terminate(Terminator::Jump(block));
+        self.seal(block);
+        self.current = block;
+        self.call_deferred(closure);
+    }
+
+    fn call_deferred(&mut self, closure: ValueId) {
+        let ty = self.closure_returns.get(&closure).cloned().unwrap_or(IrType::Unit);
+        self.emit(Inst::CallIndirect { callee: closure, args: vec![] }, ty);
+    }
+
+    /// Lower a statement, locating the instructions it emits at it.
+    fn lower_stmt(&mut self, stmt: &Stmt<'_>) -> Result<()> {
+        let outer = self.span.replace(stmt.span());
+        let result = self.lower_stmt_kind(stmt);
+        self.span = outer;
+        result
+    }
+
+    fn lower_stmt_kind(&mut self, stmt: &Stmt<'_>) -> Result<()> {
+        match stmt {
+            Stmt::Let { name, type_annotation, init, span } | Stmt::Mut { name, type_annotation, init, span } => {
+                let value = match init {
+                    Some(init) => self.lower_expr(init)?,
+                    None => self.constant(Constant::Nil),
+                };
+                let ty = match type_annotation {
+                    Some(ty) => self.lower_type(ty)?,
+                    None => self.func.value_type(value).clone(),
+                };
+                self.declare_local(*name, ty, value, *span);
+                Ok(())
+            }
+
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => Some(self.lower_expr(value)?),
+                    None => None,
+                };
+                // Everything deferred so far runs on the way out
+                let defers = std::mem::take(&mut self.defers);
+                for deferred in defers.iter().rev() {
+                    self.run_deferred(deferred.closure);
+                }
+                self.terminate(Terminator::Return(value));
+                self.start_dead_block();
+                self.defers = defers;
+                Ok(())
+            }
+
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.lower_if(condition, then_branch, *else_branch)?;
+                Ok(())
+            }
+
+            Stmt::Guard { condition, else_branch, .. } => {
+                let cond = self.lower_expr(condition)?;
+                let cont = self.new_block();
+                let otherwise = self.new_block();
+                self.terminate(Terminator::Branch { cond, then_block: cont, else_block: otherwise });
+                self.seal(cont);
+                self.seal(otherwise);
+
+                // The else branch must leave the scope, so it never falls through
+                self.current = otherwise;
+                self.lower_expr(else_branch)?;
+                self.terminate(Terminator::Unreachable);
+
+                self.current = cont;
+                Ok(())
+            }
+
+            Stmt::Match { scrutinee, arms, .. } => {
+                self.lower_match(scrutinee, arms)?;
+                Ok(())
+            }
+            Stmt::ForLoop { pattern, iter, body, .. } => self.lower_for(pattern, iter, body),
+
+            Stmt::Defer { body, span } => {
+                let closure = self.lower_closure(&[], body, *span)?;
+
+                // The blocks from here to the end of the block are covered
+                let region = self.new_block();
+                self.terminate(Terminator::Jump(region));
+                self.seal(region);
+                self.current = region;
+                self.defers.push(Deferred { closure, blocks: vec![region] });
+                Ok(())
+            }
+
+            Stmt::WhileLoop { condition, body, .. } => self.lower_while(condition, body),
+
+            Stmt::Assign { target, value, span } => self.lower_assign(target, value, *span),
+
+            Stmt::Expr { expr, .. } => {
+                self.lower_expr(expr)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{parse_module, verify_module};
+    use crate::lowering::lower;
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::Span;
+    use oxidex_syntax::ast::decl::{FnDecl, StructField, Visibility};
+
+    #[test]
+    fn test_loops_and_branches_build_ssa() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["sum", "n", "i", "total", "Int", "0", "1"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [sum, n, i, total, int_sym, zero_sym, one_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // fn sum(n: Int) -> Int {
+        //     mut i = 0
+        //     mut total = 0
+        //     while i < n { total = total + i; i = i + 1 }
+        //     if total > n { total } else { n }
+        // }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let zero = Expr::IntegerLiteral { value: zero_sym, type_suffix: None, span };
+        let one = Expr::IntegerLiteral { value: one_sym, type_suffix: None, span };
+        let (n_expr, i_expr, total_expr) = (Expr::Identifier(n), Expr::Identifier(i), Expr::Identifier(total));
+        let binary = |left, op, right| Expr::Binary { left, op, right, span };
+        let cond = binary(&i_expr, BinaryOp::Lt, &n_expr);
+        let total_plus = binary(&total_expr, BinaryOp::Add, &i_expr);
+        let i_plus = binary(&i_expr, BinaryOp::Add, &one);
+        let loop_body = Expr::Block {
+            stmts: vec![
+                Stmt::Assign { target: &total_expr, value: &total_plus, span },
+                Stmt::Assign { target: &i_expr, value: &i_plus, span },
+            ],
+            expr: None,
+            span,
+        };
+        let larger = binary(&total_expr, BinaryOp::Gt, &n_expr);
+        let choice = Expr::If { condition: &larger, then_branch: &total_expr, else_branch: Some(&n_expr), span };
+        let body = Expr::Block {
+            stmts: vec![
+                Stmt::Mut { name: i, type_annotation: None, init: Some(&zero), span },
+                Stmt::Mut { name: total, type_annotation: None, init: Some(&zero), span },
+                Stmt::WhileLoop { condition: &cond, body: &loop_body, span },
+            ],
+            expr: Some(&choice),
+            span,
+        };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: sum,
+            generics: vec![],
+            params: vec![FnParam { label: None, name: n, type_annotation: int.clone(), span }],
+            return_type: Some(int),
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        verify_module(&module).unwrap();
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+        let func = module.function("sum").unwrap();
+
+        // entry, loop header, loop body, loop exit, then, else, merge
+        assert_eq!(func.blocks.len(), 7);
+        assert_eq!(func.return_type, IrType::Int);
+
+        // The loop header merges `i` and `total` from the entry and the back edge
+        let header = func.block(BlockId(1));
+        assert_eq!(header.phis.len(), 2);
+        for phi in &header.phis {
+            assert_eq!(phi.incoming.len(), 2);
+            assert_eq!(func.value_type(phi.result), &IrType::Int);
+        }
+
+        // The `if` expression's value is a phi returned from the merge block
+        let merge = func.blocks.last().unwrap();
+        let [phi] = merge.phis.as_slice() else {
+            panic!("expected one phi, got {:?}", merge.phis)
+        };
+        assert_eq!(merge.terminator, Terminator::Return(Some(phi.result)));
+
+        // The parameter is never reassigned, so it needs no phi
+        let n_param = func.params[0];
+        assert!(phi.incoming.iter().any(|&(_, value)| value == n_param));
+
+        // The variables name the values they hold, the header's phis included
+        assert_eq!(func.variables[0], (n_param, "n".to_string()));
+        for phi in &header.phis {
+            assert!(func.variables.iter().any(|(value, name)| *value == phi.result && name != "n"));
+        }
+    }
+
+    #[test]
+    fn test_exports_come_from_attributes() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["id", "n", "Int", "export", "ox_id", "inline", "1id", "test", "bench"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [id, n, int_sym, export, ox_id, inline, bad, test, bench] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // @export("ox_id") fn id(n: Int) -> Int { n }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let body = Expr::Identifier(n);
+        let function = |attributes: Vec<Attribute>| Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: id,
+            generics: vec![],
+            params: vec![FnParam { label: None, name: n, type_annotation: int.clone(), span }],
+            return_type: Some(int.clone()),
+            body: &body,
+            visibility: Visibility::Public,
+            attributes,
+            span,
+        };
+        let attribute = |name, args| Attribute { name, args, span };
+
+        let marked = vec![attribute(test, vec![]), attribute(bench, vec![]), attribute(export, vec![ox_id])];
+        let decls = vec![function(marked)];
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        assert_eq!(module.exports, [Export { function: "id".into(), symbol: "ox_id".into() }]);
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+
+        for attributes in [
+            vec![attribute(inline, vec![])],
+            vec![attribute(export, vec![])],
+            vec![attribute(export, vec![bad])],
+            vec![attribute(export, vec![ox_id]), attribute(export, vec![ox_id])],
+            vec![attribute(test, vec![ox_id])],
+            vec![attribute(bench, vec![ox_id])],
+        ] {
+            let decls = vec![function(attributes)];
+            let err = build_module(&mut ctx, &lowered, &decls).unwrap_err();
+            assert!(matches!(err, CodegenError::InvalidAttribute { .. }), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_externs_carry_their_type_encoding() {
+        use oxidex_syntax::ast::decl::ExternFn;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["cos", "abort", "x", "Float", "libm.so.6"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [cos, abort, x, float_sym, libm] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // extern "libm.so.6" { fn cos(x: Float) -> Float; fn abort(); }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let float = Type::Simple { name: float_sym, span };
+        let param = FnParam { label: None, name: x, type_annotation: float.clone(), span };
+        let functions = vec![
+            ExternFn { name: cos, params: vec![param], return_type: Some(float), span },
+            ExternFn { name: abort, params: vec![], return_type: None, span },
+        ];
+        let decls = vec![Decl::Extern { library: Some(libm), functions, span }];
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+
+        let encodings: Vec<_> = module.externs.iter().map(|e| (e.name.as_str(), e.encoding.as_str())).collect();
+        assert_eq!(encodings, [("cos", "dd"), ("abort", "v")]);
+        assert_eq!(module.externs[0].library.as_deref(), Some("libm.so.6"));
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+    }
+
+    #[test]
+    fn test_methods_send_messages_and_access_fields() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Counter", "count", "bump", "by", "step", "twice", "self", "Int"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [counter, count, bump, by, step, twice, self_sym, int_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let (count_expr, step_expr, self_expr) =
+            (Expr::Identifier(count), Expr::Identifier(step), Expr::Identifier(self_sym));
+
+        // mut fn bump(by step: Int) { count = count + step }
+        let sum = Expr::Binary { left: &count_expr, op: BinaryOp::Add, right: &step_expr, span };
+        let bump_body = Expr::Block {
+            stmts: vec![Stmt::Assign { target: &count_expr, value: &sum, span }],
+            expr: None,
+            span,
+        };
+        // fn twice() { self.bump(count) }
+        let call = Expr::MethodCall {
+            receiver: &self_expr,
+            method: bump,
+            args: vec![CallArg { label: None, value: &count_expr, span }],
+            span,
+        };
+        let method = |name, params, body| FnDecl {
+            is_mut: true,
+            is_init: false,
+            is_static: false,
+            name: Some(name),
+            generics: vec![],
+            params,
+            return_type: None,
+            body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let decls = vec![
+            Decl::Class {
+                name: counter,
+                generics: vec![],
+                superclass: None,
+                fields: vec![StructField { name: count, type_annotation: int.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![counter],
+                protocol: None,
+                methods: vec![
+                    method(bump, vec![FnParam { label: Some(by), name: step, type_annotation: int, span }], &bump_body),
+                    method(twice, vec![], &call),
+                ],
+                span,
+            },
+        ];
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        verify_module(&module).unwrap();
+
+        let bump = module.function("Counter.bumpBy:").unwrap();
+        assert_eq!(bump.params.len(), 2);
+        assert_eq!(bump.value_type(bump.params[0]), &IrType::Object(Some("Counter".into())));
+        let insts: Vec<&Inst> = bump.blocks[0].insts.iter().map(|i| &i.inst).collect();
+        assert!(matches!(insts[0], Inst::GetField { field, .. } if field == "count"));
+        assert!(matches!(insts[1], Inst::Binary { op: BinaryOp::Add, .. }));
+        assert!(matches!(insts[2], Inst::SetField { field, .. } if field == "count"));
+
+        // An unlabeled argument still resolves to the labeled selector
+        let twice = module.function("Counter.twice").unwrap();
+        let sends: Vec<&str> = twice.blocks[0]
+            .insts
+            .iter()
+            .filter_map(|i| match &i.inst {
+                Inst::Send { selector, .. } => Some(selector.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sends, ["bumpBy:"]);
+        assert_eq!(twice.blocks[0].terminator, Terminator::Return(None));
+    }
+
+    #[test]
+    fn test_match_lowers_through_decision_tree() {
+        use oxidex_syntax::ast::decl::EnumVariant;
+        use oxidex_syntax::ast::pat::Pattern;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Shape", "Circle", "Empty", "size", "s", "r", "Int", "0"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [shape, circle, empty, size, s, r, int_sym, zero_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // enum Shape { Circle(Int), Empty }
+        // fn size(s: Shape) -> Int { match s { Shape::Circle(r) => r, Shape::Empty => 0 } }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let (int, shape_ty) = (Type::Simple { name: int_sym, span }, Type::Simple { name: shape, span });
+        let (s_expr, r_expr) = (Expr::Identifier(s), Expr::Identifier(r));
+        let zero = Expr::IntegerLiteral { value: zero_sym, type_suffix: None, span };
+        let variant = |variant, payload| Pattern::Enum { type_path: vec![shape], variant, payload, span };
+        let r_pat = Pattern::Variable { name: r, mutable: false, span };
+        let body = Expr::Match {
+            scrutinee: &s_expr,
+            arms: vec![
+                MatchArm { pattern: variant(circle, Some(Box::new(r_pat))), guard: None, body: &r_expr, span },
+                MatchArm { pattern: variant(empty, None), guard: None, body: &zero, span },
+            ],
+            span,
+        };
+        let decls = vec![
+            Decl::Enum {
+                name: shape,
+                generics: vec![],
+                variants: vec![
+                    EnumVariant::Tuple { name: circle, fields: vec![int.clone()], span },
+                    EnumVariant::Unit { name: empty, span },
+                ],
+                methods: vec![],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Fn {
+                is_mut: false,
+                is_init: false,
+                is_static: false,
+                name: size,
+                generics: vec![],
+                params: vec![FnParam { label: None, name: s, type_annotation: shape_ty, span }],
+                return_type: Some(int),
+                body: &body,
+                visibility: Visibility::Private,
+                attributes: Vec::new(),
+                span,
+            },
+        ];
+        oxidex_typecheck::check::check_decl(&mut ctx, &decls[0]).unwrap();
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        verify_module(&module).unwrap();
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+        let func = module.function("size").unwrap();
+
+        // The entry switches on the tag; `Shape` has no other variants
+        let entry = func.block(BlockId(0));
+        let Inst::Tag(scrutinee) = entry.insts.last().unwrap().inst else {
+            panic!("expected a tag read, got {:?}", entry.insts)
+        };
+        assert_eq!(scrutinee, func.params[0]);
+        let Terminator::Switch { cases, default, .. } = &entry.terminator else {
+            panic!("expected a switch, got {:?}", entry.terminator)
+        };
+        assert_eq!(cases.iter().map(|&(tag, _)| tag).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(func.block(*default).terminator, Terminator::Unreachable);
+
+        // The payload is bound as an `Int` and merged with the other arm
+        let payload = func.blocks.iter().flat_map(|b| &b.insts).find(|i| matches!(i.inst, Inst::Payload(_))).unwrap();
+        assert_eq!(func.value_type(payload.result), &IrType::Int);
+        let merge = func.blocks.last().unwrap();
+        let [phi] = merge.phis.as_slice() else {
+            panic!("expected one phi, got {:?}", merge.phis)
+        };
+        assert_eq!(phi.incoming.len(), 2);
+        assert!(phi.incoming.iter().any(|&(_, value)| value == payload.result));
+        assert_eq!(merge.terminator, Terminator::Return(Some(phi.result)));
+    }
+
+    /// Check and build the IR of `source`, which must verify and print back
+    /// to itself.
+    fn build(source: &str) -> Module {
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+        use oxidex_typecheck::check::{check_bodies, collect_signatures};
+
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let mut ctx = Context::new(parser.interner());
+        intrinsics::declare(&mut ctx);
+        collect_signatures(&mut ctx, &decls).unwrap();
+        check_bodies(&mut ctx, &decls).unwrap();
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        verify_module(&module).unwrap();
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+        module
+    }
+
+    /// The instructions of a function, in block order.
+    fn insts(func: &Function) -> Vec<&Inst> {
+        func.blocks.iter().flat_map(|block| &block.insts).map(|inst| &inst.inst).collect()
+    }
+
+    #[test]
+    fn test_string_literals_lower_to_their_text() {
+        let module = build(
+            r#"
+            const NAME: String = "a" + "b";
+            fn name() -> String { NAME }
+            fn greet() -> String { "say \"hi\"\n\u{e9}" }
+            "#,
+        );
+
+        // Literals are decoded, and constants fold to their value
+        let greet = module.function("greet").unwrap();
+        assert_eq!(insts(greet), [&Inst::Const(Constant::String("say \"hi\"\n\u{e9}".to_string()))]);
+        let name = module.function("name").unwrap();
+        assert_eq!(insts(name), [&Inst::Const(Constant::String("ab".to_string()))]);
+        assert!(module.to_string().contains(r#"const string "say \"hi\"\n"#));
+    }
+
+    #[test]
+    fn test_operators_lower_to_typed_instructions() {
+        let module = build("fn scalars(n: Int, x: Float) -> Bool { !(n < 2) == (x >= 1.5) }");
+        let func = module.function("scalars").unwrap();
+        let [n, x] = func.params[..] else { unreachable!() };
+        let [two, less, not, half, at_least, equal] = &func.blocks[0].insts[..] else {
+            panic!("expected six instructions, got {:?}", func.blocks[0].insts)
+        };
+        assert_eq!(two.inst, Inst::Const(Constant::Int(2)));
+        assert_eq!(less.inst, Inst::Binary { op: BinaryOp::Lt, lhs: n, rhs: two.result });
+        assert_eq!(not.inst, Inst::Unary { op: UnaryOp::Negate, operand: less.result });
+        assert_eq!(half.inst, Inst::Const(Constant::Float(1.5)));
+        assert_eq!(at_least.inst, Inst::Binary { op: BinaryOp::Gte, lhs: x, rhs: half.result });
+        assert_eq!(equal.inst, Inst::Binary { op: BinaryOp::Eq, lhs: not.result, rhs: at_least.result });
+
+        // Comparisons are booleans whatever their operands
+        for inst in [less, not, at_least, equal] {
+            assert_eq!(func.value_type(inst.result), &IrType::Bool);
+        }
+        assert_eq!(func.value_type(half.result), &IrType::Float);
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let module = build(
+            "fn both(a: Bool, b: Bool) -> Bool { a && b }\n\
+             fn either(a: Bool, b: Bool) -> Bool { a || b }",
+        );
+
+        // The right operand is evaluated in its own block, which `&&` enters
+        // when the left one holds and `||` when it does not
+        for (name, rhs_when_true) in [("both", true), ("either", false)] {
+            let func = module.function(name).unwrap();
+            let [a, b] = func.params[..] else { unreachable!() };
+            let (rhs, merge) = (BlockId(1), BlockId(2));
+            let (then_block, else_block) = if rhs_when_true { (rhs, merge) } else { (merge, rhs) };
+            assert_eq!(func.blocks[0].terminator, Terminator::Branch { cond: a, then_block, else_block });
+            let [phi] = func.block(merge).phis.as_slice() else {
+                panic!("expected one phi in {name}")
+            };
+            assert_eq!(phi.incoming, [(BlockId(0), a), (rhs, b)]);
+        }
+    }
+
+    #[test]
+    fn test_returns_and_comptime_branches() {
+        let module = build("fn pick(n: Int) -> Int { if n > 0 { return 1; }; comptime if 2 > 1 { n } else { 0 } }");
+        let func = module.function("pick").unwrap();
+
+        // An early return ends its block; only the selected comptime branch
+        // is built, so the function returns its parameter
+        let Terminator::Branch { then_block, .. } = func.blocks[0].terminator else {
+            panic!("expected the `if` to branch")
+        };
+        let then_block = func.block(then_block);
+        assert_eq!(then_block.insts[0].inst, Inst::Const(Constant::Int(1)));
+        assert_eq!(then_block.terminator, Terminator::Return(Some(then_block.insts[0].result)));
+        assert_eq!(func.blocks.last().unwrap().terminator, Terminator::Return(Some(func.params[0])));
+        assert!(!insts(func).contains(&&Inst::Const(Constant::Int(2))));
+    }
+
+    #[test]
+    fn test_closures_lift_with_their_captures() {
+        let module = build(
+            "struct Counter { step: Int }\n\
+             impl Counter {\n\
+                 fn scaled(n: Int) -> Int {\n\
+                     mut total = n;\n\
+                     let add = fn(x: Int) -> Int { total = total + x * step; total };\n\
+                     let outer = fn() -> Int { let inner = fn() -> Int { n }; inner() };\n\
+                     add(1) + outer()\n\
+                 }\n\
+             }",
+        );
+
+        // The mutable variable is boxed from its declaration, and the
+        // closure using a field captures the receiver after the box
+        let scaled = module.function("Counter.scaledN:").unwrap();
+        let [this, n] = scaled.params[..] else { unreachable!() };
+        let body = insts(scaled);
+        assert_eq!(body[0], &Inst::Box(n));
+        let cell = scaled.blocks[0].insts[0].result;
+        let add = "Counter.scaledN:$closure0".to_string();
+        assert_eq!(body[1], &Inst::Closure { function: add.clone(), captures: vec![cell, this] });
+        let outer = "Counter.scaledN:$closure1".to_string();
+        assert_eq!(body[2], &Inst::Closure { function: outer, captures: vec![n] });
+        let calls = scaled.blocks[0].insts.iter().filter(|inst| matches!(inst.inst, Inst::CallIndirect { .. }));
+        assert!(calls.clone().count() == 2 && calls.clone().all(|call| *scaled.value_type(call.result) == IrType::Int));
+
+        // Captures come before the closure's own parameters; the boxed one
+        // is read and assigned through the box
+        let add = module.function(&add).unwrap();
+        let [cell, this, x] = add.params[..] else { unreachable!() };
+        assert_eq!(add.value_type(cell), &IrType::Object(None));
+        assert_eq!(add.value_type(this), &IrType::Object(Some("Counter".to_string())));
+        assert_eq!(add.value_type(x), &IrType::Int);
+        let add_insts = insts(add);
+        assert!(add_insts.contains(&&Inst::Load(cell)));
+        assert!(add_insts.iter().any(|inst| matches!(inst, Inst::Store { cell: stored, .. } if *stored == cell)));
+        assert!(add_insts.contains(&&Inst::GetField { object: this, field: "step".to_string() }));
+
+        // A nested closure is lifted out of the closure around it
+        let outer = module.function("Counter.scaledN:$closure1").unwrap();
+        let inner = "Counter.scaledN:$closure1$closure0".to_string();
+        assert!(insts(outer).contains(&&Inst::Closure { function: inner.clone(), captures: outer.params.clone() }));
+        let inner = module.function(&inner).unwrap();
+        assert_eq!(inner.blocks[0].terminator, Terminator::Return(Some(inner.params[0])));
+    }
+
+    #[test]
+    fn test_calls_lower_by_callee() {
+        let module = build(
+            "fn double(n: Int) -> Int { n * 2 }\n\
+             fn direct(n: Int) -> Int { double(n) }\n\
+             fn apply(f: (Int) -> Int, n: Int) -> Int { f(n) }\n\
+             fn count(words: [String]) -> Int { len(words) }",
+        );
+
+        // Functions are called by name, values through their closure, and
+        // std functions the program does not declare are intrinsics
+        let direct = module.function("direct").unwrap();
+        let n = direct.params[0];
+        assert_eq!(insts(direct), [&Inst::Call { callee: "double".to_string(), args: vec![n] }]);
+        assert_eq!(direct.value_type(direct.blocks[0].insts[0].result), &IrType::Int);
+
+        let apply = module.function("apply").unwrap();
+        let [f, n] = apply.params[..] else { unreachable!() };
+        assert_eq!(insts(apply), [&Inst::CallIndirect { callee: f, args: vec![n] }]);
+
+        let count = module.function("count").unwrap();
+        let words = count.params[0];
+        assert_eq!(insts(count), [&Inst::Intrinsic { intrinsic: Intrinsic::Length, args: vec![words] }]);
+    }
+
+    #[test]
+    fn test_blocks_scope_their_bindings() {
+        let module = build("fn shadow(n: Int) -> Int { let m = n; { let m = 1; }; m }");
+        let func = module.function("shadow").unwrap();
+
+        // The inner `m` is gone once its block ends
+        assert_eq!(func.blocks[0].terminator, Terminator::Return(Some(func.params[0])));
+    }
+
+    #[test]
+    fn test_literal_patterns_switch_or_compare() {
+        let module = build(
+            "fn classify(n: Int) -> Int { match (n) { 1 => 10, 2 => 20, _ => 0 } }\n\
+             fn named(s: String) -> Int { match (s) { \"a\" => 1, _ => 0 } }",
+        );
+
+        // Integers are dispatched on at once
+        let classify = module.function("classify").unwrap();
+        let Terminator::Switch { value, cases, .. } = &classify.blocks[0].terminator else {
+            panic!("expected a switch, got {:?}", classify.blocks[0].terminator)
+        };
+        assert_eq!(*value, classify.params[0]);
+        assert_eq!(cases.iter().map(|&(key, _)| key).collect::<Vec<_>>(), [1, 2]);
+
+        // Other literals are compared one after another
+        let named = module.function("named").unwrap();
+        let s = named.params[0];
+        let [literal, test] = &named.blocks[0].insts[..] else {
+            panic!("expected a comparison, got {:?}", named.blocks[0].insts)
+        };
+        assert_eq!(literal.inst, Inst::Const(Constant::String("a".to_string())));
+        assert_eq!(test.inst, Inst::Binary { op: BinaryOp::Eq, lhs: s, rhs: literal.result });
+        assert!(matches!(named.blocks[0].terminator, Terminator::Branch { cond, .. } if cond == test.result));
+    }
+
+    #[test]
+    fn test_aggregates_allocate_and_access_fields() {
+        let module = build(
+            "enum Shape { case circle(Int), case empty }\n\
+             struct Point { x: Int, y: Int }\n\
+             fn shapes(n: Int) -> Shape { if n > 0 { Shape::circle(n) } else { Shape::empty } }\n\
+             fn points(x: Int) -> Int { mut p = Point { y: 2, x }; p.y = p.x; p.y }",
+        );
+
+        // Variants carry their payload, if any
+        let shapes = module.function("shapes").unwrap();
+        let n = shapes.params[0];
+        let variant = |variant: &str, tag, payload| Inst::Variant {
+            enum_name: "Shape".to_string(),
+            variant: variant.to_string(),
+            tag,
+            payload,
+        };
+        let variants: Vec<&Inst> =
+            insts(shapes).into_iter().filter(|inst| matches!(inst, Inst::Variant { .. })).collect();
+        assert_eq!(variants, [&variant("circle", 0, Some(n)), &variant("empty", 1, None)]);
+        assert_eq!(shapes.return_type, IrType::Object(Some("Shape".to_string())));
+
+        // Struct literals allocate, evaluate their fields in the order they
+        // are written, then set each, shorthand or not, in the order the
+        // struct declares them; fields are read and assigned through the
+        // object
+        let points = module.function("points").unwrap();
+        let x = points.params[0];
+        let insts = &points.blocks[0].insts;
+        let object = insts[0].result;
+        let set = |field: &str, value| Inst::SetField { object, field: field.to_string(), value };
+        let get = |field: &str| Inst::GetField { object, field: field.to_string() };
+        assert_eq!(insts[0].inst, Inst::Alloc { class: "Point".to_string() });
+        assert_eq!(insts[2].inst, set("x", x));
+        assert_eq!(insts[3].inst, set("y", insts[1].result));
+        assert_eq!(insts[4].inst, get("x"));
+        assert_eq!(insts[5].inst, set("y", insts[4].result));
+        assert_eq!(insts.last().unwrap().inst, get("y"));
+        assert_eq!(points.value_type(insts[4].result), &IrType::Int);
+    }
+
+    #[test]
+    fn test_collections_lower_to_literals_and_indexing() {
+        let module = build(
+            "fn collections(n: Int) -> Int {\n\
+             mut words = [\"x\", \"y\"]; let table = [\"k\": n]; words[0] = \"z\"; len(words[1])\n\
+             }",
+        );
+        let func = module.function("collections").unwrap();
+        let n = func.params[0];
+        let insts = &func.blocks[0].insts;
+        let [x, y] = [&insts[0], &insts[1]];
+        assert_eq!(insts[2].inst, Inst::Array(vec![x.result, y.result]));
+        let words = insts[2].result;
+        assert_eq!(insts[4].inst, Inst::Dict(vec![(insts[3].result, n)]));
+
+        // Index assignment and reads go through the collection
+        assert_eq!(
+            insts[7].inst,
+            Inst::SetIndex { collection: words, index: insts[5].result, value: insts[6].result }
+        );
+        let read = insts.iter().find(|inst| matches!(inst.inst, Inst::GetIndex { .. })).unwrap();
+        assert!(matches!(read.inst, Inst::GetIndex { collection, .. } if collection == words));
+        assert_eq!(func.value_type(read.result), &IrType::Object(None));
+    }
+
+    #[test]
+    fn test_for_loops_follow_the_iteration_protocol() {
+        let module = build("fn total(xs: [Int]) -> Int { mut sum = 0; for x in xs { sum = sum + x; }; sum }");
+        let func = module.function("total").unwrap();
+        let xs = func.params[0];
+
+        // The sequence makes an iterator, whose `next()` the header sends
+        let entry = &func.blocks[0].insts;
+        let iterator = entry.iter().find(|inst| matches!(inst.inst, Inst::Send { .. })).unwrap();
+        let make = Inst::Send { receiver: xs, selector: "makeIterator".to_string(), args: vec![] };
+        assert_eq!(iterator.inst, make);
+        let header = func.block(BlockId(1));
+        let next = Inst::Send { receiver: iterator.result, selector: "next".to_string(), args: vec![] };
+        assert_eq!(header.insts[0].inst, next);
+
+        // The loop ends on `nil`, and the sum flows around the back edge
+        let Terminator::Branch { cond, then_block: exit, .. } = header.terminator else {
+            panic!("expected the header to branch, got {:?}", header.terminator)
+        };
+        let done = &header.insts.iter().find(|inst| inst.result == cond).unwrap().inst;
+        assert!(matches!(done, Inst::Binary { op: BinaryOp::Eq, lhs, .. } if *lhs == header.insts[0].result));
+        let [sum] = header.phis.as_slice() else {
+            panic!("expected one phi, got {:?}", header.phis)
+        };
+        assert_eq!(func.block(exit).terminator, Terminator::Return(Some(sum.result)));
+    }
+
+    #[test]
+    fn test_defer_runs_at_exits_and_when_unwinding() {
+        let source = "fn log(n: Int) {}\n\
+                      fn guarded(n: Int) -> Int {\n\
+                          defer { log(n); }\n\
+                          if n > 0 { return 1; };\n\
+                          n\n\
+                      }";
+        let module = build(source);
+        let func = module.function("guarded").unwrap();
+
+        // The body is a closure called before the `return` and at the end
+        // of the block, and by the cleanup's handler
+        let closure = func.blocks[0].insts[0].result;
+        let lifted = "guarded$closure0".to_string();
+        assert_eq!(func.blocks[0].insts[0].inst, Inst::Closure { function: lifted.clone(), captures: func.params.clone() });
+        let calls = insts(func).into_iter().filter(|inst| **inst == Inst::CallIndirect { callee: closure, args: vec![] });
+        assert_eq!(calls.count(), 3);
+        let deferred = module.function(&lifted).unwrap();
+        assert!(insts(deferred).iter().any(|inst| matches!(inst, Inst::Call { callee, .. } if callee == "log")));
+
+        // Errors raised after the `defer` leave through the handler, which
+        // resumes unwinding; the calls made on the way out are not covered
+        let [cleanup] = func.cleanups.as_slice() else {
+            panic!("expected one cleanup, got {:?}", func.cleanups)
+        };
+        let handler = func.block(cleanup.handler);
+        assert_eq!(handler.terminator, Terminator::Resume);
+        assert_eq!(handler.insts[0].inst, Inst::CallIndirect { callee: closure, args: vec![] });
+        for block in &func.blocks {
+            let runs = block.insts.iter().any(|inst| inst.inst == Inst::CallIndirect { callee: closure, args: vec![] });
+            assert_eq!(cleanup.blocks.contains(&block.id), !runs && block.id != BlockId(0), "{block:?}");
+        }
+
+        // Instructions lowered from source know where they come from
+        let comparison = insts(func).into_iter().position(|inst| matches!(inst, Inst::Binary { .. })).unwrap();
+        let span = func.blocks.iter().flat_map(|block| &block.insts).nth(comparison).unwrap().span.unwrap();
+        assert_eq!(&source[span.start..span.end], "n > 0");
+        assert!(func.blocks.iter().flat_map(|block| &block.insts).all(|inst| inst.span.is_some()));
+    }
+}
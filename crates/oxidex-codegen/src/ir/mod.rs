@@ -0,0 +1,607 @@
+//! Mid-level intermediate representation.
+//!
+//! The IR is a typed SSA form shared by the optimizer, the bytecode compiler
+//! and the AOT backend, so none of them has to walk the AST:
+//!
+//! - A [`Function`] is a list of basic [`Block`]s; the first is the entry.
+//! - Every [`Instruction`] defines exactly one [`ValueId`], and every value is
+//!   defined once. Instructions that exist only for their effect define a
+//!   value of type [`IrType::Unit`].
+//! - Values flowing in from several predecessors are merged by [`Phi`] nodes
+//!   at the start of a block.
+//! - Every block ends in a single [`Terminator`].
+//! - Instructions the builder lowers from source carry the [`Span`] of the
+//!   expression or statement they come from.
+//!
+//! Methods of user types become functions named `Type.selector` whose first
+//! parameter is the receiver (see [`method_symbol`]). Messages to objects
+//! are explicit [`Inst::Send`]s, while calls whose target is known
+//! statically are [`Inst::Call`]s. Calls and sends to the std operations
+//! the backends compute inline are [`Inst::Intrinsic`]s (see
+//! [`crate::intrinsics`]).
+//!
+//! Closures are lifted out into functions of their own, which take the
+//! captured values before their parameters; an [`Inst::Closure`] pairs one
+//! with the values it captures. Variables a closure may assign live in an
+//! [`Inst::Box`] (see [`crate::closure`]).
+//!
+//! An error raised in the blocks of a [`Cleanup`] enters its handler block,
+//! which runs the code the program deferred and ends in
+//! [`Terminator::Resume`] to keep unwinding. Control flow never reaches a
+//! handler otherwise; its values come from the blocks before the cleanup's.
+//!
+//! The [`builder`] module constructs the IR from a checked and lowered
+//! program, [`verify`] checks its invariants, and [`text`] prints and parses
+//! it.
+
+pub mod builder;
+pub mod text;
+pub mod verify;
+
+pub use builder::build_module;
+pub use text::{ParseError, parse_function, parse_module};
+pub use verify::{VerifyError, verify_function, verify_module};
+
+use crate::intrinsics::Intrinsic;
+use oxidex_syntax::Span;
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use oxidex_typecheck::{PrimTy, Ty};
+use oxidex_mem::StringInterner;
+
+/// An SSA value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+/// A basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+/// Type of an IR value.
+///
+/// The IR only distinguishes what backends need to pass a value around:
+/// unboxed scalars and object words. Objects whose class is known
+/// statically carry its name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IrType {
+    /// No value
+    Unit,
+    /// Boolean
+    Bool,
+    /// Integer of any width (and characters)
+    Int,
+    /// Floating point number of any width
+    Float,
+    /// String
+    String,
+    /// Object word, with the name of its class when known
+    Object(Option<String>),
+}
+
+impl IrType {
+    /// Convert a checked type to its IR representation.
+    ///
+    /// Nominal types keep their name; collections, optionals, tuples,
+    /// functions and unresolved type variables become untyped objects.
+    #[must_use]
+    pub fn from_ty(ty: &Ty, interner: &StringInterner) -> Self {
+        match ty {
+            Ty::Primitive(prim) => match prim {
+                PrimTy::Unit => Self::Unit,
+                PrimTy::Bool => Self::Bool,
+                PrimTy::Float32 | PrimTy::Float64 => Self::Float,
+                PrimTy::String => Self::String,
+                PrimTy::Int8
+                | PrimTy::Int16
+                | PrimTy::Int32
+                | PrimTy::Int64
+                | PrimTy::Int128
+                | PrimTy::UInt8
+                | PrimTy::UInt16
+                | PrimTy::UInt32
+                | PrimTy::UInt64
+                | PrimTy::UInt128
+                | PrimTy::Char => Self::Int,
+            },
+            Ty::Never => Self::Unit,
+            Ty::Struct { name, .. } | Ty::Class { name, .. } | Ty::Enum { name, .. } => {
+                Self::Object(interner.resolve(*name).map(str::to_string))
+            }
+            _ => Self::Object(None),
+        }
+    }
+
+    /// Get the statically known class of an object value.
+    #[must_use]
+    pub fn class_name(&self) -> Option<&str> {
+        match self {
+            Self::Object(Some(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Whether values of this type are passed as object words.
+    #[must_use]
+    pub fn is_object(&self) -> bool {
+        matches!(self, Self::Object(_))
+    }
+}
+
+/// A constant operand.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// The unit value
+    Unit,
+    /// `nil`
+    Nil,
+    /// Boolean literal
+    Bool(bool),
+    /// Integer literal
+    Int(i64),
+    /// Floating point literal
+    Float(f64),
+    /// String literal
+    String(String),
+}
+
+/// An operation producing a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /// A constant
+    Const(Constant),
+    /// Load a global: a top-level function, constant or static
+    Global(String),
+    /// Arithmetic or comparison on unboxed operands
+    ///
+    /// `&&`, `||` and `=` never appear here; they lower to control flow
+    /// and variable writes.
+    Binary {
+        /// Operator
+        op: BinaryOp,
+        /// Left operand
+        lhs: ValueId,
+        /// Right operand
+        rhs: ValueId,
+    },
+    /// Unary operation on an unboxed operand
+    Unary {
+        /// Operator
+        op: UnaryOp,
+        /// Operand
+        operand: ValueId,
+    },
+    /// Direct call of a function known at compile time
+    Call {
+        /// Function name (see [`method_symbol`] for methods)
+        callee: String,
+        /// Arguments, including the receiver for methods
+        args: Vec<ValueId>,
+    },
+    /// Call of a function value
+    CallIndirect {
+        /// The function being called
+        callee: ValueId,
+        /// Arguments
+        args: Vec<ValueId>,
+    },
+    /// Message send, dispatched dynamically on the receiver
+    Send {
+        /// Receiver
+        receiver: ValueId,
+        /// Selector
+        selector: String,
+        /// Arguments, excluding the receiver
+        args: Vec<ValueId>,
+    },
+    /// Allocate an instance with uninitialized fields
+    Alloc {
+        /// Class to instantiate
+        class: String,
+    },
+    /// Read a field
+    GetField {
+        /// Object holding the field
+        object: ValueId,
+        /// Field name
+        field: String,
+    },
+    /// Write a field
+    SetField {
+        /// Object holding the field
+        object: ValueId,
+        /// Field name
+        field: String,
+        /// New value
+        value: ValueId,
+    },
+    /// Construct an enum value
+    Variant {
+        /// Enum name
+        enum_name: String,
+        /// Variant name
+        variant: String,
+        /// Position of the variant in the declaration, which [`Inst::Tag`]
+        /// reads back
+        tag: usize,
+        /// Payload, if the variant carries one
+        payload: Option<ValueId>,
+    },
+    /// Construct a tuple
+    Tuple(Vec<ValueId>),
+    /// Construct an array
+    Array(Vec<ValueId>),
+    /// Construct a dictionary
+    Dict(Vec<(ValueId, ValueId)>),
+    /// Read an element of a collection
+    GetIndex {
+        /// Collection
+        collection: ValueId,
+        /// Index or key
+        index: ValueId,
+    },
+    /// Write an element of a collection
+    SetIndex {
+        /// Collection
+        collection: ValueId,
+        /// Index or key
+        index: ValueId,
+        /// New value
+        value: ValueId,
+    },
+    /// Concatenate the descriptions of the operands into a string
+    Concat(Vec<ValueId>),
+    /// Read the tag of an enum value: the position of its variant in the
+    /// declaration
+    Tag(ValueId),
+    /// Read the payload of an enum value
+    Payload(ValueId),
+    /// Read the number of elements of a collection
+    Length(ValueId),
+    /// Copy the elements of an array from an index on
+    Slice {
+        /// Array
+        collection: ValueId,
+        /// Index of the first element copied
+        start: usize,
+    },
+    /// Create a closure: a function value that calls `function` with the
+    /// captured values before its own arguments
+    Closure {
+        /// Function lifted out of the closure expression, taking the
+        /// captures as its first parameters
+        function: String,
+        /// Captured values, and boxes of variables captured by box
+        captures: Vec<ValueId>,
+    },
+    /// Put a value in a new box: a mutable cell the function and the
+    /// closures it creates share
+    Box(ValueId),
+    /// Read the value in a box
+    Load(ValueId),
+    /// Replace the value in a box
+    Store {
+        /// Box
+        cell: ValueId,
+        /// New value
+        value: ValueId,
+    },
+    /// Compute a std operation inline
+    Intrinsic {
+        /// Operation
+        intrinsic: Intrinsic,
+        /// Operands, starting with the receiver of a std method
+        args: Vec<ValueId>,
+    },
+}
+
+impl Inst {
+    /// Get the values the instruction reads.
+    #[must_use]
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Self::Const(_) | Self::Global(_) | Self::Alloc { .. } => vec![],
+            Self::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Self::Unary { operand, .. } => vec![*operand],
+            Self::Call { args, .. }
+            | Self::Tuple(args)
+            | Self::Array(args)
+            | Self::Concat(args)
+            | Self::Intrinsic { args, .. }
+            | Self::Closure { captures: args, .. } => args.clone(),
+            Self::CallIndirect { callee, args } | Self::Send { receiver: callee, args, .. } => {
+                std::iter::once(*callee).chain(args.iter().copied()).collect()
+            }
+            Self::GetField { object, .. }
+            | Self::Tag(object)
+            | Self::Payload(object)
+            | Self::Length(object)
+            | Self::Slice { collection: object, .. }
+            | Self::Box(object)
+            | Self::Load(object) => vec![*object],
+            Self::SetField { object, value, .. } | Self::Store { cell: object, value } => vec![*object, *value],
+            Self::Variant { payload, .. } => payload.iter().copied().collect(),
+            Self::Dict(entries) => entries.iter().flat_map(|&(k, v)| [k, v]).collect(),
+            Self::GetIndex { collection, index } => vec![*collection, *index],
+            Self::SetIndex { collection, index, value } => vec![*collection, *index, *value],
+        }
+    }
+
+    /// Get mutable references to the values the instruction reads.
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Self::Const(_) | Self::Global(_) | Self::Alloc { .. } => vec![],
+            Self::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Self::Unary { operand, .. } => vec![operand],
+            Self::Call { args, .. }
+            | Self::Tuple(args)
+            | Self::Array(args)
+            | Self::Concat(args)
+            | Self::Intrinsic { args, .. }
+            | Self::Closure { captures: args, .. } => args.iter_mut().collect(),
+            Self::CallIndirect { callee, args } | Self::Send { receiver: callee, args, .. } => {
+                std::iter::once(callee).chain(args.iter_mut()).collect()
+            }
+            Self::GetField { object, .. }
+            | Self::Tag(object)
+            | Self::Payload(object)
+            | Self::Length(object)
+            | Self::Slice { collection: object, .. }
+            | Self::Box(object)
+            | Self::Load(object) => vec![object],
+            Self::SetField { object, value, .. } | Self::Store { cell: object, value } => vec![object, value],
+            Self::Variant { payload, .. } => payload.iter_mut().collect(),
+            Self::Dict(entries) => entries.iter_mut().flat_map(|(k, v)| [k, v]).collect(),
+            Self::GetIndex { collection, index } => vec![collection, index],
+            Self::SetIndex { collection, index, value } => vec![collection, index, value],
+        }
+    }
+
+    /// Whether the instruction may have an effect besides producing its value.
+    ///
+    /// Pure instructions whose value is unused can be removed.
+    #[must_use]
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            Self::Call { .. }
+                | Self::CallIndirect { .. }
+                | Self::Send { .. }
+                | Self::SetField { .. }
+                | Self::SetIndex { .. }
+                | Self::Store { .. }
+        )
+    }
+}
+
+/// An instruction and the value it defines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// The value defined by the instruction
+    pub result: ValueId,
+    /// The operation
+    pub inst: Inst,
+    /// Where in the source the instruction comes from, if anywhere
+    pub span: Option<Span>,
+}
+
+/// A phi node: selects a value by the predecessor control came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    /// The value defined by the phi
+    pub result: ValueId,
+    /// Incoming value for each predecessor
+    pub incoming: Vec<(BlockId, ValueId)>,
+}
+
+/// How control leaves a block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    /// Return from the function
+    Return(Option<ValueId>),
+    /// Continue in another block
+    Jump(BlockId),
+    /// Continue in one of two blocks depending on a boolean
+    Branch {
+        /// Condition
+        cond: ValueId,
+        /// Block taken when the condition holds
+        then_block: BlockId,
+        /// Block taken otherwise
+        else_block: BlockId,
+    },
+    /// Continue in the block whose case equals an integer
+    Switch {
+        /// Value compared against the cases
+        value: ValueId,
+        /// Case values and their blocks; each block appears once
+        cases: Vec<(i64, BlockId)>,
+        /// Block taken when no case matches
+        default: BlockId,
+    },
+    /// Control never reaches the end of the block
+    Unreachable,
+    /// Keep unwinding the error that entered a cleanup's handler
+    Resume,
+}
+
+impl Terminator {
+    /// Get the blocks control may continue in.
+    #[must_use]
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Jump(target) => vec![*target],
+            Self::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Self::Switch { cases, default, .. } => cases.iter().map(|&(_, b)| b).chain([*default]).collect(),
+            Self::Return(_) | Self::Unreachable | Self::Resume => vec![],
+        }
+    }
+
+    /// Get the values the terminator reads.
+    #[must_use]
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Self::Return(value) => value.iter().copied().collect(),
+            Self::Branch { cond, .. } | Self::Switch { value: cond, .. } => vec![*cond],
+            Self::Jump(_) | Self::Unreachable | Self::Resume => vec![],
+        }
+    }
+
+    /// Get mutable references to the values the terminator reads.
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Self::Return(value) => value.iter_mut().collect(),
+            Self::Branch { cond, .. } | Self::Switch { value: cond, .. } => vec![cond],
+            Self::Jump(_) | Self::Unreachable | Self::Resume => vec![],
+        }
+    }
+}
+
+/// A basic block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Block identifier (its index in [`Function::blocks`])
+    pub id: BlockId,
+    /// Phi nodes, evaluated on entry
+    pub phis: Vec<Phi>,
+    /// Instructions, in execution order
+    pub insts: Vec<Instruction>,
+    /// How control leaves the block
+    pub terminator: Terminator,
+}
+
+/// A function in SSA form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Function name
+    pub name: String,
+    /// Parameter values, starting with the receiver for instance methods
+    pub params: Vec<ValueId>,
+    /// Return type
+    pub return_type: IrType,
+    /// Basic blocks; the first one is the entry block
+    pub blocks: Vec<Block>,
+    /// Type of every value, indexed by [`ValueId`]
+    pub values: Vec<IrType>,
+    /// Cleanups of errors raised in the function, innermost first
+    pub cleanups: Vec<Cleanup>,
+    /// Values the function's named variables hold, with the name, in the
+    /// order they were first assigned
+    pub variables: Vec<(ValueId, String)>,
+}
+
+/// Code that runs when an error unwinds out of some blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cleanup {
+    /// Blocks an error leaves through the handler
+    pub blocks: Vec<BlockId>,
+    /// Block running the cleanup, which ends in [`Terminator::Resume`]
+    pub handler: BlockId,
+}
+
+impl Function {
+    /// Get the type of a value.
+    #[must_use]
+    pub fn value_type(&self, value: ValueId) -> &IrType {
+        &self.values[value.0 as usize]
+    }
+
+    /// Get a block.
+    #[must_use]
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    /// Get the handler an error raised in a block enters, if any: that of
+    /// the innermost cleanup covering it.
+    #[must_use]
+    pub fn handler(&self, block: BlockId) -> Option<BlockId> {
+        self.cleanups.iter().find(|cleanup| cleanup.blocks.contains(&block)).map(|cleanup| cleanup.handler)
+    }
+
+    /// Compute the predecessors of every block, indexed by [`BlockId`].
+    #[must_use]
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for block in &self.blocks {
+            for succ in block.terminator.successors() {
+                preds[succ.0 as usize].push(block.id);
+            }
+        }
+        preds
+    }
+
+    /// Replace every use of `old` with `new`, variables holding it included.
+    pub fn replace_uses(&mut self, old: ValueId, new: ValueId) {
+        for block in &mut self.blocks {
+            for phi in &mut block.phis {
+                for (_, value) in &mut phi.incoming {
+                    if *value == old {
+                        *value = new;
+                    }
+                }
+            }
+            for inst in &mut block.insts {
+                for value in inst.inst.operands_mut() {
+                    if *value == old {
+                        *value = new;
+                    }
+                }
+            }
+            for value in block.terminator.operands_mut() {
+                if *value == old {
+                    *value = new;
+                }
+            }
+        }
+        for (value, _) in &mut self.variables {
+            if *value == old {
+                *value = new;
+            }
+        }
+    }
+}
+
+/// A program in SSA form.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    /// Functions and methods
+    pub functions: Vec<Function>,
+    /// Functions made available to C under a symbol of their own
+    pub exports: Vec<Export>,
+    /// C functions the program declares with `extern`
+    pub externs: Vec<Extern>,
+}
+
+/// A function exported with a C ABI symbol, from `@export("symbol")`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    /// Name of the exported function
+    pub function: String,
+    /// C symbol it is exported as
+    pub symbol: String,
+}
+
+/// A C function declared in an `extern` block, called by name like a
+/// function of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extern {
+    /// Name of the function, which is also its C symbol
+    pub name: String,
+    /// Shared library it is loaded from, or `None` for the process's symbols
+    pub library: Option<String>,
+    /// Type encoding of the signature: the return type, then each parameter
+    pub encoding: String,
+}
+
+impl Module {
+    /// Look up a function by name.
+    #[must_use]
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+/// Name of the function implementing a method.
+#[must_use]
+pub fn method_symbol(class: &str, selector: &str) -> String {
+    format!("{class}.{selector}")
+}
@@ -0,0 +1,1069 @@
+//! Textual form of the IR.
+//!
+//! [`Function`] and [`Module`] print in a line-oriented format that
+//! [`parse_module`] reads back into an identical module, so passes can be
+//! tested on hand-written input and compared against expected output:
+//!
+//! ```text
+//! fn "max"(%0: int, %1: int) -> int {
+//! bb0:
+//!     %2: bool = binary gt %0, %1
+//!     branch %2, bb1, bb2
+//! bb1:
+//!     jump bb3
+//! bb2:
+//!     jump bb3
+//! bb3:
+//!     %3: int = phi [bb1: %0, bb2: %1]
+//!     return %3
+//! }
+//! ```
+//!
+//! Exported functions are listed before the functions, one per line, as
+//! `export "add" as "ox_add"`, followed by the C functions the program
+//! declares, as `extern "cos" "dd" from "libm.so.6"` with the signature's
+//! type encoding and, unless it is one of the process's symbols, the
+//! library.
+//!
+//! Every phi and instruction defines its value together with its type.
+//! Values with a type but no definition, left behind when the builder
+//! prunes dead blocks, are declared with `unused %N: type` before the first
+//! block, followed by the function's cleanups, innermost first, as
+//! `cleanup bb4 for [bb1, bb2]`, and the values of its named variables, as
+//! `var %3 "sum"`. An instruction with a source location ends
+//! in `span(start, end, line:column, line:column)`, its byte offsets and the
+//! positions of its first and last characters. Names, fields, selectors and
+//! string constants are quoted as Rust string literals. Text after `//` is
+//! a comment.
+
+use super::{
+    BlockId, Block, Cleanup, Constant, Export, Extern, Function, Inst, Instruction, IrType, Module, Phi, Terminator,
+    ValueId,
+};
+use crate::intrinsics::Intrinsic;
+use oxidex_syntax::Span;
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for IrType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unit => write!(f, "unit"),
+            Self::Bool => write!(f, "bool"),
+            Self::Int => write!(f, "int"),
+            Self::Float => write!(f, "float"),
+            Self::String => write!(f, "string"),
+            Self::Object(None) => write!(f, "object"),
+            Self::Object(Some(class)) => write!(f, "object({class:?})"),
+        }
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for export in &self.exports {
+            writeln!(f, "export {:?} as {:?}", export.function, export.symbol)?;
+        }
+        for external in &self.externs {
+            write!(f, "extern {:?} {:?}", external.name, external.encoding)?;
+            if let Some(library) = &external.library {
+                write!(f, " from {library:?}")?;
+            }
+            writeln!(f)?;
+        }
+        if (!self.exports.is_empty() || !self.externs.is_empty()) && !self.functions.is_empty() {
+            writeln!(f)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|&p| format!("{p}: {}", self.value_type(p))).collect();
+        writeln!(f, "fn {:?}({}) -> {} {{", self.name, params.join(", "), self.return_type)?;
+
+        let mut defined = vec![false; self.values.len()];
+        for value in self.params.iter().chain(self.blocks.iter().flat_map(|block| {
+            block.phis.iter().map(|phi| &phi.result).chain(block.insts.iter().map(|inst| &inst.result))
+        })) {
+            if let Some(slot) = defined.get_mut(value.0 as usize) {
+                *slot = true;
+            }
+        }
+        for (index, ty) in self.values.iter().enumerate() {
+            if !defined[index] {
+                writeln!(f, "    unused %{index}: {ty}")?;
+            }
+        }
+        for cleanup in &self.cleanups {
+            let blocks: Vec<String> = cleanup.blocks.iter().map(ToString::to_string).collect();
+            writeln!(f, "    cleanup {} for [{}]", cleanup.handler, blocks.join(", "))?;
+        }
+        for (value, name) in &self.variables {
+            writeln!(f, "    var {value} {name:?}")?;
+        }
+
+        for block in &self.blocks {
+            writeln!(f, "{}:", block.id)?;
+            for phi in &block.phis {
+                let incoming: Vec<String> =
+                    phi.incoming.iter().map(|(pred, value)| format!("{pred}: {value}")).collect();
+                writeln!(f, "    {}: {} = phi [{}]", phi.result, self.value_type(phi.result), incoming.join(", "))?;
+            }
+            for inst in &block.insts {
+                write!(f, "    {}: {} = {}", inst.result, self.value_type(inst.result), inst.inst)?;
+                if let Some(span) = inst.span {
+                    let Span { start, end, start_line, start_col, end_line, end_col } = span;
+                    write!(f, " span({start}, {end}, {start_line}:{start_col}, {end_line}:{end_col})")?;
+                }
+                writeln!(f)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Const(constant) => match constant {
+                Constant::Unit => write!(f, "const unit"),
+                Constant::Nil => write!(f, "const nil"),
+                Constant::Bool(value) => write!(f, "const bool {value}"),
+                Constant::Int(value) => write!(f, "const int {value}"),
+                Constant::Float(value) => write!(f, "const float {value:?}"),
+                Constant::String(value) => write!(f, "const string {value:?}"),
+            },
+            Self::Global(name) => write!(f, "global {name:?}"),
+            Self::Binary { op, lhs, rhs } => write!(f, "binary {} {lhs}, {rhs}", binary_name(*op)),
+            Self::Unary { op, operand } => write!(f, "unary {} {operand}", unary_name(*op)),
+            Self::Call { callee, args } => write!(f, "call {callee:?}({})", list(args)),
+            Self::CallIndirect { callee, args } => write!(f, "call_indirect {callee}({})", list(args)),
+            Self::Send { receiver, selector, args } => write!(f, "send {receiver}, {selector:?}({})", list(args)),
+            Self::Alloc { class } => write!(f, "alloc {class:?}"),
+            Self::GetField { object, field } => write!(f, "get_field {object}, {field:?}"),
+            Self::SetField { object, field, value } => write!(f, "set_field {object}, {field:?}, {value}"),
+            Self::Variant { enum_name, variant, tag, payload: None } => {
+                write!(f, "variant {enum_name:?}, {tag}, {variant:?}")
+            }
+            Self::Variant { enum_name, variant, tag, payload: Some(payload) } => {
+                write!(f, "variant {enum_name:?}, {tag}, {variant:?}({payload})")
+            }
+            Self::Tuple(elements) => write!(f, "tuple({})", list(elements)),
+            Self::Array(elements) => write!(f, "array({})", list(elements)),
+            Self::Dict(entries) => {
+                let entries: Vec<String> = entries.iter().map(|(key, value)| format!("{key}: {value}")).collect();
+                write!(f, "dict({})", entries.join(", "))
+            }
+            Self::GetIndex { collection, index } => write!(f, "get_index {collection}, {index}"),
+            Self::SetIndex { collection, index, value } => write!(f, "set_index {collection}, {index}, {value}"),
+            Self::Concat(parts) => write!(f, "concat({})", list(parts)),
+            Self::Tag(value) => write!(f, "tag {value}"),
+            Self::Payload(value) => write!(f, "payload {value}"),
+            Self::Length(value) => write!(f, "length {value}"),
+            Self::Slice { collection, start } => write!(f, "slice {collection}, {start}"),
+            Self::Intrinsic { intrinsic, args } => write!(f, "intrinsic {intrinsic}({})", list(args)),
+            Self::Closure { function, captures } => write!(f, "closure {function:?}({})", list(captures)),
+            Self::Box(value) => write!(f, "box {value}"),
+            Self::Load(cell) => write!(f, "load {cell}"),
+            Self::Store { cell, value } => write!(f, "store {cell}, {value}"),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Return(None) => write!(f, "return"),
+            Self::Return(Some(value)) => write!(f, "return {value}"),
+            Self::Jump(target) => write!(f, "jump {target}"),
+            Self::Branch { cond, then_block, else_block } => write!(f, "branch {cond}, {then_block}, {else_block}"),
+            Self::Switch { value, cases, default } => {
+                let cases: Vec<String> = cases.iter().map(|(key, block)| format!("{key}: {block}")).collect();
+                write!(f, "switch {value} [{}], default {default}", cases.join(", "))
+            }
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::Resume => write!(f, "resume"),
+        }
+    }
+}
+
+fn list(values: &[ValueId]) -> String {
+    let mut out = String::new();
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            out.push_str(", ");
+        }
+        let _ = write!(out, "{value}");
+    }
+    out
+}
+
+const BINARY_OPS: [(BinaryOp, &str); 14] = [
+    (BinaryOp::Add, "add"),
+    (BinaryOp::Sub, "sub"),
+    (BinaryOp::Mul, "mul"),
+    (BinaryOp::Div, "div"),
+    (BinaryOp::Mod, "mod"),
+    (BinaryOp::Eq, "eq"),
+    (BinaryOp::Neq, "ne"),
+    (BinaryOp::Lt, "lt"),
+    (BinaryOp::Gt, "gt"),
+    (BinaryOp::Lte, "le"),
+    (BinaryOp::Gte, "ge"),
+    (BinaryOp::And, "and"),
+    (BinaryOp::Or, "or"),
+    (BinaryOp::Assign, "assign"),
+];
+
+const UNARY_OPS: [(UnaryOp, &str); 2] = [(UnaryOp::Negate, "not"), (UnaryOp::Minus, "neg")];
+
+fn binary_name(op: BinaryOp) -> &'static str {
+    BINARY_OPS.iter().find(|(candidate, _)| *candidate == op).map_or("?", |(_, name)| name)
+}
+
+fn unary_name(op: UnaryOp) -> &'static str {
+    UNARY_OPS.iter().find(|(candidate, _)| *candidate == op).map_or("?", |(_, name)| name)
+}
+
+/// Error reading the textual form of the IR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = std::result::Result<T, ParseError>;
+
+/// Parse a module from its textual form.
+///
+/// # Errors
+///
+/// Returns an error if the text is not a sequence of well-formed exports,
+/// externs and functions. The result is not verified; see
+/// [`verify_module`](super::verify_module).
+pub fn parse_module(text: &str) -> ParseResult<Module> {
+    let lines = tokenize(text)?;
+    let mut parser = Parser { lines, pos: 0 };
+    let mut module = Module::default();
+    while parser.pos < parser.lines.len() {
+        if parser.lines[parser.pos].peek() == Some(&Token::Ident("export".to_string())) {
+            module.exports.push(parser.export()?);
+        } else if parser.lines[parser.pos].peek() == Some(&Token::Ident("extern".to_string())) {
+            module.externs.push(parser.external()?);
+        } else {
+            module.functions.push(parser.function()?);
+        }
+    }
+    Ok(module)
+}
+
+/// Parse a single function from its textual form.
+///
+/// # Errors
+///
+/// Returns an error if the text is not exactly one well-formed function.
+pub fn parse_function(text: &str) -> ParseResult<Function> {
+    let mut module = parse_module(text)?;
+    match module.functions.len() {
+        1 => Ok(module.functions.remove(0)),
+        count => Err(ParseError { line: 1, message: format!("expected one function, found {count}") }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Value(u32),
+    Arrow,
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(text) | Self::Num(text) => write!(f, "`{text}`"),
+            Self::Str(text) => write!(f, "{text:?}"),
+            Self::Value(value) => write!(f, "`%{value}`"),
+            Self::Arrow => write!(f, "`->`"),
+            Self::Punct(c) => write!(f, "`{c}`"),
+        }
+    }
+}
+
+/// Split the text into the tokens of each non-empty line.
+fn tokenize(text: &str) -> ParseResult<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (index, source) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| ParseError { line: number, message };
+        let mut tokens = Vec::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '/' {
+                chars.next();
+                if chars.next() != Some('/') {
+                    return Err(error("unexpected `/`".to_string()));
+                }
+                break;
+            } else if c == '"' {
+                chars.next();
+                tokens.push(Token::Str(string_literal(&mut chars).map_err(error)?));
+            } else if c == '%' {
+                chars.next();
+                let digits = take_while(&mut chars, |c| c.is_ascii_digit());
+                let value = digits.parse().map_err(|_| error(format!("invalid value `%{digits}`")))?;
+                tokens.push(Token::Value(value));
+            } else if c == '-' || c.is_ascii_digit() {
+                chars.next();
+                if c == '-' && chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push(Token::Arrow);
+                    continue;
+                }
+                let mut text = c.to_string();
+                let mut previous = c;
+                while let Some(&next) = chars.peek() {
+                    let exponent_sign = matches!(next, '+' | '-') && matches!(previous, 'e' | 'E');
+                    if !(next.is_ascii_alphanumeric() || next == '.' || exponent_sign) {
+                        break;
+                    }
+                    text.push(next);
+                    previous = next;
+                    chars.next();
+                }
+                tokens.push(Token::Num(text));
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                tokens.push(Token::Ident(take_while(&mut chars, |c| c.is_ascii_alphanumeric() || c == '_')));
+            } else if "(){}[]:,=".contains(c) {
+                chars.next();
+                tokens.push(Token::Punct(c));
+            } else {
+                return Err(error(format!("unexpected `{c}`")));
+            }
+        }
+
+        if !tokens.is_empty() {
+            lines.push(Line { number, tokens, pos: 0 });
+        }
+    }
+    Ok(lines)
+}
+
+fn take_while(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, pred: impl Fn(char) -> bool) -> String {
+    let mut text = String::new();
+    while let Some(&c) = chars.peek().filter(|&&c| pred(c)) {
+        text.push(c);
+        chars.next();
+    }
+    text
+}
+
+/// Read the rest of a string literal, after its opening quote, undoing the
+/// escapes `{:?}` produces.
+fn string_literal(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<String, String> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_string()),
+            Some('"') => return Ok(text),
+            Some('\\') => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some(c @ ('\\' | '"' | '\'')) => c,
+                    Some('u') => {
+                        if chars.next() != Some('{') {
+                            return Err("expected `{` after `\\u`".to_string());
+                        }
+                        let digits = take_while(chars, |c| c.is_ascii_hexdigit());
+                        if chars.next() != Some('}') {
+                            return Err("expected `}` after unicode escape".to_string());
+                        }
+                        u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid unicode escape `\\u{{{digits}}}`"))?
+                    }
+                    Some(c) => return Err(format!("unknown escape `\\{c}`")),
+                    None => return Err("unterminated string".to_string()),
+                };
+                text.push(escaped);
+            }
+            Some(c) => text.push(c),
+        }
+    }
+}
+
+/// The tokens of one line and a cursor into them.
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Line {
+    fn error<T>(&self, message: impl Into<String>) -> ParseResult<T> {
+        Err(ParseError { line: self.number, message: message.into() })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &str) -> ParseResult<Token> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => self.error(format!("expected {expected}, found end of line")),
+        }
+    }
+
+    fn unexpected<T>(&self, token: &Token, expected: &str) -> ParseResult<T> {
+        self.error(format!("expected {expected}, found {token}"))
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn punct(&mut self, c: char) -> ParseResult<()> {
+        match self.next(&format!("`{c}`"))? {
+            Token::Punct(found) if found == c => Ok(()),
+            token => self.unexpected(&token, &format!("`{c}`")),
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<String> {
+        match self.next("a keyword")? {
+            Token::Ident(text) => Ok(text),
+            token => self.unexpected(&token, "a keyword"),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> ParseResult<()> {
+        match self.next(&format!("`{keyword}`"))? {
+            Token::Ident(text) if text == keyword => Ok(()),
+            token => self.unexpected(&token, &format!("`{keyword}`")),
+        }
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        match self.next("a string")? {
+            Token::Str(text) => Ok(text),
+            token => self.unexpected(&token, "a string"),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> ParseResult<T> {
+        match self.next("a number")? {
+            Token::Num(text) | Token::Ident(text) => match text.parse() {
+                Ok(number) => Ok(number),
+                Err(_) => self.error(format!("invalid number `{text}`")),
+            },
+            token => self.unexpected(&token, "a number"),
+        }
+    }
+
+    fn value(&mut self) -> ParseResult<ValueId> {
+        match self.next("a value")? {
+            Token::Value(value) => Ok(ValueId(value)),
+            token => self.unexpected(&token, "a value"),
+        }
+    }
+
+    fn block(&mut self) -> ParseResult<BlockId> {
+        match self.next("a block")? {
+            Token::Ident(text) => match text.strip_prefix("bb").and_then(|digits| digits.parse().ok()) {
+                Some(id) => Ok(BlockId(id)),
+                None => self.error(format!("expected a block, found `{text}`")),
+            },
+            token => self.unexpected(&token, "a block"),
+        }
+    }
+
+    fn ty(&mut self) -> ParseResult<IrType> {
+        let name = self.ident()?;
+        Ok(match name.as_str() {
+            "unit" => IrType::Unit,
+            "bool" => IrType::Bool,
+            "int" => IrType::Int,
+            "float" => IrType::Float,
+            "string" => IrType::String,
+            "object" if self.eat('(') => {
+                let class = self.string()?;
+                self.punct(')')?;
+                IrType::Object(Some(class))
+            }
+            "object" => IrType::Object(None),
+            _ => return self.error(format!("unknown type `{name}`")),
+        })
+    }
+
+    /// Parse `open`, a comma-separated list of items, and `close`.
+    fn list<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        self.punct(open)?;
+        let mut items = Vec::new();
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.punct(',')?;
+        }
+    }
+
+    fn values(&mut self) -> ParseResult<Vec<ValueId>> {
+        self.list('(', ')', Self::value)
+    }
+
+    /// Parse the `span(...)` ending an instruction, if there is one.
+    fn span(&mut self) -> ParseResult<Option<Span>> {
+        if self.peek() != Some(&Token::Ident("span".to_string())) {
+            return Ok(None);
+        }
+        self.pos += 1;
+        self.punct('(')?;
+        let start = self.number()?;
+        self.punct(',')?;
+        let end = self.number()?;
+        self.punct(',')?;
+        let start_line = self.number()?;
+        self.punct(':')?;
+        let start_col = self.number()?;
+        self.punct(',')?;
+        let end_line = self.number()?;
+        self.punct(':')?;
+        let end_col = self.number()?;
+        self.punct(')')?;
+        Ok(Some(Span::new(start, end, start_line, start_col, end_line, end_col)))
+    }
+
+    fn end(&self) -> ParseResult<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => self.unexpected(token, "end of line"),
+        }
+    }
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+/// A block whose terminator has not been read yet.
+struct OpenBlock {
+    id: BlockId,
+    phis: Vec<Phi>,
+    insts: Vec<Instruction>,
+}
+
+impl Parser {
+    fn export(&mut self) -> ParseResult<Export> {
+        let line = &mut self.lines[self.pos];
+        self.pos += 1;
+        line.keyword("export")?;
+        let function = line.string()?;
+        line.keyword("as")?;
+        let symbol = line.string()?;
+        line.end()?;
+        Ok(Export { function, symbol })
+    }
+
+    fn external(&mut self) -> ParseResult<Extern> {
+        let line = &mut self.lines[self.pos];
+        self.pos += 1;
+        line.keyword("extern")?;
+        let name = line.string()?;
+        let encoding = line.string()?;
+        let library = if line.peek().is_some() {
+            line.keyword("from")?;
+            Some(line.string()?)
+        } else {
+            None
+        };
+        line.end()?;
+        Ok(Extern { name, library, encoding })
+    }
+
+    fn function(&mut self) -> ParseResult<Function> {
+        let header = &mut self.lines[self.pos];
+        self.pos += 1;
+        header.keyword("fn")?;
+        let name = header.string()?;
+        let params = header.list('(', ')', |line| {
+            let value = line.value()?;
+            line.punct(':')?;
+            Ok((value, line.ty()?))
+        })?;
+        match header.next("`->`")? {
+            Token::Arrow => {}
+            token => return header.unexpected(&token, "`->`"),
+        }
+        let return_type = header.ty()?;
+        header.punct('{')?;
+        header.end()?;
+        let header_line = header.number;
+
+        let mut types: HashMap<u32, IrType> = HashMap::new();
+        let mut declare = |line: &Line, value: ValueId, ty: IrType| match types.insert(value.0, ty.clone()) {
+            Some(previous) if previous != ty => line.error(format!("{value} is declared as both {previous} and {ty}")),
+            _ => Ok(()),
+        };
+        for (value, ty) in &params {
+            declare(&self.lines[self.pos - 1], *value, ty.clone())?;
+        }
+
+        let mut blocks = Vec::new();
+        let mut cleanups = Vec::new();
+        let mut variables = Vec::new();
+        let mut open: Option<OpenBlock> = None;
+        loop {
+            let Some(line) = self.lines.get_mut(self.pos) else {
+                return Err(ParseError { line: header_line, message: format!("function {name:?} is not closed") });
+            };
+            self.pos += 1;
+
+            match line.peek().cloned() {
+                Some(Token::Punct('}')) => {
+                    line.pos += 1;
+                    line.end()?;
+                    if let Some(block) = open {
+                        return line.error(format!("{} has no terminator", block.id));
+                    }
+                    break;
+                }
+                Some(Token::Ident(keyword)) if keyword == "unused" => {
+                    line.pos += 1;
+                    let value = line.value()?;
+                    line.punct(':')?;
+                    let ty = line.ty()?;
+                    line.end()?;
+                    declare(line, value, ty)?;
+                }
+                Some(Token::Ident(keyword)) if keyword == "cleanup" => {
+                    line.pos += 1;
+                    let handler = line.block()?;
+                    line.keyword("for")?;
+                    let covered = line.list('[', ']', Line::block)?;
+                    line.end()?;
+                    cleanups.push(Cleanup { blocks: covered, handler });
+                }
+                Some(Token::Ident(keyword)) if keyword == "var" => {
+                    line.pos += 1;
+                    let value = line.value()?;
+                    let name = line.string()?;
+                    line.end()?;
+                    variables.push((value, name));
+                }
+                Some(Token::Ident(_)) if line.tokens.get(1) == Some(&Token::Punct(':')) => {
+                    let id = line.block()?;
+                    line.punct(':')?;
+                    line.end()?;
+                    if let Some(block) = open {
+                        return line.error(format!("{} has no terminator", block.id));
+                    }
+                    open = Some(OpenBlock { id, phis: Vec::new(), insts: Vec::new() });
+                }
+                Some(Token::Value(_)) => {
+                    let Some(block) = open.as_mut() else {
+                        return line.error("instruction outside of a block");
+                    };
+                    let result = line.value()?;
+                    line.punct(':')?;
+                    let ty = line.ty()?;
+                    line.punct('=')?;
+                    if line.peek() == Some(&Token::Ident("phi".to_string())) {
+                        line.pos += 1;
+                        if !block.insts.is_empty() {
+                            return line.error("phi after an instruction");
+                        }
+                        let incoming = line.list('[', ']', |line| {
+                            let pred = line.block()?;
+                            line.punct(':')?;
+                            Ok((pred, line.value()?))
+                        })?;
+                        block.phis.push(Phi { result, incoming });
+                    } else {
+                        let inst = line.inst()?;
+                        let span = line.span()?;
+                        block.insts.push(Instruction { result, inst, span });
+                    }
+                    line.end()?;
+                    declare(line, result, ty)?;
+                }
+                Some(_) => {
+                    let Some(block) = open.take() else {
+                        return line.error("terminator outside of a block");
+                    };
+                    let terminator = line.terminator()?;
+                    line.end()?;
+                    blocks.push(Block { id: block.id, phis: block.phis, insts: block.insts, terminator });
+                }
+                None => unreachable!("empty lines are skipped"),
+            }
+        }
+
+        let count = types.keys().max().map_or(0, |&max| max as usize + 1);
+        let mut values = Vec::with_capacity(count);
+        for index in 0..count {
+            let index = u32::try_from(index).expect("too many values");
+            match types.remove(&index) {
+                Some(ty) => values.push(ty),
+                None => {
+                    let message = format!("%{index} of function {name:?} has no type");
+                    return Err(ParseError { line: header_line, message });
+                }
+            }
+        }
+
+        Ok(Function {
+            name,
+            params: params.into_iter().map(|(value, _)| value).collect(),
+            return_type,
+            blocks,
+            values,
+            cleanups,
+            variables,
+        })
+    }
+}
+
+impl Line {
+    fn inst(&mut self) -> ParseResult<Inst> {
+        let op = self.ident()?;
+        Ok(match op.as_str() {
+            "const" => Inst::Const(match self.ident()?.as_str() {
+                "unit" => Constant::Unit,
+                "nil" => Constant::Nil,
+                "bool" => Constant::Bool(self.number()?),
+                "int" => Constant::Int(self.number()?),
+                "float" => Constant::Float(self.number()?),
+                "string" => Constant::String(self.string()?),
+                kind => return self.error(format!("unknown constant kind `{kind}`")),
+            }),
+            "global" => Inst::Global(self.string()?),
+            "binary" => {
+                let name = self.ident()?;
+                let Some(&(op, _)) = BINARY_OPS.iter().find(|(_, candidate)| *candidate == name) else {
+                    return self.error(format!("unknown binary operator `{name}`"));
+                };
+                let lhs = self.value()?;
+                self.punct(',')?;
+                Inst::Binary { op, lhs, rhs: self.value()? }
+            }
+            "unary" => {
+                let name = self.ident()?;
+                let Some(&(op, _)) = UNARY_OPS.iter().find(|(_, candidate)| *candidate == name) else {
+                    return self.error(format!("unknown unary operator `{name}`"));
+                };
+                Inst::Unary { op, operand: self.value()? }
+            }
+            "call" => {
+                let callee = self.string()?;
+                Inst::Call { callee, args: self.values()? }
+            }
+            "call_indirect" => {
+                let callee = self.value()?;
+                Inst::CallIndirect { callee, args: self.values()? }
+            }
+            "send" => {
+                let receiver = self.value()?;
+                self.punct(',')?;
+                let selector = self.string()?;
+                Inst::Send { receiver, selector, args: self.values()? }
+            }
+            "alloc" => Inst::Alloc { class: self.string()? },
+            "get_field" => {
+                let object = self.value()?;
+                self.punct(',')?;
+                Inst::GetField { object, field: self.string()? }
+            }
+            "set_field" => {
+                let object = self.value()?;
+                self.punct(',')?;
+                let field = self.string()?;
+                self.punct(',')?;
+                Inst::SetField { object, field, value: self.value()? }
+            }
+            "variant" => {
+                let enum_name = self.string()?;
+                self.punct(',')?;
+                let tag = self.number()?;
+                self.punct(',')?;
+                let variant = self.string()?;
+                let payload = if self.eat('(') {
+                    let payload = self.value()?;
+                    self.punct(')')?;
+                    Some(payload)
+                } else {
+                    None
+                };
+                Inst::Variant { enum_name, variant, tag, payload }
+            }
+            "tuple" => Inst::Tuple(self.values()?),
+            "array" => Inst::Array(self.values()?),
+            "concat" => Inst::Concat(self.values()?),
+            "dict" => Inst::Dict(self.list('(', ')', |line| {
+                let key = line.value()?;
+                line.punct(':')?;
+                Ok((key, line.value()?))
+            })?),
+            "get_index" => {
+                let collection = self.value()?;
+                self.punct(',')?;
+                Inst::GetIndex { collection, index: self.value()? }
+            }
+            "set_index" => {
+                let collection = self.value()?;
+                self.punct(',')?;
+                let index = self.value()?;
+                self.punct(',')?;
+                Inst::SetIndex { collection, index, value: self.value()? }
+            }
+            "tag" => Inst::Tag(self.value()?),
+            "payload" => Inst::Payload(self.value()?),
+            "length" => Inst::Length(self.value()?),
+            "slice" => {
+                let collection = self.value()?;
+                self.punct(',')?;
+                Inst::Slice { collection, start: self.number()? }
+            }
+            "intrinsic" => {
+                let name = self.ident()?;
+                let Some(intrinsic) = Intrinsic::from_name(&name) else {
+                    return self.error(format!("unknown intrinsic `{name}`"));
+                };
+                Inst::Intrinsic { intrinsic, args: self.values()? }
+            }
+            "closure" => {
+                let function = self.string()?;
+                Inst::Closure { function, captures: self.values()? }
+            }
+            "box" => Inst::Box(self.value()?),
+            "load" => Inst::Load(self.value()?),
+            "store" => {
+                let cell = self.value()?;
+                self.punct(',')?;
+                Inst::Store { cell, value: self.value()? }
+            }
+            _ => return self.error(format!("unknown instruction `{op}`")),
+        })
+    }
+
+    fn terminator(&mut self) -> ParseResult<Terminator> {
+        let op = self.ident()?;
+        Ok(match op.as_str() {
+            "return" if self.peek().is_none() => Terminator::Return(None),
+            "return" => Terminator::Return(Some(self.value()?)),
+            "jump" => Terminator::Jump(self.block()?),
+            "branch" => {
+                let cond = self.value()?;
+                self.punct(',')?;
+                let then_block = self.block()?;
+                self.punct(',')?;
+                Terminator::Branch { cond, then_block, else_block: self.block()? }
+            }
+            "switch" => {
+                let value = self.value()?;
+                let cases = self.list('[', ']', |line| {
+                    let key = line.number()?;
+                    line.punct(':')?;
+                    Ok((key, line.block()?))
+                })?;
+                self.punct(',')?;
+                self.keyword("default")?;
+                Terminator::Switch { value, cases, default: self.block()? }
+            }
+            "unreachable" => Terminator::Unreachable,
+            "resume" => Terminator::Resume,
+            _ => return self.error(format!("unknown terminator `{op}`")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::verify_module;
+    use crate::optimize::{InlineConfig, inline};
+
+    #[test]
+    fn test_every_form_round_trips() {
+        let text = r#"export "nothing" as "ox_nothing"
+extern "strlen" "Q*"
+extern "cos" "dd" from "libm.so.6"
+
+fn "Shape.area"(%0: object("Shape"), %1: int) -> float {
+    unused %25: string
+bb0:
+    %2: unit = const unit
+    %3: object = const nil
+    %4: bool = const bool true
+    %5: int = const int -7
+    %6: float = const float 1.5e-7
+    %7: string = const string "say \"hi\"\n\u{7f}"
+    %8: object = global "main"
+    %9: int = binary mod %1, %5
+    %10: bool = unary not %4
+    %11: float = call "Shape.width"(%0)
+    %12: object = call_indirect %8(%1, %5)
+    %13: float = send %0, "scale:"(%11)
+    %14: object("Shape") = alloc "Shape"
+    %15: int = get_field %0, "sides"
+    %16: unit = set_field %14, "sides", %15
+    %17: object = variant "Option", 0, "None"
+    %18: object = variant "Option", 1, "Some"(%1)
+    %19: object = tuple()
+    %20: object = array(%1, %5)
+    %21: object = dict(%7: %1)
+    %22: int = get_index %20, %1
+    %23: unit = set_index %21, %7, %5
+    %24: string = concat(%7, %1)
+    switch %9 [0: bb1, -1: bb2], default bb3
+bb1:
+    %26: int = tag %18
+    %27: int = payload %18
+    branch %4, bb2, bb3
+bb2:
+    %28: int = phi [bb0: %1, bb1: %27]
+    %29: int = length %20
+    %30: object = slice %20, 1
+    %32: int = intrinsic max(%28, %5)
+    %33: object = box %29
+    %34: object = closure "Shape.area$closure0"(%1, %33)
+    %35: int = load %33
+    %36: unit = store %33, %35
+    jump bb3
+bb3:
+    %31: float = phi [bb0: %6, bb1: %6, bb2: %13]
+    return %31
+}
+
+fn "deferred"(%0: object) -> unit {
+    cleanup bb2 for [bb1]
+bb0:
+    jump bb1
+bb1:
+    %1: object = call_indirect %0() span(31, 36, 2:5, 2:10)
+    return
+bb2:
+    %2: object = call_indirect %0()
+    resume
+}
+
+fn "nothing"() -> unit {
+bb0:
+    return
+}
+"#;
+        let module = parse_module(text).unwrap();
+        assert_eq!(module.to_string(), text);
+        verify_module(&module).unwrap();
+        assert_eq!(parse_module(&module.to_string()).unwrap(), module);
+
+        assert_eq!(module.exports, [Export { function: "nothing".into(), symbol: "ox_nothing".into() }]);
+        let cos = Extern { name: "cos".into(), library: Some("libm.so.6".into()), encoding: "dd".into() };
+        assert_eq!(module.externs[1], cos);
+        let area = module.function("Shape.area").unwrap();
+        assert_eq!(area.values.len(), 37);
+        assert_eq!(area.value_type(ValueId(25)), &IrType::String);
+        assert_eq!(area.blocks[0].insts[5].inst, Inst::Const(Constant::String("say \"hi\"\n\u{7f}".into())));
+        let deferred = module.function("deferred").unwrap();
+        assert_eq!(deferred.cleanups, [Cleanup { blocks: vec![BlockId(1)], handler: BlockId(2) }]);
+        assert_eq!(deferred.blocks[1].insts[0].span, Some(Span::new(31, 36, 2, 5, 2, 10)));
+
+        let err = parse_function("fn \"f\"() -> int {\nbb0:\n    %0: int = const int 1\n}\n").unwrap_err();
+        assert_eq!(err, ParseError { line: 4, message: "bb0 has no terminator".into() });
+    }
+
+    #[test]
+    fn test_inline_on_textual_input() {
+        let mut module = parse_module(
+            r#"
+fn "double"(%0: int) -> int {
+bb0:
+    %1: int = const int 2
+    %2: int = binary mul %0, %1
+    return %2
+}
+
+fn "main"(%0: int) -> int {
+bb0:
+    %1: int = call "double"(%0)
+    %2: int = call "double"(%1)  // both calls are inlined
+    return %2
+}
+"#,
+        )
+        .unwrap();
+        inline(&mut module, &InlineConfig::default());
+
+        let expected = r#"fn "main"(%0: int) -> int {
+    unused %1: int
+    unused %2: int
+bb0:
+    %3: int = const int 2
+    %4: int = binary mul %0, %3
+    %5: int = const int 2
+    %6: int = binary mul %4, %5
+    return %6
+}
+"#;
+        assert_eq!(module.function("main").unwrap().to_string(), expected);
+    }
+}
@@ -0,0 +1,625 @@
+//! IR verifier.
+//!
+//! Checks the invariants the rest of the pipeline relies on:
+//!
+//! - **Structure**: block identifiers match their position, and every
+//!   terminator targets existing blocks, each at most once. Cleanups cover
+//!   existing blocks, and exactly their handlers end in `resume`.
+//! - **SSA**: every value is defined once, and each definition dominates
+//!   its uses. A phi operand must be available at the end of the
+//!   predecessor it flows from, and a phi lists exactly the predecessors
+//!   of its block.
+//! - **Types**: constants, comparisons, conditions, tags, phis and returns
+//!   agree with the types of their values, and closures and boxes are
+//!   objects.
+//!
+//! An error raised in a block a cleanup covers enters the cleanup's
+//! handler, which counts as an edge for dominance. Blocks unreachable from
+//! the entry are checked for structure only.
+//! Untyped object words ([`IrType::Object`] without a class) are compatible
+//! with every type, since the builder uses them where the checked type is
+//! not tracked.
+
+use super::{BlockId, Constant, Function, Inst, IrType, Module, Terminator, ValueId};
+use oxidex_syntax::ast::expr::BinaryOp;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A violated IR invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// A value is used but never defined, or has no type.
+    UnknownValue {
+        /// Function containing the use
+        function: String,
+        /// The value
+        value: ValueId,
+    },
+
+    /// A value is defined more than once.
+    Redefinition {
+        /// Function containing the definitions
+        function: String,
+        /// The value
+        value: ValueId,
+    },
+
+    /// A value is used where its definition does not dominate.
+    NotDominated {
+        /// Function containing the use
+        function: String,
+        /// The value
+        value: ValueId,
+        /// Block containing the use
+        block: BlockId,
+    },
+
+    /// A terminator targets a block that does not exist, or a block's
+    /// identifier does not match its position.
+    UnknownBlock {
+        /// Function containing the reference
+        function: String,
+        /// The block
+        block: BlockId,
+    },
+
+    /// A phi's incoming blocks differ from its block's predecessors.
+    PhiPredecessors {
+        /// Function containing the phi
+        function: String,
+        /// The phi
+        value: ValueId,
+    },
+
+    /// A value has a different type than its use requires.
+    TypeMismatch {
+        /// Function containing the use
+        function: String,
+        /// The value
+        value: ValueId,
+        /// Type the use requires
+        expected: IrType,
+        /// Type of the value
+        found: IrType,
+    },
+
+    /// A block or terminator is malformed.
+    Malformed {
+        /// Function containing the block
+        function: String,
+        /// The block
+        block: BlockId,
+        /// What is wrong
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownValue { function, value } => write!(f, "{function}: {value} is not defined"),
+            Self::Redefinition { function, value } => write!(f, "{function}: {value} is defined more than once"),
+            Self::NotDominated { function, value, block } => {
+                write!(f, "{function}: {value} does not dominate its use in {block}")
+            }
+            Self::UnknownBlock { function, block } => write!(f, "{function}: {block} does not exist"),
+            Self::PhiPredecessors { function, value } => {
+                write!(f, "{function}: phi {value} does not list the predecessors of its block")
+            }
+            Self::TypeMismatch { function, value, expected, found } => {
+                write!(f, "{function}: {value} has type {found}, expected {expected}")
+            }
+            Self::Malformed { function, block, reason } => write!(f, "{function}: {block}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verify every function of a module.
+///
+/// # Errors
+///
+/// Returns every violation found.
+pub fn verify_module(module: &Module) -> Result<(), Vec<VerifyError>> {
+    let errors: Vec<VerifyError> = module
+        .functions
+        .iter()
+        .filter_map(|function| verify_function(function).err())
+        .flatten()
+        .collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Verify a function.
+///
+/// # Errors
+///
+/// Returns every violation found.
+pub fn verify_function(function: &Function) -> Result<(), Vec<VerifyError>> {
+    let mut verifier = Verifier { function, errors: Vec::new() };
+    verifier.verify();
+    if verifier.errors.is_empty() { Ok(()) } else { Err(verifier.errors) }
+}
+
+/// Where a value is defined: its block and position. Parameters come
+/// first, then phis, then instructions in order.
+#[derive(Debug, Clone, Copy)]
+struct Def {
+    block: BlockId,
+    position: usize,
+}
+
+/// Position of a block's terminator, after every instruction.
+const TERMINATOR: usize = usize::MAX;
+
+struct Verifier<'f> {
+    function: &'f Function,
+    errors: Vec<VerifyError>,
+}
+
+impl Verifier<'_> {
+    fn verify(&mut self) {
+        if self.function.blocks.is_empty() {
+            self.malformed(BlockId(0), "the function has no entry block");
+            return;
+        }
+        if !self.verify_structure() {
+            return;
+        }
+        let Some(defs) = self.definitions() else {
+            return;
+        };
+        let idom = dominators(self.function);
+        self.verify_uses(&defs, &idom);
+        self.verify_types();
+    }
+
+    fn name(&self) -> String {
+        self.function.name.clone()
+    }
+
+    fn malformed(&mut self, block: BlockId, reason: &'static str) {
+        self.errors.push(VerifyError::Malformed { function: self.name(), block, reason });
+    }
+
+    /// Check block identifiers and terminator targets. Later checks index
+    /// blocks by identifier, so they only run if this succeeds.
+    fn verify_structure(&mut self) -> bool {
+        let count = self.function.blocks.len();
+        let before = self.errors.len();
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            if block.id.0 as usize != index {
+                self.errors.push(VerifyError::UnknownBlock { function: self.name(), block: block.id });
+            }
+
+            let successors = block.terminator.successors();
+            for &succ in &successors {
+                if succ.0 as usize >= count {
+                    self.errors.push(VerifyError::UnknownBlock { function: self.name(), block: succ });
+                }
+            }
+            let mut distinct = successors.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            if distinct.len() != successors.len() {
+                self.malformed(block.id, "the terminator targets a block more than once");
+            }
+            if let Terminator::Switch { cases, .. } = &block.terminator {
+                let mut keys: Vec<i64> = cases.iter().map(|&(key, _)| key).collect();
+                keys.sort_unstable();
+                keys.dedup();
+                if keys.len() != cases.len() {
+                    self.malformed(block.id, "the switch has duplicate cases");
+                }
+            }
+        }
+        if !self.function.blocks[0].phis.is_empty() {
+            self.malformed(BlockId(0), "the entry block has phis");
+        }
+
+        for cleanup in &self.function.cleanups {
+            for &block in cleanup.blocks.iter().chain([&cleanup.handler]) {
+                if block.0 as usize >= count {
+                    self.errors.push(VerifyError::UnknownBlock { function: self.name(), block });
+                }
+            }
+        }
+        for block in &self.function.blocks {
+            let is_handler = self.function.cleanups.iter().any(|cleanup| cleanup.handler == block.id);
+            let resumes = block.terminator == Terminator::Resume;
+            if is_handler && !resumes {
+                self.malformed(block.id, "the handler of a cleanup does not resume unwinding");
+            } else if resumes && !is_handler {
+                self.malformed(block.id, "only the handler of a cleanup resumes unwinding");
+            }
+        }
+        self.errors.len() == before
+    }
+
+    /// Collect the definition of every value.
+    fn definitions(&mut self) -> Option<HashMap<ValueId, Def>> {
+        let mut defs = HashMap::new();
+        let mut ok = true;
+        let mut define = |verifier: &mut Self, value: ValueId, def: Def| {
+            if value.0 as usize >= verifier.function.values.len() {
+                verifier.errors.push(VerifyError::UnknownValue { function: verifier.name(), value });
+                ok = false;
+            } else if defs.insert(value, def).is_some() {
+                verifier.errors.push(VerifyError::Redefinition { function: verifier.name(), value });
+                ok = false;
+            }
+        };
+
+        for &param in &self.function.params {
+            define(self, param, Def { block: BlockId(0), position: 0 });
+        }
+        for block in &self.function.blocks {
+            for phi in &block.phis {
+                define(self, phi.result, Def { block: block.id, position: 0 });
+            }
+            for (index, inst) in block.insts.iter().enumerate() {
+                define(self, inst.result, Def { block: block.id, position: index + 1 });
+            }
+        }
+        ok.then_some(defs)
+    }
+
+    fn verify_uses(&mut self, defs: &HashMap<ValueId, Def>, idom: &[Option<BlockId>]) {
+        let preds = self.function.predecessors();
+        let reachable = |block: BlockId| idom[block.0 as usize].is_some();
+
+        for block in &self.function.blocks {
+            if !reachable(block.id) {
+                continue;
+            }
+
+            for phi in &block.phis {
+                let mut incoming: Vec<BlockId> = phi.incoming.iter().map(|&(pred, _)| pred).collect();
+                let mut expected = preds[block.id.0 as usize].clone();
+                incoming.sort_unstable();
+                expected.sort_unstable();
+                if incoming != expected {
+                    self.errors.push(VerifyError::PhiPredecessors { function: self.name(), value: phi.result });
+                }
+                for &(pred, value) in &phi.incoming {
+                    // The operand must be available where the edge leaves
+                    if (pred.0 as usize) < idom.len() && reachable(pred) {
+                        self.check_use(defs, idom, value, pred, TERMINATOR);
+                    }
+                }
+            }
+
+            for (index, inst) in block.insts.iter().enumerate() {
+                for value in inst.inst.operands() {
+                    self.check_use(defs, idom, value, block.id, index + 1);
+                }
+            }
+            for value in block.terminator.operands() {
+                self.check_use(defs, idom, value, block.id, TERMINATOR);
+            }
+        }
+    }
+
+    fn check_use(
+        &mut self,
+        defs: &HashMap<ValueId, Def>,
+        idom: &[Option<BlockId>],
+        value: ValueId,
+        block: BlockId,
+        position: usize,
+    ) {
+        let Some(def) = defs.get(&value) else {
+            self.errors.push(VerifyError::UnknownValue { function: self.name(), value });
+            return;
+        };
+        let available = if def.block == block {
+            def.position < position
+        } else {
+            dominates(idom, def.block, block)
+        };
+        if !available {
+            self.errors.push(VerifyError::NotDominated { function: self.name(), value, block });
+        }
+    }
+
+    fn verify_types(&mut self) {
+        let function = self.function;
+        for block in &function.blocks {
+            for phi in &block.phis {
+                for &(_, value) in &phi.incoming {
+                    self.expect(value, function.value_type(phi.result));
+                }
+            }
+
+            for inst in &block.insts {
+                let result = function.value_type(inst.result);
+                match &inst.inst {
+                    // Unit constants stand in for values only dead code observes
+                    Inst::Const(Constant::Unit) => {}
+                    Inst::Const(constant) => {
+                        let ty = match constant {
+                            Constant::Unit => IrType::Unit,
+                            Constant::Nil => IrType::Object(None),
+                            Constant::Bool(_) => IrType::Bool,
+                            Constant::Int(_) => IrType::Int,
+                            Constant::Float(_) => IrType::Float,
+                            Constant::String(_) => IrType::String,
+                        };
+                        self.expect(inst.result, &ty);
+                    }
+                    Inst::Binary { op: BinaryOp::And | BinaryOp::Or | BinaryOp::Assign, .. } => {
+                        self.malformed(block.id, "logical operators and assignments are not binary instructions");
+                    }
+                    Inst::Binary {
+                        op: BinaryOp::Eq | BinaryOp::Neq | BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte,
+                        ..
+                    } => self.expect(inst.result, &IrType::Bool),
+                    Inst::Tag(_) | Inst::Length(_) => self.expect(inst.result, &IrType::Int),
+                    Inst::Alloc { class } => self.expect(inst.result, &IrType::Object(Some(class.clone()))),
+                    Inst::Concat(_) => self.expect(inst.result, &IrType::String),
+                    Inst::Intrinsic { intrinsic, args } => {
+                        let operands: Option<Vec<&IrType>> =
+                            args.iter().map(|arg| function.values.get(arg.0 as usize)).collect();
+                        match operands.map(|operands| intrinsic.result_type(&operands)) {
+                            Some(Some(ty)) => self.expect(inst.result, &ty),
+                            Some(None) => {
+                                self.malformed(block.id, "an intrinsic's operands are not of the types it takes");
+                            }
+                            // Undefined operands are reported on their own
+                            None => {}
+                        }
+                    }
+                    Inst::SetField { .. } | Inst::SetIndex { .. } | Inst::Store { .. } if *result != IrType::Unit => {
+                        self.expect(inst.result, &IrType::Unit);
+                    }
+                    Inst::Closure { .. } | Inst::Box(_) if !result.is_object() => {
+                        self.malformed(block.id, "closures and boxes are object words");
+                    }
+                    _ => {}
+                }
+            }
+
+            match &block.terminator {
+                Terminator::Branch { cond, .. } => self.expect(*cond, &IrType::Bool),
+                Terminator::Switch { value, .. } => self.expect(*value, &IrType::Int),
+                Terminator::Return(Some(value)) => self.expect(*value, &function.return_type),
+                Terminator::Return(None) if function.return_type != IrType::Unit => {
+                    self.malformed(block.id, "the function returns no value but its return type is not unit");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn expect(&mut self, value: ValueId, expected: &IrType) {
+        let Some(found) = self.function.values.get(value.0 as usize) else {
+            return;
+        };
+        if !compatible(expected, found) {
+            self.errors.push(VerifyError::TypeMismatch {
+                function: self.name(),
+                value,
+                expected: expected.clone(),
+                found: found.clone(),
+            });
+        }
+    }
+}
+
+/// Whether a value of type `found` may be used where `expected` is
+/// required. Classes are not checked, since the IR does not record
+/// inheritance.
+fn compatible(expected: &IrType, found: &IrType) -> bool {
+    expected == found
+        || matches!(expected, IrType::Object(None))
+        || matches!(found, IrType::Object(None))
+        || (expected.is_object() && found.is_object())
+}
+
+/// Compute the immediate dominator of every block reachable from the
+/// entry, using the iterative algorithm of Cooper, Harvey and Kennedy. The
+/// entry is its own dominator; unreachable blocks have none. A block flows
+/// into the handler errors raised in it enter, as well as its successors.
+#[must_use]
+pub fn dominators(function: &Function) -> Vec<Option<BlockId>> {
+    let count = function.blocks.len();
+    let mut preds = function.predecessors();
+    for block in &function.blocks {
+        if let Some(handler) = function.handler(block.id).filter(|handler| (handler.0 as usize) < count) {
+            preds[handler.0 as usize].push(block.id);
+        }
+    }
+
+    // Reverse postorder from the entry
+    let mut order = Vec::with_capacity(count);
+    let mut visited = vec![false; count];
+    let mut stack = vec![(BlockId(0), 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        let mut successors = function.block(block).terminator.successors();
+        successors.extend(function.handler(block));
+        if let Some(&succ) = successors.get(next) {
+            stack.push((block, next + 1));
+            if (succ.0 as usize) < count && !std::mem::replace(&mut visited[succ.0 as usize], true) {
+                stack.push((succ, 0));
+            }
+        } else {
+            order.push(block);
+        }
+    }
+    order.reverse();
+    let mut rank = vec![usize::MAX; count];
+    for (index, block) in order.iter().enumerate() {
+        rank[block.0 as usize] = index;
+    }
+
+    let mut idom: Vec<Option<BlockId>> = vec![None; count];
+    idom[0] = Some(BlockId(0));
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().skip(1) {
+            let mut new_idom = None;
+            for &pred in &preds[block.0 as usize] {
+                if idom[pred.0 as usize].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(current) => intersect(&idom, &rank, pred, current),
+                });
+            }
+            if new_idom.is_some() && idom[block.0 as usize] != new_idom {
+                idom[block.0 as usize] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn intersect(idom: &[Option<BlockId>], rank: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+    while a != b {
+        while rank[a.0 as usize] > rank[b.0 as usize] {
+            a = idom[a.0 as usize].expect("processed blocks have a dominator");
+        }
+        while rank[b.0 as usize] > rank[a.0 as usize] {
+            b = idom[b.0 as usize].expect("processed blocks have a dominator");
+        }
+    }
+    a
+}
+
+/// Whether `a` dominates `b`, given immediate dominators.
+fn dominates(idom: &[Option<BlockId>], a: BlockId, mut b: BlockId) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match idom[b.0 as usize] {
+            Some(parent) if parent != b => b = parent,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parse_function;
+
+    fn errors(text: &str) -> Vec<VerifyError> {
+        verify_function(&parse_function(text).unwrap()).unwrap_err()
+    }
+
+    #[test]
+    fn test_invalid_ir_is_rejected() {
+        let valid = r#"fn "f"(%0: bool) -> int {
+bb0:
+    %1: int = const int 1
+    branch %0, bb1, bb2
+bb1:
+    jump bb2
+bb2:
+    %2: int = phi [bb0: %1, bb1: %1]
+    return %2
+}"#;
+        verify_function(&parse_function(valid).unwrap()).unwrap();
+
+        // A value defined in one arm is used after the merge
+        let undominated = r#"fn "f"(%0: bool) -> int {
+bb0:
+    branch %0, bb1, bb2
+bb1:
+    %1: int = const int 1
+    jump bb2
+bb2:
+    return %1
+}"#;
+        let name = || "f".to_string();
+        assert_eq!(
+            errors(undominated),
+            [VerifyError::NotDominated { function: name(), value: ValueId(1), block: BlockId(2) }]
+        );
+
+        // The phi misses an edge and the branch condition is not a boolean
+        let malformed = r#"fn "f"(%0: int) -> int {
+bb0:
+    branch %0, bb1, bb2
+bb1:
+    jump bb2
+bb2:
+    %1: int = phi [bb1: %0]
+    %2: int = binary lt %1, %0
+    return %1
+}"#;
+        assert_eq!(
+            errors(malformed),
+            [
+                VerifyError::PhiPredecessors { function: name(), value: ValueId(1) },
+                VerifyError::TypeMismatch {
+                    function: name(),
+                    value: ValueId(0),
+                    expected: IrType::Bool,
+                    found: IrType::Int,
+                },
+                VerifyError::TypeMismatch {
+                    function: name(),
+                    value: ValueId(2),
+                    expected: IrType::Bool,
+                    found: IrType::Int,
+                },
+            ]
+        );
+
+        // Targets must exist, and a use must follow its definition
+        let unordered = r#"fn "f"() -> int {
+bb0:
+    %0: int = binary add %1, %1
+    %1: int = const int 2
+    return %0
+}"#;
+        assert_eq!(
+            errors(unordered),
+            [
+                VerifyError::NotDominated { function: name(), value: ValueId(1), block: BlockId(0) },
+                VerifyError::NotDominated { function: name(), value: ValueId(1), block: BlockId(0) },
+            ]
+        );
+        assert_eq!(
+            errors("fn \"f\"() -> unit {\nbb0:\n    jump bb4\n}"),
+            [VerifyError::UnknownBlock { function: name(), block: BlockId(4) }]
+        );
+
+        // Intrinsics take operands of particular types
+        let intrinsic = r#"fn "f"(%0: int) -> float {
+bb0:
+    %1: float = intrinsic sqrt(%0)
+    return %1
+}"#;
+        let reason = "an intrinsic's operands are not of the types it takes";
+        assert_eq!(errors(intrinsic), [VerifyError::Malformed { function: name(), block: BlockId(0), reason }]);
+
+        // Boxes are objects, and storing to one defines no value
+        let boxed = r#"fn "f"(%0: int) -> unit {
+bb0:
+    %1: int = box %0
+    %2: int = store %1, %0
+    return
+}"#;
+        let reason = "closures and boxes are object words";
+        assert_eq!(
+            errors(boxed),
+            [
+                VerifyError::Malformed { function: name(), block: BlockId(0), reason },
+                VerifyError::TypeMismatch {
+                    function: name(),
+                    value: ValueId(2),
+                    expected: IrType::Unit,
+                    found: IrType::Int,
+                },
+            ]
+        );
+    }
+}
@@ -6,12 +6,37 @@
 //! - Optimization passes
 //! - Selector and metadata emission
 //!
-//! **Phase:** 6 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 6 - Code Generation
+//! **Status:** In Progress
 
 #![warn(missing_docs)]
+#![allow(clippy::result_large_err)]
 
-// Module declarations will be added during Phase 6 implementation:
-// pub mod lowering;
-// pub mod optimize;
-// pub mod emit;
+// Code generation errors
+pub mod error;
+
+// Lowering of declarations to runtime registrations
+pub mod lowering;
+
+// SSA intermediate representation
+pub mod ir;
+
+// Decision trees for pattern matching
+pub mod decision;
+
+// Std operations the backends compute inline
+pub mod intrinsics;
+
+// IR optimization passes
+pub mod optimize;
+
+// Selector tables, metadata descriptors and string pools
+pub mod emit;
+
+// Conversion of closures to lifted functions and captured values
+pub mod closure;
+
+// Re-exports for convenience
+pub use emit::{MetadataTables, emit_tables};
+pub use error::{CodegenError, Result};
+pub use lowering::{LoweredModule, lower};
@@ -0,0 +1,803 @@
+//! Lowering of type declarations to runtime registrations.
+//!
+//! Every nominal type in a checked program becomes a runtime class: classes
+//! keep their superclass, while structs and enums become root classes so
+//! their boxed values can receive messages like any other object. Methods
+//! are attached to the class of the type that declares them, whether in the
+//! type's body or in an `impl` block.
+//!
+//! # Selectors
+//!
+//! Method selectors follow the Objective-C convention. The first parameter's
+//! label is folded into the method name and every parameter contributes a
+//! colon, so the number of colons is the method's arity:
+//!
+//! | Declaration                       | Selector      |
+//! |-----------------------------------|---------------|
+//! | `fn reset()`                      | `reset`       |
+//! | `fn move(by offset: Float)`       | `moveBy:`     |
+//! | `fn add(x: Int, y: Int)`          | `addX:y:`     |
+//! | `init(count: Int)`                | `initCount:`  |
+//!
+//! # Method implementations
+//!
+//! Runtime methods are C function pointers and cannot capture the method
+//! body they stand for. Every lowered method is therefore registered with
+//! the same IMP thunk, which forwards the receiver, selector and arguments
+//! to the [`MethodHandler`] installed by the executing backend. The backend
+//! finds the body to run with [`LoweredModule::method`].
+//!
+//! Static methods have no receiver and are not added to the runtime method
+//! tables; backends call them directly.
+
+use crate::error::{CodegenError, Result};
+use oxidec::runtime::encoding::types;
+use oxidec::runtime::selector::SelectorHandle;
+use oxidec::runtime::{ClassBuilder, ObjectPtr, class_from_name};
+use oxidec::{Class, Method, Protocol, RuntimeString, Selector, get_global_arena};
+use oxidex_syntax::ast::decl::{Decl, FnDecl, StructField};
+use oxidex_syntax::Span;
+use oxidex_typecheck::check::ast_to_ty;
+use oxidex_typecheck::infer::Context;
+use oxidex_typecheck::{PrimTy, Ty};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Kind of type a lowered class was produced from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeKind {
+    /// A `class` declaration
+    Class,
+    /// A `struct` declaration
+    Struct,
+    /// An `enum` declaration
+    Enum,
+}
+
+/// An instance variable of a lowered class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ivar {
+    /// Field name
+    pub name: String,
+    /// Field type
+    pub ty: Ty,
+}
+
+/// A method ready to be registered with the runtime.
+#[derive(Debug, Clone)]
+pub struct LoweredMethod<'a> {
+    /// Method name as declared (`init` for initializers)
+    pub name: String,
+    /// Parameter labels, as written at call sites
+    pub labels: Vec<String>,
+    /// Method selector (see the module documentation)
+    pub selector: String,
+    /// Parameter types, in declaration order
+    pub params: Vec<Ty>,
+    /// Return type
+    pub return_type: Ty,
+    /// Runtime type encoding of the signature
+    pub encoding: String,
+    /// The declaration the method was lowered from
+    pub decl: &'a FnDecl<'a>,
+}
+
+impl LoweredMethod<'_> {
+    /// Whether the method is static (has no receiver).
+    #[must_use]
+    pub fn is_static(&self) -> bool {
+        self.decl.is_static
+    }
+
+    /// Number of arguments the method takes, excluding the receiver.
+    #[must_use]
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+}
+
+/// A nominal type lowered to a runtime class.
+#[derive(Debug, Clone)]
+pub struct LoweredClass<'a> {
+    /// Class name
+    pub name: String,
+    /// Kind of declaration the class was lowered from
+    pub kind: TypeKind,
+    /// Superclass name, for classes that inherit
+    pub superclass: Option<String>,
+    /// Adopted protocols, from the declaration and its `impl` blocks
+    pub protocols: Vec<String>,
+    /// Fields declared by the type itself (not inherited ones)
+    pub ivars: Vec<Ivar>,
+    /// Instance and static methods
+    pub methods: Vec<LoweredMethod<'a>>,
+    /// Source location of the declaration
+    pub span: Span,
+}
+
+/// A protocol lowered to a runtime protocol.
+#[derive(Debug, Clone)]
+pub struct LoweredProtocol {
+    /// Protocol name
+    pub name: String,
+    /// Selectors and type encodings of the required methods
+    pub requirements: Vec<(String, String)>,
+}
+
+/// The runtime form of a checked program.
+#[derive(Debug, Clone, Default)]
+pub struct LoweredModule<'a> {
+    /// Classes, ordered so that superclasses precede their subclasses
+    pub classes: Vec<LoweredClass<'a>>,
+    /// Protocols declared by the program
+    pub protocols: Vec<LoweredProtocol>,
+    /// Every selector the module uses, deduplicated in first-use order
+    pub selectors: Vec<String>,
+}
+
+impl<'a> LoweredModule<'a> {
+    /// Look up a lowered class by name.
+    #[must_use]
+    pub fn class(&self, name: &str) -> Option<&LoweredClass<'a>> {
+        self.classes.iter().find(|class| class.name == name)
+    }
+
+    /// Look up the method a class responds to, searching superclasses.
+    ///
+    /// Only instance methods are found; see [`LoweredModule::static_method`].
+    #[must_use]
+    pub fn method(&self, class: &str, selector: &str) -> Option<&LoweredMethod<'a>> {
+        self.defining_class(class, selector)?
+            .methods
+            .iter()
+            .find(|m| !m.is_static() && m.selector == selector)
+    }
+
+    /// Find the class whose instance method a class inherits for a selector:
+    /// the class itself or its nearest superclass defining the method.
+    #[must_use]
+    pub fn defining_class(&self, class: &str, selector: &str) -> Option<&LoweredClass<'a>> {
+        let mut visited = HashSet::new();
+        let mut current = self.class(class);
+        while let Some(class) = current {
+            if !visited.insert(class.name.as_str()) {
+                break;
+            }
+            if class.methods.iter().any(|m| !m.is_static() && m.selector == selector) {
+                return Some(class);
+            }
+            current = class.superclass.as_deref().and_then(|name| self.class(name));
+        }
+        None
+    }
+
+    /// Iterate over the classes inheriting from a class, directly or not.
+    pub fn subclasses<'s>(&'s self, class: &'s str) -> impl Iterator<Item = &'s LoweredClass<'a>> + 's {
+        self.classes.iter().filter(move |candidate| {
+            let mut visited = HashSet::new();
+            let mut current = candidate.superclass.as_deref();
+            while let Some(name) = current {
+                if name == class {
+                    return true;
+                }
+                if !visited.insert(name) {
+                    break;
+                }
+                current = self.class(name).and_then(|c| c.superclass.as_deref());
+            }
+            false
+        })
+    }
+
+    /// Look up a static method of a class.
+    #[must_use]
+    pub fn static_method(&self, class: &str, selector: &str) -> Option<&LoweredMethod<'a>> {
+        self.class(class)?
+            .methods
+            .iter()
+            .find(|m| m.is_static() && m.selector == selector)
+    }
+
+    /// Resolve the method a call refers to.
+    ///
+    /// `labels` are the argument labels written at the call site; unlabeled
+    /// arguments match any parameter. When the receiver's class is known the
+    /// search starts there and walks up its superclasses; otherwise the call
+    /// resolves only if every matching method shares one selector.
+    #[must_use]
+    pub fn resolve_method(
+        &self,
+        class: Option<&str>,
+        name: &str,
+        labels: &[Option<String>],
+        is_static: bool,
+    ) -> Option<&LoweredMethod<'a>> {
+        let matches = |method: &&LoweredMethod<'a>| {
+            method.name == name
+                && method.is_static() == is_static
+                && method.arity() == labels.len()
+                && labels
+                    .iter()
+                    .zip(&method.labels)
+                    .all(|(label, expected)| label.as_ref().is_none_or(|l| l == expected))
+        };
+
+        if let Some(class) = class {
+            let mut visited = HashSet::new();
+            let mut current = self.class(class);
+            while let Some(class) = current {
+                if !visited.insert(class.name.as_str()) {
+                    break;
+                }
+                if let Some(method) = class.methods.iter().find(matches) {
+                    return Some(method);
+                }
+                current = class.superclass.as_deref().and_then(|name| self.class(name));
+            }
+            return None;
+        }
+
+        let mut candidates = self.classes.iter().flat_map(|c| c.methods.iter()).filter(matches);
+        let first = candidates.next()?;
+        candidates.all(|m| m.selector == first.selector).then_some(first)
+    }
+
+    /// Look up a field of a class, searching superclasses.
+    #[must_use]
+    pub fn ivar(&self, class: &str, field: &str) -> Option<&Ivar> {
+        let mut visited = HashSet::new();
+        let mut current = self.class(class);
+        while let Some(class) = current {
+            if !visited.insert(class.name.as_str()) {
+                break;
+            }
+            if let Some(ivar) = class.ivars.iter().find(|ivar| ivar.name == field) {
+                return Some(ivar);
+            }
+            current = class.superclass.as_deref().and_then(|name| self.class(name));
+        }
+        None
+    }
+
+    /// Intern every selector the module uses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime fails to intern a selector.
+    pub fn intern_selectors(&self) -> Result<Vec<Selector>> {
+        self.selectors
+            .iter()
+            .map(|name| Selector::from_str(name).map_err(CodegenError::from))
+            .collect()
+    }
+
+    /// Register the module's protocols, classes and methods with the runtime.
+    ///
+    /// Returns the registered classes in the same order as
+    /// [`LoweredModule::classes`]. Instance methods are bound to the IMP
+    /// thunk, so messages sent to them reach the installed
+    /// [`MethodHandler`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a superclass is neither part of the module nor
+    /// already registered, or if the runtime rejects a class (for example
+    /// because a class with the same name already exists).
+    pub fn register(&self) -> Result<Vec<Class>> {
+        self.intern_selectors()?;
+
+        let arena = get_global_arena();
+        let mut protocols: Vec<Protocol> = Vec::new();
+        for lowered in &self.protocols {
+            let protocol = Protocol::new(&lowered.name, None)?;
+            for (selector, encoding) in &lowered.requirements {
+                protocol.add_required(Selector::from_str(selector)?, encoding, arena)?;
+            }
+            protocols.push(protocol);
+        }
+
+        let mut classes: Vec<Class> = Vec::with_capacity(self.classes.len());
+        for lowered in &self.classes {
+            let superclass = match &lowered.superclass {
+                Some(name) => Some(
+                    classes
+                        .iter()
+                        .find(|class| class.name() == name)
+                        .cloned()
+                        .or_else(|| class_from_name(name))
+                        .ok_or_else(|| CodegenError::UnknownSuperclass {
+                            class: lowered.name.clone(),
+                            superclass: name.clone(),
+                        })?,
+                ),
+                None => None,
+            };
+
+            let mut builder = ClassBuilder::new(&lowered.name, superclass.as_ref());
+            for name in &lowered.protocols {
+                let protocol = match protocols.iter().find(|p| p.name() == name) {
+                    Some(protocol) => protocol.clone(),
+                    None => {
+                        let protocol = Protocol::new(name, None)?;
+                        protocols.push(protocol.clone());
+                        protocol
+                    }
+                };
+                builder.add_protocol(&protocol);
+            }
+            let class = builder.register()?;
+
+            // Fields are recorded for introspection, encoded like the return
+            // values of their getters
+            for ivar in &lowered.ivars {
+                class.add_ivar(oxidec::runtime::Ivar::new(
+                    RuntimeString::new(&ivar.name, arena),
+                    RuntimeString::new(encode_ty(&ivar.ty), arena),
+                ))?;
+            }
+
+            // The builder registers methods without type encodings, which
+            // dispatch needs to validate argument counts
+            for method in lowered.methods.iter().filter(|m| !m.is_static()) {
+                class.add_method(Method {
+                    selector: Selector::from_str(&method.selector)?,
+                    imp: method_thunk,
+                    types: RuntimeString::new(&method.encoding, arena),
+                })?;
+            }
+            classes.push(class);
+        }
+
+        Ok(classes)
+    }
+}
+
+/// Executes the body of a lowered method.
+///
+/// Called by the IMP thunk with the receiver, the selector the method was
+/// registered under, and the type-erased arguments. The returned word is
+/// written to the message's return slot.
+///
+/// # Thread Safety
+///
+/// Handlers may be called from any thread and must be thread-safe.
+pub type MethodHandler = fn(receiver: ObjectPtr, selector: &Selector, args: &[usize]) -> Option<usize>;
+
+/// Handler invoked by the IMP thunk.
+static METHOD_HANDLER: RwLock<Option<MethodHandler>> = RwLock::new(None);
+
+/// Install the handler that executes lowered methods.
+///
+/// # Panics
+///
+/// Panics if the handler lock is poisoned.
+pub fn set_method_handler(handler: MethodHandler) {
+    *METHOD_HANDLER.write().unwrap() = Some(handler);
+}
+
+/// Remove the installed method handler.
+///
+/// Messages to lowered methods do nothing until a new handler is installed.
+///
+/// # Panics
+///
+/// Panics if the handler lock is poisoned.
+pub fn clear_method_handler() {
+    *METHOD_HANDLER.write().unwrap() = None;
+}
+
+/// IMP shared by every lowered method.
+///
+/// # Safety
+///
+/// `args` must point to as many argument words as the selector has colons,
+/// and `ret` must be writable for a `usize`, as arranged by dispatch.
+unsafe extern "C-unwind" fn method_thunk(
+    receiver: ObjectPtr,
+    cmd: SelectorHandle,
+    args: *const *mut u8,
+    ret: *mut u8,
+) {
+    let Some(handler) = *METHOD_HANDLER.read().unwrap() else {
+        return;
+    };
+    // SAFETY: dispatch passes the handle of an interned selector
+    let selector = unsafe { Selector::from_handle(cmd) };
+    let arity = selector.name().matches(':').count();
+    let args: &[usize] = if arity == 0 {
+        &[]
+    } else {
+        // SAFETY: dispatch passes the message's argument words, one per colon
+        unsafe { std::slice::from_raw_parts(args.cast::<usize>(), arity) }
+    };
+    if let Some(value) = handler(receiver, &selector, args) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(value) };
+    }
+}
+
+/// Lower the type declarations of a checked program.
+///
+/// `ctx` must be the context the program was checked in, so that type
+/// annotations resolve the same way they did during checking.
+///
+/// # Errors
+///
+/// Returns an error if an `impl` block extends a type the program does not
+/// define, if a type is defined twice, or if an annotation fails to resolve.
+pub fn lower<'a>(ctx: &mut Context<'_>, decls: &'a [Decl<'a>]) -> Result<LoweredModule<'a>> {
+    let mut lowerer = Lowerer {
+        ctx,
+        module: LoweredModule::default(),
+    };
+
+    // Types first, so `impl` blocks can come before the type they extend
+    for decl in decls {
+        lowerer.lower_type(decl)?;
+    }
+    for decl in decls {
+        if let Decl::Impl { type_path, protocol, methods, span } = decl {
+            let name = lowerer.resolve(type_path);
+            let Some(index) = lowerer.module.classes.iter().position(|c| c.name == name) else {
+                return Err(CodegenError::UnknownType { name, span: *span });
+            };
+            if let Some(protocol) = protocol {
+                let protocol = lowerer.resolve(protocol);
+                if !lowerer.module.classes[index].protocols.contains(&protocol) {
+                    lowerer.module.classes[index].protocols.push(protocol);
+                }
+            }
+            lowerer.lower_methods(index, methods)?;
+        }
+    }
+
+    let mut module = lowerer.module;
+    module.classes = order_by_superclass(module.classes);
+    Ok(module)
+}
+
+/// State threaded through lowering.
+struct Lowerer<'m, 'ctx, 'a> {
+    ctx: &'m mut Context<'ctx>,
+    module: LoweredModule<'a>,
+}
+
+impl<'a> Lowerer<'_, '_, 'a> {
+    /// Lower a type-defining declaration; other declarations are skipped.
+    fn lower_type(&mut self, decl: &'a Decl<'a>) -> Result<()> {
+        let (name, kind, superclass, fields, protocols, methods, span): (_, _, _, &[StructField], _, &[FnDecl<'a>], _) =
+            match decl {
+                Decl::Class { name, superclass, fields, protocols, span, .. } => {
+                    (*name, TypeKind::Class, superclass.as_deref(), fields, protocols, &[], *span)
+                }
+                Decl::Struct { name, fields, protocols, span, .. } => {
+                    (*name, TypeKind::Struct, None, fields, protocols, &[], *span)
+                }
+                Decl::Enum { name, methods, protocols, span, .. } => {
+                    (*name, TypeKind::Enum, None, &[], protocols, methods, *span)
+                }
+                Decl::Protocol { name, methods, .. } => {
+                    let mut requirements = Vec::new();
+                    for method in methods {
+                        let method_name = self.name(method.name);
+                        let labels: Vec<String> = method
+                            .params
+                            .iter()
+                            .map(|p| self.name(p.label.unwrap_or(p.name)))
+                            .collect();
+                        let selector = selector_name(&method_name, &labels);
+                        let mut params = Vec::new();
+                        for param in &method.params {
+                            params.push(ast_to_ty(self.ctx, &param.type_annotation)?);
+                        }
+                        let return_type = match &method.return_type {
+                            Some(ty) => ast_to_ty(self.ctx, ty)?,
+                            None => Ty::Primitive(PrimTy::Unit),
+                        };
+                        self.intern(&selector);
+                        requirements.push((selector, encode_signature(&return_type, &params)));
+                    }
+                    let name = self.name(*name);
+                    self.module.protocols.push(LoweredProtocol { name, requirements });
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            };
+
+        let name = self.name(name);
+        if self.module.classes.iter().any(|c| c.name == name) {
+            return Err(CodegenError::DuplicateType { name, span });
+        }
+
+        let mut ivars = Vec::with_capacity(fields.len());
+        for field in fields {
+            ivars.push(Ivar {
+                name: self.name(field.name),
+                ty: ast_to_ty(self.ctx, &field.type_annotation)?,
+            });
+        }
+
+        self.module.classes.push(LoweredClass {
+            name,
+            kind,
+            superclass: superclass.map(|path| self.resolve(path)),
+            protocols: protocols.iter().map(|path| self.resolve(path)).collect(),
+            ivars,
+            methods: Vec::new(),
+            span,
+        });
+        let index = self.module.classes.len() - 1;
+        self.lower_methods(index, methods)
+    }
+
+    /// Lower methods onto the class at `index`.
+    fn lower_methods(&mut self, index: usize, methods: &'a [FnDecl<'a>]) -> Result<()> {
+        for decl in methods {
+            let method = self.lower_method(decl)?;
+            self.module.classes[index].methods.push(method);
+        }
+        Ok(())
+    }
+
+    /// Lower a single method.
+    fn lower_method(&mut self, decl: &'a FnDecl<'a>) -> Result<LoweredMethod<'a>> {
+        let name = match decl.name {
+            Some(name) => self.name(name),
+            None => "init".to_string(),
+        };
+        let labels: Vec<String> = decl
+            .params
+            .iter()
+            .map(|p| self.name(p.label.unwrap_or(p.name)))
+            .collect();
+        let selector = selector_name(&name, &labels);
+        self.intern(&selector);
+
+        // Generic methods lower to their erased form; parameters stay type
+        // variables and are passed as object words
+        self.ctx.push_generic_params(&decl.generics);
+        let mut params = Vec::with_capacity(decl.params.len());
+        for param in &decl.params {
+            params.push(ast_to_ty(self.ctx, &param.type_annotation)?);
+        }
+        let return_type = match &decl.return_type {
+            Some(ty) => ast_to_ty(self.ctx, ty)?,
+            None if decl.is_init => Ty::SelfType,
+            None => Ty::Primitive(PrimTy::Unit),
+        };
+        self.ctx.pop_generic_params(&decl.generics);
+
+        Ok(LoweredMethod {
+            encoding: encode_signature(&return_type, &params),
+            name,
+            labels,
+            selector,
+            params,
+            return_type,
+            decl,
+        })
+    }
+
+    /// Record a selector in the module's selector table.
+    fn intern(&mut self, selector: &str) {
+        if !self.module.selectors.iter().any(|s| s == selector) {
+            self.module.selectors.push(selector.to_string());
+        }
+    }
+
+    /// Resolve a symbol to its name.
+    fn name(&self, sym: oxidex_mem::Symbol) -> String {
+        self.ctx.interner.resolve(sym).unwrap_or("").to_string()
+    }
+
+    /// Resolve a type path to the name of the type it refers to.
+    fn resolve(&self, path: &[oxidex_mem::Symbol]) -> String {
+        path.last().map(|&sym| self.name(sym)).unwrap_or_default()
+    }
+}
+
+/// Build the selector of a method from its name and parameter labels.
+///
+/// See the module documentation for the naming scheme.
+#[must_use]
+pub fn selector_name(name: &str, labels: &[String]) -> String {
+    let Some((first, rest)) = labels.split_first() else {
+        return name.to_string();
+    };
+
+    let mut selector = name.to_string();
+    let mut chars = first.chars();
+    if let Some(c) = chars.next() {
+        selector.extend(c.to_uppercase());
+        selector.push_str(chars.as_str());
+    }
+    selector.push(':');
+    for label in rest {
+        selector.push_str(label);
+        selector.push(':');
+    }
+    selector
+}
+
+/// Build the runtime type encoding of a method signature.
+///
+/// The receiver and selector are implicit in every method and encoded as
+/// `@:` after the return type.
+#[must_use]
+pub fn encode_signature(return_type: &Ty, params: &[Ty]) -> String {
+    let mut encoding = encode_ty(return_type).to_string();
+    encoding.push_str(types::OBJECT);
+    encoding.push_str(types::SELECTOR);
+    for param in params {
+        encoding.push_str(encode_ty(param));
+    }
+    encoding
+}
+
+/// Encode a type as it is passed through a message.
+///
+/// Integers up to 32 bits travel as `int`, 64-bit integers as `long long`,
+/// and everything that does not fit in a machine word is boxed and passed
+/// as an object.
+fn encode_ty(ty: &Ty) -> &'static str {
+    match ty {
+        Ty::Primitive(prim) => match prim {
+            PrimTy::Unit => types::VOID,
+            PrimTy::Bool
+            | PrimTy::Char
+            | PrimTy::Int8
+            | PrimTy::Int16
+            | PrimTy::Int32
+            | PrimTy::UInt8
+            | PrimTy::UInt16
+            | PrimTy::UInt32 => types::INT,
+            PrimTy::Int64 | PrimTy::UInt64 => types::LONG_LONG,
+            PrimTy::Float32 => types::FLOAT,
+            PrimTy::Float64 => types::DOUBLE,
+            PrimTy::Int128 | PrimTy::UInt128 | PrimTy::String => types::OBJECT,
+        },
+        Ty::Never => types::VOID,
+        _ => types::OBJECT,
+    }
+}
+
+/// Order classes so that every superclass precedes its subclasses.
+///
+/// Classes whose superclass is outside the module keep their relative
+/// order; an inheritance cycle leaves the remaining classes in source order.
+fn order_by_superclass(mut pending: Vec<LoweredClass<'_>>) -> Vec<LoweredClass<'_>> {
+    let mut ordered: Vec<LoweredClass<'_>> = Vec::with_capacity(pending.len());
+    loop {
+        let ready = pending.iter().position(|class| match &class.superclass {
+            Some(superclass) => !pending.iter().any(|c| &c.name == superclass),
+            None => true,
+        });
+        match ready {
+            Some(index) => ordered.push(pending.remove(index)),
+            None => break,
+        }
+    }
+    ordered.extend(pending);
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::Object;
+    use oxidec::runtime::MessageArgs;
+    use oxidex_mem::{StringInterner, Symbol};
+    use oxidex_syntax::ast::decl::{FnParam, Visibility};
+    use oxidex_syntax::ast::expr::Expr;
+    use oxidex_syntax::ast::ty::Type;
+
+    #[test]
+    fn test_selector_names() {
+        let labels = |labels: &[&str]| labels.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(selector_name("reset", &[]), "reset");
+        assert_eq!(selector_name("move", &labels(&["by"])), "moveBy:");
+        assert_eq!(selector_name("add", &labels(&["x", "y"])), "addX:y:");
+        assert_eq!(
+            encode_signature(&Ty::Primitive(PrimTy::Int64), &[Ty::Primitive(PrimTy::Float64)]),
+            "q@:d"
+        );
+    }
+
+    fn get_answer(_receiver: ObjectPtr, selector: &Selector, args: &[usize]) -> Option<usize> {
+        match selector.name() {
+            "answer" => Some(42),
+            "addX:y:" => Some(args[0] + args[1]),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_lowered_classes_dispatch_through_the_thunk() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = [
+            "LoweringBase", "LoweringDerived", "LoweringValue", "answer", "add", "x", "y", "count", "Int",
+        ]
+        .iter()
+        .map(|n| interner.intern(n))
+        .collect();
+        let [base, derived, value, answer, add, x, y, count, int_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let body = Expr::Identifier(count);
+        let method = |name, params: Vec<Symbol>| FnDecl {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: Some(name),
+            generics: vec![],
+            params: params
+                .into_iter()
+                .map(|name| FnParam { label: None, name, type_annotation: int.clone(), span })
+                .collect(),
+            return_type: Some(int.clone()),
+            body: &body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let decls = vec![
+            // Declared before its superclass and extended by a later `impl`
+            Decl::Class {
+                name: derived,
+                generics: vec![],
+                superclass: Some(vec![base]),
+                fields: vec![],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Class {
+                name: base,
+                generics: vec![],
+                superclass: None,
+                fields: vec![StructField { name: count, type_annotation: int.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![base],
+                protocol: None,
+                methods: vec![method(answer, vec![]), method(add, vec![x, y])],
+                span,
+            },
+        ];
+        let module = lower(&mut ctx, &decls).unwrap();
+
+        let names: Vec<&str> = module.classes.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["LoweringBase", "LoweringDerived"]);
+        assert_eq!(module.selectors, ["answer", "addX:y:"]);
+        let base_class = &module.classes[0];
+        assert_eq!(base_class.ivars, [Ivar { name: "count".into(), ty: Ty::Primitive(PrimTy::Int64) }]);
+        assert_eq!(base_class.methods[1].encoding, "q@:qq");
+        // Subclasses inherit the lowered methods of their superclass
+        assert!(module.method("LoweringDerived", "answer").is_some());
+
+        let classes = module.register().unwrap();
+        let ivars = oxidec::runtime::instance_variables(&classes[1]);
+        let ivars: Vec<_> =
+            ivars.iter().map(|ivar| (ivar.name.as_str().unwrap(), ivar.types.as_str().unwrap())).collect();
+        assert_eq!(ivars, [("count", "q")]);
+        set_method_handler(get_answer);
+        let object = Object::new(&classes[1]).unwrap();
+        let answer = Selector::from_str("answer").unwrap();
+        assert_eq!(object.send_message(&answer, &MessageArgs::None).unwrap(), Some(42));
+        let add = Selector::from_str("addX:y:").unwrap();
+        assert_eq!(object.send_message(&add, &MessageArgs::two(2, 3)).unwrap(), Some(5));
+        clear_method_handler();
+
+        // Extending a type the program does not define is an error
+        let orphan = vec![Decl::Impl { type_path: vec![value], protocol: None, methods: vec![], span }];
+        let err = lower(&mut ctx, &orphan).unwrap_err();
+        assert!(matches!(err, CodegenError::UnknownType { ref name, .. } if name == "LoweringValue"));
+    }
+}
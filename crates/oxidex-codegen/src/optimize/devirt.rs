@@ -0,0 +1,244 @@
+//! Devirtualization.
+//!
+//! A message send can become a direct call when the method it reaches is
+//! known at compile time. That holds when the receiver's class is known
+//! statically and either:
+//!
+//! - the class is sealed: structs and enums always are, and classes can be
+//!   sealed through [`DevirtConfig::seal`]; or
+//! - the method the class inherits is final, per [`DevirtConfig::finalize`].
+//!
+//! The language has no attribute syntax yet, so classes and methods are
+//! sealed or finalized by the driver. A class the module subclasses is
+//! never treated as sealed, and a method a subclass overrides is never
+//! treated as final, whatever the configuration says.
+//!
+//! Receivers typed with a known class are never nil, so the direct call
+//! preserves the semantics of the send.
+
+use crate::ir::{Inst, Module, method_symbol};
+use crate::lowering::{LoweredModule, TypeKind};
+use std::collections::HashSet;
+
+/// Classes and methods the pass may assume are not overridden.
+#[derive(Debug, Clone, Default)]
+pub struct DevirtConfig {
+    sealed: HashSet<String>,
+    final_methods: HashSet<(String, String)>,
+}
+
+impl DevirtConfig {
+    /// Create an empty configuration.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark a class as having no subclasses.
+    #[must_use]
+    pub fn seal(mut self, class: impl Into<String>) -> Self {
+        self.sealed.insert(class.into());
+        self
+    }
+
+    /// Mark a method of a class as not overridden by any subclass.
+    #[must_use]
+    pub fn finalize(mut self, class: impl Into<String>, selector: impl Into<String>) -> Self {
+        self.final_methods.insert((class.into(), selector.into()));
+        self
+    }
+
+    /// Whether a class was sealed.
+    #[must_use]
+    pub fn is_sealed(&self, class: &str) -> bool {
+        self.sealed.contains(class)
+    }
+
+    /// Whether a method was finalized.
+    #[must_use]
+    pub fn is_final(&self, class: &str, selector: &str) -> bool {
+        self.final_methods.contains(&(class.to_string(), selector.to_string()))
+    }
+}
+
+/// A send rewritten into a direct call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Devirtualized {
+    /// Function containing the send
+    pub function: String,
+    /// Selector of the send
+    pub selector: String,
+    /// Function now called directly
+    pub target: String,
+}
+
+/// Outcome of [`devirtualize`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DevirtReport {
+    /// Number of sends examined
+    pub sends: usize,
+    /// Sends rewritten into direct calls
+    pub devirtualized: Vec<Devirtualized>,
+}
+
+impl DevirtReport {
+    /// Number of sends rewritten into direct calls.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.devirtualized.len()
+    }
+
+    /// Number of sends left dynamic.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.sends - self.count()
+    }
+}
+
+/// Rewrite sends whose target is known statically into direct calls.
+pub fn devirtualize(module: &mut Module, lowered: &LoweredModule<'_>, config: &DevirtConfig) -> DevirtReport {
+    let mut report = DevirtReport::default();
+
+    for function in &mut module.functions {
+        for block in &mut function.blocks {
+            for inst in &mut block.insts {
+                let Inst::Send { receiver, selector, args } = &inst.inst else {
+                    continue;
+                };
+                report.sends += 1;
+
+                let Some(class) = function.values[receiver.0 as usize].class_name() else {
+                    continue;
+                };
+                let Some(target) = static_target(lowered, config, class, selector) else {
+                    continue;
+                };
+
+                report.devirtualized.push(Devirtualized {
+                    function: function.name.clone(),
+                    selector: selector.clone(),
+                    target: target.clone(),
+                });
+                let mut call_args = Vec::with_capacity(args.len() + 1);
+                call_args.push(*receiver);
+                call_args.extend(args);
+                inst.inst = Inst::Call { callee: target, args: call_args };
+            }
+        }
+    }
+
+    report
+}
+
+/// Get the function a send to an instance of `class` reaches, if no
+/// subclass can change it.
+fn static_target(lowered: &LoweredModule<'_>, config: &DevirtConfig, class: &str, selector: &str) -> Option<String> {
+    let receiver = lowered.class(class)?;
+    let owner = lowered.defining_class(class, selector)?;
+
+    let sealed = matches!(receiver.kind, TypeKind::Struct | TypeKind::Enum)
+        || (config.is_sealed(class) && lowered.subclasses(class).next().is_none());
+    let is_final = config.is_final(&owner.name, selector)
+        && !lowered.subclasses(&owner.name).any(|sub| {
+            sub.methods.iter().any(|m| !m.is_static() && m.selector == selector)
+        });
+
+    (sealed || is_final).then(|| method_symbol(&owner.name, selector))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::build_module;
+    use crate::lowering::lower;
+    use oxidex_mem::{StringInterner, Symbol};
+    use oxidex_syntax::Span;
+    use oxidex_syntax::ast::decl::{Decl, FnDecl, Visibility};
+    use oxidex_syntax::ast::expr::Expr;
+    use oxidex_syntax::ast::ty::Type;
+    use oxidex_typecheck::infer::Context;
+
+    #[test]
+    fn test_sends_to_sealed_receivers_become_calls() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Point", "Base", "Derived", "get", "name", "call", "self", "Int", "1"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [point, base, derived, get, name, call, self_sym, int_sym, one_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let one = Expr::IntegerLiteral { value: one_sym, type_suffix: None, span };
+        let self_expr = Expr::Identifier(self_sym);
+        let send = |method| Expr::MethodCall { receiver: &self_expr, method, args: vec![], span };
+        let (send_get, send_name) = (send(get), send(name));
+        let method = |name, body| FnDecl {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: Some(name),
+            generics: vec![],
+            params: vec![],
+            return_type: Some(int.clone()),
+            body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let class = |name, superclass| Decl::Class {
+            name,
+            generics: vec![],
+            superclass,
+            fields: vec![],
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span,
+        };
+        let methods = |type_name, methods| Decl::Impl { type_path: vec![type_name], protocol: None, methods, span };
+        let decls = vec![
+            Decl::Struct {
+                name: point,
+                generics: vec![],
+                fields: vec![],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            class(base, None),
+            class(derived, Some(vec![base])),
+            methods(point, vec![method(get, &one), method(call, &send_get)]),
+            methods(base, vec![method(get, &one), method(name, &one), method(call, &send_get)]),
+            methods(derived, vec![method(get, &one), method(call, &send_name)]),
+        ];
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut module = build_module(&mut ctx, &lowered, &decls).unwrap();
+        // `Base` is subclassed and `get` is overridden, so neither holds
+        let config = DevirtConfig::new().seal("Base").finalize("Base", "get").finalize("Base", "name");
+        let report = devirtualize(&mut module, &lowered, &config);
+
+        assert_eq!(report.sends, 3);
+        let targets: Vec<(&str, &str)> = report
+            .devirtualized
+            .iter()
+            .map(|d| (d.function.as_str(), d.target.as_str()))
+            .collect();
+        // Structs are sealed; `name` is final and inherited from `Base`
+        assert_eq!(targets, [("Point.call", "Point.get"), ("Derived.call", "Base.name")]);
+        assert_eq!(report.remaining(), 1);
+
+        let body = &module.function("Derived.call").unwrap().blocks[0];
+        let Inst::Call { callee, args } = &body.insts.last().unwrap().inst else {
+            panic!("expected a direct call, got {:?}", body.insts)
+        };
+        assert_eq!(callee, "Base.name");
+        assert_eq!(args, &module.function("Derived.call").unwrap().params);
+        assert!(matches!(
+            module.function("Base.call").unwrap().blocks[0].insts.last().unwrap().inst,
+            Inst::Send { .. }
+        ));
+    }
+}
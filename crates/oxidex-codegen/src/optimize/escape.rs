@@ -0,0 +1,559 @@
+//! Escape analysis and allocation sinking.
+//!
+//! An allocation whose object never leaves the function is replaced by its
+//! fields: each read of a field takes the value last written to it, and
+//! the writes and the allocation are removed. An object escapes when it is
+//! passed to a call or send, stored anywhere, returned, merged by a phi, or
+//! used by anything but a field read or write.
+//!
+//! Boxes are handled the same way, as objects with a single field holding
+//! the boxed value, so a number boxed for a closure that was inlined or
+//! removed is unboxed back into plain values.
+//!
+//! Fields are followed across blocks: a read takes the value written on
+//! every path reaching it, and where paths with different values meet, a
+//! phi merges them. An object is kept if a read may see a field that was
+//! never written since its allocation.
+
+use crate::ir::verify::dominators;
+use crate::ir::{BlockId, Function, Inst, IrType, Module, Phi, ValueId};
+use std::collections::{HashMap, HashSet};
+
+/// An allocation replaced by its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sunk {
+    /// Function containing the allocation
+    pub function: String,
+    /// Class of the object
+    pub class: String,
+    /// Value the allocation defined
+    pub value: ValueId,
+}
+
+/// A box replaced by the values stored in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unboxed {
+    /// Function containing the box
+    pub function: String,
+    /// Value the box defined
+    pub value: ValueId,
+}
+
+/// Outcome of [`sink_allocations`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EscapeReport {
+    /// Allocations removed
+    pub sunk: Vec<Sunk>,
+    /// Boxes removed
+    pub unboxed: Vec<Unboxed>,
+}
+
+impl EscapeReport {
+    /// Number of allocations and boxes removed.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.sunk.len() + self.unboxed.len()
+    }
+}
+
+/// Replace allocations of objects and boxes that do not escape by their
+/// fields.
+pub fn sink_allocations(module: &mut Module) -> EscapeReport {
+    let mut report = EscapeReport::default();
+    for function in &mut module.functions {
+        sink_function(function, &mut report);
+    }
+    report
+}
+
+/// What an allocation that may be replaced allocates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Allocation {
+    /// An instance of a class
+    Object(String),
+    /// A box
+    Box,
+}
+
+/// A field of an allocation; a box's single field has an empty name.
+type Slot = (ValueId, String);
+
+fn sink_function(function: &mut Function, report: &mut EscapeReport) {
+    let mut candidates = candidates(function);
+    while !candidates.is_empty() {
+        // Replacing the fields finds reads that may see a field
+        // uninitialized; keep those objects and start over
+        let mut sunk = function.clone();
+        let failed = Promotion::new(function, &candidates).run(&mut sunk);
+        if !failed.is_empty() {
+            candidates.retain(|value, _| !failed.contains(value));
+            continue;
+        }
+        *function = sunk;
+
+        let mut candidates: Vec<_> = candidates.into_iter().collect();
+        candidates.sort_by_key(|(value, _)| *value);
+        for (value, allocation) in candidates {
+            let function = function.name.clone();
+            match allocation {
+                Allocation::Object(class) => report.sunk.push(Sunk { function, class, value }),
+                Allocation::Box => report.unboxed.push(Unboxed { function, value }),
+            }
+        }
+        return;
+    }
+}
+
+/// Find the allocations used only as the object of field reads and writes
+/// in reachable blocks.
+fn candidates(function: &Function) -> HashMap<ValueId, Allocation> {
+    let idom = dominators(function);
+    let mut allocations = HashMap::new();
+    let mut escaped: HashSet<ValueId> = HashSet::new();
+    let mut used: HashSet<ValueId> = HashSet::new();
+    // Allocations accessed as objects and as boxes
+    let mut fields: HashSet<ValueId> = HashSet::new();
+    let mut cells: HashSet<ValueId> = HashSet::new();
+    let mut writes = Vec::new();
+    for block in &function.blocks {
+        let reachable = idom[block.id.0 as usize].is_some();
+        for phi in &block.phis {
+            for &(_, value) in &phi.incoming {
+                escaped.insert(value);
+                used.insert(value);
+            }
+        }
+        for inst in &block.insts {
+            let (object, value) = match &inst.inst {
+                Inst::Alloc { class } => {
+                    allocations.insert(inst.result, Allocation::Object(class.clone()));
+                    continue;
+                }
+                Inst::Box(value) => {
+                    allocations.insert(inst.result, Allocation::Box);
+                    escaped.insert(*value);
+                    used.insert(*value);
+                    continue;
+                }
+                Inst::GetField { object, .. } => {
+                    fields.insert(*object);
+                    (*object, None)
+                }
+                Inst::Load(cell) => {
+                    cells.insert(*cell);
+                    (*cell, None)
+                }
+                Inst::SetField { object, value, .. } => {
+                    fields.insert(*object);
+                    writes.push((inst.result, *object));
+                    (*object, Some(*value))
+                }
+                Inst::Store { cell, value } => {
+                    cells.insert(*cell);
+                    writes.push((inst.result, *cell));
+                    (*cell, Some(*value))
+                }
+                other => {
+                    for value in other.operands() {
+                        escaped.insert(value);
+                        used.insert(value);
+                    }
+                    continue;
+                }
+            };
+            used.insert(object);
+            if !reachable {
+                escaped.insert(object);
+            }
+            if let Some(value) = value {
+                escaped.insert(value);
+                used.insert(value);
+            }
+        }
+        for value in block.terminator.operands() {
+            escaped.insert(value);
+            used.insert(value);
+        }
+    }
+    // A write whose unit result is used must stay
+    for (result, object) in writes {
+        if used.contains(&result) {
+            escaped.insert(object);
+        }
+    }
+
+    allocations.retain(|value, allocation| {
+        let misused = match allocation {
+            Allocation::Object(_) => cells.contains(value),
+            Allocation::Box => fields.contains(value),
+        };
+        !misused && !escaped.contains(value)
+    });
+    allocations
+}
+
+/// Replacement of the fields of allocations by the values written to them.
+struct Promotion<'a> {
+    function: &'a Function,
+    /// Allocations replaced
+    candidates: &'a HashMap<ValueId, Allocation>,
+    /// Predecessors of every block
+    preds: Vec<Vec<BlockId>>,
+    /// Whether every block is reachable from the entry
+    reachable: Vec<bool>,
+    /// Value of each slot at the end of every block that writes it, and
+    /// `None` for slots a block allocates but does not write
+    exits: Vec<HashMap<Slot, Option<ValueId>>>,
+    /// Allocations defined by every block
+    allocated: Vec<HashSet<ValueId>>,
+    /// Value of each slot on entry to a block
+    entries: HashMap<(BlockId, Slot), Option<ValueId>>,
+    /// Phis merging slots, with their blocks
+    phis: Vec<(BlockId, Phi)>,
+    /// Types of the values the phis define
+    types: Vec<IrType>,
+    /// Allocations with a read that may see a field uninitialized
+    failed: HashSet<ValueId>,
+}
+
+impl<'a> Promotion<'a> {
+    fn new(function: &'a Function, candidates: &'a HashMap<ValueId, Allocation>) -> Self {
+        let count = function.blocks.len();
+        let mut exits = vec![HashMap::new(); count];
+        let mut allocated = vec![HashSet::new(); count];
+        for (index, block) in function.blocks.iter().enumerate() {
+            for inst in &block.insts {
+                match &inst.inst {
+                    Inst::Alloc { .. } if candidates.contains_key(&inst.result) => {
+                        allocated[index].insert(inst.result);
+                    }
+                    Inst::Box(value) if candidates.contains_key(&inst.result) => {
+                        allocated[index].insert(inst.result);
+                        exits[index].insert((inst.result, String::new()), Some(*value));
+                    }
+                    Inst::SetField { object, field, value } if candidates.contains_key(object) => {
+                        exits[index].insert((*object, field.clone()), Some(*value));
+                    }
+                    Inst::Store { cell, value } if candidates.contains_key(cell) => {
+                        exits[index].insert((*cell, String::new()), Some(*value));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Self {
+            function,
+            candidates,
+            preds: function.predecessors(),
+            reachable: dominators(function).iter().map(Option::is_some).collect(),
+            exits,
+            allocated,
+            entries: HashMap::new(),
+            phis: Vec::new(),
+            types: Vec::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    /// Rewrite `target`, a copy of the function, returning the allocations
+    /// that could not be replaced; `target` is only valid if there are none.
+    fn run(mut self, target: &mut Function) -> HashSet<ValueId> {
+        let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+        for index in 0..self.function.blocks.len() {
+            if !self.reachable[index] {
+                continue;
+            }
+            let block = BlockId(u32::try_from(index).expect("too many blocks"));
+            let mut current: HashMap<Slot, Option<ValueId>> = HashMap::new();
+            let mut rewritten = Vec::with_capacity(self.function.blocks[index].insts.len());
+            for inst in &self.function.blocks[index].insts {
+                let read = match &inst.inst {
+                    Inst::Alloc { .. } if self.candidates.contains_key(&inst.result) => continue,
+                    Inst::Box(value) if self.candidates.contains_key(&inst.result) => {
+                        current.insert((inst.result, String::new()), Some(*value));
+                        continue;
+                    }
+                    Inst::SetField { object, field, value } if self.candidates.contains_key(object) => {
+                        current.insert((*object, field.clone()), Some(*value));
+                        continue;
+                    }
+                    Inst::Store { cell, value } if self.candidates.contains_key(cell) => {
+                        current.insert((*cell, String::new()), Some(*value));
+                        continue;
+                    }
+                    Inst::GetField { object, field } if self.candidates.contains_key(object) => {
+                        (*object, field.clone())
+                    }
+                    Inst::Load(cell) if self.candidates.contains_key(cell) => (*cell, String::new()),
+                    _ => {
+                        rewritten.push(inst.clone());
+                        continue;
+                    }
+                };
+                let object = read.0;
+                let value = match current.get(&read) {
+                    Some(&value) => value,
+                    None if self.allocated[index].contains(&object) => None,
+                    None => self.entry(block, &read, self.function.value_type(inst.result)),
+                };
+                match value {
+                    Some(value) => {
+                        replacements.insert(inst.result, value);
+                    }
+                    None => {
+                        self.failed.insert(object);
+                    }
+                }
+            }
+            target.blocks[index].insts = rewritten;
+        }
+        if !self.failed.is_empty() {
+            return self.failed;
+        }
+
+        let phis: Vec<ValueId> = self.phis.iter().map(|(_, phi)| phi.result).collect();
+        target.values.append(&mut self.types);
+        for (block, phi) in self.phis {
+            target.blocks[block.0 as usize].phis.push(phi);
+        }
+
+        // Reads take the value written, which may itself be a read
+        let resolve = |mut value: ValueId| {
+            while let Some(&by) = replacements.get(&value) {
+                value = by;
+            }
+            value
+        };
+        for block in &mut target.blocks {
+            let phis = block.phis.iter_mut().flat_map(|phi| phi.incoming.iter_mut().map(|(_, value)| value));
+            let insts = block.insts.iter_mut().flat_map(|inst| inst.inst.operands_mut());
+            for value in phis.chain(insts).chain(block.terminator.operands_mut()) {
+                *value = resolve(*value);
+            }
+        }
+
+        // Remove the phis merging a single value
+        let mut removed = true;
+        while removed {
+            removed = false;
+            for &phi in &phis {
+                let Some(block) = target.blocks.iter().position(|b| b.phis.iter().any(|p| p.result == phi)) else {
+                    continue;
+                };
+                let incoming: HashSet<ValueId> = target.blocks[block]
+                    .phis
+                    .iter()
+                    .find(|p| p.result == phi)
+                    .map(|p| p.incoming.iter().map(|&(_, value)| value).filter(|&value| value != phi).collect())
+                    .unwrap_or_default();
+                if let [value] = incoming.into_iter().collect::<Vec<_>>()[..] {
+                    target.blocks[block].phis.retain(|p| p.result != phi);
+                    target.replace_uses(phi, value);
+                    removed = true;
+                }
+            }
+        }
+        HashSet::new()
+    }
+
+    /// Get the value of `slot` at the end of `block`, or `None` if it may
+    /// be uninitialized.
+    fn exit(&mut self, block: BlockId, slot: &Slot, ty: &IrType) -> Option<ValueId> {
+        if let Some(&value) = self.exits[block.0 as usize].get(slot) {
+            return value;
+        }
+        if self.allocated[block.0 as usize].contains(&slot.0) {
+            return None;
+        }
+        self.entry(block, slot, ty)
+    }
+
+    /// Get the value of `slot` on entry to `block`, merging the values
+    /// reaching it from its predecessors with a phi.
+    fn entry(&mut self, block: BlockId, slot: &Slot, ty: &IrType) -> Option<ValueId> {
+        let key = (block, slot.clone());
+        if let Some(&value) = self.entries.get(&key) {
+            return value;
+        }
+        let preds = self.preds[block.0 as usize].clone();
+        if let [pred] = preds[..] {
+            let value = self.exit(pred, slot, ty);
+            self.entries.insert(key, value);
+            return value;
+        }
+        if preds.is_empty() {
+            return None;
+        }
+
+        // Record the phi before visiting the predecessors, which may reach
+        // the block again around a loop
+        let count = self.function.values.len() + self.types.len();
+        let result = ValueId(u32::try_from(count).expect("too many values"));
+        self.types.push(ty.clone());
+        self.entries.insert(key, Some(result));
+        let mut incoming = Vec::with_capacity(preds.len());
+        for pred in preds {
+            // Unreachable predecessors are never taken
+            if !self.reachable[pred.0 as usize] {
+                incoming.push((pred, result));
+                continue;
+            }
+            match self.exit(pred, slot, ty) {
+                Some(value) => incoming.push((pred, value)),
+                None => {
+                    self.failed.insert(slot.0);
+                    incoming.push((pred, result));
+                }
+            }
+        }
+        self.phis.push((block, Phi { result, incoming }));
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{parse_module, verify_module};
+
+    #[test]
+    fn test_local_objects_are_replaced_by_their_fields() {
+        let mut module = parse_module(
+            r#"
+fn "length"(%0: float, %1: float, %2: object) -> float {
+bb0:
+    %3: object("Point") = alloc "Point"       // sunk
+    %4: unit = set_field %3, "x", %0
+    %5: unit = set_field %3, "y", %1
+    %6: unit = set_field %3, "x", %1
+    %7: float = get_field %3, "x"
+    %8: float = get_field %3, "y"
+    %9: float = binary add %7, %8
+    %10: object("Point") = alloc "Point"      // passed to a send
+    %11: unit = set_field %10, "x", %9
+    %12: float = send %10, "norm"()
+    %13: object("Point") = alloc "Point"      // read before written
+    %14: float = get_field %13, "x"
+    %15: object("Point") = alloc "Point"      // stored in another object
+    %16: unit = set_field %2, "p", %15
+    %17: object("Point") = alloc "Point"      // read uninitialized in another block
+    jump bb1
+bb1:
+    %18: float = get_field %17, "x"
+    %19: float = binary add %12, %14
+    return %19
+}
+"#,
+        )
+        .unwrap();
+
+        let report = sink_allocations(&mut module);
+        let sunk: Vec<(&str, u32)> = report.sunk.iter().map(|s| (s.class.as_str(), s.value.0)).collect();
+        assert_eq!(sunk, [("Point", 3)]);
+        verify_module(&module).unwrap();
+
+        let length = module.function("length").unwrap();
+        let insts = &length.blocks[0].insts;
+        assert_eq!(insts.len(), 15 - 6);
+        let Inst::Binary { lhs, rhs, .. } = &insts[0].inst else {
+            panic!("unexpected body {insts:?}")
+        };
+        assert_eq!((*lhs, *rhs), (ValueId(1), ValueId(1)));
+    }
+
+    #[test]
+    fn test_fields_are_followed_across_blocks() {
+        let mut module = parse_module(
+            r#"
+fn "pick"(%0: bool, %1: float, %2: float) -> float {
+bb0:
+    %3: object("Point") = alloc "Point"       // sunk, merged by a phi
+    %4: unit = set_field %3, "x", %1
+    %5: object("Point") = alloc "Point"       // written on one path only
+    branch %0, bb1, bb2
+bb1:
+    %6: unit = set_field %3, "x", %2
+    %7: unit = set_field %5, "x", %2
+    jump bb3
+bb2:
+    jump bb3
+bb3:
+    %8: float = get_field %3, "x"
+    %9: float = get_field %5, "x"
+    %10: float = binary add %8, %9
+    return %10
+}
+"#,
+        )
+        .unwrap();
+
+        let report = sink_allocations(&mut module);
+        let sunk: Vec<u32> = report.sunk.iter().map(|s| s.value.0).collect();
+        assert_eq!(sunk, [3]);
+        verify_module(&module).unwrap();
+
+        let pick = module.function("pick").unwrap();
+        let [phi] = &pick.blocks[3].phis[..] else { panic!("unexpected phis {:?}", pick.blocks[3].phis) };
+        let mut incoming = phi.incoming.clone();
+        incoming.sort();
+        assert_eq!(incoming, [(BlockId(1), ValueId(2)), (BlockId(2), ValueId(1))]);
+        assert_eq!(pick.value_type(phi.result), &IrType::Float);
+    }
+
+    #[test]
+    fn test_boxed_numbers_that_do_not_escape_are_unboxed() {
+        let mut module = parse_module(
+            r#"
+fn "sum"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %2: object = box %1                       // unboxed
+    %3: object = box %1                       // captured by a closure
+    %4: object = closure "read"(%3)
+    %5: int = const int 1
+    jump bb1
+bb1:
+    %6: int = phi [bb0: %0, bb2: %10]
+    %7: bool = binary gt %6, %1
+    branch %7, bb2, bb3
+bb2:
+    %8: int = load %2
+    %9: int = binary add %8, %6
+    %11: unit = store %2, %9
+    %10: int = binary sub %6, %5
+    jump bb1
+bb3:
+    %12: int = load %2
+    %13: int = call_indirect %4()
+    %14: int = binary add %12, %13
+    return %14
+}
+
+fn "read"(%0: object) -> int {
+bb0:
+    %1: int = load %0
+    return %1
+}
+"#,
+        )
+        .unwrap();
+
+        let report = sink_allocations(&mut module);
+        let unboxed: Vec<(&str, u32)> = report.unboxed.iter().map(|u| (u.function.as_str(), u.value.0)).collect();
+        assert_eq!(unboxed, [("sum", 2)]);
+        assert_eq!(report.count(), 1);
+        verify_module(&module).unwrap();
+
+        // The loop carries the running sum in a phi
+        let sum = module.function("sum").unwrap();
+        let [_, phi] = &sum.blocks[1].phis[..] else { panic!("unexpected phis {:?}", sum.blocks[1].phis) };
+        let mut sums = phi.incoming.clone();
+        sums.sort();
+        assert_eq!(sums, [(BlockId(0), ValueId(1)), (BlockId(2), ValueId(9))]);
+        assert!(sum.blocks[2].insts.iter().all(|inst| !matches!(inst.inst, Inst::Load(_) | Inst::Store { .. })));
+        let Inst::Binary { lhs, .. } = &sum.blocks[3].insts[1].inst else {
+            panic!("unexpected body {:?}", sum.blocks[3].insts)
+        };
+        assert_eq!(*lhs, phi.result);
+    }
+}
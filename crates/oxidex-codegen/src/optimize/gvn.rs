@@ -0,0 +1,222 @@
+//! Global value numbering.
+//!
+//! An instruction that computes what an instruction dominating it already
+//! computed is removed, and its uses read the earlier value instead. Only
+//! instructions whose value depends on nothing but their operands take
+//! part: constants, globals, arithmetic and comparisons, intrinsics other
+//! than lengths, and the tag and payload of enum values. Field and element
+//! reads, lengths, allocations and anything with side effects are never
+//! merged.
+//!
+//! Blocks are visited down the dominator tree, so a value is only reused
+//! where its definition dominates the use. Operands are compared after
+//! earlier replacements, so chains of redundant instructions collapse in
+//! one run.
+
+use crate::intrinsics::Intrinsic;
+use crate::ir::verify::dominators;
+use crate::ir::{BlockId, Constant, Function, Inst, Module, ValueId};
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use std::collections::HashMap;
+
+/// What a mergeable instruction computes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Unit,
+    Nil,
+    Bool(bool),
+    Int(i64),
+    /// Bits of a float, so that `0.0` and `-0.0` stay apart
+    Float(u64),
+    String(String),
+    Global(String),
+    Binary(BinaryOp, ValueId, ValueId),
+    Unary(UnaryOp, ValueId),
+    Intrinsic(Intrinsic, Vec<ValueId>),
+    Tag(ValueId),
+    Payload(ValueId),
+}
+
+impl Key {
+    fn of(inst: &Inst) -> Option<Self> {
+        Some(match inst {
+            Inst::Const(Constant::Unit) => Self::Unit,
+            Inst::Const(Constant::Nil) => Self::Nil,
+            Inst::Const(Constant::Bool(value)) => Self::Bool(*value),
+            Inst::Const(Constant::Int(value)) => Self::Int(*value),
+            Inst::Const(Constant::Float(value)) => Self::Float(value.to_bits()),
+            Inst::Const(Constant::String(value)) => Self::String(value.clone()),
+            Inst::Global(name) => Self::Global(name.clone()),
+            Inst::Binary { op, lhs, rhs } => Self::Binary(*op, *lhs, *rhs),
+            Inst::Unary { op, operand } => Self::Unary(*op, *operand),
+            Inst::Intrinsic { intrinsic, args } if intrinsic.is_pure() => Self::Intrinsic(*intrinsic, args.clone()),
+            Inst::Tag(value) => Self::Tag(*value),
+            Inst::Payload(value) => Self::Payload(*value),
+            _ => return None,
+        })
+    }
+}
+
+/// An instruction found redundant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    /// Function containing the instruction
+    pub function: String,
+    /// Value the instruction defined
+    pub value: ValueId,
+    /// Earlier value its uses now read
+    pub by: ValueId,
+}
+
+/// Outcome of [`gvn`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GvnReport {
+    /// Instructions removed in favour of an earlier one
+    pub merged: Vec<Merged>,
+}
+
+impl GvnReport {
+    /// Number of instructions removed.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.merged.len()
+    }
+}
+
+/// Remove instructions that recompute a value available where they are.
+pub fn gvn(module: &mut Module) -> GvnReport {
+    let mut report = GvnReport::default();
+    for function in &mut module.functions {
+        number_function(function, &mut report);
+    }
+    report
+}
+
+fn number_function(function: &mut Function, report: &mut GvnReport) {
+    let idom = dominators(function);
+    let mut children = vec![Vec::new(); function.blocks.len()];
+    for (index, parent) in idom.iter().enumerate().skip(1) {
+        if let Some(parent) = parent {
+            children[parent.0 as usize].push(BlockId(u32::try_from(index).expect("block ids fit in u32")));
+        }
+    }
+
+    // Values available in the block being visited, and the keys each
+    // block added so they are forgotten when leaving it
+    let mut available: HashMap<Key, ValueId> = HashMap::new();
+    let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+    let mut stack = vec![(BlockId(0), false)];
+    let mut added: Vec<Vec<Key>> = vec![Vec::new(); function.blocks.len()];
+    while let Some((block, leaving)) = stack.pop() {
+        let index = block.0 as usize;
+        if leaving {
+            for key in added[index].drain(..) {
+                available.remove(&key);
+            }
+            continue;
+        }
+        stack.push((block, true));
+        stack.extend(children[index].iter().map(|&child| (child, false)));
+
+        let insts = std::mem::take(&mut function.blocks[index].insts);
+        let mut kept = Vec::with_capacity(insts.len());
+        for mut inst in insts {
+            for operand in inst.inst.operands_mut() {
+                if let Some(&by) = replacements.get(operand) {
+                    *operand = by;
+                }
+            }
+            let Some(key) = Key::of(&inst.inst) else {
+                kept.push(inst);
+                continue;
+            };
+            if let Some(&by) = available.get(&key) {
+                replacements.insert(inst.result, by);
+                report.merged.push(Merged { function: function.name.clone(), value: inst.result, by });
+                continue;
+            }
+            available.insert(key.clone(), inst.result);
+            added[index].push(key);
+            kept.push(inst);
+        }
+        function.blocks[index].insts = kept;
+    }
+
+    // Phis and terminators may read values of blocks visited after them
+    for (old, new) in replacements {
+        function.replace_uses(old, new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{parse_module, verify_module};
+
+    #[test]
+    fn test_redundant_instructions_are_merged() {
+        let mut module = parse_module(
+            r#"
+fn "f"(%0: int, %1: object) -> int {
+bb0:
+    %2: int = const int 1
+    %3: int = binary add %0, %2
+    %4: int = const int 1            // same as %2
+    %5: int = binary add %0, %4      // same as %3 once %4 is %2
+    %6: bool = binary gt %3, %5
+    %7: int = get_field %1, "x"
+    %8: int = get_field %1, "x"      // fields are read again
+    branch %6, bb1, bb2
+bb1:
+    %9: int = binary add %0, %2      // dominated by %3
+    %10: float = const float 0.0
+    jump bb3
+bb2:
+    %11: float = const float -0.0
+    %12: float = const float 0.0     // bb1 does not dominate bb2
+    jump bb3
+bb3:
+    %13: int = phi [bb1: %9, bb2: %5]
+    %14: int = binary add %13, %8
+    return %14
+}
+"#,
+        )
+        .unwrap();
+
+        let report = gvn(&mut module);
+        let merged: Vec<(u32, u32)> = report.merged.iter().map(|m| (m.value.0, m.by.0)).collect();
+        assert_eq!(merged, [(4, 2), (5, 3), (9, 3)]);
+        verify_module(&module).unwrap();
+
+        let f = module.function("f").unwrap();
+        assert_eq!(f.blocks[0].insts.len(), 5);
+        assert_eq!(f.blocks[2].insts.len(), 2);
+        assert_eq!(f.blocks[3].phis[0].incoming, [(BlockId(1), ValueId(3)), (BlockId(2), ValueId(3))]);
+    }
+
+    #[test]
+    fn test_pure_intrinsics_are_merged() {
+        let mut module = parse_module(
+            r#"
+fn "f"(%0: int, %1: object) -> int {
+bb0:
+    %2: int = intrinsic abs(%0)
+    %3: int = intrinsic abs(%0)      // same as %2
+    %4: int = intrinsic length(%1)
+    %5: int = intrinsic length(%1)   // the array may have grown
+    %6: int = intrinsic max(%2, %3)
+    %7: int = intrinsic max(%4, %5)
+    %8: int = binary add %6, %7
+    return %8
+}
+"#,
+        )
+        .unwrap();
+
+        let report = gvn(&mut module);
+        let merged: Vec<(u32, u32)> = report.merged.iter().map(|m| (m.value.0, m.by.0)).collect();
+        assert_eq!(merged, [(3, 2)]);
+        verify_module(&module).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+//! Inlining of trivial functions.
+//!
+//! Direct calls are replaced by the body of the callee when the callee is
+//! trivial:
+//!
+//! - Accessors, which only read or write a field of their receiver, are
+//!   always inlined.
+//! - Other functions are inlined when their body is a single block of at
+//!   most [`InlineConfig::max_size`] instructions; a function written as
+//!   one expression usually is. Functions listed as [hot](InlineConfig::hot),
+//!   such as those a profile finds called most, may be as large as
+//!   [`InlineConfig::hot_max_size`].
+//!
+//! Only [`Inst::Call`]s are inlined. Run [`devirtualize`](super::devirtualize)
+//! first to turn message sends with a known target into calls.
+//!
+//! Callees are inlined as they were before the pass ran, so one run inlines
+//! one level of calls. Recursive functions are never inlined.
+
+use crate::ir::{Block, Constant, Function, Inst, Instruction, Module, Terminator, ValueId};
+use oxidex_syntax::Span;
+use std::collections::{BTreeSet, HashMap};
+
+/// Size limit of inlined functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineConfig {
+    /// Largest number of instructions of an inlined function that is not
+    /// an accessor
+    pub max_size: usize,
+    /// Functions worth inlining at a larger size
+    pub hot: BTreeSet<String>,
+    /// Largest number of instructions of an inlined hot function
+    pub hot_max_size: usize,
+}
+
+impl Default for InlineConfig {
+    fn default() -> Self {
+        Self { max_size: 4, hot: BTreeSet::new(), hot_max_size: 16 }
+    }
+}
+
+impl InlineConfig {
+    /// Largest number of instructions `function` may have to be inlined.
+    #[must_use]
+    pub fn limit(&self, function: &str) -> usize {
+        if self.hot.contains(function) { self.max_size.max(self.hot_max_size) } else { self.max_size }
+    }
+}
+
+/// A call replaced by the body of its callee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inlined {
+    /// Function containing the call
+    pub caller: String,
+    /// Function whose body replaced the call
+    pub callee: String,
+}
+
+/// Outcome of [`inline`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InlineReport {
+    /// Calls replaced by the body of their callee
+    pub inlined: Vec<Inlined>,
+}
+
+impl InlineReport {
+    /// Number of calls inlined.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.inlined.len()
+    }
+}
+
+/// Inline calls to trivial functions.
+pub fn inline(module: &mut Module, config: &InlineConfig) -> InlineReport {
+    let candidates: HashMap<String, Function> = module
+        .functions
+        .iter()
+        .filter(|f| is_accessor(f) || is_small(f, config.limit(&f.name)))
+        .map(|f| (f.name.clone(), f.clone()))
+        .collect();
+
+    let mut report = InlineReport::default();
+    for function in &mut module.functions {
+        // Results of inlined calls and the values replacing them
+        let mut replaced: HashMap<ValueId, ValueId> = HashMap::new();
+        for index in 0..function.blocks.len() {
+            let insts = std::mem::take(&mut function.blocks[index].insts);
+            let mut rewritten = Vec::with_capacity(insts.len());
+
+            for inst in insts {
+                let callee = match &inst.inst {
+                    Inst::Call { callee, args } => candidates
+                        .get(callee)
+                        .filter(|callee| callee.name != function.name && callee.params.len() == args.len()),
+                    _ => None,
+                };
+                let Some(callee) = callee else {
+                    rewritten.push(inst);
+                    continue;
+                };
+                let Inst::Call { args, .. } = &inst.inst else { unreachable!() };
+
+                match splice(function, callee, args, inst.span, &mut rewritten) {
+                    Some(value) => {
+                        replaced.insert(inst.result, value);
+                    }
+                    // The call produced a unit value; keep defining it
+                    None => rewritten.push(Instruction {
+                        result: inst.result,
+                        inst: Inst::Const(Constant::Unit),
+                        span: inst.span,
+                    }),
+                }
+                report.inlined.push(Inlined { caller: function.name.clone(), callee: callee.name.clone() });
+            }
+
+            function.blocks[index].insts = rewritten;
+        }
+        for (&call, &value) in &replaced {
+            let mut value = value;
+            while let Some(&next) = replaced.get(&value) {
+                value = next;
+            }
+            function.replace_uses(call, value);
+        }
+    }
+
+    report
+}
+
+/// Copy the body of `callee` into `function`, appending its instructions
+/// to `insts` at the location of the call, and return the value it returns.
+fn splice(
+    function: &mut Function,
+    callee: &Function,
+    args: &[ValueId],
+    span: Option<Span>,
+    insts: &mut Vec<Instruction>,
+) -> Option<ValueId> {
+    let mut map: HashMap<ValueId, ValueId> = callee.params.iter().copied().zip(args.iter().copied()).collect();
+
+    let body = &callee.blocks[0];
+    for inst in &body.insts {
+        let result = ValueId(u32::try_from(function.values.len()).expect("too many values"));
+        function.values.push(callee.value_type(inst.result).clone());
+        map.insert(inst.result, result);
+
+        let mut copy = inst.inst.clone();
+        for operand in copy.operands_mut() {
+            *operand = map[operand];
+        }
+        insts.push(Instruction { result, inst: copy, span });
+    }
+
+    match body.terminator {
+        Terminator::Return(Some(value)) => Some(map[&value]),
+        _ => None,
+    }
+}
+
+/// The single block of a function that only returns, if it has one.
+fn straight_line(function: &Function) -> Option<&Block> {
+    match function.blocks.as_slice() {
+        [block] if block.phis.is_empty() && matches!(block.terminator, Terminator::Return(_)) => Some(block),
+        _ => None,
+    }
+}
+
+/// Whether a function reads or writes a field of its first parameter and
+/// does nothing else.
+fn is_accessor(function: &Function) -> bool {
+    let Some(block) = straight_line(function) else {
+        return false;
+    };
+    let Some(&receiver) = function.params.first() else {
+        return false;
+    };
+    match (block.insts.as_slice(), &block.terminator) {
+        ([getter], Terminator::Return(Some(value))) => {
+            matches!(getter.inst, Inst::GetField { object, .. } if object == receiver) && getter.result == *value
+        }
+        ([setter], Terminator::Return(None)) | ([setter, _], Terminator::Return(None)) => {
+            matches!(setter.inst, Inst::SetField { object, .. } if object == receiver)
+                && block.insts[1..].iter().all(|i| matches!(i.inst, Inst::Const(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Whether a function is a single block of at most `max_size`
+/// instructions that does not call itself.
+fn is_small(function: &Function, max_size: usize) -> bool {
+    let Some(block) = straight_line(function) else {
+        return false;
+    };
+    block.insts.len() <= max_size
+        && !block.insts.iter().any(|i| matches!(&i.inst, Inst::Call { callee, .. } if *callee == function.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BlockId, IrType};
+    use oxidex_syntax::ast::expr::BinaryOp;
+
+    fn function(name: &str, values: Vec<IrType>, params: usize, insts: Vec<Inst>, ret: Option<u32>) -> Function {
+        let insts = insts
+            .into_iter()
+            .enumerate()
+            .map(|(i, inst)| Instruction { result: ValueId(u32::try_from(params + i).unwrap()), inst, span: None })
+            .collect();
+        Function {
+            name: name.to_string(),
+            params: (0..params).map(|i| ValueId(u32::try_from(i).unwrap())).collect(),
+            return_type: ret.map_or(IrType::Unit, |v| values[v as usize].clone()),
+            blocks: vec![Block {
+                id: BlockId(0),
+                phis: vec![],
+                insts,
+                terminator: Terminator::Return(ret.map(ValueId)),
+            }],
+            values,
+            cleanups: vec![],
+            variables: vec![],
+        }
+    }
+
+    #[test]
+    fn test_accessors_and_small_functions_are_inlined() {
+        let counter = IrType::Object(Some("Counter".into()));
+        let call = |callee: &str, args: Vec<u32>| Inst::Call {
+            callee: callee.to_string(),
+            args: args.into_iter().map(ValueId).collect(),
+        };
+        let int = |value| Inst::Const(Constant::Int(value));
+        let mul = |lhs, rhs| Inst::Binary { op: BinaryOp::Mul, lhs: ValueId(lhs), rhs: ValueId(rhs) };
+
+        let getter = Inst::GetField { object: ValueId(0), field: "count".into() };
+        let mut module = Module {
+            functions: vec![
+                // fn Counter.count(self) -> Int { self.count }
+                function("Counter.count", vec![counter.clone(), IrType::Int], 1, vec![getter], Some(1)),
+                // fn double(x: Int) -> Int { x * 2 }
+                function("double", vec![IrType::Int; 3], 1, vec![int(2), mul(0, 1)], Some(2)),
+                // Too large for the default limit
+                function("big", vec![IrType::Int; 6], 1, vec![int(1), int(2), int(3), int(4), int(5)], Some(5)),
+                // fn spin(x: Int) -> Int { spin(x) }
+                function("spin", vec![IrType::Int; 2], 1, vec![call("spin", vec![0])], Some(1)),
+                // fn main(c: Counter) -> Int { big(double(c.count())) }
+                function(
+                    "main",
+                    vec![counter, IrType::Object(None), IrType::Object(None), IrType::Int],
+                    1,
+                    vec![call("Counter.count", vec![0]), call("double", vec![1]), call("big", vec![2])],
+                    Some(3),
+                ),
+            ],
+            exports: Vec::new(),
+            externs: Vec::new(),
+        };
+
+        let mut hot = module.clone();
+        let report = inline(&mut module, &InlineConfig::default());
+        let callees: Vec<(&str, &str)> =
+            report.inlined.iter().map(|i| (i.caller.as_str(), i.callee.as_str())).collect();
+        assert_eq!(callees, [("main", "Counter.count"), ("main", "double")]);
+
+        let main = module.function("main").unwrap();
+        let insts: Vec<&Inst> = main.blocks[0].insts.iter().map(|i| &i.inst).collect();
+        let [Inst::GetField { object, .. }, Inst::Const(_), Inst::Binary { lhs, .. }, Inst::Call { callee, args }] =
+            insts.as_slice()
+        else {
+            panic!("unexpected body {insts:?}")
+        };
+        assert_eq!(*object, main.params[0]);
+        assert_eq!(*lhs, main.blocks[0].insts[0].result);
+        assert_eq!(callee, "big");
+        assert_eq!(args[0], main.blocks[0].insts[2].result);
+        assert_eq!(main.value_type(main.blocks[0].insts[0].result), &IrType::Int);
+
+        // A hot function may be larger
+        let config = InlineConfig { hot: ["big".to_string()].into(), ..InlineConfig::default() };
+        assert_eq!(config.limit("big"), 16);
+        assert_eq!(inline(&mut hot, &config).count(), 3);
+    }
+}
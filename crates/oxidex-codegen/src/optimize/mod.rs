@@ -0,0 +1,23 @@
+//! IR optimization passes.
+//!
+//! Each pass rewrites a [`Module`](crate::ir::Module) in place and returns
+//! a report of what it changed, so drivers can log or test its effect.
+//!
+//! - [`devirt`]: message sends to receivers of a known, sealed class become
+//!   direct calls.
+//! - [`inline`]: direct calls to accessors and other trivial functions are
+//!   replaced by the callee's body.
+//! - [`escape`]: objects and boxes that never leave their function are
+//!   replaced by their fields.
+//! - [`gvn`](mod@gvn): instructions recomputing a value available where they
+//!   are reuse it instead.
+
+pub mod devirt;
+pub mod escape;
+pub mod gvn;
+pub mod inline;
+
+pub use devirt::{DevirtConfig, DevirtReport, devirtualize};
+pub use escape::{EscapeReport, sink_allocations};
+pub use gvn::{GvnReport, gvn};
+pub use inline::{InlineConfig, InlineReport, inline};
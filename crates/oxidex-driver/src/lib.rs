@@ -0,0 +1,335 @@
+//! `OxideX` Driver: the whole compiler as a library
+//!
+//! This crate runs every phase of compiling a program, from source text to
+//! bytecode, so that embedders and tests can compile and run `OxideX`
+//! without the `ox` binary:
+//!
+//! 1. Lexing and parsing, in a [`Session`]
+//! 2. Type checking, which reports an error per declaration
+//! 3. Lowering and building the SSA IR
+//! 4. Optimizing the IR, if [`Options::optimize`] is set
+//! 5. Compiling the IR to bytecode
+//!
+//! [`compile_source`] stops after the first phase that finds problems and
+//! returns them as [`Diagnostics`], already rendered against the source.
+//!
+//! # Examples
+//!
+//! ```
+//! use oxidex_bytecode::Value;
+//! use oxidex_driver::{Options, compile_source};
+//!
+//! let source = "fn main(x: Int) -> Int { x * 2 }";
+//! let artifact = compile_source(source, Options::default()).unwrap();
+//! assert_eq!(artifact.call("main", vec![Value::Int(21)]).unwrap(), Value::Int(42));
+//!
+//! let diagnostics = compile_source("fn main() -> Int { missing }", Options::default()).unwrap_err();
+//! assert_eq!(diagnostics.len(), 1);
+//! assert!(diagnostics.to_string().starts_with("main.ox: error: undefined variable"));
+//! ```
+
+#![warn(missing_docs)]
+
+use oxidex_bytecode::{Function, Value, Vm, VmError, VmErrorKind, builtins, compile};
+use oxidex_codegen::ir::{self, build_module};
+use oxidex_codegen::lower;
+use oxidex_codegen::optimize::{DevirtConfig, InlineConfig, devirtualize, gvn, inline, sink_allocations};
+use oxidex_syntax::diagnostic::{Diagnostic, DiagnosticLevel};
+use oxidex_syntax::session::{Session, SessionOptions};
+use oxidex_syntax::{Decl, Span};
+use oxidex_typecheck::QueryCache;
+use oxidex_typecheck::infer::{Context, solve_constraints};
+use std::fmt;
+use std::rc::Rc;
+
+/// How [`compile_source`] compiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// The name of the source, as diagnostics show it
+    pub name: String,
+    /// Optimize the IR before compiling it to bytecode
+    pub optimize: bool,
+    /// Color rendered diagnostics with ANSI escapes
+    pub color: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { name: "main.ox".to_string(), optimize: false, color: false }
+    }
+}
+
+/// A compiled program.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// The program's IR, optimized if [`Options::optimize`] was set
+    pub ir: ir::Module,
+    /// The bytecode: a script defining each function as a global
+    pub script: Function,
+}
+
+impl Artifact {
+    /// Call the function `name` with `args` in a fresh VM with the
+    /// standard builtins (see [`builtins::install`]), after running the
+    /// script to define the program's functions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program declares no function `name`, or the
+    /// call raises one.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, VmError> {
+        let mut vm = Vm::new();
+        builtins::install(&mut vm);
+        vm.run(Rc::new(self.script.clone()))?;
+        let Some(function) = vm.global(name) else {
+            return Err(VmError { kind: VmErrorKind::UndefinedGlobal(name.to_string()), trace: Vec::new() });
+        };
+        vm.call(function, args)
+    }
+}
+
+/// The problems a phase found in a program that did not compile.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// The problems, in the order they were found
+    diagnostics: Vec<Diagnostic>,
+    /// Each rendered with the source it points at
+    rendered: String,
+}
+
+impl Diagnostics {
+    /// Take the diagnostics reported to `session`, rendering them against
+    /// its source.
+    fn take(session: &Session<'_>) -> Self {
+        let diagnostics = session.take_diagnostics();
+        let rendered = diagnostics.iter().map(|diagnostic| session.render(diagnostic)).collect();
+        Self { diagnostics, rendered }
+    }
+
+    /// The diagnostics, in the order they were found.
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// The number of diagnostics.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    /// Returns `true` if there are no diagnostics.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// The number of diagnostics that are errors.
+    #[must_use]
+    pub fn errors(&self) -> usize {
+        self.iter().filter(|diagnostic| diagnostic.level == DiagnosticLevel::Error).count()
+    }
+
+    /// The diagnostics, each rendered with the source it points at, as
+    /// `ox` prints them.
+    #[must_use]
+    pub fn rendered(&self) -> &str {
+        &self.rendered
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.rendered)
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Compile `source`, a whole program in one file, to bytecode.
+///
+/// # Errors
+///
+/// Returns the diagnostics of the first phase that found problems: every
+/// syntax error, or a type error per declaration, or the construct that
+/// could not be lowered or compiled.
+pub fn compile_source(source: &str, options: Options) -> Result<Artifact, Diagnostics> {
+    let mut session = Session::new(SessionOptions { color: options.color, ..SessionOptions::default() });
+    let file = session.add_file(options.name.as_str(), source);
+    // The parser owns the arena the declarations live in
+    let parsed = session.parse(file);
+    if session.error_count() > 0 {
+        return Err(Diagnostics::take(&session));
+    }
+    let (_parser, decls) = parsed.expect("a file that lexed is parsed");
+
+    let whole = session.sources().file(file).span();
+    let mut ctx = Context::with_session(&session);
+    builtins::declare(&mut ctx);
+    check(&session, &mut ctx, &decls, whole);
+    if session.error_count() > 0 {
+        return Err(Diagnostics::take(&session));
+    }
+
+    let fail = |message: String, span: Option<_>| {
+        session.error(message, span.unwrap_or(whole));
+        Diagnostics::take(&session)
+    };
+    let lowered = lower(&mut ctx, &decls).map_err(|err| fail(err.to_string(), err.span()))?;
+    let mut module = build_module(&mut ctx, &lowered, &decls).map_err(|err| fail(err.to_string(), err.span()))?;
+    if options.optimize {
+        devirtualize(&mut module, &lowered, &DevirtConfig::new());
+        inline(&mut module, &InlineConfig::default());
+        sink_allocations(&mut module);
+        gvn(&mut module);
+    }
+    let script = compile(&module).map_err(|err| fail(err.to_string(), None))?;
+    Ok(Artifact { ir: module, script })
+}
+
+/// Type check `decls`, reporting an error per declaration to `session`,
+/// at `whole` if it has no location of its own.
+fn check<'ctx>(session: &Session<'_>, ctx: &mut Context<'ctx>, decls: &[Decl<'ctx>], whole: Span) {
+    let mut errors = match QueryCache::new().check_program(ctx, decls) {
+        Ok(results) => results.into_iter().filter_map(Result::err).collect(),
+        Err(err) => vec![err],
+    };
+    if errors.is_empty() {
+        // Calls to functions declared later are only checked against their
+        // bounds now, and holes once everything else passed
+        match solve_constraints(ctx, true) {
+            Ok(()) => errors = ctx.hole_diagnostics(),
+            Err(err) => errors.push(err),
+        }
+    }
+    for err in errors {
+        let mut diagnostic = err.diagnostic();
+        if diagnostic.span.start_line == 0 {
+            diagnostic.span = whole;
+        }
+        session.report(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_and_call() {
+        let source = "fn square(x: Int) -> Int { x * x }\nfn main(x: Int) -> Int { square(x) + 1 }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        assert_eq!(artifact.ir.functions.len(), 2);
+        assert_eq!(artifact.call("main", vec![Value::Int(3)]).unwrap(), Value::Int(10));
+
+        let err = artifact.call("missing", Vec::new()).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::UndefinedGlobal(name) if name == "missing"));
+    }
+
+    #[test]
+    fn test_optimize_inlines() {
+        let source = "fn square(x: Int) -> Int { x * x }\nfn main(x: Int) -> Int { square(x) + 1 }";
+        let plain = compile_source(source, Options::default()).unwrap();
+        let optimized = compile_source(source, Options { optimize: true, ..Options::default() }).unwrap();
+        assert!(plain.ir.to_string().contains("call"));
+        assert!(!optimized.ir.to_string().contains("call"));
+        assert_eq!(optimized.call("main", vec![Value::Int(3)]).unwrap(), Value::Int(10));
+    }
+
+    #[test]
+    fn test_std_calls_lower_to_intrinsics() {
+        let source = "fn main(a: Int, b: Int, s: String) -> Int { max(abs(a), b) + len(s) }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        let ir = artifact.ir.to_string();
+        assert!(ir.contains("intrinsic abs(") && ir.contains("intrinsic max(") && ir.contains("intrinsic length("));
+        assert!(!ir.contains("call"));
+        let args = vec![Value::Int(-5), Value::Int(2), Value::string("four")];
+        assert_eq!(artifact.call("main", args).unwrap(), Value::Int(9));
+
+        // A function of the program's own keeps its name
+        let source = "fn abs(x: Int) -> Int { x }\nfn main(a: Int) -> Int { abs(a) }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        assert!(artifact.ir.to_string().contains("call \"abs\""));
+        assert_eq!(artifact.call("main", vec![Value::Int(-5)]).unwrap(), Value::Int(-5));
+    }
+
+    #[test]
+    fn test_closures_share_boxed_variables() {
+        let source = "fn main(n: Int) -> Int {\n\
+                      let offset = 10;\n\
+                      mut count = 0;\n\
+                      let add = fn(x: Int) -> Int { count = count + 1; x + offset };\n\
+                      let read = fn() -> Int { count };\n\
+                      let b = add(add(n));\n\
+                      count = count * 100;\n\
+                      b + read()\n\
+                      }";
+        for optimize in [false, true] {
+            let artifact = compile_source(source, Options { optimize, ..Options::default() }).unwrap();
+            let ir = artifact.ir.to_string();
+            assert!(ir.contains("closure \"main$closure0\"") && ir.contains("box "), "{ir}");
+            // Two calls add 20 and count to 2, which `main` then scales
+            assert_eq!(artifact.call("main", vec![Value::Int(1)]).unwrap(), Value::Int(221));
+        }
+    }
+
+    #[test]
+    fn test_programs_call_standard_builtins() {
+        let source = "fn main(n: Int) -> Int { assert(n > 0); print(\"checked\"); n + len(\"ab\") }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        assert_eq!(artifact.call("main", vec![Value::Int(1)]).unwrap(), Value::Int(3));
+        let err = artifact.call("main", vec![Value::Int(0)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::AssertionFailed));
+
+        // A function of the program's own shadows the builtin
+        let source = "fn print(x: Int) -> Int { x }\nfn main() -> Int { print(7) }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        assert_eq!(artifact.call("main", Vec::new()).unwrap(), Value::Int(7));
+    }
+
+    #[test]
+    fn test_string_literals_reach_bytecode_decoded() {
+        let source = "fn greet() -> String { \"say \\\"hi\\\"\\n\" }\nfn main() -> Int { len(\"h\\u{e9}\") }";
+        let artifact = compile_source(source, Options::default()).unwrap();
+        assert_eq!(artifact.call("greet", Vec::new()).unwrap(), Value::string("say \"hi\"\n"));
+        assert_eq!(artifact.call("main", Vec::new()).unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_output_is_reproducible() {
+        let source = "fn greet(name: String) -> String { \"hello, \\(name)\" }\n\
+                      fn twice(x: Int) -> Int { x + x }\n\
+                      fn main() -> String { \"\\(twice(3)) \\(greet(\"ox\"))\" }";
+        for optimize in [false, true] {
+            let options = Options { optimize, ..Options::default() };
+            let first = compile_source(source, options.clone()).unwrap();
+            let bytes = oxidex_bytecode::oxb::save(&first.script);
+            for _ in 0..8 {
+                let again = compile_source(source, options.clone()).unwrap();
+                assert_eq!(again.ir.to_string(), first.ir.to_string());
+                assert_eq!(oxidex_bytecode::oxb::save(&again.script), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_phases_report_every_error() {
+        let options = Options { name: "broken.ox".to_string(), ..Options::default() };
+        let syntax = compile_source("fn a( {}\nfn b() {}\nfn c( {}", options.clone()).unwrap_err();
+        assert_eq!((syntax.len(), syntax.errors()), (2, 2));
+        assert!(syntax.rendered().starts_with("broken.ox:1:"), "{syntax}");
+
+        let types = compile_source("fn a() -> Int { missing }\nfn b() -> Int { gone }", options).unwrap_err();
+        let messages: Vec<&str> = types.iter().map(|diagnostic| diagnostic.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("missing") && messages[1].contains("gone"), "{messages:?}");
+    }
+}
@@ -0,0 +1,288 @@
+//! Built-in functions.
+//!
+//! Builtins are Rust closures registered under an `OxideX` name together
+//! with the type signature the type checker gives them. The interpreter
+//! binds every builtin as a global, so programs can call them like any
+//! other function; a function declared by the program shadows a builtin of
+//! the same name.
+//!
+//! A builtin that reaches outside the interpreter, such as one reading
+//! files, is registered with the [`Capability`] it needs, so that a
+//! [`Sandbox`] denying it can drop it from the registry. Builtins run with
+//! the interpreter attached (see [`Interpreter::attach`]), so messages they
+//! send to the program's objects run the methods' bodies.
+//!
+//! [`Interpreter::attach`]: crate::Interpreter::attach
+
+use crate::error::{Result, RuntimeError};
+use crate::sandbox::{Capability, Sandbox};
+use crate::value::Value;
+use oxidex_syntax::Span;
+use oxidex_typecheck::InferContext as Context;
+use oxidex_typecheck::{Scheme, Ty, builtins};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the builtin that catches errors, which the interpreter
+/// evaluates itself.
+pub const CATCH: &str = "catch";
+
+/// Name of the builtin that prints a value, which the interpreter hands
+/// the value's description (see [`Interpreter::description`]).
+///
+/// [`Interpreter::description`]: crate::Interpreter::description
+pub const PRINT: &str = "print";
+
+/// Implementation of a builtin: receives the arguments and the call site.
+pub type BuiltinFn = Rc<dyn Fn(&[Value], Span) -> Result<Value>>;
+
+/// A built-in function.
+#[derive(Clone)]
+pub struct Builtin {
+    /// Type signature; quantified variables make the builtin generic
+    pub signature: Scheme,
+    /// Implementation
+    pub function: BuiltinFn,
+    /// Capability the builtin needs, if it reaches outside the interpreter
+    pub capability: Option<Capability>,
+}
+
+impl Builtin {
+    /// Number of arguments the builtin takes.
+    #[must_use]
+    pub fn arity(&self) -> usize {
+        match &self.signature.ty {
+            Ty::Function { params, .. } => params.len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Registry of built-in functions by name.
+#[derive(Clone, Default)]
+pub struct Builtins {
+    functions: HashMap<String, Builtin>,
+}
+
+impl Builtins {
+    /// Create an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the standard builtins:
+    ///
+    /// - `print(value)` writes a value's description and a newline to
+    ///   standard output
+    /// - `len(collection)` counts the elements of an array, dictionary or
+    ///   range, or the characters of a string
+    /// - `assert(condition)` fails unless the condition holds
+    /// - `clock()` returns the seconds elapsed since the Unix epoch
+    /// - `throw(value)` raises an error carrying the value
+    /// - `unwrap(optional)` returns the optional's value, failing if it is
+    ///   `nil`
+    /// - `range(start, end)` makes the range of integers from `start` up to
+    ///   `end`; it is typed as an array of integers, so loops over it and
+    ///   indexing type check
+    /// - `catch(body)` calls a function without arguments, returning
+    ///   `Result::Ok` with its value, or `Result::Err` with the error it
+    ///   raised (see [`RuntimeError::to_value`])
+    /// - `read_file(path)` returns the text of a file, and
+    ///   `write_file(path, text)` replaces a file's text; both need
+    ///   [`Capability::FileIo`], and throw the error of a failed access
+    ///
+    /// `catch` calls back into the program, so the interpreter evaluates it
+    /// itself; the registered implementation only fails.
+    ///
+    /// The signatures are the ones every backend gives the builtins (see
+    /// [`builtins::signature`]).
+    #[must_use]
+    pub fn standard() -> Self {
+        let mut builtins = Self::new();
+        builtins.register(PRINT, standard(PRINT), |args, _| {
+            println!("{}", args[0]);
+            Ok(Value::Unit)
+        });
+        builtins.register("len", standard("len"), |args, span| {
+            let len = match &args[0] {
+                Value::Array(elements) => elements.borrow().len(),
+                Value::Dict(entries) => entries.borrow().len(),
+                Value::String(text) => text.chars().count(),
+                Value::Range(start, end) => usize::try_from(end.saturating_sub(*start)).unwrap_or(0),
+                other => {
+                    let found = other.kind();
+                    return Err(RuntimeError::TypeMismatch { expected: "a collection", found, span });
+                }
+            };
+            Ok(Value::Int(i64::try_from(len).map_err(|_| RuntimeError::IntegerOverflow { span })?))
+        });
+        builtins.register("assert", standard("assert"), |args, span| match args[0] {
+            Value::Bool(true) => Ok(Value::Unit),
+            Value::Bool(false) => Err(RuntimeError::AssertionFailed { span }),
+            ref other => Err(RuntimeError::TypeMismatch { expected: "a boolean", found: other.kind(), span }),
+        });
+        builtins.register("clock", standard("clock"), |_, _| {
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(Value::Float(elapsed.as_secs_f64()))
+        });
+        builtins.register("throw", standard("throw"), |args, span| {
+            Err(RuntimeError::Thrown { value: args[0].clone(), span })
+        });
+        builtins.register("unwrap", standard("unwrap"), |args, span| match &args[0] {
+            Value::Nil => Err(RuntimeError::NilUnwrap { span }),
+            value => Ok(value.clone()),
+        });
+        builtins.register("range", standard("range"), |args, span| match (&args[0], &args[1]) {
+            (Value::Int(start), Value::Int(end)) => Ok(Value::Range(*start, *end)),
+            (Value::Int(_), other) | (other, _) => {
+                Err(RuntimeError::TypeMismatch { expected: "an integer", found: other.kind(), span })
+            }
+        });
+        builtins.register(CATCH, standard(CATCH), |_, span| {
+            Err(RuntimeError::Unsupported { construct: "`catch` outside the interpreter", span })
+        });
+        builtins.register_requiring("read_file", Capability::FileIo, standard("read_file"), |args, span| {
+            let text = std::fs::read_to_string(path(&args[0], span)?).map_err(|err| io_error(&err, span))?;
+            Ok(Value::String(text))
+        });
+        builtins.register_requiring("write_file", Capability::FileIo, standard("write_file"), |args, span| {
+            let Value::String(text) = &args[1] else {
+                return Err(RuntimeError::TypeMismatch { expected: "a string", found: args[1].kind(), span });
+            };
+            std::fs::write(path(&args[0], span)?, text).map_err(|err| io_error(&err, span))?;
+            Ok(Value::Unit)
+        });
+        builtins
+    }
+
+    /// Register a builtin, replacing any builtin of the same name.
+    ///
+    /// `signature` should be a function type; the interpreter only calls the
+    /// builtin with as many arguments as it has parameters.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        signature: Scheme,
+        function: impl Fn(&[Value], Span) -> Result<Value> + 'static,
+    ) {
+        self.functions.insert(name.into(), Builtin { signature, function: Rc::new(function), capability: None });
+    }
+
+    /// Register a builtin that needs `capability`, replacing any builtin of
+    /// the same name. Sandboxes denying the capability drop it.
+    pub fn register_requiring(
+        &mut self,
+        name: impl Into<String>,
+        capability: Capability,
+        signature: Scheme,
+        function: impl Fn(&[Value], Span) -> Result<Value> + 'static,
+    ) {
+        let builtin = Builtin { signature, function: Rc::new(function), capability: Some(capability) };
+        self.functions.insert(name.into(), builtin);
+    }
+
+    /// Drop the builtins needing a capability `sandbox` denies.
+    pub fn restrict(&mut self, sandbox: &Sandbox) {
+        self.functions.retain(|_, builtin| builtin.capability.is_none_or(|capability| sandbox.allows(capability)));
+    }
+
+    /// Look up a builtin.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.functions.get(name)
+    }
+
+    /// Iterate over the builtins and their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Builtin)> {
+        self.functions.iter().map(|(name, builtin)| (name.as_str(), builtin))
+    }
+
+    /// Bind the signature of every builtin the program mentions in the type
+    /// checker's environment, so calls to them type check.
+    pub fn declare(&self, ctx: &mut Context<'_>) {
+        for (name, builtin) in &self.functions {
+            if let Some(sym) = ctx.interner.get_symbol(name) {
+                ctx.env.bind(sym, builtin.signature.clone());
+            }
+        }
+    }
+}
+
+/// The signature every backend gives the standard builtin `name`.
+fn standard(name: &str) -> Scheme {
+    builtins::signature(name).expect("a standard builtin")
+}
+
+/// The path a file builtin was passed.
+fn path(value: &Value, span: Span) -> Result<&str> {
+    match value {
+        Value::String(path) => Ok(path),
+        other => Err(RuntimeError::TypeMismatch { expected: "a path string", found: other.kind(), span }),
+    }
+}
+
+/// A failed file access, thrown so that programs can catch it.
+fn io_error(err: &std::io::Error, span: Span) -> RuntimeError {
+    RuntimeError::Thrown { value: Value::string(err.to_string()), span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_builtins() {
+        let builtins = Builtins::standard();
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let call = |name: &str, args: &[Value]| (builtins.get(name).unwrap().function)(args, span);
+
+        let array = Value::array(vec![Value::Int(1), Value::Int(2)]);
+        assert_eq!(call("len", &[array]).unwrap(), Value::Int(2));
+        assert_eq!(call("len", &[Value::string("héllo")]).unwrap(), Value::Int(5));
+        assert!(matches!(call("len", &[Value::Int(1)]), Err(RuntimeError::TypeMismatch { .. })));
+        assert_eq!(call("range", &[Value::Int(1), Value::Int(4)]).unwrap(), Value::Range(1, 4));
+        assert_eq!(call("len", &[Value::Range(1, 4)]).unwrap(), Value::Int(3));
+        assert_eq!(call("len", &[Value::Range(4, 1)]).unwrap(), Value::Int(0));
+
+        assert_eq!(call("assert", &[Value::Bool(true)]).unwrap(), Value::Unit);
+        assert!(matches!(call("assert", &[Value::Bool(false)]), Err(RuntimeError::AssertionFailed { .. })));
+        assert!(matches!(call("clock", &[]).unwrap(), Value::Float(seconds) if seconds > 0.0));
+        assert!(matches!(call("throw", &[Value::Int(1)]), Err(RuntimeError::Thrown { value: Value::Int(1), .. })));
+        assert_eq!(call("unwrap", &[Value::Int(1)]).unwrap(), Value::Int(1));
+        assert!(matches!(call("unwrap", &[Value::Nil]), Err(RuntimeError::NilUnwrap { .. })));
+
+        let file = std::env::temp_dir().join(format!("oxidex-builtins-{}.txt", std::process::id()));
+        let path = Value::string(file.to_string_lossy());
+        assert_eq!(call("write_file", &[path.clone(), Value::string("saved")]).unwrap(), Value::Unit);
+        assert_eq!(call("read_file", &[path]).unwrap(), Value::string("saved"));
+        std::fs::remove_file(&file).unwrap();
+        let missing = Value::string(file.to_string_lossy());
+        assert!(matches!(call("read_file", &[missing]), Err(RuntimeError::Thrown { value: Value::String(_), .. })));
+        assert_eq!(builtins.get("read_file").unwrap().capability, Some(Capability::FileIo));
+
+        assert_eq!(builtins.get("print").unwrap().arity(), 1);
+        assert_eq!(builtins.get("clock").unwrap().arity(), 0);
+        assert_eq!(builtins.get("len").unwrap().signature.vars, [0]);
+    }
+
+    #[test]
+    fn test_restrict_drops_denied_builtins() {
+        let mut builtins = Builtins::standard();
+        let signature = builtins.get("clock").unwrap().signature.clone();
+        builtins.register_requiring("load", Capability::DynamicLoading, signature, |_, _| Ok(Value::Unit));
+        assert_eq!(builtins.get("load").unwrap().capability, Some(Capability::DynamicLoading));
+
+        // The standard file builtins need file I/O
+        let count = builtins.iter().count();
+        builtins.restrict(&Sandbox { deny_ffi: true, ..Sandbox::default() });
+        assert_eq!(builtins.iter().count(), count);
+        builtins.restrict(&Sandbox { deny_file_io: true, ..Sandbox::default() });
+        assert!(builtins.get("read_file").is_none() && builtins.get("write_file").is_none());
+        assert_eq!(builtins.iter().count(), count - 2);
+        builtins.restrict(&Sandbox::strict());
+        assert!(builtins.get("load").is_none());
+        assert_eq!(builtins.iter().count(), count - 3);
+    }
+}
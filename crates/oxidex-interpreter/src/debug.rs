@@ -0,0 +1,254 @@
+//! Debugger hooks.
+//!
+//! A [`Debugger`] attached to the interpreter is consulted before each
+//! statement, and before the trailing expression of each block, whenever
+//! execution reaches a new source line. It decides whether to stop there,
+//! and when execution stops it is handed a [`Pause`] to inspect the call
+//! stack and the locals of each frame before choosing how to resume.
+//!
+//! Breakpoints are owned by the debugger, so a front end can add and remove
+//! them while execution is paused; [`Breakpoints`] is a ready-made set.
+
+use crate::env::Environment;
+use crate::error::StackFrame;
+use crate::value::Value;
+use oxidex_mem::StringInterner;
+use oxidex_syntax::Span;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// A source line to stop at.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Breakpoint {
+    /// Name of the source file
+    pub file: String,
+    /// Line number (1-indexed)
+    pub line: usize,
+}
+
+impl Breakpoint {
+    /// Create a breakpoint.
+    #[must_use]
+    pub fn new(file: impl Into<String>, line: usize) -> Self {
+        Self { file: file.into(), line }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    /// Parse a breakpoint written `file:line`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (file, line) = s.rsplit_once(':').ok_or_else(|| format!("expected `file:line`, found `{s}`"))?;
+        match line.parse() {
+            Ok(line) if line > 0 && !file.is_empty() => Ok(Self::new(file, line)),
+            _ => Err(format!("expected `file:line`, found `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// A set of breakpoints.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    lines: HashSet<Breakpoint>,
+}
+
+impl Breakpoints {
+    /// Create an empty set.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a breakpoint. Returns `false` if it was already set.
+    pub fn insert(&mut self, breakpoint: Breakpoint) -> bool {
+        self.lines.insert(breakpoint)
+    }
+
+    /// Remove a breakpoint. Returns `false` if it was not set.
+    pub fn remove(&mut self, breakpoint: &Breakpoint) -> bool {
+        self.lines.remove(breakpoint)
+    }
+
+    /// Whether a breakpoint is set at a line.
+    #[must_use]
+    pub fn contains(&self, file: &str, line: usize) -> bool {
+        // Avoid allocating a key on every statement
+        self.lines.iter().any(|breakpoint| breakpoint.line == line && breakpoint.file == file)
+    }
+
+    /// Iterate over the breakpoints.
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.lines.iter()
+    }
+}
+
+/// How to resume after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Run until the next breakpoint
+    Continue,
+    /// Stop at the next line, entering calls
+    Step,
+    /// Stop at the next line of the current function or its callers,
+    /// running calls to completion
+    Next,
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// A breakpoint is set at the line
+    Breakpoint,
+    /// The previous command was [`Command::Step`] or [`Command::Next`]
+    Step,
+}
+
+/// The interpreter's state while paused.
+pub struct Pause<'p> {
+    /// Why execution stopped
+    pub reason: PauseReason,
+    /// Name of the source file
+    pub file: &'p str,
+    /// Location of the code about to run
+    pub span: Span,
+    pub(crate) stack: Vec<StackFrame>,
+    pub(crate) env: &'p Environment,
+    pub(crate) interner: &'p StringInterner,
+}
+
+impl Pause<'_> {
+    /// Line about to run (1-indexed).
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.span.start_line
+    }
+
+    /// Get the active calls, innermost first. Top-level code has no frame.
+    #[must_use]
+    pub fn stack(&self) -> &[StackFrame] {
+        &self.stack
+    }
+
+    /// Get the locals of a frame, sorted by name; frame 0 is the innermost,
+    /// and the frame after the last call is top-level code.
+    ///
+    /// Shadowed bindings are left out. Returns an empty list if there is no
+    /// such frame.
+    #[must_use]
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let mut locals: Vec<(String, Value)> = self
+            .env
+            .locals(frame)
+            .into_iter()
+            .filter_map(|(sym, binding)| Some((self.interner.resolve(sym)?.to_string(), binding.get())))
+            .collect();
+        locals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        locals
+    }
+}
+
+/// Decides where execution stops and how it resumes.
+pub trait Debugger {
+    /// Whether to stop at a line that is about to run.
+    fn breakpoint(&mut self, file: &str, line: usize) -> bool;
+
+    /// Inspect the paused interpreter and choose how to resume.
+    fn pause(&mut self, pause: &Pause<'_>) -> Command;
+}
+
+impl Debugger for Breakpoints {
+    fn breakpoint(&mut self, file: &str, line: usize) -> bool {
+        self.contains(file, line)
+    }
+
+    /// Always continue; useful when only breakpoint hits are wanted.
+    fn pause(&mut self, _pause: &Pause<'_>) -> Command {
+        Command::Continue
+    }
+}
+
+/// A debugger and the stepping state of the interpreter it is attached to.
+pub(crate) struct Session {
+    debugger: Box<dyn Debugger>,
+    /// How far execution runs before stopping without a breakpoint
+    mode: Mode,
+    /// Frame depth and line of the last hook, so a line with several
+    /// statements stops once
+    last: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Run,
+    Step,
+    /// Stop at a depth at most this
+    Next(usize),
+}
+
+impl Session {
+    pub(crate) fn new(debugger: Box<dyn Debugger>) -> Self {
+        Self { debugger, mode: Mode::Run, last: None }
+    }
+
+    pub(crate) fn into_debugger(self) -> Box<dyn Debugger> {
+        self.debugger
+    }
+
+    /// Decide whether to stop at a line about to run in a frame `depth`
+    /// calls deep.
+    pub(crate) fn reached(&mut self, file: &str, depth: usize, line: usize) -> Option<PauseReason> {
+        if line == 0 || self.last == Some((depth, line)) {
+            return None;
+        }
+        self.last = Some((depth, line));
+        if self.debugger.breakpoint(file, line) {
+            return Some(PauseReason::Breakpoint);
+        }
+        match self.mode {
+            Mode::Step => Some(PauseReason::Step),
+            Mode::Next(max) if depth <= max => Some(PauseReason::Step),
+            _ => None,
+        }
+    }
+
+    /// Hand a pause to the debugger and resume as it commands, from a frame
+    /// `depth` calls deep.
+    pub(crate) fn pause(&mut self, pause: &Pause<'_>, depth: usize) {
+        self.mode = match self.debugger.pause(pause) {
+            Command::Continue => Mode::Run,
+            Command::Step => Mode::Step,
+            Command::Next => Mode::Next(depth),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakpoints_parse_and_match() {
+        let breakpoint: Breakpoint = "src/main.ox:12".parse().unwrap();
+        assert_eq!(breakpoint, Breakpoint::new("src/main.ox", 12));
+        assert_eq!(breakpoint.to_string(), "src/main.ox:12");
+        assert!("main.ox".parse::<Breakpoint>().is_err());
+        assert!("main.ox:0".parse::<Breakpoint>().is_err());
+        assert!(":3".parse::<Breakpoint>().is_err());
+
+        let mut breakpoints = Breakpoints::new();
+        assert!(breakpoints.insert(breakpoint.clone()));
+        assert!(!breakpoints.insert(breakpoint.clone()));
+        assert!(breakpoints.contains("src/main.ox", 12));
+        assert!(!breakpoints.contains("src/main.ox", 13));
+        assert!(breakpoints.remove(&breakpoint));
+        assert!(!breakpoints.contains("src/main.ox", 12));
+    }
+}
@@ -0,0 +1,403 @@
+//! Lexical environments.
+//!
+//! Bindings live in scopes: blocks, loop iterations and match arms each push
+//! one, and a name resolves to its innermost binding. Every call runs in a
+//! fresh frame whose scopes are not visible to the callee's callees, so
+//! functions only see their own locals and the globals.
+//!
+//! Each module has its own globals; code sees those of the module it was
+//! declared in. Builtins live in a prelude every module sees.
+//!
+//! Local scopes keep their bindings in definition order, so a reference
+//! resolved ahead of time (see [`crate::resolve`]) finds its binding by
+//! position.
+//!
+//! A variable a closure captures by box is shared: its value moves into a
+//! box the closure holds too, so an assignment on either side is seen by
+//! the other.
+
+use crate::resolve::Slot;
+use crate::value::Value;
+use oxidex_mem::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A variable.
+#[derive(Debug, Clone)]
+pub struct Binding {
+    /// Current value, unless the variable is shared
+    pub value: Value,
+    /// Whether the variable can be assigned to
+    pub mutable: bool,
+    /// Box holding the value instead, once a closure captured the variable
+    /// by box; the closure holds the same box
+    pub shared: Option<Rc<RefCell<Value>>>,
+}
+
+impl Binding {
+    fn new(value: Value, mutable: bool) -> Self {
+        Self { value, mutable, shared: None }
+    }
+
+    /// Get the current value.
+    #[must_use]
+    pub fn get(&self) -> Value {
+        match &self.shared {
+            Some(cell) => cell.borrow().clone(),
+            None => self.value.clone(),
+        }
+    }
+
+    fn set(&mut self, value: Value) {
+        match &self.shared {
+            Some(cell) => *cell.borrow_mut() = value,
+            None => self.value = value,
+        }
+    }
+}
+
+/// Why an assignment failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignError {
+    /// No binding has the name
+    Undefined,
+    /// The binding is not mutable
+    Immutable,
+}
+
+type Globals = HashMap<Symbol, Binding>;
+
+/// Bindings of a local scope, in definition order.
+type Scope = Vec<(Symbol, Binding)>;
+
+fn find(scope: &Scope, name: Symbol) -> Option<&Binding> {
+    scope.iter().find(|(bound, _)| *bound == name).map(|(_, binding)| binding)
+}
+
+fn find_mut(scope: &mut Scope, name: Symbol) -> Option<&mut Binding> {
+    scope.iter_mut().find(|(bound, _)| *bound == name).map(|(_, binding)| binding)
+}
+
+/// Variables visible to the code being evaluated.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    /// Bindings visible in every module
+    prelude: Globals,
+    /// Functions, constants and statics of each module
+    globals: Vec<Globals>,
+    /// Module whose globals are visible
+    module: usize,
+    /// Scopes of each active call, innermost last; the first frame holds
+    /// top-level code
+    frames: Vec<Vec<Scope>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment {
+    /// Create an environment with no bindings.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { prelude: Globals::new(), globals: vec![Globals::new()], module: 0, frames: vec![vec![Scope::new()]] }
+    }
+
+    /// Add a module with no globals, returning its index. The environment
+    /// starts out with module 0.
+    pub fn add_module(&mut self) -> usize {
+        self.globals.push(Globals::new());
+        self.globals.len() - 1
+    }
+
+    /// Index of the module whose globals are visible.
+    #[must_use]
+    pub fn module(&self) -> usize {
+        self.module
+    }
+
+    /// Make the globals of a module visible instead of the current one's,
+    /// returning the index of the previous module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such module.
+    pub fn enter_module(&mut self, module: usize) -> usize {
+        assert!(module < self.globals.len(), "entered an unknown module");
+        std::mem::replace(&mut self.module, module)
+    }
+
+    /// Enter a nested scope of the current frame.
+    pub fn push_scope(&mut self) {
+        self.frame_mut().push(Scope::new());
+    }
+
+    /// Leave the innermost scope, dropping its bindings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current frame has no nested scope to leave.
+    pub fn pop_scope(&mut self) {
+        let frame = self.frame_mut();
+        assert!(frame.len() > 1, "popped the outermost scope of a frame");
+        frame.pop();
+    }
+
+    /// Enter the frame of a call.
+    pub fn push_frame(&mut self) {
+        self.frames.push(vec![Scope::new()]);
+    }
+
+    /// Leave the frame of a call, dropping all of its scopes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if only the top-level frame is left.
+    pub fn pop_frame(&mut self) {
+        assert!(self.frames.len() > 1, "popped the top-level frame");
+        self.frames.pop();
+    }
+
+    /// Number of active frames, including the top-level one.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Bind a name in the innermost scope, shadowing earlier bindings. A
+    /// binding of the same name in that scope is replaced in place.
+    pub fn define(&mut self, name: Symbol, value: Value, mutable: bool) {
+        let scope = self.frame_mut().last_mut().expect("frames have a scope");
+        match find_mut(scope, name) {
+            Some(binding) => *binding = Binding::new(value, mutable),
+            None => scope.push((name, Binding::new(value, mutable))),
+        }
+    }
+
+    /// Bind a name in the innermost scope to a box shared with others, as
+    /// [`Environment::define`] binds it to a value.
+    pub fn define_shared(&mut self, name: Symbol, cell: Rc<RefCell<Value>>, mutable: bool) {
+        self.define(name, Value::Unit, mutable);
+        let scope = self.frame_mut().last_mut().expect("frames have a scope");
+        let binding = find_mut(scope, name).expect("the name was just bound");
+        binding.shared = Some(cell);
+    }
+
+    /// Bind a global name in the current module.
+    pub fn define_global(&mut self, name: Symbol, value: Value, mutable: bool) {
+        self.globals[self.module].insert(name, Binding::new(value, mutable));
+    }
+
+    /// Bind a name in every module, beneath their globals.
+    pub fn define_prelude(&mut self, name: Symbol, value: Value) {
+        self.prelude.insert(name, Binding::new(value, false));
+    }
+
+    /// Look a global name up in a module.
+    #[must_use]
+    pub fn global(&self, module: usize, name: Symbol) -> Option<&Binding> {
+        self.globals.get(module)?.get(&name)
+    }
+
+    /// Look a name up, innermost scope first, then globals and the prelude.
+    #[must_use]
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        self.binding(name).map(Binding::get)
+    }
+
+    /// Look a binding up, innermost scope first, then globals and the
+    /// prelude.
+    #[must_use]
+    pub fn binding(&self, name: Symbol) -> Option<&Binding> {
+        self.frame()
+            .iter()
+            .rev()
+            .find_map(|scope| find(scope, name))
+            .or_else(|| self.globals[self.module].get(&name))
+            .or_else(|| self.prelude.get(&name))
+    }
+
+    /// Look a binding up at the slot a reference was resolved to, or by
+    /// name if the reference was not resolved or another name is there.
+    #[must_use]
+    pub fn binding_at(&self, name: Symbol, slot: Option<Slot>) -> Option<&Binding> {
+        slot.and_then(|slot| self.local(name, slot)).or_else(|| self.binding(name))
+    }
+
+    /// Assign to the innermost binding of a name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is unbound or the binding is immutable.
+    pub fn assign(&mut self, name: Symbol, value: Value) -> Result<(), AssignError> {
+        self.assign_at(name, None, value)
+    }
+
+    /// Assign to a binding found as [`Environment::binding_at`] finds it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is unbound or the binding is immutable.
+    pub fn assign_at(&mut self, name: Symbol, slot: Option<Slot>, value: Value) -> Result<(), AssignError> {
+        let binding = match self.local_mut(name, slot) {
+            Some(binding) => binding,
+            None => match self.globals[self.module].get_mut(&name) {
+                Some(binding) => binding,
+                // Prelude bindings are never mutable
+                None if self.prelude.contains_key(&name) => return Err(AssignError::Immutable),
+                None => return Err(AssignError::Undefined),
+            },
+        };
+        if !binding.mutable {
+            return Err(AssignError::Immutable);
+        }
+        binding.set(value);
+        Ok(())
+    }
+
+    /// Share a local variable of the current frame, found as
+    /// [`Environment::binding_at`] finds it, returning its box. Returns
+    /// `None` if no local has the name.
+    pub fn share(&mut self, name: Symbol, slot: Option<Slot>) -> Option<Rc<RefCell<Value>>> {
+        let binding = self.local_mut(name, slot)?;
+        let value = std::mem::replace(&mut binding.value, Value::Unit);
+        Some(Rc::clone(binding.shared.get_or_insert_with(|| Rc::new(RefCell::new(value)))))
+    }
+
+    /// Get the visible bindings of a frame, innermost frame first, leaving
+    /// out shadowed ones. Returns nothing if there is no such frame.
+    #[must_use]
+    pub fn locals(&self, frame: usize) -> Vec<(Symbol, &Binding)> {
+        let Some(scopes) = self.frames.len().checked_sub(frame + 1).map(|index| &self.frames[index]) else {
+            return Vec::new();
+        };
+        let mut visible: HashMap<Symbol, &Binding> = HashMap::new();
+        for scope in scopes {
+            visible.extend(scope.iter().map(|(name, binding)| (*name, binding)));
+        }
+        visible.into_iter().collect()
+    }
+
+    /// Iterate over the values of every binding, shadowed or not, in any
+    /// frame or module.
+    pub fn values(&self) -> impl Iterator<Item = Value> {
+        let globals = std::iter::once(&self.prelude).chain(&self.globals).flat_map(Globals::values);
+        let locals = self.frames.iter().flatten().flatten().map(|(_, binding)| binding);
+        globals.chain(locals).map(Binding::get)
+    }
+
+    /// Get the local binding at a slot of the current frame, if it has
+    /// the name.
+    fn local(&self, name: Symbol, slot: Slot) -> Option<&Binding> {
+        let frame = self.frame();
+        let (bound, binding) = frame.get(frame.len().checked_sub(slot.depth + 1)?)?.get(slot.index)?;
+        (*bound == name).then_some(binding)
+    }
+
+    /// Get the local binding of a name in the current frame, at its slot
+    /// if it is there.
+    fn local_mut(&mut self, name: Symbol, slot: Option<Slot>) -> Option<&mut Binding> {
+        let frame = self.frames.last_mut().expect("the top-level frame is never popped");
+        let position = slot.and_then(|slot| {
+            let scope = frame.len().checked_sub(slot.depth + 1)?;
+            matches!(frame[scope].get(slot.index), Some((bound, _)) if *bound == name).then_some((scope, slot.index))
+        });
+        match position {
+            Some((scope, index)) => Some(&mut frame[scope][index].1),
+            None => frame.iter_mut().rev().find_map(|scope| find_mut(scope, name)),
+        }
+    }
+
+    fn frame(&self) -> &[Scope] {
+        self.frames.last().expect("the top-level frame is never popped")
+    }
+
+    fn frame_mut(&mut self) -> &mut Vec<Scope> {
+        self.frames.last_mut().expect("the top-level frame is never popped")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_and_frames() {
+        let (x, y) = (Symbol::new(0), Symbol::new(1));
+        let mut env = Environment::new();
+        env.define_global(x, Value::Int(1), false);
+        env.define(y, Value::Int(2), true);
+
+        // Inner scopes shadow and see outer bindings
+        env.push_scope();
+        env.define(x, Value::Int(10), true);
+        assert_eq!(env.get(x), Some(Value::Int(10)));
+        assert_eq!(env.assign(y, Value::Int(3)), Ok(()));
+        env.pop_scope();
+        assert_eq!(env.get(x), Some(Value::Int(1)));
+        assert_eq!(env.get(y), Some(Value::Int(3)));
+        assert_eq!(env.assign(x, Value::Int(4)), Err(AssignError::Immutable));
+
+        // A call sees globals but not its caller's locals
+        env.push_frame();
+        assert_eq!(env.get(x), Some(Value::Int(1)));
+        assert_eq!(env.get(y), None);
+        assert_eq!(env.assign(y, Value::Int(5)), Err(AssignError::Undefined));
+        env.define(y, Value::Int(6), false);
+        assert_eq!(env.locals(0).len(), 1);
+        assert_eq!(env.locals(1).len(), 1);
+        assert!(env.locals(2).is_empty());
+        assert_eq!(env.values().count(), 3);
+        env.pop_frame();
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    fn test_resolved_slots_are_checked_against_names() {
+        let (x, y) = (Symbol::new(0), Symbol::new(1));
+        let mut env = Environment::new();
+        env.define(x, Value::Int(1), true);
+        env.push_scope();
+        env.define(y, Value::Int(2), true);
+        // Redefining keeps the binding's position
+        env.define(y, Value::Int(3), true);
+
+        let (outer, inner) = (Slot { depth: 1, index: 0 }, Slot { depth: 0, index: 0 });
+        assert_eq!(env.binding_at(x, Some(outer)).map(Binding::get), Some(Value::Int(1)));
+        assert_eq!(env.binding_at(y, Some(inner)).map(Binding::get), Some(Value::Int(3)));
+        assert_eq!(env.assign_at(x, Some(outer), Value::Int(4)), Ok(()));
+        assert_eq!(env.get(x), Some(Value::Int(4)));
+
+        // A slot holding another name falls back to lookup by name
+        assert_eq!(env.binding_at(x, Some(inner)).map(Binding::get), Some(Value::Int(4)));
+        assert_eq!(env.assign_at(y, Some(outer), Value::Int(5)), Ok(()));
+        assert_eq!(env.get(y), Some(Value::Int(5)));
+        assert!(env.binding_at(y, Some(Slot { depth: 7, index: 0 })).is_some());
+        env.pop_scope();
+        assert_eq!(env.depth(), 1);
+    }
+
+    #[test]
+    fn test_modules_have_their_own_globals() {
+        let (x, y, print) = (Symbol::new(0), Symbol::new(1), Symbol::new(2));
+        let mut env = Environment::new();
+        env.define_prelude(print, Value::Function("print".into()));
+        env.define_global(x, Value::Int(1), true);
+
+        let module = env.add_module();
+        assert_eq!(env.enter_module(module), 0);
+        assert_eq!(env.get(x), None);
+        assert_eq!(env.assign(x, Value::Int(2)), Err(AssignError::Undefined));
+        env.define_global(y, Value::Int(3), false);
+        assert!(env.get(print).is_some());
+
+        env.enter_module(0);
+        assert_eq!(env.get(x), Some(Value::Int(1)));
+        assert_eq!(env.get(y), None);
+        assert_eq!(env.global(module, y).map(Binding::get), Some(Value::Int(3)));
+        assert_eq!(env.assign(print, Value::Unit), Err(AssignError::Immutable));
+    }
+}
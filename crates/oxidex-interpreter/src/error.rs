@@ -0,0 +1,434 @@
+//! Runtime errors and stack traces.
+//!
+//! Scripts recover from errors with the `catch` builtin, which receives
+//! them as values of the `RuntimeError` enum. Interruptions (see
+//! [`crate::limits`]) and sandbox violations (see [`crate::sandbox`]) cannot
+//! be caught.
+
+use crate::limits::Interrupt;
+use crate::sandbox::Capability;
+use crate::value::Value;
+use oxidex_codegen::CodegenError;
+use oxidex_syntax::Span;
+use oxidex_syntax::diagnostic::{Diagnostic, DiagnosticBuilder, DiagnosticLevel, Emitter};
+use oxidex_typecheck::error::TypeError;
+use std::fmt;
+use std::rc::Rc;
+
+/// Name of the enum whose variants describe caught errors (see
+/// [`RuntimeError::to_value`]).
+pub const ERROR_ENUM: &str = "RuntimeError";
+
+/// Variants of [`ERROR_ENUM`].
+pub const ERROR_VARIANTS: [&str; 6] = ["TypeError", "IndexOutOfBounds", "MissingKey", "NilUnwrap", "Thrown", "Failure"];
+
+/// Errors raised while evaluating a program.
+#[derive(Debug, Clone)]
+pub enum RuntimeError {
+    /// A name is not bound in any enclosing scope.
+    UndefinedVariable {
+        /// The name
+        name: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// An immutable binding is assigned to.
+    ImmutableAssignment {
+        /// The name
+        name: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// An operation received a value of the wrong kind.
+    TypeMismatch {
+        /// Kind of value the operation requires
+        expected: &'static str,
+        /// Kind of value it received
+        found: &'static str,
+        /// Source location
+        span: Span,
+    },
+
+    /// An integer was divided by zero.
+    DivisionByZero {
+        /// Source location
+        span: Span,
+    },
+
+    /// Integer arithmetic overflowed.
+    IntegerOverflow {
+        /// Source location
+        span: Span,
+    },
+
+    /// An array was indexed outside of its bounds.
+    IndexOutOfBounds {
+        /// The index
+        index: i64,
+        /// Length of the array
+        len: usize,
+        /// Source location
+        span: Span,
+    },
+
+    /// A dictionary has no entry for a key.
+    MissingKey {
+        /// Description of the key
+        key: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// A value has no field with a name, or the field was never set.
+    UnknownField {
+        /// Type of the value
+        ty: String,
+        /// Field name
+        field: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// A value does not respond to a method.
+    UnknownMethod {
+        /// Type of the receiver
+        ty: String,
+        /// Method name
+        method: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// No function or initializer with a name accepts a call's arguments.
+    NoOverload {
+        /// Name of the function or type
+        name: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// A value that is not a function is called.
+    NotCallable {
+        /// Kind of the value
+        found: &'static str,
+        /// Source location
+        span: Span,
+    },
+
+    /// No arm of a `match` accepts the scrutinee.
+    NoMatch {
+        /// Source location of the `match`
+        span: Span,
+    },
+
+    /// An `assert` failed.
+    AssertionFailed {
+        /// Source location of the call
+        span: Span,
+    },
+
+    /// A test expectation, such as `assert_eq`, failed.
+    ExpectationFailed {
+        /// What was expected
+        message: String,
+        /// How the values differed, if the expectation compared values
+        detail: Option<String>,
+        /// Source location of the call
+        span: Span,
+    },
+
+    /// `nil` was unwrapped.
+    NilUnwrap {
+        /// Source location of the unwrap
+        span: Span,
+    },
+
+    /// The program threw a value with `throw`.
+    Thrown {
+        /// The thrown value
+        value: Value,
+        /// Source location of the `throw`
+        span: Span,
+    },
+
+    /// No file matches an import.
+    ModuleNotFound {
+        /// Module path, as written
+        path: String,
+        /// Source location of the import
+        span: Span,
+    },
+
+    /// A module imports itself, directly or through other modules.
+    ImportCycle {
+        /// Files of the modules in the cycle, starting and ending with the
+        /// module imported again
+        cycle: Vec<String>,
+        /// Source location of the import closing the cycle
+        span: Span,
+    },
+
+    /// The declarations of an imported module could not be read.
+    ModuleUnavailable {
+        /// File of the module
+        path: String,
+        /// Why they could not be read
+        reason: String,
+        /// Source location of the import
+        span: Span,
+    },
+
+    /// Evaluation hit a limit or was cancelled.
+    Interrupted {
+        /// Why evaluation stopped
+        cause: Interrupt,
+        /// Source location of the step that was not taken
+        span: Span,
+    },
+
+    /// The script used a capability its sandbox denies.
+    Sandboxed {
+        /// The capability denied
+        capability: Capability,
+        /// Source location of the operation that was not performed
+        span: Span,
+    },
+
+    /// A construct the interpreter does not evaluate.
+    Unsupported {
+        /// Description of the construct
+        construct: &'static str,
+        /// Source location
+        span: Span,
+    },
+
+    /// A C function declared `extern` could not be loaded or called.
+    ForeignCall {
+        /// Name of the function
+        function: String,
+        /// Why the runtime could not call it
+        error: oxidec::Error,
+        /// Source location of the call
+        span: Span,
+    },
+
+    /// A literal failed to evaluate.
+    Type(TypeError),
+
+    /// The program's types could not be lowered or registered.
+    Lowering(CodegenError),
+
+    /// The runtime rejected an operation.
+    Runtime(oxidec::Error),
+
+    /// An error raised inside a call, with the calls active when it was
+    /// raised.
+    Traced {
+        /// The error
+        error: Box<RuntimeError>,
+        /// Active calls, innermost first
+        trace: Vec<StackFrame>,
+    },
+}
+
+/// A call that was active when an error was raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Name of the called function or method
+    pub function: String,
+    /// Location of the call
+    pub call_site: Span,
+}
+
+impl RuntimeError {
+    /// Get the source location of the error, if it has one.
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::UndefinedVariable { span, .. }
+            | Self::ImmutableAssignment { span, .. }
+            | Self::TypeMismatch { span, .. }
+            | Self::DivisionByZero { span }
+            | Self::IntegerOverflow { span }
+            | Self::IndexOutOfBounds { span, .. }
+            | Self::MissingKey { span, .. }
+            | Self::UnknownField { span, .. }
+            | Self::UnknownMethod { span, .. }
+            | Self::NoOverload { span, .. }
+            | Self::NotCallable { span, .. }
+            | Self::NoMatch { span }
+            | Self::AssertionFailed { span }
+            | Self::ExpectationFailed { span, .. }
+            | Self::NilUnwrap { span }
+            | Self::Thrown { span, .. }
+            | Self::ModuleNotFound { span, .. }
+            | Self::ImportCycle { span, .. }
+            | Self::ModuleUnavailable { span, .. }
+            | Self::Interrupted { span, .. }
+            | Self::Sandboxed { span, .. }
+            | Self::Unsupported { span, .. }
+            | Self::ForeignCall { span, .. } => Some(*span),
+            Self::Type(err) => Some(err.span()),
+            Self::Lowering(err) => err.span(),
+            Self::Runtime(_) => None,
+            Self::Traced { error, .. } => error.span(),
+        }
+    }
+
+    /// Get the error without its stack trace.
+    #[must_use]
+    pub fn untraced(&self) -> &RuntimeError {
+        match self {
+            Self::Traced { error, .. } => error,
+            error => error,
+        }
+    }
+
+    /// Whether evaluation was interrupted rather than failing, in which case
+    /// scripts cannot catch the error.
+    #[must_use]
+    pub fn is_interrupt(&self) -> bool {
+        matches!(self.untraced(), Self::Interrupted { .. })
+    }
+
+    /// Whether a script can catch the error: all errors but interruptions
+    /// and sandbox violations.
+    #[must_use]
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self.untraced(), Self::Interrupted { .. } | Self::Sandboxed { .. })
+    }
+
+    /// Get the calls that were active when the error was raised, innermost
+    /// first.
+    #[must_use]
+    pub fn trace(&self) -> &[StackFrame] {
+        match self {
+            Self::Traced { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+
+    /// Convert the error to the value a script catches.
+    ///
+    /// Errors are variants of the `RuntimeError` enum ([`ERROR_ENUM`]):
+    ///
+    /// - `TypeError(message)` for values of the wrong kind that escaped
+    ///   type checking, and calls or accesses they do not support
+    /// - `IndexOutOfBounds(index, len)` for array indices out of bounds
+    /// - `MissingKey(key)` for absent dictionary keys
+    /// - `NilUnwrap` for unwrapping `nil`
+    /// - `Thrown(value)` for values the program threw
+    /// - `Failure(message)` for every other error
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        let error = self.untraced();
+        let (variant, payload) = match error {
+            Self::TypeMismatch { .. }
+            | Self::UnknownField { .. }
+            | Self::UnknownMethod { .. }
+            | Self::NoOverload { .. }
+            | Self::NotCallable { .. }
+            | Self::Type(_) => ("TypeError", Some(Value::string(error.to_string()))),
+            Self::IndexOutOfBounds { index, len, .. } => {
+                let len = i64::try_from(*len).unwrap_or(i64::MAX);
+                ("IndexOutOfBounds", Some(Value::Tuple(Rc::from([Value::Int(*index), Value::Int(len)]))))
+            }
+            Self::MissingKey { key, .. } => ("MissingKey", Some(Value::string(key.clone()))),
+            Self::NilUnwrap { .. } => ("NilUnwrap", None),
+            Self::Thrown { value, .. } => ("Thrown", Some(value.clone())),
+            _ => ("Failure", Some(Value::string(error.to_string()))),
+        };
+        Value::variant(ERROR_ENUM, variant, payload)
+    }
+
+    /// Describe the error as a diagnostic, with a note at each call site of
+    /// its stack trace, innermost first.
+    ///
+    /// The detail of a failed expectation, such as a diff of the values, is
+    /// the first note.
+    ///
+    /// Errors without a location of their own are reported at the innermost
+    /// call site.
+    #[must_use]
+    pub fn diagnostic(&self) -> Diagnostic {
+        let trace = self.trace();
+        let span = self
+            .span()
+            .or_else(|| trace.first().map(|frame| frame.call_site))
+            .unwrap_or(Span::new(0, 0, 0, 0, 0, 0));
+        let mut error = DiagnosticBuilder::new(DiagnosticLevel::Error, self.to_string(), span);
+        if let Self::ExpectationFailed { detail: Some(detail), .. } = self.untraced() {
+            error = error.note(detail.clone(), span);
+        }
+        for frame in trace {
+            error = error.note(format!("in `{}`, called here", frame.function), frame.call_site);
+        }
+        error.build()
+    }
+
+    /// Print the error and its stack trace with source snippets.
+    pub fn emit(&self, emitter: &Emitter, source: &str) {
+        emitter.emit(&self.diagnostic(), source);
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedVariable { name, .. } => write!(f, "undefined variable `{name}`"),
+            Self::ImmutableAssignment { name, .. } => write!(f, "cannot assign twice to immutable variable `{name}`"),
+            Self::TypeMismatch { expected, found, .. } => write!(f, "expected {expected}, found {found}"),
+            Self::DivisionByZero { .. } => write!(f, "division by zero"),
+            Self::IntegerOverflow { .. } => write!(f, "integer overflow"),
+            Self::IndexOutOfBounds { index, len, .. } => {
+                write!(f, "index {index} is out of bounds for an array of length {len}")
+            }
+            Self::MissingKey { key, .. } => write!(f, "no entry for key {key}"),
+            Self::UnknownField { ty, field, .. } => write!(f, "`{ty}` has no field `{field}`"),
+            Self::UnknownMethod { ty, method, .. } => write!(f, "`{ty}` has no method `{method}`"),
+            Self::NoOverload { name, .. } => write!(f, "no overload of `{name}` accepts these arguments"),
+            Self::NotCallable { found, .. } => write!(f, "{found} is not callable"),
+            Self::NoMatch { .. } => write!(f, "no match arm accepts the value"),
+            Self::AssertionFailed { .. } => write!(f, "assertion failed"),
+            Self::ExpectationFailed { message, .. } => write!(f, "{message}"),
+            Self::NilUnwrap { .. } => write!(f, "unwrapped a nil value"),
+            Self::Thrown { value, .. } => write!(f, "uncaught error: {value:#}"),
+            Self::ModuleNotFound { path, .. } => write!(f, "no module found for `{path}`"),
+            Self::ImportCycle { cycle, .. } => write!(f, "import cycle: {}", cycle.join(" -> ")),
+            Self::ModuleUnavailable { path, reason, .. } => write!(f, "cannot load module `{path}`: {reason}"),
+            Self::Interrupted { cause, .. } => write!(f, "evaluation stopped: it {cause}"),
+            Self::Sandboxed { capability, .. } => write!(f, "{capability} is denied by the sandbox"),
+            Self::Unsupported { construct, .. } => write!(f, "{construct} cannot be evaluated yet"),
+            Self::ForeignCall { function, error, .. } => write!(f, "cannot call extern function `{function}`: {error}"),
+            Self::Type(err) => write!(f, "{err}"),
+            Self::Lowering(err) => write!(f, "{err}"),
+            Self::Runtime(err) => write!(f, "runtime error: {err}"),
+            Self::Traced { error, .. } => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<TypeError> for RuntimeError {
+    fn from(err: TypeError) -> Self {
+        Self::Type(err)
+    }
+}
+
+impl From<CodegenError> for RuntimeError {
+    fn from(err: CodegenError) -> Self {
+        Self::Lowering(err)
+    }
+}
+
+impl From<oxidec::Error> for RuntimeError {
+    fn from(err: oxidec::Error) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// Result type for evaluation.
+pub type Result<T> = std::result::Result<T, RuntimeError>;
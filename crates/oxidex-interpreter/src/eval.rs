@@ -0,0 +1,3192 @@
+//! Tree-walking evaluation.
+//!
+//! The interpreter executes a type-checked AST directly. It shares the
+//! codegen backend's view of the program's types: classes, structs and enums
+//! are lowered with [`oxidex_codegen::lower`], registered with the runtime,
+//! and methods are resolved through [`LoweredModule::resolve_method`], so a
+//! call dispatches to the same method in both backends.
+//!
+//! Each module the program imports is evaluated once, in globals of its
+//! own (see [`crate::module`]); functions and methods run in the globals of
+//! the module that declares them. A [`Sandbox`] set with
+//! [`Interpreter::set_sandbox`] is checked before each import, each call to
+//! a builtin needing a capability and each message sent to native code.
+//!
+//! Control flow that leaves a function early (`return`) unwinds through
+//! the evaluator and is caught at the call boundary. Errors unwind the same
+//! way, up to the nearest `catch` call or out of the interpreter. Blocks
+//! run the bodies they `defer` as they exit, however they exit.
+
+use crate::builtins::{Builtins, CATCH, PRINT};
+use crate::debug::{Debugger, Pause, Session};
+use crate::env::{AssignError, Environment};
+use crate::error::{ERROR_ENUM, ERROR_VARIANTS, Result, RuntimeError, StackFrame};
+use crate::gc::Collector;
+use crate::iter::{Cursor, MAKE_ITERATOR, NEXT};
+use crate::limits::{CancelToken, Interrupt, Limits};
+use crate::module::{Module, ModuleSource, Resolver};
+use crate::native::{self, Receiver};
+use crate::resolve::{Resolution, Slot};
+use crate::sandbox::{Capability, Sandbox};
+use crate::value::{Captured, Closure, Instance, Value};
+use oxidec::runtime::MessageArgs;
+use oxidec::runtime::ffi::{ForeignFunction, Library};
+use oxidec::runtime::introspection::{class_from_name, instance_variables};
+use oxidec::{Class, Object, Selector};
+use oxidex_codegen::ir::method_symbol;
+use oxidex_codegen::lowering::{TypeKind, selector_name, set_method_handler};
+use oxidex_codegen::{CodegenError, LoweredModule};
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::{Decl, ExternFn, FnParam, Visibility};
+use oxidex_syntax::ast::expr::{BinaryOp, CallArg, InterpolationPart, MatchArm, StructField, UnaryOp};
+use oxidex_syntax::ast::pat::Pattern;
+use oxidex_syntax::ast::stmt::Stmt;
+use oxidex_syntax::ast::ty::Type;
+use oxidex_syntax::lexer::string_value;
+use oxidex_syntax::token::TokenKind;
+use oxidex_syntax::{Expr, Span, Spanned};
+use oxidex_typecheck::InferContext as Context;
+use oxidex_typecheck::infer::CaptureMode;
+use oxidex_typecheck::PrimTy;
+use oxidex_typecheck::check::{ConstValue, binary_bound, comptime_branch, eval_const, resolve_primitive, unary_bound};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Why evaluation stopped before producing a value.
+enum Unwind {
+    /// A `return` is leaving the current function
+    Return(Value),
+    /// An error is propagating to the caller; boxed to keep the frames of
+    /// the recursive evaluator small
+    Error(Box<RuntimeError>),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(err: RuntimeError) -> Self {
+        Self::Error(Box::new(err))
+    }
+}
+
+type Flow<T> = std::result::Result<T, Unwind>;
+
+/// Name of closures in stack traces and errors.
+const CLOSURE: &str = "<closure>";
+
+/// Turn a flow into a result, treating a stray `return` as the value.
+fn settle(flow: Flow<Value>) -> Result<Value> {
+    match flow {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Error(err)) => Err(*err),
+    }
+}
+
+/// Turn a folded constant into a value.
+fn const_value(value: ConstValue, span: Span) -> Result<Value> {
+    Ok(match value {
+        ConstValue::Int(value) => Value::Int(i64::try_from(value).map_err(|_| RuntimeError::IntegerOverflow { span })?),
+        ConstValue::Float(value) => Value::Float(value),
+        ConstValue::Bool(value) => Value::Bool(value),
+        ConstValue::String(value) => Value::String(value),
+    })
+}
+
+/// A function or static method body.
+#[derive(Clone)]
+struct Callable<'a> {
+    /// Name shown in stack traces
+    name: String,
+    /// External parameter labels
+    labels: Vec<String>,
+    /// Parameters
+    params: &'a [FnParam],
+    /// Body
+    body: &'a Expr<'a>,
+    /// Type the function is declared in
+    owner: Option<String>,
+    /// Module the function is declared in; static methods run in their
+    /// type's module instead
+    module: usize,
+}
+
+/// A C function declared in an `extern` block, loaded on its first call.
+struct Extern {
+    /// C symbol of the function
+    name: String,
+    /// Library the function is in, or `None` for the process's symbols
+    library: Option<String>,
+    /// Type encoding of its signature
+    encoding: String,
+    /// The function, once loaded
+    loaded: Option<Rc<ForeignFunction>>,
+}
+
+/// The state of an active call.
+struct Frame {
+    /// Name of the called function, for stack traces
+    function: String,
+    /// Location of the call
+    call_site: Span,
+    /// Type whose method is running, which `Self` refers to
+    owner: Option<String>,
+    /// Receiver of an instance method
+    receiver: Option<Value>,
+    /// Module whose globals the call sees
+    module: usize,
+}
+
+/// Evaluates a program.
+pub struct Interpreter<'a, 'ctx> {
+    ctx: &'a Context<'ctx>,
+    lowered: &'a LoweredModule<'a>,
+    env: Environment,
+    /// Functions and static methods by symbol; overloads share a name
+    functions: HashMap<String, Vec<Callable<'a>>>,
+    /// Functions implemented in Rust, called when no program function
+    /// has the name
+    builtins: Builtins,
+    /// C functions declared in `extern` blocks, by symbol
+    externs: HashMap<String, Extern>,
+    /// Runtime classes of the module's types
+    classes: HashMap<String, Class>,
+    /// Instances by object address, so native messages can find them
+    instances: HashMap<usize, Weak<Instance>>,
+    /// Error raised by a method that native code called
+    native_error: Option<RuntimeError>,
+    /// Active calls, innermost last; the first frame is top-level code
+    frames: Vec<Frame>,
+    /// Resolved variables of the code each active call runs, parallel to
+    /// `frames`
+    slots: Vec<Rc<Resolution>>,
+    /// Resolutions of function and method bodies, by body address
+    resolutions: HashMap<usize, Rc<Resolution>>,
+    /// Parameters and bodies of the closures created so far, by closure
+    /// expression address
+    closures: HashMap<usize, (&'a [FnParam], &'a Expr<'a>)>,
+    self_sym: Option<Symbol>,
+    /// Loaded modules; the first is the program being run
+    modules: Vec<Module>,
+    /// Imported modules by file
+    module_ids: HashMap<PathBuf, usize>,
+    /// Modules whose declarations are being evaluated, importers first
+    loading: Vec<usize>,
+    /// Modules declaring each type, for types not declared by the program
+    /// being run
+    type_modules: HashMap<String, usize>,
+    /// Finds the files imports name
+    resolver: Resolver,
+    /// Supplies the declarations of imported modules
+    source: Option<Box<dyn ModuleSource<'a> + 'a>>,
+    /// Attached debugger, consulted at each new line
+    debugger: Option<Session>,
+    /// Frees cycles of containers the program changed
+    collector: Collector,
+    limits: Limits,
+    /// Capabilities denied to the program
+    sandbox: Sandbox,
+    /// Stops evaluation when cancelled
+    cancel: CancelToken,
+    /// Steps taken by the current evaluation
+    steps: u64,
+    /// When the current evaluation started
+    started: Instant,
+}
+
+impl<'a, 'ctx> Interpreter<'a, 'ctx> {
+    /// Create an interpreter for a checked and lowered program, with the
+    /// standard builtins.
+    ///
+    /// Static methods and builtins are callable right away; call
+    /// [`Interpreter::load`] to register the program's types and declare its
+    /// globals.
+    #[must_use]
+    pub fn new(ctx: &'a Context<'ctx>, lowered: &'a LoweredModule<'a>) -> Self {
+        Self::with_builtins(ctx, lowered, Builtins::standard())
+    }
+
+    /// Create an interpreter with a custom set of builtins.
+    #[must_use]
+    pub fn with_builtins(ctx: &'a Context<'ctx>, lowered: &'a LoweredModule<'a>, builtins: Builtins) -> Self {
+        let mut env = Environment::new();
+        for (name, _) in builtins.iter() {
+            if let Some(sym) = ctx.interner.get_symbol(name) {
+                env.define_prelude(sym, Value::Function(name.into()));
+            }
+        }
+
+        let mut functions: HashMap<String, Vec<Callable<'a>>> = HashMap::new();
+        for class in &lowered.classes {
+            for method in class.methods.iter().filter(|method| method.is_static()) {
+                functions.entry(method_symbol(&class.name, &method.selector)).or_default().push(Callable {
+                    name: format!("{}.{}", class.name, method.name),
+                    labels: method.labels.clone(),
+                    params: &method.decl.params,
+                    body: method.decl.body,
+                    owner: Some(class.name.clone()),
+                    module: 0,
+                });
+            }
+        }
+        Self {
+            ctx,
+            lowered,
+            env,
+            functions,
+            builtins,
+            externs: HashMap::new(),
+            classes: HashMap::new(),
+            instances: HashMap::new(),
+            native_error: None,
+            frames: vec![Frame {
+                function: String::new(),
+                call_site: Span::new(0, 0, 0, 0, 0, 0),
+                owner: None,
+                receiver: None,
+                module: 0,
+            }],
+            slots: vec![Rc::default()],
+            resolutions: HashMap::new(),
+            closures: HashMap::new(),
+            self_sym: ctx.interner.get_symbol("self"),
+            modules: vec![Module { file: String::new(), path: PathBuf::new(), exports: Vec::new() }],
+            module_ids: HashMap::new(),
+            loading: Vec::new(),
+            type_modules: HashMap::new(),
+            resolver: Resolver::new(),
+            source: None,
+            debugger: None,
+            collector: Collector::new(),
+            limits: Limits::default(),
+            sandbox: Sandbox::default(),
+            cancel: CancelToken::new(),
+            steps: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Get the environment.
+    #[must_use]
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Get the environment mutably.
+    pub fn env_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    /// Get the active calls, innermost first.
+    #[must_use]
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        self.frames[1..]
+            .iter()
+            .rev()
+            .map(|frame| StackFrame { function: frame.function.clone(), call_site: frame.call_site })
+            .collect()
+    }
+
+    /// Set the name of the source file being run, which breakpoints refer
+    /// to and relative imports are resolved against.
+    pub fn set_file(&mut self, file: impl Into<String>) {
+        let file = file.into();
+        self.modules[0].path = PathBuf::from(&file);
+        self.modules[0].file = file;
+    }
+
+    /// Set how imports find files, replacing the current resolver.
+    pub fn set_resolver(&mut self, resolver: Resolver) {
+        self.resolver = resolver;
+    }
+
+    /// Get the resolver imports find files with.
+    pub fn resolver_mut(&mut self) -> &mut Resolver {
+        &mut self.resolver
+    }
+
+    /// Set what supplies the declarations of imported modules. Without one,
+    /// every import fails.
+    pub fn set_module_source(&mut self, source: impl ModuleSource<'a> + 'a) {
+        self.source = Some(Box::new(source));
+    }
+
+    /// Get the files of the modules imported so far, in the order they were
+    /// loaded.
+    #[must_use]
+    pub fn imported_files(&self) -> Vec<&Path> {
+        self.modules[1..].iter().map(|module| module.path.as_path()).collect()
+    }
+
+    /// Attach a debugger, replacing any attached one. It is consulted
+    /// whenever execution reaches a new line, and stepping starts out
+    /// continuing to the first breakpoint.
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Session::new(Box::new(debugger)));
+    }
+
+    /// Detach the debugger, returning it.
+    pub fn take_debugger(&mut self) -> Option<Box<dyn Debugger>> {
+        self.debugger.take().map(Session::into_debugger)
+    }
+
+    /// Let native code send messages to the interpreter's objects while `f`
+    /// runs.
+    ///
+    /// Messages are delivered on this thread only. An error raised by a
+    /// method called this way cannot unwind through native code; it is kept
+    /// until [`Interpreter::take_native_error`].
+    pub fn attach<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        native::attach(self, f)
+    }
+
+    /// Take the error raised by the last method native code called, if it
+    /// failed.
+    pub fn take_native_error(&mut self) -> Option<RuntimeError> {
+        self.native_error.take()
+    }
+
+    /// Free unreachable cycles of arrays, dictionaries and instances,
+    /// returning how many containers were emptied to break them.
+    ///
+    /// Only variables keep values alive here: a value held outside the
+    /// interpreter that is part of a cycle is emptied unless a variable
+    /// also reaches it.
+    pub fn collect_cycles(&mut self) -> usize {
+        let receivers = self.frames.iter().filter_map(|frame| frame.receiver.as_ref());
+        let values: Vec<Value> = self.env.values().collect();
+        self.collector.collect(values.iter().chain(receivers))
+    }
+
+    /// Collect cycles between top-level statements once `threshold`
+    /// containers may have joined one since the last collection, or never
+    /// if `None`, the default. See [`Interpreter::collect_cycles`] for the
+    /// values a collection keeps.
+    pub fn set_cycle_threshold(&mut self, threshold: Option<usize>) {
+        self.collector.set_threshold(threshold);
+    }
+
+    /// Bound the work each evaluation may do.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get the limits on each evaluation.
+    #[must_use]
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Deny the program the capabilities `sandbox` denies.
+    ///
+    /// Builtins needing a denied capability stay bound but fail when
+    /// called; drop them with [`Builtins::restrict`] before declaring them
+    /// to the type checker to reject programs calling them.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = sandbox;
+    }
+
+    /// Get the capabilities denied to the program.
+    #[must_use]
+    pub fn sandbox(&self) -> Sandbox {
+        self.sandbox
+    }
+
+    /// Get a token that stops evaluation when cancelled. All tokens of an
+    /// interpreter share one flag.
+    #[must_use]
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Register the program's types with the runtime and evaluate its
+    /// declarations.
+    ///
+    /// The types' instance methods are bound to an IMP that re-enters the
+    /// interpreter (see [`Interpreter::attach`]). Imports are evaluated
+    /// first, then functions are declared before constants and statics, so
+    /// their initializers can call any function.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a type cannot be registered, an import fails or
+    /// an initializer fails.
+    pub fn load(&mut self, decls: &'a [Decl<'a>]) -> Result<()> {
+        self.start(Resolution::default);
+        let classes = self.lowered.register()?;
+        set_method_handler(native::handle_message);
+        for (lowered, class) in self.lowered.classes.iter().zip(classes) {
+            self.classes.insert(lowered.name.clone(), class);
+        }
+
+        // An import of the program itself is a cycle
+        if let Ok(path) = self.modules[0].path.canonicalize() {
+            self.module_ids.insert(path, 0);
+        }
+        self.loading.push(0);
+        let result = self.load_decls(decls);
+        self.loading.pop();
+        result
+    }
+
+    fn load_decls(&mut self, decls: &'a [Decl<'a>]) -> Result<()> {
+        let rank = |decl: &&Decl<'_>| match decl {
+            Decl::Import { .. } => 0,
+            Decl::Fn { .. } | Decl::Extern { .. } => 1,
+            _ => 2,
+        };
+        let mut ordered: Vec<_> = decls.iter().collect();
+        ordered.sort_by_key(rank);
+        for decl in ordered {
+            self.eval_decl(decl)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate a declaration.
+    ///
+    /// Type declarations have nothing to evaluate; their methods are
+    /// reached through the lowered module.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an import fails or the initializer of a constant
+    /// or static fails.
+    pub fn eval_decl(&mut self, decl: &'a Decl<'a>) -> Result<()> {
+        let module = self.env.module();
+        match decl {
+            Decl::Fn { name, params, body, .. } => {
+                let text = self.name(*name).to_string();
+                // Functions of imported modules may share names with others
+                let symbol = if module == 0 { text.clone() } else { format!("{text}#{module}") };
+                let labels = params.iter().map(|param| self.name(param.label.unwrap_or(param.name)).to_string());
+                let callable = Callable { name: text, labels: labels.collect(), params, body, owner: None, module };
+                self.functions.entry(symbol.clone()).or_default().push(callable);
+                self.env.define_global(*name, Value::Function(symbol.into()), false);
+            }
+            Decl::Extern { library, functions, .. } => {
+                let library = library.map(|library| self.name(library).to_string());
+                for function in functions {
+                    let encoding = self.extern_encoding(function)?;
+                    let text = self.name(function.name).to_string();
+                    let symbol = if module == 0 { text.clone() } else { format!("{text}#{module}") };
+                    let external = Extern { name: text, library: library.clone(), encoding, loaded: None };
+                    self.externs.insert(symbol.clone(), external);
+                    self.env.define_global(function.name, Value::Function(symbol.into()), false);
+                }
+            }
+            Decl::Const { name, value, span, .. } => {
+                // Checking folded the value, which a `--define` may have replaced
+                let value = match self.ctx.consts.get(name) {
+                    Some(folded) => const_value(folded.clone(), *span)?,
+                    None => settle(self.eval_expr(value))?,
+                };
+                self.env.define_global(*name, value, false);
+            }
+            Decl::Static { name, init, mutable, .. } => {
+                let value = match init {
+                    Some(init) => settle(self.eval_expr(init))?,
+                    None => Value::Nil,
+                };
+                self.env.define_global(*name, value, *mutable);
+            }
+            Decl::Struct { name, .. } | Decl::Class { name, .. } | Decl::Enum { name, .. } if module != 0 => {
+                self.type_modules.insert(self.name(*name).to_string(), module);
+            }
+            Decl::Import { path, span } => self.import(&string_value(self.name(*path)), *span)?,
+            Decl::Struct { .. }
+            | Decl::Class { .. }
+            | Decl::Enum { .. }
+            | Decl::Protocol { .. }
+            | Decl::Impl { .. }
+            | Decl::TypeAlias { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Get the type encoding of the signature of an extern function: its
+    /// return type, then each parameter's, as C sees them.
+    fn extern_encoding(&self, function: &ExternFn) -> Result<String> {
+        let params = function.params.iter().map(|param| Some(&param.type_annotation));
+        std::iter::once(function.return_type.as_ref())
+            .chain(params)
+            .map(|ty| {
+                let prim = match ty {
+                    Some(Type::Simple { name, .. }) => resolve_primitive(self.name(*name)),
+                    Some(_) => None,
+                    None => Some(PrimTy::Unit),
+                };
+                let construct = "an extern signature type without a C counterpart";
+                prim.and_then(PrimTy::c_encoding).ok_or(RuntimeError::Unsupported { construct, span: function.span })
+            })
+            .collect()
+    }
+
+    /// Evaluate an import: load the module on first import, then bind its
+    /// public globals in the current module.
+    fn import(&mut self, path: &str, span: Span) -> Result<()> {
+        // Resolving probes the file system, and a new module is code loaded
+        // at run time
+        self.require(Capability::FileIo, span)?;
+        self.require(Capability::DynamicLoading, span)?;
+        let importer = &self.modules[self.env.module()].path;
+        let Some(file) = self.resolver.resolve(path, Some(importer)) else {
+            return Err(RuntimeError::ModuleNotFound { path: path.to_string(), span });
+        };
+        let module = match self.module_ids.get(&file) {
+            Some(&module) if let Some(start) = self.loading.iter().position(|&loading| loading == module) => {
+                let cycle = self.loading[start..].iter().chain([&module]);
+                let cycle = cycle.map(|&module| self.modules[module].file.clone()).collect();
+                return Err(RuntimeError::ImportCycle { cycle, span });
+            }
+            Some(&module) => module,
+            None => self.load_module(file, span)?,
+        };
+
+        let exports = self.modules[module].exports.clone();
+        for name in exports {
+            if let Some(binding) = self.env.global(module, name) {
+                let value = binding.get();
+                self.env.define_global(name, value, false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the declarations of a module in globals of its own.
+    fn load_module(&mut self, file: PathBuf, span: Span) -> Result<usize> {
+        let unavailable = |reason: String| RuntimeError::ModuleUnavailable {
+            path: file.display().to_string(),
+            reason,
+            span,
+        };
+        let source = self.source.as_mut().ok_or_else(|| unavailable("no module source is set".to_string()))?;
+        let decls = source.declarations(&file).map_err(unavailable)?;
+
+        let module = self.env.add_module();
+        self.modules.push(Module { file: file.display().to_string(), path: file.clone(), exports: Vec::new() });
+        self.module_ids.insert(file.clone(), module);
+        let importer = self.env.enter_module(module);
+        self.loading.push(module);
+        let result = self.load_decls(decls);
+        self.loading.pop();
+        self.env.enter_module(importer);
+        if let Err(err) = result {
+            // A later import tries again
+            self.module_ids.remove(&file);
+            return Err(err);
+        }
+
+        self.modules[module].exports = decls
+            .iter()
+            .filter_map(|decl| match decl {
+                Decl::Fn { name, visibility: Visibility::Public, .. }
+                | Decl::Const { name, visibility: Visibility::Public, .. }
+                | Decl::Static { name, visibility: Visibility::Public, .. } => Some(*name),
+                _ => None,
+            })
+            .collect();
+        Ok(module)
+    }
+
+    /// Get the module that declares a type.
+    fn type_module(&self, ty: &str) -> usize {
+        self.type_modules.get(ty).copied().unwrap_or(0)
+    }
+
+    /// Evaluate an expression.
+    ///
+    /// A `return` at the top level ends evaluation with its value.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first runtime error raised.
+    pub fn eval(&mut self, expr: &'a Expr<'a>) -> Result<Value> {
+        self.start(|| Resolution::top_level_expr(expr));
+        let value = settle(self.eval_expr(expr));
+        self.finish();
+        let value = value?;
+        self.safepoint(Some(&value));
+        Ok(value)
+    }
+
+    /// Execute a statement.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first runtime error raised.
+    pub fn exec(&mut self, stmt: &'a Stmt<'a>) -> Result<()> {
+        self.start(|| Resolution::top_level_stmt(stmt));
+        let result = settle(self.exec_stmt(stmt).map(|()| Value::Unit));
+        self.finish();
+        result?;
+        self.safepoint(None);
+        Ok(())
+    }
+
+    /// Call a function or static method by symbol.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no overload takes `args`, or the call fails.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        self.start(Resolution::default);
+        let labels = vec![None; args.len()];
+        settle(self.call_function(name, &labels, args, Span::new(0, 0, 0, 0, 0, 0)))
+    }
+
+    /// Describe a value as `print` and interpolation do: by its
+    /// `description()` when its type implements `CustomStringConvertible`,
+    /// and otherwise by its fields (see [`Self::debug_description`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a `description()` fails.
+    pub fn description(&mut self, value: Value) -> Result<String> {
+        self.start(Resolution::default);
+        settle(self.describe(value, Span::new(0, 0, 0, 0, 0, 0)).map(Value::String))
+            .map(|text| text.to_string())
+    }
+
+    /// Describe a value for debugging, as a REPL prints a result: by its
+    /// `debugDescription()` when its type implements
+    /// `CustomDebugStringConvertible`, then by its `description()`, and
+    /// otherwise as `Class(field: value, ...)`, with the fields its runtime
+    /// class declares, each described for debugging.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a `debugDescription()` or `description()` fails.
+    pub fn debug_description(&mut self, value: Value) -> Result<String> {
+        self.start(Resolution::default);
+        settle(self.debug_describe(value, Span::new(0, 0, 0, 0, 0, 0)).map(Value::String))
+            .map(|text| text.to_string())
+    }
+
+    /// Start a fresh budget and resolve the top-level code about to run,
+    /// unless a call is active.
+    fn start(&mut self, resolve: impl FnOnce() -> Resolution) {
+        if self.frames.len() == 1 {
+            self.steps = 0;
+            self.started = Instant::now();
+            self.slots[0] = Rc::new(resolve());
+        }
+    }
+
+    /// Forget the resolution of top-level code that finished running, since
+    /// its nodes may be freed.
+    fn finish(&mut self) {
+        if self.frames.len() == 1 {
+            self.slots[0] = Rc::default();
+        }
+    }
+
+    /// Get the slot a variable reference in the running code resolved to.
+    fn slot(&self, reference: &Expr<'_>) -> Option<Slot> {
+        self.slots.last().and_then(|resolution| resolution.get(reference))
+    }
+
+    /// Take a step, failing if a limit was reached or evaluation was
+    /// cancelled.
+    fn tick(&mut self, span: Span) -> Result<()> {
+        self.steps += 1;
+        let cause = if self.cancel.is_cancelled() {
+            Interrupt::Cancelled
+        } else if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+            Interrupt::Steps(max)
+        } else if let Some(timeout) = self.limits.timeout.filter(|timeout| self.started.elapsed() > *timeout) {
+            Interrupt::Timeout(timeout)
+        } else {
+            return Ok(());
+        };
+        Err(RuntimeError::Interrupted { cause, span })
+    }
+
+    /// Fail unless the sandbox allows `capability`.
+    fn require(&self, capability: Capability, span: Span) -> Result<()> {
+        self.sandbox.check(capability).map_err(|capability| RuntimeError::Sandboxed { capability, span })
+    }
+
+    /// Collect cycles if a collection is due and no call is active, keeping
+    /// `result` alive too.
+    fn safepoint(&mut self, result: Option<&Value>) {
+        if self.frames.len() > 1 || !self.collector.is_due() {
+            return;
+        }
+        let receivers = self.frames.iter().filter_map(|frame| frame.receiver.as_ref());
+        let values: Vec<Value> = self.env.values().collect();
+        self.collector.collect(values.iter().chain(receivers).chain(result));
+    }
+
+    fn name(&self, sym: Symbol) -> &'a str {
+        self.ctx.interner.resolve(sym).unwrap_or("")
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("the top-level frame is never popped")
+    }
+
+    /// Resolve a type path to a type name, expanding `Self`.
+    fn type_name(&self, path: &[Symbol]) -> String {
+        let name = path.last().map_or("", |sym| self.name(*sym));
+        match (name, &self.frame().owner) {
+            ("Self", Some(owner)) => owner.clone(),
+            _ => name.to_string(),
+        }
+    }
+
+    fn is_variant(&self, enum_name: &str, variant: &str) -> bool {
+        match enum_name {
+            "Option" | "Optional" => matches!(variant, "None" | "Some"),
+            "Result" => matches!(variant, "Ok" | "Err"),
+            ERROR_ENUM => ERROR_VARIANTS.contains(&variant),
+            _ => self.variant_info(enum_name, variant).is_some(),
+        }
+    }
+
+    /// Field names of a struct variant, in payload order.
+    fn variant_fields(&self, enum_name: &str, variant: &str) -> Option<Vec<Symbol>> {
+        self.variant_info(enum_name, variant).filter(|fields| !fields.is_empty())
+    }
+
+    fn variant_info(&self, enum_name: &str, variant: &str) -> Option<Vec<Symbol>> {
+        let info = self.ctx.types.lookup_enum(self.ctx.interner.get_symbol(enum_name)?)?;
+        info.variants.iter().find(|v| self.name(v.name) == variant).map(|v| v.fields.clone())
+    }
+
+    fn is_subclass(&self, class: &str, ancestor: &str) -> bool {
+        let mut current = Some(class);
+        for _ in 0..=self.lowered.classes.len() {
+            match current {
+                Some(name) if name == ancestor => return true,
+                Some(name) => current = self.lowered.class(name).and_then(|c| c.superclass.as_deref()),
+                None => return false,
+            }
+        }
+        false
+    }
+
+    fn instantiate(&mut self, class: &str, span: Span) -> Result<Rc<Instance>> {
+        let runtime_class = match self.classes.get(class) {
+            Some(runtime_class) => runtime_class.clone(),
+            None => class_from_name(class)
+                .ok_or_else(|| CodegenError::UnknownType { name: class.to_string(), span })?,
+        };
+        let instance = Rc::new(Instance {
+            class: class.to_string(),
+            object: Object::new(&runtime_class)?,
+            fields: RefCell::new(Vec::new()),
+        });
+        self.instances.retain(|_, instance| instance.strong_count() > 0);
+        self.instances.insert(native::object_address(instance.object.as_raw()), Rc::downgrade(&instance));
+        Ok(instance)
+    }
+
+    fn eval_expr(&mut self, expr: &'a Expr<'a>) -> Flow<Value> {
+        match expr {
+            Expr::IntegerLiteral { .. }
+            | Expr::FloatLiteral { .. }
+            | Expr::StringLiteral { .. }
+            | Expr::BoolLiteral { .. }
+            | Expr::Nil { .. }
+            | Expr::Hole { .. }
+            | Expr::Closure { .. }
+            | Expr::Identifier(_)
+            | Expr::Path { .. }
+            | Expr::Field { .. }
+            | Expr::Index { .. } => self.eval_access(expr),
+            Expr::Unary { op, operand, span } => {
+                let value = self.eval_expr(operand)?;
+                self.unary(*op, value, *span)
+            }
+            Expr::Binary { left, op: BinaryOp::And, right, .. } => {
+                Ok(Value::Bool(self.condition(left)? && self.condition(right)?))
+            }
+            Expr::Binary { left, op: BinaryOp::Or, right, .. } => {
+                Ok(Value::Bool(self.condition(left)? || self.condition(right)?))
+            }
+            Expr::Binary { left, op: BinaryOp::Assign, right, span } => {
+                self.assign(left, right, *span)?;
+                Ok(Value::Unit)
+            }
+            Expr::Binary { left, op, right, span } => {
+                let left = self.eval_expr(left)?;
+                let right = self.eval_expr(right)?;
+                self.binary(*op, left, right, *span)
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.eval_if(condition, then_branch, *else_branch)
+            }
+            Expr::Match { scrutinee, arms, span } => {
+                let value = self.eval_expr(scrutinee)?;
+                self.eval_match(&value, arms, *span)
+            }
+            Expr::Block { stmts, expr, .. } => self.eval_block(stmts, *expr),
+            Expr::ForLoop { pattern, iter, body, span } => {
+                self.eval_for(pattern, iter, body, *span)?;
+                Ok(Value::Unit)
+            }
+            Expr::WhileLoop { condition, body, span } => {
+                while self.condition(condition)? {
+                    self.tick(*span)?;
+                    self.eval_expr(body)?;
+                }
+                Ok(Value::Unit)
+            }
+            Expr::Call { callee, args, span } => self.eval_call(callee, args, *span),
+            Expr::MethodCall { receiver, method, args, span } => self.eval_send(receiver, *method, args, *span),
+            Expr::Struct { type_path, fields, span } => self.eval_struct(type_path, fields, *span),
+            Expr::Enum { .. } | Expr::Array { .. } | Expr::Dict { .. } | Expr::Interpolation { .. } => {
+                self.eval_aggregate(expr)
+            }
+            Expr::Paren { expr, .. } => self.eval_expr(expr),
+            // Only the branch a `comptime if` selects is run
+            Expr::Comptime { expr: inner @ Expr::If { .. }, .. } => {
+                match comptime_branch(self.ctx, inner).map_err(RuntimeError::from)? {
+                    Some(branch) => self.eval_expr(branch),
+                    None => Ok(Value::Unit),
+                }
+            }
+            Expr::Comptime { .. } => Ok(self.literal(expr)?),
+        }
+    }
+
+    /// Evaluate a literal, a name, or a field or element access.
+    ///
+    /// Kept out of [`Self::eval_expr`], like [`Self::eval_aggregate`], so
+    /// the recursive evaluator's frames stay small.
+    fn eval_access(&mut self, expr: &'a Expr<'a>) -> Flow<Value> {
+        match expr {
+            Expr::IntegerLiteral { .. } | Expr::FloatLiteral { .. } | Expr::StringLiteral { .. } => {
+                Ok(self.literal(expr)?)
+            }
+            Expr::BoolLiteral { value, .. } => Ok(Value::Bool(*value)),
+            Expr::Nil { .. } => Ok(Value::Nil),
+            Expr::Hole { span } => Err(RuntimeError::Unsupported { construct: "a typed hole", span: *span }.into()),
+            Expr::Closure { params, body, span, .. } => Ok(self.closure(expr, params, body, *span)?),
+            Expr::Identifier(sym) => Ok(self.lookup(*sym, self.slot(expr), expr.span())?),
+            Expr::Path { segments, span } => Ok(self.eval_path(segments, *span)?),
+            Expr::Field { object, field, span } => {
+                let object = self.eval_expr(object)?;
+                Ok(get_field(&object, self.name(*field), *span)?)
+            }
+            Expr::Index { collection, index, span } => {
+                let collection = self.eval_expr(collection)?;
+                let index = self.eval_expr(index)?;
+                Ok(get_index(&collection, &index, *span)?)
+            }
+            _ => unreachable!("not an access expression"),
+        }
+    }
+
+    /// Evaluate an enum value, collection literal or interpolated string.
+    fn eval_aggregate(&mut self, expr: &'a Expr<'a>) -> Flow<Value> {
+        match expr {
+            Expr::Enum { type_path, variant, payload, .. } => {
+                let payload = payload.map(|payload| self.eval_expr(payload)).transpose()?;
+                Ok(Value::variant(self.type_name(type_path), self.name(*variant), payload))
+            }
+            Expr::Array { elements, .. } => {
+                let elements = elements.iter().map(|element| self.eval_expr(element)).collect::<Flow<_>>()?;
+                Ok(Value::array(elements))
+            }
+            Expr::Dict { entries, .. } => {
+                let mut pairs: Vec<(Value, Value)> = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    let key = self.eval_expr(entry.key)?;
+                    let value = self.eval_expr(entry.value)?;
+                    insert_entry(&mut pairs, key, value);
+                }
+                Ok(Value::dict(pairs))
+            }
+            Expr::Interpolation { parts, span } => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        InterpolationPart::Text(sym) => text.push_str(self.name(*sym)),
+                        InterpolationPart::Expr(expr) => {
+                            let value = self.eval_expr(expr)?;
+                            text.push_str(&self.describe(value, *span)?);
+                        }
+                    }
+                }
+                Ok(Value::String(text))
+            }
+            _ => unreachable!("not an aggregate expression"),
+        }
+    }
+
+    /// Evaluate a block, then the bodies it deferred, last deferred first.
+    ///
+    /// Deferred bodies run even if the block returns early or fails. One that
+    /// fails replaces the block's outcome, unless the block failed first.
+    fn eval_block(&mut self, stmts: &'a [Stmt<'a>], expr: Option<&'a Expr<'a>>) -> Flow<Value> {
+        self.scoped(|this| {
+            let mut deferred = Vec::new();
+            let mut result = this.run_block(stmts, expr, &mut deferred);
+            while let Some(body) = deferred.pop() {
+                if let Err(unwind) = this.eval_expr(body)
+                    && !matches!(result, Err(Unwind::Error(_)))
+                {
+                    result = Err(unwind);
+                }
+            }
+            result
+        })
+    }
+
+    fn run_block(
+        &mut self,
+        stmts: &'a [Stmt<'a>],
+        expr: Option<&'a Expr<'a>>,
+        deferred: &mut Vec<&'a Expr<'a>>,
+    ) -> Flow<Value> {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Defer { body, .. } => deferred.push(body),
+                stmt => self.exec_stmt(stmt)?,
+            }
+        }
+        expr.map_or(Ok(Value::Unit), |expr| {
+            if self.debugger.is_some() {
+                self.reach(expr.span());
+            }
+            self.eval_expr(expr)
+        })
+    }
+
+    fn exec_stmt(&mut self, stmt: &'a Stmt<'a>) -> Flow<()> {
+        self.tick(stmt.span())?;
+        if self.debugger.is_some() {
+            self.reach(stmt.span());
+        }
+        match stmt {
+            Stmt::Let { name, init, .. } | Stmt::Mut { name, init, .. } => {
+                let value = match init {
+                    Some(init) => self.eval_expr(init)?,
+                    None => Value::Nil,
+                };
+                self.env.define(*name, value, matches!(stmt, Stmt::Mut { .. }));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value)?,
+                    None => Value::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.eval_if(condition, then_branch, *else_branch)?;
+            }
+            Stmt::Guard { condition, else_branch, .. } => {
+                if !self.condition(condition)? {
+                    self.eval_expr(else_branch)?;
+                }
+            }
+            Stmt::Match { scrutinee, arms, span } => {
+                let value = self.eval_expr(scrutinee)?;
+                self.eval_match(&value, arms, *span)?;
+            }
+            Stmt::ForLoop { pattern, iter, body, span } => self.eval_for(pattern, iter, body, *span)?,
+            Stmt::WhileLoop { condition, body, span } => {
+                while self.condition(condition)? {
+                    self.tick(*span)?;
+                    self.eval_expr(body)?;
+                }
+            }
+            Stmt::Assign { target, value, span } => self.assign(target, value, *span)?,
+            // Blocks collect their deferred bodies before executing statements
+            Stmt::Defer { span, .. } => {
+                return Err(RuntimeError::Unsupported { construct: "a defer outside a block", span: *span }.into());
+            }
+            Stmt::Expr { expr, .. } => {
+                self.eval_expr(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `f` in a nested scope, leaving it even if `f` fails.
+    /// Tell the debugger execution reached `span`, pausing if it says to.
+    fn reach(&mut self, span: Span) {
+        let Some(mut session) = self.debugger.take() else { return };
+        let depth = self.frames.len() - 1;
+        let file = &self.modules[self.env.module()].file;
+        if let Some(reason) = session.reached(file, depth, span.start_line) {
+            let pause = Pause {
+                reason,
+                file,
+                span,
+                stack: self.call_stack(),
+                env: &self.env,
+                interner: self.ctx.interner,
+            };
+            session.pause(&pause, depth);
+        }
+        self.debugger = Some(session);
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> Flow<T>) -> Flow<T> {
+        self.env.push_scope();
+        let result = f(self);
+        self.env.pop_scope();
+        result
+    }
+
+    fn condition(&mut self, expr: &'a Expr<'a>) -> Flow<bool> {
+        match self.eval_expr(expr)? {
+            Value::Bool(value) => Ok(value),
+            other => {
+                Err(RuntimeError::TypeMismatch { expected: "a boolean", found: other.kind(), span: expr.span() }.into())
+            }
+        }
+    }
+
+    fn eval_if(&mut self, condition: &'a Expr<'a>, then_branch: &'a Expr<'a>, else_branch: Option<&'a Expr<'a>>) -> Flow<Value> {
+        if self.condition(condition)? {
+            self.eval_expr(then_branch)
+        } else {
+            else_branch.map_or(Ok(Value::Unit), |branch| self.eval_expr(branch))
+        }
+    }
+
+    fn eval_match(&mut self, value: &Value, arms: &'a [MatchArm<'a>], span: Span) -> Flow<Value> {
+        for arm in arms {
+            let result = self.scoped(|this| {
+                if !this.bind(&arm.pattern, value)? {
+                    return Ok(None);
+                }
+                if let Some(guard) = arm.guard
+                    && !this.condition(guard)?
+                {
+                    return Ok(None);
+                }
+                this.eval_expr(arm.body).map(Some)
+            })?;
+            if let Some(result) = result {
+                return Ok(result);
+            }
+        }
+        Err(RuntimeError::NoMatch { span }.into())
+    }
+
+    /// Run a loop body for each element of a sequence (see [`crate::iter`]).
+    fn eval_for(&mut self, pattern: &'a Pattern, iter: &'a Expr<'a>, body: &'a Expr<'a>, span: Span) -> Flow<()> {
+        let sequence = self.eval_expr(iter)?;
+        let iterator = self.make_iterator(sequence, span)?;
+        while let Some(item) = self.next_element(&iterator, span)? {
+            self.tick(span)?;
+            self.scoped(|this| {
+                if !this.bind(pattern, &item)? {
+                    return Err(RuntimeError::NoMatch { span }.into());
+                }
+                this.eval_expr(body).map(drop)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Get an iterator over a sequence: a built-in cursor, the result of the
+    /// sequence's `makeIterator()`, or the sequence itself if it is an
+    /// iterator.
+    fn make_iterator(&mut self, sequence: Value, span: Span) -> Flow<Value> {
+        if let Some(ty) = sequence.type_name() {
+            if self.lowered.resolve_method(Some(ty), MAKE_ITERATOR, &[], false).is_some() {
+                return self.send(sequence, MAKE_ITERATOR, &[], Vec::new(), span);
+            }
+            if self.lowered.resolve_method(Some(ty), NEXT, &[], false).is_some() {
+                return Ok(sequence);
+            }
+        }
+        match sequence {
+            Value::Iterator(_) => Ok(sequence),
+            sequence => match Cursor::new(&sequence) {
+                Some(cursor) => Ok(Value::Iterator(Rc::new(RefCell::new(cursor)))),
+                None => Err(RuntimeError::TypeMismatch { expected: "a sequence", found: sequence.kind(), span }.into()),
+            },
+        }
+    }
+
+    /// Advance an iterator. An optional `next()` result ends iteration when
+    /// it is `nil`.
+    fn next_element(&mut self, iterator: &Value, span: Span) -> Flow<Option<Value>> {
+        if let Value::Iterator(cursor) = iterator {
+            return Ok(cursor.borrow_mut().advance());
+        }
+        match self.send(iterator.clone(), NEXT, &[], Vec::new(), span)? {
+            Value::Nil => Ok(None),
+            Value::Variant(variant) if matches!(variant.enum_name.as_str(), "Option" | "Optional") => {
+                Ok(variant.payload.clone())
+            }
+            value => Ok(Some(value)),
+        }
+    }
+
+    fn literal(&self, expr: &Expr<'_>) -> Result<Value> {
+        const_value(eval_const(self.ctx, expr)?, expr.span())
+    }
+
+    /// Look a name up in scope, then among the fields of `self`.
+    fn lookup(&self, sym: Symbol, slot: Option<Slot>, span: Span) -> Result<Value> {
+        if let Some(binding) = self.env.binding_at(sym, slot) {
+            return Ok(binding.get());
+        }
+        let name = self.name(sym);
+        if let Some(Value::Object(this)) = &self.frame().receiver
+            && let Some(value) = this.field(name)
+        {
+            return Ok(value);
+        }
+        Err(RuntimeError::UndefinedVariable { name: name.to_string(), span })
+    }
+
+    /// Create a closure, capturing the variables the checker found it uses.
+    fn closure(&mut self, expr: &'a Expr<'a>, params: &'a [FnParam], body: &'a Expr<'a>, span: Span) -> Result<Value> {
+        let Some(info) = self.ctx.closures.get(&span) else {
+            return Err(RuntimeError::Unsupported { construct: "an unchecked closure", span });
+        };
+        let mut captures = Vec::with_capacity(info.captures.len());
+        let mut receiver = None;
+        for capture in &info.captures {
+            if Some(capture.name) == self.self_sym {
+                receiver = self.frame().receiver.clone();
+                continue;
+            }
+            // A variable the closure can assign to moves into a box both
+            // sides share; one that is not a local of this frame, such as a
+            // global, is found by name when the closure runs
+            let captured = match capture.mode {
+                CaptureMode::Box => self.env.share(capture.name, None).map(Captured::Box),
+                CaptureMode::Value => self.env.binding_at(capture.name, None).map(|binding| Captured::Value(binding.get())),
+            };
+            captures.extend(captured.map(|captured| (capture.name, captured)));
+        }
+        let address = std::ptr::from_ref(expr) as usize;
+        self.closures.insert(address, (params, body));
+        let frame = self.frame();
+        let closure = Closure { expr: address, captures, receiver, owner: frame.owner.clone(), module: frame.module };
+        Ok(Value::Closure(Rc::new(closure)))
+    }
+
+    /// Evaluate `Type::member`: a unit variant or a static method.
+    fn eval_path(&self, segments: &[Symbol], span: Span) -> Result<Value> {
+        let Some((member, type_path)) = segments.split_last() else {
+            return Err(RuntimeError::UndefinedVariable { name: String::new(), span });
+        };
+        if type_path.is_empty() {
+            return self.lookup(*member, None, span);
+        }
+        let (ty, member) = (self.type_name(type_path), self.name(*member));
+        if self.is_variant(&ty, member) {
+            return Ok(Value::variant(ty, member, None));
+        }
+        let method = self
+            .lowered
+            .class(&ty)
+            .and_then(|class| class.methods.iter().find(|method| method.is_static() && method.name == member));
+        match method {
+            Some(method) => Ok(Value::Function(method_symbol(&ty, &method.selector).into())),
+            None => Err(RuntimeError::UndefinedVariable { name: format!("{ty}::{member}"), span }),
+        }
+    }
+
+    fn labels(&self, args: &[CallArg<'_>]) -> Vec<Option<String>> {
+        args.iter().map(|arg| arg.label.map(|label| self.name(label).to_string())).collect()
+    }
+
+    fn eval_args(&mut self, args: &'a [CallArg<'a>]) -> Flow<Vec<Value>> {
+        args.iter().map(|arg| self.eval_expr(arg.value)).collect()
+    }
+
+    fn eval_send(&mut self, receiver: &'a Expr<'a>, method: Symbol, args: &'a [CallArg<'a>], span: Span) -> Flow<Value> {
+        let receiver = self.eval_expr(receiver)?;
+        let labels = self.labels(args);
+        let args = self.eval_args(args)?;
+        self.send(receiver, self.name(method), &labels, args, span)
+    }
+
+    fn eval_call(&mut self, callee: &'a Expr<'a>, args: &'a [CallArg<'a>], span: Span) -> Flow<Value> {
+        match callee {
+            // `Type(args)` creates an instance
+            Expr::Identifier(sym) if self.env.get(*sym).is_none() => {
+                let ty = self.type_name(&[*sym]);
+                if self.lowered.class(&ty).is_some() {
+                    return self.construct(&ty, args, span);
+                }
+            }
+            // `Enum::variant(payload)` or `Type::method(args)`
+            Expr::Path { segments, .. } if segments.len() > 1 => {
+                let (member, type_path) = segments.split_last().expect("paths have segments");
+                let (ty, member) = (self.type_name(type_path), self.name(*member));
+                if self.is_variant(&ty, member) {
+                    let payload = match self.eval_args(args)? {
+                        values if values.is_empty() => None,
+                        values if values.len() == 1 => values.into_iter().next(),
+                        values => Some(Value::Tuple(values.into())),
+                    };
+                    return Ok(Value::variant(ty, member, payload));
+                }
+                let labels = self.labels(args);
+                let Some(method) = self.lowered.resolve_method(Some(&ty), member, &labels, true) else {
+                    return Err(RuntimeError::UnknownMethod { ty, method: member.to_string(), span }.into());
+                };
+                let symbol = method_symbol(&ty, &method.selector);
+                let args = self.eval_args(args)?;
+                return self.call_function(&symbol, &labels, args, span);
+            }
+            _ => {}
+        }
+
+        let callee = self.eval_expr(callee)?;
+        let labels = self.labels(args);
+        let args = self.eval_args(args)?;
+        self.call_value(&callee, &labels, args, span)
+    }
+
+    /// Call a function or closure value.
+    fn call_value(&mut self, callee: &Value, labels: &[Option<String>], args: Vec<Value>, span: Span) -> Flow<Value> {
+        match callee {
+            Value::Function(name) => self.call_function(name, labels, args, span),
+            Value::Closure(closure) => self.call_closure(closure, args, span),
+            other => Err(RuntimeError::NotCallable { found: other.kind(), span }.into()),
+        }
+    }
+
+    /// Run a closure's body with its captured variables in scope.
+    fn call_closure(&mut self, closure: &Closure, args: Vec<Value>, span: Span) -> Flow<Value> {
+        let (params, body) = self.closures[&closure.expr];
+        if params.len() != args.len() {
+            return Err(RuntimeError::NoOverload { name: CLOSURE.to_string(), span }.into());
+        }
+        let frame = Frame {
+            function: CLOSURE.to_string(),
+            call_site: span,
+            owner: closure.owner.clone(),
+            receiver: closure.receiver.clone(),
+            module: closure.module,
+        };
+        self.invoke(params, body, frame, &closure.captures, args)
+    }
+
+    fn call_function(&mut self, name: &str, labels: &[Option<String>], args: Vec<Value>, span: Span) -> Flow<Value> {
+        if self.externs.contains_key(name) {
+            return self.call_extern(name, &args, span);
+        }
+        let Some(candidates) = self.functions.get(name) else {
+            // `print` writes the description its argument's type gives it
+            let args = match args.as_slice() {
+                [value] if name == PRINT => vec![Value::String(self.describe(value.clone(), span)?)],
+                _ => args,
+            };
+            return match self.builtins.get(name) {
+                Some(builtin) if builtin.arity() == args.len() && name == CATCH => self.catch(&args[0], span),
+                Some(builtin) if builtin.arity() == args.len() => {
+                    if let Some(capability) = builtin.capability {
+                        self.require(capability, span)?;
+                    }
+                    // The runtime enforces the sandbox on what the builtin does too,
+                    // and messages the builtin sends to the program's objects run
+                    // their methods
+                    let function = Rc::clone(&builtin.function);
+                    let _sandbox = self.sandbox.enter();
+                    let result = self.attach(|| function(&args, span));
+                    if let Some(err) = self.native_error.take() {
+                        return Err(err.into());
+                    }
+                    Ok(result?)
+                }
+                Some(_) => Err(RuntimeError::NoOverload { name: name.to_string(), span }.into()),
+                None => Err(RuntimeError::UndefinedVariable { name: name.to_string(), span }.into()),
+            };
+        };
+        let callable = candidates.iter().find(|callable| {
+            callable.labels.len() == labels.len()
+                && labels
+                    .iter()
+                    .zip(&callable.labels)
+                    .all(|(label, expected)| label.as_ref().is_none_or(|l| l == expected))
+        });
+        let Some(callable) = callable.cloned() else {
+            return Err(RuntimeError::NoOverload { name: name.to_string(), span }.into());
+        };
+        let module = callable.owner.as_deref().map_or(callable.module, |ty| self.type_module(ty));
+        let frame = Frame { function: callable.name, call_site: span, owner: callable.owner, receiver: None, module };
+        self.invoke(callable.params, callable.body, frame, &[], args)
+    }
+
+    /// Call a C function declared in an `extern` block, loading it on its
+    /// first call.
+    fn call_extern(&mut self, symbol: &str, args: &[Value], span: Span) -> Flow<Value> {
+        self.require(Capability::Ffi, span)?;
+        let Some(external) = self.externs.get_mut(symbol) else {
+            return Err(RuntimeError::UndefinedVariable { name: symbol.to_string(), span }.into());
+        };
+        let failed = |error| RuntimeError::ForeignCall { function: external.name.clone(), error, span };
+        let function = match &external.loaded {
+            Some(function) => Rc::clone(function),
+            None => {
+                let library = Library::open(external.library.as_deref()).map_err(failed)?;
+                let function = Rc::new(library.function(&external.name, &external.encoding).map_err(failed)?);
+                external.loaded = Some(Rc::clone(&function));
+                function
+            }
+        };
+        let args = args.iter().map(|arg| native::to_foreign(arg, span)).collect::<Result<Vec<_>>>()?;
+        // SAFETY: the program declared the function's signature, and the
+        // checker kept it to types C has; running native code is what the
+        // sandbox's FFI capability allows
+        let value = unsafe { function.call(&args) }.map_err(failed)?;
+        Ok(native::from_foreign(value))
+    }
+
+    /// Call `body` without arguments, turning an error it raises into
+    /// `Result::Err`.
+    fn catch(&mut self, body: &Value, span: Span) -> Flow<Value> {
+        match self.call_value(body, &[], Vec::new(), span) {
+            Ok(value) => Ok(Value::variant("Result", "Ok", Some(value))),
+            Err(Unwind::Error(err)) if err.is_catchable() => Ok(Value::variant("Result", "Err", Some(err.to_value()))),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    /// Send a message to a value, dispatching on its dynamic type.
+    fn send(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        labels: &[Option<String>],
+        args: Vec<Value>,
+        span: Span,
+    ) -> Flow<Value> {
+        let Some(ty) = receiver.type_name().map(str::to_string) else {
+            // Built-in sequences and their iterators conform to the
+            // iteration protocol
+            match (method, &receiver) {
+                (MAKE_ITERATOR, _) if args.is_empty() && Cursor::new(&receiver).is_some() => {
+                    return self.make_iterator(receiver, span);
+                }
+                (NEXT, Value::Iterator(cursor)) if args.is_empty() => {
+                    return Ok(cursor.borrow_mut().advance().unwrap_or(Value::Nil));
+                }
+                _ => {}
+            }
+            return Err(RuntimeError::UnknownMethod { ty: receiver.kind().to_string(), method: method.to_string(), span }
+                .into());
+        };
+        let Some(found) = self.lowered.resolve_method(Some(&ty), method, labels, false) else {
+            // The runtime class may respond to methods defined natively
+            if let Value::Object(instance) = &receiver
+                && let Some(value) = self.send_native(instance, method, labels, &args, span)?
+            {
+                return Ok(value);
+            }
+            return Err(RuntimeError::UnknownMethod { ty, method: method.to_string(), span }.into());
+        };
+        let function = format!("{ty}.{method}");
+        let module = self.type_module(&ty);
+        let frame = Frame { function, call_site: span, owner: Some(ty), receiver: Some(receiver), module };
+        self.invoke(&found.decl.params, found.decl.body, frame, &[], args)
+    }
+
+    /// Send a message through the runtime, for methods the program does
+    /// not define. Returns `None` if the object does not respond.
+    fn send_native(
+        &mut self,
+        instance: &Instance,
+        method: &str,
+        labels: &[Option<String>],
+        args: &[Value],
+        span: Span,
+    ) -> Flow<Option<Value>> {
+        let labels: Vec<String> = labels.iter().map(|label| label.clone().unwrap_or_default()).collect();
+        let selector = Selector::from_str(&selector_name(method, &labels)).map_err(RuntimeError::from)?;
+        let class = instance.object.class();
+        let Some(encoding) = class.lookup_method(&selector).and_then(|method| method.types.as_str().ok()) else {
+            return Ok(None);
+        };
+        self.require(Capability::Ffi, span)?;
+        let words = args.iter().map(|arg| native::encode(arg, span)).collect::<Result<Vec<_>>>()?;
+        let args = match words[..] {
+            [] => MessageArgs::None,
+            [a] => MessageArgs::one(a),
+            [a, b] => MessageArgs::two(a, b),
+            [a, b, c] => MessageArgs::three([a, b, c]),
+            [a, b, c, d] => MessageArgs::four([a, b, c, d]),
+            _ => {
+                let construct = "a native message with more than four arguments";
+                return Err(RuntimeError::Unsupported { construct, span }.into());
+            }
+        };
+
+        let word = self.attach(|| instance.object.send_message(&selector, &args)).map_err(RuntimeError::from)?;
+        if let Some(err) = self.native_error.take() {
+            return Err(err.into());
+        }
+        match native::decode_return(encoding, word, |address| self.object_at(address)) {
+            Some(value) => Ok(Some(value)),
+            None => Err(RuntimeError::Unsupported { construct: "a native return value of this type", span }.into()),
+        }
+    }
+
+    /// Look up a live instance by object address.
+    fn object_at(&self, address: usize) -> Option<Value> {
+        self.instances.get(&address)?.upgrade().map(Value::Object)
+    }
+
+    /// Run a body in a new frame with a closure's `captures` and its
+    /// parameters bound to `args`.
+    ///
+    /// An error leaving the body is tagged with the calls active when it was
+    /// raised, unless a callee already tagged it.
+    fn invoke(
+        &mut self,
+        params: &'a [FnParam],
+        body: &'a Expr<'a>,
+        frame: Frame,
+        captures: &[(Symbol, Captured)],
+        args: Vec<Value>,
+    ) -> Flow<Value> {
+        self.tick(frame.call_site)?;
+        if let Some(max) = self.limits.max_depth.filter(|max| self.frames.len() > *max) {
+            return Err(RuntimeError::Interrupted { cause: Interrupt::Depth(max), span: frame.call_site }.into());
+        }
+        self.env.push_frame();
+        if let (Some(receiver), Some(sym)) = (&frame.receiver, self.self_sym) {
+            self.env.define(sym, receiver.clone(), false);
+        }
+        for (name, captured) in captures {
+            match captured {
+                Captured::Value(value) => self.env.define(*name, value.clone(), false),
+                Captured::Box(cell) => self.env.define_shared(*name, Rc::clone(cell), true),
+            }
+        }
+        for (param, arg) in params.iter().zip(args) {
+            self.env.define(param.name, arg, false);
+        }
+        let receiver = self.self_sym.filter(|_| frame.receiver.is_some());
+        let captured = captures.iter().map(|(name, _)| *name);
+        let bound = receiver.into_iter().chain(captured).chain(params.iter().map(|param| param.name));
+        let slots = self
+            .resolutions
+            .entry(std::ptr::from_ref(body) as usize)
+            .or_insert_with(|| Rc::new(Resolution::function(bound, body)));
+        let slots = Rc::clone(slots);
+        let caller = self.env.enter_module(frame.module);
+        self.frames.push(frame);
+        self.slots.push(slots);
+        let result = match self.eval_expr(body) {
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) if !matches!(*error, RuntimeError::Traced { .. }) => {
+                Err(Unwind::from(RuntimeError::Traced { error, trace: self.call_stack() }))
+            }
+            result => result,
+        };
+        self.slots.pop();
+        self.frames.pop();
+        self.env.enter_module(caller);
+        self.env.pop_frame();
+        result
+    }
+
+    /// Create an instance and run the initializer the arguments select.
+    fn construct(&mut self, ty: &str, args: &'a [CallArg<'a>], span: Span) -> Flow<Value> {
+        let instance = Value::Object(self.instantiate(ty, span)?);
+        let labels = self.labels(args);
+        match self.lowered.resolve_method(Some(ty), "init", &labels, false) {
+            Some(init) => {
+                let args = self.eval_args(args)?;
+                let frame = Frame {
+                    function: format!("{ty}.init"),
+                    call_site: span,
+                    owner: Some(ty.to_string()),
+                    receiver: Some(instance.clone()),
+                    module: self.type_module(ty),
+                };
+                self.invoke(&init.decl.params, init.decl.body, frame, &[], args)?;
+            }
+            None if !args.is_empty() => {
+                return Err(RuntimeError::NoOverload { name: ty.to_string(), span }.into());
+            }
+            None => {}
+        }
+        Ok(instance)
+    }
+
+    /// Evaluate a struct literal, or a struct variant `Enum::Variant { .. }`.
+    fn eval_struct(&mut self, type_path: &[Symbol], fields: &'a [StructField<'a>], span: Span) -> Flow<Value> {
+        let mut values = Vec::with_capacity(fields.len());
+        for field in fields {
+            let value = match field.value {
+                Some(value) => self.eval_expr(value)?,
+                None => self.lookup(field.name, None, field.span)?,
+            };
+            values.push((self.name(field.name), value));
+        }
+
+        if let Some((variant, enum_path)) = type_path.split_last()
+            && !enum_path.is_empty()
+        {
+            let (ty, variant) = (self.type_name(enum_path), self.name(*variant));
+            if let Some(order) = self.variant_fields(&ty, variant) {
+                let mut payload = Vec::with_capacity(order.len());
+                for field in order {
+                    let field = self.name(field);
+                    match values.iter().find(|(name, _)| *name == field) {
+                        Some((_, value)) => payload.push(value.clone()),
+                        None => {
+                            let ty = format!("{ty}::{variant}");
+                            return Err(RuntimeError::UnknownField { ty, field: field.to_string(), span }.into());
+                        }
+                    }
+                }
+                return Ok(Value::variant(ty, variant, Some(Value::Tuple(payload.into()))));
+            }
+        }
+
+        let ty = self.type_name(type_path);
+        let instance = self.instantiate(&ty, span)?;
+        for (name, value) in values {
+            instance.set_field(name, value);
+        }
+        Ok(Value::Object(instance))
+    }
+
+    fn unary(&mut self, op: UnaryOp, value: Value, span: Span) -> Flow<Value> {
+        if value.type_name().is_some()
+            && let Some((method, _)) = unary_bound(&op).and_then(|bound| bound.method())
+        {
+            return self.send(value, method, &[], Vec::new(), span);
+        }
+        match (op, value) {
+            (UnaryOp::Negate, Value::Bool(value)) => Ok(Value::Bool(!value)),
+            (UnaryOp::Minus, Value::Int(value)) => {
+                Ok(Value::Int(value.checked_neg().ok_or(RuntimeError::IntegerOverflow { span })?))
+            }
+            (UnaryOp::Minus, Value::Float(value)) => Ok(Value::Float(-value)),
+            (UnaryOp::Negate, other) => {
+                Err(RuntimeError::TypeMismatch { expected: "a boolean", found: other.kind(), span }.into())
+            }
+            (UnaryOp::Minus, other) => {
+                Err(RuntimeError::TypeMismatch { expected: "a number", found: other.kind(), span }.into())
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: Value, right: Value, span: Span) -> Flow<Value> {
+        // Arithmetic on user types calls the method implementing the operator
+        if left.type_name().is_some()
+            && let Some((method, _)) = binary_bound(&op).and_then(|bound| bound.method())
+        {
+            return self.send(left, method, &[None], vec![right], span);
+        }
+
+        let overflow = RuntimeError::IntegerOverflow { span };
+        let value = match (op, &left, &right) {
+            (BinaryOp::Eq, _, _) => Value::Bool(self.equal(&left, &right)),
+            (BinaryOp::Neq, _, _) => Value::Bool(!self.equal(&left, &right)),
+            (BinaryOp::Div | BinaryOp::Mod, Value::Int(_), Value::Int(0)) => {
+                return Err(RuntimeError::DivisionByZero { span }.into());
+            }
+            (BinaryOp::Add, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_add(*b).ok_or(overflow)?),
+            (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_sub(*b).ok_or(overflow)?),
+            (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_mul(*b).ok_or(overflow)?),
+            (BinaryOp::Div, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_div(*b).ok_or(overflow)?),
+            (BinaryOp::Mod, Value::Int(a), Value::Int(b)) => Value::Int(a.checked_rem(*b).ok_or(overflow)?),
+            (BinaryOp::Add, Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+            (BinaryOp::Sub, Value::Float(a), Value::Float(b)) => Value::Float(a - b),
+            (BinaryOp::Mul, Value::Float(a), Value::Float(b)) => Value::Float(a * b),
+            (BinaryOp::Div, Value::Float(a), Value::Float(b)) => Value::Float(a / b),
+            (BinaryOp::Mod, Value::Float(a), Value::Float(b)) => Value::Float(a % b),
+            (BinaryOp::Add, Value::String(a), Value::String(b)) => Value::String(format!("{a}{b}")),
+            (BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte, _, _) => {
+                let ordering = compare(&left, &right).ok_or_else(|| RuntimeError::TypeMismatch {
+                    expected: "comparable values",
+                    found: right.kind(),
+                    span,
+                })?;
+                Value::Bool(match op {
+                    BinaryOp::Lt => ordering == Ordering::Less,
+                    BinaryOp::Lte => ordering != Ordering::Greater,
+                    BinaryOp::Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                })
+            }
+            _ => {
+                let expected = if matches!(left, Value::String(_)) { "a string" } else { "numbers of one type" };
+                return Err(RuntimeError::TypeMismatch { expected, found: right.kind(), span }.into());
+            }
+        };
+        Ok(value)
+    }
+
+    /// Compare values for `==`: structs by their fields, other instances by
+    /// identity, and everything else structurally.
+    fn equal(&self, left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Object(a), Value::Object(b))
+                if a.class == b.class
+                    && self.lowered.class(&a.class).is_some_and(|class| class.kind == TypeKind::Struct) =>
+            {
+                let (a, b) = (a.fields.borrow(), b.fields.borrow());
+                a.len() == b.len()
+                    && a.iter().all(|(name, value)| {
+                        b.iter().any(|(other, other_value)| other == name && self.equal(value, other_value))
+                    })
+            }
+            _ => left == right,
+        }
+    }
+
+    /// Describe a value for interpolation, using its `description()` when
+    /// its type implements one.
+    fn describe(&mut self, value: Value, span: Span) -> Flow<String> {
+        if self.implements(&value, "description") {
+            return Ok(self.send(value, "description", &[], Vec::new(), span)?.to_string());
+        }
+        match value {
+            Value::String(text) => Ok(text),
+            value => self.default_description(value, span),
+        }
+    }
+
+    /// Describe a value for debugging, preferring its `debugDescription()`
+    /// to its `description()`.
+    fn debug_describe(&mut self, value: Value, span: Span) -> Flow<String> {
+        for method in ["debugDescription", "description"] {
+            if self.implements(&value, method) {
+                return Ok(self.send(value, method, &[], Vec::new(), span)?.to_string());
+            }
+        }
+        self.default_description(value, span)
+    }
+
+    /// Whether the type of a value has an instance method without arguments.
+    fn implements(&self, value: &Value, method: &str) -> bool {
+        value.type_name().is_some_and(|ty| self.lowered.resolve_method(Some(ty), method, &[], false).is_some())
+    }
+
+    /// Describe a value whose type has no description of its own: an
+    /// instance by its class and the fields its runtime class declares,
+    /// arrays and tuples by their elements, each described for debugging.
+    fn default_description(&mut self, value: Value, span: Span) -> Flow<String> {
+        match &value {
+            Value::Object(instance) => {
+                // Fields in the order their classes declare them, rather than
+                // the order they were set in
+                let ivars = instance_variables(&instance.object.class());
+                let position = |field: &str| ivars.iter().position(|ivar| ivar.name.as_str().ok() == Some(field));
+                let mut fields = instance.fields.borrow().clone();
+                fields.sort_by_key(|(field, _)| position(field).unwrap_or(usize::MAX));
+                let mut described = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    described.push(format!("{field}: {}", self.debug_describe(value, span)?));
+                }
+                Ok(format!("{}({})", instance.class, described.join(", ")))
+            }
+            Value::Array(elements) => {
+                let elements = elements.borrow().clone();
+                Ok(format!("[{}]", self.describe_elements(&elements, span)?))
+            }
+            Value::Tuple(elements) => Ok(format!("({})", self.describe_elements(elements, span)?)),
+            _ => Ok(format!("{value:#}")),
+        }
+    }
+
+    /// Describe elements for debugging, separated by commas.
+    fn describe_elements(&mut self, elements: &[Value], span: Span) -> Flow<String> {
+        let mut described = Vec::with_capacity(elements.len());
+        for element in elements {
+            described.push(self.debug_describe(element.clone(), span)?);
+        }
+        Ok(described.join(", "))
+    }
+
+    fn assign(&mut self, target: &'a Expr<'a>, value: &'a Expr<'a>, span: Span) -> Flow<()> {
+        let value = self.eval_expr(value)?;
+        self.store(target, value, span)
+    }
+
+    fn store(&mut self, target: &'a Expr<'a>, value: Value, span: Span) -> Flow<()> {
+        match target {
+            Expr::Identifier(sym) => match self.env.assign_at(*sym, self.slot(target), value.clone()) {
+                Ok(()) => Ok(()),
+                Err(AssignError::Immutable) => {
+                    Err(RuntimeError::ImmutableAssignment { name: self.name(*sym).to_string(), span }.into())
+                }
+                Err(AssignError::Undefined) => {
+                    // Inside a method, a bare field name assigns to `self`
+                    let name = self.name(*sym);
+                    match &self.frame().receiver {
+                        Some(Value::Object(this)) if self.lowered.ivar(&this.class, name).is_some() => {
+                            this.set_field(name, value);
+                            self.collector.track(&Value::Object(Rc::clone(this)));
+                            Ok(())
+                        }
+                        _ => Err(RuntimeError::UndefinedVariable { name: name.to_string(), span }.into()),
+                    }
+                }
+            },
+            Expr::Field { object, field, span } => match self.eval_expr(object)? {
+                Value::Object(instance) => {
+                    instance.set_field(self.name(*field), value);
+                    self.collector.track(&Value::Object(instance));
+                    Ok(())
+                }
+                other => {
+                    Err(RuntimeError::TypeMismatch { expected: "an object", found: other.kind(), span: *span }.into())
+                }
+            },
+            Expr::Index { collection, index, span } => {
+                let collection = self.eval_expr(collection)?;
+                let index = self.eval_expr(index)?;
+                set_index(&collection, index, value, *span)?;
+                self.collector.track(&collection);
+                Ok(())
+            }
+            Expr::Paren { expr, .. } => self.store(expr, value, span),
+            _ => Err(RuntimeError::Unsupported { construct: "assignment to this expression", span }.into()),
+        }
+    }
+
+    /// Match a value against a pattern, binding its variables in the
+    /// current scope.
+    fn bind(&mut self, pattern: &Pattern, value: &Value) -> Result<bool> {
+        match pattern {
+            Pattern::Wildcard { .. } => Ok(true),
+            Pattern::Variable { name, mutable, .. } => {
+                self.env.define(*name, value.clone(), *mutable);
+                Ok(true)
+            }
+            Pattern::Literal { value: token, span } => Ok(self.pattern_literal(token, *span)? == *value),
+            Pattern::Tuple { elements, .. } => match value {
+                Value::Tuple(values) if values.len() == elements.len() => self.bind_all(elements, values),
+                _ => Ok(false),
+            },
+            Pattern::Array { elements, rest, .. } => {
+                let Value::Array(values) = value else {
+                    return Ok(false);
+                };
+                let values = values.borrow().clone();
+                let fits = if rest.is_some() { values.len() >= elements.len() } else { values.len() == elements.len() };
+                if !fits || !self.bind_all(elements, &values[..elements.len()])? {
+                    return Ok(false);
+                }
+                match rest {
+                    Some(rest) => self.bind(rest, &Value::array(values[elements.len()..].to_vec())),
+                    None => Ok(true),
+                }
+            }
+            Pattern::Or { left, right, .. } => Ok(self.bind(left, value)? || self.bind(right, value)?),
+            Pattern::Enum { type_path, variant, payload, .. } => {
+                let variant = self.name(*variant);
+                match value {
+                    Value::Variant(found)
+                        if found.variant == variant && self.names_enum(type_path, &found.enum_name) =>
+                    {
+                        match (payload, &found.payload) {
+                            (Some(pattern), Some(value)) => self.bind(pattern, value),
+                            (Some(_), None) => Ok(false),
+                            (None, _) => Ok(true),
+                        }
+                    }
+                    Value::Variant(_) => Ok(false),
+                    // Optionals are plain values or `nil`
+                    Value::Nil => Ok(variant == "None" && self.names_enum(type_path, "Option")),
+                    other if variant == "Some" && self.names_enum(type_path, "Option") => {
+                        payload.as_ref().map_or(Ok(true), |pattern| self.bind(pattern, other))
+                    }
+                    _ => Ok(false),
+                }
+            }
+            Pattern::Struct { type_path, fields, .. } => {
+                if let Some((variant, enum_path)) = type_path.split_last()
+                    && !enum_path.is_empty()
+                    && let Value::Variant(found) = value
+                {
+                    let variant = self.name(*variant);
+                    if found.variant != variant || !self.names_enum(enum_path, &found.enum_name) {
+                        return Ok(false);
+                    }
+                    let order = self.variant_fields(&found.enum_name, variant).unwrap_or_default();
+                    let payload = match &found.payload {
+                        Some(Value::Tuple(values)) => values.to_vec(),
+                        Some(value) => vec![value.clone()],
+                        None => Vec::new(),
+                    };
+                    for field in fields {
+                        let Some(value) = order.iter().position(|name| *name == field.name).and_then(|i| payload.get(i))
+                        else {
+                            return Ok(false);
+                        };
+                        if !self.bind_field(field.name, field.pattern.as_deref(), value)? {
+                            return Ok(false);
+                        }
+                    }
+                    return Ok(true);
+                }
+
+                let Value::Object(instance) = value else {
+                    return Ok(false);
+                };
+                if !self.is_subclass(&instance.class, &self.type_name(type_path)) {
+                    return Ok(false);
+                }
+                for field in fields {
+                    let Some(value) = instance.field(self.name(field.name)) else {
+                        return Ok(false);
+                    };
+                    if !self.bind_field(field.name, field.pattern.as_deref(), &value)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn bind_all(&mut self, patterns: &[Pattern], values: &[Value]) -> Result<bool> {
+        for (pattern, value) in patterns.iter().zip(values) {
+            if !self.bind(pattern, value)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Match a field against its pattern; `{ x }` binds the field to `x`.
+    fn bind_field(&mut self, name: Symbol, pattern: Option<&Pattern>, value: &Value) -> Result<bool> {
+        match pattern {
+            Some(pattern) => self.bind(pattern, value),
+            None => {
+                self.env.define(name, value.clone(), false);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Whether a pattern's type path names an enum. An omitted path matches
+    /// any enum.
+    fn names_enum(&self, type_path: &[Symbol], enum_name: &str) -> bool {
+        if type_path.is_empty() {
+            return true;
+        }
+        match self.type_name(type_path).as_str() {
+            "Optional" | "Option" => matches!(enum_name, "Option" | "Optional"),
+            name => name == enum_name,
+        }
+    }
+
+    fn pattern_literal(&self, token: &TokenKind, span: Span) -> Result<Value> {
+        let expr = match token {
+            TokenKind::Nil => return Ok(Value::Nil),
+            TokenKind::BoolLiteral(value) => return Ok(Value::Bool(*value)),
+            TokenKind::IntegerLiteral(value, type_suffix) => {
+                Expr::IntegerLiteral { value: *value, type_suffix: *type_suffix, span }
+            }
+            TokenKind::FloatLiteral(value, type_suffix) => {
+                Expr::FloatLiteral { value: *value, type_suffix: *type_suffix, span }
+            }
+            TokenKind::StringLiteral(value) => Expr::StringLiteral { value: *value, span },
+            _ => {
+                return Err(RuntimeError::Unsupported { construct: "this literal pattern", span });
+            }
+        };
+        self.literal(&expr)
+    }
+}
+
+/// Messages from native code run the lowered method the selector names.
+impl Receiver for Interpreter<'_, '_> {
+    fn receive(&mut self, receiver: usize, selector: &Selector, args: &[usize]) -> Option<usize> {
+        let Some(Value::Object(instance)) = self.object_at(receiver) else {
+            return None;
+        };
+        let lowered = self.lowered;
+        let method = lowered.method(&instance.class, selector.name())?;
+        let span = method.decl.span;
+        let args = method.params.iter().zip(args).map(|(ty, word)| native::decode(ty, *word, |a| self.object_at(a)));
+        let Some(args) = args.collect::<Option<Vec<_>>>() else {
+            let construct = "a native argument of this type";
+            self.native_error = Some(RuntimeError::Unsupported { construct, span });
+            return None;
+        };
+
+        let frame = Frame {
+            function: format!("{}.{}", instance.class, method.name),
+            call_site: span,
+            owner: Some(instance.class.clone()),
+            module: self.type_module(&instance.class),
+            receiver: Some(Value::Object(instance)),
+        };
+        let result = settle(self.invoke(&method.decl.params, method.decl.body, frame, &[], args));
+        match result.and_then(|value| native::encode(&value, span)) {
+            Ok(word) => Some(word),
+            Err(err) => {
+                self.native_error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+/// Insert a dictionary entry, replacing the value of an existing key.
+fn insert_entry(entries: &mut Vec<(Value, Value)>, key: Value, value: Value) {
+    match entries.iter_mut().find(|(existing, _)| *existing == key) {
+        Some((_, slot)) => *slot = value,
+        None => entries.push((key, value)),
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn get_field(object: &Value, field: &str, span: Span) -> Result<Value> {
+    match object {
+        Value::Object(instance) => instance
+            .field(field)
+            .ok_or_else(|| RuntimeError::UnknownField { ty: instance.class.clone(), field: field.to_string(), span }),
+        Value::Tuple(elements) => field
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| elements.get(index).cloned())
+            .ok_or_else(|| RuntimeError::UnknownField { ty: "tuple".to_string(), field: field.to_string(), span }),
+        other => Err(RuntimeError::TypeMismatch { expected: "an object", found: other.kind(), span }),
+    }
+}
+
+/// Check an array index against the array's length.
+fn array_index(index: &Value, len: usize, span: Span) -> Result<usize> {
+    match index {
+        Value::Int(i) => usize::try_from(*i)
+            .ok()
+            .filter(|i| *i < len)
+            .ok_or(RuntimeError::IndexOutOfBounds { index: *i, len, span }),
+        other => Err(RuntimeError::TypeMismatch { expected: "an integer", found: other.kind(), span }),
+    }
+}
+
+fn get_index(collection: &Value, index: &Value, span: Span) -> Result<Value> {
+    match collection {
+        Value::Array(elements) => {
+            let elements = elements.borrow();
+            Ok(elements[array_index(index, elements.len(), span)?].clone())
+        }
+        Value::Dict(entries) => entries
+            .borrow()
+            .iter()
+            .find(|(key, _)| key == index)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| RuntimeError::MissingKey { key: format!("{index:#}"), span }),
+        Value::Range(start, end) => {
+            let len = usize::try_from(end.saturating_sub(*start)).unwrap_or(0);
+            let offset = array_index(index, len, span)?;
+            let offset = i64::try_from(offset).map_err(|_| RuntimeError::IntegerOverflow { span })?;
+            Ok(Value::Int(start + offset))
+        }
+        other => Err(RuntimeError::TypeMismatch { expected: "an array or a dictionary", found: other.kind(), span }),
+    }
+}
+
+fn set_index(collection: &Value, index: Value, value: Value, span: Span) -> Result<()> {
+    match collection {
+        Value::Array(elements) => {
+            let mut elements = elements.borrow_mut();
+            let index = array_index(&index, elements.len(), span)?;
+            elements[index] = value;
+            Ok(())
+        }
+        Value::Dict(entries) => {
+            insert_entry(&mut entries.borrow_mut(), index, value);
+            Ok(())
+        }
+        other => Err(RuntimeError::TypeMismatch { expected: "an array or a dictionary", found: other.kind(), span }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_codegen::lower;
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::ast::decl::{EnumVariant, FnDecl, StructField as FieldDecl, Visibility};
+    use oxidex_syntax::ast::ty::Type;
+    use std::time::Duration;
+
+    #[test]
+    fn test_functions_recurse_and_report_errors() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["fact", "n", "Int", "1", "2", "0", "x"].iter().map(|n| interner.intern(n)).collect();
+        let [fact, n, int_sym, one_sym, two_sym, zero_sym, x] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // fn fact(n: Int) -> Int { if n < 2 { return 1 }; n * fact(n - 1) }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let literal = |value| Expr::IntegerLiteral { value, type_suffix: None, span };
+        let (one, two, zero) = (literal(one_sym), literal(two_sym), literal(zero_sym));
+        let (n_expr, fact_expr) = (Expr::Identifier(n), Expr::Identifier(fact));
+        let binary = |left, op, right| Expr::Binary { left, op, right, span };
+        let small = binary(&n_expr, BinaryOp::Lt, &two);
+        let early = Expr::Block { stmts: vec![Stmt::Return { value: Some(&one), span }], expr: None, span };
+        let minus_one = binary(&n_expr, BinaryOp::Sub, &one);
+        let call_site = Span::new(40, 52, 2, 9, 2, 21);
+        let recurse = Expr::Call {
+            callee: &fact_expr,
+            args: vec![CallArg { label: None, value: &minus_one, span }],
+            span: call_site,
+        };
+        let product = binary(&n_expr, BinaryOp::Mul, &recurse);
+        let body = Expr::Block {
+            stmts: vec![Stmt::If { condition: &small, then_branch: &early, else_branch: None, span }],
+            expr: Some(&product),
+            span,
+        };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: fact,
+            generics: vec![],
+            params: vec![FnParam { label: None, name: n, type_annotation: int.clone(), span }],
+            return_type: Some(int),
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+        let quotient = binary(&one, BinaryOp::Div, &zero);
+        let x_expr = Expr::Identifier(x);
+        let (let_x, mut_x) = (
+            Stmt::Let { name: x, type_annotation: None, init: Some(&one), span },
+            Stmt::Mut { name: x, type_annotation: None, init: Some(&one), span },
+        );
+        let assign = Stmt::Assign { target: &x_expr, value: &two, span };
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+        assert_eq!(interp.call("fact", vec![Value::Int(10)]).unwrap(), Value::Int(3_628_800));
+        assert_eq!(interp.env().depth(), 1);
+
+        // Errors leave every frame and scope they entered, and record the
+        // calls that were active: `fact(21)` overflows nine calls deep
+        let err = interp.call("fact", vec![Value::Int(30)]).unwrap_err();
+        assert!(matches!(err.untraced(), RuntimeError::IntegerOverflow { .. }));
+        assert_eq!(interp.env().depth(), 1);
+        assert!(interp.call_stack().is_empty());
+        let trace = err.trace();
+        assert_eq!(trace.len(), 10);
+        assert!(trace.iter().all(|frame| frame.function == "fact"));
+        assert_eq!(trace[0].call_site, call_site);
+        assert_eq!(trace[9].call_site, span);
+
+        let diagnostic = err.diagnostic();
+        assert_eq!(diagnostic.message, "integer overflow");
+        assert_eq!(diagnostic.notes.len(), 10);
+        assert_eq!(diagnostic.notes[0].message, "in `fact`, called here");
+        assert_eq!(diagnostic.notes[0].span, call_site);
+
+        // Errors outside any call have no trace
+        let err = interp.eval(&quotient).unwrap_err();
+        assert!(matches!(err, RuntimeError::DivisionByZero { .. }));
+        assert!(err.diagnostic().notes.is_empty());
+
+        // Bindings are immutable unless declared with `mut`
+        interp.exec(&let_x).unwrap();
+        assert!(matches!(interp.exec(&assign), Err(RuntimeError::ImmutableAssignment { .. })));
+        interp.exec(&mut_x).unwrap();
+        interp.exec(&assign).unwrap();
+        assert_eq!(interp.eval(&x_expr).unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_objects_dispatch_methods_and_enums_match() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = [
+            "EvalCounter", "count", "bump", "by", "step", "twice", "start", "self", "Int", "c", "EvalShape",
+            "Circle", "Empty", "size", "s", "r", "5", "0",
+        ]
+        .iter()
+        .map(|n| interner.intern(n))
+        .collect();
+        let [
+            counter, count, bump, by, step, twice, start, self_sym, int_sym, c, shape, circle, empty, size, s, r,
+            five_sym, zero_sym,
+        ] = names[..]
+        else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let (int, shape_ty) = (Type::Simple { name: int_sym, span }, Type::Simple { name: shape, span });
+        let (count_expr, step_expr, start_expr, self_expr) =
+            (Expr::Identifier(count), Expr::Identifier(step), Expr::Identifier(start), Expr::Identifier(self_sym));
+
+        // init(start: Int) { count = start }
+        let init_body = Expr::Block {
+            stmts: vec![Stmt::Assign { target: &count_expr, value: &start_expr, span }],
+            expr: None,
+            span,
+        };
+        // mut fn bump(by step: Int) { count = count + step }
+        let sum = Expr::Binary { left: &count_expr, op: BinaryOp::Add, right: &step_expr, span };
+        let bump_body = Expr::Block {
+            stmts: vec![Stmt::Assign { target: &count_expr, value: &sum, span }],
+            expr: None,
+            span,
+        };
+        // fn twice() { self.bump(count) }
+        let call = Expr::MethodCall {
+            receiver: &self_expr,
+            method: bump,
+            args: vec![CallArg { label: None, value: &count_expr, span }],
+            span,
+        };
+        let method = |name: Option<Symbol>, params, body| FnDecl {
+            is_mut: true,
+            is_init: name.is_none(),
+            is_static: false,
+            name,
+            generics: vec![],
+            params,
+            return_type: None,
+            body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let param = |label, name| FnParam { label, name, type_annotation: int.clone(), span };
+
+        // fn size(s: EvalShape) -> Int { match s { EvalShape::Circle(r) => r, EvalShape::Empty => 0 } }
+        let (s_expr, r_expr) = (Expr::Identifier(s), Expr::Identifier(r));
+        let zero = Expr::IntegerLiteral { value: zero_sym, type_suffix: None, span };
+        let variant = |variant, payload| Pattern::Enum { type_path: vec![shape], variant, payload, span };
+        let r_pat = Pattern::Variable { name: r, mutable: false, span };
+        let size_body = Expr::Match {
+            scrutinee: &s_expr,
+            arms: vec![
+                MatchArm { pattern: variant(circle, Some(Box::new(r_pat))), guard: None, body: &r_expr, span },
+                MatchArm { pattern: variant(empty, None), guard: None, body: &zero, span },
+            ],
+            span,
+        };
+
+        let decls = vec![
+            Decl::Class {
+                name: counter,
+                generics: vec![],
+                superclass: None,
+                fields: vec![FieldDecl { name: count, type_annotation: int.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![counter],
+                protocol: None,
+                methods: vec![
+                    method(None, vec![param(None, start)], &init_body),
+                    method(Some(bump), vec![param(Some(by), step)], &bump_body),
+                    method(Some(twice), vec![], &call),
+                ],
+                span,
+            },
+            Decl::Enum {
+                name: shape,
+                generics: vec![],
+                variants: vec![
+                    EnumVariant::Tuple { name: circle, fields: vec![int.clone()], span },
+                    EnumVariant::Unit { name: empty, span },
+                ],
+                methods: vec![],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Fn {
+                is_mut: false,
+                is_init: false,
+                is_static: false,
+                name: size,
+                generics: vec![],
+                params: vec![FnParam { label: None, name: s, type_annotation: shape_ty, span }],
+                return_type: Some(int.clone()),
+                body: &size_body,
+                visibility: Visibility::Private,
+                attributes: Vec::new(),
+                span,
+            },
+        ];
+        // { let c = EvalCounter(start: 5); c.twice(); c.count }
+        let five = Expr::IntegerLiteral { value: five_sym, type_suffix: None, span };
+        let counter_expr = Expr::Identifier(counter);
+        let create = Expr::Call {
+            callee: &counter_expr,
+            args: vec![CallArg { label: Some(start), value: &five, span }],
+            span,
+        };
+        let c_expr = Expr::Identifier(c);
+        let send = Expr::MethodCall { receiver: &c_expr, method: twice, args: vec![], span };
+        let read = Expr::Field { object: &c_expr, field: count, span };
+        let block = Expr::Block {
+            stmts: vec![
+                Stmt::Let { name: c, type_annotation: None, init: Some(&create), span },
+                Stmt::Expr { expr: &send, span },
+            ],
+            expr: Some(&read),
+            span,
+        };
+        oxidex_typecheck::check::check_decl(&mut ctx, &decls[2]).unwrap();
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+
+        assert_eq!(interp.eval(&block).unwrap(), Value::Int(10));
+
+        // Instances are backed by runtime objects of the registered class
+        let Value::Object(instance) = interp.eval(&create).unwrap() else {
+            panic!("expected an object")
+        };
+        assert_eq!(instance.object.class().name(), "EvalCounter");
+        assert_eq!(Value::Object(instance).to_string(), "EvalCounter(count: 5)");
+
+        let circle_value = Value::variant("EvalShape", "Circle", Some(Value::Int(7)));
+        assert_eq!(interp.call("size", vec![circle_value]).unwrap(), Value::Int(7));
+        assert_eq!(interp.call("size", vec![Value::variant("EvalShape", "Empty", None)]).unwrap(), Value::Int(0));
+        let missing = interp.call("size", vec![Value::Int(1)]).unwrap_err();
+        assert!(matches!(missing.untraced(), RuntimeError::NoMatch { .. }));
+    }
+
+    #[test]
+    fn test_builtins_are_globals() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["main", "log", "len", "1", "2"].iter().map(|n| interner.intern(n)).collect();
+        let [main, log, len, one_sym, two_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // fn main() { log(len([1, 2])) }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let (one, two) = (
+            Expr::IntegerLiteral { value: one_sym, type_suffix: None, span },
+            Expr::IntegerLiteral { value: two_sym, type_suffix: None, span },
+        );
+        let array = Expr::Array { elements: vec![&one, &two], span };
+        let (log_expr, len_expr) = (Expr::Identifier(log), Expr::Identifier(len));
+        let count = Expr::Call { callee: &len_expr, args: vec![CallArg { label: None, value: &array, span }], span };
+        let body = Expr::Call { callee: &log_expr, args: vec![CallArg { label: None, value: &count, span }], span };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: main,
+            generics: vec![],
+            params: vec![],
+            return_type: None,
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        let mut builtins = Builtins::standard();
+        let sink = Rc::clone(&logged);
+        let signature = builtins.get("print").unwrap().signature.clone();
+        builtins.register("log", signature, move |args, _| {
+            sink.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        });
+
+        // The signatures let calls to builtins type check
+        builtins.declare(&mut ctx);
+        oxidex_typecheck::check::collect_signatures(&mut ctx, &decls).unwrap();
+        oxidex_typecheck::check::check_bodies(&mut ctx, &decls).unwrap();
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::with_builtins(&ctx, &lowered, builtins);
+        interp.load(&decls).unwrap();
+        interp.call("main", vec![]).unwrap();
+        assert_eq!(*logged.borrow(), ["2"]);
+
+        let err = interp.call("len", vec![]).unwrap_err();
+        assert!(matches!(err, RuntimeError::NoOverload { .. }));
+    }
+
+    #[test]
+    fn test_parsed_string_literals_are_decoded() {
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let source = r#"
+            fn main() {
+                print("Hello, World!");
+                print(len("hi"));
+                print("a\nb\t\"c\"")
+            }
+            fn tab() -> String { "\u{e9}\\" + "\t" }
+        "#;
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty());
+        let mut ctx = Context::new(parser.interner());
+
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let mut builtins = Builtins::standard();
+        let sink = Rc::clone(&printed);
+        let signature = builtins.get("print").unwrap().signature.clone();
+        builtins.register("print", signature, move |args, _| {
+            sink.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        });
+        builtins.declare(&mut ctx);
+        oxidex_typecheck::check::collect_signatures(&mut ctx, &decls).unwrap();
+        oxidex_typecheck::check::check_bodies(&mut ctx, &decls).unwrap();
+
+        // Literals evaluate to their text, without quotes and with escapes
+        // decoded
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::with_builtins(&ctx, &lowered, builtins);
+        interp.load(&decls).unwrap();
+        interp.call("main", vec![]).unwrap();
+        assert_eq!(*printed.borrow(), ["Hello, World!", "2", "a\nb\t\"c\""]);
+        assert_eq!(interp.call("tab", vec![]).unwrap(), Value::string("\u{e9}\\\t"));
+    }
+
+    #[test]
+    fn test_closures_capture_values_and_share_boxes() {
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let source = r"
+            fn counter(n: Int) -> Int {
+                let offset = 10;
+                mut count = 0;
+                let add = fn(x: Int) -> Int { count = count + 1; x + offset };
+                let read = fn() -> Int { count };
+                let b = add(add(n));
+                count = count * 100;
+                b + read()
+            }
+            fn twice(f: (Int) -> Int, x: Int) -> Int { f(f(x)) }
+            fn apply() -> Int {
+                let triple = fn(x: Int) -> Int { x * 3 };
+                twice(triple, 2)
+            }
+            fn nested(a: Int) -> Int {
+                let outer = fn() -> Int { let inner = fn() -> Int { a + 1 }; inner() };
+                outer()
+            }
+            fn failing() -> Int {
+                let fail = fn() -> Int { 1 / 0 };
+                catch(fail);
+                fail()
+            }
+            struct Tally { total: Int }
+            impl Tally {
+                mut fn bump(by: Int) -> Int {
+                    let step = fn(x: Int) -> Int { total = total + x; total };
+                    step(by);
+                    step(by)
+                }
+            }
+            fn tally() -> Int {
+                mut t = Tally { total: 1 };
+                t.bump(5)
+            }
+        ";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(16384));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let mut ctx = Context::new(parser.interner());
+        let builtins = Builtins::standard();
+        builtins.declare(&mut ctx);
+        oxidex_typecheck::check::collect_signatures(&mut ctx, &decls).unwrap();
+        oxidex_typecheck::check::check_bodies(&mut ctx, &decls).unwrap();
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::with_builtins(&ctx, &lowered, builtins);
+        interp.load(&decls).unwrap();
+
+        // `offset` is copied, `count` shared: two calls add 20 and count to
+        // 2, which `counter` then scales, as compiled code does
+        assert_eq!(interp.call("counter", vec![Value::Int(1)]).unwrap(), Value::Int(221));
+        assert_eq!(interp.call("apply", vec![]).unwrap(), Value::Int(18));
+        assert_eq!(interp.call("nested", vec![Value::Int(4)]).unwrap(), Value::Int(5));
+        // A closure using a field captures the receiver
+        assert_eq!(interp.call("tally", vec![]).unwrap(), Value::Int(11));
+
+        // `catch` runs closures too; the error names the closure's frame
+        let err = interp.call("failing", vec![]).unwrap_err();
+        assert!(matches!(err.untraced(), RuntimeError::DivisionByZero { .. }), "{err}");
+        assert_eq!(err.trace()[0].function, CLOSURE);
+        assert!(interp.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_for_loops_follow_the_iteration_protocol() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["IterCountdown", "n", "next", "Int", "sum", "x", "range", "text", "makeIterator"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [countdown, n, next, int_sym, sum, x, range, text, make_iterator] = names[..] else {
+            unreachable!()
+        };
+        let literals: Vec<Symbol> = ["0", "1", "3", "10", "\"hé\""].iter().map(|n| interner.intern(n)).collect();
+        let [zero_sym, one_sym, three_sym, ten_sym, string_sym] = literals[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let literal = |value| Expr::IntegerLiteral { value, type_suffix: None, span };
+        let (zero, one, three, ten) = (literal(zero_sym), literal(one_sym), literal(three_sym), literal(ten_sym));
+        let (n_expr, sum_expr, x_expr) = (Expr::Identifier(n), Expr::Identifier(sum), Expr::Identifier(x));
+        let binary = |left, op, right| Expr::Binary { left, op, right, span };
+
+        // mut fn next() { if n == 0 { return nil }; n = n - 1; n }
+        let done = binary(&n_expr, BinaryOp::Eq, &zero);
+        let nil = Expr::Nil { span };
+        let stop = Expr::Block { stmts: vec![Stmt::Return { value: Some(&nil), span }], expr: None, span };
+        let decrement = binary(&n_expr, BinaryOp::Sub, &one);
+        let next_body = Expr::Block {
+            stmts: vec![
+                Stmt::If { condition: &done, then_branch: &stop, else_branch: None, span },
+                Stmt::Assign { target: &n_expr, value: &decrement, span },
+            ],
+            expr: Some(&n_expr),
+            span,
+        };
+        let decls = vec![
+            Decl::Class {
+                name: countdown,
+                generics: vec![],
+                superclass: None,
+                fields: vec![FieldDecl { name: n, type_annotation: int.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![countdown],
+                protocol: None,
+                methods: vec![FnDecl {
+                    is_mut: true,
+                    is_init: false,
+                    is_static: false,
+                    name: Some(next),
+                    generics: vec![],
+                    params: vec![],
+                    return_type: None,
+                    body: &next_body,
+                    visibility: Visibility::Private,
+                    span,
+                }],
+                span,
+            },
+        ];
+        // { mut sum = 0; for x in sequence { sum = sum * 10 + x }; sum }
+        let shifted = binary(&sum_expr, BinaryOp::Mul, &ten);
+        let digits = Expr::Binary { left: &shifted, op: BinaryOp::Add, right: &x_expr, span };
+        let x_pat = Pattern::Variable { name: x, mutable: false, span };
+        let body = Expr::Block {
+            stmts: vec![Stmt::Assign { target: &sum_expr, value: &digits, span }],
+            expr: None,
+            span,
+        };
+        let fold = |sequence| Expr::Block {
+            stmts: vec![
+                Stmt::Mut { name: sum, type_annotation: None, init: Some(&zero), span },
+                Stmt::ForLoop { pattern: x_pat.clone(), iter: sequence, body: &body, span },
+            ],
+            expr: Some(&sum_expr),
+            span,
+        };
+
+        // A type with `next()` is its own iterator
+        let countdown_expr = Expr::Struct {
+            type_path: vec![countdown],
+            fields: vec![StructField { name: n, value: Some(&three), span }],
+            span,
+        };
+        let fold_countdown = fold(&countdown_expr);
+
+        // Ranges are lazy sequences of integers
+        let range_expr = Expr::Identifier(range);
+        let range_call = Expr::Call {
+            callee: &range_expr,
+            args: vec![CallArg { label: None, value: &one, span }, CallArg { label: None, value: &three, span }],
+            span,
+        };
+        let fold_range = fold(&range_call);
+
+        // Built-in sequences make iterators on request
+        let string = Expr::StringLiteral { value: string_sym, span };
+        let text_expr = Expr::Identifier(text);
+        let let_text = Stmt::Let { name: text, type_annotation: None, init: Some(&string), span };
+        let make = Expr::MethodCall { receiver: &text_expr, method: make_iterator, args: vec![], span };
+        let fold_one = fold(&one);
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+        assert_eq!(interp.eval(&fold_countdown).unwrap(), Value::Int(210));
+        assert_eq!(interp.eval(&fold_range).unwrap(), Value::Int(12));
+        interp.exec(&let_text).unwrap();
+        let Value::Iterator(cursor) = interp.eval(&make).unwrap() else {
+            panic!("expected an iterator")
+        };
+        assert!(cursor.borrow_mut().advance().is_some());
+
+        assert!(matches!(interp.eval(&fold_one), Err(RuntimeError::TypeMismatch { expected: "a sequence", .. })));
+    }
+
+    #[test]
+    fn test_unreachable_cycles_are_collected() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["kept", "lost", "0"].iter().map(|n| interner.intern(n)).collect();
+        let [kept, lost, zero_sym] = names[..] else { unreachable!() };
+        let mut ctx = Context::new(&interner);
+
+        // mut a = [0]; a[0] = a
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let zero = Expr::IntegerLiteral { value: zero_sym, type_suffix: None, span };
+        let array = Expr::Array { elements: vec![&zero], span };
+        let (kept_expr, lost_expr) = (Expr::Identifier(kept), Expr::Identifier(lost));
+        let (kept_slot, lost_slot) = (
+            Expr::Index { collection: &kept_expr, index: &zero, span },
+            Expr::Index { collection: &lost_expr, index: &zero, span },
+        );
+        let create = Stmt::Mut { name: kept, type_annotation: None, init: Some(&array), span };
+        let assign = Stmt::Assign { target: &kept_slot, value: &kept_expr, span };
+
+        // The same, in a block whose scope ends
+        let block = Expr::Block {
+            stmts: vec![
+                Stmt::Mut { name: lost, type_annotation: None, init: Some(&array), span },
+                Stmt::Assign { target: &lost_slot, value: &lost_expr, span },
+            ],
+            expr: None,
+            span,
+        };
+
+        let lowered = lower(&mut ctx, &[]).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.exec(&create).unwrap();
+        interp.exec(&assign).unwrap();
+        interp.eval(&block).unwrap();
+        assert_eq!(interp.collect_cycles(), 1);
+        assert_eq!(interp.collect_cycles(), 0);
+
+        // Collections run between statements once enough containers changed
+        interp.set_cycle_threshold(Some(1));
+        interp.eval(&block).unwrap();
+        assert_eq!(interp.collect_cycles(), 0);
+
+        let Value::Array(elements) = interp.eval(&kept_expr).unwrap() else { panic!("expected an array") };
+        assert!(matches!(&elements.borrow()[0], Value::Array(inner) if Rc::ptr_eq(inner, &elements)));
+    }
+
+    #[test]
+    fn test_limits_and_cancellation_interrupt_evaluation() {
+        let mut interner = StringInterner::new();
+        let recurse = interner.intern("recurse");
+        let mut ctx = Context::new(&interner);
+
+        // fn recurse() { recurse() }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let recurse_expr = Expr::Identifier(recurse);
+        let body = Expr::Call { callee: &recurse_expr, args: vec![], span };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: recurse,
+            generics: vec![],
+            params: vec![],
+            return_type: None,
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+
+        // while true {}
+        let condition = Expr::BoolLiteral { value: true, span };
+        let empty = Expr::Block { stmts: vec![], expr: None, span };
+        let spin = Expr::WhileLoop { condition: &condition, body: &empty, span };
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+        let cause = |result: Result<Value>| match result.map_err(|err| err.untraced().clone()) {
+            Err(RuntimeError::Interrupted { cause, .. }) => cause,
+            other => panic!("expected an interruption, found {other:?}"),
+        };
+        interp.set_limits(Limits { max_steps: Some(100), ..Limits::default() });
+        assert_eq!(cause(interp.eval(&spin)), Interrupt::Steps(100));
+        interp.set_limits(Limits { timeout: Some(Duration::ZERO), ..Limits::default() });
+        assert_eq!(cause(interp.eval(&spin)), Interrupt::Timeout(Duration::ZERO));
+
+        // Scripts cannot catch interruptions
+        interp.set_limits(Limits { max_depth: Some(8), ..Limits::default() });
+        assert_eq!(cause(interp.call("recurse", vec![])), Interrupt::Depth(8));
+        assert_eq!(cause(interp.call(CATCH, vec![Value::Function("recurse".into())])), Interrupt::Depth(8));
+        assert_eq!(interp.env().depth(), 1);
+
+        let token = interp.cancel_token();
+        token.cancel();
+        assert_eq!(cause(interp.eval(&spin)), Interrupt::Cancelled);
+        token.reset();
+        interp.set_limits(Limits { max_steps: Some(100), ..Limits::default() });
+        assert_eq!(cause(interp.eval(&spin)), Interrupt::Steps(100));
+    }
+
+    #[test]
+    fn test_deferred_bodies_run_when_blocks_exit() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> =
+            ["main", "log", "assert", "a", "b", "1"].iter().map(|n| interner.intern(n)).collect();
+        let [main, log, assert, a_sym, b_sym, one_sym] = names[..] else { unreachable!() };
+        let ctx = Context::new(&interner);
+
+        // fn main() { defer { log("a") } defer { log("b") } return 1; }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let (log_expr, assert_expr) = (Expr::Identifier(log), Expr::Identifier(assert));
+        let (a, b) = (Expr::StringLiteral { value: a_sym, span }, Expr::StringLiteral { value: b_sym, span });
+        let one = Expr::IntegerLiteral { value: one_sym, type_suffix: None, span };
+        let no = Expr::BoolLiteral { value: false, span };
+        let call = |callee, arg| Expr::Call { callee, args: vec![CallArg { label: None, value: arg, span }], span };
+        let (log_a, log_b, assert_no) = (call(&log_expr, &a), call(&log_expr, &b), call(&assert_expr, &no));
+        let defer_a = Expr::Block { stmts: vec![], expr: Some(&log_a), span };
+        let defer_b = Expr::Block { stmts: vec![], expr: Some(&log_b), span };
+        let body = Expr::Block {
+            stmts: vec![
+                Stmt::Defer { body: &defer_a, span },
+                Stmt::Defer { body: &defer_b, span },
+                Stmt::Return { value: Some(&one), span },
+            ],
+            expr: None,
+            span,
+        };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: main,
+            generics: vec![],
+            params: vec![],
+            return_type: None,
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+        let failing = Expr::Block {
+            stmts: vec![Stmt::Defer { body: &defer_a, span }],
+            expr: Some(&assert_no),
+            span,
+        };
+        let stray = Stmt::Defer { body: &defer_a, span };
+
+        let logged = Rc::new(RefCell::new(Vec::new()));
+        let mut builtins = Builtins::standard();
+        let sink = Rc::clone(&logged);
+        let signature = builtins.get("print").unwrap().signature.clone();
+        builtins.register("log", signature, move |args, _| {
+            sink.borrow_mut().push(args[0].to_string());
+            Ok(Value::Unit)
+        });
+        let lowered = LoweredModule::default();
+        let mut interp = Interpreter::with_builtins(&ctx, &lowered, builtins);
+        interp.load(&decls).unwrap();
+
+        // Last deferred runs first, after an early return
+        assert_eq!(interp.call("main", vec![]).unwrap(), Value::Int(1));
+        assert_eq!(*logged.borrow(), ["b", "a"]);
+
+        // { defer { log("a") } assert(false) }
+        assert!(matches!(interp.eval(&failing), Err(RuntimeError::AssertionFailed { .. })));
+        assert_eq!(*logged.borrow(), ["b", "a", "a"]);
+
+        // Only blocks defer
+        assert!(matches!(interp.exec(&stray), Err(RuntimeError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn test_catch_recovers_from_errors() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["risky", "fail", "fine", "throw", "1", "2", "RuntimeError", "NilUnwrap"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [risky, fail, fine, throw, one_sym, two_sym, error_enum, nil_unwrap] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // fn risky() { [1][2] }  fn fail() { throw(1) }  fn fine() { 2 }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let (one, two) = (
+            Expr::IntegerLiteral { value: one_sym, type_suffix: None, span },
+            Expr::IntegerLiteral { value: two_sym, type_suffix: None, span },
+        );
+        let array = Expr::Array { elements: vec![&one], span };
+        let index = Expr::Index { collection: &array, index: &two, span };
+        let throw_expr = Expr::Identifier(throw);
+        let thrown = Expr::Call { callee: &throw_expr, args: vec![CallArg { label: None, value: &one, span }], span };
+        let function = |name, body| Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name,
+            generics: vec![],
+            params: vec![],
+            return_type: None,
+            body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        };
+        let decls = vec![function(risky, &index), function(fail, &thrown), function(fine, &two)];
+
+        let path = Expr::Path { segments: vec![error_enum, nil_unwrap], span };
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+        let catch = |interp: &mut Interpreter<'_, '_>, name: &str| {
+            let Value::Variant(result) = interp.call("catch", vec![Value::Function(name.into())]).unwrap() else {
+                panic!("`catch` returns a result");
+            };
+            assert_eq!(result.enum_name, "Result");
+            (result.variant.clone(), result.payload.clone().unwrap())
+        };
+
+        assert_eq!(catch(&mut interp, "fine"), ("Ok".to_string(), Value::Int(2)));
+        let bounds = Value::Tuple(Rc::from([Value::Int(2), Value::Int(1)]));
+        let expected = Value::variant("RuntimeError", "IndexOutOfBounds", Some(bounds));
+        assert_eq!(catch(&mut interp, "risky"), ("Err".to_string(), expected));
+        let expected = Value::variant("RuntimeError", "Thrown", Some(Value::Int(1)));
+        assert_eq!(catch(&mut interp, "fail"), ("Err".to_string(), expected));
+        assert!(interp.call_stack().is_empty());
+        assert_eq!(interp.env().depth(), 1);
+
+        // Uncaught, a thrown value ends evaluation
+        let err = interp.call("fail", vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "uncaught error: 1");
+
+        // Scripts name the error variants to match on them
+        assert_eq!(interp.eval(&path).unwrap(), RuntimeError::NilUnwrap { span }.to_value());
+    }
+
+    unsafe extern "C-unwind" fn native_answer(
+        _receiver: oxidec::runtime::ObjectPtr,
+        _cmd: oxidec::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(42) };
+    }
+
+    #[test]
+    fn test_native_code_messages_interpreted_objects() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["NativeCounter", "count", "bump", "by", "step", "answer", "c", "Int"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [counter, count, bump, by, step, answer, c, int_sym] = names[..] else { unreachable!() };
+        let mut ctx = Context::new(&interner);
+
+        // mut fn bump(by step: Int) -> Int { count = count + step; count }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let (count_expr, step_expr) = (Expr::Identifier(count), Expr::Identifier(step));
+        let sum = Expr::Binary { left: &count_expr, op: BinaryOp::Add, right: &step_expr, span };
+        let bump_body = Expr::Block {
+            stmts: vec![Stmt::Assign { target: &count_expr, value: &sum, span }],
+            expr: Some(&count_expr),
+            span,
+        };
+        let decls = vec![
+            Decl::Class {
+                name: counter,
+                generics: vec![],
+                superclass: None,
+                fields: vec![FieldDecl { name: count, type_annotation: int.clone(), span }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![counter],
+                protocol: None,
+                methods: vec![FnDecl {
+                    is_mut: true,
+                    is_init: false,
+                    is_static: false,
+                    name: Some(bump),
+                    generics: vec![],
+                    params: vec![FnParam { label: Some(by), name: step, type_annotation: int.clone(), span }],
+                    return_type: Some(int),
+                    body: &bump_body,
+                    visibility: Visibility::Private,
+                    span,
+                }],
+                span,
+            },
+        ];
+        // NativeCounter { count: 0 }
+        let literal = Expr::Struct { type_path: vec![counter], fields: vec![], span };
+        let c_expr = Expr::Identifier(c);
+        let call = Expr::MethodCall { receiver: &c_expr, method: answer, args: vec![], span };
+
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+        let Value::Object(instance) = interp.eval(&literal).unwrap() else { panic!("expected an object") };
+        instance.set_field("count", Value::Int(0));
+
+        // Messages from native code run the method's body
+        let bump_by = Selector::from_str("bumpBy:").unwrap();
+        let send = |interp: &mut Interpreter<'_, '_>, amount: usize| {
+            interp.attach(|| instance.object.send_message(&bump_by, &MessageArgs::one(amount)).unwrap())
+        };
+        assert_eq!(send(&mut interp, 5), Some(5));
+        assert_eq!(send(&mut interp, 2), Some(7));
+        assert_eq!(instance.field("count"), Some(Value::Int(7)));
+        assert!(interp.take_native_error().is_none());
+
+        // Errors are kept for the caller instead of unwinding through the runtime
+        send(&mut interp, i64::MAX as usize);
+        let err = interp.take_native_error().unwrap();
+        assert!(matches!(err.untraced(), RuntimeError::IntegerOverflow { .. }));
+        assert_eq!(err.trace()[0].function, "NativeCounter.bump");
+
+        // Interpreted code reaches methods added natively
+        instance
+            .object
+            .class()
+            .add_method(oxidec::Method {
+                selector: Selector::from_str("answer").unwrap(),
+                imp: native_answer,
+                types: oxidec::RuntimeString::new("q@:", oxidec::get_global_arena()),
+            })
+            .unwrap();
+        interp.env_mut().define(c, Value::Object(Rc::clone(&instance)), false);
+        assert_eq!(interp.eval(&call).unwrap(), Value::Int(42));
+
+        // Unless the sandbox denies calling native code; interpreted methods
+        // still run
+        interp.set_sandbox(Sandbox::strict());
+        let err = interp.eval(&call).unwrap_err();
+        assert!(matches!(err, RuntimeError::Sandboxed { capability: Capability::Ffi, .. }), "{err:?}");
+        assert_eq!(send(&mut interp, 1), Some(8));
+    }
+
+    #[test]
+    fn test_sandbox_denies_builtins_and_imports() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["secret", "\"./secret\""].iter().map(|n| interner.intern(n)).collect();
+        let [_, secret_path] = names[..] else { unreachable!() };
+        let mut ctx = Context::new(&interner);
+        let lowered = lower(&mut ctx, &[]).unwrap();
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let import = Decl::Import { path: secret_path, span };
+
+        let mut builtins = Builtins::standard();
+        let signature = builtins.get("clock").unwrap().signature.clone();
+        builtins.register_requiring("secret", Capability::FileIo, signature, |_, _| Ok(Value::string("hunter2")));
+        let mut interp = Interpreter::with_builtins(&ctx, &lowered, builtins);
+        assert_eq!(interp.call("secret", vec![]).unwrap(), Value::string("hunter2"));
+
+        let sandbox = Sandbox { deny_file_io: true, ..Sandbox::default() };
+        interp.set_sandbox(sandbox);
+        assert_eq!(interp.sandbox(), sandbox);
+        let denied = |result: Result<Value>| match result.map_err(|err| err.untraced().clone()) {
+            Err(RuntimeError::Sandboxed { capability, .. }) => capability,
+            other => panic!("expected a sandbox violation, found {other:?}"),
+        };
+        assert_eq!(denied(interp.call("secret", vec![])), Capability::FileIo);
+        assert_eq!(interp.call("len", vec![Value::string("ok")]).unwrap(), Value::Int(2));
+
+        // Scripts cannot catch violations to probe the sandbox
+        assert_eq!(denied(interp.call(CATCH, vec![Value::Function("secret".into())])), Capability::FileIo);
+
+        // Imports are refused before their file is looked for
+        interp.set_sandbox(Sandbox { deny_dynamic_loading: true, ..Sandbox::default() });
+        let err = interp.eval_decl(&import).unwrap_err();
+        assert!(matches!(err, RuntimeError::Sandboxed { capability: Capability::DynamicLoading, .. }), "{err:?}");
+        interp.set_sandbox(Sandbox::default());
+        assert!(matches!(interp.eval_decl(&import), Err(RuntimeError::ModuleNotFound { .. })));
+    }
+
+    #[test]
+    #[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn test_extern_functions_call_c() {
+        use oxidex_syntax::ast::decl::ExternFn;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["abs", "strlen", "missing_c_function", "n", "Int32", "String", "UInt64"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [abs, strlen, missing, n, int32, string, uint64] = names[..] else { unreachable!() };
+        let mut ctx = Context::new(&interner);
+        let lowered = lower(&mut ctx, &[]).unwrap();
+
+        // extern { fn abs(n: Int32) -> Int32; fn strlen(n: String) -> UInt64; fn missing_c_function(); }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let simple = |name| Type::Simple { name, span };
+        let function = |name, param: Option<Symbol>, ret: Option<Symbol>| {
+            let params = param.map(|ty| FnParam { label: None, name: n, type_annotation: simple(ty), span });
+            ExternFn { name, params: params.into_iter().collect(), return_type: ret.map(simple), span }
+        };
+        let functions = vec![
+            function(abs, Some(int32), Some(int32)),
+            function(strlen, Some(string), Some(uint64)),
+            function(missing, None, None),
+        ];
+        let decls = vec![Decl::Extern { library: None, functions, span }];
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+
+        assert_eq!(interp.call("abs", vec![Value::Int(-7)]).unwrap(), Value::Int(7));
+        assert_eq!(interp.call("strlen", vec![Value::string("hello")]).unwrap(), Value::Int(5));
+        let err = interp.call("missing_c_function", vec![]).unwrap_err();
+        let RuntimeError::ForeignCall { function, error, .. } = err.untraced() else { panic!("{err:?}") };
+        assert_eq!(function, "missing_c_function");
+        assert_eq!(error, &oxidec::Error::SymbolNotFound { symbol: function.clone() });
+        assert!(matches!(interp.call("abs", vec![Value::Int(i64::MAX)]), Err(RuntimeError::ForeignCall { .. })));
+
+        interp.set_sandbox(Sandbox { deny_ffi: true, ..Sandbox::default() });
+        let err = interp.call("abs", vec![Value::Int(-7)]).unwrap_err();
+        assert!(matches!(err, RuntimeError::Sandboxed { capability: Capability::Ffi, .. }), "{err:?}");
+    }
+
+    #[test]
+    fn test_debugger_breaks_and_steps() {
+        use crate::debug::{Breakpoint, Breakpoints, Command, PauseReason};
+
+        /// Reason, line, stack depth and innermost locals of a pause
+        type Stop = (PauseReason, usize, usize, Vec<(String, Value)>);
+
+        struct Recorder {
+            breakpoints: Breakpoints,
+            commands: Vec<Command>,
+            pauses: Rc<RefCell<Vec<Stop>>>,
+        }
+
+        impl Debugger for Recorder {
+            fn breakpoint(&mut self, file: &str, line: usize) -> bool {
+                self.breakpoints.contains(file, line)
+            }
+
+            fn pause(&mut self, pause: &Pause<'_>) -> Command {
+                let locals = pause.locals(0);
+                self.pauses.borrow_mut().push((pause.reason, pause.line(), pause.stack().len(), locals));
+                self.commands.remove(0)
+            }
+        }
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> =
+            ["double", "x", "y", "a", "b", "c", "Int", "1", "2"].iter().map(|n| interner.intern(n)).collect();
+        let [double, x, y, a, b, c, int_sym, one_sym, two_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // 1 fn double(x: Int) -> Int {
+        // 2     let y = x + x
+        // 3     return y
+        // 4 }
+        // 5 let a = double(2)
+        // 6 let b = a + 1
+        // 7 let c = double(b)
+        let line = |line| Span::new(0, 0, line, 1, line, 1);
+        let span = line(0);
+        let int = Type::Simple { name: int_sym, span };
+        let (x_expr, y_expr, a_expr, b_expr) =
+            (Expr::Identifier(x), Expr::Identifier(y), Expr::Identifier(a), Expr::Identifier(b));
+        let double_expr = Expr::Identifier(double);
+        let sum = Expr::Binary { left: &x_expr, op: BinaryOp::Add, right: &x_expr, span };
+        let body = Expr::Block {
+            stmts: vec![
+                Stmt::Let { name: y, type_annotation: None, init: Some(&sum), span: line(2) },
+                Stmt::Return { value: Some(&y_expr), span: line(3) },
+            ],
+            expr: None,
+            span,
+        };
+        let decls = vec![Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: double,
+            generics: vec![],
+            params: vec![FnParam { label: None, name: x, type_annotation: int.clone(), span }],
+            return_type: Some(int),
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        }];
+        let (one, two) = (
+            Expr::IntegerLiteral { value: one_sym, type_suffix: None, span },
+            Expr::IntegerLiteral { value: two_sym, type_suffix: None, span },
+        );
+        let call =
+            |arg| Expr::Call { callee: &double_expr, args: vec![CallArg { label: None, value: arg, span }], span };
+        let (first, second) = (call(&two), call(&b_expr));
+        let increment = Expr::Binary { left: &a_expr, op: BinaryOp::Add, right: &one, span };
+        let program = [
+            Stmt::Let { name: a, type_annotation: None, init: Some(&first), span: line(5) },
+            Stmt::Let { name: b, type_annotation: None, init: Some(&increment), span: line(6) },
+            Stmt::Let { name: c, type_annotation: None, init: Some(&second), span: line(7) },
+        ];
+        let lowered = lower(&mut ctx, &decls).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        interp.load(&decls).unwrap();
+
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.insert(Breakpoint::new("main.ox", 5));
+        breakpoints.insert(Breakpoint::new("main.ox", 6));
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let commands = vec![Command::Step, Command::Next, Command::Continue, Command::Next, Command::Next];
+        interp.set_file("main.ox");
+        interp.set_debugger(Recorder { breakpoints, commands, pauses: Rc::clone(&pauses) });
+        for stmt in &program {
+            interp.exec(stmt).unwrap();
+        }
+        assert!(interp.take_debugger().is_some());
+
+        // Step enters `double`, next stays in it, continue runs to the next
+        // breakpoint, and next runs the second call to completion
+        let pauses = pauses.borrow();
+        let summary: Vec<_> = pauses.iter().map(|(reason, line, depth, _)| (*reason, *line, *depth)).collect();
+        assert_eq!(
+            summary,
+            [
+                (PauseReason::Breakpoint, 5, 0),
+                (PauseReason::Step, 2, 1),
+                (PauseReason::Step, 3, 1),
+                (PauseReason::Breakpoint, 6, 0),
+                (PauseReason::Step, 7, 0),
+            ]
+        );
+        assert_eq!(pauses[1].3, [("x".to_string(), Value::Int(2))]);
+        assert_eq!(pauses[2].3, [("x".to_string(), Value::Int(2)), ("y".to_string(), Value::Int(4))]);
+        assert_eq!(pauses[4].3, [("a".to_string(), Value::Int(4)), ("b".to_string(), Value::Int(5))]);
+    }
+
+    #[test]
+    fn test_imports_load_modules_once_in_their_own_globals() {
+        let root = std::env::temp_dir().join(format!("oxidex-imports-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let [main_file, math_file, a_file, b_file] =
+            ["main.ox", "math.ox", "a.ox", "b.ox"].map(|file| root.join(file));
+        for file in [&main_file, &math_file, &a_file, &b_file] {
+            std::fs::write(file, "").unwrap();
+        }
+
+        let mut interner = StringInterner::new();
+        // String literals keep their quotes
+        let paths = ["\"./math\"", "\"./a\"", "\"./b\"", "\"./missing\""];
+        let names: Vec<Symbol> = ["double", "x", "factor", "Int", "2", "21"]
+            .iter()
+            .chain(&paths)
+            .map(|n| interner.intern(n))
+            .collect();
+        let [double, x, factor, int_sym, two_sym, lit_sym, math_path, a_path, b_path, missing_path] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        // math.ox: const factor: Int = 2; pub fn double(x: Int) -> Int { x * factor }
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let int = Type::Simple { name: int_sym, span };
+        let two = Expr::IntegerLiteral { value: two_sym, type_suffix: None, span };
+        let (x_expr, factor_expr) = (Expr::Identifier(x), Expr::Identifier(factor));
+        let product = Expr::Binary { left: &x_expr, op: BinaryOp::Mul, right: &factor_expr, span };
+        let math = [
+            Decl::Const {
+                name: factor,
+                type_annotation: int.clone(),
+                value: &two,
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Fn {
+                is_mut: false,
+                is_init: false,
+                is_static: false,
+                name: double,
+                generics: vec![],
+                params: vec![FnParam { label: None, name: x, type_annotation: int.clone(), span }],
+                return_type: Some(int),
+                body: &product,
+                visibility: Visibility::Public,
+                attributes: Vec::new(),
+                span,
+            },
+        ];
+        // a.ox imports b.ox, which imports a.ox
+        let a = [Decl::Import { path: b_path, span }];
+        let b = [Decl::Import { path: a_path, span }];
+        // main.ox: import "./math";
+        let main = vec![Decl::Import { path: math_path, span }];
+        let (import_a, import_missing) =
+            (Decl::Import { path: a_path, span }, Decl::Import { path: missing_path, span });
+        let lit = Expr::IntegerLiteral { value: lit_sym, type_suffix: None, span };
+        let double_expr = Expr::Identifier(double);
+        let call = Expr::Call { callee: &double_expr, args: vec![CallArg { label: None, value: &lit, span }], span };
+
+        let lowered = lower(&mut ctx, &main).unwrap();
+        let mut interp = Interpreter::new(&ctx, &lowered);
+        let canonical = |file: &PathBuf| file.canonicalize().unwrap();
+        let modules: HashMap<PathBuf, &[Decl<'_>]> =
+            [(canonical(&math_file), &math[..]), (canonical(&a_file), &a[..]), (canonical(&b_file), &b[..])].into();
+        interp.set_module_source(modules);
+        interp.set_file(main_file.display().to_string());
+        interp.load(&main).unwrap();
+
+        // Imported functions run in their own module's globals, which the
+        // importer cannot see
+        assert_eq!(interp.eval(&call).unwrap(), Value::Int(42));
+        assert!(matches!(interp.eval(&factor_expr), Err(RuntimeError::UndefinedVariable { .. })));
+
+        // A second import reuses the loaded module
+        interp.eval_decl(&main[0]).unwrap();
+        assert_eq!(interp.imported_files(), [canonical(&math_file)]);
+
+        let err = interp.eval_decl(&import_a).unwrap_err();
+        let RuntimeError::ImportCycle { cycle, .. } = err else {
+            panic!("expected an import cycle, found {err:?}");
+        };
+        let [a_name, b_name] = [&a_file, &b_file].map(|file| canonical(file).display().to_string());
+        assert_eq!(cycle, [a_name.clone(), b_name, a_name]);
+
+        assert!(matches!(interp.eval_decl(&import_missing), Err(RuntimeError::ModuleNotFound { .. })));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! Cycle collection.
+//!
+//! Arrays, dictionaries and instances are reference counted, so they are
+//! freed when the last reference to them goes, unless they refer to each
+//! other in a cycle. The [`Collector`] frees cycles by marking every value
+//! reachable from the interpreter's variables, then emptying the containers
+//! that were not marked. Emptying a container breaks the cycles through it,
+//! and reference counting frees the rest.
+//!
+//! A new container can only refer to values older than itself, so every
+//! cycle passes through a container that was changed after it was made. The
+//! collector only needs to know of those: instances whose fields were
+//! assigned and collections whose elements were.
+//!
+//! Values held outside variables are not roots, so collection only runs at
+//! safepoints, between top-level statements.
+
+use crate::value::{Instance, Value};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+
+/// A container that may be part of a cycle.
+#[derive(Debug)]
+enum Candidate {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Dict(Weak<RefCell<Vec<(Value, Value)>>>),
+    Object(Weak<Instance>),
+}
+
+impl Candidate {
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Array(elements) => elements.strong_count() > 0,
+            Self::Dict(entries) => entries.strong_count() > 0,
+            Self::Object(instance) => instance.strong_count() > 0,
+        }
+    }
+}
+
+/// Finds and frees unreachable cycles.
+#[derive(Debug, Default)]
+pub struct Collector {
+    /// Tracked containers, by address
+    candidates: Vec<(usize, Candidate)>,
+    /// Addresses of the candidates, so each is tracked once
+    tracked: HashSet<usize>,
+    /// Candidates tracked since the last collection
+    pending: usize,
+    /// Pending candidates that make a collection due
+    threshold: Option<usize>,
+}
+
+impl Collector {
+    /// Create a collector that never becomes due.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make a collection due once `threshold` containers have been tracked
+    /// since the last one, or never if `None`.
+    pub fn set_threshold(&mut self, threshold: Option<usize>) {
+        self.threshold = threshold;
+    }
+
+    /// Note that a container changed, so it may now be part of a cycle.
+    /// Other values are ignored.
+    pub fn track(&mut self, value: &Value) {
+        let (address, candidate) = match value {
+            Value::Array(elements) => (address(elements), Candidate::Array(Rc::downgrade(elements))),
+            Value::Dict(entries) => (address(entries), Candidate::Dict(Rc::downgrade(entries))),
+            Value::Object(instance) => (address(instance), Candidate::Object(Rc::downgrade(instance))),
+            _ => return,
+        };
+        // A weak reference keeps the allocation, so the address is not reused
+        if self.tracked.insert(address) {
+            self.candidates.push((address, candidate));
+            self.pending += 1;
+        }
+    }
+
+    /// Number of tracked containers that are still alive.
+    #[must_use]
+    pub fn tracked(&self) -> usize {
+        self.candidates.iter().filter(|(_, candidate)| candidate.is_alive()).count()
+    }
+
+    /// Whether enough containers were tracked to make a collection due.
+    #[must_use]
+    pub fn is_due(&self) -> bool {
+        self.threshold.is_some_and(|threshold| self.pending >= threshold)
+    }
+
+    /// Empty the tracked containers not reachable from `roots`, returning
+    /// how many were emptied.
+    ///
+    /// Every value still in use must be reachable from a root.
+    pub fn collect<'v>(&mut self, roots: impl IntoIterator<Item = &'v Value>) -> usize {
+        let marked = mark(roots);
+        let mut freed = 0;
+        for (address, candidate) in std::mem::take(&mut self.candidates) {
+            // Contents are dropped after the borrow ends, since dropping
+            // them may reach this container again
+            match &candidate {
+                Candidate::Array(elements) => match elements.upgrade() {
+                    Some(elements) if !marked.contains(&address) => {
+                        drop(std::mem::take(&mut *elements.borrow_mut()));
+                        freed += 1;
+                    }
+                    Some(_) => self.candidates.push((address, candidate)),
+                    None => {}
+                },
+                Candidate::Dict(entries) => match entries.upgrade() {
+                    Some(entries) if !marked.contains(&address) => {
+                        drop(std::mem::take(&mut *entries.borrow_mut()));
+                        freed += 1;
+                    }
+                    Some(_) => self.candidates.push((address, candidate)),
+                    None => {}
+                },
+                Candidate::Object(instance) => match instance.upgrade() {
+                    Some(instance) if !marked.contains(&address) => {
+                        drop(std::mem::take(&mut *instance.fields.borrow_mut()));
+                        freed += 1;
+                    }
+                    Some(_) => self.candidates.push((address, candidate)),
+                    None => {}
+                },
+            }
+        }
+        self.tracked = self.candidates.iter().map(|(address, _)| *address).collect();
+        self.pending = 0;
+        freed
+    }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc).cast::<()>() as usize
+}
+
+/// Find the addresses of the shared values reachable from `roots`.
+fn mark<'v>(roots: impl IntoIterator<Item = &'v Value>) -> HashSet<usize> {
+    let mut marked = HashSet::new();
+    let mut stack: Vec<Value> = roots.into_iter().cloned().collect();
+    while let Some(value) = stack.pop() {
+        match &value {
+            Value::Tuple(elements) if marked.insert(elements.as_ptr() as usize) => {
+                stack.extend(elements.iter().cloned());
+            }
+            Value::Array(elements) if marked.insert(address(elements)) => {
+                stack.extend(elements.borrow().iter().cloned());
+            }
+            Value::Dict(entries) if marked.insert(address(entries)) => {
+                stack.extend(entries.borrow().iter().flat_map(|(key, value)| [key.clone(), value.clone()]));
+            }
+            Value::Object(instance) if marked.insert(address(instance)) => {
+                stack.extend(instance.fields.borrow().iter().map(|(_, value)| value.clone()));
+            }
+            Value::Variant(variant) if marked.insert(address(variant)) => stack.extend(variant.payload.clone()),
+            Value::Closure(closure) if marked.insert(address(closure)) => {
+                stack.extend(closure.captures.iter().map(|(_, captured)| captured.get()));
+                stack.extend(closure.receiver.clone());
+            }
+            Value::Iterator(cursor) if marked.insert(address(cursor)) => {
+                stack.extend(cursor.borrow().remaining().iter().cloned());
+            }
+            _ => {}
+        }
+    }
+    marked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_frees_unreachable_cycles() {
+        let mut collector = Collector::new();
+        collector.set_threshold(Some(2));
+
+        // An array containing itself, and one reachable from a root
+        let cycle = Value::array(vec![]);
+        let Value::Array(elements) = &cycle else { unreachable!() };
+        elements.borrow_mut().push(cycle.clone());
+        let weak = Rc::downgrade(elements);
+        collector.track(&cycle);
+        collector.track(&cycle);
+        assert!(!collector.is_due());
+
+        let root = Value::dict(vec![]);
+        let Value::Dict(entries) = &root else { unreachable!() };
+        entries.borrow_mut().push((Value::Int(1), root.clone()));
+        collector.track(&root);
+        assert!(collector.is_due());
+
+        drop(cycle);
+        assert_eq!(collector.collect([&root]), 1);
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(entries.borrow().len(), 1);
+        assert_eq!(collector.tracked(), 1);
+        assert!(!collector.is_due());
+    }
+}
@@ -0,0 +1,116 @@
+//! The iteration protocol.
+//!
+//! `for x in sequence` asks the sequence for an iterator with
+//! `makeIterator()`, then calls the iterator's `next()` until it returns
+//! `nil`. A type that defines `next()` but not `makeIterator()` is its own
+//! iterator.
+//!
+//! Arrays, dictionaries, strings and ranges conform through a built-in
+//! [`Cursor`](crate::iter::Cursor). Arrays and dictionaries are iterated as
+//! they were when the iterator was made; dictionaries yield `(key, value)`
+//! tuples in insertion order, and strings yield one-character strings.
+
+use crate::value::Value;
+use std::rc::Rc;
+
+/// Method that makes an iterator over a sequence.
+pub const MAKE_ITERATOR: &str = "makeIterator";
+
+/// Method that advances an iterator, returning `nil` when it is done.
+pub const NEXT: &str = "next";
+
+/// Progress through a built-in sequence.
+#[derive(Debug, Clone)]
+pub enum Cursor {
+    /// Elements of an array, or entries of a dictionary
+    Items(std::vec::IntoIter<Value>),
+    /// Characters of a string, from a byte offset
+    Chars {
+        /// The string
+        text: Rc<str>,
+        /// Byte offset of the next character
+        offset: usize,
+    },
+    /// Integers up to an exclusive end
+    Range {
+        /// Next integer
+        next: i64,
+        /// End of the range
+        end: i64,
+    },
+}
+
+impl Cursor {
+    /// Start iterating over a built-in sequence. Returns `None` for values
+    /// that are not sequences.
+    #[must_use]
+    pub fn new(sequence: &Value) -> Option<Self> {
+        match sequence {
+            Value::Array(elements) => Some(Self::Items(elements.borrow().clone().into_iter())),
+            Value::Dict(entries) => {
+                let entries = entries.borrow();
+                let items = entries.iter().map(|(key, value)| Value::Tuple(Rc::from([key.clone(), value.clone()])));
+                Some(Self::Items(items.collect::<Vec<_>>().into_iter()))
+            }
+            Value::String(text) => Some(Self::Chars { text: Rc::from(text.as_str()), offset: 0 }),
+            Value::Range(start, end) => Some(Self::Range { next: *start, end: *end }),
+            _ => None,
+        }
+    }
+
+    /// Get the next element, or `None` if there are no more.
+    pub fn advance(&mut self) -> Option<Value> {
+        match self {
+            Self::Items(items) => items.next(),
+            Self::Chars { text, offset } => {
+                let ch = text[*offset..].chars().next()?;
+                *offset += ch.len_utf8();
+                Some(Value::String(ch.to_string()))
+            }
+            Self::Range { next, end } => {
+                if next >= end {
+                    return None;
+                }
+                let value = *next;
+                *next += 1;
+                Some(Value::Int(value))
+            }
+        }
+    }
+
+    /// Values the cursor holds that it has not yielded yet.
+    #[must_use]
+    pub fn remaining(&self) -> &[Value] {
+        match self {
+            Self::Items(items) => items.as_slice(),
+            Self::Chars { .. } | Self::Range { .. } => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(sequence: &Value) -> Vec<Value> {
+        let mut cursor = Cursor::new(sequence).unwrap();
+        std::iter::from_fn(|| cursor.advance()).collect()
+    }
+
+    #[test]
+    fn test_builtin_sequences() {
+        let array = Value::array(vec![Value::Int(1), Value::Int(2)]);
+        let mut cursor = Cursor::new(&array).unwrap();
+        // Changes after the iterator is made are not seen
+        let Value::Array(elements) = &array else { unreachable!() };
+        elements.borrow_mut().push(Value::Int(3));
+        assert_eq!(std::iter::from_fn(|| cursor.advance()).collect::<Vec<_>>(), [Value::Int(1), Value::Int(2)]);
+
+        let dict = Value::dict(vec![(Value::string("a"), Value::Int(1))]);
+        assert_eq!(drain(&dict), [Value::Tuple(Rc::from([Value::string("a"), Value::Int(1)]))]);
+        assert_eq!(drain(&Value::string("hé!")), [Value::string("h"), Value::string("é"), Value::string("!")]);
+        assert_eq!(drain(&Value::Range(-1, 2)), [Value::Int(-1), Value::Int(0), Value::Int(1)]);
+        assert!(drain(&Value::Range(3, 1)).is_empty());
+        assert!(Cursor::new(&Value::Int(1)).is_none());
+    }
+}
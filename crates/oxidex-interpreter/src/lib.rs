@@ -3,15 +3,70 @@
 //! This crate provides an AST interpreter for `OxideX`, including:
 //! - AST evaluation
 //! - Environment and scope management
+//! - Variable resolution to scope slots ahead of evaluation
+//! - Cycle collection for reference-counted values
 //! - REPL (Read-Eval-Print Loop)
 //! - Built-in functions and operations
+//! - Debugger hooks: breakpoints, stepping and frame inspection
+//! - Module loading and import resolution
+//! - Resource limits and cooperative cancellation
+//! - Sandboxing of untrusted scripts
 //!
-//! **Phase:** 7 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 7 - In progress
+//! **Status:** Tree-walking evaluator and built-ins implemented; REPL pending
 
 #![warn(missing_docs)]
+#![allow(clippy::result_large_err)]
+
+/// Runtime errors
+pub mod error;
+
+/// Runtime values
+pub mod value;
+
+/// Lexical environments
+pub mod env;
+
+/// Variable resolution
+pub mod resolve;
+
+/// Cycle collection
+pub mod gc;
+
+/// Tree-walking evaluation
+pub mod eval;
+
+/// Built-in functions
+pub mod builtins;
+
+/// The iteration protocol
+pub mod iter;
+
+/// Messaging between interpreted objects and native code
+pub mod native;
+
+/// Debugger hooks
+pub mod debug;
+
+/// Modules and imports
+pub mod module;
+
+/// Resource limits and cancellation
+pub mod limits;
+
+/// Sandboxing of untrusted scripts
+pub mod sandbox;
 
 // Module declarations will be added during Phase 7 implementation:
-// pub mod eval;
-// pub mod env;
 // pub mod repl;
+
+pub use builtins::{Builtin, Builtins};
+pub use debug::{Breakpoint, Breakpoints, Command, Debugger, Pause};
+pub use env::Environment;
+pub use error::{Result, RuntimeError, StackFrame};
+pub use eval::Interpreter;
+pub use gc::Collector;
+pub use limits::{CancelToken, Limits};
+pub use module::{ModuleSource, Resolver};
+pub use sandbox::{Capability, Sandbox};
+pub use value::Value;
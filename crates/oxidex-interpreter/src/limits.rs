@@ -0,0 +1,101 @@
+//! Resource limits and cancellation.
+//!
+//! [`Limits`] bound how much work one evaluation may do: how many steps it
+//! takes, how deep its calls nest and how long it runs. A step is a
+//! statement, a loop iteration or a call. Each call to
+//! [`Interpreter::eval`](crate::Interpreter::eval),
+//! [`exec`](crate::Interpreter::exec), [`call`](crate::Interpreter::call) or
+//! [`load`](crate::Interpreter::load) starts a fresh budget.
+//!
+//! A [`CancelToken`] stops evaluation from outside, such as from another
+//! thread or a signal handler. The interpreter checks limits and the token
+//! before each step, and stops with [`RuntimeError::Interrupted`], which
+//! scripts cannot catch.
+//!
+//! [`RuntimeError::Interrupted`]: crate::RuntimeError::Interrupted
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Bounds on one evaluation. No limit is set by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Most steps an evaluation may take
+    pub max_steps: Option<u64>,
+    /// Most calls that may be active at once
+    pub max_depth: Option<usize>,
+    /// Longest an evaluation may run
+    pub timeout: Option<Duration>,
+}
+
+/// Shared flag asking the interpreter to stop.
+///
+/// Clones share the flag, and can be sent to other threads.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a token that is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask evaluation to stop at its next step.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`CancelToken::cancel`] was called since the last reset.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Let evaluation run again. A cancelled token stops every evaluation
+    /// until it is reset.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Why evaluation was stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// The step limit was reached
+    Steps(u64),
+    /// The depth limit was reached
+    Depth(usize),
+    /// The timeout elapsed
+    Timeout(Duration),
+    /// The cancel token was cancelled
+    Cancelled,
+}
+
+impl fmt::Display for Interrupt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Steps(steps) => write!(f, "took more than {steps} steps"),
+            Self::Depth(depth) => write!(f, "nested more than {depth} calls"),
+            Self::Timeout(timeout) => write!(f, "ran longer than {timeout:?}"),
+            Self::Cancelled => write!(f, "was cancelled"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_token_is_shared() {
+        let token = CancelToken::new();
+        let remote = token.clone();
+        std::thread::spawn(move || remote.cancel()).join().unwrap();
+        assert!(token.is_cancelled());
+        token.reset();
+        assert!(!token.is_cancelled());
+    }
+}
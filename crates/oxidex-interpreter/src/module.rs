@@ -0,0 +1,172 @@
+//! Modules and imports.
+//!
+//! A program can span several files. `import "path";` evaluates another
+//! file once, in its own globals, and binds its public functions, constants
+//! and statics in the importing module. Later imports of the same file
+//! reuse the loaded module.
+//!
+//! A [`Resolver`] turns the path an import names into a file: paths
+//! starting with `./` or `../` are relative to the importing file, others
+//! are looked up in each search path in turn. The `.ox` extension may be
+//! left out. A path whose first component names a package is looked up in
+//! the package's directory instead, and the package's name alone stands for
+//! its [`LIBRARY`] module.
+//!
+//! The interpreter does not parse. A [`ModuleSource`] supplies each file's
+//! declarations, which must already be type checked and lowered as part of
+//! the program the interpreter runs.
+
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::Decl;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Extension of source files.
+pub const EXTENSION: &str = "ox";
+
+/// Name of the module an import of a package by its name alone finds.
+pub const LIBRARY: &str = "lib";
+
+/// Finds the files imports name.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    search_paths: Vec<PathBuf>,
+    packages: Vec<(String, PathBuf)>,
+}
+
+impl Resolver {
+    /// Create a resolver with no search paths.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory to look up non-relative imports in, after the ones
+    /// added before it.
+    pub fn add_search_path(&mut self, path: impl Into<PathBuf>) {
+        self.search_paths.push(path.into());
+    }
+
+    /// Get the search paths, in lookup order.
+    #[must_use]
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Make imports starting with `name` look in `dir`. A package added
+    /// before it under the same name takes precedence.
+    pub fn add_package(&mut self, name: impl Into<String>, dir: impl Into<PathBuf>) {
+        self.packages.push((name.into(), dir.into()));
+    }
+
+    /// Find the file an import names.
+    ///
+    /// `importer` is the file containing the import; relative imports in a
+    /// module without a file resolve against the working directory. The
+    /// file is returned in canonical form, so each file has one path.
+    #[must_use]
+    pub fn resolve(&self, path: &str, importer: Option<&Path>) -> Option<PathBuf> {
+        if path.starts_with("./") || path.starts_with("../") {
+            let base = importer.and_then(Path::parent).unwrap_or(Path::new("."));
+            return find(&base.join(path));
+        }
+        let (first, rest) = path.split_once('/').unwrap_or((path, LIBRARY));
+        if let Some((_, dir)) = self.packages.iter().find(|(name, _)| name == first) {
+            return find(&dir.join(rest));
+        }
+        self.search_paths.iter().find_map(|dir| find(&dir.join(path)))
+    }
+}
+
+/// Find a source file, trying the path as written and then with the source
+/// extension.
+fn find(path: &Path) -> Option<PathBuf> {
+    let with_extension = path.with_extension(EXTENSION);
+    [path, with_extension.as_path()]
+        .into_iter()
+        .filter(|candidate| candidate.is_file())
+        .find_map(|candidate| candidate.canonicalize().ok())
+}
+
+/// Supplies the declarations of imported modules.
+pub trait ModuleSource<'a> {
+    /// Get the declarations of the module in `file`, a canonical path the
+    /// resolver found.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the declarations are unavailable.
+    fn declarations(&mut self, file: &Path) -> Result<&'a [Decl<'a>], String>;
+}
+
+/// Modules parsed ahead of time, by file.
+impl<'a> ModuleSource<'a> for HashMap<PathBuf, &'a [Decl<'a>]> {
+    fn declarations(&mut self, file: &Path) -> Result<&'a [Decl<'a>], String> {
+        self.get(file).copied().ok_or_else(|| "the module was not parsed with the program".to_string())
+    }
+}
+
+/// A module the interpreter loaded.
+#[derive(Debug, Clone)]
+pub(crate) struct Module {
+    /// Name of the module's file, for breakpoints and messages
+    pub file: String,
+    /// Path of the file, which relative imports resolve against
+    pub path: PathBuf,
+    /// Public globals, which importers bind
+    pub exports: Vec<Symbol>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_resolver_finds_relative_and_search_path_modules() {
+        let root = std::env::temp_dir().join(format!("oxidex-resolver-{}", std::process::id()));
+        let (app, lib) = (root.join("app"), root.join("lib"));
+        fs::create_dir_all(app.join("util")).unwrap();
+        fs::create_dir_all(&lib).unwrap();
+        for file in [app.join("main.ox"), app.join("util/math.ox"), lib.join("strings.ox")] {
+            fs::write(file, "").unwrap();
+        }
+
+        let mut resolver = Resolver::new();
+        resolver.add_search_path(&lib);
+        let main = app.join("main.ox");
+        let math = app.join("util/math.ox").canonicalize().unwrap();
+        assert_eq!(resolver.resolve("./util/math", Some(&main)), Some(math.clone()));
+        assert_eq!(resolver.resolve("./util/math.ox", Some(&main)), Some(math.clone()));
+        assert_eq!(resolver.resolve("../main", Some(&math)), Some(main.canonicalize().unwrap()));
+        assert_eq!(resolver.resolve("strings", Some(&main)), Some(lib.join("strings.ox").canonicalize().unwrap()));
+
+        // Relative imports ignore search paths, and others ignore the importer
+        assert_eq!(resolver.resolve("./strings", Some(&main)), None);
+        assert_eq!(resolver.resolve("util/math", Some(&main)), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_resolver_finds_package_modules() {
+        let root = std::env::temp_dir().join(format!("oxidex-packages-{}", std::process::id()));
+        let (math, shadow) = (root.join("math/src"), root.join("shadow"));
+        fs::create_dir_all(math.join("linear")).unwrap();
+        fs::create_dir_all(shadow.join("math")).unwrap();
+        for file in [math.join("lib.ox"), math.join("linear/vec.ox"), shadow.join("math/vec.ox")] {
+            fs::write(file, "").unwrap();
+        }
+
+        let mut resolver = Resolver::new();
+        resolver.add_search_path(&shadow);
+        resolver.add_package("math", &math);
+        assert_eq!(resolver.resolve("math", None), Some(math.join("lib.ox").canonicalize().unwrap()));
+        let vec = math.join("linear/vec.ox").canonicalize().unwrap();
+        assert_eq!(resolver.resolve("math/linear/vec", None), Some(vec));
+
+        // Packages hide search paths, rather than falling back to them
+        assert_eq!(resolver.resolve("math/vec", None), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+//! Messaging between interpreted objects and native code.
+//!
+//! The program's classes are registered with the runtime as real classes
+//! whose instance methods are bound to the codegen IMP thunk. The
+//! interpreter installs [`handle_message`] as the thunk's handler; while an
+//! interpreter is attached to the current thread, a message sent to one of
+//! its objects re-enters it to run the method's body.
+//!
+//! Values cross the boundary as machine words, encoded as the method's
+//! signature describes them: integers and booleans as integers, floats as
+//! their bits and instances as their object pointers.
+//!
+//! Functions declared in `extern` blocks are C functions; their arguments
+//! and results cross as [`ForeignValue`]s, which the runtime converts to
+//! the C types of the declared signature.
+
+use crate::error::{Result, RuntimeError};
+use crate::value::Value;
+use oxidec::Selector;
+use oxidec::runtime::ObjectPtr;
+use oxidec::runtime::encoding::parse_signature;
+use oxidec::runtime::ffi::ForeignValue;
+use oxidex_syntax::Span;
+use oxidex_typecheck::{PrimTy, Ty};
+use std::cell::Cell;
+use std::ptr::NonNull;
+
+/// Something that runs messages sent to interpreted objects.
+pub(crate) trait Receiver {
+    /// Run the method `selector` names on the object at `receiver`.
+    fn receive(&mut self, receiver: usize, selector: &Selector, args: &[usize]) -> Option<usize>;
+}
+
+thread_local! {
+    /// The receiver attached to this thread, innermost attachment only.
+    static ACTIVE: Cell<Option<NonNull<dyn Receiver>>> = const { Cell::new(None) };
+}
+
+/// Restores the previous attachment, even when unwinding.
+struct Detach(Option<NonNull<dyn Receiver>>);
+
+impl Drop for Detach {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.set(self.0));
+    }
+}
+
+/// Attach `receiver` to the current thread while `f` runs.
+pub(crate) fn attach<R>(receiver: &mut dyn Receiver, f: impl FnOnce() -> R) -> R {
+    let receiver = NonNull::from(receiver);
+    // SAFETY: the pointer is only dereferenced by `handle_message` while `f`
+    // runs, and `receiver` stays mutably borrowed until then; the guard
+    // removes the pointer before the borrow ends. Erasing the lifetime is
+    // sound for the same reason.
+    let receiver: NonNull<dyn Receiver> = unsafe { std::mem::transmute(receiver) };
+    let _detach = Detach(ACTIVE.with(|active| active.replace(Some(receiver))));
+    f()
+}
+
+/// Method handler that forwards messages to the attached interpreter.
+///
+/// Messages sent while no interpreter is attached, or to objects the
+/// attached interpreter did not create, do nothing.
+pub fn handle_message(receiver: ObjectPtr, selector: &Selector, args: &[usize]) -> Option<usize> {
+    let mut active = ACTIVE.with(Cell::get)?;
+    // SAFETY: `attach` keeps the pointee exclusively borrowed for as long as
+    // the pointer is installed, and messages are delivered on its thread
+    unsafe { active.as_mut() }.receive(object_address(receiver), selector, args)
+}
+
+/// Get the address of an object, which identifies it across the boundary.
+pub(crate) fn object_address(object: ObjectPtr) -> usize {
+    // SAFETY: `ObjectPtr` is a transparent wrapper around a raw pointer
+    unsafe { std::mem::transmute::<ObjectPtr, *mut u8>(object) as usize }
+}
+
+/// Encode a value as an argument or return word.
+pub(crate) fn encode(value: &Value, span: Span) -> Result<usize> {
+    match value {
+        Value::Unit | Value::Nil => Ok(0),
+        Value::Bool(value) => Ok(usize::from(*value)),
+        Value::Int(value) => Ok(*value as usize),
+        Value::Float(value) => Ok(value.to_bits() as usize),
+        Value::Object(instance) => Ok(object_address(instance.object.as_raw())),
+        _ => Err(RuntimeError::Unsupported { construct: "passing this value to native code", span }),
+    }
+}
+
+/// Decode a word of a lowered type. Instances are looked up with `object`.
+pub(crate) fn decode(ty: &Ty, word: usize, object: impl Fn(usize) -> Option<Value>) -> Option<Value> {
+    match ty {
+        Ty::Primitive(prim) => Some(match prim {
+            PrimTy::Unit => Value::Unit,
+            PrimTy::Bool => Value::Bool(word != 0),
+            PrimTy::Int8 | PrimTy::Int16 | PrimTy::Int32 => Value::Int(i64::from(word as i32)),
+            PrimTy::UInt8 | PrimTy::UInt16 | PrimTy::UInt32 | PrimTy::Char => Value::Int(i64::from(word as u32)),
+            PrimTy::Int64 | PrimTy::UInt64 => Value::Int(word as i64),
+            PrimTy::Float32 => Value::Float(f64::from(f32::from_bits(word as u32))),
+            PrimTy::Float64 => Value::Float(f64::from_bits(word as u64)),
+            PrimTy::Int128 | PrimTy::UInt128 | PrimTy::String => return None,
+        }),
+        Ty::Class { .. } | Ty::Struct { .. } => object(word),
+        _ => None,
+    }
+}
+
+/// Decode the return word of a native method from its type encoding.
+pub(crate) fn decode_return(
+    encoding: &str,
+    word: Option<usize>,
+    object: impl Fn(usize) -> Option<Value>,
+) -> Option<Value> {
+    let (return_type, _) = parse_signature(encoding).ok()?;
+    let word = word.unwrap_or_default();
+    match return_type {
+        'v' => Some(Value::Unit),
+        'c' | 's' | 'i' | 'l' => Some(Value::Int(i64::from(word as i32))),
+        'C' | 'S' | 'I' | 'L' => Some(Value::Int(i64::from(word as u32))),
+        'q' | 'Q' => Some(Value::Int(word as i64)),
+        'B' => Some(Value::Bool(word != 0)),
+        'f' => Some(Value::Float(f64::from(f32::from_bits(word as u32)))),
+        'd' => Some(Value::Float(f64::from_bits(word as u64))),
+        '@' => object(word),
+        _ => None,
+    }
+}
+
+/// Convert a value to an argument of a C function.
+pub(crate) fn to_foreign(value: &Value, span: Span) -> Result<ForeignValue> {
+    match value {
+        Value::Nil => Ok(ForeignValue::Nil),
+        Value::Bool(value) => Ok(ForeignValue::Bool(*value)),
+        Value::Int(value) => Ok(ForeignValue::Int(*value)),
+        Value::Float(value) => Ok(ForeignValue::Float(*value)),
+        Value::String(value) => Ok(ForeignValue::String(value.clone())),
+        _ => Err(RuntimeError::Unsupported { construct: "passing this value to a C function", span }),
+    }
+}
+
+/// Convert the result of a C function to a value.
+pub(crate) fn from_foreign(value: ForeignValue) -> Value {
+    match value {
+        ForeignValue::Void => Value::Unit,
+        ForeignValue::Nil => Value::Nil,
+        ForeignValue::Int(value) => Value::Int(value),
+        ForeignValue::Bool(value) => Value::Bool(value),
+        ForeignValue::Float(value) => Value::Float(value),
+        ForeignValue::String(value) => Value::String(value),
+    }
+}
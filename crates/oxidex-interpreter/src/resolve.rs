@@ -0,0 +1,386 @@
+//! Variable resolution.
+//!
+//! Before code runs, a resolution pass finds the binding each reference to
+//! a local variable denotes, as a [`Slot`]: how many scopes out from the
+//! innermost one the binding is, and its position in that scope. Scopes
+//! keep their bindings in definition order, so the evaluator reads a
+//! resolved variable by index instead of searching every scope by name.
+//!
+//! The pass mirrors the evaluator's scopes: the receiver and parameters of
+//! a call make up its frame's first scope, and blocks, match arms and loop
+//! iterations each push one. Bodies a block defers are resolved as of the
+//! end of the block. The evaluator checks that a slot holds the name it
+//! expects, and looks a name up by name when it does not (as when only one
+//! side of an or-pattern bound) or when the reference is not resolved.
+//! References to globals, and to locals that top-level code defined before
+//! it was resolved, are never resolved.
+
+use oxidex_mem::Symbol;
+use oxidex_syntax::Expr;
+use oxidex_syntax::ast::expr::{InterpolationPart, MatchArm};
+use oxidex_syntax::ast::pat::Pattern;
+use oxidex_syntax::ast::stmt::Stmt;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Where a local variable's binding is, relative to the code using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    /// Scopes between the innermost scope and the binding's
+    pub depth: usize,
+    /// Position of the binding in its scope
+    pub index: usize,
+}
+
+/// Slots of the variable references in some code.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Slots by reference address; `None` for references to globals, and
+    /// for nodes used in several places that resolve differently
+    slots: HashMap<usize, Option<Slot>, BuildHasherDefault<AddressHasher>>,
+}
+
+impl Resolution {
+    /// Resolve the body of a function or method, whose frame starts out
+    /// with `params` bound in order.
+    #[must_use]
+    pub fn function(params: impl IntoIterator<Item = Symbol>, body: &Expr<'_>) -> Self {
+        let mut pass = Pass { resolution: Self::default(), scopes: vec![Vec::new()] };
+        for param in params {
+            pass.define(param);
+        }
+        pass.expr(body);
+        pass.resolution
+    }
+
+    /// Resolve top-level code, which runs in scopes the pass knows nothing
+    /// of.
+    #[must_use]
+    pub fn top_level_expr(expr: &Expr<'_>) -> Self {
+        let mut pass = Pass { resolution: Self::default(), scopes: Vec::new() };
+        pass.expr(expr);
+        pass.resolution
+    }
+
+    /// Resolve a top-level statement (see [`Resolution::top_level_expr`]).
+    #[must_use]
+    pub fn top_level_stmt(stmt: &Stmt<'_>) -> Self {
+        let mut pass = Pass { resolution: Self::default(), scopes: Vec::new() };
+        pass.stmt(stmt);
+        pass.resolution
+    }
+
+    /// Get the slot of a variable reference, if it was resolved.
+    #[must_use]
+    pub fn get(&self, reference: &Expr<'_>) -> Option<Slot> {
+        self.slots.get(&address(reference)).copied().flatten()
+    }
+
+    /// Number of references resolved.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.slots.values().flatten().count()
+    }
+
+    /// Whether no reference was resolved.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn address(expr: &Expr<'_>) -> usize {
+    std::ptr::from_ref(expr) as usize
+}
+
+/// Hashes addresses by folding a multiplication, so the low bits the
+/// table indexes by depend on every bit of the address.
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64((self.0 << 8) | u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        let product = u128::from(n ^ self.0).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        #[allow(clippy::cast_possible_truncation)]
+        let folded = (product as u64) ^ ((product >> 64) as u64);
+        self.0 = folded;
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+}
+
+/// State of a resolution pass.
+struct Pass {
+    resolution: Resolution,
+    /// Names bound in each scope the pass entered, outermost first
+    scopes: Vec<Vec<Symbol>>,
+}
+
+impl Pass {
+    /// Bind a name in the innermost scope. Binding a name again replaces
+    /// its binding, which keeps its position.
+    fn define(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut()
+            && !scope.contains(&name)
+        {
+            scope.push(name);
+        }
+    }
+
+    fn define_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard { .. } | Pattern::Literal { .. } => {}
+            Pattern::Variable { name, .. } => self.define(*name),
+            Pattern::Struct { fields, .. } => {
+                for field in fields {
+                    match &field.pattern {
+                        Some(pattern) => self.define_pattern(pattern),
+                        None => self.define(field.name),
+                    }
+                }
+            }
+            Pattern::Enum { payload, .. } => {
+                if let Some(payload) = payload {
+                    self.define_pattern(payload);
+                }
+            }
+            Pattern::Tuple { elements, .. } => elements.iter().for_each(|element| self.define_pattern(element)),
+            Pattern::Array { elements, rest, .. } => {
+                elements.iter().for_each(|element| self.define_pattern(element));
+                if let Some(rest) = rest {
+                    self.define_pattern(rest);
+                }
+            }
+            Pattern::Or { left, right, .. } => {
+                self.define_pattern(left);
+                self.define_pattern(right);
+            }
+        }
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn reference(&mut self, expr: &Expr<'_>, name: Symbol) {
+        let found = self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            scope.iter().position(|bound| *bound == name).map(|index| Slot { depth, index })
+        });
+        let entry = self.resolution.slots.entry(address(expr)).or_insert(found);
+        if *entry != found {
+            *entry = None;
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<'_>) {
+        match expr {
+            Expr::IntegerLiteral { .. }
+            | Expr::FloatLiteral { .. }
+            | Expr::StringLiteral { .. }
+            | Expr::BoolLiteral { .. }
+            | Expr::Nil { .. }
+            | Expr::Hole { .. }
+            | Expr::Path { .. }
+            // The evaluator does not run closures
+            | Expr::Closure { .. } => {}
+            Expr::Identifier(name) => self.reference(expr, *name),
+            Expr::Unary { operand, .. } => self.expr(operand),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::If { condition, then_branch, else_branch, .. } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Match { scrutinee, arms, .. } => self.match_arms(scrutinee, arms),
+            Expr::Block { stmts, expr, .. } => self.scoped(|pass| {
+                let mut deferred = Vec::new();
+                for stmt in stmts {
+                    match stmt {
+                        Stmt::Defer { body, .. } => deferred.push(*body),
+                        stmt => pass.stmt(stmt),
+                    }
+                }
+                if let Some(expr) = expr {
+                    pass.expr(expr);
+                }
+                for body in deferred {
+                    pass.expr(body);
+                }
+            }),
+            Expr::ForLoop { pattern, iter, body, .. } => self.for_loop(pattern, iter, body),
+            Expr::WhileLoop { condition, body, .. } => {
+                self.expr(condition);
+                self.expr(body);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg.value));
+            }
+            Expr::MethodCall { receiver, args, .. } => {
+                self.expr(receiver);
+                args.iter().for_each(|arg| self.expr(arg.value));
+            }
+            Expr::Struct { fields, .. } => {
+                fields.iter().filter_map(|field| field.value).for_each(|value| self.expr(value));
+            }
+            Expr::Enum { payload, .. } => {
+                if let Some(payload) = payload {
+                    self.expr(payload);
+                }
+            }
+            Expr::Array { elements, .. } => elements.iter().for_each(|element| self.expr(element)),
+            Expr::Dict { entries, .. } => {
+                for entry in entries {
+                    self.expr(entry.key);
+                    self.expr(entry.value);
+                }
+            }
+            Expr::Field { object, .. } => self.expr(object),
+            Expr::Index { collection, index, .. } => {
+                self.expr(collection);
+                self.expr(index);
+            }
+            Expr::Paren { expr, .. } | Expr::Comptime { expr, .. } => self.expr(expr),
+            Expr::Interpolation { parts, .. } => {
+                for part in parts {
+                    if let InterpolationPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+        }
+    }
+
+    fn match_arms(&mut self, scrutinee: &Expr<'_>, arms: &[MatchArm<'_>]) {
+        self.expr(scrutinee);
+        for arm in arms {
+            self.scoped(|pass| {
+                pass.define_pattern(&arm.pattern);
+                if let Some(guard) = arm.guard {
+                    pass.expr(guard);
+                }
+                pass.expr(arm.body);
+            });
+        }
+    }
+
+    fn for_loop(&mut self, pattern: &Pattern, iter: &Expr<'_>, body: &Expr<'_>) {
+        self.expr(iter);
+        self.scoped(|pass| {
+            pass.define_pattern(pattern);
+            pass.expr(body);
+        });
+    }
+
+    fn stmt(&mut self, stmt: &Stmt<'_>) {
+        match stmt {
+            Stmt::Let { name, init, .. } | Stmt::Mut { name, init, .. } => {
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                self.define(*name);
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::If { condition, then_branch, else_branch, .. } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            Stmt::Guard { condition, else_branch, .. } => {
+                self.expr(condition);
+                self.expr(else_branch);
+            }
+            Stmt::Match { scrutinee, arms, .. } => self.match_arms(scrutinee, arms),
+            Stmt::ForLoop { pattern, iter, body, .. } => self.for_loop(pattern, iter, body),
+            Stmt::WhileLoop { condition, body, .. } => {
+                self.expr(condition);
+                self.expr(body);
+            }
+            Stmt::Defer { body, .. } => self.expr(body),
+            Stmt::Assign { target, value, .. } => {
+                self.expr(value);
+                self.expr(target);
+            }
+            Stmt::Expr { expr, .. } => self.expr(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_syntax::Span;
+
+    #[test]
+    fn test_references_resolve_to_scope_and_position() {
+        let (x, y, z, a) = (Symbol::new(0), Symbol::new(1), Symbol::new(2), Symbol::new(3));
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+
+        // fn f(a) { let x = a; let y = x; { let x = y; z; x } }
+        let (a_ref, x_ref, y_ref, z_ref, inner_x) =
+            (Expr::Identifier(a), Expr::Identifier(x), Expr::Identifier(y), Expr::Identifier(z), Expr::Identifier(x));
+        let inner = Expr::Block {
+            stmts: vec![
+                Stmt::Let { name: x, type_annotation: None, init: Some(&y_ref), span },
+                Stmt::Expr { expr: &z_ref, span },
+            ],
+            expr: Some(&inner_x),
+            span,
+        };
+        let body = Expr::Block {
+            stmts: vec![
+                Stmt::Let { name: x, type_annotation: None, init: Some(&a_ref), span },
+                Stmt::Let { name: y, type_annotation: None, init: Some(&x_ref), span },
+            ],
+            expr: Some(&inner),
+            span,
+        };
+
+        let resolution = Resolution::function([a], &body);
+        assert_eq!(resolution.get(&a_ref), Some(Slot { depth: 1, index: 0 }));
+        assert_eq!(resolution.get(&x_ref), Some(Slot { depth: 0, index: 0 }));
+        assert_eq!(resolution.get(&y_ref), Some(Slot { depth: 1, index: 1 }));
+        assert_eq!(resolution.get(&inner_x), Some(Slot { depth: 0, index: 0 }));
+        // Globals are looked up by name
+        assert_eq!(resolution.get(&z_ref), None);
+        assert_eq!(resolution.len(), 4);
+
+        // Top-level code resolves only what it binds itself
+        let resolution = Resolution::top_level_expr(&body);
+        assert_eq!(resolution.get(&a_ref), None);
+        assert_eq!(resolution.get(&y_ref), Some(Slot { depth: 1, index: 1 }));
+
+        // A node used where it resolves differently is not resolved
+        let shared = Expr::Block {
+            stmts: vec![Stmt::Let { name: x, type_annotation: None, init: Some(&x_ref), span }],
+            expr: Some(&x_ref),
+            span,
+        };
+        assert!(Resolution::function([y, x], &shared).get(&x_ref).is_none());
+    }
+}
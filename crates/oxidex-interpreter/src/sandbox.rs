@@ -0,0 +1,29 @@
+//! Sandboxing untrusted scripts.
+//!
+//! A [`Sandbox`] denies a script the [`Capability`]s that reach outside the
+//! interpreter, so that an embedder can run scripts it does not trust. It is
+//! enforced in two places:
+//!
+//! - The builtin registry: a builtin registered with
+//!   [`Builtins::register_requiring`](crate::Builtins::register_requiring)
+//!   names the capability it needs. [`Builtins::restrict`](crate::Builtins::restrict)
+//!   drops the builtins a sandbox denies, so programs calling them fail to
+//!   type check, and the interpreter refuses to call them.
+//! - The loader: an import loads code at run time, so it needs
+//!   [`Capability::DynamicLoading`], and finds its file on disk, so it needs
+//!   [`Capability::FileIo`]. Messages to methods the program does not define
+//!   and calls to `extern` functions run native code, so they need
+//!   [`Capability::Ffi`].
+//!
+//! A denied operation raises [`RuntimeError::Sandboxed`], which scripts
+//! cannot catch, so that a script cannot probe what it is denied.
+//!
+//! The sandbox is the runtime's (see [`oxidec::runtime::sandbox`]), which
+//! the VM shares. The interpreter enters it while a builtin runs, so that
+//! the runtime refuses native libraries to builtins when the sandbox denies
+//! [`Capability::Ffi`].
+//!
+//! [`RuntimeError::Sandboxed`]: crate::RuntimeError::Sandboxed
+
+pub use oxidec::runtime::sandbox::{Capability, Sandbox};
+
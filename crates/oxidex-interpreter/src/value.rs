@@ -0,0 +1,356 @@
+//! Runtime values.
+//!
+//! Scalars and strings are stored inline. Collections and instances are
+//! shared: copying a value copies a reference, as assigning an array or an
+//! object does in compiled code. They are reference counted; cycles among
+//! them are freed by the [cycle collector](crate::gc).
+//!
+//! Instances of classes and structs are backed by a runtime object of the
+//! type's registered class, so they keep an identity the runtime can
+//! dispatch on. The runtime does not store instance variables yet, so the
+//! fields live beside the object handle.
+//!
+//! Closures hold the variables they capture as the checker decided: a copy
+//! of each variable that cannot change, and a box shared with the
+//! enclosing function for each one that can.
+
+use crate::iter::Cursor;
+use oxidec::Object;
+use oxidex_mem::Symbol;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Write as _;
+use std::rc::Rc;
+
+/// A value produced by evaluation.
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// The unit value
+    Unit,
+    /// `nil`
+    Nil,
+    /// Boolean
+    Bool(bool),
+    /// Integer of any width (and characters)
+    Int(i64),
+    /// Floating point number of any width
+    Float(f64),
+    /// String
+    String(String),
+    /// Tuple
+    Tuple(Rc<[Value]>),
+    /// Array
+    Array(Rc<RefCell<Vec<Value>>>),
+    /// Dictionary, in insertion order
+    Dict(Rc<RefCell<Vec<(Value, Value)>>>),
+    /// Instance of a class or struct
+    Object(Rc<Instance>),
+    /// Enum value
+    Variant(Rc<Variant>),
+    /// Named function or static method (see [`oxidex_codegen::ir::method_symbol`])
+    Function(Rc<str>),
+    /// Closure
+    Closure(Rc<Closure>),
+    /// Integers from a start up to an exclusive end
+    Range(i64, i64),
+    /// Iterator over a built-in sequence
+    Iterator(Rc<RefCell<Cursor>>),
+}
+
+/// An instance of a class or struct.
+#[derive(Debug)]
+pub struct Instance {
+    /// Name of the instance's class
+    pub class: String,
+    /// Runtime object giving the instance its identity
+    pub object: Object,
+    /// Fields set so far, in assignment order
+    pub fields: RefCell<Vec<(String, Value)>>,
+}
+
+impl Instance {
+    /// Get the value of a field.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().iter().find(|(field, _)| field == name).map(|(_, value)| value.clone())
+    }
+
+    /// Set the value of a field.
+    pub fn set_field(&self, name: &str, value: Value) {
+        let mut fields = self.fields.borrow_mut();
+        match fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, slot)) => *slot = value,
+            None => fields.push((name.to_string(), value)),
+        }
+    }
+}
+
+/// A closure, with the variables it captured.
+#[derive(Debug)]
+pub struct Closure {
+    /// Address of the closure expression, which its parameters and body are
+    /// found by
+    pub expr: usize,
+    /// Captured variables other than `self`, in the order the body first
+    /// uses them
+    pub captures: Vec<(Symbol, Captured)>,
+    /// Receiver of the method the closure was created in, if it uses `self`
+    pub receiver: Option<Value>,
+    /// Type whose method the closure was created in
+    pub owner: Option<String>,
+    /// Module the closure was created in
+    pub module: usize,
+}
+
+/// How a closure holds a captured variable.
+#[derive(Debug, Clone)]
+pub enum Captured {
+    /// A copy of the variable's value
+    Value(Value),
+    /// The box the variable shares with the enclosing function
+    Box(Rc<RefCell<Value>>),
+}
+
+impl Captured {
+    /// Get the current value.
+    #[must_use]
+    pub fn get(&self) -> Value {
+        match self {
+            Self::Value(value) => value.clone(),
+            Self::Box(cell) => cell.borrow().clone(),
+        }
+    }
+}
+
+/// A value of an enum type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    /// Enum name
+    pub enum_name: String,
+    /// Variant name
+    pub variant: String,
+    /// Payload; a tuple for variants with several fields
+    pub payload: Option<Value>,
+}
+
+impl Value {
+    /// Create a string value.
+    #[must_use]
+    pub fn string(text: impl Into<String>) -> Self {
+        Self::String(text.into())
+    }
+
+    /// Create an array value.
+    #[must_use]
+    pub fn array(elements: Vec<Value>) -> Self {
+        Self::Array(Rc::new(RefCell::new(elements)))
+    }
+
+    /// Create a dictionary value.
+    #[must_use]
+    pub fn dict(entries: Vec<(Value, Value)>) -> Self {
+        Self::Dict(Rc::new(RefCell::new(entries)))
+    }
+
+    /// Create an enum value.
+    #[must_use]
+    pub fn variant(enum_name: impl Into<String>, variant: impl Into<String>, payload: Option<Value>) -> Self {
+        Self::Variant(Rc::new(Variant { enum_name: enum_name.into(), variant: variant.into(), payload }))
+    }
+
+    /// Describe the kind of the value, for error messages.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Unit => "unit",
+            Self::Nil => "nil",
+            Self::Bool(_) => "a boolean",
+            Self::Int(_) => "an integer",
+            Self::Float(_) => "a float",
+            Self::String(_) => "a string",
+            Self::Tuple(_) => "a tuple",
+            Self::Array(_) => "an array",
+            Self::Dict(_) => "a dictionary",
+            Self::Object(_) => "an object",
+            Self::Variant(_) => "an enum value",
+            Self::Function(_) | Self::Closure(_) => "a function",
+            Self::Range(..) => "a range",
+            Self::Iterator(_) => "an iterator",
+        }
+    }
+
+    /// Get the name of the user type the value is an instance of.
+    #[must_use]
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            Self::Object(instance) => Some(&instance.class),
+            Self::Variant(variant) => Some(&variant.enum_name),
+            _ => None,
+        }
+    }
+
+    /// Render the value over several lines, with each element, entry or
+    /// field of a container on a line of its own, indented under it, as
+    /// `{:#?}` renders Rust values. A line diff of two renderings shows
+    /// which elements differ. Other values render as `{:#}` displays them.
+    #[must_use]
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        write_pretty(&mut out, self, 0);
+        out
+    }
+}
+
+fn write_pretty(out: &mut String, value: &Value, depth: usize) {
+    let unlabeled = |values: &[Value]| values.iter().map(|value| (String::new(), value.clone())).collect::<Vec<_>>();
+    let (open, items, close) = match value {
+        Value::Tuple(elements) if !elements.is_empty() => ("(".to_string(), unlabeled(elements), ")"),
+        Value::Array(elements) if !elements.borrow().is_empty() => {
+            ("[".to_string(), unlabeled(&elements.borrow()), "]")
+        }
+        Value::Dict(entries) if !entries.borrow().is_empty() => {
+            let entries = entries.borrow().iter().map(|(key, value)| (format!("{key:#}: "), value.clone())).collect();
+            ("[".to_string(), entries, "]")
+        }
+        Value::Object(instance) if !instance.fields.borrow().is_empty() => {
+            let fields = instance.fields.borrow();
+            let fields = fields.iter().map(|(field, value)| (format!("{field}: "), value.clone())).collect();
+            (format!("{}(", instance.class), fields, ")")
+        }
+        Value::Variant(variant) if variant.payload.is_some() => {
+            let payload = match &variant.payload {
+                Some(Value::Tuple(fields)) => unlabeled(fields),
+                payload => unlabeled(payload.as_slice()),
+            };
+            (format!("{}::{}(", variant.enum_name, variant.variant), payload, ")")
+        }
+        value => {
+            let _ = write!(out, "{value:#}");
+            return;
+        }
+    };
+
+    out.push_str(&open);
+    out.push('\n');
+    for (label, value) in &items {
+        out.push_str(&"    ".repeat(depth + 1));
+        out.push_str(label);
+        write_pretty(out, value, depth + 1);
+        out.push_str(",\n");
+    }
+    out.push_str(&"    ".repeat(depth));
+    out.push_str(close);
+}
+
+/// Values compare structurally, except instances, closures and iterators,
+/// which compare by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Unit, Self::Unit) | (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Float(a), Self::Float(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Tuple(a), Self::Tuple(b)) => a == b,
+            (Self::Array(a), Self::Array(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Self::Dict(a), Self::Dict(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().all(|(key, value)| b.iter().any(|(k, v)| k == key && v == value))
+            }
+            (Self::Object(a), Self::Object(b)) => Rc::ptr_eq(a, b),
+            (Self::Variant(a), Self::Variant(b)) => a == b,
+            (Self::Function(a), Self::Function(b)) => a == b,
+            (Self::Closure(a), Self::Closure(b)) => Rc::ptr_eq(a, b),
+            (Self::Range(a, b), Self::Range(c, d)) => (a, b) == (c, d),
+            (Self::Iterator(a), Self::Iterator(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Values display as string interpolation shows them: strings without
+/// quotes at the top level and quoted inside collections. The alternate
+/// form (`{:#}`) quotes top-level strings too.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(text) if !f.alternate() => write!(f, "{text}"),
+            value => write_nested(f, value),
+        }
+    }
+}
+
+fn write_nested(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    let list = |f: &mut fmt::Formatter<'_>, values: &[Value]| -> fmt::Result {
+        for (index, value) in values.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write_nested(f, value)?;
+        }
+        Ok(())
+    };
+
+    match value {
+        Value::Unit => write!(f, "()"),
+        Value::Nil => write!(f, "nil"),
+        Value::Bool(value) => write!(f, "{value}"),
+        Value::Int(value) => write!(f, "{value}"),
+        Value::Float(value) => write!(f, "{value:?}"),
+        Value::String(text) => write!(f, "{text:?}"),
+        Value::Tuple(elements) => {
+            write!(f, "(")?;
+            list(f, elements)?;
+            write!(f, ")")
+        }
+        Value::Array(elements) => {
+            write!(f, "[")?;
+            list(f, &elements.borrow())?;
+            write!(f, "]")
+        }
+        Value::Dict(entries) => {
+            let entries = entries.borrow();
+            if entries.is_empty() {
+                return write!(f, "[:]");
+            }
+            write!(f, "[")?;
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, key)?;
+                write!(f, ": ")?;
+                write_nested(f, value)?;
+            }
+            write!(f, "]")
+        }
+        Value::Object(instance) => {
+            write!(f, "{}(", instance.class)?;
+            for (index, (field, value)) in instance.fields.borrow().iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{field}: ")?;
+                write_nested(f, value)?;
+            }
+            write!(f, ")")
+        }
+        Value::Variant(variant) => {
+            write!(f, "{}::{}", variant.enum_name, variant.variant)?;
+            match &variant.payload {
+                Some(Value::Tuple(fields)) => write_nested(f, &Value::Tuple(fields.clone())),
+                Some(payload) => {
+                    write!(f, "(")?;
+                    write_nested(f, payload)?;
+                    write!(f, ")")
+                }
+                None => Ok(()),
+            }
+        }
+        Value::Function(name) => write!(f, "<fn {name}>"),
+        Value::Closure(_) => write!(f, "<closure>"),
+        Value::Range(start, end) => write!(f, "{start}..{end}"),
+        Value::Iterator(_) => write!(f, "<iterator>"),
+    }
+}
@@ -0,0 +1,731 @@
+//! Baseline compilation.
+//!
+//! The baseline tier translates a stack-encoded function to machine code
+//! one instruction at a time, from a template per kind of instruction. Each
+//! template calls the VM's [helper](oxidex_bytecode::native_helper) for the
+//! instruction's opcode, which executes it as the interpreter would, so the
+//! code behaves exactly like the bytecode. What the code saves is decoding
+//! and dispatching each instruction, and jumps, which become native
+//! branches:
+//!
+//! ```text
+//! call helper(vm, floor, offset)      ; every instruction but SEND
+//! call [site](vm, floor, offset, site)
+//!                                     ; SEND
+//! exit unless status is Next          ; most instructions
+//! branch to target if status is Branch, exit if it is Exit
+//!                                     ; JUMP, JUMP_IF_FALSE and LOOP
+//! ```
+//!
+//! Each `SEND` gets an inline cache of its own, a [`SendSite`] the code
+//! calls through. The site starts out guarding on the class of its first
+//! receiver, taking the method found for it without resolving the message
+//! again, and relinks itself to full dispatch when its receivers turn out to
+//! be of too many classes. Messages are still sent through the runtime, so
+//! replaced implementations are called.
+//!
+//! The code can be entered at any instruction, which is how the VM resumes
+//! it after a call returns, and at the header of a loop in a function that
+//! was compiled while it looped.
+//!
+//! Templates exist for x86-64 and AArch64. Code is assembled into writable
+//! memory, which is made executable, and never writable again, before it
+//! runs. A [`Jit`] compiles each function once the [profile](crate::profile)
+//! finds it warm, and names its code after the function through its
+//! [symbols](crate::symbols). Given the IR of the program, and with the
+//! `cranelift` feature, it recompiles hot functions in the
+//! [optimizing tier](crate::optimize) once they are hot.
+
+mod aarch64;
+mod memory;
+mod x86_64;
+
+#[cfg(feature = "cranelift")]
+use crate::optimize::OptimizedFunction;
+use crate::profile::{Hotness, Profile};
+use crate::stats::{FunctionStats, JitStats, SiteOccupancy, Tier};
+use crate::symbols::{Symbol, Symbols};
+use memory::ExecutableMemory;
+use oxidex_bytecode::{
+    Chunk, Compiler, Encoding, Function, InlineCache, NativeCode, OpCode, SendSite, Vm, native_helper,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// Largest machine code a function compiles to, which keeps every branch
+/// within reach on AArch64.
+pub const MAX_CODE: usize = 1 << 20;
+
+/// Entry table value for offsets that are not the start of an instruction.
+const NO_ENTRY: u32 = u32::MAX;
+
+/// Entry sequence of compiled code: the VM, the frame floor and the address
+/// to start at, returning the last helper's status.
+type Entry = unsafe extern "C" fn(*mut Vm, usize, *const u8) -> u32;
+
+/// An instruction set templates are written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// x86-64, with the System V calling convention
+    X86_64,
+    /// AArch64, with the AAPCS64 calling convention
+    Aarch64,
+}
+
+impl Arch {
+    /// The architecture this program runs on, if there are templates for it.
+    pub const HOST: Option<Self> = if cfg!(target_arch = "x86_64") {
+        Some(Self::X86_64)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(Self::Aarch64)
+    } else {
+        None
+    };
+}
+
+/// Emits the templates for one architecture.
+trait Target {
+    /// Emit the entry sequence, which saves registers, keeps the VM and the
+    /// floor and jumps to the start address, followed by the exit sequence,
+    /// returning the exit's offset.
+    fn entry(code: &mut Vec<u8>) -> usize;
+
+    /// Emit a call to `helper` for the instruction at `offset`.
+    fn call(code: &mut Vec<u8>, helper: usize, offset: u32);
+
+    /// Emit a call through the send site at `site` for the `SEND` at
+    /// `offset`.
+    fn send(code: &mut Vec<u8>, site: usize, offset: u32);
+
+    /// Emit a jump to the exit unless the status is `Next`.
+    fn exit_unless_next(code: &mut Vec<u8>, exit: usize);
+
+    /// Emit a branch on the status `Branch` and a jump to the exit on
+    /// `Exit`, returning where the branch is to be patched.
+    fn branch(code: &mut Vec<u8>, exit: usize) -> usize;
+
+    /// Emit a jump to the exit with the status `Exit`.
+    fn exit(code: &mut Vec<u8>, exit: usize);
+
+    /// Point the branch emitted at `fixup` at `target`.
+    fn patch(code: &mut [u8], fixup: usize, target: usize);
+}
+
+/// Machine code for a chunk, with the send sites it calls through.
+#[derive(Debug)]
+pub struct Assembly {
+    /// The code, starting with the entry sequence
+    pub code: Vec<u8>,
+    /// Offset in the code of each instruction, by bytecode offset
+    entries: Vec<u32>,
+    /// Site of each `SEND`, by bytecode offset, at the address in the code
+    sites: Vec<(usize, Box<SendSite>)>,
+}
+
+impl Assembly {
+    /// Offset in the code of the instruction at a bytecode offset.
+    #[must_use]
+    pub fn entry(&self, offset: usize) -> Option<usize> {
+        self.entries.get(offset).filter(|entry| **entry != NO_ENTRY).map(|entry| *entry as usize)
+    }
+
+    /// The site of the `SEND` at a bytecode offset.
+    #[must_use]
+    pub fn send_site(&self, offset: usize) -> Option<&SendSite> {
+        site_at(&self.sites, offset)
+    }
+}
+
+fn site_at(sites: &[(usize, Box<SendSite>)], offset: usize) -> Option<&SendSite> {
+    let index = sites.binary_search_by_key(&offset, |(at, _)| *at).ok()?;
+    Some(&sites[index].1)
+}
+
+/// Assemble a stack-encoded chunk for `arch`, or `None` if it holds bytes
+/// that are not instructions, jumps that do not land on one, or too many
+/// instructions.
+#[must_use]
+pub fn assemble(chunk: &Chunk, arch: Arch) -> Option<Assembly> {
+    if chunk.encoding() != Encoding::Stack {
+        return None;
+    }
+    match arch {
+        Arch::X86_64 => assemble_for::<x86_64::X86_64>(chunk),
+        Arch::Aarch64 => assemble_for::<aarch64::Aarch64>(chunk),
+    }
+}
+
+fn assemble_for<T: Target>(chunk: &Chunk) -> Option<Assembly> {
+    let mut code = Vec::new();
+    let exit = T::entry(&mut code);
+    let mut entries = vec![NO_ENTRY; chunk.len()];
+    let mut fixups = Vec::new();
+    let mut sites = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.len() {
+        let op = OpCode::try_from(chunk.code()[offset]).ok()?;
+        let next = offset + 1 + op.operand_width();
+        entries[offset] = u32::try_from(code.len()).ok()?;
+        if op == OpCode::Send {
+            let site = Box::new(SendSite::new());
+            T::send(&mut code, std::ptr::from_ref(&*site) as usize, u32::try_from(offset).ok()?);
+            sites.push((offset, site));
+        } else {
+            T::call(&mut code, native_helper(op) as usize, u32::try_from(offset).ok()?);
+        }
+        let distance = || chunk.read_u16(offset + 1).map(usize::from);
+        match op {
+            OpCode::Jump | OpCode::JumpIfFalse => fixups.push((T::branch(&mut code, exit), next + distance()?)),
+            OpCode::Loop => fixups.push((T::branch(&mut code, exit), next.checked_sub(distance()?)?)),
+            _ => T::exit_unless_next(&mut code, exit),
+        }
+        if code.len() > MAX_CODE {
+            return None;
+        }
+        offset = next;
+    }
+    // Running off the end leaves the VM to report the missing return
+    T::exit(&mut code, exit);
+    for (fixup, target) in fixups {
+        let target = *entries.get(target).filter(|entry| **entry != NO_ENTRY)?;
+        T::patch(&mut code, fixup, target as usize);
+    }
+    Some(Assembly { code, entries, sites })
+}
+
+/// A function compiled for this machine.
+#[derive(Debug)]
+pub struct NativeFunction {
+    /// The naming of the code, undone before it is unmapped
+    symbol: Option<Symbol>,
+    /// The code, executable
+    memory: ExecutableMemory,
+    /// Length of the code
+    size: usize,
+    /// Offset in the code of each instruction, by bytecode offset
+    entries: Vec<u32>,
+    /// Site of each `SEND`, by bytecode offset
+    sites: Vec<(usize, Box<SendSite>)>,
+    /// Times the code was entered
+    runs: Cell<u64>,
+    /// Times the code had no entry at the offset asked for
+    fallbacks: Cell<u64>,
+}
+
+impl NativeFunction {
+    /// Compile a function for this machine, or `None` if it cannot be, such
+    /// as when it is register encoded.
+    #[must_use]
+    pub fn compile(function: &Function) -> Option<Self> {
+        let Assembly { code, entries, sites } = assemble(&function.chunk, Arch::HOST?)?;
+        let memory = ExecutableMemory::new(&code)?;
+        let (runs, fallbacks) = (Cell::new(0), Cell::new(0));
+        Some(Self { symbol: None, memory, size: code.len(), entries, sites, runs, fallbacks })
+    }
+
+    /// Start of the code.
+    #[must_use]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.memory.as_ptr()
+    }
+
+    /// Length of the code.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the code is registered with debuggers.
+    #[must_use]
+    pub fn is_registered(&self) -> bool {
+        self.symbol.as_ref().is_some_and(Symbol::is_registered)
+    }
+
+    /// The send sites of the code by the state of their inline cache.
+    #[must_use]
+    pub fn occupancy(&self) -> SiteOccupancy {
+        let mut occupancy = SiteOccupancy::default();
+        for (_, site) in &self.sites {
+            match site.cache() {
+                InlineCache::Empty => occupancy.empty += 1,
+                InlineCache::Monomorphic(..) => occupancy.monomorphic += 1,
+                InlineCache::Polymorphic(_) => occupancy.polymorphic += 1,
+                InlineCache::Megamorphic => occupancy.megamorphic += 1,
+            }
+        }
+        occupancy
+    }
+
+    /// Name the code `name` through `symbols`, for as long as it lives.
+    pub fn define(&mut self, name: &str, symbols: &mut Symbols) {
+        self.symbol = Some(symbols.define(name, self.as_ptr(), self.size));
+    }
+
+    /// The site of the `SEND` at a bytecode offset.
+    #[must_use]
+    pub fn send_site(&self, offset: usize) -> Option<&SendSite> {
+        site_at(&self.sites, offset)
+    }
+}
+
+impl NativeCode for NativeFunction {
+    fn run(&self, vm: &mut Vm, floor: usize, offset: usize) -> bool {
+        let Some(&entry) = self.entries.get(offset).filter(|entry| **entry != NO_ENTRY) else {
+            self.fallbacks.set(self.fallbacks.get() + 1);
+            return false;
+        };
+        self.runs.set(self.runs.get() + 1);
+        let start = self.memory.as_ptr();
+        // SAFETY: the memory holds code assembled for this machine, which
+        // starts with the entry sequence
+        let code: Entry = unsafe { std::mem::transmute::<*const u8, Entry>(start) };
+        // SAFETY: the entry is an instruction's template, which calls the
+        // helpers with the VM and the offsets they expect
+        unsafe { code(std::ptr::from_mut(vm), floor, start.add(entry as usize)) };
+        true
+    }
+}
+
+/// A function the JIT has considered.
+#[derive(Debug)]
+struct Compiled {
+    /// The function, kept allocated so its address is not reused
+    function: Weak<Function>,
+    /// Its code, if it could be compiled
+    code: Option<Rc<NativeFunction>>,
+    /// Time spent compiling it
+    compile_time: Duration,
+    /// Its optimized code, once the optimizing tier considered it, if it
+    /// could be compiled
+    #[cfg(feature = "cranelift")]
+    optimized: Option<Option<Rc<OptimizedFunction>>>,
+}
+
+/// Compiles the functions a VM runs once they are warm, shared by its
+/// clones.
+#[derive(Debug, Clone)]
+pub struct Jit {
+    /// Counts that decide when a function is compiled
+    profile: Profile,
+    /// Functions compiled or found not compilable, by address
+    functions: Rc<RefCell<HashMap<usize, Compiled>>>,
+    /// Where compiled code is named
+    symbols: Rc<RefCell<Symbols>>,
+    /// The IR hot functions are optimized from
+    #[cfg(feature = "cranelift")]
+    module: Option<Rc<oxidex_codegen::ir::Module>>,
+}
+
+impl Jit {
+    /// Create a JIT compiling the functions `profile` finds warm.
+    #[must_use]
+    pub fn new(profile: Profile) -> Self {
+        Self {
+            profile,
+            functions: Rc::default(),
+            symbols: Rc::default(),
+            #[cfg(feature = "cranelift")]
+            module: None,
+        }
+    }
+
+    /// Optimize functions once they are hot, from their IR in `module`,
+    /// which the functions the VM runs were compiled from.
+    #[cfg(feature = "cranelift")]
+    #[must_use]
+    pub fn with_module(mut self, module: oxidex_codegen::ir::Module) -> Self {
+        self.module = Some(Rc::new(module));
+        self
+    }
+
+    /// Name the code of functions compiled from now on through `symbols`.
+    #[must_use]
+    pub fn with_symbols(self, symbols: Symbols) -> Self {
+        *self.symbols.borrow_mut() = symbols;
+        self
+    }
+
+    /// Make `vm` profile its functions and run them through this JIT.
+    pub fn install(&self, vm: &mut Vm) {
+        vm.set_profiler(self.profile.clone());
+        vm.set_compiler(self.clone());
+    }
+
+    /// The profile deciding when functions are compiled.
+    #[must_use]
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Number of functions still alive that were compiled.
+    #[must_use]
+    pub fn compiled(&self) -> usize {
+        let functions = self.functions.borrow();
+        functions.values().filter(|compiled| compiled.code.is_some() && compiled.function.strong_count() > 0).count()
+    }
+
+    /// Report what the JIT did with the functions it considered that are
+    /// still alive.
+    #[must_use]
+    pub fn stats(&self) -> JitStats {
+        let snapshot = self.profile.snapshot();
+        let functions = self.functions.borrow();
+        let mut stats: Vec<_> = functions
+            .values()
+            .filter_map(|compiled| {
+                let function = compiled.function.upgrade()?;
+                let profile = snapshot.functions.iter().find(|profile| Rc::ptr_eq(&profile.function, &function));
+                let code = compiled.code.as_deref();
+                #[allow(unused_mut)]
+                let mut stats = FunctionStats {
+                    name: function.name.clone(),
+                    tier: if code.is_some() { Tier::Baseline } else { Tier::Interpreted },
+                    tiers: if code.is_some() { vec![Tier::Interpreted, Tier::Baseline] } else { vec![Tier::Interpreted] },
+                    hotness: profile.map_or(Hotness::Cold, |profile| profile.hotness),
+                    calls: profile.map_or(0, |profile| profile.calls),
+                    runs: code.map_or(0, |code| code.runs.get()),
+                    fallbacks: code.map_or(0, |code| code.fallbacks.get()),
+                    deopts: 0,
+                    code_size: code.map_or(0, NativeFunction::size),
+                    compile_time: compiled.compile_time,
+                    sends: code.map(NativeFunction::occupancy).unwrap_or_default(),
+                };
+                #[cfg(feature = "cranelift")]
+                if let Some(Some(optimized)) = &compiled.optimized {
+                    stats.tiers.push(Tier::Optimized);
+                    if optimized.is_abandoned() {
+                        stats.tiers.push(Tier::Baseline);
+                    } else {
+                        stats.tier = Tier::Optimized;
+                    }
+                    stats.runs += optimized.runs();
+                    stats.deopts = optimized.deopts();
+                    stats.code_size += optimized.size();
+                }
+                Some(stats)
+            })
+            .collect();
+        stats.sort_by(|a, b| (b.hotness, b.calls, &a.name).cmp(&(a.hotness, a.calls, &b.name)));
+        JitStats { functions: stats }
+    }
+}
+
+impl Compiler for Jit {
+    fn compile(&mut self, function: &Rc<Function>) -> Option<Rc<dyn NativeCode>> {
+        let address = Rc::as_ptr(function) as usize;
+        let mut functions = self.functions.borrow_mut();
+        if !functions.contains_key(&address) {
+            if self.profile.hotness(function) < Hotness::Warm {
+                return None;
+            }
+            functions.retain(|_, compiled| compiled.function.strong_count() > 0);
+            let start = Instant::now();
+            let code = NativeFunction::compile(function).map(|mut code| {
+                code.define(&function.name, &mut self.symbols.borrow_mut());
+                Rc::new(code)
+            });
+            let compiled = Compiled {
+                function: Rc::downgrade(function),
+                code,
+                compile_time: start.elapsed(),
+                #[cfg(feature = "cranelift")]
+                optimized: None,
+            };
+            functions.insert(address, compiled);
+        }
+        let compiled = functions.get_mut(&address)?;
+        #[cfg(feature = "cranelift")]
+        if let Some(code) = self.optimize(function, compiled) {
+            return Some(code);
+        }
+        compiled.code.clone().map(|code| code as Rc<dyn NativeCode>)
+    }
+}
+
+#[cfg(feature = "cranelift")]
+impl Jit {
+    /// The optimized code of a function, compiling it once the function is
+    /// hot, unless it could not be compiled or was abandoned.
+    fn optimize(&self, function: &Rc<Function>, compiled: &mut Compiled) -> Option<Rc<dyn NativeCode>> {
+        if compiled.optimized.is_none() {
+            let module = self.module.as_deref()?;
+            if self.profile.hotness(function) < Hotness::Hot {
+                return None;
+            }
+            let arguments = self.profile.arguments(function)?;
+            let start = Instant::now();
+            let mut symbols = self.symbols.borrow_mut();
+            let code = OptimizedFunction::compile(module, function, &arguments, compiled.code.clone(), &mut symbols);
+            compiled.compile_time += start.elapsed();
+            compiled.optimized = Some(code.map(Rc::new));
+        }
+        let code = compiled.optimized.clone().flatten()?;
+        (!code.is_abandoned()).then_some(code as Rc<dyn NativeCode>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Thresholds;
+    use oxidex_bytecode::{Constant, Handler, HandlerKind, Intrinsic, Value, VmErrorKind};
+    use oxidex_syntax::Span;
+
+    fn line(line: usize) -> Span {
+        Span::new(line, line + 1, line, 1, line, 2)
+    }
+
+    /// `fn fib(n) { if n < 2 { return n }; fib(n - 1) + fib(n - 2) }`
+    fn fib() -> Function {
+        let mut chunk = Chunk::new();
+        let span = line(1);
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(0, span);
+        chunk.write_constant(OpCode::Constant, Constant::Int(2), span).unwrap();
+        chunk.write_op(OpCode::Less, span);
+        let recurse = chunk.write_jump(OpCode::JumpIfFalse, span);
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(0, span);
+        chunk.write_op(OpCode::Return, span);
+        chunk.patch_jump(recurse).unwrap();
+        for step in [1, 2] {
+            chunk.write_constant(OpCode::GetGlobal, Constant::String(Rc::from("fib")), span).unwrap();
+            chunk.write_op(OpCode::GetLocal, span);
+            chunk.write(0, span);
+            chunk.write_constant(OpCode::Constant, Constant::Int(step), span).unwrap();
+            chunk.write_op(OpCode::Subtract, span);
+            chunk.write_op(OpCode::Call, span);
+            chunk.write(1, span);
+        }
+        chunk.write_op(OpCode::Add, span);
+        chunk.write_op(OpCode::Return, span);
+        Function { name: "fib".to_string(), arity: 1, captures: vec![], chunk }
+    }
+
+    /// `fn sum(n) { var total = 0; while n != 0 { total = total + 100 / (n + 1); n = n - 1 }; total }`,
+    /// which divides by zero for `n` of -1, catching it to return -1.
+    fn sum() -> Function {
+        let mut chunk = Chunk::new();
+        let local = |chunk: &mut Chunk, op: OpCode, slot: u8, span: Span| {
+            chunk.write_op(op, span);
+            chunk.write(slot, span);
+        };
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), line(1)).unwrap();
+        let header = chunk.len();
+        local(&mut chunk, OpCode::GetLocal, 0, line(2));
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), line(2)).unwrap();
+        chunk.write_op(OpCode::NotEqual, line(2));
+        let exit = chunk.write_jump(OpCode::JumpIfFalse, line(2));
+        local(&mut chunk, OpCode::GetLocal, 1, line(3));
+        chunk.write_constant(OpCode::Constant, Constant::Int(100), line(3)).unwrap();
+        local(&mut chunk, OpCode::GetLocal, 0, line(3));
+        chunk.write_constant(OpCode::Constant, Constant::Int(1), line(3)).unwrap();
+        chunk.write_op(OpCode::Add, line(3));
+        chunk.write_op(OpCode::Divide, line(3));
+        chunk.write_op(OpCode::Add, line(3));
+        local(&mut chunk, OpCode::SetLocal, 1, line(3));
+        chunk.write_op(OpCode::Pop, line(3));
+        local(&mut chunk, OpCode::GetLocal, 0, line(4));
+        chunk.write_constant(OpCode::Constant, Constant::Int(1), line(4)).unwrap();
+        chunk.write_op(OpCode::Subtract, line(4));
+        local(&mut chunk, OpCode::SetLocal, 0, line(4));
+        chunk.write_op(OpCode::Pop, line(4));
+        chunk.write_loop(header, line(4)).unwrap();
+        chunk.patch_jump(exit).unwrap();
+        let end = chunk.len();
+        local(&mut chunk, OpCode::GetLocal, 1, line(5));
+        chunk.write_op(OpCode::Return, line(5));
+        let target = chunk.len();
+        chunk.write_op(OpCode::Pop, line(6));
+        chunk.write_constant(OpCode::Constant, Constant::Int(-1), line(6)).unwrap();
+        chunk.write_op(OpCode::Return, line(6));
+        chunk.add_handler(Handler { kind: HandlerKind::Catch, start: header, end, target, depth: 2 });
+        Function { name: "sum".to_string(), arity: 1, captures: vec![], chunk }
+    }
+
+    fn closure(function: Function) -> Value {
+        Value::from(&Constant::Function(Rc::new(function)))
+    }
+
+    #[test]
+    fn test_compiled_code_runs_like_the_interpreter() {
+        let map = std::env::temp_dir().join(format!("oxidex-jit-{}.map", std::process::id()));
+        let symbols = Symbols::new().perf_map_at(&map).unwrap().gdb();
+        let jit = Jit::new(Profile::with_thresholds(Thresholds { warm: 2, hot: 100 })).with_symbols(symbols);
+        let (mut interpreter, mut vm) = (Vm::new(), Vm::new());
+        jit.install(&mut vm);
+        for vm in [&mut interpreter, &mut vm] {
+            vm.define_global("fib", closure(fib()));
+            vm.define_global("sum", closure(sum()));
+        }
+
+        let fib = vm.global("fib").unwrap();
+        assert_eq!(vm.call(fib, vec![Value::Int(15)]).unwrap(), Value::Int(610));
+        for n in [0, 5, 40, -1] {
+            let expected = interpreter.call(interpreter.global("sum").unwrap(), vec![Value::Int(n)]).unwrap();
+            assert_eq!(vm.call(vm.global("sum").unwrap(), vec![Value::Int(n)]).unwrap(), expected);
+        }
+        assert_eq!(jit.compiled(), if Arch::HOST.is_some() { 2 } else { 0 });
+
+        // Compiled code is named after its function
+        let names: Vec<String> = std::fs::read_to_string(&map)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(names.len(), jit.compiled());
+        assert!(names.iter().all(|name| name == "fib" || name == "sum"));
+        let functions = jit.functions.borrow();
+        assert!(functions.values().filter_map(|compiled| compiled.code.as_ref()).all(|code| code.is_registered()));
+        drop(functions);
+        let _ = std::fs::remove_file(&map);
+
+        // Errors raised in compiled code report the instruction raising them
+        let err = vm.call(vm.global("fib").unwrap(), vec![Value::string("ten")]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::TypeMismatch { .. }));
+        assert_eq!(err.offset(), Some(5));
+    }
+
+    /// `fn spread(a, b) { max(abs(a), abs(b)) }`, computed by intrinsics.
+    fn spread() -> Function {
+        let mut chunk = Chunk::new();
+        let span = line(1);
+        for slot in [0, 1] {
+            chunk.write_op(OpCode::GetLocal, span);
+            chunk.write(slot, span);
+            chunk.write_op(OpCode::Intrinsic, span);
+            chunk.write(Intrinsic::Abs.id(), span);
+        }
+        chunk.write_op(OpCode::Intrinsic, span);
+        chunk.write(Intrinsic::Max.id(), span);
+        chunk.write_op(OpCode::Return, span);
+        Function { name: "spread".to_string(), arity: 2, captures: vec![], chunk }
+    }
+
+    #[test]
+    fn test_compiled_intrinsics_run_like_the_interpreter() {
+        let jit = Jit::new(Profile::with_thresholds(Thresholds { warm: 1, hot: 100 }));
+        let (mut interpreter, mut vm) = (Vm::new(), Vm::new());
+        jit.install(&mut vm);
+        for vm in [&mut interpreter, &mut vm] {
+            vm.define_global("spread", closure(spread()));
+        }
+        for (a, b) in [(3, -4), (-9, 2), (0, 0)] {
+            let args = vec![Value::Int(a), Value::Int(b)];
+            let expected = interpreter.call(interpreter.global("spread").unwrap(), args.clone()).unwrap();
+            assert_eq!(vm.call(vm.global("spread").unwrap(), args).unwrap(), expected);
+        }
+        assert_eq!(jit.compiled(), if Arch::HOST.is_some() { 1 } else { 0 });
+
+        let err = vm.call(vm.global("spread").unwrap(), vec![Value::Int(i64::MIN), Value::Int(0)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::IntegerOverflow));
+        assert_eq!(err.offset(), Some(2));
+    }
+
+    unsafe extern "C-unwind" fn native_answer(
+        _receiver: oxidec::runtime::ObjectPtr,
+        _cmd: oxidec::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(42) };
+    }
+
+    #[test]
+    fn test_send_sites_follow_receiver_classes() {
+        use oxidec::{Class, Method, Object, RuntimeString, Selector, get_global_arena};
+        use oxidex_bytecode::InlineCache;
+        use oxidex_bytecode::cache::POLYMORPHIC_LIMIT;
+        use std::str::FromStr;
+
+        let base = Class::new_root("JitCached").unwrap();
+        let selector = Selector::from_str("answer").unwrap();
+        base.add_method(Method { selector, imp: native_answer, types: RuntimeString::new("q@:", get_global_arena()) })
+            .unwrap();
+        let jit = Jit::new(Profile::with_thresholds(Thresholds { warm: 1, hot: 100 }));
+        let mut vm = Vm::new();
+        jit.install(&mut vm);
+        let instances: Vec<_> = (0..=POLYMORPHIC_LIMIT)
+            .map(|index| {
+                let class = Class::new(&format!("JitCached{index}"), &base).unwrap();
+                vm.instance(Object::new(&class).unwrap())
+            })
+            .collect();
+
+        // fn ask(receiver) { receiver.answer() }
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::GetLocal, line(1));
+        chunk.write(0, line(1));
+        chunk.write_constant(OpCode::Send, Constant::String(Rc::from("answer")), line(1)).unwrap();
+        chunk.write(0, line(1));
+        chunk.write_op(OpCode::Return, line(1));
+        let ask = closure(Function { name: "ask".to_string(), arity: 1, captures: vec![], chunk });
+        let mut answer = |instance: &Value| vm.call(ask.clone(), vec![instance.clone()]);
+
+        assert_eq!(answer(&instances[0]).unwrap(), Value::Int(42));
+        if Arch::HOST.is_none() {
+            return;
+        }
+        let site = || {
+            let functions = jit.functions.borrow();
+            let code = functions.values().find_map(|compiled| compiled.code.clone()).unwrap();
+            code.send_site(2).unwrap().cache()
+        };
+        assert!(matches!(site(), InlineCache::Monomorphic(..)));
+        for instance in &instances[1..POLYMORPHIC_LIMIT] {
+            assert_eq!(answer(instance).unwrap(), Value::Int(42));
+        }
+        assert!(matches!(site(), InlineCache::Polymorphic(entries) if entries.len() == POLYMORPHIC_LIMIT));
+        for instance in [&instances[POLYMORPHIC_LIMIT], &instances[0]] {
+            assert_eq!(answer(instance).unwrap(), Value::Int(42));
+        }
+        assert!(matches!(site(), InlineCache::Megamorphic));
+        let stats = jit.stats();
+        let ask = &stats.functions[0];
+        assert_eq!((ask.name.as_str(), ask.tier, ask.calls, ask.runs), ("ask", Tier::Baseline, 6, 6));
+        assert_eq!(stats.sends(), SiteOccupancy { megamorphic: 1, ..SiteOccupancy::default() });
+
+        // Receivers that do not respond raise at the send
+        let err = answer(&Value::Int(1)).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::DoesNotRespond { .. }));
+        assert_eq!(err.offset(), Some(2));
+    }
+
+    #[test]
+    fn test_assemble_templates() {
+        // JUMP over a NIL to a RETURN
+        let mut chunk = Chunk::new();
+        let jump = chunk.write_jump(OpCode::Jump, line(1));
+        chunk.write_op(OpCode::Nil, line(1));
+        chunk.patch_jump(jump).unwrap();
+        chunk.write_op(OpCode::Return, line(1));
+
+        let x86_64 = assemble(&chunk, Arch::X86_64).unwrap();
+        // Entry and exit, then 23 bytes to call, 8 to exit unless the status
+        // is Next and 15 to branch
+        assert_eq!(x86_64.entry(0), Some(17));
+        assert_eq!(x86_64.entry(1), None);
+        assert_eq!(x86_64.entry(3), Some(17 + 23 + 15));
+        assert_eq!(x86_64.entry(4), Some(17 + 23 + 15 + 23 + 8));
+        let je = &x86_64.code[17 + 23 + 3..][..6];
+        assert_eq!(je[..2], [0x0f, 0x84]);
+        let rel = i32::from_le_bytes(je[2..].try_into().unwrap());
+        assert_eq!(17 + 23 + 3 + 6 + rel as usize, x86_64.entry(4).unwrap());
+
+        let aarch64 = assemble(&chunk, Arch::Aarch64).unwrap();
+        let words: Vec<u32> =
+            aarch64.code.chunks(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect();
+        assert_eq!(words[..3], [0xa9be_7bfd, 0x9100_03fd, 0xa901_53f3]);
+        // Entry and exit, then eleven words to call and three to branch
+        let entry = |offset| aarch64.entry(offset).unwrap() / 4;
+        assert_eq!((entry(0), entry(3), entry(4)), (9, 9 + 14, 9 + 14 + 12));
+        assert_eq!(words[9..13], [0xaa13_03e0, 0xaa14_03e1, 0xd280_0002, 0xf2a0_0002]);
+        assert_eq!(words[9 + 10], 0xd63f_0200);
+        // b.eq forward to RETURN, and b.hi back to the exit
+        assert_eq!(words[9 + 12], 0x5400_0000 | ((entry(4) - (9 + 12)) as u32) << 5);
+        assert_eq!(words[9 + 13], 0x5400_0008 | ((6_i32 - (9 + 13)) as u32 & 0x7ffff) << 5);
+    }
+}
@@ -0,0 +1,98 @@
+//! Templates for AArch64, in the AAPCS64 calling convention.
+//!
+//! The VM and the frame floor are kept in the callee-saved `x19` and `x20`
+//! while the code runs, helpers are called through `x16`, and they return
+//! their status in `w0`.
+
+use super::Target;
+
+/// The AArch64 templates.
+pub(super) struct Aarch64;
+
+impl Aarch64 {
+    fn word(code: &mut Vec<u8>, word: u32) {
+        code.extend_from_slice(&word.to_le_bytes());
+    }
+
+    /// Distance in instructions from the instruction at `from` to `to`,
+    /// as a field of `bits` bits.
+    fn distance(from: usize, to: usize, bits: u32) -> u32 {
+        let words = (to as i64 - from as i64) / 4;
+        (words as u32) & ((1 << bits) - 1)
+    }
+
+    /// Load a 64-bit immediate into register `rd` with `movz` and `movk`.
+    fn load(code: &mut Vec<u8>, rd: u32, value: u64) {
+        Self::word(code, 0xd280_0000 | ((value & 0xffff) as u32) << 5 | rd); // movz xd, #value
+        for shift in 1..4 {
+            let part = ((value >> (16 * shift)) & 0xffff) as u32;
+            Self::word(code, 0xf280_0000 | shift << 21 | part << 5 | rd); // movk xd, #part, lsl 16 * shift
+        }
+    }
+}
+
+impl Target for Aarch64 {
+    fn entry(code: &mut Vec<u8>) -> usize {
+        for word in [
+            0xa9be_7bfd, // stp x29, x30, [sp, #-32]!
+            0x9100_03fd, // mov x29, sp
+            0xa901_53f3, // stp x19, x20, [sp, #16]
+            0xaa00_03f3, // mov x19, x0
+            0xaa01_03f4, // mov x20, x1
+            0xd61f_0040, // br x2
+        ] {
+            Self::word(code, word);
+        }
+        let exit = code.len();
+        for word in [
+            0xa941_53f3, // ldp x19, x20, [sp, #16]
+            0xa8c2_7bfd, // ldp x29, x30, [sp], #32
+            0xd65f_03c0, // ret
+        ] {
+            Self::word(code, word);
+        }
+        exit
+    }
+
+    fn call(code: &mut Vec<u8>, helper: usize, offset: u32) {
+        Self::word(code, 0xaa13_03e0); // mov x0, x19
+        Self::word(code, 0xaa14_03e1); // mov x1, x20
+        Self::load(code, 2, u64::from(offset));
+        Self::load(code, 16, helper as u64);
+        Self::word(code, 0xd63f_0200); // blr x16
+    }
+
+    fn send(code: &mut Vec<u8>, site: usize, offset: u32) {
+        Self::word(code, 0xaa13_03e0); // mov x0, x19
+        Self::word(code, 0xaa14_03e1); // mov x1, x20
+        Self::load(code, 2, u64::from(offset));
+        Self::load(code, 3, site as u64);
+        Self::word(code, 0xf940_0070); // ldr x16, [x3]
+        Self::word(code, 0xd63f_0200); // blr x16
+    }
+
+    fn exit_unless_next(code: &mut Vec<u8>, exit: usize) {
+        let distance = Self::distance(code.len(), exit, 19);
+        Self::word(code, 0x3500_0000 | distance << 5); // cbnz w0, exit
+    }
+
+    fn branch(code: &mut Vec<u8>, exit: usize) -> usize {
+        Self::word(code, 0x7100_041f); // cmp w0, Branch
+        let fixup = code.len();
+        Self::word(code, 0x5400_0000); // b.eq target
+        let distance = Self::distance(code.len(), exit, 19);
+        Self::word(code, 0x5400_0008 | distance << 5); // b.hi exit
+        fixup
+    }
+
+    fn exit(code: &mut Vec<u8>, exit: usize) {
+        Self::word(code, 0x5280_0040); // mov w0, Exit
+        let distance = Self::distance(code.len(), exit, 26);
+        Self::word(code, 0x1400_0000 | distance); // b exit
+    }
+
+    fn patch(code: &mut [u8], fixup: usize, target: usize) {
+        let word = 0x5400_0000 | Self::distance(fixup, target, 19) << 5; // b.eq target
+        code[fixup..fixup + 4].copy_from_slice(&word.to_le_bytes());
+    }
+}
@@ -0,0 +1,71 @@
+//! Executable memory.
+//!
+//! Code is copied into pages mapped readable and writable, which are then
+//! made readable and executable, so no page is ever writable and executable
+//! at once.
+
+use std::ptr::NonNull;
+
+/// Pages holding machine code, unmapped when dropped.
+#[derive(Debug)]
+pub(crate) struct ExecutableMemory {
+    /// Start of the mapping
+    ptr: NonNull<u8>,
+    /// Length of the mapping, a whole number of pages
+    len: usize,
+}
+
+impl ExecutableMemory {
+    /// Map pages holding `code`, or `None` if the system refuses.
+    #[cfg(unix)]
+    pub(crate) fn new(code: &[u8]) -> Option<Self> {
+        // SAFETY: sysconf has no preconditions
+        let page = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+        let len = code.len().max(1).div_ceil(page) * page;
+        let (read_write, private) = (libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS);
+        // SAFETY: an anonymous mapping at an address of the system's choice
+        // aliases no memory
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, read_write, private, -1, 0) };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+        let memory = Self { ptr: NonNull::new(ptr.cast())?, len };
+        // SAFETY: the mapping is writable and at least as long as the code
+        unsafe { std::ptr::copy_nonoverlapping(code.as_ptr(), memory.ptr.as_ptr(), code.len()) };
+        // SAFETY: the pages are the mapping's own
+        if unsafe { libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) } != 0 {
+            return None;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            unsafe extern "C" {
+                fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+            }
+            // SAFETY: the range is the mapping's
+            unsafe { __clear_cache(ptr.cast(), ptr.cast::<libc::c_char>().add(len)) };
+        }
+        Some(memory)
+    }
+
+    /// Executable memory is only available on Unix.
+    #[cfg(not(unix))]
+    pub(crate) fn new(_code: &[u8]) -> Option<Self> {
+        None
+    }
+
+    /// Start of the code.
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was made by `new` and nothing refers into it
+        // once the code is dropped
+        #[cfg(unix)]
+        unsafe {
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
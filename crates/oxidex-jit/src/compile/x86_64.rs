@@ -0,0 +1,86 @@
+//! Templates for x86-64, in the System V calling convention.
+//!
+//! The VM and the frame floor are kept in the callee-saved `rbx` and `r12`
+//! while the code runs, and helpers return their status in `eax`.
+
+use super::Target;
+
+/// The x86-64 templates.
+pub(super) struct X86_64;
+
+impl X86_64 {
+    /// Emit a 32-bit displacement from the end of the instruction to
+    /// `target`.
+    fn rel32(code: &mut Vec<u8>, target: usize) {
+        let rel = target as i64 - (code.len() + 4) as i64;
+        code.extend_from_slice(&(rel as i32).to_le_bytes());
+    }
+}
+
+impl Target for X86_64 {
+    fn entry(code: &mut Vec<u8>) -> usize {
+        code.extend_from_slice(&[
+            0x55, // push rbp, aligning the stack for calls
+            0x53, // push rbx
+            0x41, 0x54, // push r12
+            0x48, 0x89, 0xfb, // mov rbx, rdi
+            0x49, 0x89, 0xf4, // mov r12, rsi
+            0xff, 0xe2, // jmp rdx
+        ]);
+        let exit = code.len();
+        code.extend_from_slice(&[
+            0x41, 0x5c, // pop r12
+            0x5b, // pop rbx
+            0x5d, // pop rbp
+            0xc3, // ret
+        ]);
+        exit
+    }
+
+    fn call(code: &mut Vec<u8>, helper: usize, offset: u32) {
+        code.extend_from_slice(&[0x48, 0x89, 0xdf]); // mov rdi, rbx
+        code.extend_from_slice(&[0x4c, 0x89, 0xe6]); // mov rsi, r12
+        code.push(0xba); // mov edx, offset
+        code.extend_from_slice(&offset.to_le_bytes());
+        code.extend_from_slice(&[0x48, 0xb8]); // mov rax, helper
+        code.extend_from_slice(&(helper as u64).to_le_bytes());
+        code.extend_from_slice(&[0xff, 0xd0]); // call rax
+    }
+
+    fn send(code: &mut Vec<u8>, site: usize, offset: u32) {
+        code.extend_from_slice(&[0x48, 0x89, 0xdf]); // mov rdi, rbx
+        code.extend_from_slice(&[0x4c, 0x89, 0xe6]); // mov rsi, r12
+        code.push(0xba); // mov edx, offset
+        code.extend_from_slice(&offset.to_le_bytes());
+        code.extend_from_slice(&[0x48, 0xb9]); // mov rcx, site
+        code.extend_from_slice(&(site as u64).to_le_bytes());
+        code.extend_from_slice(&[0xff, 0x11]); // call [rcx]
+    }
+
+    fn exit_unless_next(code: &mut Vec<u8>, exit: usize) {
+        code.extend_from_slice(&[0x85, 0xc0]); // test eax, eax
+        code.extend_from_slice(&[0x0f, 0x85]); // jnz exit
+        Self::rel32(code, exit);
+    }
+
+    fn branch(code: &mut Vec<u8>, exit: usize) -> usize {
+        code.extend_from_slice(&[0x83, 0xf8, 0x01]); // cmp eax, Branch
+        code.extend_from_slice(&[0x0f, 0x84]); // je target
+        let fixup = code.len();
+        code.extend_from_slice(&[0; 4]);
+        code.extend_from_slice(&[0x0f, 0x87]); // ja exit
+        Self::rel32(code, exit);
+        fixup
+    }
+
+    fn exit(code: &mut Vec<u8>, exit: usize) {
+        code.extend_from_slice(&[0xb8, 2, 0, 0, 0]); // mov eax, Exit
+        code.push(0xe9); // jmp exit
+        Self::rel32(code, exit);
+    }
+
+    fn patch(code: &mut [u8], fixup: usize, target: usize) {
+        let rel = target as i64 - (fixup + 4) as i64;
+        code[fixup..fixup + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+    }
+}
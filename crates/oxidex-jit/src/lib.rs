@@ -4,14 +4,37 @@
 //! - Hot path detection and profiling
 //! - Bytecode to native compilation
 //! - Code cache management
-//! - Tiered compilation
+//! - Tiered compilation, optimizing hot numeric functions through
+//!   Cranelift with the `cranelift` feature
 //!
-//! **Phase:** 9 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 9 - JIT
+//! **Status:** In Progress
 
 #![warn(missing_docs)]
 
+// Call and loop counters fed by the VM
+pub mod profile;
+
+// Baseline compilation of bytecode to machine code
+pub mod compile;
+
+// Optimizing compilation of hot numeric code through Cranelift
+#[cfg(feature = "cranelift")]
+pub mod optimize;
+
+// Naming compiled code for profilers and debuggers
+pub mod symbols;
+
+// What the JIT compiled, and how its code runs
+pub mod stats;
+
 // Module declarations will be added during Phase 9 implementation:
-// pub mod profile;
-// pub mod compile;
 // pub mod cache;
+
+// Re-exports for convenience
+pub use compile::{Arch, Jit, NativeFunction};
+#[cfg(feature = "cranelift")]
+pub use optimize::OptimizedFunction;
+pub use profile::{ArgumentKind, Hotness, Profile, ProfileSnapshot, SendProfile, Thresholds};
+pub use stats::{JitStats, Tier};
+pub use symbols::Symbols;
@@ -0,0 +1,1181 @@
+//! Optimizing compilation.
+//!
+//! The optimizing tier recompiles a hot function from the IR its bytecode
+//! was compiled from, through Cranelift. It takes numerically heavy code:
+//! functions whose values are all integers, floats and booleans, computed
+//! by arithmetic, comparisons, numeric intrinsics and direct calls of
+//! functions of the same kind, which are compiled along with it. Such a
+//! function has no effect but its result, so its code computes the whole
+//! call from the frame's arguments and returns it, without a frame per call.
+//!
+//! Values are unboxed: each is held in a register as a plain `i64`, `f64`
+//! or byte, and Cranelift allocates the registers. The representation of a
+//! parameter comes from the [profile](crate::profile) of the arguments the
+//! function was called with, so a parameter of no declared numeric type
+//! that was only ever passed integers is compiled as an integer. Before
+//! lowering, [escape analysis](oxidex_codegen::optimize::escape) replaces
+//! the boxes and objects that never leave a function by the values stored
+//! in them, so a number boxed for a closure the function no longer creates
+//! stays unboxed, [global value numbering](oxidex_codegen::optimize::gvn)
+//! removes recomputed values, and Cranelift's own optimizations run after.
+//!
+//! The code is entered only at the start of a call, and guards what it
+//! assumed: that the arguments are of the kinds it was specialized for,
+//! and that the globals it calls still hold the functions they held when
+//! it first ran.
+//! Arithmetic that would raise in the VM, such as overflow or division by
+//! zero, and calls nested deeper than the VM allows, bail out of the code.
+//! Either way the call deoptimizes: it runs again in the baseline tier,
+//! which raises the error the VM would, and since the code had no effect,
+//! running it again is unobservable. Code that deoptimizes too often is
+//! abandoned for the baseline.
+
+use crate::compile::NativeFunction;
+use crate::profile::ArgumentKind;
+use crate::symbols::{Symbol, Symbols};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{AbiParam, Block, FuncRef, InstBuilder, MemFlags, Signature, Type, types};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module as _};
+use oxidex_bytecode::vm::MAX_FRAMES;
+use oxidex_bytecode::{Function, NativeCode, Value, Vm};
+use oxidex_codegen::intrinsics::Intrinsic;
+use oxidex_codegen::ir::{self, BlockId, Constant, Inst, IrType, Terminator, ValueId};
+use oxidex_codegen::optimize::{gvn, sink_allocations};
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+/// Deoptimizations after which optimized code is abandoned.
+pub const MAX_DEOPTS: u64 = 64;
+
+/// Status of code that bailed out on what the VM would raise.
+const RAISES: i64 = 1;
+
+/// Status of code that bailed out on a call nested deeper than the VM
+/// allows.
+const EXHAUSTED: i64 = 2;
+
+/// Symbol of the float remainder the code calls.
+const FMOD: &str = "oxidex_jit_fmod";
+
+/// Entry of optimized code: the arguments as words, the calls that may
+/// still be made, and where to set a status other than 0 on bailing out.
+type Entry = unsafe extern "C" fn(*const u64, i64, *mut u32) -> u64;
+
+/// How the code holds a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Repr {
+    /// An `i64`
+    Int,
+    /// An `f64`
+    Float,
+    /// A byte, 0 or 1
+    Bool,
+}
+
+impl Repr {
+    /// The representation of values of a type, if they can be unboxed.
+    fn of(ty: &IrType) -> Option<Self> {
+        match ty {
+            IrType::Int => Some(Self::Int),
+            IrType::Float => Some(Self::Float),
+            IrType::Bool => Some(Self::Bool),
+            _ => None,
+        }
+    }
+
+    /// The representation of arguments of a kind, if they can be unboxed.
+    fn of_kind(kind: ArgumentKind) -> Option<Self> {
+        match kind {
+            ArgumentKind::Int => Some(Self::Int),
+            ArgumentKind::Float => Some(Self::Float),
+            ArgumentKind::Bool => Some(Self::Bool),
+            ArgumentKind::Mixed => None,
+        }
+    }
+
+    fn clif(self) -> Type {
+        match self {
+            Self::Int => types::I64,
+            Self::Float => types::F64,
+            Self::Bool => types::I8,
+        }
+    }
+
+    /// A value as the word it is passed in, if it is of this representation.
+    fn unbox(self, value: &Value) -> Option<u64> {
+        match (self, value) {
+            #[allow(clippy::cast_sign_loss)]
+            (Self::Int, Value::Int(value)) => Some(*value as u64),
+            (Self::Float, Value::Float(value)) => Some(value.to_bits()),
+            (Self::Bool, Value::Bool(value)) => Some(u64::from(*value)),
+            _ => None,
+        }
+    }
+
+    /// The value a returned word holds.
+    fn box_word(self, word: u64) -> Value {
+        match self {
+            #[allow(clippy::cast_possible_wrap)]
+            Self::Int => Value::Int(word as i64),
+            Self::Float => Value::Float(f64::from_bits(word)),
+            Self::Bool => Value::Bool(word != 0),
+        }
+    }
+}
+
+/// A function of a group, specialized for the representation of its
+/// parameters.
+#[derive(Debug)]
+struct Specialized {
+    /// The function, after value numbering
+    function: Rc<ir::Function>,
+    /// Representation of each parameter
+    params: Vec<Repr>,
+    /// Representation of the result
+    result: Repr,
+    /// Representation of each value the function uses
+    values: HashMap<ValueId, Repr>,
+    /// Specialization each call calls, by the value it defines
+    calls: HashMap<ValueId, usize>,
+}
+
+/// A hot function and the functions it calls, which compile together.
+#[derive(Debug, Default)]
+struct Group {
+    /// The functions, the hot one first
+    functions: Vec<Specialized>,
+    /// Each function by its name and parameter representations
+    index: HashMap<(String, Vec<Repr>), usize>,
+    /// Names of the globals the functions call
+    callees: HashSet<String>,
+}
+
+/// Plan the compilation of `name`, whose arguments were of the kinds
+/// `arguments`, or `None` if it or a function it calls is not numeric.
+fn plan(module: &ir::Module, name: &str, arguments: &[ArgumentKind]) -> Option<Group> {
+    let root = module.function(name)?;
+    if root.params.len() != arguments.len() {
+        return None;
+    }
+    let params = root
+        .params
+        .iter()
+        .zip(arguments)
+        .map(|(&param, &kind)| match (root.value_type(param), Repr::of_kind(kind)) {
+            (IrType::Object(None), profiled) => profiled,
+            (ty, profiled) => Repr::of(ty).filter(|repr| profiled.is_none_or(|profiled| profiled == *repr)),
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // Unbox and number the values of the functions the group may call, once
+    // each
+    let mut functions = HashMap::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+        if functions.contains_key(&name) {
+            continue;
+        }
+        let function = module.function(&name)?;
+        for block in &function.blocks {
+            for inst in &block.insts {
+                if let Inst::Call { callee, .. } = &inst.inst {
+                    pending.push(callee.clone());
+                }
+            }
+        }
+        functions.insert(name, function.clone());
+    }
+    let mut numbered = ir::Module { functions: functions.into_values().collect(), exports: vec![], externs: vec![] };
+    sink_allocations(&mut numbered);
+    gvn(&mut numbered);
+    let numbered: HashMap<String, Rc<ir::Function>> =
+        numbered.functions.into_iter().map(|function| (function.name.clone(), Rc::new(function))).collect();
+
+    let mut group = Group::default();
+    specialize(&mut group, &numbered, name, params)?;
+    Some(group)
+}
+
+/// Add the specialization of `name` for `params` to `group`, with those of
+/// the functions it calls, returning its index.
+fn specialize(
+    group: &mut Group,
+    functions: &HashMap<String, Rc<ir::Function>>,
+    name: &str,
+    params: Vec<Repr>,
+) -> Option<usize> {
+    let key = (name.to_string(), params);
+    if let Some(&index) = group.index.get(&key) {
+        return Some(index);
+    }
+    let function = Rc::clone(functions.get(name)?);
+    let params = key.1.clone();
+    let index = group.functions.len();
+    group.index.insert(key, index);
+    let result = Repr::of(&function.return_type)?;
+    let mut values: HashMap<ValueId, Repr> = function.params.iter().copied().zip(params.iter().copied()).collect();
+    let defined = function.blocks.iter().flat_map(|block| {
+        let phis = block.phis.iter().map(|phi| phi.result);
+        phis.chain(block.insts.iter().map(|inst| inst.result))
+    });
+    for value in defined {
+        if let Some(repr) = Repr::of(function.value_type(value)) {
+            values.insert(value, repr);
+        }
+    }
+    let specialized = Specialized { function: Rc::clone(&function), params, result, values, calls: HashMap::new() };
+    group.functions.push(specialized);
+
+    let values = group.functions[index].values.clone();
+    let repr = |value: &ValueId| values.get(value).copied();
+    let used = used(&function);
+    let mut calls = HashMap::new();
+    if function.blocks.first().is_none_or(|entry| !entry.phis.is_empty()) {
+        return None;
+    }
+    for block in &function.blocks {
+        for phi in &block.phis {
+            let phi_repr = repr(&phi.result)?;
+            if phi.incoming.iter().any(|(_, value)| repr(value) != Some(phi_repr)) {
+                return None;
+            }
+        }
+        for inst in &block.insts {
+            let defined = repr(&inst.result);
+            if let Inst::Call { callee, args } = &inst.inst {
+                let args = args.iter().map(repr).collect::<Option<Vec<_>>>()?;
+                let callee_index = specialize(group, functions, callee, args)?;
+                if Some(group.functions[callee_index].result) != defined {
+                    return None;
+                }
+                group.callees.insert(callee.clone());
+                calls.insert(inst.result, callee_index);
+                continue;
+            }
+            match computes(&inst.inst, &repr) {
+                Some(computed) if Some(computed) == defined => {}
+                // Constants and globals nothing reads are left out
+                _ if matches!(inst.inst, Inst::Const(_) | Inst::Global(_)) && !used.contains(&inst.result) => {}
+                _ => return None,
+            }
+        }
+        let edges_agree = block.terminator.successors().iter().all(|&target| {
+            function.block(target).phis.iter().all(|phi| {
+                phi.incoming.iter().any(|&(from, value)| from == block.id && repr(&value) == repr(&phi.result))
+            })
+        });
+        let reads = match &block.terminator {
+            Terminator::Return(Some(value)) => repr(value) == Some(result),
+            // Native code does not unwind through cleanups
+            Terminator::Return(None) | Terminator::Resume => false,
+            Terminator::Branch { cond, .. } => repr(cond) == Some(Repr::Bool),
+            Terminator::Switch { value, .. } => repr(value) == Some(Repr::Int),
+            Terminator::Jump(_) | Terminator::Unreachable => true,
+        };
+        if !edges_agree || !reads {
+            return None;
+        }
+    }
+    group.functions[index].calls = calls;
+    Some(index)
+}
+
+/// The values a function reads.
+fn used(function: &ir::Function) -> HashSet<ValueId> {
+    let mut used = HashSet::new();
+    for block in &function.blocks {
+        used.extend(block.phis.iter().flat_map(|phi| phi.incoming.iter().map(|&(_, value)| value)));
+        used.extend(block.insts.iter().flat_map(|inst| inst.inst.operands()));
+        used.extend(block.terminator.operands());
+    }
+    used
+}
+
+/// The representation of what an instruction other than a call computes,
+/// if the code can compute it as the VM would.
+fn computes(inst: &Inst, repr: &impl Fn(&ValueId) -> Option<Repr>) -> Option<Repr> {
+    use Repr::{Bool, Float, Int};
+    match inst {
+        Inst::Const(Constant::Int(_)) => Some(Int),
+        Inst::Const(Constant::Float(_)) => Some(Float),
+        Inst::Const(Constant::Bool(_)) => Some(Bool),
+        Inst::Binary { op, lhs, rhs } => {
+            let operand = repr(lhs).filter(|&lhs| Some(lhs) == repr(rhs))?;
+            match (op, operand) {
+                (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod, Int | Float) => {
+                    Some(operand)
+                }
+                (BinaryOp::Eq | BinaryOp::Neq, _) => Some(Bool),
+                (BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte, Int | Float) => Some(Bool),
+                _ => None,
+            }
+        }
+        Inst::Unary { op: UnaryOp::Minus, operand } => repr(operand).filter(|&repr| repr != Bool),
+        Inst::Unary { op: UnaryOp::Negate, operand } => repr(operand).filter(|&repr| repr == Bool),
+        Inst::Intrinsic { intrinsic, args } => {
+            let args = args.iter().map(repr).collect::<Option<Vec<_>>>()?;
+            match (intrinsic, args.as_slice()) {
+                (Intrinsic::Abs, [number @ (Int | Float)]) => Some(*number),
+                (Intrinsic::Min | Intrinsic::Max, [a @ (Int | Float), b]) if a == b => Some(*a),
+                (Intrinsic::Sqrt, [Float]) => Some(Float),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Float remainder, which Cranelift has no instruction for.
+extern "C" fn fmod(a: f64, b: f64) -> f64 {
+    a % b
+}
+
+/// Machine code for a group, with the module that owns it.
+struct Code {
+    /// The naming of the code, undone before it is freed
+    symbols: Vec<Symbol>,
+    /// The module the code lives in, freed on drop
+    module: Option<JITModule>,
+    /// Entry of the hot function
+    entry: Entry,
+    /// Length of the code
+    size: usize,
+}
+
+impl std::fmt::Debug for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Code").field("size", &self.size).finish_non_exhaustive()
+    }
+}
+
+impl Drop for Code {
+    fn drop(&mut self) {
+        self.symbols.clear();
+        if let Some(module) = self.module.take() {
+            // SAFETY: nothing calls into the code once it is dropped
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// Compile a planned group for this machine, naming its functions through
+/// `symbols`.
+fn lower(group: &Group, symbols: &mut Symbols) -> Option<Code> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+    let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    builder.symbol(FMOD, fmod as *const u8);
+    let mut module = JITModule::new(builder);
+
+    let mut ids = Vec::new();
+    for (index, specialized) in group.functions.iter().enumerate() {
+        let signature = signature(&module, specialized);
+        ids.push(module.declare_function(&format!("{index}:{}", specialized.function.name), Linkage::Local, &signature).ok()?);
+    }
+    let mut fmod_signature = module.make_signature();
+    fmod_signature.params.extend([AbiParam::new(types::F64), AbiParam::new(types::F64)]);
+    fmod_signature.returns.push(AbiParam::new(types::F64));
+    let fmod = module.declare_function(FMOD, Linkage::Import, &fmod_signature).ok()?;
+
+    let mut context = module.make_context();
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut sizes = Vec::new();
+    for (index, specialized) in group.functions.iter().enumerate() {
+        context.func.signature = signature(&module, specialized);
+        let builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let lowering = Lowering { module: &mut module, ids: &ids, fmod, builder, refs: HashMap::new() };
+        lowering.function(specialized)?;
+        module.define_function(ids[index], &mut context).ok()?;
+        sizes.push(context.compiled_code()?.code_info().total_size as usize);
+        module.clear_context(&mut context);
+    }
+    let trampoline = trampoline(&mut module, &mut context, &mut builder_context, &group.functions[0], ids[0])?;
+    sizes.push(context.compiled_code()?.code_info().total_size as usize);
+    module.finalize_definitions().ok()?;
+
+    let mut named = Vec::new();
+    for ((id, size), specialized) in ids.iter().zip(&sizes).zip(&group.functions) {
+        named.push(symbols.define(&specialized.function.name, module.get_finalized_function(*id), *size));
+    }
+    let start = module.get_finalized_function(trampoline);
+    // SAFETY: the trampoline was compiled with the signature of an entry
+    let entry = unsafe { std::mem::transmute::<*const u8, Entry>(start) };
+    Some(Code { symbols: named, module: Some(module), entry, size: sizes.iter().sum() })
+}
+
+/// The signature of a specialized function: its parameters, the calls that
+/// may still be made and the status address.
+fn signature(module: &JITModule, specialized: &Specialized) -> Signature {
+    let mut signature = module.make_signature();
+    signature.params.extend(specialized.params.iter().map(|repr| AbiParam::new(repr.clif())));
+    signature.params.push(AbiParam::new(types::I64));
+    signature.params.push(AbiParam::new(module.target_config().pointer_type()));
+    signature.returns.push(AbiParam::new(specialized.result.clif()));
+    signature
+}
+
+/// Compile the entry of the hot function, which reads its arguments from
+/// words, calls it and returns its result as a word.
+fn trampoline(
+    module: &mut JITModule,
+    context: &mut cranelift_codegen::Context,
+    builder_context: &mut FunctionBuilderContext,
+    root: &Specialized,
+    id: FuncId,
+) -> Option<FuncId> {
+    let pointer = module.target_config().pointer_type();
+    let mut signature = module.make_signature();
+    signature.params.extend([AbiParam::new(pointer), AbiParam::new(types::I64), AbiParam::new(pointer)]);
+    signature.returns.push(AbiParam::new(types::I64));
+    let trampoline = module.declare_function("entry", Linkage::Local, &signature).ok()?;
+    context.func.signature = signature;
+    let mut builder = FunctionBuilder::new(&mut context.func, builder_context);
+    let block = builder.create_block();
+    builder.append_block_params_for_function_params(block);
+    builder.switch_to_block(block);
+    let [words, remaining, status] = builder.block_params(block) else { return None };
+    let (words, remaining, status) = (*words, *remaining, *status);
+    let mut args = Vec::new();
+    for (index, repr) in root.params.iter().enumerate() {
+        let word = builder.ins().load(types::I64, MemFlags::trusted(), words, i32::try_from(index * 8).ok()?);
+        args.push(match repr {
+            Repr::Int => word,
+            Repr::Float => builder.ins().bitcast(types::F64, MemFlags::new(), word),
+            Repr::Bool => builder.ins().ireduce(types::I8, word),
+        });
+    }
+    args.extend([remaining, status]);
+    let callee = module.declare_func_in_func(id, builder.func);
+    let call = builder.ins().call(callee, &args);
+    let result = builder.inst_results(call)[0];
+    let word = match root.result {
+        Repr::Int => result,
+        Repr::Float => builder.ins().bitcast(types::I64, MemFlags::new(), result),
+        Repr::Bool => builder.ins().uextend(types::I64, result),
+    };
+    builder.ins().return_(&[word]);
+    builder.seal_all_blocks();
+    builder.finalize();
+    module.define_function(trampoline, context).ok()?;
+    Some(trampoline)
+}
+
+/// Translates one specialized function to Cranelift IR.
+struct Lowering<'a> {
+    module: &'a mut JITModule,
+    /// Each function of the group
+    ids: &'a [FuncId],
+    /// The float remainder
+    fmod: FuncId,
+    builder: FunctionBuilder<'a>,
+    /// Functions referenced so far
+    refs: HashMap<FuncId, FuncRef>,
+}
+
+/// Where a function's code keeps what it needs in every block.
+#[derive(Clone, Copy)]
+struct Frame {
+    /// Calls that may still be made
+    remaining: cranelift_codegen::ir::Value,
+    /// Address of the status
+    status: cranelift_codegen::ir::Value,
+    /// Block that bails out with the status it is passed
+    bail: Block,
+}
+
+impl Lowering<'_> {
+    fn function(mut self, specialized: &Specialized) -> Option<()> {
+        let function = &*specialized.function;
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        let blocks: HashMap<BlockId, Block> = function
+            .blocks
+            .iter()
+            .map(|block| {
+                let target = self.builder.create_block();
+                for phi in &block.phis {
+                    self.builder.append_block_param(target, specialized.values[&phi.result].clif());
+                }
+                (block.id, target)
+            })
+            .collect();
+        for (value, repr) in &specialized.values {
+            self.builder.declare_var(Variable::from_u32(value.0), repr.clif());
+        }
+
+        self.builder.switch_to_block(entry);
+        let params = self.builder.block_params(entry).to_vec();
+        let arity = function.params.len();
+        for (param, value) in function.params.iter().zip(&params) {
+            self.builder.def_var(Variable::from_u32(param.0), *value);
+        }
+        let bail = self.builder.create_block();
+        self.builder.append_block_param(bail, types::I32);
+        let frame = Frame { remaining: params[arity], status: params[arity + 1], bail };
+        self.builder.ins().jump(blocks[&function.blocks[0].id], &[]);
+
+        for block in &function.blocks {
+            let target = blocks[&block.id];
+            self.builder.switch_to_block(target);
+            let params = self.builder.block_params(target).to_vec();
+            for (phi, value) in block.phis.iter().zip(params) {
+                self.builder.def_var(Variable::from_u32(phi.result.0), value);
+            }
+            for inst in &block.insts {
+                if !specialized.values.contains_key(&inst.result) {
+                    continue;
+                }
+                let value = match specialized.calls.get(&inst.result) {
+                    Some(&callee) => self.call(callee, &inst.inst, frame)?,
+                    None => self.instruction(&inst.inst, frame)?,
+                };
+                self.builder.def_var(Variable::from_u32(inst.result.0), value);
+            }
+            self.terminator(function, block.id, &block.terminator, &blocks, frame);
+        }
+
+        self.builder.switch_to_block(bail);
+        let status = self.builder.block_params(bail)[0];
+        self.builder.ins().store(MemFlags::trusted(), status, frame.status, 0);
+        let zero = self.zero(specialized.result);
+        self.builder.ins().return_(&[zero]);
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+        Some(())
+    }
+
+    fn zero(&mut self, repr: Repr) -> cranelift_codegen::ir::Value {
+        match repr {
+            Repr::Int => self.builder.ins().iconst(types::I64, 0),
+            Repr::Float => self.builder.ins().f64const(0.0),
+            Repr::Bool => self.builder.ins().iconst(types::I8, 0),
+        }
+    }
+
+    fn get(&mut self, value: ValueId) -> cranelift_codegen::ir::Value {
+        self.builder.use_var(Variable::from_u32(value.0))
+    }
+
+    /// Bail out on what the VM would raise if `failed` is not zero.
+    fn guard(&mut self, failed: cranelift_codegen::ir::Value, frame: Frame) {
+        let status = self.builder.ins().iconst(types::I32, RAISES);
+        self.bail_if(failed, status, frame);
+    }
+
+    /// Bail out with `status` if `failed` is not zero.
+    fn bail_if(&mut self, failed: cranelift_codegen::ir::Value, status: cranelift_codegen::ir::Value, frame: Frame) {
+        let next = self.builder.create_block();
+        self.builder.ins().brif(failed, frame.bail, &[status], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn func_ref(&mut self, id: FuncId) -> FuncRef {
+        if let Some(&func_ref) = self.refs.get(&id) {
+            return func_ref;
+        }
+        let func_ref = self.module.declare_func_in_func(id, self.builder.func);
+        self.refs.insert(id, func_ref);
+        func_ref
+    }
+
+    fn call(&mut self, callee: usize, inst: &Inst, frame: Frame) -> Option<cranelift_codegen::ir::Value> {
+        let Inst::Call { args, .. } = inst else { return None };
+        // The VM raises on the call that would exceed its frames
+        let exhausted = self.builder.ins().icmp_imm(IntCC::Equal, frame.remaining, 0);
+        let status = self.builder.ins().iconst(types::I32, EXHAUSTED);
+        self.bail_if(exhausted, status, frame);
+        let mut values: Vec<_> = args.iter().map(|&arg| self.get(arg)).collect();
+        values.push(self.builder.ins().iadd_imm(frame.remaining, -1));
+        values.push(frame.status);
+        let func_ref = self.func_ref(self.ids[callee]);
+        let call = self.builder.ins().call(func_ref, &values);
+        let result = self.builder.inst_results(call)[0];
+        // Whatever the callee bailed out on, so does its caller
+        let status = self.builder.ins().load(types::I32, MemFlags::trusted(), frame.status, 0);
+        self.bail_if(status, status, frame);
+        Some(result)
+    }
+
+    fn instruction(&mut self, inst: &Inst, frame: Frame) -> Option<cranelift_codegen::ir::Value> {
+        Some(match inst {
+            Inst::Const(Constant::Int(value)) => self.builder.ins().iconst(types::I64, *value),
+            Inst::Const(Constant::Float(value)) => self.builder.ins().f64const(*value),
+            Inst::Const(Constant::Bool(value)) => self.builder.ins().iconst(types::I8, i64::from(*value)),
+            Inst::Binary { op, lhs, rhs } => {
+                let (a, b) = (self.get(*lhs), self.get(*rhs));
+                if self.builder.func.dfg.value_type(a) == types::F64 { self.float(*op, a, b)? } else { self.int(*op, a, b, frame)? }
+            }
+            Inst::Unary { op: UnaryOp::Negate, operand } => {
+                let value = self.get(*operand);
+                self.builder.ins().icmp_imm(IntCC::Equal, value, 0)
+            }
+            Inst::Unary { op: UnaryOp::Minus, operand } => {
+                let value = self.get(*operand);
+                if self.builder.func.dfg.value_type(value) == types::F64 {
+                    self.builder.ins().fneg(value)
+                } else {
+                    let overflows = self.builder.ins().icmp_imm(IntCC::Equal, value, i64::MIN);
+                    self.guard(overflows, frame);
+                    self.builder.ins().ineg(value)
+                }
+            }
+            Inst::Intrinsic { intrinsic, args } => {
+                let args: Vec<_> = args.iter().map(|&arg| self.get(arg)).collect();
+                let float = self.builder.func.dfg.value_type(args[0]) == types::F64;
+                match (intrinsic, args.as_slice(), float) {
+                    (Intrinsic::Abs, [value], true) => self.builder.ins().fabs(*value),
+                    (Intrinsic::Abs, [value], false) => {
+                        let overflows = self.builder.ins().icmp_imm(IntCC::Equal, *value, i64::MIN);
+                        self.guard(overflows, frame);
+                        self.builder.ins().iabs(*value)
+                    }
+                    (Intrinsic::Min, [a, b], false) => self.builder.ins().smin(*a, *b),
+                    (Intrinsic::Max, [a, b], false) => self.builder.ins().smax(*a, *b),
+                    (Intrinsic::Min | Intrinsic::Max, [a, b], true) => {
+                        // Like Rust's, a NaN operand yields the other one
+                        let both = if *intrinsic == Intrinsic::Min { self.builder.ins().fmin(*a, *b) } else { self.builder.ins().fmax(*a, *b) };
+                        let b_nan = self.builder.ins().fcmp(FloatCC::Unordered, *b, *b);
+                        let either = self.builder.ins().select(b_nan, *a, both);
+                        let a_nan = self.builder.ins().fcmp(FloatCC::Unordered, *a, *a);
+                        self.builder.ins().select(a_nan, *b, either)
+                    }
+                    (Intrinsic::Sqrt, [value], true) => self.builder.ins().sqrt(*value),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    fn int(
+        &mut self,
+        op: BinaryOp,
+        a: cranelift_codegen::ir::Value,
+        b: cranelift_codegen::ir::Value,
+        frame: Frame,
+    ) -> Option<cranelift_codegen::ir::Value> {
+        let checked = |lowering: &mut Self, (value, overflows)| {
+            lowering.guard(overflows, frame);
+            value
+        };
+        let compare = |lowering: &mut Self, cc| lowering.builder.ins().icmp(cc, a, b);
+        Some(match op {
+            BinaryOp::Add => {
+                let sum = self.builder.ins().sadd_overflow(a, b);
+                checked(self, sum)
+            }
+            BinaryOp::Sub => {
+                let difference = self.builder.ins().ssub_overflow(a, b);
+                checked(self, difference)
+            }
+            BinaryOp::Mul => {
+                let product = self.builder.ins().smul_overflow(a, b);
+                checked(self, product)
+            }
+            BinaryOp::Div | BinaryOp::Mod => {
+                let zero = self.builder.ins().icmp_imm(IntCC::Equal, b, 0);
+                self.guard(zero, frame);
+                let min = self.builder.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+                let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, b, -1);
+                let overflows = self.builder.ins().band(min, minus_one);
+                self.guard(overflows, frame);
+                if op == BinaryOp::Div { self.builder.ins().sdiv(a, b) } else { self.builder.ins().srem(a, b) }
+            }
+            BinaryOp::Eq => compare(self, IntCC::Equal),
+            BinaryOp::Neq => compare(self, IntCC::NotEqual),
+            BinaryOp::Lt => compare(self, IntCC::SignedLessThan),
+            BinaryOp::Lte => compare(self, IntCC::SignedLessThanOrEqual),
+            BinaryOp::Gt => compare(self, IntCC::SignedGreaterThan),
+            BinaryOp::Gte => compare(self, IntCC::SignedGreaterThanOrEqual),
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Assign => return None,
+        })
+    }
+
+    fn float(
+        &mut self,
+        op: BinaryOp,
+        a: cranelift_codegen::ir::Value,
+        b: cranelift_codegen::ir::Value,
+    ) -> Option<cranelift_codegen::ir::Value> {
+        let compare = |lowering: &mut Self, cc| lowering.builder.ins().fcmp(cc, a, b);
+        Some(match op {
+            BinaryOp::Add => self.builder.ins().fadd(a, b),
+            BinaryOp::Sub => self.builder.ins().fsub(a, b),
+            BinaryOp::Mul => self.builder.ins().fmul(a, b),
+            BinaryOp::Div => self.builder.ins().fdiv(a, b),
+            BinaryOp::Mod => {
+                let fmod = self.func_ref(self.fmod);
+                let call = self.builder.ins().call(fmod, &[a, b]);
+                self.builder.inst_results(call)[0]
+            }
+            // Unordered operands are unequal, and satisfy no ordering
+            BinaryOp::Eq => compare(self, FloatCC::Equal),
+            BinaryOp::Neq => compare(self, FloatCC::NotEqual),
+            BinaryOp::Lt => compare(self, FloatCC::LessThan),
+            BinaryOp::Lte => compare(self, FloatCC::LessThanOrEqual),
+            BinaryOp::Gt => compare(self, FloatCC::GreaterThan),
+            BinaryOp::Gte => compare(self, FloatCC::GreaterThanOrEqual),
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Assign => return None,
+        })
+    }
+
+    /// The values passed to the phis of `to` on the edge from `from`.
+    fn edge(&mut self, function: &ir::Function, from: BlockId, to: BlockId) -> Vec<cranelift_codegen::ir::Value> {
+        let incoming: Vec<ValueId> = function
+            .block(to)
+            .phis
+            .iter()
+            .filter_map(|phi| phi.incoming.iter().find(|(pred, _)| *pred == from).map(|&(_, value)| value))
+            .collect();
+        incoming.into_iter().map(|value| self.get(value)).collect()
+    }
+
+    fn terminator(
+        &mut self,
+        function: &ir::Function,
+        from: BlockId,
+        terminator: &Terminator,
+        blocks: &HashMap<BlockId, Block>,
+        frame: Frame,
+    ) {
+        match terminator {
+            Terminator::Return(Some(value)) => {
+                let value = self.get(*value);
+                self.builder.ins().return_(&[value]);
+            }
+            Terminator::Jump(target) => {
+                let args = self.edge(function, from, *target);
+                self.builder.ins().jump(blocks[target], &args);
+            }
+            Terminator::Branch { cond, then_block, else_block } => {
+                let cond = self.get(*cond);
+                let then_args = self.edge(function, from, *then_block);
+                let else_args = self.edge(function, from, *else_block);
+                self.builder.ins().brif(cond, blocks[then_block], &then_args, blocks[else_block], &else_args);
+            }
+            Terminator::Switch { value, cases, default } => {
+                let value = self.get(*value);
+                for &(case, target) in cases {
+                    let hit = self.builder.ins().icmp_imm(IntCC::Equal, value, case);
+                    let args = self.edge(function, from, target);
+                    let next = self.builder.create_block();
+                    self.builder.ins().brif(hit, blocks[&target], &args, next, &[]);
+                    self.builder.switch_to_block(next);
+                }
+                let args = self.edge(function, from, *default);
+                self.builder.ins().jump(blocks[default], &args);
+            }
+            // The VM raises where control was not to reach
+            Terminator::Return(None) | Terminator::Unreachable | Terminator::Resume => {
+                let status = self.builder.ins().iconst(types::I32, RAISES);
+                self.builder.ins().jump(frame.bail, &[status]);
+            }
+        }
+    }
+}
+
+/// A global the code calls.
+#[derive(Debug)]
+struct Callee {
+    /// Its name
+    name: String,
+    /// Arity of the function compiled for it
+    arity: usize,
+    /// The function it held when the code first ran
+    function: RefCell<Weak<Function>>,
+}
+
+impl Callee {
+    /// Whether the global still holds the function the code was compiled
+    /// for.
+    fn holds(&self, vm: &Vm) -> bool {
+        let Some(Value::Closure(closure)) = vm.global(&self.name) else { return false };
+        let mut bound = self.function.borrow_mut();
+        if bound.strong_count() > 0 {
+            return bound.as_ptr() == Rc::as_ptr(&closure.function);
+        }
+        let function = &closure.function;
+        if function.name != self.name || usize::from(function.arity) != self.arity {
+            return false;
+        }
+        *bound = Rc::downgrade(function);
+        true
+    }
+}
+
+/// A function compiled by the optimizing tier, which runs in the baseline
+/// tier wherever its code cannot.
+#[derive(Debug)]
+pub struct OptimizedFunction {
+    /// The code of the function and the functions it calls
+    code: Code,
+    /// Representation of each parameter
+    params: Vec<Repr>,
+    /// Representation of the result
+    result: Repr,
+    /// Globals the code calls
+    callees: Vec<Callee>,
+    /// The function compiled by the baseline tier, if it could be
+    baseline: Option<Rc<NativeFunction>>,
+    /// Calls the code computed
+    runs: Cell<u64>,
+    /// Calls the code left to the baseline tier
+    deopts: Cell<u64>,
+    /// Of those, calls that nested deeper than the VM allows
+    exhausted: Cell<u64>,
+}
+
+impl OptimizedFunction {
+    /// Compile `function` from its IR in `module`, specialized for
+    /// arguments of the kinds `arguments`, or `None` if it is not numeric
+    /// code. Its code and that of the functions it calls is named through
+    /// `symbols`.
+    #[must_use]
+    pub fn compile(
+        module: &ir::Module,
+        function: &Function,
+        arguments: &[ArgumentKind],
+        baseline: Option<Rc<NativeFunction>>,
+        symbols: &mut Symbols,
+    ) -> Option<Self> {
+        let group = plan(module, &function.name, arguments)?;
+        let code = lower(&group, symbols)?;
+        let root = &group.functions[0];
+        let callees = group
+            .callees
+            .iter()
+            .map(|name| Callee {
+                name: name.clone(),
+                arity: module.function(name).map_or(0, |callee| callee.params.len()),
+                function: RefCell::default(),
+            })
+            .collect();
+        let (runs, deopts, exhausted) = (Cell::new(0), Cell::new(0), Cell::new(0));
+        let (params, result) = (root.params.clone(), root.result);
+        Some(Self { code, params, result, callees, baseline, runs, deopts, exhausted })
+    }
+
+    /// Length of the code.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.code.size
+    }
+
+    /// Calls the code computed.
+    #[must_use]
+    pub fn runs(&self) -> u64 {
+        self.runs.get()
+    }
+
+    /// Calls the code left to the baseline tier, because an assumption
+    /// failed or the VM would have raised.
+    #[must_use]
+    pub fn deopts(&self) -> u64 {
+        self.deopts.get()
+    }
+
+    /// Whether the code deoptimized too often to be run again. Calls
+    /// nested too deeply do not count: every call they made deoptimizes
+    /// too, and the VM raises on the deepest.
+    #[must_use]
+    pub fn is_abandoned(&self) -> bool {
+        self.deopts.get() - self.exhausted.get() >= MAX_DEOPTS
+    }
+
+    /// The function compiled by the baseline tier.
+    #[must_use]
+    pub fn baseline(&self) -> Option<&Rc<NativeFunction>> {
+        self.baseline.as_ref()
+    }
+
+    /// Compute the call of the running frame, or `None` to deoptimize.
+    fn call(&self, vm: &Vm) -> Option<Value> {
+        let words = vm
+            .native_arguments()
+            .iter()
+            .zip(&self.params)
+            .map(|(value, repr)| repr.unbox(value))
+            .collect::<Option<Vec<_>>>()?;
+        if !self.callees.iter().all(|callee| callee.holds(vm)) {
+            return None;
+        }
+        let remaining = i64::try_from(MAX_FRAMES.saturating_sub(vm.native_depth())).ok()?;
+        let mut status = 0;
+        // SAFETY: the words are the arguments in the representations the
+        // code was compiled for, and the code calls nothing but itself and
+        // the float remainder
+        let word = unsafe { (self.code.entry)(words.as_ptr(), remaining, &raw mut status) };
+        match i64::from(status) {
+            0 => Some(self.result.box_word(word)),
+            EXHAUSTED => {
+                self.exhausted.set(self.exhausted.get() + 1);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl NativeCode for OptimizedFunction {
+    fn run(&self, vm: &mut Vm, floor: usize, offset: usize) -> bool {
+        if offset == 0 && !self.is_abandoned() {
+            if let Some(result) = self.call(vm) {
+                self.runs.set(self.runs.get() + 1);
+                vm.native_return(result, floor);
+                return true;
+            }
+            self.deopts.set(self.deopts.get() + 1);
+        }
+        self.baseline.as_ref().is_some_and(|baseline| baseline.run(vm, floor, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Profile, Thresholds};
+    use crate::stats::Tier;
+    use crate::{Arch, Jit};
+    use oxidex_bytecode::Constant;
+    use oxidex_codegen::ir::text::parse_module;
+
+    const PROGRAM: &str = r#"
+fn "fib"(%0: int) -> int {
+bb0:
+    %1: int = const int 2
+    %2: bool = binary lt %0, %1
+    branch %2, bb1, bb2
+bb1:
+    return %0
+bb2:
+    %3: int = const int 1
+    %4: int = binary sub %0, %3
+    %5: int = call "fib"(%4)
+    %6: int = const int 2
+    %7: int = binary sub %0, %6
+    %8: int = call "fib"(%7)
+    %9: int = binary add %5, %8
+    return %9
+}
+
+fn "clamp"(%0: float) -> float {
+bb0:
+    %1: float = const float 0.0
+    %2: float = intrinsic max(%0, %1)
+    %3: float = const float 1.0
+    %4: float = intrinsic min(%2, %3)
+    return %4
+}
+
+fn "wave"(%0: float, %1: int) -> float {
+bb0:
+    %2: float = const float 0.0
+    %3: int = const int 0
+    jump bb1
+bb1:
+    %4: float = phi [bb0: %2, bb2: %9]
+    %5: int = phi [bb0: %3, bb2: %10]
+    %6: bool = binary lt %5, %1
+    branch %6, bb2, bb3
+bb2:
+    %7: float = binary mul %0, %4
+    %8: float = const float 3.5
+    %11: float = binary mod %7, %8
+    %12: float = unary neg %11
+    %13: float = intrinsic abs(%12)
+    %9: float = binary add %13, %0
+    %14: int = const int 1
+    %10: int = binary add %5, %14
+    jump bb1
+bb3:
+    return %4
+}
+
+fn "split"(%0: int, %1: int) -> int {
+bb0:
+    %2: int = binary div %0, %1
+    %3: int = binary mod %0, %1
+    switch %3 [0: bb1, 1: bb2], default bb2
+bb1:
+    return %2
+bb2:
+    %4: int = unary neg %2
+    return %4
+}
+
+fn "square"(%0: object) -> int {
+bb0:
+    %1: int = call "times"(%0, %0)
+    return %1
+}
+
+fn "times"(%0: int, %1: int) -> int {
+bb0:
+    %2: int = binary mul %0, %1
+    return %2
+}
+
+fn "depth"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %2: bool = binary eq %0, %1
+    branch %2, bb1, bb2
+bb1:
+    return %1
+bb2:
+    %3: int = const int 1
+    %4: int = binary sub %0, %3
+    %5: int = call "depth"(%4)
+    %6: int = binary add %5, %3
+    return %6
+}
+
+fn "tally"(%0: int) -> int {
+bb0:
+    %1: int = const int 0
+    %2: object = box %1
+    %3: int = const int 1
+    jump bb1
+bb1:
+    %4: int = phi [bb0: %0, bb2: %8]
+    %5: bool = binary gt %4, %1
+    branch %5, bb2, bb3
+bb2:
+    %6: int = load %2
+    %7: int = binary add %6, %4
+    %9: unit = store %2, %7
+    %8: int = binary sub %4, %3
+    jump bb1
+bb3:
+    %10: int = load %2
+    return %10
+}
+"#;
+
+    fn closure(function: Function) -> Value {
+        Value::from(&Constant::Function(Rc::new(function)))
+    }
+
+    /// A VM that ran the program, defining its functions.
+    fn vm(module: &ir::Module, jit: Option<&Jit>) -> Vm {
+        let mut vm = Vm::new();
+        if let Some(jit) = jit {
+            jit.install(&mut vm);
+        }
+        vm.call(closure(oxidex_bytecode::compile(module).unwrap()), vec![]).unwrap();
+        vm
+    }
+
+    fn stats(jit: &Jit, name: &str) -> crate::stats::FunctionStats {
+        jit.stats().functions.into_iter().find(|function| function.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_optimized_code_runs_like_the_interpreter() {
+        if Arch::HOST.is_none() {
+            return;
+        }
+        let module = parse_module(PROGRAM).unwrap();
+        let jit = Jit::new(Profile::with_thresholds(Thresholds { warm: 2, hot: 4 })).with_module(module.clone());
+        let (mut interpreter, mut vm) = (vm(&module, None), vm(&module, Some(&jit)));
+        let mut check = |name: &str, args: Vec<Value>| {
+            let expected = interpreter.call(interpreter.global(name).unwrap(), args.clone());
+            let found = vm.call(vm.global(name).unwrap(), args);
+            match (expected, found) {
+                (Ok(expected), Ok(found)) => assert_eq!(found, expected, "{name}"),
+                (Err(expected), Err(found)) => assert_eq!(format!("{:?}", found.kind), format!("{:?}", expected.kind)),
+                (expected, found) => panic!("{name}: expected {expected:?}, found {found:?}"),
+            }
+        };
+
+        for n in 0..20 {
+            check("fib", vec![Value::Int(n)]);
+        }
+        // A NaN operand of min and max yields the other one
+        for x in [-2.0, 0.25, 7.0, f64::NAN, f64::INFINITY] {
+            check("clamp", vec![Value::Float(x)]);
+        }
+        for (x, n) in [(0.5, 10), (1.25, 100), (-3.0, 7), (2.0, 0)] {
+            check("wave", vec![Value::Float(x), Value::Int(n)]);
+        }
+        for (a, b) in [(7, 2), (-7, 2), (9, 3), (100, -7), (1, 5)] {
+            check("split", vec![Value::Int(a), Value::Int(b)]);
+        }
+        // Guarded by the profile of its arguments, which were all integers
+        for x in [3, -4, 11, 0, 6] {
+            check("square", vec![Value::Int(x)]);
+        }
+        for n in [0, 5, 1000] {
+            check("depth", vec![Value::Int(n)]);
+        }
+        // The box holding the sum is unboxed
+        for n in [0, 1, 10, 500, 20] {
+            check("tally", vec![Value::Int(n)]);
+        }
+        for name in ["fib", "clamp", "wave", "split", "square", "depth", "tally"] {
+            let stats = stats(&jit, name);
+            assert_eq!((stats.tier, stats.deopts), (Tier::Optimized, 0), "{name}");
+            assert!(stats.runs > 0 && stats.code_size > 0, "{name}");
+        }
+
+        // Calls the VM raises on leave the code for the baseline tier, which
+        // raises as the VM does
+        check("split", vec![Value::Int(1), Value::Int(0)]);
+        check("split", vec![Value::Int(i64::MIN), Value::Int(-1)]);
+        check("square", vec![Value::Int(i64::MAX)]);
+        // As do arguments of kinds the code was not specialized for
+        check("square", vec![Value::Float(1.5)]);
+        check("clamp", vec![Value::Int(1)]);
+        // And calls nested deeper than the VM allows
+        check("depth", vec![Value::Int(5000)]);
+        assert_eq!(stats(&jit, "split").deopts, 2);
+        assert_eq!(stats(&jit, "square").deopts, 2);
+        assert_eq!(stats(&jit, "clamp").deopts, 1);
+        // Every call it made deoptimizes too, which is not held against it
+        let depth = stats(&jit, "depth");
+        assert_eq!(depth.tier, Tier::Optimized);
+        assert!(depth.deopts > MAX_DEOPTS);
+    }
+
+    #[test]
+    fn test_optimized_code_guards_its_callees() {
+        if Arch::HOST.is_none() {
+            return;
+        }
+        let module = parse_module(PROGRAM).unwrap();
+        let jit = Jit::new(Profile::with_thresholds(Thresholds { warm: 1, hot: 2 })).with_module(module.clone());
+        let mut vm = vm(&module, Some(&jit));
+        let fib = vm.global("fib").unwrap();
+        assert_eq!(vm.call(fib.clone(), vec![Value::Int(30)]).unwrap(), Value::Int(832_040));
+        assert_eq!(stats(&jit, "fib").tier, Tier::Optimized);
+
+        // Redefining a callee makes calls deoptimize
+        let tenfold = r#"
+fn "tenfold"(%0: int) -> int {
+bb0:
+    %1: int = const int 10
+    %2: int = binary mul %0, %1
+    return %2
+}
+"#;
+        let tenfold = parse_module(tenfold).unwrap();
+        let tenfold = oxidex_bytecode::compiler::compile_function(&tenfold, &tenfold.functions[0]).unwrap();
+        vm.define_global("fib", closure(tenfold));
+        assert_eq!(vm.call(fib.clone(), vec![Value::Int(2)]).unwrap(), Value::Int(10));
+        assert_eq!(stats(&jit, "fib").deopts, 1);
+
+        // Code that keeps deoptimizing is abandoned
+        for _ in 1..MAX_DEOPTS {
+            vm.call(fib.clone(), vec![Value::Int(2)]).unwrap();
+        }
+        let stats = stats(&jit, "fib");
+        assert_eq!((stats.tier, stats.deopts), (Tier::Baseline, MAX_DEOPTS));
+        assert_eq!(stats.tiers, [Tier::Interpreted, Tier::Baseline, Tier::Optimized, Tier::Baseline]);
+    }
+}
@@ -0,0 +1,494 @@
+//! Execution profiling.
+//!
+//! A [`Profile`] is set as the VM's [`Profiler`], and counts the calls to
+//! each function and the iterations of each of its loops, by the offset of
+//! the loop's header. Counts are classified by [`Thresholds`]: a function or
+//! loop is warm once it is worth compiling, and hot once it is worth
+//! optimizing. A function is as hot as its calls or its hottest loop.
+//!
+//! The compile tier reads a [`ProfileSnapshot`], which lists the profiled
+//! functions hottest first. A profile keeps no function alive; counts of
+//! functions that were freed are dropped.
+//!
+//! A profile also records the kind of each argument a function is called
+//! with, which the optimizing tier specializes the function's parameters
+//! for, and counts the classes of the receivers of each selector a
+//! function sends. A snapshot prints as text an AOT build can read back,
+//! one line per function and per receiver class of a selector:
+//!
+//! ```text
+//! oxidex-profile 1
+//! fn <function> <hotness> <calls> <iterations>
+//! send <function> <selector> <class> <count>
+//! ```
+//!
+//! ```
+//! use oxidex_bytecode::Vm;
+//! use oxidex_jit::profile::Profile;
+//!
+//! let profile = Profile::new();
+//! let mut vm = Vm::new();
+//! vm.set_profiler(profile.clone());
+//! assert!(profile.snapshot().functions.is_empty());
+//! ```
+
+use oxidex_bytecode::{Function, Profiler, Value};
+use oxidec::Class;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+/// First line of a printed snapshot.
+pub const PROFILE_HEADER: &str = "oxidex-profile 1";
+
+/// How often a function or loop runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hotness {
+    /// Not run enough to compile
+    Cold,
+    /// Run enough to compile
+    Warm,
+    /// Run enough to optimize
+    Hot,
+}
+
+/// Counts at which functions and loops become warm and hot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Calls or iterations that make a function or loop warm
+    pub warm: u64,
+    /// Calls or iterations that make a function or loop hot
+    pub hot: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self { warm: 1_000, hot: 10_000 }
+    }
+}
+
+impl fmt::Display for Hotness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Cold => "cold",
+            Self::Warm => "warm",
+            Self::Hot => "hot",
+        })
+    }
+}
+
+impl Thresholds {
+    /// Classify a count.
+    #[must_use]
+    pub fn classify(&self, count: u64) -> Hotness {
+        if count >= self.hot {
+            Hotness::Hot
+        } else if count >= self.warm {
+            Hotness::Warm
+        } else {
+            Hotness::Cold
+        }
+    }
+}
+
+/// The kind of the values passed as one argument of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArgumentKind {
+    /// Always an integer
+    Int,
+    /// Always a float
+    Float,
+    /// Always a boolean
+    Bool,
+    /// Values of several kinds, or of one that is not a number or boolean
+    Mixed,
+}
+
+impl ArgumentKind {
+    /// The kind of a value.
+    #[must_use]
+    pub fn of(value: &Value) -> Self {
+        match value {
+            Value::Int(_) => Self::Int,
+            Value::Float(_) => Self::Float,
+            Value::Bool(_) => Self::Bool,
+            _ => Self::Mixed,
+        }
+    }
+
+    /// The kind of the values of this kind and of `other`.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        if self == other { self } else { Self::Mixed }
+    }
+}
+
+/// Counts for one function.
+#[derive(Debug)]
+struct Counters {
+    /// The function, kept allocated so its address is not reused
+    function: Weak<Function>,
+    /// Calls to the function
+    calls: u64,
+    /// Iterations of each loop, by header offset
+    backedges: HashMap<usize, u64>,
+    /// Messages sent, by selector and receiver class
+    sends: HashMap<String, HashMap<String, u64>>,
+    /// Kind of each argument, once the function was called
+    arguments: Option<Vec<ArgumentKind>>,
+}
+
+/// The counts of a profile, by function address.
+#[derive(Debug, Default)]
+struct Counts {
+    /// Counters of each function
+    functions: HashMap<usize, Counters>,
+    /// Classification of the counts
+    thresholds: Thresholds,
+}
+
+impl Counts {
+    fn counters(&mut self, function: &Rc<Function>) -> &mut Counters {
+        let address = Rc::as_ptr(function) as usize;
+        self.functions.entry(address).or_insert_with(|| Counters {
+            function: Rc::downgrade(function),
+            calls: 0,
+            backedges: HashMap::new(),
+            sends: HashMap::new(),
+            arguments: None,
+        })
+    }
+}
+
+/// Counts of calls and loop iterations, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    counts: Rc<RefCell<Counts>>,
+}
+
+impl Profile {
+    /// Create an empty profile with the default thresholds.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty profile classifying counts by `thresholds`.
+    #[must_use]
+    pub fn with_thresholds(thresholds: Thresholds) -> Self {
+        let profile = Self::new();
+        profile.counts.borrow_mut().thresholds = thresholds;
+        profile
+    }
+
+    /// The thresholds counts are classified by.
+    #[must_use]
+    pub fn thresholds(&self) -> Thresholds {
+        self.counts.borrow().thresholds
+    }
+
+    /// How hot a function is so far.
+    #[must_use]
+    pub fn hotness(&self, function: &Rc<Function>) -> Hotness {
+        let counts = self.counts.borrow();
+        let address = Rc::as_ptr(function) as usize;
+        match counts.functions.get(&address) {
+            Some(counters) => {
+                let iterations = counters.backedges.values().copied().max().unwrap_or(0);
+                counts.thresholds.classify(counters.calls.max(iterations))
+            }
+            _ => Hotness::Cold,
+        }
+    }
+
+    /// The kind of each argument a function was called with, or `None` if
+    /// it was not called.
+    #[must_use]
+    pub fn arguments(&self, function: &Rc<Function>) -> Option<Vec<ArgumentKind>> {
+        let counts = self.counts.borrow();
+        counts.functions.get(&(Rc::as_ptr(function) as usize))?.arguments.clone()
+    }
+
+    /// Copy the counts of the functions that are still alive, hottest
+    /// first.
+    #[must_use]
+    pub fn snapshot(&self) -> ProfileSnapshot {
+        let mut counts = self.counts.borrow_mut();
+        counts.functions.retain(|_, counters| counters.function.strong_count() > 0);
+        let thresholds = counts.thresholds;
+        let mut functions: Vec<_> = counts
+            .functions
+            .values()
+            .filter_map(|counters| {
+                let mut loops: Vec<_> = counters
+                    .backedges
+                    .iter()
+                    .map(|(&header, &iterations)| LoopProfile {
+                        header,
+                        iterations,
+                        hotness: thresholds.classify(iterations),
+                    })
+                    .collect();
+                loops.sort_by_key(|profile| profile.header);
+                let iterations = loops.iter().map(|profile| profile.iterations).max().unwrap_or(0);
+                let mut sends: Vec<_> = counters
+                    .sends
+                    .iter()
+                    .map(|(selector, classes)| {
+                        let mut receivers: Vec<_> = classes.iter().map(|(class, &n)| (class.clone(), n)).collect();
+                        receivers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                        SendProfile { selector: selector.clone(), receivers }
+                    })
+                    .collect();
+                sends.sort_by(|a, b| a.selector.cmp(&b.selector));
+                Some(FunctionProfile {
+                    function: counters.function.upgrade()?,
+                    calls: counters.calls,
+                    hotness: thresholds.classify(counters.calls.max(iterations)),
+                    loops,
+                    sends,
+                })
+            })
+            .collect();
+        functions.sort_by(|a, b| {
+            let heat = |profile: &FunctionProfile| (profile.hotness, profile.calls + profile.iterations());
+            heat(b).cmp(&heat(a)).then_with(|| a.function.name.cmp(&b.function.name))
+        });
+        ProfileSnapshot { functions }
+    }
+
+    /// Forget every count.
+    pub fn reset(&self) {
+        self.counts.borrow_mut().functions.clear();
+    }
+}
+
+impl Profiler for Profile {
+    fn call(&mut self, function: &Rc<Function>) {
+        self.counts.borrow_mut().counters(function).calls += 1;
+    }
+
+    fn backedge(&mut self, function: &Rc<Function>, header: usize) {
+        *self.counts.borrow_mut().counters(function).backedges.entry(header).or_default() += 1;
+    }
+
+    fn send(&mut self, function: &Rc<Function>, selector: &str, class: &Class) {
+        let mut counts = self.counts.borrow_mut();
+        let classes = counts.counters(function).sends.entry(selector.to_string()).or_default();
+        *classes.entry(class.name().to_string()).or_default() += 1;
+    }
+
+    fn arguments(&mut self, function: &Rc<Function>, args: &[Value]) {
+        let mut counts = self.counts.borrow_mut();
+        let kinds = args.iter().map(ArgumentKind::of);
+        match &mut counts.counters(function).arguments {
+            Some(seen) => seen.iter_mut().zip(kinds).for_each(|(seen, kind)| *seen = seen.merge(kind)),
+            arguments @ None => *arguments = Some(kinds.collect()),
+        }
+    }
+}
+
+/// The counts of a profile at one point.
+#[derive(Debug, Clone)]
+pub struct ProfileSnapshot {
+    /// Profiled functions, hottest first
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl ProfileSnapshot {
+    /// The functions at least as hot as `hotness`, hottest first.
+    pub fn at_least(&self, hotness: Hotness) -> impl Iterator<Item = &FunctionProfile> {
+        self.functions.iter().filter(move |profile| profile.hotness >= hotness)
+    }
+}
+
+impl fmt::Display for ProfileSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{PROFILE_HEADER}")?;
+        for profile in &self.functions {
+            let name = &profile.function.name;
+            writeln!(f, "fn {name} {} {} {}", profile.hotness, profile.calls, profile.iterations())?;
+            for send in &profile.sends {
+                for (class, count) in &send.receivers {
+                    writeln!(f, "send {name} {} {class} {count}", send.selector)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The counts of one function.
+#[derive(Debug, Clone)]
+pub struct FunctionProfile {
+    /// The function
+    pub function: Rc<Function>,
+    /// Calls to the function
+    pub calls: u64,
+    /// How hot the function or its hottest loop is
+    pub hotness: Hotness,
+    /// The function's loops that ran, by header offset
+    pub loops: Vec<LoopProfile>,
+    /// The messages the function sent, by selector
+    pub sends: Vec<SendProfile>,
+}
+
+impl FunctionProfile {
+    /// Iterations of all the function's loops.
+    #[must_use]
+    pub fn iterations(&self) -> u64 {
+        self.loops.iter().map(|profile| profile.iterations).sum()
+    }
+}
+
+/// The receivers of the messages a function sent with one selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendProfile {
+    /// The selector
+    pub selector: String,
+    /// Names of the receivers' classes and the messages each received,
+    /// most first
+    pub receivers: Vec<(String, u64)>,
+}
+
+/// The counts of one loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopProfile {
+    /// Offset of the loop's header, where its back edge jumps to
+    pub header: usize,
+    /// Jumps back to the header
+    pub iterations: u64,
+    /// How hot the loop is
+    pub hotness: Hotness,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_bytecode::{Chunk, Constant, OpCode, Value, Vm};
+    use oxidex_syntax::Span;
+
+    /// `fn count(n) { var i = 0; while i < n { i = i + 1 }; i }`
+    fn count() -> Function {
+        let span = Span::new(0, 1, 1, 1, 1, 2);
+        let mut chunk = Chunk::new();
+        chunk.write_constant(OpCode::Constant, Constant::Int(0), span).unwrap();
+        let header = chunk.len();
+        for (op, slot) in [(OpCode::GetLocal, 1), (OpCode::GetLocal, 0)] {
+            chunk.write_op(op, span);
+            chunk.write(slot, span);
+        }
+        chunk.write_op(OpCode::Less, span);
+        let exit = chunk.write_jump(OpCode::JumpIfFalse, span);
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(1, span);
+        chunk.write_constant(OpCode::Constant, Constant::Int(1), span).unwrap();
+        chunk.write_op(OpCode::Add, span);
+        chunk.write_op(OpCode::SetLocal, span);
+        chunk.write(1, span);
+        chunk.write_op(OpCode::Pop, span);
+        chunk.write_loop(header, span).unwrap();
+        chunk.patch_jump(exit).unwrap();
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(1, span);
+        chunk.write_op(OpCode::Return, span);
+        Function { name: "count".to_string(), arity: 1, captures: vec![], chunk }
+    }
+
+    fn closure(function: &Rc<Function>) -> Value {
+        Value::from(&Constant::Function(Rc::clone(function)))
+    }
+
+    #[test]
+    fn test_profile_counts_calls_and_loops() {
+        let profile = Profile::with_thresholds(Thresholds { warm: 5, hot: 50 });
+        let mut vm = Vm::new();
+        vm.set_profiler(profile.clone());
+        let (count, other) = (Rc::new(count()), Rc::new(count()));
+
+        for n in [0, 1, 2] {
+            assert_eq!(vm.call(closure(&count), vec![Value::Int(n)]).unwrap(), Value::Int(n));
+        }
+        assert_eq!(profile.hotness(&count), Hotness::Cold);
+        vm.call(closure(&other), vec![Value::Int(5)]).unwrap();
+        assert_eq!(profile.hotness(&other), Hotness::Warm);
+        vm.call(closure(&count), vec![Value::Int(60)]).unwrap();
+        assert_eq!(profile.hotness(&count), Hotness::Hot);
+        assert_eq!(profile.arguments(&count), Some(vec![ArgumentKind::Int]));
+
+        let snapshot = profile.snapshot();
+        let summary: Vec<_> = snapshot
+            .functions
+            .iter()
+            .map(|profile| (Rc::ptr_eq(&profile.function, &count), profile.calls, profile.hotness))
+            .collect();
+        assert_eq!(summary, [(true, 4, Hotness::Hot), (false, 1, Hotness::Warm)]);
+        // The loop starts after `var i = 0`
+        let header = 3;
+        assert_eq!(snapshot.functions[0].loops, [LoopProfile { header, iterations: 63, hotness: Hotness::Hot }]);
+        assert_eq!(snapshot.at_least(Hotness::Hot).count(), 1);
+
+        // Freed functions are dropped
+        drop((snapshot, other));
+        assert_eq!(profile.snapshot().functions.len(), 1);
+        profile.reset();
+        assert!(profile.snapshot().functions.is_empty());
+
+        // Arguments of several kinds are mixed
+        vm.call(closure(&count), vec![Value::Int(1)]).unwrap();
+        vm.call(closure(&count), vec![Value::Float(0.5)]).unwrap_err();
+        assert_eq!(profile.arguments(&count), Some(vec![ArgumentKind::Mixed]));
+        assert!(vm.take_profiler().is_some());
+    }
+
+    unsafe extern "C-unwind" fn answer(
+        _self: oxidec::runtime::ObjectPtr,
+        _cmd: oxidec::runtime::selector::SelectorHandle,
+        _args: *const *mut u8,
+        ret: *mut u8,
+    ) {
+        // SAFETY: dispatch provides a return buffer large enough for a word
+        unsafe { ret.cast::<usize>().write_unaligned(42) };
+    }
+
+    #[test]
+    fn test_profile_counts_receivers_and_prints() {
+        use oxidec::{Method, Object, RuntimeString, Selector, get_global_arena};
+        use std::str::FromStr;
+
+        let base = Class::new_root("ProfileAnswerer").unwrap();
+        let selector = Selector::from_str("answer").unwrap();
+        base.add_method(Method { selector, imp: answer, types: RuntimeString::new("q@:", get_global_arena()) })
+            .unwrap();
+        let derived = Class::new("ProfileDerived", &base).unwrap();
+        let profile = Profile::with_thresholds(Thresholds { warm: 2, hot: 3 });
+        let mut vm = Vm::new();
+        vm.set_profiler(profile.clone());
+
+        // fn ask(receiver) { receiver.answer() }
+        let span = Span::new(0, 1, 1, 1, 1, 2);
+        let mut chunk = Chunk::new();
+        chunk.write_op(OpCode::GetLocal, span);
+        chunk.write(0, span);
+        chunk.write_constant(OpCode::Send, Constant::String(Rc::from("answer")), span).unwrap();
+        chunk.write(0, span);
+        chunk.write_op(OpCode::Return, span);
+        let ask = Rc::new(Function { name: "ask".to_string(), arity: 1, captures: vec![], chunk });
+        for class in [&derived, &base, &derived] {
+            let receiver = vm.instance(Object::new(class).unwrap());
+            assert_eq!(vm.call(closure(&ask), vec![receiver]).unwrap(), Value::Int(42));
+        }
+
+        let snapshot = profile.snapshot();
+        let receivers = vec![("ProfileDerived".to_string(), 2), ("ProfileAnswerer".to_string(), 1)];
+        assert_eq!(snapshot.functions[0].sends, [SendProfile { selector: "answer".to_string(), receivers }]);
+        assert_eq!(
+            snapshot.to_string(),
+            "oxidex-profile 1\n\
+             fn ask hot 3 0\n\
+             send ask answer ProfileDerived 2\n\
+             send ask answer ProfileAnswerer 1\n"
+        );
+    }
+}
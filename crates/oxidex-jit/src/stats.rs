@@ -0,0 +1,220 @@
+//! Compilation statistics.
+//!
+//! [`Jit::stats`](crate::Jit::stats) reports what the JIT did with each
+//! function it considered that is still alive: the tier it runs in, how
+//! long it took to compile, how big its code is, how often the code was
+//! entered and how often the VM fell back to interpreting it, and how full
+//! the inline caches of its message sends are. The baseline tier runs every
+//! instruction exactly as the interpreter would, so it never deoptimizes;
+//! its only fallbacks are the VM resuming a frame at an offset the code has
+//! no entry for. Optimized code deoptimizes to the baseline when a call
+//! breaks its assumptions, and falls back to it for good when that happens
+//! too often; the tiers a function went through are its transitions.
+//!
+//! The report prints as a table, one line per function, hottest first,
+//! followed by the transitions of the functions that were compiled:
+//!
+//! ```text
+//! 1 functions compiled, 0 interpreted, 150 bytes of code, 0.025ms compiling
+//! function  tier         hotness      calls       runs  fallbacks     deopts    bytes    compile  sends e/m/p/M
+//! fib       baseline     hot           1973       1973          0          0      150    0.025ms  0/0/0/0
+//! transitions:
+//!   fib: interpreted -> baseline
+//! ```
+
+use crate::profile::Hotness;
+use std::fmt;
+use std::time::Duration;
+
+/// How a function runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    /// Interpreted, because its code could not be compiled
+    Interpreted,
+    /// Compiled by the baseline tier
+    Baseline,
+    /// Compiled by the optimizing tier
+    Optimized,
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Interpreted => "interpreted",
+            Self::Baseline => "baseline",
+            Self::Optimized => "optimized",
+        })
+    }
+}
+
+/// Send sites of compiled code by the state of their inline cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SiteOccupancy {
+    /// Sites that have seen no receiver
+    pub empty: usize,
+    /// Sites that have seen receivers of one class
+    pub monomorphic: usize,
+    /// Sites that have seen receivers of a few classes
+    pub polymorphic: usize,
+    /// Sites that gave up caching
+    pub megamorphic: usize,
+}
+
+impl SiteOccupancy {
+    /// Number of sites.
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.empty + self.monomorphic + self.polymorphic + self.megamorphic
+    }
+}
+
+/// What the JIT did with one function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// Name of the function
+    pub name: String,
+    /// How it runs
+    pub tier: Tier,
+    /// Tiers it ran in, in order, ending with [`tier`](Self::tier)
+    pub tiers: Vec<Tier>,
+    /// How hot the profile finds it
+    pub hotness: Hotness,
+    /// Calls the profile counted
+    pub calls: u64,
+    /// Times its code was entered
+    pub runs: u64,
+    /// Times the VM interpreted an instruction of it instead of entering
+    /// its code
+    pub fallbacks: u64,
+    /// Times its optimized code left a call to the baseline tier
+    pub deopts: u64,
+    /// Length of its code
+    pub code_size: usize,
+    /// Time spent compiling it
+    pub compile_time: Duration,
+    /// Its send sites
+    pub sends: SiteOccupancy,
+}
+
+/// What the JIT did with the functions it considered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JitStats {
+    /// Functions considered that are still alive, hottest first
+    pub functions: Vec<FunctionStats>,
+}
+
+impl JitStats {
+    /// Number of functions running compiled code.
+    #[must_use]
+    pub fn compiled(&self) -> usize {
+        self.functions.iter().filter(|function| function.tier > Tier::Interpreted).count()
+    }
+
+    /// Total length of compiled code.
+    #[must_use]
+    pub fn code_size(&self) -> usize {
+        self.functions.iter().map(|function| function.code_size).sum()
+    }
+
+    /// Total time spent compiling.
+    #[must_use]
+    pub fn compile_time(&self) -> Duration {
+        self.functions.iter().map(|function| function.compile_time).sum()
+    }
+
+    /// Send sites of all compiled code.
+    #[must_use]
+    pub fn sends(&self) -> SiteOccupancy {
+        self.functions.iter().fold(SiteOccupancy::default(), |total, function| SiteOccupancy {
+            empty: total.empty + function.sends.empty,
+            monomorphic: total.monomorphic + function.sends.monomorphic,
+            polymorphic: total.polymorphic + function.sends.polymorphic,
+            megamorphic: total.megamorphic + function.sends.megamorphic,
+        })
+    }
+}
+
+impl fmt::Display for JitStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |time: Duration| format!("{:.3}ms", time.as_secs_f64() * 1000.0);
+        let compiled = self.compiled();
+        writeln!(
+            f,
+            "{compiled} functions compiled, {} interpreted, {} bytes of code, {} compiling",
+            self.functions.len() - compiled,
+            self.code_size(),
+            millis(self.compile_time()),
+        )?;
+        let width = self.functions.iter().map(|function| function.name.len()).max().unwrap_or(0).max(8);
+        let mut row = |cells: [String; 10]| {
+            let [name, tier, hotness, calls, runs, fallbacks, deopts, bytes, compile, sends] = cells;
+            write!(f, "{name:width$}  {tier:11}  {hotness:7}  {calls:>9}  {runs:>9}  ")?;
+            writeln!(f, "{fallbacks:>9}  {deopts:>9}  {bytes:>7}  {compile:>9}  {sends}")
+        };
+        let header =
+            ["function", "tier", "hotness", "calls", "runs", "fallbacks", "deopts", "bytes", "compile", "sends e/m/p/M"];
+        row(header.map(str::to_string))?;
+        for function in &self.functions {
+            let hotness = match function.hotness {
+                Hotness::Cold => "cold",
+                Hotness::Warm => "warm",
+                Hotness::Hot => "hot",
+            };
+            let SiteOccupancy { empty, monomorphic, polymorphic, megamorphic } = function.sends;
+            row([
+                function.name.clone(),
+                function.tier.to_string(),
+                hotness.to_string(),
+                function.calls.to_string(),
+                function.runs.to_string(),
+                function.fallbacks.to_string(),
+                function.deopts.to_string(),
+                function.code_size.to_string(),
+                millis(function.compile_time),
+                format!("{empty}/{monomorphic}/{polymorphic}/{megamorphic}"),
+            ])?;
+        }
+
+        let moved: Vec<_> = self.functions.iter().filter(|function| function.tiers.len() > 1).collect();
+        if !moved.is_empty() {
+            writeln!(f, "transitions:")?;
+        }
+        for function in moved {
+            let tiers: Vec<String> = function.tiers.iter().map(Tier::to_string).collect();
+            writeln!(f, "  {}: {}", function.name, tiers.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_table() {
+        let function = |name: &str, tier, code_size| FunctionStats {
+            name: name.to_string(),
+            tier,
+            tiers: if tier == Tier::Interpreted { vec![tier] } else { vec![Tier::Interpreted, tier] },
+            hotness: Hotness::Hot,
+            calls: 1973,
+            runs: 1973,
+            fallbacks: 0,
+            deopts: 0,
+            code_size,
+            compile_time: Duration::from_micros(25),
+            sends: SiteOccupancy { monomorphic: 2, ..SiteOccupancy::default() },
+        };
+        let functions = vec![function("fib", Tier::Baseline, 150), function("f", Tier::Interpreted, 0)];
+        let stats = JitStats { functions };
+        assert_eq!((stats.compiled(), stats.code_size(), stats.sends().total()), (1, 150, 4));
+        let report = stats.to_string();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines[0], "1 functions compiled, 1 interpreted, 150 bytes of code, 0.050ms compiling");
+        assert!(lines[1].starts_with("function  tier         hotness"));
+        assert!(lines[2].starts_with("fib       baseline     hot"));
+        assert!(lines[3].ends_with("0.025ms  0/2/0/0"));
+        assert_eq!(lines[4..], ["transitions:", "  fib: interpreted -> baseline"]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Symbols for compiled code.
+//!
+//! Profilers and debuggers see compiled code as anonymous memory unless
+//! told what it is. [`Symbols`] names each compiled function's code after
+//! the function, in up to two places:
+//!
+//! - A perf map, `/tmp/perf-<pid>.map`, which `perf report` reads to
+//!   attribute samples: a line per code region with its start and size in
+//!   hex and its name. Lines are only ever appended, so a region whose
+//!   memory is reused by later code keeps its line, and perf takes the
+//!   later one.
+//! - The GDB JIT interface, through which GDB and LLDB pick up an in-memory
+//!   object file for each region, holding a function symbol that covers
+//!   it. The object is unregistered when the code is freed.
+//!
+//! Naming is best effort: code that cannot be named, because the map could
+//! not be written, still runs.
+
+mod gdb;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where compiled code is named.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    /// The perf map, if code is named for perf
+    perf_map: Option<File>,
+    /// Whether code is registered with debuggers
+    gdb: bool,
+}
+
+impl Symbols {
+    /// Name code nowhere.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path of this process's perf map.
+    #[must_use]
+    pub fn perf_map_path() -> PathBuf {
+        PathBuf::from(format!("/tmp/perf-{}.map", std::process::id()))
+    }
+
+    /// Also name code in this process's perf map.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map cannot be opened for appending.
+    pub fn perf_map(self) -> io::Result<Self> {
+        self.perf_map_at(Self::perf_map_path())
+    }
+
+    /// Also name code in a perf map at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the map cannot be opened for appending.
+    pub fn perf_map_at(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.perf_map = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(self)
+    }
+
+    /// Also register code with debuggers through the GDB JIT interface.
+    #[must_use]
+    pub fn gdb(mut self) -> Self {
+        self.gdb = true;
+        self
+    }
+
+    /// Name the `size` bytes of code at `start`, returning the symbol that
+    /// keeps it named for as long as the code lives.
+    pub(crate) fn define(&mut self, name: &str, start: *const u8, size: usize) -> Symbol {
+        if let Some(map) = &mut self.perf_map {
+            // perf reads the map after the run, so each line is written whole
+            let line = format!("{:x} {size:x} {}\n", start as usize, name.replace('\n', " "));
+            if map.write_all(line.as_bytes()).is_err() {
+                self.perf_map = None;
+            }
+        }
+        Symbol { gdb: self.gdb.then(|| gdb::Registration::new(name, start, size)).flatten() }
+    }
+}
+
+/// The naming of one code region, undone when dropped.
+#[derive(Debug)]
+pub(crate) struct Symbol {
+    /// The object file registered with debuggers, if any
+    gdb: Option<gdb::Registration>,
+}
+
+impl Symbol {
+    /// Whether the region is registered with debuggers.
+    pub(crate) fn is_registered(&self) -> bool {
+        self.gdb.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_map_lines() {
+        let path = std::env::temp_dir().join(format!("oxidex-perf-{}.map", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut symbols = Symbols::new().perf_map_at(&path).unwrap().gdb();
+        let code = [0xc3_u8; 32];
+        let symbol = symbols.define("fib", code.as_ptr(), code.len());
+        symbols.define("two\nlines", code[16..].as_ptr(), 16);
+
+        let map = std::fs::read_to_string(&path).unwrap();
+        let start = code.as_ptr() as usize;
+        assert_eq!(map, format!("{start:x} 20 fib\n{:x} 10 two lines\n", start + 16));
+        assert!(symbol.is_registered());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,253 @@
+//! The GDB JIT interface.
+//!
+//! A debugger that finds `__jit_debug_register_code` and
+//! `__jit_debug_descriptor` in a process breaks on the function, and on
+//! each call reads the object file the descriptor's relevant entry points
+//! to, or forgets it, as the action says. Entries form a list headed by the
+//! descriptor, which is only changed while holding a lock.
+//!
+//! Each code region gets a relocatable ELF object with a `.text` section
+//! placed at the region and one function symbol covering it.
+
+use std::sync::Mutex;
+
+/// Version of the interface.
+const VERSION: u32 = 1;
+
+/// Actions in the descriptor.
+const NO_ACTION: u32 = 0;
+const REGISTER: u32 = 1;
+const UNREGISTER: u32 = 2;
+
+/// ELF machine of the host, if there is code for it.
+const MACHINE: Option<u16> = if cfg!(target_arch = "x86_64") {
+    Some(62)
+} else if cfg!(target_arch = "aarch64") {
+    Some(183)
+} else {
+    None
+};
+
+/// An entry of the list debuggers read.
+#[repr(C)]
+struct CodeEntry {
+    next: *mut CodeEntry,
+    prev: *mut CodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+/// The head of the list, where debuggers look for it.
+#[repr(C)]
+struct Descriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut CodeEntry,
+    first_entry: *mut CodeEntry,
+}
+
+/// The list of registered objects.
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+static mut __jit_debug_descriptor: Descriptor = Descriptor {
+    version: VERSION,
+    action_flag: NO_ACTION,
+    relevant_entry: std::ptr::null_mut(),
+    first_entry: std::ptr::null_mut(),
+};
+
+/// Serializes changes to the list.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Called after each change to the list, for a debugger to break on.
+#[unsafe(no_mangle)]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    // Keeps the function from being folded into another
+    std::hint::black_box(());
+}
+
+/// An object file registered with debuggers, unregistered when dropped.
+#[derive(Debug)]
+pub(super) struct Registration {
+    /// The entry, linked into the list
+    entry: *mut CodeEntry,
+    /// The object file the entry points to, kept alive for it
+    _object: Vec<u8>,
+}
+
+impl Registration {
+    /// Register an object naming the `size` bytes of code at `start`, or
+    /// `None` on a machine without code.
+    pub(super) fn new(name: &str, start: *const u8, size: usize) -> Option<Self> {
+        let object = object(MACHINE?, name, start as u64, size as u64);
+        let entry = Box::into_raw(Box::new(CodeEntry {
+            next: std::ptr::null_mut(),
+            prev: std::ptr::null_mut(),
+            symfile_addr: object.as_ptr(),
+            symfile_size: object.len() as u64,
+        }));
+        let _guard = LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        // SAFETY: the list is only changed under the lock, and its entries
+        // are alive until unlinked
+        unsafe {
+            let descriptor = &raw mut __jit_debug_descriptor;
+            (*entry).next = (*descriptor).first_entry;
+            if let Some(next) = (*entry).next.as_mut() {
+                next.prev = entry;
+            }
+            (*descriptor).first_entry = entry;
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = REGISTER;
+            __jit_debug_register_code();
+        }
+        Some(Self { entry, _object: object })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _guard = LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        // SAFETY: the entry was linked by `new` and is unlinked once, under
+        // the lock
+        unsafe {
+            let (descriptor, entry) = (&raw mut __jit_debug_descriptor, self.entry);
+            match (*entry).prev.as_mut() {
+                Some(prev) => prev.next = (*entry).next,
+                None => (*descriptor).first_entry = (*entry).next,
+            }
+            if let Some(next) = (*entry).next.as_mut() {
+                next.prev = (*entry).prev;
+            }
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = UNREGISTER;
+            __jit_debug_register_code();
+            (*descriptor).relevant_entry = std::ptr::null_mut();
+            (*descriptor).action_flag = NO_ACTION;
+            drop(Box::from_raw(entry));
+        }
+    }
+}
+
+/// Build a relocatable ELF object for `machine` whose `.text` section is
+/// the `size` bytes at `address`, with a function symbol `name` covering
+/// them.
+fn object(machine: u16, name: &str, address: u64, size: u64) -> Vec<u8> {
+    const HEADER: usize = 64;
+    const SECTION: u16 = 64;
+    const SYMBOL: u64 = 24;
+    let shstrtab = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+    let strtab = [b"\0".as_slice(), name.as_bytes(), b"\0"].concat();
+
+    let mut symtab = vec![0; SYMBOL as usize];
+    symtab.extend_from_slice(&1_u32.to_le_bytes()); // st_name
+    symtab.push(0x12); // st_info: global function
+    symtab.push(0); // st_other
+    symtab.extend_from_slice(&1_u16.to_le_bytes()); // st_shndx: .text
+    symtab.extend_from_slice(&0_u64.to_le_bytes()); // st_value: start of .text
+    symtab.extend_from_slice(&size.to_le_bytes()); // st_size
+
+    let symtab_at = HEADER;
+    let strtab_at = symtab_at + symtab.len();
+    let shstrtab_at = strtab_at + strtab.len();
+    let sections_at = (shstrtab_at + shstrtab.len()).next_multiple_of(8);
+
+    let mut elf = Vec::with_capacity(sections_at + 5 * usize::from(SECTION));
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]); // 64-bit, little endian, version 1
+    elf.resize(16, 0);
+    elf.extend_from_slice(&1_u16.to_le_bytes()); // e_type: relocatable
+    elf.extend_from_slice(&machine.to_le_bytes());
+    elf.extend_from_slice(&1_u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&0_u64.to_le_bytes()); // e_entry
+    elf.extend_from_slice(&0_u64.to_le_bytes()); // e_phoff
+    elf.extend_from_slice(&(sections_at as u64).to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0_u32.to_le_bytes()); // e_flags
+    for half in [HEADER as u16, 0, 0, SECTION, 5, 4] {
+        // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx
+        elf.extend_from_slice(&half.to_le_bytes());
+    }
+    elf.extend_from_slice(&symtab);
+    elf.extend_from_slice(&strtab);
+    elf.extend_from_slice(shstrtab);
+    elf.resize(sections_at, 0);
+
+    // Name, type, flags, address, offset, size, link and info
+    let mut section = |name: u32, kind: u32, flags: u64, address: u64, offset: usize, size: u64, link: u32, info| {
+        for word in [name, kind] {
+            elf.extend_from_slice(&word.to_le_bytes());
+        }
+        for word in [flags, address, offset as u64, size] {
+            elf.extend_from_slice(&word.to_le_bytes());
+        }
+        for word in [link, info] {
+            elf.extend_from_slice(&word.to_le_bytes());
+        }
+        elf.extend_from_slice(&(if kind == 2 { 8_u64 } else { 1 }).to_le_bytes()); // sh_addralign
+        elf.extend_from_slice(&(if kind == 2 { SYMBOL } else { 0 }).to_le_bytes()); // sh_entsize
+    };
+    section(0, 0, 0, 0, 0, 0, 0, 0);
+    // .text: no bits in the file, allocated and executable at the code
+    section(1, 8, 0x6, address, 0, size, 0, 0);
+    // .symtab, linked to .strtab, with its one local symbol first
+    section(7, 2, 0, 0, symtab_at, symtab.len() as u64, 3, 1);
+    // .strtab and .shstrtab
+    section(15, 3, 0, 0, strtab_at, strtab.len() as u64, 0, 0);
+    section(23, 3, 0, 0, shstrtab_at, shstrtab.len() as u64, 0, 0);
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_object_names_the_code() {
+        let elf = object(62, "fib", 0x1000, 0x40);
+        assert_eq!(elf[..4], [0x7f, b'E', b'L', b'F']);
+        let sections = usize::try_from(u64_at(&elf, 40)).unwrap();
+        assert_eq!(elf.len(), sections + 5 * 64);
+
+        // .text sits at the code
+        let text = sections + 64;
+        assert_eq!((u64_at(&elf, text + 16), u64_at(&elf, text + 32)), (0x1000, 0x40));
+        // The symbol is the name in .strtab, covering .text
+        let symbol = 64 + 24;
+        assert_eq!(elf[symbol + 6..symbol + 8], 1_u16.to_le_bytes());
+        assert_eq!(u64_at(&elf, symbol + 16), 0x40);
+        let strtab = 64 + 48;
+        assert_eq!(&elf[strtab..strtab + 5], b"\0fib\0");
+    }
+
+    #[test]
+    fn test_registrations_link_and_unlink() {
+        // Other tests may register code meanwhile, so the list is searched
+        fn registered(entry: *mut CodeEntry) -> bool {
+            let _guard = LOCK.lock().unwrap();
+            // SAFETY: the list is read under the lock
+            unsafe {
+                let descriptor = &raw const __jit_debug_descriptor;
+                let mut next = (*descriptor).first_entry;
+                while !next.is_null() && next != entry {
+                    next = (*next).next;
+                }
+                !next.is_null()
+            }
+        }
+
+        let code = [0_u8; 8];
+        let Some(first) = Registration::new("first", code.as_ptr(), 8) else {
+            return;
+        };
+        let second = Registration::new("second", code.as_ptr(), 8).unwrap();
+        let (first_entry, second_entry) = (first.entry, second.entry);
+        assert!(registered(first_entry) && registered(second_entry));
+        drop(first);
+        assert!(registered(second_entry));
+        drop(second);
+        assert!(!registered(second_entry));
+    }
+}
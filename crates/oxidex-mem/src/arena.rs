@@ -147,9 +147,8 @@ impl Chunk {
             return Err(ArenaAllocError);
         }
 
-        let layout = unsafe {
-            Layout::from_size_align_unchecked(size, DEFAULT_ALIGNMENT)
-        };
+        let layout = Layout::from_size_align(size, DEFAULT_ALIGNMENT)
+            .map_err(|_| ArenaAllocError)?;
 
         let start = unsafe { alloc::alloc(layout) };
         let start = NonNull::new(start).ok_or(ArenaAllocError)?;
@@ -350,10 +349,33 @@ impl GlobalArena {
     /// # Panics
     ///
     /// Panics if the allocation fails (e.g., out of memory and unable to
-    /// allocate additional chunks).
+    /// allocate additional chunks). See [`GlobalArena::try_alloc`] for a
+    /// variant that returns an error instead.
     #[inline(always)]
     #[allow(clippy::mut_from_ref)] // Uses interior mutability via UnsafeCell
     pub fn alloc<T>(&self, value: T) -> &mut T {
+        self.try_alloc(value).expect("Failed to allocate new chunk")
+    }
+
+    /// Allocates a value in the global arena, or returns an error if no
+    /// chunk large enough can be allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArenaAllocError`] if the system allocator cannot provide
+    /// a new chunk. The value is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oxidex_mem::arena::GlobalArena;
+    ///
+    /// let arena = GlobalArena::new(65536);
+    /// assert_eq!(*arena.try_alloc(7u8).unwrap(), 7);
+    /// ```
+    #[inline(always)]
+    #[allow(clippy::mut_from_ref)] // Uses interior mutability via UnsafeCell
+    pub fn try_alloc<T>(&self, value: T) -> Result<&mut T, ArenaAllocError> {
         let size = std::mem::size_of::<T>();
         let align = std::mem::align_of::<T>().max(self.alignment);
 
@@ -370,42 +392,57 @@ impl GlobalArena {
                     unsafe {
                         std::ptr::write(ptr.as_ptr().cast::<T>(), value);
                         self.total_allocated.fetch_add(size, Ordering::Relaxed);
-                        return &mut *(ptr.as_ptr().cast::<T>());
+                        return Ok(&mut *(ptr.as_ptr().cast::<T>()));
                     }
                 }
             }
 
             // Need to allocate a new chunk
-            self.allocate_new_chunk(size);
+            self.allocate_new_chunk(size, align)?;
         }
     }
 
-    /// Allocates a new chunk and updates `current_chunk` pointer.
+    /// Allocates a new chunk with room for `min_size` bytes aligned to
+    /// `align`, and updates `current_chunk` pointer.
     #[cold]
-    fn allocate_new_chunk(&self, min_size: usize) {
+    fn allocate_new_chunk(
+        &self,
+        min_size: usize,
+        align: usize,
+    ) -> Result<(), ArenaAllocError> {
+        // Chunks are only aligned to `DEFAULT_ALIGNMENT`, so leave room to
+        // align the allocation within the chunk
+        let min_size = min_size.checked_add(align).ok_or(ArenaAllocError)?;
         let new_size = (self.chunk_size * 2).min(MAX_CHUNK_SIZE).max(min_size);
 
         // SAFETY: We immediately convert the &'static mut Chunk to a raw pointer
         // and never use the reference again. This prevents Stacked Borrows violations.
-        let new_chunk = Chunk::new(new_size)
-            .expect("Failed to allocate new chunk");
+        let new_chunk = Chunk::new(new_size)?;
 
         let new_chunk_ptr: *mut Chunk = new_chunk;
         let new_chunk_nonnull = NonNull::new(new_chunk_ptr)
             .expect("Chunk pointer should not be null");
 
-        // Add to chunks list
-        let mut chunks = self.chunks.lock().unwrap();
+        // Add to chunks list; a panic elsewhere cannot leave a `Vec` that is
+        // only pushed to inconsistent, so a poisoned lock is recovered
+        let mut chunks = self
+            .chunks
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
         chunks.push(new_chunk_nonnull);
 
         // Update current chunk pointer
         self.current_chunk.store(new_chunk_ptr, Ordering::Release);
+        Ok(())
     }
 
     /// Returns allocation statistics for this arena.
     #[must_use]
     pub fn stats(&self) -> ArenaStats {
-        let chunks = self.chunks.lock().unwrap();
+        let chunks = self
+            .chunks
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
         let chunk_count = chunks.len();
         let total_capacity = chunks.iter().map(|c| {
             // SAFETY: c is a valid pointer to a Chunk
@@ -443,12 +480,41 @@ impl GlobalArena {
     /// The caller is responsible for properly managing the flexible array.
     /// The returned pointer must not be used to create references that extend
     /// beyond the original value's size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the allocation fails. See
+    /// [`GlobalArena::try_alloc_string`] for a variant that returns an
+    /// error instead.
     #[inline(always)]
     #[allow(clippy::mut_from_ref)] // Uses interior mutability via UnsafeCell
     pub fn alloc_string<T>(&self, value: T, capacity: usize) -> *mut T {
+        self.try_alloc_string(value, capacity)
+            .expect("Failed to allocate new chunk")
+    }
+
+    /// Allocates a value with flexible array member in the global arena,
+    /// or returns an error if it cannot be allocated.
+    ///
+    /// See [`GlobalArena::alloc_string`] for how the returned pointer may
+    /// be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArenaAllocError`] if `capacity` is too large to allocate,
+    /// or the system allocator cannot provide a new chunk. The value is
+    /// dropped.
+    pub fn try_alloc_string<T>(
+        &self,
+        value: T,
+        capacity: usize,
+    ) -> Result<*mut T, ArenaAllocError> {
         let size = std::mem::size_of::<T>();
         let align = std::mem::align_of::<T>().max(self.alignment);
-        let total_size = size + capacity;
+        let total_size = size
+            .checked_add(capacity)
+            .filter(|&total| total <= isize::MAX as usize - align)
+            .ok_or(ArenaAllocError)?;
 
         loop {
             // Try to allocate from current chunk
@@ -463,13 +529,13 @@ impl GlobalArena {
                     unsafe {
                         std::ptr::write(ptr.as_ptr().cast::<T>(), value);
                         self.total_allocated.fetch_add(total_size, Ordering::Relaxed);
-                        return ptr.as_ptr().cast::<T>();
+                        return Ok(ptr.as_ptr().cast::<T>());
                     }
                 }
             }
 
             // Need to allocate a new chunk
-            self.allocate_new_chunk(total_size);
+            self.allocate_new_chunk(total_size, align)?;
         }
     }
 }
@@ -529,9 +595,8 @@ impl LocalChunk {
             return Err(ArenaAllocError);
         }
 
-        let layout = unsafe {
-            Layout::from_size_align_unchecked(size, DEFAULT_ALIGNMENT)
-        };
+        let layout = Layout::from_size_align(size, DEFAULT_ALIGNMENT)
+            .map_err(|_| ArenaAllocError)?;
 
         let start = unsafe { alloc::alloc(layout) };
         let start = NonNull::new(start).ok_or(ArenaAllocError)?;
@@ -900,6 +965,22 @@ mod tests {
         assert!(stats.total_capacity >= 8192);
     }
 
+    #[test]
+    fn test_global_arena_try_alloc() {
+        let arena = GlobalArena::new(8192);
+        assert_eq!(*arena.try_alloc(42u32).unwrap(), 42);
+
+        // A flexible array too large to exist is an error, not a panic
+        for capacity in [usize::MAX, 1 << 62] {
+            let result = arena.try_alloc_string(0u8, capacity);
+            assert_eq!(result, Err(ArenaAllocError));
+        }
+
+        // Values larger than a chunk get a chunk of their own
+        let ptr = arena.try_alloc_string(7u64, 1 << 20).unwrap();
+        assert_eq!(unsafe { *ptr }, 7);
+    }
+
     #[test]
     fn test_global_arena_singleton() {
         let arena1 = global_arena();
@@ -0,0 +1,438 @@
+//! JSON.
+//!
+//! [`JsonEncoder`] writes an [`Encoded`] tree as JSON text, compact or
+//! indented, and [`JsonDecoder`] reads one back. Records become objects
+//! with their fields in order and `nil` becomes `null`. Numbers written
+//! without a fraction or exponent read back as `Int`s, others as `Float`s;
+//! JSON has no infinite or NaN floats, so they cannot be written.
+
+use super::{CodingError, Decodable, Decoder, Encodable, Encoded, Encoder};
+use crate::core::OxString;
+use std::fmt::Write;
+
+/// Deepest nesting of arrays and objects the decoder reads, so that
+/// hostile input cannot overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// Writes values as JSON text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonEncoder {
+    /// Put each element and field on its own line, indented by two spaces
+    pub pretty: bool,
+}
+
+impl JsonEncoder {
+    /// An encoder writing indented JSON.
+    #[must_use]
+    pub const fn pretty() -> Self {
+        Self { pretty: true }
+    }
+
+    fn write_value(&self, out: &mut String, value: &Encoded, depth: usize) -> Result<(), CodingError> {
+        match value {
+            Encoded::Nil => out.push_str("null"),
+            Encoded::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Encoded::Int(value) => out.push_str(&value.to_string()),
+            Encoded::Float(value) if !value.is_finite() => {
+                return Err(CodingError::Unrepresentable(format!("{value} in JSON")));
+            }
+            Encoded::Float(value) => {
+                let _ = write!(out, "{value:?}");
+            }
+            Encoded::String(value) => write_string(out, value.as_str()),
+            Encoded::Array(elements) => {
+                out.push('[');
+                for (index, element) in elements.iter().enumerate() {
+                    self.separate(out, index, depth + 1);
+                    self.write_value(out, element, depth + 1)?;
+                }
+                self.close(out, elements.is_empty(), depth, ']');
+            }
+            Encoded::Record(fields) => {
+                out.push('{');
+                for (index, (name, field)) in fields.iter().enumerate() {
+                    self.separate(out, index, depth + 1);
+                    write_string(out, name.as_str());
+                    out.push_str(if self.pretty { ": " } else { ":" });
+                    self.write_value(out, field, depth + 1)?;
+                }
+                self.close(out, fields.is_empty(), depth, '}');
+            }
+        }
+        Ok(())
+    }
+
+    fn separate(&self, out: &mut String, index: usize, depth: usize) {
+        if index > 0 {
+            out.push(',');
+        }
+        if self.pretty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        }
+    }
+
+    fn close(&self, out: &mut String, empty: bool, depth: usize, bracket: char) {
+        if self.pretty && !empty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth));
+        }
+        out.push(bracket);
+    }
+}
+
+impl Encoder for JsonEncoder {
+    type Output = String;
+
+    fn write(&self, encoded: &Encoded) -> Result<String, CodingError> {
+        let mut out = String::new();
+        self.write_value(&mut out, encoded, 0)?;
+        Ok(out)
+    }
+}
+
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if u32::from(ch) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(ch));
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+/// Reads values from JSON text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonDecoder;
+
+impl Decoder for JsonDecoder {
+    type Input = str;
+
+    fn read(&self, input: &str) -> Result<Encoded, CodingError> {
+        let mut parser = Parser { input: input.as_bytes(), pos: 0 };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < parser.input.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+/// A recursive-descent parser over the bytes of the input.
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> CodingError {
+        CodingError::Syntax { message: message.to_string(), offset: self.pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), CodingError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", char::from(byte))))
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Encoded) -> Result<Encoded, CodingError> {
+        if self.input[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Encoded, CodingError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.literal("null", Encoded::Nil),
+            Some(b't') => self.literal("true", Encoded::Bool(true)),
+            Some(b'f') => self.literal("false", Encoded::Bool(false)),
+            Some(b'"') => Ok(Encoded::String(OxString::new(&self.string()?))),
+            Some(b'[' | b'{') if depth >= MAX_DEPTH => Err(self.error("nesting too deep")),
+            Some(b'[') => self.array(depth + 1),
+            Some(b'{') => self.object(depth + 1),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Encoded, CodingError> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Encoded::Array(elements));
+        }
+        loop {
+            elements.push(self.value(depth)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Encoded::Array(elements));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Encoded, CodingError> {
+        self.pos += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Encoded::Record(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a field name"));
+            }
+            let name = OxString::new(&self.string()?);
+            self.skip_whitespace();
+            self.expect(b':')?;
+            fields.push((name, self.value(depth)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Encoded::Record(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CodingError> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let start = self.pos;
+            while !matches!(self.peek(), None | Some(b'"' | b'\\' | 0..0x20)) {
+                self.pos += 1;
+            }
+            // The input is a `str` and runs stop at ASCII bytes, so each run
+            // is whole characters
+            text.push_str(std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default());
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    text.push(self.escape()?);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, CodingError> {
+        let byte = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += 1;
+        let ch = match byte {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let high = self.hex4()?;
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    // A high surrogate must be followed by an escaped low one
+                    if !self.input[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                return char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"));
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("invalid escape"));
+            }
+        };
+        Ok(ch)
+    }
+
+    fn hex4(&mut self) -> Result<u32, CodingError> {
+        let digits = self.input.get(self.pos..self.pos + 4).ok_or_else(|| self.error("invalid unicode escape"))?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Encoded, CodingError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while parser.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        let mut integer = true;
+        if self.peek() == Some(b'.') {
+            integer = false;
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            integer = false;
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        // Only ASCII digits, signs, points and exponents were consumed
+        let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default();
+        if integer && let Ok(value) = text.parse() {
+            return Ok(Encoded::Int(value));
+        }
+        // Integers beyond `Int` read as the nearest `Float`
+        text.parse()
+            .map(Encoded::Float)
+            .map_err(|_| CodingError::Syntax { message: "invalid number".into(), offset: start })
+    }
+}
+
+/// Encode `value` as compact JSON text.
+///
+/// # Errors
+///
+/// Returns an error if the value cannot be encoded or JSON cannot
+/// represent it.
+pub fn to_json<T: Encodable + ?Sized>(value: &T) -> Result<String, CodingError> {
+    JsonEncoder::default().encode(value)
+}
+
+/// Decode a value from JSON text.
+///
+/// # Errors
+///
+/// Returns an error if the text is not JSON or does not have the shape of
+/// the type.
+pub fn from_json<T: Decodable>(text: &str) -> Result<T, CodingError> {
+    JsonDecoder.decode(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: Vec<(&str, Encoded)>) -> Encoded {
+        Encoded::Record(fields.into_iter().map(|(name, value)| (OxString::new(name), value)).collect())
+    }
+
+    #[test]
+    fn test_write_json() {
+        let value = record(vec![
+            ("name", Encoded::String(OxString::new("a \"quoted\"\nline"))),
+            ("scores", Encoded::Array(vec![Encoded::Int(1), Encoded::Float(2.5), Encoded::Float(3.0)])),
+            ("parent", Encoded::Nil),
+            ("empty", Encoded::Array(Vec::new())),
+        ]);
+        assert_eq!(
+            to_json(&value).unwrap(),
+            r#"{"name":"a \"quoted\"\nline","scores":[1,2.5,3.0],"parent":null,"empty":[]}"#
+        );
+        let nested = record(vec![("ok", Encoded::Bool(true)), ("list", Encoded::Array(vec![Encoded::Int(1)]))]);
+        assert_eq!(
+            JsonEncoder::pretty().write(&nested).unwrap(),
+            "{\n  \"ok\": true,\n  \"list\": [\n    1\n  ]\n}"
+        );
+        assert_eq!(to_json(&f64::NAN), Err(CodingError::Unrepresentable("NaN in JSON".to_string())));
+    }
+
+    #[test]
+    fn test_read_json() {
+        let text = r#" {"a": [1, -2.5e1, true, null], "b": "\u00e9\ud83d\ude00\t", "c": {}} "#;
+        let value: Encoded = from_json(text).unwrap();
+        assert_eq!(
+            value,
+            record(vec![
+                ("a", Encoded::Array(vec![Encoded::Int(1), Encoded::Float(-25.0), Encoded::Bool(true), Encoded::Nil])),
+                ("b", Encoded::String(OxString::new("é😀\t"))),
+                ("c", Encoded::Record(Vec::new())),
+            ])
+        );
+        assert_eq!(from_json::<Encoded>("18446744073709551616").unwrap(), Encoded::Float(18_446_744_073_709_551_616.0));
+
+        // Whatever is written reads back the same
+        assert_eq!(from_json::<Encoded>(&JsonEncoder::pretty().write(&value).unwrap()).unwrap(), value);
+        assert_eq!(from_json::<Vec<Option<i64>>>("[1, null]").unwrap(), vec![Some(1), None]);
+    }
+
+    #[test]
+    fn test_json_syntax_errors() {
+        let offset = |text: &str| match from_json::<Encoded>(text) {
+            Err(CodingError::Syntax { offset, .. }) => offset,
+            other => panic!("expected a syntax error, got {other:?}"),
+        };
+        assert_eq!(offset("[1, 2"), 5);
+        assert_eq!(offset("{\"a\" 1}"), 5);
+        assert_eq!(offset("\"\\x\""), 2);
+        assert_eq!(offset("\"\\ud83d\""), 7);
+        assert_eq!(offset("1 2"), 2);
+        assert_eq!(offset("-"), 1);
+        assert_eq!(offset(&"[".repeat(MAX_DEPTH + 1)), MAX_DEPTH);
+        assert!(from_json::<Encoded>(&"[".repeat(MAX_DEPTH)).is_err());
+    }
+}
@@ -0,0 +1,471 @@
+//! Serialization.
+//!
+//! A value is [`Encodable`] if it can be turned into an [`Encoded`] tree
+//! and [`Decodable`] if it can be rebuilt from one. An [`Encoder`] writes a
+//! tree in some format and a [`Decoder`] reads one back, so a type is
+//! written once against the tree and works with every format; [`json`] is
+//! the first.
+//!
+//! Objects need no hand-written conformance: [`encode_object`] and
+//! [`decode_object`] have the runtime synthesize `Encodable` and
+//! `Decodable` for the object's class from its fields, then read or write
+//! each field through the caller, which holds the values. The record they
+//! produce has the fields in order, inherited ones first.
+
+// The JSON encoder and decoder
+pub mod json;
+
+// Re-exports for convenience
+pub use json::{JsonDecoder, JsonEncoder, from_json, to_json};
+
+use crate::collections::Array;
+use crate::core::OxString;
+use oxidec::runtime::Object;
+use oxidec::runtime::codable::{FieldKind, synthesize_codable};
+use std::fmt;
+
+/// A value in the form every format reads and writes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encoded {
+    /// No value, `nil`
+    Nil,
+    /// A boolean
+    Bool(bool),
+    /// An integer
+    Int(i64),
+    /// A floating-point number
+    Float(f64),
+    /// A string
+    String(OxString),
+    /// An ordered list of values
+    Array(Vec<Encoded>),
+    /// Named values, such as an object's fields, in order
+    Record(Vec<(OxString, Encoded)>),
+}
+
+impl Encoded {
+    /// Name of the kind of value, for error messages.
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Bool(_) => "Bool",
+            Self::Int(_) => "Int",
+            Self::Float(_) => "Float",
+            Self::String(_) => "String",
+            Self::Array(_) => "Array",
+            Self::Record(_) => "record",
+        }
+    }
+
+    /// The value of the field `name`, if this is a record that has one.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&Self> {
+        match self {
+            Self::Record(fields) => fields.iter().find(|(field, _)| field == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Why a value could not be encoded or decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodingError {
+    /// A value of one kind where another was expected
+    TypeMismatch {
+        /// Kind of value expected
+        expected: &'static str,
+        /// Kind of value found
+        found: &'static str,
+    },
+    /// A record lacks a field the type needs
+    MissingField(String),
+    /// The format cannot represent a value, such as JSON an infinite float
+    Unrepresentable(String),
+    /// The input is not well-formed
+    Syntax {
+        /// What is wrong
+        message: String,
+        /// Byte offset in the input where it went wrong
+        offset: usize,
+    },
+    /// The runtime rejected the object's class, such as for a field no
+    /// coder can encode
+    Runtime(oxidec::Error),
+}
+
+impl fmt::Display for CodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TypeMismatch { expected, found } => write!(f, "expected {expected}, found {found}"),
+            Self::MissingField(name) => write!(f, "missing field '{name}'"),
+            Self::Unrepresentable(what) => write!(f, "cannot represent {what}"),
+            Self::Syntax { message, offset } => write!(f, "{message} at byte {offset}"),
+            Self::Runtime(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for CodingError {}
+
+impl From<oxidec::Error> for CodingError {
+    fn from(err: oxidec::Error) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// A value that can be encoded.
+pub trait Encodable {
+    /// The value as an encoded tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value, or a value it holds, cannot be
+    /// encoded.
+    fn encode(&self) -> Result<Encoded, CodingError>;
+}
+
+/// A value that can be decoded.
+pub trait Decodable: Sized {
+    /// Rebuild a value from an encoded tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree does not have the shape of the type.
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError>;
+}
+
+/// A format values are written in.
+pub trait Encoder {
+    /// What the format writes, such as a string of text
+    type Output;
+
+    /// Write an encoded tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format cannot represent the tree.
+    fn write(&self, encoded: &Encoded) -> Result<Self::Output, CodingError>;
+
+    /// Encode `value` and write it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value cannot be encoded or written.
+    fn encode<T: Encodable + ?Sized>(&self, value: &T) -> Result<Self::Output, CodingError> {
+        self.write(&value.encode()?)
+    }
+}
+
+/// A format values are read from.
+pub trait Decoder {
+    /// What the format reads, such as a string of text
+    type Input: ?Sized;
+
+    /// Read an encoded tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is not well-formed.
+    fn read(&self, input: &Self::Input) -> Result<Encoded, CodingError>;
+
+    /// Read a value and decode it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be read or does not have the
+    /// shape of the type.
+    fn decode<T: Decodable>(&self, input: &Self::Input) -> Result<T, CodingError> {
+        T::decode(&self.read(input)?)
+    }
+}
+
+const fn mismatch(expected: &'static str, found: &Encoded) -> CodingError {
+    CodingError::TypeMismatch { expected, found: found.kind() }
+}
+
+impl Encodable for Encoded {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(self.clone())
+    }
+}
+
+impl Decodable for Encoded {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        Ok(encoded.clone())
+    }
+}
+
+impl Encodable for bool {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(Encoded::Bool(*self))
+    }
+}
+
+impl Decodable for bool {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::Bool(value) => Ok(*value),
+            other => Err(mismatch("Bool", other)),
+        }
+    }
+}
+
+impl Encodable for i64 {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(Encoded::Int(*self))
+    }
+}
+
+impl Decodable for i64 {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::Int(value) => Ok(*value),
+            other => Err(mismatch("Int", other)),
+        }
+    }
+}
+
+impl Encodable for f64 {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(Encoded::Float(*self))
+    }
+}
+
+impl Decodable for f64 {
+    #[allow(clippy::cast_precision_loss)]
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::Float(value) => Ok(*value),
+            // Formats such as JSON write whole floats as integers
+            Encoded::Int(value) => Ok(*value as f64),
+            other => Err(mismatch("Float", other)),
+        }
+    }
+}
+
+impl Encodable for OxString {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(Encoded::String(self.clone()))
+    }
+}
+
+impl Decodable for OxString {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::String(value) => Ok(value.clone()),
+            other => Err(mismatch("String", other)),
+        }
+    }
+}
+
+impl Encodable for str {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        Ok(Encoded::String(OxString::new(self)))
+    }
+}
+
+impl Encodable for String {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        self.as_str().encode()
+    }
+}
+
+impl Decodable for String {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        OxString::decode(encoded).map(|value| value.as_str().to_string())
+    }
+}
+
+impl<T: Encodable> Encodable for Option<T> {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        self.as_ref().map_or(Ok(Encoded::Nil), Encodable::encode)
+    }
+}
+
+impl<T: Decodable> Decodable for Option<T> {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::Nil => Ok(None),
+            other => T::decode(other).map(Some),
+        }
+    }
+}
+
+impl<T: Encodable> Encodable for [T] {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        self.iter().map(Encodable::encode).collect::<Result<_, _>>().map(Encoded::Array)
+    }
+}
+
+impl<T: Encodable> Encodable for Vec<T> {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        self.as_slice().encode()
+    }
+}
+
+impl<T: Decodable> Decodable for Vec<T> {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        match encoded {
+            Encoded::Array(elements) => elements.iter().map(T::decode).collect(),
+            other => Err(mismatch("Array", other)),
+        }
+    }
+}
+
+impl<T: Encodable> Encodable for Array<T> {
+    fn encode(&self) -> Result<Encoded, CodingError> {
+        self.as_slice().encode()
+    }
+}
+
+impl<T: Decodable> Decodable for Array<T> {
+    fn decode(encoded: &Encoded) -> Result<Self, CodingError> {
+        Vec::decode(encoded).map(Self::from)
+    }
+}
+
+/// Check that `value` can be stored in a field of kind `kind`.
+fn check_field(kind: FieldKind, value: &Encoded) -> Result<(), CodingError> {
+    let fits = match kind {
+        // Booleans declared as `Int` fields are stored as integers
+        FieldKind::Integer => matches!(value, Encoded::Int(_) | Encoded::Bool(_)),
+        FieldKind::Bool => matches!(value, Encoded::Bool(_)),
+        FieldKind::Float => matches!(value, Encoded::Float(_) | Encoded::Int(_)),
+        FieldKind::String => matches!(value, Encoded::String(_)),
+        // Objects encode in any form, nested objects as records
+        FieldKind::Object => true,
+    };
+    let expected = match kind {
+        FieldKind::Integer => "Int",
+        FieldKind::Bool => "Bool",
+        FieldKind::Float => "Float",
+        FieldKind::String => "String",
+        FieldKind::Object => "object",
+    };
+    if fits { Ok(()) } else { Err(mismatch(expected, value)) }
+}
+
+/// Encode `object` as a record of its fields, synthesizing `Encodable`
+/// for its class.
+///
+/// `field` gives the value of each field by name; a field it has no value
+/// for is encoded as `nil`.
+///
+/// # Errors
+///
+/// Returns an error if the class has a field no coder can encode, or a
+/// value does not have its field's type.
+pub fn encode_object(object: &Object, mut field: impl FnMut(&str) -> Option<Encoded>) -> Result<Encoded, CodingError> {
+    let fields = synthesize_codable(&object.class())?;
+    let mut record = Vec::with_capacity(fields.len());
+    for codable in fields {
+        let name = codable.name.as_str().unwrap_or_default();
+        let value = field(name).unwrap_or(Encoded::Nil);
+        if value != Encoded::Nil {
+            check_field(codable.kind, &value)?;
+        }
+        record.push((OxString::new(name), value));
+    }
+    Ok(Encoded::Record(record))
+}
+
+/// Decode the fields of `object` from the record `encoded`, synthesizing
+/// `Decodable` for its class.
+///
+/// `set` stores each field's value; it is called in field order, inherited
+/// fields first. A field the record gives as `nil` is still passed to it.
+///
+/// # Errors
+///
+/// Returns an error if the class has a field no coder can encode,
+/// `encoded` is not a record, a field is missing or does not have its
+/// field's type, or `set` fails.
+pub fn decode_object(
+    object: &Object,
+    encoded: &Encoded,
+    mut set: impl FnMut(&str, &Encoded) -> Result<(), CodingError>,
+) -> Result<(), CodingError> {
+    let fields = synthesize_codable(&object.class())?;
+    if !matches!(encoded, Encoded::Record(_)) {
+        return Err(mismatch("record", encoded));
+    }
+    for codable in fields {
+        let name = codable.name.as_str().unwrap_or_default();
+        let value = encoded.field(name).ok_or_else(|| CodingError::MissingField(name.to_string()))?;
+        if *value != Encoded::Nil {
+            check_field(codable.kind, value)?;
+        }
+        set(name, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::runtime::{Class, Ivar, RuntimeString, get_global_arena};
+    use std::collections::HashMap;
+
+    fn class_with_fields(name: &str, fields: &[(&str, &str)]) -> Class {
+        let arena = get_global_arena();
+        let class = Class::new_root(name).unwrap();
+        for &(name, types) in fields {
+            let ivar = Ivar::new(RuntimeString::new(name, arena), RuntimeString::new(types, arena));
+            class.add_ivar(ivar).unwrap();
+        }
+        class
+    }
+
+    #[test]
+    fn test_primitive_round_trips() {
+        let values = vec![Some(OxString::new("a")), None];
+        assert_eq!(Vec::<Option<OxString>>::decode(&values.encode().unwrap()).unwrap(), values);
+        assert_eq!(f64::decode(&Encoded::Int(2)).unwrap(), 2.0);
+        assert_eq!(
+            i64::decode(&Encoded::Float(1.5)),
+            Err(CodingError::TypeMismatch { expected: "Int", found: "Float" })
+        );
+
+        let array: Array<i64> = vec![1, 2].into();
+        assert_eq!(Array::<i64>::decode(&array.encode().unwrap()).unwrap(), array);
+    }
+
+    #[test]
+    fn test_object_round_trip() {
+        let class = class_with_fields("CodableStdPoint", &[("x", "q"), ("label", "@"), ("visible", "B")]);
+        let object = Object::new(&class).unwrap();
+
+        let values = HashMap::from([("x", Encoded::Int(3)), ("label", Encoded::String(OxString::new("origin")))]);
+        let encoded = encode_object(&object, |name| values.get(name).cloned()).unwrap();
+        assert_eq!(encoded.field("x"), Some(&Encoded::Int(3)));
+        assert_eq!(encoded.field("visible"), Some(&Encoded::Nil));
+
+        let mut decoded = Vec::new();
+        decode_object(&object, &encoded, |name, value| {
+            decoded.push((name.to_string(), value.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[1], ("label".to_string(), Encoded::String(OxString::new("origin"))));
+
+        let wrong = Encoded::Record(vec![(OxString::new("x"), Encoded::String(OxString::new("3")))]);
+        assert_eq!(
+            decode_object(&object, &wrong, |_, _| Ok(())),
+            Err(CodingError::TypeMismatch { expected: "Int", found: "String" })
+        );
+        let partial = Encoded::Record(vec![(OxString::new("x"), Encoded::Int(1))]);
+        assert_eq!(decode_object(&object, &partial, |_, _| Ok(())), Err(CodingError::MissingField("label".into())));
+    }
+
+    #[test]
+    fn test_uncodable_object() {
+        let class = class_with_fields("CodableStdHandle", &[("handle", "^")]);
+        let object = Object::new(&class).unwrap();
+        assert_eq!(
+            encode_object(&object, |_| None).unwrap_err().to_string(),
+            "Instance variable 'handle' cannot be encoded"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! The `Array` of `OxideX`.
+//!
+//! [`Array<T>`] is an ordered, growable sequence. Elements are any Rust
+//! type, so the interpreter and the bytecode VM store their own value
+//! representations in it unchanged, and closures passed to [`Array::map`],
+//! [`Array::filter`] and [`Array::reduce`] are plain Rust closures that may
+//! call back into either.
+//!
+//! # Growth
+//!
+//! The elements are stored contiguously. An array that is full when an
+//! element is added grows to twice its capacity, starting at
+//! [`MIN_CAPACITY`], so a run of `n` pushes copies each element a constant
+//! number of times on average, and an array never holds more than twice the
+//! space its elements need. Removing elements never shrinks the storage;
+//! [`Array::shrink_to_fit`] gives it back.
+
+use std::fmt;
+use std::ops::{Index, IndexMut, Range};
+
+/// Capacity of an array's first allocation.
+pub const MIN_CAPACITY: usize = 4;
+
+/// An index past the end of an array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexError {
+    /// The index
+    pub index: usize,
+    /// Length of the array
+    pub len: usize,
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "index {} out of bounds for an array of length {}", self.index, self.len)
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+/// An ordered, growable sequence of elements.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Array<T> {
+    elements: Vec<T>,
+}
+
+impl<T> Array<T> {
+    /// Create an empty array, which allocates nothing until it is added to.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { elements: Vec::new() }
+    }
+
+    /// Create an empty array with room for `capacity` elements.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { elements: Vec::with_capacity(capacity) }
+    }
+
+    /// Number of elements.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Whether the array has no elements.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Number of elements the array holds before it grows.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.elements.capacity()
+    }
+
+    /// The elements as a slice.
+    #[must_use]
+    pub fn as_slice(&self) -> &[T] {
+        &self.elements
+    }
+
+    /// The element at `index`, if there is one.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.elements.get(index)
+    }
+
+    /// The first element, if there is one.
+    #[must_use]
+    pub fn first(&self) -> Option<&T> {
+        self.elements.first()
+    }
+
+    /// The last element, if there is one.
+    #[must_use]
+    pub fn last(&self) -> Option<&T> {
+        self.elements.last()
+    }
+
+    /// Make room for one more element, doubling the capacity if the array is
+    /// full.
+    fn grow(&mut self) {
+        if self.elements.len() == self.elements.capacity() {
+            let capacity = (2 * self.elements.capacity()).max(MIN_CAPACITY);
+            self.elements.reserve_exact(capacity - self.elements.len());
+        }
+    }
+
+    /// Add an element at the end.
+    pub fn push(&mut self, element: T) {
+        self.grow();
+        self.elements.push(element);
+    }
+
+    /// Remove and return the last element, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        self.elements.pop()
+    }
+
+    /// Insert an element at `index`, moving the elements after it up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is greater than the length.
+    pub fn insert(&mut self, index: usize, element: T) -> Result<(), IndexError> {
+        if index > self.len() {
+            return Err(IndexError { index, len: self.len() });
+        }
+        self.grow();
+        self.elements.insert(index, element);
+        Ok(())
+    }
+
+    /// Remove and return the element at `index`, moving the elements after
+    /// it down.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no element at `index`.
+    pub fn remove(&mut self, index: usize) -> Result<T, IndexError> {
+        if index >= self.len() {
+            return Err(IndexError { index, len: self.len() });
+        }
+        Ok(self.elements.remove(index))
+    }
+
+    /// Remove every element, keeping the storage.
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+
+    /// Give back storage the elements do not use.
+    pub fn shrink_to_fit(&mut self) {
+        self.elements.shrink_to_fit();
+    }
+
+    /// The elements, in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.elements.iter()
+    }
+
+    /// The elements, in order, mutably.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.elements.iter_mut()
+    }
+
+    /// A new array of `transform` applied to each element.
+    #[must_use]
+    pub fn map<U>(&self, transform: impl FnMut(&T) -> U) -> Array<U> {
+        self.iter().map(transform).collect()
+    }
+
+    /// Combine the elements, in order, into one value starting from
+    /// `initial`.
+    #[must_use]
+    pub fn reduce<A>(&self, initial: A, combine: impl FnMut(A, &T) -> A) -> A {
+        self.iter().fold(initial, combine)
+    }
+
+    /// Whether any element satisfies `predicate`.
+    #[must_use]
+    pub fn any(&self, predicate: impl FnMut(&T) -> bool) -> bool {
+        self.iter().any(predicate)
+    }
+
+    /// Whether every element satisfies `predicate`.
+    #[must_use]
+    pub fn all(&self, predicate: impl FnMut(&T) -> bool) -> bool {
+        self.iter().all(predicate)
+    }
+
+    /// Position of the first element satisfying `predicate`.
+    #[must_use]
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+        self.iter().position(predicate)
+    }
+
+    /// Reverse the order of the elements in place.
+    pub fn reverse(&mut self) {
+        self.elements.reverse();
+    }
+
+    /// Swap the elements at `a` and `b`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.elements.swap(a, b);
+    }
+
+    /// Sort the elements in place by `compare`, keeping equal elements in
+    /// order.
+    pub fn sort_by(&mut self, compare: impl FnMut(&T, &T) -> std::cmp::Ordering) {
+        self.elements.sort_by(compare);
+    }
+}
+
+impl<T: Clone> Array<T> {
+    /// A new array of the elements satisfying `predicate`.
+    #[must_use]
+    pub fn filter(&self, mut predicate: impl FnMut(&T) -> bool) -> Self {
+        self.iter().filter(|element| predicate(element)).cloned().collect()
+    }
+
+    /// A new array of the elements from `range.start` up to `range.end`, or
+    /// `None` if the range is out of bounds.
+    #[must_use]
+    pub fn slice(&self, range: Range<usize>) -> Option<Self> {
+        self.elements.get(range).map(|elements| Self { elements: elements.to_vec() })
+    }
+
+    /// A new array of the elements sorted by `compare`, keeping equal
+    /// elements in order.
+    #[must_use]
+    pub fn sorted_by(&self, compare: impl FnMut(&T, &T) -> std::cmp::Ordering) -> Self {
+        let mut sorted = self.clone();
+        sorted.sort_by(compare);
+        sorted
+    }
+
+    /// A new array of the elements in reverse order.
+    #[must_use]
+    pub fn reversed(&self) -> Self {
+        self.iter().rev().cloned().collect()
+    }
+}
+
+impl<T: Clone + Ord> Array<T> {
+    /// A new array of the elements in ascending order.
+    #[must_use]
+    pub fn sorted(&self) -> Self {
+        self.sorted_by(T::cmp)
+    }
+}
+
+impl<T: PartialEq> Array<T> {
+    /// Whether an element equals `element`.
+    #[must_use]
+    pub fn contains(&self, element: &T) -> bool {
+        self.elements.contains(element)
+    }
+
+    /// Position of the first element equal to `element`.
+    #[must_use]
+    pub fn index_of(&self, element: &T) -> Option<usize> {
+        self.position(|candidate| candidate == element)
+    }
+}
+
+impl<T> Default for Array<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Array<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.elements).finish()
+    }
+}
+
+impl<T> Index<usize> for Array<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.elements[index]
+    }
+}
+
+impl<T> IndexMut<usize> for Array<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.elements[index]
+    }
+}
+
+impl<T> From<Vec<T>> for Array<T> {
+    fn from(elements: Vec<T>) -> Self {
+        Self { elements }
+    }
+}
+
+impl<T> From<Array<T>> for Vec<T> {
+    fn from(array: Array<T>) -> Self {
+        array.elements
+    }
+}
+
+impl<T> FromIterator<T> for Array<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut array = Self::new();
+        array.extend(iter);
+        array
+    }
+}
+
+impl<T> Extend<T> for Array<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for element in iter {
+            self.push(element);
+        }
+    }
+}
+
+impl<T> IntoIterator for Array<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Array<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editing_and_growth() {
+        let mut array = Array::new();
+        assert_eq!(array.capacity(), 0);
+        let mut capacities = Vec::new();
+        for value in 0..9 {
+            array.push(value);
+            capacities.push(array.capacity());
+        }
+        assert_eq!(capacities, [4, 4, 4, 4, 8, 8, 8, 8, 16]);
+
+        assert_eq!(array.pop(), Some(8));
+        array.insert(0, -1).unwrap();
+        assert_eq!(array.remove(1), Ok(0));
+        assert_eq!(array.insert(10, 0), Err(IndexError { index: 10, len: 8 }));
+        assert_eq!(array.remove(8).unwrap_err().to_string(), "index 8 out of bounds for an array of length 8");
+        assert_eq!(Vec::from(array.clone()), [-1, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!((array.first(), array.last(), array.get(8)), (Some(&-1), Some(&7), None));
+        array[0] = 0;
+        assert_eq!(array[0], 0);
+
+        array.clear();
+        assert!(array.is_empty() && array.capacity() == 16);
+        array.shrink_to_fit();
+        assert_eq!(array.capacity(), 0);
+    }
+
+    #[test]
+    fn test_closures_slices_and_sorting() {
+        let array: Array<i64> = vec![5, 3, 8, 1, 9, 2].into();
+        assert_eq!(array.map(|x| x * 10).as_slice(), [50, 30, 80, 10, 90, 20]);
+        assert_eq!(array.filter(|x| x % 2 == 1).as_slice(), [5, 3, 1, 9]);
+        assert_eq!(array.reduce(0, |sum, x| sum + x), 28);
+        assert!(array.any(|&x| x > 8) && !array.all(|&x| x > 1));
+        assert_eq!((array.index_of(&8), array.index_of(&7)), (Some(2), None));
+        assert!(array.contains(&9));
+
+        assert_eq!(array.slice(1..4).unwrap().as_slice(), [3, 8, 1]);
+        assert!(array.slice(4..7).is_none());
+        assert_eq!(array.sorted().as_slice(), [1, 2, 3, 5, 8, 9]);
+        assert_eq!(array.sorted_by(|a, b| b.cmp(a)).as_slice(), [9, 8, 5, 3, 2, 1]);
+        assert_eq!(array.reversed().as_slice(), [2, 9, 1, 8, 3, 5]);
+        // Sorting copies; the original keeps its order
+        assert_eq!(array.first(), Some(&5));
+
+        let words: Array<&str> = ["b", "a"].into_iter().collect();
+        assert_eq!(format!("{:?}", words.sorted()), r#"["a", "b"]"#);
+        let total: i64 = (&array).into_iter().sum();
+        assert_eq!(total, array.into_iter().sum());
+    }
+}
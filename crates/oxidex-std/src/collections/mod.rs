@@ -0,0 +1,9 @@
+//! Collections.
+//!
+//! Containers of `OxideX` values, generic over the element type.
+
+// Ordered, growable sequences
+pub mod array;
+
+// Re-exports for convenience
+pub use array::{Array, IndexError};
@@ -0,0 +1,123 @@
+//! Tasks, channels and timers for bytecode programs.
+//!
+//! [`install`] gives a VM's programs the functions of cooperative
+//! concurrency, built on its task scheduler (see [`Vm::spawn`]):
+//!
+//! - `spawn(f)` runs `f()` as a new task, returning the task
+//! - `join(task)` waits for a task to finish, returning its result or
+//!   raising its error
+//! - `yield_now()` lets the other ready tasks run first
+//! - `sleep(milliseconds)` waits for a time, letting other tasks run
+//! - `channel()` makes a channel, `send(channel, value)` sends a value on
+//!   it without waiting, and `receive(channel)` waits for the next value
+//!
+//! Tasks take turns on one thread, and only switch when one waits, so a
+//! task's code between waits runs without interruption. [`run`] runs a
+//! script as the main task: it returns when the script does, as a program
+//! ends when its main function does, leaving the tasks that are still
+//! waiting.
+
+use oxidex_bytecode::value::Closure;
+use oxidex_bytecode::{Function, Value, Vm, VmError, VmErrorKind};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Define the concurrency functions as globals of `vm`.
+pub fn install(vm: &mut Vm) {
+    vm.define_native("spawn", 1, |vm, args| Ok(Value::Task(vm.spawn(args[0].clone(), Vec::new()))));
+    vm.define_native("join", 1, |vm, args| match &args[0] {
+        Value::Task(task) => vm.join(*task),
+        other => Err(VmErrorKind::TypeMismatch { expected: "a task", found: other.kind() }),
+    });
+    vm.define_native("yield_now", 0, |vm, _| vm.yield_now().map(|()| Value::Nil));
+    vm.define_native("sleep", 1, |vm, args| match &args[0] {
+        Value::Int(milliseconds) => {
+            let duration = Duration::from_millis(u64::try_from(*milliseconds).unwrap_or(0));
+            vm.sleep_until(Instant::now() + duration).map(|()| Value::Nil)
+        }
+        other => Err(VmErrorKind::TypeMismatch { expected: "an integer", found: other.kind() }),
+    });
+    vm.define_native("channel", 0, |vm, _| Ok(Value::Channel(vm.channel())));
+    vm.define_native("send", 2, |vm, args| match &args[0] {
+        Value::Channel(channel) => vm.channel_send(*channel, args[1].clone()).map(|()| Value::Nil),
+        other => Err(VmErrorKind::TypeMismatch { expected: "a channel", found: other.kind() }),
+    });
+    vm.define_native("receive", 1, |vm, args| match &args[0] {
+        Value::Channel(channel) => vm.channel_receive(*channel),
+        other => Err(VmErrorKind::TypeMismatch { expected: "a channel", found: other.kind() }),
+    });
+}
+
+/// Run a script as the main task, with the tasks it spawns, until it
+/// returns.
+///
+/// # Errors
+///
+/// Returns the error the script raised, or a deadlock if it waits for
+/// something no task can send.
+pub fn run(vm: &mut Vm, script: Rc<Function>) -> Result<Value, VmError> {
+    let main = Value::Closure(Rc::new(Closure { function: script, upvalues: Vec::new() }));
+    let main = vm.spawn(main, Vec::new());
+    vm.run_task(main)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_bytecode::{Chunk, Constant, OpCode};
+    use oxidex_syntax::Span;
+
+    /// A script calling global functions with globals as arguments,
+    /// returning the result of the last call.
+    fn script(calls: &[(&str, &[&str])]) -> Rc<Function> {
+        let span = Span::new(0, 0, 1, 1, 1, 1);
+        let mut chunk = Chunk::new();
+        let global = |chunk: &mut Chunk, name: &str| {
+            chunk.write_constant(OpCode::GetGlobal, Constant::String(Rc::from(name)), span).unwrap();
+        };
+        for (i, (function, args)) in calls.iter().enumerate() {
+            if i > 0 {
+                chunk.write_op(OpCode::Pop, span);
+            }
+            global(&mut chunk, function);
+            for arg in *args {
+                global(&mut chunk, arg);
+            }
+            chunk.write_op(OpCode::Call, span);
+            chunk.write(u8::try_from(args.len()).unwrap(), span);
+        }
+        chunk.write_op(OpCode::Return, span);
+        Rc::new(Function { name: "main".to_string(), arity: 0, captures: Vec::new(), chunk })
+    }
+
+    #[test]
+    fn test_tasks_communicate_over_channels() {
+        let mut vm = Vm::new();
+        install(&mut vm);
+        let channel = vm.channel();
+        vm.define_global("ch", Value::Channel(channel));
+        vm.define_global("answer", Value::Int(42));
+        vm.define_native("done", 0, |_, _| Ok(Value::Bool(true)));
+
+        // A task sleeps while main waits for it, then main sends itself a
+        // value
+        let sleep = vm.global("sleep").unwrap();
+        let sleeper = vm.spawn(sleep, vec![Value::Int(5)]);
+        vm.define_global("sleeper", Value::Task(sleeper));
+        let start = Instant::now();
+        let main = script(&[("join", &["sleeper"]), ("send", &["ch", "answer"]), ("receive", &["ch"])]);
+        assert_eq!(run(&mut vm, main).unwrap(), Value::Int(42));
+        assert!(start.elapsed() >= Duration::from_millis(5));
+
+        // Main ends the program even while another task still waits
+        let never = vm.channel();
+        vm.define_native("forever", 0, move |vm, _| vm.channel_receive(never));
+        let main = script(&[("spawn", &["forever"]), ("yield_now", &[]), ("done", &[])]);
+        assert_eq!(run(&mut vm, main).unwrap(), Value::Bool(true));
+
+        // Unless main waits for something no task can send
+        let main = script(&[("receive", &["ch"])]);
+        let err = run(&mut vm, main).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Deadlock { blocked: 2 }), "{err}");
+    }
+}
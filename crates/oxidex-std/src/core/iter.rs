@@ -0,0 +1,391 @@
+//! The iteration protocol.
+//!
+//! A [`Sequence`] makes an iterator with `makeIterator()`; an
+//! [`IteratorProtocol`] yields elements from `next()` until it returns
+//! `nil`, as `for` loops expect. The adapters ([`map`], [`filter`],
+//! [`take`], [`zip`], [`enumerate`]) are lazy: each is a struct wrapping the
+//! iterator before it, and does its work only when asked for its next
+//! element, so a pipeline over a collection allocates no intermediate
+//! arrays and stops reading its source as soon as it is done.
+//! [`generator`] turns a closure into an iterator, for sequences that are
+//! computed rather than stored.
+//!
+//! [`map`]: IteratorProtocol::map
+//! [`filter`]: IteratorProtocol::filter
+//! [`take`]: IteratorProtocol::take
+//! [`zip`]: IteratorProtocol::zip
+//! [`enumerate`]: IteratorProtocol::enumerate
+
+use crate::collections::Array;
+use crate::core::OxString;
+
+/// Something that yields elements one at a time.
+pub trait IteratorProtocol {
+    /// Type of the elements
+    type Element;
+
+    /// The next element, or `None` once the iterator is done.
+    fn next(&mut self) -> Option<Self::Element>;
+
+    /// An iterator of `transform` applied to each element.
+    fn map<U, F: FnMut(Self::Element) -> U>(self, transform: F) -> Map<Self, F>
+    where
+        Self: Sized,
+    {
+        Map { inner: self, transform }
+    }
+
+    /// An iterator of the elements satisfying `predicate`.
+    fn filter<P: FnMut(&Self::Element) -> bool>(self, predicate: P) -> Filter<Self, P>
+    where
+        Self: Sized,
+    {
+        Filter { inner: self, predicate }
+    }
+
+    /// An iterator of at most the first `count` elements.
+    fn take(self, count: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take { inner: self, remaining: count }
+    }
+
+    /// An iterator of pairs of this iterator's elements and `other`'s,
+    /// ending when either ends.
+    fn zip<O: IteratorProtocol>(self, other: O) -> Zip<Self, O>
+    where
+        Self: Sized,
+    {
+        Zip { first: self, second: other }
+    }
+
+    /// An iterator of the elements paired with their positions.
+    fn enumerate(self) -> Enumerate<Self>
+    where
+        Self: Sized,
+    {
+        Enumerate { inner: self, index: 0 }
+    }
+
+    /// Combine the remaining elements, in order, into one value starting
+    /// from `initial`.
+    fn reduce<A>(mut self, initial: A, mut combine: impl FnMut(A, Self::Element) -> A) -> A
+    where
+        Self: Sized,
+    {
+        let mut value = initial;
+        while let Some(element) = self.next() {
+            value = combine(value, element);
+        }
+        value
+    }
+
+    /// Number of remaining elements.
+    fn count(self) -> usize
+    where
+        Self: Sized,
+    {
+        self.reduce(0, |count, _| count + 1)
+    }
+
+    /// The remaining elements, collected into an array.
+    fn to_array(self) -> Array<Self::Element>
+    where
+        Self: Sized,
+    {
+        self.elements().collect()
+    }
+
+    /// The iterator as a Rust iterator, for `for` loops and adapters this
+    /// protocol lacks.
+    fn elements(self) -> Elements<Self>
+    where
+        Self: Sized,
+    {
+        Elements(self)
+    }
+}
+
+/// Something that can be iterated.
+pub trait Sequence {
+    /// Type of the elements
+    type Element;
+    /// Type of the iterator
+    type Iterator: IteratorProtocol<Element = Self::Element>;
+
+    /// An iterator over the sequence, from its start.
+    fn make_iterator(self) -> Self::Iterator;
+}
+
+impl<I: IteratorProtocol> Sequence for I {
+    type Element = I::Element;
+    type Iterator = Self;
+
+    /// An iterator is its own sequence.
+    fn make_iterator(self) -> Self {
+        self
+    }
+}
+
+/// Iterator of an array's elements, in order.
+#[derive(Debug, Clone)]
+pub struct ArrayIterator<'a, T> {
+    elements: std::slice::Iter<'a, T>,
+}
+
+impl<'a, T> IteratorProtocol for ArrayIterator<'a, T> {
+    type Element = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.elements.next()
+    }
+}
+
+impl<T> Array<T> {
+    /// An iterator over the elements, in order.
+    #[must_use]
+    pub fn make_iterator(&self) -> ArrayIterator<'_, T> {
+        ArrayIterator { elements: self.iter() }
+    }
+}
+
+impl<'a, T> Sequence for &'a Array<T> {
+    type Element = &'a T;
+    type Iterator = ArrayIterator<'a, T>;
+
+    fn make_iterator(self) -> ArrayIterator<'a, T> {
+        Array::make_iterator(self)
+    }
+}
+
+/// Iterator of a string's characters, in order.
+#[derive(Debug, Clone)]
+pub struct CharIterator<'a> {
+    chars: std::str::Chars<'a>,
+}
+
+impl IteratorProtocol for CharIterator<'_> {
+    type Element = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+}
+
+impl OxString {
+    /// An iterator over the characters, in order.
+    #[must_use]
+    pub fn make_iterator(&self) -> CharIterator<'_> {
+        CharIterator { chars: self.chars() }
+    }
+}
+
+impl<'a> Sequence for &'a OxString {
+    type Element = char;
+    type Iterator = CharIterator<'a>;
+
+    fn make_iterator(self) -> CharIterator<'a> {
+        OxString::make_iterator(self)
+    }
+}
+
+/// Iterator of the integers of a half-open range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeIterator {
+    next: i64,
+    end: i64,
+}
+
+/// The integers from `start` up to `end`, excluding `end`.
+#[must_use]
+pub fn range(start: i64, end: i64) -> RangeIterator {
+    RangeIterator { next: start, end }
+}
+
+impl IteratorProtocol for RangeIterator {
+    type Element = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        (self.next < self.end).then(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+}
+
+/// An iterator computing each element with a closure, until it returns
+/// `None`.
+#[derive(Debug, Clone)]
+pub struct Generator<F> {
+    step: F,
+}
+
+/// An iterator whose elements are what `step` returns, until it returns
+/// `None`. State the closure captures carries over between elements.
+pub fn generator<T, F: FnMut() -> Option<T>>(step: F) -> Generator<F> {
+    Generator { step }
+}
+
+impl<T, F: FnMut() -> Option<T>> IteratorProtocol for Generator<F> {
+    type Element = T;
+
+    fn next(&mut self) -> Option<T> {
+        (self.step)()
+    }
+}
+
+/// Iterator of [`IteratorProtocol::map`].
+#[derive(Debug, Clone)]
+pub struct Map<I, F> {
+    inner: I,
+    transform: F,
+}
+
+impl<I: IteratorProtocol, U, F: FnMut(I::Element) -> U> IteratorProtocol for Map<I, F> {
+    type Element = U;
+
+    fn next(&mut self) -> Option<U> {
+        self.inner.next().map(&mut self.transform)
+    }
+}
+
+/// Iterator of [`IteratorProtocol::filter`].
+#[derive(Debug, Clone)]
+pub struct Filter<I, P> {
+    inner: I,
+    predicate: P,
+}
+
+impl<I: IteratorProtocol, P: FnMut(&I::Element) -> bool> IteratorProtocol for Filter<I, P> {
+    type Element = I::Element;
+
+    fn next(&mut self) -> Option<I::Element> {
+        while let Some(element) = self.inner.next() {
+            if (self.predicate)(&element) {
+                return Some(element);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator of [`IteratorProtocol::take`].
+#[derive(Debug, Clone)]
+pub struct Take<I> {
+    inner: I,
+    remaining: usize,
+}
+
+impl<I: IteratorProtocol> IteratorProtocol for Take<I> {
+    type Element = I::Element;
+
+    fn next(&mut self) -> Option<I::Element> {
+        // The source is not read once the count is reached
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.inner.next()
+    }
+}
+
+/// Iterator of [`IteratorProtocol::zip`].
+#[derive(Debug, Clone)]
+pub struct Zip<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: IteratorProtocol, B: IteratorProtocol> IteratorProtocol for Zip<A, B> {
+    type Element = (A::Element, B::Element);
+
+    fn next(&mut self) -> Option<Self::Element> {
+        let first = self.first.next()?;
+        Some((first, self.second.next()?))
+    }
+}
+
+/// Iterator of [`IteratorProtocol::enumerate`].
+#[derive(Debug, Clone)]
+pub struct Enumerate<I> {
+    inner: I,
+    index: usize,
+}
+
+impl<I: IteratorProtocol> IteratorProtocol for Enumerate<I> {
+    type Element = (usize, I::Element);
+
+    fn next(&mut self) -> Option<Self::Element> {
+        let element = self.inner.next()?;
+        self.index += 1;
+        Some((self.index - 1, element))
+    }
+}
+
+/// A protocol iterator as a Rust iterator, from
+/// [`IteratorProtocol::elements`].
+#[derive(Debug, Clone)]
+pub struct Elements<I>(I);
+
+impl<I: IteratorProtocol> Iterator for Elements<I> {
+    type Item = I::Element;
+
+    fn next(&mut self) -> Option<I::Element> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_adapters_are_lazy() {
+        let array: Array<i64> = (1..=1000).collect();
+        let visited = Cell::new(0);
+        let squares = array
+            .make_iterator()
+            .map(|x| {
+                visited.set(visited.get() + 1);
+                x * x
+            })
+            .filter(|x| x % 2 == 1)
+            .take(3);
+        // Nothing runs until an element is asked for
+        assert_eq!(visited.get(), 0);
+        assert_eq!(squares.to_array().as_slice(), [1, 9, 25]);
+        // Only as much of the array is read as the first three odd squares
+        // need
+        assert_eq!(visited.get(), 5);
+    }
+
+    #[test]
+    fn test_sequences_zip_enumerate_and_generate() {
+        let word = OxString::new("héllo");
+        let pairs = word.make_iterator().zip(range(10, 13)).enumerate().to_array();
+        assert_eq!(pairs.as_slice(), [(0, ('h', 10)), (1, ('é', 11)), (2, ('l', 12))]);
+        assert_eq!(range(0, 5).reduce(0, |sum, x| sum + x), 10);
+        assert_eq!(range(3, 3).count(), 0);
+
+        let (mut a, mut b) = (0_u64, 1_u64);
+        let fibonacci = generator(move || {
+            let next = a;
+            (a, b) = (b, a + b);
+            Some(next)
+        });
+        let mut evens = Vec::new();
+        for value in fibonacci.filter(|x| x % 2 == 0).take(4).elements() {
+            evens.push(value);
+        }
+        assert_eq!(evens, [0, 2, 8, 34]);
+
+        // Collections and iterators alike are sequences
+        fn count_of<S: Sequence>(sequence: S) -> usize {
+            sequence.make_iterator().count()
+        }
+        let array: Array<i64> = vec![1, 2, 3].into();
+        assert_eq!((count_of(&array), count_of(&word), count_of(range(0, 2))), (3, 5, 2));
+    }
+}
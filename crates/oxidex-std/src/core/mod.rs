@@ -0,0 +1,13 @@
+//! Core types.
+//!
+//! The types every `OxideX` program uses, implemented natively over the
+//! runtime's own representations.
+
+// The iteration protocol and its lazy adapters
+pub mod iter;
+// Strings
+pub mod string;
+
+// Re-exports for convenience
+pub use iter::{IteratorProtocol, Sequence, generator, range};
+pub use string::{Interpolate, OxString, ParseNumberError};
@@ -0,0 +1,464 @@
+//! The `String` of `OxideX`.
+//!
+//! [`OxString`] is an immutable UTF-8 string stored as a runtime
+//! [`RuntimeString`], so short strings stay inline and long ones live in the
+//! runtime's arena. Every operation that changes text returns a new string.
+//!
+//! Positions and lengths count characters, as `len` does for `OxideX`
+//! programs; [`OxString::byte_len`] and [`OxString::bytes`] give the UTF-8
+//! view. Values are written into strings through [`Interpolate`], the hook
+//! string interpolation (`"\(value)"`) uses, which formats them the way the
+//! interpreter prints them.
+
+use oxidec::runtime::{RuntimeString, get_global_arena};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Add;
+use std::str::FromStr;
+
+/// An immutable UTF-8 string.
+#[derive(Clone)]
+pub struct OxString {
+    inner: RuntimeString,
+}
+
+/// Why text could not be read as a number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNumberError {
+    /// The text, trimmed
+    pub text: String,
+    /// What it should have been: "an integer" or "a float"
+    pub expected: &'static str,
+}
+
+impl fmt::Display for ParseNumberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not {}", self.text, self.expected)
+    }
+}
+
+impl std::error::Error for ParseNumberError {}
+
+impl OxString {
+    /// Create a string holding `text`.
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        Self { inner: RuntimeString::new(text, get_global_arena()) }
+    }
+
+    /// Wrap a runtime string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the runtime string is not valid UTF-8.
+    pub fn from_runtime(inner: RuntimeString) -> oxidec::Result<Self> {
+        inner.as_str()?;
+        Ok(Self { inner })
+    }
+
+    /// The runtime string holding the text.
+    #[must_use]
+    pub fn as_runtime(&self) -> &RuntimeString {
+        &self.inner
+    }
+
+    /// The text.
+    ///
+    /// # Panics
+    ///
+    /// Never: strings are checked to be UTF-8 when created.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(self.inner.as_bytes()).expect("strings are UTF-8")
+    }
+
+    /// Number of characters.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.as_str().chars().count()
+    }
+
+    /// Whether the string has no characters.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Number of bytes of the UTF-8 encoding.
+    #[must_use]
+    pub fn byte_len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// The characters, in order.
+    pub fn chars(&self) -> std::str::Chars<'_> {
+        self.as_str().chars()
+    }
+
+    /// The bytes of the UTF-8 encoding, in order.
+    pub fn bytes(&self) -> std::iter::Copied<std::slice::Iter<'_, u8>> {
+        self.inner.as_bytes().iter().copied()
+    }
+
+    /// The character at `index`, if there is one.
+    #[must_use]
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        self.chars().nth(index)
+    }
+
+    /// Whether `pattern` occurs in the string.
+    #[must_use]
+    pub fn contains(&self, pattern: &str) -> bool {
+        self.as_str().contains(pattern)
+    }
+
+    /// Whether the string starts with `prefix`.
+    #[must_use]
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        self.as_str().starts_with(prefix)
+    }
+
+    /// Whether the string ends with `suffix`.
+    #[must_use]
+    pub fn ends_with(&self, suffix: &str) -> bool {
+        self.as_str().ends_with(suffix)
+    }
+
+    /// Position of the first occurrence of `pattern`, in characters.
+    #[must_use]
+    pub fn find(&self, pattern: &str) -> Option<usize> {
+        let byte = self.as_str().find(pattern)?;
+        Some(self.as_str()[..byte].chars().count())
+    }
+
+    /// The characters from `start` up to `end`, or `None` if the range is
+    /// out of bounds.
+    #[must_use]
+    pub fn substring(&self, start: usize, end: usize) -> Option<Self> {
+        if start > end {
+            return None;
+        }
+        let text = self.as_str();
+        let offset = |index: usize| text.char_indices().map(|(byte, _)| byte).chain([text.len()]).nth(index);
+        Some(Self::new(&text[offset(start)?..offset(end)?]))
+    }
+
+    /// The pieces between occurrences of `separator`. An empty separator
+    /// splits the string into its characters.
+    #[must_use]
+    pub fn split(&self, separator: &str) -> Vec<Self> {
+        if separator.is_empty() {
+            return self.chars().map(|c| Self::new(c.encode_utf8(&mut [0; 4]))).collect();
+        }
+        self.as_str().split(separator).map(Self::new).collect()
+    }
+
+    /// The words of the string, separated by runs of whitespace.
+    #[must_use]
+    pub fn words(&self) -> Vec<Self> {
+        self.as_str().split_whitespace().map(Self::new).collect()
+    }
+
+    /// The lines of the string, without their line endings.
+    #[must_use]
+    pub fn lines(&self) -> Vec<Self> {
+        self.as_str().lines().map(Self::new).collect()
+    }
+
+    /// The string without leading and trailing whitespace.
+    #[must_use]
+    pub fn trim(&self) -> Self {
+        Self::new(self.as_str().trim())
+    }
+
+    /// The string without leading whitespace.
+    #[must_use]
+    pub fn trim_start(&self) -> Self {
+        Self::new(self.as_str().trim_start())
+    }
+
+    /// The string without trailing whitespace.
+    #[must_use]
+    pub fn trim_end(&self) -> Self {
+        Self::new(self.as_str().trim_end())
+    }
+
+    /// The string with every occurrence of `from` replaced by `to`.
+    #[must_use]
+    pub fn replace(&self, from: &str, to: &str) -> Self {
+        Self::new(&self.as_str().replace(from, to))
+    }
+
+    /// The string in upper case.
+    #[must_use]
+    pub fn uppercased(&self) -> Self {
+        Self::new(&self.as_str().to_uppercase())
+    }
+
+    /// The string in lower case.
+    #[must_use]
+    pub fn lowercased(&self) -> Self {
+        Self::new(&self.as_str().to_lowercase())
+    }
+
+    /// The string with its first character in upper case.
+    #[must_use]
+    pub fn capitalized(&self) -> Self {
+        let mut chars = self.chars();
+        match chars.next() {
+            Some(first) => Self::new(&first.to_uppercase().chain(chars).collect::<String>()),
+            None => self.clone(),
+        }
+    }
+
+    /// The string repeated `count` times.
+    #[must_use]
+    pub fn repeated(&self, count: usize) -> Self {
+        Self::new(&self.as_str().repeat(count))
+    }
+
+    /// The characters in reverse order.
+    #[must_use]
+    pub fn reversed(&self) -> Self {
+        Self::new(&self.chars().rev().collect::<String>())
+    }
+
+    /// `parts` joined with the string between them.
+    #[must_use]
+    pub fn join(&self, parts: &[Self]) -> Self {
+        let parts: Vec<&str> = parts.iter().map(Self::as_str).collect();
+        Self::new(&parts.join(self.as_str()))
+    }
+
+    /// Read the string, ignoring surrounding whitespace, as a decimal
+    /// integer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it is not an integer that fits in 64 bits.
+    pub fn to_int(&self) -> Result<i64, ParseNumberError> {
+        let text = self.as_str().trim();
+        text.parse().map_err(|_| ParseNumberError { text: text.to_string(), expected: "an integer" })
+    }
+
+    /// Read the string, ignoring surrounding whitespace, as a float.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it is not a float.
+    pub fn to_float(&self) -> Result<f64, ParseNumberError> {
+        let text = self.as_str().trim();
+        text.parse().map_err(|_| ParseNumberError { text: text.to_string(), expected: "a float" })
+    }
+
+    /// Join interpolated parts into a string, as `"\(a) and \(b)"` does.
+    #[must_use]
+    pub fn interpolate(parts: &[&dyn Interpolate]) -> Self {
+        let mut text = String::new();
+        for part in parts {
+            part.interpolate(&mut text);
+        }
+        Self::new(&text)
+    }
+}
+
+/// A value that can be written into a string by interpolation.
+pub trait Interpolate {
+    /// Append the value's text to `out`.
+    fn interpolate(&self, out: &mut String);
+}
+
+impl Interpolate for str {
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(self);
+    }
+}
+
+impl Interpolate for &str {
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(self);
+    }
+}
+
+impl Interpolate for OxString {
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(self.as_str());
+    }
+}
+
+impl Interpolate for i64 {
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(&self.to_string());
+    }
+}
+
+impl Interpolate for f64 {
+    /// Floats keep a fractional part, so `1.0` stays distinct from `1`.
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(&format!("{self:?}"));
+    }
+}
+
+impl Interpolate for bool {
+    fn interpolate(&self, out: &mut String) {
+        out.push_str(if *self { "true" } else { "false" });
+    }
+}
+
+impl Interpolate for char {
+    fn interpolate(&self, out: &mut String) {
+        out.push(*self);
+    }
+}
+
+impl Default for OxString {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl fmt::Display for OxString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for OxString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl PartialEq for OxString {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for OxString {}
+
+impl PartialEq<str> for OxString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for OxString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for OxString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OxString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for OxString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl Add<&OxString> for &OxString {
+    type Output = OxString;
+
+    fn add(self, rhs: &OxString) -> OxString {
+        OxString::new(&[self.as_str(), rhs.as_str()].concat())
+    }
+}
+
+impl From<&str> for OxString {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<i64> for OxString {
+    fn from(value: i64) -> Self {
+        Self::interpolate(&[&value])
+    }
+}
+
+impl From<f64> for OxString {
+    fn from(value: f64) -> Self {
+        Self::interpolate(&[&value])
+    }
+}
+
+impl From<bool> for OxString {
+    fn from(value: bool) -> Self {
+        Self::interpolate(&[&value])
+    }
+}
+
+impl FromStr for OxString {
+    type Err = std::convert::Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_searching_and_slicing_count_characters() {
+        let text = OxString::new("héllo wörld, héllo");
+        assert_eq!((text.len(), text.byte_len()), (18, 21));
+        assert!(text.contains("wörld") && text.starts_with("hé") && text.ends_with("llo"));
+        assert_eq!(text.find("wörld"), Some(6));
+        assert_eq!(text.find("xyz"), None);
+        assert_eq!(text.char_at(1), Some('é'));
+        assert_eq!(text.substring(6, 11).unwrap(), "wörld");
+        assert_eq!(text.substring(13, 18).unwrap(), "héllo");
+        assert!(text.substring(5, 19).is_none() && text.substring(3, 2).is_none());
+        assert_eq!(text.bytes().next(), Some(b'h'));
+        assert_eq!(text.chars().filter(|&c| c == 'é').count(), 2);
+    }
+
+    #[test]
+    fn test_transformations_return_new_strings() {
+        let text = OxString::new("  a long enough string, past the inline size  ");
+        assert!(!text.as_runtime().is_inline());
+        assert_eq!(text.trim(), "a long enough string, past the inline size");
+        assert_eq!(text.trim_start().len(), text.len() - 2);
+        assert_eq!(text.trim().split(", "), ["a long enough string", "past the inline size"]);
+        assert_eq!(OxString::new("ab").split(""), ["a", "b"]);
+        assert_eq!(text.words().len(), 8);
+        assert_eq!(OxString::new("one\ntwo\r\n").lines(), ["one", "two"]);
+        assert_eq!(text.trim().replace("inline", "SSO").uppercased(), "A LONG ENOUGH STRING, PAST THE SSO SIZE");
+        assert_eq!(OxString::new("ÉCOLE").lowercased(), "école");
+        assert_eq!(OxString::new("élan").capitalized(), "Élan");
+        assert_eq!(OxString::new("ab").repeated(3).reversed(), "bababa");
+        assert_eq!(OxString::new(", ").join(&[OxString::new("x"), OxString::new("y")]), "x, y");
+        assert_eq!(&OxString::new("foo") + &OxString::new("bar"), OxString::new("foobar"));
+        assert!(OxString::new("apple") < OxString::new("banana"));
+    }
+
+    #[test]
+    fn test_numbers_and_interpolation() {
+        assert_eq!(OxString::new(" 42 ").to_int(), Ok(42));
+        assert_eq!(OxString::new("-1.5e3").to_float(), Ok(-1500.0));
+        let err = OxString::new("4x2").to_int().unwrap_err();
+        assert_eq!(err.to_string(), "'4x2' is not an integer");
+        assert!(OxString::new("99999999999999999999").to_int().is_err());
+
+        assert_eq!(OxString::from(7), "7");
+        assert_eq!(OxString::from(2.0), "2.0");
+        assert_eq!(OxString::from(true), "true");
+        let name = OxString::new("Ada");
+        let greeting = OxString::interpolate(&[&"Hello, ", &name, &"! You are ", &36_i64, &" and ", &'#', &1.5]);
+        assert_eq!(greeting, "Hello, Ada! You are 36 and #1.5");
+    }
+}
@@ -0,0 +1,125 @@
+//! Standard input and output.
+//!
+//! [`print`], [`println`] and [`read_line`] use the process's standard
+//! streams. A [`Console`] does the same over any reader and writer, which
+//! is how an embedder captures a script's output.
+
+use super::IoResult;
+use crate::core::OxString;
+use std::io::{BufRead, Write};
+
+/// A text console over a reader and a writer.
+#[derive(Debug)]
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+}
+
+impl Console<std::io::StdinLock<'static>, std::io::Stdout> {
+    /// The process's standard input and output.
+    #[must_use]
+    pub fn stdio() -> Self {
+        Self::new(std::io::stdin().lock(), std::io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    /// A console reading from `input` and writing to `output`.
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Write text without a newline, flushing it so a prompt shows before
+    /// input is read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output cannot be written.
+    pub fn print(&mut self, text: &str) -> IoResult<()> {
+        self.output.write_all(text.as_bytes())?;
+        Ok(self.output.flush()?)
+    }
+
+    /// Write text and a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output cannot be written.
+    pub fn println(&mut self, text: &str) -> IoResult<()> {
+        writeln!(self.output, "{text}")?;
+        Ok(self.output.flush()?)
+    }
+
+    /// Read a line without its line ending, or `None` at the end of the
+    /// input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input cannot be read or is not UTF-8.
+    pub fn read_line(&mut self) -> IoResult<Option<OxString>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let end = line.trim_end_matches(['\n', '\r']).len();
+        Ok(Some(OxString::new(&line[..end])))
+    }
+
+    /// The reader and writer.
+    pub fn into_parts(self) -> (R, W) {
+        (self.input, self.output)
+    }
+}
+
+/// Write text to standard output without a newline.
+///
+/// # Errors
+///
+/// Returns an error if standard output cannot be written.
+pub fn print(text: &str) -> IoResult<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(text.as_bytes())?;
+    Ok(stdout.flush()?)
+}
+
+/// Write text and a newline to standard output.
+///
+/// # Errors
+///
+/// Returns an error if standard output cannot be written.
+pub fn println(text: &str) -> IoResult<()> {
+    Ok(writeln!(std::io::stdout(), "{text}")?)
+}
+
+/// Read a line from standard input, or `None` at its end.
+///
+/// # Errors
+///
+/// Returns an error if standard input cannot be read or is not UTF-8.
+pub fn read_line() -> IoResult<Option<OxString>> {
+    Console::stdio().read_line()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::IoErrorKind;
+
+    #[test]
+    fn test_console_over_buffers() {
+        let input: &[u8] = b"Ada\r\nLovelace\nlast";
+        let mut console = Console::new(input, Vec::new());
+        console.print("name? ").unwrap();
+        assert_eq!(console.read_line().unwrap().unwrap(), "Ada");
+        console.println("hello").unwrap();
+        assert_eq!(console.read_line().unwrap().unwrap(), "Lovelace");
+        assert_eq!(console.read_line().unwrap().unwrap(), "last");
+        assert_eq!(console.read_line().unwrap(), None);
+        let (_, output) = console.into_parts();
+        assert_eq!(output, b"name? hello\n");
+
+        let invalid: &[u8] = b"\xff\n";
+        let err = Console::new(invalid, Vec::new()).read_line().unwrap_err();
+        assert_eq!(err.kind, IoErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,229 @@
+//! Files.
+//!
+//! A [`File`] is opened for reading, created for writing, or opened for
+//! appending, and reads or writes whole strings. For line-at-a-time access
+//! it becomes a [`BufferedReader`] or [`BufferedWriter`], which batch the
+//! underlying system calls; a buffered writer writes what it holds when it
+//! is flushed or dropped. Every error carries the file's path.
+
+use super::{IoError, IoResult};
+use crate::core::OxString;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+
+/// An open file.
+#[derive(Debug)]
+pub struct File {
+    inner: std::fs::File,
+    path: PathBuf,
+}
+
+impl File {
+    fn with_options(path: impl AsRef<std::path::Path>, options: &std::fs::OpenOptions) -> IoResult<Self> {
+        let path = path.as_ref();
+        let inner = options.open(path).map_err(|err| IoError::from_io(&err, Some(path)))?;
+        Ok(Self { inner, path: path.to_path_buf() })
+    }
+
+    /// Open a file for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn open(path: impl AsRef<std::path::Path>) -> IoResult<Self> {
+        Self::with_options(path, std::fs::OpenOptions::new().read(true))
+    }
+
+    /// Create a file for writing, emptying it if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created.
+    pub fn create(path: impl AsRef<std::path::Path>) -> IoResult<Self> {
+        Self::with_options(path, std::fs::OpenOptions::new().write(true).create(true).truncate(true))
+    }
+
+    /// Open a file for writing at its end, creating it if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn append(path: impl AsRef<std::path::Path>) -> IoResult<Self> {
+        Self::with_options(path, std::fs::OpenOptions::new().append(true).create(true))
+    }
+
+    /// Read a whole file as text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not UTF-8.
+    pub fn read_all(path: impl AsRef<std::path::Path>) -> IoResult<OxString> {
+        Self::open(path)?.read_to_string()
+    }
+
+    /// Replace a file's contents with `text`, creating it if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write_all(path: impl AsRef<std::path::Path>, text: &str) -> IoResult<()> {
+        Self::create(path)?.write(text)
+    }
+
+    /// Path the file was opened with.
+    #[must_use]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    fn error(&self, err: &std::io::Error) -> IoError {
+        IoError::from_io(err, Some(&self.path))
+    }
+
+    /// Read the rest of the file as text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not UTF-8.
+    pub fn read_to_string(&mut self) -> IoResult<OxString> {
+        let mut text = String::new();
+        self.inner.read_to_string(&mut text).map_err(|err| self.error(&err))?;
+        Ok(OxString::new(&text))
+    }
+
+    /// Write text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&mut self, text: &str) -> IoResult<()> {
+        self.inner.write_all(text.as_bytes()).map_err(|err| self.error(&err))
+    }
+
+    /// Read the file through a buffer.
+    #[must_use]
+    pub fn reader(self) -> BufferedReader {
+        BufferedReader { inner: BufReader::new(self.inner), path: self.path }
+    }
+
+    /// Write the file through a buffer.
+    #[must_use]
+    pub fn writer(self) -> BufferedWriter {
+        BufferedWriter { inner: BufWriter::new(self.inner), path: self.path }
+    }
+}
+
+/// A file read through a buffer.
+#[derive(Debug)]
+pub struct BufferedReader {
+    inner: BufReader<std::fs::File>,
+    path: PathBuf,
+}
+
+impl BufferedReader {
+    /// Read a line without its line ending, or `None` at the end of the
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not UTF-8.
+    pub fn read_line(&mut self) -> IoResult<Option<OxString>> {
+        let mut line = String::new();
+        match self.inner.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                let end = line.trim_end_matches(['\n', '\r']).len();
+                Ok(Some(OxString::new(&line[..end])))
+            }
+            Err(err) => Err(IoError::from_io(&err, Some(&self.path))),
+        }
+    }
+
+    /// Read the remaining lines.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not UTF-8.
+    pub fn read_lines(&mut self) -> IoResult<Vec<OxString>> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.read_line()? {
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+}
+
+/// A file written through a buffer.
+#[derive(Debug)]
+pub struct BufferedWriter {
+    inner: BufWriter<std::fs::File>,
+    path: PathBuf,
+}
+
+impl BufferedWriter {
+    fn error(&self, err: &std::io::Error) -> IoError {
+        IoError::from_io(err, Some(&self.path))
+    }
+
+    /// Write text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer had to be written and could not be.
+    pub fn write(&mut self, text: &str) -> IoResult<()> {
+        self.inner.write_all(text.as_bytes()).map_err(|err| self.error(&err))
+    }
+
+    /// Write text and a newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the buffer had to be written and could not be.
+    pub fn write_line(&mut self, text: &str) -> IoResult<()> {
+        self.write(text)?;
+        self.write("\n")
+    }
+
+    /// Write whatever the buffer holds to the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush().map_err(|err| self.error(&err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::IoErrorKind;
+
+    #[test]
+    fn test_files_read_write_and_append() {
+        let dir = std::env::temp_dir().join(format!("oxidex-std-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.txt");
+
+        File::write_all(&path, "first\n").unwrap();
+        File::append(&path).unwrap().write("second\r\n").unwrap();
+        let mut writer = File::append(&path).unwrap().writer();
+        writer.write_line("third").unwrap();
+        writer.write("fourth").unwrap();
+        drop(writer);
+        assert_eq!(File::read_all(&path).unwrap(), "first\nsecond\r\nthird\nfourth");
+
+        let mut reader = File::open(&path).unwrap().reader();
+        assert_eq!(reader.read_line().unwrap().unwrap(), "first");
+        assert_eq!(reader.read_lines().unwrap(), ["second", "third", "fourth"]);
+        assert_eq!(reader.read_line().unwrap(), None);
+
+        let missing = dir.join("missing.txt");
+        let err = File::open(&missing).unwrap_err();
+        assert_eq!((err.kind, err.path.as_deref()), (IoErrorKind::NotFound, missing.to_str()));
+        assert!(err.to_string().starts_with("NotFound: "));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,115 @@
+//! Input and output.
+//!
+//! - [`console`]: `print`, `println` and `readLine` on the process's
+//!   standard streams;
+//! - [`file`]: opening, reading, writing and appending to files, directly
+//!   or through buffered readers and writers;
+//! - [`path`]: joining and taking apart file system paths.
+//!
+//! Every operation that can fail returns an [`IoError`], whose
+//! [kind](IoErrorKind) names the case of the `IOError` enum a program
+//! matches on, so errors reach `OxideX` code as ordinary `Result` values.
+
+use std::fmt;
+
+// Standard input and output
+pub mod console;
+
+// Files and buffered access to them
+pub mod file;
+
+// File system paths
+pub mod path;
+
+// Re-exports for convenience
+pub use console::{Console, print, println, read_line};
+pub use file::{BufferedReader, BufferedWriter, File};
+pub use path::Path;
+
+/// What kind of failure an [`IoError`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IoErrorKind {
+    /// The file or directory does not exist
+    NotFound,
+    /// The process may not access it
+    PermissionDenied,
+    /// It exists already
+    AlreadyExists,
+    /// The data is not what was expected, such as text that is not UTF-8
+    InvalidData,
+    /// The input ended early
+    UnexpectedEof,
+    /// Any other failure
+    Other,
+}
+
+impl IoErrorKind {
+    /// Name of the `IOError` case.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NotFound => "NotFound",
+            Self::PermissionDenied => "PermissionDenied",
+            Self::AlreadyExists => "AlreadyExists",
+            Self::InvalidData => "InvalidData",
+            Self::UnexpectedEof => "UnexpectedEof",
+            Self::Other => "Other",
+        }
+    }
+}
+
+impl From<std::io::ErrorKind> for IoErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            std::io::ErrorKind::InvalidData => Self::InvalidData,
+            std::io::ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A failed I/O operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoError {
+    /// What kind of failure it is
+    pub kind: IoErrorKind,
+    /// Path of the file involved, if there is one
+    pub path: Option<String>,
+    /// Description from the operating system
+    pub message: String,
+}
+
+impl IoError {
+    /// An error of the operating system, involving `path` if it is given.
+    #[must_use]
+    pub fn from_io(err: &std::io::Error, path: Option<&std::path::Path>) -> Self {
+        Self {
+            kind: err.kind().into(),
+            path: path.map(|path| path.display().to_string()),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}: {}", self.kind.name(), path, self.message),
+            None => write!(f, "{}: {}", self.kind.name(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for IoError {}
+
+impl From<std::io::Error> for IoError {
+    fn from(err: std::io::Error) -> Self {
+        Self::from_io(&err, None)
+    }
+}
+
+/// Result of an I/O operation.
+pub type IoResult<T> = Result<T, IoError>;
@@ -0,0 +1,160 @@
+//! File system paths.
+//!
+//! A [`Path`] is a path as text, taken apart and put together without
+//! touching the file system, except by [`Path::exists`] and the checks next
+//! to it. Separators are those of the host.
+
+use crate::core::OxString;
+use std::fmt;
+use std::path::{Component, PathBuf};
+
+/// A file system path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path {
+    inner: PathBuf,
+}
+
+impl Path {
+    /// A path from its text.
+    #[must_use]
+    pub fn new(path: &str) -> Self {
+        Self { inner: PathBuf::from(path) }
+    }
+
+    /// The path as a standard library path.
+    #[must_use]
+    pub fn as_std(&self) -> &std::path::Path {
+        &self.inner
+    }
+
+    /// `other` appended to the path, or `other` itself if it is absolute.
+    #[must_use]
+    pub fn join(&self, other: &str) -> Self {
+        Self { inner: self.inner.join(other) }
+    }
+
+    /// The path without its last component, if it has one.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        self.inner.parent().map(|parent| Self { inner: parent.to_path_buf() })
+    }
+
+    /// The last component, if it names a file or directory.
+    #[must_use]
+    pub fn file_name(&self) -> Option<OxString> {
+        self.inner.file_name().map(|name| OxString::new(&name.to_string_lossy()))
+    }
+
+    /// The file name without its extension.
+    #[must_use]
+    pub fn stem(&self) -> Option<OxString> {
+        self.inner.file_stem().map(|stem| OxString::new(&stem.to_string_lossy()))
+    }
+
+    /// The file name's extension, without the dot.
+    #[must_use]
+    pub fn extension(&self) -> Option<OxString> {
+        self.inner.extension().map(|extension| OxString::new(&extension.to_string_lossy()))
+    }
+
+    /// The path with its extension replaced, or removed if `extension` is
+    /// empty.
+    #[must_use]
+    pub fn with_extension(&self, extension: &str) -> Self {
+        Self { inner: self.inner.with_extension(extension) }
+    }
+
+    /// Whether the path starts at the root.
+    #[must_use]
+    pub fn is_absolute(&self) -> bool {
+        self.inner.is_absolute()
+    }
+
+    /// The components of the path, in order.
+    #[must_use]
+    pub fn components(&self) -> Vec<OxString> {
+        self.inner.components().map(|component| OxString::new(&component.as_os_str().to_string_lossy())).collect()
+    }
+
+    /// The path with `.` components removed and each `..` cancelling the
+    /// component before it, where there is one.
+    #[must_use]
+    pub fn normalized(&self) -> Self {
+        let mut inner = PathBuf::new();
+        for component in self.inner.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir
+                    if matches!(inner.components().next_back(), Some(Component::Normal(_))) =>
+                {
+                    inner.pop();
+                }
+                Component::ParentDir if inner.has_root() => {}
+                component => inner.push(component),
+            }
+        }
+        Self { inner }
+    }
+
+    /// Whether something exists at the path.
+    #[must_use]
+    pub fn exists(&self) -> bool {
+        self.inner.exists()
+    }
+
+    /// Whether the path names a regular file.
+    #[must_use]
+    pub fn is_file(&self) -> bool {
+        self.inner.is_file()
+    }
+
+    /// Whether the path names a directory.
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.inner.is_dir()
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner.display())
+    }
+}
+
+impl AsRef<std::path::Path> for Path {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.inner
+    }
+}
+
+impl From<&str> for Path {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_are_taken_apart_and_joined() {
+        let path = Path::new("/home/ada/notes/draft.ox");
+        assert!(path.is_absolute());
+        assert_eq!(path.file_name().unwrap(), "draft.ox");
+        assert_eq!(path.stem().unwrap(), "draft");
+        assert_eq!(path.extension().unwrap(), "ox");
+        assert_eq!(path.with_extension("oxb").to_string(), "/home/ada/notes/draft.oxb");
+        assert_eq!(path.parent().unwrap().join("final.ox"), Path::new("/home/ada/notes/final.ox"));
+        assert_eq!(path.components(), ["/", "home", "ada", "notes", "draft.ox"]);
+
+        assert_eq!(Path::new("a/./b/../c").normalized(), Path::new("a/c"));
+        assert_eq!(Path::new("../a/..").normalized(), Path::new(".."));
+        assert_eq!(Path::new("/..").normalized(), Path::new("/"));
+        assert!(Path::new("relative").join("/absolute").is_absolute());
+
+        let dir = Path::new(&std::env::temp_dir().to_string_lossy());
+        assert!(dir.exists() && dir.is_dir() && !dir.is_file());
+        assert!(!dir.join("oxidex-std-no-such-file").exists());
+    }
+}
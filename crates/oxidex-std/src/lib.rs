@@ -6,16 +6,40 @@
 //! - I/O operations
 //! - Concurrency primitives
 //! - Runtime reflection
+//! - Serialization (Encodable, Decodable and JSON)
+//! - Test assertions and snapshots
 //!
-//! **Phase:** 11 - Planned
-//! **Status:** Placeholder - Implementation TBD
+//! **Phase:** 11 - Standard Library
+//! **Status:** In Progress
 
 #![warn(missing_docs)]
+#![allow(clippy::result_large_err)]
 
-pub mod prelude;
+// Strings and the other core types
+pub mod core;
+
+// Arrays and the other collections
+pub mod collections;
+
+// Console, files and paths
+pub mod io;
+
+// Durations, clocks and calendar dates
+pub mod time;
+
+// Tasks, channels and timers for bytecode programs
+pub mod concurrency;
 
-// Module declarations will be added during Phase 11 implementation:
-// pub mod core;
-// pub mod collections;
-// pub mod io;
-// pub mod runtime;
+// Seedable random numbers
+pub mod random;
+
+// Mirrors of objects, dynamic sends and memory statistics
+pub mod runtime;
+
+// Encodable and Decodable values and the JSON coder
+pub mod codable;
+
+// Assertions with value diffs and snapshot files, for tests
+pub mod testing;
+
+pub mod prelude;
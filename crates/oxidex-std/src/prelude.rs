@@ -6,6 +6,5 @@
 //! use oxidex_std::prelude::*;
 //! ```
 
-// Re-exports will be added during Phase 11 implementation:
-// pub use crate::core::*;
-// pub use crate::collections::*;
+pub use crate::collections::*;
+pub use crate::core::*;
@@ -0,0 +1,262 @@
+//! Random numbers.
+//!
+//! An [`Rng`] is a xoshiro256** generator: fast, with a period of
+//! 2<sup>256</sup> - 1 and good statistical quality, but not suitable for
+//! cryptography. A generator made with [`Rng::seeded`] yields the same
+//! sequence on every run and platform; one made with [`Rng::new`] is seeded
+//! from the operating system's hash keys.
+//!
+//! The free functions use a generator per thread. [`seed`] puts it in
+//! deterministic mode, so a test that calls it first sees the same numbers
+//! every time, and [`randomize`] takes it back out.
+
+use crate::collections::Array;
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::ops::{Bound, Range, RangeBounds};
+
+/// A seedable pseudo-random number generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    /// A generator seeded from the operating system.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::seeded(RandomState::new().hash_one(std::process::id()))
+    }
+
+    /// A generator that yields the same sequence for the same seed.
+    #[must_use]
+    pub fn seeded(seed: u64) -> Self {
+        // SplitMix64 spreads the seed over the state, which must not be
+        // all zeros
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        Self { state: [next(), next(), next(), next()] }
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
+    }
+
+    /// A uniform integer below `bound`, which must not be zero, without
+    /// modulo bias (Lemire's method).
+    #[allow(clippy::cast_possible_truncation)]
+    fn below(&mut self, bound: u64) -> u64 {
+        let mut product = u128::from(self.next_u64()) * u128::from(bound);
+        if (product as u64) < bound {
+            let threshold = bound.wrapping_neg() % bound;
+            while (product as u64) < threshold {
+                product = u128::from(self.next_u64()) * u128::from(bound);
+            }
+        }
+        (product >> 64) as u64
+    }
+
+    /// A uniform integer in `range`, or `None` if the range is empty.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    pub fn int_in(&mut self, range: impl RangeBounds<i64>) -> Option<i64> {
+        let low = match range.start_bound() {
+            Bound::Included(&low) => low,
+            Bound::Excluded(&low) => low.checked_add(1)?,
+            Bound::Unbounded => i64::MIN,
+        };
+        let high = match range.end_bound() {
+            Bound::Included(&high) => high,
+            Bound::Excluded(&high) => high.checked_sub(1)?,
+            Bound::Unbounded => i64::MAX,
+        };
+        if low > high {
+            return None;
+        }
+        let span = high.wrapping_sub(low) as u64;
+        let offset = match span.checked_add(1) {
+            Some(count) => self.below(count),
+            // Every integer is in range
+            None => self.next_u64(),
+        };
+        Some(low.wrapping_add(offset as i64))
+    }
+
+    /// A uniform float from zero up to one, excluding one.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn float(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// A uniform float from `range.start` up to `range.end`, or `None` if
+    /// the range is empty or not finite.
+    pub fn float_in(&mut self, range: Range<f64>) -> Option<f64> {
+        let width = range.end - range.start;
+        if !(range.start < range.end && width.is_finite()) {
+            return None;
+        }
+        // Rounding can land on the end, which the range excludes
+        let value = range.start + width * self.float();
+        Some(if value < range.end { value } else { range.start })
+    }
+
+    /// `true` with probability `probability`: never if it is zero or less,
+    /// always if it is one or more.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.float() < probability
+    }
+
+    /// Put the elements of `array` in a uniformly random order.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn shuffle<T>(&mut self, array: &mut Array<T>) {
+        // Fisher-Yates, from the back
+        for index in (1..array.len()).rev() {
+            let other = self.below(index as u64 + 1) as usize;
+            array.swap(index, other);
+        }
+    }
+
+    /// A uniformly chosen element of `array`, or `None` if it is empty.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn choice<'a, T>(&mut self, array: &'a Array<T>) -> Option<&'a T> {
+        if array.is_empty() {
+            return None;
+        }
+        array.get(self.below(array.len() as u64) as usize)
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+thread_local! {
+    static THREAD_RNG: RefCell<Rng> = RefCell::new(Rng::new());
+}
+
+fn with_thread_rng<R>(f: impl FnOnce(&mut Rng) -> R) -> R {
+    THREAD_RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// Seed the thread's generator, so the free functions yield the same
+/// sequence for the same seed.
+pub fn seed(seed: u64) {
+    with_thread_rng(|rng| *rng = Rng::seeded(seed));
+}
+
+/// Seed the thread's generator from the operating system again, leaving
+/// deterministic mode.
+pub fn randomize() {
+    with_thread_rng(|rng| *rng = Rng::new());
+}
+
+/// A uniform integer in `range` from the thread's generator, or `None` if
+/// the range is empty.
+#[must_use]
+pub fn int_in(range: impl RangeBounds<i64>) -> Option<i64> {
+    with_thread_rng(|rng| rng.int_in(range))
+}
+
+/// A uniform float from zero up to one, excluding one, from the thread's
+/// generator.
+#[must_use]
+pub fn float() -> f64 {
+    with_thread_rng(Rng::float)
+}
+
+/// A uniform float in `range` from the thread's generator, or `None` if the
+/// range is empty or not finite.
+#[must_use]
+pub fn float_in(range: Range<f64>) -> Option<f64> {
+    with_thread_rng(|rng| rng.float_in(range))
+}
+
+/// Shuffle `array` with the thread's generator.
+pub fn shuffle<T>(array: &mut Array<T>) {
+    with_thread_rng(|rng| rng.shuffle(array));
+}
+
+/// A uniformly chosen element of `array` from the thread's generator, or
+/// `None` if it is empty.
+#[must_use]
+pub fn choice<T>(array: &Array<T>) -> Option<&T> {
+    with_thread_rng(|rng| rng.choice(array))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generator_is_xoshiro_and_reproducible() {
+        // Reference outputs of xoshiro256** from the state 1, 2, 3, 4
+        let mut rng = Rng { state: [1, 2, 3, 4] };
+        assert_eq!([rng.next_u64(), rng.next_u64()], [11_520, 0]);
+
+        let sample = |seed| {
+            let mut rng = Rng::seeded(seed);
+            (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(sample(42), sample(42));
+        assert_ne!(sample(42), sample(43));
+        assert_ne!(Rng::new(), Rng::new());
+    }
+
+    #[test]
+    fn test_ranges_are_uniform_and_bounded() {
+        let mut rng = Rng::seeded(7);
+        let mut counts = [0_u32; 6];
+        for _ in 0..60_000 {
+            let roll = rng.int_in(1..=6).unwrap();
+            counts[usize::try_from(roll - 1).unwrap()] += 1;
+        }
+        assert!(counts.iter().all(|&count| (9_500..10_500).contains(&count)), "{counts:?}");
+
+        assert_eq!(rng.int_in(5..5), None);
+        assert_eq!(rng.int_in(3..=3), Some(3));
+        assert!(rng.int_in(..).is_some() && rng.int_in(i64::MAX..).is_some());
+        assert!((0..1000).all(|_| (-2.5..2.5).contains(&rng.float_in(-2.5..2.5).unwrap())));
+        assert_eq!(rng.float_in(1.0..1.0), None);
+        assert_eq!(rng.float_in(0.0..f64::INFINITY), None);
+        assert!(!rng.chance(0.0) && rng.chance(1.0));
+    }
+
+    #[test]
+    fn test_shuffle_choice_and_deterministic_mode() {
+        let ordered: Array<i64> = (0..20).collect();
+        seed(99);
+        let mut first = ordered.clone();
+        shuffle(&mut first);
+        let picked = choice(&ordered).copied();
+        let roll = int_in(1..=100);
+        seed(99);
+        let mut second = ordered.clone();
+        shuffle(&mut second);
+        assert_eq!((first.clone(), picked, roll), (second, choice(&ordered).copied(), int_in(1..=100)));
+
+        assert_ne!(first, ordered);
+        assert_eq!(first.sorted(), ordered);
+        assert_eq!(choice(&Array::<i64>::new()), None);
+        randomize();
+        assert!((0.0..1.0).contains(&float()));
+    }
+}
@@ -0,0 +1,382 @@
+//! Reflection and memory builtins.
+//!
+//! [`register`] adds the reflection and memory functions to an
+//! interpreter's builtins, and [`install`] defines them as globals of a VM,
+//! with [`declare`] binding their signatures for the type checker of
+//! bytecode programs. The reflection functions take an object as their
+//! first argument and read it through a [`Mirror`]:
+//!
+//! - `class_name(object)` returns the name of the object's class
+//! - `conforms_to(object, protocol)` and `responds_to(object, selector)`
+//!   tell whether the class conforms to a protocol and has a method for a
+//!   selector
+//! - `perform(object, selector)` and `perform_with(object, selector,
+//!   argument)` send the message `selector` names, returning the method's
+//!   result as its type encoding describes it; they need
+//!   [`Capability::Ffi`], since the method may be native code
+//! - `superclass_names(object)`, `field_names(object)`,
+//!   `method_names(object)` and `protocol_names(object)` return arrays of
+//!   names, in the order of the [`Mirror`] methods; only the interpreter has
+//!   them, since the VM has no arrays
+//!
+//! Arguments and results cross a send as words, as they do for the
+//! backends' own messages to native code: numbers, booleans and objects can
+//! be passed, and numbers, booleans and nothing returned.
+//!
+//! The memory functions read the runtime's memory report:
+//!
+//! - `memory_stat(name)` returns the statistic of [`MemoryStats`] named
+//!   `name`, such as `"classes"` or `"arena_bytes"`
+//! - `memory_stats()` returns them all as a dictionary from their names;
+//!   only the interpreter has it, since the VM has no dictionaries
+//! - `heap_snapshot()` returns the live objects of each class as JSON, as
+//!   [`heap_snapshot`] does
+//!
+//! [`MemoryStats`]: crate::runtime::MemoryStats
+
+use crate::runtime::memory::{heap_snapshot, memory_stats};
+use crate::runtime::mirror::{Mirror, ReflectionError};
+use oxidec::runtime::ObjectPtr;
+use oxidec::runtime::encoding::parse_signature;
+use oxidex_bytecode::{Capability, Value as VmValue, Vm, VmErrorKind};
+use oxidex_interpreter::{Builtins, RuntimeError, Value};
+use oxidex_syntax::Span;
+use oxidex_typecheck::{InferContext as Context, PrimTy, Scheme, Ty};
+
+/// Names of the reflection builtins of both backends.
+pub const REFLECTION: [&str; 5] = ["class_name", "conforms_to", "responds_to", "perform", "perform_with"];
+
+/// Names of the reflection builtins returning arrays, which only the
+/// interpreter has.
+pub const NAME_LISTS: [&str; 4] = ["superclass_names", "field_names", "method_names", "protocol_names"];
+
+/// Names of the memory builtins of both backends. The interpreter also
+/// has `memory_stats`.
+pub const MEMORY: [&str; 2] = ["memory_stat", "heap_snapshot"];
+
+/// The signature of the reflection or memory builtin `name`, or `None` if
+/// there is no such builtin. The object is of any type, and a send returns
+/// whatever type the caller expects.
+#[must_use]
+pub fn signature(name: &str) -> Option<Scheme> {
+    let object = Ty::TypeVar(0);
+    let string = Ty::Primitive(PrimTy::String);
+    let (params, return_type) = match name {
+        "class_name" => (vec![object], string),
+        "conforms_to" | "responds_to" => (vec![object, string], Ty::Primitive(PrimTy::Bool)),
+        "perform" => (vec![object, string], Ty::TypeVar(1)),
+        "perform_with" => (vec![object, string, Ty::TypeVar(2)], Ty::TypeVar(1)),
+        "superclass_names" | "field_names" | "method_names" | "protocol_names" => {
+            (vec![object], Ty::Array(Box::new(string)))
+        }
+        "memory_stat" => (vec![string], Ty::Primitive(PrimTy::Int64)),
+        "memory_stats" => {
+            let value = Box::new(Ty::Primitive(PrimTy::Int64));
+            (Vec::new(), Ty::Dict { key: Box::new(string), value })
+        }
+        "heap_snapshot" => (Vec::new(), string),
+        _ => return None,
+    };
+    let vars = match name {
+        "perform" => vec![0, 1],
+        "perform_with" => vec![0, 1, 2],
+        "memory_stat" | "memory_stats" | "heap_snapshot" => Vec::new(),
+        _ => vec![0],
+    };
+    let labels = vec![None; params.len()];
+    Some(Scheme::poly(vars, Ty::Function { params, return_type: Box::new(return_type), labels }))
+}
+
+/// Bind the signatures of the VM's reflection and memory builtins the
+/// program mentions in the type checker's environment.
+pub fn declare(ctx: &mut Context<'_>) {
+    for name in REFLECTION.into_iter().chain(MEMORY) {
+        if let (Some(sym), Some(scheme)) = (ctx.interner.get_symbol(name), signature(name)) {
+            ctx.env.bind(sym, scheme);
+        }
+    }
+}
+
+/// A result word decoded by its type encoding.
+enum Word {
+    Unit,
+    Int(i64),
+    Bool(bool),
+    Float(f64),
+}
+
+/// Send `selector` to the object `mirror` reflects, decoding the result by
+/// the method's return type.
+fn perform(mirror: &Mirror, selector: &str, args: &[usize]) -> Result<Result<Word, &'static str>, ReflectionError> {
+    let word = mirror.send(selector, args)?.unwrap_or_default();
+    let encoding = mirror.methods().into_iter().find(|method| method.selector == selector).map(|m| m.type_encoding);
+    let Some((return_type, _)) = encoding.and_then(|encoding| parse_signature(encoding.as_str()).ok()) else {
+        return Ok(Err("a message without a type encoding"));
+    };
+    Ok(Ok(match return_type {
+        'v' => Word::Unit,
+        'c' | 's' | 'i' | 'l' => Word::Int(i64::from(word as i32)),
+        'C' | 'S' | 'I' | 'L' => Word::Int(i64::from(word as u32)),
+        'q' | 'Q' => Word::Int(word as i64),
+        'B' => Word::Bool(word != 0),
+        'f' => Word::Float(f64::from(f32::from_bits(word as u32))),
+        'd' => Word::Float(f64::from_bits(word as u64)),
+        _ => return Ok(Err("a dynamic send result of this type")),
+    }))
+}
+
+/// The statistic of the memory report named `name`, if there is one.
+fn memory_stat(name: &str) -> Option<i64> {
+    memory_stats().entries().into_iter().find(|(stat, _)| stat.as_str() == name).map(|(_, value)| value)
+}
+
+/// Get the address of an object, which identifies it across the boundary.
+fn object_address(object: ObjectPtr) -> usize {
+    // SAFETY: `ObjectPtr` is a transparent wrapper around a raw pointer
+    unsafe { std::mem::transmute::<ObjectPtr, *mut u8>(object) as usize }
+}
+
+// ===== Interpreter =====
+
+/// Add the reflection and memory builtins to `builtins`.
+pub fn register(builtins: &mut Builtins) {
+    let sig = |name| signature(name).expect("a reflection builtin");
+    builtins.register("class_name", sig("class_name"), |args, span| {
+        Ok(Value::string(mirror(&args[0], span)?.class_name().as_str()))
+    });
+    builtins.register("conforms_to", sig("conforms_to"), |args, span| {
+        Ok(Value::Bool(mirror(&args[0], span)?.conforms_to(text(&args[1], span)?)))
+    });
+    builtins.register("responds_to", sig("responds_to"), |args, span| {
+        Ok(Value::Bool(mirror(&args[0], span)?.responds_to(text(&args[1], span)?)))
+    });
+    builtins.register_requiring("perform", Capability::Ffi, sig("perform"), |args, span| {
+        send(&args[0], &args[1], &[], span)
+    });
+    builtins.register_requiring("perform_with", Capability::Ffi, sig("perform_with"), |args, span| {
+        send(&args[0], &args[1], &args[2..], span)
+    });
+
+    let names = |names: Vec<crate::core::OxString>| {
+        Value::array(names.iter().map(|name| Value::string(name.as_str())).collect())
+    };
+    builtins.register("superclass_names", sig("superclass_names"), move |args, span| {
+        Ok(names(mirror(&args[0], span)?.superclass_names()))
+    });
+    builtins.register("field_names", sig("field_names"), move |args, span| {
+        Ok(names(mirror(&args[0], span)?.fields().into_iter().map(|field| field.name).collect()))
+    });
+    builtins.register("method_names", sig("method_names"), move |args, span| {
+        Ok(names(mirror(&args[0], span)?.methods().into_iter().map(|method| method.selector).collect()))
+    });
+    builtins.register("protocol_names", sig("protocol_names"), move |args, span| {
+        Ok(names(mirror(&args[0], span)?.protocols()))
+    });
+
+    builtins.register("memory_stat", sig("memory_stat"), |args, span| {
+        memory_stat(text(&args[0], span)?)
+            .map(Value::Int)
+            .ok_or_else(|| RuntimeError::MissingKey { key: format!("{:#}", args[0]), span })
+    });
+    builtins.register("memory_stats", sig("memory_stats"), |_, _| {
+        let entries = memory_stats().entries().into_iter();
+        Ok(Value::dict(entries.map(|(name, value)| (Value::string(name.as_str()), Value::Int(value))).collect()))
+    });
+    builtins.register("heap_snapshot", sig("heap_snapshot"), |_, _| Ok(Value::string(heap_snapshot().as_str())));
+}
+
+/// A mirror of an interpreted object.
+fn mirror(value: &Value, span: Span) -> Result<Mirror, RuntimeError> {
+    match value {
+        Value::Object(instance) => Ok(Mirror::of(&instance.object)),
+        other => Err(RuntimeError::TypeMismatch { expected: "an object", found: other.kind(), span }),
+    }
+}
+
+/// The text of a string argument.
+fn text(value: &Value, span: Span) -> Result<&str, RuntimeError> {
+    match value {
+        Value::String(text) => Ok(text),
+        other => Err(RuntimeError::TypeMismatch { expected: "a string", found: other.kind(), span }),
+    }
+}
+
+/// Send a message by name to an interpreted object.
+fn send(object: &Value, selector: &Value, args: &[Value], span: Span) -> Result<Value, RuntimeError> {
+    let unsupported = |construct| RuntimeError::Unsupported { construct, span };
+    let words = args
+        .iter()
+        .map(|arg| match arg {
+            Value::Unit | Value::Nil => Ok(0),
+            Value::Bool(value) => Ok(usize::from(*value)),
+            Value::Int(value) => Ok(*value as usize),
+            Value::Float(value) => Ok(value.to_bits() as usize),
+            Value::Object(instance) => Ok(object_address(instance.object.as_raw())),
+            _ => Err(unsupported("passing this value to a dynamic send")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let result = perform(&mirror(object, span)?, text(selector, span)?, &words).map_err(|err| match err {
+        ReflectionError::Runtime(err) => RuntimeError::Runtime(err),
+        ReflectionError::TooManyArguments { .. } => unsupported("a dynamic send with this many arguments"),
+    })?;
+    Ok(match result.map_err(unsupported)? {
+        Word::Unit => Value::Unit,
+        Word::Int(value) => Value::Int(value),
+        Word::Bool(value) => Value::Bool(value),
+        Word::Float(value) => Value::Float(value),
+    })
+}
+
+// ===== VM =====
+
+/// Define the reflection and memory builtins the VM has as globals of `vm`.
+pub fn install(vm: &mut Vm) {
+    vm.define_native("class_name", 1, |_, args| Ok(VmValue::string(vm_mirror(&args[0])?.class_name().as_str())));
+    vm.define_native("conforms_to", 2, |_, args| {
+        Ok(VmValue::Bool(vm_mirror(&args[0])?.conforms_to(vm_text(&args[1])?)))
+    });
+    vm.define_native("responds_to", 2, |_, args| {
+        Ok(VmValue::Bool(vm_mirror(&args[0])?.responds_to(vm_text(&args[1])?)))
+    });
+    vm.define_native_requiring("perform", Capability::Ffi, 2, |_, args| vm_send(&args[0], &args[1], &[]));
+    vm.define_native_requiring("perform_with", Capability::Ffi, 3, |_, args| {
+        vm_send(&args[0], &args[1], &args[2..])
+    });
+    vm.define_native("memory_stat", 1, |_, args| {
+        let name = vm_text(&args[0])?;
+        memory_stat(name).map(VmValue::Int).ok_or_else(|| VmErrorKind::NoSuchField(name.to_string()))
+    });
+    vm.define_native("heap_snapshot", 0, |_, _| Ok(VmValue::string(heap_snapshot().as_str())));
+}
+
+/// A mirror of a VM instance.
+fn vm_mirror(value: &VmValue) -> Result<Mirror, VmErrorKind> {
+    match value {
+        VmValue::Object(instance) => Ok(Mirror::of(&instance.object)),
+        other => Err(VmErrorKind::TypeMismatch { expected: "an object", found: other.kind() }),
+    }
+}
+
+/// The text of a string argument.
+fn vm_text(value: &VmValue) -> Result<&str, VmErrorKind> {
+    match value {
+        VmValue::String(text) => Ok(text),
+        other => Err(VmErrorKind::TypeMismatch { expected: "a string", found: other.kind() }),
+    }
+}
+
+/// Send a message by name to a VM instance.
+fn vm_send(object: &VmValue, selector: &VmValue, args: &[VmValue]) -> Result<VmValue, VmErrorKind> {
+    let words = args
+        .iter()
+        .map(|arg| match arg {
+            VmValue::Nil => Ok(0),
+            VmValue::Bool(value) => Ok(usize::from(*value)),
+            VmValue::Int(value) => Ok(*value as usize),
+            VmValue::Float(value) => Ok(value.to_bits() as usize),
+            VmValue::Object(instance) => Ok(object_address(instance.object.as_raw())),
+            other => Err(VmErrorKind::NotNative(other.kind())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let result = perform(&vm_mirror(object)?, vm_text(selector)?, &words).map_err(|err| match err {
+        ReflectionError::Runtime(err) => VmErrorKind::Runtime(err),
+        ReflectionError::TooManyArguments { .. } => VmErrorKind::NotNative("a message with this many arguments"),
+    })?;
+    Ok(match result.map_err(VmErrorKind::NotNative)? {
+        Word::Unit => VmValue::Nil,
+        Word::Int(value) => VmValue::Int(value),
+        Word::Bool(value) => VmValue::Bool(value),
+        Word::Float(value) => VmValue::Float(value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::runtime::selector::SelectorHandle;
+    use oxidec::runtime::{RuntimeString, get_global_arena};
+    use oxidec::{Class, Method, Object, Selector};
+    use oxidex_codegen::ir::parse_module;
+    use std::rc::Rc;
+    use std::str::FromStr;
+
+    unsafe extern "C-unwind" fn twice(_self: ObjectPtr, _cmd: SelectorHandle, args: *const *mut u8, ret: *mut u8) {
+        // SAFETY: dispatch passes one word per colon and a word-sized
+        // return buffer
+        unsafe { ret.cast::<usize>().write_unaligned(args.cast::<usize>().read_unaligned() * 2) }
+    }
+
+    #[test]
+    fn test_bytecode_programs_reflect_objects() {
+        let class = Class::new_root("ReflectedDoubler").unwrap();
+        let types = RuntimeString::new("q@:q", get_global_arena());
+        class.add_method(Method { selector: Selector::from_str("twice:").unwrap(), imp: twice, types }).unwrap();
+
+        let module = parse_module(
+            r#"
+fn "main"(%0: object) -> int {
+bb0:
+    %1: string = call "class_name"(%0)
+    %2: string = const string "ReflectedDoubler"
+    %3: bool = binary eq %1, %2
+    %4: unit = call "assert"(%3)
+    %5: string = const string "twice:"
+    %6: bool = call "responds_to"(%0, %5)
+    %7: unit = call "assert"(%6)
+    %8: int = const int 21
+    %9: int = call "perform_with"(%0, %5, %8)
+    return %9
+}
+"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new();
+        oxidex_bytecode::builtins::install(&mut vm);
+        install(&mut vm);
+        let script = oxidex_bytecode::compile(&module).unwrap();
+        vm.run(Rc::new(script)).unwrap();
+        let object = vm.instance(Object::new(&class).unwrap());
+        let main = vm.global("main").unwrap();
+        assert_eq!(vm.call(main.clone(), vec![object.clone()]).unwrap(), VmValue::Int(42));
+
+        // Sends go through the runtime, which rejects unknown selectors
+        let area = vm.global("perform").unwrap();
+        let err = vm.call(area, vec![object, VmValue::string("area")]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::Runtime(oxidec::Error::SelectorNotFound)), "{err:?}");
+        let err = vm.call(main, vec![VmValue::Int(1)]).unwrap_err();
+        assert!(matches!(err.kind, VmErrorKind::TypeMismatch { expected: "an object", .. }), "{err:?}");
+    }
+
+    #[test]
+    fn test_bytecode_programs_read_memory_stats() {
+        let class = Class::new_root("MemoryBuiltinsProbe").unwrap();
+        let _object = Object::new(&class).unwrap();
+        let module = parse_module(
+            r#"
+fn "main"(%0: string) -> int {
+bb0:
+    %1: string = call "heap_snapshot"()
+    %2: int = call "memory_stat"(%0)
+    return %2
+}
+"#,
+        )
+        .unwrap();
+        let mut vm = Vm::new();
+        install(&mut vm);
+        vm.run(Rc::new(oxidex_bytecode::compile(&module).unwrap())).unwrap();
+        let main = vm.global("main").unwrap();
+        let VmValue::Int(classes) = vm.call(main.clone(), vec![VmValue::string("classes")]).unwrap() else {
+            panic!("`memory_stat` returns an Int");
+        };
+        assert!(classes >= 1);
+        let err = vm.call(main, vec![VmValue::string("objects")]).unwrap_err();
+        assert!(matches!(&err.kind, VmErrorKind::NoSuchField(name) if name == "objects"), "{err:?}");
+
+        let snapshot = vm.global("heap_snapshot").unwrap();
+        let VmValue::String(json) = vm.call(snapshot, Vec::new()).unwrap() else {
+            panic!("`heap_snapshot` returns a String");
+        };
+        assert!(json.contains(r#"{"class":"MemoryBuiltinsProbe","instances":1,"#));
+    }
+}
@@ -0,0 +1,108 @@
+//! Memory statistics.
+//!
+//! The figures of the `OxideC` runtime's memory report, as `Int`s a program
+//! can read: the arena all runtime metadata lives in, the selectors,
+//! classes and strings in it, and the calling thread's invocation pool.
+//! [`heap_snapshot`] adds the live objects of each class, as JSON.
+
+use crate::core::OxString;
+use oxidec::runtime::census;
+use oxidec::runtime::{MemoryReport, memory_report};
+
+/// What the runtime holds, as [`memory_stats`] reads it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes allocated in the runtime's arena
+    pub arena_bytes: i64,
+    /// Bytes the arena's chunks can hold
+    pub arena_capacity: i64,
+    /// Number of chunks in the arena
+    pub arena_chunks: i64,
+    /// Number of interned selectors
+    pub selectors: i64,
+    /// Number of registered classes
+    pub classes: i64,
+    /// Number of interned strings
+    pub interned_strings: i64,
+    /// Arena bytes the interned strings take
+    pub string_bytes: i64,
+    /// Invocations pooled for reuse by the calling thread
+    pub pooled_invocations: i64,
+}
+
+impl MemoryStats {
+    /// The statistics as name and value pairs, in declaration order.
+    #[must_use]
+    pub fn entries(&self) -> Vec<(OxString, i64)> {
+        [
+            ("arena_bytes", self.arena_bytes),
+            ("arena_capacity", self.arena_capacity),
+            ("arena_chunks", self.arena_chunks),
+            ("selectors", self.selectors),
+            ("classes", self.classes),
+            ("interned_strings", self.interned_strings),
+            ("string_bytes", self.string_bytes),
+            ("pooled_invocations", self.pooled_invocations),
+        ]
+        .into_iter()
+        .map(|(name, value)| (OxString::new(name), value))
+        .collect()
+    }
+}
+
+impl From<MemoryReport> for MemoryStats {
+    fn from(report: MemoryReport) -> Self {
+        // Counts beyond `Int` cannot happen in memory that exists
+        let int = |count: usize| i64::try_from(count).unwrap_or(i64::MAX);
+        Self {
+            arena_bytes: int(report.arena.total_allocated),
+            arena_capacity: int(report.arena.total_capacity),
+            arena_chunks: int(report.arena.chunk_count),
+            selectors: int(report.selectors),
+            classes: int(report.classes),
+            interned_strings: int(report.strings.interned),
+            string_bytes: int(report.strings.bytes),
+            pooled_invocations: int(report.pool.map_or(0, |pool| pool.pool_size)),
+        }
+    }
+}
+
+/// The memory the runtime holds now.
+#[must_use]
+pub fn memory_stats() -> MemoryStats {
+    memory_report().into()
+}
+
+/// The live objects by class and the memory the runtime holds, as the
+/// JSON of a heap dump.
+#[must_use]
+pub fn heap_snapshot() -> OxString {
+    OxString::new(&census::heap_snapshot().to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::Class;
+
+    #[test]
+    fn test_memory_stats() {
+        let before = memory_stats();
+        Class::new_root("MemoryStatsClass").unwrap();
+        let after = memory_stats();
+        assert!(after.classes > before.classes && after.arena_bytes > before.arena_bytes);
+        assert!(after.arena_capacity >= after.arena_bytes && after.arena_chunks >= 1);
+
+        let entries = after.entries();
+        assert_eq!(entries.len(), 8);
+        assert_eq!(entries[4], (OxString::new("classes"), after.classes));
+    }
+
+    #[test]
+    fn test_heap_snapshot() {
+        let class = Class::new_root("HeapSnapshotClass").unwrap();
+        let _object = oxidec::Object::new(&class).unwrap();
+        let json = heap_snapshot();
+        assert!(json.as_str().contains(r#"{"class":"HeapSnapshotClass","instances":1,"#));
+    }
+}
@@ -0,0 +1,267 @@
+//! Mirrors of objects.
+//!
+//! Fields are the instance variables the runtime records for a class and
+//! its superclasses, as a class declared in `OxideX` code has them; methods
+//! are its instance methods, each with the class that provides it. A send
+//! passes its arguments and returns its result as words, the way native
+//! method implementations take them.
+
+use crate::core::OxString;
+use oxidec::runtime::{Class, MessageArgs, Object, Selector, instance_methods, instance_variables, method_provider};
+use std::fmt;
+use std::str::FromStr;
+
+/// Most arguments a dynamic send passes.
+pub const MAX_SEND_ARGUMENTS: usize = 8;
+
+/// A field of an object's class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Field name
+    pub name: OxString,
+    /// Type encoding of the field, such as `q` for an `Int`
+    pub type_encoding: OxString,
+}
+
+/// An instance method of an object's class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodInfo {
+    /// Selector the method answers, such as `addX:y:`
+    pub selector: OxString,
+    /// Type encoding of the method's return value and arguments
+    pub type_encoding: OxString,
+    /// Name of the class that provides the method, the object's own or a
+    /// superclass
+    pub declared_by: OxString,
+}
+
+/// A dynamic send that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReflectionError {
+    /// More arguments than [`MAX_SEND_ARGUMENTS`]
+    TooManyArguments {
+        /// Selector of the send
+        selector: String,
+        /// Number of arguments given
+        count: usize,
+    },
+    /// The runtime rejected the send, such as for a selector the object
+    /// does not respond to
+    Runtime(oxidec::Error),
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyArguments { selector, count } => write!(
+                f,
+                "cannot send {selector} with {count} arguments; at most {MAX_SEND_ARGUMENTS} are supported"
+            ),
+            Self::Runtime(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+impl From<oxidec::Error> for ReflectionError {
+    fn from(err: oxidec::Error) -> Self {
+        Self::Runtime(err)
+    }
+}
+
+/// A view of an object through the runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mirror {
+    subject: Object,
+}
+
+impl Mirror {
+    /// A mirror of `object`, which it keeps alive.
+    #[must_use]
+    pub fn of(object: &Object) -> Self {
+        Self { subject: object.clone() }
+    }
+
+    /// The object the mirror reflects.
+    #[must_use]
+    pub fn subject(&self) -> &Object {
+        &self.subject
+    }
+
+    fn class(&self) -> Class {
+        self.subject.class()
+    }
+
+    /// Name of the object's class.
+    #[must_use]
+    pub fn class_name(&self) -> OxString {
+        OxString::new(self.class().name())
+    }
+
+    /// Names of the superclasses, nearest first.
+    #[must_use]
+    pub fn superclass_names(&self) -> Vec<OxString> {
+        let mut names = Vec::new();
+        let mut current = self.class().super_class();
+        while let Some(class) = current {
+            names.push(OxString::new(class.name()));
+            current = class.super_class();
+        }
+        names
+    }
+
+    /// The fields, inherited ones first, each class's in declaration
+    /// order.
+    #[must_use]
+    pub fn fields(&self) -> Vec<Field> {
+        instance_variables(&self.class())
+            .iter()
+            .map(|ivar| Field {
+                name: OxString::new(ivar.name.as_str().unwrap_or_default()),
+                type_encoding: OxString::new(ivar.types.as_str().unwrap_or_default()),
+            })
+            .collect()
+    }
+
+    /// The instance methods, inherited ones included, sorted by selector.
+    #[must_use]
+    pub fn methods(&self) -> Vec<MethodInfo> {
+        let class = self.class();
+        let mut methods: Vec<MethodInfo> = instance_methods(&class)
+            .iter()
+            .map(|method| MethodInfo {
+                selector: OxString::new(method.selector.name()),
+                type_encoding: OxString::new(method.types.as_str().unwrap_or_default()),
+                declared_by: OxString::new(
+                    method_provider(&class, &method.selector).as_ref().map_or(class.name(), Class::name),
+                ),
+            })
+            .collect();
+        methods.sort_by(|a, b| a.selector.cmp(&b.selector));
+        methods
+    }
+
+    /// Names of the protocols the class conforms to, its superclasses'
+    /// included.
+    #[must_use]
+    pub fn protocols(&self) -> Vec<OxString> {
+        self.class().protocols().iter().map(|protocol| OxString::new(protocol.name())).collect()
+    }
+
+    /// Whether the class conforms to the protocol named `protocol`.
+    #[must_use]
+    pub fn conforms_to(&self, protocol: &str) -> bool {
+        self.class().protocols().iter().any(|adopted| adopted.name() == protocol)
+    }
+
+    /// Whether the object has a method for `selector`.
+    #[must_use]
+    pub fn responds_to(&self, selector: &str) -> bool {
+        Selector::from_str(selector).is_ok_and(|selector| self.subject.responds_to(&selector))
+    }
+
+    /// Send the object the message `selector` with `args`, returning the
+    /// method's result word, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are more than [`MAX_SEND_ARGUMENTS`]
+    /// arguments, or the runtime rejects the send.
+    pub fn send(&self, selector: &str, args: &[usize]) -> Result<Option<usize>, ReflectionError> {
+        send(&self.subject, selector, args)
+    }
+}
+
+/// Send `object` the message `selector` with `args`, as
+/// [`Mirror::send`] does.
+///
+/// # Errors
+///
+/// Returns an error if there are more than [`MAX_SEND_ARGUMENTS`]
+/// arguments, or the runtime rejects the send.
+pub fn send(object: &Object, selector: &str, args: &[usize]) -> Result<Option<usize>, ReflectionError> {
+    let message = match *args {
+        [] => MessageArgs::None,
+        [a] => MessageArgs::One(a),
+        [a, b] => MessageArgs::Two([a, b]),
+        [a, b, c] => MessageArgs::Three([a, b, c]),
+        [a, b, c, d] => MessageArgs::Four([a, b, c, d]),
+        [a, b, c, d, e] => MessageArgs::Five([a, b, c, d, e]),
+        [a, b, c, d, e, f] => MessageArgs::Six([a, b, c, d, e, f]),
+        [a, b, c, d, e, f, g] => MessageArgs::Seven([a, b, c, d, e, f, g]),
+        [a, b, c, d, e, f, g, h] => MessageArgs::Eight([a, b, c, d, e, f, g, h]),
+        _ => return Err(ReflectionError::TooManyArguments { selector: selector.to_string(), count: args.len() }),
+    };
+    let selector = Selector::from_str(selector)?;
+    Ok(object.send_message(&selector, &message)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidec::runtime::selector::SelectorHandle;
+    use oxidec::runtime::{Ivar, ObjectPtr, RuntimeString, get_global_arena};
+    use oxidec::{Method, Protocol};
+
+    unsafe extern "C-unwind" fn add(_self: ObjectPtr, _cmd: SelectorHandle, args: *const *mut u8, ret: *mut u8) {
+        // SAFETY: dispatch passes one word per colon and a word-sized
+        // return buffer
+        unsafe {
+            let args = std::slice::from_raw_parts(args.cast::<usize>(), 2);
+            ret.cast::<usize>().write_unaligned(args[0] + args[1]);
+        }
+    }
+
+    /// A square class under a shape class, with names starting `prefix`.
+    fn class(prefix: &str) -> Class {
+        let arena = get_global_arena();
+        let text = |text| RuntimeString::new(text, arena);
+        let shape = Class::new_root(&format!("{prefix}Shape")).unwrap();
+        shape.add_ivar(Ivar::new(text("sides"), text("q"))).unwrap();
+        let method = |name| Method { selector: Selector::from_str(name).unwrap(), imp: add, types: text("q@:qq") };
+        shape.add_method(method("add:to:")).unwrap();
+        shape.add_protocol(&Protocol::new(&format!("{prefix}Drawable"), None).unwrap()).unwrap();
+
+        let square = Class::new(&format!("{prefix}Square"), &shape).unwrap();
+        square.add_ivar(Ivar::new(text("side"), text("d"))).unwrap();
+        square.add_method(method("combine:with:")).unwrap();
+        square
+    }
+
+    #[test]
+    fn test_mirror_describes_an_object() {
+        let object = Object::new(&class("Mirror")).unwrap();
+        let mirror = Mirror::of(&object);
+        assert_eq!(mirror.subject(), &object);
+        assert_eq!(mirror.class_name(), "MirrorSquare");
+        assert_eq!(mirror.superclass_names(), ["MirrorShape"]);
+
+        let fields = mirror.fields();
+        let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+        let encodings: Vec<&str> = fields.iter().map(|field| field.type_encoding.as_str()).collect();
+        assert_eq!((names, encodings), (vec!["sides", "side"], vec!["q", "d"]));
+
+        let methods = mirror.methods();
+        let selectors: Vec<&str> = methods.iter().map(|method| method.selector.as_str()).collect();
+        let owners: Vec<&str> = methods.iter().map(|method| method.declared_by.as_str()).collect();
+        assert_eq!((selectors, owners), (vec!["add:to:", "combine:with:"], vec!["MirrorShape", "MirrorSquare"]));
+        assert_eq!(methods[0].type_encoding, "q@:qq");
+
+        assert_eq!(mirror.protocols(), ["MirrorDrawable"]);
+        assert!(mirror.conforms_to("MirrorDrawable") && !mirror.conforms_to("MirrorSized"));
+        assert!(mirror.responds_to("add:to:") && !mirror.responds_to("area") && !mirror.responds_to(""));
+    }
+
+    #[test]
+    fn test_dynamic_sends() {
+        let mirror = Mirror::of(&Object::new(&class("Sent")).unwrap());
+        assert_eq!(mirror.send("add:to:", &[2, 40]), Ok(Some(42)));
+        assert_eq!(send(mirror.subject(), "combine:with:", &[1, 2]), Ok(Some(3)));
+
+        assert_eq!(mirror.send("area", &[]), Err(ReflectionError::Runtime(oxidec::Error::SelectorNotFound)));
+        let err = mirror.send("add:to:", &[0; 9]).unwrap_err();
+        assert_eq!(err, ReflectionError::TooManyArguments { selector: "add:to:".into(), count: 9 });
+        assert_eq!(err.to_string(), "cannot send add:to: with 9 arguments; at most 8 are supported");
+    }
+}
@@ -0,0 +1,28 @@
+//! Runtime reflection.
+//!
+//! A [`Mirror`] is the view `Mirror(of:)` gives a program of one object:
+//! its class name and superclasses, the fields, methods and protocol
+//! conformances the runtime records for its class, and messages sent by
+//! selector name rather than by a call the compiler checked. Everything is
+//! read from the `OxideC` runtime's introspection, so it describes
+//! interpreted and compiled objects alike.
+//!
+//! [`memory_stats`] gives a program the runtime's memory report: the
+//! arena, selectors, classes, strings and invocations it holds, and
+//! [`heap_snapshot`] the live objects of each class.
+//!
+//! [`builtins`] makes mirrors, sends and the memory report callable from
+//! `OxideX` programs, under the interpreter and the VM alike.
+
+// Reflection and memory functions for interpreted and bytecode programs
+pub mod builtins;
+
+// Statistics of the memory the runtime holds
+pub mod memory;
+
+// Mirrors of objects and dynamic sends
+pub mod mirror;
+
+// Re-exports for convenience
+pub use memory::{MemoryStats, heap_snapshot, memory_stats};
+pub use mirror::{Field, MethodInfo, Mirror, ReflectionError, send};
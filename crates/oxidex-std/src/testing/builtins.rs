@@ -0,0 +1,131 @@
+//! Assertion builtins.
+//!
+//! [`register`] adds the assertions to an interpreter's builtins, and
+//! [`install`] defines them as globals of a VM, with [`declare`] binding
+//! their signatures for the type checker of bytecode programs, so that
+//! `ox test` and `ox bench` check and run the same tests:
+//!
+//! - `assert_eq(actual, expected)` fails with the [`Mismatch`] of the two
+//!   values, or a diff of them as a note in the interpreter when either
+//!   spans lines
+//! - `expect(condition, message)` fails with `message` unless `condition`
+//!   holds
+//!
+//! `assert_snapshot` needs to know where the test file is, so `ox test`
+//! registers it itself.
+
+use crate::testing::{self, Mismatch};
+use oxidex_bytecode::{Value as VmValue, Vm, VmErrorKind};
+use oxidex_interpreter::{Builtins, RuntimeError, Value};
+use oxidex_syntax::Span;
+use oxidex_typecheck::{InferContext as Context, PrimTy, Scheme, Ty};
+
+/// Names of the assertion builtins of both backends.
+pub const ASSERTIONS: [&str; 2] = ["assert_eq", "expect"];
+
+/// The signature of the assertion builtin `name`, or `None` if there is no
+/// such builtin. `assert_eq` takes two values of any one type.
+#[must_use]
+pub fn signature(name: &str) -> Option<Scheme> {
+    let params = match name {
+        "assert_eq" => vec![Ty::TypeVar(0), Ty::TypeVar(0)],
+        "expect" => vec![Ty::Primitive(PrimTy::Bool), Ty::Primitive(PrimTy::String)],
+        _ => return None,
+    };
+    Some(unit_signature(params))
+}
+
+/// A generic signature returning unit, whose type variables are those
+/// `params` use.
+#[must_use]
+pub fn unit_signature(params: Vec<Ty>) -> Scheme {
+    let vars = params.iter().filter_map(|param| if let Ty::TypeVar(var) = param { Some(*var) } else { None });
+    let mut vars: Vec<u32> = vars.collect();
+    vars.dedup();
+    let labels = vec![None; params.len()];
+    Scheme::poly(vars, Ty::Function { params, return_type: Box::new(Ty::Primitive(PrimTy::Unit)), labels })
+}
+
+/// Bind the signatures of the VM's assertion builtins the program mentions
+/// in the type checker's environment.
+pub fn declare(ctx: &mut Context<'_>) {
+    for name in ASSERTIONS {
+        if let (Some(sym), Some(scheme)) = (ctx.interner.get_symbol(name), signature(name)) {
+            ctx.env.bind(sym, scheme);
+        }
+    }
+}
+
+/// Add the assertion builtins to `builtins`.
+// Builtins return the interpreter's errors, which are large
+#[allow(clippy::result_large_err)]
+pub fn register(builtins: &mut Builtins) {
+    let sig = |name| signature(name).expect("an assertion builtin");
+    builtins.register("assert_eq", sig("assert_eq"), |args, span| {
+        if args[0] == args[1] {
+            return Ok(Value::Unit);
+        }
+        let mismatch = Mismatch::new(args[1].pretty(), args[0].pretty());
+        Err(if mismatch.expected.contains('\n') || mismatch.actual.contains('\n') {
+            let detail = format!("- expected, + found:\n{}", mismatch.diff());
+            failure("`assert_eq` failed: values differ".to_string(), Some(detail), span)
+        } else {
+            failure(format!("`assert_eq` failed: {mismatch}"), None, span)
+        })
+    });
+    builtins.register("expect", sig("expect"), |args, span| {
+        let Value::Bool(condition) = args[0] else {
+            return Err(RuntimeError::TypeMismatch { expected: "a boolean", found: args[0].kind(), span });
+        };
+        let message = args[1].to_string();
+        testing::expect(condition, message).map(|()| Value::Unit).map_err(|message| failure(message, None, span))
+    });
+}
+
+/// A failed expectation, reported at `span` with `detail` as a note.
+#[must_use]
+pub fn failure(message: String, detail: Option<String>, span: Span) -> RuntimeError {
+    RuntimeError::ExpectationFailed { message, detail, span }
+}
+
+/// Define the assertion builtins as globals of `vm`.
+pub fn install(vm: &mut Vm) {
+    vm.define_native("assert_eq", 2, |_, args| {
+        if args[0] == args[1] {
+            return Ok(VmValue::Nil);
+        }
+        let mismatch = Mismatch::new(format!("{:#}", args[1]), format!("{:#}", args[0]));
+        Err(VmErrorKind::ExpectationFailed(format!("`assert_eq` failed: {mismatch}")))
+    });
+    vm.define_native("expect", 2, |_, args| match args[0] {
+        VmValue::Bool(condition) => testing::expect(condition, args[1].to_string())
+            .map(|()| VmValue::Nil)
+            .map_err(VmErrorKind::ExpectationFailed),
+        ref other => Err(VmErrorKind::TypeMismatch { expected: "a boolean", found: other.kind() }),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vm_assertion_failures() {
+        let mut vm = Vm::new();
+        install(&mut vm);
+        let mut fail = |name: &str, args: Vec<VmValue>| {
+            let function = vm.global(name).unwrap();
+            vm.call(function, args).err().map(|err| err.kind)
+        };
+
+        let err = fail("assert_eq", vec![VmValue::Int(4), VmValue::Int(5)]).unwrap();
+        assert_eq!(err.to_string(), "`assert_eq` failed: expected 5, found 4");
+        let err = fail("assert_eq", vec![VmValue::string("a"), VmValue::string("b")]).unwrap();
+        assert_eq!(err.to_string(), "`assert_eq` failed: expected \"b\", found \"a\"");
+        assert!(fail("assert_eq", vec![VmValue::Int(4), VmValue::Int(4)]).is_none());
+
+        let err = fail("expect", vec![VmValue::Bool(false), VmValue::string("too slow")]).unwrap();
+        assert!(matches!(&err, VmErrorKind::ExpectationFailed(message) if message == "too slow"));
+        assert!(fail("expect", vec![VmValue::Bool(true), VmValue::string("unused")]).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! Test assertions.
+//!
+//! [`expect_eq`] compares two values and, when they differ, returns a
+//! [`Mismatch`] holding both as text. A mismatch displays as a line diff of
+//! the two (see [`diff`]), so that the line of a large value that changed
+//! stands out rather than the whole value. [`expect`] checks a condition
+//! with a message of its own.
+//!
+//! [`snapshot`] compares text with an approved copy kept in a file,
+//! writing the file when there is none yet.
+//!
+//! `ox test` reports failures of the `assert_eq`, `expect` and
+//! `assert_snapshot` builtins through these, at the call that failed; the
+//! first two are in [`builtins`], for `ox bench` to run too.
+
+use std::fmt;
+
+// The assertions as builtins of both backends
+pub mod builtins;
+// Approved output kept in files
+pub mod snapshot;
+
+pub use snapshot::{SnapshotError, SnapshotOutcome, Snapshots};
+
+/// Two values that should have been equal, as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The value the test expected
+    pub expected: String,
+    /// The value it got
+    pub actual: String,
+}
+
+impl Mismatch {
+    /// The mismatch between `expected` and `actual`.
+    #[must_use]
+    pub fn new(expected: impl Into<String>, actual: impl Into<String>) -> Self {
+        Self { expected: expected.into(), actual: actual.into() }
+    }
+
+    /// A diff from the expected to the actual value, as [`diff`] makes it.
+    #[must_use]
+    pub fn diff(&self) -> String {
+        diff(&self.expected, &self.actual)
+    }
+}
+
+/// Single-line values display as `expected X, found Y`, others as a diff
+/// after a line saying they differ.
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected.contains('\n') || self.actual.contains('\n') {
+            write!(f, "values differ (- expected, + found):\n{}", self.diff())
+        } else {
+            write!(f, "expected {}, found {}", self.expected, self.actual)
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Check that `actual` equals `expected`.
+///
+/// # Errors
+///
+/// Returns the mismatch if they differ, with both values pretty-printed as
+/// `{:#?}` formats them.
+///
+/// ```rust
+/// use oxidex_std::testing::expect_eq;
+///
+/// assert!(expect_eq(&[1, 2], &[1, 2]).is_ok());
+/// let mismatch = expect_eq(&[1, 3], &[1, 2]).unwrap_err();
+/// assert_eq!(mismatch.diff(), "  [\n      1,\n-     2,\n+     3,\n  ]");
+/// ```
+pub fn expect_eq<T: PartialEq + fmt::Debug + ?Sized>(actual: &T, expected: &T) -> Result<(), Mismatch> {
+    if actual == expected { Ok(()) } else { Err(Mismatch::new(format!("{expected:#?}"), format!("{actual:#?}"))) }
+}
+
+/// Check that `condition` holds.
+///
+/// # Errors
+///
+/// Returns `message` if it does not.
+pub fn expect(condition: bool, message: impl Into<String>) -> Result<(), String> {
+    if condition { Ok(()) } else { Err(message.into()) }
+}
+
+/// A line diff from `expected` to `actual`.
+///
+/// Each line of the diff is a line of either text after a marker: `- ` for
+/// a line only `expected` has, `+ ` for one only `actual` has, and two
+/// spaces for one they share. The diff is a longest common subsequence of
+/// the lines, so unchanged lines between changes line up.
+#[must_use]
+pub fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // common[i][j]: length of the longest common subsequence of old[i..]
+    // and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] =
+                if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines_up_unchanged_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nb\nc"), "  a\n  b\n  c");
+        assert_eq!(diff("a\nb\nc", "a\nx\nc\nd"), "  a\n- b\n+ x\n  c\n+ d");
+        assert_eq!(diff("", "a"), "+ a");
+        assert_eq!(diff("a", ""), "- a");
+    }
+
+    #[test]
+    fn test_expectations() {
+        assert_eq!(expect_eq("a", "a"), Ok(()));
+        let mismatch = expect_eq(&4, &5).unwrap_err();
+        assert_eq!(mismatch.to_string(), "expected 5, found 4");
+
+        let mismatch = Mismatch::new("x: 1\ny: 2", "x: 1\ny: 3");
+        assert_eq!(mismatch.to_string(), "values differ (- expected, + found):\n  x: 1\n- y: 2\n+ y: 3");
+        assert_eq!(expect(true, "unused"), Ok(()));
+        assert_eq!(expect(false, "too slow"), Err("too slow".to_string()));
+    }
+}
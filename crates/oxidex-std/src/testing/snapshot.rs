@@ -0,0 +1,160 @@
+//! Snapshot tests.
+//!
+//! A snapshot is the approved output of a test, kept in a file named after
+//! it. [`Snapshots::check`] compares new output with the snapshot: the
+//! first run writes the file, which is then reviewed and committed, and
+//! later runs fail with a diff when the output changes. A collection in
+//! update mode rewrites changed snapshots instead, approving the new
+//! output.
+
+use super::Mismatch;
+use crate::io::IoError;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Extension of snapshot files.
+pub const EXTENSION: &str = "snap";
+
+/// The snapshots in a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshots {
+    dir: PathBuf,
+    update: bool,
+}
+
+/// What checking a snapshot did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotOutcome {
+    /// The output matched the snapshot
+    Matched,
+    /// There was no snapshot, so the output became it
+    Created,
+    /// The output differed and replaced the snapshot, in update mode
+    Updated,
+}
+
+/// Why a snapshot check failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The name cannot name a file: it is empty, starts with `.`, or has a
+    /// character other than a letter, digit, `_`, `-` or `.`
+    InvalidName(String),
+    /// The output differs from the snapshot
+    Mismatch {
+        /// The snapshot's file
+        path: PathBuf,
+        /// The snapshot and the output
+        mismatch: Mismatch,
+    },
+    /// The snapshot could not be read or written
+    Io(IoError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "`{name}` is not a valid snapshot name"),
+            Self::Mismatch { path, mismatch } => {
+                write!(f, "output differs from snapshot {} (- snapshot, + output):\n{}", path.display(), mismatch.diff())
+            }
+            Self::Io(err) => write!(f, "cannot access snapshot: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshots {
+    /// The snapshots in `dir`, which is created when the first is written.
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), update: false }
+    }
+
+    /// The same snapshots, rewriting those that differ from the output if
+    /// `update` is set.
+    #[must_use]
+    pub fn updating(self, update: bool) -> Self {
+        Self { update, ..self }
+    }
+
+    /// The directory the snapshots are in.
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file of the snapshot named `name`.
+    #[must_use]
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{EXTENSION}"))
+    }
+
+    /// Compare `output` with the snapshot named `name`, writing it if there
+    /// is none, or if it differs in update mode.
+    ///
+    /// Snapshot files end in a newline, which is not part of the output, so
+    /// that they are well-formed text files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid, the output differs from the
+    /// snapshot outside update mode, or the file cannot be read or written.
+    pub fn check(&self, name: &str, output: &str) -> Result<SnapshotOutcome, SnapshotError> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            return Err(SnapshotError::InvalidName(name.to_string()));
+        }
+
+        let path = self.path(name);
+        let outcome = match std::fs::read_to_string(&path) {
+            Ok(snapshot) => {
+                let snapshot = snapshot.strip_suffix('\n').unwrap_or(&snapshot);
+                if snapshot == output {
+                    return Ok(SnapshotOutcome::Matched);
+                }
+                if !self.update {
+                    let mismatch = Mismatch::new(snapshot, output);
+                    return Err(SnapshotError::Mismatch { path, mismatch });
+                }
+                SnapshotOutcome::Updated
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => SnapshotOutcome::Created,
+            Err(err) => return Err(SnapshotError::Io(IoError::from_io(&err, Some(&path)))),
+        };
+
+        let io = |err: std::io::Error, path: &Path| SnapshotError::Io(IoError::from_io(&err, Some(path)));
+        std::fs::create_dir_all(&self.dir).map_err(|err| io(err, &self.dir))?;
+        std::fs::write(&path, format!("{output}\n")).map_err(|err| io(err, &path))?;
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_are_created_checked_and_updated() {
+        let dir = std::env::temp_dir().join(format!("oxidex-std-snapshot-{}", std::process::id()));
+        let snapshots = Snapshots::new(dir.join("snapshots"));
+
+        assert_eq!(snapshots.check("greeting", "hello\nworld"), Ok(SnapshotOutcome::Created));
+        assert_eq!(std::fs::read_to_string(snapshots.path("greeting")).unwrap(), "hello\nworld\n");
+        assert_eq!(snapshots.check("greeting", "hello\nworld"), Ok(SnapshotOutcome::Matched));
+
+        let Err(SnapshotError::Mismatch { mismatch, .. }) = snapshots.check("greeting", "hello\nthere") else {
+            panic!("changed output should not match");
+        };
+        assert_eq!(mismatch.diff(), "  hello\n- world\n+ there");
+
+        let updating = snapshots.clone().updating(true);
+        assert_eq!(updating.check("greeting", "hello\nthere"), Ok(SnapshotOutcome::Updated));
+        assert_eq!(snapshots.check("greeting", "hello\nthere"), Ok(SnapshotOutcome::Matched));
+
+        for name in ["", ".hidden", "../escape", "a b"] {
+            assert_eq!(snapshots.check(name, ""), Err(SnapshotError::InvalidName(name.to_string())));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,581 @@
+//! Calendar dates and times.
+//!
+//! A [`DateTime`] is a moment on the wall clock, kept as nanoseconds since
+//! the Unix epoch in UTC, which covers the years 1677 to 2262. The calendar
+//! is the proleptic Gregorian one, and there are no leap seconds.
+//!
+//! Dates are written and read in one of the [`DateFormat`]s rather than
+//! with format strings:
+//!
+//! | Format                  | Example                           |
+//! |-------------------------|-----------------------------------|
+//! | [`DateFormat::Iso8601`] | `2024-03-05T14:07:09.25Z`         |
+//! | [`DateFormat::Rfc2822`] | `Tue, 05 Mar 2024 14:07:09 +0000` |
+//! | [`DateFormat::Date`]    | `2024-03-05`                      |
+//! | [`DateFormat::DateTime`]| `2024-03-05 14:07:09`             |
+//!
+//! Times are always written in UTC. ISO 8601 and RFC 2822 text may carry
+//! another offset, which parsing converts from.
+
+use super::Duration;
+use crate::core::OxString;
+use std::fmt;
+use std::ops::{Add, Sub};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// A day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Weekday {
+    /// Monday
+    Monday,
+    /// Tuesday
+    Tuesday,
+    /// Wednesday
+    Wednesday,
+    /// Thursday
+    Thursday,
+    /// Friday
+    Friday,
+    /// Saturday
+    Saturday,
+    /// Sunday
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+
+    /// The day's name, such as `"Monday"`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Monday => "Monday",
+            Self::Tuesday => "Tuesday",
+            Self::Wednesday => "Wednesday",
+            Self::Thursday => "Thursday",
+            Self::Friday => "Friday",
+            Self::Saturday => "Saturday",
+            Self::Sunday => "Sunday",
+        }
+    }
+
+    /// The first three letters of the day's name.
+    #[must_use]
+    pub fn short_name(self) -> &'static str {
+        &self.name()[..3]
+    }
+}
+
+/// A way of writing a date and time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DateFormat {
+    /// `2024-03-05T14:07:09.25Z`, with a fraction of a second only if
+    /// there is one
+    Iso8601,
+    /// `Tue, 05 Mar 2024 14:07:09 +0000`, as in email and HTTP headers
+    Rfc2822,
+    /// `2024-03-05`, the date alone, read as its midnight
+    Date,
+    /// `2024-03-05 14:07:09`, for people to read
+    DateTime,
+}
+
+impl DateFormat {
+    /// The format's name, as error messages give it.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Iso8601 => "ISO 8601",
+            Self::Rfc2822 => "RFC 2822",
+            Self::Date => "date",
+            Self::DateTime => "date and time",
+        }
+    }
+}
+
+/// Text that is not a valid date in the format it was read in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDateError {
+    /// The text
+    pub text: String,
+    /// The format it was read in
+    pub format: DateFormat,
+}
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot parse {:?} in the {} format", self.text, self.format.name())
+    }
+}
+
+impl std::error::Error for ParseDateError {}
+
+/// A moment on the wall clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DateTime {
+    nanos: i64,
+}
+
+impl DateTime {
+    /// The Unix epoch, 1970-01-01 00:00:00 UTC.
+    pub const UNIX_EPOCH: Self = Self { nanos: 0 };
+
+    /// The system clock now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system clock is set outside the representable years.
+    #[must_use]
+    pub fn now() -> Self {
+        let since_epoch = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(span) => Duration::from(span),
+            Err(err) => -Duration::from(err.duration()),
+        };
+        Self::UNIX_EPOCH + since_epoch
+    }
+
+    /// The moment `nanos` nanoseconds after the Unix epoch.
+    #[must_use]
+    pub const fn from_unix_nanos(nanos: i64) -> Self {
+        Self { nanos }
+    }
+
+    /// The moment `secs` seconds after the Unix epoch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the moment is outside the representable years.
+    #[must_use]
+    pub fn from_unix_secs(secs: i64) -> Self {
+        Self::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// The moment at a date and time in UTC, or `None` if there is no such
+    /// date or time or it is outside the representable years.
+    #[must_use]
+    pub fn from_parts(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<Self> {
+        let valid_date = (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month);
+        if !valid_date || hour >= 24 || minute >= 60 || second >= 60 {
+            return None;
+        }
+        let secs = days_from_civil(year, month, day)
+            .checked_mul(SECS_PER_DAY)?
+            .checked_add(i64::from(hour * 3600 + minute * 60 + second))?;
+        secs.checked_mul(NANOS_PER_SEC).map(Self::from_unix_nanos)
+    }
+
+    /// Nanoseconds since the Unix epoch.
+    #[must_use]
+    pub const fn unix_nanos(self) -> i64 {
+        self.nanos
+    }
+
+    /// Whole seconds since the Unix epoch, rounded down.
+    #[must_use]
+    pub const fn unix_secs(self) -> i64 {
+        self.nanos.div_euclid(NANOS_PER_SEC)
+    }
+
+    fn days(self) -> i64 {
+        self.unix_secs().div_euclid(SECS_PER_DAY)
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn second_of_day(self) -> u32 {
+        self.unix_secs().rem_euclid(SECS_PER_DAY) as u32
+    }
+
+    /// The year.
+    #[must_use]
+    pub fn year(self) -> i64 {
+        civil_from_days(self.days()).0
+    }
+
+    /// The month, from 1 for January.
+    #[must_use]
+    pub fn month(self) -> u32 {
+        civil_from_days(self.days()).1
+    }
+
+    /// The day of the month, from 1.
+    #[must_use]
+    pub fn day(self) -> u32 {
+        civil_from_days(self.days()).2
+    }
+
+    /// The hour, from 0 to 23.
+    #[must_use]
+    pub fn hour(self) -> u32 {
+        self.second_of_day() / 3600
+    }
+
+    /// The minute, from 0 to 59.
+    #[must_use]
+    pub fn minute(self) -> u32 {
+        self.second_of_day() / 60 % 60
+    }
+
+    /// The second, from 0 to 59.
+    #[must_use]
+    pub fn second(self) -> u32 {
+        self.second_of_day() % 60
+    }
+
+    /// Nanoseconds past the second.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn nanosecond(self) -> u32 {
+        self.nanos.rem_euclid(NANOS_PER_SEC) as u32
+    }
+
+    /// The day of the week.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn weekday(self) -> Weekday {
+        // The epoch was a Thursday
+        Weekday::ALL[(self.days() + 3).rem_euclid(7) as usize]
+    }
+
+    /// The day of the year, from 1.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn day_of_year(self) -> u32 {
+        (self.days() - days_from_civil(self.year(), 1, 1) + 1) as u32
+    }
+
+    /// The moment `span` later, or `None` if it is outside the
+    /// representable years.
+    #[must_use]
+    pub fn checked_add(self, span: Duration) -> Option<Self> {
+        self.nanos.checked_add(span.as_nanos()).map(Self::from_unix_nanos)
+    }
+
+    /// The moment written in `format`.
+    #[must_use]
+    pub fn format(self, format: DateFormat) -> OxString {
+        let (year, month, day) = civil_from_days(self.days());
+        let (hour, minute, second) = (self.hour(), self.minute(), self.second());
+        let text = match format {
+            DateFormat::Iso8601 => {
+                let mut text = format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}");
+                if self.nanosecond() != 0 {
+                    let fraction = format!("{:09}", self.nanosecond());
+                    text.push('.');
+                    text.push_str(fraction.trim_end_matches('0'));
+                }
+                text.push('Z');
+                text
+            }
+            DateFormat::Rfc2822 => format!(
+                "{}, {day:02} {} {year:04} {hour:02}:{minute:02}:{second:02} +0000",
+                self.weekday().short_name(),
+                MONTHS[month as usize - 1],
+            ),
+            DateFormat::Date => format!("{year:04}-{month:02}-{day:02}"),
+            DateFormat::DateTime => format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}"),
+        };
+        OxString::new(&text)
+    }
+
+    /// Read a moment written in `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `text` is not a valid date in `format`, or is
+    /// outside the representable years.
+    pub fn parse(text: &str, format: DateFormat) -> Result<Self, ParseDateError> {
+        let mut scanner = Scanner { bytes: text.as_bytes(), pos: 0 };
+        let parsed = match format {
+            DateFormat::Iso8601 => scanner.iso8601(),
+            DateFormat::Rfc2822 => scanner.rfc2822(),
+            DateFormat::Date => {
+                scanner.date().and_then(|(year, month, day)| Self::from_parts(year, month, day, 0, 0, 0))
+            }
+            DateFormat::DateTime => scanner.date_time(b' ', false),
+        };
+        parsed
+            .filter(|_| scanner.pos == scanner.bytes.len())
+            .ok_or_else(|| ParseDateError { text: text.to_string(), format })
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// The moment in ISO 8601.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(DateFormat::Iso8601))
+    }
+}
+
+impl Add<Duration> for DateTime {
+    type Output = Self;
+
+    fn add(self, span: Duration) -> Self {
+        self.checked_add(span).expect("date out of range")
+    }
+}
+
+impl Sub<Duration> for DateTime {
+    type Output = Self;
+
+    fn sub(self, span: Duration) -> Self {
+        self + -span
+    }
+}
+
+impl Sub for DateTime {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        Duration::from_nanos(self.nanos.checked_sub(earlier.nanos).expect("duration out of range"))
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from the Unix epoch to a date, after Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date a number of days from the Unix epoch, the inverse of
+/// [`days_from_civil`].
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A cursor over date text. Each reader returns `None` if the text does not
+/// match, and the position is then meaningless.
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matched = self.peek().is_some_and(|next| next.eq_ignore_ascii_case(&byte));
+        self.pos += usize::from(matched);
+        matched
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    fn expect_str(&mut self, text: &str) -> Option<()> {
+        text.bytes().try_for_each(|byte| self.expect(byte))
+    }
+
+    /// Between `min` and `max` digits as a number.
+    fn number(&mut self, min: usize, max: usize) -> Option<u32> {
+        let start = self.pos;
+        let mut value = 0;
+        while self.pos - start < max
+            && let Some(digit @ b'0'..=b'9') = self.peek()
+        {
+            value = value * 10 + u32::from(digit - b'0');
+            self.pos += 1;
+        }
+        (self.pos - start >= min).then_some(value)
+    }
+
+    /// `YYYY-MM-DD`.
+    fn date(&mut self) -> Option<(i64, u32, u32)> {
+        let year = self.number(4, 4)?;
+        self.expect(b'-')?;
+        let month = self.number(2, 2)?;
+        self.expect(b'-')?;
+        Some((i64::from(year), month, self.number(2, 2)?))
+    }
+
+    /// `HH:MM:SS`, followed by a fraction of a second if `fraction` is set,
+    /// as the time of day in seconds and the fraction in nanoseconds.
+    fn time(&mut self, fraction: bool) -> Option<(u32, u32, u32, i64)> {
+        let hour = self.number(2, 2)?;
+        self.expect(b':')?;
+        let minute = self.number(2, 2)?;
+        self.expect(b':')?;
+        let second = self.number(2, 2)?;
+        let mut nanos = 0;
+        if fraction && self.eat(b'.') {
+            let start = self.pos;
+            let digits = self.number(1, 9)?;
+            let scale = 10_i64.pow(u32::try_from(9 - (self.pos - start)).ok()?);
+            nanos = i64::from(digits) * scale;
+        }
+        Some((hour, minute, second, nanos))
+    }
+
+    /// A date and time separated by `separator`, and the fraction of a
+    /// second if `fraction` is set.
+    fn date_time(&mut self, separator: u8, fraction: bool) -> Option<DateTime> {
+        let (year, month, day) = self.date()?;
+        self.expect(separator)?;
+        let (hour, minute, second, nanos) = self.time(fraction)?;
+        let date = DateTime::from_parts(year, month, day, hour, minute, second)?;
+        date.checked_add(Duration::from_nanos(nanos))
+    }
+
+    /// `±HH:MM` or `±HHMM`, as seconds east of UTC.
+    fn offset(&mut self, colon: bool) -> Option<i64> {
+        let sign = match self.peek()? {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None,
+        };
+        self.pos += 1;
+        let hours = self.number(2, 2)?;
+        if colon {
+            self.expect(b':')?;
+        }
+        let minutes = self.number(2, 2)?;
+        (hours < 24 && minutes < 60).then(|| sign * i64::from(hours * 3600 + minutes * 60))
+    }
+
+    fn iso8601(&mut self) -> Option<DateTime> {
+        let date = self.date_time(b'T', true)?;
+        let offset = if self.eat(b'Z') { 0 } else { self.offset(true)? };
+        date.checked_add(Duration::from_secs(-offset))
+    }
+
+    fn rfc2822(&mut self) -> Option<DateTime> {
+        let weekday = if self.peek()?.is_ascii_alphabetic() {
+            let name = self.bytes.get(self.pos..self.pos + 3)?;
+            let weekday = Weekday::ALL.into_iter().find(|day| day.short_name().as_bytes().eq_ignore_ascii_case(name))?;
+            self.pos += 3;
+            self.expect_str(", ")?;
+            Some(weekday)
+        } else {
+            None
+        };
+        let day = self.number(1, 2)?;
+        self.expect(b' ')?;
+        let name = self.bytes.get(self.pos..self.pos + 3)?;
+        let month = MONTHS.iter().position(|month| month.as_bytes().eq_ignore_ascii_case(name))?;
+        self.pos += 3;
+        self.expect(b' ')?;
+        let year = self.number(4, 4)?;
+        self.expect(b' ')?;
+        let (hour, minute, second, _) = self.time(false)?;
+        self.expect(b' ')?;
+        let offset = match self.peek()? {
+            b'+' | b'-' => self.offset(false)?,
+            _ if self.bytes[self.pos..].eq_ignore_ascii_case(b"GMT") => {
+                self.pos += 3;
+                0
+            }
+            _ => {
+                self.expect_str("UT")?;
+                0
+            }
+        };
+        let month = u32::try_from(month + 1).ok()?;
+        let date = DateTime::from_parts(i64::from(year), month, day, hour, minute, second)?;
+        // The weekday, if given, must be the date's own
+        if weekday.is_some_and(|weekday| weekday != date.weekday()) {
+            return None;
+        }
+        date.checked_add(Duration::from_secs(-offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_fields() {
+        let date = DateTime::from_parts(2024, 2, 29, 23, 59, 30).unwrap();
+        assert_eq!((date.year(), date.month(), date.day()), (2024, 2, 29));
+        assert_eq!((date.hour(), date.minute(), date.second()), (23, 59, 30));
+        assert_eq!((date.weekday(), date.day_of_year()), (Weekday::Thursday, 60));
+        assert_eq!(date.unix_secs(), 1_709_251_170);
+        assert_eq!((date + Duration::from_secs(30)).format(DateFormat::Date), "2024-03-01");
+
+        assert_eq!(DateTime::from_parts(2023, 2, 29, 0, 0, 0), None);
+        assert_eq!(DateTime::from_parts(2024, 1, 1, 24, 0, 0), None);
+        assert_eq!(DateTime::from_parts(3000, 1, 1, 0, 0, 0), None);
+
+        let before_epoch = DateTime::from_unix_nanos(-1);
+        assert_eq!(before_epoch.to_string(), "1969-12-31T23:59:59.999999999Z");
+        assert_eq!(DateTime::UNIX_EPOCH - before_epoch, Duration::from_nanos(1));
+        assert_eq!(DateTime::from_unix_secs(0).weekday().name(), "Thursday");
+        assert!(DateTime::now().year() >= 2024);
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        let date = DateTime::from_parts(2024, 3, 5, 14, 7, 9).unwrap() + Duration::from_millis(250);
+        let written: Vec<OxString> = [DateFormat::Iso8601, DateFormat::Rfc2822, DateFormat::Date, DateFormat::DateTime]
+            .into_iter()
+            .map(|format| date.format(format))
+            .collect();
+        assert_eq!(
+            written,
+            ["2024-03-05T14:07:09.25Z", "Tue, 05 Mar 2024 14:07:09 +0000", "2024-03-05", "2024-03-05 14:07:09"]
+        );
+
+        assert_eq!(DateTime::parse("2024-03-05T14:07:09.25Z", DateFormat::Iso8601), Ok(date));
+        assert_eq!(DateTime::parse("2024-03-05T16:37:09.250+02:30", DateFormat::Iso8601), Ok(date));
+        let whole = date - Duration::from_millis(250);
+        assert_eq!(DateTime::parse("Tue, 05 Mar 2024 14:07:09 +0000", DateFormat::Rfc2822), Ok(whole));
+        assert_eq!(DateTime::parse("5 Mar 2024 09:07:09 -0500", DateFormat::Rfc2822), Ok(whole));
+        assert_eq!(DateTime::parse("Tue, 05 Mar 2024 14:07:09 GMT", DateFormat::Rfc2822), Ok(whole));
+        assert_eq!(DateTime::parse("2024-03-05 14:07:09", DateFormat::DateTime), Ok(whole));
+        assert_eq!(DateTime::parse("2024-03-05", DateFormat::Date).unwrap().hour(), 0);
+
+        for (text, format) in [
+            ("2024-03-05T14:07:09", DateFormat::Iso8601),
+            ("Wed, 05 Mar 2024 14:07:09 +0000", DateFormat::Rfc2822),
+            ("2024-02-30", DateFormat::Date),
+            ("2024-03-05 14:07:09 extra", DateFormat::DateTime),
+        ] {
+            assert_eq!(DateTime::parse(text, format), Err(ParseDateError { text: text.to_string(), format }));
+        }
+        let err = DateTime::parse("soon", DateFormat::Iso8601).unwrap_err();
+        assert_eq!(err.to_string(), "cannot parse \"soon\" in the ISO 8601 format");
+    }
+}
@@ -0,0 +1,274 @@
+//! Spans of time.
+//!
+//! A [`Duration`] counts nanoseconds in an `i64`, so it may be negative, as
+//! the difference between two times can be, and spans about 292 years
+//! either way. Arithmetic that would leave that range panics; the
+//! `checked_` methods return `None` instead.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+const NANOS_PER_MICRO: i64 = 1_000;
+const NANOS_PER_MILLI: i64 = 1_000_000;
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// A signed span of time, to the nanosecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Duration {
+    nanos: i64,
+}
+
+impl Duration {
+    /// The empty span.
+    pub const ZERO: Self = Self { nanos: 0 };
+
+    /// A span of `nanos` nanoseconds.
+    #[must_use]
+    pub const fn from_nanos(nanos: i64) -> Self {
+        Self { nanos }
+    }
+
+    /// A span of `micros` microseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span is out of range.
+    #[must_use]
+    pub fn from_micros(micros: i64) -> Self {
+        Self::scaled(micros, NANOS_PER_MICRO)
+    }
+
+    /// A span of `millis` milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span is out of range.
+    #[must_use]
+    pub fn from_millis(millis: i64) -> Self {
+        Self::scaled(millis, NANOS_PER_MILLI)
+    }
+
+    /// A span of `secs` seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span is out of range.
+    #[must_use]
+    pub fn from_secs(secs: i64) -> Self {
+        Self::scaled(secs, NANOS_PER_SEC)
+    }
+
+    /// A span of `minutes` minutes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span is out of range.
+    #[must_use]
+    pub fn from_minutes(minutes: i64) -> Self {
+        Self::scaled(minutes, 60 * NANOS_PER_SEC)
+    }
+
+    /// A span of `hours` hours.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span is out of range.
+    #[must_use]
+    pub fn from_hours(hours: i64) -> Self {
+        Self::scaled(hours, 3600 * NANOS_PER_SEC)
+    }
+
+    /// A span of `secs` seconds, rounded to the nanosecond and clamped to
+    /// the range.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_secs_f64(secs: f64) -> Self {
+        // Float to int casts saturate, and NaN becomes zero
+        Self { nanos: (secs * 1e9).round() as i64 }
+    }
+
+    fn scaled(count: i64, unit: i64) -> Self {
+        Self { nanos: count.checked_mul(unit).expect("duration out of range") }
+    }
+
+    /// The span in whole nanoseconds.
+    #[must_use]
+    pub const fn as_nanos(self) -> i64 {
+        self.nanos
+    }
+
+    /// The span in whole microseconds, rounded toward zero.
+    #[must_use]
+    pub const fn as_micros(self) -> i64 {
+        self.nanos / NANOS_PER_MICRO
+    }
+
+    /// The span in whole milliseconds, rounded toward zero.
+    #[must_use]
+    pub const fn as_millis(self) -> i64 {
+        self.nanos / NANOS_PER_MILLI
+    }
+
+    /// The span in whole seconds, rounded toward zero.
+    #[must_use]
+    pub const fn as_secs(self) -> i64 {
+        self.nanos / NANOS_PER_SEC
+    }
+
+    /// The span in seconds.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn as_secs_f64(self) -> f64 {
+        self.nanos as f64 / 1e9
+    }
+
+    /// Whether the span is shorter than zero.
+    #[must_use]
+    pub const fn is_negative(self) -> bool {
+        self.nanos < 0
+    }
+
+    /// The span with its sign dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics on the most negative span, which has no positive counterpart.
+    #[must_use]
+    pub fn abs(self) -> Self {
+        Self { nanos: self.nanos.checked_abs().expect("duration out of range") }
+    }
+
+    /// The sum of two spans, or `None` if it is out of range.
+    #[must_use]
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.nanos.checked_add(other.nanos).map(Self::from_nanos)
+    }
+
+    /// The difference of two spans, or `None` if it is out of range.
+    #[must_use]
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.nanos.checked_sub(other.nanos).map(Self::from_nanos)
+    }
+
+    /// The span `factor` times over, or `None` if it is out of range.
+    #[must_use]
+    pub fn checked_mul(self, factor: i64) -> Option<Self> {
+        self.nanos.checked_mul(factor).map(Self::from_nanos)
+    }
+
+    /// The span as a standard library duration, or `None` if it is
+    /// negative.
+    #[must_use]
+    pub fn to_std(self) -> Option<std::time::Duration> {
+        u64::try_from(self.nanos).ok().map(std::time::Duration::from_nanos)
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    /// The span, clamped to the range.
+    fn from(duration: std::time::Duration) -> Self {
+        Self { nanos: i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX) }
+    }
+}
+
+impl fmt::Display for Duration {
+    /// The span in the largest unit it fills, such as `1.5s`, `250ms` or
+    /// `-40ns`.
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let magnitude = self.nanos.unsigned_abs();
+        let (unit, suffix) = match magnitude {
+            m if m >= NANOS_PER_SEC.unsigned_abs() => (NANOS_PER_SEC, "s"),
+            m if m >= NANOS_PER_MILLI.unsigned_abs() => (NANOS_PER_MILLI, "ms"),
+            m if m >= NANOS_PER_MICRO.unsigned_abs() => (NANOS_PER_MICRO, "µs"),
+            _ => return write!(f, "{}ns", self.nanos),
+        };
+        write!(f, "{}{}", self.nanos as f64 / unit as f64, suffix)
+    }
+}
+
+impl Add for Duration {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("duration out of range")
+    }
+}
+
+impl Sub for Duration {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other).expect("duration out of range")
+    }
+}
+
+impl Mul<i64> for Duration {
+    type Output = Self;
+
+    fn mul(self, factor: i64) -> Self {
+        self.checked_mul(factor).expect("duration out of range")
+    }
+}
+
+impl Div<i64> for Duration {
+    type Output = Self;
+
+    /// The span divided into `divisor` parts, rounded toward zero.
+    fn div(self, divisor: i64) -> Self {
+        Self { nanos: self.nanos / divisor }
+    }
+}
+
+impl Neg for Duration {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::ZERO - self
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_units_and_arithmetic() {
+        let span = Duration::from_secs(90) + Duration::from_millis(500);
+        assert_eq!((span.as_secs(), span.as_millis()), (90, 90_500));
+        assert_eq!(span, Duration::from_minutes(1) + Duration::from_secs_f64(30.5));
+        assert_eq!(Duration::from_hours(1) / 60, Duration::from_minutes(1));
+        assert_eq!(Duration::from_micros(3) * 4, Duration::from_nanos(12_000));
+        assert!((Duration::from_secs(1) - span).is_negative());
+        assert_eq!((-span).abs(), span);
+
+        let mut total = Duration::ZERO;
+        total += Duration::from_millis(250);
+        total -= Duration::from_millis(100);
+        assert_eq!(total.as_secs_f64(), 0.15);
+
+        assert_eq!(Duration::from_nanos(i64::MAX).checked_add(Duration::from_nanos(1)), None);
+        assert_eq!(Duration::from_nanos(-1).to_std(), None);
+        let std_span = std::time::Duration::from_millis(7);
+        assert_eq!(Duration::from(std_span).to_std(), Some(std_span));
+
+        let shown: Vec<String> = [Duration::from_millis(1500), Duration::from_millis(250), Duration::from_nanos(1200)]
+            .iter()
+            .chain(&[Duration::from_nanos(-40)])
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(shown, ["1.5s", "250ms", "1.2µs", "-40ns"]);
+    }
+}
@@ -0,0 +1,102 @@
+//! The monotonic clock.
+//!
+//! An [`Instant`] is a reading of a clock that never goes backward and is
+//! unaffected by changes to the system time, so the span between two
+//! readings is how long passed between them. Readings have no meaning on
+//! their own and cannot be shown or stored.
+
+use super::Duration;
+use std::ops::{Add, Sub};
+
+/// A reading of the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Instant {
+    inner: std::time::Instant,
+}
+
+impl Instant {
+    /// The clock now.
+    #[must_use]
+    pub fn now() -> Self {
+        Self { inner: std::time::Instant::now() }
+    }
+
+    /// Time passed since this reading.
+    #[must_use]
+    pub fn elapsed(self) -> Duration {
+        Self::now() - self
+    }
+
+    /// Time from `earlier` to this reading, negative if `earlier` is in
+    /// fact later.
+    #[must_use]
+    pub fn duration_since(self, earlier: Self) -> Duration {
+        match self.inner.checked_duration_since(earlier.inner) {
+            Some(span) => Duration::from(span),
+            None => -Duration::from(earlier.inner - self.inner),
+        }
+    }
+
+    /// The reading `span` later, or `None` if the clock cannot represent
+    /// it.
+    #[must_use]
+    pub fn checked_add(self, span: Duration) -> Option<Self> {
+        let inner = match span.to_std() {
+            Some(span) => self.inner.checked_add(span),
+            None => self.inner.checked_sub((-span).to_std()?),
+        };
+        inner.map(|inner| Self { inner })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    fn add(self, span: Duration) -> Self {
+        self.checked_add(span).expect("instant out of range")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    fn sub(self, span: Duration) -> Self {
+        self + -span
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Self) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Block the current thread for at least `span`. A span that is zero or
+/// negative returns at once.
+pub fn sleep(span: Duration) {
+    if let Some(span) = span.to_std() {
+        std::thread::sleep(span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instants_measure_sleeps() {
+        let start = Instant::now();
+        sleep(Duration::from_millis(20));
+        sleep(Duration::from_millis(-20));
+        let end = Instant::now();
+        assert!(end - start >= Duration::from_millis(20));
+        assert!((start - end).is_negative());
+        assert!(start.elapsed() >= end - start);
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(later - start, Duration::from_secs(5));
+        assert_eq!(later - Duration::from_secs(5), start);
+    }
+}
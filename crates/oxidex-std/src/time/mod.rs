@@ -0,0 +1,25 @@
+//! Time.
+//!
+//! - [`duration`]: signed spans of time and their arithmetic;
+//! - [`instant`]: the monotonic clock, for measuring how long something
+//!   takes, and [`sleep`];
+//! - [`datetime`]: the wall clock, as dates and times in UTC, formatted and
+//!   parsed in a fixed set of [formats](DateFormat).
+//!
+//! An [`Instant`] only ever moves forward and means nothing outside the
+//! process, so benchmarks use it; a [`DateTime`] is what a calendar shows,
+//! and may jump when the system clock is set.
+
+// Spans of time
+pub mod duration;
+
+// The monotonic clock
+pub mod instant;
+
+// Calendar dates and times
+pub mod datetime;
+
+// Re-exports for convenience
+pub use datetime::{DateFormat, DateTime, ParseDateError, Weekday};
+pub use duration::Duration;
+pub use instant::{Instant, sleep};
@@ -1,7 +1,7 @@
 //! Declaration nodes in the `OxideX` AST.
 //!
 //! Declarations represent top-level items in `OxideX` programs: functions,
-//! structs, classes, enums, protocols, and more.
+//! structs, classes, enums, protocols, foreign functions, and more.
 
 use crate::span::{Span, Spanned};
 use oxidex_mem::Symbol;
@@ -32,6 +32,8 @@ pub enum Decl<'arena> {
         body: &'arena super::expr::Expr<'arena>,
         /// Visibility
         visibility: Visibility,
+        /// Attributes written before the function: `@export("name")`
+        attributes: Vec<Attribute>,
         /// Source location
         span: Span,
     },
@@ -79,7 +81,7 @@ pub enum Decl<'arena> {
         /// Variants
         variants: Vec<EnumVariant>,
         /// Methods (can be defined directly in enum body)
-        methods: Vec<FnDecl>,
+        methods: Vec<FnDecl<'arena>>,
         /// Protocol conformances
         protocols: Vec<Vec<Symbol>>,
         /// Visibility
@@ -109,7 +111,7 @@ pub enum Decl<'arena> {
         /// Optional protocol being implemented
         protocol: Option<Vec<Symbol>>,
         /// Methods
-        methods: Vec<FnDecl>,
+        methods: Vec<FnDecl<'arena>>,
         /// Source location
         span: Span,
     },
@@ -157,6 +159,25 @@ pub enum Decl<'arena> {
         /// Source location
         span: Span,
     },
+
+    /// Module import: `import "path/to/module";`
+    Import {
+        /// Module path, as written (a string literal, with its quotes)
+        path: Symbol,
+        /// Source location
+        span: Span,
+    },
+
+    /// Foreign functions: `extern "libm.so.6" { fn cos(x: Float) -> Float; }`
+    Extern {
+        /// Library declaring the functions, without its quotes; `None` for
+        /// the symbols already loaded into the process
+        library: Option<Symbol>,
+        /// Function signatures
+        functions: Vec<ExternFn>,
+        /// Source location
+        span: Span,
+    },
 }
 
 impl Spanned for Decl<'_> {
@@ -170,7 +191,9 @@ impl Spanned for Decl<'_> {
             | Self::Impl { span, .. }
             | Self::Const { span, .. }
             | Self::Static { span, .. }
-            | Self::TypeAlias { span, .. } => *span,
+            | Self::TypeAlias { span, .. }
+            | Self::Import { span, .. }
+            | Self::Extern { span, .. } => *span,
         }
     }
 }
@@ -184,6 +207,17 @@ pub enum Visibility {
     Private,
 }
 
+/// An attribute of a declaration: `@name` or `@name("argument", ...)`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    /// Attribute name
+    pub name: Symbol,
+    /// String literal arguments, without their quotes
+    pub args: Vec<Symbol>,
+    /// Source location
+    pub span: Span,
+}
+
 /// A function parameter: `x: Type` or `label: Type` or `external internal: Type`
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FnParam {
@@ -251,9 +285,23 @@ pub struct ProtocolMethod {
     pub span: Span,
 }
 
+/// A foreign function signature in an `extern` block:
+/// `fn cos(x: Float) -> Float;`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExternFn {
+    /// Function name, which is also the symbol looked up in the library
+    pub name: Symbol,
+    /// Parameters
+    pub params: Vec<FnParam>,
+    /// Return type
+    pub return_type: Option<crate::ast::ty::Type>,
+    /// Source location
+    pub span: Span,
+}
+
 /// A function declaration (standalone, for impl blocks and protocols).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct FnDecl {
+pub struct FnDecl<'arena> {
     /// Is this a mutable method (`mut fn`)?
     pub is_mut: bool,
     /// Is this an initializer (`init`)?
@@ -268,6 +316,8 @@ pub struct FnDecl {
     pub params: Vec<FnParam>,
     /// Return type
     pub return_type: Option<crate::ast::ty::Type>,
+    /// Function body
+    pub body: &'arena crate::ast::expr::Expr<'arena>,
     /// Visibility (resolved to most restrictive of parent and method during semantic analysis)
     pub visibility: Visibility,
     /// Source location
@@ -88,6 +88,15 @@ pub enum Expr<'arena> {
         span: Span,
     },
 
+    /// Typed hole: `???`
+    ///
+    /// A placeholder for an unwritten expression. It checks as any type and
+    /// reports the type expected at its position.
+    Hole {
+        /// Source location
+        span: Span,
+    },
+
     // ===== Identifiers =====
 
     /// Simple identifier: `x`, `myVariable`
@@ -205,6 +214,23 @@ pub enum Expr<'arena> {
         span: Span,
     },
 
+    // ===== Closures =====
+
+    /// Closure: `fn(x: Int) -> Int { x + offset }`
+    ///
+    /// Names the closure uses from the enclosing function are captured; the
+    /// type checker decides how each one is captured.
+    Closure {
+        /// Parameters
+        params: Vec<crate::ast::decl::FnParam>,
+        /// Declared return type (inferred from the body when omitted)
+        return_type: Option<crate::ast::ty::Type>,
+        /// Body block
+        body: &'arena Expr<'arena>,
+        /// Source location
+        span: Span,
+    },
+
     // ===== Struct and Enum Construction =====
 
     /// Struct construction: `Point { x: 0, y: 0 }`
@@ -279,6 +305,18 @@ pub enum Expr<'arena> {
         span: Span,
     },
 
+    /// Compile-time expression: `comptime expr`
+    ///
+    /// The expression is folded to a constant while checking. If it is an
+    /// `if`, its conditions are folded instead, and only the branch they
+    /// select is checked and compiled: `comptime if DEBUG { trace() }`.
+    Comptime {
+        /// Expression evaluated at compile time
+        expr: &'arena Expr<'arena>,
+        /// Source location
+        span: Span,
+    },
+
     /// String interpolation: `"Hello \(name)!"`
     Interpolation {
         /// String parts and interpolations
@@ -296,6 +334,7 @@ impl Spanned for Expr<'_> {
             | Self::StringLiteral { span, .. }
             | Self::BoolLiteral { span, .. }
             | Self::Nil { span }
+            | Self::Hole { span }
             | Self::Path { span, .. }
             | Self::Unary { span, .. }
             | Self::Binary { span, .. }
@@ -306,6 +345,7 @@ impl Spanned for Expr<'_> {
             | Self::WhileLoop { span, .. }
             | Self::Call { span, .. }
             | Self::MethodCall { span, .. }
+            | Self::Closure { span, .. }
             | Self::Struct { span, .. }
             | Self::Enum { span, .. }
             | Self::Array { span, .. }
@@ -313,6 +353,7 @@ impl Spanned for Expr<'_> {
             | Self::Field { span, .. }
             | Self::Index { span, .. }
             | Self::Paren { span, .. }
+            | Self::Comptime { span, .. }
             | Self::Interpolation { span, .. } => *span,
             Self::Identifier(_sym) => {
                 // Identifier doesn't have a direct span - this is a limitation
@@ -102,6 +102,14 @@ pub enum Stmt<'arena> {
         span: Span,
     },
 
+    /// Defer statement: `defer { body }`, run when the enclosing block exits
+    Defer {
+        /// Deferred body
+        body: &'arena super::expr::Expr<'arena>,
+        /// Source location
+        span: Span,
+    },
+
     /// Assignment: `target = value;`
     Assign {
         /// Assignment target (lvalue)
@@ -129,6 +137,7 @@ impl Spanned for Stmt<'_> {
             | Self::Return { span, .. }
             | Self::If { span, .. }
             | Self::Guard { span, .. }
+            | Self::Defer { span, .. }
             | Self::Match { span, .. }
             | Self::ForLoop { span, .. }
             | Self::WhileLoop { span, .. }
@@ -3,9 +3,11 @@
 //! This module provides Rust-style error reporting with source highlighting,
 //! error codes, and helpful suggestions.
 
+use crate::session::SourceMap;
 use crate::{error::SyntaxError, span::Span, Spanned};
 use oxidex_mem::StringInterner;
 use std::fmt;
+use std::fmt::Write as _;
 
 /// A diagnostic message (error, warning, note, or help).
 ///
@@ -144,9 +146,11 @@ pub struct Emitter {
     /// String interner for resolving symbols to strings in diagnostics.
     /// TODO: Use for resolving Symbol identifiers in error messages
     #[allow(dead_code)]
-    interner: StringInterner,
+    interner: Option<StringInterner>,
     /// Use colors in output
     use_colors: bool,
+    /// Name of the file diagnostics are reported in, if known
+    file: Option<String>,
 }
 
 impl Emitter {
@@ -154,57 +158,143 @@ impl Emitter {
     #[must_use] 
     pub fn new(interner: StringInterner, use_colors: bool) -> Self {
         Self {
-            interner,
+            interner: Some(interner),
             use_colors,
+            file: None,
         }
     }
 
-    /// Emits a diagnostic with source highlighting.
+    /// Creates a diagnostic emitter without an interner, for diagnostics
+    /// whose messages already name what they are about.
+    #[must_use]
+    pub const fn plain(use_colors: bool) -> Self {
+        Self {
+            interner: None,
+            use_colors,
+            file: None,
+        }
+    }
+
+    /// Names the file diagnostics are reported in, so each location reads
+    /// `file:line:col` rather than `line:col`.
+    #[must_use]
+    pub fn with_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Emits a diagnostic with source highlighting to standard output.
+    ///
+    /// See [`Emitter::render`] to write it elsewhere.
     pub fn emit(&self, diagnostic: &Diagnostic, source: &str) {
+        print!("{}", self.render(diagnostic, source));
+    }
+
+    /// Formats a diagnostic with source highlighting, as
+    /// [`Emitter::emit`] prints it.
+    #[must_use]
+    pub fn render(&self, diagnostic: &Diagnostic, source: &str) -> String {
+        self.render_located(diagnostic, source, |span| {
+            Self::location(self.file.as_deref(), span)
+        })
+    }
+
+    /// Formats a diagnostic as [`Emitter::render`] does, against the file
+    /// of `sources` its span is in, named as `sources` names it.
+    ///
+    /// Notes are located in the files their own spans are in, so a note
+    /// may point into another file than the diagnostic.
+    #[must_use]
+    pub fn render_in(
+        &self,
+        diagnostic: &Diagnostic,
+        sources: &SourceMap<'_>,
+    ) -> String {
+        let source = sources
+            .find(diagnostic.span)
+            .map_or("", |file| sources.file(file).text());
+        self.render_located(diagnostic, source, |span| {
+            let file = sources.find(span).map(|file| sources.file(file).name());
+            Self::location(file.or(self.file.as_deref()), span)
+        })
+    }
+
+    /// Formats a diagnostic against `source`, with `locate` formatting the
+    /// location of each span.
+    fn render_located(
+        &self,
+        diagnostic: &Diagnostic,
+        source: &str,
+        locate: impl Fn(Span) -> String,
+    ) -> String {
+        let mut out = String::new();
         let span = diagnostic.span;
 
-        // Print primary error message with location and colored level
+        // Primary error message with location and colored level
         let level_str = diagnostic.level.format_colored(self.use_colors);
-        println!(
-            "{}:{}:{}: {}",
-            span.start_line,
-            span.start_col,
+        let _ = writeln!(
+            out,
+            "{}: {}: {}",
+            locate(span),
             level_str,
             diagnostic.message
         );
 
-        // Print error code if present
+        // Error code if present
         if let Some(code) = &diagnostic.code {
-            println!("   [{code}]");
+            let _ = writeln!(out, "   [{code}]");
         }
 
-        // Print source highlighting
-        self.emit_source_highlight(diagnostic.level, span, source);
+        // Source highlighting
+        self.render_source_highlight(&mut out, diagnostic.level, span, source);
 
-        // Print suggestions
+        // Suggestions
         for suggestion in &diagnostic.suggestions {
             let help_prefix = DiagnosticLevel::Help.format_colored(self.use_colors);
-            println!("   {}: {}", help_prefix, suggestion);
+            let _ = writeln!(out, "   {}: {}", help_prefix, suggestion);
         }
 
-        // Print notes
+        // Notes
         for note in &diagnostic.notes {
             let note_prefix = DiagnosticLevel::Note.format_colored(self.use_colors);
-            println!(
-                "   {} at {}:{}: {}",
+            let _ = writeln!(
+                out,
+                "   {} at {}: {}",
                 note_prefix,
-                note.span.start_line,
-                note.span.start_col,
+                locate(note.span),
                 note.message
             );
         }
+        out
     }
 
-    /// Emits source code highlighting for a span.
-    fn emit_source_highlight(&self, level: DiagnosticLevel, span: Span, source: &str) {
+    /// Formats the start of a span as `line:col`, after the name of `file`
+    /// if there is one.
+    ///
+    /// Lines count from one, so a span on line zero has no known location
+    /// and only the file is named.
+    fn location(file: Option<&str>, span: Span) -> String {
+        match file {
+            Some(file) if span.start_line == 0 => file.to_string(),
+            Some(file) => {
+                format!("{file}:{}:{}", span.start_line, span.start_col)
+            }
+            None => format!("{}:{}", span.start_line, span.start_col),
+        }
+    }
+
+    /// Writes source code highlighting for a span.
+    fn render_source_highlight(
+        &self,
+        out: &mut String,
+        level: DiagnosticLevel,
+        span: Span,
+        source: &str,
+    ) {
         let lines: Vec<&str> = source.lines().collect();
 
-        if lines.is_empty() {
+        // Nothing to highlight without a location
+        if lines.is_empty() || span.start_line == 0 {
             return;
         }
 
@@ -216,12 +306,13 @@ impl Emitter {
         let end_line = end_line.min(lines.len() - 1);
 
         // Print each line with highlighting
+        #[allow(clippy::needless_range_loop)]
         for line_idx in start_line..=end_line {
             let line_num = line_idx + 1;
             let line: &str = lines[line_idx];
 
             // Print line number and source
-            println!("{line_num:4} | {line}");
+            let _ = writeln!(out, "{line_num:4} | {line}");
 
             // Calculate highlight positions
             let line_start = if line_idx == start_line {
@@ -245,19 +336,15 @@ impl Emitter {
                     // Use color based on diagnostic level
                     format!(
                         "{}{}{}",
-                        " ".repeat(indent + 6), // 6 for "   | "
+                        " ".repeat(indent),
                         level.color_code(),
                         "^".repeat(width) + DiagnosticLevel::reset_code()
                     )
                 } else {
-                    format!(
-                        "{}{}",
-                        " ".repeat(indent + 6),
-                        "^".repeat(width)
-                    )
+                    format!("{}{}", " ".repeat(indent), "^".repeat(width))
                 };
 
-                println!("     | {underline}");
+                let _ = writeln!(out, "     | {underline}");
             }
         }
     }
@@ -359,6 +446,45 @@ mod tests {
         emitter.emit_syntax_error(&error, source);
     }
 
+    #[test]
+    fn test_render_names_the_file() {
+        let interner = StringInterner::with_pre_interned(keywords::KEYWORDS);
+        let emitter = Emitter::new(interner, false).with_file("main.ox");
+
+        let source = "let x = 42;\nx = 1;";
+        let diagnostic = DiagnosticBuilder::new(
+            DiagnosticLevel::Error,
+            "assignment to immutable variable".to_string(),
+            Span::new(12, 13, 2, 1, 2, 2),
+        )
+        .note("declared here".to_string(), Span::new(4, 5, 1, 5, 1, 6))
+        .build();
+
+        assert_eq!(
+            emitter.render(&diagnostic, source),
+            "main.ox:2:1: error: assignment to immutable variable\n   \
+             2 | x = 1;\n     | ^\n   \
+             note at main.ox:1:5: declared here\n"
+        );
+    }
+
+    #[test]
+    fn test_render_without_a_location() {
+        let interner = StringInterner::with_pre_interned(keywords::KEYWORDS);
+        let emitter = Emitter::new(interner, false).with_file("main.ox");
+        let diagnostic = DiagnosticBuilder::new(
+            DiagnosticLevel::Error,
+            "division by zero".to_string(),
+            Span::new(0, 0, 0, 0, 0, 0),
+        )
+        .build();
+
+        assert_eq!(
+            emitter.render(&diagnostic, "fn main() { 1 / 0 }"),
+            "main.ox: error: division by zero\n"
+        );
+    }
+
     #[test]
     fn test_diagnostic_with_notes() {
         let span = Span::new(0, 10, 1, 1, 1, 11);
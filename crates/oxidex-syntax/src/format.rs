@@ -0,0 +1,447 @@
+//! Source formatter for `OxideX`.
+//!
+//! Unlike the [`pretty`](crate::pretty) printer, which prints an AST and so
+//! loses everything the AST does not keep, the formatter works on the
+//! token stream together with the trivia between tokens: the whitespace and
+//! comments the lexer skips, recovered from the source text between one
+//! token's span and the next. Every token and comment is kept, verbatim and
+//! in order, and the formatter only decides the whitespace around them:
+//!
+//! - Each line is indented by [`INDENT`] once for every line that opened a
+//!   bracket still open, and once more if it continues an expression from
+//!   the line before: if it starts with a binary operator, or with a `.`
+//!   call it was indented under that line for
+//! - Tokens on a line are separated by single spaces, except where they
+//!   bind tightly: before `,`, `;`, `:` and closing brackets, after opening
+//!   brackets and prefix operators, around `.` and `::`, before the `(`
+//!   or `[` of a call or index, and around the `\(` and `)` of a string
+//!   interpolation
+//! - Line breaks stay where they were, runs of blank lines become one, and
+//!   blank lines right inside braces go, as does trailing whitespace
+//!
+//! `<` and `>` are both comparisons and generic brackets, and `|`, `&` and
+//! `..` have several roles too, which tokens alone cannot tell apart; the
+//! spacing around them is kept as written.
+//!
+//! # Examples
+//!
+//! ```
+//! use oxidex_syntax::format::format_source;
+//!
+//! let source = "fn  main ( ) {\nlet x=1+2 // three\n\n\n  print( x )}";
+//! let formatted = format_source(source).unwrap();
+//! assert_eq!(
+//!     formatted,
+//!     "fn main() {\n    let x = 1 + 2 // three\n\n    print(x) }\n"
+//! );
+//! ```
+
+use crate::error::LexerResult;
+use crate::lexer::Lexer;
+use crate::token::TokenKind;
+
+/// Indentation of one nesting level.
+pub const INDENT: &str = "    ";
+
+/// Formats `source`, keeping its tokens and comments.
+///
+/// The result is stable: formatting it again changes nothing.
+///
+/// # Errors
+///
+/// Returns an error if the source does not lex. Source that lexes but does
+/// not parse is formatted all the same.
+pub fn format_source(source: &str) -> LexerResult<String> {
+    let tokens = Lexer::new(source).lex()?;
+    let mut formatter = Formatter {
+        out: String::with_capacity(source.len()),
+        open: Vec::new(),
+        line_indent: 0,
+        source_indent: 0,
+        at_line_start: true,
+        blank_line: false,
+        last: None,
+        after_comment: false,
+    };
+
+    let mut end = 0;
+    for token in &tokens {
+        let is_eof = token.kind == TokenKind::EOF;
+        let start = if is_eof { source.len() } else { token.span.start };
+        let gap = &source[end..start.max(end)];
+        formatter.trivia(gap);
+        if is_eof {
+            break;
+        }
+        let text = &source[token.span.start..token.span.end];
+        formatter.token(&token.kind, text, gap);
+        end = token.span.end;
+    }
+
+    let mut out = formatter.out;
+    out.truncate(out.trim_end().len());
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The last token written.
+struct Last {
+    /// Its kind
+    kind: TokenKind,
+    /// Whether it is a prefix operator
+    prefix: bool,
+    /// Whether it is a postfix `?` or `!`
+    postfix: bool,
+}
+
+impl Last {
+    /// Whether the token ends an operand, so that what follows it applies
+    /// to it: a call, an index, a field or a postfix operator.
+    fn ends_operand(&self) -> bool {
+        self.postfix || ends_operand(&self.kind)
+    }
+}
+
+/// Formatting state.
+struct Formatter {
+    /// The formatted source so far
+    out: String,
+    /// For each bracket still open, the indentation of the line it opened
+    /// on
+    open: Vec<usize>,
+    /// The indentation of the current line
+    line_indent: usize,
+    /// The indentation of the current line in the source, in bytes
+    source_indent: usize,
+    /// Whether nothing was written on the current line yet
+    at_line_start: bool,
+    /// Whether a blank line came before the current line
+    blank_line: bool,
+    /// The last token written
+    last: Option<Last>,
+    /// Whether a comment was written after the last token
+    after_comment: bool,
+}
+
+impl Formatter {
+    /// Writes the comments in `gap`, the text between two tokens, and the
+    /// line breaks around them.
+    fn trivia(&mut self, gap: &str) {
+        let mut rest = gap;
+        while let Some(c) = rest.chars().next() {
+            if c == '\n' {
+                // Count the line breaks in this run of whitespace
+                let run_end = rest
+                    .find(|c: char| !c.is_whitespace())
+                    .unwrap_or(rest.len());
+                let breaks = rest[..run_end].matches('\n').count();
+                self.line_break(breaks);
+                rest = &rest[run_end..];
+            } else if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+            } else {
+                let len = comment_len(rest);
+                let comment = rest[..len].trim_end();
+                self.comment(comment);
+                rest = &rest[len..];
+            }
+        }
+    }
+
+    /// Ends the current line, after `breaks` line breaks in the source.
+    fn line_break(&mut self, breaks: usize) {
+        if !self.at_line_start {
+            self.out.push('\n');
+            self.at_line_start = true;
+        }
+        if breaks > 1 && !self.out.is_empty() {
+            self.blank_line = true;
+        }
+    }
+
+    /// Writes a comment.
+    fn comment(&mut self, comment: &str) {
+        if self.at_line_start {
+            let indent = self.open.last().map_or(0, |indent| indent + 1);
+            self.start_line(indent, false);
+        } else {
+            self.out.push(' ');
+        }
+        self.out.push_str(comment);
+        self.after_comment = true;
+    }
+
+    /// Writes a token, which came after `gap` in the source.
+    fn token(&mut self, kind: &TokenKind, text: &str, gap: &str) {
+        let closes = matches!(
+            kind,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace
+        );
+        let operand = self.last.as_ref().is_some_and(Last::ends_operand);
+
+        if self.at_line_start {
+            // The gap ends with the token's line, up to the token
+            let source_indent = gap.rsplit('\n').next().map_or(0, str::len);
+            let continues = match kind {
+                // Otherwise the `.` starts an enum case, in a new statement
+                TokenKind::Dot => source_indent > self.source_indent,
+                _ => continues(kind),
+            };
+            let indent = if closes {
+                self.open.last().copied().unwrap_or(0)
+            } else {
+                let base = self.open.last().map_or(0, |indent| indent + 1);
+                base + usize::from(operand && continues)
+            };
+            self.start_line(indent, closes);
+            if !continues {
+                self.source_indent = source_indent;
+            }
+        } else if self.after_comment
+            || self.spaced(kind, operand, !gap.is_empty())
+        {
+            self.out.push(' ');
+        }
+
+        self.out.push_str(text);
+        self.after_comment = false;
+        match kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => {
+                self.open.push(self.line_indent);
+            }
+            _ if closes => {
+                self.open.pop();
+            }
+            _ => {}
+        }
+        let postfix =
+            operand && matches!(kind, TokenKind::Question | TokenKind::Bang);
+        let prefix = !operand
+            && matches!(kind, TokenKind::Minus | TokenKind::Bang);
+        self.last = Some(Last { kind: kind.clone(), prefix, postfix });
+    }
+
+    /// Starts a line indented `indent` levels, after a blank line if the
+    /// source had one there, unless it is right inside braces.
+    fn start_line(&mut self, indent: usize, closes: bool) {
+        let opened = self.last.as_ref().is_some_and(|last| {
+            matches!(last.kind, TokenKind::LBrace) && !self.after_comment
+        });
+        if self.blank_line && !closes && !opened {
+            self.out.push('\n');
+        }
+        self.blank_line = false;
+        self.out.push_str(&INDENT.repeat(indent));
+        self.line_indent = indent;
+        self.at_line_start = false;
+    }
+
+    /// Whether a space goes between the last token and `next`, on one line.
+    fn spaced(&self, next: &TokenKind, operand: bool, spaced: bool) -> bool {
+        let Some(last) = &self.last else {
+            return false;
+        };
+        if keeps_spacing(&last.kind) || keeps_spacing(next) {
+            return spaced;
+        }
+        let tight_after = last.prefix
+            || matches!(
+                last.kind,
+                TokenKind::LParen
+                    | TokenKind::LBracket
+                    | TokenKind::Dot
+                    | TokenKind::ColonColon
+                    | TokenKind::At
+                    | TokenKind::InterpolationStart
+                    | TokenKind::InterpolationEnd
+            );
+        if tight_after {
+            return false;
+        }
+        match next {
+            TokenKind::RParen
+            | TokenKind::InterpolationStart
+            | TokenKind::InterpolationEnd
+            | TokenKind::RBracket
+            | TokenKind::Comma
+            | TokenKind::Semicolon
+            | TokenKind::Colon
+            | TokenKind::ColonColon => false,
+            // The parameters of a function type follow `fn`
+            TokenKind::LParen => !operand && last.kind != TokenKind::Fn,
+            TokenKind::LBracket | TokenKind::Dot => !operand,
+            TokenKind::Question | TokenKind::Bang => !operand,
+            TokenKind::RBrace => last.kind != TokenKind::LBrace,
+            _ => true,
+        }
+    }
+}
+
+/// Whether a token of kind `kind` ends an operand.
+fn ends_operand(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Ident(_)
+            | TokenKind::IntegerLiteral(..)
+            | TokenKind::FloatLiteral(..)
+            | TokenKind::StringLiteral(_)
+            | TokenKind::BoolLiteral(_)
+            | TokenKind::Nil
+            | TokenKind::Hole
+            | TokenKind::Underscore
+            | TokenKind::SelfValue
+            | TokenKind::SelfType
+            | TokenKind::Init
+            | TokenKind::RParen
+            | TokenKind::RBracket
+    )
+}
+
+/// Whether a line starting with the binary operator `kind`, after an
+/// operand, continues the expression on the line before.
+fn continues(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Plus
+            | TokenKind::Star
+            | TokenKind::Slash
+            | TokenKind::Percent
+            | TokenKind::EqEq
+            | TokenKind::BangEq
+            | TokenKind::LtEq
+            | TokenKind::GtEq
+            | TokenKind::AmpAmp
+            | TokenKind::PipePipe
+    )
+}
+
+/// Whether the spacing around tokens of kind `kind` is kept as written,
+/// since their role depends on more than the tokens around them.
+fn keeps_spacing(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LAngle
+            | TokenKind::RAngle
+            | TokenKind::Pipe
+            | TokenKind::Amp
+            | TokenKind::DotDot
+    )
+}
+
+/// The length of the comment `text` starts with: to the end of the line,
+/// or past the `*/` closing a block comment, which may nest.
+fn comment_len(text: &str) -> usize {
+    if !text.starts_with("/*") {
+        return text.find('\n').unwrap_or(text.len());
+    }
+    let mut depth = 0usize;
+    let mut i = 0;
+    let bytes = text.as_bytes();
+    while i + 1 < bytes.len() {
+        match &bytes[i..i + 2] {
+            b"/*" => {
+                depth += 1;
+                i += 2;
+            }
+            b"*/" => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source).unwrap()
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(
+            format("let   x=foo (a,b)[0].bar ( )+-y"),
+            "let x = foo(a, b)[0].bar() + -y\n"
+        );
+        assert_eq!(
+            format("fn f<T>( a : [ T ] )->T?{ a[0]! }"),
+            "fn f<T>(a: [T]) -> T? { a[0]! }\n"
+        );
+        assert_eq!(
+            format("if !done&&x<y { Counter::new( ) } else {}"),
+            "if !done && x<y { Counter::new() } else {}\n"
+        );
+        assert_eq!(
+            format("@export( \"f\" ) fn f() { x = .circle { radius: 1.0 } }"),
+            "@export(\"f\") fn f() { x = .circle { radius: 1.0 } }\n"
+        );
+        assert_eq!(format("for i in 0..n { }"), "for i in 0..n {}\n");
+        assert_eq!(format("let f: fn (Int)->Int"), "let f: fn(Int) -> Int\n");
+        assert_eq!(
+            format(r#"print( "a \(x+1)b\(f( y ))" )"#),
+            "print(\"a \\(x + 1)b\\(f(y))\")\n"
+        );
+    }
+
+    #[test]
+    fn test_format_lines_and_indentation() {
+        let source = "\n\nfn main() {\n\n  let x = foo(1,\n2)\n\
+                      let y = x\n    .bar()\n  && z\n\n\n\n   print(y)\n\
+                      .ok(y)\n\n}\n\n";
+        assert_eq!(
+            format(source),
+            "fn main() {\n    let x = foo(1,\n        2)\n    let y = x\n\
+             \x20       .bar()\n        && z\n\n    print(y)\n    .ok(y)\n}\n"
+        );
+        let nested = "match x {\n.a => {\nf()\n},\n}";
+        assert_eq!(
+            format(nested),
+            "match x {\n    .a => {\n        f()\n    },\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "// header\nfn f() { // opens\n/* block /* nested */ */\n\
+                      x /* inline */ + 1 // trailing  \n  // last\n}";
+        assert_eq!(
+            format(source),
+            "// header\nfn f() { // opens\n    /* block /* nested */ */\n    \
+             x /* inline */ + 1 // trailing\n    // last\n}\n"
+        );
+        assert_eq!(format(""), "");
+        assert_eq!(format("// only a comment"), "// only a comment\n");
+    }
+
+    #[test]
+    fn test_format_is_stable_and_keeps_tokens() {
+        let examples =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../../examples");
+        let mut seen = 0;
+        for entry in std::fs::read_dir(examples).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            let Ok(tokens) = Lexer::new(&source).lex() else {
+                continue;
+            };
+            let formatted = format(&source);
+            assert_eq!(format(&formatted), formatted, "{}", path.display());
+            let kinds = |tokens: Vec<crate::Token>| -> Vec<TokenKind> {
+                tokens.into_iter().map(|token| token.kind).collect()
+            };
+            let reformatted = Lexer::new(&formatted).lex().unwrap();
+            assert_eq!(kinds(reformatted), kinds(tokens), "{}", path.display());
+            seen += 1;
+        }
+        assert!(seen > 0);
+    }
+}
@@ -111,6 +111,33 @@ impl<'input> Lexer<'input> {
         }
     }
 
+    /// Creates a new lexer that interns into an existing interner.
+    ///
+    /// Symbols already in `interner` keep their IDs, so the tokens of
+    /// several sources lexed one after another, each with the interner the
+    /// previous one left, can be compared. The interner must come from a
+    /// lexer, or a copy of one, so that keywords have their reserved IDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oxidex_syntax::lexer::Lexer;
+    ///
+    /// let (_, interner) = Lexer::new("let x = 1").lex_with_interner().unwrap();
+    /// let x = interner.get_symbol("x");
+    /// let (_, interner) = Lexer::with_interner("let y = x", interner)
+    ///     .lex_with_interner()
+    ///     .unwrap();
+    /// assert_eq!(interner.get_symbol("x"), x);
+    /// ```
+    #[must_use]
+    pub fn with_interner(input: &'input str, interner: StringInterner) -> Self {
+        Self {
+            interner,
+            ..Self::new(input)
+        }
+    }
+
     /// Tokenizes the entire source code.
     ///
     /// Returns a vector of tokens or a vector of errors. The lexer attempts
@@ -137,33 +164,7 @@ impl<'input> Lexer<'input> {
     /// Returns a `LexerError` if the source contains invalid characters that
     /// cannot be recovered from.
     pub fn lex(mut self) -> LexerResult<Vec<Token>> {
-        // Initialize the character iterator
-        self.chars = Some(self.input.chars().peekable());
-
-        // Main tokenization loop
-        while self.peek().is_some() {
-            // Skip whitespace
-            self.skip_whitespace();
-
-            // Check for EOF
-            if self.peek().is_none() {
-                break;
-            }
-
-            // Get next token
-            match self.next_token() {
-                Ok(token) => self.tokens.push(token),
-                Err(err) => {
-                    self.errors.push(err);
-                    // Attempt recovery by skipping to next known token
-                    self.recover();
-                }
-            }
-        }
-
-        // Add EOF token
-        let eof_span = Span::point(self.position, self.line, self.column);
-        self.tokens.push(Token::new(TokenKind::EOF, eof_span));
+        self.tokenize();
 
         // Return result
         if self.errors.is_empty() {
@@ -259,13 +260,52 @@ impl<'input> Lexer<'input> {
     pub fn lex_with_interner(
         mut self,
     ) -> LexerResult<(Vec<Token>, StringInterner)> {
+        self.tokenize();
+
+        // Return result with interner
+        if self.errors.is_empty() {
+            Ok((self.tokens, self.interner))
+        } else {
+            // Return first error for now (we could enhance this to return all errors)
+            Err(self.errors.into_iter().next().unwrap())
+        }
+    }
+
+    /// Tokenizes the source code, recovering from every error, and returns
+    /// the tokens, the errors and the interner.
+    ///
+    /// Unlike [`Lexer::lex_with_interner`], the interner comes back even if
+    /// the source has errors, so that a driver lexing several files into
+    /// one interner can go on to the next, and every error is returned, not
+    /// only the first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use oxidex_syntax::Lexer;
+    ///
+    /// let lexer = Lexer::new("let x = 1 $ 2 $");
+    /// let (tokens, errors, interner) = lexer.lex_all();
+    /// assert_eq!(errors.len(), 2);
+    /// assert!(interner.get_symbol("x").is_some());
+    /// assert!(!tokens.is_empty());
+    /// ```
+    #[must_use]
+    pub fn lex_all(mut self) -> (Vec<Token>, Vec<LexerError>, StringInterner) {
+        self.tokenize();
+        (self.tokens, self.errors, self.interner)
+    }
+
+    /// Runs the tokenization loop over the whole input, ending the tokens
+    /// with `EOF`.
+    fn tokenize(&mut self) {
         // Initialize the character iterator
         self.chars = Some(self.input.chars().peekable());
 
         // Main tokenization loop
         while self.peek().is_some() {
-            // Skip whitespace
-            self.skip_whitespace();
+            // Skip whitespace and comments
+            self.skip_trivia();
 
             // Check for EOF
             if self.peek().is_none() {
@@ -286,14 +326,6 @@ impl<'input> Lexer<'input> {
         // Add EOF token
         let eof_span = Span::point(self.position, self.line, self.column);
         self.tokens.push(Token::new(TokenKind::EOF, eof_span));
-
-        // Return result with interner
-        if self.errors.is_empty() {
-            Ok((self.tokens, self.interner))
-        } else {
-            // Return first error for now (we could enhance this to return all errors)
-            Err(self.errors.into_iter().next().unwrap())
-        }
     }
 
     /// Peeks at the next character without consuming it.
@@ -339,6 +371,22 @@ impl<'input> Lexer<'input> {
         }
     }
 
+    /// Skips whitespace and comments, so that a comment ending the source
+    /// leaves no token to read.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match (self.peek(), self.peek2()) {
+                (Some('/'), Some('/')) => self.read_line_comment(),
+                (Some('/'), Some('*')) => {
+                    self.bump(); // Consume '/'
+                    self.read_block_comment();
+                }
+                _ => break,
+            }
+        }
+    }
+
     /// Reads the next token from the source.
     #[allow(clippy::too_many_lines)]
     fn next_token(&mut self) -> LexerResult<Token> {
@@ -355,19 +403,16 @@ impl<'input> Lexer<'input> {
         let kind = match ch {
             // Underscore (wildcard pattern)
             '_' => {
-                self.bump();
-                // Check if it's just "_" or the start of an identifier
-                if let Some(next_ch) = self.peek() {
-                    if next_ch.is_alphanumeric() || next_ch == '_' {
-                        // It's the start of an identifier
+                // Check if it's just "_" or the start of an identifier,
+                // which keeps its leading underscore
+                match self.peek2() {
+                    Some(next) if next.is_alphanumeric() || next == '_' => {
                         self.read_identifier()
-                    } else {
-                        // Just a standalone underscore
+                    }
+                    _ => {
+                        self.bump();
                         TokenKind::Underscore
                     }
-                } else {
-                    // Just underscore at EOF
-                    TokenKind::Underscore
                 }
             }
 
@@ -378,7 +423,7 @@ impl<'input> Lexer<'input> {
             '0'..='9' => self.read_number(),
 
             // String literals
-            '"' => self.read_string(),
+            '"' => return Ok(self.read_string()),
 
             // Character literals (if we support them)
             // '\'' => self.read_char(),
@@ -401,23 +446,9 @@ impl<'input> Lexer<'input> {
                 self.bump();
                 TokenKind::Star
             }
+            // Comments were skipped before the token
             '/' => {
                 self.bump();
-                if let Some('/') = self.peek() {
-                    // Line comment
-                    self.read_line_comment();
-                    // Skip whitespace including the newline after comment
-                    self.skip_whitespace();
-                    // Return a token after the comment
-                    return self.next_token();
-                } else if let Some('*') = self.peek() {
-                    // Block comment
-                    self.read_block_comment();
-                    // Skip whitespace after comment
-                    self.skip_whitespace();
-                    // Return a token after the comment
-                    return self.next_token();
-                }
                 TokenKind::Slash
             }
             '%' => {
@@ -531,9 +562,19 @@ impl<'input> Lexer<'input> {
                 self.bump();
                 TokenKind::Semicolon
             }
+            '@' => {
+                self.bump();
+                TokenKind::At
+            }
             '?' => {
                 self.bump();
-                TokenKind::Question
+                if self.peek() == Some('?') && self.peek2() == Some('?') {
+                    self.bump();
+                    self.bump();
+                    TokenKind::Hole
+                } else {
+                    TokenKind::Question
+                }
             }
             _ => {
                 // Unknown character
@@ -597,14 +638,19 @@ impl<'input> Lexer<'input> {
             "impl" => TokenKind::Impl,
             "return" => TokenKind::Return,
             "if" => TokenKind::If,
+            "else" => TokenKind::Else,
             "guard" => TokenKind::Guard,
+            "defer" => TokenKind::Defer,
             "match" => TokenKind::Match,
             "for" => TokenKind::For,
+            "in" => TokenKind::In,
             "while" => TokenKind::While,
             "comptime" => TokenKind::Comptime,
             "const" => TokenKind::Const,
             "static" => TokenKind::Static,
             "type" => TokenKind::Type,
+            "import" => TokenKind::Import,
+            "extern" => TokenKind::Extern,
             "pub" => TokenKind::Pub,
             "prv" => TokenKind::Prv,
             "self" => TokenKind::SelfValue,
@@ -762,87 +808,115 @@ impl<'input> Lexer<'input> {
         None
     }
 
-    /// Reads a string literal, potentially with interpolation.
-    fn read_string(&mut self) -> TokenKind {
-        let start = self.position;
+    /// Reads a string literal, splitting it at its interpolations.
+    ///
+    /// Each `\(...)` in the literal ends the string literal token of the
+    /// text before it, which keeps the opening quote if it is the first,
+    /// and is lexed as `InterpolationStart`, the tokens of the expression,
+    /// and `InterpolationEnd`. The text after the last interpolation, up to
+    /// and including the closing quote, is the token returned.
+    fn read_string(&mut self) -> Token {
+        let mut start = (self.position, self.line, self.column);
         self.bump(); // Consume opening quote
 
-        // Check if this is an empty string (next char is closing quote or EOF)
-        if let Some(ch) = self.peek() {
-            if ch == '"' {
-                // Empty string ""
-                self.bump(); // Consume closing quote
-                let sym =
-                    self.interner.intern(&self.input[start..self.position]);
-                return TokenKind::StringLiteral(sym);
-            }
-        } else {
-            // EOF - unterminated string
-            let sym = self.interner.intern(&self.input[start..self.position]);
-            return TokenKind::StringLiteral(sym);
-        }
-
-        // Non-empty string - parse contents
         while let Some(ch) = self.peek() {
-            if ch == '"' {
-                self.bump();
-                break;
-            } else if ch == '\\' {
-                // Escape sequence
-                self.bump();
-                if self.peek().is_some() {
+            match ch {
+                '"' => {
                     self.bump();
+                    break;
                 }
-            } else if ch == '(' && self.position > start {
-                // Check for interpolation start: "\("
-                // Look back to see if previous char was '\'
-                let prev_byte = self.input.as_bytes()[self.position - 1];
-                if prev_byte == b'\\' {
-                    // It's an escaped '('
+                '\\' if self.peek2() == Some('(') => {
+                    let text = self.string_part(start);
+                    self.tokens.push(text);
+                    self.read_interpolation();
+                    start = (self.position, self.line, self.column);
+                }
+                '\\' => {
+                    // Escape sequence
+                    self.bump();
+                    self.bump();
+                }
+                '\n' => {
+                    // Unterminated string
+                    // We'll still consume it and return what we have
+                    self.bump();
+                    break;
+                }
+                _ => {
                     self.bump();
-                } else {
-                    // String interpolation start!
-                    // For now, we'll handle this as multiple tokens
-                    // Return the string part so far as a StringLiteral token
-                    // The parser will handle combining interpolation parts
-
-                    // For simplicity in the current implementation, just consume it
-                    // In a full implementation, this would break into:
-                    // - StringEnd token
-                    // - InterpolationStart token
-                    // - expression tokens
-                    // - InterpolationEnd token
-                    // - StringStart token
-
-                    // For now, just continue and treat it as part of the string
-                    self.bump(); // Consume '('
-
-                    // Consume until closing ')'
-                    let mut depth = 1;
-                    while depth > 0 && self.peek().is_some() {
-                        if let Some('(') = self.peek() {
-                            depth += 1;
-                            self.bump();
-                        } else if let Some(')') = self.peek() {
-                            depth -= 1;
-                            self.bump();
-                        } else {
-                            self.bump();
-                        }
-                    }
                 }
-            } else if ch == '\n' {
-                // Unterminated string
-                // We'll still consume it and return what we have
-                self.bump();
-                break;
-            } else {
-                self.bump();
             }
         }
 
+        self.string_part(start)
+    }
+
+    /// The string literal token of the text of a string from `start` on.
+    fn string_part(&mut self, start: (usize, usize, usize)) -> Token {
+        let (start, start_line, start_col) = start;
         let sym = self.interner.intern(&self.input[start..self.position]);
-        TokenKind::StringLiteral(sym)
+        let span = Span::new(
+            start,
+            self.position,
+            start_line,
+            start_col,
+            self.line,
+            self.column,
+        );
+        Token::new(TokenKind::StringLiteral(sym), span)
+    }
+
+    /// Reads an interpolation, from its `\(` to the `)` that closes it,
+    /// into the tokens.
+    fn read_interpolation(&mut self) {
+        let delimiter = |lexer: &mut Self, kind, len| {
+            let (start, line, column) = (lexer.position, lexer.line, lexer.column);
+            for _ in 0..len {
+                lexer.bump();
+            }
+            let span = Span::new(
+                start,
+                lexer.position,
+                line,
+                column,
+                lexer.line,
+                lexer.column,
+            );
+            lexer.tokens.push(Token::new(kind, span));
+            span
+        };
+        let open = delimiter(self, TokenKind::InterpolationStart, 2);
+
+        // Parentheses opened inside the interpolation
+        let mut depth = 0usize;
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None => {
+                    self.errors
+                        .push(LexerError::UnterminatedInterpolation { start: open });
+                    return;
+                }
+                Some(')') if depth == 0 => {
+                    delimiter(self, TokenKind::InterpolationEnd, 1);
+                    return;
+                }
+                Some(_) => match self.next_token() {
+                    Ok(token) => {
+                        match token.kind {
+                            TokenKind::LParen => depth += 1,
+                            TokenKind::RParen => depth = depth.saturating_sub(1),
+                            _ => {}
+                        }
+                        self.tokens.push(token);
+                    }
+                    Err(err) => {
+                        self.errors.push(err);
+                        self.recover();
+                    }
+                },
+            }
+        }
     }
 
     /// Reads a line comment (consumes to end of line).
@@ -913,6 +987,66 @@ impl<'input> Lexer<'input> {
     }
 }
 
+/// Returns the text a string literal token stands for.
+///
+/// String literal tokens keep their source text, quotes and escapes
+/// included; every backend decodes them here. The escapes are `\n`, `\t`,
+/// `\r`, `\0`, `\\`, `\"`, `\'` and `\u{...}`. Any other backslash, such as
+/// the `\(` that starts an interpolation, is kept as written.
+///
+/// # Examples
+///
+/// ```
+/// use oxidex_syntax::lexer::string_value;
+///
+/// assert_eq!(string_value(r#""hi""#), "hi");
+/// assert_eq!(string_value(r#""a\nb \"c\"""#), "a\nb \"c\"");
+/// assert_eq!(string_value(r#""\u{e9}""#), "\u{e9}");
+/// ```
+#[must_use]
+pub fn string_value(token: &str) -> String {
+    // An unterminated literal has only its opening quote
+    let body = token.strip_prefix('"').map_or(token, |body| {
+        body.strip_suffix('"').unwrap_or(body)
+    });
+
+    let mut value = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        let rest = chars.as_str();
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('0') => value.push('\0'),
+            Some(quoted @ ('\\' | '"' | '\'')) => value.push(quoted),
+            Some('u') if let Some((decoded, len)) = unicode_escape(rest) => {
+                value.push(decoded);
+                chars = rest[len..].chars();
+            }
+            Some(other) => {
+                value.push('\\');
+                value.push(other);
+            }
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+/// Decodes the `u{...}` of a `\u{...}` escape at the start of `text`,
+/// returning the character and the length of the escape.
+fn unicode_escape(text: &str) -> Option<(char, usize)> {
+    let digits = text.strip_prefix("u{")?;
+    let end = digits.find('}')?;
+    let code = u32::from_str_radix(&digits[..end], 16).ok()?;
+    Some((char::from_u32(code)?, "u{".len() + end + 1))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1037,6 +1171,17 @@ mod tests {
         assert_eq!(result[5].kind, TokenKind::RBracket);
     }
 
+    #[test]
+    fn test_lexer_hole() {
+        let source = "??? ?";
+        let lexer = Lexer::new(source);
+        let result = lexer.lex().unwrap();
+
+        assert_eq!(result[0].kind, TokenKind::Hole);
+        assert_eq!(result[0].span.end - result[0].span.start, 3);
+        assert_eq!(result[1].kind, TokenKind::Question);
+    }
+
     #[test]
     fn test_lexer_line_comment() {
         let source = "let x = 42 // this is a comment\nlet y";
@@ -1049,6 +1194,16 @@ mod tests {
         assert_eq!(result[4].kind, TokenKind::Let);
     }
 
+    #[test]
+    fn test_lexer_comment_at_end_of_source() {
+        for source in ["x // trailing", "x /* trailing */", "// only"] {
+            let result = Lexer::new(source).lex().unwrap();
+            assert_eq!(result.last().unwrap().kind, TokenKind::EOF);
+        }
+        let result = Lexer::new("x / y").lex().unwrap();
+        assert_eq!(result[1].kind, TokenKind::Slash);
+    }
+
     #[test]
     fn test_lexer_bool_literals() {
         let source = "true false nil";
@@ -1238,26 +1393,54 @@ mod tests {
         let lexer = Lexer::new(source);
         let result = lexer.lex().unwrap();
 
-        // Should parse as a single string literal with interpolation
+        // The literal is split around the interpolation's tokens
+        let syms = intern_for_test_many(&[r#""Hello "#, "name", r#"!""#]);
+        let kinds: Vec<_> = result.iter().map(|t| t.kind.clone()).collect();
         assert_eq!(
-            result[0].kind,
-            TokenKind::StringLiteral(intern_for_test(r#""Hello \(name)!""#))
+            kinds,
+            [
+                TokenKind::StringLiteral(syms[0]),
+                TokenKind::InterpolationStart,
+                TokenKind::Ident(syms[1]),
+                TokenKind::InterpolationEnd,
+                TokenKind::StringLiteral(syms[2]),
+                TokenKind::EOF,
+            ]
+        );
+        let text = |i: usize| &source[result[i].span.start..result[i].span.end];
+        assert_eq!(
+            (0..5).map(text).collect::<Vec<_>>(),
+            [r#""Hello "#, "\\(", "name", ")", r#"!""#]
         );
     }
 
     #[test]
     fn test_lexer_string_interpolation_nested() {
-        let source = r#""Value: \(x + \(y))""#;
+        let source = r#""Value: \(f(x) + len("\(y)"))" "\\(no)""#;
         let lexer = Lexer::new(source);
         let result = lexer.lex().unwrap();
 
-        // Should parse nested interpolation
-        assert_eq!(
-            result[0].kind,
-            TokenKind::StringLiteral(intern_for_test(
-                r#""Value: \(x + \(y))""#
-            ))
-        );
+        // Parentheses and strings nest inside an interpolation, and an
+        // escaped backslash does not start one
+        let kinds: Vec<_> = result.iter().map(|t| t.kind.clone()).collect();
+        let starts = kinds
+            .iter()
+            .filter(|kind| **kind == TokenKind::InterpolationStart)
+            .count();
+        let ends = kinds
+            .iter()
+            .filter(|kind| **kind == TokenKind::InterpolationEnd)
+            .count();
+        assert_eq!((starts, ends), (2, 2));
+        assert_eq!(kinds.len(), 19);
+        let last = &result[result.len() - 2].span;
+        assert_eq!(&source[last.start..last.end], r#""\\(no)""#);
+
+        let (_, errors, _) = Lexer::new(r#""a \(b"#).lex_all();
+        assert!(matches!(
+            errors.as_slice(),
+            [LexerError::UnterminatedInterpolation { start }] if start.start == 3
+        ));
     }
 
     // ===== Operator Tests =====
@@ -1366,6 +1549,13 @@ mod tests {
         assert_eq!(result[20].kind, TokenKind::SelfType);
         assert_eq!(result[21].kind, TokenKind::Init);
         assert_eq!(result[22].kind, TokenKind::Case);
+
+        let result = Lexer::new("if else for in").lex().unwrap();
+        let kinds: Vec<_> = result.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds[..4],
+            [TokenKind::If, TokenKind::Else, TokenKind::For, TokenKind::In]
+        );
     }
 
     #[test]
@@ -1706,6 +1896,14 @@ mod tests {
                 result[0].kind,
                 TokenKind::Ident(intern_for_test(source))
             );
+
+            // The underscore is part of the name
+            let (tokens, interner) =
+                Lexer::new(source).lex_with_interner().unwrap();
+            let TokenKind::Ident(name) = tokens[0].kind else {
+                panic!("expected an identifier, found {:?}", tokens[0].kind);
+            };
+            assert_eq!(interner.resolve(name), Some(source));
         }
     }
 
@@ -2079,6 +2277,12 @@ mod tests {
         assert_eq!(lexer.lex().unwrap()[0].kind, TokenKind::Guard);
     }
 
+    #[test]
+    fn test_lexer_defer_keyword() {
+        let lexer = Lexer::new("defer");
+        assert_eq!(lexer.lex().unwrap()[0].kind, TokenKind::Defer);
+    }
+
     #[test]
     fn test_lexer_comptime_keyword() {
         let lexer = Lexer::new("comptime");
@@ -20,7 +20,11 @@
 //! - [`ast`] - Abstract Syntax Tree definitions
 //! - [`parser`] - Recursive descent parser
 //! - [`diagnostic`] - Error reporting with source highlighting
+//! - [`session`] - What the phases compiling a program share
+//! - [`sink`] - Collecting, deduplicating and sorting diagnostics
 //! - [`pretty`] - AST pretty-printer
+//! - [`format`] - Source formatter that keeps comments
+//! - [`visit`] - Read-only AST traversal
 //!
 //! # Examples
 //!
@@ -93,7 +97,11 @@ pub mod lexer;
 pub mod ast;
 pub mod parser;
 pub mod diagnostic;
+pub mod session;
+pub mod sink;
 pub mod pretty;
+pub mod format;
+pub mod visit;
 
 // Re-exports for convenience
 pub use span::{LineCol, Span, Spanned};
@@ -5,7 +5,8 @@
 
 use crate::{
     ast::decl::{
-        EnumVariant, FnDecl, FnParam, ProtocolMethod, StructField, Visibility,
+        Attribute, EnumVariant, ExternFn, FnDecl, FnParam, ProtocolMethod,
+        StructField, Visibility,
     },
     ast::expr::{
         BinaryOp, CallArg, DictEntry, InterpolationPart, MatchArm,
@@ -15,6 +16,7 @@ use crate::{
     ast::stmt,
     ast::{Decl, Expr, Pattern, Stmt, Type},
     error::{ParserError, ParserResult},
+    lexer::string_value,
     span::{Span, Spanned},
     token::{Token, TokenKind},
 };
@@ -47,6 +49,9 @@ pub struct Parser<'input, 'arena> {
     arena: LocalArena,
     /// Accumulated parsing errors
     errors: Vec<ParserError>,
+    /// Whether a condition is being parsed, where `name {` starts the
+    /// block rather than a struct literal
+    in_condition: bool,
     /// `PhantomData` to track arena lifetime
     _phantom: PhantomData<&'arena ()>,
 }
@@ -74,6 +79,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             interner,
             arena,
             errors: Vec::new(),
+            in_condition: false,
             _phantom: PhantomData,
         }
     }
@@ -83,6 +89,16 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         self.tokens.get(self.pos)
     }
 
+    /// Where errors at the end of the input point: the `EOF` token the
+    /// lexer ends every token stream with, or the end of the source if
+    /// there is none.
+    fn end_span(&self) -> Span {
+        self.tokens.last().map_or_else(
+            || Span::point(self.source.len(), 1, 1),
+            |t| t.span,
+        )
+    }
+
     /// Peeks at the current token without consuming it.
     #[must_use]
     pub fn peek(&self) -> Option<&Token> {
@@ -118,7 +134,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                 .peek()
                 .map_or_else(|| "EOF".to_string(), |t| format!("{:?}", t.kind));
             let span = self.peek().map_or_else(
-                || Span::point(self.source.len(), 1, 1),
+                || self.end_span(),
                 |t| t.span,
             );
 
@@ -168,6 +184,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         precedence: u8,
     ) -> ParserResult<&'arena Expr<'arena>> {
         // Parse postfix expression (primary expr + calls, fields, indexing)
+        let first = self.peek().map(|t| t.span);
         let mut left = self.parse_postfix_expr()?;
 
         // Parse binary operators with higher precedence
@@ -194,7 +211,8 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             let right = self.parse_expr(token_prec + 1)?;
 
             // Merge spans and allocate binary expression
-            let total_span = Span::merge(left.span(), right.span());
+            let total_span =
+                Span::merge(Self::start_of(left, first), right.span());
             left = self.alloc_expr(Expr::Binary {
                 left,
                 op,
@@ -212,7 +230,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -222,7 +240,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.kind.clone(),
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -273,6 +291,11 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                 Ok(self.alloc_expr(Expr::Nil { span: token_span }))
             }
 
+            TokenKind::Hole => {
+                self.bump();
+                Ok(self.alloc_expr(Expr::Hole { span: token_span }))
+            }
+
             // Unary operators
             TokenKind::Bang => {
                 self.bump();
@@ -294,6 +317,34 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                 }))
             }
 
+            // `comptime` applies to the postfix expression after it
+            TokenKind::Comptime => {
+                self.bump();
+                let expr = self.parse_postfix_expr()?;
+                Ok(self.alloc_expr(Expr::Comptime {
+                    expr,
+                    span: Span::merge(token_span, expr.span()),
+                }))
+            }
+
+            // `self` is an ordinary binding inside methods
+            TokenKind::SelfValue => {
+                self.bump();
+                let sym = self.interner.intern("self");
+                Ok(self.alloc_expr(Expr::Identifier(sym)))
+            }
+
+            // `Self::item` and `Self { ... }` name the enclosing type
+            TokenKind::SelfType => match self.peek_next().map(|next| &next.kind) {
+                Some(TokenKind::ColonColon) => self.parse_path_or_enum_expr(),
+                Some(TokenKind::LBrace) => self.parse_struct_expr(),
+                _ => Err(ParserError::UnexpectedToken {
+                    expected: vec!["expression".to_string()],
+                    found: format!("{token_kind:?}"),
+                    span: token_span,
+                }),
+            },
+
             // Identifiers and paths
             TokenKind::Ident(_) => {
                 // Check for path expression (could be enum construction)
@@ -302,7 +353,9 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                         return self.parse_path_or_enum_expr();
                     }
                     // Check for struct construction: Type { field: value }
-                    if matches!(next.kind, TokenKind::LBrace) {
+                    if matches!(next.kind, TokenKind::LBrace)
+                        && !self.in_condition
+                    {
                         return self.parse_struct_expr();
                     }
                 }
@@ -317,7 +370,10 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             // Parenthesized expressions
             TokenKind::LParen => {
                 self.bump();
-                let expr = self.parse_expr(MIN_PRECEDENCE)?;
+                let in_condition = std::mem::take(&mut self.in_condition);
+                let expr = self.parse_expr(MIN_PRECEDENCE);
+                self.in_condition = in_condition;
+                let expr = expr?;
                 self.expect(TokenKind::RParen)?;
                 Ok(self.alloc_expr(Expr::Paren {
                     expr,
@@ -337,6 +393,9 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             TokenKind::For => self.parse_for_loop_expr(),
             TokenKind::While => self.parse_while_loop_expr(),
 
+            // Closures
+            TokenKind::Fn => self.parse_closure_expr(),
+
             _ => {
                 let found = format!("{token_kind:?}");
                 Err(ParserError::UnexpectedToken {
@@ -352,9 +411,21 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }
     }
 
+    /// Where `expr`, parsed from the token spanning `first`, starts.
+    ///
+    /// Identifiers carry no span, so an expression that is one starts at
+    /// its token instead.
+    fn start_of(expr: &Expr<'_>, first: Option<Span>) -> Span {
+        match (expr, first) {
+            (Expr::Identifier(_), Some(first)) => first,
+            _ => expr.span(),
+        }
+    }
+
     /// Parses a postfix expression (primary expr with calls, fields, indexing).
     fn parse_postfix_expr(&mut self) -> ParserResult<&'arena Expr<'arena>> {
         // Parse the primary expression first
+        let first = self.peek().map(|t| t.span);
         let mut expr = self.parse_prefix_expr()?;
 
         // Loop to handle chained postfix operations
@@ -369,7 +440,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                 // Or method call: obj.method(args)
                 TokenKind::Dot => {
                     // Check if this is a method call by looking ahead
-                    let start_span = expr.span();
+                    let start_span = Self::start_of(expr, first);
                     self.bump(); // consume .
 
                     let field_token = self.expect_identifier()?;
@@ -398,7 +469,8 @@ impl<'input, 'arena> Parser<'input, 'arena> {
 
                 // Index access: arr[index]
                 TokenKind::LBracket => {
-                    expr = self.parse_index_expr(expr)?;
+                    let start_span = Self::start_of(expr, first);
+                    expr = self.parse_index_expr(expr, start_span)?;
                 }
 
                 _ => break,
@@ -413,7 +485,15 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         &mut self,
         callee: &'arena Expr<'arena>,
     ) -> ParserResult<&'arena Expr<'arena>> {
-        let start_span = callee.span();
+        // Identifiers carry no span, but one is the token just before `(`,
+        // and calls of named functions are where runtime errors point
+        let start_span = match callee {
+            Expr::Identifier(_) => self
+                .tokens
+                .get(self.pos.saturating_sub(1))
+                .map_or_else(|| callee.span(), |t| t.span),
+            _ => callee.span(),
+        };
         self.bump(); // consume (
 
         let mut args = Vec::new();
@@ -539,8 +619,8 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     fn parse_index_expr(
         &mut self,
         collection: &'arena Expr<'arena>,
+        start_span: Span,
     ) -> ParserResult<&'arena Expr<'arena>> {
-        let start_span = collection.span();
         self.bump(); // consume [
 
         let index = self.parse_expr(MIN_PRECEDENCE)?;
@@ -568,7 +648,12 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         let mut segments = Vec::new();
 
         loop {
-            let ident = self.expect_identifier()?;
+            let ident = if segments.is_empty() && self.check(TokenKind::SelfType) {
+                self.bump();
+                self.interner.intern("Self")
+            } else {
+                self.expect_identifier()?
+            };
             segments.push(ident);
 
             if !self.check(TokenKind::ColonColon) {
@@ -611,7 +696,12 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         let start_span =
             self.peek().map_or_else(|| Span::point(0, 1, 1), |t| t.span);
 
-        let type_name = self.expect_identifier()?;
+        let type_name = if self.check(TokenKind::SelfType) {
+            self.bump();
+            self.interner.intern("Self")
+        } else {
+            self.expect_identifier()?
+        };
         let type_path = vec![type_name];
 
         self.bump(); // consume {
@@ -726,7 +816,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     fn parse_if_expr(&mut self) -> ParserResult<&'arena Expr<'arena>> {
         let start_span = self.bump().unwrap().span; // consume 'if'
 
-        let condition = self.parse_expr(MIN_PRECEDENCE)?;
+        let condition = self.parse_condition()?;
 
         let then_branch = self.parse_block_expr()?;
 
@@ -753,11 +843,21 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }))
     }
 
+    /// Parses the condition of an `if` or `while`, the scrutinee of a
+    /// `match` or the iterable of a `for`, in which `name { ... }` is the
+    /// name followed by the body, not a struct literal.
+    fn parse_condition(&mut self) -> ParserResult<&'arena Expr<'arena>> {
+        let in_condition = std::mem::replace(&mut self.in_condition, true);
+        let condition = self.parse_expr(MIN_PRECEDENCE);
+        self.in_condition = in_condition;
+        condition
+    }
+
     /// Parses a match expression.
     fn parse_match_expr(&mut self) -> ParserResult<&'arena Expr<'arena>> {
         let start_span = self.bump().unwrap().span; // consume 'match'
 
-        let scrutinee = self.parse_expr(MIN_PRECEDENCE)?;
+        let scrutinee = self.parse_condition()?;
 
         self.expect(TokenKind::LBrace)?;
 
@@ -862,7 +962,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
 
         self.expect(TokenKind::In)?;
 
-        let iter = self.parse_expr(MIN_PRECEDENCE)?;
+        let iter = self.parse_condition()?;
 
         let body = self.parse_block_expr()?;
 
@@ -876,11 +976,49 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }))
     }
 
+    /// Parses a closure expression: `fn(x: Int) -> Int { x + 1 }`.
+    fn parse_closure_expr(&mut self) -> ParserResult<&'arena Expr<'arena>> {
+        let start_span = self.bump().unwrap().span; // consume 'fn'
+
+        self.expect(TokenKind::LParen)?;
+
+        let mut params = Vec::new();
+        while !self.check(TokenKind::RParen) && !self.is_at_eof() {
+            params.push(self.parse_fn_param()?);
+
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+
+        self.expect(TokenKind::RParen)?;
+
+        let return_type = if self.check(TokenKind::Arrow) {
+            self.bump(); // consume ->
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        // A closure in a condition still owns the braces after it
+        let in_condition = std::mem::take(&mut self.in_condition);
+        let body = self.parse_block_expr();
+        self.in_condition = in_condition;
+        let body = body?;
+
+        Ok(self.alloc_expr(Expr::Closure {
+            params,
+            return_type,
+            body,
+            span: Span::merge(start_span, body.span()),
+        }))
+    }
+
     /// Parses a while loop expression.
     fn parse_while_loop_expr(&mut self) -> ParserResult<&'arena Expr<'arena>> {
         let start_span = self.bump().unwrap().span; // consume 'while'
 
-        let condition = self.parse_expr(MIN_PRECEDENCE)?;
+        let condition = self.parse_condition()?;
 
         let body = self.parse_block_expr()?;
 
@@ -985,31 +1123,54 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     }
 
     /// Parses string interpolation.
+    ///
+    /// The lexer splits an interpolated literal into string literal tokens
+    /// around each `\(...)`; the text parts are their decoded text, and
+    /// empty ones are left out.
     fn parse_interpolation(
         &mut self,
         start_span: Span,
         initial_value: Symbol,
     ) -> ParserResult<&'arena Expr<'arena>> {
         let mut parts = Vec::new();
-        parts.push(InterpolationPart::Text(initial_value));
+        let mut text = initial_value;
+        let mut first = true;
 
-        while self.check(TokenKind::InterpolationStart) {
+        loop {
+            let last = !self.check(TokenKind::InterpolationStart);
+            self.push_text(&mut parts, text, first, last);
+            if last {
+                break;
+            }
+            first = false;
             self.bump(); // consume \(
 
             let expr = self.parse_expr(MIN_PRECEDENCE)?;
-
-            self.expect(TokenKind::RParen)?;
-
+            self.expect(TokenKind::InterpolationEnd)?;
             parts.push(InterpolationPart::Expr(expr));
 
-            // Check for more string parts
-            if let Some(Token {
-                kind: TokenKind::StringLiteral(value),
-                ..
-            }) = self.peek()
-            {
-                parts.push(InterpolationPart::Text(*value));
-                self.bump();
+            // The lexer ends the literal with the text after the
+            // interpolation
+            let token = self.peek().cloned();
+            match token.map(|t| (t.kind, t.span)) {
+                Some((TokenKind::StringLiteral(value), _)) => {
+                    self.bump();
+                    text = value;
+                }
+                Some((kind, span)) => {
+                    return Err(ParserError::UnexpectedToken {
+                        expected: vec!["string literal".to_string()],
+                        found: format!("{kind:?}"),
+                        span,
+                    });
+                }
+                None => {
+                    return Err(ParserError::UnexpectedToken {
+                        expected: vec!["string literal".to_string()],
+                        found: "EOF".to_string(),
+                        span: self.end_span(),
+                    });
+                }
             }
         }
 
@@ -1024,13 +1185,32 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }))
     }
 
+    /// Adds the decoded text of a string literal token of an interpolated
+    /// literal to its parts, unless it is empty. Only the `first` token has
+    /// the opening quote, and only the `last` the closing one.
+    fn push_text(
+        &mut self,
+        parts: &mut Vec<InterpolationPart<'arena>>,
+        token: Symbol,
+        first: bool,
+        last: bool,
+    ) {
+        let source = self.resolve_symbol(token);
+        let source = if first { source.strip_prefix('"').unwrap_or(source) } else { source };
+        let closing = if last { "" } else { "\"" };
+        let text = string_value(&format!("\"{source}{closing}"));
+        if !text.is_empty() {
+            parts.push(InterpolationPart::Text(self.interner.intern(&text)));
+        }
+    }
+
     /// Parses a statement.
     fn parse_stmt(&mut self) -> ParserResult<Stmt<'arena>> {
         let token = match self.peek() {
             Some(t) => t,
             None => {
                 return Err(ParserError::ExpectedStatement {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -1039,6 +1219,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             TokenKind::Let => self.parse_let_stmt(false),
             TokenKind::Mut => self.parse_let_stmt(true),
             TokenKind::Return => self.parse_return_stmt(),
+            TokenKind::Defer => self.parse_defer_stmt(),
             _ => {
                 // Try as expression statement
                 let expr = self.parse_expr(MIN_PRECEDENCE)?;
@@ -1119,13 +1300,27 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         })
     }
 
+    /// Parses a defer statement: `defer { body }`.
+    fn parse_defer_stmt(&mut self) -> ParserResult<Stmt<'arena>> {
+        let start_span = self.bump().unwrap().span; // consume 'defer'
+
+        let body = self.parse_block_expr()?;
+        let span = Span::merge(start_span, body.span());
+
+        if self.check(TokenKind::Semicolon) {
+            self.bump();
+        }
+
+        Ok(Stmt::Defer { body, span })
+    }
+
     /// Parses a type annotation.
     fn parse_type(&mut self) -> ParserResult<Type> {
         let start_span = match self.peek() {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedType {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -1219,13 +1414,10 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         // Check for array with size: [T; N]
         if self.check(TokenKind::Semicolon) {
             self.bump(); // consume ;
-            // Parse size (should be a number literal)
-            let size_sym = self.peek().and_then(|t| {
-                if let TokenKind::IntegerLiteral(sym, _) = t.kind {
-                    Some(sym)
-                } else {
-                    None
-                }
+            // Parse size (a number literal or the name of a constant)
+            let size_sym = self.peek().and_then(|t| match t.kind {
+                TokenKind::IntegerLiteral(sym, _) | TokenKind::Ident(sym) => Some(sym),
+                _ => None,
             });
 
             if size_sym.is_some() {
@@ -1262,7 +1454,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     /// Parses a simple or generic type: T or T<Args>
     fn parse_simple_or_generic_type(&mut self) -> ParserResult<Type> {
         let token = self.peek().ok_or(ParserError::ExpectedType {
-            span: Span::point(self.source.len(), 1, 1),
+            span: self.end_span(),
         })?;
 
         match &token.kind {
@@ -1360,7 +1552,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             None => {
                 return Err(ParserError::InvalidPattern {
                     message: "unexpected EOF".to_string(),
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -1537,7 +1729,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             let field_name = self.expect_identifier()?;
             let field_span = match self.peek() {
                 Some(t) => t.span,
-                None => Span::point(self.source.len(), 1, 1),
+                None => self.end_span(),
             };
 
             // Check for shorthand `x` vs explicit `x: pattern`
@@ -1590,9 +1782,24 @@ impl<'input, 'arena> Parser<'input, 'arena> {
 
         // Check for payload
         let payload = if self.check(TokenKind::LParen) {
+            let paren_span = self.peek().unwrap().span;
             self.bump(); // consume (
-            let pat = self.parse_pattern()?;
+            let mut elements = vec![self.parse_pattern()?];
+            // Several payload patterns match the fields of a tuple variant
+            while self.check(TokenKind::Comma) {
+                self.bump(); // consume ,
+                elements.push(self.parse_pattern()?);
+            }
             self.expect(TokenKind::RParen)?;
+            let pat = if elements.len() == 1 {
+                elements.pop().unwrap()
+            } else {
+                let end_span = elements.last().map_or(paren_span, Pattern::span);
+                Pattern::Tuple {
+                    elements,
+                    span: Span::merge(paren_span, end_span),
+                }
+            };
             Some(Box::new(pat))
         } else {
             None
@@ -1698,7 +1905,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     /// Expects and returns an identifier.
     fn expect_identifier(&mut self) -> ParserResult<Symbol> {
         let token = self.peek().ok_or(ParserError::ExpectedIdentifier {
-            span: Span::point(self.source.len(), 1, 1),
+            span: self.end_span(),
         })?;
 
         match &token.kind {
@@ -1758,6 +1965,41 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }
     }
 
+    /// Returns the string interner, which holds every identifier in the
+    /// source and the names the parser introduced, such as `self`.
+    ///
+    /// Later phases resolve the AST's symbols through it, so the parser
+    /// must outlive them.
+    #[must_use]
+    pub fn interner(&self) -> &StringInterner {
+        &self.interner
+    }
+
+    /// Returns a copy of the string interner, with every symbol at the same
+    /// ID.
+    ///
+    /// Lexing another file with the copy (see [`crate::Lexer::with_interner`])
+    /// keeps the symbols of both comparable, so their declarations can be
+    /// checked as one program.
+    #[must_use]
+    pub fn clone_interner(&self) -> StringInterner {
+        let mut copy = StringInterner::new();
+        for id in 0..self.interner.len() {
+            let sym = Symbol::new(id as u32);
+            copy.intern(self.interner.resolve(sym).unwrap_or_default());
+        }
+        copy
+    }
+
+    /// Takes the string interner out of the parser, leaving an empty one.
+    ///
+    /// The declarations parsed so far keep their symbols, which resolve
+    /// through the interner taken, so a driver can lex the next file into
+    /// it (see [`crate::session::Session`]) rather than into a copy.
+    pub fn take_interner(&mut self) -> StringInterner {
+        std::mem::take(&mut self.interner)
+    }
+
     /// Returns all accumulated errors.
     #[must_use]
     pub fn errors(&self) -> &[ParserError] {
@@ -1783,8 +2025,53 @@ impl<'input, 'arena> Parser<'input, 'arena> {
 
     // ===== Declaration Parsing =====
 
+    /// Parses declarations until the end of the input.
+    ///
+    /// A declaration that fails to parse is recorded in [`Parser::errors`]
+    /// and skipped up to the next token that can start a declaration, so
+    /// one mistake does not hide the errors after it.
+    pub fn parse_program(&mut self) -> Vec<Decl<'arena>> {
+        const DECL_START: &[TokenKind] = &[
+            TokenKind::At,
+            TokenKind::Pub,
+            TokenKind::Prv,
+            TokenKind::Fn,
+            TokenKind::Struct,
+            TokenKind::Class,
+            TokenKind::Enum,
+            TokenKind::Protocol,
+            TokenKind::Impl,
+            TokenKind::Const,
+            TokenKind::Static,
+            TokenKind::Type,
+            TokenKind::Import,
+        ];
+
+        let mut decls = Vec::new();
+        while !self.is_at_eof() {
+            let start = self.pos;
+            match self.parse_decl() {
+                Ok(decl) => decls.push(decl),
+                Err(error) => {
+                    self.emit_error(error);
+                    // Always move past the token the declaration began at
+                    if self.pos == start {
+                        self.bump();
+                    }
+                    self.recover_to_sync_point(DECL_START);
+                }
+            }
+        }
+        decls
+    }
+
     /// Parses a top-level declaration.
     pub fn parse_decl(&mut self) -> ParserResult<Decl<'arena>> {
+        // Attributes apply to the declaration that follows them
+        if self.check(TokenKind::At) {
+            return self.parse_attributed_decl();
+        }
+
         // Check for visibility modifier
         let visibility = self.parse_visibility();
 
@@ -1792,7 +2079,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -1801,7 +2088,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.kind.clone(),
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -1871,6 +2158,12 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                 self.parse_type_alias_decl(visibility, start_span)
             }
 
+            // Module import
+            TokenKind::Import => self.parse_import_decl(start_span),
+
+            // Foreign functions
+            TokenKind::Extern => self.parse_extern_decl(start_span),
+
             _ => {
                 let found = format!("{token_kind:?}");
                 Err(ParserError::UnexpectedToken {
@@ -1885,6 +2178,8 @@ impl<'input, 'arena> Parser<'input, 'arena> {
                         "const".to_string(),
                         "static".to_string(),
                         "type".to_string(),
+                        "import".to_string(),
+                        "extern".to_string(),
                     ],
                     found,
                     span: start_span,
@@ -1893,6 +2188,96 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         }
     }
 
+    /// Parses attributes and the function declaration they apply to.
+    fn parse_attributed_decl(&mut self) -> ParserResult<Decl<'arena>> {
+        let mut attributes = Vec::new();
+        while self.check(TokenKind::At) {
+            attributes.push(self.parse_attribute()?);
+        }
+
+        let target = self.peek().map_or_else(
+            || ("EOF".to_string(), self.end_span()),
+            |t| (format!("{:?}", t.kind), t.span),
+        );
+        match self.parse_decl()? {
+            Decl::Fn {
+                is_mut,
+                is_init,
+                is_static,
+                name,
+                generics,
+                params,
+                return_type,
+                body,
+                visibility,
+                attributes: inner,
+                span,
+            } => {
+                attributes.extend(inner);
+                Ok(Decl::Fn {
+                    is_mut,
+                    is_init,
+                    is_static,
+                    name,
+                    generics,
+                    params,
+                    return_type,
+                    body,
+                    visibility,
+                    span: Span::merge(attributes[0].span, span),
+                    attributes,
+                })
+            }
+            // Only functions take attributes
+            _ => Err(ParserError::UnexpectedToken {
+                expected: vec!["fn".to_string()],
+                found: target.0,
+                span: target.1,
+            }),
+        }
+    }
+
+    /// Parses an attribute: `@name` or `@name("argument", ...)`.
+    fn parse_attribute(&mut self) -> ParserResult<Attribute> {
+        let start_span = self.expect(TokenKind::At)?.span;
+        let mut end_span = self.peek().map_or(start_span, |t| t.span);
+        let name = self.expect_identifier()?;
+
+        let mut args = Vec::new();
+        if self.check(TokenKind::LParen) {
+            self.bump(); // consume (
+            while !self.check(TokenKind::RParen) && !self.is_at_eof() {
+                let token = self.peek().cloned();
+                match token.map(|t| (t.kind, t.span)) {
+                    Some((TokenKind::StringLiteral(value), _)) => {
+                        self.bump();
+                        let text = string_value(self.resolve_symbol(value));
+                        args.push(self.interner.intern(&text));
+                    }
+                    Some((kind, span)) => {
+                        return Err(ParserError::UnexpectedToken {
+                            expected: vec!["string literal".to_string()],
+                            found: format!("{kind:?}"),
+                            span,
+                        });
+                    }
+                    None => break,
+                }
+
+                if !self.check(TokenKind::RParen) {
+                    self.expect(TokenKind::Comma)?;
+                }
+            }
+            end_span = self.expect(TokenKind::RParen)?.span;
+        }
+
+        Ok(Attribute {
+            name,
+            args,
+            span: Span::merge(start_span, end_span),
+        })
+    }
+
     /// Parses a visibility modifier (pub/prv).
     fn parse_visibility(&mut self) -> Visibility {
         if self.check(TokenKind::Pub) {
@@ -1971,6 +2356,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             return_type,
             body,
             visibility,
+            attributes: Vec::new(),
             span: Span::merge(start_span, end_span),
         })
     }
@@ -1981,7 +2367,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -2075,7 +2461,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -2195,22 +2581,12 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             } else if self.check(TokenKind::Mut) || self.check(TokenKind::Static) || self.check(TokenKind::Init) || self.check(TokenKind::Fn) || self.check(TokenKind::Pub) || self.check(TokenKind::Prv) {
                 // Parse method (pub/prv mut fn, pub/prv static fn, pub/prv init, pub/prv fn)
                 let method = self.parse_impl_method()?;
-                methods.push(FnDecl {
-                    is_mut: method.is_mut,
-                    is_init: method.is_init,
-                    is_static: method.is_static,
-                    name: method.name,
-                    generics: method.generics,
-                    params: method.params,
-                    return_type: method.return_type,
-                    visibility: method.visibility,
-                    span: method.span,
-                });
+                methods.push(method);
             } else {
                 return Err(ParserError::UnexpectedToken {
                     expected: vec!["case".to_string(), "pub".to_string(), "prv".to_string(), "fn".to_string(), "mut".to_string(), "static".to_string(), "init".to_string()],
                     found: format!("{:?}", self.peek().map(|t| &t.kind)),
-                    span: self.peek().map(|t| t.span).unwrap_or_else(|| Span::point(self.source.len(), 1, 1)),
+                    span: self.peek().map(|t| t.span).unwrap_or_else(|| self.end_span()),
                 });
             }
 
@@ -2354,7 +2730,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -2407,13 +2783,13 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         let _generics = self.parse_generics()?;
 
         // Check if this is "impl Protocol for Type"
-        let type_path = self.parse_path_segments()?;
+        let first = self.parse_path_segments()?;
 
-        let protocol = if self.check(TokenKind::For) {
+        let (type_path, protocol) = if self.check(TokenKind::For) {
             self.bump(); // consume 'for'
-            Some(self.parse_path_segments()?)
+            (self.parse_path_segments()?, Some(first))
         } else {
-            None
+            (first, None)
         };
 
         self.expect(TokenKind::LBrace)?;
@@ -2440,14 +2816,14 @@ impl<'input, 'arena> Parser<'input, 'arena> {
     }
 
     /// Parses a method inside an impl block.
-    fn parse_impl_method(&mut self) -> ParserResult<FnDecl> {
+    fn parse_impl_method(&mut self) -> ParserResult<FnDecl<'arena>> {
         // Parse visibility (will be resolved to most restrictive of parent and method later)
         let visibility = self.parse_visibility();
         let start_span = match self.peek() {
             Some(t) => t.span,
             None => {
                 return Err(ParserError::ExpectedExpression {
-                    span: Span::point(self.source.len(), 1, 1),
+                    span: self.end_span(),
                 });
             }
         };
@@ -2513,6 +2889,7 @@ impl<'input, 'arena> Parser<'input, 'arena> {
             generics,
             params,
             return_type,
+            body: body_expr,
             visibility,
             span: Span::merge(start_span, end_span),
         })
@@ -2617,6 +2994,83 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         })
     }
 
+    /// Parses a module import: `import "path/to/module";`
+    fn parse_import_decl(
+        &mut self,
+        start_span: Span,
+    ) -> ParserResult<Decl<'arena>> {
+        self.bump(); // consume 'import'
+
+        let (path, end_span) = match self.peek() {
+            Some(Token {
+                kind: TokenKind::StringLiteral(path),
+                span,
+            }) => (*path, *span),
+            token => {
+                return Err(ParserError::UnexpectedToken {
+                    expected: vec!["module path".to_string()],
+                    found: token.map_or_else(
+                        || "end of input".to_string(),
+                        |t| format!("{:?}", t.kind),
+                    ),
+                    span: token.map_or_else(
+                        || self.end_span(),
+                        |t| t.span,
+                    ),
+                });
+            }
+        };
+        self.bump();
+        self.expect(TokenKind::Semicolon)?;
+
+        Ok(Decl::Import {
+            path,
+            span: Span::merge(start_span, end_span),
+        })
+    }
+
+    /// Parses a block of foreign functions:
+    /// `extern "library" { fn name(params) -> Type; ... }`, the library
+    /// being optional.
+    fn parse_extern_decl(
+        &mut self,
+        start_span: Span,
+    ) -> ParserResult<Decl<'arena>> {
+        self.bump(); // consume 'extern'
+
+        let library = match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::StringLiteral(library)) => {
+                self.bump();
+                let text = string_value(self.resolve_symbol(library));
+                Some(self.interner.intern(&text))
+            }
+            _ => None,
+        };
+
+        self.expect(TokenKind::LBrace)?;
+
+        // Signatures are written like protocol methods, each ending in `;`
+        let mut functions = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_eof() {
+            let signature = self.parse_protocol_method()?;
+            self.expect(TokenKind::Semicolon)?;
+            functions.push(ExternFn {
+                name: signature.name,
+                params: signature.params,
+                return_type: signature.return_type,
+                span: signature.span,
+            });
+        }
+
+        let end_span = self.expect(TokenKind::RBrace)?.span;
+
+        Ok(Decl::Extern {
+            library,
+            functions,
+            span: Span::merge(start_span, end_span),
+        })
+    }
+
     /// Parses generic type parameters: <T, U>
     fn parse_generics(&mut self) -> ParserResult<Vec<Symbol>> {
         if !self.check(TokenKind::LAngle) {
@@ -2661,7 +3115,12 @@ impl<'input, 'arena> Parser<'input, 'arena> {
         let mut segments = Vec::new();
 
         loop {
-            let ident = self.expect_identifier()?;
+            let ident = if segments.is_empty() && self.check(TokenKind::SelfType) {
+                self.bump();
+                self.interner.intern("Self")
+            } else {
+                self.expect_identifier()?
+            };
             segments.push(ident);
 
             if !self.check(TokenKind::ColonColon) {
@@ -2691,6 +3150,40 @@ mod tests {
         parser.parse_expression().map(|expr| (*expr).clone())
     }
 
+    #[test]
+    fn test_parse_program_recovers_between_declarations() {
+        let source = "fn a() -> Int { 1 }\nfn b( { }\nconst C: Int = 2;";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser =
+            Parser::new(tokens, source, interner, LocalArena::new(8192));
+
+        let decls = parser.parse_program();
+        assert_eq!(decls.len(), 2);
+        assert!(matches!(decls[0], Decl::Fn { .. }));
+        assert!(matches!(decls[1], Decl::Const { .. }));
+        assert_eq!(parser.errors().len(), 1);
+        assert_eq!(parser.errors()[0].span().start_line, 2);
+        assert!(parser.interner().get_symbol("C").is_some());
+    }
+
+    #[test]
+    fn test_clone_interner_keeps_symbols_across_files() {
+        let first = "fn a() -> Int { 1 }";
+        let (tokens, interner) = Lexer::new(first).lex_with_interner().unwrap();
+        let parser = Parser::new(tokens, first, interner, LocalArena::new(1024));
+        let a = parser.interner().get_symbol("a").unwrap();
+
+        let second = "fn b() -> Int { a() }";
+        let (tokens, interner) = Lexer::with_interner(second, parser.clone_interner())
+            .lex_with_interner()
+            .unwrap();
+        let mut parser = Parser::new(tokens, second, interner, LocalArena::new(1024));
+        let decls = parser.parse_program();
+        assert_eq!(decls.len(), 1);
+        assert_eq!(parser.interner().get_symbol("a"), Some(a));
+        assert_eq!(parser.resolve_symbol(a), "a");
+    }
+
     #[test]
     fn test_parse_integer_literal() {
         let expr = parse_expr("42").unwrap();
@@ -2709,6 +3202,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_hole() {
+        let expr = parse_expr("???").unwrap();
+        match expr {
+            Expr::Hole { .. } => {}
+            _ => panic!("Expected Hole, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_parse_self_field_access() {
+        let expr = parse_expr("self.count").unwrap();
+        match expr {
+            Expr::Field { object, .. } => {
+                assert!(matches!(object, Expr::Identifier(_)));
+            }
+            _ => panic!("Expected Field, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_parse_self_type_path() {
+        let expr = parse_expr("Self::new").unwrap();
+        match expr {
+            Expr::Path { segments, .. } => assert_eq!(segments.len(), 2),
+            _ => panic!("Expected Path, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_parse_self_struct_literal() {
+        let expr = parse_expr("Self { count: 0 }").unwrap();
+        match expr {
+            Expr::Struct { type_path, fields, .. } => {
+                assert_eq!(type_path.len(), 1);
+                assert_eq!(fields.len(), 1);
+            }
+            _ => panic!("Expected Struct, got {:?}", expr),
+        }
+    }
+
     #[test]
     fn test_parse_binary_addition() {
         let expr = parse_expr("1 + 2").unwrap();
@@ -2758,6 +3292,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_interpolation() {
+        let source = r#""a\n\(x + 1)\"b\(f("\(y)"))""#;
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser =
+            Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let expr = parser.parse_expression().unwrap();
+        let Expr::Interpolation { parts, span } = expr else {
+            panic!("Expected Interpolation, got {:?}", expr);
+        };
+        assert_eq!((span.start, span.end), (0, source.len()));
+
+        // Text parts are decoded, and the empty one at the end left out
+        let text = |part: &InterpolationPart<'_>| match part {
+            InterpolationPart::Text(sym) => {
+                Some(parser.resolve_symbol(*sym).to_string())
+            }
+            InterpolationPart::Expr(_) => None,
+        };
+        let texts: Vec<_> = parts.iter().map(text).collect();
+        assert_eq!(
+            texts,
+            [Some("a\n".to_string()), None, Some("\"b".to_string()), None]
+        );
+        assert!(matches!(parts[1], InterpolationPart::Expr(Expr::Binary { .. })));
+        let InterpolationPart::Expr(Expr::Call { args, .. }) = parts[3] else {
+            panic!("Expected a call, got {:?}", parts[3]);
+        };
+        assert!(matches!(args[0].value, Expr::Interpolation { .. }));
+
+        let err = parse_expr(r#""\(1 2)""#).unwrap_err();
+        assert!(matches!(err, ParserError::UnexpectedToken { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn test_parse_call_spans_from_named_callee() {
+        let expr = parse_expr("x + assert(y)").unwrap();
+        let Expr::Binary { right, .. } = expr else {
+            panic!("Expected Binary, got {:?}", expr);
+        };
+        let span = right.span();
+        assert_eq!((span.start, span.end), (4, 13));
+        assert_eq!((span.start_line, span.start_col), (1, 5));
+    }
+
     #[test]
     fn test_parse_if_expression() {
         let expr = parse_expr("if true { nil } else { nil }").unwrap();
@@ -2767,6 +3346,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_comptime() {
+        let expr = parse_expr("comptime if DEBUG { 1 } else { 2 }").unwrap();
+        let Expr::Comptime { expr: inner, span } = expr else {
+            panic!("Expected Comptime, got {:?}", expr);
+        };
+        let Expr::If { condition, .. } = inner else {
+            panic!("Expected If, got {:?}", inner);
+        };
+        assert!(matches!(condition, Expr::Identifier(_)));
+        assert_eq!((span.start, span.end), (0, 34));
+
+        // Struct literals in conditions need parentheses
+        let expr = parse_expr("if (Point { x } == p) { p }").unwrap();
+        assert!(matches!(expr, Expr::If { .. }));
+
+        // It binds tighter than binary operators
+        let expr = parse_expr("comptime WIDTH * 2").unwrap();
+        let Expr::Binary { left, .. } = expr else {
+            panic!("Expected Binary, got {:?}", expr);
+        };
+        assert!(matches!(left, Expr::Comptime { .. }));
+    }
+
+    #[test]
+    fn test_parse_match_and_for_take_names_before_their_bodies() {
+        let source = "fn total(value: Int, items: [Int]) -> Int {\n\
+                      mut sum = 0;\n\
+                      for item in items { sum = sum + item; };\n\
+                      match value { 0 => sum, _ => value }\n\
+                      }";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let Decl::Fn { body: Expr::Block { stmts, expr: Some(last), .. }, .. } = &decls[0] else {
+            panic!("Expected a function with a body, got {:?}", decls[0]);
+        };
+        assert_eq!(stmts.len(), 2);
+        let Expr::Match { scrutinee, arms, .. } = last else {
+            panic!("Expected Match, got {:?}", last);
+        };
+        assert!(matches!(scrutinee, Expr::Identifier(_)));
+        assert_eq!(arms.len(), 2);
+
+        let expr = parse_expr("for item in items { item }").unwrap();
+        let Expr::ForLoop { iter, .. } = expr else {
+            panic!("Expected ForLoop, got {:?}", expr);
+        };
+        assert!(matches!(iter, Expr::Identifier(_)));
+
+        // Struct literals there need parentheses, as in conditions
+        let expr = parse_expr("match (Point { x: 1 }) { _ => 0 }").unwrap();
+        let Expr::Match { scrutinee, .. } = expr else {
+            panic!("Expected Match, got {:?}", expr);
+        };
+        assert!(matches!(scrutinee, Expr::Paren { .. }));
+        assert!(parse_expr("for p in (Points { all: 1 }).all { p }").is_ok());
+    }
+
     #[test]
     fn test_parse_while_loop() {
         let expr = parse_expr("while true { nil }").unwrap();
@@ -2776,6 +3415,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_closure() {
+        let expr = parse_expr("fn(x: Int, y: Int) -> Int { x + y }").unwrap();
+        let Expr::Closure {
+            params,
+            return_type,
+            body,
+            span,
+        } = expr
+        else {
+            panic!("Expected Closure, got {:?}", expr);
+        };
+        assert_eq!(params.len(), 2);
+        assert!(return_type.is_some());
+        assert!(matches!(body, Expr::Block { expr: Some(_), .. }));
+        assert_eq!((span.start, span.end), (0, 35));
+
+        // The return type may be omitted, and closures can be called
+        let expr = parse_expr("fn() { 1 }()").unwrap();
+        let Expr::Call { callee, .. } = expr else {
+            panic!("Expected Call, got {:?}", expr);
+        };
+        assert!(matches!(
+            callee,
+            Expr::Closure {
+                return_type: None,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_operator_precedence() {
         let expr = parse_expr("1 + 2 * 3").unwrap();
@@ -3138,6 +3808,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_impl_protocol_for_type() {
+        let source = "impl Shape for Circle { fn area() -> Float { 0.0 } }";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        let decl = parser.parse_decl().unwrap();
+        match decl {
+            Decl::Impl {
+                type_path,
+                protocol: Some(protocol),
+                ..
+            } => {
+                assert_eq!(parser.resolve_symbol(type_path[0]), "Circle");
+                assert_eq!(parser.resolve_symbol(protocol[0]), "Shape");
+            }
+            _ => panic!("Expected Impl decl, got {:?}", decl),
+        }
+    }
+
     #[test]
     fn test_parse_const_decl() {
         let source = "const MAX_SIZE: Int = 100;";
@@ -3215,6 +3907,26 @@ mod tests {
         assert!(matches!(tokens[0].kind, TokenKind::Ident(_)));
     }
 
+    #[test]
+    fn test_parse_enum_pattern_with_several_payloads() {
+        let source = "Pair::Both(a, _)";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_pattern().unwrap() {
+            Pattern::Enum {
+                payload: Some(payload),
+                ..
+            } => match *payload {
+                Pattern::Tuple { elements, .. } => assert_eq!(elements.len(), 2),
+                other => panic!("Expected Tuple payload, got {:?}", other),
+            },
+            other => panic!("Expected Enum pattern, got {:?}", other),
+        }
+    }
+
     // ===== Type Parsing Tests =====
 
     #[test]
@@ -3315,6 +4027,23 @@ mod tests {
         assert!(stmt.is_ok());
     }
 
+    #[test]
+    fn test_parse_array_type_sized_by_constant() {
+        let source = "let x: [Int; SIZE] = [];";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_stmt().unwrap() {
+            Stmt::Let {
+                type_annotation: Some(Type::Array { size: Some(size), .. }),
+                ..
+            } => assert_eq!(parser.resolve_symbol(size), "SIZE"),
+            other => panic!("Expected sized array annotation, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_dict_type() {
         let source = "let x: [String: Int] = [];";
@@ -3354,6 +4083,84 @@ mod tests {
         assert!(decl.is_ok());
     }
 
+    #[test]
+    fn test_parse_import_decl() {
+        let source = "import \"./util/math\"; import util;";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_decl().unwrap() {
+            Decl::Import { path, span } => {
+                assert_eq!(parser.resolve_symbol(path), "\"./util/math\"");
+                assert_eq!(span.end, 20);
+            }
+            decl => panic!("Expected Import, got {:?}", decl),
+        }
+
+        // The path must be a string literal
+        assert!(parser.parse_decl().is_err());
+    }
+
+    #[test]
+    fn test_parse_extern_decl() {
+        let source = "extern \"libm.so.6\" { fn cos(x: Float) -> Float; \
+                      fn pow(_ x: Float, _ y: Float) -> Float; } \
+                      extern { fn abort(); } extern { fn f() }";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_decl().unwrap() {
+            Decl::Extern {
+                library,
+                functions,
+                span,
+            } => {
+                assert_eq!(parser.resolve_symbol(library.unwrap()), "libm.so.6");
+                assert_eq!(functions.len(), 2);
+                assert_eq!(parser.resolve_symbol(functions[1].name), "pow");
+                assert_eq!(functions[1].params.len(), 2);
+                assert!(functions[0].return_type.is_some());
+                assert_eq!(span.start, 0);
+            }
+            decl => panic!("Expected Extern, got {:?}", decl),
+        }
+        match parser.parse_decl().unwrap() {
+            Decl::Extern {
+                library, functions, ..
+            } => {
+                assert!(library.is_none());
+                assert!(functions[0].return_type.is_none());
+            }
+            decl => panic!("Expected Extern, got {:?}", decl),
+        }
+
+        // Each signature ends in a semicolon
+        assert!(parser.parse_decl().is_err());
+    }
+
+    #[test]
+    fn test_parse_defer_stmt() {
+        let source = "{ defer { close(); } defer { flush(); }; 1 }";
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_block_expr().unwrap() {
+            Expr::Block { stmts, expr, .. } => {
+                assert_eq!(stmts.len(), 2);
+                assert!(stmts.iter().all(|stmt| matches!(stmt, Stmt::Defer { .. })));
+                assert_eq!(stmts[0].span().end, 20);
+                assert!(expr.is_some());
+            }
+            expr => panic!("Expected Block, got {:?}", expr),
+        }
+    }
+
     #[test]
     fn test_parse_mut_fn() {
         let source = "mut fn increment(x: Int) -> Int { x + 1 }";
@@ -3380,6 +4187,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_attributes() {
+        let source =
+            r#"@export("ox_add") pub fn add(a: Int, b: Int) -> Int { a + b }"#;
+        let arena = LocalArena::new(8192);
+        let lexer = Lexer::new(source);
+        let (tokens, interner) = lexer.lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, arena);
+
+        match parser.parse_decl().unwrap() {
+            Decl::Fn {
+                attributes,
+                visibility,
+                span,
+                ..
+            } => {
+                assert_eq!(visibility, Visibility::Public);
+                assert_eq!(attributes.len(), 1);
+                assert_eq!(parser.resolve_symbol(attributes[0].name), "export");
+                assert_eq!(attributes[0].args.len(), 1);
+                assert_eq!(
+                    parser.resolve_symbol(attributes[0].args[0]),
+                    "ox_add"
+                );
+                assert_eq!(span.start, 0);
+            }
+            _ => panic!("Expected Fn declaration"),
+        }
+
+        // Only functions take attributes, with string arguments
+        for source in [
+            r#"@export("point") struct Point { x: Int }"#,
+            "@export(name) fn f() { }",
+        ] {
+            let arena = LocalArena::new(8192);
+            let (tokens, interner) =
+                Lexer::new(source).lex_with_interner().unwrap();
+            let mut parser = Parser::new(tokens, source, interner, arena);
+            assert!(matches!(
+                parser.parse_decl(),
+                Err(ParserError::UnexpectedToken { .. })
+            ));
+        }
+    }
+
     #[test]
     fn test_parse_init() {
         let source = "init(x: Int) { }";
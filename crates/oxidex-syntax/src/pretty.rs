@@ -102,6 +102,7 @@ impl PrettyPrinter {
             Expr::BoolLiteral { value, .. } => value.to_string(),
 
             Expr::Nil { .. } => "nil".to_string(),
+            Expr::Hole { .. } => "???".to_string(),
 
             Expr::Identifier(sym) => self
                 .interner
@@ -133,6 +134,11 @@ impl PrettyPrinter {
                 format!("({inner})")
             }
 
+            Expr::Comptime { expr, .. } => {
+                let inner = self.print_expr(expr);
+                format!("comptime {inner}")
+            }
+
             Expr::Block { stmts, expr, .. } => {
                 self.indent_level += 1;
                 let mut result = "{\n".to_string();
@@ -176,6 +182,32 @@ impl PrettyPrinter {
                 format!("{callee_str}({args_str})")
             }
 
+            Expr::Closure {
+                params,
+                return_type,
+                body,
+                ..
+            } => {
+                let param_strs: Vec<String> = params
+                    .iter()
+                    .map(|p| {
+                        let name_str =
+                            self.interner.resolve(p.name).unwrap_or("<unknown>");
+                        let type_str = self.print_type(&p.type_annotation);
+                        format!("{name_str}: {type_str}")
+                    })
+                    .collect();
+                let params_str = param_strs.join(", ");
+                let body_str = self.print_expr(body);
+                match return_type {
+                    Some(ty) => {
+                        let return_str = self.print_type(ty);
+                        format!("fn({params_str}) -> {return_str} {body_str}")
+                    }
+                    None => format!("fn({params_str}) {body_str}"),
+                }
+            }
+
             Expr::Array { elements, .. } => {
                 let elems_str = elements
                     .iter()
@@ -381,7 +413,18 @@ impl PrettyPrinter {
                                 .interner
                                 .resolve(*sym)
                                 .unwrap_or("<unknown>");
-                            result.push_str(text);
+                            // Text parts are decoded; escape them again
+                            for ch in text.chars() {
+                                match ch {
+                                    '\\' => result.push_str("\\\\"),
+                                    '"' => result.push_str("\\\""),
+                                    '\n' => result.push_str("\\n"),
+                                    '\t' => result.push_str("\\t"),
+                                    '\r' => result.push_str("\\r"),
+                                    '\0' => result.push_str("\\0"),
+                                    _ => result.push(ch),
+                                }
+                            }
                         }
                         InterpolationPart::Expr(expr) => {
                             result.push_str("\\(");
@@ -484,6 +527,10 @@ impl PrettyPrinter {
                 format!("guard {condition_str} else {else_str}")
             }
 
+            Stmt::Defer { body, .. } => {
+                format!("defer {}", self.print_expr(body))
+            }
+
             Stmt::Match {
                 scrutinee, arms, ..
             } => {
@@ -726,10 +773,26 @@ impl PrettyPrinter {
                 return_type,
                 body,
                 visibility,
+                attributes,
                 ..
             } => {
                 let mut parts = Vec::new();
 
+                // Attributes
+                for attribute in attributes {
+                    let name_str = self.interner.resolve(attribute.name).unwrap_or("<unknown>");
+                    let arg_strs: Vec<String> = attribute
+                        .args
+                        .iter()
+                        .map(|arg| format!("{:?}", self.interner.resolve(*arg).unwrap_or("")))
+                        .collect();
+                    if arg_strs.is_empty() {
+                        parts.push(format!("@{}", name_str));
+                    } else {
+                        parts.push(format!("@{}({})", name_str, arg_strs.join(", ")));
+                    }
+                }
+
                 // Visibility
                 match visibility {
                     crate::ast::Visibility::Public => parts.push("pub".to_string()),
@@ -919,7 +982,7 @@ impl PrettyPrinter {
 
                 let all_items: Vec<String> = variant_strs
                     .into_iter()
-                    .chain(method_strs.into_iter())
+                    .chain(method_strs)
                     .collect();
 
                 format!("{} {{ {} }}", parts.join(" "), all_items.join(", "))
@@ -1088,6 +1151,35 @@ impl PrettyPrinter {
 
                 format!("{} {};", parts.join(" "), name_str)
             }
+
+            Decl::Import { path, .. } => {
+                let path_str = self.interner.resolve(*path).unwrap_or("<unknown>");
+                format!("import {};", path_str)
+            }
+
+            Decl::Extern {
+                library, functions, ..
+            } => {
+                // Foreign signatures read like protocol methods
+                let function_strs: Vec<String> = functions
+                    .iter()
+                    .map(|function| {
+                        self.print_protocol_method(&crate::ast::ProtocolMethod {
+                            name: function.name,
+                            params: function.params.clone(),
+                            return_type: function.return_type.clone(),
+                            span: function.span,
+                        })
+                    })
+                    .collect();
+                match library {
+                    Some(library) => {
+                        let library_str = self.interner.resolve(*library).unwrap_or("<unknown>");
+                        format!("extern {:?} {{ {} }}", library_str, function_strs.join(" "))
+                    }
+                    None => format!("extern {{ {} }}", function_strs.join(" ")),
+                }
+            }
         }
     }
 
@@ -1122,7 +1214,7 @@ impl PrettyPrinter {
     }
 
     /// Pretty-prints a function declaration (for impl blocks, protocols, etc).
-    fn print_fn_decl(&self, decl: &crate::ast::FnDecl) -> String {
+    fn print_fn_decl(&self, decl: &crate::ast::FnDecl<'_>) -> String {
         let mut parts = Vec::new();
 
         // Visibility
@@ -1593,6 +1685,9 @@ mod tests {
             name: sym(&mut interner, "Self"),
             span: Span::new(27, 31, 1, 28, 1, 32),
         };
+        let body = Expr::Nil {
+            span: Span::new(32, 34, 1, 33, 1, 35),
+        };
 
         let decl = Decl::Impl {
             type_path: vec![type_name],
@@ -1610,6 +1705,7 @@ mod tests {
                     span: Span::new(20, 23, 1, 21, 1, 24),
                 }],
                 return_type: Some(return_type),
+                body: &body,
                 visibility: Visibility::Public,
                 span: Span::new(6, 32, 1, 7, 1, 33),
             }],
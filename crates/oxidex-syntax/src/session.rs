@@ -0,0 +1,453 @@
+//! Compilation sessions: what every phase compiling a program shares.
+//!
+//! A [`Session`] holds the program's files in a [`SourceMap`], the one
+//! [`StringInterner`] the symbols of every file are interned in, the
+//! diagnostics the phases report, in a [`DiagnosticsSink`], and the
+//! [`SessionOptions`] the driver was given. The driver creates one per
+//! compilation and hands it to the lexer and parser through
+//! [`Session::parse`], and its interner to the type checker and code
+//! generation, so that no phase builds an interner or an emitter of its own.
+//!
+//! Each file in the source map has a range of offsets of its own, and
+//! [`Session::parse`] shifts the spans of a file's tokens into it. Spans
+//! therefore say which file they are in, whichever phase reported them,
+//! [`Session::take_diagnostics`] sorts diagnostics by file, and
+//! [`Session::render`] shows each against the file it points into.
+//!
+//! # Examples
+//!
+//! ```
+//! use oxidex_syntax::session::{Session, SessionOptions};
+//!
+//! let mut session = Session::new(SessionOptions::default());
+//! let util = session.add_file("util.ox", "fn one() -> Int { 1 }");
+//! let main = session.add_file("main.ox", "fn main() -> Int { one( }");
+//!
+//! let (_, decls) = session.parse(util).unwrap();
+//! assert_eq!(decls.len(), 1);
+//! session.parse(main).unwrap();
+//!
+//! // The error is reported against the file it is in
+//! let diagnostics = session.take_diagnostics();
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(session.sources().find(diagnostics[0].span), Some(main));
+//! assert!(session.render(&diagnostics[0]).starts_with("main.ox:1:"));
+//! ```
+
+use crate::ast::Decl;
+use crate::diagnostic::{
+    Diagnostic, DiagnosticBuilder, DiagnosticLevel, Emitter,
+};
+use crate::keywords;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sink::{DiagnosticsSink, SinkOptions};
+use crate::span::{Span, Spanned};
+use crate::token::Token;
+use oxidex_mem::{LocalArena, StringInterner};
+use std::cell::RefCell;
+
+/// Identifies a file in a [`SourceMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// The position of the file in its source map, counting from zero in
+    /// the order the files were added.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A file in a [`SourceMap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile<'src> {
+    /// The file's name, as diagnostics show it
+    name: String,
+    /// The file's contents
+    text: &'src str,
+    /// The offset the file starts at
+    start: usize,
+}
+
+impl<'src> SourceFile<'src> {
+    /// The file's name, as diagnostics show it.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The file's contents.
+    #[must_use]
+    pub const fn text(&self) -> &'src str {
+        self.text
+    }
+
+    /// The offset the file starts at.
+    #[must_use]
+    pub const fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The offset just past the file's last byte, where its `EOF` token is.
+    #[must_use]
+    pub const fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+
+    /// A span naming the file but no place in it, for diagnostics about
+    /// the file as a whole.
+    #[must_use]
+    pub const fn span(&self) -> Span {
+        Span::point(self.start, 0, 0)
+    }
+}
+
+/// The number of offsets each file of a [`SourceMap`] has to itself.
+///
+/// Half the bits of an offset say which file it is in, so files can be up
+/// to 4 GiB long on 64-bit targets.
+pub const FILE_SPACE: usize = 1 << (usize::BITS / 2);
+
+/// The files of a program, each at its own range of offsets.
+///
+/// A file's offsets start at its position in the map times
+/// [`FILE_SPACE`], not where the file before it ends, so that they stay
+/// the same when the files before it change. The spans of a declaration
+/// that did not change are then the same from one build to the next, and
+/// results cached under them still apply.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap<'src> {
+    files: Vec<SourceFile<'src>>,
+}
+
+impl<'src> SourceMap<'src> {
+    /// Creates an empty source map.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, after the files already added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `text` does not fit in [`FILE_SPACE`], or the map has no
+    /// offsets left for another file.
+    pub fn add(&mut self, name: impl Into<String>, text: &'src str) -> FileId {
+        assert!(text.len() < FILE_SPACE, "file too large");
+        let start = self
+            .files
+            .len()
+            .checked_mul(FILE_SPACE)
+            .expect("too many files");
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile {
+            name: name.into(),
+            text,
+            start,
+        });
+        id
+    }
+
+    /// The file `id` names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is from another source map.
+    #[must_use]
+    pub fn file(&self, id: FileId) -> &SourceFile<'src> {
+        &self.files[id.index()]
+    }
+
+    /// The file `span` starts in, if it is in one.
+    #[must_use]
+    pub fn find(&self, span: Span) -> Option<FileId> {
+        let index = span.start / FILE_SPACE;
+        let file = self.files.get(index)?;
+        (span.start <= file.end()).then_some(FileId(index as u32))
+    }
+
+    /// The files, in the order they were added.
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile<'src>)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| (FileId(index as u32), file))
+    }
+
+    /// The number of files.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if there are no files.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// How a session reports what it finds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionOptions {
+    /// Color diagnostics with ANSI escapes
+    pub color: bool,
+    /// What the diagnostics sink deduplicates, limits and escalates
+    pub sink: SinkOptions,
+}
+
+/// What the phases compiling one program share: its files, the interner
+/// its symbols live in, and the diagnostics reported about it.
+pub struct Session<'src> {
+    /// The options the session was created with
+    pub options: SessionOptions,
+    /// The program's files
+    sources: SourceMap<'src>,
+    /// The interner every file is lexed into
+    interner: StringInterner,
+    /// Renders diagnostics against the source map
+    emitter: Emitter,
+    /// Diagnostics reported and not yet taken
+    diagnostics: RefCell<DiagnosticsSink>,
+}
+
+impl<'src> Session<'src> {
+    /// Creates a session with no files and an interner holding only the
+    /// keywords.
+    #[must_use]
+    pub fn new(options: SessionOptions) -> Self {
+        Self::with_interner(
+            StringInterner::with_pre_interned(keywords::KEYWORDS),
+            options,
+        )
+    }
+
+    /// Creates a session whose files are lexed into `interner`, which must
+    /// hold the keywords first, as a lexer's does, so that symbols interned
+    /// before keep their IDs.
+    #[must_use]
+    pub fn with_interner(
+        interner: StringInterner,
+        options: SessionOptions,
+    ) -> Self {
+        Self {
+            options,
+            sources: SourceMap::new(),
+            interner,
+            emitter: Emitter::plain(options.color),
+            diagnostics: RefCell::new(DiagnosticsSink::new(options.sink)),
+        }
+    }
+
+    /// Adds a file to the program, after those already added.
+    pub fn add_file(
+        &mut self,
+        name: impl Into<String>,
+        text: &'src str,
+    ) -> FileId {
+        self.sources.add(name, text)
+    }
+
+    /// The program's files.
+    #[must_use]
+    pub const fn sources(&self) -> &SourceMap<'src> {
+        &self.sources
+    }
+
+    /// The interner the symbols of every file parsed so far resolve
+    /// through.
+    #[must_use]
+    pub const fn interner(&self) -> &StringInterner {
+        &self.interner
+    }
+
+    /// Lexes `file` into the session's interner, with its spans at the
+    /// file's offsets.
+    ///
+    /// Returns `None`, after reporting every error, if the file does not
+    /// lex.
+    pub fn lex(&mut self, file: FileId) -> Option<Vec<Token>> {
+        let source = self.sources.file(file);
+        let (start, text) = (source.start(), source.text());
+        let interner = std::mem::take(&mut self.interner);
+        let (mut tokens, errors, interner) =
+            Lexer::with_interner(text, interner).lex_all();
+        self.interner = interner;
+        for error in &errors {
+            self.error(error.to_string(), shift(error.span(), start));
+        }
+        if !errors.is_empty() {
+            return None;
+        }
+        for token in &mut tokens {
+            token.span = shift(token.span, start);
+        }
+        Some(tokens)
+    }
+
+    /// Lexes and parses `file`, reporting its syntax errors.
+    ///
+    /// Returns the parser, which owns the arena the declarations live in,
+    /// with the declarations, or `None` if the file does not lex. The
+    /// declarations' symbols resolve through [`Session::interner`].
+    pub fn parse(
+        &mut self,
+        file: FileId,
+    ) -> Option<(Parser<'src, 'src>, Vec<Decl<'src>>)> {
+        let tokens = self.lex(file)?;
+        let text = self.sources.file(file).text();
+        let interner = std::mem::take(&mut self.interner);
+        let mut parser =
+            Parser::new(tokens, text, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        self.interner = parser.take_interner();
+        for error in parser.errors() {
+            self.error(error.to_string(), error.span());
+        }
+        Some((parser, decls))
+    }
+
+    /// Reports a diagnostic to the session's [`DiagnosticsSink`], which
+    /// drops it if it is a cascade of one already reported.
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().report(diagnostic);
+    }
+
+    /// Reports an error at `span`.
+    pub fn error(&self, message: impl Into<String>, span: Span) {
+        self.report(
+            DiagnosticBuilder::new(
+                DiagnosticLevel::Error,
+                message.into(),
+                span,
+            )
+            .build(),
+        );
+    }
+
+    /// The number of errors reported and not yet taken, including those
+    /// over the error limit.
+    #[must_use]
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.borrow().error_count()
+    }
+
+    /// The number of errors reported over the error limit and not yet
+    /// taken, which [`Session::take_diagnostics`] leaves out.
+    #[must_use]
+    pub fn omitted_errors(&self) -> usize {
+        self.diagnostics.borrow().omitted()
+    }
+
+    /// Takes the diagnostics reported so far, sorted by file and position.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow_mut().take()
+    }
+
+    /// Formats `diagnostic` with the source it points at, in the file its
+    /// span is in.
+    #[must_use]
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        self.emitter.render_in(diagnostic, &self.sources)
+    }
+}
+
+/// `span`, in a file starting at `offset`.
+const fn shift(span: Span, offset: usize) -> Span {
+    Span {
+        start: span.start + offset,
+        end: span.end + offset,
+        ..span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_map_finds_files() {
+        let mut sources = SourceMap::new();
+        let a = sources.add("a.ox", "fn a() {}");
+        let b = sources.add("b.ox", "");
+        assert_eq!(sources.file(b).start(), FILE_SPACE);
+
+        assert_eq!(sources.find(Span::point(0, 1, 1)), Some(a));
+        // A file's end, where its `EOF` is, is still in it
+        assert_eq!(sources.find(Span::point(9, 1, 10)), Some(a));
+        assert_eq!(sources.find(Span::point(10, 1, 1)), None);
+        assert_eq!(sources.find(Span::point(FILE_SPACE, 1, 1)), Some(b));
+        assert_eq!(sources.find(Span::point(FILE_SPACE * 2, 1, 1)), None);
+    }
+
+    #[test]
+    fn test_files_share_symbols_and_keep_their_spans() {
+        let mut session = Session::new(SessionOptions::default());
+        let a = session.add_file("a.ox", "fn shared() -> Int { 1 }");
+        let b = session.add_file("b.ox", "fn other() -> Int { shared() }");
+        let (_, a_decls) = session.parse(a).unwrap();
+        let (_, b_decls) = session.parse(b).unwrap();
+        assert_eq!(session.error_count(), 0);
+
+        let shared = session.interner().get_symbol("shared").unwrap();
+        assert!(matches!(a_decls[0], Decl::Fn { name, .. } if name == shared));
+        let start = session.sources().file(b).start();
+        assert_eq!(b_decls[0].span().start, start);
+        assert_eq!(b_decls[0].span().start_line, 1);
+    }
+
+    #[test]
+    fn test_errors_render_against_their_file() {
+        let mut session = Session::new(SessionOptions::default());
+        let a = session.add_file("a.ox", "fn a() -> Int { 1 }\nfn b( {}");
+        let b = session.add_file("b.ox", "fn c() -> Int { 1 $ 2 }");
+        session.parse(a).unwrap();
+        assert!(session.parse(b).is_none());
+        assert_eq!(session.error_count(), 2);
+
+        let rendered: Vec<String> = session
+            .take_diagnostics()
+            .iter()
+            .map(|diagnostic| session.render(diagnostic))
+            .collect();
+        assert!(rendered[0].starts_with("a.ox:2:"), "{}", rendered[0]);
+        assert!(rendered[1].starts_with("b.ox:1:"), "{}", rendered[1]);
+        assert_eq!(session.error_count(), 0);
+
+        // The interner survives a file that did not lex
+        assert!(session.interner().get_symbol("a").is_some());
+        let whole = session.sources().file(b).span();
+        session.error("no `main`", whole);
+        let rendered = session.render(&session.take_diagnostics()[0]);
+        assert_eq!(rendered, "b.ox: error: no `main`\n");
+    }
+
+    #[test]
+    fn test_diagnostics_are_sorted_and_limited() {
+        let options = SessionOptions {
+            sink: SinkOptions {
+                max_errors: Some(2),
+                ..SinkOptions::default()
+            },
+            ..SessionOptions::default()
+        };
+        let mut session = Session::new(options);
+        let a = session.add_file("a.ox", "fn a( {}\nfn b( {}");
+        let b = session.add_file("b.ox", "fn c( {}");
+        session.parse(b).unwrap();
+        session.parse(a).unwrap();
+        assert_eq!(session.error_count(), 3);
+        assert_eq!(session.omitted_errors(), 1);
+
+        // Reported in `b` first, taken in `a` first, after the limit
+        let files: Vec<Option<FileId>> = session
+            .take_diagnostics()
+            .iter()
+            .map(|diagnostic| session.sources().find(diagnostic.span))
+            .collect();
+        assert_eq!(files, [Some(a), Some(b)]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Collecting the diagnostics of every phase.
+//!
+//! A [`DiagnosticsSink`] is where the phases compiling a program report
+//! what they find. It applies the policies the driver configured through
+//! [`SinkOptions`] as diagnostics arrive, and hands them back in the order
+//! a reader wants them:
+//!
+//! - A diagnostic at the same primary span as one already reported is a
+//!   cascade of it, and is dropped; a more severe one takes its place.
+//! - Warnings are reported, turned into errors or dropped, as
+//!   [`WarningPolicy`] says.
+//! - Once [`SinkOptions::max_errors`] errors are held, further errors are
+//!   only counted, in [`DiagnosticsSink::omitted`].
+//! - [`DiagnosticsSink::take`] sorts the diagnostics by where their spans
+//!   start. The spans of a [`Session`](crate::session::Session) are at the
+//!   offsets of their file, so this orders them by file, then by position.
+//!
+//! # Examples
+//!
+//! ```
+//! use oxidex_syntax::diagnostic::{DiagnosticBuilder, DiagnosticLevel};
+//! use oxidex_syntax::sink::{DiagnosticsSink, SinkOptions};
+//! use oxidex_syntax::span::Span;
+//!
+//! let error = |message: &str, start| {
+//!     let span = Span::new(start, start + 1, 1, start + 1, 1, start + 2);
+//!     DiagnosticBuilder::new(DiagnosticLevel::Error, message.into(), span)
+//!         .build()
+//! };
+//! let mut sink = DiagnosticsSink::new(SinkOptions::default());
+//! sink.report(error("type mismatch", 8));
+//! sink.report(error("undefined variable `x`", 2));
+//! // A cascade of the first error
+//! sink.report(error("cannot call `x`", 8));
+//!
+//! let messages: Vec<String> =
+//!     sink.take().into_iter().map(|d| d.message).collect();
+//! assert_eq!(messages, ["undefined variable `x`", "type mismatch"]);
+//! ```
+
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+use std::collections::HashMap;
+
+/// What a [`DiagnosticsSink`] does with warnings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WarningPolicy {
+    /// Report them as warnings
+    #[default]
+    Warn,
+    /// Report them as errors
+    Deny,
+    /// Drop them
+    Allow,
+}
+
+/// The policies a [`DiagnosticsSink`] applies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinkOptions {
+    /// The most errors to hold, if there is a limit
+    pub max_errors: Option<usize>,
+    /// What to do with warnings
+    pub warnings: WarningPolicy,
+}
+
+/// Collects the diagnostics of every phase, deduplicating cascades and
+/// applying the driver's policies.
+#[derive(Debug, Default)]
+pub struct DiagnosticsSink {
+    /// The policies applied
+    options: SinkOptions,
+    /// The diagnostics held, in the order they were reported
+    diagnostics: Vec<Diagnostic>,
+    /// The index in `diagnostics` of the diagnostic at each primary span
+    spans: HashMap<(usize, usize), usize>,
+    /// The number of errors held
+    errors: usize,
+    /// The number of errors dropped for being over the limit
+    omitted: usize,
+}
+
+impl DiagnosticsSink {
+    /// Creates an empty sink applying `options`.
+    #[must_use]
+    pub fn new(options: SinkOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// The policies the sink applies.
+    #[must_use]
+    pub const fn options(&self) -> SinkOptions {
+        self.options
+    }
+
+    /// Reports a diagnostic, applying the sink's policies.
+    pub fn report(&mut self, mut diagnostic: Diagnostic) {
+        if diagnostic.level == DiagnosticLevel::Warning {
+            match self.options.warnings {
+                WarningPolicy::Warn => {}
+                WarningPolicy::Deny => {
+                    diagnostic.level = DiagnosticLevel::Error
+                }
+                WarningPolicy::Allow => return,
+            }
+        }
+
+        // A span on line zero has no location, so sharing one says nothing
+        let key = (diagnostic.span.start, diagnostic.span.end);
+        let earlier = (diagnostic.span.start_line != 0)
+            .then(|| self.spans.get(&key).copied())
+            .flatten();
+        if let Some(index) = earlier {
+            let held = &self.diagnostics[index];
+            if severity(diagnostic.level) <= severity(held.level) {
+                return;
+            }
+            if diagnostic.level == DiagnosticLevel::Error {
+                if self.is_full() {
+                    self.omitted += 1;
+                    return;
+                }
+                self.errors += 1;
+            }
+            self.diagnostics[index] = diagnostic;
+            return;
+        }
+
+        if diagnostic.level == DiagnosticLevel::Error {
+            if self.is_full() {
+                self.omitted += 1;
+                return;
+            }
+            self.errors += 1;
+        }
+        if diagnostic.span.start_line != 0 {
+            self.spans.insert(key, self.diagnostics.len());
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Returns `true` if the sink holds as many errors as it may, so that
+    /// further errors are only counted.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.options
+            .max_errors
+            .is_some_and(|max| self.errors >= max)
+    }
+
+    /// The number of errors reported and not yet taken, counting those
+    /// over the limit.
+    #[must_use]
+    pub const fn error_count(&self) -> usize {
+        self.errors + self.omitted
+    }
+
+    /// The number of errors dropped for being over the limit.
+    #[must_use]
+    pub const fn omitted(&self) -> usize {
+        self.omitted
+    }
+
+    /// The number of diagnostics held.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    /// Returns `true` if no diagnostics are held.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Takes the diagnostics held, sorted by where their spans start,
+    /// leaving the sink empty.
+    ///
+    /// Diagnostics at the same place stay in the order they were reported.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|diagnostic| {
+            (diagnostic.span.start, diagnostic.span.end)
+        });
+        self.spans.clear();
+        self.errors = 0;
+        self.omitted = 0;
+        diagnostics
+    }
+}
+
+/// How much `level` matters, so that an error replaces a warning at the
+/// same span rather than being dropped as its cascade.
+const fn severity(level: DiagnosticLevel) -> u8 {
+    match level {
+        DiagnosticLevel::Error => 3,
+        DiagnosticLevel::Warning => 2,
+        DiagnosticLevel::Note => 1,
+        DiagnosticLevel::Help => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::DiagnosticBuilder;
+    use crate::span::Span;
+
+    fn diagnostic(
+        level: DiagnosticLevel,
+        message: &str,
+        at: usize,
+    ) -> Diagnostic {
+        let span = Span::new(at, at + 1, 1, at + 1, 1, at + 2);
+        DiagnosticBuilder::new(level, message.to_string(), span).build()
+    }
+
+    fn messages(sink: &mut DiagnosticsSink) -> Vec<String> {
+        sink.take().into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn test_cascades_are_dropped_and_order_is_by_span() {
+        let mut sink = DiagnosticsSink::new(SinkOptions::default());
+        sink.report(diagnostic(DiagnosticLevel::Warning, "unused", 9));
+        sink.report(diagnostic(DiagnosticLevel::Error, "mismatch", 9));
+        sink.report(diagnostic(DiagnosticLevel::Error, "cascade", 9));
+        sink.report(diagnostic(DiagnosticLevel::Error, "first", 1));
+        sink.report(diagnostic(DiagnosticLevel::Note, "later", 20));
+        assert_eq!((sink.len(), sink.error_count()), (3, 2));
+        assert_eq!(messages(&mut sink), ["first", "mismatch", "later"]);
+        assert!(sink.is_empty() && sink.error_count() == 0);
+
+        // Diagnostics without a location are never cascades
+        let nowhere = Span::point(0, 0, 0);
+        for message in ["a", "b"] {
+            let level = DiagnosticLevel::Error;
+            let build = DiagnosticBuilder::new(level, message.into(), nowhere);
+            sink.report(build.build());
+        }
+        assert_eq!(messages(&mut sink), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_error_limit() {
+        let options = SinkOptions {
+            max_errors: Some(2),
+            ..SinkOptions::default()
+        };
+        let mut sink = DiagnosticsSink::new(options);
+        for at in 0..5 {
+            sink.report(diagnostic(DiagnosticLevel::Error, "error", at));
+        }
+        sink.report(diagnostic(DiagnosticLevel::Warning, "warning", 10));
+        assert!(sink.is_full());
+        assert_eq!((sink.len(), sink.omitted(), sink.error_count()), (3, 3, 5));
+    }
+
+    #[test]
+    fn test_warning_policies() {
+        let report = |warnings| {
+            let options = SinkOptions {
+                warnings,
+                ..SinkOptions::default()
+            };
+            let mut sink = DiagnosticsSink::new(options);
+            sink.report(diagnostic(DiagnosticLevel::Warning, "unused", 1));
+            (sink.error_count(), sink.take().first().map(|d| d.level))
+        };
+        let (warning, error) =
+            (DiagnosticLevel::Warning, DiagnosticLevel::Error);
+        assert_eq!(report(WarningPolicy::Warn), (0, Some(warning)));
+        assert_eq!(report(WarningPolicy::Deny), (1, Some(error)));
+        assert_eq!(report(WarningPolicy::Allow), (0, None));
+    }
+}
@@ -69,6 +69,9 @@ pub enum TokenKind {
     /// Guard statement
     Guard,
 
+    /// Defer statement
+    Defer,
+
     /// Match expression
     Match,
 
@@ -90,6 +93,12 @@ pub enum TokenKind {
     /// Type alias
     Type,
 
+    /// Module import
+    Import,
+
+    /// Foreign function declarations
+    Extern,
+
     /// Public visibility
     Pub,
 
@@ -185,6 +194,9 @@ pub enum TokenKind {
     /// Question mark: `?`
     Question,
 
+    /// Typed hole: `???`
+    Hole,
+
     /// Assignment: `=`
     Eq,
 
@@ -234,6 +246,9 @@ pub enum TokenKind {
     /// Semicolon: `;`
     Semicolon,
 
+    /// At sign, starting an attribute: `@`
+    At,
+
     /// Thin arrow: `->`
     Arrow,
 
@@ -283,6 +298,7 @@ impl TokenKind {
                 | Self::If
                 | Self::Else
                 | Self::Guard
+                | Self::Defer
                 | Self::Match
                 | Self::For
                 | Self::While
@@ -290,6 +306,8 @@ impl TokenKind {
                 | Self::Const
                 | Self::Static
                 | Self::Type
+                | Self::Import
+                | Self::Extern
                 | Self::Pub
                 | Self::Prv
         )
@@ -418,6 +436,7 @@ impl fmt::Display for TokenKind {
             Self::If => write!(f, "if"),
             Self::Else => write!(f, "else"),
             Self::Guard => write!(f, "guard"),
+            Self::Defer => write!(f, "defer"),
             Self::Match => write!(f, "match"),
             Self::For => write!(f, "for"),
             Self::While => write!(f, "while"),
@@ -425,6 +444,8 @@ impl fmt::Display for TokenKind {
             Self::Const => write!(f, "const"),
             Self::Static => write!(f, "static"),
             Self::Type => write!(f, "type"),
+            Self::Import => write!(f, "import"),
+            Self::Extern => write!(f, "extern"),
             Self::Pub => write!(f, "pub"),
             Self::Prv => write!(f, "prv"),
             Self::SelfType => write!(f, "Self"),
@@ -475,6 +496,7 @@ impl fmt::Display for TokenKind {
             Self::Bang => write!(f, "!"),
             Self::Amp => write!(f, "&"),
             Self::Question => write!(f, "?"),
+            Self::Hole => write!(f, "???"),
             Self::Underscore => write!(f, "_"),
             Self::Eq => write!(f, "="),
             Self::In => write!(f, "in"),
@@ -495,6 +517,7 @@ impl fmt::Display for TokenKind {
             Self::ColonColon => write!(f, "::"),
             Self::Comma => write!(f, ","),
             Self::Semicolon => write!(f, ";"),
+            Self::At => write!(f, "@"),
             Self::Arrow => write!(f, "->"),
             Self::FatArrow => write!(f, "=>"),
 
@@ -0,0 +1,379 @@
+//! Read-only traversal of the `OxideX` AST.
+//!
+//! A [`Visitor`] has one method per kind of node, each of which by default
+//! visits the node's children through the matching `walk_*` function. An
+//! analysis overrides the methods for the nodes it cares about, and calls
+//! the `walk_*` function from its override to keep descending, which lets
+//! it do work both before and after a node's children are visited: entering
+//! and leaving a scope, for instance.
+//!
+//! # Examples
+//!
+//! ```
+//! use oxidex_syntax::ast::Expr;
+//! use oxidex_syntax::visit::{Visitor, walk_expr};
+//!
+//! /// Counts the integer literals in an expression.
+//! struct Integers(usize);
+//!
+//! impl Visitor for Integers {
+//!     fn visit_expr(&mut self, expr: &Expr<'_>) {
+//!         if matches!(expr, Expr::IntegerLiteral { .. }) {
+//!             self.0 += 1;
+//!         }
+//!         walk_expr(self, expr);
+//!     }
+//! }
+//! ```
+
+use crate::ast::decl::FnDecl;
+use crate::ast::expr::{InterpolationPart, MatchArm};
+use crate::ast::{Decl, Expr, FnParam, Pattern, Stmt};
+
+/// A read-only pass over the AST.
+///
+/// Every method defaults to walking the node's children, so a visitor
+/// only overrides what it needs.
+pub trait Visitor {
+    /// Visits a declaration.
+    fn visit_decl(&mut self, decl: &Decl<'_>) {
+        walk_decl(self, decl);
+    }
+
+    /// Visits a free function: its parameters, then its body.
+    fn visit_fn(&mut self, params: &[FnParam], body: &Expr<'_>) {
+        walk_fn(self, params, body);
+    }
+
+    /// Visits a method of an enum or an `impl` block.
+    fn visit_method(&mut self, method: &FnDecl<'_>) {
+        walk_method(self, method);
+    }
+
+    /// Visits a function or method parameter. Parameters have no children.
+    fn visit_param(&mut self, param: &FnParam) {
+        let _ = param;
+    }
+
+    /// Visits a statement.
+    fn visit_stmt(&mut self, stmt: &Stmt<'_>) {
+        walk_stmt(self, stmt);
+    }
+
+    /// Visits an expression.
+    fn visit_expr(&mut self, expr: &Expr<'_>) {
+        walk_expr(self, expr);
+    }
+
+    /// Visits a `match` arm: its pattern, guard, then body.
+    fn visit_arm(&mut self, arm: &MatchArm<'_>) {
+        walk_arm(self, arm);
+    }
+
+    /// Visits a pattern.
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+}
+
+/// Visits the children of `decl`: the functions, methods and initializers
+/// it declares.
+pub fn walk_decl<V: Visitor + ?Sized>(visitor: &mut V, decl: &Decl<'_>) {
+    match decl {
+        Decl::Fn { params, body, .. } => visitor.visit_fn(params, body),
+        Decl::Enum { methods, .. } | Decl::Impl { methods, .. } => {
+            for method in methods {
+                visitor.visit_method(method);
+            }
+        }
+        Decl::Const { value, .. } => visitor.visit_expr(value),
+        Decl::Static { init: Some(init), .. } => visitor.visit_expr(init),
+        Decl::Static { init: None, .. }
+        | Decl::Struct { .. }
+        | Decl::Class { .. }
+        | Decl::Protocol { .. }
+        | Decl::TypeAlias { .. }
+        | Decl::Import { .. }
+        | Decl::Extern { .. } => {}
+    }
+}
+
+/// Visits the parameters of a function, then its body.
+pub fn walk_fn<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    params: &[FnParam],
+    body: &Expr<'_>,
+) {
+    for param in params {
+        visitor.visit_param(param);
+    }
+    visitor.visit_expr(body);
+}
+
+/// Visits a method as a function.
+pub fn walk_method<V: Visitor + ?Sized>(visitor: &mut V, method: &FnDecl<'_>) {
+    visitor.visit_fn(&method.params, method.body);
+}
+
+/// Visits the children of `stmt`.
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt<'_>) {
+    match stmt {
+        Stmt::Let { init, .. } | Stmt::Mut { init, .. } => {
+            if let Some(init) = init {
+                visitor.visit_expr(init);
+            }
+        }
+        Stmt::Return { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        Stmt::If { condition, then_branch, else_branch, .. } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_expr(else_branch);
+            }
+        }
+        Stmt::Guard { condition, else_branch, .. } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(else_branch);
+        }
+        Stmt::Match { scrutinee, arms, .. } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+        Stmt::ForLoop { pattern, iter, body, .. } => {
+            visitor.visit_expr(iter);
+            visitor.visit_pattern(pattern);
+            visitor.visit_expr(body);
+        }
+        Stmt::WhileLoop { condition, body, .. } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(body);
+        }
+        Stmt::Defer { body, .. } => visitor.visit_expr(body),
+        Stmt::Assign { target, value, .. } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        Stmt::Expr { expr, .. } => visitor.visit_expr(expr),
+    }
+}
+
+/// Visits the children of `expr`, in evaluation order.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr<'_>) {
+    match expr {
+        Expr::IntegerLiteral { .. }
+        | Expr::FloatLiteral { .. }
+        | Expr::StringLiteral { .. }
+        | Expr::BoolLiteral { .. }
+        | Expr::Nil { .. }
+        | Expr::Hole { .. }
+        | Expr::Identifier(_)
+        | Expr::Path { .. } => {}
+        Expr::Unary { operand, .. } => visitor.visit_expr(operand),
+        Expr::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        Expr::If { condition, then_branch, else_branch, .. } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_expr(else_branch);
+            }
+        }
+        Expr::Match { scrutinee, arms, .. } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+        Expr::Block { stmts, expr, .. } => {
+            for stmt in stmts {
+                visitor.visit_stmt(stmt);
+            }
+            if let Some(expr) = expr {
+                visitor.visit_expr(expr);
+            }
+        }
+        Expr::ForLoop { pattern, iter, body, .. } => {
+            visitor.visit_expr(iter);
+            visitor.visit_pattern(pattern);
+            visitor.visit_expr(body);
+        }
+        Expr::WhileLoop { condition, body, .. } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(body);
+        }
+        Expr::Call { callee, args, .. } => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg.value);
+            }
+        }
+        Expr::MethodCall { receiver, args, .. } => {
+            visitor.visit_expr(receiver);
+            for arg in args {
+                visitor.visit_expr(arg.value);
+            }
+        }
+        Expr::Closure { params, body, .. } => visitor.visit_fn(params, body),
+        Expr::Struct { fields, .. } => {
+            for field in fields {
+                if let Some(value) = field.value {
+                    visitor.visit_expr(value);
+                }
+            }
+        }
+        Expr::Enum { payload, .. } => {
+            if let Some(payload) = payload {
+                visitor.visit_expr(payload);
+            }
+        }
+        Expr::Array { elements, .. } => {
+            for element in elements {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::Dict { entries, .. } => {
+            for entry in entries {
+                visitor.visit_expr(entry.key);
+                visitor.visit_expr(entry.value);
+            }
+        }
+        Expr::Field { object, .. } => visitor.visit_expr(object),
+        Expr::Index { collection, index, .. } => {
+            visitor.visit_expr(collection);
+            visitor.visit_expr(index);
+        }
+        Expr::Paren { expr, .. } | Expr::Comptime { expr, .. } => {
+            visitor.visit_expr(expr);
+        }
+        Expr::Interpolation { parts, .. } => {
+            for part in parts {
+                if let InterpolationPart::Expr(expr) = part {
+                    visitor.visit_expr(expr);
+                }
+            }
+        }
+    }
+}
+
+/// Visits the pattern of a `match` arm, then its guard and body.
+pub fn walk_arm<V: Visitor + ?Sized>(visitor: &mut V, arm: &MatchArm<'_>) {
+    visitor.visit_pattern(&arm.pattern);
+    if let Some(guard) = arm.guard {
+        visitor.visit_expr(guard);
+    }
+    visitor.visit_expr(arm.body);
+}
+
+/// Visits the subpatterns of `pattern`.
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Wildcard { .. }
+        | Pattern::Literal { .. }
+        | Pattern::Variable { .. } => {}
+        Pattern::Struct { fields, .. } => {
+            for field in fields {
+                if let Some(pattern) = &field.pattern {
+                    visitor.visit_pattern(pattern);
+                }
+            }
+        }
+        Pattern::Enum { payload, .. } => {
+            if let Some(payload) = payload {
+                visitor.visit_pattern(payload);
+            }
+        }
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                visitor.visit_pattern(element);
+            }
+        }
+        Pattern::Array { elements, rest, .. } => {
+            for element in elements {
+                visitor.visit_pattern(element);
+            }
+            if let Some(rest) = rest {
+                visitor.visit_pattern(rest);
+            }
+        }
+        Pattern::Or { left, right, .. } => {
+            visitor.visit_pattern(left);
+            visitor.visit_pattern(right);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+    use crate::parser::Parser;
+    use oxidex_mem::{LocalArena, Symbol};
+
+    /// Records the identifiers read and the variables bound, in order.
+    #[derive(Default)]
+    struct Names {
+        read: Vec<Symbol>,
+        bound: Vec<Symbol>,
+        params: usize,
+    }
+
+    impl Visitor for Names {
+        fn visit_param(&mut self, _param: &FnParam) {
+            self.params += 1;
+        }
+
+        fn visit_expr(&mut self, expr: &Expr<'_>) {
+            if let Expr::Identifier(name) = expr {
+                self.read.push(*name);
+            }
+            walk_expr(self, expr);
+        }
+
+        fn visit_pattern(&mut self, pattern: &Pattern) {
+            if let Pattern::Variable { name, .. } = pattern {
+                self.bound.push(*name);
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    #[test]
+    fn test_visitor_reaches_every_node() {
+        let source = "fn f(a: Int, b: Int) -> Int {\n\
+                      let c = g(a, [b]);\n\
+                      match (c) { Some(d) => e + d, _ => h }\n\
+                      }\n\
+                      impl T { fn m(x: Int) { x.y = z; } }";
+        let (tokens, interner) =
+            Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser =
+            Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+
+        let mut names = Names::default();
+        for decl in &decls {
+            names.visit_decl(decl);
+        }
+        let resolve = |symbols: &[Symbol]| -> Vec<String> {
+            symbols
+                .iter()
+                .map(|s| parser.interner().resolve(*s).unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(names.params, 3);
+        assert_eq!(resolve(&names.bound), ["d"]);
+        assert_eq!(
+            resolve(&names.read),
+            ["g", "a", "b", "c", "e", "d", "h", "x", "z"]
+        );
+    }
+}
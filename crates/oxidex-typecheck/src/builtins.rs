@@ -0,0 +1,121 @@
+//! Signatures of the standard builtins.
+//!
+//! Every backend provides the standard builtins, such as `print` and
+//! `assert`, under the same names; this module gives the type each of them
+//! has, so that programs type check the same whichever backend runs them.
+//! A function declared by the program shadows a builtin of the same name.
+
+use crate::context::Scheme;
+use crate::infer::Context;
+use crate::types::{PrimTy, Ty};
+
+/// Names of the standard builtins, in the order their signatures are
+/// listed by [`standard`].
+pub const STANDARD: [&str; 10] =
+    ["print", "len", "assert", "clock", "throw", "unwrap", "range", "catch", "read_file", "write_file"];
+
+/// The signature of the standard builtin `name`, or `None` if there is no
+/// such builtin:
+///
+/// - `print(value)` and `throw(value)` take a value of any type
+/// - `len(collection)` takes a value of any type and returns an `Int`
+/// - `assert(condition)` takes a `Bool`
+/// - `clock()` returns a `Float`
+/// - `unwrap(optional)` takes a `T?` and returns a `T`
+/// - `range(start, end)` takes two `Int`s and returns the `Range` of the
+///   integers from `start` up to `end`
+/// - `catch(body)` takes a function without parameters returning a `T` and
+///   returns a `Result` of a `T` or an error
+/// - `read_file(path)` takes a `String` and returns the file's text, and
+///   `write_file(path, text)` takes two `String`s
+#[must_use]
+pub fn signature(name: &str) -> Option<Scheme> {
+    let scheme = match name {
+        "print" | "throw" => generic_fn(1, PrimTy::Unit),
+        "len" => generic_fn(1, PrimTy::Int64),
+        "assert" => function(vec![prim(PrimTy::Bool)], prim(PrimTy::Unit)),
+        "clock" => function(vec![], prim(PrimTy::Float64)),
+        "unwrap" => {
+            let optional = Ty::Optional(Box::new(Ty::TypeVar(0)));
+            Scheme::poly(vec![0], function(vec![optional], Ty::TypeVar(0)).ty)
+        }
+        "range" => function(vec![prim(PrimTy::Int64); 2], Ty::Range),
+        "catch" => {
+            let body = function(vec![], Ty::TypeVar(0)).ty;
+            let result = Ty::Result { ok: Box::new(Ty::TypeVar(0)), error: Box::new(Ty::TypeVar(1)) };
+            Scheme::poly(vec![0, 1], function(vec![body], result).ty)
+        }
+        "read_file" => function(vec![prim(PrimTy::String)], prim(PrimTy::String)),
+        "write_file" => function(vec![prim(PrimTy::String); 2], prim(PrimTy::Unit)),
+        _ => return None,
+    };
+    Some(scheme)
+}
+
+/// Every standard builtin with its signature.
+#[must_use]
+pub fn standard() -> Vec<(&'static str, Scheme)> {
+    STANDARD.into_iter().filter_map(|name| Some((name, signature(name)?))).collect()
+}
+
+/// Bind the signature of each standard builtin in `names` that the program
+/// mentions in the type checker's environment, so calls to them type check.
+pub fn declare<'a>(ctx: &mut Context<'_>, names: impl IntoIterator<Item = &'a str>) {
+    for name in names {
+        if let (Some(sym), Some(scheme)) = (ctx.interner.get_symbol(name), signature(name)) {
+            ctx.env.bind(sym, scheme);
+        }
+    }
+}
+
+fn prim(prim: PrimTy) -> Ty {
+    Ty::Primitive(prim)
+}
+
+/// A monomorphic function signature.
+fn function(params: Vec<Ty>, return_type: Ty) -> Scheme {
+    let labels = vec![None; params.len()];
+    Scheme::mono(Ty::Function { params, return_type: Box::new(return_type), labels })
+}
+
+/// A signature taking `arity` arguments of independent generic types.
+fn generic_fn(arity: u32, return_type: PrimTy) -> Scheme {
+    let vars: Vec<u32> = (0..arity).collect();
+    let Scheme { ty, .. } = function(vars.iter().map(|&var| Ty::TypeVar(var)).collect(), prim(return_type));
+    Scheme::poly(vars, ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::StringInterner;
+
+    #[test]
+    fn test_standard_signatures() {
+        let all = standard();
+        assert_eq!(all.len(), STANDARD.len());
+        assert!(signature("missing").is_none());
+        assert_eq!(signature("len").unwrap().vars, [0]);
+        assert_eq!(signature("catch").unwrap().vars, [0, 1]);
+        assert!(signature("clock").unwrap().vars.is_empty());
+        match signature("unwrap").unwrap().ty {
+            Ty::Function { params, return_type, .. } => {
+                assert_eq!(params, [Ty::Optional(Box::new(Ty::TypeVar(0)))]);
+                assert_eq!(*return_type, Ty::TypeVar(0));
+            }
+            other => panic!("Expected a function, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_declare_binds_mentioned_names() {
+        let mut interner = StringInterner::new();
+        let print = interner.intern("print");
+        let assert = interner.intern("assert");
+        let mut ctx = Context::new(&interner);
+        declare(&mut ctx, ["print", "clock", "missing"]);
+        assert!(ctx.env.lookup(print).is_some());
+        // Only the names asked for are bound
+        assert!(ctx.env.lookup(assert).is_none());
+    }
+}
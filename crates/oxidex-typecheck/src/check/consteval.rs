@@ -0,0 +1,486 @@
+//! Compile-time evaluation of constant expressions.
+//!
+//! Constant initializers and array sizes are folded while checking:
+//!
+//! ```ignore
+//! const WIDTH: Int = 16;
+//! const CELLS: Int = WIDTH * WIDTH - 1;
+//! let grid: [Bool; CELLS] = ...;
+//! ```
+//!
+//! Literals, references to earlier constants, parentheses, and unary and
+//! binary operators over them are constant. Anything else (calls, variables,
+//! field accesses) is rejected with [`TypeError::NotConstant`].
+//!
+//! `comptime` expressions are folded the same way wherever they appear, and
+//! `comptime if` picks its branch while checking (see [`comptime_branch`]).
+//! Constants can be given their values from outside the program, as the CLI
+//! does for `--define NAME=VALUE`, through [`Context::defines`].
+
+use crate::error::{Result, TypeError};
+use crate::infer::Context;
+use crate::types::{PrimTy, Ty};
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use oxidex_syntax::lexer::string_value;
+use oxidex_syntax::{Expr, Span, Spanned};
+use std::fmt;
+
+/// The value of a folded constant expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    /// Integer of any width
+    Int(i128),
+    /// Floating-point number
+    Float(f64),
+    /// Boolean
+    Bool(bool),
+    /// String
+    String(String),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Int(value) => write!(f, "{}", value),
+            ConstValue::Float(value) => write!(f, "{}", value),
+            ConstValue::Bool(value) => write!(f, "{}", value),
+            ConstValue::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// Evaluate a constant expression.
+pub fn eval_const(ctx: &Context<'_>, expr: &Expr<'_>) -> Result<ConstValue> {
+    match expr {
+        Expr::IntegerLiteral { value, span, .. } => {
+            let text = ctx.interner.resolve(*value).unwrap_or("");
+            parse_int(text)
+                .map(ConstValue::Int)
+                .ok_or_else(|| const_error(format!("integer literal {} is too large", text), *span))
+        }
+
+        Expr::FloatLiteral { value, span, .. } => {
+            let text = ctx.interner.resolve(*value).unwrap_or("");
+            let digits: String = text
+                .trim_end_matches(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E')
+                .chars()
+                .filter(|&c| c != '_')
+                .collect();
+            digits
+                .parse()
+                .map(ConstValue::Float)
+                .map_err(|_| const_error(format!("invalid float literal {}", text), *span))
+        }
+
+        Expr::BoolLiteral { value, .. } => Ok(ConstValue::Bool(*value)),
+
+        Expr::StringLiteral { value, .. } => Ok(ConstValue::String(string_value(
+            ctx.interner.resolve(*value).unwrap_or(""),
+        ))),
+
+        Expr::Paren { expr, .. } => eval_const(ctx, expr),
+        Expr::Block { stmts, expr: Some(expr), .. } if stmts.is_empty() => eval_const(ctx, expr),
+
+        Expr::Comptime { expr: inner, span } => match inner {
+            Expr::If { .. } => match comptime_branch(ctx, inner)? {
+                Some(branch) => eval_const(ctx, branch),
+                None => Err(TypeError::NotConstant { span: *span }),
+            },
+            _ => eval_const(ctx, inner),
+        },
+
+        Expr::Identifier(name) => lookup_const(ctx, *name, expr.span()),
+        Expr::Path { segments, span } if segments.len() == 1 => lookup_const(ctx, segments[0], *span),
+
+        Expr::Unary { op, operand, span } => {
+            let value = eval_const(ctx, operand)?;
+            match (op, value) {
+                (UnaryOp::Minus, ConstValue::Int(v)) => v
+                    .checked_neg()
+                    .map(ConstValue::Int)
+                    .ok_or_else(|| overflow(*span)),
+                (UnaryOp::Minus, ConstValue::Float(v)) => Ok(ConstValue::Float(-v)),
+                (UnaryOp::Negate, ConstValue::Bool(v)) => Ok(ConstValue::Bool(!v)),
+                (op, value) => Err(const_error(
+                    format!("cannot apply `{}` to constant {}", unary_symbol(op), value),
+                    *span,
+                )),
+            }
+        }
+
+        Expr::Binary { op, left, right, span } => {
+            let left = eval_const(ctx, left)?;
+            let right = eval_const(ctx, right)?;
+            eval_binary(op, left, right, *span)
+        }
+
+        _ => Err(TypeError::NotConstant { span: expr.span() }),
+    }
+}
+
+/// Select the branch of a `comptime if` by folding its conditions.
+///
+/// An `else if` is folded as part of the same chain. Returns `None` when no
+/// condition holds and there is no final `else`; `expr` that is not an `if`
+/// is its own branch.
+pub fn comptime_branch<'e>(ctx: &Context<'_>, expr: &'e Expr<'e>) -> Result<Option<&'e Expr<'e>>> {
+    let Expr::If { condition, then_branch, else_branch, .. } = expr else {
+        return Ok(Some(expr));
+    };
+    match eval_const(ctx, condition)? {
+        ConstValue::Bool(true) => Ok(Some(then_branch)),
+        ConstValue::Bool(false) => match else_branch {
+            Some(branch @ Expr::If { .. }) => comptime_branch(ctx, branch),
+            branch => Ok(*branch),
+        },
+        other => Err(const_error(
+            format!("`comptime if` condition must be a Bool, found {}", other),
+            condition.span(),
+        )),
+    }
+}
+
+/// Give a constant of type `ty` the value defined for it from outside the
+/// program.
+///
+/// An integer defines a float constant too; any other mismatch between the
+/// value and the type is an error.
+pub fn apply_define(ctx: &Context<'_>, define: &ConstValue, ty: &Ty, span: Span) -> Result<ConstValue> {
+    use PrimTy::{Float32, Float64, Int8, Int16, Int32, Int64, Int128, UInt8, UInt16, UInt32, UInt64, UInt128};
+
+    let value = match (define, ty) {
+        (ConstValue::Int(v), Ty::Primitive(Float32 | Float64)) => ConstValue::Float(*v as f64),
+        (ConstValue::Int(_), Ty::Primitive(Int8 | Int16 | Int32 | Int64 | Int128))
+        | (ConstValue::Int(_), Ty::Primitive(UInt8 | UInt16 | UInt32 | UInt64 | UInt128))
+        | (ConstValue::Float(_), Ty::Primitive(Float32 | Float64))
+        | (ConstValue::Bool(_), Ty::Primitive(PrimTy::Bool))
+        | (ConstValue::String(_), Ty::Primitive(PrimTy::String)) => define.clone(),
+        _ => {
+            return Err(const_error(
+                format!("defined value {} does not have the constant's type {}", define, ty.display(ctx.interner)),
+                span,
+            ));
+        }
+    };
+    check_const_fits(&value, ty, span)?;
+    Ok(value)
+}
+
+impl ConstValue {
+    /// Read a value given on the command line: `true` and `false` are
+    /// booleans, decimal numbers are integers or floats, and anything else
+    /// is a string.
+    pub fn parse(text: &str) -> Self {
+        let numeric = text.strip_prefix('-').unwrap_or(text).starts_with(|c: char| c.is_ascii_digit());
+        match text {
+            "true" => ConstValue::Bool(true),
+            "false" => ConstValue::Bool(false),
+            _ if numeric => match (text.parse(), text.parse()) {
+                (Ok(value), _) => ConstValue::Int(value),
+                (_, Ok(value)) => ConstValue::Float(value),
+                _ => ConstValue::String(text.to_string()),
+            },
+            _ => ConstValue::String(text.to_string()),
+        }
+    }
+}
+
+/// Evaluate the size of a fixed-size array type `[T; N]`.
+///
+/// The size is either an integer literal or the name of an integer
+/// constant, and must not be negative.
+pub fn eval_array_size(ctx: &Context<'_>, size: Symbol, span: Span) -> Result<u64> {
+    let text = ctx.interner.resolve(size).unwrap_or("");
+    let value = if text.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_int(text).ok_or_else(|| const_error(format!("array size {} is too large", text), span))?
+    } else {
+        match lookup_const(ctx, size, span)? {
+            ConstValue::Int(value) => value,
+            other => {
+                return Err(const_error(
+                    format!("array size must be an integer, found {}", other),
+                    span,
+                ));
+            }
+        }
+    };
+    u64::try_from(value).map_err(|_| const_error(format!("array size {} is negative", value), span))
+}
+
+/// Check that a constant fits the type it is declared with.
+pub fn check_const_fits(value: &ConstValue, ty: &Ty, span: Span) -> Result<()> {
+    let (ConstValue::Int(v), Ty::Primitive(prim)) = (value, ty) else {
+        return Ok(());
+    };
+    let range: Option<(i128, i128)> = match prim {
+        PrimTy::Int8 => Some((i8::MIN.into(), i8::MAX.into())),
+        PrimTy::Int16 => Some((i16::MIN.into(), i16::MAX.into())),
+        PrimTy::Int32 => Some((i32::MIN.into(), i32::MAX.into())),
+        PrimTy::Int64 => Some((i64::MIN.into(), i64::MAX.into())),
+        PrimTy::UInt8 => Some((0, u8::MAX.into())),
+        PrimTy::UInt16 => Some((0, u16::MAX.into())),
+        PrimTy::UInt32 => Some((0, u32::MAX.into())),
+        PrimTy::UInt64 => Some((0, u64::MAX.into())),
+        PrimTy::UInt128 => Some((0, i128::MAX)),
+        _ => None,
+    };
+    match range {
+        Some((min, max)) if *v < min || *v > max => Err(const_error(
+            format!("constant {} does not fit in {:?}", v, prim),
+            span,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Look up the value of a previously declared constant.
+fn lookup_const(ctx: &Context<'_>, name: Symbol, span: Span) -> Result<ConstValue> {
+    ctx.consts
+        .get(&name)
+        .cloned()
+        .ok_or(TypeError::NotConstant { span })
+}
+
+/// Fold a binary operation on two constants.
+fn eval_binary(op: &BinaryOp, left: ConstValue, right: ConstValue, span: Span) -> Result<ConstValue> {
+    use ConstValue::{Bool, Float, Int};
+
+    let result = match (op, &left, &right) {
+        (BinaryOp::Add, Int(a), Int(b)) => a.checked_add(*b).map(Int),
+        (BinaryOp::Sub, Int(a), Int(b)) => a.checked_sub(*b).map(Int),
+        (BinaryOp::Mul, Int(a), Int(b)) => a.checked_mul(*b).map(Int),
+        (BinaryOp::Div | BinaryOp::Mod, Int(_), Int(0)) => {
+            return Err(const_error("division by zero in constant".to_string(), span));
+        }
+        (BinaryOp::Div, Int(a), Int(b)) => a.checked_div(*b).map(Int),
+        (BinaryOp::Mod, Int(a), Int(b)) => a.checked_rem(*b).map(Int),
+
+        (BinaryOp::Add, Float(a), Float(b)) => Some(Float(a + b)),
+        (BinaryOp::Sub, Float(a), Float(b)) => Some(Float(a - b)),
+        (BinaryOp::Mul, Float(a), Float(b)) => Some(Float(a * b)),
+        (BinaryOp::Div, Float(a), Float(b)) => Some(Float(a / b)),
+
+        (BinaryOp::Add, ConstValue::String(a), ConstValue::String(b)) => {
+            Some(ConstValue::String(format!("{}{}", a, b)))
+        }
+
+        (BinaryOp::And, Bool(a), Bool(b)) => Some(Bool(*a && *b)),
+        (BinaryOp::Or, Bool(a), Bool(b)) => Some(Bool(*a || *b)),
+
+        (BinaryOp::Eq, a, b) => Some(Bool(a == b)),
+        (BinaryOp::Neq, a, b) => Some(Bool(a != b)),
+        (BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte, Int(a), Int(b)) => {
+            Some(Bool(compare(op, a.cmp(b))))
+        }
+        (BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte, Float(a), Float(b)) => {
+            a.partial_cmp(b).map(|ordering| Bool(compare(op, ordering)))
+        }
+
+        _ => {
+            return Err(const_error(
+                format!("cannot apply `{}` to constants {} and {}", op, left, right),
+                span,
+            ));
+        }
+    };
+    result.ok_or_else(|| overflow(span))
+}
+
+/// Decide a comparison operator from an ordering.
+fn compare(op: &BinaryOp, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Lte => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+/// Parse the text of an integer literal, including `0x`/`0b` prefixes,
+/// `_` separators, and a trailing type suffix.
+fn parse_int(text: &str) -> Option<i128> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        (bin, 2)
+    } else {
+        (text, 10)
+    };
+    let digits: String = digits
+        .chars()
+        .take_while(|c| c.is_digit(radix) || *c == '_')
+        .filter(|&c| c != '_')
+        .collect();
+    i128::from_str_radix(&digits, radix).ok()
+}
+
+/// Get the source form of a unary operator.
+fn unary_symbol(op: &UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Minus => "-",
+        UnaryOp::Negate => "!",
+    }
+}
+
+/// Build the error for a constant that overflows `i128`.
+fn overflow(span: Span) -> TypeError {
+    const_error("arithmetic overflow in constant".to_string(), span)
+}
+
+/// Build a constant evaluation error.
+fn const_error(message: String, span: Span) -> TypeError {
+    TypeError::ConstEval { message, span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::StringInterner;
+
+    fn span() -> Span {
+        Span::new(0, 0, 0, 0, 0, 0)
+    }
+
+    #[test]
+    fn test_parse_int_literals() {
+        assert_eq!(parse_int("1_000"), Some(1000));
+        assert_eq!(parse_int("0xFF"), Some(255));
+        assert_eq!(parse_int("0b101"), Some(5));
+        assert_eq!(parse_int("42i32"), Some(42));
+    }
+
+    #[test]
+    fn test_fold_constant_expressions() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["WIDTH", "16", "1", "0", "x"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [width, sixteen, one, zero, x] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+        ctx.consts.insert(width, ConstValue::Int(16));
+
+        // WIDTH * 16 - 1
+        let lhs = Expr::Identifier(width);
+        let rhs = Expr::IntegerLiteral { value: sixteen, type_suffix: None, span: span() };
+        let product = Expr::Binary { left: &lhs, op: BinaryOp::Mul, right: &rhs, span: span() };
+        let lit_one = Expr::IntegerLiteral { value: one, type_suffix: None, span: span() };
+        let expr = Expr::Binary { left: &product, op: BinaryOp::Sub, right: &lit_one, span: span() };
+        assert_eq!(eval_const(&ctx, &expr).unwrap(), ConstValue::Int(255));
+
+        // Variables are not constants
+        let variable = Expr::Binary { left: &lhs, op: BinaryOp::Add, right: &Expr::Identifier(x), span: span() };
+        assert!(matches!(eval_const(&ctx, &variable), Err(TypeError::NotConstant { .. })));
+
+        // Division by zero is reported
+        let zero = Expr::IntegerLiteral { value: zero, type_suffix: None, span: span() };
+        let division = Expr::Binary { left: &lhs, op: BinaryOp::Div, right: &zero, span: span() };
+        assert!(matches!(eval_const(&ctx, &division), Err(TypeError::ConstEval { .. })));
+
+        assert_eq!(eval_array_size(&ctx, width, span()).unwrap(), 16);
+        assert!(check_const_fits(&ConstValue::Int(300), &Ty::Primitive(PrimTy::UInt8), span()).is_err());
+    }
+
+    #[test]
+    fn test_comptime_and_defines() {
+        let mut interner = StringInterner::new();
+        let debug = interner.intern("DEBUG");
+        let mut ctx = Context::new(&interner);
+        ctx.consts.insert(debug, ConstValue::Bool(false));
+
+        // `comptime if DEBUG { 1 } else if !DEBUG { 2 }` selects the second
+        // branch; the logical `!` is `UnaryOp::Negate`, `-` is `UnaryOp::Minus`
+        let (one, two) = (Expr::Nil { span: span() }, Expr::Hole { span: span() });
+        let condition = Expr::Identifier(debug);
+        let negated = Expr::Unary { op: UnaryOp::Negate, operand: &condition, span: span() };
+        let inner = Expr::If { condition: &negated, then_branch: &two, else_branch: None, span: span() };
+        let chain = Expr::If { condition: &condition, then_branch: &one, else_branch: Some(&inner), span: span() };
+        assert!(matches!(comptime_branch(&ctx, &chain).unwrap(), Some(Expr::Hole { .. })));
+        assert!(comptime_branch(&ctx, &inner).unwrap().is_some());
+        let non_bool = Expr::If { condition: &one, then_branch: &one, else_branch: None, span: span() };
+        assert!(matches!(comptime_branch(&ctx, &non_bool), Err(TypeError::NotConstant { .. })));
+
+        assert_eq!(ConstValue::parse("true"), ConstValue::Bool(true));
+        assert_eq!(ConstValue::parse("-12"), ConstValue::Int(-12));
+        assert_eq!(ConstValue::parse("2.5"), ConstValue::Float(2.5));
+        assert_eq!(ConstValue::parse("inf"), ConstValue::String("inf".to_string()));
+
+        let float = Ty::Primitive(PrimTy::Float64);
+        assert_eq!(apply_define(&ctx, &ConstValue::Int(2), &float, span()).unwrap(), ConstValue::Float(2.0));
+        let byte = Ty::Primitive(PrimTy::UInt8);
+        assert!(apply_define(&ctx, &ConstValue::Int(256), &byte, span()).is_err());
+        assert!(apply_define(&ctx, &ConstValue::Bool(true), &byte, span()).is_err());
+    }
+
+    #[test]
+    fn test_const_declarations_size_arrays() {
+        use crate::check::{ast_to_ty, check_decl};
+        use oxidex_syntax::ast::decl::{Decl, Visibility};
+        use oxidex_syntax::ast::ty::Type;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["SIZE", "BAD", "Int", "4", "f"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [size, bad, int_sym, four, f] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let int = Type::Simple { name: int_sym, span: span() };
+        let lit = Expr::IntegerLiteral { value: four, type_suffix: None, span: span() };
+        let square = Expr::Binary { left: &lit, op: BinaryOp::Mul, right: &lit, span: span() };
+        let constant = |name, value| Decl::Const {
+            name,
+            type_annotation: int.clone(),
+            value,
+            visibility: Visibility::Private,
+            span: span(),
+        };
+
+        check_decl(&mut ctx, &constant(size, &square)).unwrap();
+        assert_eq!(ctx.consts.get(&size), Some(&ConstValue::Int(16)));
+
+        let sized = |size| Type::Array { element: Box::new(int.clone()), size: Some(size), span: span() };
+        assert!(ast_to_ty(&mut ctx, &sized(size)).is_ok());
+        assert!(matches!(ast_to_ty(&mut ctx, &sized(f)), Err(TypeError::NotConstant { .. })));
+
+        // Calls are not constant, even when they type check
+        ctx.env.bind(
+            f,
+            crate::context::Scheme::mono(Ty::Function {
+                params: vec![],
+                return_type: Box::new(Ty::Primitive(PrimTy::Int64)),
+                labels: vec![],
+            }),
+        );
+        let callee = Expr::Identifier(f);
+        let call = Expr::Call { callee: &callee, args: vec![], span: span() };
+        let err = check_decl(&mut ctx, &constant(bad, &call)).unwrap_err();
+        assert!(matches!(err, TypeError::NotConstant { .. }));
+    }
+
+    #[test]
+    fn test_string_constants_fold_decoded_literals() {
+        use crate::check::check_decl;
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let source = r#"const GREETING: String = "a" + "b\n\"c\"";"#;
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(4096));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty());
+
+        let interner = parser.interner();
+        let mut ctx = Context::new(interner);
+        check_decl(&mut ctx, &decls[0]).unwrap();
+        let greeting = interner.get_symbol("GREETING").unwrap();
+        assert_eq!(ctx.consts.get(&greeting), Some(&ConstValue::String("ab\n\"c\"".to_string())));
+    }
+}
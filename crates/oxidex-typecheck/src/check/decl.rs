@@ -8,12 +8,17 @@
 //! - Impl blocks (method implementation checking)
 //! - Constants and Statics
 //! - Type aliases
+//! - Foreign functions (C-compatible signatures)
 
-use crate::error::Result;
-use crate::infer::Context;
+use crate::context::{MethodInfo, Scheme};
+use crate::error::{Result, TypeError};
+use crate::infer::{Context, CurrentSelf};
 use crate::types::{PrimTy, Ty};
-use oxidex_syntax::ast::decl::Decl;
-use oxidex_syntax::Span;
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::{Decl, FnDecl, FnParam};
+use oxidex_syntax::ast::expr::Expr;
+use oxidex_syntax::ast::ty::Type;
+use oxidex_syntax::span::Spanned;
 
 /// Type check a declaration.
 ///
@@ -29,11 +34,12 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
             params,
             return_type,
             body,
-            span,
+            span: _,
             is_mut: _,
             is_init: _,
             is_static: _,
             visibility: _,
+            attributes: _,
         } => {
             // Enter a new scope for the function
             ctx.new_scope();
@@ -53,19 +59,25 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
                 let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
 
                 // Bind the parameter in the environment
-                use crate::context::Scheme;
                 let scheme = Scheme::mono(ty_param);
                 ctx.env.bind(param.name, scheme);
+                ctx.env.declare(param.name, param.span);
             }
 
+            // Calls to this function check the bounds its generic parameters pick up
+            let generic_vars = generics
+                .iter()
+                .filter_map(|&g| ctx.lookup_generic_param(g))
+                .collect();
+            ctx.fn_generics.insert(*name, generic_vars);
+
             // Type check the function body
             let ty_body = super::expr::synth(ctx, body)?;
+            unify_tail(ctx, body, &ty_body)?;
 
-            // If there's a return type annotation, unify with body type
-            if let Some(_) = return_type {
-                // Already handled by set_return_type + return statement validation
-                let _ = ty_body;
-            }
+            // Operators and method calls on type variables are resolved now
+            // that the body has been unified
+            crate::infer::solve_constraints(ctx, false)?;
 
             // Clear the return type
             ctx.clear_return_type();
@@ -113,8 +125,13 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
             // Pop generic parameters from scope
             ctx.pop_generic_params(generics);
 
-            // TODO: Register protocol conformances
-            let _ = (name, protocols, span);
+            // Record declared protocol conformances
+            for path in protocols {
+                if let Some(&protocol) = path.last() {
+                    ctx.types.register_conformance(*name, protocol);
+                }
+            }
+            let _ = span;
 
             Ok(())
         }
@@ -167,8 +184,13 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
             // Pop generic parameters from scope
             ctx.pop_generic_params(generics);
 
-            // TODO: Register protocol conformances
-            let _ = (name, protocols, span);
+            // Record declared protocol conformances
+            for path in protocols {
+                if let Some(&protocol) = path.last() {
+                    ctx.types.register_conformance(*name, protocol);
+                }
+            }
+            let _ = span;
 
             Ok(())
         }
@@ -194,6 +216,7 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
                         variant_infos.push(crate::context::EnumVariantInfo {
                             name: *name,
                             payload: None,
+                            fields: vec![],
                         });
                     }
                     oxidex_syntax::ast::decl::EnumVariant::Tuple {
@@ -217,6 +240,7 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
                         variant_infos.push(crate::context::EnumVariantInfo {
                             name: *name,
                             payload,
+                            fields: vec![],
                         });
                     }
                     oxidex_syntax::ast::decl::EnumVariant::Struct {
@@ -234,6 +258,7 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
                         variant_infos.push(crate::context::EnumVariantInfo {
                             name: *name,
                             payload,
+                            fields: fields.iter().map(|f| f.name).collect(),
                         });
                     }
                 }
@@ -245,19 +270,32 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
                 variants: variant_infos,
                 methods: vec![], // Methods will be collected during type checking
                 generics: generics.clone(),
+                type_vars: generics
+                    .iter()
+                    .filter_map(|&g| ctx.lookup_generic_param(g))
+                    .collect(),
             };
             ctx.types.register_enum(enum_info);
 
             // Pop generic parameters from scope
             ctx.pop_generic_params(generics);
 
-            // Type check methods defined in the enum body
-            for method in methods {
-                check_fn_decl(ctx, method)?;
-            }
+            // Register and type check methods defined in the enum body
+            with_self(ctx, *name, |ctx| {
+                register_methods(ctx, *name, methods)?;
+                for method in methods {
+                    check_fn_decl(ctx, method)?;
+                }
+                Ok(())
+            })?;
 
-            // TODO: Register protocol conformances
-            let _ = (name, protocols, span);
+            // Record declared protocol conformances
+            for path in protocols {
+                if let Some(&protocol) = path.last() {
+                    ctx.types.register_conformance(*name, protocol);
+                }
+            }
+            let _ = span;
 
             Ok(())
         }
@@ -267,7 +305,7 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
             name,
             generics,
             methods,
-            span,
+            span: _,
             visibility: _,
         } => {
             // Push generic parameters into scope
@@ -326,117 +364,126 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
 
             let type_name = type_path[0];
 
-            // If implementing a protocol, validate conformance
-            if let Some(proto_path) = protocol {
-                if proto_path.len() != 1 {
-                    // TODO: Handle paths like Module::Protocol
-                    return Ok(());
-                }
+            // Methods see the implemented type as `Self`
+            with_self(ctx, type_name, |ctx| {
+                // Make the methods visible to method calls on the type
+                register_methods(ctx, type_name, methods)?;
+
+                // If implementing a protocol, validate conformance
+                if let Some(proto_path) = protocol {
+                    if proto_path.len() != 1 {
+                        // TODO: Handle paths like Module::Protocol
+                        return Ok(());
+                    }
 
-                let proto_name = proto_path[0];
+                    let proto_name = proto_path[0];
+                    ctx.types.register_conformance(type_name, proto_name);
 
-                // Look up the protocol
-                if let Some(protocol_info) = ctx.types.lookup_protocol(proto_name) {
-                    // Clone the protocol methods to avoid holding the borrow
-                    let protocol_methods = protocol_info.methods.clone();
+                    // Look up the protocol
+                    if let Some(protocol_info) = ctx.types.lookup_protocol(proto_name) {
+                        // Clone the protocol methods to avoid holding the borrow
+                        let protocol_methods = protocol_info.methods.clone();
 
-                    // Collect methods that are implemented
-                    let mut implemented_methods = std::collections::HashSet::new();
-                    for method in methods {
-                        if let Some(method_name) = method.name {
-                            implemented_methods.insert(method_name);
+                        // Collect methods that are implemented
+                        let mut implemented_methods = std::collections::HashSet::new();
+                        for method in methods {
+                            if let Some(method_name) = method.name {
+                                implemented_methods.insert(method_name);
+                            }
                         }
-                    }
 
-                    // Check that all required methods are implemented
-                    for required_method in &protocol_methods {
-                        if !implemented_methods.contains(&required_method.name) {
-                            return Err(crate::error::TypeError::MissingProtocolMethod {
-                                ty: ctx.interner.resolve(type_name).unwrap_or("").to_string(),
-                                protocol: ctx.interner.resolve(proto_name).unwrap_or("").to_string(),
-                                method: ctx.interner.resolve(required_method.name).unwrap_or("").to_string(),
-                                span: *span,
-                            });
+                        // Check that all required methods are implemented
+                        for required_method in &protocol_methods {
+                            if !implemented_methods.contains(&required_method.name) {
+                                return Err(crate::error::TypeError::MissingProtocolMethod {
+                                    ty: ctx.interner.resolve(type_name).unwrap_or("").to_string(),
+                                    protocol: ctx.interner.resolve(proto_name).unwrap_or("").to_string(),
+                                    method: ctx.interner.resolve(required_method.name).unwrap_or("").to_string(),
+                                    span: *span,
+                                });
+                            }
                         }
-                    }
 
-                    // Now validate each method's signature matches the protocol
-                    for method in methods {
-                        // Find the corresponding protocol method
-                        if let Some(method_name) = method.name {
-                            if let Some(proto_method) = protocol_methods.iter()
-                                .find(|m| m.name == method_name)
-                            {
-                                // Clone the protocol method data to avoid holding the borrow
-                                let proto_params = proto_method.params.clone();
-                                let proto_return = proto_method.return_type.clone();
-
-                                // Enter a new scope for the method
-                                ctx.new_scope();
-
-                                // Push generic parameters
-                                ctx.push_generic_params(&method.generics);
-
-                                // Check parameter count matches
-                                if method.params.len() != proto_params.len() {
-                                    // Build the found type by converting parameter types
-                                    let mut found_params = Vec::new();
-                                    for param in &method.params {
+                        // Now validate each method's signature matches the protocol
+                        for method in methods {
+                            // Find the corresponding protocol method
+                            if let Some(method_name) = method.name
+                                && let Some(proto_method) = protocol_methods.iter()
+                                    .find(|m| m.name == method_name)
+                                {
+                                    // Protocol signatures use `Self` for the conforming type
+                                    let self_ty = ctx.current_self.as_ref().map_or(Ty::SelfType, |c| c.ty.clone());
+                                    let proto_params: Vec<Ty> = proto_method.params.iter()
+                                        .map(|p| p.replace_self(&self_ty))
+                                        .collect();
+                                    let proto_return = proto_method.return_type.replace_self(&self_ty);
+
+                                    // Enter a new scope for the method
+                                    ctx.new_scope();
+
+                                    // Push generic parameters
+                                    ctx.push_generic_params(&method.generics);
+
+                                    // Check parameter count matches
+                                    if method.params.len() != proto_params.len() {
+                                        // Build the found type by converting parameter types
+                                        let mut found_params = Vec::new();
+                                        for param in &method.params {
+                                            let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
+                                            found_params.push(ty_param);
+                                        }
+
+                                        let found_return = if let Some(ret_type) = &method.return_type {
+                                            super::ty::ast_to_ty(ctx, ret_type)?
+                                        } else {
+                                            Ty::Primitive(PrimTy::Unit)
+                                        };
+
+                                        return Err(crate::error::TypeError::Mismatch {
+                                            expected: Ty::Function {
+                                                params: proto_params.clone(),
+                                                return_type: Box::new(proto_return.clone()),
+                                                labels: vec![None; proto_params.len()],
+                                            },
+                                            found: Ty::Function {
+                                                params: found_params,
+                                                return_type: Box::new(found_return),
+                                                labels: vec![None; method.params.len()],
+                                            },
+                                            span: method.span,
+                                        });
+                                    }
+
+                                    // Validate each parameter type matches
+                                    for (param, proto_param_ty) in method.params.iter().zip(&proto_params) {
                                         let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
-                                        found_params.push(ty_param);
+                                        ctx.unify(&ty_param, proto_param_ty, param.span)?;
                                     }
 
-                                    let found_return = if let Some(ret_type) = &method.return_type {
-                                        super::ty::ast_to_ty(ctx, ret_type)?
-                                    } else {
-                                        Ty::Primitive(PrimTy::Unit)
-                                    };
-
-                                    return Err(crate::error::TypeError::Mismatch {
-                                        expected: Ty::Function {
-                                            params: proto_params.clone(),
-                                            return_type: Box::new(proto_return.clone()),
-                                            labels: vec![None; proto_params.len()],
-                                        },
-                                        found: Ty::Function {
-                                            params: found_params,
-                                            return_type: Box::new(found_return),
-                                            labels: vec![None; method.params.len()],
-                                        },
-                                        span: method.span,
-                                    });
-                                }
-
-                                // Validate each parameter type matches
-                                for (param, proto_param_ty) in method.params.iter().zip(&proto_params) {
-                                    let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
-                                    ctx.unify(&ty_param, proto_param_ty, param.span)?;
-                                }
-
-                                // Validate return type matches
-                                if let Some(ret_type) = &method.return_type {
-                                    let ty_return = super::ty::ast_to_ty(ctx, ret_type)?;
-                                    ctx.unify(&ty_return, &proto_return, *span)?;
-                                }
-
-                                // Pop generic parameters
-                                ctx.pop_generic_params(&method.generics);
-
-                                // Exit the method scope
-                                ctx.pop_scope();
-                            }  // Close if let Some(proto_method)
-                        }  // Close if let Some(method_name)
-                    }  // Close for method in methods
-                }  // Close if let Some(protocol_info)
-            }  // Close if let Some(proto_path)
-
-
-            // Type check all methods in the impl block
-            for method in methods {
-                check_fn_decl(ctx, method)?;
-            }
+                                    // Validate return type matches
+                                    if let Some(ret_type) = &method.return_type {
+                                        let ty_return = super::ty::ast_to_ty(ctx, ret_type)?;
+                                        ctx.unify(&ty_return, &proto_return, *span)?;
+                                    }
 
-            Ok(())
+                                    // Pop generic parameters
+                                    ctx.pop_generic_params(&method.generics);
+
+                                    // Exit the method scope
+                                    ctx.pop_scope();
+                                }  // Close if let Some(proto_method)
+                        }  // Close for method in methods
+                    }  // Close if let Some(protocol_info)
+                }  // Close if let Some(proto_path)
+
+
+                // Type check all methods in the impl block
+                for method in methods {
+                    check_fn_decl(ctx, method)?;
+                }
+
+                Ok(())
+            })
         }
 
         // Constant declaration
@@ -450,12 +497,24 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
             // Type check the value
             let ty_value = super::expr::synth(ctx, value)?;
 
-            // If there's a type annotation, unify with value type
-            // TODO: Convert the Type annotation to Ty
-            let _ = (ty_value, type_annotation, span);
+            // The value must have the annotated type
+            let ty_const = super::ty::ast_to_ty(ctx, type_annotation)?;
+            ctx.unify(&ty_const, &ty_value, *span)?;
 
-            // TODO: Bind the constant in the environment
-            let _ = name;
+            // The initializer is folded so later constants and array sizes can use it
+            let const_value = super::consteval::eval_const(ctx, value)?;
+            let ty_const = ctx.subst().apply_ty(&ty_const);
+            super::consteval::check_const_fits(&const_value, &ty_const, *span)?;
+
+            // A value defined from outside the program replaces the initializer
+            let const_value = match ctx.defines.get(name) {
+                Some(define) => super::consteval::apply_define(ctx, define, &ty_const, *span)?,
+                None => const_value,
+            };
+            ctx.consts.insert(*name, const_value);
+
+            ctx.env.bind(*name, Scheme::mono(ty_const));
+            ctx.env.declare(*name, *span);
 
             Ok(())
         }
@@ -502,30 +561,143 @@ pub fn check_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &Decl<'ctx>) -> Result<()
 
             Ok(())
         }
+
+        // Imported modules are checked as part of the same program; the
+        // import itself introduces no types
+        Decl::Import { .. } => Ok(()),
+
+        // Foreign functions have no bodies; their signatures must only use
+        // types C has a counterpart for
+        Decl::Extern { functions, .. } => {
+            for function in functions {
+                for param in &function.params {
+                    check_c_type(ctx, &param.type_annotation, false)?;
+                }
+                if let Some(return_type) = &function.return_type {
+                    check_c_type(ctx, return_type, true)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Check that a type in the signature of an `extern` function crosses into
+/// C, as a primitive with a C encoding. `Unit` is only a result.
+fn check_c_type(ctx: &mut Context<'_>, annotation: &Type, is_return: bool) -> Result<()> {
+    let ty = super::ty::ast_to_ty(ctx, annotation)?;
+    let compatible = match ty {
+        Ty::Primitive(PrimTy::Unit) => is_return,
+        Ty::Primitive(prim) => prim.c_encoding().is_some(),
+        _ => false,
+    };
+    if compatible {
+        Ok(())
+    } else {
+        Err(TypeError::NotCCompatible { ty: ty.display(ctx.interner).to_string(), span: annotation.span() })
     }
 }
 
-/// Type check a function declaration (used in impl blocks and enums).
-fn check_fn_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &oxidex_syntax::ast::decl::FnDecl) -> Result<()> {
+/// Run `f` with `type_name` as the enclosing type of the methods it checks.
+///
+/// The type's generic parameters are in scope for the duration, and `Self`
+/// resolves to the type applied to them.
+fn with_self<'ctx, T>(
+    ctx: &mut Context<'ctx>,
+    type_name: Symbol,
+    f: impl FnOnce(&mut Context<'ctx>) -> Result<T>,
+) -> Result<T> {
+    let generics = ctx.types.lookup_generics(type_name).unwrap_or_default().to_vec();
+    ctx.push_generic_params(&generics);
+    let type_args = generics
+        .iter()
+        .filter_map(|&g| ctx.lookup_generic_param(g))
+        .map(Ty::TypeVar)
+        .collect();
+
+    let ty = if ctx.types.has_class(type_name) {
+        Ty::Class { name: type_name, type_args }
+    } else if ctx.types.has_enum(type_name) {
+        Ty::Enum { name: type_name, type_args }
+    } else {
+        Ty::Struct { name: type_name, type_args }
+    };
+
+    let previous = ctx.current_self.replace(CurrentSelf {
+        name: type_name,
+        ty,
+        is_static: false,
+    });
+    let result = f(ctx);
+    ctx.current_self = previous;
+    ctx.pop_generic_params(&generics);
+    result
+}
+
+/// Type check a method declaration (used in impl blocks and enums).
+///
+/// Instance methods see `self` and the type's fields; both are mutable in
+/// `mut` methods, initializers, and class methods. Static methods see
+/// neither.
+fn check_fn_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &FnDecl<'ctx>) -> Result<()> {
     // Enter a new scope for the function
     ctx.new_scope();
 
     // Push generic parameters into scope
     ctx.push_generic_params(&decl.generics);
 
+    let enclosing = ctx.current_self.as_mut().map(|current| {
+        current.is_static = decl.is_static;
+        (current.name, current.ty.clone())
+    });
+
+    if let Some((type_name, self_ty)) = enclosing {
+        // Sibling methods can be called without `self.`
+        let methods = ctx.types.lookup_methods(type_name).unwrap_or_default().to_vec();
+        for method in methods.into_iter().filter(|m| m.is_static || !decl.is_static) {
+            let ty = Ty::Function {
+                params: method.params,
+                return_type: Box::new(method.return_type),
+                labels: method.labels,
+            };
+            ctx.env.bind_overload(method.name, Scheme::mono(ty));
+        }
+
+        if !decl.is_static {
+            let mutable = decl.is_mut || decl.is_init || matches!(self_ty, Ty::Class { .. });
+            for field in ctx.types.lookup_fields(type_name) {
+                ctx.env.bind_mut(field.name, Scheme::mono(field.ty), mutable);
+            }
+            if let Some(self_sym) = ctx.interner.get_symbol("self") {
+                ctx.env.bind_mut(self_sym, Scheme::mono(self_ty), mutable);
+            }
+        }
+    }
+
     // Type check parameters and bind them in the environment
     for param in &decl.params {
         // Convert the Type annotation to Ty
         let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
 
         // Bind the parameter in the environment
-        use crate::context::Scheme;
         let scheme = Scheme::mono(ty_param);
         ctx.env.bind(param.name, scheme);
+        ctx.env.declare(param.name, param.span);
+    }
+
+    // Set the return type if specified
+    if let Some(ret_type) = &decl.return_type {
+        let ty_ret = super::ty::ast_to_ty(ctx, ret_type)?;
+        ctx.set_return_type(ty_ret);
     }
 
-    // Note: FnDecl doesn't have a body, it's just a signature
-    // The actual body is in Decl::Fn
+    // Type check the method body
+    let ty_body = super::expr::synth(ctx, decl.body)?;
+    unify_tail(ctx, decl.body, &ty_body)?;
+    crate::infer::solve_constraints(ctx, false)?;
+
+    // Clear the return type
+    ctx.clear_return_type();
 
     // Pop generic parameters from scope
     ctx.pop_generic_params(&decl.generics);
@@ -536,25 +708,131 @@ fn check_fn_decl<'ctx>(ctx: &mut Context<'ctx>, decl: &oxidex_syntax::ast::decl:
     Ok(())
 }
 
+/// Unify the final expression of a function body with the declared return
+/// type, so the value it falls through with is checked like a `return`.
+///
+/// Bodies without a final expression, or functions without a return type,
+/// are left alone: their value is `Unit` and only `return` produces one.
+fn unify_tail<'ctx>(ctx: &mut Context<'ctx>, body: &Expr<'ctx>, ty_body: &Ty) -> Result<()> {
+    let Some(ty_ret) = ctx.get_return_type().cloned() else {
+        return Ok(());
+    };
+    match body {
+        Expr::Block { expr: Some(tail), .. } => ctx.unify(&ty_ret, ty_body, tail.span()),
+        _ => Ok(()),
+    }
+}
+
+/// Build the function type of a signature.
+///
+/// Returns the type along with the type variables standing for its generic
+/// parameters, so callers can generalize over them.
+fn fn_signature<'ctx>(
+    ctx: &mut Context<'ctx>,
+    generics: &[Symbol],
+    params: &[FnParam],
+    return_type: Option<&Type>,
+) -> Result<(Ty, Vec<u32>)> {
+    ctx.push_generic_params(generics);
+    let vars = generics
+        .iter()
+        .filter_map(|&g| ctx.lookup_generic_param(g))
+        .collect();
+
+    let mut param_types = Vec::new();
+    for param in params {
+        param_types.push(super::ty::ast_to_ty(ctx, &param.type_annotation)?);
+    }
+
+    let ty_return = if let Some(ret_type) = return_type {
+        super::ty::ast_to_ty(ctx, ret_type)?
+    } else {
+        Ty::Primitive(PrimTy::Unit)
+    };
+
+    ctx.pop_generic_params(generics);
+
+    let ty = Ty::Function {
+        params: param_types,
+        return_type: Box::new(ty_return),
+        labels: params.iter().map(|p| p.label.or(Some(p.name))).collect(),
+    };
+    Ok((ty, vars))
+}
+
+/// Register the methods of an impl block or enum body on their type.
+///
+/// Methods sharing a name are all kept; calls pick among them by overload
+/// resolution.
+fn register_methods<'ctx>(
+    ctx: &mut Context<'ctx>,
+    type_name: Symbol,
+    methods: &[FnDecl<'_>],
+) -> Result<()> {
+    for method in methods {
+        let Some(name) = method.name else {
+            continue;
+        };
+
+        let (ty, _) = fn_signature(ctx, &method.generics, &method.params, method.return_type.as_ref())?;
+        let Ty::Function { params, return_type, labels } = ty else {
+            unreachable!("fn_signature always builds a function type");
+        };
+
+        ctx.types.register_method(
+            type_name,
+            MethodInfo {
+                name,
+                params,
+                labels,
+                return_type: *return_type,
+                is_mut: method.is_mut,
+                is_static: method.is_static,
+            },
+        );
+    }
+    Ok(())
+}
+
 /// First pass: collect all function signatures.
 ///
 /// This is used to support mutual recursion and forward references.
 pub fn collect_signatures<'ctx>(ctx: &mut Context<'ctx>, decls: &[Decl<'ctx>]) -> Result<()> {
+    // Variances only depend on field annotations, so every body can rely on them
+    super::variance::infer_variances(ctx, decls);
+
+    // Signatures can name types declared after them
+    for decl in decls {
+        match decl {
+            Decl::Enum { name, .. } => ctx.types.declare_enum(*name),
+            Decl::Class { name, .. } => ctx.types.declare_class(*name),
+            _ => {}
+        }
+    }
+
     for decl in decls {
         match decl {
             Decl::Fn {
                 name, generics, params, return_type, ..
             } => {
-                // TODO: Build the function type
-                let _ = (name, generics, params, return_type);
-                // For now, just create a fresh type variable
-                let ty = Ty::TypeVar(ctx.fresh_var());
-
-                // Bind the function name in the environment
-                use crate::context::Scheme;
-                let scheme = Scheme::mono(ty);
-                let _ = (*name, scheme);
-                // ctx.env.bind(*name, scheme);
+                let (ty, vars) = fn_signature(ctx, generics, params, return_type.as_ref())?;
+
+                // Bind the function name in the environment, keeping earlier
+                // overloads declared under the same name
+                ctx.env.bind_overload(*name, Scheme::poly(vars, ty));
+            }
+            Decl::Extern { functions, .. } => {
+                for function in functions {
+                    let (ty, _) = fn_signature(ctx, &[], &function.params, function.return_type.as_ref())?;
+                    ctx.env.bind_overload(function.name, Scheme::mono(ty));
+                }
+            }
+            Decl::Const {
+                name, type_annotation, ..
+            } => {
+                // Constants are usable before their declaration is checked
+                let ty = super::ty::ast_to_ty(ctx, type_annotation)?;
+                ctx.env.bind(*name, Scheme::mono(ty));
             }
             _ => {
                 // Other declarations don't need signature collection
@@ -567,11 +845,26 @@ pub fn collect_signatures<'ctx>(ctx: &mut Context<'ctx>, decls: &[Decl<'ctx>]) -
 /// Second pass: check all declaration bodies.
 ///
 /// This runs after signatures are collected, so all declarations are visible.
-pub fn check_bodies<'ctx>(ctx: &mut Context<'ctx>, decls: &[Decl<'ctx>]) -> Result<()> {
+///
+/// # Errors
+///
+/// Returns the first type error or, once everything else checks, a
+/// diagnostic for every typed hole.
+pub fn check_bodies<'ctx>(
+    ctx: &mut Context<'ctx>,
+    decls: &[Decl<'ctx>],
+) -> std::result::Result<(), Vec<TypeError>> {
     for decl in decls {
-        check_decl(ctx, decl)?;
+        check_decl(ctx, decl).map_err(|err| vec![err])?;
     }
-    Ok(())
+    super::recursion::check_recursive_types(ctx, decls).map_err(|err| vec![err])?;
+
+    // Calls to functions declared later are only checked against their bounds now
+    crate::infer::solve_constraints(ctx, true).map_err(|err| vec![err])?;
+
+    // Holes do not stop checking, so they are reported once everything else passed
+    let holes = ctx.hole_diagnostics();
+    if holes.is_empty() { Ok(()) } else { Err(holes) }
 }
 
 #[cfg(test)]
@@ -600,4 +893,231 @@ mod tests {
         let decls = vec![];
         assert!(check_bodies(&mut ctx, &decls).is_ok());
     }
+
+    #[test]
+    fn test_overloaded_functions_resolve_by_argument_type() {
+        use oxidex_syntax::ast::decl::Visibility;
+        use oxidex_syntax::ast::expr::{CallArg, Expr};
+        use oxidex_syntax::Span;
+
+        let mut interner = StringInterner::new();
+        let show = interner.intern("show");
+        let value = interner.intern("value");
+        let int_sym = interner.intern("Int");
+        let bool_sym = interner.intern("Bool");
+        let string_sym = interner.intern("String");
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let body = Expr::Nil { span };
+        let simple = |name| Type::Simple { name, span };
+        let overload = |param_ty, ret_ty| Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name: show,
+            generics: vec![],
+            params: vec![FnParam {
+                label: None,
+                name: value,
+                type_annotation: simple(param_ty),
+                span,
+            }],
+            return_type: Some(simple(ret_ty)),
+            body: &body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span,
+        };
+
+        let decls = vec![overload(int_sym, string_sym), overload(bool_sym, int_sym)];
+        collect_signatures(&mut ctx, &decls).unwrap();
+        assert_eq!(ctx.env.lookup_overloads(show).len(), 2);
+
+        let arg = Expr::BoolLiteral { value: true, span };
+        let call = Expr::Call {
+            callee: &Expr::Identifier(show),
+            args: vec![CallArg { label: None, value: &arg, span }],
+            span,
+        };
+        let ty = super::super::expr::synth(&mut ctx, &call).unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::Int64));
+    }
+
+    #[test]
+    fn test_extern_functions_take_c_types() {
+        use oxidex_syntax::ast::decl::ExternFn;
+        use oxidex_syntax::ast::expr::{CallArg, Expr};
+        use oxidex_syntax::Span;
+
+        let mut interner = StringInterner::new();
+        let cos = interner.intern("cos");
+        let x = interner.intern("x");
+        let float_sym = interner.intern("Float");
+        let char_sym = interner.intern("Char");
+        let unit_sym = interner.intern("Unit");
+        let half = interner.intern("0.5");
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let simple = |name| Type::Simple { name, span };
+        let block = |param_ty, ret_ty| Decl::Extern {
+            library: None,
+            functions: vec![ExternFn {
+                name: cos,
+                params: vec![FnParam { label: None, name: x, type_annotation: simple(param_ty), span }],
+                return_type: Some(simple(ret_ty)),
+                span,
+            }],
+            span,
+        };
+
+        let decl = block(float_sym, float_sym);
+        collect_signatures(&mut ctx, std::slice::from_ref(&decl)).unwrap();
+        check_decl(&mut ctx, &decl).unwrap();
+        let arg = Expr::FloatLiteral { value: half, type_suffix: None, span };
+        let call = Expr::Call {
+            callee: &Expr::Identifier(cos),
+            args: vec![CallArg { label: None, value: &arg, span }],
+            span,
+        };
+        let ty = super::super::expr::synth(&mut ctx, &call).unwrap();
+        crate::infer::constraint::solve_constraints(&mut ctx, true).unwrap();
+        assert_eq!(ctx.subst().apply_ty(&ty), Ty::Primitive(PrimTy::Float64));
+
+        // Characters have no C counterpart, and only results can be Unit
+        for (param_ty, ret_ty) in [(char_sym, float_sym), (unit_sym, float_sym)] {
+            let err = check_decl(&mut ctx, &block(param_ty, ret_ty)).unwrap_err();
+            assert!(matches!(err, TypeError::NotCCompatible { .. }), "{err}");
+        }
+        check_decl(&mut ctx, &block(float_sym, unit_sym)).unwrap();
+    }
+
+    #[test]
+    fn test_self_resolves_inside_methods() {
+        use crate::error::TypeError;
+        use oxidex_syntax::ast::decl::{StructField, Visibility};
+        use oxidex_syntax::ast::expr::{Expr, StructField as FieldInit};
+        use oxidex_syntax::Span;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Counter", "count", "make", "get", "Int", "self", "Self", "0"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [counter, count, make, get, int_sym, self_sym, self_ty, zero_sym] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let zero = Expr::IntegerLiteral { value: zero_sym, type_suffix: None, span };
+        let literal = Expr::Struct {
+            type_path: vec![self_ty],
+            fields: vec![FieldInit { name: count, value: Some(&zero), span }],
+            span,
+        };
+        let self_expr = Expr::Identifier(self_sym);
+        let read = Expr::Field { object: &self_expr, field: count, span };
+        let method = |name, is_static, return_type, body| FnDecl {
+            is_mut: false,
+            is_init: false,
+            is_static,
+            name: Some(name),
+            generics: vec![],
+            params: vec![],
+            return_type: Some(return_type),
+            body,
+            visibility: Visibility::Private,
+            span,
+        };
+        let decls = vec![
+            Decl::Struct {
+                name: counter,
+                generics: vec![],
+                fields: vec![StructField {
+                    name: count,
+                    type_annotation: Type::Simple { name: int_sym, span },
+                    span,
+                }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span,
+            },
+            Decl::Impl {
+                type_path: vec![counter],
+                protocol: None,
+                methods: vec![
+                    method(make, true, Type::SelfType { span }, &literal),
+                    method(get, false, Type::Simple { name: int_sym, span }, &read),
+                ],
+                span,
+            },
+        ];
+        collect_signatures(&mut ctx, &decls).unwrap();
+        for decl in &decls {
+            check_decl(&mut ctx, decl).unwrap();
+        }
+
+        // `Counter::make` is a static method returning `Self`
+        let counter_ty = Ty::Struct { name: counter, type_args: vec![] };
+        let path = Expr::Path { segments: vec![counter, make], span };
+        let call = Expr::Call { callee: &path, args: vec![], span };
+        let ty = super::super::expr::synth(&mut ctx, &call).unwrap();
+        assert_eq!(ctx.subst().apply_ty(&ty), counter_ty);
+
+        // Static and instance methods are not interchangeable
+        let instance = Expr::MethodCall { receiver: &call, method: make, args: vec![], span };
+        let err = super::super::expr::synth(&mut ctx, &instance).unwrap_err();
+        assert!(matches!(err, TypeError::StaticMethodOnInstance { .. }));
+        let path = Expr::Path { segments: vec![counter, get], span };
+        let err = super::super::expr::synth(&mut ctx, &path).unwrap_err();
+        assert!(matches!(err, TypeError::InstanceMethodOnType { .. }));
+
+        // `self` is unavailable in static methods and outside methods
+        let misuse = Decl::Impl {
+            type_path: vec![counter],
+            protocol: None,
+            methods: vec![method(make, true, Type::Simple { name: int_sym, span }, &read)],
+            span,
+        };
+        let err = check_decl(&mut ctx, &misuse).unwrap_err();
+        assert!(matches!(err, TypeError::SelfInStaticMethod { .. }));
+        let err = super::super::expr::synth(&mut ctx, &self_expr).unwrap_err();
+        assert!(matches!(err, TypeError::SelfOutsideMethod { .. }));
+    }
+
+    #[test]
+    fn test_holes_in_tail_position_take_the_return_type() {
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let source = "fn pick(count: Int, name: String) -> Int {
+    let flag = true;
+    ???
+}
+fn label(count: Int, name: String) -> String { ??? }";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let mut ctx = Context::new(parser.interner());
+        collect_signatures(&mut ctx, &decls).unwrap();
+
+        // Every hole is reported, each with the type its function returns
+        let holes: Vec<_> = check_bodies(&mut ctx, &decls)
+            .unwrap_err()
+            .into_iter()
+            .map(|err| match err {
+                TypeError::TypedHole { expected, candidates, span } => (expected, candidates, span.start_line),
+                other => panic!("Expected TypedHole, got {:?}", other),
+            })
+            .collect();
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            holes,
+            [("Int".to_string(), names(&["count"]), 3), ("String".to_string(), names(&["name"]), 5)]
+        );
+    }
 }
@@ -4,11 +4,15 @@
 //! - **Synthesis mode** (synth): Infer the type of an expression
 //! - **Checking mode** (check): Verify an expression has the expected type
 
+use crate::context::Scheme;
 use crate::error::{Result, TypeError};
-use crate::infer::Context;
+use crate::infer::{Bound, Constraint, Context, resolve_overload};
 use crate::types::{PrimTy, Ty};
 use oxidex_syntax::{Expr, Span, Spanned};
+use oxidex_syntax::ast::decl::FnParam;
 use oxidex_syntax::ast::expr::BinaryOp;
+use oxidex_syntax::ast::ty::Type;
+use super::operator::{binary_bound, check_operand};
 
 /// Type check an expression and infer its type.
 ///
@@ -27,8 +31,15 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
 
         Expr::Nil { .. } => Ok(Ty::Primitive(PrimTy::Unit)),
 
+        Expr::Hole { span } => Ok(ctx.record_hole(*span)),
+
         // Binary operators
         Expr::Binary { op, left, right, span } => {
+            // The target of `=` must be a mutable place
+            if matches!(op, BinaryOp::Assign) {
+                super::stmt::check_assign_target(ctx, left, *span)?;
+            }
+
             // Type check both operands first
             let ty_left = synth(ctx, left)?;
             let ty_right = synth(ctx, right)?;
@@ -47,23 +58,16 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                     Ok(Ty::Primitive(PrimTy::Bool))
                 }
                 oxidex_syntax::ast::expr::UnaryOp::Minus => {
-                    // Arithmetic negation: Int -> Int or Float -> Float
-                    // Create a fresh type variable and unify with Int or Float
-                    let var = ctx.fresh_var();
-                    ctx.unify(&ty_operand, &Ty::TypeVar(var), *span)?;
-                    // For now, assume Int (we should try both Int and Float)
-                    Ok(Ty::Primitive(PrimTy::Int64))
+                    // Arithmetic negation: signed numbers, or types implementing Negate
+                    check_operand(ctx, Bound::Negate, &ty_operand, *span)
                 }
             }
         }
 
         // Identifiers (variable lookup)
         Expr::Identifier(sym) => {
-            // Look up the variable in the environment
-            let name = ctx.interner.resolve(*sym).unwrap_or("");
-            let scheme = ctx.lookup(name);
-
-            match scheme {
+            ctx.capture(*sym);
+            match ctx.env.lookup(*sym) {
                 Some(scheme) => {
                     // Clone the scheme so we can drop the borrow
                     let scheme_clone = scheme.clone();
@@ -71,10 +75,12 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                     let ty = scheme_clone.instantiate(ctx.subst());
                     Ok(ty)
                 }
+                None if ctx.interner.resolve(*sym) == Some("self") => Err(ctx.self_error(expr.span())),
                 None => {
                     // CRITICAL: Undefined variable is an error, not a fresh type var
                     Err(TypeError::UndefinedVar {
-                        name: name.to_string(),
+                        name: ctx.interner.resolve(*sym).unwrap_or("").to_string(),
+                        candidates: ctx.suggest_binding(*sym),
                         span: expr.span(),
                     })
                 }
@@ -86,6 +92,7 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
             // Handle simple single-segment paths (identifiers)
             if segments.len() == 1 {
                 let name = segments[0];
+                ctx.capture(name);
                 // Look up as a variable
                 if let Some(scheme) = ctx.env.lookup(name) {
                     // Instantiate the scheme to get the type
@@ -94,11 +101,56 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                 } else {
                     return Err(TypeError::UndefinedVar {
                         name: ctx.interner.resolve(name).unwrap_or("").to_string(),
+                        candidates: ctx.suggest_binding(name),
                         span: *span,
                     });
                 }
             }
 
+            // `Type::member` refers to a static method or a unit variant
+            if let [type_name, member] = segments.as_slice() {
+                let type_name = ctx.resolve_self_name(*type_name);
+                let method = ctx
+                    .types
+                    .lookup_methods(type_name)
+                    .and_then(|methods| methods.iter().find(|m| m.name == *member))
+                    .cloned();
+                if let Some(info) = method {
+                    if !info.is_static {
+                        return Err(TypeError::InstanceMethodOnType {
+                            ty: ctx.interner.resolve(type_name).unwrap_or("").to_string(),
+                            method: ctx.interner.resolve(*member).unwrap_or("").to_string(),
+                            span: *span,
+                        });
+                    }
+                    return Ok(Ty::Function {
+                        params: info.params,
+                        return_type: Box::new(info.return_type),
+                        labels: info.labels,
+                    });
+                }
+
+                // A unit variant is a value of its enum, a tuple variant a
+                // function building one from its payload
+                if ctx.types.has_enum(type_name) {
+                    let shape = super::pat::instantiate_variant(ctx, type_name, *member, *span)?;
+                    return Ok(match shape.payload {
+                        None => shape.enum_ty,
+                        Some(_) if !shape.fields.is_empty() => Ty::TypeVar(ctx.fresh_var()),
+                        Some(Ty::Tuple(params)) => Ty::Function {
+                            labels: vec![None; params.len()],
+                            params,
+                            return_type: Box::new(shape.enum_ty),
+                        },
+                        Some(payload) => Ty::Function {
+                            labels: vec![None],
+                            params: vec![payload],
+                            return_type: Box::new(shape.enum_ty),
+                        },
+                    });
+                }
+            }
+
             // TODO: Handle multi-segment paths like Module::Type::item
             // For now, return a fresh type variable
             let var = ctx.fresh_var();
@@ -107,13 +159,38 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
 
         // Function calls
         Expr::Call { callee, args, span } => {
-            // Type check callee (should be a function type)
-            let ty_callee = synth(ctx, callee)?;
-
             // Type check arguments
-            let ty_args: Result<Vec<Ty>> = args.iter().map(|arg| synth(ctx, &arg.value)).collect();
+            let ty_args: Result<Vec<Ty>> = args.iter().map(|arg| synth(ctx, arg.value)).collect();
             let ty_args = ty_args?;
 
+            // Overloaded functions are resolved by ranking every signature
+            if let Some(name) = callee_name(callee) {
+                let candidates = ctx.env.lookup_overloads(name).to_vec();
+                if candidates.len() > 1 {
+                    let labels: Vec<_> = args.iter().map(|arg| arg.label).collect();
+                    let (_, ty) = resolve_overload(ctx, name, &candidates, &labels, &ty_args, *span)?;
+                    return Ok(ty);
+                }
+            }
+
+            // Type check callee (should be a function type). Calls to generic
+            // functions are recorded so the callee's bounds can be checked
+            let generic = callee_name(callee).and_then(|name| {
+                ctx.capture(name);
+                ctx.env
+                    .lookup(name)
+                    .filter(|scheme| !scheme.vars.is_empty())
+                    .map(|scheme| (name, scheme.clone()))
+            });
+            let ty_callee = match generic {
+                Some((name, scheme)) => {
+                    let (ty, args) = scheme.instantiate_vars(ctx.subst());
+                    ctx.instances.push(Constraint::Instance { name, args, span: *span });
+                    ty
+                }
+                None => synth(ctx, callee)?,
+            };
+
             // Create fresh return type variable
             let ty_ret = ctx.fresh_var();
 
@@ -129,170 +206,127 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
             Ok(Ty::TypeVar(ty_ret))
         }
 
+        // Closures: names bound outside the body are captured
+        Expr::Closure { params, return_type, body, span } => {
+            ctx.enter_closure(*span);
+            ctx.new_scope();
+            let result = synth_closure(ctx, params, return_type.as_ref(), body);
+            ctx.pop_scope();
+            ctx.exit_closure(result.as_ref().unwrap_or(&Ty::Never));
+            result
+        }
+
         // Method calls
         Expr::MethodCall { receiver, method, args, span } => {
             // Type check receiver
             let ty_receiver = synth(ctx, receiver)?;
 
             // Type check arguments
-            let ty_args: Result<Vec<Ty>> = args.iter().map(|arg| synth(ctx, &arg.value)).collect();
+            let ty_args: Result<Vec<Ty>> = args.iter().map(|arg| synth(ctx, arg.value)).collect();
             let ty_args = ty_args?;
 
             // Look up method in receiver's type
-            match &ty_receiver {
-                Ty::Struct { name, .. } => {
-                    if let Some(struct_info) = ctx.types.lookup_struct(*name) {
-                        // Clone method info to avoid borrow checker issues
-                        let method_return = struct_info.methods.iter()
-                            .find(|m| m.name == *method)
-                            .map(|m| (m.params.clone(), m.return_type.clone()));
-
-                        if let Some((method_params, method_return_type)) = method_return {
-                            // Check parameter count
-                            if method_params.len() != args.len() {
-                                return Err(crate::error::TypeError::Mismatch {
-                                    expected: Ty::Function {
-                                        params: method_params.clone(),
-                                        return_type: Box::new(method_return_type.clone()),
-                                        labels: vec![None; method_params.len()],
-                                    },
-                                    found: Ty::Function {
-                                        params: std::iter::once(ty_receiver).chain(ty_args).collect(),
-                                        return_type: Box::new(Ty::TypeVar(0)),
-                                        labels: vec![None; args.len() + 1],
-                                    },
-                                    span: *span,
-                                });
-                            }
-
-                            // Validate argument types
-                            for (ty_arg, ty_param) in ty_args.iter().zip(&method_params) {
-                                ctx.unify(ty_arg, ty_param, *span)?;
-                            }
-
-                            // Return the method's return type
-                            Ok(method_return_type)
-                        } else {
-                            // Method not found
-                            return Err(crate::error::TypeError::UndefinedFunction {
-                                name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
-                                candidates: struct_info.methods.iter()
-                                    .map(|m| ctx.interner.resolve(m.name).unwrap_or("").to_string())
-                                    .collect(),
-                                span: *span,
-                            });
-                        }
-                    } else {
-                        // Struct not in registry - shouldn't happen
-                        let ty_ret = ctx.fresh_var();
-                        Ok(Ty::TypeVar(ty_ret))
-                    }
-                }
-                Ty::Enum { name, .. } => {
-                    if let Some(enum_info) = ctx.types.lookup_enum(*name) {
-                        // Clone method info to avoid borrow checker issues
-                        let method_return = enum_info.methods.iter()
-                            .find(|m| m.name == *method)
-                            .map(|m| (m.params.clone(), m.return_type.clone()));
-
-                        if let Some((method_params, method_return_type)) = method_return {
-                            // Check parameter count
-                            if method_params.len() != args.len() {
-                                return Err(crate::error::TypeError::Mismatch {
-                                    expected: Ty::Function {
-                                        params: method_params.clone(),
-                                        return_type: Box::new(method_return_type.clone()),
-                                        labels: vec![None; method_params.len()],
-                                    },
-                                    found: Ty::Function {
-                                        params: std::iter::once(ty_receiver).chain(ty_args).collect(),
-                                        return_type: Box::new(Ty::TypeVar(0)),
-                                        labels: vec![None; args.len() + 1],
-                                    },
-                                    span: *span,
-                                });
-                            }
-
-                            // Validate argument types
-                            for (ty_arg, ty_param) in ty_args.iter().zip(&method_params) {
-                                ctx.unify(ty_arg, ty_param, *span)?;
-                            }
-
-                            // Return the method's return type
-                            Ok(method_return_type)
-                        } else {
-                            // Method not found
-                            return Err(crate::error::TypeError::UndefinedFunction {
-                                name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
-                                candidates: enum_info.methods.iter()
-                                    .map(|m| ctx.interner.resolve(m.name).unwrap_or("").to_string())
-                                    .collect(),
-                                span: *span,
-                            });
-                        }
-                    } else {
-                        // Enum not in registry - shouldn't happen
-                        let ty_ret = ctx.fresh_var();
-                        Ok(Ty::TypeVar(ty_ret))
-                    }
-                }
-                Ty::Class { name, .. } => {
-                    if let Some(class_info) = ctx.types.lookup_class(*name) {
-                        // Clone method info to avoid borrow checker issues
-                        let method_return = class_info.methods.iter()
-                            .find(|m| m.name == *method)
-                            .map(|m| (m.params.clone(), m.return_type.clone()));
-
-                        if let Some((method_params, method_return_type)) = method_return {
-                            // Check parameter count
-                            if method_params.len() != args.len() {
-                                return Err(crate::error::TypeError::Mismatch {
-                                    expected: Ty::Function {
-                                        params: method_params.clone(),
-                                        return_type: Box::new(method_return_type.clone()),
-                                        labels: vec![None; method_params.len()],
-                                    },
-                                    found: Ty::Function {
-                                        params: std::iter::once(ty_receiver).chain(ty_args).collect(),
-                                        return_type: Box::new(Ty::TypeVar(0)),
-                                        labels: vec![None; args.len() + 1],
-                                    },
-                                    span: *span,
-                                });
-                            }
-
-                            // Validate argument types
-                            for (ty_arg, ty_param) in ty_args.iter().zip(&method_params) {
-                                ctx.unify(ty_arg, ty_param, *span)?;
-                            }
-
-                            // Return the method's return type
-                            Ok(method_return_type)
-                        } else {
-                            // Method not found
-                            return Err(crate::error::TypeError::UndefinedFunction {
-                                name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
-                                candidates: class_info.methods.iter()
-                                    .map(|m| ctx.interner.resolve(m.name).unwrap_or("").to_string())
-                                    .collect(),
-                                span: *span,
-                            });
-                        }
-                    } else {
-                        // Class not in registry - shouldn't happen
-                        let ty_ret = ctx.fresh_var();
-                        Ok(Ty::TypeVar(ty_ret))
-                    }
+            let ty_receiver = ctx.subst().apply_ty(&ty_receiver);
+            let type_name = match &ty_receiver {
+                Ty::Struct { name, .. } | Ty::Enum { name, .. } | Ty::Class { name, .. } => *name,
+                Ty::TypeVar(_) => {
+                    // The receiver is resolved after unification
+                    let ret = Ty::TypeVar(ctx.fresh_var());
+                    ctx.constraints.push(Constraint::Method {
+                        receiver: ty_receiver,
+                        method: *method,
+                        labels: args.iter().map(|arg| arg.label).collect(),
+                        args: ty_args,
+                        ret: ret.clone(),
+                        span: *span,
+                    });
+                    return Ok(ret);
                 }
                 _ => {
                     // Not a struct, enum, or class - error
-                    return Err(crate::error::TypeError::UndefinedFunction {
+                    return Err(TypeError::UndefinedFunction {
                         name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
                         candidates: vec![],
                         span: *span,
                     });
                 }
+            };
+
+            let Some(methods) = ctx.types.lookup_methods(type_name) else {
+                // Type not in registry - shouldn't happen
+                let ty_ret = ctx.fresh_var();
+                return Ok(Ty::TypeVar(ty_ret));
+            };
+
+            let matching: Vec<_> = methods.iter().filter(|m| m.name == *method).cloned().collect();
+
+            let (is_mut, ty_ret) = match matching.as_slice() {
+                [] => {
+                    // Method not found
+                    return Err(TypeError::UndefinedFunction {
+                        name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
+                        candidates: ctx.suggest(*method, methods.iter().map(|m| m.name)),
+                        span: *span,
+                    });
+                }
+                [info] if info.is_static => {
+                    return Err(TypeError::StaticMethodOnInstance {
+                        ty: ctx.interner.resolve(type_name).unwrap_or("").to_string(),
+                        method: ctx.interner.resolve(*method).unwrap_or("").to_string(),
+                        span: *span,
+                    });
+                }
+                [info] => {
+                    // Check parameter count
+                    if info.params.len() != args.len() {
+                        return Err(TypeError::Mismatch {
+                            expected: Ty::Function {
+                                params: info.params.clone(),
+                                return_type: Box::new(info.return_type.clone()),
+                                labels: vec![None; info.params.len()],
+                            },
+                            found: Ty::Function {
+                                params: std::iter::once(ty_receiver).chain(ty_args).collect(),
+                                return_type: Box::new(Ty::TypeVar(0)),
+                                labels: vec![None; args.len() + 1],
+                            },
+                            span: *span,
+                        });
+                    }
+
+                    // Validate argument types
+                    for (ty_arg, ty_param) in ty_args.iter().zip(&info.params) {
+                        ctx.unify(ty_arg, ty_param, *span)?;
+                    }
+
+                    (info.is_mut, info.return_type.clone())
+                }
+                overloads => {
+                    // Several methods share the name - rank them against the call
+                    let candidates: Vec<Scheme> = overloads
+                        .iter()
+                        .map(|m| {
+                            Scheme::mono(Ty::Function {
+                                params: m.params.clone(),
+                                return_type: Box::new(m.return_type.clone()),
+                                labels: m.labels.clone(),
+                            })
+                        })
+                        .collect();
+                    let labels: Vec<_> = args.iter().map(|arg| arg.label).collect();
+                    let (index, ty) = resolve_overload(ctx, *method, &candidates, &labels, &ty_args, *span)?;
+                    (overloads[index].is_mut, ty)
+                }
+            };
+
+            // A `mut fn` mutates its receiver in place, which requires a mutable
+            // binding unless the receiver is a class (classes are references)
+            if is_mut && !matches!(ty_receiver, Ty::Class { .. }) {
+                super::stmt::check_mutable_place(ctx, receiver, *span)?;
             }
+
+            // Return the method's return type
+            Ok(ty_ret)
         }
 
         // If expressions
@@ -329,8 +363,8 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
             let ty_scrut = synth(ctx, scrutinee)?;
 
             // Check exhaustiveness for enum types
-            if let Ty::Enum { name, .. } = &ty_scrut {
-                if let Some(enum_info) = ctx.types.lookup_enum(*name) {
+            if let Ty::Enum { name, .. } = &ty_scrut
+                && let Some(enum_info) = ctx.types.lookup_enum(*name) {
                     // Collect variants that are covered
                     let mut covered_variants = std::collections::HashSet::new();
 
@@ -374,7 +408,6 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                         });
                     }
                 }
-            }
 
             // All arms must have the same type
             let mut arm_types = Vec::new();
@@ -411,15 +444,15 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
         Expr::Block { stmts, expr, span: _ } => {
             ctx.new_scope();
 
-            // Type check statements (for now, just ignore)
-            // TODO: Actually type check the statements
-            let _ = stmts;
-
-            // Type check final expression if present
-            let result = match expr {
-                Some(e) => synth(ctx, e),
-                None => Ok(Ty::Primitive(PrimTy::Unit)),
-            };
+            // Statements bind in the block's scope, for the statements and
+            // final expression after them
+            let result = stmts
+                .iter()
+                .try_for_each(|stmt| super::stmt::check_stmt(ctx, stmt))
+                .and_then(|()| match expr {
+                    Some(e) => synth(ctx, e),
+                    None => Ok(Ty::Primitive(PrimTy::Unit)),
+                });
 
             // CRITICAL: Pop the scope before returning
             ctx.pop_scope();
@@ -430,35 +463,30 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
         // Parenthesized expressions
         Expr::Paren { expr, .. } => synth(ctx, expr),
 
+        // Compile-time expressions: `comptime if` checks only the branch its
+        // conditions select, anything else must fold to a constant
+        Expr::Comptime { expr: inner, .. } => {
+            if let Expr::If { .. } = inner {
+                match super::consteval::comptime_branch(ctx, inner)? {
+                    Some(branch) => synth(ctx, branch),
+                    None => Ok(Ty::Primitive(PrimTy::Unit)),
+                }
+            } else {
+                let ty = synth(ctx, inner)?;
+                super::consteval::eval_const(ctx, inner)?;
+                Ok(ty)
+            }
+        }
+
         // For loops
         Expr::ForLoop { pattern, iter, body, span: _ } => {
-            // Type check iterator (should be a collection type)
+            // The sequence's type gives its elements' by the iteration protocol
             let ty_iter = synth(ctx, iter)?;
-
-            // Get the element type from the iterator
-            let ty_elem = match &ty_iter {
-                Ty::Array(elem_ty) => elem_ty.as_ref(),
-                Ty::Dict { .. } => {
-                    // For dicts, we iterate over (key, value) tuples
-                    return Err(TypeError::Mismatch {
-                        expected: Ty::Array(Box::new(Ty::TypeVar(ctx.fresh_var()))),
-                        found: ty_iter,
-                        span: iter.span(),
-                    });
-                }
-                _ => {
-                    // Unknown iterator type
-                    return Err(TypeError::Mismatch {
-                        expected: Ty::Array(Box::new(Ty::TypeVar(ctx.fresh_var()))),
-                        found: ty_iter,
-                        span: iter.span(),
-                    });
-                }
-            };
+            let ty_elem = element_type(ctx, &ty_iter, iter.span())?;
 
             // Type check pattern against element type
             ctx.new_scope();
-            super::pat::check_pat(ctx, pattern, ty_elem, pattern.span())?;
+            super::pat::check_pat(ctx, pattern, &ty_elem, pattern.span())?;
 
             // Type check body
             let ty_body = synth(ctx, body)?;
@@ -493,7 +521,7 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                 return Ok(Ty::TypeVar(var));
             }
 
-            let struct_name = type_path[0];
+            let struct_name = ctx.resolve_self_name(type_path[0]);
             if let Some(struct_info) = ctx.types.lookup_struct(struct_name) {
                 // Clone struct info to avoid borrow checker issues
                 let struct_fields: Vec<_> = struct_info.fields.iter()
@@ -514,14 +542,15 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                     };
 
                     // Check if field exists in struct
-                    if let Some(&(_, ref declared_ty)) = struct_fields.iter().find(|(name, _)| *name == field.name) {
+                    if let Some((_, declared_ty)) = struct_fields.iter().find(|(name, _)| *name == field.name) {
                         // Unify field type with declared type
                         ctx.unify(&ty_field, declared_ty, *span)?;
                         provided_fields.insert(field.name, ty_field);
                     } else {
-                        return Err(crate::error::TypeError::FieldAccessOnNonStruct {
+                        return Err(crate::error::TypeError::UnknownField {
                             ty: ctx.interner.resolve(struct_name).unwrap_or("").to_string(),
                             field: ctx.interner.resolve(field.name).unwrap_or("").to_string(),
+                            candidates: ctx.suggest(field.name, struct_fields.iter().map(|(name, _)| *name)),
                             span: *span,
                         });
                     }
@@ -567,70 +596,40 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                 return Ok(Ty::TypeVar(var));
             }
 
-            let enum_name = type_path[0];
-            if let Some(enum_info) = ctx.types.lookup_enum(enum_name) {
-                // Clone variant payload to avoid borrow checker issues
-                let variant_payload = enum_info.variants.iter()
-                    .find(|v| v.name == *variant)
-                    .and_then(|v| v.payload.clone());
-
-                if variant_payload.is_some() || enum_info.variants.iter().any(|v| v.name == *variant) {
-                    // Type check payload if present
-                    if let Some(payload_expr) = payload {
-                        let ty_payload = synth(ctx, payload_expr)?;
-
-                        // Unify with expected payload type
-                        if let Some(expected_payload) = &variant_payload {
-                            ctx.unify(&ty_payload, expected_payload, *span)?;
-                        } else {
-                            // Variant has no payload but we provided one
-                            return Err(crate::error::TypeError::Mismatch {
-                                expected: Ty::Enum {
-                                    name: enum_name,
-                                    type_args: vec![],
-                                },
-                                found: ty_payload,
-                                span: *span,
-                            });
-                        }
-                    } else {
-                        // No payload provided
-                        if variant_payload.is_some() {
-                            // Variant requires payload but none provided
-                            return Err(crate::error::TypeError::Mismatch {
-                                expected: Ty::Enum {
-                                    name: enum_name,
-                                    type_args: vec![],
-                                },
-                                found: Ty::Primitive(PrimTy::Unit),
-                                span: *span,
-                            });
-                        }
-                    }
-
-                    // Return the enum type
-                    Ok(Ty::Enum {
-                        name: enum_name,
-                        type_args: vec![],
-                    })
-                } else {
-                    // Variant not found
-                    return Err(crate::error::TypeError::UndefinedType {
-                        name: format!(
-                            "{}::{}",
-                            ctx.interner.resolve(enum_name).unwrap_or(""),
-                            ctx.interner.resolve(*variant).unwrap_or("")
-                        ),
-                        span: *span,
-                    });
-                }
-            } else {
-                // Enum not found
+            let enum_name = ctx.resolve_self_name(type_path[0]);
+            if !ctx.types.has_enum(enum_name) {
                 return Err(crate::error::TypeError::UndefinedType {
                     name: ctx.interner.resolve(enum_name).unwrap_or("").to_string(),
                     span: *span,
                 });
             }
+
+            let shape = super::pat::instantiate_variant(ctx, enum_name, *variant, *span)?;
+            match (payload, &shape.payload) {
+                (Some(payload_expr), Some(expected_payload)) => {
+                    let ty_payload = synth(ctx, payload_expr)?;
+                    ctx.unify(&ty_payload, expected_payload, *span)?;
+                }
+                // Variant has no payload but we provided one
+                (Some(payload_expr), None) => {
+                    let ty_payload = synth(ctx, payload_expr)?;
+                    return Err(crate::error::TypeError::Mismatch {
+                        expected: shape.enum_ty,
+                        found: ty_payload,
+                        span: *span,
+                    });
+                }
+                // Variant requires payload but none provided
+                (None, Some(_)) => {
+                    return Err(crate::error::TypeError::Mismatch {
+                        expected: shape.enum_ty,
+                        found: Ty::Primitive(PrimTy::Unit),
+                        span: *span,
+                    });
+                }
+                (None, None) => {}
+            }
+            Ok(shape.enum_ty)
         }
 
         // Array literals
@@ -665,13 +664,13 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
             }
 
             // Type check first entry to get key and value types
-            let ty_key_first = synth(ctx, &entries[0].key)?;
-            let ty_value_first = synth(ctx, &entries[0].value)?;
+            let ty_key_first = synth(ctx, entries[0].key)?;
+            let ty_value_first = synth(ctx, entries[0].value)?;
 
             // Type check all other entries and unify with first
             for entry in &entries[1..] {
-                let ty_key = synth(ctx, &entry.key)?;
-                let ty_value = synth(ctx, &entry.value)?;
+                let ty_key = synth(ctx, entry.key)?;
+                let ty_value = synth(ctx, entry.value)?;
                 ctx.unify(&ty_key_first, &ty_key, *span)?;
                 ctx.unify(&ty_value_first, &ty_value, *span)?;
             }
@@ -698,9 +697,10 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                             Ok(field_info.ty.clone())
                         } else {
                             // Field not found in struct
-                            Err(crate::error::TypeError::FieldAccessOnNonStruct {
+                            Err(crate::error::TypeError::UnknownField {
                                 ty: ctx.interner.resolve(*name).unwrap_or("").to_string(),
                                 field: ctx.interner.resolve(*field).unwrap_or("").to_string(),
+                                candidates: ctx.suggest(*field, struct_info.fields.iter().map(|f| f.name)),
                                 span: *span,
                             })
                         }
@@ -716,9 +716,10 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                         {
                             Ok(field_info.ty.clone())
                         } else {
-                            Err(crate::error::TypeError::FieldAccessOnNonStruct {
+                            Err(crate::error::TypeError::UnknownField {
                                 ty: ctx.interner.resolve(*name).unwrap_or("").to_string(),
                                 field: ctx.interner.resolve(*field).unwrap_or("").to_string(),
+                                candidates: ctx.suggest(*field, class_info.fields.iter().map(|f| f.name)),
                                 span: *span,
                             })
                         }
@@ -761,7 +762,7 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
         }
 
         // String interpolation
-        Expr::Interpolation { parts, span: _ } => {
+        Expr::Interpolation { parts, span } => {
             // Type check all interpolation parts
             for part in parts {
                 match part {
@@ -769,9 +770,9 @@ pub fn synth<'ctx>(ctx: &mut Context<'ctx>, expr: &Expr<'ctx>) -> Result<Ty> {
                         // Text literals don't need type checking
                     }
                     oxidex_syntax::ast::expr::InterpolationPart::Expr(expr) => {
-                        // Type check the interpolated expression
-                        let _ = synth(ctx, expr)?;
-                        // TODO: Convert to string at runtime
+                        // Interpolated values must be convertible to a string
+                        let ty = synth(ctx, expr)?;
+                        ctx.require(&ty, Bound::Describable, *span);
                     }
                 }
             }
@@ -793,9 +794,59 @@ pub fn check<'ctx>(
     // Infer the type of the expression
     let inferred = synth(ctx, expr)?;
 
-    // Unify with the expected type
+    // Subtypes are accepted where a supertype is expected
     let span = expr.span();
-    ctx.unify(&inferred, expected, span)
+    super::variance::subsume(ctx, &inferred, expected, span)
+}
+
+/// Extract the function name from a call's callee, if it names one directly.
+fn callee_name(callee: &Expr<'_>) -> Option<oxidex_mem::Symbol> {
+    match callee {
+        Expr::Identifier(sym) => Some(*sym),
+        Expr::Path { segments, .. } if segments.len() == 1 => Some(segments[0]),
+        _ => None,
+    }
+}
+
+/// Infer the type of a closure, in the scope of its parameters.
+///
+/// Without a declared return type the body gives it; a body that ends in a
+/// statement and returns nothing returns `Unit`.
+fn synth_closure<'ctx>(
+    ctx: &mut Context<'ctx>,
+    params: &[FnParam],
+    return_type: Option<&Type>,
+    body: &Expr<'ctx>,
+) -> Result<Ty> {
+    let mut ty_params = Vec::with_capacity(params.len());
+    for param in params {
+        let ty_param = super::ty::ast_to_ty(ctx, &param.type_annotation)?;
+        ctx.env.bind(param.name, Scheme::mono(ty_param.clone()));
+        ctx.env.declare(param.name, param.span);
+        ty_params.push(ty_param);
+    }
+    let ty_ret = match return_type {
+        Some(ty) => super::ty::ast_to_ty(ctx, ty)?,
+        None => Ty::TypeVar(ctx.fresh_var()),
+    };
+
+    // `return` in the body leaves the closure, not the enclosing function
+    let enclosing = ctx.return_type.replace(ty_ret.clone());
+    let ty_body = synth(ctx, body);
+    ctx.return_type = enclosing;
+    let ty_body = ty_body?;
+
+    if matches!(body, Expr::Block { expr: Some(_), .. }) {
+        ctx.unify(&ty_ret, &ty_body, body.span())?;
+    } else if let Ty::TypeVar(_) = ctx.subst().apply_ty(&ty_ret) {
+        ctx.unify(&ty_ret, &Ty::Primitive(PrimTy::Unit), body.span())?;
+    }
+
+    Ok(Ty::Function {
+        labels: vec![None; ty_params.len()],
+        params: ty_params,
+        return_type: Box::new(ty_ret),
+    })
 }
 
 /// Check a binary operation and infer its type.
@@ -810,30 +861,21 @@ fn check_binary_op<'ctx>(
     span: Span,
 ) -> Result<Ty> {
     match op {
-        // Arithmetic operators: require numeric types
+        // Arithmetic operators: both operands share a type that supports the
+        // operator's protocol (see `super::operator`)
         BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
-            // Both operands must be the same numeric type
             ctx.unify(ty_left, ty_right, span)?;
-
-            // Check that at least one operand is numeric (Int or Float)
-            match (ty_left, ty_right) {
-                (Ty::Primitive(PrimTy::Int64), _) | (_, Ty::Primitive(PrimTy::Int64)) => {
-                    Ok(Ty::Primitive(PrimTy::Int64))
-                }
-                (Ty::Primitive(PrimTy::Float64), _) | (_, Ty::Primitive(PrimTy::Float64)) => {
-                    Ok(Ty::Primitive(PrimTy::Float64))
-                }
-                _ => {
-                    // Both type vars - assume Int
-                    Ok(Ty::Primitive(PrimTy::Int64))
-                }
-            }
+            let bound = binary_bound(op).expect("arithmetic operators have a protocol");
+            check_operand(ctx, bound, ty_left, span)
         }
 
-        // Comparison operators: require comparable types
+        // Comparison operators: require equatable or comparable types
         BinaryOp::Eq | BinaryOp::Neq | BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => {
             // Both operands must be the same type
             ctx.unify(ty_left, ty_right, span)?;
+            let bound = binary_bound(op).expect("comparison operators have a protocol");
+            check_operand(ctx, bound, ty_left, span)?;
+
             // Comparison operators always return Bool
             Ok(Ty::Primitive(PrimTy::Bool))
         }
@@ -849,14 +891,59 @@ fn check_binary_op<'ctx>(
 
         // Assignment operator
         BinaryOp::Assign => {
-            // Assignment has side effects, returns Unit
-            // TODO: Check that left side is mutable (can't assign to literals or immutable vars)
+            // Assignment has side effects, returns Unit; its target was
+            // checked with the operands
             ctx.unify(ty_left, ty_right, span)?;
             Ok(Ty::Primitive(PrimTy::Unit))
         }
     }
 }
 
+/// Selector of the method making an iterator over a sequence.
+const MAKE_ITERATOR: &str = "makeIterator";
+/// Selector of the method advancing an iterator.
+const NEXT: &str = "next";
+
+/// The type of the elements a `for` loop takes from a value of type
+/// `sequence`, by the iteration protocol. Arrays yield their elements,
+/// dictionaries `(key, value)` tuples, strings one-character strings and
+/// ranges integers. Any other type yields what the `next()` of its
+/// `makeIterator()` returns, or its own `next()` if it has no
+/// `makeIterator()`, with an optional unwrapped, since `nil` ends the loop.
+fn element_type(ctx: &mut Context<'_>, sequence: &Ty, span: Span) -> Result<Ty> {
+    let sequence = ctx.subst().apply_ty(sequence);
+    match &sequence {
+        Ty::Array(elem) => return Ok((**elem).clone()),
+        Ty::Dict { key, value } => return Ok(Ty::Tuple(vec![(**key).clone(), (**value).clone()])),
+        Ty::Primitive(PrimTy::String) => return Ok(sequence),
+        Ty::Range => return Ok(Ty::Primitive(PrimTy::Int64)),
+        Ty::Error | Ty::Never => return Ok(sequence),
+        _ => {}
+    }
+    let iterator = match protocol_method(ctx, &sequence, MAKE_ITERATOR) {
+        Some(iterator) => ctx.subst().apply_ty(&iterator),
+        None => sequence.clone(),
+    };
+    match protocol_method(ctx, &iterator, NEXT) {
+        Some(Ty::Optional(elem)) => Ok(*elem),
+        Some(elem) => Ok(elem),
+        None => Err(TypeError::NotASequence { ty: sequence.display(ctx.interner).to_string(), span }),
+    }
+}
+
+/// The return type of the instance method `name` of a nominal type, if it
+/// has one taking no arguments.
+fn protocol_method(ctx: &Context<'_>, ty: &Ty, name: &str) -> Option<Ty> {
+    let (Ty::Struct { name: type_name, .. } | Ty::Enum { name: type_name, .. } | Ty::Class { name: type_name, .. }) = ty
+    else {
+        return None;
+    };
+    let method = ctx.interner.get_symbol(name)?;
+    let methods = ctx.types.lookup_methods(*type_name)?;
+    let info = methods.iter().find(|m| m.name == method && !m.is_static && m.params.is_empty())?;
+    Some(info.return_type.clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1138,4 +1225,252 @@ mod tests {
         let result = check(&mut ctx, &expr, &expected);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_hole_reports_expected_type_and_fitting_bindings() {
+        let mut interner = StringInterner::new();
+        let x = interner.intern("x");
+        let y = interner.intern("y");
+        let flag = interner.intern("flag");
+        let mut ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        ctx.env.bind(x, Scheme::mono(int.clone()));
+        ctx.env.bind(y, Scheme::mono(int.clone()));
+        ctx.env.bind(flag, Scheme::mono(Ty::Primitive(PrimTy::Bool)));
+
+        let span = Span::new(4, 7, 1, 5, 1, 8);
+        let expr = Expr::Hole { span };
+        assert!(check(&mut ctx, &expr, &int).is_ok());
+
+        let diagnostics = ctx.hole_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        match &diagnostics[0] {
+            TypeError::TypedHole {
+                expected,
+                candidates,
+                span: at,
+            } => {
+                assert_eq!(expected, "Int");
+                assert_eq!(candidates, &["x", "y"]);
+                assert_eq!(*at, span);
+            }
+            other => panic!("Expected TypedHole, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_undefined_var_suggests_similar_bindings() {
+        let mut interner = StringInterner::new();
+        let count = interner.intern("count");
+        let total = interner.intern("total");
+        let typo = interner.intern("coutn");
+        let mut ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        ctx.env.bind(count, Scheme::mono(int.clone()));
+        ctx.env.bind(total, Scheme::mono(int));
+
+        let err = synth(&mut ctx, &Expr::Identifier(typo)).unwrap_err();
+        match &err {
+            TypeError::UndefinedVar { candidates, .. } => assert_eq!(candidates, &["count"]),
+            other => panic!("Expected UndefinedVar, got {:?}", other),
+        }
+        assert!(err.to_string().ends_with("did you mean count?"));
+    }
+
+    #[test]
+    fn test_closure_captures() {
+        use crate::check::{check_bodies, collect_signatures};
+        use crate::infer::{Capture, CaptureMode};
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let source = "fn main() -> Int {
+    let offset = 1;
+    mut count = 0;
+    let add = fn(x: Int) -> Int { x + offset };
+    let bump = fn() { count = count + add(1); };
+    let outer = fn() { let inner = fn() { offset }; inner() };
+    bump();
+    count + outer()
+}";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        let mut ctx = Context::new(parser.interner());
+        collect_signatures(&mut ctx, &decls).unwrap();
+        check_bodies(&mut ctx, &decls).unwrap();
+
+        let sym = |name| parser.interner().get_symbol(name).unwrap();
+        let by_value = |name| Capture { name: sym(name), mode: CaptureMode::Value };
+        let mut closures: Vec<_> = ctx.closures.clone().into_iter().collect();
+        closures.sort_by_key(|(span, _)| span.start);
+        let captures: Vec<Vec<Capture>> = closures.iter().map(|(_, info)| info.captures.clone()).collect();
+        assert_eq!(
+            captures,
+            [
+                vec![by_value("offset")],
+                vec![Capture { name: sym("count"), mode: CaptureMode::Box }, by_value("add")],
+                // The outer closure captures what the inner one does
+                vec![by_value("offset")],
+                vec![by_value("offset")],
+            ]
+        );
+
+        // Only the declaration of `count` is boxed
+        let boxed: Vec<usize> = ctx.boxed.iter().map(|span| span.start_line).collect();
+        assert_eq!(boxed, [3]);
+
+        // Return types are inferred from the body without an annotation
+        let int = Ty::Primitive(PrimTy::Int64);
+        let types: Vec<Ty> = closures.iter().map(|(_, info)| ctx.subst().apply_ty(&info.ty)).collect();
+        let function = |params: Vec<Ty>, ret: Ty| Ty::Function {
+            labels: vec![None; params.len()],
+            params,
+            return_type: Box::new(ret),
+        };
+        assert_eq!(types[0], function(vec![int.clone()], int.clone()));
+        assert_eq!(types[1], function(vec![], Ty::Primitive(PrimTy::Unit)));
+        assert_eq!(types[2], function(vec![], int.clone()));
+
+        // Calls are checked against the closure's parameters
+        let source = "fn main() -> Int { let f = fn(x: Int) -> Int { x }; f(true) }";
+        let (tokens, interner) = Lexer::new(source).lex_with_interner().unwrap();
+        let mut parser = Parser::new(tokens, source, interner, LocalArena::new(8192));
+        let decls = parser.parse_program();
+        let mut ctx = Context::new(parser.interner());
+        collect_signatures(&mut ctx, &decls).unwrap();
+        let result = check_bodies(&mut ctx, &decls);
+        assert!(matches!(result.as_ref().map_err(Vec::as_slice), Err([TypeError::Mismatch { .. }])), "{result:?}");
+    }
+
+    #[test]
+    fn test_variant_paths_are_typed_and_suggest_similar_variants() {
+        use crate::check::{check_bodies, collect_signatures};
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let check_source = |body: &str| {
+            let source = format!(
+                "enum Color {{ case red, case green, case gray(Int) }}\nfn main() -> Color {{\n{body}\n}}"
+            );
+            let (tokens, interner) = Lexer::new(&source).lex_with_interner().unwrap();
+            let mut parser = Parser::new(tokens, &source, interner, LocalArena::new(8192));
+            let decls = parser.parse_program();
+            assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+            let mut ctx = Context::new(parser.interner());
+            collect_signatures(&mut ctx, &decls).unwrap();
+            check_bodies(&mut ctx, &decls).map_err(|mut errors| errors.remove(0))
+        };
+
+        assert!(check_source("Color::green").is_ok());
+        assert!(check_source("Color::gray(1)").is_ok());
+
+        // Payloads are checked like arguments
+        let err = check_source("Color::gray(true)").unwrap_err();
+        assert!(matches!(err, TypeError::Mismatch { .. }), "{err:?}");
+        let err = check_source("let n: Int = Color::red;\nColor::red").unwrap_err();
+        assert!(matches!(err, TypeError::Mismatch { .. }), "{err:?}");
+
+        match check_source("Color::gren").unwrap_err() {
+            TypeError::UnknownVariant { ty, variant, candidates, span } => {
+                assert_eq!(ty, "Color");
+                assert_eq!(variant, "gren");
+                assert_eq!(candidates, ["green"]);
+                assert_eq!(span.start_line, 3);
+            }
+            other => panic!("Expected UnknownVariant, got {:?}", other),
+        }
+        match check_source("Color::grey(1)").unwrap_err() {
+            TypeError::UnknownVariant { candidates, .. } => assert_eq!(candidates, ["gray"]),
+            other => panic!("Expected UnknownVariant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_for_loops_type_elements_by_the_iteration_protocol() {
+        use crate::check::{check_bodies, collect_signatures};
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let check_source = |body: &str| {
+            let source = format!(
+                "struct Countdown {{ n: Int }}
+impl Countdown {{ fn next() -> Int {{ n }} }}
+struct Deck {{ size: Int }}
+impl Deck {{ fn makeIterator() -> Countdown {{ Countdown {{ n: size }} }} }}
+fn main() {{
+{body}
+}}"
+            );
+            let (tokens, interner) = Lexer::new(&source).lex_with_interner().unwrap();
+            let mut parser = Parser::new(tokens, &source, interner, LocalArena::new(8192));
+            let decls = parser.parse_program();
+            assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+            let mut ctx = Context::new(parser.interner());
+            crate::builtins::declare(&mut ctx, ["range"]);
+            collect_signatures(&mut ctx, &decls).unwrap();
+            check_bodies(&mut ctx, &decls).map_err(|mut errors| errors.remove(0))
+        };
+
+        let ok = |body: &str| {
+            let result = check_source(body);
+            assert!(result.is_ok(), "{body}: {result:?}");
+        };
+        ok("mut total = 0; for x in [1, 2] { total = total + x; };");
+        ok("mut text = \"\"; for ch in \"ab\" { text = text + ch; };");
+        ok("mut total = 0; for (k, v) in [1: \"a\"] { total = total + k; };");
+        ok("mut total = 0; for i in range(1, 4) { total = total + i; };");
+        ok("let r: Range = range(0, 2);");
+        // A type with `next()` yields what it returns, and one with
+        // `makeIterator()` what its iterator does
+        ok("let c = Countdown { n: 3 }; mut total = 0; for i in c { total = total + i; };");
+        ok("let d = Deck { size: 3 }; mut total = 0; for i in d { total = total + i; };");
+
+        // Elements have the sequence's element type
+        let err = check_source("for ch in \"ab\" { let n: Int = ch; };").unwrap_err();
+        assert!(matches!(err, TypeError::Mismatch { .. }), "{err:?}");
+        let err = check_source("for i in range(1, 4) { let s: String = i; };").unwrap_err();
+        assert!(matches!(err, TypeError::Mismatch { .. }), "{err:?}");
+
+        match check_source("for x in 1 { };").unwrap_err() {
+            TypeError::NotASequence { ty, span } => {
+                assert_eq!(ty, "Int");
+                assert_eq!(span.start_line, 6);
+            }
+            other => panic!("Expected NotASequence, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interpolations_in_source_are_checked() {
+        use crate::check::{check_bodies, collect_signatures};
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        let check_source = |body: &str| {
+            let source = format!("fn main() -> String {{\nlet n = 1;\n{body}\n}}");
+            let (tokens, interner) = Lexer::new(&source).lex_with_interner().unwrap();
+            let mut parser = Parser::new(tokens, &source, interner, LocalArena::new(8192));
+            let decls = parser.parse_program();
+            assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+            let mut ctx = Context::new(parser.interner());
+            collect_signatures(&mut ctx, &decls).unwrap();
+            check_bodies(&mut ctx, &decls).map_err(|mut errors| errors.remove(0))
+        };
+
+        assert!(check_source(r#""n is \(n + 1), \("nested \(n)")""#).is_ok());
+        match check_source(r#""\(nonexistent + 1)""#).unwrap_err() {
+            TypeError::UndefinedVar { name, .. } => assert_eq!(name, "nonexistent"),
+            other => panic!("Expected UndefinedVar, got {:?}", other),
+        }
+        let err = check_source(r#""\(n + true)""#).unwrap_err();
+        assert!(matches!(err, TypeError::Mismatch { .. }), "{err:?}");
+    }
 }
@@ -6,15 +6,26 @@
 //! - Declarations
 //! - Type annotation conversion
 //! - Pattern type checking
+//! - Operator typing
+//! - Recursive type validation
+//! - Constant evaluation
 
+pub mod consteval;
 pub mod decl;
 pub mod expr;
+pub mod operator;
 pub mod pat;
+pub mod recursion;
 pub mod stmt;
 pub mod ty;
+pub mod variance;
 
+pub use consteval::{ConstValue, apply_define, comptime_branch, eval_const};
 pub use decl::{check_decl, check_bodies, collect_signatures};
 pub use expr::{check, synth};
+pub use operator::{binary_bound, check_operand, unary_bound};
 pub use pat::check_pat;
+pub use recursion::check_recursive_types;
 pub use stmt::check_stmt;
-pub use ty::ast_to_ty;
+pub use ty::{ast_to_ty, resolve_primitive};
+pub use variance::{infer_variances, subsume};
@@ -0,0 +1,159 @@
+//! Operator typing.
+//!
+//! Every overloadable operator is backed by a protocol, which a type can
+//! satisfy either by declaring conformance or by implementing the
+//! protocol's method:
+//!
+//! | Operator               | Protocol    | Method         |
+//! |------------------------|-------------|----------------|
+//! | `+`                    | Add         | `add(_:)`      |
+//! | `-` (binary)           | Subtract    | `subtract(_:)` |
+//! | `*`                    | Multiply    | `multiply(_:)` |
+//! | `/`                    | Divide      | `divide(_:)`   |
+//! | `%`                    | Remainder   | `remainder(_:)`|
+//! | `-` (unary)            | Negate      | `negate()`     |
+//! | `==`, `!=`             | Equatable   |                |
+//! | `<`, `<=`, `>`, `>=`   | Comparable  |                |
+//!
+//! Primitive operands take a fast path: they are checked immediately against
+//! their built-in conformances. Operands that are still type variables are
+//! constrained and checked once unification has run.
+
+use crate::error::Result;
+use crate::infer::{Bound, Context, find_method, satisfies, unsatisfied};
+use crate::types::Ty;
+use oxidex_syntax::ast::expr::{BinaryOp, UnaryOp};
+use oxidex_syntax::Span;
+
+/// Get the protocol requirement of a binary operator.
+///
+/// Returns `None` for the logical and assignment operators, which are not
+/// overloadable.
+pub fn binary_bound(op: &BinaryOp) -> Option<Bound> {
+    match op {
+        BinaryOp::Add => Some(Bound::Add),
+        BinaryOp::Sub => Some(Bound::Subtract),
+        BinaryOp::Mul => Some(Bound::Multiply),
+        BinaryOp::Div => Some(Bound::Divide),
+        BinaryOp::Mod => Some(Bound::Remainder),
+        BinaryOp::Eq | BinaryOp::Neq => Some(Bound::Equatable),
+        BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => Some(Bound::Comparable),
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Assign => None,
+    }
+}
+
+/// Get the protocol requirement of a unary operator.
+///
+/// Returns `None` for logical negation, which only applies to `Bool`.
+pub fn unary_bound(op: &UnaryOp) -> Option<Bound> {
+    match op {
+        UnaryOp::Minus => Some(Bound::Negate),
+        UnaryOp::Negate => None,
+    }
+}
+
+/// Check an operand against an operator's requirement.
+///
+/// Returns the type the operation produces: the operand type for
+/// primitives and unresolved operands, or the return type of the
+/// implementing method for user types. Comparison operators yield `Bool`
+/// regardless; callers override the result for them.
+pub fn check_operand<'ctx>(ctx: &mut Context<'ctx>, bound: Bound, operand: &Ty, span: Span) -> Result<Ty> {
+    let operand = ctx.subst().apply_ty(operand);
+    match &operand {
+        Ty::Primitive(_) => {
+            if satisfies(ctx, &operand, &bound) {
+                Ok(operand)
+            } else {
+                Err(unsatisfied(ctx, &operand, &bound, span))
+            }
+        }
+
+        Ty::Struct { name, .. } | Ty::Enum { name, .. } | Ty::Class { name, .. } => {
+            let method = bound
+                .method()
+                .and_then(|(method, arity)| find_method(ctx, *name, method, arity))
+                .cloned();
+            match method {
+                Some(info) => {
+                    // Operator methods take their other operand as `Self`
+                    for param in &info.params {
+                        ctx.unify(param, &operand, span)?;
+                    }
+                    Ok(info.return_type)
+                }
+                None => {
+                    ctx.require(&operand, bound, span);
+                    Ok(operand)
+                }
+            }
+        }
+
+        _ => {
+            ctx.require(&operand, bound, span);
+            Ok(operand)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::MethodInfo;
+    use crate::error::TypeError;
+    use crate::types::PrimTy;
+    use oxidex_mem::{StringInterner, Symbol};
+
+    #[test]
+    fn test_operators_on_user_types_use_protocol_methods() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Vector", "Point", "add"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [vector, point, add] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        for name in [vector, point] {
+            ctx.types.register_struct(crate::context::StructInfo {
+                name,
+                fields: vec![],
+                methods: vec![],
+                generics: vec![],
+            });
+        }
+        let vector_ty = Ty::Struct { name: vector, type_args: vec![] };
+        let point_ty = Ty::Struct { name: point, type_args: vec![] };
+        ctx.types.register_method(
+            vector,
+            MethodInfo {
+                name: add,
+                params: vec![vector_ty.clone()],
+                labels: vec![None],
+                return_type: vector_ty.clone(),
+                is_mut: false,
+                is_static: false,
+            },
+        );
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+
+        let ty = check_operand(&mut ctx, Bound::Add, &vector_ty, span).unwrap();
+        assert_eq!(ty, vector_ty);
+
+        // Without the method or a conformance the missing protocol is named
+        check_operand(&mut ctx, Bound::Add, &point_ty, span).unwrap();
+        match crate::infer::solve_constraints(&mut ctx, true).unwrap_err() {
+            TypeError::ProtocolConstraintNotSatisfied { ty, protocol, .. } => {
+                assert_eq!(ty, "Point");
+                assert_eq!(protocol, "Add");
+            }
+            other => panic!("Expected ProtocolConstraintNotSatisfied, got {:?}", other),
+        }
+
+        // Primitives are checked on the spot
+        let err = check_operand(&mut ctx, Bound::Multiply, &Ty::Primitive(PrimTy::String), span).unwrap_err();
+        assert!(matches!(err, TypeError::ProtocolConstraintNotSatisfied { ref protocol, .. } if protocol == "Multiply"));
+    }
+}
@@ -10,9 +10,11 @@
 //! - Array patterns
 //! - Or patterns
 
+use crate::context::Scheme;
 use crate::error::Result;
 use crate::infer::Context;
 use crate::types::{PrimTy, Ty};
+use oxidex_mem::Symbol;
 use oxidex_syntax::ast::pat::{FieldPat, Pattern};
 use oxidex_syntax::Span;
 
@@ -35,11 +37,11 @@ pub fn check_pat<'ctx>(ctx: &mut Context<'ctx>, pat: &Pattern, expected: &Ty, sp
         }
 
         // Variable binding pattern: `x`, `mut x`
-        Pattern::Variable { name, mutable, span: _ } => {
+        Pattern::Variable { name, mutable, span: var_span } => {
             // Bind the variable to the expected type
-            use crate::context::Scheme;
             let scheme = Scheme::mono(expected.clone());
             ctx.env.bind_mut(*name, scheme, *mutable);
+            ctx.env.declare(*name, *var_span);
             Ok(())
         }
 
@@ -49,6 +51,26 @@ pub fn check_pat<'ctx>(ctx: &mut Context<'ctx>, pat: &Pattern, expected: &Ty, sp
             fields,
             span: _,
         } => {
+            // Struct variant pattern: `Shape::Rect { width, height }`
+            if let [.., enum_name, variant] = type_path.as_slice()
+                && ctx.types.has_enum(ctx.resolve_self_name(*enum_name))
+            {
+                let enum_name = ctx.resolve_self_name(*enum_name);
+                let shape = instantiate_variant(ctx, enum_name, *variant, span)?;
+                unify_enum(ctx, expected, &shape.enum_ty, span)?;
+                let types = match shape.payload {
+                    Some(Ty::Tuple(types)) if shape.fields.len() == types.len() => types,
+                    _ => vec![],
+                };
+                let declared: Vec<_> = shape.fields.into_iter().zip(types).collect();
+                let owner = format!(
+                    "{}::{}",
+                    ctx.interner.resolve(enum_name).unwrap_or(""),
+                    ctx.interner.resolve(*variant).unwrap_or("")
+                );
+                return check_fields(ctx, &owner, fields, &declared);
+            }
+
             // Look up the struct type definition
             if type_path.len() != 1 {
                 // TODO: Handle paths like Module::Struct
@@ -87,30 +109,9 @@ pub fn check_pat<'ctx>(ctx: &mut Context<'ctx>, pat: &Pattern, expected: &Ty, sp
                         };
 
                         // Type check each field pattern
-                        for field_pat in fields {
-                            // Find the field in the struct definition
-                            if let Some(field_info) = struct_fields.iter()
-                                .find(|f| f.name == field_pat.name)
-                            {
-                                // If there's a nested pattern, check it with the field's type
-                                if let Some(pattern) = &field_pat.pattern {
-                                    check_pat(ctx, pattern, &field_info.ty, field_pat.span)?;
-                                } else {
-                                    // Bind the field name to the field's type
-                                    use crate::context::Scheme;
-                                    let scheme = Scheme::mono(field_info.ty.clone());
-                                    ctx.env.bind_mut(field_pat.name, scheme, false);
-                                }
-                            } else {
-                                // Unknown field
-                                return Err(crate::error::TypeError::UnknownField {
-                                    ty: ctx.interner.resolve(struct_name).unwrap_or("").to_string(),
-                                    field: ctx.interner.resolve(field_pat.name).unwrap_or("").to_string(),
-                                    span: field_pat.span,
-                                });
-                            }
-                        }
-                        Ok(())
+                        let declared: Vec<_> = struct_fields.into_iter().map(|f| (f.name, f.ty)).collect();
+                        let owner = ctx.interner.resolve(struct_name).unwrap_or("").to_string();
+                        check_fields(ctx, &owner, fields, &declared)
                     }
                     Ty::Struct { .. } => Err(crate::error::TypeError::Mismatch {
                         expected: expected.clone(),
@@ -139,94 +140,53 @@ pub fn check_pat<'ctx>(ctx: &mut Context<'ctx>, pat: &Pattern, expected: &Ty, sp
             payload,
             span: _,
         } => {
-            // Look up the enum type definition
-            if type_path.len() != 1 {
-                // TODO: Handle paths like Module::Enum
-                match expected {
-                    Ty::Enum { .. } => {
-                        if let Some(payload_pat) = payload {
-                            check_pat(ctx, payload_pat, expected, span)?;
-                        }
-                        Ok(())
-                    }
-                    _ => Err(crate::error::TypeError::Mismatch {
-                        expected: expected.clone(),
-                        found: Ty::Enum {
-                            name: oxidex_mem::Symbol::new(0),
-                            type_args: vec![],
+            let variant_payload = match builtin_variant(ctx, expected, type_path, *variant) {
+                Some(payload) => payload,
+                None => {
+                    // Unqualified variants (`Some(x)`) take the enum from the scrutinee
+                    let enum_name = match type_path.last() {
+                        Some(&name) => Some(ctx.resolve_self_name(name)),
+                        None => match ctx.subst().apply_ty(expected) {
+                            Ty::Enum { name, .. } | Ty::Struct { name, .. } => Some(name),
+                            _ => None,
                         },
-                        span,
-                    })
-                }
-            } else {
-                let enum_name = type_path[0];
+                    };
+                    let Some(enum_name) = enum_name else {
+                        return Err(crate::error::TypeError::UnknownVariant {
+                            ty: expected.display(ctx.interner).to_string(),
+                            variant: ctx.interner.resolve(*variant).unwrap_or("").to_string(),
+                            candidates: vec![],
+                            span,
+                        });
+                    };
 
-                // Verify the expected type matches
-                match expected {
-                    Ty::Enum { name, .. } if *name == enum_name => {
-                        // Look up the enum definition and clone variant info to avoid borrow issues
-                        let variant_payload = if let Some(enum_info) = ctx.types.lookup_enum(enum_name) {
-                            // Find the variant
-                            if let Some(variant_info) = enum_info.variants.iter()
-                                .find(|v| v.name == *variant)
-                            {
-                                variant_info.payload.clone()
-                            } else {
-                                // Unknown variant
-                                return Err(crate::error::TypeError::UnknownVariant {
-                                    ty: ctx.interner.resolve(enum_name).unwrap_or("").to_string(),
-                                    variant: ctx.interner.resolve(*variant).unwrap_or("").to_string(),
-                                    span,
-                                });
-                            }
-                        } else {
-                            // Unknown enum
-                            return Err(crate::error::TypeError::UnknownType {
-                                name: ctx.interner.resolve(enum_name).unwrap_or("").to_string(),
-                                span,
-                            });
-                        };
+                    let shape = instantiate_variant(ctx, enum_name, *variant, span)?;
+                    unify_enum(ctx, expected, &shape.enum_ty, span)?;
+                    shape.payload
+                }
+            };
 
-                        // Type check the payload if present
-                        if let Some(payload_pat) = payload {
-                            if let Some(payload_ty) = &variant_payload {
-                                check_pat(ctx, payload_pat, payload_ty, span)?;
-                            } else {
-                                // Variant has no payload but pattern provides one
-                                return Err(crate::error::TypeError::Mismatch {
-                                    expected: Ty::Tuple(vec![]),
-                                    found: Ty::Tuple(vec![Ty::TypeVar(ctx.fresh_var())]),
-                                    span,
-                                });
-                            }
-                        } else if variant_payload.is_some() {
-                            // Variant has payload but pattern doesn't
-                            return Err(crate::error::TypeError::Mismatch {
-                                expected: variant_payload.unwrap(),
-                                found: Ty::Tuple(vec![]),
-                                span,
-                            });
-                        }
-                        Ok(())
-                    }
-                    Ty::Enum { .. } => Err(crate::error::TypeError::Mismatch {
-                        expected: expected.clone(),
-                        found: Ty::Enum {
-                            name: enum_name,
-                            type_args: vec![],
-                        },
+            // Type check the payload if present
+            if let Some(payload_pat) = payload {
+                if let Some(payload_ty) = &variant_payload {
+                    check_pat(ctx, payload_pat, payload_ty, span)?;
+                } else {
+                    // Variant has no payload but pattern provides one
+                    return Err(crate::error::TypeError::Mismatch {
+                        expected: Ty::Tuple(vec![]),
+                        found: Ty::Tuple(vec![Ty::TypeVar(ctx.fresh_var())]),
                         span,
-                    }),
-                    _ => Err(crate::error::TypeError::Mismatch {
-                        expected: expected.clone(),
-                        found: Ty::Enum {
-                            name: enum_name,
-                            type_args: vec![],
-                        },
-                        span,
-                    })
+                    });
                 }
+            } else if let Some(payload_ty) = variant_payload {
+                // Variant has payload but pattern doesn't
+                return Err(crate::error::TypeError::Mismatch {
+                    expected: payload_ty,
+                    found: Ty::Tuple(vec![]),
+                    span,
+                });
             }
+            Ok(())
         }
 
         // Tuple pattern: `(x, y, z)`
@@ -239,7 +199,7 @@ pub fn check_pat<'ctx>(ctx: &mut Context<'ctx>, pat: &Pattern, expected: &Ty, sp
                     }
                     Ok(())
                 }
-                Ty::Tuple(types) => {
+                Ty::Tuple(_types) => {
                     // Wrong number of elements
                     Err(crate::error::TypeError::Mismatch {
                         expected: expected.clone(),
@@ -303,13 +263,141 @@ fn check_field_pat<'ctx>(ctx: &mut Context<'ctx>, field: &FieldPat, expected: &T
         check_pat(ctx, pattern, expected, field.span)?;
     } else {
         // Bind the field name to the expected type
-        use crate::context::Scheme;
         let scheme = Scheme::mono(expected.clone());
         ctx.env.bind_mut(field.name, scheme, false);
     }
     Ok(())
 }
 
+/// Type check field patterns against the declared fields of a struct or
+/// struct variant.
+fn check_fields<'ctx>(
+    ctx: &mut Context<'ctx>,
+    owner: &str,
+    fields: &[FieldPat],
+    declared: &[(Symbol, Ty)],
+) -> Result<()> {
+    for field_pat in fields {
+        let Some((_, field_ty)) = declared.iter().find(|(name, _)| *name == field_pat.name) else {
+            return Err(crate::error::TypeError::UnknownField {
+                ty: owner.to_string(),
+                field: ctx.interner.resolve(field_pat.name).unwrap_or("").to_string(),
+                candidates: ctx.suggest(field_pat.name, declared.iter().map(|(name, _)| *name)),
+                span: field_pat.span,
+            });
+        };
+
+        // If there's a nested pattern, check it with the field's type
+        if let Some(pattern) = &field_pat.pattern {
+            check_pat(ctx, pattern, field_ty, field_pat.span)?;
+        } else {
+            // Bind the field name to the field's type
+            let scheme = Scheme::mono(field_ty.clone());
+            ctx.env.bind_mut(field_pat.name, scheme, false);
+        }
+    }
+    Ok(())
+}
+
+/// An enum variant instantiated for one pattern or constructor.
+pub(super) struct VariantShape {
+    /// The enum applied to fresh type arguments
+    pub(super) enum_ty: Ty,
+    /// The payload type in terms of those arguments
+    pub(super) payload: Option<Ty>,
+    /// Field names of a struct variant
+    pub(super) fields: Vec<Symbol>,
+}
+
+/// Look up an enum variant and instantiate the enum's generic parameters
+/// with fresh type variables.
+///
+/// Unifying the result with the scrutinee type is what turns the payload of
+/// `Option::Some(x)` into `Int` when matching an `Option<Int>`.
+pub(super) fn instantiate_variant<'ctx>(
+    ctx: &mut Context<'ctx>,
+    enum_name: Symbol,
+    variant: Symbol,
+    span: Span,
+) -> Result<VariantShape> {
+    let Some(enum_info) = ctx.types.lookup_enum(enum_name) else {
+        return Err(crate::error::TypeError::UnknownType {
+            name: ctx.interner.resolve(enum_name).unwrap_or("").to_string(),
+            span,
+        });
+    };
+    let Some(variant_info) = enum_info.variants.iter().find(|v| v.name == variant) else {
+        return Err(crate::error::TypeError::UnknownVariant {
+            ty: ctx.interner.resolve(enum_name).unwrap_or("").to_string(),
+            variant: ctx.interner.resolve(variant).unwrap_or("").to_string(),
+            candidates: ctx.suggest(variant, enum_info.variants.iter().map(|v| v.name)),
+            span,
+        });
+    };
+
+    let type_vars = enum_info.type_vars.clone();
+    let fields = variant_info.fields.clone();
+    let generic = Ty::Enum {
+        name: enum_name,
+        type_args: type_vars.iter().copied().map(Ty::TypeVar).collect(),
+    };
+    let parts = std::iter::once(generic).chain(variant_info.payload.clone()).collect();
+    let Ty::Tuple(mut parts) = Scheme::poly(type_vars, Ty::Tuple(parts)).instantiate(ctx.subst()) else {
+        unreachable!("instantiating a tuple yields a tuple");
+    };
+
+    let payload = if parts.len() == 2 { parts.pop() } else { None };
+    let enum_ty = parts.pop().expect("the enum type is always present");
+    Ok(VariantShape { enum_ty, payload, fields })
+}
+
+/// Unify the scrutinee type with an instantiated enum type.
+fn unify_enum<'ctx>(ctx: &mut Context<'ctx>, expected: &Ty, enum_ty: &Ty, span: Span) -> Result<()> {
+    let expected = ctx.subst().apply_ty(expected);
+    let Ty::Enum { name, type_args: fresh } = enum_ty else {
+        unreachable!("instantiate_variant builds an enum type");
+    };
+    match &expected {
+        // Annotations name enums the same way as structs
+        Ty::Enum { name: found, type_args } | Ty::Struct { name: found, type_args } if found == name => {
+            // Values constructed without type arguments leave them open
+            if type_args.is_empty() {
+                Ok(())
+            } else {
+                ctx.unify(&Ty::Tuple(type_args.clone()), &Ty::Tuple(fresh.clone()), span)
+            }
+        }
+        Ty::TypeVar(_) => ctx.unify(&expected, enum_ty, span),
+        _ => Err(crate::error::TypeError::Mismatch {
+            expected,
+            found: enum_ty.clone(),
+            span,
+        }),
+    }
+}
+
+/// Resolve `Some`/`None` against optionals and `Ok`/`Err` against results.
+///
+/// Returns the payload type of the variant, or `None` if the pattern does
+/// not name a built-in variant of the scrutinee type.
+fn builtin_variant(ctx: &mut Context<'_>, expected: &Ty, type_path: &[Symbol], variant: Symbol) -> Option<Option<Ty>> {
+    let owner = type_path.last().and_then(|&name| ctx.interner.resolve(name));
+    let variant = ctx.interner.resolve(variant)?;
+    match (ctx.subst().apply_ty(expected), owner) {
+        (Ty::Optional(inner), None | Some("Option" | "Optional")) => match variant {
+            "Some" => Some(Some(*inner)),
+            "None" => Some(None),
+            _ => None,
+        },
+        (Ty::Result { ok, error }, None | Some("Result")) => match variant {
+            "Ok" => Some(Some(*ok)),
+            "Err" => Some(Some(*error)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Get the type of a literal token.
 fn ty_from_literal(token: &oxidex_syntax::token::TokenKind) -> Ty {
     match token {
@@ -382,4 +470,108 @@ mod tests {
 
         assert!(check_pat(&mut ctx, &pat, &expected, Span::new(0, 0, 0, 0, 0, 0)).is_ok());
     }
+
+    #[test]
+    fn test_generic_enum_payloads_bind_instantiated_types() {
+        use oxidex_syntax::ast::decl::{Decl, EnumVariant, StructField, Visibility};
+        use oxidex_syntax::ast::ty::Type;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Maybe", "Just", "Shape", "Rect", "T", "w", "h", "x", "y", "z", "Int", "Some"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [maybe, just, shape, rect, t, w, h, x, y, z, int_sym, some] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let enum_decl = |name, generics, variants| Decl::Enum {
+            name,
+            generics,
+            variants,
+            methods: vec![],
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span,
+        };
+        let decls = [
+            enum_decl(
+                maybe,
+                vec![t],
+                vec![EnumVariant::Tuple {
+                    name: just,
+                    fields: vec![Type::Simple { name: t, span }],
+                    span,
+                }],
+            ),
+            enum_decl(
+                shape,
+                vec![],
+                vec![EnumVariant::Struct {
+                    name: rect,
+                    fields: [w, h]
+                        .map(|name| StructField {
+                            name,
+                            type_annotation: Type::Simple { name: int_sym, span },
+                            span,
+                        })
+                        .to_vec(),
+                    span,
+                }],
+            ),
+        ];
+        for decl in &decls {
+            crate::check::check_decl(&mut ctx, decl).unwrap();
+        }
+
+        let bound = |ctx: &mut Context<'_>, name| {
+            let ty = ctx.env.lookup(name).unwrap().ty.clone();
+            ctx.subst().apply_ty(&ty)
+        };
+        let var = |name| Pattern::Variable { name, mutable: false, span };
+        let just_pat = |payload| Pattern::Enum {
+            type_path: vec![maybe],
+            variant: just,
+            payload: Some(Box::new(payload)),
+            span,
+        };
+        let maybe_of = |arg| Ty::Struct { name: maybe, type_args: vec![arg] };
+
+        // `Maybe::Just(Maybe::Just(x))` against `Maybe<Maybe<Bool>>`
+        let expected = maybe_of(maybe_of(Ty::Primitive(PrimTy::Bool)));
+        check_pat(&mut ctx, &just_pat(just_pat(var(x))), &expected, span).unwrap();
+        assert_eq!(bound(&mut ctx, x), Ty::Primitive(PrimTy::Bool));
+
+        // Each match instantiates the enum afresh
+        let expected = maybe_of(Ty::Primitive(PrimTy::Int64));
+        check_pat(&mut ctx, &just_pat(var(y)), &expected, span).unwrap();
+        assert_eq!(bound(&mut ctx, y), Ty::Primitive(PrimTy::Int64));
+
+        // Struct variant fields: `Shape::Rect { w, h: z }`
+        let rect_pat = Pattern::Struct {
+            type_path: vec![shape, rect],
+            fields: vec![
+                FieldPat { name: w, pattern: None, span },
+                FieldPat { name: h, pattern: Some(Box::new(var(z))), span },
+            ],
+            span,
+        };
+        let expected = Ty::Enum { name: shape, type_args: vec![] };
+        check_pat(&mut ctx, &rect_pat, &expected, span).unwrap();
+        assert_eq!(bound(&mut ctx, w), Ty::Primitive(PrimTy::Int64));
+        assert_eq!(bound(&mut ctx, z), Ty::Primitive(PrimTy::Int64));
+
+        // Built-in optionals: `Some(x)` against `String?`
+        let some_pat = Pattern::Enum {
+            type_path: vec![],
+            variant: some,
+            payload: Some(Box::new(var(x))),
+            span,
+        };
+        let expected = Ty::Optional(Box::new(Ty::Primitive(PrimTy::String)));
+        check_pat(&mut ctx, &some_pat, &expected, span).unwrap();
+        assert_eq!(bound(&mut ctx, x), Ty::Primitive(PrimTy::String));
+    }
 }
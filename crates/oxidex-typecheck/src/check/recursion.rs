@@ -0,0 +1,361 @@
+//! Recursive type validation.
+//!
+//! Structs and enums are stored inline, so a type that contains itself,
+//! directly or through other types, has infinite size:
+//!
+//! ```ignore
+//! struct A { b: B }
+//! struct B { a: A }   // error: A -> B -> A
+//! ```
+//!
+//! Recursion is allowed when some edge in the cycle goes through indirection:
+//!
+//! - `Box<T>`
+//! - Optionals (`T?`, `Option<T>`)
+//! - Arrays and dictionaries
+//! - Function types
+//! - Classes (always heap-allocated and reference-counted)
+//!
+//! Generic types are followed through their instantiations, so
+//! `struct Pair<T> { a: T, b: T }` and `struct A { p: Pair<A> }` is rejected
+//! while `struct A { items: Array<A> }` is accepted.
+
+use crate::error::{Result, TypeError};
+use crate::infer::Context;
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::{Decl, EnumVariant};
+use oxidex_syntax::ast::ty::Type;
+use std::collections::HashMap;
+
+/// Check that no struct or enum has infinite size.
+///
+/// Every cycle is reported once, at the first declaration in source order
+/// that takes part in it.
+///
+/// # Errors
+///
+/// Returns `RecursiveType` describing the first cycle found.
+pub fn check_recursive_types<'ctx>(ctx: &Context<'ctx>, decls: &[Decl<'ctx>]) -> Result<()> {
+    let types: HashMap<Symbol, &Decl<'ctx>> = decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Struct { name, .. } | Decl::Enum { name, .. } | Decl::Class { name, .. } => {
+                Some((*name, decl))
+            }
+            _ => None,
+        })
+        .collect();
+
+    for decl in decls {
+        let (name, span) = match decl {
+            Decl::Struct { name, span, .. } | Decl::Enum { name, span, .. } => (*name, *span),
+            _ => continue,
+        };
+
+        let mut walker = Walker {
+            types: &types,
+            result: ctx.interner.get_symbol("Result"),
+            path: Vec::new(),
+        };
+        if let Some(cycle) = walker.visit(name, &HashMap::new()) {
+            let resolve = |sym: Symbol| ctx.interner.resolve(sym).unwrap_or("").to_string();
+            return Err(TypeError::RecursiveType {
+                name: resolve(name),
+                cycle: cycle.into_iter().map(resolve).collect(),
+                span,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Depth-first search for inline containment cycles.
+struct Walker<'a, 'ctx> {
+    /// Struct, enum, and class declarations by name.
+    types: &'a HashMap<Symbol, &'a Decl<'ctx>>,
+
+    /// The interned `Result` name, if it occurs in the program.
+    ///
+    /// A user type named `Result` shadows the built-in and is handled as a
+    /// declaration instead.
+    result: Option<Symbol>,
+
+    /// Types currently being laid out, outermost first.
+    path: Vec<Symbol>,
+}
+
+impl Walker<'_, '_> {
+    /// Visit a type declaration instantiated with the given generic arguments.
+    ///
+    /// Returns the cycle if the type at the root of the path contains itself.
+    fn visit(&mut self, name: Symbol, args: &HashMap<Symbol, Type>) -> Option<Vec<Symbol>> {
+        if let Some(pos) = self.path.iter().position(|&n| n == name) {
+            // Only cycles through the root are reported here; others are
+            // reported at their own declaration
+            if pos == 0 {
+                let mut cycle = self.path.clone();
+                cycle.push(name);
+                return Some(cycle);
+            }
+            return None;
+        }
+
+        let decl = *self.types.get(&name)?;
+        let (generics, field_types): (&[Symbol], Vec<&Type>) = match decl {
+            Decl::Struct {
+                generics, fields, ..
+            } => (generics, fields.iter().map(|f| &f.type_annotation).collect()),
+            Decl::Enum {
+                generics, variants, ..
+            } => (
+                generics,
+                variants
+                    .iter()
+                    .flat_map(|variant| match variant {
+                        EnumVariant::Unit { .. } => Vec::new(),
+                        EnumVariant::Tuple { fields, .. } => fields.iter().collect(),
+                        EnumVariant::Struct { fields, .. } => {
+                            fields.iter().map(|f| &f.type_annotation).collect()
+                        }
+                    })
+                    .collect(),
+            ),
+            // Classes are references, so they never contain anything inline
+            _ => return None,
+        };
+
+        // Bind this declaration's generics to the caller's arguments
+        let scope: HashMap<Symbol, Type> = generics
+            .iter()
+            .filter_map(|g| args.get(g).map(|ty| (*g, ty.clone())))
+            .collect();
+
+        self.path.push(name);
+        let cycle = field_types
+            .into_iter()
+            .find_map(|ty| self.visit_type(ty, name, &scope));
+        self.path.pop();
+        cycle
+    }
+
+    /// Visit a field type stored inline in `owner`.
+    fn visit_type(
+        &mut self,
+        ty: &Type,
+        owner: Symbol,
+        scope: &HashMap<Symbol, Type>,
+    ) -> Option<Vec<Symbol>> {
+        match ty {
+            Type::Simple { name, .. } => match scope.get(name) {
+                Some(arg) => {
+                    // Arguments were substituted by the caller, so they are already closed
+                    let arg = arg.clone();
+                    self.visit_type(&arg, owner, &HashMap::new())
+                }
+                None => self.visit(*name, &HashMap::new()),
+            },
+
+            Type::SelfType { .. } => self.visit(owner, &HashMap::new()),
+
+            Type::Generic { name, params, .. } => {
+                match self.types.get(name).copied() {
+                    Some(Decl::Struct { generics, .. }) | Some(Decl::Enum { generics, .. }) => {
+                        let args = generics
+                            .iter()
+                            .zip(params)
+                            .map(|(g, p)| (*g, substitute(p, scope)))
+                            .collect();
+                        self.visit(*name, &args)
+                    }
+                    Some(_) => None,
+                    None => {
+                        // `Result<T, E>` stores its payloads inline; the other
+                        // built-in generics are indirections
+                        if self.result == Some(*name) {
+                            params
+                                .iter()
+                                .find_map(|p| self.visit_type(p, owner, scope))
+                        } else {
+                            None
+                        }
+                    }
+                }
+            }
+
+            Type::Tuple { elements, .. } => elements
+                .iter()
+                .find_map(|element| self.visit_type(element, owner, scope)),
+
+            Type::Function { .. }
+            | Type::Array { .. }
+            | Type::Dict { .. }
+            | Type::Optional { .. } => None,
+        }
+    }
+}
+
+/// Substitute generic arguments into a type annotation.
+fn substitute(ty: &Type, scope: &HashMap<Symbol, Type>) -> Type {
+    match ty {
+        Type::Simple { name, .. } => scope.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Generic { name, params, span } => Type::Generic {
+            name: *name,
+            params: params.iter().map(|p| substitute(p, scope)).collect(),
+            span: *span,
+        },
+        Type::Tuple { elements, span } => Type::Tuple {
+            elements: elements.iter().map(|e| substitute(e, scope)).collect(),
+            span: *span,
+        },
+        // Indirections stop the walk, so their contents never need substituting
+        _ => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::Span;
+    use oxidex_syntax::ast::decl::{StructField, Visibility};
+
+    fn span(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    fn simple(name: Symbol) -> Type {
+        Type::Simple { name, span: span(0) }
+    }
+
+    fn generic(name: Symbol, params: Vec<Type>) -> Type {
+        Type::Generic {
+            name,
+            params,
+            span: span(0),
+        }
+    }
+
+    fn structure(name: Symbol, generics: Vec<Symbol>, fields: Vec<(Symbol, Type)>, line: usize) -> Decl<'static> {
+        Decl::Struct {
+            name,
+            generics,
+            fields: fields
+                .into_iter()
+                .map(|(name, type_annotation)| StructField {
+                    name,
+                    type_annotation,
+                    span: span(line),
+                })
+                .collect(),
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span: span(line),
+        }
+    }
+
+    #[test]
+    fn test_mutual_recursion_is_rejected() {
+        let mut interner = StringInterner::new();
+        let a = interner.intern("A");
+        let b = interner.intern("B");
+        let c = interner.intern("C");
+        let field = interner.intern("field");
+        let ctx = Context::new(&interner);
+
+        // C only refers to the cycle, so the cycle is reported at A
+        let decls = vec![
+            structure(c, vec![], vec![(field, simple(a))], 1),
+            structure(a, vec![], vec![(field, simple(b))], 2),
+            structure(b, vec![], vec![(field, simple(a))], 3),
+        ];
+
+        let err = check_recursive_types(&ctx, &decls).unwrap_err();
+        match &err {
+            TypeError::RecursiveType { name, cycle, span: at } => {
+                assert_eq!(name, "A");
+                assert_eq!(cycle, &["A", "B", "A"]);
+                assert_eq!(*at, span(2));
+            }
+            other => panic!("Expected RecursiveType, got {:?}", other),
+        }
+        assert!(err.to_string().contains("Box<A>"));
+    }
+
+    #[test]
+    fn test_recursion_through_indirection_is_allowed() {
+        let mut interner = StringInterner::new();
+        let node = interner.intern("Node");
+        let next = interner.intern("next");
+        let boxed = interner.intern("Box");
+        let array = interner.intern("Array");
+        let ctx = Context::new(&interner);
+
+        let optional = Type::Optional {
+            inner: Box::new(simple(node)),
+            span: span(0),
+        };
+        let decls = vec![structure(
+            node,
+            vec![],
+            vec![
+                (next, optional),
+                (next, generic(boxed, vec![simple(node)])),
+                (next, generic(array, vec![simple(node)])),
+            ],
+            1,
+        )];
+
+        assert!(check_recursive_types(&ctx, &decls).is_ok());
+    }
+
+    #[test]
+    fn test_recursion_through_class_is_allowed() {
+        let mut interner = StringInterner::new();
+        let a = interner.intern("A");
+        let b = interner.intern("B");
+        let field = interner.intern("field");
+        let ctx = Context::new(&interner);
+
+        let decls = vec![
+            structure(a, vec![], vec![(field, simple(b))], 1),
+            Decl::Class {
+                name: b,
+                generics: vec![],
+                superclass: None,
+                fields: vec![StructField {
+                    name: field,
+                    type_annotation: simple(a),
+                    span: span(2),
+                }],
+                protocols: vec![],
+                visibility: Visibility::Private,
+                span: span(2),
+            },
+        ];
+
+        assert!(check_recursive_types(&ctx, &decls).is_ok());
+    }
+
+    #[test]
+    fn test_recursion_through_generic_instantiation() {
+        let mut interner = StringInterner::new();
+        let pair = interner.intern("Pair");
+        let a = interner.intern("A");
+        let t = interner.intern("T");
+        let field = interner.intern("field");
+        let ctx = Context::new(&interner);
+
+        // struct Pair<T> { field: T }  struct A { field: Pair<A> }
+        let decls = vec![
+            structure(pair, vec![t], vec![(field, simple(t))], 1),
+            structure(a, vec![], vec![(field, generic(pair, vec![simple(a)]))], 2),
+        ];
+
+        let err = check_recursive_types(&ctx, &decls).unwrap_err();
+        assert!(matches!(
+            err,
+            TypeError::RecursiveType { ref cycle, .. } if cycle == &["A", "Pair", "A"]
+        ));
+    }
+}
@@ -7,9 +7,10 @@
 //! - Control flow statements (if, match, for, while)
 //! - Expression statements
 
-use crate::error::Result;
+use crate::error::{Result, TypeError};
 use crate::infer::Context;
 use crate::types::{PrimTy, Ty};
+use oxidex_syntax::ast::expr::Expr;
 use oxidex_syntax::ast::stmt::Stmt;
 use oxidex_syntax::{Span, Spanned};
 
@@ -26,17 +27,25 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
             init,
             span,
         } => {
-            // Type check initializer if present
-            let ty_init = if let Some(init_expr) = init {
-                Some(super::expr::synth(ctx, init_expr)?)
-            } else {
-                None
-            };
+            // Type check initializer if present, one level deeper so that the
+            // variables it introduces can be generalized
+            ctx.enter_level();
+            let ty_init = init.map(|init_expr| super::expr::synth(ctx, init_expr)).transpose();
+            ctx.exit_level();
+            let ty_init = ty_init?;
 
             // If there's a type annotation, convert it and unify with initializer type
             if let (Some(ty_init), Some(type_anno)) = (&ty_init, type_annotation) {
                 let ty_anno = super::ty::ast_to_ty(ctx, type_anno)?;
-                ctx.unify(&ty_init, &ty_anno, *span)?;
+                ctx.unify(ty_init, &ty_anno, *span)?;
+            }
+
+            // Immutable bindings with an initializer are generalized
+            if let Some(ty_init) = &ty_init {
+                let scheme = ctx.generalize(ty_init);
+                ctx.env.bind(*name, scheme);
+                ctx.env.declare(*name, *span);
+                return Ok(());
             }
 
             // Bind the variable in the environment
@@ -51,6 +60,7 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
             });
             let scheme = Scheme::mono(ty);
             ctx.env.bind(*name, scheme);
+            ctx.env.declare(*name, *span);
 
             Ok(())
         }
@@ -72,7 +82,7 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
             // If there's a type annotation, convert it and unify with initializer type
             if let (Some(ty_init), Some(type_anno)) = (&ty_init, type_annotation) {
                 let ty_anno = super::ty::ast_to_ty(ctx, type_anno)?;
-                ctx.unify(&ty_init, &ty_anno, *span)?;
+                ctx.unify(ty_init, &ty_anno, *span)?;
             }
 
             // Bind the variable as mutable in the environment
@@ -87,6 +97,7 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
             });
             let scheme = Scheme::mono(ty);
             ctx.env.bind_mut(*name, scheme, true);
+            ctx.env.declare(*name, *span);
 
             Ok(())
         }
@@ -151,6 +162,19 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
             Ok(())
         }
 
+        // Defer statement: `defer { body }`. The body runs later, as a
+        // closure over the variables it uses.
+        Stmt::Defer { body, span } => {
+            ctx.enter_closure(*span);
+            ctx.new_scope();
+            let result = super::expr::synth(ctx, body);
+            ctx.pop_scope();
+            let unit = Box::new(Ty::Primitive(PrimTy::Unit));
+            ctx.exit_closure(&Ty::Function { labels: vec![], params: vec![], return_type: unit });
+            result?;
+            Ok(())
+        }
+
         // Match statement: `match value { pattern => expr }`
         Stmt::Match {
             scrutinee, arms, span,
@@ -214,57 +238,7 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
         Stmt::Assign {
             target, value, span,
         } => {
-            // CRITICAL: Check if target is a valid lvalue
-            use oxidex_syntax::ast::expr::Expr;
-            match target {
-                // Identifier: check if mutable
-                Expr::Identifier(sym) => {
-                    let name = ctx.interner.resolve(*sym).unwrap_or("");
-                    if !ctx.env.is_mutable(*sym) {
-                        return Err(crate::error::TypeError::AssignToImmutable {
-                            name: name.to_string(),
-                            span: *span,
-                        });
-                    }
-                }
-
-                // Field access: p.x = value (need to check if p is mutable and field is mutable)
-                Expr::Field { .. } => {
-                    // TODO: For now, we'll allow field access assignments
-                    // In the future, we need to check if the base object is mutable
-                    // and if the specific field is declared as mutable
-                }
-
-                // Index access: arr[i] = value (need to check if array is mutable)
-                Expr::Index { .. } => {
-                    // TODO: For now, we'll allow index assignments
-                    // In the future, we need to check if the collection is mutable
-                }
-
-                // Invalid assignment targets (literals)
-                Expr::IntegerLiteral { .. }
-                | Expr::FloatLiteral { .. }
-                | Expr::StringLiteral { .. }
-                | Expr::BoolLiteral { .. }
-                | Expr::Nil { .. } => {
-                    return Err(crate::error::TypeError::InvalidAssignmentTarget {
-                        span: *span,
-                    });
-                }
-
-                Expr::Binary { .. } | Expr::Unary { .. } | Expr::Call { .. } | Expr::MethodCall { .. } => {
-                    return Err(crate::error::TypeError::InvalidAssignmentTarget {
-                        span: target.span(),
-                    });
-                }
-
-                _ => {
-                    // Other expressions are not valid lvalues
-                    return Err(crate::error::TypeError::InvalidAssignmentTarget {
-                        span: target.span(),
-                    });
-                }
-            }
+            check_assign_target(ctx, target, *span)?;
 
             // Type check target
             let ty_target = super::expr::synth(ctx, target)?;
@@ -287,6 +261,72 @@ pub fn check_stmt<'ctx>(ctx: &mut Context<'ctx>, stmt: &Stmt<'ctx>) -> Result<()
     }
 }
 
+/// Require that `target` can be assigned to.
+///
+/// Literals, operators and calls are not places at all; places must be
+/// mutable (see [`check_mutable_place`]). Both assignment statements and
+/// `=` expressions are checked here.
+pub fn check_assign_target<'ctx>(
+    ctx: &mut Context<'ctx>,
+    target: &Expr<'ctx>,
+    span: Span,
+) -> Result<()> {
+    match target {
+        // Identifiers and projections: the root binding must be mutable
+        Expr::Identifier(_)
+        | Expr::Path { .. }
+        | Expr::Field { .. }
+        | Expr::Index { .. }
+        | Expr::Paren { .. } => check_mutable_place(ctx, target, span),
+
+        // Invalid assignment targets (literals)
+        Expr::IntegerLiteral { .. }
+        | Expr::FloatLiteral { .. }
+        | Expr::StringLiteral { .. }
+        | Expr::BoolLiteral { .. }
+        | Expr::Nil { .. } => Err(TypeError::InvalidAssignmentTarget { span }),
+
+        // Other expressions are not valid lvalues
+        _ => Err(TypeError::InvalidAssignmentTarget { span: target.span() }),
+    }
+}
+
+/// Require that a place expression can be mutated.
+///
+/// Field and index projections are followed down to the root binding, which
+/// must be declared with `mut`. Projections through a class instance stop the
+/// walk, since classes are references and their fields can be mutated through
+/// any binding. Temporaries such as call results are always mutable.
+pub fn check_mutable_place<'ctx>(
+    ctx: &mut Context<'ctx>,
+    place: &Expr<'ctx>,
+    span: Span,
+) -> Result<()> {
+    let root = match place {
+        Expr::Identifier(sym) => *sym,
+        Expr::Path { segments, .. } if segments.len() == 1 => segments[0],
+        Expr::Paren { expr, .. } => return check_mutable_place(ctx, expr, span),
+        Expr::Field { object: base, .. } | Expr::Index { collection: base, .. } => {
+            let ty_base = super::expr::synth(ctx, base)?;
+            if matches!(ctx.subst().apply_ty(&ty_base), Ty::Class { .. }) {
+                return Ok(());
+            }
+            return check_mutable_place(ctx, base, span);
+        }
+        _ => return Ok(()),
+    };
+
+    if ctx.env.is_mutable(root) {
+        return Ok(());
+    }
+
+    Err(TypeError::AssignToImmutable {
+        name: ctx.interner.resolve(root).unwrap_or("").to_string(),
+        declared_at: ctx.env.decl_span(root),
+        span,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -298,12 +338,27 @@ mod tests {
 
     #[test]
     fn test_check_let_binding() {
-        let interner = StringInterner::new();
+        let mut interner = StringInterner::new();
+        let x_sym = interner.intern("x");
         let mut ctx = Context::new(&interner);
 
-        // TODO: Create a proper let binding statement
-        // For now, this test just checks that the function exists
-        // and doesn't panic
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let init = Expr::IntegerLiteral {
+            value: oxidex_mem::Symbol::new(0),
+            type_suffix: None,
+            span,
+        };
+        let stmt = Stmt::Let {
+            name: x_sym,
+            type_annotation: None,
+            init: Some(&init),
+            span,
+        };
+
+        check_stmt(&mut ctx, &stmt).unwrap();
+        let scheme = ctx.env.lookup(x_sym).unwrap();
+        assert_eq!(scheme.ty, Ty::Primitive(PrimTy::Int64));
+        assert!(!ctx.env.is_mutable(x_sym));
     }
 
     #[test]
@@ -363,4 +418,145 @@ mod tests {
         let result = check_stmt(&mut ctx, &assign);
         assert!(result.is_ok());
     }
+
+    /// Register `struct Point { x: Int }` with a `mut fn shift()` and a `fn norm() -> Int`.
+    fn register_point(ctx: &mut Context<'_>, point: oxidex_mem::Symbol, x: oxidex_mem::Symbol, shift: oxidex_mem::Symbol, norm: oxidex_mem::Symbol) {
+        use crate::context::{FieldInfo, MethodInfo, StructInfo};
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        let method = |name, is_mut, return_type| MethodInfo {
+            name,
+            params: vec![],
+            labels: vec![],
+            return_type,
+            is_mut,
+            is_static: false,
+        };
+        ctx.types.register_struct(StructInfo {
+            name: point,
+            fields: vec![FieldInfo { name: x, ty: int.clone() }],
+            methods: vec![
+                method(shift, true, Ty::Primitive(PrimTy::Unit)),
+                method(norm, false, int),
+            ],
+            generics: vec![],
+        });
+    }
+
+    #[test]
+    fn test_field_assignment_requires_mutable_base() {
+        use crate::check::{check_bodies, collect_signatures};
+        use oxidex_mem::LocalArena;
+        use oxidex_syntax::Lexer;
+        use oxidex_syntax::parser::Parser;
+
+        // `=` is parsed as a binary expression, so this goes through the parser
+        let check_source = |body: &str| {
+            let source = format!("struct Point {{ x: Int, y: Int }}\nfn main() -> Int {{\n{body}\n}}");
+            let (tokens, interner) = Lexer::new(&source).lex_with_interner().unwrap();
+            let mut parser = Parser::new(tokens, &source, interner, LocalArena::new(8192));
+            let decls = parser.parse_program();
+            assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+            let mut ctx = Context::new(parser.interner());
+            collect_signatures(&mut ctx, &decls)
+                .map_err(|err| vec![err])
+                .and_then(|()| check_bodies(&mut ctx, &decls))
+                .map_err(|mut errors| errors.remove(0))
+        };
+
+        let err = check_source("let p = Point { x: 1, y: 2 };\np.x = 5;\np.x").unwrap_err();
+        match &err {
+            TypeError::AssignToImmutable { name, declared_at, span } => {
+                assert_eq!(name, "p");
+                assert_eq!(declared_at.map(|at| at.start_line), Some(3));
+                assert_eq!((span.start_line, span.start_col), (4, 1));
+            }
+            other => panic!("Expected AssignToImmutable error, got {:?}", other),
+        }
+        assert_eq!(err.notes().len(), 1);
+        assert_eq!(err.diagnostic().span.start_line, 4);
+
+        let err = check_source("let x = 1;\nx = 2;\nx").unwrap_err();
+        match &err {
+            TypeError::AssignToImmutable { name, span, .. } => {
+                assert_eq!(name, "x");
+                assert_eq!((span.start_line, span.start_col), (4, 1));
+            }
+            other => panic!("Expected AssignToImmutable error, got {:?}", other),
+        }
+
+        // Literals are not places at all
+        let err = check_source("1 = 2;\n0").unwrap_err();
+        assert!(matches!(err, TypeError::InvalidAssignmentTarget { .. }), "{err:?}");
+
+        // The same assignments through mutable bindings are fine
+        assert!(check_source("mut p = Point { x: 1, y: 2 };\np.x = 5;\np.x").is_ok());
+        assert!(check_source("mut x = 1;\nx = 2;\nx").is_ok());
+    }
+
+    #[test]
+    fn test_mut_method_requires_mutable_receiver() {
+        let mut interner = StringInterner::new();
+        let point = interner.intern("Point");
+        let p = interner.intern("p");
+        let x = interner.intern("x");
+        let shift = interner.intern("shift");
+        let norm = interner.intern("norm");
+        let mut ctx = Context::new(&interner);
+        register_point(&mut ctx, point, x, shift, norm);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+        let point_ty = Ty::Struct { name: point, type_args: vec![] };
+        ctx.env.bind(p, crate::context::Scheme::mono(point_ty.clone()));
+
+        let receiver = Expr::Identifier(p);
+        let call = |method| Expr::MethodCall {
+            receiver: &receiver,
+            method,
+            args: vec![],
+            span,
+        };
+
+        // Non-mutating methods are allowed on immutable bindings
+        assert!(super::super::expr::synth(&mut ctx, &call(norm)).is_ok());
+
+        let result = super::super::expr::synth(&mut ctx, &call(shift));
+        assert!(matches!(result, Err(TypeError::AssignToImmutable { .. })));
+
+        ctx.env.bind_mut(p, crate::context::Scheme::mono(point_ty), true);
+        assert!(super::super::expr::synth(&mut ctx, &call(shift)).is_ok());
+    }
+
+    #[test]
+    fn test_let_generalizes_initializer_vars() {
+        let mut interner = StringInterner::new();
+        let xs = interner.intern("xs");
+        let outer = interner.intern("outer");
+        let mut ctx = Context::new(&interner);
+
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+
+        // `let xs = []` has type Array<T> for any T
+        let empty = Expr::Array { elements: vec![], span };
+        let stmt = Stmt::Let {
+            name: xs,
+            type_annotation: None,
+            init: Some(&empty),
+            span,
+        };
+        check_stmt(&mut ctx, &stmt).unwrap();
+        assert_eq!(ctx.env.lookup(xs).unwrap().vars.len(), 1);
+
+        // A variable that is already in the environment is not generalized
+        let var = ctx.fresh_var();
+        ctx.env.bind(outer, crate::context::Scheme::mono(Ty::TypeVar(var)));
+        let stmt = Stmt::Let {
+            name: xs,
+            type_annotation: None,
+            init: Some(&Expr::Identifier(outer)),
+            span,
+        };
+        check_stmt(&mut ctx, &stmt).unwrap();
+        assert!(ctx.env.lookup(xs).unwrap().vars.is_empty());
+    }
 }
@@ -3,11 +3,10 @@
 //! This module converts AST type annotations to the internal `Ty` representation,
 //! enabling proper type checking of annotated signatures and fields.
 
-use crate::error::{Result, TypeError};
+use crate::error::Result;
 use crate::infer::Context;
 use crate::types::{PrimTy, Ty};
 use oxidex_syntax::ast::ty::Type;
-use oxidex_syntax::Span;
 
 /// Convert an AST type annotation to a `Ty`.
 ///
@@ -15,7 +14,7 @@ use oxidex_syntax::Span;
 /// ensures the type annotation is well-formed.
 pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
     match ast_ty {
-        // Simple type identifier: `Int`, `String`, `MyType`, `T`
+        // Simple type identifier: `Int`, `String`, `Range`, `MyType`, `T`
         Type::Simple { name, span: _ } => {
             let name_str = ctx.interner.resolve(*name).unwrap_or("");
 
@@ -23,6 +22,9 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
             if name_str == "Never" {
                 return Ok(Ty::Never);
             }
+            if name_str == "Range" {
+                return Ok(Ty::Range);
+            }
 
             // Check if this is a generic parameter in scope
             if let Some(type_var) = ctx.lookup_generic_param(*name) {
@@ -34,17 +36,13 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
                 return Ok(Ty::Primitive(prim));
             }
 
-            // Look up type in environment
-            // TODO: Implement proper type lookup
-            // For now, create a struct type reference
-            Ok(Ty::Struct {
-                name: *name,
-                type_args: vec![],
-            })
+            // User-defined types; names not declared as enums or classes
+            // are taken to be structs
+            Ok(ctx.types.nominal(*name, vec![]))
         }
 
         // Generic type: `List<T>`, `Map<K, V>`
-        Type::Generic { name, params, span } => {
+        Type::Generic { name, params, span: _ } => {
             let name_str = ctx.interner.resolve(*name).unwrap_or("");
 
             // Convert type parameters
@@ -55,33 +53,25 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
 
             // Check for special generic types
             match name_str {
-                "Array" | "List" => {
-                    if ty_params.len() == 1 {
-                        return Ok(Ty::Array(Box::new(ty_params.into_iter().next().unwrap())));
-                    }
+                "Array" | "List" if ty_params.len() == 1 => {
+                    return Ok(Ty::Array(Box::new(ty_params.into_iter().next().unwrap())));
                 }
-                "Dict" | "Map" => {
-                    if ty_params.len() == 2 {
-                        let mut iter = ty_params.into_iter();
-                        return Ok(Ty::Dict {
-                            key: Box::new(iter.next().unwrap()),
-                            value: Box::new(iter.next().unwrap()),
-                        });
-                    }
+                "Dict" | "Map" if ty_params.len() == 2 => {
+                    let mut iter = ty_params.into_iter();
+                    return Ok(Ty::Dict {
+                        key: Box::new(iter.next().unwrap()),
+                        value: Box::new(iter.next().unwrap()),
+                    });
                 }
-                "Option" | "Optional" => {
-                    if ty_params.len() == 1 {
-                        return Ok(Ty::Optional(Box::new(ty_params.into_iter().next().unwrap())));
-                    }
+                "Option" | "Optional" if ty_params.len() == 1 => {
+                    return Ok(Ty::Optional(Box::new(ty_params.into_iter().next().unwrap())));
                 }
-                "Result" => {
-                    if ty_params.len() == 2 {
-                        let mut iter = ty_params.into_iter();
-                        return Ok(Ty::Result {
-                            ok: Box::new(iter.next().unwrap()),
-                            error: Box::new(iter.next().unwrap()),
-                        });
-                    }
+                "Result" if ty_params.len() == 2 => {
+                    let mut iter = ty_params.into_iter();
+                    return Ok(Ty::Result {
+                        ok: Box::new(iter.next().unwrap()),
+                        error: Box::new(iter.next().unwrap()),
+                    });
                 }
                 _ => {
                     // User-defined generic type
@@ -89,11 +79,7 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
                 }
             }
 
-            // Default to struct with type args
-            Ok(Ty::Struct {
-                name: *name,
-                type_args: ty_params,
-            })
+            Ok(ctx.types.nominal(*name, ty_params))
         }
 
         // Tuple type: `(T1, T2, T3)`
@@ -129,9 +115,13 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
         // Array type: `[T]` or `[T; N]`
         Type::Array {
             element,
-            size: _,
-            span: _,
+            size,
+            span,
         } => {
+            // Fixed sizes must be non-negative integer constants
+            if let Some(size) = size {
+                super::consteval::eval_array_size(ctx, *size, *span)?;
+            }
             let ty_elem = ast_to_ty(ctx, element)?;
             Ok(Ty::Array(Box::new(ty_elem)))
         }
@@ -153,12 +143,16 @@ pub fn ast_to_ty<'ctx>(ctx: &mut Context<'ctx>, ast_ty: &Type) -> Result<Ty> {
         }
 
         // Self type: `Self`
-        Type::SelfType { span: _ } => Ok(Ty::SelfType),
+        // Inside a type's methods `Self` is that type; protocols keep it abstract
+        Type::SelfType { span: _ } => Ok(ctx
+            .current_self
+            .as_ref()
+            .map_or(Ty::SelfType, |current| current.ty.clone())),
     }
 }
 
 /// Resolve a primitive type name to a `PrimTy`.
-fn resolve_primitive(name: &str) -> Option<PrimTy> {
+pub fn resolve_primitive(name: &str) -> Option<PrimTy> {
     match name {
         "Int8" => Some(PrimTy::Int8),
         "Int16" => Some(PrimTy::Int16),
@@ -187,6 +181,7 @@ fn resolve_primitive(name: &str) -> Option<PrimTy> {
 mod tests {
     use super::*;
     use oxidex_mem::StringInterner;
+    use oxidex_syntax::Span;
 
     #[test]
     fn test_ast_to_ty_primitive() {
@@ -0,0 +1,539 @@
+//! Variance inference and subtyping of generic types.
+//!
+//! Class inheritance makes `Dog` a subtype of `Animal`. Whether that carries
+//! over to a generic type such as `Box<Dog>` depends on how `Box` uses its
+//! parameter, which is inferred from the field types:
+//!
+//! ```ignore
+//! struct Pair<T> { first: T }        // covariant: fields of values are read-only
+//! class Cell<T> { value: T }         // invariant: class fields can be written
+//! struct Sink<T> { put: (T) -> () }  // contravariant: T is only consumed
+//! ```
+//!
+//! Variances are computed as a fixpoint over every generic struct, enum, and
+//! class, so a type that wraps another generic type inherits its variance.
+//! Built-in containers (arrays, optionals, results) are covariant, dictionary
+//! keys are invariant, and generic types that are not declared in the program
+//! are conservatively invariant.
+
+use crate::context::{ParamVariance, Variance};
+use crate::error::{Result, TypeError};
+use crate::infer::Context;
+use crate::types::Ty;
+use oxidex_mem::Symbol;
+use oxidex_syntax::Span;
+use oxidex_syntax::ast::decl::{Decl, EnumVariant};
+use oxidex_syntax::ast::ty::Type;
+use std::collections::HashMap;
+
+/// Infer the variance of every generic parameter and record it in the registry.
+pub fn infer_variances<'ctx>(ctx: &mut Context<'ctx>, decls: &[Decl<'ctx>]) {
+    let types: Vec<GenericType<'_>> = decls.iter().filter_map(GenericType::new).collect();
+
+    let mut table: HashMap<Symbol, Vec<ParamVariance>> = types
+        .iter()
+        .map(|t| {
+            let params = t
+                .generics
+                .iter()
+                .map(|&param| ParamVariance {
+                    param,
+                    variance: Variance::Bivariant,
+                    field: None,
+                })
+                .collect();
+            (t.name, params)
+        })
+        .collect();
+
+    // Variances only ever grow towards invariant, so this terminates
+    loop {
+        let mut changed = false;
+        for t in &types {
+            for (index, &param) in t.generics.iter().enumerate() {
+                let mut inferred = ParamVariance {
+                    param,
+                    variance: Variance::Bivariant,
+                    field: None,
+                };
+                for field in &t.fields {
+                    let occurrence = occurrence(ctx, &table, field.ty, param, field.position);
+                    let joined = inferred.variance.join(occurrence);
+                    if joined != inferred.variance {
+                        inferred.variance = joined;
+                        inferred.field = Some((field.name, field.span));
+                    }
+                }
+
+                let slot = &mut table.get_mut(&t.name).expect("every generic type has an entry")[index];
+                if *slot != inferred {
+                    *slot = inferred;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (name, variances) in table {
+        ctx.types.register_variances(name, variances);
+    }
+}
+
+/// Check that `sub` can be used where `sup` is expected.
+///
+/// Nominal types are related through the superclass chain and their generic
+/// arguments are compared according to the inferred variances. Everything
+/// else falls back to unification.
+///
+/// # Errors
+///
+/// Returns `VarianceViolation` when the arguments of a generic type are
+/// related by subtyping, but in a direction the parameter's variance
+/// forbids. Other failures are reported as by `unify`.
+pub fn subsume<'ctx>(ctx: &mut Context<'ctx>, sub: &Ty, sup: &Ty, span: Span) -> Result<()> {
+    let sub = ctx.subst().apply_ty(sub);
+    let sup = ctx.subst().apply_ty(sup);
+
+    if let (Some((a, a_args)), Some((b, b_args))) = (nominal(&sub), nominal(&sup)) {
+        if a == b && a_args.len() == b_args.len() {
+            return subsume_args(ctx, a, a_args, b_args, (&sub, &sup), span);
+        }
+        // Superclasses are never instantiated with arguments
+        if a != b && b_args.is_empty() && is_subclass(ctx, a, b) {
+            return Ok(());
+        }
+        return ctx.unify(&sub, &sup, span);
+    }
+
+    match (&sub, &sup) {
+        (Ty::Array(a), Ty::Array(b)) | (Ty::Optional(a), Ty::Optional(b)) => {
+            subsume(ctx, a, b, span)
+        }
+        (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => {
+            for (a, b) in a.iter().zip(b) {
+                subsume(ctx, a, b, span)?;
+            }
+            Ok(())
+        }
+        (Ty::Dict { key: k1, value: v1 }, Ty::Dict { key: k2, value: v2 }) => {
+            ctx.unify(k1, k2, span)?;
+            subsume(ctx, v1, v2, span)
+        }
+        (Ty::Result { ok: o1, error: e1 }, Ty::Result { ok: o2, error: e2 }) => {
+            subsume(ctx, o1, o2, span)?;
+            subsume(ctx, e1, e2, span)
+        }
+        (
+            Ty::Function {
+                params: p1,
+                return_type: r1,
+                ..
+            },
+            Ty::Function {
+                params: p2,
+                return_type: r2,
+                ..
+            },
+        ) if p1.len() == p2.len() => {
+            for (a, b) in p1.iter().zip(p2) {
+                subsume(ctx, b, a, span)?;
+            }
+            subsume(ctx, r1, r2, span)
+        }
+        _ => ctx.unify(&sub, &sup, span),
+    }
+}
+
+/// Compare the arguments of two instantiations of the same generic type.
+fn subsume_args<'ctx>(
+    ctx: &mut Context<'ctx>,
+    name: Symbol,
+    sub_args: &[Ty],
+    sup_args: &[Ty],
+    (sub, sup): (&Ty, &Ty),
+    span: Span,
+) -> Result<()> {
+    let variances = match ctx.types.lookup_variances(name) {
+        Some(variances) if variances.len() == sub_args.len() => variances.to_vec(),
+        _ => return ctx.unify(sub, sup, span),
+    };
+
+    for ((a, b), info) in sub_args.iter().zip(sup_args).zip(&variances) {
+        let result = match info.variance {
+            Variance::Bivariant => Ok(()),
+            Variance::Covariant => subsume(ctx, a, b, span),
+            Variance::Contravariant => subsume(ctx, b, a, span),
+            Variance::Invariant => ctx.unify(a, b, span),
+        };
+
+        let Err(err) = result else { continue };
+
+        // Blame the variance only if the arguments are related in the
+        // direction a covariant parameter would have accepted
+        let blame = match info.variance {
+            Variance::Contravariant => trial(ctx, a, b, span),
+            Variance::Invariant => trial(ctx, a, b, span) || trial(ctx, b, a, span),
+            _ => false,
+        };
+        if !blame {
+            return Err(err);
+        }
+
+        let resolve = |sym: Symbol| ctx.interner.resolve(sym).unwrap_or("").to_string();
+        return Err(TypeError::VarianceViolation {
+            expected: sup.display(ctx.interner).to_string(),
+            found: sub.display(ctx.interner).to_string(),
+            param: resolve(info.param),
+            variance: info.variance.to_string(),
+            field: info.field.map(|(field, at)| (resolve(field), at)),
+            span,
+        });
+    }
+
+    Ok(())
+}
+
+/// Check whether `sub` subsumes `sup` without keeping any bindings.
+fn trial<'ctx>(ctx: &mut Context<'ctx>, sub: &Ty, sup: &Ty, span: Span) -> bool {
+    let snapshot = ctx.subst().clone();
+    let ok = subsume(ctx, sub, sup, span).is_ok();
+    *ctx.subst() = snapshot;
+    ok
+}
+
+/// Get the name and arguments of a nominal type.
+///
+/// Annotations name classes through `Ty::Struct`, so both are accepted.
+fn nominal(ty: &Ty) -> Option<(Symbol, &[Ty])> {
+    match ty {
+        Ty::Struct { name, type_args } | Ty::Class { name, type_args } | Ty::Enum { name, type_args } => {
+            Some((*name, type_args))
+        }
+        _ => None,
+    }
+}
+
+/// Check whether class `sub` inherits from `sup`, directly or indirectly.
+fn is_subclass(ctx: &Context<'_>, sub: Symbol, sup: Symbol) -> bool {
+    let mut current = sub;
+    let mut seen = vec![current];
+    while let Some(parent) = ctx.types.lookup_class(current).and_then(|c| c.superclass) {
+        if parent == sup {
+            return true;
+        }
+        // Inheritance cycles are reported elsewhere
+        if seen.contains(&parent) {
+            return false;
+        }
+        seen.push(parent);
+        current = parent;
+    }
+    false
+}
+
+/// Compute the variance of the occurrences of `param` in a type annotation
+/// that appears in a position of variance `position`.
+fn occurrence(
+    ctx: &Context<'_>,
+    table: &HashMap<Symbol, Vec<ParamVariance>>,
+    ty: &Type,
+    param: Symbol,
+    position: Variance,
+) -> Variance {
+    let nested = |ty: &Type, position: Variance| occurrence(ctx, table, ty, param, position);
+
+    match ty {
+        Type::Simple { name, .. } if *name == param => position,
+        Type::Simple { .. } | Type::SelfType { .. } => Variance::Bivariant,
+
+        Type::Generic { name, params, .. } => {
+            let variances: Vec<Variance> = match table.get(name) {
+                Some(declared) => declared.iter().map(|p| p.variance).collect(),
+                None => builtin_variances(ctx, *name, params.len()),
+            };
+            params
+                .iter()
+                .zip(variances)
+                .fold(Variance::Bivariant, |acc, (p, v)| {
+                    acc.join(nested(p, position.compose(v)))
+                })
+        }
+
+        Type::Tuple { elements, .. } => elements
+            .iter()
+            .fold(Variance::Bivariant, |acc, e| acc.join(nested(e, position))),
+
+        Type::Function {
+            params,
+            return_type,
+            ..
+        } => params
+            .iter()
+            .fold(nested(return_type, position), |acc, p| {
+                acc.join(nested(p, position.flip()))
+            }),
+
+        Type::Array { element, .. } => nested(element, position),
+        Type::Optional { inner, .. } => nested(inner, position),
+        Type::Dict { key, value, .. } => nested(key, position.compose(Variance::Invariant))
+            .join(nested(value, position)),
+    }
+}
+
+/// Get the variances of a generic type that is not declared in the program.
+fn builtin_variances(ctx: &Context<'_>, name: Symbol, arity: usize) -> Vec<Variance> {
+    match ctx.interner.resolve(name).unwrap_or("") {
+        "Array" | "List" | "Option" | "Optional" | "Box" | "Result" => {
+            vec![Variance::Covariant; arity]
+        }
+        "Dict" | "Map" => vec![Variance::Invariant, Variance::Covariant],
+        _ => vec![Variance::Invariant; arity],
+    }
+}
+
+/// A generic struct, enum, or class, flattened to its field types.
+struct GenericType<'a> {
+    name: Symbol,
+    generics: &'a [Symbol],
+    fields: Vec<Field<'a>>,
+}
+
+/// A field type and the variance of the position it is stored in.
+struct Field<'a> {
+    /// Field name, or the variant name for tuple variant payloads
+    name: Symbol,
+    span: Span,
+    ty: &'a Type,
+    position: Variance,
+}
+
+impl<'a> GenericType<'a> {
+    /// Flatten a declaration, if it is a generic type.
+    fn new(decl: &'a Decl<'_>) -> Option<Self> {
+        let (name, generics, fields) = match decl {
+            Decl::Struct {
+                name,
+                generics,
+                fields,
+                ..
+            } => (*name, generics, Self::fields(fields, Variance::Covariant)),
+            // Class fields can be assigned through any reference
+            Decl::Class {
+                name,
+                generics,
+                fields,
+                ..
+            } => (*name, generics, Self::fields(fields, Variance::Invariant)),
+            Decl::Enum {
+                name,
+                generics,
+                variants,
+                ..
+            } => {
+                let fields = variants
+                    .iter()
+                    .flat_map(|variant| match variant {
+                        EnumVariant::Unit { .. } => Vec::new(),
+                        EnumVariant::Tuple { name, fields, span } => fields
+                            .iter()
+                            .map(|ty| Field {
+                                name: *name,
+                                span: *span,
+                                ty,
+                                position: Variance::Covariant,
+                            })
+                            .collect(),
+                        EnumVariant::Struct { fields, .. } => {
+                            Self::fields(fields, Variance::Covariant)
+                        }
+                    })
+                    .collect();
+                (*name, generics, fields)
+            }
+            _ => return None,
+        };
+
+        if generics.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name,
+            generics,
+            fields,
+        })
+    }
+
+    fn fields(
+        fields: &'a [oxidex_syntax::ast::decl::StructField],
+        position: Variance,
+    ) -> Vec<Field<'a>> {
+        fields
+            .iter()
+            .map(|f| Field {
+                name: f.name,
+                span: f.span,
+                ty: &f.type_annotation,
+                position,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::decl::{check_decl, collect_signatures};
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::ast::decl::{StructField, Visibility};
+
+    fn span(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    fn simple(name: Symbol) -> Type {
+        Type::Simple { name, span: span(0) }
+    }
+
+    fn field(name: Symbol, type_annotation: Type, line: usize) -> StructField {
+        StructField {
+            name,
+            type_annotation,
+            span: span(line),
+        }
+    }
+
+    fn class(name: Symbol, generics: Vec<Symbol>, superclass: Option<Symbol>, fields: Vec<StructField>) -> Decl<'static> {
+        Decl::Class {
+            name,
+            generics,
+            superclass: superclass.map(|s| vec![s]),
+            fields,
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span: span(0),
+        }
+    }
+
+    fn structure(name: Symbol, generics: Vec<Symbol>, fields: Vec<StructField>) -> Decl<'static> {
+        Decl::Struct {
+            name,
+            generics,
+            fields,
+            protocols: vec![],
+            visibility: Visibility::Private,
+            span: span(0),
+        }
+    }
+
+    fn variance_of(ctx: &Context<'_>, name: Symbol) -> Variance {
+        ctx.types.lookup_variances(name).unwrap()[0].variance
+    }
+
+    #[test]
+    fn test_variance_inference() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Pair", "Cell", "Sink", "Wrap", "Ghost", "T", "value", "Array"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [pair, cell, sink, wrap, ghost, t, value, array] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let consumer = Type::Function {
+            params: vec![simple(t)],
+            return_type: Box::new(Type::Tuple {
+                elements: vec![],
+                span: span(0),
+            }),
+            span: span(0),
+        };
+        let wrapped = Type::Generic {
+            name: sink,
+            params: vec![Type::Generic {
+                name: array,
+                params: vec![simple(t)],
+                span: span(0),
+            }],
+            span: span(0),
+        };
+        let decls = vec![
+            structure(pair, vec![t], vec![field(value, simple(t), 1)]),
+            class(cell, vec![t], None, vec![field(value, simple(t), 2)]),
+            structure(sink, vec![t], vec![field(value, consumer, 3)]),
+            // Declared before Sink is resolved, so this needs the fixpoint
+            structure(wrap, vec![t], vec![field(value, wrapped, 4)]),
+            structure(ghost, vec![t], vec![]),
+        ];
+        infer_variances(&mut ctx, &decls);
+
+        assert_eq!(variance_of(&ctx, pair), Variance::Covariant);
+        assert_eq!(variance_of(&ctx, cell), Variance::Invariant);
+        assert_eq!(variance_of(&ctx, sink), Variance::Contravariant);
+        assert_eq!(variance_of(&ctx, wrap), Variance::Contravariant);
+        assert_eq!(variance_of(&ctx, ghost), Variance::Bivariant);
+        assert_eq!(
+            ctx.types.lookup_variances(cell).unwrap()[0].field,
+            Some((value, span(2)))
+        );
+    }
+
+    #[test]
+    fn test_generic_class_subtyping_follows_variance() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Animal", "Dog", "Cell", "Pair", "T", "value"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [animal, dog, cell, pair, t, value] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+
+        let decls = vec![
+            class(animal, vec![], None, vec![]),
+            class(dog, vec![], Some(animal), vec![]),
+            class(cell, vec![t], None, vec![field(value, simple(t), 3)]),
+            structure(pair, vec![t], vec![field(value, simple(t), 4)]),
+        ];
+        collect_signatures(&mut ctx, &decls).unwrap();
+        for decl in &decls {
+            check_decl(&mut ctx, decl).unwrap();
+        }
+
+        let class_ty = |name| Ty::Class {
+            name,
+            type_args: vec![],
+        };
+        let apply = |name, arg: Symbol| Ty::Class {
+            name,
+            type_args: vec![class_ty(arg)],
+        };
+
+        // Dog <: Animal, and covariant Pair<Dog> <: Pair<Animal>
+        assert!(subsume(&mut ctx, &class_ty(dog), &class_ty(animal), span(9)).is_ok());
+        assert!(subsume(&mut ctx, &class_ty(animal), &class_ty(dog), span(9)).is_err());
+        assert!(subsume(&mut ctx, &apply(pair, dog), &apply(pair, animal), span(9)).is_ok());
+
+        // Cell<T> is invariant because its field can be written
+        let err = subsume(&mut ctx, &apply(cell, dog), &apply(cell, animal), span(9)).unwrap_err();
+        match &err {
+            TypeError::VarianceViolation {
+                param,
+                variance,
+                field,
+                ..
+            } => {
+                assert_eq!(param, "T");
+                assert_eq!(variance, "invariant");
+                assert_eq!(field, &Some(("value".to_string(), span(3))));
+            }
+            other => panic!("Expected VarianceViolation, got {:?}", other),
+        }
+        assert_eq!(err.notes()[0].1, span(3));
+    }
+}
@@ -33,6 +33,7 @@
 use crate::context::subst::Subst;
 use crate::types::Ty;
 use oxidex_mem::Symbol;
+use oxidex_syntax::Span;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
@@ -121,25 +122,27 @@ impl Scheme {
         let mapping: HashMap<u32, u32> =
             self.vars.iter().map(|&v| (v, subst.fresh_var())).collect();
 
-        self.instantiate_with_mapping(&mapping, subst)
+        self.instantiate_with_mapping(&mapping)
+    }
+
+    /// Instantiate this scheme, also returning the fresh type variables.
+    ///
+    /// The variables are returned in the order of `vars`, so callers can
+    /// relate them to the generic parameters they stand for.
+    pub fn instantiate_vars(&self, subst: &mut Subst) -> (Ty, Vec<Ty>) {
+        let fresh: Vec<u32> = self.vars.iter().map(|_| subst.fresh_var()).collect();
+        let mapping: HashMap<u32, u32> = self.vars.iter().copied().zip(fresh.iter().copied()).collect();
+        let ty = self.instantiate_with_mapping(&mapping);
+        (ty, fresh.into_iter().map(Ty::TypeVar).collect())
     }
 
     /// Instantiate with a specific mapping (for testing).
-    fn instantiate_with_mapping(
-        &self,
-        mapping: &HashMap<u32, u32>,
-        subst: &mut Subst,
-    ) -> Ty {
-        self.replace_vars(&self.ty, mapping, subst)
+    fn instantiate_with_mapping(&self, mapping: &HashMap<u32, u32>) -> Ty {
+        self.replace_vars(&self.ty, mapping)
     }
 
     /// Replace type variables according to a mapping.
-    fn replace_vars(
-        &self,
-        ty: &Ty,
-        mapping: &HashMap<u32, u32>,
-        subst: &mut Subst,
-    ) -> Ty {
+    fn replace_vars(&self, ty: &Ty, mapping: &HashMap<u32, u32>) -> Ty {
         match ty {
             Ty::TypeVar(v) => {
                 if let Some(&new_var) = mapping.get(v) {
@@ -153,7 +156,7 @@ impl Scheme {
                 name: *name,
                 type_args: type_args
                     .iter()
-                    .map(|t| self.replace_vars(t, mapping, subst))
+                    .map(|t| self.replace_vars(t, mapping))
                     .collect(),
             },
 
@@ -161,7 +164,7 @@ impl Scheme {
                 name: *name,
                 type_args: type_args
                     .iter()
-                    .map(|t| self.replace_vars(t, mapping, subst))
+                    .map(|t| self.replace_vars(t, mapping))
                     .collect(),
             },
 
@@ -169,7 +172,7 @@ impl Scheme {
                 name: *name,
                 type_args: type_args
                     .iter()
-                    .map(|t| self.replace_vars(t, mapping, subst))
+                    .map(|t| self.replace_vars(t, mapping))
                     .collect(),
             },
 
@@ -177,14 +180,14 @@ impl Scheme {
                 name: *name,
                 type_args: type_args
                     .iter()
-                    .map(|t| self.replace_vars(t, mapping, subst))
+                    .map(|t| self.replace_vars(t, mapping))
                     .collect(),
             },
 
             Ty::Tuple(types) => Ty::Tuple(
                 types
                     .iter()
-                    .map(|t| self.replace_vars(t, mapping, subst))
+                    .map(|t| self.replace_vars(t, mapping))
                     .collect(),
             ),
 
@@ -195,36 +198,32 @@ impl Scheme {
             } => Ty::Function {
                 params: params
                     .iter()
-                    .map(|p| self.replace_vars(p, mapping, subst))
+                    .map(|p| self.replace_vars(p, mapping))
                     .collect(),
-                return_type: Box::new(self.replace_vars(
-                    return_type,
-                    mapping,
-                    subst,
-                )),
+                return_type: Box::new(self.replace_vars(return_type, mapping)),
                 labels: labels.clone(),
             },
 
             Ty::Array(inner) => {
-                Ty::Array(Box::new(self.replace_vars(inner, mapping, subst)))
+                Ty::Array(Box::new(self.replace_vars(inner, mapping)))
             }
 
             Ty::Dict { key, value } => Ty::Dict {
-                key: Box::new(self.replace_vars(key, mapping, subst)),
-                value: Box::new(self.replace_vars(value, mapping, subst)),
+                key: Box::new(self.replace_vars(key, mapping)),
+                value: Box::new(self.replace_vars(value, mapping)),
             },
 
             Ty::Optional(inner) => {
-                Ty::Optional(Box::new(self.replace_vars(inner, mapping, subst)))
+                Ty::Optional(Box::new(self.replace_vars(inner, mapping)))
             }
 
             Ty::Result { ok, error } => Ty::Result {
-                ok: Box::new(self.replace_vars(ok, mapping, subst)),
-                error: Box::new(self.replace_vars(error, mapping, subst)),
+                ok: Box::new(self.replace_vars(ok, mapping)),
+                error: Box::new(self.replace_vars(error, mapping)),
             },
 
             // These types don't contain other types
-            Ty::Primitive(_) | Ty::SelfType | Ty::Never | Ty::Error => {
+            Ty::Primitive(_) | Ty::Range | Ty::SelfType | Ty::Never | Ty::Error => {
                 ty.clone()
             }
         }
@@ -265,6 +264,14 @@ pub struct TypeEnv {
     /// Each scope tracks which symbols are mutable.
     mutable: Vec<HashMap<Symbol, bool>>,
 
+    /// Stack of overload sets (parallel to scopes).
+    /// Each scope maps a function name to all signatures declared under it.
+    overloads: Vec<HashMap<Symbol, Vec<Scheme>>>,
+
+    /// Stack of declaration sites (parallel to scopes).
+    /// Used to point diagnostics at the binding a name refers to.
+    decl_spans: Vec<HashMap<Symbol, Span>>,
+
     /// Substitution accumulated during type checking.
     pub subst: Subst,
 
@@ -285,6 +292,8 @@ impl TypeEnv {
         Self {
             scopes: vec![HashMap::new()],
             mutable: vec![HashMap::new()],
+            overloads: vec![HashMap::new()],
+            decl_spans: vec![HashMap::new()],
             subst: Subst::new(),
             level: 0,
         }
@@ -314,6 +323,8 @@ impl TypeEnv {
     pub fn new_scope(&mut self) {
         self.scopes.push(HashMap::new());
         self.mutable.push(HashMap::new());
+        self.overloads.push(HashMap::new());
+        self.decl_spans.push(HashMap::new());
     }
 
     /// Exit the current scope.
@@ -332,6 +343,8 @@ impl TypeEnv {
         if self.scopes.len() > 1 {
             self.scopes.pop();
             self.mutable.pop();
+            self.overloads.pop();
+            self.decl_spans.pop();
         }
     }
 
@@ -360,6 +373,55 @@ impl TypeEnv {
         }
     }
 
+    /// Bind a symbol as one overload of a function.
+    ///
+    /// Unlike [`TypeEnv::bind`], earlier signatures bound under the same name
+    /// in the same scope are kept, so that calls can pick among them. The
+    /// plain binding is also updated, so `lookup` keeps working for names
+    /// with a single signature.
+    pub fn bind_overload(&mut self, sym: Symbol, scheme: Scheme) {
+        if let Some(overloads) = self.overloads.last_mut() {
+            overloads.entry(sym).or_default().push(scheme.clone());
+        }
+        self.bind(sym, scheme);
+    }
+
+    /// Look up every overload bound under a symbol.
+    ///
+    /// Returns the overload set from the innermost scope that declares the
+    /// name, or an empty slice if the name was never bound as an overload.
+    pub fn lookup_overloads(&self, sym: Symbol) -> &[Scheme] {
+        for (scope, overloads) in self.scopes.iter().zip(&self.overloads).rev() {
+            if let Some(set) = overloads.get(&sym) {
+                return set;
+            }
+            if scope.contains_key(&sym) {
+                // Shadowed by an ordinary binding
+                break;
+            }
+        }
+        &[]
+    }
+
+    /// Record where a symbol was declared in the current scope.
+    pub fn declare(&mut self, sym: Symbol, span: Span) {
+        if let Some(decl_spans) = self.decl_spans.last_mut() {
+            decl_spans.insert(sym, span);
+        }
+    }
+
+    /// Look up where a symbol was declared.
+    ///
+    /// Returns `None` if the binding was introduced without a recorded site.
+    pub fn decl_span(&self, sym: Symbol) -> Option<Span> {
+        for (scope, decl_spans) in self.scopes.iter().zip(&self.decl_spans).rev() {
+            if scope.contains_key(&sym) {
+                return decl_spans.get(&sym).copied();
+            }
+        }
+        None
+    }
+
     /// Check if a symbol is mutable.
     ///
     /// Returns true if the symbol exists and is mutable, false otherwise.
@@ -400,6 +462,29 @@ impl TypeEnv {
         None
     }
 
+    /// Get the index of the innermost scope binding a symbol, the global
+    /// scope being 0.
+    pub fn scope_of(&self, sym: Symbol) -> Option<usize> {
+        self.scopes.iter().rposition(|scope| scope.contains_key(&sym))
+    }
+
+    /// Get every binding visible from the current scope.
+    ///
+    /// Shadowed bindings are omitted, so each name appears once with its
+    /// innermost scheme.
+    pub fn visible_bindings(&self) -> Vec<(Symbol, Scheme)> {
+        let mut seen = HashSet::new();
+        let mut bindings = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            for (sym, scheme) in scope {
+                if seen.insert(*sym) {
+                    bindings.push((*sym, scheme.clone()));
+                }
+            }
+        }
+        bindings
+    }
+
     /// Look up a mutable reference to a symbol.
     pub fn lookup_mut(&mut self, sym: Symbol) -> Option<&mut Scheme> {
         for scope in self.scopes.iter_mut().rev() {
@@ -412,6 +497,11 @@ impl TypeEnv {
 
     /// Generalize a type over free variables not in the environment.
     ///
+    /// This scans every binding in every scope, so it is linear in the size of
+    /// the environment. The checker generalizes with
+    /// [`Context::generalize`](crate::infer::Context::generalize) instead, which
+    /// uses variable levels; this remains for standalone use of `TypeEnv`.
+    ///
     /// This is the heart of let-polymorphism:
     ///
     /// ```ignore
@@ -425,8 +515,10 @@ impl TypeEnv {
         let free_in_env = self.free_vars();
 
         // Generalize over variables free in ty but not in env
-        let vars: Vec<u32> =
+        // Sorted, as sets iterate in an order that varies from run to run
+        let mut vars: Vec<u32> =
             free_in_ty.difference(&free_in_env).copied().collect();
+        vars.sort_unstable();
 
         Scheme {
             vars,
@@ -556,6 +648,55 @@ mod tests {
         assert!(matches!(scheme.ty, Ty::Primitive(PrimTy::Int64)));
     }
 
+    #[test]
+    fn test_env_decl_span() {
+        let mut env = TypeEnv::new();
+        let sym = Symbol::new(0);
+        let outer = Span::new(0, 1, 1, 1, 1, 2);
+        let inner = Span::new(10, 11, 2, 1, 2, 2);
+
+        env.bind(sym, Scheme::mono(Ty::Primitive(PrimTy::Int64)));
+        env.declare(sym, outer);
+        assert_eq!(env.decl_span(sym), Some(outer));
+
+        env.new_scope();
+        env.bind(sym, Scheme::mono(Ty::Primitive(PrimTy::Bool)));
+        env.declare(sym, inner);
+        assert_eq!(env.decl_span(sym), Some(inner));
+
+        env.pop_scope();
+        assert_eq!(env.decl_span(sym), Some(outer));
+    }
+
+    #[test]
+    fn test_env_overloads() {
+        let mut env = TypeEnv::new();
+        let sym = Symbol::new(0);
+
+        let int_fn = Ty::Function {
+            params: vec![Ty::Primitive(PrimTy::Int64)],
+            return_type: Box::new(Ty::Primitive(PrimTy::Int64)),
+            labels: vec![None],
+        };
+        let bool_fn = Ty::Function {
+            params: vec![Ty::Primitive(PrimTy::Bool)],
+            return_type: Box::new(Ty::Primitive(PrimTy::Bool)),
+            labels: vec![None],
+        };
+
+        env.bind_overload(sym, Scheme::mono(int_fn));
+        env.bind_overload(sym, Scheme::mono(bool_fn));
+        assert_eq!(env.lookup_overloads(sym).len(), 2);
+
+        // An ordinary binding in an inner scope shadows the overload set
+        env.new_scope();
+        env.bind(sym, Scheme::mono(Ty::Primitive(PrimTy::Int64)));
+        assert!(env.lookup_overloads(sym).is_empty());
+
+        env.pop_scope();
+        assert_eq!(env.lookup_overloads(sym).len(), 2);
+    }
+
     #[test]
     fn test_generalize() {
         let env = TypeEnv::new();
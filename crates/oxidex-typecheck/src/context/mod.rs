@@ -11,5 +11,5 @@ pub mod registry;
 pub mod subst;
 
 pub use env::{Scheme, TypeEnv};
-pub use registry::{ClassInfo, EnumInfo, EnumVariantInfo, FieldInfo, MethodInfo, ProtocolInfo, ProtocolMethodInfo, StructInfo, TypeRegistry};
+pub use registry::{ClassInfo, EnumInfo, EnumVariantInfo, FieldInfo, MethodInfo, ParamVariance, ProtocolInfo, ProtocolMethodInfo, StructInfo, TypeRegistry, Variance};
 pub use subst::Subst;
@@ -5,7 +5,9 @@
 
 use crate::types::Ty;
 use oxidex_mem::Symbol;
-use std::collections::HashMap;
+use oxidex_syntax::Span;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 /// Information about a protocol method.
 #[derive(Debug, Clone)]
@@ -25,6 +27,8 @@ pub struct MethodInfo {
     pub name: Symbol,
     /// Parameter types
     pub params: Vec<Ty>,
+    /// Argument labels for each parameter
+    pub labels: Vec<Option<Symbol>>,
     /// Return type
     pub return_type: Ty,
     /// Is this a mutable method?
@@ -62,6 +66,8 @@ pub struct EnumVariantInfo {
     pub name: Symbol,
     /// Payload type (None for no payload, Some for typed payload)
     pub payload: Option<Ty>,
+    /// Field names of a struct variant, in payload order (empty otherwise)
+    pub fields: Vec<Symbol>,
 }
 
 /// Information about an enum definition.
@@ -75,6 +81,8 @@ pub struct EnumInfo {
     pub methods: Vec<MethodInfo>,
     /// Generic type parameters
     pub generics: Vec<Symbol>,
+    /// Type variables standing for `generics` in variant payloads
+    pub type_vars: Vec<u32>,
 }
 
 /// Information about a class definition.
@@ -92,6 +100,74 @@ pub struct ClassInfo {
     pub generics: Vec<Symbol>,
 }
 
+/// Variance of a generic parameter.
+///
+/// Describes how subtyping of the argument carries over to the generic
+/// type: with a covariant `T`, `Box<Dog>` is a subtype of `Box<Animal>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variance {
+    /// The parameter is unused; any arguments are compatible
+    Bivariant,
+    /// Subtyping is preserved (`T` only appears in read positions)
+    Covariant,
+    /// Subtyping is reversed (`T` only appears in parameter positions)
+    Contravariant,
+    /// Arguments must be equal
+    Invariant,
+}
+
+impl Variance {
+    /// Combine the variances of two occurrences of the same parameter.
+    pub fn join(self, other: Variance) -> Variance {
+        match (self, other) {
+            (Variance::Bivariant, v) | (v, Variance::Bivariant) => v,
+            (a, b) if a == b => a,
+            _ => Variance::Invariant,
+        }
+    }
+
+    /// Get the variance of an occurrence nested in a position of this variance.
+    pub fn compose(self, inner: Variance) -> Variance {
+        match (self, inner) {
+            (Variance::Bivariant, _) | (_, Variance::Bivariant) => Variance::Bivariant,
+            (Variance::Covariant, v) => v,
+            (Variance::Contravariant, v) => v.flip(),
+            (Variance::Invariant, _) => Variance::Invariant,
+        }
+    }
+
+    /// Swap covariance and contravariance.
+    pub fn flip(self) -> Variance {
+        match self {
+            Variance::Covariant => Variance::Contravariant,
+            Variance::Contravariant => Variance::Covariant,
+            v => v,
+        }
+    }
+}
+
+impl fmt::Display for Variance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variance::Bivariant => write!(f, "bivariant"),
+            Variance::Covariant => write!(f, "covariant"),
+            Variance::Contravariant => write!(f, "contravariant"),
+            Variance::Invariant => write!(f, "invariant"),
+        }
+    }
+}
+
+/// Inferred variance of one generic parameter of a type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamVariance {
+    /// Parameter name
+    pub param: Symbol,
+    /// Inferred variance
+    pub variance: Variance,
+    /// Field (or enum variant) whose use of the parameter decided the variance
+    pub field: Option<(Symbol, Span)>,
+}
+
 /// Information about a protocol definition.
 #[derive(Debug, Clone)]
 pub struct ProtocolInfo {
@@ -117,6 +193,17 @@ pub struct TypeRegistry {
 
     /// Protocol definitions
     protocols: HashMap<Symbol, ProtocolInfo>,
+
+    /// Inferred variance of generic parameters, by type name
+    variances: HashMap<Symbol, Vec<ParamVariance>>,
+
+    /// Protocols each type declares conformance to
+    conformances: HashMap<Symbol, Vec<Symbol>>,
+
+    /// Enums and classes declared by the program, known before their
+    /// definitions are registered so that signatures can name them
+    declared_enums: HashSet<Symbol>,
+    declared_classes: HashSet<Symbol>,
 }
 
 impl TypeRegistry {
@@ -127,6 +214,34 @@ impl TypeRegistry {
             enums: HashMap::new(),
             classes: HashMap::new(),
             protocols: HashMap::new(),
+            variances: HashMap::new(),
+            conformances: HashMap::new(),
+            declared_enums: HashSet::new(),
+            declared_classes: HashSet::new(),
+        }
+    }
+
+    /// Record that the program declares an enum, before its definition is
+    /// registered.
+    pub fn declare_enum(&mut self, name: Symbol) {
+        self.declared_enums.insert(name);
+    }
+
+    /// Record that the program declares a class, before its definition is
+    /// registered.
+    pub fn declare_class(&mut self, name: Symbol) {
+        self.declared_classes.insert(name);
+    }
+
+    /// The type a user-defined type name stands for: an enum or class if
+    /// one is declared under the name, otherwise a struct.
+    pub fn nominal(&self, name: Symbol, type_args: Vec<Ty>) -> Ty {
+        if self.enums.contains_key(&name) || self.declared_enums.contains(&name) {
+            Ty::Enum { name, type_args }
+        } else if self.classes.contains_key(&name) || self.declared_classes.contains(&name) {
+            Ty::Class { name, type_args }
+        } else {
+            Ty::Struct { name, type_args }
         }
     }
 
@@ -150,6 +265,94 @@ impl TypeRegistry {
         self.protocols.insert(info.name, info);
     }
 
+    /// Register a method on a struct, enum, or class.
+    ///
+    /// Methods sharing a name are kept side by side so that calls can be
+    /// resolved by overload ranking. Returns `false` if no type with the
+    /// given name is registered.
+    pub fn register_method(&mut self, type_name: Symbol, info: MethodInfo) -> bool {
+        if let Some(struct_info) = self.structs.get_mut(&type_name) {
+            struct_info.methods.push(info);
+        } else if let Some(enum_info) = self.enums.get_mut(&type_name) {
+            enum_info.methods.push(info);
+        } else if let Some(class_info) = self.classes.get_mut(&type_name) {
+            class_info.methods.push(info);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Look up all methods of a struct, enum, or class.
+    pub fn lookup_methods(&self, type_name: Symbol) -> Option<&[MethodInfo]> {
+        if let Some(struct_info) = self.structs.get(&type_name) {
+            Some(&struct_info.methods)
+        } else if let Some(enum_info) = self.enums.get(&type_name) {
+            Some(&enum_info.methods)
+        } else {
+            self.classes.get(&type_name).map(|c| c.methods.as_slice())
+        }
+    }
+
+    /// Look up the fields of a struct or class, including inherited ones.
+    pub fn lookup_fields(&self, type_name: Symbol) -> Vec<FieldInfo> {
+        if let Some(struct_info) = self.structs.get(&type_name) {
+            return struct_info.fields.clone();
+        }
+        let mut fields = Vec::new();
+        let mut visited = Vec::new();
+        let mut current = Some(type_name);
+        while let Some(name) = current {
+            // A cyclic superclass chain is reported elsewhere
+            if visited.contains(&name) {
+                break;
+            }
+            visited.push(name);
+            let Some(class_info) = self.classes.get(&name) else {
+                break;
+            };
+            fields.extend(class_info.fields.iter().cloned());
+            current = class_info.superclass;
+        }
+        fields
+    }
+
+    /// Look up the generic parameters of a struct, enum, or class.
+    pub fn lookup_generics(&self, type_name: Symbol) -> Option<&[Symbol]> {
+        if let Some(struct_info) = self.structs.get(&type_name) {
+            Some(&struct_info.generics)
+        } else if let Some(enum_info) = self.enums.get(&type_name) {
+            Some(&enum_info.generics)
+        } else {
+            self.classes.get(&type_name).map(|c| c.generics.as_slice())
+        }
+    }
+
+    /// Register the inferred variance of a type's generic parameters.
+    pub fn register_variances(&mut self, type_name: Symbol, variances: Vec<ParamVariance>) {
+        self.variances.insert(type_name, variances);
+    }
+
+    /// Look up the inferred variance of a type's generic parameters.
+    pub fn lookup_variances(&self, type_name: Symbol) -> Option<&[ParamVariance]> {
+        self.variances.get(&type_name).map(Vec::as_slice)
+    }
+
+    /// Record that a type conforms to a protocol.
+    pub fn register_conformance(&mut self, type_name: Symbol, protocol: Symbol) {
+        let protocols = self.conformances.entry(type_name).or_default();
+        if !protocols.contains(&protocol) {
+            protocols.push(protocol);
+        }
+    }
+
+    /// Check whether a type declares conformance to a protocol matching `pred`.
+    pub fn conforms_to(&self, type_name: Symbol, pred: impl Fn(Symbol) -> bool) -> bool {
+        self.conformances
+            .get(&type_name)
+            .is_some_and(|protocols| protocols.iter().any(|&p| pred(p)))
+    }
+
     /// Look up a struct definition.
     pub fn lookup_struct(&self, name: Symbol) -> Option<&StructInfo> {
         self.structs.get(&name)
@@ -211,7 +414,6 @@ mod tests {
 
     #[test]
     fn test_register_struct() {
-        let mut registry = TypeRegistry::new();
         let name = Symbol::new(0);
 
         let info = StructInfo {
@@ -245,10 +447,12 @@ mod tests {
                 EnumVariantInfo {
                     name: Symbol::new(1),
                     payload: Some(Ty::Primitive(PrimTy::Int64)),
+                    fields: vec![],
                 },
             ],
             methods: vec![],
             generics: vec![],
+            type_vars: vec![],
         };
 
         registry.register_enum(info);
@@ -257,4 +461,43 @@ mod tests {
         let lookup = registry.lookup_enum(name).unwrap();
         assert_eq!(lookup.variants.len(), 1);
     }
+
+    #[test]
+    fn test_register_overloaded_methods() {
+        let mut registry = TypeRegistry::new();
+        let name = Symbol::new(0);
+        let method = Symbol::new(1);
+
+        registry.register_struct(StructInfo {
+            name,
+            fields: vec![],
+            methods: vec![],
+            generics: vec![],
+        });
+
+        for ty in [PrimTy::Int64, PrimTy::Bool] {
+            let info = MethodInfo {
+                name: method,
+                params: vec![Ty::Primitive(ty)],
+                labels: vec![None],
+                return_type: Ty::Primitive(PrimTy::Unit),
+                is_mut: false,
+                is_static: false,
+            };
+            assert!(registry.register_method(name, info));
+        }
+
+        let methods = registry.lookup_methods(name).unwrap();
+        assert_eq!(methods.iter().filter(|m| m.name == method).count(), 2);
+
+        let orphan = MethodInfo {
+            name: method,
+            params: vec![],
+            labels: vec![],
+            return_type: Ty::Primitive(PrimTy::Unit),
+            is_mut: false,
+            is_static: false,
+        };
+        assert!(!registry.register_method(Symbol::new(2), orphan));
+    }
 }
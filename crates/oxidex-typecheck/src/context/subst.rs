@@ -8,6 +8,10 @@
 //! - **Union-Find**: Each type variable points to its parent (or None if it's a root)
 //! - **Path Compression**: When looking up a variable, we flatten the structure for O(1) future lookups
 //! - **Union by Rank**: Not implemented yet (could be added for optimization)
+//! - **Levels**: Each variable records the let-nesting level it was created at
+//!   (Rémy-style). Binding a variable lowers the levels of the variables it is
+//!   bound to, so generalization only has to compare levels instead of scanning
+//!   the environment for free variables.
 //!
 //! # Example
 //!
@@ -46,6 +50,16 @@ pub struct Subst {
     /// `parent[i] = Some(ty)` means variable `i` is bound to `ty`.
     parent: Vec<Option<Ty>>,
 
+    /// Let-nesting level of each variable (parallel to `parent`).
+    ///
+    /// A variable whose level is deeper than the current level was created
+    /// inside a let initializer that has since been left, and is not reachable
+    /// from the enclosing environment, so it can be generalized.
+    levels: Vec<u32>,
+
+    /// Current let-nesting level.
+    current_level: u32,
+
     /// Next available type variable index.
     next_var: u32,
 }
@@ -62,6 +76,8 @@ impl Subst {
     pub fn new() -> Self {
         Self {
             parent: Vec::new(),
+            levels: Vec::new(),
+            current_level: 0,
             next_var: 0,
         }
     }
@@ -98,10 +114,82 @@ impl Subst {
     pub fn fresh_var(&mut self) -> u32 {
         let var = self.next_var;
         self.parent.push(None);
+        self.levels.push(self.current_level);
         self.next_var += 1;
         var
     }
 
+    /// Get the current let-nesting level.
+    pub fn level(&self) -> u32 {
+        self.current_level
+    }
+
+    /// Enter a let initializer.
+    ///
+    /// Variables created until the matching `exit_level` are candidates for
+    /// generalization.
+    pub fn enter_level(&mut self) {
+        self.current_level += 1;
+    }
+
+    /// Leave a let initializer.
+    pub fn exit_level(&mut self) {
+        self.current_level = self.current_level.saturating_sub(1);
+    }
+
+    /// Get the level of a type variable, or `None` if it is out of bounds.
+    pub fn var_level(&self, var: u32) -> Option<u32> {
+        self.levels.get(var as usize).copied()
+    }
+
+    /// Collect the variables of `ty` that can be generalized at the current level.
+    ///
+    /// A variable is generalizable if it is still unbound and its level is
+    /// deeper than the current level. This is linear in the size of `ty` and
+    /// independent of the size of the environment.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut subst = Subst::new();
+    /// subst.enter_level();
+    /// let var = subst.fresh_var();
+    /// subst.exit_level();
+    ///
+    /// assert_eq!(subst.generalizable_vars(&Ty::TypeVar(var)), vec![var]);
+    /// ```
+    pub fn generalizable_vars(&mut self, ty: &Ty) -> Vec<u32> {
+        let ty = self.apply_ty(ty);
+        let mut vars: Vec<u32> = ty
+            .free_vars()
+            .into_iter()
+            .filter(|&var| {
+                self.var_level(var)
+                    .is_some_and(|level| level > self.current_level)
+            })
+            .collect();
+        vars.sort_unstable();
+        vars
+    }
+
+    /// Lower the level of every variable reachable from `ty` to at most `level`.
+    ///
+    /// Called whenever a variable of level `level` is bound, so that variables
+    /// escaping into an outer scope through the binding are not generalized.
+    fn adjust_levels(&mut self, ty: &Ty, level: u32) {
+        for var in ty.free_vars() {
+            match self.lookup_rep(var) {
+                Ok(Ty::TypeVar(rep)) => {
+                    if let Some(rep_level) = self.levels.get_mut(rep as usize) {
+                        *rep_level = (*rep_level).min(level);
+                    }
+                }
+                Ok(bound) => self.adjust_levels(&bound, level),
+                Err(_) => {}
+            }
+        }
+    }
+
     /// Look up a type variable, following parent pointers.
     ///
     /// This performs path compression to flatten the structure.
@@ -195,6 +283,8 @@ impl Subst {
     /// ```
     pub fn bind(&mut self, var: u32, ty: Ty) {
         if (var as usize) < self.parent.len() {
+            let level = self.levels[var as usize];
+            self.adjust_levels(&ty, level);
             self.parent[var as usize] = Some(ty);
         }
     }
@@ -218,6 +308,8 @@ impl Subst {
     /// ```
     pub fn union(&mut self, var1: u32, var2: u32) {
         if (var1 as usize) < self.parent.len() && (var2 as usize) < self.parent.len() {
+            let level = self.levels[var1 as usize];
+            self.adjust_levels(&Ty::TypeVar(var2), level);
             self.parent[var1 as usize] = Some(Ty::TypeVar(var2));
         }
     }
@@ -234,8 +326,7 @@ impl Subst {
         let mut subst = Self::new();
         // Ensure the variable exists
         while subst.next_var <= var {
-            subst.parent.push(None);
-            subst.next_var += 1;
+            subst.fresh_var();
         }
         subst.bind(var, ty);
         subst
@@ -266,6 +357,8 @@ impl Subst {
         for (i, ty) in other.parent.into_iter().enumerate() {
             if i >= self.parent.len() {
                 self.parent.push(ty);
+                self.levels.push(other.levels[i]);
+                self.next_var = self.next_var.max(i as u32 + 1);
             } else if self.parent[i].is_none() && ty.is_some() {
                 self.parent[i] = ty;
             }
@@ -345,7 +438,7 @@ impl Subst {
             },
 
             // These types don't contain other types
-            Ty::Primitive(_) | Ty::SelfType | Ty::Never | Ty::Error => ty.clone(),
+            Ty::Primitive(_) | Ty::Range | Ty::SelfType | Ty::Never | Ty::Error => ty.clone(),
         }
     }
 
@@ -506,4 +599,39 @@ mod tests {
         let lookup = subst.lookup(v1);
         assert_eq!(lookup, Some(&Ty::Primitive(PrimTy::Int64)));
     }
+
+    #[test]
+    fn test_generalizable_vars_by_level() {
+        let mut subst = Subst::new();
+        let outer = subst.fresh_var();
+
+        subst.enter_level();
+        let inner = subst.fresh_var();
+        assert_eq!(subst.var_level(inner), Some(1));
+        subst.exit_level();
+
+        let ty = Ty::Function {
+            params: vec![Ty::TypeVar(inner)],
+            return_type: Box::new(Ty::TypeVar(outer)),
+            labels: vec![None],
+        };
+
+        // Only the variable created inside the initializer is generalized
+        assert_eq!(subst.generalizable_vars(&ty), vec![inner]);
+    }
+
+    #[test]
+    fn test_binding_lowers_levels() {
+        let mut subst = Subst::new();
+        let outer = subst.fresh_var();
+
+        subst.enter_level();
+        let inner = subst.fresh_var();
+        // The inner variable escapes into the outer scope through `outer`
+        subst.bind(outer, Ty::Array(Box::new(Ty::TypeVar(inner))));
+        subst.exit_level();
+
+        assert_eq!(subst.var_level(inner), Some(0));
+        assert!(subst.generalizable_vars(&Ty::TypeVar(inner)).is_empty());
+    }
 }
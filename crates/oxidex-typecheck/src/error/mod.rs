@@ -3,8 +3,11 @@
 //! This module defines all error types that can occur during type checking,
 //! with support for rich error reporting and suggestions.
 
+pub mod suggest;
+
 use crate::types::Ty;
 use oxidex_syntax::Span;
+use oxidex_syntax::diagnostic::{Diagnostic, DiagnosticBuilder, DiagnosticLevel};
 use std::fmt;
 
 /// Type checking errors.
@@ -24,6 +27,8 @@ pub enum TypeError {
     UndefinedVar {
         /// Name of the undefined variable
         name: String,
+        /// Bindings in scope with similar names (for suggestions)
+        candidates: Vec<String>,
         /// Source location
         span: Span,
     },
@@ -46,6 +51,26 @@ pub enum TypeError {
         span: Span,
     },
 
+    /// Call matches more than one overload equally well.
+    AmbiguousOverload {
+        /// Name of the overloaded function or method
+        name: String,
+        /// Signatures of the equally ranked candidates
+        candidates: Vec<String>,
+        /// Source location
+        span: Span,
+    },
+
+    /// Call matches none of the available overloads.
+    NoMatchingOverload {
+        /// Name of the overloaded function or method
+        name: String,
+        /// Signatures of all candidates that were considered
+        candidates: Vec<String>,
+        /// Source location
+        span: Span,
+    },
+
     /// Non-exhaustive match expression.
     NonExhaustiveMatch {
         /// Missing patterns/variants
@@ -76,6 +101,8 @@ pub enum TypeError {
     AssignToImmutable {
         /// Name of the immutable binding
         name: String,
+        /// Where the binding was declared, if known
+        declared_at: Option<Span>,
         /// Source location
         span: Span,
     },
@@ -106,6 +133,35 @@ pub enum TypeError {
     RecursiveType {
         /// Name of the type
         name: String,
+        /// Types in the containment cycle, starting and ending with `name`
+        cycle: Vec<String>,
+        /// Source location
+        span: Span,
+    },
+
+    /// Generic arguments related by subtyping in a direction the parameter's
+    /// variance does not allow.
+    VarianceViolation {
+        /// The expected type
+        expected: String,
+        /// The actual type found
+        found: String,
+        /// The generic parameter
+        param: String,
+        /// Inferred variance of the parameter
+        variance: String,
+        /// Field that decided the variance, with its location
+        field: Option<(String, Span)>,
+        /// Source location
+        span: Span,
+    },
+
+    /// Typed hole (`???`) left in the program.
+    TypedHole {
+        /// Type the hole was inferred to have
+        expected: String,
+        /// In-scope bindings whose type fits the hole
+        candidates: Vec<String>,
         /// Source location
         span: Span,
     },
@@ -166,6 +222,52 @@ pub enum TypeError {
         span: Span,
     },
 
+    /// `self` used outside a method.
+    SelfOutsideMethod {
+        /// Source location
+        span: Span,
+    },
+
+    /// `self` used inside a static method.
+    SelfInStaticMethod {
+        /// Source location
+        span: Span,
+    },
+
+    /// Static method called on an instance.
+    StaticMethodOnInstance {
+        /// The type declaring the method
+        ty: String,
+        /// The method name
+        method: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// Instance method accessed through its type.
+    InstanceMethodOnType {
+        /// The type declaring the method
+        ty: String,
+        /// The method name
+        method: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// Expression used where a compile-time constant is required.
+    NotConstant {
+        /// Source location
+        span: Span,
+    },
+
+    /// Constant expression that cannot be evaluated.
+    ConstEval {
+        /// What went wrong (overflow, division by zero, ...)
+        message: String,
+        /// Source location
+        span: Span,
+    },
+
     /// Invalid return type.
     InvalidReturnType {
         /// Expected return type
@@ -190,6 +292,8 @@ pub enum TypeError {
         ty: String,
         /// The unknown field name
         field: String,
+        /// Fields of the type with similar names (for suggestions)
+        candidates: Vec<String>,
         /// Source location
         span: Span,
     },
@@ -200,6 +304,26 @@ pub enum TypeError {
         ty: String,
         /// The unknown variant name
         variant: String,
+        /// Variants of the enum with similar names (for suggestions)
+        candidates: Vec<String>,
+        /// Source location
+        span: Span,
+    },
+
+    /// Type in the signature of an `extern` function that C has no
+    /// counterpart for.
+    NotCCompatible {
+        /// The type, as written for the user
+        ty: String,
+        /// Source location
+        span: Span,
+    },
+
+    /// `for` loop over a value that does not follow the iteration
+    /// protocol.
+    NotASequence {
+        /// The type, as written for the user
+        ty: String,
         /// Source location
         span: Span,
     },
@@ -213,6 +337,8 @@ impl TypeError {
             | TypeError::UndefinedVar { span, .. }
             | TypeError::UndefinedType { span, .. }
             | TypeError::UndefinedFunction { span, .. }
+            | TypeError::AmbiguousOverload { span, .. }
+            | TypeError::NoMatchingOverload { span, .. }
             | TypeError::NonExhaustiveMatch { span, .. }
             | TypeError::InfiniteType { span, .. }
             | TypeError::MissingProtocolMethod { span, .. }
@@ -220,6 +346,8 @@ impl TypeError {
             | TypeError::WrongTypeArgCount { span, .. }
             | TypeError::ProtocolConstraintNotSatisfied { span, .. }
             | TypeError::RecursiveType { span, .. }
+            | TypeError::VarianceViolation { span, .. }
+            | TypeError::TypedHole { span, .. }
             | TypeError::AmbiguousType { span, .. }
             | TypeError::MatchOnNonEnum { span, .. }
             | TypeError::FieldAccessOnNonStruct { span, .. }
@@ -228,10 +356,18 @@ impl TypeError {
             | TypeError::NonBooleanCondition { span, .. }
             | TypeError::BreakOutsideLoop { span, .. }
             | TypeError::ReturnOutsideFunction { span, .. }
+            | TypeError::SelfOutsideMethod { span, .. }
+            | TypeError::SelfInStaticMethod { span, .. }
+            | TypeError::StaticMethodOnInstance { span, .. }
+            | TypeError::InstanceMethodOnType { span, .. }
+            | TypeError::NotConstant { span, .. }
+            | TypeError::ConstEval { span, .. }
             | TypeError::InvalidReturnType { span, .. }
             | TypeError::UnknownType { span, .. }
             | TypeError::UnknownField { span, .. }
-            | TypeError::UnknownVariant { span, .. } => *span,
+            | TypeError::UnknownVariant { span, .. }
+            | TypeError::NotCCompatible { span, .. }
+            | TypeError::NotASequence { span, .. } => *span,
         }
     }
 
@@ -242,6 +378,8 @@ impl TypeError {
             TypeError::UndefinedVar { .. } => "undefined variable".to_string(),
             TypeError::UndefinedType { .. } => "undefined type".to_string(),
             TypeError::UndefinedFunction { .. } => "undefined function".to_string(),
+            TypeError::AmbiguousOverload { .. } => "ambiguous overload".to_string(),
+            TypeError::NoMatchingOverload { .. } => "no matching overload".to_string(),
             TypeError::NonExhaustiveMatch { .. } => "non-exhaustive match expression".to_string(),
             TypeError::InfiniteType { .. } => "infinite type".to_string(),
             TypeError::MissingProtocolMethod { .. } => "missing protocol method".to_string(),
@@ -251,6 +389,8 @@ impl TypeError {
                 "protocol constraint not satisfied".to_string()
             }
             TypeError::RecursiveType { .. } => "recursive type without indirection".to_string(),
+            TypeError::VarianceViolation { .. } => "variance violation".to_string(),
+            TypeError::TypedHole { .. } => "typed hole".to_string(),
             TypeError::AmbiguousType { .. } => "ambiguous type".to_string(),
             TypeError::MatchOnNonEnum { .. } => "match on non-enum type".to_string(),
             TypeError::FieldAccessOnNonStruct { .. } => "field access on non-struct type".to_string(),
@@ -259,10 +399,56 @@ impl TypeError {
             TypeError::NonBooleanCondition { .. } => "non-boolean condition".to_string(),
             TypeError::BreakOutsideLoop { .. } => "break outside loop".to_string(),
             TypeError::ReturnOutsideFunction { .. } => "return outside function".to_string(),
+            TypeError::SelfOutsideMethod { .. } => "self outside method".to_string(),
+            TypeError::SelfInStaticMethod { .. } => "self in static method".to_string(),
+            TypeError::StaticMethodOnInstance { .. } => "static method called on instance".to_string(),
+            TypeError::InstanceMethodOnType { .. } => "instance method accessed through type".to_string(),
+            TypeError::NotConstant { .. } => "non-constant expression".to_string(),
+            TypeError::ConstEval { .. } => "constant evaluation failed".to_string(),
             TypeError::InvalidReturnType { .. } => "invalid return type".to_string(),
             TypeError::UnknownType { .. } => "unknown type".to_string(),
             TypeError::UnknownField { .. } => "unknown field".to_string(),
             TypeError::UnknownVariant { .. } => "unknown enum variant".to_string(),
+            TypeError::NotCCompatible { .. } => "type not C-compatible".to_string(),
+            TypeError::NotASequence { .. } => "iteration over a non-sequence".to_string(),
+        }
+    }
+
+    /// Describe the error as a diagnostic, with its notes, ready to report
+    /// to a [`Session`](oxidex_syntax::session::Session).
+    pub fn diagnostic(&self) -> Diagnostic {
+        let builder =
+            DiagnosticBuilder::new(DiagnosticLevel::Error, self.to_string(), self.span());
+        self.notes()
+            .into_iter()
+            .fold(builder, |builder, (note, span)| builder.note(note, span))
+            .build()
+    }
+
+    /// Get secondary notes attached to this error, each with its own span.
+    ///
+    /// Notes point at related source locations, such as the declaration of
+    /// a binding that was used incorrectly.
+    pub fn notes(&self) -> Vec<(String, Span)> {
+        match self {
+            TypeError::AssignToImmutable {
+                name,
+                declared_at: Some(declared_at),
+                ..
+            } => vec![(
+                format!("`{}` is declared here; consider declaring it with `mut`", name),
+                *declared_at,
+            )],
+            TypeError::VarianceViolation {
+                param,
+                variance,
+                field: Some((field, at)),
+                ..
+            } => vec![(
+                format!("field `{}` makes {} {}", field, param, variance),
+                *at,
+            )],
+            _ => vec![],
         }
     }
 }
@@ -278,8 +464,11 @@ impl fmt::Display for TypeError {
                 )
             }
 
-            TypeError::UndefinedVar { name, .. } => {
-                write!(f, "undefined variable: {}", name)
+            TypeError::UndefinedVar {
+                name, candidates, ..
+            } => {
+                write!(f, "undefined variable: {}", name)?;
+                write_suggestions(f, candidates)
             }
 
             TypeError::UndefinedType { name, .. } => {
@@ -290,11 +479,27 @@ impl fmt::Display for TypeError {
                 name, candidates, ..
             } => {
                 write!(f, "undefined function: {}", name)?;
-                if !candidates.is_empty() {
-                    write!(f, "\ndid you mean {}?", candidates.join(", "))
-                } else {
-                    Ok(())
+                write_suggestions(f, candidates)
+            }
+
+            TypeError::AmbiguousOverload {
+                name, candidates, ..
+            } => {
+                write!(f, "ambiguous call to {}", name)?;
+                for candidate in candidates {
+                    write!(f, "\ncandidate: {}", candidate)?;
+                }
+                Ok(())
+            }
+
+            TypeError::NoMatchingOverload {
+                name, candidates, ..
+            } => {
+                write!(f, "no overload of {} matches the given arguments", name)?;
+                for candidate in candidates {
+                    write!(f, "\ncandidate: {}", candidate)?;
                 }
+                Ok(())
             }
 
             TypeError::NonExhaustiveMatch { missing, .. } => {
@@ -337,14 +542,47 @@ impl fmt::Display for TypeError {
                 write!(f, "type {} does not satisfy protocol {}", ty, protocol)
             }
 
-            TypeError::RecursiveType { name, .. } => {
+            TypeError::RecursiveType { name, cycle, .. } => {
                 write!(
                     f,
-                    "recursive type {} requires indirection (use Box)",
+                    "recursive type {} has infinite size ({}); insert indirection such as Box<{}>, an optional, an array, or make a type in the cycle a class",
+                    name,
+                    cycle.join(" -> "),
                     name
                 )
             }
 
+            TypeError::VarianceViolation {
+                expected,
+                found,
+                param,
+                variance,
+                field,
+                ..
+            } => {
+                write!(
+                    f,
+                    "cannot use {} as {}: type parameter {} is {}",
+                    found, expected, param, variance
+                )?;
+                match field {
+                    Some((field, _)) => write!(f, " because of field `{}`", field),
+                    None => Ok(()),
+                }
+            }
+
+            TypeError::TypedHole {
+                expected,
+                candidates,
+                ..
+            } => {
+                write!(f, "found hole of type {}", expected)?;
+                if !candidates.is_empty() {
+                    write!(f, "; bindings in scope that fit: {}", candidates.join(", "))?;
+                }
+                Ok(())
+            }
+
             TypeError::AmbiguousType { .. } => {
                 write!(f, "ambiguous type: add type annotation")
             }
@@ -377,6 +615,34 @@ impl fmt::Display for TypeError {
                 write!(f, "return outside function")
             }
 
+            TypeError::SelfOutsideMethod { .. } => {
+                write!(f, "`self` can only be used inside a method")
+            }
+
+            TypeError::SelfInStaticMethod { .. } => {
+                write!(f, "`self` is not available in a static method")
+            }
+
+            TypeError::StaticMethodOnInstance { ty, method, .. } => {
+                write!(
+                    f,
+                    "static method {} cannot be called on an instance; use {}::{}",
+                    method, ty, method
+                )
+            }
+
+            TypeError::InstanceMethodOnType { ty, method, .. } => {
+                write!(f, "method {}::{} requires an instance of {}", ty, method, ty)
+            }
+
+            TypeError::NotConstant { .. } => {
+                write!(f, "expression is not a compile-time constant")
+            }
+
+            TypeError::ConstEval { message, .. } => {
+                write!(f, "cannot evaluate constant: {}", message)
+            }
+
             TypeError::InvalidReturnType { expected, found, .. } => {
                 write!(
                     f,
@@ -389,17 +655,54 @@ impl fmt::Display for TypeError {
                 write!(f, "unknown type: {}", name)
             }
 
-            TypeError::UnknownField { ty, field, .. } => {
-                write!(f, "type {} has no field {}", ty, field)
+            TypeError::UnknownField {
+                ty,
+                field,
+                candidates,
+                ..
+            } => {
+                write!(f, "type {} has no field {}", ty, field)?;
+                write_suggestions(f, candidates)
             }
 
-            TypeError::UnknownVariant { ty, variant, .. } => {
-                write!(f, "enum {} has no variant {}", ty, variant)
+            TypeError::UnknownVariant {
+                ty,
+                variant,
+                candidates,
+                ..
+            } => {
+                write!(f, "enum {} has no variant {}", ty, variant)?;
+                write_suggestions(f, candidates)
+            }
+
+            TypeError::NotCCompatible { ty, .. } => {
+                write!(
+                    f,
+                    "type {} cannot be passed to or returned from C; use an integer, Float, Bool or String",
+                    ty
+                )
+            }
+
+            TypeError::NotASequence { ty, .. } => {
+                write!(
+                    f,
+                    "cannot iterate over {}; a sequence is an array, dictionary, string, range, \
+                     or a type with `makeIterator()` or `next()`",
+                    ty
+                )
             }
         }
     }
 }
 
+/// Write a "did you mean" line if there are any suggestions.
+fn write_suggestions(f: &mut fmt::Formatter<'_>, candidates: &[String]) -> fmt::Result {
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    write!(f, "\ndid you mean {}?", candidates.join(", "))
+}
+
 impl std::error::Error for TypeError {}
 
 /// A result type for type checking operations.
@@ -414,11 +717,26 @@ mod tests {
     fn test_error_display() {
         let err = TypeError::UndefinedVar {
             name: "x".to_string(),
+            candidates: vec![],
             span: Span::new(0, 0, 0, 0, 0, 0),
         };
         assert_eq!(format!("{}", err), "undefined variable: x");
     }
 
+    #[test]
+    fn test_suggestion_display() {
+        let err = TypeError::UnknownField {
+            ty: "Point".to_string(),
+            field: "z".to_string(),
+            candidates: vec!["x".to_string(), "y".to_string()],
+            span: Span::new(0, 0, 0, 0, 0, 0),
+        };
+        assert_eq!(
+            format!("{}", err),
+            "type Point has no field z\ndid you mean x, y?"
+        );
+    }
+
     #[test]
     fn test_mismatch_error() {
         let err = TypeError::Mismatch {
@@ -428,4 +746,20 @@ mod tests {
         };
         assert!(format!("{}", err).contains("type mismatch"));
     }
+
+    #[test]
+    fn test_ambiguous_overload_display() {
+        let err = TypeError::AmbiguousOverload {
+            name: "add".to_string(),
+            candidates: vec![
+                "add(Int64) -> Int64".to_string(),
+                "add(Int64) -> Bool".to_string(),
+            ],
+            span: Span::new(0, 0, 0, 0, 0, 0),
+        };
+        let rendered = format!("{}", err);
+        assert!(rendered.starts_with("ambiguous call to add"));
+        assert!(rendered.contains("candidate: add(Int64) -> Int64"));
+        assert!(rendered.contains("candidate: add(Int64) -> Bool"));
+    }
 }
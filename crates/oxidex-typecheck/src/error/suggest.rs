@@ -0,0 +1,92 @@
+//! "Did you mean" suggestions for misspelled names.
+//!
+//! Candidates are ranked by Damerau-Levenshtein distance (insertions,
+//! deletions, substitutions, and transpositions of adjacent characters), so
+//! `lenght` suggests `length` at distance one.
+
+/// Maximum number of suggestions attached to a diagnostic.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Suggest names close to `name` from a set of candidates.
+///
+/// A candidate is kept if its distance is at most a third of the length of
+/// `name` (and at least one). Results are ordered by distance, then
+/// alphabetically, and exact matches are never suggested.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut ranked: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect();
+    ranked.sort_unstable();
+    ranked.dedup();
+
+    ranked
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Compute the Damerau-Levenshtein distance between two strings.
+///
+/// This is the optimal string alignment variant: a substring is never
+/// edited more than once.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // d[i][j] is the distance between a[..i] and b[..j]
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        // Transposition counts as a single edit
+        assert_eq!(distance("lenght", "length"), 1);
+    }
+
+    #[test]
+    fn test_suggest_ranks_by_distance() {
+        let candidates = ["width", "light", "lengths", "length", "height"];
+        assert_eq!(
+            suggest("lenght", candidates),
+            vec!["length", "height", "lengths"]
+        );
+    }
+
+    #[test]
+    fn test_suggest_skips_distant_and_exact_names() {
+        assert!(suggest("x", ["x", "value"]).is_empty());
+        assert_eq!(suggest("y", ["x", "value"]), vec!["x"]);
+    }
+}
@@ -0,0 +1,651 @@
+//! Deferred constraints and instance resolution.
+//!
+//! Some requirements cannot be decided while an expression is checked,
+//! because the types involved are still type variables:
+//!
+//! ```ignore
+//! fn same<T>(a: T, b: T) -> Bool { a == b }   // T: Equatable
+//! fn area<S>(shape: S) -> Float { shape.area() } // S has method area()
+//! ```
+//!
+//! Instead of demanding concrete types at the use site, the checker records
+//! a [`Constraint`] and solves it once unification has run:
+//!
+//! - A bound on a concrete type is checked against the type (primitives
+//!   have built-in conformances, nominal types their declared ones)
+//! - A bound on a generic parameter becomes a requirement of that
+//!   parameter, inferred from the body
+//! - A method call on a type variable is resolved once the receiver is known
+//! - A call to a generic function checks the callee's inferred requirements
+//!   against the instantiated type arguments
+//!
+//! Constraints are solved at the end of every function body, and once more
+//! after the whole program has been checked so that calls to functions
+//! declared later are covered too.
+
+use crate::context::{MethodInfo, Scheme};
+use crate::error::{Result, TypeError};
+use crate::infer::{Context, resolve_overload};
+use crate::types::{PrimTy, Ty};
+use oxidex_mem::Symbol;
+use oxidex_syntax::Span;
+use std::fmt;
+
+/// A requirement a type must satisfy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Bound {
+    /// Supports `==` and `!=`
+    Equatable,
+    /// Supports `<`, `<=`, `>`, and `>=`
+    Comparable,
+    /// Supports `+` (numbers and strings)
+    Add,
+    /// Supports binary `-`
+    Subtract,
+    /// Supports `*`
+    Multiply,
+    /// Supports `/`
+    Divide,
+    /// Supports `%`
+    Remainder,
+    /// Supports unary `-`
+    Negate,
+    /// Can be interpolated into a string
+    Describable,
+    /// Has a method with the given name and number of arguments
+    Method {
+        /// Method name
+        name: Symbol,
+        /// Number of arguments, excluding the receiver
+        arity: usize,
+    },
+}
+
+impl Bound {
+    /// Get the protocol a nominal type must declare to satisfy this bound.
+    fn protocol_name(&self) -> Option<&'static str> {
+        match self {
+            Bound::Equatable => Some("Equatable"),
+            Bound::Comparable => Some("Comparable"),
+            Bound::Add => Some("Add"),
+            Bound::Subtract => Some("Subtract"),
+            Bound::Multiply => Some("Multiply"),
+            Bound::Divide => Some("Divide"),
+            Bound::Remainder => Some("Remainder"),
+            Bound::Negate => Some("Negate"),
+            Bound::Describable => Some("CustomStringConvertible"),
+            Bound::Method { .. } => None,
+        }
+    }
+
+    /// Get the method a nominal type can implement instead of declaring the
+    /// protocol, with its number of arguments.
+    pub fn method(&self) -> Option<(&'static str, usize)> {
+        match self {
+            Bound::Add => Some(("add", 1)),
+            Bound::Subtract => Some(("subtract", 1)),
+            Bound::Multiply => Some(("multiply", 1)),
+            Bound::Divide => Some(("divide", 1)),
+            Bound::Remainder => Some(("remainder", 1)),
+            Bound::Negate => Some(("negate", 0)),
+            Bound::Describable => Some(("description", 0)),
+            Bound::Equatable | Bound::Comparable | Bound::Method { .. } => None,
+        }
+    }
+
+    /// Whether tuples, arrays, and optionals satisfy this bound when their
+    /// elements do.
+    fn is_structural(&self) -> bool {
+        matches!(self, Bound::Equatable | Bound::Describable)
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.protocol_name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "method"),
+        }
+    }
+}
+
+/// A constraint whose solution is deferred until after unification.
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// `ty` must satisfy `bound`
+    Bound {
+        /// Constrained type
+        ty: Ty,
+        /// Required bound
+        bound: Bound,
+        /// Source location
+        span: Span,
+    },
+
+    /// A method call whose receiver type was not known yet
+    Method {
+        /// Receiver type
+        receiver: Ty,
+        /// Method name
+        method: Symbol,
+        /// Argument labels
+        labels: Vec<Option<Symbol>>,
+        /// Argument types
+        args: Vec<Ty>,
+        /// Result type of the call
+        ret: Ty,
+        /// Source location
+        span: Span,
+    },
+
+    /// A call to a generic function, instantiated with `args` for its
+    /// generic parameters in declaration order
+    Instance {
+        /// Function name
+        name: Symbol,
+        /// Type arguments
+        args: Vec<Ty>,
+        /// Source location
+        span: Span,
+    },
+}
+
+/// Outcome of one attempt at a constraint.
+enum Step {
+    /// The constraint is satisfied (possibly by producing new constraints)
+    Solved,
+    /// The types involved are not known yet
+    Deferred,
+}
+
+/// Solve the pending constraints.
+///
+/// With `final_pass` set, instance constraints are expanded until the
+/// inferred requirements stop growing, and constraints that are still
+/// blocked on unknown types are discarded.
+///
+/// # Errors
+///
+/// - `ProtocolConstraintNotSatisfied` if a concrete type does not satisfy a bound
+/// - `UndefinedFunction` if a receiver has no matching method
+/// - `AmbiguousType` if a method receiver is never inferred
+pub fn solve_constraints<'ctx>(ctx: &mut Context<'ctx>, final_pass: bool) -> Result<()> {
+    loop {
+        let before = requirement_count(ctx);
+        expand_instances(ctx);
+        solve_pending(ctx, final_pass)?;
+
+        // Expanding again only helps once the requirements have grown
+        if !final_pass || requirement_count(ctx) == before {
+            break;
+        }
+    }
+
+    if final_pass {
+        ctx.constraints.clear();
+        ctx.instances.clear();
+    }
+    Ok(())
+}
+
+/// Turn every instance of a function with known requirements into bounds.
+///
+/// Instances are kept, since the callee's requirements may still grow.
+fn expand_instances(ctx: &mut Context<'_>) {
+    let mut bounds = Vec::new();
+    for instance in &ctx.instances {
+        let Constraint::Instance { name, args, span } = instance else {
+            continue;
+        };
+        let Some(generics) = ctx.fn_generics.get(name) else {
+            continue;
+        };
+        for (var, arg) in generics.iter().zip(args) {
+            for bound in ctx.requirements.get(var).into_iter().flatten() {
+                bounds.push(Constraint::Bound {
+                    ty: arg.clone(),
+                    bound: bound.clone(),
+                    span: *span,
+                });
+            }
+        }
+    }
+    ctx.constraints.extend(bounds);
+}
+
+/// Solve bound and method constraints until no more progress is made.
+fn solve_pending(ctx: &mut Context<'_>, final_pass: bool) -> Result<()> {
+    loop {
+        let pending = std::mem::take(&mut ctx.constraints);
+        let mut progress = false;
+        let mut deferred = Vec::new();
+
+        for constraint in pending {
+            match step(ctx, &constraint, final_pass)? {
+                Step::Solved => progress = true,
+                Step::Deferred => deferred.push(constraint),
+            }
+        }
+
+        ctx.constraints.extend(deferred);
+        if !progress {
+            return Ok(());
+        }
+    }
+}
+
+/// Attempt a single constraint.
+fn step(ctx: &mut Context<'_>, constraint: &Constraint, final_pass: bool) -> Result<Step> {
+    match constraint {
+        Constraint::Bound { ty, bound, span } => {
+            let ty = ctx.subst().apply_ty(ty);
+            match ty {
+                Ty::TypeVar(var) if ctx.requirements.contains_key(&var) => {
+                    let requirements = ctx.requirements.entry(var).or_default();
+                    if !requirements.contains(bound) {
+                        requirements.push(bound.clone());
+                    }
+                    Ok(Step::Solved)
+                }
+                Ty::TypeVar(_) if final_pass => Ok(Step::Solved),
+                Ty::TypeVar(_) => Ok(Step::Deferred),
+                _ if satisfies(ctx, &ty, bound) => Ok(Step::Solved),
+                _ => Err(unsatisfied(ctx, &ty, bound, *span)),
+            }
+        }
+
+        Constraint::Method {
+            receiver,
+            method,
+            labels,
+            args,
+            ret,
+            span,
+        } => {
+            let receiver = ctx.subst().apply_ty(receiver);
+            match &receiver {
+                Ty::TypeVar(var) if ctx.requirements.contains_key(var) => {
+                    let bound = Bound::Method {
+                        name: *method,
+                        arity: args.len(),
+                    };
+                    let requirements = ctx.requirements.entry(*var).or_default();
+                    if !requirements.contains(&bound) {
+                        requirements.push(bound);
+                    }
+                    Ok(Step::Solved)
+                }
+                Ty::TypeVar(_) if final_pass => Err(TypeError::AmbiguousType { span: *span }),
+                Ty::TypeVar(_) => Ok(Step::Deferred),
+                Ty::Struct { name, .. } | Ty::Enum { name, .. } | Ty::Class { name, .. } => {
+                    resolve_method(ctx, *name, *method, labels, args, ret, *span)?;
+                    Ok(Step::Solved)
+                }
+                _ => Err(TypeError::UndefinedFunction {
+                    name: ctx.interner.resolve(*method).unwrap_or("").to_string(),
+                    candidates: vec![],
+                    span: *span,
+                }),
+            }
+        }
+
+        // Instances are expanded separately and never solved directly
+        Constraint::Instance { .. } => Ok(Step::Solved),
+    }
+}
+
+/// Resolve a deferred method call against the receiver's methods.
+fn resolve_method(
+    ctx: &mut Context<'_>,
+    type_name: Symbol,
+    method: Symbol,
+    labels: &[Option<Symbol>],
+    args: &[Ty],
+    ret: &Ty,
+    span: Span,
+) -> Result<()> {
+    let methods = ctx.types.lookup_methods(type_name).unwrap_or(&[]);
+    let matching: Vec<Scheme> = methods
+        .iter()
+        .filter(|m| m.name == method)
+        .map(|m| {
+            Scheme::mono(Ty::Function {
+                params: m.params.clone(),
+                return_type: Box::new(m.return_type.clone()),
+                labels: m.labels.clone(),
+            })
+        })
+        .collect();
+
+    if matching.is_empty() {
+        return Err(TypeError::UndefinedFunction {
+            name: ctx.interner.resolve(method).unwrap_or("").to_string(),
+            candidates: ctx.suggest(method, methods.iter().map(|m| m.name)),
+            span,
+        });
+    }
+
+    let (_, ty) = resolve_overload(ctx, method, &matching, labels, args, span)?;
+    ctx.unify(&ty, ret, span)
+}
+
+/// Check whether a type without type variables at the top satisfies a bound.
+pub fn satisfies(ctx: &Context<'_>, ty: &Ty, bound: &Bound) -> bool {
+    match ty {
+        // Errors were already reported
+        Ty::Error => true,
+
+        Ty::Primitive(prim) => {
+            let numeric = !matches!(
+                prim,
+                PrimTy::Bool | PrimTy::String | PrimTy::Unit | PrimTy::Char
+            );
+            let unsigned = matches!(
+                prim,
+                PrimTy::UInt8 | PrimTy::UInt16 | PrimTy::UInt32 | PrimTy::UInt64 | PrimTy::UInt128
+            );
+            match bound {
+                Bound::Equatable => true,
+                Bound::Comparable => !matches!(prim, PrimTy::Bool | PrimTy::Unit),
+                Bound::Add => numeric || *prim == PrimTy::String,
+                Bound::Subtract | Bound::Multiply | Bound::Divide | Bound::Remainder => numeric,
+                Bound::Negate => numeric && !unsigned,
+                Bound::Describable => true,
+                Bound::Method { .. } => false,
+            }
+        }
+
+        // Structural types are equatable and describable when their elements are
+        Ty::Tuple(elements) if bound.is_structural() => {
+            elements.iter().all(|e| satisfies_nested(ctx, e, bound))
+        }
+        Ty::Array(element) | Ty::Optional(element) if bound.is_structural() => {
+            satisfies_nested(ctx, element, bound)
+        }
+        Ty::Dict { key, value } if *bound == Bound::Describable => {
+            satisfies_nested(ctx, key, bound) && satisfies_nested(ctx, value, bound)
+        }
+
+        Ty::Struct { name, .. } | Ty::Enum { name, .. } | Ty::Class { name, .. } => match bound {
+            // Without a `description()`, values are described by their fields
+            Bound::Describable => true,
+            Bound::Method { name: method, arity } => ctx
+                .types
+                .lookup_methods(*name)
+                .is_some_and(|methods| {
+                    methods
+                        .iter()
+                        .any(|m| m.name == *method && m.params.len() == *arity)
+                }),
+            _ => {
+                ctx.types.conforms_to(*name, |protocol| {
+                    ctx.interner.resolve(protocol) == bound.protocol_name()
+                }) || bound
+                    .method()
+                    .is_some_and(|(method, arity)| find_method(ctx, *name, method, arity).is_some())
+            }
+        },
+
+        _ => false,
+    }
+}
+
+/// Find an instance method of a nominal type by name and number of arguments.
+pub fn find_method<'a>(ctx: &'a Context<'_>, type_name: Symbol, method: &str, arity: usize) -> Option<&'a MethodInfo> {
+    ctx.types.lookup_methods(type_name)?.iter().find(|m| {
+        !m.is_static && m.params.len() == arity && ctx.interner.resolve(m.name) == Some(method)
+    })
+}
+
+/// Check an element type, treating unknown types as satisfying the bound.
+fn satisfies_nested(ctx: &Context<'_>, ty: &Ty, bound: &Bound) -> bool {
+    matches!(ty, Ty::TypeVar(_)) || satisfies(ctx, ty, bound)
+}
+
+/// Build the error for a type that does not satisfy a bound.
+pub fn unsatisfied(ctx: &Context<'_>, ty: &Ty, bound: &Bound, span: Span) -> TypeError {
+    match bound {
+        Bound::Method { name, .. } => {
+            let candidates = match ty {
+                Ty::Struct { name: ty_name, .. }
+                | Ty::Enum { name: ty_name, .. }
+                | Ty::Class { name: ty_name, .. } => {
+                    let methods = ctx.types.lookup_methods(*ty_name).unwrap_or(&[]);
+                    ctx.suggest(*name, methods.iter().map(|m| m.name))
+                }
+                _ => vec![],
+            };
+            TypeError::UndefinedFunction {
+                name: ctx.interner.resolve(*name).unwrap_or("").to_string(),
+                candidates,
+                span,
+            }
+        }
+        _ => TypeError::ProtocolConstraintNotSatisfied {
+            ty: ty.display(ctx.interner).to_string(),
+            protocol: bound.to_string(),
+            span,
+        },
+    }
+}
+
+/// Count the inferred requirements of all generic parameters.
+fn requirement_count(ctx: &Context<'_>) -> usize {
+    ctx.requirements.values().map(Vec::len).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::{check_bodies, collect_signatures};
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::ast::decl::{Decl, FnParam, Visibility};
+    use oxidex_syntax::ast::expr::{BinaryOp, CallArg, Expr};
+    use oxidex_syntax::ast::ty::Type;
+
+    fn span(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    fn param(name: Symbol, ty: Symbol) -> FnParam {
+        FnParam {
+            label: None,
+            name,
+            type_annotation: Type::Simple { name: ty, span: span(0) },
+            span: span(0),
+        }
+    }
+
+    fn func<'a>(name: Symbol, generics: Vec<Symbol>, params: Vec<FnParam>, body: &'a Expr<'a>, line: usize) -> Decl<'a> {
+        Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name,
+            generics,
+            params,
+            return_type: None,
+            body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span: span(line),
+        }
+    }
+
+    fn point(name: Symbol, protocols: Vec<Vec<Symbol>>) -> Decl<'static> {
+        Decl::Struct {
+            name,
+            generics: vec![],
+            fields: vec![],
+            protocols,
+            visibility: Visibility::Private,
+            span: span(0),
+        }
+    }
+
+    fn arg<'a>(value: &'a Expr<'a>) -> CallArg<'a> {
+        CallArg {
+            label: None,
+            value,
+            span: span(0),
+        }
+    }
+
+    #[test]
+    fn test_generic_equality_requires_equatable_arguments() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["same", "main", "Point", "T", "a", "b", "p", "Equatable"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [same, main, point_ty, t, a, b, p, equatable] = names[..] else {
+            unreachable!()
+        };
+
+        // fn same<T>(a: T, b: T) { a == b }
+        let (lhs, rhs) = (Expr::Identifier(a), Expr::Identifier(b));
+        let same_body = Expr::Binary {
+            left: &lhs,
+            op: BinaryOp::Eq,
+            right: &rhs,
+            span: span(2),
+        };
+
+        // fn main(p: Point) { same(p, p) }, checked before `same`
+        let callee = Expr::Identifier(same);
+        let value = Expr::Identifier(p);
+        let main_body = Expr::Call {
+            callee: &callee,
+            args: vec![arg(&value), arg(&value)],
+            span: span(1),
+        };
+
+        let check = |protocols: Vec<Vec<Symbol>>| {
+            let decls = vec![
+                point(point_ty, protocols),
+                func(main, vec![], vec![param(p, point_ty)], &main_body, 1),
+                func(same, vec![t], vec![param(a, t), param(b, t)], &same_body, 2),
+            ];
+            let mut ctx = Context::new(&interner);
+            collect_signatures(&mut ctx, &decls).unwrap();
+            check_bodies(&mut ctx, &decls)
+        };
+
+        match check(vec![]).unwrap_err().remove(0) {
+            TypeError::ProtocolConstraintNotSatisfied { ty, protocol, span: at } => {
+                assert_eq!(ty, "Point");
+                assert_eq!(protocol, "Equatable");
+                assert_eq!(at, span(1));
+            }
+            other => panic!("Expected ProtocolConstraintNotSatisfied, got {:?}", other),
+        }
+        assert!(check(vec![vec![equatable]]).is_ok());
+    }
+
+    #[test]
+    fn test_method_on_generic_parameter_is_checked_at_call_site() {
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["area", "main", "Point", "S", "shape", "size", "p"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [area, main, point_ty, s, shape, size, p] = names[..] else {
+            unreachable!()
+        };
+
+        // fn area<S>(shape: S) { shape.size() }
+        let receiver = Expr::Identifier(shape);
+        let area_body = Expr::MethodCall {
+            receiver: &receiver,
+            method: size,
+            args: vec![],
+            span: span(1),
+        };
+
+        // fn main(p: Point) { area(p) }
+        let callee = Expr::Identifier(area);
+        let value = Expr::Identifier(p);
+        let main_body = Expr::Call {
+            callee: &callee,
+            args: vec![arg(&value)],
+            span: span(2),
+        };
+
+        let decls = vec![
+            point(point_ty, vec![]),
+            func(area, vec![s], vec![param(shape, s)], &area_body, 1),
+            func(main, vec![], vec![param(p, point_ty)], &main_body, 2),
+        ];
+        let mut ctx = Context::new(&interner);
+        collect_signatures(&mut ctx, &decls).unwrap();
+
+        let err = check_bodies(&mut ctx, &decls).unwrap_err().remove(0);
+        assert!(matches!(err, TypeError::UndefinedFunction { ref name, .. } if name == "size"));
+        assert_eq!(err.span(), span(2));
+    }
+
+    #[test]
+    fn test_primitive_bounds() {
+        let interner = StringInterner::new();
+        let ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        let string = Ty::Primitive(PrimTy::String);
+        let boolean = Ty::Primitive(PrimTy::Bool);
+
+        assert!(satisfies(&ctx, &int, &Bound::Multiply));
+        assert!(satisfies(&ctx, &string, &Bound::Add));
+        assert!(!satisfies(&ctx, &string, &Bound::Multiply));
+        assert!(!satisfies(&ctx, &Ty::Primitive(PrimTy::UInt8), &Bound::Negate));
+        assert!(!satisfies(&ctx, &boolean, &Bound::Comparable));
+        assert!(satisfies(&ctx, &Ty::Array(Box::new(boolean)), &Bound::Equatable));
+    }
+
+    #[test]
+    fn test_interpolation_requires_describable_values() {
+        use crate::check::expr::synth;
+        use oxidex_syntax::ast::expr::InterpolationPart;
+
+        let mut interner = StringInterner::new();
+        let names: Vec<Symbol> = ["Point", "Label", "p", "l", "n", "f", "CustomStringConvertible"]
+            .iter()
+            .map(|n| interner.intern(n))
+            .collect();
+        let [point_ty, label_ty, p, l, n, f, describable] = names[..] else {
+            unreachable!()
+        };
+        let mut ctx = Context::new(&interner);
+        let decls = vec![point(point_ty, vec![]), point(label_ty, vec![vec![describable]])];
+        for decl in &decls {
+            crate::check::check_decl(&mut ctx, decl).unwrap();
+        }
+        let nominal = |name| Ty::Struct { name, type_args: vec![] };
+        ctx.env.bind(p, Scheme::mono(nominal(point_ty)));
+        ctx.env.bind(l, Scheme::mono(nominal(label_ty)));
+        ctx.env.bind(n, Scheme::mono(Ty::Array(Box::new(Ty::Primitive(PrimTy::Int64)))));
+        let unit = Box::new(Ty::Primitive(PrimTy::Unit));
+        ctx.env.bind(f, Scheme::mono(Ty::Function { params: vec![], return_type: unit, labels: vec![] }));
+
+        let values = [n, l, p, f].map(Expr::Identifier);
+        let exprs = values.each_ref().map(|value| Expr::Interpolation {
+            parts: vec![InterpolationPart::Expr(value)],
+            span: span(3),
+        });
+        let mut interpolate = |index: usize| {
+            let ty = synth(&mut ctx, &exprs[index])?;
+            solve_constraints(&mut ctx, true)?;
+            Ok::<_, TypeError>(ty)
+        };
+
+        assert_eq!(interpolate(0).unwrap(), Ty::Primitive(PrimTy::String));
+        assert!(interpolate(1).is_ok());
+        // Types without a description are described by their fields
+        assert!(interpolate(2).is_ok());
+        match interpolate(3).unwrap_err() {
+            TypeError::ProtocolConstraintNotSatisfied { protocol, .. } => {
+                assert_eq!(protocol, "CustomStringConvertible");
+            }
+            other => panic!("Expected ProtocolConstraintNotSatisfied, got {:?}", other),
+        }
+    }
+}
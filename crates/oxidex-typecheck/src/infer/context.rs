@@ -3,12 +3,16 @@
 //! This module provides the main context for type checking, combining
 //! the type environment, substitution, and symbol interner.
 
-use crate::context::{Scheme, Subst, TypeEnv, TypeRegistry};
-use crate::error::Result;
-use crate::infer::Unifier;
-use crate::types::Ty;
-use oxidex_mem::StringInterner;
+use crate::check::consteval::ConstValue;
+use crate::context::{ProtocolInfo, ProtocolMethodInfo, Scheme, Subst, TypeEnv, TypeRegistry};
+use crate::error::suggest::suggest;
+use crate::error::{Result, TypeError};
+use crate::infer::{Bound, Constraint, Unifier};
+use crate::types::{PrimTy, Ty};
+use oxidex_mem::{StringInterner, Symbol};
 use oxidex_syntax::Span;
+use oxidex_syntax::session::Session;
+use std::collections::{HashMap, HashSet};
 
 /// Main type checking context.
 pub struct Context<'ctx> {
@@ -32,15 +36,90 @@ pub struct Context<'ctx> {
 
     /// Generic parameters in scope (mapping from name to type variable)
     pub generic_params: std::collections::HashMap<oxidex_mem::Symbol, u32>,
+
+    /// Typed holes (`???`) encountered so far, in source order
+    pub holes: Vec<TypedHole>,
+
+    /// Constraints waiting for unification (see [`crate::infer::solve_constraints`])
+    pub constraints: Vec<Constraint>,
+
+    /// Calls to generic functions, checked against the callee's requirements
+    pub instances: Vec<Constraint>,
+
+    /// Bounds inferred for each generic parameter's type variable
+    pub requirements: HashMap<u32, Vec<Bound>>,
+
+    /// Type variables of each checked function's generic parameters
+    pub fn_generics: HashMap<Symbol, Vec<u32>>,
+
+    /// Values of the constants declared so far
+    pub consts: HashMap<Symbol, ConstValue>,
+
+    /// Values given to constants from outside the program (such as `--define`),
+    /// replacing their initializers
+    pub defines: HashMap<Symbol, ConstValue>,
+
+    /// Closures checked so far, keyed by their span
+    pub closures: HashMap<Span, ClosureInfo>,
+
+    /// Declarations of the variables some closure captures by box
+    pub boxed: HashSet<Span>,
+
+    /// Closures being checked, innermost last, with the scope depth each
+    /// was entered at
+    open_closures: Vec<(Span, usize)>,
+}
+
+/// What checking a closure expression found.
+#[derive(Debug, Clone)]
+pub struct ClosureInfo {
+    /// Variables the closure uses from enclosing functions, in the order
+    /// its body first uses them
+    pub captures: Vec<Capture>,
+    /// Type of the closure, a function type once its body is checked
+    pub ty: Ty,
+}
+
+/// A variable a closure uses from an enclosing function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capture {
+    /// The variable; `self` when the closure uses the receiver or its fields
+    pub name: Symbol,
+    /// How the closure holds it
+    pub mode: CaptureMode,
+}
+
+/// How a closure holds a captured variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// A copy of the value when the closure is created, for variables
+    /// that cannot change afterwards
+    Value,
+    /// A box shared with the enclosing function, for mutable variables,
+    /// so that assignments on either side are seen by the other
+    Box,
+}
+
+/// A typed hole recorded during checking.
+#[derive(Debug, Clone)]
+pub struct TypedHole {
+    /// Type variable standing in for the hole
+    pub var: u32,
+    /// Bindings visible at the hole
+    pub bindings: Vec<(Symbol, Scheme)>,
+    /// Source location
+    pub span: Span,
 }
 
 /// Information about the current Self type.
 #[derive(Debug, Clone)]
 pub struct CurrentSelf {
     /// Name of the current type
-    pub name: String,
-    /// Type parameters if generic
-    pub type_params: Vec<String>,
+    pub name: Symbol,
+    /// The type `Self` stands for, applied to the type's generic parameters
+    pub ty: Ty,
+    /// Whether the method being checked is static (has no `self`)
+    pub is_static: bool,
 }
 
 impl<'ctx> Context<'ctx> {
@@ -49,7 +128,7 @@ impl<'ctx> Context<'ctx> {
         let subst = Subst::new();
         let unifier = Unifier::new(subst);
 
-        Self {
+        let mut ctx = Self {
             interner,
             env: TypeEnv::new(),
             types: TypeRegistry::new(),
@@ -57,9 +136,47 @@ impl<'ctx> Context<'ctx> {
             current_self: None,
             return_type: None,
             generic_params: std::collections::HashMap::new(),
+            holes: Vec::new(),
+            constraints: Vec::new(),
+            instances: Vec::new(),
+            requirements: HashMap::new(),
+            fn_generics: HashMap::new(),
+            consts: HashMap::new(),
+            defines: HashMap::new(),
+            closures: HashMap::new(),
+            boxed: HashSet::new(),
+            open_closures: Vec::new(),
+        };
+        ctx.declare_description_protocols();
+        ctx
+    }
+
+    /// Declare the protocols that describe values, for programs that name
+    /// them: `CustomStringConvertible` requires `description() -> String`,
+    /// which `print` and interpolation use, and
+    /// `CustomDebugStringConvertible` requires `debugDescription() -> String`,
+    /// which debugging output prefers. Types implementing neither are
+    /// described by a default rendering of their fields.
+    fn declare_description_protocols(&mut self) {
+        let protocols =
+            [("CustomStringConvertible", "description"), ("CustomDebugStringConvertible", "debugDescription")];
+        for (protocol, method) in protocols {
+            let (Some(name), Some(method)) = (self.interner.get_symbol(protocol), self.interner.get_symbol(method))
+            else {
+                continue;
+            };
+            let string = Ty::Primitive(PrimTy::String);
+            let method = ProtocolMethodInfo { name: method, params: vec![], return_type: string };
+            self.types.register_protocol(ProtocolInfo { name, methods: vec![method], generics: vec![] });
         }
     }
 
+    /// Create a type checking context for the program parsed in
+    /// `session`, resolving symbols through its interner.
+    pub fn with_session(session: &'ctx Session<'_>) -> Self {
+        Self::new(session.interner())
+    }
+
     /// Set the expected return type for the current function.
     pub fn set_return_type(&mut self, ty: Ty) {
         self.return_type = Some(ty);
@@ -83,6 +200,8 @@ impl<'ctx> Context<'ctx> {
         for &param in params {
             let type_var = self.fresh_var();
             self.generic_params.insert(param, type_var);
+            // Generic parameters collect the bounds their uses require
+            self.requirements.insert(type_var, Vec::new());
         }
     }
 
@@ -117,6 +236,63 @@ impl<'ctx> Context<'ctx> {
         self.env.pop_scope();
     }
 
+    /// Enter the body of the closure at `span`: the bindings in scope so
+    /// far belong to enclosing functions.
+    pub fn enter_closure(&mut self, span: Span) {
+        let info = ClosureInfo { captures: Vec::new(), ty: Ty::Primitive(PrimTy::Unit) };
+        self.closures.insert(span, info);
+        self.open_closures.push((span, self.env.depth()));
+    }
+
+    /// Leave the body of the innermost closure, whose type is `ty`.
+    pub fn exit_closure(&mut self, ty: &Ty) {
+        if let Some((span, _)) = self.open_closures.pop()
+            && let Some(info) = self.closures.get_mut(&span)
+        {
+            info.ty = ty.clone();
+        }
+    }
+
+    /// Record a use of `sym`, capturing it into each closure being checked
+    /// that it is bound outside of.
+    ///
+    /// Globals are reached by name and never captured. Fields, which have
+    /// no declaration of their own, are reached through the receiver, so
+    /// the closure captures `self` instead. Mutable variables are captured
+    /// by box, anything else by value.
+    pub fn capture(&mut self, sym: Symbol) {
+        let Some(scope) = self.env.scope_of(sym).filter(|&scope| scope > 0) else {
+            return;
+        };
+        let decl = self.env.decl_span(sym);
+        let capture = match decl {
+            Some(_) if self.env.is_mutable(sym) => Capture { name: sym, mode: CaptureMode::Box },
+            Some(_) => Capture { name: sym, mode: CaptureMode::Value },
+            // Sibling methods are called by name
+            None if !self.env.lookup_overloads(sym).is_empty() => return,
+            None => match self.interner.get_symbol("self") {
+                Some(receiver) => Capture { name: receiver, mode: CaptureMode::Value },
+                None => return,
+            },
+        };
+
+        let mut captured = false;
+        for &(span, depth) in self.open_closures.iter().rev() {
+            if scope >= depth {
+                break;
+            }
+            if let Some(info) = self.closures.get_mut(&span)
+                && !info.captures.iter().any(|other| other.name == capture.name)
+            {
+                info.captures.push(capture);
+            }
+            captured = true;
+        }
+        if let (true, CaptureMode::Box, Some(decl)) = (captured, capture.mode, decl) {
+            self.boxed.insert(decl);
+        }
+    }
+
     /// Get the current substitution.
     pub fn subst(&mut self) -> &mut Subst {
         &mut self.unifier.subst
@@ -127,18 +303,135 @@ impl<'ctx> Context<'ctx> {
         self.unifier.unify(ty1, ty2, span)
     }
 
+    /// Enter a let initializer (see [`Subst::enter_level`]).
+    pub fn enter_level(&mut self) {
+        self.unifier.subst.enter_level();
+    }
+
+    /// Leave a let initializer (see [`Subst::exit_level`]).
+    pub fn exit_level(&mut self) {
+        self.unifier.subst.exit_level();
+    }
+
+    /// Generalize a type over the variables created at a deeper level.
+    ///
+    /// Must be called after `exit_level`. Unlike [`TypeEnv::generalize`],
+    /// this never scans the environment.
+    pub fn generalize(&mut self, ty: &Ty) -> Scheme {
+        let vars = self.unifier.subst.generalizable_vars(ty);
+        let ty = self.unifier.subst.apply_ty(ty);
+        Scheme::poly(vars, ty)
+    }
+
     /// Create a fresh type variable.
     pub fn fresh_var(&mut self) -> u32 {
-        self.env.fresh_var()
+        self.unifier.subst.fresh_var()
+    }
+
+    /// Require a type to satisfy a bound once unification has run.
+    pub fn require(&mut self, ty: &Ty, bound: Bound, span: Span) {
+        self.constraints.push(Constraint::Bound {
+            ty: ty.clone(),
+            bound,
+            span,
+        });
+    }
+
+    /// Record a typed hole and return the type it stands for.
+    ///
+    /// The hole type-checks as any type; its diagnostic is produced later by
+    /// [`Context::hole_diagnostics`], once the surrounding code has
+    /// constrained it.
+    pub fn record_hole(&mut self, span: Span) -> Ty {
+        let var = self.fresh_var();
+        self.holes.push(TypedHole {
+            var,
+            bindings: self.env.visible_bindings(),
+            span,
+        });
+        Ty::TypeVar(var)
+    }
+
+    /// Build a diagnostic for every recorded hole.
+    ///
+    /// Each diagnostic reports the type the hole was inferred to have and the
+    /// bindings in scope at the hole whose type is compatible with it.
+    pub fn hole_diagnostics(&mut self) -> Vec<TypeError> {
+        let holes = self.holes.clone();
+        holes
+            .into_iter()
+            .map(|hole| {
+                let expected = self.unifier.subst.apply_ty(&Ty::TypeVar(hole.var));
+                let fitting: Vec<Symbol> = hole
+                    .bindings
+                    .iter()
+                    .filter(|(_, scheme)| self.fits(scheme, &expected, hole.span))
+                    .map(|(sym, _)| *sym)
+                    .collect();
+                let mut candidates: Vec<String> = fitting
+                    .into_iter()
+                    .filter_map(|sym| self.interner.resolve(sym).map(str::to_string))
+                    .collect();
+                candidates.sort();
+                TypeError::TypedHole {
+                    expected: expected.display(self.interner).to_string(),
+                    candidates,
+                    span: hole.span,
+                }
+            })
+            .collect()
+    }
+
+    /// Check whether a binding could fill a hole of the given type.
+    ///
+    /// The substitution is restored afterwards, so trial unification never
+    /// leaks bindings.
+    fn fits(&mut self, scheme: &Scheme, expected: &Ty, span: Span) -> bool {
+        let snapshot = self.unifier.subst.clone();
+        let ty = scheme.instantiate(&mut self.unifier.subst);
+        let fits = self.unify(&ty, expected, span).is_ok();
+        self.unifier.subst = snapshot;
+        fits
+    }
+
+    /// Suggest names similar to `name` from a set of candidate symbols.
+    ///
+    /// See [`suggest`] for the ranking rules.
+    pub fn suggest(&self, name: Symbol, candidates: impl IntoIterator<Item = Symbol>) -> Vec<String> {
+        let name = self.interner.resolve(name).unwrap_or("");
+        let candidates: Vec<&str> = candidates
+            .into_iter()
+            .filter_map(|sym| self.interner.resolve(sym))
+            .collect();
+        suggest(name, candidates)
+    }
+
+    /// Suggest in-scope bindings with names similar to `name`.
+    pub fn suggest_binding(&self, name: Symbol) -> Vec<String> {
+        let bindings = self.env.visible_bindings();
+        self.suggest(name, bindings.into_iter().map(|(sym, _)| sym))
+    }
+
+    /// Map a type name written as `Self` to the enclosing type's name.
+    pub fn resolve_self_name(&self, name: Symbol) -> Symbol {
+        match &self.current_self {
+            Some(current) if self.interner.resolve(name) == Some("Self") => current.name,
+            _ => name,
+        }
+    }
+
+    /// Explain why `self` did not resolve at `span`.
+    pub fn self_error(&self, span: Span) -> TypeError {
+        match &self.current_self {
+            Some(current) if current.is_static => TypeError::SelfInStaticMethod { span },
+            _ => TypeError::SelfOutsideMethod { span },
+        }
     }
 
     /// Look up a symbol in the environment.
     pub fn lookup(&self, name: &str) -> Option<&Scheme> {
-        use oxidex_mem::Symbol;
-        // For now, we'll use a simple hash-based approach
-        // In a real implementation, we'd look up the symbol in the interner
-        // This is a placeholder for the actual symbol lookup
-        self.env.lookup(Symbol::new(0))
+        let sym = self.interner.get_symbol(name)?;
+        self.env.lookup(sym)
     }
 }
 
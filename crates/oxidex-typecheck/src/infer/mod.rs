@@ -2,8 +2,12 @@
 //!
 //! This module implements Hindley-Milner type inference with bidirectional checking.
 
+pub mod constraint;
 pub mod context;
+pub mod overload;
 pub mod unify;
 
-pub use context::Context;
+pub use constraint::{Bound, Constraint, find_method, satisfies, solve_constraints, unsatisfied};
+pub use context::{Capture, CaptureMode, ClosureInfo, Context, CurrentSelf, TypedHole};
+pub use overload::resolve_overload;
 pub use unify::Unifier;
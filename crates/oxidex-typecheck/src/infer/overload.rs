@@ -0,0 +1,389 @@
+//! Overload resolution for functions and methods.
+//!
+//! When several signatures share a name, a call is resolved by ranking every
+//! viable candidate against the call site:
+//!
+//! 1. **Arity**: the candidate must take exactly as many parameters as there are arguments
+//! 2. **Labels**: every labeled argument must match the candidate's label at that position
+//! 3. **Types**: every argument must unify with the corresponding parameter
+//!
+//! Viable candidates are scored per argument: an argument whose type is
+//! already identical to the parameter type scores higher than one that only
+//! unifies by binding type variables. This makes concrete overloads win over
+//! generic ones. If the best score is shared by several candidates the call
+//! is rejected with `AmbiguousOverload`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! fn show(value: Int) -> String { ... }
+//! fn show(value: Bool) -> String { ... }
+//! fn show<T>(value: T) -> String { ... }
+//!
+//! show(42)    // picks show(value: Int): exact match beats the generic overload
+//! show(true)  // picks show(value: Bool)
+//! show("hi")  // picks show<T>(value: T)
+//! ```
+
+use crate::context::Scheme;
+use crate::error::{Result, TypeError};
+use crate::infer::Context;
+use crate::types::Ty;
+use oxidex_mem::Symbol;
+use oxidex_syntax::Span;
+
+/// Score awarded to an argument whose type is identical to the parameter type.
+const EXACT_MATCH: u32 = 2;
+
+/// Score awarded to an argument that only matches through unification.
+const UNIFIED_MATCH: u32 = 1;
+
+/// Resolve a call against a set of overloaded signatures.
+///
+/// Each scheme in `candidates` must instantiate to a `Ty::Function`.
+/// On success, the winning signature is unified with the arguments and its
+/// index in `candidates` is returned together with its return type.
+///
+/// # Errors
+///
+/// - `NoMatchingOverload` if no candidate accepts the arguments
+/// - `AmbiguousOverload` if several candidates share the best rank
+pub fn resolve_overload(
+    ctx: &mut Context<'_>,
+    name: Symbol,
+    candidates: &[Scheme],
+    arg_labels: &[Option<Symbol>],
+    arg_tys: &[Ty],
+    span: Span,
+) -> Result<(usize, Ty)> {
+    // Rank every candidate without committing any bindings
+    let mut ranked = Vec::new();
+    for (index, scheme) in candidates.iter().enumerate() {
+        if let Some(score) = rank_candidate(ctx, scheme, arg_labels, arg_tys, span) {
+            ranked.push((index, score));
+        }
+    }
+
+    let Some(best) = ranked.iter().map(|&(_, score)| score).max() else {
+        return Err(TypeError::NoMatchingOverload {
+            name: resolve_name(ctx, name),
+            candidates: candidates
+                .iter()
+                .map(|scheme| render_signature(ctx, name, &scheme.ty))
+                .collect(),
+            span,
+        });
+    };
+
+    let winners: Vec<usize> = ranked
+        .iter()
+        .filter(|&&(_, score)| score == best)
+        .map(|&(index, _)| index)
+        .collect();
+
+    if winners.len() > 1 {
+        return Err(TypeError::AmbiguousOverload {
+            name: resolve_name(ctx, name),
+            candidates: winners
+                .iter()
+                .map(|&index| render_signature(ctx, name, &candidates[index].ty))
+                .collect(),
+            span,
+        });
+    }
+
+    // Commit the winning candidate
+    let winner = winners[0];
+    let ty = candidates[winner].instantiate(ctx.subst());
+    let Ty::Function {
+        params,
+        return_type,
+        ..
+    } = ty
+    else {
+        unreachable!("ranked candidates are always function types");
+    };
+
+    for (arg, param) in arg_tys.iter().zip(&params) {
+        ctx.unify(arg, param, span)?;
+    }
+
+    Ok((winner, ctx.subst().apply_ty(&return_type)))
+}
+
+/// Rank a single candidate against the call site.
+///
+/// Returns `None` if the candidate is not viable. The substitution is
+/// restored before returning, so ranking never leaks bindings.
+fn rank_candidate(
+    ctx: &mut Context<'_>,
+    scheme: &Scheme,
+    arg_labels: &[Option<Symbol>],
+    arg_tys: &[Ty],
+    span: Span,
+) -> Option<u32> {
+    let snapshot = ctx.subst().clone();
+    let score = try_candidate(ctx, scheme, arg_labels, arg_tys, span);
+    *ctx.subst() = snapshot;
+    score
+}
+
+/// Instantiate a candidate and trial-unify it with the arguments.
+fn try_candidate(
+    ctx: &mut Context<'_>,
+    scheme: &Scheme,
+    arg_labels: &[Option<Symbol>],
+    arg_tys: &[Ty],
+    span: Span,
+) -> Option<u32> {
+    let Ty::Function { params, labels, .. } = scheme.instantiate(ctx.subst()) else {
+        return None;
+    };
+
+    if params.len() != arg_tys.len() {
+        return None;
+    }
+
+    // A labeled argument must name the parameter it is passed to
+    for (index, arg_label) in arg_labels.iter().enumerate() {
+        if let Some(label) = arg_label
+            && labels.get(index).copied().flatten() != Some(*label)
+        {
+            return None;
+        }
+    }
+
+    let mut score = 0;
+    for (arg, param) in arg_tys.iter().zip(&params) {
+        let arg = ctx.subst().apply_ty(arg);
+        let param = ctx.subst().apply_ty(param);
+
+        score += if arg == param && arg.free_vars().is_empty() {
+            EXACT_MATCH
+        } else {
+            UNIFIED_MATCH
+        };
+
+        ctx.unify(&arg, &param, span).ok()?;
+    }
+
+    Some(score)
+}
+
+/// Render a candidate signature for diagnostics, e.g. `add(Int) -> Int`.
+fn render_signature(ctx: &Context<'_>, name: Symbol, ty: &Ty) -> String {
+    format!("{}{}", resolve_name(ctx, name), ty.display(ctx.interner))
+}
+
+/// Resolve a symbol to its name for diagnostics.
+fn resolve_name(ctx: &Context<'_>, name: Symbol) -> String {
+    ctx.interner.resolve(name).unwrap_or("").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PrimTy;
+    use oxidex_mem::StringInterner;
+
+    fn span() -> Span {
+        Span::new(0, 0, 0, 0, 0, 0)
+    }
+
+    fn unary(param: Ty, ret: Ty, label: Option<Symbol>) -> Scheme {
+        Scheme::mono(Ty::Function {
+            params: vec![param],
+            return_type: Box::new(ret),
+            labels: vec![label],
+        })
+    }
+
+    #[test]
+    fn test_resolve_by_type() {
+        let mut interner = StringInterner::new();
+        let show = interner.intern("show");
+        let mut ctx = Context::new(&interner);
+
+        let candidates = vec![
+            unary(Ty::Primitive(PrimTy::Int64), Ty::Primitive(PrimTy::Int64), None),
+            unary(Ty::Primitive(PrimTy::Bool), Ty::Primitive(PrimTy::Bool), None),
+        ];
+
+        let (_, ty) = resolve_overload(
+            &mut ctx,
+            show,
+            &candidates,
+            &[None],
+            &[Ty::Primitive(PrimTy::Bool)],
+            span(),
+        )
+        .unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::Bool));
+    }
+
+    #[test]
+    fn test_resolve_by_label() {
+        let mut interner = StringInterner::new();
+        let move_to = interner.intern("move");
+        let x = interner.intern("x");
+        let y = interner.intern("y");
+        let mut ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        let candidates = vec![
+            unary(int.clone(), Ty::Primitive(PrimTy::Bool), Some(x)),
+            unary(int.clone(), Ty::Primitive(PrimTy::String), Some(y)),
+        ];
+
+        let (_, ty) = resolve_overload(&mut ctx, move_to, &candidates, &[Some(y)], &[int], span())
+            .unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::String));
+    }
+
+    #[test]
+    fn test_resolve_by_arity() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let mut ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        let candidates = vec![
+            unary(int.clone(), Ty::Primitive(PrimTy::Bool), None),
+            Scheme::mono(Ty::Function {
+                params: vec![int.clone(), int.clone()],
+                return_type: Box::new(Ty::Primitive(PrimTy::String)),
+                labels: vec![None, None],
+            }),
+        ];
+
+        let (_, ty) = resolve_overload(
+            &mut ctx,
+            f,
+            &candidates,
+            &[None, None],
+            &[int.clone(), int],
+            span(),
+        )
+        .unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::String));
+    }
+
+    #[test]
+    fn test_concrete_overload_beats_generic() {
+        let mut interner = StringInterner::new();
+        let show = interner.intern("show");
+        let mut ctx = Context::new(&interner);
+
+        // show<T>(T) -> T and show(Int) -> Bool
+        let generic = Scheme::poly(
+            vec![100],
+            Ty::Function {
+                params: vec![Ty::TypeVar(100)],
+                return_type: Box::new(Ty::TypeVar(100)),
+                labels: vec![None],
+            },
+        );
+        let concrete = unary(Ty::Primitive(PrimTy::Int64), Ty::Primitive(PrimTy::Bool), None);
+        let candidates = vec![generic, concrete];
+
+        let (_, ty) = resolve_overload(
+            &mut ctx,
+            show,
+            &candidates,
+            &[None],
+            &[Ty::Primitive(PrimTy::Int64)],
+            span(),
+        )
+        .unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::Bool));
+
+        // A String argument only fits the generic overload
+        let (_, ty) = resolve_overload(
+            &mut ctx,
+            show,
+            &candidates,
+            &[None],
+            &[Ty::Primitive(PrimTy::String)],
+            span(),
+        )
+        .unwrap();
+        assert_eq!(ty, Ty::Primitive(PrimTy::String));
+    }
+
+    #[test]
+    fn test_ambiguous_overload() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let mut ctx = Context::new(&interner);
+
+        let int = Ty::Primitive(PrimTy::Int64);
+        let candidates = vec![
+            unary(int.clone(), Ty::Primitive(PrimTy::Bool), None),
+            unary(int.clone(), Ty::Primitive(PrimTy::String), None),
+        ];
+
+        let err = resolve_overload(&mut ctx, f, &candidates, &[None], &[int], span())
+            .unwrap_err();
+        match err {
+            TypeError::AmbiguousOverload { name, candidates, .. } => {
+                assert_eq!(name, "f");
+                assert_eq!(
+                    candidates,
+                    vec!["f(Int) -> Bool".to_string(), "f(Int) -> String".to_string()]
+                );
+            }
+            other => panic!("Expected AmbiguousOverload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_no_matching_overload() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let mut ctx = Context::new(&interner);
+
+        let candidates = vec![
+            unary(Ty::Primitive(PrimTy::Int64), Ty::Primitive(PrimTy::Unit), None),
+            unary(Ty::Primitive(PrimTy::Bool), Ty::Primitive(PrimTy::Unit), None),
+        ];
+
+        let err = resolve_overload(
+            &mut ctx,
+            f,
+            &candidates,
+            &[None],
+            &[Ty::Primitive(PrimTy::String)],
+            span(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            TypeError::NoMatchingOverload { ref candidates, .. } if candidates.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_ranking_does_not_leak_bindings() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let mut ctx = Context::new(&interner);
+
+        let var = ctx.fresh_var();
+        let candidates = vec![
+            unary(Ty::Primitive(PrimTy::Int64), Ty::Primitive(PrimTy::Unit), None),
+            unary(Ty::Primitive(PrimTy::Bool), Ty::Primitive(PrimTy::Unit), None),
+        ];
+
+        // The argument type is unknown, so both candidates rank equally
+        let result = resolve_overload(
+            &mut ctx,
+            f,
+            &candidates,
+            &[None],
+            &[Ty::TypeVar(var)],
+            span(),
+        );
+        assert!(matches!(result, Err(TypeError::AmbiguousOverload { .. })));
+        assert_eq!(ctx.subst().apply_ty(&Ty::TypeVar(var)), Ty::TypeVar(var));
+    }
+}
@@ -160,8 +160,8 @@ impl<'ctx> Unifier<'ctx> {
                 self.unify(e1, e2, span)
             }
 
-            // Self type
-            (Ty::SelfType, Ty::SelfType) => Ok(()),
+            // Types without parameters
+            (Ty::Range, Ty::Range) | (Ty::SelfType, Ty::SelfType) => Ok(()),
 
             // Never type (bottom) - unifies with anything
             (Ty::Never, _) | (_, Ty::Never) => Ok(()),
@@ -185,6 +185,13 @@ impl<'ctx> Unifier<'ctx> {
 
         match rep {
             Ty::TypeVar(other_var) if other_var == var => {
+                // Unifying a variable with itself (possibly through links) is a no-op
+                if let Ty::TypeVar(ty_var) = ty
+                    && matches!(self.subst.lookup_rep(*ty_var), Ok(Ty::TypeVar(r)) if r == var)
+                {
+                    return Ok(());
+                }
+
                 // Variable is unbound - bind it
                 // Occurs check: prevent infinite types
                 if ty.occurs_in(var) {
@@ -197,9 +204,8 @@ impl<'ctx> Unifier<'ctx> {
             }
 
             Ty::TypeVar(other_var) => {
-                // Variable is bound to another variable - union them
-                self.subst.union(var, other_var);
-                Ok(())
+                // Variable is linked to another unbound variable - unify its representative
+                self.unify_var(other_var, ty, span)
             }
 
             _ => {
@@ -255,6 +261,21 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_unify_var_with_itself() {
+        let mut subst = Subst::new();
+        let a = subst.fresh_var();
+        let b = subst.fresh_var();
+        let mut unifier = Unifier::new(subst);
+        let span = Span::new(0, 0, 0, 0, 0, 0);
+
+        unifier.unify(&Ty::TypeVar(a), &Ty::TypeVar(a), span).unwrap();
+
+        // Also through a link
+        unifier.unify(&Ty::TypeVar(a), &Ty::TypeVar(b), span).unwrap();
+        unifier.unify(&Ty::TypeVar(b), &Ty::TypeVar(a), span).unwrap();
+    }
+
     #[test]
     fn test_unify_tuples() {
         let subst = Subst::new();
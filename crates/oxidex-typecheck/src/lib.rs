@@ -5,11 +5,13 @@
 //! - Constraint solving
 //! - Type checking and validation
 //! - Protocol conformance checking
+//! - Incremental re-checking of edited declarations
 //!
 //! **Phase:** 6 - Type Checker
 //! **Status:** In Progress (Phase 6.2)
 
 #![warn(missing_docs)]
+#![allow(clippy::result_large_err)]
 
 // Type representation and operations
 pub mod types;
@@ -26,8 +28,15 @@ pub mod infer;
 // Type checking validators
 pub mod check;
 
+// Incremental checking
+pub mod query;
+
+// Signatures of the standard builtins
+pub mod builtins;
+
 // Re-exports for convenience
 pub use context::{Scheme, Subst, TypeEnv};
 pub use error::Result;
 pub use infer::Context as InferContext;
+pub use query::QueryCache;
 pub use types::{PrimTy, Ty};
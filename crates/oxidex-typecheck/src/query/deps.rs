@@ -0,0 +1,674 @@
+//! Dependency extraction and interface hashing for declarations.
+//!
+//! A declaration's check result depends on its own syntax and on the
+//! *interfaces* of the declarations it refers to: signatures, field types,
+//! variants, and so on, but not their bodies. This module provides:
+//!
+//! - [`referenced_names`]: every name a declaration may refer to
+//! - [`interface_names`]: every name a declaration's interface refers to
+//! - [`interface_hash`]: a span-insensitive hash of a declaration's interface
+//!
+//! Name collection over-approximates: it records every identifier, path
+//! segment, type name, and method name. Extra names only cause extra
+//! invalidation, never a stale result.
+
+use oxidex_mem::Symbol;
+use oxidex_syntax::ast::decl::{Decl, EnumVariant, FnDecl, FnParam, StructField};
+use oxidex_syntax::ast::expr::{Expr, InterpolationPart};
+use oxidex_syntax::ast::pat::Pattern;
+use oxidex_syntax::ast::stmt::Stmt;
+use oxidex_syntax::ast::ty::Type;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Get the name a top-level declaration introduces.
+///
+/// Impl blocks introduce no name of their own; they extend the interface of
+/// the type they implement (see [`impl_target`]).
+pub fn decl_name(decl: &Decl<'_>) -> Option<Symbol> {
+    match decl {
+        Decl::Fn { name, .. }
+        | Decl::Struct { name, .. }
+        | Decl::Class { name, .. }
+        | Decl::Enum { name, .. }
+        | Decl::Protocol { name, .. }
+        | Decl::Const { name, .. }
+        | Decl::Static { name, .. }
+        | Decl::TypeAlias { name, .. } => Some(*name),
+        Decl::Impl { .. } | Decl::Import { .. } | Decl::Extern { .. } => None,
+    }
+}
+
+/// Get the names of the foreign functions an `extern` block declares.
+pub fn extern_names(decl: &Decl<'_>) -> Vec<Symbol> {
+    match decl {
+        Decl::Extern { functions, .. } => functions.iter().map(|function| function.name).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Get the type an impl block extends, if it is a single-segment path.
+pub fn impl_target(decl: &Decl<'_>) -> Option<Symbol> {
+    match decl {
+        Decl::Impl { type_path, .. } if type_path.len() == 1 => Some(type_path[0]),
+        _ => None,
+    }
+}
+
+/// Collect every name a declaration may refer to, including its body.
+pub fn referenced_names(decl: &Decl<'_>) -> HashSet<Symbol> {
+    let mut names = interface_names(decl);
+    match decl {
+        Decl::Fn { body, .. } => expr_names(body, &mut names),
+        Decl::Const { value, .. } => expr_names(value, &mut names),
+        Decl::Static { init: Some(init), .. } => expr_names(init, &mut names),
+        _ => {}
+    }
+    names
+}
+
+/// Collect every name a declaration's interface refers to.
+pub fn interface_names(decl: &Decl<'_>) -> HashSet<Symbol> {
+    let mut names = HashSet::new();
+    match decl {
+        Decl::Fn {
+            params, return_type, ..
+        } => signature_names(params, return_type.as_ref(), &mut names),
+        Decl::Struct {
+            fields, protocols, ..
+        } => {
+            field_names(fields, &mut names);
+            names.extend(protocols.iter().flatten());
+        }
+        Decl::Class {
+            superclass,
+            fields,
+            protocols,
+            ..
+        } => {
+            names.extend(superclass.iter().flatten());
+            field_names(fields, &mut names);
+            names.extend(protocols.iter().flatten());
+        }
+        Decl::Enum {
+            variants,
+            methods,
+            protocols,
+            ..
+        } => {
+            for variant in variants {
+                match variant {
+                    EnumVariant::Unit { .. } => {}
+                    EnumVariant::Tuple { fields, .. } => {
+                        for ty in fields {
+                            type_names(ty, &mut names);
+                        }
+                    }
+                    EnumVariant::Struct { fields, .. } => field_names(fields, &mut names),
+                }
+            }
+            for method in methods {
+                signature_names(&method.params, method.return_type.as_ref(), &mut names);
+            }
+            names.extend(protocols.iter().flatten());
+        }
+        Decl::Protocol { methods, .. } => {
+            for method in methods {
+                signature_names(&method.params, method.return_type.as_ref(), &mut names);
+            }
+        }
+        Decl::Impl {
+            type_path,
+            protocol,
+            methods,
+            ..
+        } => {
+            names.extend(type_path);
+            names.extend(protocol.iter().flatten());
+            for method in methods {
+                signature_names(&method.params, method.return_type.as_ref(), &mut names);
+            }
+        }
+        Decl::Const {
+            type_annotation, ..
+        }
+        | Decl::Static {
+            type_annotation, ..
+        } => type_names(type_annotation, &mut names),
+        Decl::TypeAlias { target, .. } => type_names(target, &mut names),
+        Decl::Import { .. } => {}
+        Decl::Extern { functions, .. } => {
+            for function in functions {
+                signature_names(&function.params, function.return_type.as_ref(), &mut names);
+            }
+        }
+    }
+    names
+}
+
+/// Hash a declaration's interface, ignoring bodies and source locations.
+///
+/// Two declarations with the same interface hash are interchangeable for
+/// every declaration that depends on them.
+pub fn interface_hash(decl: &Decl<'_>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    std::mem::discriminant(decl).hash(&mut hasher);
+    match decl {
+        Decl::Fn {
+            is_mut,
+            is_static,
+            name,
+            generics,
+            params,
+            return_type,
+            ..
+        } => {
+            (is_mut, is_static, name, generics).hash(&mut hasher);
+            hash_signature(params, return_type.as_ref(), &mut hasher);
+        }
+        Decl::Struct {
+            name,
+            generics,
+            fields,
+            protocols,
+            ..
+        } => {
+            (name, generics, protocols).hash(&mut hasher);
+            hash_fields(fields, &mut hasher);
+        }
+        Decl::Class {
+            name,
+            generics,
+            superclass,
+            fields,
+            protocols,
+            ..
+        } => {
+            (name, generics, superclass, protocols).hash(&mut hasher);
+            hash_fields(fields, &mut hasher);
+        }
+        Decl::Enum {
+            name,
+            generics,
+            variants,
+            methods,
+            protocols,
+            ..
+        } => {
+            (name, generics, protocols).hash(&mut hasher);
+            for variant in variants {
+                std::mem::discriminant(variant).hash(&mut hasher);
+                match variant {
+                    EnumVariant::Unit { name, .. } => name.hash(&mut hasher),
+                    EnumVariant::Tuple { name, fields, .. } => {
+                        name.hash(&mut hasher);
+                        for ty in fields {
+                            hash_type(ty, &mut hasher);
+                        }
+                    }
+                    EnumVariant::Struct { name, fields, .. } => {
+                        name.hash(&mut hasher);
+                        hash_fields(fields, &mut hasher);
+                    }
+                }
+            }
+            hash_methods(methods, &mut hasher);
+        }
+        Decl::Protocol {
+            name,
+            generics,
+            methods,
+            ..
+        } => {
+            (name, generics).hash(&mut hasher);
+            for method in methods {
+                method.name.hash(&mut hasher);
+                hash_signature(&method.params, method.return_type.as_ref(), &mut hasher);
+            }
+        }
+        Decl::Impl {
+            type_path,
+            protocol,
+            methods,
+            ..
+        } => {
+            (type_path, protocol).hash(&mut hasher);
+            hash_methods(methods, &mut hasher);
+        }
+        Decl::Const {
+            name,
+            type_annotation,
+            ..
+        } => {
+            name.hash(&mut hasher);
+            hash_type(type_annotation, &mut hasher);
+        }
+        Decl::Static {
+            name,
+            type_annotation,
+            mutable,
+            ..
+        } => {
+            (name, mutable).hash(&mut hasher);
+            hash_type(type_annotation, &mut hasher);
+        }
+        Decl::TypeAlias {
+            name,
+            generics,
+            target,
+            ..
+        } => {
+            (name, generics).hash(&mut hasher);
+            hash_type(target, &mut hasher);
+        }
+        Decl::Import { path, .. } => path.hash(&mut hasher),
+        Decl::Extern { library, functions, .. } => {
+            library.hash(&mut hasher);
+            for function in functions {
+                function.name.hash(&mut hasher);
+                hash_signature(&function.params, function.return_type.as_ref(), &mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// Collect names from a function signature.
+fn signature_names(params: &[FnParam], return_type: Option<&Type>, names: &mut HashSet<Symbol>) {
+    for param in params {
+        type_names(&param.type_annotation, names);
+    }
+    if let Some(ty) = return_type {
+        type_names(ty, names);
+    }
+}
+
+/// Collect names from struct field types.
+fn field_names(fields: &[StructField], names: &mut HashSet<Symbol>) {
+    for field in fields {
+        type_names(&field.type_annotation, names);
+    }
+}
+
+/// Collect names from a type annotation.
+fn type_names(ty: &Type, names: &mut HashSet<Symbol>) {
+    match ty {
+        Type::Simple { name, .. } => {
+            names.insert(*name);
+        }
+        Type::Generic { name, params, .. } => {
+            names.insert(*name);
+            for param in params {
+                type_names(param, names);
+            }
+        }
+        Type::Tuple { elements, .. } => {
+            for element in elements {
+                type_names(element, names);
+            }
+        }
+        Type::Function {
+            params,
+            return_type,
+            ..
+        } => {
+            for param in params {
+                type_names(param, names);
+            }
+            type_names(return_type, names);
+        }
+        Type::Array { element, .. } => type_names(element, names),
+        Type::Dict { key, value, .. } => {
+            type_names(key, names);
+            type_names(value, names);
+        }
+        Type::Optional { inner, .. } => type_names(inner, names),
+        Type::SelfType { .. } => {}
+    }
+}
+
+/// Collect names from an expression.
+fn expr_names(expr: &Expr<'_>, names: &mut HashSet<Symbol>) {
+    match expr {
+        Expr::IntegerLiteral { .. }
+        | Expr::FloatLiteral { .. }
+        | Expr::StringLiteral { .. }
+        | Expr::BoolLiteral { .. }
+        | Expr::Nil { .. }
+        | Expr::Hole { .. } => {}
+        Expr::Identifier(name) => {
+            names.insert(*name);
+        }
+        Expr::Path { segments, .. } => names.extend(segments),
+        Expr::Unary { operand, .. } => expr_names(operand, names),
+        Expr::Binary { left, right, .. } => {
+            expr_names(left, names);
+            expr_names(right, names);
+        }
+        Expr::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            expr_names(condition, names);
+            expr_names(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                expr_names(else_branch, names);
+            }
+        }
+        Expr::Match {
+            scrutinee, arms, ..
+        } => {
+            expr_names(scrutinee, names);
+            for arm in arms {
+                pattern_names(&arm.pattern, names);
+                if let Some(guard) = arm.guard {
+                    expr_names(guard, names);
+                }
+                expr_names(arm.body, names);
+            }
+        }
+        Expr::Block { stmts, expr, .. } => {
+            for stmt in stmts {
+                stmt_names(stmt, names);
+            }
+            if let Some(expr) = expr {
+                expr_names(expr, names);
+            }
+        }
+        Expr::ForLoop {
+            pattern,
+            iter,
+            body,
+            ..
+        } => {
+            pattern_names(pattern, names);
+            expr_names(iter, names);
+            expr_names(body, names);
+        }
+        Expr::WhileLoop {
+            condition, body, ..
+        } => {
+            expr_names(condition, names);
+            expr_names(body, names);
+        }
+        Expr::Call { callee, args, .. } => {
+            expr_names(callee, names);
+            for arg in args {
+                expr_names(arg.value, names);
+            }
+        }
+        Expr::MethodCall {
+            receiver,
+            method,
+            args,
+            ..
+        } => {
+            expr_names(receiver, names);
+            names.insert(*method);
+            for arg in args {
+                expr_names(arg.value, names);
+            }
+        }
+        Expr::Closure {
+            params,
+            return_type,
+            body,
+            ..
+        } => {
+            for param in params {
+                type_names(&param.type_annotation, names);
+            }
+            if let Some(return_type) = return_type {
+                type_names(return_type, names);
+            }
+            expr_names(body, names);
+        }
+        Expr::Struct {
+            type_path, fields, ..
+        } => {
+            names.extend(type_path);
+            for field in fields {
+                match field.value {
+                    Some(value) => expr_names(value, names),
+                    // Shorthand `Point { x }` refers to the binding `x`
+                    None => {
+                        names.insert(field.name);
+                    }
+                }
+            }
+        }
+        Expr::Enum {
+            type_path, payload, ..
+        } => {
+            names.extend(type_path);
+            if let Some(payload) = payload {
+                expr_names(payload, names);
+            }
+        }
+        Expr::Array { elements, .. } => {
+            for element in elements {
+                expr_names(element, names);
+            }
+        }
+        Expr::Dict { entries, .. } => {
+            for entry in entries {
+                expr_names(entry.key, names);
+                expr_names(entry.value, names);
+            }
+        }
+        Expr::Field { object, .. } => expr_names(object, names),
+        Expr::Index {
+            collection, index, ..
+        } => {
+            expr_names(collection, names);
+            expr_names(index, names);
+        }
+        Expr::Paren { expr, .. } | Expr::Comptime { expr, .. } => expr_names(expr, names),
+        Expr::Interpolation { parts, .. } => {
+            for part in parts {
+                if let InterpolationPart::Expr(expr) = part {
+                    expr_names(expr, names);
+                }
+            }
+        }
+    }
+}
+
+/// Collect names from a statement.
+fn stmt_names(stmt: &Stmt<'_>, names: &mut HashSet<Symbol>) {
+    match stmt {
+        Stmt::Let {
+            type_annotation,
+            init,
+            ..
+        }
+        | Stmt::Mut {
+            type_annotation,
+            init,
+            ..
+        } => {
+            if let Some(ty) = type_annotation {
+                type_names(ty, names);
+            }
+            if let Some(init) = init {
+                expr_names(init, names);
+            }
+        }
+        Stmt::Return { value, .. } => {
+            if let Some(value) = value {
+                expr_names(value, names);
+            }
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            expr_names(condition, names);
+            expr_names(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                expr_names(else_branch, names);
+            }
+        }
+        Stmt::Guard {
+            condition,
+            else_branch,
+            ..
+        } => {
+            expr_names(condition, names);
+            expr_names(else_branch, names);
+        }
+        Stmt::Defer { body, .. } => expr_names(body, names),
+        Stmt::Match {
+            scrutinee, arms, ..
+        } => {
+            expr_names(scrutinee, names);
+            for arm in arms {
+                pattern_names(&arm.pattern, names);
+                if let Some(guard) = arm.guard {
+                    expr_names(guard, names);
+                }
+                expr_names(arm.body, names);
+            }
+        }
+        Stmt::ForLoop {
+            pattern,
+            iter,
+            body,
+            ..
+        } => {
+            pattern_names(pattern, names);
+            expr_names(iter, names);
+            expr_names(body, names);
+        }
+        Stmt::WhileLoop {
+            condition, body, ..
+        } => {
+            expr_names(condition, names);
+            expr_names(body, names);
+        }
+        Stmt::Assign { target, value, .. } => {
+            expr_names(target, names);
+            expr_names(value, names);
+        }
+        Stmt::Expr { expr, .. } => expr_names(expr, names),
+    }
+}
+
+/// Collect type names from a pattern.
+fn pattern_names(pattern: &Pattern, names: &mut HashSet<Symbol>) {
+    match pattern {
+        Pattern::Wildcard { .. } | Pattern::Literal { .. } | Pattern::Variable { .. } => {}
+        Pattern::Struct {
+            type_path, fields, ..
+        } => {
+            names.extend(type_path);
+            for field in fields {
+                if let Some(pattern) = &field.pattern {
+                    pattern_names(pattern, names);
+                }
+            }
+        }
+        Pattern::Enum {
+            type_path, payload, ..
+        } => {
+            names.extend(type_path);
+            if let Some(payload) = payload {
+                pattern_names(payload, names);
+            }
+        }
+        Pattern::Tuple { elements, .. } => {
+            for element in elements {
+                pattern_names(element, names);
+            }
+        }
+        Pattern::Array { elements, rest, .. } => {
+            for element in elements {
+                pattern_names(element, names);
+            }
+            if let Some(rest) = rest {
+                pattern_names(rest, names);
+            }
+        }
+        Pattern::Or { left, right, .. } => {
+            pattern_names(left, names);
+            pattern_names(right, names);
+        }
+    }
+}
+
+/// Hash a function signature without spans.
+fn hash_signature<H: Hasher>(params: &[FnParam], return_type: Option<&Type>, hasher: &mut H) {
+    params.len().hash(hasher);
+    for param in params {
+        (param.label, param.name).hash(hasher);
+        hash_type(&param.type_annotation, hasher);
+    }
+    return_type.is_some().hash(hasher);
+    if let Some(ty) = return_type {
+        hash_type(ty, hasher);
+    }
+}
+
+/// Hash method signatures without spans.
+fn hash_methods<H: Hasher>(methods: &[FnDecl], hasher: &mut H) {
+    methods.len().hash(hasher);
+    for method in methods {
+        (method.is_mut, method.is_static, method.name, &method.generics).hash(hasher);
+        hash_signature(&method.params, method.return_type.as_ref(), hasher);
+    }
+}
+
+/// Hash struct fields without spans.
+fn hash_fields<H: Hasher>(fields: &[StructField], hasher: &mut H) {
+    fields.len().hash(hasher);
+    for field in fields {
+        field.name.hash(hasher);
+        hash_type(&field.type_annotation, hasher);
+    }
+}
+
+/// Hash a type annotation without spans.
+fn hash_type<H: Hasher>(ty: &Type, hasher: &mut H) {
+    std::mem::discriminant(ty).hash(hasher);
+    match ty {
+        Type::Simple { name, .. } => name.hash(hasher),
+        Type::Generic { name, params, .. } => {
+            name.hash(hasher);
+            params.len().hash(hasher);
+            for param in params {
+                hash_type(param, hasher);
+            }
+        }
+        Type::Tuple { elements, .. } => {
+            elements.len().hash(hasher);
+            for element in elements {
+                hash_type(element, hasher);
+            }
+        }
+        Type::Function {
+            params,
+            return_type,
+            ..
+        } => {
+            params.len().hash(hasher);
+            for param in params {
+                hash_type(param, hasher);
+            }
+            hash_type(return_type, hasher);
+        }
+        Type::Array { element, size, .. } => {
+            size.hash(hasher);
+            hash_type(element, hasher);
+        }
+        Type::Dict { key, value, .. } => {
+            hash_type(key, hasher);
+            hash_type(value, hasher);
+        }
+        Type::Optional { inner, .. } => hash_type(inner, hasher),
+        Type::SelfType { .. } => {}
+    }
+}
@@ -0,0 +1,328 @@
+//! Incremental type checking.
+//!
+//! This module provides a query-style layer over the declaration checker.
+//! Each declaration's check result is cached under a key derived from:
+//!
+//! - The declaration itself (including spans, so cached diagnostics always
+//!   point at the right location)
+//! - The interfaces of every declaration it transitively depends on
+//!
+//! Re-checking a program after an edit only recomputes the declarations whose
+//! key changed. Editing a function body re-checks that function alone; editing
+//! a signature re-checks the function and everything that refers to it.
+//!
+//! Type-defining declarations (structs, classes, enums, protocols, impls,
+//! aliases) are cheap to check and populate the type registry that other
+//! declarations rely on, so they are always re-run. Function, constant, and
+//! static declarations are cached.
+//!
+//! Intended clients are the CLI watch mode and the language server, which
+//! keep one `QueryCache` alive across edits.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut cache = QueryCache::new();
+//!
+//! let results = cache.check_program(&mut Context::new(&interner), &decls)?;
+//! // ... user edits one function body and the file is re-parsed ...
+//! let results = cache.check_program(&mut Context::new(&interner), &edited)?;
+//! assert_eq!(cache.stats().misses, 1);
+//! ```
+
+pub mod deps;
+
+use crate::check::{check_decl, check_recursive_types, collect_signatures};
+use crate::error::Result;
+use crate::infer::Context;
+use oxidex_mem::Symbol;
+use oxidex_syntax::Spanned;
+use oxidex_syntax::ast::decl::Decl;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Hit and miss counts for the most recent `check_program` run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Declarations whose result was reused
+    pub hits: usize,
+    /// Declarations that were checked
+    pub misses: usize,
+}
+
+/// Cache of per-declaration check results.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    /// Check results keyed by declaration key.
+    results: HashMap<u64, Result<()>>,
+
+    /// Statistics for the most recent run.
+    stats: CacheStats,
+}
+
+impl QueryCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of cached results.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Check if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Drop every cached result.
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    /// Get statistics for the most recent `check_program` run.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Check a program, reusing cached results where possible.
+    ///
+    /// Returns one result per declaration, in source order. Entries that were
+    /// not used by this run are evicted, so the cache only ever holds results
+    /// for the latest version of the program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if signature collection fails. Errors in individual
+    /// declarations are reported in the returned vector instead.
+    pub fn check_program<'ctx>(
+        &mut self,
+        ctx: &mut Context<'ctx>,
+        decls: &[Decl<'ctx>],
+    ) -> Result<Vec<Result<()>>> {
+        self.stats = CacheStats::default();
+        collect_signatures(ctx, decls)?;
+
+        let mut results: Vec<Option<Result<()>>> = vec![None; decls.len()];
+
+        // Type-defining declarations populate the registry, so always run them first
+        for (index, decl) in decls.iter().enumerate() {
+            if !is_cacheable(decl) {
+                results[index] = Some(check_decl(ctx, decl));
+            }
+        }
+
+        // Attach a size cycle to the declaration it is reported at
+        if let Err(err) = check_recursive_types(ctx, decls)
+            && let Some(index) = decls.iter().position(|d| d.span() == err.span())
+            && matches!(results[index], Some(Ok(())))
+        {
+            results[index] = Some(Err(err));
+        }
+
+        let index = NameIndex::new(decls);
+        let mut live = HashSet::new();
+        for (i, decl) in decls.iter().enumerate() {
+            if !is_cacheable(decl) {
+                continue;
+            }
+
+            let key = decl_key(decls, &index, i);
+            live.insert(key);
+
+            let result = match self.results.get(&key) {
+                Some(cached) => {
+                    self.stats.hits += 1;
+                    cached.clone()
+                }
+                None => {
+                    self.stats.misses += 1;
+                    let result = check_decl(ctx, decl);
+                    self.results.insert(key, result.clone());
+                    result
+                }
+            };
+            results[i] = Some(result);
+        }
+
+        self.results.retain(|key, _| live.contains(key));
+
+        Ok(results.into_iter().map(|r| r.unwrap_or(Ok(()))).collect())
+    }
+}
+
+/// Check whether a declaration's result can be cached.
+fn is_cacheable(decl: &Decl<'_>) -> bool {
+    matches!(
+        decl,
+        Decl::Fn { .. } | Decl::Const { .. } | Decl::Static { .. }
+    )
+}
+
+/// Index from names to the declarations that define or extend them.
+struct NameIndex {
+    by_name: HashMap<Symbol, Vec<usize>>,
+}
+
+impl NameIndex {
+    /// Build the index for a program.
+    fn new(decls: &[Decl<'_>]) -> Self {
+        let mut by_name: HashMap<Symbol, Vec<usize>> = HashMap::new();
+        for (index, decl) in decls.iter().enumerate() {
+            let names = deps::decl_name(decl).or_else(|| deps::impl_target(decl)).into_iter();
+            for name in names.chain(deps::extern_names(decl)) {
+                by_name.entry(name).or_default().push(index);
+            }
+        }
+        Self { by_name }
+    }
+
+    /// Get the declarations for a name.
+    fn get(&self, name: Symbol) -> &[usize] {
+        self.by_name.get(&name).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Compute the cache key of the declaration at `index`.
+///
+/// The key combines the declaration's full hash with the interface hashes of
+/// the transitive closure of declarations it refers to.
+fn decl_key(decls: &[Decl<'_>], names: &NameIndex, index: usize) -> u64 {
+    let mut visited = HashSet::from([index]);
+    let mut worklist: Vec<Symbol> = deps::referenced_names(&decls[index]).into_iter().collect();
+    let mut dep_hashes = Vec::new();
+
+    while let Some(name) = worklist.pop() {
+        for &dep in names.get(name) {
+            if visited.insert(dep) {
+                dep_hashes.push(deps::interface_hash(&decls[dep]));
+                worklist.extend(deps::interface_names(&decls[dep]));
+            }
+        }
+    }
+
+    // Dependency order must not affect the key
+    dep_hashes.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    decls[index].hash(&mut hasher);
+    dep_hashes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidex_mem::StringInterner;
+    use oxidex_syntax::Span;
+    use oxidex_syntax::ast::decl::{FnParam, Visibility};
+    use oxidex_syntax::ast::expr::{CallArg, Expr};
+    use oxidex_syntax::ast::ty::Type;
+
+    fn span(line: usize) -> Span {
+        Span::new(0, 0, line, 1, line, 1)
+    }
+
+    fn func<'a>(name: Symbol, param_ty: Symbol, body: &'a Expr<'a>, line: usize) -> Decl<'a> {
+        Decl::Fn {
+            is_mut: false,
+            is_init: false,
+            is_static: false,
+            name,
+            generics: vec![],
+            params: vec![FnParam {
+                label: None,
+                name: param_ty,
+                type_annotation: Type::Simple {
+                    name: param_ty,
+                    span: span(line),
+                },
+                span: span(line),
+            }],
+            return_type: None,
+            body,
+            visibility: Visibility::Private,
+            attributes: Vec::new(),
+            span: span(line),
+        }
+    }
+
+    #[test]
+    fn test_unchanged_program_hits_cache() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let g = interner.intern("g");
+        let int = interner.intern("Int");
+
+        let body = Expr::Nil { span: span(1) };
+        let decls = vec![func(f, int, &body, 1), func(g, int, &body, 2)];
+
+        let mut cache = QueryCache::new();
+        let results = cache
+            .check_program(&mut Context::new(&interner), &decls)
+            .unwrap();
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+
+        cache
+            .check_program(&mut Context::new(&interner), &decls)
+            .unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 0 });
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_body_edit_only_rechecks_edited_decl() {
+        let mut interner = StringInterner::new();
+        let f = interner.intern("f");
+        let g = interner.intern("g");
+        let int = interner.intern("Int");
+
+        // g calls f
+        let f_body = Expr::Nil { span: span(1) };
+        let callee = Expr::Identifier(f);
+        let arg = Expr::BoolLiteral {
+            value: true,
+            span: span(2),
+        };
+        let g_body = Expr::Call {
+            callee: &callee,
+            args: vec![CallArg {
+                label: None,
+                value: &arg,
+                span: span(2),
+            }],
+            span: span(2),
+        };
+        let bool_sym = interner.intern("Bool");
+
+        let mut cache = QueryCache::new();
+        let decls = vec![func(f, bool_sym, &f_body, 1), func(g, int, &g_body, 2)];
+        cache
+            .check_program(&mut Context::new(&interner), &decls)
+            .unwrap();
+
+        // Editing f's body leaves g's key intact
+        let edited_body = Expr::BoolLiteral {
+            value: false,
+            span: span(1),
+        };
+        let decls = vec![func(f, bool_sym, &edited_body, 1), func(g, int, &g_body, 2)];
+        cache
+            .check_program(&mut Context::new(&interner), &decls)
+            .unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // Editing f's signature invalidates g as well
+        let decls = vec![func(f, int, &edited_body, 1), func(g, int, &g_body, 2)];
+        let results = cache
+            .check_program(&mut Context::new(&interner), &decls)
+            .unwrap();
+        assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+
+        // g now passes a Bool to a function expecting an Int
+        assert!(results[1].is_err());
+        assert_eq!(cache.len(), 2);
+    }
+}
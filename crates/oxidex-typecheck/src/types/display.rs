@@ -1,7 +1,9 @@
 //! Type pretty-printing for error messages.
 //!
 //! This module provides display implementations for types, making them
-//! human-readable for error messages and diagnostics.
+//! human-readable for error messages and diagnostics. Types print as they
+//! are written in source, so the default-width primitives are `Int`, `UInt`
+//! and `Float` rather than `Int64`, `UInt64` and `Float64`.
 
 use crate::types::PrimTy;
 use crate::types::Ty;
@@ -123,6 +125,8 @@ impl<'a> DisplayTy<'a> {
                 write!(f, ">")
             }
 
+            Ty::Range => write!(f, "Range"),
+
             Ty::SelfType => write!(f, "Self"),
 
             Ty::Never => write!(f, "!"),
@@ -137,15 +141,15 @@ impl<'a> DisplayTy<'a> {
             PrimTy::Int8 => write!(f, "Int8"),
             PrimTy::Int16 => write!(f, "Int16"),
             PrimTy::Int32 => write!(f, "Int32"),
-            PrimTy::Int64 => write!(f, "Int64"),
+            PrimTy::Int64 => write!(f, "Int"),
             PrimTy::Int128 => write!(f, "Int128"),
             PrimTy::UInt8 => write!(f, "UInt8"),
             PrimTy::UInt16 => write!(f, "UInt16"),
             PrimTy::UInt32 => write!(f, "UInt32"),
-            PrimTy::UInt64 => write!(f, "UInt64"),
+            PrimTy::UInt64 => write!(f, "UInt"),
             PrimTy::UInt128 => write!(f, "UInt128"),
             PrimTy::Float32 => write!(f, "Float32"),
-            PrimTy::Float64 => write!(f, "Float64"),
+            PrimTy::Float64 => write!(f, "Float"),
             PrimTy::Bool => write!(f, "Bool"),
             PrimTy::String => write!(f, "String"),
             PrimTy::Unit => write!(f, "()"),
@@ -195,7 +199,14 @@ mod tests {
         let interner = StringInterner::new();
 
         let ty = Ty::Primitive(PrimTy::Int64);
-        assert_eq!(format!("{}", ty.display(&interner)), "Int64");
+        assert_eq!(format!("{}", ty.display(&interner)), "Int");
+
+        // Sized primitives keep their width
+        let ty = Ty::Primitive(PrimTy::Int32);
+        assert_eq!(format!("{}", ty.display(&interner)), "Int32");
+
+        let ty = Ty::Primitive(PrimTy::Float64);
+        assert_eq!(format!("{}", ty.display(&interner)), "Float");
 
         let ty = Ty::Primitive(PrimTy::Bool);
         assert_eq!(format!("{}", ty.display(&interner)), "Bool");
@@ -225,7 +236,7 @@ mod tests {
             Ty::Primitive(PrimTy::String),
         ]);
 
-        assert_eq!(format!("{}", ty.display(&interner)), "(Int64, Bool, String)");
+        assert_eq!(format!("{}", ty.display(&interner)), "(Int, Bool, String)");
     }
 
     #[test]
@@ -233,7 +244,7 @@ mod tests {
         let interner = StringInterner::new();
 
         let ty = Ty::Array(Box::new(Ty::Primitive(PrimTy::Int64)));
-        assert_eq!(format!("{}", ty.display(&interner)), "[Int64]");
+        assert_eq!(format!("{}", ty.display(&interner)), "[Int]");
     }
 
     #[test]
@@ -245,7 +256,7 @@ mod tests {
             value: Box::new(Ty::Primitive(PrimTy::Int64)),
         };
 
-        assert_eq!(format!("{}", ty.display(&interner)), "[String: Int64]");
+        assert_eq!(format!("{}", ty.display(&interner)), "[String: Int]");
     }
 
     #[test]
@@ -258,7 +269,7 @@ mod tests {
             labels: vec![None, None],
         };
 
-        assert_eq!(format!("{}", ty.display(&interner)), "(Int64, Bool) -> String");
+        assert_eq!(format!("{}", ty.display(&interner)), "(Int, Bool) -> String");
     }
 
     #[test]
@@ -266,7 +277,7 @@ mod tests {
         let interner = StringInterner::new();
 
         let ty = Ty::Optional(Box::new(Ty::Primitive(PrimTy::Int64)));
-        assert_eq!(format!("{}", ty.display(&interner)), "Int64?");
+        assert_eq!(format!("{}", ty.display(&interner)), "Int?");
     }
 
     #[test]
@@ -278,7 +289,7 @@ mod tests {
             error: Box::new(Ty::Primitive(PrimTy::String)),
         };
 
-        assert_eq!(format!("{}", ty.display(&interner)), "Result<Int64, String>");
+        assert_eq!(format!("{}", ty.display(&interner)), "Result<Int, String>");
     }
 
     #[test]
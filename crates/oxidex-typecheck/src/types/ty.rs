@@ -46,7 +46,9 @@ pub enum Ty {
     /// Structs are value types with static dispatch.
     /// They are immutable by default and allocated on the stack.
     Struct {
+        /// Struct name
         name: Symbol,
+        /// Generic type arguments
         type_args: Vec<Ty>,
     },
 
@@ -55,7 +57,9 @@ pub enum Ty {
     /// Classes are reference types with dynamic dispatch.
     /// They are allocated on the heap with reference counting.
     Class {
+        /// Class name
         name: Symbol,
+        /// Generic type arguments
         type_args: Vec<Ty>,
     },
 
@@ -63,7 +67,9 @@ pub enum Ty {
     ///
     /// Enums are value types with exhaustive pattern matching.
     Enum {
+        /// Enum name
         name: Symbol,
+        /// Generic type arguments
         type_args: Vec<Ty>,
     },
 
@@ -71,7 +77,9 @@ pub enum Ty {
     ///
     /// Protocols define interfaces that types can implement.
     Protocol {
+        /// Protocol name
         name: Symbol,
+        /// Generic type arguments
         type_args: Vec<Ty>,
     },
 
@@ -107,10 +115,16 @@ pub enum Ty {
     ///
     /// Example: `Dict<String, Int>`
     Dict {
+        /// Key type
         key: Box<Ty>,
+        /// Value type
         value: Box<Ty>,
     },
 
+    /// Range type: the integers from a start up to an end, which
+    /// `range(start, end)` returns.
+    Range,
+
     /// Optional type (syntactic sugar for `Option<T>`).
     ///
     /// Example: `Int?` desugars to `Optional<Int>`
@@ -120,7 +134,9 @@ pub enum Ty {
     ///
     /// Example: `Result<Int, String>`
     Result {
+        /// Success type
         ok: Box<Ty>,
+        /// Error type
         error: Box<Ty>,
     },
 
@@ -145,22 +161,31 @@ pub enum Ty {
 /// Primitive types built into the language.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PrimTy {
-    /// Signed integers
+    /// 8-bit signed integer
     Int8,
+    /// 16-bit signed integer
     Int16,
+    /// 32-bit signed integer
     Int32,
+    /// 64-bit signed integer (`Int`)
     Int64,
+    /// 128-bit signed integer
     Int128,
 
-    /// Unsigned integers
+    /// 8-bit unsigned integer
     UInt8,
+    /// 16-bit unsigned integer
     UInt16,
+    /// 32-bit unsigned integer
     UInt32,
+    /// 64-bit unsigned integer (`UInt`)
     UInt64,
+    /// 128-bit unsigned integer
     UInt128,
 
-    /// Floating point numbers
+    /// 32-bit floating point number
     Float32,
+    /// 64-bit floating point number (`Float`)
     Float64,
 
     /// Boolean type
@@ -176,6 +201,30 @@ pub enum PrimTy {
     Char,
 }
 
+impl PrimTy {
+    /// Get the type encoding of the C type this type crosses into C as, in
+    /// the signature of an `extern` function, or `None` if C has no such
+    /// type. `Unit` is `v`, for functions returning nothing.
+    pub const fn c_encoding(self) -> Option<char> {
+        Some(match self {
+            PrimTy::Int8 => 'c',
+            PrimTy::Int16 => 's',
+            PrimTy::Int32 => 'i',
+            PrimTy::Int64 => 'q',
+            PrimTy::UInt8 => 'C',
+            PrimTy::UInt16 => 'S',
+            PrimTy::UInt32 => 'I',
+            PrimTy::UInt64 => 'Q',
+            PrimTy::Float32 => 'f',
+            PrimTy::Float64 => 'd',
+            PrimTy::Bool => 'B',
+            PrimTy::String => '*',
+            PrimTy::Unit => 'v',
+            PrimTy::Int128 | PrimTy::UInt128 | PrimTy::Char => return None,
+        })
+    }
+}
+
 impl Ty {
     /// Check if this type contains a specific type variable.
     ///
@@ -211,6 +260,7 @@ impl Ty {
 
             // These types don't contain other types
             Ty::Primitive(_)
+            | Ty::Range
             | Ty::SelfType
             | Ty::Never
             | Ty::Error
@@ -278,7 +328,59 @@ impl Ty {
             }
 
             // These types don't contain type variables
-            Ty::Primitive(_) | Ty::SelfType | Ty::Never | Ty::Error => {}
+            Ty::Primitive(_) | Ty::Range | Ty::SelfType | Ty::Never | Ty::Error => {}
+        }
+    }
+
+    /// Replace every occurrence of `Self` with a concrete type.
+    ///
+    /// Protocol signatures are written in terms of `Self`; conforming types
+    /// compare against them after substituting themselves in.
+    pub fn replace_self(&self, self_ty: &Ty) -> Ty {
+        let map = |ty: &Ty| ty.replace_self(self_ty);
+        match self {
+            Ty::SelfType => self_ty.clone(),
+
+            Ty::Struct { name, type_args } => Ty::Struct {
+                name: *name,
+                type_args: type_args.iter().map(map).collect(),
+            },
+            Ty::Class { name, type_args } => Ty::Class {
+                name: *name,
+                type_args: type_args.iter().map(map).collect(),
+            },
+            Ty::Enum { name, type_args } => Ty::Enum {
+                name: *name,
+                type_args: type_args.iter().map(map).collect(),
+            },
+            Ty::Protocol { name, type_args } => Ty::Protocol {
+                name: *name,
+                type_args: type_args.iter().map(map).collect(),
+            },
+            Ty::Tuple(types) => Ty::Tuple(types.iter().map(map).collect()),
+
+            Ty::Function {
+                params,
+                return_type,
+                labels,
+            } => Ty::Function {
+                params: params.iter().map(map).collect(),
+                return_type: Box::new(map(return_type)),
+                labels: labels.clone(),
+            },
+
+            Ty::Array(inner) => Ty::Array(Box::new(map(inner))),
+            Ty::Dict { key, value } => Ty::Dict {
+                key: Box::new(map(key)),
+                value: Box::new(map(value)),
+            },
+            Ty::Optional(inner) => Ty::Optional(Box::new(map(inner))),
+            Ty::Result { ok, error } => Ty::Result {
+                ok: Box::new(map(ok)),
+                error: Box::new(map(error)),
+            },
+
+            Ty::Primitive(_) | Ty::Range | Ty::Never | Ty::Error | Ty::TypeVar(_) => self.clone(),
         }
     }
 
@@ -373,7 +475,7 @@ impl Ty {
                 Ty::Result { ok: o2, error: e2 },
             ) => o1.eq_structural(o2) && e1.eq_structural(e2),
 
-            (Ty::SelfType, Ty::SelfType) | (Ty::Never, Ty::Never) => true,
+            (Ty::Range, Ty::Range) | (Ty::SelfType, Ty::SelfType) | (Ty::Never, Ty::Never) => true,
 
             // Error type unifies with anything
             (Ty::Error, _) | (_, Ty::Error) => true,